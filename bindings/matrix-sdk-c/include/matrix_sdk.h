@@ -0,0 +1,113 @@
+/*
+ * Copyright 2025 The Matrix.org Foundation C.I.C.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C API of the Matrix Rust SDK.
+ *
+ * All the functions are blocking. Callbacks are called from a dedicated
+ * thread of the library, and the functions returning a `MatrixResult` can't
+ * be called from them. See the documentation of the `matrix-sdk-c` crate for
+ * the ownership conventions.
+ */
+
+#ifndef MATRIX_SDK_H
+#define MATRIX_SDK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum MatrixResult {
+    MATRIX_RESULT_OK = 0,
+    MATRIX_RESULT_INVALID_ARGUMENT = 1,
+    MATRIX_RESULT_NOT_LOGGED_IN = 2,
+    MATRIX_RESULT_ROOM_NOT_FOUND = 3,
+    MATRIX_RESULT_SDK_ERROR = 4,
+    MATRIX_RESULT_REENTRANT_CALL = 5,
+    MATRIX_RESULT_PANIC = 6,
+} MatrixResult;
+
+typedef struct MatrixClient MatrixClient;
+typedef struct MatrixSyncService MatrixSyncService;
+
+typedef struct MatrixRoomSummary {
+    char *room_id;
+    char *display_name;
+    uint64_t num_unread_messages;
+} MatrixRoomSummary;
+
+typedef struct MatrixRoomSummaryList {
+    MatrixRoomSummary *rooms;
+    size_t len;
+} MatrixRoomSummaryList;
+
+typedef struct MatrixMessage {
+    const char *room_id;
+    const char *event_id;
+    const char *sender;
+    const char *body;
+    uint64_t origin_server_ts;
+} MatrixMessage;
+
+typedef void (*MatrixMessageCallback)(const MatrixMessage *message, void *user_data);
+
+/* Errors and strings. */
+
+const char *matrix_last_error_message(void);
+void matrix_string_free(char *string);
+
+/* Client. */
+
+MatrixResult matrix_client_new(const char *homeserver_url,
+                               const char *store_path,
+                               const char *store_passphrase,
+                               MatrixClient **out_client);
+void matrix_client_free(MatrixClient *client);
+MatrixResult matrix_client_login(const MatrixClient *client,
+                                 const char *username,
+                                 const char *password,
+                                 const char *device_display_name);
+char *matrix_client_user_id(const MatrixClient *client);
+
+/* Sync service. */
+
+MatrixResult matrix_sync_service_new(const MatrixClient *client,
+                                     MatrixSyncService **out_sync_service);
+MatrixResult matrix_sync_service_start(const MatrixSyncService *sync_service);
+MatrixResult matrix_sync_service_stop(const MatrixSyncService *sync_service);
+void matrix_sync_service_free(MatrixSyncService *sync_service);
+
+/* Rooms and messages. */
+
+MatrixResult matrix_client_joined_rooms(const MatrixClient *client,
+                                        MatrixRoomSummaryList *out_rooms);
+void matrix_room_summary_list_free(MatrixRoomSummaryList rooms);
+MatrixResult matrix_client_send_text(const MatrixClient *client,
+                                     const char *room_id,
+                                     const char *body,
+                                     char **out_event_id);
+MatrixResult matrix_client_set_message_callback(const MatrixClient *client,
+                                                MatrixMessageCallback callback,
+                                                void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MATRIX_SDK_H */
@@ -0,0 +1,145 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{ffi::c_char, ptr, sync::Mutex};
+
+use matrix_sdk::Client;
+
+use crate::{
+    block_on, catch, guard, object_arg, optional_str_arg, room::MessageCallbackThread, sdk_error,
+    str_arg, to_c_string, MatrixResult,
+};
+
+/// A Matrix client.
+pub struct MatrixClient {
+    pub(crate) inner: Client,
+    /// The thread calling the message callback, if one is set.
+    pub(crate) message_callback: Mutex<Option<MessageCallbackThread>>,
+}
+
+/// Create a new client.
+///
+/// `homeserver_url` is the URL of the homeserver, or a server name whose
+/// homeserver will be discovered. If `store_path` isn't null, the data of the
+/// client is persisted in a SQLite store in this directory, encrypted with
+/// `store_passphrase` if it isn't null. Otherwise, the data is kept in memory.
+///
+/// On success, the client is written to `out_client`, and must be released
+/// with [`matrix_client_free`].
+///
+/// # Safety
+///
+/// The strings must be null or valid NUL-terminated strings, and `out_client`
+/// must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_new(
+    homeserver_url: *const c_char,
+    store_path: *const c_char,
+    store_passphrase: *const c_char,
+    out_client: *mut *mut MatrixClient,
+) -> MatrixResult {
+    catch(|| {
+        let homeserver_url = str_arg("homeserver_url", homeserver_url)?;
+        let store_path = optional_str_arg("store_path", store_path)?;
+        let store_passphrase = optional_str_arg("store_passphrase", store_passphrase)?;
+
+        if out_client.is_null() {
+            return Err((MatrixResult::InvalidArgument, "`out_client` is null".to_owned()));
+        }
+
+        let mut builder = Client::builder().server_name_or_homeserver_url(homeserver_url);
+        if let Some(store_path) = store_path {
+            builder = builder.sqlite_store(store_path, store_passphrase);
+        }
+
+        let client = block_on(builder.build()).map_err(sdk_error)?;
+
+        let client = Box::new(MatrixClient { inner: client, message_callback: Mutex::new(None) });
+        *out_client = Box::into_raw(client);
+
+        Ok(())
+    })
+}
+
+/// Release a client.
+///
+/// The sync services created from this client keep working until they're
+/// released themselves. The message callback isn't called anymore once this
+/// returns.
+///
+/// # Safety
+///
+/// `client` must be null, or a client created by [`matrix_client_new`] which
+/// hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_free(client: *mut MatrixClient) {
+    guard((), || {
+        if client.is_null() {
+            return;
+        }
+
+        let client = Box::from_raw(client);
+        if let Some(message_callback) = client.message_callback.lock().unwrap().take() {
+            message_callback.stop(&client.inner);
+        }
+    })
+}
+
+/// Log in with a username and a password.
+///
+/// `device_display_name` is the display name of the new device, it can be
+/// null.
+///
+/// # Safety
+///
+/// `client` must be a live client, and the strings must be null or valid
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_login(
+    client: *const MatrixClient,
+    username: *const c_char,
+    password: *const c_char,
+    device_display_name: *const c_char,
+) -> MatrixResult {
+    catch(|| {
+        let client = object_arg("client", client)?;
+        let username = str_arg("username", username)?;
+        let password = str_arg("password", password)?;
+        let device_display_name = optional_str_arg("device_display_name", device_display_name)?;
+
+        let mut login = client.inner.matrix_auth().login_username(username, password);
+        if let Some(device_display_name) = device_display_name {
+            login = login.initial_device_display_name(device_display_name);
+        }
+
+        block_on(login.send()).map_err(sdk_error)?;
+
+        Ok(())
+    })
+}
+
+/// Get the user ID of the client, or null if it isn't logged in.
+///
+/// The returned string must be released with [`crate::matrix_string_free`].
+///
+/// # Safety
+///
+/// `client` must be null or a live client.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_user_id(client: *const MatrixClient) -> *mut c_char {
+    guard(ptr::null_mut(), || match client.as_ref().and_then(|client| client.inner.user_id()) {
+        Some(user_id) => to_c_string(user_id.as_str()),
+        None => ptr::null_mut(),
+    })
+}
@@ -0,0 +1,296 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A C ABI over the Matrix Rust SDK, for clients which can't use the UniFFI
+//! bindings, like Qt/C++ applications or game engines.
+//!
+//! The API is declared in `include/matrix_sdk.h`. All the functions are
+//! blocking: they run the asynchronous SDK calls to completion on an internal
+//! Tokio runtime. Callbacks are called from a dedicated thread, so they can't
+//! block the runtime.
+//!
+//! Conventions:
+//!
+//! - Fallible functions return a [`MatrixResult`]. When it's not
+//!   [`MatrixResult::Ok`], a description of the error can be obtained with
+//!   [`matrix_last_error_message`], on the same thread.
+//! - Objects are opaque pointers, created by a `matrix_*_new` function or
+//!   returned through an out-parameter, and released with the matching
+//!   `matrix_*_free` function.
+//! - Strings are NUL-terminated and UTF-8 encoded. The strings returned by the
+//!   library are owned by the caller, and must be released with
+//!   [`matrix_string_free`].
+//! - The functions returning a [`MatrixResult`] can't be called from a
+//!   callback, they fail with [`MatrixResult::ReentrantCall`].
+//! - Panics never cross the C ABI: they are caught and reported as
+//!   [`MatrixResult::Panic`], or logged by the functions which don't return a
+//!   [`MatrixResult`].
+
+mod client;
+mod room;
+mod sync_service;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    ffi::{c_char, CStr, CString},
+    future::IntoFuture,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use once_cell::sync::Lazy;
+use tokio::runtime::Runtime;
+use tracing::error;
+
+pub use self::{client::*, room::*, sync_service::*};
+
+static RUNTIME: Lazy<Runtime> =
+    Lazy::new(|| Runtime::new().expect("Couldn't create the Tokio runtime"));
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+
+    /// Whether the current thread is the one calling the callbacks.
+    static IS_CALLBACK_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The result of a fallible function.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixResult {
+    /// The function succeeded.
+    Ok = 0,
+    /// An argument was null, or not valid UTF-8, or not a valid identifier.
+    InvalidArgument = 1,
+    /// The client isn't logged in.
+    NotLoggedIn = 2,
+    /// The room wasn't found.
+    RoomNotFound = 3,
+    /// Any other error returned by the SDK.
+    SdkError = 4,
+    /// The function was called from a callback.
+    ReentrantCall = 5,
+    /// The library panicked. This is a bug.
+    Panic = 6,
+}
+
+/// Get the description of the last error which happened on the calling
+/// thread, or null if there was none.
+///
+/// The returned string is owned by the library, and is valid until the next
+/// call to a function of the library on the same thread.
+#[no_mangle]
+pub extern "C" fn matrix_last_error_message() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+            Some(message) => message.as_ptr(),
+            None => ptr::null(),
+        })
+    })
+}
+
+/// Release a string returned by the library.
+///
+/// # Safety
+///
+/// `string` must be null, or a string returned by the library which hasn't
+/// been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_string_free(string: *mut c_char) {
+    guard((), || {
+        if !string.is_null() {
+            drop(CString::from_raw(string));
+        }
+    })
+}
+
+/// Record the error, and return its code.
+fn set_last_error(code: MatrixResult, message: impl ToString) -> MatrixResult {
+    // Interior NUL bytes can't be represented in a C string, drop them.
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = CString::new(message).ok();
+    });
+    code
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+}
+
+/// Run the body, recording its error if it fails.
+///
+/// The body isn't run if the function was called from a callback, and a panic
+/// of the body is recorded as an error.
+fn catch(body: impl FnOnce() -> Result<(), (MatrixResult, String)>) -> MatrixResult {
+    clear_last_error();
+
+    if is_callback_thread() {
+        return set_last_error(
+            MatrixResult::ReentrantCall,
+            "the function can't be called from a callback",
+        );
+    }
+
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => MatrixResult::Ok,
+        Ok(Err((code, message))) => set_last_error(code, message),
+        Err(panic) => set_last_error(MatrixResult::Panic, panic_message(&*panic)),
+    }
+}
+
+/// Run the body of a function which doesn't return a [`MatrixResult`],
+/// returning `default` if it panics.
+fn guard<T>(default: T, body: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| {
+        error!("The C API panicked: {}", panic_message(&*panic));
+        default
+    })
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        format!("panicked: {message}")
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        format!("panicked: {message}")
+    } else {
+        "panicked".to_owned()
+    }
+}
+
+/// Mark the current thread as the one calling the callbacks.
+fn set_callback_thread() {
+    IS_CALLBACK_THREAD.with(|is_callback_thread| is_callback_thread.set(true));
+}
+
+fn is_callback_thread() -> bool {
+    IS_CALLBACK_THREAD.with(Cell::get)
+}
+
+fn block_on<F: IntoFuture>(future: F) -> F::Output {
+    RUNTIME.block_on(future.into_future())
+}
+
+fn sdk_error(error: impl ToString) -> (MatrixResult, String) {
+    (MatrixResult::SdkError, error.to_string())
+}
+
+/// Borrow a string argument.
+///
+/// # Safety
+///
+/// `string` must be null, or point to a NUL-terminated string which outlives
+/// the returned reference.
+unsafe fn str_arg<'a>(
+    name: &str,
+    string: *const c_char,
+) -> Result<&'a str, (MatrixResult, String)> {
+    if string.is_null() {
+        return Err((MatrixResult::InvalidArgument, format!("`{name}` is null")));
+    }
+
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| (MatrixResult::InvalidArgument, format!("`{name}` is not valid UTF-8")))
+}
+
+/// Borrow an optional string argument.
+///
+/// # Safety
+///
+/// Same as [`str_arg`].
+unsafe fn optional_str_arg<'a>(
+    name: &str,
+    string: *const c_char,
+) -> Result<Option<&'a str>, (MatrixResult, String)> {
+    if string.is_null() {
+        Ok(None)
+    } else {
+        str_arg(name, string).map(Some)
+    }
+}
+
+/// Check that an object argument isn't null, and borrow it.
+///
+/// # Safety
+///
+/// `object` must be null, or point to a live object created by the library.
+unsafe fn object_arg<'a, T>(name: &str, object: *const T) -> Result<&'a T, (MatrixResult, String)> {
+    object.as_ref().ok_or_else(|| (MatrixResult::InvalidArgument, format!("`{name}` is null")))
+}
+
+/// Convert a string into a string owned by the caller.
+fn to_c_string(string: impl Into<Vec<u8>>) -> *mut c_char {
+    let mut bytes = string.into();
+    bytes.retain(|byte| *byte != 0);
+    CString::new(bytes).expect("interior NUL bytes were removed").into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::{
+        matrix_last_error_message, matrix_string_free, set_callback_thread, str_arg, to_c_string,
+    };
+    use crate::{catch, MatrixResult};
+
+    #[test]
+    fn test_errors_are_recorded() {
+        let result = catch(|| {
+            unsafe { str_arg("user_id", std::ptr::null()) }?;
+            Ok(())
+        });
+        assert_eq!(result, MatrixResult::InvalidArgument);
+
+        let message = unsafe { CStr::from_ptr(matrix_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "`user_id` is null");
+
+        // A successful call clears the last error.
+        assert_eq!(catch(|| Ok(())), MatrixResult::Ok);
+        assert!(matrix_last_error_message().is_null());
+    }
+
+    #[test]
+    fn test_panics_are_caught() {
+        let result = catch(|| panic!("oops"));
+        assert_eq!(result, MatrixResult::Panic);
+
+        let message = unsafe { CStr::from_ptr(matrix_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: oops");
+    }
+
+    #[test]
+    fn test_reentrant_calls_are_rejected() {
+        std::thread::spawn(|| {
+            set_callback_thread();
+
+            let result = catch(|| panic!("the body must not run"));
+            assert_eq!(result, MatrixResult::ReentrantCall);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_strings_round_trip() {
+        let string = to_c_string("hello\0 world");
+
+        let borrowed = unsafe { str_arg("string", string) }.unwrap();
+        assert_eq!(borrowed, "hello world");
+
+        unsafe { matrix_string_free(string) };
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    ffi::{c_char, c_void, CString},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use matrix_sdk::{
+    event_handler::EventHandlerHandle,
+    ruma::{
+        events::room::message::{OriginalSyncRoomMessageEvent, RoomMessageEventContent},
+        RoomId,
+    },
+    Client, Room,
+};
+
+use crate::{
+    block_on, catch, guard, matrix_string_free, object_arg, sdk_error, set_callback_thread,
+    str_arg, to_c_string, MatrixClient, MatrixResult,
+};
+
+/// A summary of a joined room.
+#[repr(C)]
+pub struct MatrixRoomSummary {
+    /// The ID of the room.
+    pub room_id: *mut c_char,
+    /// The name of the room, as it should be displayed.
+    pub display_name: *mut c_char,
+    /// The number of unread messages in the room.
+    pub num_unread_messages: u64,
+}
+
+/// A list of room summaries, owned by the caller.
+///
+/// It must be released with [`matrix_room_summary_list_free`].
+#[repr(C)]
+pub struct MatrixRoomSummaryList {
+    pub rooms: *mut MatrixRoomSummary,
+    pub len: usize,
+}
+
+/// A message received in a room.
+///
+/// The strings are owned by the library, and are only valid during the call to
+/// the callback.
+#[repr(C)]
+pub struct MatrixMessage {
+    pub room_id: *const c_char,
+    pub event_id: *const c_char,
+    pub sender: *const c_char,
+    /// The textual representation of the message.
+    pub body: *const c_char,
+    /// The timestamp of the message, in milliseconds since the Unix epoch,
+    /// according to the homeserver of the sender.
+    pub origin_server_ts: u64,
+}
+
+/// A callback called for every message received by a client.
+pub type MatrixMessageCallback =
+    Option<unsafe extern "C" fn(message: *const MatrixMessage, user_data: *mut c_void)>;
+
+/// The user data passed to a callback.
+///
+/// The caller is responsible for making it usable from any thread.
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// SAFETY: the documentation of `matrix_client_set_message_callback` requires
+// the user data to be usable from any thread.
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+/// A message received in a room, owned by Rust until it's passed to the
+/// callback.
+struct ReceivedMessage {
+    room_id: CString,
+    event_id: CString,
+    sender: CString,
+    body: CString,
+    origin_server_ts: u64,
+}
+
+/// The thread calling the message callback of a client.
+///
+/// The event handler of the client sends the messages to this thread, so the
+/// callback doesn't run on the threads of the runtime, where it could block
+/// the sync or call blocking functions of the library.
+pub(crate) struct MessageCallbackThread {
+    handler: EventHandlerHandle,
+    /// Whether the callback can still be called.
+    is_active: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl MessageCallbackThread {
+    /// Stop calling the callback, and wait for the current call to be done,
+    /// if any.
+    pub(crate) fn stop(self, client: &Client) {
+        self.is_active.store(false, Ordering::SeqCst);
+        client.remove_event_handler(self.handler);
+
+        // The thread stops once the event handler, which owns the sender of the
+        // messages, is dropped. It can't be joined from the callback itself.
+        if self.thread.thread().id() != thread::current().id() {
+            _ = self.thread.join();
+        }
+    }
+}
+
+/// List the rooms the client has joined.
+///
+/// On success, the list is written to `out_rooms`.
+///
+/// # Safety
+///
+/// `client` must be a live client, and `out_rooms` must point to writable
+/// memory.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_joined_rooms(
+    client: *const MatrixClient,
+    out_rooms: *mut MatrixRoomSummaryList,
+) -> MatrixResult {
+    catch(|| {
+        let client = object_arg("client", client)?;
+
+        if out_rooms.is_null() {
+            return Err((MatrixResult::InvalidArgument, "`out_rooms` is null".to_owned()));
+        }
+
+        let mut rooms = Vec::new();
+        for room in client.inner.joined_rooms() {
+            let display_name = block_on(room.display_name()).map_err(sdk_error)?;
+
+            rooms.push(MatrixRoomSummary {
+                room_id: to_c_string(room.room_id().as_str()),
+                display_name: to_c_string(display_name.to_string()),
+                num_unread_messages: room.num_unread_messages(),
+            });
+        }
+
+        let rooms = rooms.into_boxed_slice();
+        let len = rooms.len();
+        *out_rooms = MatrixRoomSummaryList { rooms: Box::into_raw(rooms).cast(), len };
+
+        Ok(())
+    })
+}
+
+/// Release a list of room summaries.
+///
+/// # Safety
+///
+/// `rooms` must be a list returned by [`matrix_client_joined_rooms`] which
+/// hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_room_summary_list_free(rooms: MatrixRoomSummaryList) {
+    guard((), || {
+        if rooms.rooms.is_null() {
+            return;
+        }
+
+        let rooms = Box::from_raw(ptr::slice_from_raw_parts_mut(rooms.rooms, rooms.len));
+        for room in rooms.iter() {
+            matrix_string_free(room.room_id);
+            matrix_string_free(room.display_name);
+        }
+    })
+}
+
+/// Send a plain text message to a joined room.
+///
+/// On success, if `out_event_id` isn't null, the ID of the sent event is
+/// written to it, and must be released with [`crate::matrix_string_free`].
+///
+/// # Safety
+///
+/// `client` must be a live client, the strings must be null or valid
+/// NUL-terminated strings, and `out_event_id` must be null or point to
+/// writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_send_text(
+    client: *const MatrixClient,
+    room_id: *const c_char,
+    body: *const c_char,
+    out_event_id: *mut *mut c_char,
+) -> MatrixResult {
+    catch(|| {
+        let client = object_arg("client", client)?;
+        let room_id = str_arg("room_id", room_id)?;
+        let body = str_arg("body", body)?;
+
+        let room_id = <&RoomId>::try_from(room_id)
+            .map_err(|error| (MatrixResult::InvalidArgument, error.to_string()))?;
+        let room = client
+            .inner
+            .get_room(room_id)
+            .ok_or_else(|| (MatrixResult::RoomNotFound, format!("unknown room {room_id}")))?;
+
+        let response =
+            block_on(room.send(RoomMessageEventContent::text_plain(body))).map_err(sdk_error)?;
+
+        if !out_event_id.is_null() {
+            *out_event_id = to_c_string(response.event_id.as_str());
+        }
+
+        Ok(())
+    })
+}
+
+/// Set the callback called for every message received by the client, in any
+/// room, while it's syncing.
+///
+/// It replaces the previous callback, if any. A null callback removes it.
+///
+/// The callback is called from a dedicated thread of the library, with
+/// `user_data` as its second argument: it must be safe to use from another
+/// thread, and outlive the client or the next call to this function. The
+/// previous callback isn't called anymore once this returns.
+///
+/// The functions of the library returning a [`MatrixResult`] can't be called
+/// from the callback.
+///
+/// # Safety
+///
+/// `client` must be a live client.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_client_set_message_callback(
+    client: *const MatrixClient,
+    callback: MatrixMessageCallback,
+    user_data: *mut c_void,
+) -> MatrixResult {
+    catch(|| {
+        let client = object_arg("client", client)?;
+
+        let mut message_callback = client.message_callback.lock().unwrap();
+
+        if let Some(message_callback) = message_callback.take() {
+            message_callback.stop(&client.inner);
+        }
+
+        let Some(callback) = callback else {
+            return Ok(());
+        };
+
+        let user_data = UserData(user_data);
+        let is_active = Arc::new(AtomicBool::new(true));
+        let (sender, receiver) = mpsc::channel::<ReceivedMessage>();
+
+        let thread = thread::Builder::new()
+            .name("matrix-sdk-c-callbacks".to_owned())
+            .spawn({
+                let is_active = is_active.clone();
+                move || {
+                    // Move the whole wrapper in the closure, not only its non-`Send` pointer.
+                    let user_data = user_data;
+                    set_callback_thread();
+
+                    for received in receiver {
+                        if !is_active.load(Ordering::SeqCst) {
+                            break;
+                        }
+
+                        let message = MatrixMessage {
+                            room_id: received.room_id.as_ptr(),
+                            event_id: received.event_id.as_ptr(),
+                            sender: received.sender.as_ptr(),
+                            body: received.body.as_ptr(),
+                            origin_server_ts: received.origin_server_ts,
+                        };
+
+                        // SAFETY: the caller guarantees that the callback is valid as long as
+                        // it's set, and the message is valid during the call.
+                        unsafe { callback(&message, user_data.0) };
+                    }
+                }
+            })
+            .map_err(sdk_error)?;
+
+        let handler = client.inner.add_event_handler(
+            move |event: OriginalSyncRoomMessageEvent, room: Room| {
+                let received = ReceivedMessage {
+                    room_id: c_string(room.room_id().as_str()),
+                    event_id: c_string(event.event_id.as_str()),
+                    sender: c_string(event.sender.as_str()),
+                    body: c_string(event.content.body()),
+                    origin_server_ts: event.origin_server_ts.get().into(),
+                };
+
+                // The thread is only gone if the callback was replaced in the meantime.
+                _ = sender.send(received);
+
+                async {}
+            },
+        );
+
+        *message_callback = Some(MessageCallbackThread { handler, is_active, thread });
+
+        Ok(())
+    })
+}
+
+/// Convert a string into a C string owned by Rust, without its NUL bytes.
+fn c_string(string: &str) -> CString {
+    CString::new(string.replace('\0', "")).expect("NUL bytes were removed")
+}
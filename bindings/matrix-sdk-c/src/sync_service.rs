@@ -0,0 +1,110 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_ui::sync_service::SyncService;
+
+use crate::{block_on, catch, guard, object_arg, sdk_error, MatrixClient, MatrixResult};
+
+/// A service keeping a client in sync with its homeserver, in the background.
+pub struct MatrixSyncService {
+    inner: SyncService,
+}
+
+/// Create a sync service for a logged-in client.
+///
+/// On success, the service is written to `out_sync_service`, and must be
+/// released with [`matrix_sync_service_free`]. It doesn't sync until
+/// [`matrix_sync_service_start`] is called.
+///
+/// # Safety
+///
+/// `client` must be a live client, and `out_sync_service` must point to
+/// writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_sync_service_new(
+    client: *const MatrixClient,
+    out_sync_service: *mut *mut MatrixSyncService,
+) -> MatrixResult {
+    catch(|| {
+        let client = object_arg("client", client)?;
+
+        if out_sync_service.is_null() {
+            return Err((MatrixResult::InvalidArgument, "`out_sync_service` is null".to_owned()));
+        }
+
+        if client.inner.user_id().is_none() {
+            return Err((MatrixResult::NotLoggedIn, "the client isn't logged in".to_owned()));
+        }
+
+        let sync_service =
+            block_on(SyncService::builder(client.inner.clone()).build()).map_err(sdk_error)?;
+
+        *out_sync_service = Box::into_raw(Box::new(MatrixSyncService { inner: sync_service }));
+
+        Ok(())
+    })
+}
+
+/// Start syncing in the background.
+///
+/// This returns as soon as the sync has started. Calling it when the service
+/// is already running has no effect.
+///
+/// # Safety
+///
+/// `sync_service` must be a live sync service.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_sync_service_start(
+    sync_service: *const MatrixSyncService,
+) -> MatrixResult {
+    catch(|| {
+        let sync_service = object_arg("sync_service", sync_service)?;
+        block_on(sync_service.inner.start());
+        Ok(())
+    })
+}
+
+/// Stop syncing, and wait for the background tasks to be done.
+///
+/// # Safety
+///
+/// `sync_service` must be a live sync service.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_sync_service_stop(
+    sync_service: *const MatrixSyncService,
+) -> MatrixResult {
+    catch(|| {
+        let sync_service = object_arg("sync_service", sync_service)?;
+        block_on(sync_service.inner.stop());
+        Ok(())
+    })
+}
+
+/// Stop syncing and release a sync service.
+///
+/// # Safety
+///
+/// `sync_service` must be null, or a sync service created by
+/// [`matrix_sync_service_new`] which hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn matrix_sync_service_free(sync_service: *mut MatrixSyncService) {
+    guard((), || {
+        if sync_service.is_null() {
+            return;
+        }
+
+        let sync_service = Box::from_raw(sync_service);
+        block_on(sync_service.inner.stop());
+    })
+}
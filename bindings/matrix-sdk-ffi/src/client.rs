@@ -7,6 +7,7 @@ use std::{
 use anyhow::{anyhow, Context as _};
 use async_compat::get_runtime_handle;
 use matrix_sdk::{
+    account::breadcrumbs::BreadcrumbsEventContent,
     authentication::oauth::{
         AccountManagementActionFull, ClientId, OAuthAuthorizationData, OAuthSession,
     },
@@ -73,6 +74,7 @@ use crate::{
     encryption::Encryption,
     notification::NotificationClient,
     notification_settings::NotificationSettings,
+    platform::{reload_log_filter, LogFilter},
     room::RoomHistoryVisibility,
     room_directory_search::RoomDirectorySearch,
     room_preview::RoomPreview,
@@ -549,6 +551,19 @@ impl Client {
     pub async fn reset_server_capabilities(&self) -> Result<(), ClientError> {
         Ok(self.inner.reset_server_capabilities().await?)
     }
+
+    /// Change the log levels of the SDK at runtime, without restarting the
+    /// application.
+    ///
+    /// The available targets and their default log levels can be listed with
+    /// [`crate::platform::available_log_targets`].
+    ///
+    /// Note that there's a single tracing subscriber per process, so this
+    /// affects the logs of all the clients, not only this one. This will fail
+    /// if the logs haven't been set up with [`crate::platform::init_platform`].
+    pub fn set_log_filter(&self, filter: LogFilter) -> Result<(), ClientError> {
+        reload_log_filter(&filter)
+    }
 }
 
 impl Client {
@@ -979,6 +994,35 @@ impl Client {
         })))
     }
 
+    /// Get the current bandwidth profile.
+    pub fn bandwidth_profile(&self) -> BandwidthProfile {
+        self.inner.bandwidth_profile().into()
+    }
+
+    /// Get the settings of the current bandwidth profile, e.g. to know
+    /// whether media should be downloaded automatically.
+    pub fn bandwidth_settings(&self) -> BandwidthSettings {
+        self.inner.bandwidth_settings().into()
+    }
+
+    /// Switch to another bandwidth profile.
+    pub fn set_bandwidth_profile(&self, profile: BandwidthProfile) {
+        self.inner.set_bandwidth_profile(profile.into());
+    }
+
+    /// Subscribe to the changes of the bandwidth profile.
+    pub fn subscribe_to_bandwidth_profile(
+        &self,
+        listener: Box<dyn BandwidthProfileListener>,
+    ) -> Arc<TaskHandle> {
+        let mut subscriber = self.inner.subscribe_to_bandwidth_profile();
+        Arc::new(TaskHandle::new(get_runtime_handle().spawn(async move {
+            while let Some(profile) = subscriber.next().await {
+                listener.call(profile.into());
+            }
+        })))
+    }
+
     pub fn room_directory_search(&self) -> Arc<RoomDirectorySearch> {
         Arc::new(RoomDirectorySearch::new(
             matrix_sdk::room_directory_search::RoomDirectorySearch::new((*self.inner).clone()),
@@ -1048,6 +1092,49 @@ impl Client {
         Ok(())
     }
 
+    /// Get the rooms recently opened by the account, the most recent one
+    /// first, as shared with its other clients.
+    pub async fn breadcrumbs(&self) -> Result<Vec<String>, ClientError> {
+        Ok(self.inner.account().breadcrumbs().await?.into_iter().map(Into::into).collect())
+    }
+
+    /// Record that the given room was opened, by moving it to the front of the
+    /// breadcrumbs shared with the other clients of the account.
+    pub async fn push_breadcrumb(&self, room_id: String) -> Result<(), ClientError> {
+        let room_id = RoomId::parse(room_id)?;
+        self.inner.account().push_breadcrumb(&room_id).await?;
+        Ok(())
+    }
+
+    /// Subscribe to the breadcrumbs of the account.
+    ///
+    /// The listener is called with the current breadcrumbs first, then every
+    /// time they change.
+    pub async fn subscribe_to_breadcrumbs(
+        &self,
+        listener: Box<dyn BreadcrumbsListener>,
+    ) -> Result<Arc<TaskHandle>, ClientError> {
+        let observable = self.inner.account().observe_breadcrumbs().await?;
+        let mut subscriber = observable.subscribe();
+
+        let to_room_ids = |content: Option<BreadcrumbsEventContent>| -> Vec<String> {
+            content
+                .map(|content| content.recent_rooms.into_iter().map(Into::into).collect())
+                .unwrap_or_default()
+        };
+
+        Ok(Arc::new(TaskHandle::new(get_runtime_handle().spawn(async move {
+            // Keep the observer alive as long as the task runs.
+            let _observable = observable;
+
+            listener.call(to_room_ids(subscriber.get()));
+
+            while let Some(content) = subscriber.next().await {
+                listener.call(to_room_ids(content));
+            }
+        }))))
+    }
+
     /// Resolves the given room alias to a room ID (and a list of servers), if
     /// possible.
     pub async fn resolve_room_alias(
@@ -1146,12 +1233,7 @@ impl Client {
         auth_data: Option<AuthData>,
         erase_data: bool,
     ) -> Result<(), ClientError> {
-        if let Some(auth_data) = auth_data {
-            _ = self.inner.account().deactivate(None, Some(auth_data.into()), erase_data).await?;
-        } else {
-            _ = self.inner.account().deactivate(None, None, erase_data).await?;
-        }
-
+        self.inner.account().deactivate(None, auth_data.map(Into::into), erase_data).await?;
         Ok(())
     }
 
@@ -1209,6 +1291,71 @@ pub trait IgnoredUsersListener: Sync + Send {
     fn call(&self, ignored_user_ids: Vec<String>);
 }
 
+#[matrix_sdk_ffi_macros::export(callback_interface)]
+pub trait BreadcrumbsListener: Sync + Send {
+    fn call(&self, room_ids: Vec<String>);
+}
+
+#[matrix_sdk_ffi_macros::export(callback_interface)]
+pub trait BandwidthProfileListener: Sync + Send {
+    fn call(&self, profile: BandwidthProfile);
+}
+
+/// A bandwidth profile of the client.
+#[derive(uniffi::Enum)]
+pub enum BandwidthProfile {
+    /// The default profile.
+    Normal,
+    /// A profile that reduces the data used by the client.
+    LowBandwidth,
+}
+
+impl From<matrix_sdk::bandwidth::BandwidthProfile> for BandwidthProfile {
+    fn from(value: matrix_sdk::bandwidth::BandwidthProfile) -> Self {
+        match value {
+            matrix_sdk::bandwidth::BandwidthProfile::Normal => Self::Normal,
+            matrix_sdk::bandwidth::BandwidthProfile::LowBandwidth => Self::LowBandwidth,
+        }
+    }
+}
+
+impl From<BandwidthProfile> for matrix_sdk::bandwidth::BandwidthProfile {
+    fn from(value: BandwidthProfile) -> Self {
+        match value {
+            BandwidthProfile::Normal => Self::Normal,
+            BandwidthProfile::LowBandwidth => Self::LowBandwidth,
+        }
+    }
+}
+
+/// The settings of a bandwidth profile.
+#[derive(uniffi::Record)]
+pub struct BandwidthSettings {
+    /// The maximum `timeline_limit` of the sliding sync requests, if any.
+    pub max_timeline_limit: Option<u32>,
+    /// Whether media files should be downloaded automatically.
+    pub auto_download_media: bool,
+    /// The maximum size, in bytes, of the thumbnails that should be
+    /// downloaded automatically, if any.
+    pub max_auto_download_thumbnail_size: Option<u64>,
+    /// Whether the download of room keys from the backup is deferred.
+    pub defer_backup_download: bool,
+    /// The delay during which read receipts are batched, in milliseconds.
+    pub receipts_batch_delay_ms: u64,
+}
+
+impl From<matrix_sdk::bandwidth::BandwidthSettings> for BandwidthSettings {
+    fn from(value: matrix_sdk::bandwidth::BandwidthSettings) -> Self {
+        Self {
+            max_timeline_limit: value.max_timeline_limit,
+            auto_download_media: value.auto_download_media,
+            max_auto_download_thumbnail_size: value.max_auto_download_thumbnail_size,
+            defer_backup_download: value.defer_backup_download,
+            receipts_batch_delay_ms: value.receipts_batch_delay.as_millis() as u64,
+        }
+    }
+}
+
 #[derive(uniffi::Enum)]
 pub enum NotificationProcessSetup {
     MultipleProcesses,
@@ -4,20 +4,21 @@ use async_compat::get_runtime_handle;
 use futures_util::StreamExt;
 use matrix_sdk::{
     authentication::oauth::qrcode::{self, DeviceCodeErrorResponseType, LoginFailureReason},
+    change_sqlite_passphrase,
     crypto::{
         types::qr_login::{LoginQrCodeDecodeError, QrCodeModeData},
         CollectStrategy, TrustRequirement,
     },
     encryption::{BackupDownloadStrategy, EncryptionSettings},
     event_cache::EventCacheError,
-    reqwest::Certificate,
     ruma::{ServerName, UserId},
+    rustls_pki_types::{pem::PemObject, CertificateDer},
     sliding_sync::{
         Error as MatrixSlidingSyncError, VersionBuilder as MatrixSlidingSyncVersionBuilder,
         VersionBuilderError,
     },
     Client as MatrixClient, ClientBuildError as MatrixClientBuildError, HttpError, IdParseError,
-    RumaApiError, SqliteStoreConfig,
+    RumaApiError, SqliteOpenStoreError, SqliteStoreConfig,
 };
 use ruma::api::error::{DeserializationError, FromHttpResponseError};
 use tracing::{debug, error};
@@ -205,6 +206,9 @@ pub enum ClientBuildError {
     Sdk(MatrixClientBuildError),
     #[error(transparent)]
     EventCache(#[from] EventCacheError),
+    /// The session passphrase is wrong, the user should be asked for it again.
+    #[error("The session passphrase is wrong.")]
+    WrongPassphrase,
     #[error("Failed to build the client: {message}")]
     Generic { message: String },
 }
@@ -223,11 +227,23 @@ impl From<MatrixClientBuildError> for ClientBuildError {
             MatrixClientBuildError::SlidingSyncVersion(e) => {
                 ClientBuildError::SlidingSyncVersion(e)
             }
+            MatrixClientBuildError::SqliteStore(SqliteOpenStoreError::WrongPassphrase) => {
+                ClientBuildError::WrongPassphrase
+            }
             _ => ClientBuildError::Sdk(e),
         }
     }
 }
 
+impl From<SqliteOpenStoreError> for ClientBuildError {
+    fn from(e: SqliteOpenStoreError) -> ClientBuildError {
+        match e {
+            SqliteOpenStoreError::WrongPassphrase => ClientBuildError::WrongPassphrase,
+            e => ClientBuildError::Generic { message: format!("{e:#}") },
+        }
+    }
+}
+
 impl From<IdParseError> for ClientBuildError {
     fn from(e: IdParseError) -> ClientBuildError {
         ClientBuildError::Generic { message: format!("{e:#}") }
@@ -265,6 +281,8 @@ pub struct ClientBuilder {
     user_agent: Option<String>,
     sliding_sync_version_builder: SlidingSyncVersionBuilder,
     proxy: Option<String>,
+    disable_system_proxy: bool,
+    disable_local_dns_resolution: bool,
     disable_ssl_verification: bool,
     disable_automatic_token_refresh: bool,
     cross_process_store_locks_holder_name: Option<String>,
@@ -298,6 +316,8 @@ impl ClientBuilder {
             user_agent: None,
             sliding_sync_version_builder: SlidingSyncVersionBuilder::None,
             proxy: None,
+            disable_system_proxy: false,
+            disable_local_dns_resolution: false,
             disable_ssl_verification: false,
             disable_automatic_token_refresh: false,
             cross_process_store_locks_holder_name: None,
@@ -484,6 +504,18 @@ impl ClientBuilder {
         Arc::new(builder)
     }
 
+    pub fn disable_system_proxy(self: Arc<Self>) -> Arc<Self> {
+        let mut builder = unwrap_or_clone_arc(self);
+        builder.disable_system_proxy = true;
+        Arc::new(builder)
+    }
+
+    pub fn disable_local_dns_resolution(self: Arc<Self>) -> Arc<Self> {
+        let mut builder = unwrap_or_clone_arc(self);
+        builder.disable_local_dns_resolution = true;
+        Arc::new(builder)
+    }
+
     pub fn disable_ssl_verification(self: Arc<Self>) -> Arc<Self> {
         let mut builder = unwrap_or_clone_arc(self);
         builder.disable_ssl_verification = true;
@@ -644,19 +676,17 @@ impl ClientBuilder {
 
         for certificate in builder.additional_root_certificates {
             // We don't really know what type of certificate we may get here, so let's try
-            // first one type, then the other.
-            match Certificate::from_der(&certificate) {
-                Ok(cert) => {
-                    certificates.push(cert);
-                }
-                Err(der_error) => {
-                    let cert = Certificate::from_pem(&certificate).map_err(|pem_error| {
-                        ClientBuildError::Generic {
-                            message: format!("Failed to add a root certificate as DER ({der_error:?}) or PEM ({pem_error:?})"),
-                        }
-                    })?;
-                    certificates.push(cert);
-                }
+            // to find PEM sections first, and consider it as DER otherwise.
+            let pem_certificates = CertificateDer::pem_slice_iter(&certificate)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| ClientBuildError::Generic {
+                    message: format!("Failed to add a root certificate as PEM ({error:?})"),
+                })?;
+
+            if pem_certificates.is_empty() {
+                certificates.push(CertificateDer::from(certificate));
+            } else {
+                certificates.extend(pem_certificates);
             }
         }
 
@@ -670,6 +700,14 @@ impl ClientBuilder {
             inner_builder = inner_builder.proxy(proxy);
         }
 
+        if builder.disable_system_proxy {
+            inner_builder = inner_builder.disable_system_proxy();
+        }
+
+        if builder.disable_local_dns_resolution {
+            inner_builder = inner_builder.disable_local_dns_resolution();
+        }
+
         if builder.disable_ssl_verification {
             inner_builder = inner_builder.disable_ssl_verification();
         }
@@ -801,6 +839,38 @@ impl ClientBuilder {
     }
 }
 
+/// Change the passphrase of the stores of a session, see
+/// [`ClientBuilder::session_paths`] and [`ClientBuilder::session_passphrase`].
+///
+/// Only the keys protecting the stores are encrypted again, so this is fast
+/// even for large stores. [`ClientBuildError::WrongPassphrase`] is returned if
+/// `old_passphrase` is wrong.
+///
+/// The client using the stores must not be running.
+#[matrix_sdk_ffi_macros::export]
+pub async fn change_session_passphrase(
+    data_path: String,
+    cache_path: String,
+    old_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), ClientBuildError> {
+    let old_passphrase = Zeroizing::new(old_passphrase);
+    let new_passphrase = Zeroizing::new(new_passphrase);
+
+    // The cache path can be the same as the data path.
+    let mut paths = vec![data_path];
+    if !paths.contains(&cache_path) {
+        paths.push(cache_path);
+    }
+
+    for path in paths {
+        change_sqlite_passphrase(&SqliteStoreConfig::new(path), &old_passphrase, &new_passphrase)
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// The store paths the client will use when built.
 #[derive(Clone)]
 struct SessionPaths {
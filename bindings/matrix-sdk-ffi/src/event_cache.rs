@@ -0,0 +1,160 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    future::Future,
+    panic,
+    sync::{Arc, Mutex},
+};
+
+use async_compat::get_runtime_handle;
+use matrix_sdk::event_cache::{
+    BackPaginationOutcome, EventCacheDropHandles, RoomPagination, RoomPaginationMetrics,
+    RoomPaginationStatus,
+};
+use tokio::task::AbortHandle;
+use tracing::error;
+
+use crate::{error::ClientError, timeline::PaginationStatusListener, TaskHandle};
+
+/// The outcome of a back-pagination run with [`RoomEventCachePagination`].
+#[derive(uniffi::Enum)]
+pub enum RoomPaginationOutcome {
+    /// The back-pagination completed.
+    Paginated {
+        /// The number of events which have been retrieved.
+        num_events: u32,
+        /// Whether the start of the timeline has been reached, i.e.
+        /// back-paginating again wouldn't have any effect.
+        hit_timeline_start: bool,
+    },
+
+    /// The back-pagination has been cancelled with
+    /// [`RoomEventCachePagination::cancel`].
+    Cancelled,
+}
+
+impl From<BackPaginationOutcome> for RoomPaginationOutcome {
+    fn from(outcome: BackPaginationOutcome) -> Self {
+        Self::Paginated {
+            num_events: outcome.events.len().try_into().unwrap_or(u32::MAX),
+            hit_timeline_start: outcome.reached_start,
+        }
+    }
+}
+
+/// Controls over the back-paginations of a room's event cache, independently
+/// of any timeline.
+///
+/// The events retrieved by a back-pagination are pushed to the event cache,
+/// so the timelines of the room will receive them too.
+#[derive(uniffi::Object)]
+pub struct RoomEventCachePagination {
+    inner: RoomPagination,
+    ongoing: Mutex<Option<AbortHandle>>,
+    _drop_handles: Arc<EventCacheDropHandles>,
+}
+
+impl RoomEventCachePagination {
+    pub(crate) fn new(inner: RoomPagination, drop_handles: Arc<EventCacheDropHandles>) -> Self {
+        Self { inner, ongoing: Mutex::new(None), _drop_handles: drop_handles }
+    }
+
+    /// Run the back-pagination in a task, that can be aborted with
+    /// [`Self::cancel`].
+    async fn run(
+        &self,
+        pagination: impl Future<Output = matrix_sdk::event_cache::Result<BackPaginationOutcome>>
+            + Send
+            + 'static,
+    ) -> Result<RoomPaginationOutcome, ClientError> {
+        let join_handle = get_runtime_handle().spawn(pagination);
+
+        *self.ongoing.lock().unwrap() = Some(join_handle.abort_handle());
+
+        match join_handle.await {
+            Ok(outcome) => Ok(outcome?.into()),
+            Err(err) => {
+                if err.is_cancelled() {
+                    return Ok(RoomPaginationOutcome::Cancelled);
+                }
+                error!("task panicked! resuming panic from here.");
+                panic::resume_unwind(err.into_panic());
+            }
+        }
+    }
+}
+
+#[matrix_sdk_ffi_macros::export]
+impl RoomEventCachePagination {
+    /// Back-paginate until either the start of the timeline has been reached,
+    /// or at least `num_events` events have been retrieved.
+    pub async fn run_backwards_until(
+        &self,
+        num_events: u16,
+    ) -> Result<RoomPaginationOutcome, ClientError> {
+        let pagination = self.inner.clone();
+        self.run(async move { pagination.run_backwards_until(num_events).await }).await
+    }
+
+    /// Run a single back-pagination, for at most `batch_size` events.
+    pub async fn run_backwards_once(
+        &self,
+        batch_size: u16,
+    ) -> Result<RoomPaginationOutcome, ClientError> {
+        let pagination = self.inner.clone();
+        self.run(async move { pagination.run_backwards_once(batch_size).await }).await
+    }
+
+    /// Cancel the ongoing back-pagination, if any.
+    ///
+    /// The pending call to [`Self::run_backwards_until`] or
+    /// [`Self::run_backwards_once`] will return
+    /// [`RoomPaginationOutcome::Cancelled`].
+    pub fn cancel(&self) {
+        if let Some(ongoing) = self.ongoing.lock().unwrap().take() {
+            ongoing.abort();
+        }
+    }
+
+    /// The current status of the back-pagination.
+    pub fn status(&self) -> RoomPaginationStatus {
+        self.inner.status().get()
+    }
+
+    /// Subscribe to the status of the back-pagination.
+    ///
+    /// The current status is passed to the listener immediately.
+    pub fn subscribe_to_status(
+        &self,
+        listener: Box<dyn PaginationStatusListener>,
+    ) -> Arc<TaskHandle> {
+        let mut subscriber = self.inner.status();
+
+        // Like for the timeline, send the current status before spawning the task, so
+        // the caller is immediately aware of it.
+        listener.on_update(subscriber.get());
+
+        Arc::new(TaskHandle::new(get_runtime_handle().spawn(async move {
+            while let Some(status) = subscriber.next().await {
+                listener.on_update(status);
+            }
+        })))
+    }
+
+    /// Counters about the back-paginations which ran on this room so far.
+    pub fn metrics(&self) -> RoomPaginationMetrics {
+        self.inner.metrics()
+    }
+}
@@ -12,6 +12,7 @@ mod element;
 mod encryption;
 mod error;
 mod event;
+mod event_cache;
 mod helpers;
 mod identity_status_change;
 mod live_location_share;
@@ -27,6 +28,7 @@ mod room_member;
 mod room_preview;
 mod ruma;
 mod session_verification;
+mod subscription;
 mod sync_service;
 mod task_handle;
 mod timeline;
@@ -3,8 +3,8 @@ use std::sync::{Arc, RwLock};
 use matrix_sdk::{
     event_handler::EventHandlerHandle,
     notification_settings::{
-        NotificationSettings as SdkNotificationSettings,
-        RoomNotificationMode as SdkRoomNotificationMode,
+        EffectiveRoomNotificationSettings, NotificationSettings as SdkNotificationSettings,
+        RoomNotificationMode as SdkRoomNotificationMode, RoomNotificationModeSource,
     },
     ruma::events::push_rules::PushRulesEvent,
     Client as MatrixClient,
@@ -398,11 +398,19 @@ pub struct RoomNotificationSettings {
     mode: RoomNotificationMode,
     /// Whether the mode is the default one
     is_default: bool,
+    /// The sound played for the messages that notify in this mode, if any.
+    ///
+    /// It's the sound of the mentions if the mode is `MentionsAndKeywordsOnly`.
+    sound: Option<String>,
 }
 
-impl RoomNotificationSettings {
-    fn new(mode: RoomNotificationMode, is_default: bool) -> Self {
-        RoomNotificationSettings { mode, is_default }
+impl From<EffectiveRoomNotificationSettings> for RoomNotificationSettings {
+    fn from(value: EffectiveRoomNotificationSettings) -> Self {
+        RoomNotificationSettings {
+            mode: value.mode.into(),
+            is_default: value.source == RoomNotificationModeSource::Default,
+            sound: value.sound,
+        }
     }
 }
 
@@ -477,20 +485,15 @@ impl NotificationSettings {
 
         let notification_settings = self.sdk_notification_settings.read().await;
 
-        // Get the current user defined mode for this room
-        if let Some(mode) =
-            notification_settings.get_user_defined_room_notification_mode(&parsed_room_id).await
-        {
-            return Ok(RoomNotificationSettings::new(mode.into(), false));
-        }
-
-        // If the user has not defined a notification mode, return the default one for
-        // this room
-        let mode = notification_settings
-            .get_default_room_notification_mode(is_encrypted.into(), is_one_to_one.into())
-            .await;
-
-        Ok(RoomNotificationSettings::new(mode.into(), true))
+        // The user defined mode for this room if any, the default one otherwise.
+        Ok(notification_settings
+            .get_effective_room_notification_settings(
+                &parsed_room_id,
+                is_encrypted.into(),
+                is_one_to_one.into(),
+            )
+            .await
+            .into())
     }
 
     /// Set the notification mode for a room.
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_core::Subscriber;
 use tracing_subscriber::{
@@ -10,11 +13,16 @@ use tracing_subscriber::{
     },
     layer::SubscriberExt,
     registry::LookupSpan,
+    reload,
     util::SubscriberInitExt,
-    EnvFilter, Layer,
+    EnvFilter, Layer, Registry,
 };
 
-use crate::tracing::LogLevel;
+use crate::{error::ClientError, tracing::LogLevel};
+
+/// The handle used to swap the [`EnvFilter`] set up in [`init_platform`] at
+/// runtime.
+static LOG_FILTER_RELOAD_HANDLE: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
 
 pub fn log_panics() {
     std::env::set_var("RUST_BACKTRACE", "1");
@@ -325,6 +333,10 @@ pub enum TraceLogPacks {
     SendQueue,
     /// Enables all the logs relevant to the timeline.
     Timeline,
+    /// Enables all the logs relevant to the crypto layer.
+    Crypto,
+    /// Enables all the logs relevant to sliding sync.
+    SlidingSync,
 }
 
 impl TraceLogPacks {
@@ -339,6 +351,12 @@ impl TraceLogPacks {
             ],
             TraceLogPacks::SendQueue => &[LogTarget::MatrixSdkSendQueue],
             TraceLogPacks::Timeline => &[LogTarget::MatrixSdkUiTimeline],
+            TraceLogPacks::Crypto => {
+                &[LogTarget::MatrixSdkCrypto, LogTarget::MatrixSdkCryptoAccount]
+            }
+            TraceLogPacks::SlidingSync => {
+                &[LogTarget::MatrixSdkSlidingSync, LogTarget::MatrixSdkBaseSlidingSync]
+            }
         }
     }
 }
@@ -365,20 +383,92 @@ pub struct TracingConfiguration {
     write_to_files: Option<TracingFileConfiguration>,
 }
 
+/// A set of log levels that can be applied at runtime with
+/// [`crate::client::Client::set_log_filter`], without restarting the
+/// application.
+#[derive(uniffi::Record)]
+pub struct LogFilter {
+    /// The desired log level.
+    log_level: LogLevel,
+
+    /// All the log packs, that will be set to `TRACE` when they're enabled.
+    trace_log_packs: Vec<TraceLogPacks>,
+
+    /// Explicit log levels for some targets, as returned by
+    /// [`available_log_targets`].
+    ///
+    /// These take precedence over `log_level` and `trace_log_packs`. Targets
+    /// which aren't mutable are ignored.
+    target_log_levels: HashMap<String, LogLevel>,
+
+    /// Additional targets that the FFI client would like to use.
+    ///
+    /// These targets will use `log_level`.
+    extra_targets: Vec<String>,
+}
+
+/// Information about a log target whose level can be configured.
+#[derive(uniffi::Record)]
+pub struct LogTargetInfo {
+    /// The name of the target, e.g. `matrix_sdk::event_cache`.
+    target: String,
+
+    /// The log level used for this target when nothing else is configured.
+    default_log_level: LogLevel,
+
+    /// Whether the log level of this target can be changed.
+    is_mutable: bool,
+}
+
+/// List all the SDK log targets, along with their default log levels.
+///
+/// This is useful to build a debug panel allowing to change the log level of
+/// individual targets with [`crate::client::Client::set_log_filter`].
+#[matrix_sdk_ffi_macros::export]
+pub fn available_log_targets() -> Vec<LogTargetInfo> {
+    DEFAULT_TARGET_LOG_LEVELS
+        .iter()
+        .map(|(target, default_log_level)| LogTargetInfo {
+            target: target.as_str().to_owned(),
+            default_log_level: *default_log_level,
+            is_mutable: !IMMUTABLE_LOG_TARGETS.contains(target),
+        })
+        .collect()
+}
+
 fn build_tracing_filter(config: &TracingConfiguration) -> String {
+    build_filter(config.log_level, &config.trace_log_packs, &HashMap::new(), &config.extra_targets)
+}
+
+fn build_log_filter(filter: &LogFilter) -> String {
+    build_filter(
+        filter.log_level,
+        &filter.trace_log_packs,
+        &filter.target_log_levels,
+        &filter.extra_targets,
+    )
+}
+
+fn build_filter(
+    global_level: LogLevel,
+    trace_log_packs: &[TraceLogPacks],
+    target_log_levels: &HashMap<String, LogLevel>,
+    extra_targets: &[String],
+) -> String {
     // We are intentionally not setting a global log level because we don't want to
     // risk third party crates logging sensitive information.
     // As such we need to make sure that panics will be properly logged.
     // On 2025-01-08, `log_panics` uses the `panic` target, at the error log level.
     let mut filters = vec!["panic=error".to_owned()];
 
-    let global_level = config.log_level;
-
     DEFAULT_TARGET_LOG_LEVELS.iter().for_each(|(target, default_level)| {
         let level = if IMMUTABLE_LOG_TARGETS.contains(target) {
             // If the target is immutable, keep the log level.
             *default_level
-        } else if config.trace_log_packs.iter().any(|pack| pack.targets().contains(target)) {
+        } else if let Some(level) = target_log_levels.get(target.as_str()) {
+            // If the target has been configured explicitly, use that.
+            *level
+        } else if trace_log_packs.iter().any(|pack| pack.targets().contains(target)) {
             // If a log pack includes that target, set the associated log level to TRACE.
             LogLevel::Trace
         } else if *default_level > global_level {
@@ -393,13 +483,34 @@ fn build_tracing_filter(config: &TracingConfiguration) -> String {
     });
 
     // Finally append the extra targets requested by the client.
-    for target in &config.extra_targets {
-        filters.push(format!("{}={}", target, config.log_level.as_str()));
+    for target in extra_targets {
+        filters.push(format!("{}={}", target, global_level.as_str()));
     }
 
     filters.join(",")
 }
 
+/// Replace the log filter set up in [`init_platform`] with a new one.
+///
+/// This applies to the whole process, since there's a single tracing
+/// subscriber for all the clients.
+pub(crate) fn reload_log_filter(filter: &LogFilter) -> Result<(), ClientError> {
+    let handle = LOG_FILTER_RELOAD_HANDLE.get().ok_or_else(|| {
+        ClientError::from_str(
+            "the tracing subscriber hasn't been set up with `init_platform`",
+            None,
+        )
+    })?;
+
+    let env_filter = build_log_filter(filter);
+
+    handle.reload(EnvFilter::new(&env_filter)).map_err(ClientError::from_err)?;
+
+    tracing::info!(env_filter, "The log filter has been updated");
+
+    Ok(())
+}
+
 /// Sets up logs and the tokio runtime for the current application.
 ///
 /// If `use_lightweight_tokio_runtime` is set to true, this will set up a
@@ -412,10 +523,13 @@ pub fn init_platform(config: TracingConfiguration, use_lightweight_tokio_runtime
 
     let env_filter = build_tracing_filter(&config);
 
-    tracing_subscriber::registry()
-        .with(EnvFilter::new(&env_filter))
-        .with(text_layers(config))
-        .init();
+    let (filter_layer, reload_handle) = reload::Layer::new(EnvFilter::new(&env_filter));
+
+    tracing_subscriber::registry().with(filter_layer).with(text_layers(config)).init();
+
+    // Only the first call to `init_platform` sets up the subscriber, so only the
+    // first handle is relevant.
+    let _ = LOG_FILTER_RELOAD_HANDLE.set(reload_handle);
 
     // Log the log levels 🧠.
     tracing::info!(env_filter, "Logging has been set up");
@@ -468,8 +582,10 @@ fn setup_lightweight_tokio_runtime() {
 
 #[cfg(test)]
 mod tests {
-    use super::build_tracing_filter;
-    use crate::platform::TraceLogPacks;
+    use std::collections::HashMap;
+
+    use super::{build_log_filter, build_tracing_filter, LogFilter};
+    use crate::{platform::TraceLogPacks, tracing::LogLevel};
 
     #[test]
     fn test_default_tracing_filter() {
@@ -583,4 +699,49 @@ mod tests {
                 .join("")
         );
     }
+
+    #[test]
+    fn test_log_filter_with_target_log_levels() {
+        let filter = LogFilter {
+            log_level: LogLevel::Info,
+            trace_log_packs: vec![TraceLogPacks::Crypto],
+            target_log_levels: HashMap::from([
+                ("matrix_sdk::sliding_sync".to_owned(), LogLevel::Debug),
+                // Explicit levels win over the log packs.
+                ("matrix_sdk_crypto::olm::account".to_owned(), LogLevel::Warn),
+                // Immutable targets can't be changed.
+                ("hyper".to_owned(), LogLevel::Trace),
+            ]),
+            extra_targets: vec!["super_duper_app".to_owned()],
+        };
+
+        let filter = build_log_filter(&filter);
+
+        assert_eq!(
+            filter,
+            r#"panic=error,
+            hyper=warn,
+            matrix_sdk_ffi=info,
+            matrix_sdk=info,
+            matrix_sdk::client=trace,
+            matrix_sdk_crypto=trace,
+            matrix_sdk_crypto::olm::account=warn,
+            matrix_sdk::oidc=trace,
+            matrix_sdk::http_client=debug,
+            matrix_sdk::sliding_sync=debug,
+            matrix_sdk_base::sliding_sync=info,
+            matrix_sdk_ui::timeline=info,
+            matrix_sdk::send_queue=info,
+            matrix_sdk::event_cache=info,
+            matrix_sdk_base::event_cache=info,
+            matrix_sdk_sqlite::event_cache_store=info,
+            matrix_sdk_common::store_locks=warn,
+            matrix_sdk_base::store::ambiguity_map=warn,
+            super_duper_app=info"#
+                .split('\n')
+                .map(|s| s.trim())
+                .collect::<Vec<_>>()
+                .join("")
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, pin::pin, sync::Arc};
+use std::{collections::HashMap, pin::pin, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
 use async_compat::get_runtime_handle;
@@ -24,6 +24,7 @@ use ruma::{
             join_rules::JoinRule as RumaJoinRule, message::RoomMessageEventContentWithoutRelation,
             power_levels::RoomPowerLevels as RumaPowerLevels, MediaSource,
         },
+        tag::{TagInfo, TagName},
         AnyMessageLikeEventContent, AnySyncTimelineEvent, TimelineEventType,
     },
     EventId, Int, OwnedDeviceId, OwnedUserId, RoomAliasId, UserId,
@@ -36,8 +37,10 @@ use crate::{
     client::{JoinRule, RoomVisibility},
     error::{ClientError, MediaInfoError, NotYetImplemented, RoomError},
     event::{MessageLikeEventType, StateEventType},
+    event_cache::RoomEventCachePagination,
     identity_status_change::IdentityStatusChange,
     live_location_share::{LastLocation, LiveLocationShare},
+    notification_settings::RoomNotificationSettings,
     room_info::RoomInfo,
     room_member::{RoomMember, RoomMemberWithSenderInfo},
     ruma::{ImageInfo, LocationContent, Mentions, NotifyType},
@@ -320,6 +323,27 @@ impl Room {
         })))
     }
 
+    /// Subscribe to the notification settings that apply to this room.
+    ///
+    /// The listener is called with the current settings first, then every
+    /// time they change, because the push rules changed or the room changed.
+    /// It's called with `None` while the room isn't joined, or if its
+    /// encryption state couldn't be loaded.
+    pub async fn subscribe_to_notification_settings(
+        &self,
+        listener: Box<dyn RoomNotificationSettingsListener>,
+    ) -> Arc<TaskHandle> {
+        let stream = self.inner.subscribe_to_effective_notification_settings().await;
+
+        Arc::new(TaskHandle::new(get_runtime_handle().spawn(async move {
+            pin_mut!(stream);
+
+            while let Some(settings) = stream.next().await {
+                listener.call(settings.map(Into::into));
+            }
+        })))
+    }
+
     pub async fn set_is_favourite(
         &self,
         is_favourite: bool,
@@ -338,6 +362,21 @@ impl Room {
         Ok(())
     }
 
+    /// Add a tag to the room, or update its order if the room already has it.
+    ///
+    /// Unlike [`Self::set_is_favourite`] and [`Self::set_is_low_priority`],
+    /// it doesn't remove the other tags of the room.
+    pub async fn set_tag(&self, name: RoomTagName, order: Option<f64>) -> Result<(), ClientError> {
+        self.inner.set_tag(name.into(), assign!(TagInfo::new(), { order })).await?;
+        Ok(())
+    }
+
+    /// Remove a tag from the room.
+    pub async fn remove_tag(&self, name: RoomTagName) -> Result<(), ClientError> {
+        self.inner.remove_tag(name.into()).await?;
+        Ok(())
+    }
+
     /// Send a raw event to the room.
     ///
     /// # Arguments
@@ -785,6 +824,34 @@ impl Room {
         Ok(self.inner.clear_composer_draft().await?)
     }
 
+    /// Store the playback position of the voice message with the given event
+    /// id in the state store.
+    pub async fn save_voice_playback_position(
+        &self,
+        event_id: String,
+        position: Duration,
+    ) -> Result<(), ClientError> {
+        let event_id = EventId::parse(event_id)?;
+        Ok(self.inner.save_voice_playback_position(&event_id, position).await?)
+    }
+
+    /// Retrieve the playback position of the voice message with the given
+    /// event id stored in the state store.
+    pub async fn voice_playback_position(
+        &self,
+        event_id: String,
+    ) -> Result<Option<Duration>, ClientError> {
+        let event_id = EventId::parse(event_id)?;
+        Ok(self.inner.voice_playback_position(&event_id).await?)
+    }
+
+    /// Remove the playback position of the voice message with the given event
+    /// id stored in the state store.
+    pub async fn clear_voice_playback_position(&self, event_id: String) -> Result<(), ClientError> {
+        let event_id = EventId::parse(event_id)?;
+        Ok(self.inner.clear_voice_playback_position(&event_id).await?)
+    }
+
     /// Edit an event given its event id.
     ///
     /// Useful outside the context of a timeline, or when a timeline doesn't
@@ -880,6 +947,14 @@ impl Room {
         Ok(())
     }
 
+    /// Get the controls over the back-paginations of this room's event cache.
+    pub async fn event_cache_pagination(
+        &self,
+    ) -> Result<Arc<RoomEventCachePagination>, ClientError> {
+        let (room_event_cache, drop_handles) = self.inner.event_cache().await?;
+        Ok(Arc::new(RoomEventCachePagination::new(room_event_cache.pagination(), drop_handles)))
+    }
+
     /// Subscribes to requests to join this room (knock member events), using a
     /// `listener` to be notified of the changes.
     ///
@@ -1232,6 +1307,11 @@ pub trait RoomInfoListener: Sync + Send {
     fn call(&self, room_info: RoomInfo);
 }
 
+#[matrix_sdk_ffi_macros::export(callback_interface)]
+pub trait RoomNotificationSettingsListener: Sync + Send {
+    fn call(&self, settings: Option<RoomNotificationSettings>);
+}
+
 #[matrix_sdk_ffi_macros::export(callback_interface)]
 pub trait TypingNotificationsListener: Sync + Send {
     fn call(&self, typing_user_ids: Vec<String>);
@@ -1460,3 +1540,55 @@ impl TryFrom<RoomHistoryVisibility> for RumaHistoryVisibility {
         }
     }
 }
+
+/// The name of a tag of a room.
+#[derive(Debug, Clone, PartialEq, uniffi::Enum)]
+pub enum RoomTagName {
+    /// The `m.favourite` tag.
+    Favourite,
+
+    /// The `m.lowpriority` tag.
+    LowPriority,
+
+    /// The `m.server_notice` tag.
+    ServerNotice,
+
+    /// A tag defined by the user, whose name must start with `u.`.
+    User { name: String },
+
+    /// Another tag.
+    Custom { name: String },
+}
+
+impl From<TagName> for RoomTagName {
+    fn from(value: TagName) -> Self {
+        match value {
+            TagName::Favorite => Self::Favourite,
+            TagName::LowPriority => Self::LowPriority,
+            TagName::ServerNotice => Self::ServerNotice,
+            TagName::User(name) => Self::User { name: name.as_ref().to_owned() },
+            _ => Self::Custom { name: value.to_string() },
+        }
+    }
+}
+
+impl From<RoomTagName> for TagName {
+    fn from(value: RoomTagName) -> Self {
+        match value {
+            RoomTagName::Favourite => Self::Favorite,
+            RoomTagName::LowPriority => Self::LowPriority,
+            RoomTagName::ServerNotice => Self::ServerNotice,
+            RoomTagName::User { name } | RoomTagName::Custom { name } => Self::from(name),
+        }
+    }
+}
+
+/// A tag of a room, with its order.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RoomTag {
+    pub name: RoomTagName,
+
+    /// The order of the room among the rooms with the same tag, between 0 and
+    /// 1, if any.
+    pub order: Option<f64>,
+}
@@ -7,7 +7,7 @@ use crate::{
     client::JoinRule,
     error::ClientError,
     notification_settings::RoomNotificationMode,
-    room::{Membership, RoomHero, RoomHistoryVisibility},
+    room::{Membership, RoomHero, RoomHistoryVisibility, RoomTag},
     room_member::RoomMember,
 };
 
@@ -64,6 +64,8 @@ pub struct RoomInfo {
     join_rule: Option<JoinRule>,
     /// The history visibility for this room, if known.
     history_visibility: RoomHistoryVisibility,
+    /// The tags of this room, including the favourite and low priority ones.
+    tags: Vec<RoomTag>,
 }
 
 impl RoomInfo {
@@ -134,6 +136,11 @@ impl RoomInfo {
             pinned_event_ids,
             join_rule: join_rule.ok(),
             history_visibility: room.history_visibility_or_default().try_into()?,
+            tags: room
+                .cached_tags()
+                .into_iter()
+                .map(|(name, info)| RoomTag { name: name.into(), order: info.order })
+                .collect(),
         })
     }
 }
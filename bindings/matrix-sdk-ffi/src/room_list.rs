@@ -3,7 +3,7 @@
 use std::{fmt::Debug, mem::MaybeUninit, ptr::addr_of_mut, sync::Arc, time::Duration};
 
 use async_compat::get_runtime_handle;
-use eyeball_im::VectorDiff;
+use eyeball_im::{Vector, VectorDiff};
 use futures_util::{pin_mut, StreamExt, TryFutureExt};
 use matrix_sdk::ruma::{
     api::client::sync::sync_events::UnreadNotificationsCount as RumaUnreadNotificationsCount,
@@ -27,6 +27,7 @@ use crate::{
     room::{Membership, Room},
     room_info::RoomInfo,
     room_preview::RoomPreview,
+    subscription::{LatestStates, SubscriptionBufferingPolicy, SubscriptionHandle, VectorDiffs},
     timeline::{configuration::TimelineEventTypeFilter, EventTimelineItem, Timeline},
     utils::AsyncRuntimeDropped,
     TaskHandle,
@@ -101,6 +102,21 @@ impl RoomListService {
         })))
     }
 
+    /// Like [`Self::state`], but with updates buffered according to the given
+    /// policy.
+    fn subscribe_to_state(
+        &self,
+        listener: Box<dyn RoomListServiceStateListener>,
+        buffering: SubscriptionBufferingPolicy,
+    ) -> Arc<SubscriptionHandle> {
+        Arc::new(SubscriptionHandle::buffered(
+            self.inner.state(),
+            buffering,
+            LatestStates,
+            move |state| listener.on_update(state.into()),
+        ))
+    }
+
     fn room(&self, room_id: String) -> Result<Arc<RoomListItem>, RoomListError> {
         let room_id = <&RoomId>::try_from(room_id.as_str()).map_err(RoomListError::from)?;
 
@@ -181,6 +197,33 @@ impl RoomList {
         self: Arc<Self>,
         page_size: u32,
         listener: Box<dyn RoomListEntriesListener>,
+    ) -> Arc<RoomListEntriesWithDynamicAdaptersResult> {
+        self.entries_with_dynamic_adapters_impl(page_size, listener, None)
+    }
+
+    /// Like [`Self::entries_with_dynamic_adapters`], but with updates buffered
+    /// according to the given policy, for listeners that can't keep up with the
+    /// room list.
+    fn entries_with_dynamic_adapters_and_buffering(
+        self: Arc<Self>,
+        page_size: u32,
+        listener: Box<dyn RoomListEntriesListener>,
+        buffering: SubscriptionBufferingPolicy,
+    ) -> Arc<RoomListEntriesWithDynamicAdaptersResult> {
+        self.entries_with_dynamic_adapters_impl(page_size, listener, Some(buffering))
+    }
+
+    fn room(&self, room_id: String) -> Result<Arc<RoomListItem>, RoomListError> {
+        self.room_list_service.room(room_id)
+    }
+}
+
+impl RoomList {
+    fn entries_with_dynamic_adapters_impl(
+        self: Arc<Self>,
+        page_size: u32,
+        listener: Box<dyn RoomListEntriesListener>,
+        buffering: Option<SubscriptionBufferingPolicy>,
     ) -> Arc<RoomListEntriesWithDynamicAdaptersResult> {
         let this = self.clone();
         let utd_hook = self.room_list_service.utd_hook.clone();
@@ -237,18 +280,20 @@ impl RoomList {
         let dynamic_entries_controller =
             Arc::new(RoomListDynamicEntriesController::new(dynamic_entries_controller));
 
-        let entries_stream = Arc::new(TaskHandle::new(get_runtime_handle().spawn(async move {
-            pin_mut!(entries_stream);
-
-            while let Some(diffs) = entries_stream.next().await {
+        // The stream starts with a reset, so the list is initially empty.
+        let subscription = Arc::new(SubscriptionHandle::new(
+            entries_stream,
+            buffering,
+            VectorDiffs::new(Vector::new()),
+            move |diffs| {
                 listener.on_update(
                     diffs
                         .into_iter()
                         .map(|diff| RoomListEntriesUpdate::from(diff, utd_hook.clone()))
                         .collect(),
                 );
-            }
-        })));
+            },
+        ));
 
         // Initialize the second field `controller`.
         //
@@ -257,11 +302,11 @@ impl RoomList {
             addr_of_mut!((*ptr).controller).write(dynamic_entries_controller);
         }
 
-        // Initialize the third and last field `entries_stream`.
+        // Initialize the third and last field `subscription`.
         //
         // SAFETY: `ptr` is correctly aligned.
         unsafe {
-            addr_of_mut!((*ptr).entries_stream).write(entries_stream);
+            addr_of_mut!((*ptr).subscription).write(subscription);
         }
 
         // The result is complete, let's return it!
@@ -270,17 +315,13 @@ impl RoomList {
         // value.
         Arc::new(unsafe { result.assume_init() })
     }
-
-    fn room(&self, room_id: String) -> Result<Arc<RoomListItem>, RoomListError> {
-        self.room_list_service.room(room_id)
-    }
 }
 
 #[derive(uniffi::Object)]
 pub struct RoomListEntriesWithDynamicAdaptersResult {
     this: Arc<RoomList>,
     controller: Arc<RoomListDynamicEntriesController>,
-    entries_stream: Arc<TaskHandle>,
+    subscription: Arc<SubscriptionHandle>,
 }
 
 #[matrix_sdk_ffi_macros::export]
@@ -290,7 +331,11 @@ impl RoomListEntriesWithDynamicAdaptersResult {
     }
 
     fn entries_stream(&self) -> Arc<TaskHandle> {
-        self.entries_stream.clone()
+        self.subscription.task()
+    }
+
+    fn subscription(&self) -> Arc<SubscriptionHandle> {
+        self.subscription.clone()
     }
 }
 
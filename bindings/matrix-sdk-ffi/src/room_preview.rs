@@ -45,6 +45,7 @@ impl RoomPreview {
                 .heroes
                 .as_ref()
                 .map(|heroes| heroes.iter().map(|h| h.to_owned().into()).collect()),
+            is_encrypted: info.is_encrypted,
         })
     }
 
@@ -127,6 +128,8 @@ pub struct RoomPreviewInfo {
     pub is_direct: Option<bool>,
     /// Room heroes.
     pub heroes: Option<Vec<RoomHero>>,
+    /// Whether the room is encrypted, if known.
+    pub is_encrypted: Option<bool>,
 }
 
 impl TryFrom<SpaceRoomJoinRule> for JoinRule {
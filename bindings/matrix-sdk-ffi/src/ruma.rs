@@ -15,7 +15,10 @@
 use std::{collections::BTreeSet, sync::Arc, time::Duration};
 
 use extension_trait::extension_trait;
-use matrix_sdk::attachment::{BaseAudioInfo, BaseFileInfo, BaseImageInfo, BaseVideoInfo};
+use matrix_sdk::{
+    attachment::{BaseAudioInfo, BaseFileInfo, BaseImageInfo, BaseVideoInfo},
+    room::message_builder::MessageBuilder,
+};
 use ruma::{
     assign,
     events::{
@@ -164,14 +167,14 @@ pub fn message_event_content_new(
 pub fn message_event_content_from_markdown(
     md: String,
 ) -> Arc<RoomMessageEventContentWithoutRelation> {
-    Arc::new(RoomMessageEventContentWithoutRelation::new(RumaMessageType::text_markdown(md)))
+    build_message(MessageBuilder::markdown(md))
 }
 
 #[matrix_sdk_ffi_macros::export]
 pub fn message_event_content_from_markdown_as_emote(
     md: String,
 ) -> Arc<RoomMessageEventContentWithoutRelation> {
-    Arc::new(RoomMessageEventContentWithoutRelation::new(RumaMessageType::emote_markdown(md)))
+    build_message(MessageBuilder::markdown(md).emote())
 }
 
 #[matrix_sdk_ffi_macros::export]
@@ -179,9 +182,7 @@ pub fn message_event_content_from_html(
     body: String,
     html_body: String,
 ) -> Arc<RoomMessageEventContentWithoutRelation> {
-    Arc::new(RoomMessageEventContentWithoutRelation::new(RumaMessageType::text_html(
-        body, html_body,
-    )))
+    build_message(MessageBuilder::html(body, html_body))
 }
 
 #[matrix_sdk_ffi_macros::export]
@@ -189,9 +190,16 @@ pub fn message_event_content_from_html_as_emote(
     body: String,
     html_body: String,
 ) -> Arc<RoomMessageEventContentWithoutRelation> {
-    Arc::new(RoomMessageEventContentWithoutRelation::new(RumaMessageType::emote_html(
-        body, html_body,
-    )))
+    build_message(MessageBuilder::html(body, html_body).emote())
+}
+
+fn build_message(builder: MessageBuilder) -> Arc<RoomMessageEventContentWithoutRelation> {
+    // The size of the message is checked when it's sent, not here.
+    let content = builder
+        .max_size(usize::MAX)
+        .build()
+        .expect("building a message without a maximum size should never fail");
+    Arc::new(content)
 }
 
 #[derive(Clone, uniffi::Object)]
@@ -0,0 +1,450 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Subscriptions forwarding the updates of a stream to a foreign listener,
+//! with a bounded buffer in between.
+//!
+//! A listener that is slower than the stream it listens to makes the updates
+//! pile up. The [`SubscriptionBufferingPolicy`] decides what happens then:
+//! the pending updates can be merged, the oldest ones can be dropped, or the
+//! stream can stop being read until the listener has caught up.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_compat::get_runtime_handle;
+use eyeball_im::{Vector, VectorDiff};
+use futures_util::{pin_mut, Stream, StreamExt};
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::TaskHandle;
+
+/// What to do with the updates of a subscription when its listener can't keep
+/// up with them.
+#[derive(Clone, Copy, Debug, uniffi::Enum)]
+pub enum SubscriptionBufferingPolicy {
+    /// Merge all the pending updates into a single one: only the latest state
+    /// is kept, and the pending diffs are delivered together, or as a single
+    /// reset of the whole list if that's shorter.
+    Coalesce,
+
+    /// Keep at most `capacity` pending updates, dropping the oldest ones.
+    ///
+    /// Dropping a diff would corrupt the list of the listener, so pending
+    /// diffs are replaced by a single reset of the whole list instead.
+    DropOldest { capacity: u32 },
+
+    /// Keep at most `capacity` pending updates, and stop reading the stream
+    /// until the listener has caught up.
+    Block { capacity: u32 },
+}
+
+/// A handle to a subscription, that can be used to close it.
+///
+/// The subscription is also closed when the handle is dropped.
+#[derive(uniffi::Object)]
+pub struct SubscriptionHandle {
+    task: Arc<TaskHandle>,
+    stats: Arc<SubscriptionStats>,
+}
+
+impl SubscriptionHandle {
+    /// Forward the updates of the stream to the listener with `deliver`,
+    /// without any buffering.
+    pub(crate) fn unbuffered<S, T>(stream: S, mut deliver: impl FnMut(T) + Send + 'static) -> Self
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let stats = Arc::new(SubscriptionStats::default());
+
+        let task = get_runtime_handle().spawn({
+            let stats = stats.clone();
+
+            async move {
+                pin_mut!(stream);
+
+                while let Some(update) = stream.next().await {
+                    deliver(update);
+                }
+
+                stats.closed.store(true, Ordering::SeqCst);
+            }
+        });
+
+        Self { task: Arc::new(TaskHandle::new(task)), stats }
+    }
+
+    /// Forward the updates of the stream to the listener with `deliver`,
+    /// buffering them according to the policy.
+    ///
+    /// The stream is read in one task, and the listener is called in another
+    /// one, so a slow listener doesn't prevent the buffer from being updated.
+    pub(crate) fn buffered<S, T, M>(
+        stream: S,
+        policy: SubscriptionBufferingPolicy,
+        mut merger: M,
+        mut deliver: impl FnMut(T) + Send + 'static,
+    ) -> Self
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Send + 'static,
+        M: MergeUpdates<T>,
+    {
+        let stats = Arc::new(SubscriptionStats::default());
+        let buffer = Arc::new(Buffer::new(stats.clone()));
+
+        let consumer = get_runtime_handle().spawn({
+            let buffer = buffer.clone();
+
+            async move {
+                while let Some(update) = buffer.pop().await {
+                    deliver(update);
+                }
+
+                buffer.stats.closed.store(true, Ordering::SeqCst);
+            }
+        });
+
+        let producer = get_runtime_handle().spawn(async move {
+            // Aborting the producer drops this guard, which aborts the consumer.
+            let _consumer = AbortOnDrop(consumer);
+
+            pin_mut!(stream);
+
+            while let Some(update) = stream.next().await {
+                merger.observe(&update);
+                buffer.push(update, policy, &mut merger).await;
+            }
+
+            buffer.finish();
+
+            // Wait for the consumer to deliver the remaining updates.
+            buffer.drained.notified().await;
+        });
+
+        Self { task: Arc::new(TaskHandle::new(producer)), stats }
+    }
+
+    /// Forward the updates of the stream with `deliver`, buffering them
+    /// according to the policy if one is given.
+    pub(crate) fn new<S, T, M>(
+        stream: S,
+        policy: Option<SubscriptionBufferingPolicy>,
+        merger: M,
+        deliver: impl FnMut(T) + Send + 'static,
+    ) -> Self
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Send + 'static,
+        M: MergeUpdates<T>,
+    {
+        match policy {
+            Some(policy) => Self::buffered(stream, policy, merger, deliver),
+            None => Self::unbuffered(stream, deliver),
+        }
+    }
+
+    /// The task running this subscription.
+    pub(crate) fn task(&self) -> Arc<TaskHandle> {
+        self.task.clone()
+    }
+}
+
+#[matrix_sdk_ffi_macros::export]
+impl SubscriptionHandle {
+    /// Close the subscription: the listener won't be called anymore, and the
+    /// pending updates are discarded.
+    pub fn close(&self) {
+        self.task.cancel();
+        self.stats.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the subscription has been closed, or the stream has ended and
+    /// all its updates have been delivered.
+    pub fn is_closed(&self) -> bool {
+        self.stats.closed.load(Ordering::SeqCst)
+    }
+
+    /// The number of updates waiting to be delivered to the listener.
+    pub fn num_pending_updates(&self) -> u32 {
+        self.stats.pending.load(Ordering::SeqCst).try_into().unwrap_or(u32::MAX)
+    }
+
+    /// The number of updates that were dropped or merged because the listener
+    /// couldn't keep up.
+    pub fn num_dropped_updates(&self) -> u64 {
+        self.stats.dropped.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for SubscriptionHandle {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[derive(Debug, Default)]
+struct SubscriptionStats {
+    closed: AtomicBool,
+    pending: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// How the pending updates of a subscription can be merged.
+pub(crate) trait MergeUpdates<T>: Send + 'static {
+    /// Observe an update read from the stream, before it's buffered.
+    fn observe(&mut self, _update: &T) {}
+
+    /// Merge all the pending updates into a single one.
+    fn merge(&mut self, pending: &mut VecDeque<T>);
+
+    /// Shrink the pending updates so that at most `capacity` remain.
+    fn trim(&mut self, pending: &mut VecDeque<T>, capacity: usize);
+}
+
+/// Merge state updates: only the most recent states are relevant.
+pub(crate) struct LatestStates;
+
+impl<T> MergeUpdates<T> for LatestStates {
+    fn merge(&mut self, pending: &mut VecDeque<T>) {
+        self.trim(pending, 1);
+    }
+
+    fn trim(&mut self, pending: &mut VecDeque<T>, capacity: usize) {
+        while pending.len() > capacity.max(1) {
+            pending.pop_front();
+        }
+    }
+}
+
+/// Merge batches of diffs over a list.
+///
+/// It keeps a copy of the list, so that pending diffs can be replaced by a
+/// single reset when needed.
+pub(crate) struct VectorDiffs<T: Clone> {
+    items: Vector<T>,
+}
+
+impl<T: Clone> VectorDiffs<T> {
+    /// Create a new merger, for a list starting with the given items.
+    pub(crate) fn new(items: Vector<T>) -> Self {
+        Self { items }
+    }
+
+    fn reset(&self) -> Vec<VectorDiff<T>> {
+        vec![VectorDiff::Reset { values: self.items.clone() }]
+    }
+}
+
+impl<T> MergeUpdates<Vec<VectorDiff<T>>> for VectorDiffs<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn observe(&mut self, diffs: &Vec<VectorDiff<T>>) {
+        for diff in diffs {
+            diff.clone().apply(&mut self.items);
+        }
+    }
+
+    fn merge(&mut self, pending: &mut VecDeque<Vec<VectorDiff<T>>>) {
+        let diffs: Vec<_> = pending.drain(..).flatten().collect();
+
+        if diffs.len() > self.items.len() {
+            pending.push_back(self.reset());
+        } else {
+            pending.push_back(diffs);
+        }
+    }
+
+    fn trim(&mut self, pending: &mut VecDeque<Vec<VectorDiff<T>>>, capacity: usize) {
+        if pending.len() > capacity {
+            pending.clear();
+            pending.push_back(self.reset());
+        }
+    }
+}
+
+/// The buffer between the task reading the stream and the task calling the
+/// listener.
+struct Buffer<T> {
+    pending: Mutex<VecDeque<T>>,
+    finished: AtomicBool,
+    /// Notified when an update has been pushed, or the stream has ended.
+    readable: Notify,
+    /// Notified when an update has been popped.
+    writable: Notify,
+    /// Notified when the stream has ended and all the updates were popped.
+    drained: Notify,
+    stats: Arc<SubscriptionStats>,
+}
+
+impl<T> Buffer<T> {
+    fn new(stats: Arc<SubscriptionStats>) -> Self {
+        Self {
+            pending: Mutex::new(VecDeque::new()),
+            finished: AtomicBool::new(false),
+            readable: Notify::new(),
+            writable: Notify::new(),
+            drained: Notify::new(),
+            stats,
+        }
+    }
+
+    async fn push(
+        &self,
+        update: T,
+        policy: SubscriptionBufferingPolicy,
+        merger: &mut impl MergeUpdates<T>,
+    ) {
+        let mut update = Some(update);
+
+        while let Some(next) = update.take() {
+            {
+                let mut pending = self.pending.lock().unwrap();
+                let len_before = pending.len();
+
+                match policy {
+                    SubscriptionBufferingPolicy::Coalesce => {
+                        pending.push_back(next);
+                        merger.merge(&mut pending);
+                    }
+
+                    SubscriptionBufferingPolicy::DropOldest { capacity } => {
+                        pending.push_back(next);
+                        merger.trim(&mut pending, capacity as usize);
+                    }
+
+                    SubscriptionBufferingPolicy::Block { capacity } => {
+                        if len_before < (capacity as usize).max(1) {
+                            pending.push_back(next);
+                        } else {
+                            update = Some(next);
+                        }
+                    }
+                }
+
+                let dropped = (len_before + 1).saturating_sub(pending.len());
+                if update.is_none() && dropped > 0 {
+                    self.stats.dropped.fetch_add(dropped as u64, Ordering::SeqCst);
+                }
+                self.stats.pending.store(pending.len(), Ordering::SeqCst);
+            }
+
+            if update.is_some() {
+                // The buffer is full, wait for the listener to catch up.
+                self.writable.notified().await;
+            } else {
+                self.readable.notify_one();
+            }
+        }
+    }
+
+    fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+        self.readable.notify_one();
+    }
+
+    /// Get the next update to deliver, or `None` if the stream has ended and
+    /// all its updates have been delivered.
+    async fn pop(&self) -> Option<T> {
+        loop {
+            let next = {
+                let mut pending = self.pending.lock().unwrap();
+                let next = pending.pop_front();
+                self.stats.pending.store(pending.len(), Ordering::SeqCst);
+                next
+            };
+
+            if let Some(next) = next {
+                self.writable.notify_one();
+                return Some(next);
+            }
+
+            if self.finished.load(Ordering::SeqCst) {
+                self.drained.notify_one();
+                return None;
+            }
+
+            self.readable.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use eyeball_im::{Vector, VectorDiff};
+
+    use super::{LatestStates, MergeUpdates, VectorDiffs};
+
+    #[test]
+    fn test_latest_states() {
+        let mut pending = VecDeque::from([1, 2, 3, 4]);
+
+        LatestStates.trim(&mut pending, 2);
+        assert_eq!(pending, [3, 4]);
+
+        LatestStates.merge(&mut pending);
+        assert_eq!(pending, [4]);
+    }
+
+    #[test]
+    fn test_vector_diffs_are_merged() {
+        let mut merger = VectorDiffs::new(Vector::from(vec![1, 2, 3]));
+
+        let first = vec![VectorDiff::PushBack { value: 4 }];
+        let second = vec![VectorDiff::Remove { index: 0 }];
+        merger.observe(&first);
+        merger.observe(&second);
+
+        // The diffs are shorter than the list, so they are kept.
+        let mut pending = VecDeque::from([first, second]);
+        merger.merge(&mut pending);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].len(), 2);
+    }
+
+    #[test]
+    fn test_vector_diffs_are_trimmed_into_a_reset() {
+        let mut merger = VectorDiffs::new(Vector::new());
+
+        let mut pending = VecDeque::new();
+        for value in 0..3 {
+            let diffs = vec![VectorDiff::PushBack { value }];
+            merger.observe(&diffs);
+            pending.push_back(diffs);
+        }
+
+        merger.trim(&mut pending, 2);
+
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].len(), 1);
+        assert_eq!(pending[0][0], VectorDiff::Reset { values: Vector::from(vec![0, 1, 2]) });
+    }
+}
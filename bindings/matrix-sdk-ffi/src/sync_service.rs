@@ -29,7 +29,11 @@ use matrix_sdk_ui::{
 use tracing::error;
 
 use crate::{
-    error::ClientError, helpers::unwrap_or_clone_arc, room_list::RoomListService, TaskHandle,
+    error::ClientError,
+    helpers::unwrap_or_clone_arc,
+    room_list::RoomListService,
+    subscription::{LatestStates, SubscriptionBufferingPolicy, SubscriptionHandle},
+    TaskHandle,
 };
 
 #[derive(uniffi::Enum)]
@@ -92,6 +96,21 @@ impl SyncService {
             }
         })))
     }
+
+    /// Like [`Self::state`], but with updates buffered according to the given
+    /// policy.
+    pub fn subscribe_to_state(
+        &self,
+        listener: Box<dyn SyncServiceStateObserver>,
+        buffering: SubscriptionBufferingPolicy,
+    ) -> Arc<SubscriptionHandle> {
+        Arc::new(SubscriptionHandle::buffered(
+            self.inner.state(),
+            buffering,
+            LatestStates,
+            move |state| listener.on_update(state.into()),
+        ))
+    }
 }
 
 #[derive(Clone, uniffi::Object)]
@@ -211,6 +230,11 @@ pub struct UnableToDecryptInfo {
     /// we were not a member of this room?
     pub cause: UtdCause,
 
+    /// Whether this UTD was expected, because the keys for the event were
+    /// never meant to be shared with us. Expected UTDs shouldn't be reported
+    /// as decryption failures.
+    pub is_expected: bool,
+
     /// The difference between the event creation time (`origin_server_ts`) and
     /// the time our device was created. If negative, this event was sent
     /// *before* our device was created.
@@ -234,6 +258,7 @@ impl From<SdkUnableToDecryptInfo> for UnableToDecryptInfo {
             event_id: value.event_id.to_string(),
             time_to_decrypt_ms: value.time_to_decrypt.map(|ttd| ttd.as_millis() as u64),
             cause: value.cause,
+            is_expected: value.is_expected(),
             event_local_age_millis: value.event_local_age_millis,
             user_trusts_own_identity: value.user_trusts_own_identity,
             sender_homeserver: value.sender_homeserver.to_string(),
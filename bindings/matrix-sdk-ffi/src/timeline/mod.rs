@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, fmt::Write as _, fs, panic, sync::Arc};
+use std::{collections::HashMap, fs, panic, sync::Arc};
 
 use anyhow::{Context, Result};
 use as_variant::as_variant;
@@ -28,6 +28,7 @@ use matrix_sdk::{
     event_cache::RoomPaginationStatus,
     room::{
         edit::EditedContent as SdkEditedContent,
+        polls::NewPoll,
         reply::{EnforceThread, Reply},
     },
 };
@@ -40,20 +41,11 @@ use reply::{InReplyToDetails, RepliedToEventDetails};
 use ruma::{
     events::{
         location::{AssetType as RumaAssetType, LocationContent, ZoomLevel},
-        poll::{
-            unstable_end::UnstablePollEndEventContent,
-            unstable_response::UnstablePollResponseEventContent,
-            unstable_start::{
-                NewUnstablePollStartEventContent, UnstablePollAnswer, UnstablePollAnswers,
-                UnstablePollStartContentBlock,
-            },
-        },
         receipt::ReceiptThread,
         room::message::{
             LocationMessageEventContent, MessageType, ReplyWithinThread,
             RoomMessageEventContentWithoutRelation,
         },
-        AnyMessageLikeEventContent,
     },
     EventId, UInt,
 };
@@ -62,7 +54,6 @@ use tokio::{
     task::{AbortHandle, JoinHandle},
 };
 use tracing::{error, warn};
-use uuid::Uuid;
 
 use self::content::TimelineItemContent;
 pub use self::msg_like::MessageContent;
@@ -75,6 +66,7 @@ use crate::{
         AssetType, AudioInfo, FileInfo, FormattedBody, ImageInfo, Mentions, PollKind,
         ThumbnailInfo, VideoInfo,
     },
+    subscription::{SubscriptionBufferingPolicy, SubscriptionHandle, VectorDiffs},
     task_handle::TaskHandle,
     utils::Timestamp,
 };
@@ -274,6 +266,32 @@ impl Timeline {
         })))
     }
 
+    /// Like [`Self::add_listener`], but with updates buffered according to
+    /// the given policy, for listeners that can't keep up with the timeline.
+    pub async fn add_listener_with_buffering(
+        &self,
+        listener: Box<dyn TimelineListener>,
+        buffering: SubscriptionBufferingPolicy,
+    ) -> Arc<SubscriptionHandle> {
+        let (timeline_items, timeline_stream) = self.inner.subscribe().await;
+
+        // Same as in `add_listener`, the initial items must be passed before the
+        // stream updates.
+        listener.on_update(vec![Arc::new(TimelineDiff::new(VectorDiff::Reset {
+            values: timeline_items.clone(),
+        }))]);
+
+        Arc::new(SubscriptionHandle::buffered(
+            timeline_stream,
+            buffering,
+            VectorDiffs::new(timeline_items),
+            move |diffs| {
+                listener
+                    .on_update(diffs.into_iter().map(|d| Arc::new(TimelineDiff::new(d))).collect())
+            },
+        ))
+    }
+
     pub fn retry_decryption(self: Arc<Self>, session_ids: Vec<String>) {
         get_runtime_handle().spawn(async move {
             self.inner.retry_decryption(&session_ids).await;
@@ -451,14 +469,7 @@ impl Timeline {
     ) -> Result<(), ClientError> {
         let poll_data = PollData { question, answers, max_selections, poll_kind };
 
-        let poll_start_event_content = NewUnstablePollStartEventContent::plain_text(
-            poll_data.fallback_text(),
-            poll_data.try_into()?,
-        );
-        let event_content =
-            AnyMessageLikeEventContent::UnstablePollStart(poll_start_event_content.into());
-
-        if let Err(err) = self.inner.send(event_content).await {
+        if let Err(err) = self.inner.send_poll(poll_data.try_into()?).await {
             error!("unable to start poll: {err}");
         }
 
@@ -472,12 +483,8 @@ impl Timeline {
     ) -> Result<(), ClientError> {
         let poll_start_event_id =
             EventId::parse(poll_start_event_id).context("Failed to parse EventId")?;
-        let poll_response_event_content =
-            UnstablePollResponseEventContent::new(answers, poll_start_event_id);
-        let event_content =
-            AnyMessageLikeEventContent::UnstablePollResponse(poll_response_event_content);
 
-        if let Err(err) = self.inner.send(event_content).await {
+        if let Err(err) = self.inner.send_poll_response(&poll_start_event_id, answers).await {
             error!("unable to send poll response: {err}");
         }
 
@@ -491,10 +498,8 @@ impl Timeline {
     ) -> Result<(), ClientError> {
         let poll_start_event_id =
             EventId::parse(poll_start_event_id).context("Failed to parse EventId")?;
-        let poll_end_event_content = UnstablePollEndEventContent::new(text, poll_start_event_id);
-        let event_content = AnyMessageLikeEventContent::UnstablePollEnd(poll_end_event_content);
 
-        if let Err(err) = self.inner.send(event_content).await {
+        if let Err(err) = self.inner.end_poll(&poll_start_event_id, text).await {
             error!("unable to end poll: {err}");
         }
 
@@ -684,6 +689,22 @@ impl Timeline {
         }
     }
 
+    /// Walk the reply chain of the given event, from the event it replies to
+    /// up to its oldest ancestor, or at most `max_depth` ancestors.
+    ///
+    /// Ancestors which aren't in the timeline are loaded from the event cache
+    /// or fetched from the server. The walk stops at the first ancestor that
+    /// couldn't be loaded.
+    pub async fn replied_to_chain(
+        &self,
+        event_id: String,
+        max_depth: u32,
+    ) -> Result<Vec<Arc<InReplyToDetails>>, ClientError> {
+        let event_id = EventId::parse(event_id)?;
+        let chain = self.inner.replied_to_chain(&event_id, max_depth as usize).await?;
+        Ok(chain.into_iter().map(|details| Arc::new(details.into())).collect())
+    }
+
     /// Adds a new pinned event by sending an updated `m.room.pinned_events`
     /// event containing the new event id.
     ///
@@ -1143,34 +1164,14 @@ pub struct PollData {
     poll_kind: PollKind,
 }
 
-impl PollData {
-    fn fallback_text(&self) -> String {
-        self.answers.iter().enumerate().fold(self.question.clone(), |mut acc, (index, answer)| {
-            write!(&mut acc, "\n{}. {answer}", index + 1).unwrap();
-            acc
-        })
-    }
-}
-
-impl TryFrom<PollData> for UnstablePollStartContentBlock {
+impl TryFrom<PollData> for NewPoll {
     type Error = ClientError;
 
     fn try_from(value: PollData) -> Result<Self, Self::Error> {
-        let poll_answers_vec: Vec<UnstablePollAnswer> = value
-            .answers
-            .iter()
-            .map(|answer| UnstablePollAnswer::new(Uuid::new_v4().to_string(), answer))
-            .collect();
-
-        let poll_answers = UnstablePollAnswers::try_from(poll_answers_vec)
-            .context("Failed to create poll answers")?;
-
-        let mut poll_content_block =
-            UnstablePollStartContentBlock::new(value.question.clone(), poll_answers);
-        poll_content_block.kind = value.poll_kind.into();
-        poll_content_block.max_selections = value.max_selections.into();
-
-        Ok(poll_content_block)
+        Ok(NewPoll::new(value.question, value.answers)
+            .context("Failed to create poll answers")?
+            .kind(value.poll_kind.into())
+            .max_selections(value.max_selections))
     }
 }
 
@@ -1284,10 +1285,10 @@ impl TryFrom<EditedContent> for SdkEditedContent {
                 })
             }
             EditedContent::PollStart { poll_data } => {
-                let block: UnstablePollStartContentBlock = poll_data.clone().try_into()?;
+                let poll: NewPoll = poll_data.clone().try_into()?;
                 Ok(SdkEditedContent::PollStart {
-                    fallback_text: poll_data.fallback_text(),
-                    new_content: block,
+                    fallback_text: poll.fallback_text(),
+                    new_content: poll.into_content_block(),
                 })
             }
         }
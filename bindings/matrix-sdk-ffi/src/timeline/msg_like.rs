@@ -211,6 +211,10 @@ pub enum EncryptedMessage {
         /// What we know about what caused this UTD. E.g. was this event sent
         /// when we were not a member of this room?
         cause: UtdCause,
+
+        /// Whether we expected to be unable to decrypt this message, because
+        /// its keys were never meant to be shared with us.
+        is_expected_utd: bool,
     },
     Unknown,
 }
@@ -226,7 +230,11 @@ impl EncryptedMessage {
             }
             Message::MegolmV1AesSha2 { session_id, cause, .. } => {
                 let session_id = session_id.clone();
-                Self::MegolmV1AesSha2 { session_id, cause: *cause }
+                Self::MegolmV1AesSha2 {
+                    session_id,
+                    cause: *cause,
+                    is_expected_utd: cause.is_expected(),
+                }
             }
             Message::Unknown => Self::Unknown,
         }
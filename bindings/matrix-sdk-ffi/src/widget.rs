@@ -4,7 +4,7 @@ use async_compat::get_runtime_handle;
 use language_tags::LanguageTag;
 use matrix_sdk::{
     async_trait,
-    widget::{MessageLikeEventFilter, StateEventFilter},
+    widget::{MessageLikeEventFilter, StateEventFilter, ToDeviceEventFilter},
 };
 use ruma::events::MessageLikeEventType;
 use tracing::error;
@@ -320,11 +320,14 @@ pub fn get_element_call_required_permissions(
         WidgetEventFilter::MessageLikeWithType {
             event_type: "org.matrix.rageshake_request".to_owned(),
         },
-        // To read and send encryption keys
-        // TODO change this to the appropriate to-device version once ready
+        // To read and send encryption keys, in the room for the older versions of
+        // Element Call, and with to-device events for the newer ones.
         WidgetEventFilter::MessageLikeWithType {
             event_type: "io.element.call.encryption_keys".to_owned(),
         },
+        WidgetEventFilter::ToDeviceWithType {
+            event_type: "io.element.call.encryption_keys".to_owned(),
+        },
         // To read and send custom EC reactions. They are different to normal `m.reaction`
         // because they can be send multiple times to the same event.
         WidgetEventFilter::MessageLikeWithType {
@@ -477,7 +480,8 @@ impl From<matrix_sdk::widget::Capabilities> for WidgetCapabilities {
     }
 }
 
-/// Different kinds of filters that could be applied to the timeline events.
+/// Different kinds of filters that could be applied to the timeline and
+/// to-device events.
 #[derive(uniffi::Enum, Clone)]
 pub enum WidgetEventFilter {
     /// Matches message-like events with the given `type`.
@@ -488,6 +492,8 @@ pub enum WidgetEventFilter {
     StateWithType { event_type: String },
     /// Matches state events with the given `type` and `state_key`.
     StateWithTypeAndStateKey { event_type: String, state_key: String },
+    /// Matches to-device events with the given `type`.
+    ToDeviceWithType { event_type: String },
 }
 
 impl From<WidgetEventFilter> for matrix_sdk::widget::EventFilter {
@@ -505,6 +511,9 @@ impl From<WidgetEventFilter> for matrix_sdk::widget::EventFilter {
             WidgetEventFilter::StateWithTypeAndStateKey { event_type, state_key } => {
                 Self::State(StateEventFilter::WithTypeAndStateKey(event_type.into(), state_key))
             }
+            WidgetEventFilter::ToDeviceWithType { event_type } => {
+                Self::ToDevice(ToDeviceEventFilter::new(event_type.into()))
+            }
         }
     }
 }
@@ -526,6 +535,9 @@ impl From<matrix_sdk::widget::EventFilter> for WidgetEventFilter {
             F::State(StateEventFilter::WithTypeAndStateKey(event_type, state_key)) => {
                 Self::StateWithTypeAndStateKey { event_type: event_type.to_string(), state_key }
             }
+            F::ToDevice(ToDeviceEventFilter { event_type }) => {
+                Self::ToDeviceWithType { event_type: event_type.to_string() }
+            }
         }
     }
 }
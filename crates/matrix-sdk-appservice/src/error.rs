@@ -0,0 +1,52 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{ClientBuildError, HttpError};
+use ruma::{IdParseError, OwnedUserId};
+use thiserror::Error;
+
+/// Result type of the application service.
+pub type Result<T, E = AppServiceError> = std::result::Result<T, E>;
+
+/// An error from the application service.
+#[derive(Debug, Error)]
+pub enum AppServiceError {
+    /// A regular expression of the namespaces of the registration is invalid.
+    #[error("invalid namespace in the registration: {0}")]
+    InvalidNamespace(#[from] regex::Error),
+
+    /// The user isn't in the users namespace of the application service.
+    #[error("the user {0} isn't in the namespace of the application service")]
+    UserNotInNamespace(OwnedUserId),
+
+    /// A user ID couldn't be built from a localpart.
+    #[error(transparent)]
+    IdParse(#[from] IdParseError),
+
+    /// A client couldn't be built.
+    #[error(transparent)]
+    ClientBuild(#[from] ClientBuildError),
+
+    /// An error occurred in a client.
+    #[error(transparent)]
+    Sdk(#[from] matrix_sdk::Error),
+
+    /// An HTTP request to the homeserver failed.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// The webserver receiving the requests of the homeserver failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
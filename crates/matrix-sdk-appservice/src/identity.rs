@@ -0,0 +1,88 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Identity assertion, allowing an application service to send requests on
+//! behalf of the users in its namespace.
+//!
+//! See [the specification] for more details.
+//!
+//! [the specification]: https://spec.matrix.org/v1.14/application-service-api/#identity-assertion
+
+use http::{uri::PathAndQuery, Uri};
+use matrix_sdk::{bytes::Bytes, HttpMiddleware};
+use ruma::OwnedUserId;
+use tracing::warn;
+
+/// A middleware adding the `user_id` query parameter to all the requests, to
+/// masquerade as the given user.
+#[derive(Debug)]
+pub(crate) struct AssertIdentity {
+    user_id: OwnedUserId,
+}
+
+impl AssertIdentity {
+    pub fn new(user_id: OwnedUserId) -> Self {
+        Self { user_id }
+    }
+
+    fn assert_identity(&self, uri: &Uri) -> Option<Uri> {
+        let path_and_query = uri.path_and_query()?;
+        let query = url::form_urlencoded::Serializer::for_suffix(
+            path_and_query.query().map(ToOwned::to_owned).unwrap_or_default(),
+            0,
+        )
+        .append_pair("user_id", self.user_id.as_str())
+        .finish();
+
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query =
+            Some(PathAndQuery::try_from(format!("{}?{query}", path_and_query.path())).ok()?);
+
+        Uri::from_parts(parts).ok()
+    }
+}
+
+impl HttpMiddleware for AssertIdentity {
+    fn on_request(&self, request: &mut http::Request<Bytes>) {
+        match self.assert_identity(request.uri()) {
+            Some(uri) => *request.uri_mut() = uri,
+            None => warn!(uri = %request.uri(), "Couldn't assert the identity of the request"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Uri;
+    use ruma::owned_user_id;
+
+    use super::AssertIdentity;
+
+    #[test]
+    fn test_assert_identity() {
+        let middleware = AssertIdentity::new(owned_user_id!("@_bridge_alice:localhost"));
+
+        let uri: Uri = "https://localhost/_matrix/client/v3/account/whoami".parse().unwrap();
+        assert_eq!(
+            middleware.assert_identity(&uri).unwrap(),
+            "https://localhost/_matrix/client/v3/account/whoami?user_id=%40_bridge_alice%3Alocalhost"
+        );
+
+        let uri: Uri = "https://localhost/_matrix/client/v3/sync?timeout=0".parse().unwrap();
+        assert_eq!(
+            middleware.assert_identity(&uri).unwrap(),
+            "https://localhost/_matrix/client/v3/sync?timeout=0&user_id=%40_bridge_alice%3Alocalhost"
+        );
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![warn(missing_docs)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use matrix_sdk::{
+    authentication::matrix::MatrixSession, config::RequestConfig, store::RoomLoadSettings,
+    BoxFuture, Client, ClientBuilder, SessionMeta, SessionTokens,
+};
+use ruma::{
+    api::{
+        appservice::Registration,
+        client::{
+            account::register::{self, LoginType},
+            appservice::request_ping,
+            error::ErrorKind,
+        },
+    },
+    device_id,
+    events::AnyTimelineEvent,
+    OwnedRoomAliasId, OwnedServerName, OwnedTransactionId, OwnedUserId, TransactionId, UserId,
+};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tracing::{debug, instrument};
+use url::Url;
+
+mod error;
+mod identity;
+mod namespaces;
+mod server;
+
+use self::identity::AssertIdentity;
+pub use self::{
+    error::{AppServiceError, Result},
+    namespaces::Namespaces,
+};
+
+/// The number of transaction IDs remembered to ignore transactions sent again
+/// by the homeserver.
+const COMPLETED_TRANSACTIONS_CAPACITY: usize = 128;
+
+type EventHandler =
+    Arc<dyn Fn(AnyTimelineEvent, AppService) -> BoxFuture<'static, ()> + Send + Sync>;
+type UserQueryHandler =
+    Arc<dyn Fn(OwnedUserId, AppService) -> BoxFuture<'static, bool> + Send + Sync>;
+type RoomAliasQueryHandler =
+    Arc<dyn Fn(OwnedRoomAliasId, AppService) -> BoxFuture<'static, bool> + Send + Sync>;
+
+/// A builder for an [`AppService`].
+#[derive(Debug)]
+pub struct AppServiceBuilder {
+    homeserver_url: Url,
+    server_name: OwnedServerName,
+    registration: Registration,
+    client_builder: Option<ClientBuilder>,
+}
+
+impl AppServiceBuilder {
+    /// Set the builder used to create the clients of the application service.
+    ///
+    /// The homeserver URL and the session of the clients are overridden.
+    /// Because the builder is used for every virtual user, it must not use
+    /// a persistent store shared between users.
+    pub fn client_builder(mut self, client_builder: ClientBuilder) -> Self {
+        self.client_builder = Some(client_builder);
+        self
+    }
+
+    /// Build the application service.
+    pub async fn build(self) -> Result<AppService> {
+        let namespaces = Namespaces::new(&self.registration.namespaces)?;
+        let sender_user_id = UserId::parse_with_server_name(
+            self.registration.sender_localpart.as_str(),
+            &self.server_name,
+        )?;
+
+        let appservice = AppService {
+            inner: Arc::new(AppServiceInner {
+                homeserver_url: self.homeserver_url,
+                server_name: self.server_name,
+                registration: self.registration,
+                namespaces,
+                sender_user_id,
+                client_builder: self.client_builder.unwrap_or_else(Client::builder),
+                clients: Default::default(),
+                event_handlers: Default::default(),
+                user_query_handler: Default::default(),
+                room_alias_query_handler: Default::default(),
+                completed_transactions: Default::default(),
+            }),
+        };
+
+        // Make sure the main client can be created.
+        appservice.client().await?;
+
+        Ok(appservice)
+    }
+}
+
+/// An application service, i.e. a privileged service that receives the events
+/// of the rooms it's interested in from the homeserver, and can act on behalf
+/// of the users in its namespace.
+///
+/// See [the specification] for more details.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk_appservice::AppService;
+/// use ruma::{api::appservice::Registration, events::AnyTimelineEvent, server_name};
+/// use url::Url;
+///
+/// # async fn example(registration: Registration) -> Result<(), Box<dyn std::error::Error>> {
+/// let appservice = AppService::builder(
+///     Url::parse("http://localhost:8008")?,
+///     server_name!("localhost").to_owned(),
+///     registration,
+/// )
+/// .build()
+/// .await?;
+///
+/// appservice.add_event_handler(|event: AnyTimelineEvent, appservice: AppService| async move {
+///     println!("Received {} in {}", event.event_type(), event.room_id());
+/// });
+///
+/// appservice.run("127.0.0.1:9000").await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [the specification]: https://spec.matrix.org/v1.14/application-service-api/
+#[derive(Clone)]
+pub struct AppService {
+    inner: Arc<AppServiceInner>,
+}
+
+struct AppServiceInner {
+    homeserver_url: Url,
+    server_name: OwnedServerName,
+    registration: Registration,
+    namespaces: Namespaces,
+    sender_user_id: OwnedUserId,
+    client_builder: ClientBuilder,
+    /// The clients of the virtual users, and of the sender user.
+    clients: Mutex<HashMap<OwnedUserId, Client>>,
+    event_handlers: RwLock<Vec<EventHandler>>,
+    user_query_handler: RwLock<Option<UserQueryHandler>>,
+    room_alias_query_handler: RwLock<Option<RoomAliasQueryHandler>>,
+    /// The IDs of the most recent transactions that were handled.
+    completed_transactions: Mutex<VecDeque<OwnedTransactionId>>,
+}
+
+impl AppService {
+    /// Create a builder for an application service.
+    ///
+    /// # Arguments
+    ///
+    /// * `homeserver_url` - The URL of the homeserver's client-server API.
+    /// * `server_name` - The name of the homeserver, used to build the user IDs
+    ///   of the virtual users.
+    /// * `registration` - The registration of the application service with the
+    ///   homeserver.
+    pub fn builder(
+        homeserver_url: Url,
+        server_name: OwnedServerName,
+        registration: Registration,
+    ) -> AppServiceBuilder {
+        AppServiceBuilder { homeserver_url, server_name, registration, client_builder: None }
+    }
+
+    /// The registration of the application service.
+    pub fn registration(&self) -> &Registration {
+        &self.inner.registration
+    }
+
+    /// The namespaces of the application service.
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.inner.namespaces
+    }
+
+    /// Build the user ID of the user with the given localpart on the
+    /// homeserver.
+    pub fn user_id(&self, localpart: &str) -> Result<OwnedUserId> {
+        Ok(UserId::parse_with_server_name(localpart, &self.inner.server_name)?)
+    }
+
+    /// Get the client of the application service's own user, i.e. the
+    /// `sender_localpart` of the registration.
+    pub async fn client(&self) -> Result<Client> {
+        self.client_for(&self.inner.sender_user_id).await
+    }
+
+    /// Get a client acting on behalf of the virtual user with the given
+    /// localpart.
+    ///
+    /// The user must be in the users namespace of the application service. It
+    /// may need to be registered first with
+    /// [`AppService::register_virtual_user()`].
+    ///
+    /// The clients are cached, so calling this method several times for the
+    /// same user returns the same client.
+    pub async fn virtual_user(&self, localpart: &str) -> Result<Client> {
+        let user_id = self.user_id(localpart)?;
+
+        if user_id != self.inner.sender_user_id && !self.inner.namespaces.contains_user(&user_id) {
+            return Err(AppServiceError::UserNotInNamespace(user_id));
+        }
+
+        self.client_for(&user_id).await
+    }
+
+    /// Register the virtual user with the given localpart on the homeserver.
+    ///
+    /// It's not an error if the user is already registered.
+    #[instrument(skip(self))]
+    pub async fn register_virtual_user(&self, localpart: &str) -> Result<()> {
+        let user_id = self.user_id(localpart)?;
+
+        if !self.inner.namespaces.contains_user(&user_id) {
+            return Err(AppServiceError::UserNotInNamespace(user_id));
+        }
+
+        let mut request = register::v3::Request::new();
+        request.username = Some(localpart.to_owned());
+        request.login_type = Some(LoginType::ApplicationService);
+        request.inhibit_login = true;
+
+        let client = self.client().await?;
+        match client.send(request).with_request_config(RequestConfig::new().force_auth()).await {
+            Ok(_) => Ok(()),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::UserInUse) => {
+                debug!("The virtual user is already registered");
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Ask the homeserver to ping the application service, to check that the
+    /// homeserver can reach it.
+    ///
+    /// Returns the time the homeserver took to reach the application service.
+    pub async fn ping_homeserver(&self) -> Result<Duration> {
+        let mut request = request_ping::v1::Request::new(self.inner.registration.id.clone());
+        request.transaction_id = Some(TransactionId::new());
+
+        let response = self.client().await?.send(request).await?;
+        Ok(response.duration)
+    }
+
+    /// Register a handler for the events pushed by the homeserver.
+    ///
+    /// The handlers are called one after the other for every event of a
+    /// transaction, and the transaction is acknowledged once all of them
+    /// returned.
+    pub fn add_event_handler<F, Fut>(&self, handler: F)
+    where
+        F: Fn(AnyTimelineEvent, AppService) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.inner
+            .event_handlers
+            .write()
+            .unwrap()
+            .push(Arc::new(move |event, appservice| Box::pin(handler(event, appservice))));
+    }
+
+    /// Set the handler called when the homeserver queries whether a user in
+    /// the namespace of the application service exists.
+    ///
+    /// The handler returns whether the user exists, in which case it must have
+    /// been registered with [`AppService::register_virtual_user()`]. Without a
+    /// handler, users are reported as not existing.
+    pub fn on_user_query<F, Fut>(&self, handler: F)
+    where
+        F: Fn(OwnedUserId, AppService) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        *self.inner.user_query_handler.write().unwrap() =
+            Some(Arc::new(move |user_id, appservice| Box::pin(handler(user_id, appservice))));
+    }
+
+    /// Set the handler called when the homeserver queries whether a room alias
+    /// in the namespace of the application service exists.
+    ///
+    /// The handler returns whether the room exists, in which case it must have
+    /// been created with this alias. Without a handler, room aliases are
+    /// reported as not existing.
+    pub fn on_room_alias_query<F, Fut>(&self, handler: F)
+    where
+        F: Fn(OwnedRoomAliasId, AppService) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        *self.inner.room_alias_query_handler.write().unwrap() =
+            Some(Arc::new(move |alias, appservice| Box::pin(handler(alias, appservice))));
+    }
+
+    /// The router of the webserver receiving the requests of the homeserver.
+    ///
+    /// It can be used to embed the application service in an existing
+    /// [`axum`] server. Otherwise, use [`AppService::run()`].
+    pub fn router(&self) -> axum::Router {
+        server::router(self.clone())
+    }
+
+    /// Run the webserver receiving the requests of the homeserver on the
+    /// given address.
+    ///
+    /// This only returns if the webserver fails.
+    pub async fn run(&self, address: impl ToSocketAddrs) -> Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    async fn client_for(&self, user_id: &UserId) -> Result<Client> {
+        if let Some(client) = self.inner.clients.lock().unwrap().get(user_id) {
+            return Ok(client.clone());
+        }
+
+        let mut builder =
+            self.inner.client_builder.clone().homeserver_url(self.inner.homeserver_url.as_str());
+
+        if user_id != self.inner.sender_user_id {
+            builder = builder.add_http_middleware(AssertIdentity::new(user_id.to_owned()));
+        }
+
+        let client = builder.build().await?;
+
+        let session = MatrixSession {
+            meta: SessionMeta {
+                user_id: user_id.to_owned(),
+                // Users controlled by an application service don't have a device.
+                device_id: device_id!("APPSERVICE").to_owned(),
+            },
+            tokens: SessionTokens {
+                access_token: self.inner.registration.as_token.clone(),
+                refresh_token: None,
+            },
+        };
+        client.matrix_auth().restore_session(session, RoomLoadSettings::default()).await?;
+
+        // Another task might have created the client in the meantime, keep the first
+        // one.
+        Ok(self.inner.clients.lock().unwrap().entry(user_id.to_owned()).or_insert(client).clone())
+    }
+
+    /// Whether the transaction with the given ID was already handled.
+    fn is_transaction_completed(&self, txn_id: &TransactionId) -> bool {
+        self.inner.completed_transactions.lock().unwrap().iter().any(|id| id == txn_id)
+    }
+
+    fn mark_transaction_completed(&self, txn_id: OwnedTransactionId) {
+        let mut completed = self.inner.completed_transactions.lock().unwrap();
+
+        if completed.len() == COMPLETED_TRANSACTIONS_CAPACITY {
+            completed.pop_front();
+        }
+
+        completed.push_back(txn_id);
+    }
+}
+
+impl std::fmt::Debug for AppService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AppService")
+            .field("homeserver_url", &self.inner.homeserver_url)
+            .field("id", &self.inner.registration.id)
+            .field("sender_user_id", &self.inner.sender_user_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+matrix_sdk_test::init_tracing_for_tests!();
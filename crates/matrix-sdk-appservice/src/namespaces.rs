@@ -0,0 +1,135 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Matching of the user IDs, room aliases and room IDs against the namespaces
+//! of an application service.
+
+use regex::Regex;
+use ruma::{api::appservice::Namespace, RoomAliasId, RoomId, UserId};
+
+use crate::Result;
+
+/// The compiled regular expressions of a namespace.
+#[derive(Clone, Debug)]
+struct CompiledNamespace {
+    regex: Regex,
+    exclusive: bool,
+}
+
+impl CompiledNamespace {
+    fn compile(namespaces: &[Namespace]) -> Result<Vec<Self>> {
+        namespaces
+            .iter()
+            .map(|namespace| {
+                // The regular expressions of the registration must match whole IDs.
+                let regex = Regex::new(&format!("^(?:{})$", namespace.regex))?;
+                Ok(Self { regex, exclusive: namespace.exclusive })
+            })
+            .collect()
+    }
+}
+
+/// The namespaces of an application service, i.e. the users, room aliases
+/// and rooms it's interested in.
+#[derive(Clone, Debug)]
+pub struct Namespaces {
+    users: Vec<CompiledNamespace>,
+    aliases: Vec<CompiledNamespace>,
+    rooms: Vec<CompiledNamespace>,
+}
+
+impl Namespaces {
+    pub(crate) fn new(namespaces: &ruma::api::appservice::Namespaces) -> Result<Self> {
+        Ok(Self {
+            users: CompiledNamespace::compile(&namespaces.users)?,
+            aliases: CompiledNamespace::compile(&namespaces.aliases)?,
+            rooms: CompiledNamespace::compile(&namespaces.rooms)?,
+        })
+    }
+
+    /// Whether the given user is in the users namespace.
+    pub fn contains_user(&self, user_id: &UserId) -> bool {
+        Self::matches(&self.users, user_id.as_str())
+    }
+
+    /// Whether the given user is in an exclusive users namespace, i.e. only
+    /// this application service can create and control it.
+    pub fn is_user_exclusive(&self, user_id: &UserId) -> bool {
+        Self::matches_exclusive(&self.users, user_id.as_str())
+    }
+
+    /// Whether the given room alias is in the aliases namespace.
+    pub fn contains_alias(&self, alias: &RoomAliasId) -> bool {
+        Self::matches(&self.aliases, alias.as_str())
+    }
+
+    /// Whether the given room alias is in an exclusive aliases namespace.
+    pub fn is_alias_exclusive(&self, alias: &RoomAliasId) -> bool {
+        Self::matches_exclusive(&self.aliases, alias.as_str())
+    }
+
+    /// Whether the given room is in the rooms namespace.
+    pub fn contains_room(&self, room_id: &RoomId) -> bool {
+        Self::matches(&self.rooms, room_id.as_str())
+    }
+
+    fn matches(namespaces: &[CompiledNamespace], id: &str) -> bool {
+        namespaces.iter().any(|namespace| namespace.regex.is_match(id))
+    }
+
+    fn matches_exclusive(namespaces: &[CompiledNamespace], id: &str) -> bool {
+        namespaces.iter().any(|namespace| namespace.exclusive && namespace.regex.is_match(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{api::appservice::Namespace, room_alias_id, room_id, user_id};
+
+    use super::Namespaces;
+
+    #[test]
+    fn test_namespaces_match_whole_ids() {
+        let mut registration_namespaces = ruma::api::appservice::Namespaces::new();
+        registration_namespaces
+            .users
+            .push(Namespace::new(true, r"@_bridge_.*:localhost".to_owned()));
+        registration_namespaces
+            .users
+            .push(Namespace::new(false, r"@shared_.*:localhost".to_owned()));
+        registration_namespaces.aliases.push(Namespace::new(true, r"#_bridge_.*".to_owned()));
+
+        let namespaces = Namespaces::new(&registration_namespaces).unwrap();
+
+        assert!(namespaces.contains_user(user_id!("@_bridge_alice:localhost")));
+        assert!(namespaces.is_user_exclusive(user_id!("@_bridge_alice:localhost")));
+        assert!(namespaces.contains_user(user_id!("@shared_bob:localhost")));
+        assert!(!namespaces.is_user_exclusive(user_id!("@shared_bob:localhost")));
+        // The regex must match the whole user ID.
+        assert!(!namespaces.contains_user(user_id!("@_bridge_alice:localhost.evil")));
+        assert!(!namespaces.contains_user(user_id!("@alice:localhost")));
+
+        assert!(namespaces.contains_alias(room_alias_id!("#_bridge_room:localhost")));
+        assert!(!namespaces.contains_alias(room_alias_id!("#room:localhost")));
+        assert!(!namespaces.contains_room(room_id!("!room:localhost")));
+    }
+
+    #[test]
+    fn test_invalid_namespace_regex() {
+        let mut registration_namespaces = ruma::api::appservice::Namespaces::new();
+        registration_namespaces.rooms.push(Namespace::new(false, "(".to_owned()));
+
+        Namespaces::new(&registration_namespaces).unwrap_err();
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The webserver receiving the requests of the homeserver.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use ruma::{
+    events::AnyTimelineEvent, serde::Raw, OwnedRoomAliasId, OwnedTransactionId, OwnedUserId,
+};
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tracing::{debug, instrument, warn};
+
+use crate::AppService;
+
+/// The body of a transaction pushed by the homeserver.
+#[derive(Debug, Deserialize)]
+struct Transaction {
+    events: Vec<Raw<AnyTimelineEvent>>,
+}
+
+pub(crate) fn router(appservice: AppService) -> Router {
+    Router::new()
+        .route("/_matrix/app/v1/transactions/{txn_id}", put(push_transaction))
+        .route("/_matrix/app/v1/users/{user_id}", get(query_user))
+        .route("/_matrix/app/v1/rooms/{room_alias}", get(query_room_alias))
+        .route("/_matrix/app/v1/ping", post(ping))
+        .route_layer(middleware::from_fn_with_state(appservice.clone(), authenticate))
+        .with_state(appservice)
+}
+
+fn error_response(status: StatusCode, errcode: &str, error: &str) -> Response {
+    (status, Json(json!({ "errcode": errcode, "error": error }))).into_response()
+}
+
+fn empty_response() -> Response {
+    Json(json!({})).into_response()
+}
+
+/// Check that the request was sent by the homeserver, with the `hs_token` of
+/// the registration.
+async fn authenticate(
+    State(appservice): State<AppService>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let header_token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(ToOwned::to_owned);
+
+    // Older homeservers send the token in the query string.
+    let token = header_token.or_else(|| {
+        url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+            .find(|(key, _)| key == "access_token")
+            .map(|(_, value)| value.into_owned())
+    });
+
+    let Some(token) = token else {
+        return error_response(StatusCode::UNAUTHORIZED, "M_UNAUTHORIZED", "Missing token");
+    };
+
+    // Compare the tokens in constant time, to not leak the expected token through
+    // timing.
+    let expected_token = appservice.registration().hs_token.as_bytes();
+    if !bool::from(token.as_bytes().ct_eq(expected_token)) {
+        warn!("Rejecting a request with an invalid homeserver token");
+        return error_response(StatusCode::FORBIDDEN, "M_FORBIDDEN", "Invalid token");
+    }
+
+    next.run(request).await
+}
+
+#[instrument(skip(appservice, transaction), fields(num_events = transaction.events.len()))]
+async fn push_transaction(
+    State(appservice): State<AppService>,
+    Path(txn_id): Path<OwnedTransactionId>,
+    Json(transaction): Json<Transaction>,
+) -> Response {
+    if appservice.is_transaction_completed(&txn_id) {
+        debug!("Ignoring a transaction that was already handled");
+        return empty_response();
+    }
+
+    let handlers = appservice.inner.event_handlers.read().unwrap().clone();
+
+    for raw_event in transaction.events {
+        let event = match raw_event.deserialize() {
+            Ok(event) => event,
+            Err(error) => {
+                warn!("Couldn't deserialize an event of the transaction: {error}");
+                continue;
+            }
+        };
+
+        for handler in &handlers {
+            handler(event.clone(), appservice.clone()).await;
+        }
+    }
+
+    appservice.mark_transaction_completed(txn_id);
+
+    empty_response()
+}
+
+async fn query_user(
+    State(appservice): State<AppService>,
+    Path(user_id): Path<OwnedUserId>,
+) -> Response {
+    let handler = appservice.inner.user_query_handler.read().unwrap().clone();
+
+    let exists = match handler {
+        Some(handler) if appservice.namespaces().contains_user(&user_id) => {
+            handler(user_id, appservice).await
+        }
+        _ => false,
+    };
+
+    if exists {
+        empty_response()
+    } else {
+        error_response(StatusCode::NOT_FOUND, "M_NOT_FOUND", "Unknown user")
+    }
+}
+
+async fn query_room_alias(
+    State(appservice): State<AppService>,
+    Path(alias): Path<OwnedRoomAliasId>,
+) -> Response {
+    let handler = appservice.inner.room_alias_query_handler.read().unwrap().clone();
+
+    let exists = match handler {
+        Some(handler) if appservice.namespaces().contains_alias(&alias) => {
+            handler(alias, appservice).await
+        }
+        _ => false,
+    };
+
+    if exists {
+        empty_response()
+    } else {
+        error_response(StatusCode::NOT_FOUND, "M_NOT_FOUND", "Unknown room alias")
+    }
+}
+
+async fn ping() -> Response {
+    debug!("Received a ping from the homeserver");
+    empty_response()
+}
@@ -0,0 +1,59 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::Client;
+use matrix_sdk_appservice::AppService;
+use ruma::{
+    api::{appservice::Registration, MatrixVersion},
+    server_name,
+};
+use serde_json::json;
+use url::Url;
+
+mod server;
+mod virtual_users;
+
+matrix_sdk_test::init_tracing_for_tests!();
+
+const AS_TOKEN: &str = "as_token";
+const HS_TOKEN: &str = "hs_token";
+
+fn registration() -> Registration {
+    serde_json::from_value(json!({
+        "id": "bridge",
+        "url": "http://localhost:9000",
+        "as_token": AS_TOKEN,
+        "hs_token": HS_TOKEN,
+        "sender_localpart": "_bridge",
+        "namespaces": {
+            "users": [{ "exclusive": true, "regex": "@_bridge_.*:localhost" }],
+            "aliases": [{ "exclusive": true, "regex": "#_bridge_.*:localhost" }],
+            "rooms": [],
+        },
+    }))
+    .unwrap()
+}
+
+async fn appservice(homeserver_url: &str) -> AppService {
+    AppService::builder(
+        Url::parse(homeserver_url).unwrap(),
+        server_name!("localhost").to_owned(),
+        registration(),
+    )
+    // Don't query the versions supported by the homeserver.
+    .client_builder(Client::builder().server_versions([MatrixVersion::V1_7]))
+    .build()
+    .await
+    .unwrap()
+}
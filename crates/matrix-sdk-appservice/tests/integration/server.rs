@@ -0,0 +1,130 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use matrix_sdk_appservice::AppService;
+use matrix_sdk_test::async_test;
+use ruma::{events::AnyTimelineEvent, OwnedEventId, OwnedUserId};
+use serde_json::json;
+use tower::ServiceExt as _;
+
+use crate::{appservice, HS_TOKEN};
+
+fn transaction_request(txn_id: &str, token: Option<&str>) -> Request<Body> {
+    let body = json!({
+        "events": [{
+            "type": "m.room.message",
+            "event_id": "$message:localhost",
+            "room_id": "!room:localhost",
+            "sender": "@alice:localhost",
+            "origin_server_ts": 1,
+            "content": { "msgtype": "m.text", "body": "hello" },
+        }],
+    });
+
+    let mut builder = Request::put(format!("/_matrix/app/v1/transactions/{txn_id}"))
+        .header("content-type", "application/json");
+    if let Some(token) = token {
+        builder = builder.header("authorization", format!("Bearer {token}"));
+    }
+
+    builder.body(Body::from(body.to_string())).unwrap()
+}
+
+#[async_test]
+async fn test_transactions_require_the_homeserver_token() {
+    let appservice = appservice("http://localhost:8008").await;
+    let router = appservice.router();
+
+    let response = router.clone().oneshot(transaction_request("1", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = router.clone().oneshot(transaction_request("1", Some("wrong"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = router.oneshot(transaction_request("1", Some(HS_TOKEN))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[async_test]
+async fn test_transaction_events_are_handled_once() {
+    let appservice = appservice("http://localhost:8008").await;
+    let received = Arc::new(Mutex::new(Vec::<OwnedEventId>::new()));
+
+    appservice.add_event_handler({
+        let received = received.clone();
+        move |event: AnyTimelineEvent, _: AppService| {
+            let received = received.clone();
+            async move {
+                received.lock().unwrap().push(event.event_id().to_owned());
+            }
+        }
+    });
+
+    let router = appservice.router();
+    for _ in 0..2 {
+        // The homeserver sends the same transaction again, e.g. because it didn't
+        // receive the response.
+        let response =
+            router.clone().oneshot(transaction_request("txn", Some(HS_TOKEN))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    assert_eq!(*received.lock().unwrap(), ["$message:localhost"]);
+}
+
+#[async_test]
+async fn test_user_query() {
+    let appservice = appservice("http://localhost:8008").await;
+    appservice.on_user_query(|user_id: OwnedUserId, _: AppService| async move {
+        user_id.localpart() == "_bridge_alice"
+    });
+
+    let router = appservice.router();
+    let query = |user_id: &str| {
+        Request::get(format!("/_matrix/app/v1/users/{user_id}"))
+            .header("authorization", format!("Bearer {HS_TOKEN}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router.clone().oneshot(query("@_bridge_alice:localhost")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router.clone().oneshot(query("@_bridge_bob:localhost")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Users outside of the namespace are never queried.
+    let response = router.oneshot(query("@alice:localhost")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[async_test]
+async fn test_ping() {
+    let appservice = appservice("http://localhost:8008").await;
+
+    let request = Request::post("/_matrix/app/v1/ping")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {HS_TOKEN}"))
+        .body(Body::from(json!({ "transaction_id": "ping" }).to_string()))
+        .unwrap();
+
+    let response = appservice.router().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,105 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_matches;
+use matrix_sdk_appservice::AppServiceError;
+use matrix_sdk_test::async_test;
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{appservice, AS_TOKEN};
+
+#[async_test]
+async fn test_virtual_user_asserts_its_identity() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server.uri()).await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header("authorization", format!("Bearer {AS_TOKEN}")))
+        .and(query_param("user_id", "@_bridge_alice:localhost"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@_bridge_alice:localhost",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = appservice.virtual_user("_bridge_alice").await.unwrap();
+    assert_eq!(client.user_id().unwrap().as_str(), "@_bridge_alice:localhost");
+
+    let response = client.whoami().await.unwrap();
+    assert_eq!(response.user_id, "@_bridge_alice:localhost");
+
+    // The client is cached.
+    let same_client = appservice.virtual_user("_bridge_alice").await.unwrap();
+    assert_eq!(same_client.user_id(), client.user_id());
+}
+
+#[async_test]
+async fn test_virtual_user_must_be_in_namespace() {
+    let appservice = appservice("http://localhost:8008").await;
+
+    assert_matches!(
+        appservice.virtual_user("alice").await,
+        Err(AppServiceError::UserNotInNamespace(user_id))
+    );
+    assert_eq!(user_id, "@alice:localhost");
+}
+
+#[async_test]
+async fn test_register_virtual_user() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server.uri()).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/register"))
+        .and(header("authorization", format!("Bearer {AS_TOKEN}")))
+        .and(body_partial_json(json!({
+            "username": "_bridge_alice",
+            "type": "m.login.application_service",
+        })))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_USER_IN_USE",
+            "error": "User ID already taken.",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // An already registered user isn't an error.
+    appservice.register_virtual_user("_bridge_alice").await.unwrap();
+}
+
+#[async_test]
+async fn test_ping_homeserver() {
+    let server = MockServer::start().await;
+    let appservice = appservice(&server.uri()).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v1/appservice/bridge/ping"))
+        .and(header("authorization", format!("Bearer {AS_TOKEN}")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "duration_ms": 123 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let duration = appservice.ping_homeserver().await.unwrap();
+    assert_eq!(duration, Duration::from_millis(123));
+}
@@ -36,29 +36,31 @@ use ruma::DeviceId;
 use ruma::{
     api::client::{self as api, sync::sync_events::v5},
     events::{
+        ignored_user_list::IgnoredUserListEventContent,
         push_rules::{PushRulesEvent, PushRulesEventContent},
-        room::member::SyncRoomMemberEvent,
-        StateEvent, StateEventType,
+        room::member::{RoomMemberEvent, SyncRoomMemberEvent},
+        GlobalAccountDataEventType, StateEvent, StateEventType,
     },
     push::Ruleset,
+    serde::Raw,
     time::Instant,
-    OwnedRoomId, OwnedUserId, RoomId,
+    MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedUserId, RoomId,
 };
 use tokio::sync::{broadcast, Mutex};
 #[cfg(feature = "e2e-encryption")]
 use tokio::sync::{RwLock, RwLockReadGuard};
-use tracing::{debug, enabled, info, instrument, Level};
+use tracing::{debug, enabled, info, instrument, warn, Level};
 
 #[cfg(feature = "e2e-encryption")]
 use crate::RoomMemberships;
 use crate::{
-    deserialized_responses::DisplayName,
+    deserialized_responses::{DisplayName, RawMemberEvent},
     error::{Error, Result},
     event_cache::store::EventCacheStoreLock,
     response_processors::{self as processors, Context},
     rooms::{
         normal::{RoomInfoNotableUpdate, RoomInfoNotableUpdateReasons, RoomMembersUpdate},
-        Room, RoomState,
+        Room, RoomInfo, RoomState,
     },
     store::{
         ambiguity_map::AmbiguityCache, BaseStateStore, DynStateStore, MemoryStore,
@@ -260,6 +262,16 @@ impl BaseClient {
         )
     }
 
+    /// Create a `Room` with the given info, that isn't added to the list of
+    /// rooms nor saved in the store.
+    ///
+    /// This is useful to look at a room the user isn't a member of, like when
+    /// peeking into it.
+    pub fn create_detached_room(&self, room_info: RoomInfo) -> Room {
+        self.state_store
+            .create_detached_room(room_info, self.room_info_notable_update_sender.clone())
+    }
+
     /// Get a reference to the state store.
     pub fn state_store(&self) -> &DynStateStore {
         self.state_store.deref()
@@ -327,6 +339,7 @@ impl BaseClient {
             .await?;
         self.state_store.load_sync_token().await?;
         self.state_store.set_session_meta(session_meta);
+        self.load_ignored_user_list().await?;
 
         #[cfg(feature = "e2e-encryption")]
         self.regenerate_olm(custom_account).await?;
@@ -360,6 +373,19 @@ impl BaseClient {
         Ok(())
     }
 
+    /// Wipe the crypto store, and drop the `OlmMachine` using it.
+    ///
+    /// The end-to-end encryption keys of the device are lost, so this must
+    /// only be used once the session is over for good, e.g. after the account
+    /// has been deactivated.
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn clear_crypto_store(&self) -> Result<()> {
+        let mut olm_machine = self.olm_machine.write().await;
+        *olm_machine = None;
+        self.crypto_store.clear().await?;
+        Ok(())
+    }
+
     /// Get the current, if any, sync token of the client.
     /// This will be None if the client didn't sync at least once.
     pub async fn sync_token(&self) -> Option<String> {
@@ -693,8 +719,14 @@ impl BaseClient {
     /// Receive a get member events response and convert it to a deserialized
     /// `MembersResponse`
     ///
-    /// This client-server request must be made without filters to make sure all
-    /// members are received. Otherwise, an error is returned.
+    /// This client-server request must be made without membership filters to
+    /// make sure all members are received. Otherwise, an error is returned. It
+    /// can be scoped to a sync token with its `at` field, to receive the
+    /// members at that point of the timeline.
+    ///
+    /// The member events of the response are only applied if they are newer
+    /// than the stored ones, which might have been received by a sync while
+    /// the request was in flight.
     ///
     /// # Arguments
     ///
@@ -708,8 +740,7 @@ impl BaseClient {
         request: &api::membership::get_member_events::v3::Request,
         response: &api::membership::get_member_events::v3::Response,
     ) -> Result<()> {
-        if request.membership.is_some() || request.not_membership.is_some() || request.at.is_some()
-        {
+        if request.membership.is_some() || request.not_membership.is_some() {
             // This function assumes all members are loaded at once to optimise how display
             // name disambiguation works. Using it with partial member list results
             // would produce incorrect disambiguated display name entries
@@ -739,34 +770,38 @@ impl BaseClient {
                 }
             };
 
-            // TODO: All the actions in this loop used to be done only when the membership
-            // event was not in the store before. This was changed with the new room API,
-            // because e.g. leaving a room makes members events outdated and they need to be
-            // fetched by `members`. Therefore, they need to be overwritten here, even
-            // if they exist.
-            // However, this makes a new problem occur where setting the member events here
-            // potentially races with the sync.
+            // The member events are overwritten even if they exist, because e.g. leaving a
+            // room makes members events outdated and they need to be fetched by `members`.
+            // However, the sync might have received newer member events than the ones of
+            // the response, which must be kept.
             // See <https://github.com/matrix-org/matrix-rust-sdk/issues/1205>.
+            let newer_member = self.newer_stored_member_event(room_id, &member).await?;
+            let is_newer_stored = newer_member.is_some();
+            let sync_member: SyncRoomMemberEvent =
+                newer_member.unwrap_or_else(|| member.clone().into());
 
             #[cfg(feature = "e2e-encryption")]
-            match member.membership() {
+            match sync_member.membership() {
                 MembershipState::Join | MembershipState::Invite => {
-                    user_ids.insert(member.state_key().to_owned());
+                    user_ids.insert(sync_member.state_key().to_owned());
                 }
                 _ => (),
             }
 
-            if let StateEvent::Original(e) = &member {
+            if let StateEvent::Original(e) = &sync_member {
                 if let Some(d) = &e.content.displayname {
                     let display_name = DisplayName::new(d);
                     ambiguity_map
                         .entry(display_name)
                         .or_default()
-                        .insert(member.state_key().clone());
+                        .insert(sync_member.state_key().clone());
                 }
             }
 
-            let sync_member: SyncRoomMemberEvent = member.clone().into();
+            if is_newer_stored {
+                continue;
+            }
+
             processors::profiles::upsert_or_delete(&mut context, room_id, &sync_member);
 
             context
@@ -809,6 +844,28 @@ impl BaseClient {
         Ok(())
     }
 
+    /// Get the stored member event of the same member as the given one, if it
+    /// is more recent.
+    async fn newer_stored_member_event(
+        &self,
+        room_id: &RoomId,
+        member: &RoomMemberEvent,
+    ) -> Result<Option<SyncRoomMemberEvent>> {
+        let Some(RawMemberEvent::Sync(raw)) =
+            self.state_store.get_member_event(room_id, member.state_key()).await?
+        else {
+            return Ok(None);
+        };
+
+        let stored_ts =
+            raw.get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts").ok().flatten();
+        if stored_ts.is_none_or(|ts| ts <= member.origin_server_ts()) {
+            return Ok(None);
+        }
+
+        Ok(raw.deserialize().ok())
+    }
+
     /// Receive a successful filter upload response, the filter id will be
     /// stored under the given name in the store.
     ///
@@ -961,6 +1018,58 @@ impl BaseClient {
         self.ignore_user_list_changes.subscribe()
     }
 
+    /// Save the given ignored user list in the store, as if it had been
+    /// received from the sync, and notify the subscribers of the ignore user
+    /// list changes if it changed.
+    ///
+    /// This is used to apply a change made by the current user without waiting
+    /// for the sync to return it.
+    pub async fn receive_ignored_user_list(
+        &self,
+        content: IgnoredUserListEventContent,
+    ) -> Result<()> {
+        let event = Raw::new(&serde_json::json!({
+            "type": GlobalAccountDataEventType::IgnoredUserList,
+            "content": content,
+        }))?
+        .cast();
+
+        let mut context = Context::default();
+        context
+            .state_changes
+            .account_data
+            .insert(GlobalAccountDataEventType::IgnoredUserList, event);
+
+        let _sync_lock = self.sync_lock().lock().await;
+
+        processors::changes::save_and_apply(
+            context,
+            &self.state_store,
+            &self.ignore_user_list_changes,
+            None,
+        )
+        .await
+    }
+
+    /// Initialize the ignored user list from the one saved in the store.
+    async fn load_ignored_user_list(&self) -> Result<()> {
+        let Some(event) =
+            self.state_store.get_account_data_event_static::<IgnoredUserListEventContent>().await?
+        else {
+            return Ok(());
+        };
+
+        match event.deserialize() {
+            Ok(event) => {
+                let user_ids = event.content.ignored_users.into_keys().map(String::from);
+                self.ignore_user_list_changes.set_if_not_eq(user_ids.collect());
+            }
+            Err(error) => warn!("Failed to deserialize the stored ignored user list: {error}"),
+        }
+
+        Ok(())
+    }
+
     /// Returns a new receiver that gets future room info notable updates.
     ///
     /// Learn more by reading the [`RoomInfoNotableUpdate`] type.
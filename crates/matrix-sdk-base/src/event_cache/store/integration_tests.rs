@@ -152,6 +152,10 @@ pub trait EventCacheStoreIntegrationTests {
 
     /// Test that saving an event works as expected.
     async fn test_save_event(&self);
+
+    /// Test that retained rooms and events are kept when clearing all the
+    /// rooms' linked chunks.
+    async fn test_retained_content(&self);
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -1014,6 +1018,93 @@ impl EventCacheStoreIntegrationTests for DynEventCacheStore {
             .expect("failed to query for finding an event")
             .is_none());
     }
+
+    async fn test_retained_content(&self) {
+        let r0 = room_id!("!r0:matrix.org");
+        let r1 = room_id!("!r1:matrix.org");
+        let r2 = room_id!("!r2:matrix.org");
+
+        // Nothing is retained at first.
+        assert!(self.retained_content().await.unwrap().is_empty());
+
+        let event_r0 = make_test_event(r0, "hello");
+        let retained_event_r1 = make_test_event(r1, "brie");
+        let other_event_r1 = make_test_event(r1, "camembert");
+        let saved_event_r2 = make_test_event(r2, "reblochon");
+
+        self.handle_linked_chunk_updates(
+            r0,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![event_r0.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        self.handle_linked_chunk_updates(
+            r1,
+            vec![
+                Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                Update::PushItems {
+                    at: Position::new(CId::new(0), 0),
+                    items: vec![retained_event_r1.clone(), other_event_r1.clone()],
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        self.save_event(r2, saved_event_r2.clone()).await.unwrap();
+
+        // Retain the first room as a whole, and a single event in the second room.
+        self.set_room_retained(r0, true).await.unwrap();
+        let retained_event_id = retained_event_r1.event_id().unwrap();
+        self.set_event_retained(r1, &retained_event_id, true).await.unwrap();
+
+        let retained = self.retained_content().await.unwrap();
+        assert!(retained.contains_room(r0));
+        assert!(!retained.contains_room(r1));
+        assert!(retained.contains_event(r0, &event_r0.event_id().unwrap()));
+        assert!(retained.contains_event(r1, &retained_event_id));
+        assert!(!retained.contains_event(r1, &other_event_r1.event_id().unwrap()));
+        assert_eq!(retained.events.len(), 1);
+
+        // Clear all the rooms.
+        self.clear_all_rooms_chunks().await.unwrap();
+
+        // The retained room still has its linked chunk.
+        let linked_chunk =
+            lazy_loader::from_all_chunks::<3, _, _>(self.load_all_chunks(r0).await.unwrap())
+                .unwrap()
+                .expect("the linked chunk of a retained room must be kept");
+        assert_eq!(linked_chunk.num_items(), 1);
+        assert!(self.find_event(r0, &event_r0.event_id().unwrap()).await.unwrap().is_some());
+
+        // The other room lost its linked chunk, but the retained event can still be
+        // found.
+        assert!(lazy_loader::from_all_chunks::<3, _, _>(self.load_all_chunks(r1).await.unwrap())
+            .unwrap()
+            .is_none());
+        assert!(self.find_event(r1, &retained_event_id).await.unwrap().is_some());
+        assert!(self.find_event(r1, &other_event_r1.event_id().unwrap()).await.unwrap().is_none());
+
+        // Events that were not retained are gone.
+        assert!(self.find_event(r2, &saved_event_r2.event_id().unwrap()).await.unwrap().is_none());
+
+        // Releasing the content makes it go away on the next clear.
+        self.set_room_retained(r0, false).await.unwrap();
+        self.set_event_retained(r1, &retained_event_id, false).await.unwrap();
+        assert!(self.retained_content().await.unwrap().is_empty());
+
+        self.clear_all_rooms_chunks().await.unwrap();
+
+        assert!(self.find_event(r0, &event_r0.event_id().unwrap()).await.unwrap().is_none());
+        assert!(self.find_event(r1, &retained_event_id).await.unwrap().is_none());
+    }
 }
 
 /// Macro building to allow your `EventCacheStore` implementation to run the
@@ -1131,6 +1222,13 @@ macro_rules! event_cache_store_integration_tests {
                     get_event_cache_store().await.unwrap().into_event_cache_store();
                 event_cache_store.test_save_event().await;
             }
+
+            #[async_test]
+            async fn test_retained_content() {
+                let event_cache_store =
+                    get_event_cache_store().await.unwrap().into_event_cache_store();
+                event_cache_store.test_retained_content().await;
+            }
         }
     };
 }
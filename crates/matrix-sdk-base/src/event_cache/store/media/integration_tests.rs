@@ -23,7 +23,8 @@ use ruma::{
 };
 
 use super::{
-    media_service::IgnoreMediaRetentionPolicy, EventCacheStoreMedia, MediaRetentionPolicy,
+    media_service::IgnoreMediaRetentionPolicy, EventCacheStoreMedia, MediaCacheUsage,
+    MediaRetentionPolicy,
 };
 use crate::media::{MediaFormat, MediaRequestParameters};
 
@@ -56,6 +57,9 @@ pub trait EventCacheStoreMediaIntegrationTests {
 
     /// Test last media cleanup time storage.
     async fn test_store_last_media_cleanup_time(&self);
+
+    /// Test the reported media cache usage.
+    async fn test_media_cache_usage(&self);
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -963,6 +967,75 @@ where
         let stored = self.last_media_cleanup_time_inner().await.unwrap();
         assert_eq!(stored, Some(new_time));
     }
+
+    async fn test_media_cache_usage(&self) {
+        let mut time = SystemTime::now();
+        let policy = MediaRetentionPolicy::empty();
+
+        // The cache is empty.
+        let usage = self.media_cache_usage_inner().await.unwrap();
+        assert_eq!(usage, MediaCacheUsage::default());
+
+        // 256 bytes content, that is pinned.
+        let content_big = vec![0; 256];
+        let uri_big = owned_mxc_uri!("mxc://localhost/big-media");
+        let request_big = MediaRequestParameters {
+            source: MediaSource::Plain(uri_big),
+            format: MediaFormat::File,
+        };
+        self.add_media_content_inner(
+            &request_big,
+            content_big,
+            time,
+            policy,
+            IgnoreMediaRetentionPolicy::Yes,
+        )
+        .await
+        .unwrap();
+
+        // 64 bytes content.
+        let content_small = vec![0; 64];
+        let uri_small = owned_mxc_uri!("mxc://localhost/small-media");
+        let request_small = MediaRequestParameters {
+            source: MediaSource::Plain(uri_small),
+            format: MediaFormat::File,
+        };
+        time += Duration::from_secs(1);
+        self.add_media_content_inner(
+            &request_small,
+            content_small,
+            time,
+            policy,
+            IgnoreMediaRetentionPolicy::No,
+        )
+        .await
+        .unwrap();
+
+        let usage = self.media_cache_usage_inner().await.unwrap();
+        assert_eq!(usage, MediaCacheUsage::new(2, 320, 1, 256));
+
+        // The pinned content is not removed during a cleanup.
+        let policy = MediaRetentionPolicy::empty().with_max_cache_size(Some(100));
+        time += Duration::from_secs(1);
+        self.clean_up_media_cache_inner(policy, time).await.unwrap();
+
+        let usage = self.media_cache_usage_inner().await.unwrap();
+        assert_eq!(usage, MediaCacheUsage::new(2, 320, 1, 256));
+
+        // Once it is unpinned, it is the least recently used content so it is removed.
+        self.set_ignore_media_retention_policy_inner(&request_big, IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+
+        let usage = self.media_cache_usage_inner().await.unwrap();
+        assert_eq!(usage, MediaCacheUsage::new(2, 320, 0, 0));
+
+        time += Duration::from_secs(1);
+        self.clean_up_media_cache_inner(policy, time).await.unwrap();
+
+        let usage = self.media_cache_usage_inner().await.unwrap();
+        assert_eq!(usage, MediaCacheUsage::new(1, 64, 0, 0));
+    }
 }
 
 /// Macro building to allow your [`EventCacheStoreMedia`] implementation to run
@@ -1021,6 +1094,12 @@ macro_rules! event_cache_store_media_integration_tests {
                 let event_cache_store_media = get_event_cache_store().await.unwrap();
                 event_cache_store_media.test_media_ignore_max_size().await;
             }
+
+            #[async_test]
+            async fn test_media_cache_usage() {
+                let event_cache_store_media = get_event_cache_store().await.unwrap();
+                event_cache_store_media.test_media_cache_usage().await;
+            }
         }
     };
 
@@ -245,6 +245,18 @@ where
         Ok(content)
     }
 
+    /// Get the current usage of the media cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The `EventCacheStoreMedia`.
+    pub async fn media_cache_usage<Store: EventCacheStoreMedia>(
+        &self,
+        store: &Store,
+    ) -> Result<MediaCacheUsage, Store::Error> {
+        store.media_cache_usage_inner().await
+    }
+
     /// Clean up the media cache with the current `MediaRetentionPolicy`.
     ///
     /// If there is already an ongoing cleanup, this is a noop.
@@ -473,6 +485,13 @@ pub trait EventCacheStoreMedia: AsyncTraitDeps + Clone {
 
     /// The time of the last media cache cleanup.
     async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>, Self::Error>;
+
+    /// The current usage of the media cache.
+    ///
+    /// The size of a media content is the size taken by the content in the
+    /// store, after it was possibly encrypted, like for the
+    /// [`MediaRetentionPolicy`].
+    async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage, Self::Error>;
 }
 
 /// Whether the [`MediaRetentionPolicy`] should be ignored for the current
@@ -494,8 +513,9 @@ pub enum IgnoreMediaRetentionPolicy {
     /// `MediaRetentionPolicy`. This applies to ANY criteria, like the maximum
     /// file size, the maximum cache size or the last access expiry.
     ///
-    /// This state is supposed to be transient, and to only be used internally
-    /// by the SDK.
+    /// This is used internally by the SDK for content that must be kept
+    /// temporarily, but it can also be used to pin media content that should
+    /// never be evicted from the cache, like avatars or stickers.
     Yes,
 
     /// The media retention policy will be respected and the current action
@@ -510,6 +530,42 @@ impl IgnoreMediaRetentionPolicy {
     }
 }
 
+/// The current usage of the media cache.
+///
+/// Sizes are in bytes, and are the sizes taken by the (possibly encrypted)
+/// media contents in the store, excluding any metadata associated with them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MediaCacheUsage {
+    /// The number of media contents in the cache.
+    pub media_count: u64,
+
+    /// The total size of the media contents in the cache.
+    pub total_size: u64,
+
+    /// The number of media contents in the cache that ignore the
+    /// [`MediaRetentionPolicy`].
+    ///
+    /// They are never removed during a cleanup.
+    pub pinned_media_count: u64,
+
+    /// The total size of the media contents in the cache that ignore the
+    /// [`MediaRetentionPolicy`].
+    pub pinned_size: u64,
+}
+
+impl MediaCacheUsage {
+    /// Construct a new `MediaCacheUsage` with the given values.
+    pub fn new(
+        media_count: u64,
+        total_size: u64,
+        pinned_media_count: u64,
+        pinned_size: u64,
+    ) -> Self {
+        Self { media_count, total_size, pinned_media_count, pinned_size }
+    }
+}
+
 /// An abstract trait to provide the current `SystemTime` for the
 /// [`MediaService`].
 pub trait TimeProvider: SendOutsideWasm + SyncOutsideWasm {
@@ -544,7 +600,10 @@ mod tests {
         MxcUri, OwnedMxcUri,
     };
 
-    use super::{EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaService, TimeProvider};
+    use super::{
+        EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage, MediaService,
+        TimeProvider,
+    };
     use crate::{
         event_cache::store::{media::MediaRetentionPolicy, EventCacheStoreError},
         media::{MediaFormat, MediaRequestParameters, UniqueKey},
@@ -751,6 +810,18 @@ mod tests {
         async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>, Self::Error> {
             Ok(self.inner().cleanup_time)
         }
+
+        async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage, Self::Error> {
+            let inner = self.inner();
+            let pinned = inner.media_list.iter().filter(|content| content.ignore_policy);
+
+            Ok(MediaCacheUsage::new(
+                inner.media_list.len() as u64,
+                inner.media_list.iter().map(|content| content.content.len() as u64).sum(),
+                pinned.clone().count() as u64,
+                pinned.map(|content| content.content.len() as u64).sum(),
+            ))
+        }
     }
 
     #[derive(Debug)]
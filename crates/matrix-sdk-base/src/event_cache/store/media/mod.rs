@@ -24,5 +24,7 @@ pub mod integration_tests;
 pub use self::integration_tests::EventCacheStoreMediaIntegrationTests;
 pub use self::{
     media_retention_policy::MediaRetentionPolicy,
-    media_service::{EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaService},
+    media_service::{
+        EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage, MediaService,
+    },
 };
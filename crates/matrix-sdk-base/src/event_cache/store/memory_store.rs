@@ -36,8 +36,11 @@ use tracing::error;
 
 use super::{
     compute_filters_string, extract_event_relation,
-    media::{EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaRetentionPolicy, MediaService},
-    EventCacheStore, EventCacheStoreError, Result,
+    media::{
+        EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage, MediaRetentionPolicy,
+        MediaService,
+    },
+    EventCacheStore, EventCacheStoreError, Result, RetainedContent,
 };
 use crate::{
     event_cache::{Event, Gap},
@@ -60,6 +63,7 @@ struct MemoryStoreInner {
     events: RelationalLinkedChunk<OwnedEventId, Event, Gap>,
     media_retention_policy: Option<MediaRetentionPolicy>,
     last_media_cleanup_time: SystemTime,
+    retained: RetainedContent,
 }
 
 /// A media content in the `MemoryStore`.
@@ -97,6 +101,7 @@ impl Default for MemoryStore {
                 events: RelationalLinkedChunk::new(),
                 media_retention_policy: None,
                 last_media_cleanup_time,
+                retained: RetainedContent::default(),
             })),
             media_service,
         }
@@ -172,7 +177,19 @@ impl EventCacheStore for MemoryStore {
     }
 
     async fn clear_all_rooms_chunks(&self) -> Result<(), Self::Error> {
-        self.inner.write().unwrap().events.clear();
+        let mut inner = self.inner.write().unwrap();
+        let inner = &mut *inner;
+
+        if inner.retained.is_empty() {
+            inner.events.clear();
+        } else {
+            let retained = &inner.retained;
+            inner.events.clear_except(
+                |room_id| retained.contains_room(room_id),
+                |room_id, event_id| retained.contains_event(room_id, event_id),
+            );
+        }
+
         Ok(())
     }
 
@@ -267,6 +284,25 @@ impl EventCacheStore for MemoryStore {
         Ok(())
     }
 
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<()> {
+        self.inner.write().unwrap().retained.set_room(room_id, retained);
+        Ok(())
+    }
+
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<()> {
+        self.inner.write().unwrap().retained.set_event(room_id, event_id, retained);
+        Ok(())
+    }
+
+    async fn retained_content(&self) -> Result<RetainedContent> {
+        Ok(self.inner.read().unwrap().retained.clone())
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequestParameters,
@@ -362,6 +398,10 @@ impl EventCacheStore for MemoryStore {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.media_service.clean_up_media_cache(self).await
     }
+
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage, Self::Error> {
+        self.media_service.media_cache_usage(self).await
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -567,6 +607,25 @@ impl EventCacheStoreMedia for MemoryStore {
     async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>, Self::Error> {
         Ok(Some(self.inner.read().unwrap().last_media_cleanup_time))
     }
+
+    async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage, Self::Error> {
+        let inner = self.inner.read().unwrap();
+
+        let mut usage = MediaCacheUsage::default();
+        for content in inner.media.iter() {
+            let size = content.data.len() as u64;
+
+            usage.media_count += 1;
+            usage.total_size += size;
+
+            if content.ignore_policy {
+                usage.pinned_media_count += 1;
+                usage.pinned_size += size;
+            }
+        }
+
+        Ok(usage)
+    }
 }
 
 #[cfg(test)]
@@ -19,7 +19,13 @@
 //! into the event cache for the actual storage. By default this brings an
 //! in-memory store.
 
-use std::{fmt, ops::Deref, str::Utf8Error, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Deref,
+    str::Utf8Error,
+    sync::Arc,
+};
 
 #[cfg(any(test, feature = "testing"))]
 #[macro_use]
@@ -35,8 +41,9 @@ pub use matrix_sdk_store_encryption::Error as StoreEncryptionError;
 use ruma::{
     events::{relation::RelationType, AnySyncTimelineEvent},
     serde::Raw,
-    OwnedEventId,
+    EventId, OwnedEventId, OwnedRoomId, RoomId,
 };
+use serde::{Deserialize, Serialize};
 use tracing::trace;
 
 #[cfg(any(test, feature = "testing"))]
@@ -180,6 +187,62 @@ impl EventCacheStoreError {
 /// An `EventCacheStore` specific result type.
 pub type Result<T, E = EventCacheStoreError> = std::result::Result<T, E>;
 
+/// The rooms and events that an [`EventCacheStore`] keeps, regardless of any
+/// cleanup.
+///
+/// A retained room keeps its linked chunk and all its events when
+/// [`EventCacheStore::clear_all_rooms_chunks`] is called. A retained event is
+/// kept in the store, even if the linked chunk it belonged to is gone.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetainedContent {
+    /// The rooms that are retained as a whole.
+    pub rooms: BTreeSet<OwnedRoomId>,
+
+    /// The individual events that are retained, grouped by room.
+    pub events: BTreeMap<OwnedRoomId, BTreeSet<OwnedEventId>>,
+}
+
+impl RetainedContent {
+    /// Whether nothing is retained.
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty() && self.events.is_empty()
+    }
+
+    /// Whether the given room is retained as a whole.
+    pub fn contains_room(&self, room_id: &RoomId) -> bool {
+        self.rooms.contains(room_id)
+    }
+
+    /// Whether the given event is retained, either individually or because
+    /// its room is.
+    pub fn contains_event(&self, room_id: &RoomId, event_id: &EventId) -> bool {
+        self.contains_room(room_id)
+            || self.events.get(room_id).is_some_and(|events| events.contains(event_id))
+    }
+
+    /// Mark the given room as retained, or not.
+    pub fn set_room(&mut self, room_id: &RoomId, retained: bool) {
+        if retained {
+            self.rooms.insert(room_id.to_owned());
+        } else {
+            self.rooms.remove(room_id);
+        }
+    }
+
+    /// Mark the given event as retained, or not.
+    pub fn set_event(&mut self, room_id: &RoomId, event_id: &EventId, retained: bool) {
+        if retained {
+            self.events.entry(room_id.to_owned()).or_default().insert(event_id.to_owned());
+        } else if let Some(events) = self.events.get_mut(room_id) {
+            events.remove(event_id);
+
+            if events.is_empty() {
+                self.events.remove(room_id);
+            }
+        }
+    }
+}
+
 /// A type that wraps the [`EventCacheStore`] but implements [`BackingStore`] to
 /// make it usable inside the cross process lock.
 #[derive(Clone, Debug)]
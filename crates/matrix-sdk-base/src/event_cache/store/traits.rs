@@ -22,8 +22,8 @@ use matrix_sdk_common::{
 use ruma::{events::relation::RelationType, EventId, MxcUri, OwnedEventId, RoomId};
 
 use super::{
-    media::{IgnoreMediaRetentionPolicy, MediaRetentionPolicy},
-    EventCacheStoreError,
+    media::{IgnoreMediaRetentionPolicy, MediaCacheUsage, MediaRetentionPolicy},
+    EventCacheStoreError, RetainedContent,
 };
 use crate::{
     event_cache::{Event, Gap},
@@ -102,6 +102,11 @@ pub trait EventCacheStore: AsyncTraitDeps {
     /// must *also* delete all the events' content, if they were stored in a
     /// separate table.
     ///
+    /// The content marked as retained with [`Self::set_room_retained`] and
+    /// [`Self::set_event_retained`] must be kept: the linked chunks and events
+    /// of retained rooms are left untouched, and retained events are kept
+    /// without a position in a linked chunk.
+    ///
     /// ⚠ This is meant only for super specific use cases, where there shouldn't
     /// be any live in-memory linked chunks. In general, prefer using
     /// `EventCache::clear_all_rooms()` from the common SDK crate.
@@ -143,6 +148,27 @@ pub trait EventCacheStore: AsyncTraitDeps {
     /// without causing an error.
     async fn save_event(&self, room_id: &RoomId, event: Event) -> Result<(), Self::Error>;
 
+    /// Mark a room as retained, or not.
+    ///
+    /// The linked chunk and all the events of a retained room are kept by
+    /// [`Self::clear_all_rooms_chunks`].
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<(), Self::Error>;
+
+    /// Mark a single event as retained, or not.
+    ///
+    /// A retained event is kept by [`Self::clear_all_rooms_chunks`], and can
+    /// still be found with [`Self::find_event`] afterwards. This doesn't check
+    /// that the event has been saved in the store.
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<(), Self::Error>;
+
+    /// Get all the rooms and events that are currently retained.
+    async fn retained_content(&self) -> Result<RetainedContent, Self::Error>;
+
     /// Add a media file's content in the media store.
     ///
     /// # Arguments
@@ -265,6 +291,12 @@ pub trait EventCacheStore: AsyncTraitDeps {
     ///
     /// If there is already an ongoing cleanup, this is a noop.
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error>;
+
+    /// Get the current usage of the media cache.
+    ///
+    /// This can be used to show how much space the media cache takes, and
+    /// how much of it is pinned, in the settings of a client.
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage, Self::Error>;
 }
 
 #[repr(transparent)]
@@ -354,6 +386,23 @@ impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
         self.0.save_event(room_id, event).await.map_err(Into::into)
     }
 
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<(), Self::Error> {
+        self.0.set_room_retained(room_id, retained).await.map_err(Into::into)
+    }
+
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<(), Self::Error> {
+        self.0.set_event_retained(room_id, event_id, retained).await.map_err(Into::into)
+    }
+
+    async fn retained_content(&self) -> Result<RetainedContent, Self::Error> {
+        self.0.retained_content().await.map_err(Into::into)
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequestParameters,
@@ -418,6 +467,10 @@ impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.0.clean_up_media_cache().await.map_err(Into::into)
     }
+
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage, Self::Error> {
+        self.0.media_cache_usage().await.map_err(Into::into)
+    }
 }
 
 /// A type-erased [`EventCacheStore`].
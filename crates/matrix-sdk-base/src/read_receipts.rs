@@ -527,7 +527,11 @@ pub(crate) fn compute_unread_counts(
 }
 
 /// Is the event worth marking a room as unread?
-fn marks_as_unread(event: &Raw<AnySyncTimelineEvent>, user_id: &UserId) -> bool {
+///
+/// The events sent by the given user, the redacted events, the edits, and the
+/// events that aren't visible messages, like reactions or call signalling, are
+/// not.
+pub fn marks_as_unread(event: &Raw<AnySyncTimelineEvent>, user_id: &UserId) -> bool {
     let event = match event.deserialize() {
         Ok(event) => event,
         Err(err) => {
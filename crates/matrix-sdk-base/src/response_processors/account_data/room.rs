@@ -76,6 +76,16 @@ pub async fn for_room(
                             &mut context.state_changes,
                             state_store,
                             |room_info| {
+                                if room_info.base_info.tags != event.content.tags {
+                                    // Notify the room list about the new tags, so that it can
+                                    // sort the room again.
+                                    context
+                                        .room_info_notable_updates
+                                        .entry(room_id.to_owned())
+                                        .or_default()
+                                        .insert(RoomInfoNotableUpdateReasons::TAGS);
+                                }
+
                                 room_info.base_info.handle_notable_tags(&event.content.tags);
                             },
                         );
@@ -176,6 +176,9 @@ pub struct BaseRoomInfo {
     /// others, and this field collects them.
     #[serde(skip_serializing_if = "RoomNotableTags::is_empty", default)]
     pub(crate) notable_tags: RoomNotableTags,
+    /// All the tags of this room, from the `m.tag` room account data event.
+    #[serde(skip_serializing_if = "Tags::is_empty", default)]
+    pub(crate) tags: Tags,
     /// The `m.room.pinned_events` of this room.
     pub(crate) pinned_events: Option<RoomPinnedEventsEventContent>,
 }
@@ -359,7 +362,10 @@ impl BaseRoomInfo {
         }
     }
 
+    /// Handle the tags of this room, from the `m.tag` room account data event.
     pub fn handle_notable_tags(&mut self, tags: &Tags) {
+        self.tags = tags.clone();
+
         let mut notable_tags = RoomNotableTags::empty();
 
         if tags.contains_key(&TagName::Favorite) {
@@ -423,6 +429,7 @@ impl Default for BaseRoomInfo {
             rtc_member_events: BTreeMap::new(),
             is_marked_unread: false,
             notable_tags: RoomNotableTags::empty(),
+            tags: Tags::new(),
             pinned_events: None,
         }
     }
@@ -591,7 +598,10 @@ impl RoomMemberships {
 mod tests {
     use std::ops::Not;
 
-    use ruma::events::tag::{TagInfo, TagName, Tags};
+    use ruma::{
+        assign,
+        events::tag::{TagInfo, TagName, Tags},
+    };
 
     use super::{BaseRoomInfo, RoomNotableTags};
     use crate::RoomDisplayName;
@@ -626,6 +636,22 @@ mod tests {
         assert!(base_room_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY).not());
     }
 
+    #[test]
+    fn test_handle_notable_tags_keeps_all_tags() {
+        let mut base_room_info = BaseRoomInfo::default();
+
+        let mut tags = Tags::new();
+        tags.insert(TagName::Favorite, assign!(TagInfo::new(), { order: Some(0.5) }));
+        tags.insert(TagName::User("u.work".parse().unwrap()), TagInfo::new());
+
+        base_room_info.handle_notable_tags(&tags);
+        assert_eq!(base_room_info.tags, tags);
+
+        tags.clear();
+        base_room_info.handle_notable_tags(&tags);
+        assert!(base_room_info.tags.is_empty());
+    }
+
     #[test]
     fn test_room_alias_from_room_display_name_lowercases() {
         assert_eq!(
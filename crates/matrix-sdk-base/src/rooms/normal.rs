@@ -47,7 +47,7 @@ use ruma::{
             redaction::SyncRoomRedactionEvent,
             tombstone::RoomTombstoneEventContent,
         },
-        tag::{TagEventContent, Tags},
+        tag::{TagEventContent, TagName, Tags},
         AnyRoomAccountDataEvent, AnyStrippedStateEvent, AnySyncStateEvent, AnySyncTimelineEvent,
         RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
@@ -117,6 +117,9 @@ bitflags! {
         /// The display name has changed.
         const DISPLAY_NAME = 0b0010_0000;
 
+        /// The tags of the `Room` have changed.
+        const TAGS = 0b0100_0000;
+
         /// This is a temporary hack.
         ///
         /// So here is the thing. Ideally, we DO NOT want to emit this reason. It does not
@@ -1173,6 +1176,20 @@ impl Room {
         }
     }
 
+    /// Get the tags of this room, as received in the last `m.tag` room account
+    /// data event.
+    ///
+    /// Unlike [`Room::tags`], it doesn't hit the store.
+    pub fn cached_tags(&self) -> Tags {
+        self.inner.read().base_info.tags.clone()
+    }
+
+    /// Get the order of the given tag in this room, if the room has the tag
+    /// and the tag has an order.
+    pub fn tag_order(&self, tag: &TagName) -> Option<f64> {
+        self.inner.read().base_info.tags.get(tag).and_then(|info| info.order)
+    }
+
     /// Check whether the room is marked as favourite.
     ///
     /// A room is considered favourite if it has received the `m.favourite` tag.
@@ -1489,7 +1506,7 @@ impl RoomInfo {
     #[doc(hidden)] // used by store tests, otherwise it would be pub(crate)
     pub fn new(room_id: &RoomId, room_state: RoomState) -> Self {
         Self {
-            version: 1,
+            version: 2,
             room_id: room_id.into(),
             room_state,
             prev_room_state: None,
@@ -1915,6 +1932,11 @@ impl RoomInfo {
         Some(&self.base_info.topic.as_ref()?.as_original()?.content.topic)
     }
 
+    /// Returns the tags of this room.
+    pub fn tags(&self) -> &Tags {
+        &self.base_info.tags
+    }
+
     /// Get a list of all the valid (non expired) matrixRTC memberships and
     /// associated UserId's in this room.
     ///
@@ -2060,6 +2082,31 @@ impl RoomInfo {
             migrated = true;
         }
 
+        if self.version < 2 {
+            info!("Migrating room info to version 2");
+
+            // tags, only the notable ones were kept before.
+            match store.get_room_account_data_event_static::<TagEventContent>(&self.room_id).await {
+                Ok(Some(raw_event)) => match raw_event.deserialize() {
+                    Ok(event) => {
+                        self.base_info.handle_notable_tags(&event.content.tags);
+                    }
+                    Err(error) => {
+                        warn!("Failed to deserialize room tags: {error}");
+                    }
+                },
+                Ok(_) => {
+                    // Nothing to do.
+                }
+                Err(error) => {
+                    warn!("Failed to load room tags: {error}");
+                }
+            }
+
+            self.version = 2;
+            migrated = true;
+        }
+
         migrated
     }
 }
@@ -3799,14 +3846,14 @@ mod tests {
         // Apply migrations with an empty store.
         assert!(room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.version, 1);
+        assert_eq!(room_info.version, 2);
         assert!(room_info.base_info.notable_tags.is_empty());
         assert!(room_info.base_info.pinned_events.is_none());
 
         // Applying migrations again has no effect.
         assert!(!room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.version, 1);
+        assert_eq!(room_info.version, 2);
         assert!(room_info.base_info.notable_tags.is_empty());
         assert!(room_info.base_info.pinned_events.is_none());
 
@@ -3827,13 +3874,22 @@ mod tests {
         room_info.version = 0;
         assert!(room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.version, 1);
+        assert_eq!(room_info.version, 2);
         assert!(room_info.base_info.notable_tags.contains(RoomNotableTags::FAVOURITE));
+        assert!(!room_info.base_info.tags.is_empty());
         assert!(room_info.base_info.pinned_events.is_some());
 
-        // Creating a new room info initializes it to version 1.
+        // A room info at version 1 only gets its tags reloaded.
+        room_info.version = 1;
+        room_info.base_info.tags.clear();
+        assert!(room_info.apply_migrations(store.clone()).await);
+
+        assert_eq!(room_info.version, 2);
+        assert!(!room_info.base_info.tags.is_empty());
+
+        // Creating a new room info initializes it to version 2.
         let new_room_info = RoomInfo::new(room_id!("!new_room:localhost"), RoomState::Joined);
-        assert_eq!(new_room_info.version, 1);
+        assert_eq!(new_room_info.version, 2);
     }
 
     #[async_test]
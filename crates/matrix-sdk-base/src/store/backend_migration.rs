@@ -0,0 +1,1159 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migration of the data of a client between two sets of stores, for example
+//! to switch from one store backend to another.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::IntoFuture,
+};
+
+use eyeball::SharedObservable;
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_common::deserialized_responses::TimelineEventKind;
+use matrix_sdk_common::{
+    boxed_into_future,
+    linked_chunk::{ChunkContent, Position, RawChunk, Update},
+    store_locks::LockStoreError,
+};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_crypto::{
+    store::{Changes, CryptoStoreError, DynCryptoStore, PendingChanges},
+    SecretInfo,
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::events::secret::request::SecretName;
+use ruma::{
+    events::{
+        AnyGlobalAccountDataEvent, GlobalAccountDataEventType, RoomAccountDataEventType,
+        StateEventType,
+    },
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
+use tracing::{debug, info};
+
+use super::{DynStateStore, RoomLoadSettings, StateChanges, StoreConfig, StoreError};
+use crate::{
+    deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    event_cache::{
+        store::{DynEventCacheStore, EventCacheStoreError},
+        Event, Gap,
+    },
+    RoomInfo, RoomMemberships, StateStoreDataKey,
+};
+
+/// The types of the state events that are copied.
+///
+/// The state store can't list the types of the state events it contains, so
+/// only the state events of those types are copied.
+const STATE_EVENT_TYPES: &[StateEventType] = &[
+    StateEventType::BeaconInfo,
+    StateEventType::CallMember,
+    StateEventType::MemberHints,
+    StateEventType::RoomAvatar,
+    StateEventType::RoomCanonicalAlias,
+    StateEventType::RoomCreate,
+    StateEventType::RoomEncryption,
+    StateEventType::RoomGuestAccess,
+    StateEventType::RoomHistoryVisibility,
+    StateEventType::RoomJoinRules,
+    StateEventType::RoomMember,
+    StateEventType::RoomName,
+    StateEventType::RoomPinnedEvents,
+    StateEventType::RoomPowerLevels,
+    StateEventType::RoomServerAcl,
+    StateEventType::RoomThirdPartyInvite,
+    StateEventType::RoomTombstone,
+    StateEventType::RoomTopic,
+    StateEventType::SpaceChild,
+    StateEventType::SpaceParent,
+];
+
+/// The types of the global account data events that are copied, in addition to
+/// the `m.secret_storage.key.*` event of the default secret storage key.
+const GLOBAL_ACCOUNT_DATA_EVENT_TYPES: &[GlobalAccountDataEventType] = &[
+    GlobalAccountDataEventType::Direct,
+    GlobalAccountDataEventType::IgnoredUserList,
+    GlobalAccountDataEventType::PushRules,
+    GlobalAccountDataEventType::SecretStorageDefaultKey,
+];
+
+/// The types of the room account data events that are copied.
+const ROOM_ACCOUNT_DATA_EVENT_TYPES: &[RoomAccountDataEventType] = &[
+    RoomAccountDataEventType::FullyRead,
+    RoomAccountDataEventType::MarkedUnread,
+    RoomAccountDataEventType::Tag,
+];
+
+/// The number of room keys saved at once in the target crypto store.
+#[cfg(feature = "e2e-encryption")]
+const ROOM_KEYS_BATCH_SIZE: usize = 1000;
+
+/// The names of the secrets whose inbox and outgoing requests are copied.
+#[cfg(feature = "e2e-encryption")]
+const SECRET_NAMES: &[SecretName] = &[
+    SecretName::CrossSigningMasterKey,
+    SecretName::CrossSigningSelfSigningKey,
+    SecretName::CrossSigningUserSigningKey,
+    SecretName::RecoveryKey,
+];
+
+/// The keys of the custom values that `matrix-sdk-crypto` saves in the crypto
+/// store, which are always copied.
+#[cfg(feature = "e2e-encryption")]
+const CRYPTO_CUSTOM_VALUE_KEYS: &[&str] =
+    &["HAS_MIGRATED_VERIFICATION_LATCH", "generation-counter", "crypto-store-journal"];
+
+/// Copy the data of the stores of `source` to the stores of `target`.
+///
+/// This can be used to switch to another store backend without having to log
+/// in again, and without losing the encryption keys. The stores of the target
+/// must be empty, and none of the stores should be used by a client while the
+/// migration runs.
+///
+/// The copy uses the methods of the store traits, so it works between any two
+/// implementations of the stores. Since the stores can't list everything they
+/// contain, some data is not copied:
+///
+/// * the state events and account data events of types that are not known by
+///   the SDK,
+/// * the read receipts,
+/// * the custom values whose keys are not known by the SDK, unless they are
+///   passed to [`MigrateStores::with_custom_value_keys()`],
+/// * the withheld info of the room keys that are not needed to decrypt an event
+///   of the event cache,
+/// * the outgoing requests of room keys that were already sent,
+/// * the data specific to a user ID, like the recently visited rooms,
+/// * the media cache.
+///
+/// The data is first copied to in-memory stores, and read back from them to
+/// verify that it was saved correctly. It is written to the target stores only
+/// if this succeeds, so the target stores are left untouched if the source
+/// stores can't be read. The data written to the target stores is verified
+/// the same way. If that fails, the target stores may contain a part of the
+/// data and must be cleared before trying again.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use matrix_sdk_base::store::{migrate, StoreConfig};
+/// # async {
+/// # let source = StoreConfig::new("source".to_owned());
+/// # let target = StoreConfig::new("target".to_owned());
+/// let report = migrate(&source, &target).await?;
+/// println!("Copied {} rooms", report.rooms);
+/// # Ok::<_, matrix_sdk_base::store::StoreMigrationError>(()) };
+/// ```
+pub fn migrate<'a>(source: &'a StoreConfig, target: &'a StoreConfig) -> MigrateStores<'a> {
+    MigrateStores { source, target, custom_value_keys: Vec::new(), progress: Default::default() }
+}
+
+/// Future returned by [`migrate()`].
+#[allow(missing_debug_implementations)]
+pub struct MigrateStores<'a> {
+    source: &'a StoreConfig,
+    target: &'a StoreConfig,
+    custom_value_keys: Vec<String>,
+    progress: SharedObservable<StoreMigrationProgress>,
+}
+
+impl MigrateStores<'_> {
+    /// Replace the default `SharedObservable` used for tracking the progress
+    /// of the migration.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<StoreMigrationProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Also copy the custom values with the given keys, from the state store
+    /// and from the crypto store.
+    ///
+    /// The stores can't list the keys of their custom values, so only the
+    /// custom values saved by the SDK are copied by default.
+    pub fn with_custom_value_keys(mut self, keys: impl IntoIterator<Item = String>) -> Self {
+        self.custom_value_keys.extend(keys);
+        self
+    }
+}
+
+impl<'a> IntoFuture for MigrateStores<'a> {
+    type Output = Result<StoreMigrationReport, StoreMigrationError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { source, target, custom_value_keys, progress } = self;
+
+        Box::pin(async move {
+            ensure_empty(target).await?;
+
+            // Copy the data to in-memory stores first, so that nothing is written to the
+            // target stores if the copy fails.
+            let staging = StoreConfig::new(format!(
+                "{}-migration",
+                target.cross_process_store_locks_holder_name
+            ));
+            copy_stores(source, &staging, &custom_value_keys, &progress).await?;
+
+            progress.set(StoreMigrationProgress::WritingTarget);
+            ensure_empty(target).await?;
+
+            let report =
+                copy_stores(&staging, target, &custom_value_keys, &Default::default()).await?;
+
+            progress.set(StoreMigrationProgress::Done);
+            info!(?report, "Stores migrated");
+
+            Ok(report)
+        })
+    }
+}
+
+/// Check that the stores of the given `StoreConfig` don't contain any data.
+async fn ensure_empty(config: &StoreConfig) -> Result<(), StoreMigrationError> {
+    if !config.state_store.get_room_infos(&RoomLoadSettings::All).await?.is_empty() {
+        return Err(StoreMigrationError::TargetNotEmpty);
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    if config.crypto_store.load_account().await?.is_some() {
+        return Err(StoreMigrationError::TargetNotEmpty);
+    }
+
+    Ok(())
+}
+
+/// Copy the data of the stores of `source` to the stores of `target`, and
+/// return a summary of the copied data.
+async fn copy_stores(
+    source: &StoreConfig,
+    target: &StoreConfig,
+    custom_value_keys: &[String],
+    progress: &SharedObservable<StoreMigrationProgress>,
+) -> Result<StoreMigrationReport, StoreMigrationError> {
+    let mut report = StoreMigrationReport::default();
+    let source_event_cache_store = source.event_cache_store.lock().await?;
+    let target_event_cache_store = target.event_cache_store.lock().await?;
+
+    let room_ids = migrate_state_store(
+        &*source.state_store,
+        &*target.state_store,
+        custom_value_keys,
+        progress,
+        &mut report,
+    )
+    .await?;
+
+    #[cfg(feature = "e2e-encryption")]
+    {
+        let utd_session_ids = load_utd_session_ids(&*source_event_cache_store, &room_ids).await?;
+
+        migrate_crypto_store(
+            &*source.crypto_store,
+            &*target.crypto_store,
+            &room_ids,
+            &utd_session_ids,
+            custom_value_keys,
+            progress,
+            &mut report,
+        )
+        .await?;
+    }
+
+    migrate_event_cache_store(
+        &*source_event_cache_store,
+        &*target_event_cache_store,
+        &room_ids,
+        progress,
+        &mut report,
+    )
+    .await?;
+
+    Ok(report)
+}
+
+/// The progress of a store migration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StoreMigrationProgress {
+    /// The migration didn't start yet.
+    #[default]
+    NotStarted,
+
+    /// The state store is being copied.
+    StateStore {
+        /// The number of rooms that have been copied.
+        rooms_copied: usize,
+        /// The total number of rooms to copy.
+        rooms_total: usize,
+    },
+
+    /// The crypto store is being copied.
+    #[cfg(feature = "e2e-encryption")]
+    CryptoStore {
+        /// The number of room keys that have been copied.
+        room_keys_copied: usize,
+        /// The total number of room keys to copy.
+        room_keys_total: usize,
+    },
+
+    /// The event cache store is being copied.
+    EventCacheStore {
+        /// The number of rooms that have been copied.
+        rooms_copied: usize,
+        /// The total number of rooms to copy.
+        rooms_total: usize,
+    },
+
+    /// The data was copied to in-memory stores and verified, and is being
+    /// written to the target stores.
+    WritingTarget,
+
+    /// The migration is done.
+    Done,
+}
+
+/// A summary of the data copied by a store migration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StoreMigrationReport {
+    /// The number of rooms.
+    pub rooms: usize,
+    /// The number of state events, including stripped state events.
+    pub state_events: usize,
+    /// The number of global and room account data events.
+    pub account_data_events: usize,
+    /// The number of requests in the send queue, including dependent requests.
+    pub queued_requests: usize,
+    /// The number of custom values of the state store and of the crypto store.
+    pub custom_values: usize,
+    /// The number of Olm sessions.
+    #[cfg(feature = "e2e-encryption")]
+    pub olm_sessions: usize,
+    /// The number of room keys, i.e. inbound Megolm sessions.
+    #[cfg(feature = "e2e-encryption")]
+    pub room_keys: usize,
+    /// The number of devices.
+    #[cfg(feature = "e2e-encryption")]
+    pub devices: usize,
+    /// The number of secrets in the secrets inbox.
+    #[cfg(feature = "e2e-encryption")]
+    pub secrets: usize,
+    /// The number of chunks in the event cache.
+    pub event_chunks: usize,
+    /// The number of events in the event cache.
+    pub events: usize,
+}
+
+/// An error that can happen during a store migration.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreMigrationError {
+    /// The target stores already contain data.
+    #[error("the target stores already contain data")]
+    TargetNotEmpty,
+
+    /// An error happened in a state store.
+    #[error(transparent)]
+    StateStore(#[from] StoreError),
+
+    /// An error happened in a crypto store.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    CryptoStore(#[from] CryptoStoreError),
+
+    /// An error happened in an event cache store.
+    #[error(transparent)]
+    EventCacheStore(#[from] EventCacheStoreError),
+
+    /// The lock of an event cache store couldn't be acquired.
+    #[error(transparent)]
+    EventCacheStoreLock(#[from] LockStoreError),
+
+    /// The data read back from the target stores doesn't match the data of the
+    /// source stores.
+    #[error("the {0} differ between the source and the target stores")]
+    Verification(&'static str),
+}
+
+/// Copy the state store, and return the IDs of the rooms it contains.
+async fn migrate_state_store(
+    source: &DynStateStore,
+    target: &DynStateStore,
+    custom_value_keys: &[String],
+    progress: &SharedObservable<StoreMigrationProgress>,
+    report: &mut StoreMigrationReport,
+) -> Result<Vec<OwnedRoomId>, StoreMigrationError> {
+    let room_infos = source.get_room_infos(&RoomLoadSettings::All).await?;
+    let rooms_total = room_infos.len();
+    let mut room_ids = Vec::with_capacity(rooms_total);
+
+    debug!(rooms_total, "Copying the state store");
+
+    for (rooms_copied, room_info) in room_infos.into_iter().enumerate() {
+        progress.set(StoreMigrationProgress::StateStore { rooms_copied, rooms_total });
+
+        let room_id = room_info.room_id.clone();
+        let changes = load_room_changes(source, room_info).await?;
+        let (state_events, account_data_events) = count_room_changes(&changes);
+        target.save_changes(&changes).await?;
+
+        if load_room_changes(target, changes.room_infos[&room_id].clone())
+            .await
+            .map(|changes| count_room_changes(&changes))?
+            != (state_events, account_data_events)
+        {
+            return Err(StoreMigrationError::Verification("state events"));
+        }
+
+        report.state_events += state_events;
+        report.account_data_events += account_data_events;
+
+        for key in [
+            StateStoreDataKey::ComposerDraft(&room_id),
+            StateStoreDataKey::SeenKnockRequests(&room_id),
+        ] {
+            if let Some(value) = source.get_kv_data(key).await? {
+                target.set_kv_data(key, value).await?;
+            }
+        }
+
+        report.queued_requests += migrate_send_queue(source, target, &room_id).await?;
+
+        room_ids.push(room_id);
+    }
+
+    report.rooms = room_ids.len();
+
+    let changes = StateChanges {
+        account_data: load_global_account_data(source).await?,
+        ..Default::default()
+    };
+    let account_data_events = changes.account_data.len();
+    target.save_changes(&changes).await?;
+
+    if load_global_account_data(target).await?.len() != account_data_events {
+        return Err(StoreMigrationError::Verification("account data events"));
+    }
+
+    report.account_data_events += account_data_events;
+
+    for key in custom_value_keys {
+        if let Some(value) = source.get_custom_value(key.as_bytes()).await? {
+            target.set_custom_value_no_read(key.as_bytes(), value).await?;
+            report.custom_values += 1;
+        }
+    }
+
+    // Copy the sync token last, so that the target store doesn't claim to be up to
+    // date if the migration is interrupted.
+    for key in [
+        StateStoreDataKey::ServerCapabilities,
+        StateStoreDataKey::UtdHookManagerData,
+        StateStoreDataKey::SyncToken,
+    ] {
+        if let Some(value) = source.get_kv_data(key).await? {
+            target.set_kv_data(key, value).await?;
+        }
+    }
+
+    Ok(room_ids)
+}
+
+/// Load the data of the given room from the state store, as `StateChanges`
+/// that can be saved in another store.
+async fn load_room_changes(
+    store: &DynStateStore,
+    room_info: RoomInfo,
+) -> Result<StateChanges, StoreError> {
+    let room_id = room_info.room_id.clone();
+    let mut changes = StateChanges::default();
+
+    for event_type in STATE_EVENT_TYPES {
+        for event in store.get_state_events(&room_id, event_type.clone()).await? {
+            match event {
+                RawAnySyncOrStrippedState::Sync(raw) => {
+                    let Some(state_key) = raw.get_field::<String>("state_key")? else {
+                        continue;
+                    };
+
+                    changes
+                        .state
+                        .entry(room_id.clone())
+                        .or_default()
+                        .entry(event_type.clone())
+                        .or_default()
+                        .insert(state_key, raw);
+                }
+                RawAnySyncOrStrippedState::Stripped(raw) => {
+                    let Some(state_key) = raw.get_field::<String>("state_key")? else {
+                        continue;
+                    };
+
+                    changes
+                        .stripped_state
+                        .entry(room_id.clone())
+                        .or_default()
+                        .entry(event_type.clone())
+                        .or_default()
+                        .insert(state_key, raw);
+                }
+            }
+        }
+    }
+
+    let user_ids = store.get_user_ids(&room_id, RoomMemberships::empty()).await?;
+    let profiles = store.get_profiles(&room_id, &user_ids).await?;
+    let mut ambiguity_map = HashMap::<_, BTreeSet<_>>::new();
+
+    for (user_id, profile) in profiles {
+        if let Some(display_name) =
+            profile.as_original().and_then(|profile| profile.content.displayname.as_deref())
+        {
+            ambiguity_map
+                .entry(DisplayName::new(display_name))
+                .or_default()
+                .insert(user_id.to_owned());
+        }
+
+        changes.profiles.entry(room_id.clone()).or_default().insert(user_id.to_owned(), profile);
+    }
+
+    if !ambiguity_map.is_empty() {
+        changes.ambiguity_maps.insert(room_id.clone(), ambiguity_map);
+    }
+
+    for event_type in ROOM_ACCOUNT_DATA_EVENT_TYPES {
+        if let Some(event) = store.get_room_account_data_event(&room_id, event_type.clone()).await?
+        {
+            changes
+                .room_account_data
+                .entry(room_id.clone())
+                .or_default()
+                .insert(event_type.clone(), event);
+        }
+    }
+
+    changes.add_room(room_info);
+
+    Ok(changes)
+}
+
+/// Count the state events and the account data events of the given changes.
+fn count_room_changes(changes: &StateChanges) -> (usize, usize) {
+    let state_events = changes
+        .state
+        .values()
+        .flat_map(|events| events.values())
+        .map(|events| events.len())
+        .chain(
+            changes.stripped_state.values().flat_map(|events| events.values()).map(BTreeMap::len),
+        )
+        .sum();
+    let account_data_events = changes.room_account_data.values().map(|events| events.len()).sum();
+
+    (state_events, account_data_events)
+}
+
+/// Load the global account data events from the state store.
+async fn load_global_account_data(
+    store: &DynStateStore,
+) -> Result<BTreeMap<GlobalAccountDataEventType, Raw<AnyGlobalAccountDataEvent>>, StoreError> {
+    let mut account_data = BTreeMap::new();
+
+    for event_type in GLOBAL_ACCOUNT_DATA_EVENT_TYPES {
+        if let Some(event) = store.get_account_data_event(event_type.clone()).await? {
+            account_data.insert(event_type.clone(), event);
+        }
+    }
+
+    // The event of the default secret storage key is needed to use the recovery.
+    if let Some(Ok(AnyGlobalAccountDataEvent::SecretStorageDefaultKey(event))) = account_data
+        .get(&GlobalAccountDataEventType::SecretStorageDefaultKey)
+        .map(|raw| raw.deserialize())
+    {
+        let event_type = GlobalAccountDataEventType::SecretStorageKey(event.content.key_id);
+
+        if let Some(event) = store.get_account_data_event(event_type.clone()).await? {
+            account_data.insert(event_type, event);
+        }
+    }
+
+    Ok(account_data)
+}
+
+/// Copy the requests of the send queue of the given room, and return their
+/// number.
+async fn migrate_send_queue(
+    source: &DynStateStore,
+    target: &DynStateStore,
+    room_id: &RoomId,
+) -> Result<usize, StoreError> {
+    let requests = source.load_send_queue_requests(room_id).await?;
+    let dependent_requests = source.load_dependent_queued_requests(room_id).await?;
+    let count = requests.len() + dependent_requests.len();
+
+    for request in requests {
+        target
+            .save_send_queue_request(
+                room_id,
+                request.transaction_id.clone(),
+                request.created_at,
+                request.kind,
+                request.priority,
+            )
+            .await?;
+
+        if request.error.is_some() {
+            target
+                .update_send_queue_request_status(room_id, &request.transaction_id, request.error)
+                .await?;
+        }
+    }
+
+    for request in dependent_requests {
+        target
+            .save_dependent_queued_request(
+                room_id,
+                &request.parent_transaction_id,
+                request.own_transaction_id,
+                request.created_at,
+                request.kind,
+            )
+            .await?;
+
+        if let Some(parent_key) = request.parent_key {
+            target
+                .mark_dependent_queued_requests_as_ready(
+                    room_id,
+                    &request.parent_transaction_id,
+                    parent_key,
+                )
+                .await?;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Load the IDs of the sessions of the room keys that are needed to decrypt
+/// the events of the event cache store, by room.
+#[cfg(feature = "e2e-encryption")]
+async fn load_utd_session_ids(
+    store: &DynEventCacheStore,
+    room_ids: &[OwnedRoomId],
+) -> Result<BTreeMap<OwnedRoomId, BTreeSet<String>>, EventCacheStoreError> {
+    let mut utd_session_ids = BTreeMap::new();
+
+    for room_id in room_ids {
+        let session_ids = store
+            .load_all_chunks(room_id)
+            .await?
+            .into_iter()
+            .filter_map(|chunk| match chunk.content {
+                ChunkContent::Items(events) => Some(events),
+                ChunkContent::Gap(_) => None,
+            })
+            .flatten()
+            .filter_map(|event| match event.kind {
+                TimelineEventKind::UnableToDecrypt { utd_info, .. } => utd_info.session_id,
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        if !session_ids.is_empty() {
+            utd_session_ids.insert(room_id.clone(), session_ids);
+        }
+    }
+
+    Ok(utd_session_ids)
+}
+
+/// Copy the crypto store.
+#[cfg(feature = "e2e-encryption")]
+async fn migrate_crypto_store(
+    source: &DynCryptoStore,
+    target: &DynCryptoStore,
+    room_ids: &[OwnedRoomId],
+    utd_session_ids: &BTreeMap<OwnedRoomId, BTreeSet<String>>,
+    custom_value_keys: &[String],
+    progress: &SharedObservable<StoreMigrationProgress>,
+    report: &mut StoreMigrationReport,
+) -> Result<(), StoreMigrationError> {
+    let Some(account) = source.load_account().await? else {
+        // There is nothing to copy without an account.
+        return Ok(());
+    };
+
+    debug!("Copying the crypto store");
+
+    let identity_keys = account.identity_keys();
+    target.save_pending_changes(PendingChanges { account: Some(account) }).await?;
+
+    let backup_keys = source.load_backup_keys().await?;
+    let backup_version = backup_keys.backup_version.clone();
+
+    let mut changes = Changes {
+        private_identity: source.load_identity().await?,
+        backup_version: backup_keys.backup_version,
+        backup_decryption_key: backup_keys.decryption_key,
+        dehydrated_device_pickle_key: source.load_dehydrated_device_pickle_key().await?,
+        next_batch_token: source.next_batch_token().await?,
+        ..Default::default()
+    };
+
+    // Copy the devices and identities of the tracked users, and the Olm sessions
+    // with their devices.
+    let tracked_users = source.load_tracked_users().await?;
+
+    for user in &tracked_users {
+        for device in source.get_user_devices(&user.user_id).await?.into_values() {
+            if let Some(curve_key) = device.curve25519_key() {
+                if let Some(sessions) = source.get_sessions(&curve_key.to_base64()).await? {
+                    changes.sessions.extend(sessions);
+                }
+            }
+
+            changes.devices.new.push(device);
+        }
+
+        if let Some(identity) = source.get_user_identity(&user.user_id).await? {
+            changes.identities.new.push(identity);
+        }
+    }
+
+    for room_id in room_ids {
+        if let Some(settings) = source.get_room_settings(room_id).await? {
+            changes.room_settings.insert(room_id.clone(), settings);
+        }
+
+        if let Some(session) = source.get_outbound_group_session(room_id).await? {
+            changes.outbound_group_sessions.push(session);
+        }
+    }
+
+    // Copy the withheld info of the room keys that are missing to decrypt events,
+    // for which it explains why the events can't be decrypted.
+    for (room_id, session_ids) in utd_session_ids {
+        for session_id in session_ids {
+            if let Some(event) = source.get_withheld_info(room_id, session_id).await? {
+                changes
+                    .withheld_session_info
+                    .entry(room_id.clone())
+                    .or_default()
+                    .insert(session_id.clone(), event);
+            }
+        }
+    }
+
+    // Copy the secrets inbox and the outgoing secret requests. The requests of room
+    // keys can only be listed if they were not sent yet.
+    let mut key_requests = BTreeMap::new();
+
+    for secret_name in SECRET_NAMES {
+        changes.secrets.extend(source.get_secrets_from_inbox(secret_name).await?);
+
+        if let Some(request) = source
+            .get_secret_request_by_info(&SecretInfo::SecretRequest(secret_name.clone()))
+            .await?
+        {
+            key_requests.insert(request.request_id.clone(), request);
+        }
+    }
+
+    for request in source.get_unsent_secret_requests().await? {
+        key_requests.insert(request.request_id.clone(), request);
+    }
+
+    changes.key_requests = key_requests.into_values().collect();
+
+    report.olm_sessions = changes.sessions.len();
+    report.devices = changes.devices.new.len();
+    report.secrets = changes.secrets.len();
+
+    target.save_changes(changes).await?;
+    target
+        .save_tracked_users(
+            &tracked_users.iter().map(|user| (&*user.user_id, user.dirty)).collect::<Vec<_>>(),
+        )
+        .await?;
+
+    // Copy the room keys by batches, keeping their backup state.
+    let room_keys = source.get_inbound_group_sessions().await?;
+    let room_keys_total = room_keys.len();
+
+    for (index, batch) in room_keys.chunks(ROOM_KEYS_BATCH_SIZE).enumerate() {
+        progress.set(StoreMigrationProgress::CryptoStore {
+            room_keys_copied: index * ROOM_KEYS_BATCH_SIZE,
+            room_keys_total,
+        });
+
+        let (backed_up, not_backed_up): (Vec<_>, Vec<_>) =
+            batch.iter().cloned().partition(|session| session.backed_up());
+
+        if backup_version.is_some() {
+            target.save_inbound_group_sessions(backed_up, backup_version.as_deref()).await?;
+            target.save_inbound_group_sessions(not_backed_up, None).await?;
+        } else {
+            target
+                .save_inbound_group_sessions(
+                    backed_up.into_iter().chain(not_backed_up).collect(),
+                    None,
+                )
+                .await?;
+        }
+    }
+
+    report.room_keys = room_keys_total;
+
+    for key in
+        CRYPTO_CUSTOM_VALUE_KEYS.iter().copied().chain(custom_value_keys.iter().map(String::as_str))
+    {
+        if let Some(value) = source.get_custom_value(key).await? {
+            target.set_custom_value(key, value).await?;
+            report.custom_values += 1;
+        }
+    }
+
+    // Verify that the keys can be read back.
+    let target_account = target.load_account().await?;
+
+    if target_account.map(|account| account.identity_keys()) != Some(identity_keys) {
+        return Err(StoreMigrationError::Verification("accounts"));
+    }
+
+    let source_counts = source.inbound_group_session_counts(backup_version.as_deref()).await?;
+    let target_counts = target.inbound_group_session_counts(backup_version.as_deref()).await?;
+
+    if (target_counts.total, target_counts.backed_up)
+        != (source_counts.total, source_counts.backed_up)
+    {
+        return Err(StoreMigrationError::Verification("room keys"));
+    }
+
+    if target.load_identity().await?.is_some() != source.load_identity().await?.is_some() {
+        return Err(StoreMigrationError::Verification("cross-signing identities"));
+    }
+
+    Ok(())
+}
+
+/// Copy the linked chunks of the event cache store.
+async fn migrate_event_cache_store(
+    source: &DynEventCacheStore,
+    target: &DynEventCacheStore,
+    room_ids: &[OwnedRoomId],
+    progress: &SharedObservable<StoreMigrationProgress>,
+    report: &mut StoreMigrationReport,
+) -> Result<(), StoreMigrationError> {
+    let rooms_total = room_ids.len();
+
+    debug!(rooms_total, "Copying the event cache store");
+
+    for (rooms_copied, room_id) in room_ids.iter().enumerate() {
+        progress.set(StoreMigrationProgress::EventCacheStore { rooms_copied, rooms_total });
+
+        let chunks = source.load_all_chunks(room_id).await?;
+
+        if chunks.is_empty() {
+            continue;
+        }
+
+        if !target.load_all_chunks(room_id).await?.is_empty() {
+            return Err(StoreMigrationError::TargetNotEmpty);
+        }
+
+        let chunks_count = chunks.len();
+        let events_count = count_events(&chunks);
+        target.handle_linked_chunk_updates(room_id, linked_chunk_updates(chunks)).await?;
+
+        let target_chunks = target.load_all_chunks(room_id).await?;
+
+        if target_chunks.len() != chunks_count || count_events(&target_chunks) != events_count {
+            return Err(StoreMigrationError::Verification("event cache chunks"));
+        }
+
+        report.event_chunks += chunks_count;
+        report.events += events_count;
+    }
+
+    Ok(())
+}
+
+/// Count the events in the given chunks.
+fn count_events(chunks: &[RawChunk<Event, Gap>]) -> usize {
+    chunks
+        .iter()
+        .map(|chunk| match &chunk.content {
+            ChunkContent::Items(items) => items.len(),
+            ChunkContent::Gap(_) => 0,
+        })
+        .sum()
+}
+
+/// Create the updates that recreate the given linked chunk in another store.
+///
+/// The chunks are inserted in order, each one after the previous one, because
+/// some stores expect the previous chunk to exist when inserting a new one.
+fn linked_chunk_updates(chunks: Vec<RawChunk<Event, Gap>>) -> Vec<Update<Event, Gap>> {
+    let mut chunks =
+        chunks.into_iter().map(|chunk| (chunk.identifier, chunk)).collect::<HashMap<_, _>>();
+    let mut next = chunks.values().find(|chunk| chunk.previous.is_none()).map(|c| c.identifier);
+    let mut updates = Vec::new();
+
+    while let Some(chunk) = next.and_then(|identifier| chunks.remove(&identifier)) {
+        next = chunk.next;
+
+        match chunk.content {
+            ChunkContent::Gap(gap) => {
+                updates.push(Update::NewGapChunk {
+                    previous: chunk.previous,
+                    new: chunk.identifier,
+                    next: None,
+                    gap,
+                });
+            }
+            ChunkContent::Items(items) => {
+                updates.push(Update::NewItemsChunk {
+                    previous: chunk.previous,
+                    new: chunk.identifier,
+                    next: None,
+                });
+
+                if !items.is_empty() {
+                    updates
+                        .push(Update::PushItems { at: Position::new(chunk.identifier, 0), items });
+                }
+            }
+        }
+    }
+
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use eyeball::SharedObservable;
+    use matrix_sdk_common::linked_chunk::{ChunkContent, ChunkIdentifier as CId, Position, Update};
+    use matrix_sdk_test::{async_test, event_factory::EventFactory};
+    use ruma::{
+        events::{
+            room::{member::SyncRoomMemberEvent, message::RoomMessageEventContent},
+            AnySyncStateEvent, StateEventType,
+        },
+        room_id, user_id, TransactionId,
+    };
+
+    use super::{migrate, StoreMigrationError, StoreMigrationProgress};
+    use crate::{
+        event_cache::{store::integration_tests::make_test_event, Gap},
+        store::{SerializableEventContent, StoreConfig},
+        RoomInfo, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
+    };
+
+    #[async_test]
+    async fn test_migrate() {
+        let room_id = room_id!("!room:localhost");
+        let alice = user_id!("@alice:localhost");
+        let source = StoreConfig::new("source".to_owned());
+        let target = StoreConfig::new("target".to_owned());
+
+        // Populate the state store.
+        let f = EventFactory::new().room(room_id).sender(alice);
+        let mut changes = StateChanges::new("sync_token".to_owned());
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+
+        for raw in [
+            f.member(alice).display_name("Alice").into_raw::<AnySyncStateEvent>(),
+            f.room_name("Room").into_raw::<AnySyncStateEvent>(),
+        ] {
+            changes.add_state_event(room_id, raw.deserialize().unwrap(), raw);
+        }
+
+        let member = f.member(alice).display_name("Alice").into_raw::<SyncRoomMemberEvent>();
+        changes.profiles.insert(
+            room_id.to_owned(),
+            [(alice.to_owned(), (&member.deserialize().unwrap()).into())].into(),
+        );
+
+        source.state_store.save_changes(&changes).await.unwrap();
+
+        let content =
+            SerializableEventContent::new(&RoomMessageEventContent::text_plain("Hello").into())
+                .unwrap();
+        source
+            .state_store
+            .save_send_queue_request(
+                room_id,
+                TransactionId::new(),
+                ruma::MilliSecondsSinceUnixEpoch::now(),
+                content.into(),
+                0,
+            )
+            .await
+            .unwrap();
+        source.state_store.set_custom_value(b"custom_key", b"value".to_vec()).await.unwrap();
+
+        // Populate the crypto store.
+        #[cfg(feature = "e2e-encryption")]
+        let identity_keys = {
+            use matrix_sdk_crypto::{
+                olm::{Account, SenderData},
+                store::{Changes, PendingChanges},
+                EncryptionSettings,
+            };
+
+            let account = Account::with_device_id(alice, "DEVICE".into());
+            let identity_keys = account.identity_keys();
+            let (_, room_key) = account
+                .create_group_session_pair(
+                    room_id,
+                    EncryptionSettings::default(),
+                    SenderData::unknown(),
+                )
+                .await
+                .unwrap();
+
+            source
+                .crypto_store
+                .save_pending_changes(PendingChanges { account: Some(account) })
+                .await
+                .unwrap();
+            source
+                .crypto_store
+                .save_changes(Changes {
+                    inbound_group_sessions: vec![room_key],
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            source.crypto_store.set_custom_value("custom_key", b"value".to_vec()).await.unwrap();
+
+            identity_keys
+        };
+
+        // Populate the event cache store.
+        source
+            .event_cache_store
+            .lock()
+            .await
+            .unwrap()
+            .handle_linked_chunk_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                    Update::PushItems {
+                        at: Position::new(CId::new(0), 0),
+                        items: vec![make_test_event(room_id, "a"), make_test_event(room_id, "b")],
+                    },
+                    Update::NewGapChunk {
+                        previous: Some(CId::new(0)),
+                        new: CId::new(1),
+                        next: None,
+                        gap: Gap { prev_token: "token".to_owned() },
+                    },
+                    Update::NewItemsChunk {
+                        previous: Some(CId::new(1)),
+                        new: CId::new(2),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(CId::new(2), 0),
+                        items: vec![make_test_event(room_id, "c")],
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        let progress = SharedObservable::new(StoreMigrationProgress::NotStarted);
+        let report = migrate(&source, &target)
+            .with_custom_value_keys(["custom_key".to_owned()])
+            .with_progress_observable(progress.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(progress.get(), StoreMigrationProgress::Done);
+        assert_eq!(report.rooms, 1);
+        assert_eq!(report.state_events, 2);
+        assert_eq!(report.queued_requests, 1);
+        assert_eq!(report.event_chunks, 3);
+        assert_eq!(report.events, 3);
+
+        // The state has been copied.
+        assert_matches!(
+            target.state_store.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap(),
+            Some(StateStoreDataValue::SyncToken(token)) => {
+                assert_eq!(token, "sync_token");
+            }
+        );
+        assert!(target
+            .state_store
+            .get_state_event(room_id, StateEventType::RoomName, "")
+            .await
+            .unwrap()
+            .is_some());
+        let profile = target.state_store.get_profile(room_id, alice).await.unwrap().unwrap();
+        assert_eq!(profile.as_original().unwrap().content.displayname.as_deref(), Some("Alice"));
+        assert_eq!(target.state_store.load_send_queue_requests(room_id).await.unwrap().len(), 1);
+        assert_eq!(
+            target.state_store.get_custom_value(b"custom_key").await.unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+
+        // The encryption keys have been copied.
+        #[cfg(feature = "e2e-encryption")]
+        {
+            assert_eq!(report.room_keys, 1);
+
+            let account = target.crypto_store.load_account().await.unwrap().unwrap();
+            assert_eq!(account.identity_keys(), identity_keys);
+            assert_eq!(target.crypto_store.get_inbound_group_sessions().await.unwrap().len(), 1);
+            assert_eq!(
+                target.crypto_store.get_custom_value("custom_key").await.unwrap().as_deref(),
+                Some(&b"value"[..])
+            );
+        }
+
+        // The events have been copied.
+        let mut chunks =
+            target.event_cache_store.lock().await.unwrap().load_all_chunks(room_id).await.unwrap();
+        chunks.sort_by_key(|chunk| chunk.identifier);
+
+        assert_eq!(chunks.len(), 3);
+        assert_matches!(&chunks[0].content, ChunkContent::Items(items) => {
+            assert_eq!(items.len(), 2);
+        });
+        assert_matches!(&chunks[1].content, ChunkContent::Gap(gap) => {
+            assert_eq!(gap.prev_token, "token");
+        });
+        assert_eq!(chunks[2].previous, Some(CId::new(1)));
+    }
+
+    #[async_test]
+    async fn test_migrate_to_non_empty_stores() {
+        let source = StoreConfig::new("source".to_owned());
+        let target = StoreConfig::new("target".to_owned());
+
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id!("!room:localhost"), RoomState::Joined));
+        target.state_store.save_changes(&changes).await.unwrap();
+
+        assert_matches!(migrate(&source, &target).await, Err(StoreMigrationError::TargetNotEmpty));
+    }
+}
@@ -35,10 +35,10 @@ use serde_json::{json, value::Value as JsonValue};
 
 use super::{
     send_queue::SentRequestKey, DependentQueuedRequestKind, DisplayName, DynStateStore,
-    RoomLoadSettings, ServerCapabilities,
+    RoomLoadSettings, ServerCapabilities, StateEventsFilter, StateEventsQuery,
 };
 use crate::{
-    deserialized_responses::MemberEvent,
+    deserialized_responses::{MemberEvent, RawAnySyncOrStrippedState},
     store::{ChildTransactionId, QueueWedgeError, Result, SerializableEventContent, StateStoreExt},
     RoomInfo, RoomMemberships, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
 };
@@ -96,6 +96,8 @@ pub trait StateStoreIntegrationTests {
     async fn test_server_capabilities_saving(&self);
     /// Test fetching room infos based on [`RoomLoadSettings`].
     async fn test_get_room_infos(&self);
+    /// Test querying state events across rooms.
+    async fn test_query_state_events(&self);
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -1741,6 +1743,136 @@ impl StateStoreIntegrationTests for DynStateStore {
             assert_eq!(all_rooms.len(), 0);
         }
     }
+
+    async fn test_query_state_events(&self) {
+        let room_id_0 = room_id!("!r0:localhost");
+        let room_id_1 = room_id!("!r1:localhost");
+        let room_id_2 = room_id!("!r2:localhost");
+        let alice = user_id!("@alice:localhost");
+        let bob = user_id!("@bob:localhost");
+
+        let f = EventFactory::new();
+        let mut changes = StateChanges::default();
+
+        for room_id in [room_id_0, room_id_1] {
+            changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+
+            for user_id in [alice, bob] {
+                let raw = f.member(user_id).into_raw_sync().cast::<AnySyncStateEvent>();
+                changes.add_state_event(room_id, raw.deserialize().unwrap(), raw);
+            }
+        }
+
+        // Stripped state events are found too.
+        changes.add_room(RoomInfo::new(room_id_2, RoomState::Invited));
+        changes.add_stripped_member(
+            room_id_2,
+            alice,
+            f.member(alice).membership(MembershipState::Invite).into_raw_sync().cast(),
+        );
+
+        self.save_changes(&changes).await.unwrap();
+
+        // Find the member events of a user in all the rooms.
+        let mut query = StateEventsQuery::new(StateEventType::RoomMember);
+        query.state_key = Some(alice.to_string());
+
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(page.events.len(), 3);
+        assert_eq!(page.events[0].0, room_id_0);
+        assert_eq!(page.events[1].0, room_id_1);
+        assert_eq!(page.events[2].0, room_id_2);
+        assert!(page.events.iter().all(|(_, state_key, _)| state_key == alice.as_str()));
+        assert_matches!(page.events[2].2, RawAnySyncOrStrippedState::Stripped(_));
+        assert!(page.next_token.is_none());
+
+        // Paginate through all the member events.
+        let mut query = StateEventsQuery::new(StateEventType::RoomMember);
+        query.limit = 2;
+
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(
+            page.events
+                .iter()
+                .map(|(room_id, state_key, _)| (room_id.as_str(), state_key.as_str()))
+                .collect::<Vec<_>>(),
+            [(room_id_0.as_str(), alice.as_str()), (room_id_0.as_str(), bob.as_str())]
+        );
+        assert_let!(Some(token) = page.next_token);
+        assert_eq!(token.room_id(), room_id_0);
+        assert_eq!(token.state_key(), bob.as_str());
+
+        query.from = Some(token);
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(
+            page.events
+                .iter()
+                .map(|(room_id, state_key, _)| (room_id.as_str(), state_key.as_str()))
+                .collect::<Vec<_>>(),
+            [(room_id_1.as_str(), alice.as_str()), (room_id_1.as_str(), bob.as_str())]
+        );
+        assert_let!(Some(token) = page.next_token);
+
+        query.from = Some(token);
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(
+            page.events
+                .iter()
+                .map(|(room_id, state_key, _)| (room_id.as_str(), state_key.as_str()))
+                .collect::<Vec<_>>(),
+            [(room_id_2.as_str(), alice.as_str())]
+        );
+        assert!(page.next_token.is_none());
+
+        // Filter the member events by their content.
+        let mut query = StateEventsQuery::new(StateEventType::RoomMember);
+        query.content_filter = Some(StateEventsFilter::new(|event| {
+            let content = match event {
+                RawAnySyncOrStrippedState::Sync(raw) => raw.get_field::<JsonValue>("content"),
+                RawAnySyncOrStrippedState::Stripped(raw) => raw.get_field::<JsonValue>("content"),
+            };
+            content.ok().flatten().is_some_and(|content| content["membership"] == "invite")
+        }));
+
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].0, room_id_2);
+        assert!(page.next_token.is_none());
+
+        // The token of a filtered page is the position where the scan stopped, the
+        // events rejected by the filter are not scanned again.
+        let mut query = StateEventsQuery::new(StateEventType::RoomMember);
+        query.limit = 1;
+        query.content_filter = Some(StateEventsFilter::new(move |event| match event {
+            RawAnySyncOrStrippedState::Sync(raw) => {
+                raw.get_field::<String>("state_key").ok().flatten().as_deref() == Some(bob.as_str())
+            }
+            RawAnySyncOrStrippedState::Stripped(_) => false,
+        }));
+
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].0, room_id_0);
+        assert_eq!(page.events[0].1, bob.as_str());
+        assert_let!(Some(token) = page.next_token);
+        assert_eq!(token.room_id(), room_id_1);
+        assert_eq!(token.state_key(), alice.as_str());
+
+        query.from = Some(token);
+        let page = self.query_state_events(&query).await.unwrap();
+        assert_eq!(page.events.len(), 1);
+        assert_eq!(page.events[0].0, room_id_1);
+        assert_eq!(page.events[0].1, bob.as_str());
+        assert!(page.next_token.is_none());
+
+        // Nothing is found for another event type.
+        let page = self
+            .query_state_events(&StateEventsQuery::new(StateEventType::RoomEncryption))
+            .await
+            .unwrap();
+        assert!(page.events.is_empty());
+        assert!(page.next_token.is_none());
+    }
 }
 
 /// Macro building to allow your StateStore implementation to run the entire
@@ -1911,6 +2043,12 @@ macro_rules! statestore_integration_tests {
                 let store = get_store().await.expect("creating store failed").into_state_store();
                 store.test_get_room_infos().await;
             }
+
+            #[async_test]
+            async fn test_query_state_events() {
+                let store = get_store().await.expect("creating store failed").into_state_store();
+                store.test_query_state_events().await;
+            }
         }
     };
 }
@@ -33,6 +33,7 @@ use ruma::{
     CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri,
     OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, RoomVersionId, TransactionId, UserId,
 };
+use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument, warn};
 
 use super::{
@@ -47,7 +48,7 @@ use crate::{
     MinimalRoomMemberEvent, RoomMemberships, StateStoreDataKey, StateStoreDataValue,
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[allow(clippy::type_complexity)]
 struct MemoryStoreInner {
     recently_visited_rooms: HashMap<OwnedUserId, Vec<OwnedRoomId>>,
@@ -59,6 +60,7 @@ struct MemoryStoreInner {
     utd_hook_manager_data: Option<GrowableBloom>,
     account_data: HashMap<GlobalAccountDataEventType, Raw<AnyGlobalAccountDataEvent>>,
     profiles: HashMap<OwnedRoomId, HashMap<OwnedUserId, MinimalRoomMemberEvent>>,
+    #[serde(with = "display_names_serde")]
     display_names: HashMap<OwnedRoomId, HashMap<DisplayName, BTreeSet<OwnedUserId>>>,
     members: HashMap<OwnedRoomId, HashMap<OwnedUserId, MembershipState>>,
     room_info: HashMap<OwnedRoomId, RoomInfo>,
@@ -70,21 +72,32 @@ struct MemoryStoreInner {
         HashMap<OwnedRoomId, HashMap<StateEventType, HashMap<String, Raw<AnyStrippedStateEvent>>>>,
     stripped_members: HashMap<OwnedRoomId, HashMap<OwnedUserId, MembershipState>>,
     presence: HashMap<OwnedUserId, Raw<PresenceEvent>>,
+    #[serde(with = "nested_map_as_entries")]
     room_user_receipts: HashMap<
         OwnedRoomId,
         HashMap<(String, Option<String>), HashMap<OwnedUserId, (OwnedEventId, Receipt)>>,
     >,
 
+    #[serde(with = "nested_map_as_entries")]
     room_event_receipts: HashMap<
         OwnedRoomId,
         HashMap<(String, Option<String>), HashMap<OwnedEventId, HashMap<OwnedUserId, Receipt>>>,
     >,
+    #[serde(with = "map_as_entries")]
     custom: HashMap<Vec<u8>, Vec<u8>>,
     send_queue_events: BTreeMap<OwnedRoomId, Vec<QueuedRequest>>,
     dependent_send_queue_events: BTreeMap<OwnedRoomId, Vec<DependentQueuedRequest>>,
     seen_knock_requests: BTreeMap<OwnedRoomId, BTreeMap<OwnedEventId, OwnedUserId>>,
 }
 
+/// A snapshot of the content of a [`MemoryStore`], taken with
+/// [`MemoryStore::snapshot()`].
+///
+/// It can be serialized, to be shared between test runs for example.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MemoryStoreSnapshot(MemoryStoreInner);
+
 /// In-memory, non-persistent implementation of the `StateStore`.
 ///
 /// Default if no other is configured at startup.
@@ -99,6 +112,27 @@ impl MemoryStore {
         Self::default()
     }
 
+    /// Create a new MemoryStore with the content of the given snapshot.
+    pub fn from_snapshot(snapshot: MemoryStoreSnapshot) -> Self {
+        Self { inner: RwLock::new(snapshot.0) }
+    }
+
+    /// Take a snapshot of the content of this store.
+    ///
+    /// The snapshot can be used to restore the content of the store later,
+    /// with [`MemoryStore::restore()`] or [`MemoryStore::from_snapshot()`].
+    /// This is useful in tests, to set up the state of a client once and reuse
+    /// it, instead of replaying the same sync responses every time.
+    pub fn snapshot(&self) -> MemoryStoreSnapshot {
+        MemoryStoreSnapshot(self.inner.read().unwrap().clone())
+    }
+
+    /// Replace the content of this store with the content of the given
+    /// snapshot.
+    pub fn restore(&self, snapshot: MemoryStoreSnapshot) {
+        *self.inner.write().unwrap() = snapshot.0;
+    }
+
     fn get_user_room_receipt_event_impl(
         &self,
         room_id: &RoomId,
@@ -955,13 +989,174 @@ impl StateStore for MemoryStore {
     }
 }
 
+/// (De)serialize the display names with their raw string as key, since
+/// [`DisplayName`] doesn't implement `Serialize` and `Deserialize`.
+mod display_names_serde {
+    use std::collections::{BTreeSet, HashMap};
+
+    use ruma::{OwnedRoomId, OwnedUserId};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::deserialized_responses::DisplayName;
+
+    type DisplayNames = HashMap<OwnedRoomId, HashMap<DisplayName, BTreeSet<OwnedUserId>>>;
+
+    pub(super) fn serialize<S: Serializer>(
+        display_names: &DisplayNames,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        display_names
+            .iter()
+            .map(|(room_id, names)| {
+                let names = names
+                    .iter()
+                    .map(|(name, user_ids)| (name.as_raw_str(), user_ids))
+                    .collect::<HashMap<_, _>>();
+                (room_id, names)
+            })
+            .collect::<HashMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DisplayNames, D::Error> {
+        let display_names =
+            HashMap::<OwnedRoomId, HashMap<String, BTreeSet<OwnedUserId>>>::deserialize(
+                deserializer,
+            )?;
+
+        Ok(display_names
+            .into_iter()
+            .map(|(room_id, names)| {
+                let names = names
+                    .into_iter()
+                    .map(|(name, user_ids)| (DisplayName::new(&name), user_ids))
+                    .collect();
+                (room_id, names)
+            })
+            .collect())
+    }
+}
+
+/// (De)serialize a map as a list of entries, for maps whose keys can't be
+/// serialized as strings.
+mod map_as_entries {
+    use std::{collections::HashMap, hash::Hash};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize,
+        V: Serialize,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub(super) fn deserialize<'de, D, K, V>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+/// (De)serialize the inner maps of a map of maps as lists of entries, for inner
+/// maps whose keys can't be serialized as strings.
+mod nested_map_as_entries {
+    use std::{collections::HashMap, hash::Hash};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S, K1, K2, V>(
+        map: &HashMap<K1, HashMap<K2, V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K1: Serialize,
+        K2: Serialize,
+        V: Serialize,
+    {
+        serializer
+            .collect_map(map.iter().map(|(key, inner)| (key, inner.iter().collect::<Vec<_>>())))
+    }
+
+    pub(super) fn deserialize<'de, D, K1, K2, V>(
+        deserializer: D,
+    ) -> Result<HashMap<K1, HashMap<K2, V>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K1: Deserialize<'de> + Eq + Hash,
+        K2: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+    {
+        Ok(HashMap::<K1, Vec<(K2, V)>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, inner)| (key, inner.into_iter().collect()))
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{MemoryStore, Result, StateStore};
+    use std::sync::Arc;
+
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{MemoryStore, MemoryStoreSnapshot, Result, StateStore};
+    use crate::{
+        store::{IntoStateStore, RoomLoadSettings, StateStoreIntegrationTests},
+        StateStoreDataKey,
+    };
 
     async fn get_store() -> Result<impl StateStore> {
         Ok(MemoryStore::new())
     }
 
     statestore_integration_tests!();
+
+    #[async_test]
+    async fn test_snapshot_and_restore() {
+        let store = Arc::new(MemoryStore::new());
+        store.clone().into_state_store().populate().await.unwrap();
+
+        let snapshot = store.snapshot();
+        let rooms = store.get_room_infos(&RoomLoadSettings::default()).await.unwrap();
+        assert_eq!(rooms.len(), 2);
+
+        // The snapshot survives a serialization round-trip.
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot: MemoryStoreSnapshot = serde_json::from_str(&json).unwrap();
+
+        let restored = MemoryStore::from_snapshot(snapshot.clone());
+        let restored_rooms = restored.get_room_infos(&RoomLoadSettings::default()).await.unwrap();
+        assert_eq!(restored_rooms.len(), 2);
+        assert_eq!(
+            restored
+                .get_kv_data(StateStoreDataKey::SyncToken)
+                .await
+                .unwrap()
+                .and_then(|value| value.into_sync_token()),
+            store
+                .get_kv_data(StateStoreDataKey::SyncToken)
+                .await
+                .unwrap()
+                .and_then(|value| value.into_sync_token())
+        );
+
+        // Changes made after the snapshot are reverted when it is restored.
+        restored.remove_room(room_id!("!test:localhost")).await.unwrap();
+        restored.remove_kv_data(StateStoreDataKey::SyncToken).await.unwrap();
+        assert_eq!(restored.get_room_infos(&RoomLoadSettings::default()).await.unwrap().len(), 1);
+
+        restored.restore(snapshot);
+        assert_eq!(restored.get_room_infos(&RoomLoadSettings::default()).await.unwrap().len(), 2);
+        assert!(restored.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap().is_some());
+    }
 }
@@ -65,6 +65,7 @@ use crate::{
 };
 
 pub(crate) mod ambiguity_map;
+mod backend_migration;
 mod memory_store;
 pub mod migration_helpers;
 mod send_queue;
@@ -72,7 +73,10 @@ mod send_queue;
 #[cfg(any(test, feature = "testing"))]
 pub use self::integration_tests::StateStoreIntegrationTests;
 pub use self::{
-    memory_store::MemoryStore,
+    backend_migration::{
+        migrate, MigrateStores, StoreMigrationError, StoreMigrationProgress, StoreMigrationReport,
+    },
+    memory_store::{MemoryStore, MemoryStoreSnapshot},
     send_queue::{
         ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind,
         FinishUploadThumbnailInfo, QueueWedgeError, QueuedRequest, QueuedRequestKind,
@@ -80,7 +84,8 @@ pub use self::{
     },
     traits::{
         ComposerDraft, ComposerDraftType, DynStateStore, IntoStateStore, ServerCapabilities,
-        StateStore, StateStoreDataKey, StateStoreDataValue, StateStoreExt,
+        StateEventsFilter, StateEventsPage, StateEventsPageToken, StateEventsQuery, StateStore,
+        StateStoreDataKey, StateStoreDataValue, StateStoreExt,
     },
 };
 
@@ -342,6 +347,19 @@ impl BaseStateStore {
             .clone()
     }
 
+    /// Create a `Room` with the given info, that isn't added to the rooms of
+    /// the store.
+    pub(crate) fn create_detached_room(
+        &self,
+        room_info: RoomInfo,
+        room_info_notable_update_sender: broadcast::Sender<RoomInfoNotableUpdate>,
+    ) -> Room {
+        let user_id =
+            &self.session_meta.get().expect("Creating room while not being logged in").user_id;
+
+        Room::restore(user_id, self.inner.clone(), room_info, room_info_notable_update_sender)
+    }
+
     /// Forget the room with the given room ID.
     ///
     /// # Arguments
@@ -113,7 +113,7 @@ impl From<SerializableEventContent> for QueuedRequestKind {
 }
 
 /// A request to be sent with a send queue.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct QueuedRequest {
     /// The kind of queued request we're going to send.
     pub kind: QueuedRequestKind,
@@ -160,6 +160,91 @@ pub trait StateStore: AsyncTraitDeps {
         state_keys: &[&str],
     ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error>;
 
+    /// Query the state events of a given type across all the rooms known to
+    /// the store.
+    ///
+    /// The results are sorted by room ID and then by state key, and are
+    /// returned in pages of at most [`StateEventsQuery::limit`] events. To get
+    /// the next page, set [`StateEventsQuery::from`] to the token of the
+    /// previous page. The token holds the position where the scan stopped, so
+    /// the events rejected by [`StateEventsQuery::content_filter`] are not
+    /// scanned again.
+    ///
+    /// The default implementation loads the state events of every room
+    /// separately, stores can override it with a more efficient one.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query describing the state events to find.
+    async fn query_state_events(
+        &self,
+        query: &StateEventsQuery,
+    ) -> Result<StateEventsPage, Self::Error> {
+        let mut room_ids = self
+            .get_room_infos(&RoomLoadSettings::All)
+            .await?
+            .into_iter()
+            .map(|info| info.room_id)
+            .collect::<Vec<_>>();
+        room_ids.sort();
+
+        // Resume the scan where the previous page stopped.
+        let start = query
+            .from
+            .as_ref()
+            .map_or(0, |from| room_ids.partition_point(|room_id| *room_id < from.room_id));
+
+        let mut events: Vec<(OwnedRoomId, String, RawAnySyncOrStrippedState)> = Vec::new();
+        // The position of the last scanned event, whether it matched the filter or not.
+        let mut last_scanned = None;
+
+        for room_id in &room_ids[start..] {
+            let room_events = if let Some(state_key) = &query.state_key {
+                self.get_state_events_for_keys(&room_id, query.event_type.clone(), &[state_key])
+                    .await?
+            } else {
+                self.get_state_events(&room_id, query.event_type.clone()).await?
+            };
+
+            let mut room_events = room_events
+                .into_iter()
+                .filter_map(|event| {
+                    let state_key = match &event {
+                        RawAnySyncOrStrippedState::Sync(raw) => {
+                            raw.get_field::<String>("state_key")
+                        }
+                        RawAnySyncOrStrippedState::Stripped(raw) => {
+                            raw.get_field::<String>("state_key")
+                        }
+                    };
+                    Some((state_key.ok()??, event))
+                })
+                .filter(|(state_key, _)| {
+                    query
+                        .from
+                        .as_ref()
+                        .is_none_or(|from| *room_id != from.room_id || *state_key > from.state_key)
+                })
+                .collect::<Vec<_>>();
+            room_events.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            for (state_key, event) in room_events {
+                if query.content_filter.as_ref().is_none_or(|filter| filter.matches(&event)) {
+                    // Find one event more than needed, to know whether there is a next page.
+                    if events.len() == query.limit {
+                        return Ok(StateEventsPage::new(events, last_scanned));
+                    }
+
+                    events.push((room_id.clone(), state_key.clone(), event));
+                }
+
+                last_scanned = Some(StateEventsPageToken::new(room_id.clone(), state_key));
+            }
+        }
+
+        Ok(StateEventsPage::new(events, None))
+    }
+
     /// Get the current profile for the given user in the given room.
     ///
     /// # Arguments
@@ -553,6 +638,13 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.get_state_events_for_keys(room_id, event_type, state_keys).await.map_err(Into::into)
     }
 
+    async fn query_state_events(
+        &self,
+        query: &StateEventsQuery,
+    ) -> Result<StateEventsPage, Self::Error> {
+        self.0.query_state_events(query).await.map_err(Into::into)
+    }
+
     async fn get_profile(
         &self,
         room_id: &RoomId,
@@ -950,6 +1042,130 @@ where
     }
 }
 
+/// A query for state events across rooms, used with
+/// [`StateStore::query_state_events()`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct StateEventsQuery {
+    /// The event type of the state events to find.
+    pub event_type: StateEventType,
+
+    /// Only find the state events with this state key.
+    ///
+    /// For example, this can be used to find the `m.room.member` events of a
+    /// user in all the rooms.
+    pub state_key: Option<String>,
+
+    /// Only find the state events matching this filter.
+    ///
+    /// For example, this can be used to find the `m.room.member` events of a
+    /// user with a given membership.
+    pub content_filter: Option<StateEventsFilter>,
+
+    /// The maximum number of events in a page.
+    ///
+    /// Defaults to [`StateEventsQuery::DEFAULT_LIMIT`].
+    pub limit: usize,
+
+    /// Start the page after the position of this token, that was returned
+    /// with the previous page.
+    pub from: Option<StateEventsPageToken>,
+}
+
+impl StateEventsQuery {
+    /// The default maximum number of events in a page.
+    pub const DEFAULT_LIMIT: usize = 100;
+
+    /// Create a query for the first page of the state events of the given
+    /// type.
+    pub fn new(event_type: StateEventType) -> Self {
+        Self {
+            event_type,
+            state_key: None,
+            content_filter: None,
+            limit: Self::DEFAULT_LIMIT,
+            from: None,
+        }
+    }
+}
+
+/// A predicate on the state events found by a [`StateEventsQuery`].
+#[derive(Clone)]
+pub struct StateEventsFilter(Arc<dyn Fn(&RawAnySyncOrStrippedState) -> bool + Send + Sync>);
+
+impl StateEventsFilter {
+    /// Create a filter keeping the state events for which the given function
+    /// returns `true`.
+    pub fn new(
+        filter: impl Fn(&RawAnySyncOrStrippedState) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(filter))
+    }
+
+    /// Whether the given state event matches this filter.
+    pub fn matches(&self, event: &RawAnySyncOrStrippedState) -> bool {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for StateEventsFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateEventsFilter").finish_non_exhaustive()
+    }
+}
+
+/// A page of results of a [`StateEventsQuery`].
+#[derive(Debug, Clone)]
+pub struct StateEventsPage {
+    /// The state events in this page, with the ID of their room and their
+    /// state key.
+    pub events: Vec<(OwnedRoomId, String, RawAnySyncOrStrippedState)>,
+
+    /// The token to get the next page, if there is one.
+    pub next_token: Option<StateEventsPageToken>,
+}
+
+impl StateEventsPage {
+    /// Create a new `StateEventsPage` with the given events and token.
+    pub fn new(
+        events: Vec<(OwnedRoomId, String, RawAnySyncOrStrippedState)>,
+        next_token: Option<StateEventsPageToken>,
+    ) -> Self {
+        Self { events, next_token }
+    }
+}
+
+/// The position where the scan of a [`StateEventsPage`] stopped, to get the
+/// next page.
+///
+/// It can be serialized to continue the query later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateEventsPageToken {
+    room_id: OwnedRoomId,
+    state_key: String,
+}
+
+impl StateEventsPageToken {
+    /// Create a new token for the position of the state event with the given
+    /// room ID and state key.
+    ///
+    /// This is meant for stores overriding
+    /// [`StateStore::query_state_events()`].
+    pub fn new(room_id: OwnedRoomId, state_key: String) -> Self {
+        Self { room_id, state_key }
+    }
+
+    /// The ID of the room of the last scanned event.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+
+    /// The state key of the last scanned event.
+    pub fn state_key(&self) -> &str {
+        &self.state_key
+    }
+}
+
 /// Server capabilities returned by the /client/versions endpoint.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ServerCapabilities {
@@ -0,0 +1,311 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of the arguments of a command into typed values.
+//!
+//! The arguments are separated by whitespace. An argument can contain
+//! whitespace if it's wrapped in double quotes, in which case `\"` and `\\`
+//! can be used to escape a quote and a backslash.
+
+use ruma::{OwnedEventId, OwnedRoomAliasId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId};
+use thiserror::Error;
+
+/// An error when parsing the arguments of a command.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum ArgError {
+    /// An argument is missing.
+    #[error("missing argument #{position}")]
+    Missing {
+        /// The position of the missing argument, starting at 1.
+        position: usize,
+    },
+
+    /// An argument couldn't be parsed into the expected type.
+    #[error("invalid argument #{position} `{value}`, expected {expected}")]
+    Invalid {
+        /// The position of the invalid argument, starting at 1.
+        position: usize,
+        /// The value of the argument.
+        value: String,
+        /// A description of the expected value.
+        expected: &'static str,
+    },
+
+    /// More arguments than expected were given.
+    #[error("unexpected argument `{0}`")]
+    TooMany(String),
+
+    /// A quoted argument isn't terminated.
+    #[error("unterminated quoted argument")]
+    UnterminatedQuote,
+}
+
+/// The arguments of a command that haven't been parsed yet.
+#[derive(Clone, Debug)]
+pub struct Args<'a> {
+    remaining: &'a str,
+    position: usize,
+}
+
+impl<'a> Args<'a> {
+    /// Create the arguments from the text following the name of a command.
+    pub fn new(text: &'a str) -> Self {
+        Self { remaining: text.trim_start(), position: 0 }
+    }
+
+    /// Whether all the arguments have been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// Parse the next argument into the given type.
+    pub fn next_arg<T: FromArg>(&mut self) -> Result<T, ArgError> {
+        match self.next_token()? {
+            Some(token) => T::from_arg(&token).map_err(|expected| ArgError::Invalid {
+                position: self.position,
+                value: token,
+                expected,
+            }),
+            None => Err(ArgError::Missing { position: self.position + 1 }),
+        }
+    }
+
+    /// Parse the next argument into the given type, if there is one.
+    pub fn next_optional_arg<T: FromArg>(&mut self) -> Result<Option<T>, ArgError> {
+        if self.is_empty() {
+            Ok(None)
+        } else {
+            self.next_arg().map(Some)
+        }
+    }
+
+    /// Consume all the remaining text, as-is.
+    pub fn rest(&mut self) -> &'a str {
+        let rest = self.remaining.trim_end();
+        self.remaining = "";
+        rest
+    }
+
+    /// Make sure that all the arguments have been consumed.
+    pub fn finish(mut self) -> Result<(), ArgError> {
+        match self.next_token()? {
+            Some(token) => Err(ArgError::TooMany(token)),
+            None => Ok(()),
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<String>, ArgError> {
+        if self.remaining.is_empty() {
+            return Ok(None);
+        }
+
+        self.position += 1;
+
+        let (token, rest) = if let Some(quoted) = self.remaining.strip_prefix('"') {
+            let mut token = String::new();
+            let mut chars = quoted.char_indices();
+
+            loop {
+                match chars.next() {
+                    Some((i, '"')) => break (token, &quoted[i + 1..]),
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => token.push(c),
+                        None => return Err(ArgError::UnterminatedQuote),
+                    },
+                    Some((_, c)) => token.push(c),
+                    None => return Err(ArgError::UnterminatedQuote),
+                }
+            }
+        } else {
+            let end = self.remaining.find(char::is_whitespace).unwrap_or(self.remaining.len());
+            (self.remaining[..end].to_owned(), &self.remaining[end..])
+        };
+
+        self.remaining = rest.trim_start();
+        Ok(Some(token))
+    }
+}
+
+/// A type that can be parsed from a single argument of a command.
+pub trait FromArg: Sized {
+    /// Parse the argument.
+    ///
+    /// Returns a description of the expected value on error, like
+    /// `"a user ID"`.
+    fn from_arg(arg: &str) -> Result<Self, &'static str>;
+}
+
+impl FromArg for String {
+    fn from_arg(arg: &str) -> Result<Self, &'static str> {
+        Ok(arg.to_owned())
+    }
+}
+
+impl FromArg for bool {
+    fn from_arg(arg: &str) -> Result<Self, &'static str> {
+        match arg.to_lowercase().as_str() {
+            "true" | "yes" | "on" => Ok(true),
+            "false" | "no" | "off" => Ok(false),
+            _ => Err("yes or no"),
+        }
+    }
+}
+
+macro_rules! from_arg_via_parse {
+    ($($ty:ty => $expected:literal),* $(,)?) => {
+        $(
+            impl FromArg for $ty {
+                fn from_arg(arg: &str) -> Result<Self, &'static str> {
+                    arg.parse().map_err(|_| $expected)
+                }
+            }
+        )*
+    };
+}
+
+from_arg_via_parse! {
+    i32 => "an integer",
+    i64 => "an integer",
+    u32 => "a positive integer",
+    u64 => "a positive integer",
+    usize => "a positive integer",
+    f64 => "a number",
+    OwnedUserId => "a user ID",
+    OwnedRoomId => "a room ID",
+    OwnedRoomAliasId => "a room alias",
+    OwnedRoomOrAliasId => "a room ID or alias",
+    OwnedEventId => "an event ID",
+}
+
+/// The remaining text of a command, as-is.
+///
+/// It can only be used as the last argument of a command.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+/// A type that can be parsed from all the arguments of a command.
+///
+/// It's implemented for tuples of up to 4 [`FromArg`] types, which can end
+/// with an `Option<T>` for an optional trailing argument or with [`Rest`].
+pub trait FromArgs: Sized {
+    /// Parse the arguments.
+    fn from_args(args: Args<'_>) -> Result<Self, ArgError>;
+}
+
+/// A single element of a [`FromArgs`] tuple.
+pub trait FromArgsElement: Sized {
+    /// Parse this element from the arguments.
+    fn parse(args: &mut Args<'_>) -> Result<Self, ArgError>;
+}
+
+impl<T: FromArg> FromArgsElement for T {
+    fn parse(args: &mut Args<'_>) -> Result<Self, ArgError> {
+        args.next_arg()
+    }
+}
+
+impl<T: FromArg> FromArgsElement for Option<T> {
+    fn parse(args: &mut Args<'_>) -> Result<Self, ArgError> {
+        args.next_optional_arg()
+    }
+}
+
+impl FromArgsElement for Rest {
+    fn parse(args: &mut Args<'_>) -> Result<Self, ArgError> {
+        Ok(Rest(args.rest().to_owned()))
+    }
+}
+
+impl FromArgs for () {
+    fn from_args(args: Args<'_>) -> Result<Self, ArgError> {
+        args.finish()
+    }
+}
+
+macro_rules! impl_from_args_for_tuple {
+    ($($ty:ident),+) => {
+        impl<$($ty: FromArgsElement),+> FromArgs for ($($ty,)+) {
+            fn from_args(mut args: Args<'_>) -> Result<Self, ArgError> {
+                let parsed = ($($ty::parse(&mut args)?,)+);
+                args.finish()?;
+                Ok(parsed)
+            }
+        }
+    };
+}
+
+impl_from_args_for_tuple!(A);
+impl_from_args_for_tuple!(A, B);
+impl_from_args_for_tuple!(A, B, C);
+impl_from_args_for_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{user_id, OwnedUserId};
+
+    use super::{ArgError, Args, FromArgs, Rest};
+
+    #[test]
+    fn test_parse_typed_arguments() {
+        let (user_id, level) =
+            <(OwnedUserId, i64)>::from_args(Args::new("  @alice:localhost   50 ")).unwrap();
+        assert_eq!(user_id, user_id!("@alice:localhost"));
+        assert_eq!(level, 50);
+    }
+
+    #[test]
+    fn test_parse_quoted_arguments() {
+        let (first, second) =
+            <(String, String)>::from_args(Args::new(r#""hello world" "a \"quote\"""#)).unwrap();
+        assert_eq!(first, "hello world");
+        assert_eq!(second, r#"a "quote""#);
+
+        assert_matches!(
+            <(String,)>::from_args(Args::new(r#""hello"#)),
+            Err(ArgError::UnterminatedQuote)
+        );
+    }
+
+    #[test]
+    fn test_parse_optional_and_rest_arguments() {
+        let (user_id, reason) =
+            <(OwnedUserId, Option<String>)>::from_args(Args::new("@alice:localhost")).unwrap();
+        assert_eq!(user_id, user_id!("@alice:localhost"));
+        assert_eq!(reason, None);
+
+        let (user_id, Rest(reason)) =
+            <(OwnedUserId, Rest)>::from_args(Args::new("@alice:localhost spamming  a lot "))
+                .unwrap();
+        assert_eq!(user_id, user_id!("@alice:localhost"));
+        assert_eq!(reason, "spamming  a lot");
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        assert_matches!(
+            <(OwnedUserId, i64)>::from_args(Args::new("@alice:localhost")),
+            Err(ArgError::Missing { position: 2 })
+        );
+
+        assert_matches!(
+            <(OwnedUserId,)>::from_args(Args::new("alice")),
+            Err(ArgError::Invalid { position: 1, value, expected: "a user ID" })
+        );
+        assert_eq!(value, "alice");
+
+        assert_matches!(<()>::from_args(Args::new("foo bar")), Err(ArgError::TooMany(token)));
+        assert_eq!(token, "foo");
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The [`Bot`], routing the commands received in rooms to their handlers.
+
+use std::{fmt::Write as _, sync::Arc};
+
+use matrix_sdk::{event_handler::EventHandlerHandle, executor::spawn, Client, Room, RoomState};
+use ruma::{
+    events::room::message::{MessageType, OriginalSyncRoomMessageEvent},
+    MilliSecondsSinceUnixEpoch,
+};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    args::Args,
+    command::{Command, CommandContext, CommandError, ReplyMode},
+    throttle::{RoomThrottler, Throttle},
+};
+
+/// A builder for a [`Bot`].
+#[derive(Debug)]
+pub struct BotBuilder {
+    client: Client,
+    prefixes: Vec<String>,
+    commands: Vec<Command>,
+    throttle: Option<Throttle>,
+    reply_mode: ReplyMode,
+    help_command: bool,
+}
+
+impl BotBuilder {
+    /// Add a prefix that commands must start with, like `!` or `/`.
+    ///
+    /// If no prefix is set, `!` is used.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Register a command.
+    pub fn command(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Limit how many commands are handled per room.
+    ///
+    /// Commands received above the limit are ignored. By default, commands
+    /// aren't throttled.
+    pub fn throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Set how the bot replies to commands.
+    ///
+    /// Defaults to [`ReplyMode::Threaded`].
+    pub fn reply_mode(mut self, reply_mode: ReplyMode) -> Self {
+        self.reply_mode = reply_mode;
+        self
+    }
+
+    /// Don't register the built-in `help` command, which lists the commands
+    /// of the bot.
+    pub fn without_help_command(mut self) -> Self {
+        self.help_command = false;
+        self
+    }
+
+    /// Build the bot.
+    ///
+    /// The bot doesn't react to commands until [`Bot::start()`] is called.
+    pub fn build(mut self) -> Bot {
+        if self.prefixes.is_empty() {
+            self.prefixes.push("!".to_owned());
+        }
+
+        Bot {
+            inner: Arc::new(BotInner {
+                client: self.client,
+                prefixes: self.prefixes,
+                commands: self.commands,
+                throttler: self.throttle.map(RoomThrottler::new),
+                reply_mode: self.reply_mode,
+                help_command: self.help_command,
+            }),
+        }
+    }
+}
+
+/// A bot reacting to the commands sent in the rooms it's joined.
+///
+/// It's built on top of the event handlers of the [`Client`], so the client
+/// must be synced for the bot to receive commands. Replies are sent via the
+/// send queue of the rooms.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk::Client;
+/// use matrix_sdk_bot::{Bot, Command, CommandContext};
+///
+/// # async fn example(client: Client) -> matrix_sdk::Result<()> {
+/// let bot = Bot::builder(client.clone())
+///     .command(Command::new(
+///         "ping",
+///         |ctx: CommandContext, (): ()| async move {
+///             ctx.reply("pong").await?;
+///             Ok(())
+///         },
+///     ))
+///     .build();
+///
+/// bot.start();
+/// client.sync(Default::default()).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bot {
+    inner: Arc<BotInner>,
+}
+
+#[derive(Debug)]
+struct BotInner {
+    client: Client,
+    prefixes: Vec<String>,
+    commands: Vec<Command>,
+    throttler: Option<RoomThrottler>,
+    reply_mode: ReplyMode,
+    help_command: bool,
+}
+
+impl Bot {
+    /// Create a builder for a bot using the given client.
+    pub fn builder(client: Client) -> BotBuilder {
+        BotBuilder {
+            client,
+            prefixes: Vec::new(),
+            commands: Vec::new(),
+            throttle: None,
+            reply_mode: ReplyMode::default(),
+            help_command: true,
+        }
+    }
+
+    /// Start reacting to commands.
+    ///
+    /// The commands that were sent before the bot started, according to the
+    /// timestamp of the homeserver, are ignored, so the backlog received with
+    /// the first sync isn't replayed.
+    ///
+    /// Returns the handle of the event handler used by the bot, which can be
+    /// passed to [`Client::remove_event_handler()`] to stop it.
+    pub fn start(&self) -> EventHandlerHandle {
+        let inner = self.inner.clone();
+        let started_at = MilliSecondsSinceUnixEpoch::now();
+
+        self.inner.client.add_event_handler(
+            move |event: OriginalSyncRoomMessageEvent, room: Room| {
+                let inner = inner.clone();
+
+                async move {
+                    if event.origin_server_ts < started_at {
+                        debug!("Ignoring a message sent before the bot started");
+                        return;
+                    }
+
+                    // Don't block the sync while the command is handled.
+                    spawn(async move { inner.handle_message(event, room).await });
+                }
+            },
+        )
+    }
+
+    /// The help text listing the commands of the bot.
+    pub fn help_text(&self) -> String {
+        self.inner.help_text()
+    }
+}
+
+impl BotInner {
+    #[instrument(skip_all, fields(room_id = %room.room_id(), event_id = %event.event_id))]
+    async fn handle_message(&self, event: OriginalSyncRoomMessageEvent, room: Room) {
+        if room.state() != RoomState::Joined || room.own_user_id() == event.sender {
+            return;
+        }
+
+        let MessageType::Text(text) = &event.content.msgtype else {
+            return;
+        };
+
+        let Some(invocation) = self.strip_prefix(&text.body) else {
+            return;
+        };
+
+        let (name, args) = invocation.split_once(char::is_whitespace).unwrap_or((invocation, ""));
+        let name = name.to_lowercase();
+
+        let is_help = self.help_command && name == "help";
+        let Some(command) = self.commands.iter().find(|command| command.matches(&name)) else {
+            if !is_help {
+                debug!(%name, "Ignoring unknown command");
+                return;
+            }
+
+            if self.is_throttled(&room) {
+                return;
+            }
+
+            let ctx = CommandContext::new(room, event.clone(), self.reply_mode);
+            if let Err(error) = ctx.reply(self.help_text()).await {
+                warn!("Couldn't send the help: {error}");
+            }
+            return;
+        };
+
+        if self.is_throttled(&room) {
+            return;
+        }
+
+        let ctx = CommandContext::new(room.clone(), event.clone(), self.reply_mode);
+
+        match command.is_allowed(&room, &event.sender).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(%name, sender = %event.sender, "Refusing command, insufficient permissions");
+                if let Err(error) =
+                    ctx.reply("You are not allowed to use this command in this room.").await
+                {
+                    warn!("Couldn't send the permission error: {error}");
+                }
+                return;
+            }
+            Err(error) => {
+                warn!(%name, "Couldn't check the permissions of the sender: {error}");
+                return;
+            }
+        }
+
+        let result = command.handler.call(ctx.clone(), Args::new(args)).await;
+
+        let reply = match result {
+            Ok(()) => return,
+            Err(CommandError::Args(error)) => {
+                format!("{error}\nUsage: {}", command.usage_line(&self.prefixes[0]))
+            }
+            Err(CommandError::Reply(message)) => message,
+            Err(error) => {
+                warn!(%name, "Command failed: {error}");
+                return;
+            }
+        };
+
+        if let Err(error) = ctx.reply(reply).await {
+            warn!("Couldn't send the error of the command: {error}");
+        }
+    }
+
+    /// Strip the first matching prefix from the given message, if any.
+    fn strip_prefix<'a>(&self, body: &'a str) -> Option<&'a str> {
+        let body = body.trim();
+        self.prefixes
+            .iter()
+            .find_map(|prefix| body.strip_prefix(prefix.as_str()))
+            .filter(|invocation| invocation.starts_with(|c: char| !c.is_whitespace()))
+    }
+
+    fn is_throttled(&self, room: &Room) -> bool {
+        let throttled =
+            self.throttler.as_ref().is_some_and(|throttler| !throttler.try_acquire(room.room_id()));
+
+        if throttled {
+            debug!("Ignoring command, too many commands in this room");
+        }
+
+        throttled
+    }
+
+    fn help_text(&self) -> String {
+        let prefix = &self.prefixes[0];
+        let mut help = String::from("Available commands:");
+
+        for command in &self.commands {
+            let _ = write!(help, "\n{}", command.usage_line(prefix));
+
+            if let Some(description) = &command.description {
+                let _ = write!(help, ": {description}");
+            }
+        }
+
+        if self.help_command && !self.commands.iter().any(|command| command.matches("help")) {
+            let _ = write!(help, "\n{prefix}help: Show this message");
+        }
+
+        help
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::test_utils::mocks::MatrixMockServer;
+    use matrix_sdk_test::async_test;
+
+    use super::Bot;
+    use crate::{Command, CommandContext};
+
+    #[async_test]
+    async fn test_help_text() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let bot = Bot::builder(client)
+            .prefix("/")
+            .prefix("!")
+            .command(
+                Command::new("ping", |_: CommandContext, (): ()| async { Ok(()) })
+                    .description("Check that the bot is alive"),
+            )
+            .command(
+                Command::new("echo", |_: CommandContext, (_,): (String,)| async { Ok(()) })
+                    .usage("<text>"),
+            )
+            .build();
+
+        assert_eq!(
+            bot.help_text(),
+            "Available commands:\n\
+             /ping: Check that the bot is alive\n\
+             /echo <text>\n\
+             /help: Show this message"
+        );
+    }
+
+    #[async_test]
+    async fn test_strip_prefix() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let bot = Bot::builder(client).prefix("!").prefix("/").build();
+
+        assert_eq!(bot.inner.strip_prefix("!ping"), Some("ping"));
+        assert_eq!(bot.inner.strip_prefix("  /echo hello "), Some("echo hello"));
+        assert_eq!(bot.inner.strip_prefix("! ping"), None);
+        assert_eq!(bot.inner.strip_prefix("ping"), None);
+    }
+}
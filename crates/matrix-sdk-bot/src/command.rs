@@ -0,0 +1,328 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The declaration of the commands of a bot, and the context they're invoked
+//! with.
+
+use std::{fmt, future::Future, sync::Arc};
+
+use matrix_sdk::{
+    send_queue::{RoomSendQueueError, SendHandle},
+    BoxFuture, Room, SendOutsideWasm, SyncOutsideWasm,
+};
+use ruma::{
+    events::room::{
+        message::{
+            AddMentions, ForwardThread, OriginalSyncRoomMessageEvent, ReplyWithinThread,
+            RoomMessageEventContent,
+        },
+        power_levels::PowerLevelAction,
+    },
+    UserId,
+};
+use thiserror::Error;
+
+use crate::args::{ArgError, Args, FromArgs};
+
+/// An error returned by the handler of a command.
+#[derive(Debug, Error)]
+pub enum CommandError {
+    /// The arguments of the command are invalid.
+    #[error(transparent)]
+    Args(#[from] ArgError),
+
+    /// An error that should be reported to the sender of the command, as-is.
+    #[error("{0}")]
+    Reply(String),
+
+    /// An error from the SDK.
+    #[error(transparent)]
+    Sdk(#[from] matrix_sdk::Error),
+
+    /// The reply couldn't be sent.
+    #[error(transparent)]
+    SendQueue(#[from] RoomSendQueueError),
+}
+
+/// How the bot replies to a command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplyMode {
+    /// Reply in a thread rooted at the command, or in the command's thread if
+    /// it was sent in one.
+    #[default]
+    Threaded,
+
+    /// Use a plain rich reply to the command.
+    Reply,
+
+    /// Send a regular message, without any relation to the command.
+    Plain,
+}
+
+/// The permission required to run a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Permission {
+    /// The sender must have at least this power level.
+    PowerLevel(i64),
+
+    /// The sender must be allowed to perform this action in the room.
+    Action(PowerLevelAction),
+}
+
+/// The context a command is invoked with.
+#[derive(Clone, Debug)]
+pub struct CommandContext {
+    room: Room,
+    event: OriginalSyncRoomMessageEvent,
+    reply_mode: ReplyMode,
+}
+
+impl CommandContext {
+    pub(crate) fn new(
+        room: Room,
+        event: OriginalSyncRoomMessageEvent,
+        reply_mode: ReplyMode,
+    ) -> Self {
+        Self { room, event, reply_mode }
+    }
+
+    /// The room the command was sent in.
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// The user who sent the command.
+    pub fn sender(&self) -> &UserId {
+        &self.event.sender
+    }
+
+    /// The message event containing the command.
+    pub fn event(&self) -> &OriginalSyncRoomMessageEvent {
+        &self.event
+    }
+
+    /// Reply to the command with the given plain text.
+    pub async fn reply(&self, body: impl Into<String>) -> Result<SendHandle, CommandError> {
+        self.reply_with(RoomMessageEventContent::notice_plain(body)).await
+    }
+
+    /// Reply to the command with the given Markdown text.
+    #[cfg(feature = "markdown")]
+    pub async fn reply_markdown(
+        &self,
+        body: impl AsRef<str> + Into<String>,
+    ) -> Result<SendHandle, CommandError> {
+        self.reply_with(RoomMessageEventContent::notice_markdown(body)).await
+    }
+
+    /// Reply to the command with the given content.
+    ///
+    /// The relation of the content is set according to the [`ReplyMode`] of
+    /// the bot, and the reply is sent via the room's send queue.
+    pub async fn reply_with(
+        &self,
+        content: RoomMessageEventContent,
+    ) -> Result<SendHandle, CommandError> {
+        let content = self.make_reply(content);
+        Ok(self.room.send_queue().send(content.into()).await?)
+    }
+
+    fn make_reply(&self, content: RoomMessageEventContent) -> RoomMessageEventContent {
+        // Don't mention ourselves if we're replying to our own message.
+        let mentions = if self.room.own_user_id() == self.event.sender {
+            AddMentions::No
+        } else {
+            AddMentions::Yes
+        };
+
+        match self.reply_mode {
+            ReplyMode::Threaded => {
+                let original = self.event.clone().into_full_event(self.room.room_id().to_owned());
+                content.make_for_thread(&original, ReplyWithinThread::No, mentions)
+            }
+            ReplyMode::Reply => {
+                let original = self.event.clone().into_full_event(self.room.room_id().to_owned());
+                content.make_reply_to(&original, ForwardThread::Yes, mentions)
+            }
+            ReplyMode::Plain => content,
+        }
+    }
+}
+
+/// The type-erased handler of a command.
+pub(crate) trait CommandHandler: SendOutsideWasm + SyncOutsideWasm {
+    fn call<'a>(
+        &'a self,
+        ctx: CommandContext,
+        args: Args<'a>,
+    ) -> BoxFuture<'a, Result<(), CommandError>>;
+}
+
+struct TypedHandler<F, A> {
+    handler: F,
+    _args: std::marker::PhantomData<fn() -> A>,
+}
+
+impl<F, A, Fut> CommandHandler for TypedHandler<F, A>
+where
+    F: Fn(CommandContext, A) -> Fut + SendOutsideWasm + SyncOutsideWasm,
+    A: FromArgs + 'static,
+    Fut: Future<Output = Result<(), CommandError>> + SendOutsideWasm + 'static,
+{
+    fn call<'a>(
+        &'a self,
+        ctx: CommandContext,
+        args: Args<'a>,
+    ) -> BoxFuture<'a, Result<(), CommandError>> {
+        match A::from_args(args) {
+            Ok(args) => Box::pin((self.handler)(ctx, args)),
+            Err(error) => Box::pin(async move { Err(error.into()) }),
+        }
+    }
+}
+
+/// A command that a [`Bot`](crate::Bot) reacts to.
+///
+/// The arguments of the command are parsed into the type taken by its
+/// handler, which must implement [`FromArgs`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk_bot::{args::Rest, Command, CommandContext};
+/// use ruma::OwnedUserId;
+///
+/// let kick =
+///     Command::new(
+///         "kick",
+///         |ctx: CommandContext,
+///          (user_id, Rest(reason)): (OwnedUserId, Rest)| async move {
+///             let reason = (!reason.is_empty()).then_some(reason);
+///             ctx.room().kick_user(&user_id, reason.as_deref()).await?;
+///             ctx.reply(format!("Kicked {user_id}")).await?;
+///             Ok(())
+///         },
+///     )
+///     .description("Kick a user from the room")
+///     .usage("<user ID> [reason]")
+///     .min_power_level(50);
+/// ```
+#[derive(Clone)]
+pub struct Command {
+    pub(crate) name: String,
+    pub(crate) aliases: Vec<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) usage: Option<String>,
+    pub(crate) permission: Option<Permission>,
+    pub(crate) handler: Arc<dyn CommandHandler>,
+}
+
+impl Command {
+    /// Create a new command with the given name and handler.
+    ///
+    /// The name is matched case-insensitively.
+    pub fn new<F, A, Fut>(name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(CommandContext, A) -> Fut + SendOutsideWasm + SyncOutsideWasm + 'static,
+        A: FromArgs + 'static,
+        Fut: Future<Output = Result<(), CommandError>> + SendOutsideWasm + 'static,
+    {
+        Self {
+            name: name.into().to_lowercase(),
+            aliases: Vec::new(),
+            description: None,
+            usage: None,
+            permission: None,
+            handler: Arc::new(TypedHandler { handler, _args: Default::default() }),
+        }
+    }
+
+    /// Add another name this command can be invoked with.
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into().to_lowercase());
+        self
+    }
+
+    /// Set the description of the command, shown in the help.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the usage of the command's arguments, like `<user ID> [reason]`.
+    ///
+    /// It's shown in the help, and when the arguments are invalid.
+    pub fn usage(mut self, usage: impl Into<String>) -> Self {
+        self.usage = Some(usage.into());
+        self
+    }
+
+    /// Only allow users with at least the given power level to run the
+    /// command.
+    pub fn min_power_level(mut self, level: i64) -> Self {
+        self.permission = Some(Permission::PowerLevel(level));
+        self
+    }
+
+    /// Only allow users who are allowed to do the given action in the room to
+    /// run the command.
+    pub fn requires(mut self, action: PowerLevelAction) -> Self {
+        self.permission = Some(Permission::Action(action));
+        self
+    }
+
+    /// The name of the command.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+
+    /// Whether the given user is allowed to run this command in the room.
+    pub(crate) async fn is_allowed(
+        &self,
+        room: &Room,
+        user_id: &UserId,
+    ) -> Result<bool, matrix_sdk::Error> {
+        match &self.permission {
+            None => Ok(true),
+            Some(Permission::PowerLevel(level)) => {
+                Ok(room.get_user_power_level(user_id).await? >= *level)
+            }
+            Some(Permission::Action(action)) => {
+                room.permissions().can_user(user_id, action.clone()).await
+            }
+        }
+    }
+
+    /// The usage line of the command, with the given prefix.
+    pub(crate) fn usage_line(&self, prefix: &str) -> String {
+        match &self.usage {
+            Some(usage) => format!("{prefix}{} {usage}", self.name),
+            None => format!("{prefix}{}", self.name),
+        }
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("aliases", &self.aliases)
+            .field("permission", &self.permission)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-room throttling of the commands handled by a bot.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use ruma::{time::Instant, OwnedRoomId, RoomId};
+
+/// How many commands a bot handles per room in a given period of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Throttle {
+    /// The maximum number of commands handled in a room during `period`.
+    pub max_commands: usize,
+
+    /// The sliding window over which commands are counted.
+    pub period: Duration,
+}
+
+impl Throttle {
+    /// Handle at most `max_commands` commands per room during `period`.
+    pub fn new(max_commands: usize, period: Duration) -> Self {
+        Self { max_commands, period }
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(10, Duration::from_secs(60))
+    }
+}
+
+/// Keeps track of the commands recently handled in each room.
+#[derive(Debug)]
+pub(crate) struct RoomThrottler {
+    throttle: Throttle,
+    handled: Mutex<HashMap<OwnedRoomId, VecDeque<Instant>>>,
+}
+
+impl RoomThrottler {
+    pub fn new(throttle: Throttle) -> Self {
+        Self { throttle, handled: Default::default() }
+    }
+
+    /// Try to handle a new command in the given room.
+    ///
+    /// Returns `false` if too many commands have been handled in the room
+    /// recently, in which case the command must be dropped.
+    pub fn try_acquire(&self, room_id: &RoomId) -> bool {
+        self.try_acquire_at(room_id, Instant::now())
+    }
+
+    fn try_acquire_at(&self, room_id: &RoomId, now: Instant) -> bool {
+        let mut handled = self.handled.lock().unwrap();
+        let timestamps = handled.entry(room_id.to_owned()).or_default();
+
+        while timestamps
+            .front()
+            .is_some_and(|handled_at| now.duration_since(*handled_at) >= self.throttle.period)
+        {
+            timestamps.pop_front();
+        }
+
+        if timestamps.len() >= self.throttle.max_commands {
+            return false;
+        }
+
+        timestamps.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{room_id, time::Instant};
+
+    use super::{RoomThrottler, Throttle};
+
+    #[test]
+    fn test_throttle_per_room() {
+        let throttler = RoomThrottler::new(Throttle::new(2, Duration::from_secs(10)));
+        let room = room_id!("!room:localhost");
+        let other_room = room_id!("!other:localhost");
+        let start = Instant::now();
+
+        assert!(throttler.try_acquire_at(room, start));
+        assert!(throttler.try_acquire_at(room, start + Duration::from_secs(1)));
+        // The limit is reached for this room…
+        assert!(!throttler.try_acquire_at(room, start + Duration::from_secs(2)));
+        // … but not for the other one.
+        assert!(throttler.try_acquire_at(other_room, start + Duration::from_secs(2)));
+
+        // Once the first command leaves the window, a new one is accepted.
+        assert!(throttler.try_acquire_at(room, start + Duration::from_secs(10)));
+        assert!(!throttler.try_acquire_at(room, start + Duration::from_secs(10)));
+    }
+}
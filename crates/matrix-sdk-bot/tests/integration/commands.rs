@@ -0,0 +1,229 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use matrix_sdk::{
+    send_queue::{LocalEcho, LocalEchoContent, RoomSendQueueUpdate},
+    test_utils::mocks::MatrixMockServer,
+    Client, Room,
+};
+use matrix_sdk_bot::{Bot, Command, CommandContext};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, StateTestEvent};
+use ruma::{
+    event_id,
+    events::{
+        room::message::{MessageType, Relation, RoomMessageEventContentWithoutRelation},
+        AnyMessageLikeEventContent,
+    },
+    room_id, user_id, MilliSecondsSinceUnixEpoch, OwnedUserId, RoomId,
+};
+use tokio::{sync::broadcast::Receiver, time::timeout};
+
+/// Sync a room whose power levels give 100 to the own user and 0 to
+/// `@bob:localhost`.
+async fn sync_room_with_power_levels(
+    server: &MatrixMockServer,
+    client: &Client,
+    room_id: &RoomId,
+) -> Room {
+    server
+        .sync_room(
+            client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::PowerLevels),
+        )
+        .await
+}
+
+/// Create an event factory for messages sent by `@bob:localhost` in the given
+/// room, after the bot started.
+fn event_factory(room_id: &RoomId) -> EventFactory {
+    let f = EventFactory::new().room(room_id).sender(user_id!("@bob:localhost"));
+    f.set_next_ts(MilliSecondsSinceUnixEpoch::now().get().into());
+    f
+}
+
+/// Wait for the next message queued in the room, and return its body and
+/// relation.
+async fn next_queued_message(
+    updates: &mut Receiver<RoomSendQueueUpdate>,
+) -> (String, Option<Relation<RoomMessageEventContentWithoutRelation>>) {
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::NewLocalEvent(LocalEcho {
+            content: LocalEchoContent::Event { serialized_event, .. },
+            ..
+        }))) = timeout(Duration::from_secs(1), updates.recv()).await
+    );
+
+    let content = serialized_event.deserialize().unwrap();
+    assert_let!(AnyMessageLikeEventContent::RoomMessage(message) = content);
+    assert_let!(MessageType::Notice(notice) = message.msgtype);
+
+    (notice.body, message.relates_to)
+}
+
+#[async_test]
+async fn test_command_is_answered_in_a_thread() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!bot:localhost");
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.mock_room_send().ok(event_id!("$reply")).mount().await;
+
+    let room = sync_room_with_power_levels(&server, &client, room_id).await;
+    let (_, mut updates) = room.send_queue().subscribe().await.unwrap();
+
+    let bot = Bot::builder(client.clone())
+        .command(Command::new(
+            "greet",
+            |ctx: CommandContext, (user_id,): (OwnedUserId,)| async move {
+                ctx.reply(format!("Hello {user_id}!")).await?;
+                Ok(())
+            },
+        ))
+        .build();
+    bot.start();
+
+    let f = event_factory(room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(
+                f.text_msg("!greet @alice:localhost").event_id(event_id!("$command")),
+            ),
+        )
+        .await;
+
+    let (body, relation) = next_queued_message(&mut updates).await;
+    assert_eq!(body, "Hello @alice:localhost!");
+    assert_let!(Some(Relation::Thread(thread)) = relation);
+    assert_eq!(thread.event_id, event_id!("$command"));
+    assert!(thread.is_falling_back);
+}
+
+#[async_test]
+async fn test_invalid_arguments_reply_with_usage() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!bot:localhost");
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.mock_room_send().ok(event_id!("$reply")).mount().await;
+
+    let room = sync_room_with_power_levels(&server, &client, room_id).await;
+    let (_, mut updates) = room.send_queue().subscribe().await.unwrap();
+
+    Bot::builder(client.clone())
+        .command(
+            Command::new("greet", |_: CommandContext, (_,): (OwnedUserId,)| async { Ok(()) })
+                .usage("<user ID>"),
+        )
+        .build()
+        .start();
+
+    let f = event_factory(room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(f.text_msg("!greet alice")),
+        )
+        .await;
+
+    let (body, _) = next_queued_message(&mut updates).await;
+    assert_eq!(body, "invalid argument #1 `alice`, expected a user ID\nUsage: !greet <user ID>");
+}
+
+#[async_test]
+async fn test_command_requires_power_level() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!bot:localhost");
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.mock_room_send().ok(event_id!("$reply")).mount().await;
+
+    let room = sync_room_with_power_levels(&server, &client, room_id).await;
+    let (_, mut updates) = room.send_queue().subscribe().await.unwrap();
+
+    Bot::builder(client.clone())
+        .command(
+            Command::new("shutdown", |ctx: CommandContext, (): ()| async move {
+                ctx.reply("Shutting down").await?;
+                Ok(())
+            })
+            .min_power_level(50),
+        )
+        .build()
+        .start();
+
+    // Bob only has power level 0.
+    let f = event_factory(room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(f.text_msg("!shutdown")),
+        )
+        .await;
+
+    let (body, _) = next_queued_message(&mut updates).await;
+    assert_eq!(body, "You are not allowed to use this command in this room.");
+}
+
+#[async_test]
+async fn test_commands_sent_before_the_bot_started_are_ignored() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!bot:localhost");
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server.mock_room_send().ok(event_id!("$reply")).mount().await;
+
+    let room = sync_room_with_power_levels(&server, &client, room_id).await;
+    let (_, mut updates) = room.send_queue().subscribe().await.unwrap();
+
+    // This command was sent long before the bot started.
+    let old_command = EventFactory::new()
+        .room(room_id)
+        .sender(user_id!("@bob:localhost"))
+        .text_msg("!greet @old:localhost")
+        .server_ts(0);
+
+    Bot::builder(client.clone())
+        .command(Command::new(
+            "greet",
+            |ctx: CommandContext, (user_id,): (OwnedUserId,)| async move {
+                ctx.reply(format!("Hello {user_id}!")).await?;
+                Ok(())
+            },
+        ))
+        .build()
+        .start();
+
+    let f = event_factory(room_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(old_command)
+                .add_timeline_event(f.text_msg("!greet @alice:localhost")),
+        )
+        .await;
+
+    // Only the new command is answered.
+    let (body, _) = next_queued_message(&mut updates).await;
+    assert_eq!(body, "Hello @alice:localhost!");
+    assert!(timeout(Duration::from_millis(100), updates.recv()).await.is_err());
+}
@@ -0,0 +1,178 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A source of time, that can be replaced in tests.
+//!
+//! The code that waits for some time, like retries or batching, uses a
+//! [`Clock`] instead of sleeping directly, so tests can use a [`TestClock`]
+//! and advance the time instantly instead of really waiting.
+
+use std::{
+    fmt,
+    future::poll_fn,
+    sync::{Arc, Mutex},
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use ruma::time::Instant;
+
+use crate::{sleep::sleep, BoxFuture, SendOutsideWasm, SyncOutsideWasm};
+
+/// A source of time.
+pub trait Clock: SendOutsideWasm + SyncOutsideWasm + fmt::Debug {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Wait until the given duration has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The [`Clock`] of the system, using the real time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(sleep(duration))
+    }
+}
+
+/// A [`Clock`] whose time only moves forward when
+/// [`TestClock::advance()`] is called.
+///
+/// It's meant to be used in tests: the futures returned by
+/// [`Clock::sleep()`] resolve as soon as the clock has been advanced by their
+/// duration, without waiting in real time.
+#[derive(Clone, Debug)]
+pub struct TestClock {
+    inner: Arc<Mutex<TestClockInner>>,
+}
+
+#[derive(Debug)]
+struct TestClockInner {
+    now: Instant,
+    sleepers: Vec<(Instant, Waker)>,
+}
+
+impl TestClock {
+    /// Create a new clock, starting at the current instant.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TestClockInner {
+                now: Instant::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Move the time of the clock forward, and wake up the sleepers whose
+    /// deadline has been reached.
+    pub fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.now += duration;
+
+            let now = inner.now;
+            let (ready, pending) =
+                inner.sleepers.drain(..).partition::<Vec<_>, _>(|(deadline, _)| *deadline <= now);
+            inner.sleepers = pending;
+
+            ready
+        };
+
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let inner = self.inner.clone();
+        let deadline = self.now() + duration;
+
+        Box::pin(poll_fn(move |cx| {
+            let mut inner = inner.lock().unwrap();
+
+            if inner.now >= deadline {
+                Poll::Ready(())
+            } else {
+                inner.sleepers.push((deadline, cx.waker().clone()));
+                Poll::Pending
+            }
+        }))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::FutureExt;
+    use matrix_sdk_test_macros::async_test;
+
+    use super::{Clock, TestClock};
+    use crate::timeout::timeout_with_clock;
+
+    #[async_test]
+    async fn test_test_clock_sleep() {
+        let clock = TestClock::new();
+        let start = clock.now();
+
+        let mut sleep = clock.sleep(Duration::from_secs(3));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(2));
+        assert!((&mut sleep).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(sleep.now_or_never().is_some());
+
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+    }
+
+    #[async_test]
+    async fn test_timeout_with_test_clock() {
+        let clock = TestClock::new();
+
+        let task = tokio::spawn({
+            let clock = clock.clone();
+            async move {
+                timeout_with_clock(&clock, std::future::pending::<()>(), Duration::from_secs(10))
+                    .await
+            }
+        });
+
+        tokio::task::yield_now().await;
+        assert!(!task.is_finished());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(task.await.unwrap().is_err());
+    }
+}
@@ -21,6 +21,7 @@ use futures_core::Future;
 #[doc(no_inline)]
 pub use ruma;
 
+pub mod clock;
 pub mod debug;
 pub mod deserialized_responses;
 pub mod executor;
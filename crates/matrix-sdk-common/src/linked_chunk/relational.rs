@@ -117,6 +117,28 @@ where
         self.items.clear();
     }
 
+    /// Removes all the chunks and items from this relational linked chunk,
+    /// except the ones that must be kept.
+    ///
+    /// The chunks and items of a room for which `keep_room` returns `true`
+    /// are left untouched. In the other rooms, the items for which
+    /// `keep_item` returns `true` are kept as out-of-band items.
+    pub fn clear_except(
+        &mut self,
+        keep_room: impl Fn(&RoomId) -> bool,
+        keep_item: impl Fn(&RoomId, &ItemId) -> bool,
+    ) {
+        self.chunks.retain(|chunk| keep_room(&chunk.room_id));
+        self.items_chunks.retain(|item_row| keep_room(&item_row.room_id));
+        self.items.retain(|room_id, items| {
+            if !keep_room(room_id) {
+                items.retain(|item_id, _| keep_item(room_id, item_id));
+            }
+
+            !items.is_empty()
+        });
+    }
+
     /// Apply [`Update`]s. That's the only way to write data inside this
     /// relational linked chunk.
     pub fn apply_updates(&mut self, room_id: &RoomId, updates: Vec<Update<Item, Gap>>) {
@@ -1067,6 +1089,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_clear_except() {
+        let r0 = room_id!("!r0:matrix.org");
+        let r1 = room_id!("!r1:matrix.org");
+        let r2 = room_id!("!r2:matrix.org");
+        let mut relational_linked_chunk = RelationalLinkedChunk::<_, char, ()>::new();
+
+        for (room_id, items) in [(r0, vec!['a', 'b']), (r1, vec!['x', 'y']), (r2, vec!['z'])] {
+            relational_linked_chunk.apply_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk { previous: None, new: CId::new(0), next: None },
+                    Update::PushItems { at: Position::new(CId::new(0), 0), items },
+                ],
+            );
+        }
+
+        // Keep all of r0, and only `y` in r1.
+        relational_linked_chunk
+            .clear_except(|room_id| room_id == r0, |room_id, item| room_id == r1 && *item == 'y');
+
+        // Only the chunks from r0 remain.
+        assert_eq!(
+            relational_linked_chunk.chunks,
+            &[ChunkRow {
+                room_id: r0.to_owned(),
+                previous_chunk: None,
+                chunk: CId::new(0),
+                next_chunk: None,
+            }],
+        );
+
+        assert_eq!(
+            relational_linked_chunk.items_chunks,
+            &[
+                ItemRow {
+                    room_id: r0.to_owned(),
+                    position: Position::new(CId::new(0), 0),
+                    item: Either::Item('a')
+                },
+                ItemRow {
+                    room_id: r0.to_owned(),
+                    position: Position::new(CId::new(0), 1),
+                    item: Either::Item('b')
+                },
+            ],
+        );
+
+        // `y` is still there, as an out-of-band item.
+        let mut items = relational_linked_chunk
+            .items()
+            .map(|(item, room_id)| (*item, room_id.to_owned()))
+            .collect::<Vec<_>>();
+        items.sort();
+
+        assert_eq!(items, vec![('a', r0.to_owned()), ('b', r0.to_owned()), ('y', r1.to_owned())]);
+    }
+
     #[test]
     fn test_load_empty_linked_chunk() {
         let room_id = room_id!("!r0:matrix.org");
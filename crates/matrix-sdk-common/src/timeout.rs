@@ -15,13 +15,14 @@
 use std::{error::Error, fmt, time::Duration};
 
 use futures_core::Future;
-#[cfg(target_arch = "wasm32")]
 use futures_util::future::{select, Either};
 #[cfg(target_arch = "wasm32")]
 use gloo_timers::future::TimeoutFuture;
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::time::timeout as tokio_timeout;
 
+use crate::clock::Clock;
+
 /// Error type notifying that a timeout has elapsed.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ElapsedError(());
@@ -58,6 +59,21 @@ where
     }
 }
 
+/// Like [`timeout()`], but the duration is measured with the given [`Clock`].
+pub async fn timeout_with_clock<F, T>(
+    clock: &dyn Clock,
+    future: F,
+    duration: Duration,
+) -> Result<T, ElapsedError>
+where
+    F: Future<Output = T>,
+{
+    match select(std::pin::pin!(future), clock.sleep(duration)).await {
+        Either::Left((res, _)) => Ok(res),
+        Either::Right((_, _)) => Err(ElapsedError(())),
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use std::{future, time::Duration};
@@ -283,7 +283,7 @@ impl RehydratedDevice {
         // encrypted to-device events and fetch out the room keys.
         let mut rehydrated_transaction = self.rehydrated.store().transaction().await;
 
-        let (_, changes) = self
+        let (_, changes, _) = self
             .rehydrated
             .preprocess_sync_changes(&mut rehydrated_transaction, sync_changes)
             .await?;
@@ -41,7 +41,10 @@ use ruma::{
 use tracing::{debug, field::debug, info, instrument, trace, warn, Span};
 use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey};
 
-use super::{GossipRequest, GossippedSecret, RequestEvent, RequestInfo, SecretInfo, WaitQueue};
+use super::{
+    policy::ForwardedRoomKeyLog, ForwardedRoomKeyRecord, GossipRequest, GossippedSecret,
+    KeyRequestRecipients, RequestEvent, RequestInfo, RoomKeyGossipPolicy, SecretInfo, WaitQueue,
+};
 use crate::{
     error::{EventError, OlmError, OlmResult},
     identities::IdentityManager,
@@ -83,6 +86,12 @@ pub(crate) struct GossipMachineInner {
     /// Whether we should send out `m.room_key_request` messages.
     room_key_requests_enabled: AtomicBool,
 
+    /// Which devices take part in room key requests and forwarding.
+    gossip_policy: StdRwLock<RoomKeyGossipPolicy>,
+
+    /// The room keys we recently forwarded, for debugging purposes.
+    forwarded_room_keys: ForwardedRoomKeyLog,
+
     identity_manager: IdentityManager,
 }
 
@@ -110,6 +119,8 @@ impl GossipMachine {
                 users_for_key_claim,
                 room_key_forwarding_enabled,
                 room_key_requests_enabled,
+                gossip_policy: Default::default(),
+                forwarded_room_keys: Default::default(),
                 identity_manager,
             }),
         }
@@ -141,17 +152,72 @@ impl GossipMachine {
         self.inner.room_key_requests_enabled.load(Ordering::SeqCst)
     }
 
+    /// Configure which devices take part in room key requests and
+    /// forwarding, and persist the policy in the store.
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    pub async fn set_room_key_gossip_policy(
+        &self,
+        policy: RoomKeyGossipPolicy,
+    ) -> Result<(), CryptoStoreError> {
+        self.inner.store.set_room_key_gossip_policy(&policy).await?;
+        *self.inner.gossip_policy.write() = policy;
+        Ok(())
+    }
+
+    /// Load the room key gossip policy that was persisted in the store.
+    pub async fn load_room_key_gossip_policy(&self) -> Result<(), CryptoStoreError> {
+        let policy = self.inner.store.get_room_key_gossip_policy().await?;
+        *self.inner.gossip_policy.write() = policy;
+        Ok(())
+    }
+
+    /// Get the currently active room key gossip policy.
+    pub fn room_key_gossip_policy(&self) -> RoomKeyGossipPolicy {
+        self.inner.gossip_policy.read().clone()
+    }
+
+    /// Get the room keys we recently forwarded to other devices, oldest first.
+    pub fn forwarded_room_keys(&self) -> Vec<ForwardedRoomKeyRecord> {
+        self.inner.forwarded_room_keys.entries()
+    }
+
     /// Load stored outgoing requests that were not yet sent out.
     async fn load_outgoing_requests(&self) -> Result<Vec<OutgoingRequest>, CryptoStoreError> {
-        Ok(self
-            .inner
-            .store
-            .get_unsent_secret_requests()
-            .await?
-            .into_iter()
-            .filter(|i| !i.sent_out)
-            .map(|info| info.to_request(self.device_id()))
-            .collect())
+        let mut requests = Vec::new();
+
+        for info in self.inner.store.get_unsent_secret_requests().await? {
+            if !info.sent_out {
+                requests.push(self.to_outgoing_request(&info).await?);
+            }
+        }
+
+        Ok(requests)
+    }
+
+    /// Convert a [`GossipRequest`] into an [`OutgoingRequest`], respecting the
+    /// [`KeyRequestRecipients`] of our gossip policy.
+    ///
+    /// Secret requests are always broadcast to all of our devices, they are
+    /// only ever answered by verified devices anyways.
+    async fn to_outgoing_request(
+        &self,
+        request: &GossipRequest,
+    ) -> Result<OutgoingRequest, CryptoStoreError> {
+        let request_recipients = self.inner.gossip_policy.read().request_recipients;
+
+        match (&request.info, request_recipients) {
+            (SecretInfo::KeyRequest(_), KeyRequestRecipients::OwnVerifiedDevices) => {
+                let devices = self.inner.store.get_user_devices(self.user_id()).await?;
+                let verified_devices = devices
+                    .devices()
+                    .filter(|d| d.device_id() != self.device_id() && d.is_verified())
+                    .map(|d| d.device_id().to_owned())
+                    .collect();
+
+                Ok(request.to_request_for_devices(self.device_id(), Some(&verified_devices)))
+            }
+            _ => Ok(request.to_request(self.device_id())),
+        }
     }
 
     /// Our own user id.
@@ -393,10 +459,23 @@ impl GossipMachine {
         session: &InboundGroupSession,
         message_index: Option<u32>,
     ) -> OlmResult<Option<Session>> {
+        use ruma::MilliSecondsSinceUnixEpoch;
+
         info!(?message_index, "Serving a room key request",);
 
         match self.forward_room_key(session, &device, message_index).await {
-            Ok(s) => Ok(Some(s)),
+            Ok(s) => {
+                self.inner.forwarded_room_keys.push(ForwardedRoomKeyRecord {
+                    user_id: device.user_id().to_owned(),
+                    device_id: device.device_id().to_owned(),
+                    room_id: session.room_id().to_owned(),
+                    session_id: session.session_id().to_owned(),
+                    message_index,
+                    timestamp: MilliSecondsSinceUnixEpoch::now(),
+                });
+
+                Ok(Some(s))
+            }
             Err(OlmError::MissingSession) => {
                 info!(
                     "Key request is missing an Olm session, putting the request in the wait queue",
@@ -604,6 +683,9 @@ impl GossipMachine {
     ///
     /// * In all other cases, refuse to share the session.
     ///
+    /// If the [`RoomKeyGossipPolicy`] only allows forwarding to our own
+    /// verified devices, the second case is skipped.
+    ///
     /// # Arguments
     ///
     /// * `device` - The device that is requesting a session from us.
@@ -626,7 +708,7 @@ impl GossipMachine {
         device: &Device,
         session: &InboundGroupSession,
     ) -> Result<Option<u32>, super::KeyForwardDecision> {
-        use super::KeyForwardDecision;
+        use super::{KeyForwardDecision, KeyForwardingRecipients};
         use crate::olm::ShareState;
 
         let outbound_session = self
@@ -640,6 +722,15 @@ impl GossipMachine {
         // earliest known index.
         if device.user_id() == self.user_id() && device.is_verified() {
             Ok(None)
+        // If the policy says so, we don't reshare with anybody else.
+        } else if self.inner.gossip_policy.read().forwarding_recipients
+            == KeyForwardingRecipients::OwnVerifiedDevices
+        {
+            if device.user_id() == self.user_id() {
+                Err(KeyForwardDecision::UntrustedDevice)
+            } else {
+                Err(KeyForwardDecision::ForeignDeviceNotAllowed)
+            }
         // Otherwise, if the records show we previously shared with this device,
         // we'll reshare the session from the index we previously shared
         // at. For this, we need an outbound session because this
@@ -718,7 +809,7 @@ impl GossipMachine {
 
         if let Some(request) = request {
             let cancel = request.to_cancellation(self.device_id());
-            let request = request.to_request(self.device_id());
+            let request = self.to_outgoing_request(&request).await?;
 
             Ok((Some(cancel), request))
         } else {
@@ -757,7 +848,7 @@ impl GossipMachine {
             sent_out: false,
         };
 
-        let outgoing_request = request.to_request(self.device_id());
+        let outgoing_request = self.to_outgoing_request(&request).await?;
         self.save_outgoing_key_info(request).await?;
 
         Ok(outgoing_request)
@@ -1446,6 +1537,41 @@ mod tests {
         assert!(machine.outgoing_to_device_requests().await.unwrap().is_empty());
     }
 
+    #[async_test]
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    async fn test_create_key_request_own_verified_devices_only() {
+        use ruma::to_device::DeviceIdOrAllDevices;
+
+        use crate::gossiping::RoomKeyGossipPolicy;
+
+        let machine = get_machine_test_helper().await;
+        let account = account();
+        let second_account = alice_2_account();
+        let alice_device = DeviceData::from_account(&second_account);
+
+        alice_device.set_trust_state(LocalTrust::Verified);
+        machine.inner.store.save_device_data(&[alice_device]).await.unwrap();
+
+        machine
+            .set_room_key_gossip_policy(RoomKeyGossipPolicy::own_verified_devices_only())
+            .await
+            .unwrap();
+
+        let (outbound, session) = account.create_group_session_pair_with_defaults(room_id()).await;
+        let content = outbound.encrypt("m.dummy", &message_like_event_content!({})).await;
+        let event = wrap_encrypted_content(machine.user_id(), content);
+
+        assert!(machine.create_outgoing_key_request(session.room_id(), &event).await.unwrap());
+
+        let requests = machine.outgoing_to_device_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+
+        // The request isn't broadcast, it's addressed to our verified device.
+        let messages = &requests[0].request().to_device().unwrap().messages[alice_id()];
+        let recipients: Vec<_> = messages.keys().collect();
+        assert_eq!(recipients, [&DeviceIdOrAllDevices::DeviceId(alice2_device_id().to_owned())]);
+    }
+
     /// We should *not* request keys if that has been disabled
     #[async_test]
     #[cfg(feature = "automatic-room-key-forwarding")]
@@ -1702,6 +1828,55 @@ mod tests {
         assert_matches!(machine.should_share_key(&own_device, &other_inbound).await, Ok(None));
     }
 
+    #[async_test]
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    async fn test_should_share_key_own_verified_devices_only() {
+        use crate::gossiping::RoomKeyGossipPolicy;
+
+        let machine = get_machine_test_helper().await;
+        let account = account();
+
+        machine
+            .set_room_key_gossip_policy(RoomKeyGossipPolicy::own_verified_devices_only())
+            .await
+            .unwrap();
+
+        let own_device =
+            machine.inner.store.get_device(alice_id(), alice2_device_id()).await.unwrap().unwrap();
+
+        let (outbound, inbound) = account.create_group_session_pair_with_defaults(room_id()).await;
+
+        let bob_device = DeviceData::from_account(&bob_account());
+        machine.inner.store.save_device_data(&[bob_device]).await.unwrap();
+        let bob_device =
+            machine.inner.store.get_device(bob_id(), bob_device_id()).await.unwrap().unwrap();
+
+        // Even though the session was shared with both devices originally, we won't
+        // reshare it with anybody but our own verified devices.
+        for device in [&own_device, &bob_device] {
+            outbound
+                .mark_shared_with(
+                    device.user_id(),
+                    device.device_id(),
+                    device.curve25519_key().unwrap(),
+                )
+                .await;
+        }
+        machine.inner.outbound_group_sessions.insert(outbound.clone());
+
+        assert_matches!(
+            machine.should_share_key(&bob_device, &inbound).await,
+            Err(KeyForwardDecision::ForeignDeviceNotAllowed)
+        );
+        assert_matches!(
+            machine.should_share_key(&own_device, &inbound).await,
+            Err(KeyForwardDecision::UntrustedDevice)
+        );
+
+        own_device.set_trust_state(LocalTrust::Verified);
+        assert_matches!(machine.should_share_key(&own_device, &inbound).await, Ok(None));
+    }
+
     #[cfg(feature = "automatic-room-key-forwarding")]
     async fn test_key_share_cycle(algorithm: EventEncryptionAlgorithm) {
         let (alice_machine, group_session, bob_machine) =
@@ -1727,6 +1902,13 @@ mod tests {
         // Now bob does have an outgoing request.
         assert!(!bob_machine.inner.outgoing_requests.read().is_empty());
 
+        // And the forwarded key shows up in the audit log.
+        let forwarded = bob_machine.forwarded_room_keys();
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].user_id, alice_id());
+        assert_eq!(forwarded[0].device_id, alice_machine.device_id());
+        assert_eq!(forwarded[0].session_id, group_session.session_id());
+
         // Get the request and convert it to a encrypted to-device event.
         let requests = bob_machine.outgoing_to_device_requests().await.unwrap();
         let request = &requests[0];
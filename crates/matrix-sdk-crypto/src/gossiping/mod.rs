@@ -13,6 +13,7 @@
 // limitations under the License.
 
 mod machine;
+mod policy;
 
 use std::{
     collections::{BTreeMap, BTreeSet},
@@ -21,6 +22,9 @@ use std::{
 
 pub(crate) use machine::GossipMachine;
 use matrix_sdk_common::locks::RwLock as StdRwLock;
+pub use policy::{
+    ForwardedRoomKeyRecord, KeyForwardingRecipients, KeyRequestRecipients, RoomKeyGossipPolicy,
+};
 use ruma::{
     events::{
         room_key_request::{Action, ToDeviceRoomKeyRequestEventContent},
@@ -83,6 +87,10 @@ pub enum KeyForwardDecision {
     /// accidentally or maliciously changed their curve25519 sender key.
     #[error("the device has changed their curve25519 sender key")]
     ChangedSenderKey,
+    /// The key request is from a device that we don't own and the
+    /// [`RoomKeyGossipPolicy`] forbids forwarding keys to other users.
+    #[error("forwarding room keys to other users is disabled by the gossip policy")]
+    ForeignDeviceNotAllowed,
 }
 
 /// A struct describing an outgoing key request.
@@ -154,7 +162,18 @@ impl GossipRequest {
     }
 
     fn to_request(&self, own_device_id: &DeviceId) -> OutgoingRequest {
-        let request = match &self.info {
+        self.to_request_for_devices(own_device_id, None)
+    }
+
+    /// Like [`GossipRequest::to_request()`], but if `recipient_devices` is
+    /// set, the request is addressed to those devices of the recipient
+    /// instead of being broadcast to all of them.
+    fn to_request_for_devices(
+        &self,
+        own_device_id: &DeviceId,
+        recipient_devices: Option<&BTreeSet<OwnedDeviceId>>,
+    ) -> OutgoingRequest {
+        let mut request = match &self.info {
             SecretInfo::KeyRequest(r) => {
                 let content = RoomKeyRequestContent::new_request(
                     r.clone().into(),
@@ -190,6 +209,16 @@ impl GossipRequest {
             }
         };
 
+        if let Some(devices) = recipient_devices {
+            for messages in request.messages.values_mut() {
+                if let Some(content) = messages.remove(&DeviceIdOrAllDevices::AllDevices) {
+                    messages.extend(devices.iter().map(|device_id| {
+                        (DeviceIdOrAllDevices::DeviceId(device_id.to_owned()), content.clone())
+                    }));
+                }
+            }
+        }
+
         OutgoingRequest { request_id: request.txn_id.clone(), request: Arc::new(request.into()) }
     }
 
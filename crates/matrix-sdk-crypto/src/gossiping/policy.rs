@@ -0,0 +1,172 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Policy controlling to whom room keys are requested from and forwarded to,
+//! and an in-memory audit log of the room keys we forwarded.
+
+use std::collections::VecDeque;
+
+use matrix_sdk_common::locks::RwLock as StdRwLock;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedRoomId, OwnedUserId};
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of entries kept in the [`ForwardedRoomKeyLog`].
+const FORWARDED_ROOM_KEY_LOG_CAPACITY: usize = 256;
+
+/// Which of our own devices outgoing `m.room_key_request`s are sent to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyRequestRecipients {
+    /// Address the request to our own devices that we have verified only.
+    ///
+    /// Unverified devices won't ever see the request. If none of our other
+    /// devices is verified when the request is sent out, it isn't delivered
+    /// to any device, and it isn't sent again once a device gets verified:
+    /// the missing room key has to come from the key backup instead.
+    OwnVerifiedDevices,
+
+    /// Broadcast the request to all of our own devices.
+    ///
+    /// Only our verified devices are expected to answer, but unverified
+    /// devices will learn which sessions we're missing.
+    #[default]
+    AllOwnDevices,
+}
+
+/// Which devices incoming `m.room_key_request`s are automatically answered
+/// for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyForwardingRecipients {
+    /// Forward room keys to our own verified devices only, never to devices
+    /// belonging to other users.
+    OwnVerifiedDevices,
+
+    /// Forward room keys to our own verified devices, and re-share a session
+    /// with the devices of other users if we originally shared it with them.
+    #[default]
+    OwnVerifiedDevicesAndOriginalRecipients,
+}
+
+/// Policy describing how room keys are gossiped between devices.
+///
+/// Whether requests are sent out, or forwarded keys served, at all is still
+/// controlled by [`OlmMachine::set_room_key_requests_enabled`] and
+/// [`OlmMachine::set_room_key_forwarding_enabled`]; this policy only narrows
+/// down *who* takes part in the exchange.
+///
+/// [`OlmMachine::set_room_key_requests_enabled`]: crate::OlmMachine::set_room_key_requests_enabled
+/// [`OlmMachine::set_room_key_forwarding_enabled`]: crate::OlmMachine::set_room_key_forwarding_enabled
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomKeyGossipPolicy {
+    /// The devices we ask for room keys we're missing.
+    pub request_recipients: KeyRequestRecipients,
+
+    /// The devices we automatically forward room keys to.
+    pub forwarding_recipients: KeyForwardingRecipients,
+}
+
+impl RoomKeyGossipPolicy {
+    /// The strictest policy: only talk to our own, verified devices.
+    pub fn own_verified_devices_only() -> Self {
+        Self {
+            request_recipients: KeyRequestRecipients::OwnVerifiedDevices,
+            forwarding_recipients: KeyForwardingRecipients::OwnVerifiedDevices,
+        }
+    }
+}
+
+/// A record of a room key we forwarded to another device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardedRoomKeyRecord {
+    /// The user owning the device the key was forwarded to.
+    pub user_id: OwnedUserId,
+
+    /// The device the key was forwarded to.
+    pub device_id: OwnedDeviceId,
+
+    /// The room the forwarded session belongs to.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the forwarded session.
+    pub session_id: String,
+
+    /// The message index the session was exported at, `None` if the session
+    /// was forwarded starting from the earliest known index.
+    pub message_index: Option<u32>,
+
+    /// When the key was forwarded.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+}
+
+/// A bounded, in-memory log of the room keys we forwarded.
+///
+/// This is meant for debugging only, it isn't persisted and only the most
+/// recent entries are kept.
+#[derive(Debug, Default)]
+pub(crate) struct ForwardedRoomKeyLog {
+    entries: StdRwLock<VecDeque<ForwardedRoomKeyRecord>>,
+}
+
+impl ForwardedRoomKeyLog {
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    pub fn push(&self, record: ForwardedRoomKeyRecord) {
+        let mut entries = self.entries.write();
+
+        if entries.len() == FORWARDED_ROOM_KEY_LOG_CAPACITY {
+            entries.pop_front();
+        }
+
+        entries.push_back(record);
+    }
+
+    /// Get all the entries of the log, oldest first.
+    pub fn entries(&self) -> Vec<ForwardedRoomKeyRecord> {
+        self.entries.read().iter().cloned().collect()
+    }
+}
+
+#[cfg(all(test, feature = "automatic-room-key-forwarding"))]
+mod tests {
+    use ruma::{device_id, room_id, user_id, MilliSecondsSinceUnixEpoch};
+
+    use super::{ForwardedRoomKeyLog, ForwardedRoomKeyRecord, FORWARDED_ROOM_KEY_LOG_CAPACITY};
+
+    fn record(session_id: String) -> ForwardedRoomKeyRecord {
+        ForwardedRoomKeyRecord {
+            user_id: user_id!("@alice:example.org").to_owned(),
+            device_id: device_id!("ALICEDEVICE").to_owned(),
+            room_id: room_id!("!test:example.org").to_owned(),
+            session_id,
+            message_index: None,
+            timestamp: MilliSecondsSinceUnixEpoch::now(),
+        }
+    }
+
+    #[test]
+    fn test_forwarded_room_key_log_is_bounded() {
+        let log = ForwardedRoomKeyLog::default();
+
+        for i in 0..FORWARDED_ROOM_KEY_LOG_CAPACITY + 2 {
+            log.push(record(i.to_string()));
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), FORWARDED_ROOM_KEY_LOG_CAPACITY);
+        // The two oldest entries have been evicted.
+        assert_eq!(entries[0].session_id, "2");
+        assert_eq!(
+            entries.last().unwrap().session_id,
+            (FORWARDED_ROOM_KEY_LOG_CAPACITY + 1).to_string()
+        );
+    }
+}
@@ -85,7 +85,10 @@ pub use file_encryption::{
     decrypt_room_key_export, encrypt_room_key_export, AttachmentDecryptor, AttachmentEncryptor,
     DecryptorError, KeyExportError, MediaEncryptionInfo,
 };
-pub use gossiping::{GossipRequest, GossippedSecret};
+pub use gossiping::{
+    ForwardedRoomKeyRecord, GossipRequest, GossippedSecret, KeyForwardingRecipients,
+    KeyRequestRecipients, RoomKeyGossipPolicy,
+};
 pub use identities::{
     Device, DeviceData, LocalTrust, OtherUserIdentity, OtherUserIdentityData, OwnUserIdentity,
     OwnUserIdentityData, UserDevices, UserIdentity, UserIdentityData,
@@ -101,8 +104,9 @@ pub use store::{
     CrossSigningKeyExport, CryptoStoreError, SecretImportError, SecretInfo, TrackedUser,
 };
 pub use verification::{
-    format_emojis, AcceptSettings, AcceptedProtocols, CancelInfo, Emoji, EmojiShortAuthString, Sas,
-    SasState, Verification, VerificationRequest, VerificationRequestState,
+    format_emojis, AcceptSettings, AcceptedProtocols, CancelInfo, Emoji, EmojiShortAuthString,
+    LocalizableEmoji, Sas, SasState, ShortAuthString, Verification, VerificationRequest,
+    VerificationRequestState, SAS_EMOJI_TRANSLATION_KEY_PREFIX,
 };
 #[cfg(feature = "qrcode")]
 pub use verification::{QrVerification, QrVerificationState, ScanError};
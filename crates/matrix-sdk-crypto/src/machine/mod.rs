@@ -23,7 +23,7 @@ use matrix_sdk_common::{
     deserialized_responses::{
         AlgorithmInfo, DecryptedRoomEvent, DeviceLinkProblem, EncryptionInfo, UnableToDecryptInfo,
         UnableToDecryptReason, UnsignedDecryptionResult, UnsignedEventLocation, VerificationLevel,
-        VerificationState,
+        VerificationState, WithheldCode,
     },
     locks::RwLock as StdRwLock,
     BoxFuture,
@@ -64,7 +64,7 @@ use crate::{
     backups::{BackupMachine, MegolmV1BackupKey},
     dehydrated_devices::{DehydratedDevices, DehydrationError},
     error::{EventError, MegolmError, MegolmResult, OlmError, OlmResult, SetRoomSettingsError},
-    gossiping::GossipMachine,
+    gossiping::{ForwardedRoomKeyRecord, GossipMachine, RoomKeyGossipPolicy},
     identities::{user::UserIdentity, Device, IdentityManager, UserDevices},
     olm::{
         Account, CrossSigningStatus, EncryptionSettings, IdentityKeys, InboundGroupSession,
@@ -73,9 +73,9 @@ use crate::{
     },
     session_manager::{GroupSessionManager, SessionManager},
     store::{
-        Changes, CryptoStoreWrapper, DeviceChanges, IdentityChanges, IntoCryptoStore, MemoryStore,
-        PendingChanges, Result as StoreResult, RoomKeyInfo, RoomSettings, SecretImportError, Store,
-        StoreCache, StoreTransaction,
+        Changes, CryptoStoreWrapper, DecryptedToDeviceEvent, DeviceChanges, IdentityChanges,
+        IntoCryptoStore, MemoryStore, PendingChanges, Result as StoreResult, RoomKeyInfo,
+        RoomSettings, SecretImportError, Store, StoreCache, StoreTransaction,
     },
     types::{
         events::{
@@ -152,7 +152,7 @@ impl std::fmt::Debug for OlmMachine {
 }
 
 impl OlmMachine {
-    const CURRENT_GENERATION_STORE_KEY: &'static str = "generation-counter";
+    pub(crate) const CURRENT_GENERATION_STORE_KEY: &'static str = "generation-counter";
     const HAS_MIGRATED_VERIFICATION_LATCH: &'static str = "HAS_MIGRATED_VERIFICATION_LATCH";
 
     /// Create a new memory based OlmMachine.
@@ -386,14 +386,17 @@ impl OlmMachine {
         // mechanism (at the store wrapper layer).
         Self::migration_post_verified_latch_support(&store, &identity_manager).await?;
 
-        Ok(Self::new_helper(
+        let machine = Self::new_helper(
             device_id,
             store,
             verification_machine,
             identity_manager,
             identity,
             maybe_backup_key,
-        ))
+        );
+        machine.inner.key_request_machine.load_room_key_gossip_policy().await?;
+
+        Ok(machine)
     }
 
     // The sdk now support verified identity change detection.
@@ -504,6 +507,38 @@ impl OlmMachine {
         self.inner.key_request_machine.is_room_key_forwarding_enabled()
     }
 
+    /// Configure which devices we request room keys from and forward room
+    /// keys to.
+    ///
+    /// The policy is persisted in the store, and restored when an
+    /// `OlmMachine` is created with the same store.
+    ///
+    /// This only has an effect if room key requests, respectively room key
+    /// forwarding, are enabled.
+    ///
+    /// See also [`OlmMachine::set_room_key_requests_enabled`] and
+    /// [`OlmMachine::set_room_key_forwarding_enabled`].
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    pub async fn set_room_key_gossip_policy(&self, policy: RoomKeyGossipPolicy) -> StoreResult<()> {
+        self.inner.key_request_machine.set_room_key_gossip_policy(policy).await
+    }
+
+    /// Get the currently active room key gossip policy.
+    ///
+    /// See also [`OlmMachine::set_room_key_gossip_policy`].
+    pub fn room_key_gossip_policy(&self) -> RoomKeyGossipPolicy {
+        self.inner.key_request_machine.room_key_gossip_policy()
+    }
+
+    /// Get a log of the room keys this device recently forwarded to other
+    /// devices, oldest first.
+    ///
+    /// The log is kept in memory only and is bounded in size, it's meant to
+    /// help debug key sharing issues.
+    pub fn forwarded_room_keys(&self) -> Vec<ForwardedRoomKeyRecord> {
+        self.inner.key_request_machine.forwarded_room_keys()
+    }
+
     /// Get the outgoing requests that need to be sent out.
     ///
     /// This returns a list of [`OutgoingRequest`]. Those requests need to be
@@ -1106,6 +1141,48 @@ impl OlmMachine {
             .await
     }
 
+    /// Encrypt a to-device event of a custom type for the given devices.
+    ///
+    /// Beware that the 1-to-1 sessions must be established prior to this call
+    /// by using the [`OlmMachine::get_missing_sessions`] method; devices we
+    /// don't share an Olm session with are skipped.
+    ///
+    /// On the receiving side, such events are reported by
+    /// [`Store::decrypted_to_device_events_stream`].
+    ///
+    /// # Arguments
+    ///
+    /// * `devices` - The devices the event should be sent to.
+    /// * `event_type` - The type of the event to be sent.
+    /// * `content` - The content of the event to be sent.
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the to-device requests that need to be sent out,
+    /// and the devices the event couldn't be encrypted for, because we don't
+    /// share an Olm session with them.
+    pub async fn encrypt_content_for_devices(
+        &self,
+        devices: Vec<DeviceData>,
+        event_type: &str,
+        content: &Value,
+    ) -> OlmResult<(Vec<ToDeviceRequest>, Vec<(DeviceData, WithheldCode)>)> {
+        let mut changes = Changes::default();
+
+        let result = self
+            .inner
+            .group_session_manager
+            .encrypt_content_for_devices(devices, event_type, content.clone(), &mut changes)
+            .await?;
+
+        // Persist the Olm sessions which have been used to encrypt the content.
+        if !changes.is_empty() {
+            self.store().save_changes(changes).await?;
+        }
+
+        Ok(result)
+    }
+
     /// Receive an unencrypted verification event.
     ///
     /// This method can be used to pass verification events that are happening
@@ -1285,6 +1362,7 @@ impl OlmMachine {
         &self,
         transaction: &mut StoreTransaction,
         changes: &mut Changes,
+        decrypted_custom_events: &mut Vec<DecryptedToDeviceEvent>,
         mut raw_event: Raw<AnyToDeviceEvent>,
     ) -> Option<Raw<AnyToDeviceEvent>> {
         Self::record_message_id(&raw_event);
@@ -1366,6 +1444,17 @@ impl OlmMachine {
                             .serialize_zeroized()
                             .expect("Zeroizing and reserializing our events should always work")
                             .cast();
+
+                        if let AnyDecryptedOlmEvent::Custom(custom) =
+                            decrypted.result.event.as_ref()
+                        {
+                            decrypted_custom_events.push(DecryptedToDeviceEvent {
+                                sender: e.sender.clone(),
+                                sender_key: decrypted.result.sender_key,
+                                event_type: custom.event_type.clone(),
+                                raw_event: raw_event.clone(),
+                            });
+                        }
                     }
                     Err(e) => {
                         warn!("Received an invalid encrypted to-device event: {e}");
@@ -1438,7 +1527,7 @@ impl OlmMachine {
     ) -> OlmResult<(Vec<Raw<AnyToDeviceEvent>>, Vec<RoomKeyInfo>)> {
         let mut store_transaction = self.inner.store.transaction().await;
 
-        let (events, changes) =
+        let (events, changes, decrypted_custom_events) =
             self.preprocess_sync_changes(&mut store_transaction, sync_changes).await?;
 
         // Technically save_changes also does the same work, so if it's slow we could
@@ -1449,13 +1538,16 @@ impl OlmMachine {
         self.store().save_changes(changes).await?;
         store_transaction.commit().await?;
 
+        self.inner.store.crypto_store().notify_decrypted_to_device_events(decrypted_custom_events);
+
         Ok((events, room_key_updates))
     }
 
     /// Initial processing of the changes specified within a sync response.
     ///
-    /// Returns the to-device events (decrypted where needed and where possible)
-    /// and the processed set of changes.
+    /// Returns the to-device events (decrypted where needed and where
+    /// possible), the processed set of changes, and the decrypted to-device
+    /// events of custom types.
     ///
     /// If any of the to-device events in the supplied changes were sent from
     /// dehydrated devices, these are not processed, and are omitted from
@@ -1464,7 +1556,7 @@ impl OlmMachine {
         &self,
         transaction: &mut StoreTransaction,
         sync_changes: EncryptionSyncChanges<'_>,
-    ) -> OlmResult<(Vec<Raw<AnyToDeviceEvent>>, Changes)> {
+    ) -> OlmResult<(Vec<Raw<AnyToDeviceEvent>>, Changes, Vec<DecryptedToDeviceEvent>)> {
         // Remove verification objects that have expired or are done.
         let mut events = self.inner.verification_machine.garbage_collect();
 
@@ -1492,9 +1584,16 @@ impl OlmMachine {
             error!(error = ?e, "Error marking a tracked user as changed");
         }
 
+        let mut decrypted_custom_events = Vec::new();
+
         for raw_event in sync_changes.to_device_events {
-            let raw_event =
-                Box::pin(self.receive_to_device_event(transaction, &mut changes, raw_event)).await;
+            let raw_event = Box::pin(self.receive_to_device_event(
+                transaction,
+                &mut changes,
+                &mut decrypted_custom_events,
+                raw_event,
+            ))
+            .await;
 
             if let Some(raw_event) = raw_event {
                 events.push(raw_event);
@@ -1510,7 +1609,7 @@ impl OlmMachine {
         changes.sessions.extend(changed_sessions);
         changes.next_batch_token = sync_changes.next_batch_token;
 
-        Ok((events, changes))
+        Ok((events, changes, decrypted_custom_events))
     }
 
     /// Request a room key from our devices.
@@ -2385,6 +2484,12 @@ impl OlmMachine {
             .set_custom_value(Self::CURRENT_GENERATION_STORE_KEY, gen.to_le_bytes().to_vec())
             .await?;
 
+        // Leave a trace of this generation in the journal, so other processes know it
+        // has been maintained.
+        let crypto_store = self.inner.store.crypto_store();
+        crypto_store.set_journal_generation(gen).await;
+        crypto_store.record_in_journal(|_| {}).await?;
+
         *gen_guard = Some(gen);
 
         Ok(())
@@ -2442,6 +2547,9 @@ impl OlmMachine {
         let new_gen = match gen_guard.as_ref() {
             Some(expected_gen) => {
                 if actual_gen == *expected_gen {
+                    // This `OlmMachine` might have been created after the generation was
+                    // initialized, make sure it maintains the journal.
+                    self.inner.store.crypto_store().set_journal_generation(actual_gen).await;
                     return Ok((false, actual_gen));
                 }
                 // Increment the biggest, and store it everywhere.
@@ -2470,9 +2578,68 @@ impl OlmMachine {
             .set_custom_value(Self::CURRENT_GENERATION_STORE_KEY, new_gen.to_le_bytes().to_vec())
             .await?;
 
+        let crypto_store = self.inner.store.crypto_store();
+        crypto_store.set_journal_generation(new_gen).await;
+        crypto_store.record_in_journal(|_| {}).await?;
+
         Ok((true, new_gen))
     }
 
+    /// Invalidate the in-memory caches for the data other processes have
+    /// changed in the store, using the journal they've maintained.
+    ///
+    /// This is a cheaper alternative to recreating the whole `OlmMachine`
+    /// after [`OlmMachine::maintain_crypto_store_generation`] reported that
+    /// another process wrote to the store.
+    ///
+    /// This requires that the crypto store lock has been acquired.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_generation` - The generation this process had before the
+    ///   call to [`OlmMachine::maintain_crypto_store_generation`], if any.
+    /// * `current_generation` - The generation returned by
+    ///   [`OlmMachine::maintain_crypto_store_generation`].
+    ///
+    /// # Returns
+    ///
+    /// `true` if the caches have been invalidated, `false` if the journal
+    /// doesn't describe all the changes made by other processes; in this case,
+    /// the `OlmMachine` must be recreated.
+    pub async fn invalidate_caches_from_journal(
+        &self,
+        previous_generation: Option<u64>,
+        current_generation: u64,
+    ) -> StoreResult<bool> {
+        let Some(previous_generation) = previous_generation else {
+            return Ok(false);
+        };
+
+        let journal = self.inner.store.crypto_store().load_journal().await?;
+
+        let Some(changes) = journal.changes_between(previous_generation, current_generation) else {
+            debug!("The crypto store journal is incomplete, the caches can't be invalidated");
+            return Ok(false);
+        };
+
+        if changes.backup_keys {
+            // The backup machine keeps its own copy of the backup keys, let the caller
+            // reload everything.
+            return Ok(false);
+        }
+
+        debug!(?changes, "Invalidating caches using the crypto store journal");
+
+        self.inner.store.invalidate_caches(&changes).await?;
+
+        let group_sessions = self.inner.group_session_manager.session_cache();
+        for room_id in &changes.outbound_group_sessions {
+            group_sessions.remove(room_id);
+        }
+
+        Ok(true)
+    }
+
     /// Manage dehydrated devices.
     pub fn dehydrated_devices(&self) -> DehydratedDevices {
         DehydratedDevices { inner: self.to_owned() }
@@ -1705,3 +1705,25 @@ async fn test_verified_latch_migration() {
         assert!(!tu.dirty);
     });
 }
+
+#[async_test]
+#[cfg(feature = "automatic-room-key-forwarding")]
+async fn test_room_key_gossip_policy_is_persisted() {
+    use crate::RoomKeyGossipPolicy;
+
+    let store = Arc::new(MemoryStore::new());
+
+    let alice =
+        OlmMachine::with_store(user_id(), alice_device_id(), store.clone(), None).await.unwrap();
+    assert_eq!(alice.room_key_gossip_policy(), RoomKeyGossipPolicy::default());
+
+    alice
+        .set_room_key_gossip_policy(RoomKeyGossipPolicy::own_verified_devices_only())
+        .await
+        .unwrap();
+    drop(alice);
+
+    // The policy is restored when the machine is created again with the same store.
+    let alice = OlmMachine::with_store(user_id(), alice_device_id(), store, None).await.unwrap();
+    assert_eq!(alice.room_key_gossip_policy(), RoomKeyGossipPolicy::own_verified_devices_only());
+}
@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use assert_matches2::assert_matches;
+use assert_matches2::{assert_let, assert_matches};
+use futures_util::{pin_mut, FutureExt, StreamExt};
+use matrix_sdk_common::deserialized_responses::WithheldCode;
 use matrix_sdk_test::async_test;
 use ruma::to_device::DeviceIdOrAllDevices;
 use serde_json::{json, value::to_raw_value};
@@ -121,3 +123,68 @@ async fn test_send_encrypted_to_device_no_session() {
 
     assert_matches!(encryption_result, Err(OlmError::MissingSession));
 }
+
+#[async_test]
+async fn test_encrypt_content_for_devices() {
+    let (alice, bob) =
+        get_machine_pair_with_session(tests::alice_id(), tests::user_id(), false).await;
+
+    let custom_event_type = "org.example.custom";
+    let custom_content = json!({ "secret": "hunter2" });
+
+    let device = alice.get_device(bob.user_id(), bob.device_id(), None).await.unwrap().unwrap();
+    let (requests, withheld) = alice
+        .encrypt_content_for_devices(vec![device.inner], custom_event_type, &custom_content)
+        .await
+        .unwrap();
+
+    assert!(withheld.is_empty());
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].event_type.to_string(), "m.room.encrypted");
+
+    let event = ToDeviceEvent::new(
+        alice.user_id().to_owned(),
+        tests::to_device_requests_to_content(requests.into_iter().map(Into::into).collect()),
+    );
+    let event = json_convert(&event).unwrap();
+
+    let stream = bob.store().decrypted_to_device_events_stream();
+    pin_mut!(stream);
+
+    let sync_changes = EncryptionSyncChanges {
+        to_device_events: vec![event],
+        changed_devices: &Default::default(),
+        one_time_keys_counts: &Default::default(),
+        unused_fallback_keys: None,
+        next_batch_token: None,
+    };
+    bob.receive_sync_changes(sync_changes).await.unwrap();
+
+    // The decrypted event is reported on the stream.
+    assert_let!(Some(Some(events)) = stream.next().now_or_never());
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].sender, alice.user_id());
+    assert_eq!(events[0].sender_key, alice.identity_keys().curve25519);
+    assert_eq!(events[0].event_type, custom_event_type);
+
+    let decrypted = events[0].raw_event.deserialize_as::<serde_json::Value>().unwrap();
+    assert_eq!(decrypted["content"], custom_content);
+}
+
+#[async_test]
+async fn test_encrypt_content_for_devices_no_session() {
+    let (alice, bob, _) = get_machine_pair(tests::alice_id(), tests::user_id(), false).await;
+
+    let device =
+        alice.get_device(bob.user_id(), tests::bob_device_id(), None).await.unwrap().unwrap();
+    let (requests, withheld) = alice
+        .encrypt_content_for_devices(vec![device.inner], "org.example.custom", &json!({}))
+        .await
+        .unwrap();
+
+    // The device is skipped, since we don't share an Olm session with it.
+    assert!(requests.is_empty());
+    assert_eq!(withheld.len(), 1);
+    assert_eq!(withheld[0].0.device_id(), tests::bob_device_id());
+    assert_eq!(withheld[0].1, WithheldCode::NoOlm);
+}
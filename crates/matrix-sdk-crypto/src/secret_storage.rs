@@ -99,6 +99,135 @@ pub enum DecodeError {
     KdfIterationCount(UInt),
 }
 
+/// The result of validating a, possibly partially typed in, Base58 encoded
+/// [`SecretStorageKey`], also known as a recovery key.
+///
+/// This is returned by the [`SecretStorageKey::validate_base58()`] method and
+/// allows the user to receive immediate feedback while typing in their
+/// recovery key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryKeyValidation {
+    /// The recovery key is well-formed and its checksum is correct.
+    ///
+    /// *Note*: This doesn't check that the key is the one used for the user's
+    /// secret storage.
+    Valid,
+    /// The input doesn't contain any invalid characters, but it's too short
+    /// to be a recovery key. Whitespace isn't counted.
+    Incomplete {
+        /// The number of Base58 characters which have been entered so far.
+        entered: usize,
+        /// The number of Base58 characters of a complete recovery key.
+        expected: usize,
+    },
+    /// The input contains a character which isn't part of the Base58
+    /// alphabet.
+    InvalidCharacter {
+        /// The position of the character in the input, counted in characters.
+        position: usize,
+        /// The invalid character.
+        character: char,
+    },
+    /// The input contains more characters than a recovery key.
+    TooLong {
+        /// The number of Base58 characters which have been entered.
+        entered: usize,
+        /// The number of Base58 characters of a complete recovery key.
+        expected: usize,
+    },
+    /// The input doesn't start with the recovery key prefix, it likely isn't
+    /// a recovery key.
+    InvalidPrefix,
+    /// The parity byte of the recovery key doesn't match, the key has likely
+    /// been mistyped.
+    InvalidChecksum,
+}
+
+impl RecoveryKeyValidation {
+    /// Is the recovery key well-formed.
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid)
+    }
+}
+
+/// A rough estimate of how hard a passphrase would be to guess.
+///
+/// The estimate is based on the characters used in the passphrase, penalizing
+/// repeated and sequential characters. It doesn't check the passphrase against
+/// dictionaries of common passwords, so it should only be used to give the
+/// user feedback while they choose a passphrase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PassphraseStrength {
+    /// The passphrase can be trivially guessed.
+    VeryWeak,
+    /// The passphrase can be guessed with little effort.
+    Weak,
+    /// The passphrase offers some protection, but should be made longer.
+    Fair,
+    /// The passphrase is hard to guess.
+    Strong,
+    /// The passphrase is very hard to guess.
+    VeryStrong,
+}
+
+impl PassphraseStrength {
+    /// Estimate the strength of the given passphrase.
+    pub fn estimate(passphrase: &str) -> Self {
+        match Self::entropy_bits(passphrase) {
+            bits if bits < 28.0 => Self::VeryWeak,
+            bits if bits < 36.0 => Self::Weak,
+            bits if bits < 60.0 => Self::Fair,
+            bits if bits < 80.0 => Self::Strong,
+            _ => Self::VeryStrong,
+        }
+    }
+
+    /// Is the passphrase strong enough to be used to protect the user's
+    /// secrets, i.e. is it at least [`PassphraseStrength::Fair`].
+    pub fn is_acceptable(&self) -> bool {
+        *self >= Self::Fair
+    }
+
+    /// Estimate the entropy of the passphrase in bits.
+    ///
+    /// The size of the alphabet is derived from the classes of characters
+    /// which are used, characters which repeat or continue a sequence (e.g.
+    /// `aaa` or `abc`) only count as a quarter of a character.
+    fn entropy_bits(passphrase: &str) -> f64 {
+        let (mut lower, mut upper, mut digits, mut symbols, mut other) =
+            (false, false, false, false, false);
+        let mut length = 0.0;
+        let mut previous: Option<char> = None;
+
+        for c in passphrase.chars() {
+            match c {
+                'a'..='z' => lower = true,
+                'A'..='Z' => upper = true,
+                '0'..='9' => digits = true,
+                c if c.is_ascii() => symbols = true,
+                _ => other = true,
+            }
+
+            let is_predictable =
+                previous.is_some_and(|p| (c as i64 - p as i64).abs() <= 1 && c.is_alphanumeric());
+
+            length += if is_predictable { 0.25 } else { 1.0 };
+            previous = Some(c);
+        }
+
+        let pool_size = [(lower, 26), (upper, 26), (digits, 10), (symbols, 33), (other, 100)]
+            .into_iter()
+            .filter_map(|(used, size)| used.then_some(size))
+            .sum::<u32>();
+
+        if pool_size == 0 {
+            0.0
+        } else {
+            length * f64::from(pool_size).log2()
+        }
+    }
+}
+
 /// A secret storage key which can be used to store encrypted data in the user's
 /// account data as defined in the [spec].
 ///
@@ -222,6 +351,10 @@ impl SecretStorageKey {
     // parity byte
     const DECODED_BASE58_KEY_LEN: usize = 2 + 32 + 1;
 
+    // Due to the fixed prefix, the 35 decoded bytes always encode to 48 Base58
+    // characters.
+    const ENCODED_BASE58_KEY_LEN: usize = 48;
+
     /// Calculate a parity byte for the base58-encoded variant of the
     /// [`SecretStorageKey`]. Described in the [spec].
     ///
@@ -458,6 +591,55 @@ impl SecretStorageKey {
         Ok(key)
     }
 
+    /// Validate a, possibly partially typed in, Base58 encoded
+    /// [`SecretStorageKey`], also known as a recovery key.
+    ///
+    /// Whitespace in the input is ignored. This only checks that the input is
+    /// well-formed, use [`SecretStorageKey::from_account_data()`] to check
+    /// that the key matches the description of a secret storage key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use matrix_sdk_crypto::secret_storage::{
+    ///     RecoveryKeyValidation, SecretStorageKey,
+    /// };
+    ///
+    /// assert_eq!(
+    ///     SecretStorageKey::validate_base58("EsTp RvZT"),
+    ///     RecoveryKeyValidation::Incomplete { entered: 8, expected: 48 }
+    /// );
+    /// ```
+    pub fn validate_base58(input: &str) -> RecoveryKeyValidation {
+        const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+        if let Some((position, character)) = input
+            .chars()
+            .enumerate()
+            .find(|(_, c)| !c.is_whitespace() && !BASE58_ALPHABET.contains(*c))
+        {
+            return RecoveryKeyValidation::InvalidCharacter { position, character };
+        }
+
+        let entered = input.chars().filter(|c| !c.is_whitespace()).count();
+        let expected = Self::ENCODED_BASE58_KEY_LEN;
+
+        if entered < expected {
+            RecoveryKeyValidation::Incomplete { entered, expected }
+        } else if entered > expected {
+            RecoveryKeyValidation::TooLong { entered, expected }
+        } else {
+            match Self::parse_base58_key(input) {
+                Ok(mut key) => {
+                    key.zeroize();
+                    RecoveryKeyValidation::Valid
+                }
+                Err(DecodeError::Prefix(..)) => RecoveryKeyValidation::InvalidPrefix,
+                Err(_) => RecoveryKeyValidation::InvalidChecksum,
+            }
+        }
+    }
+
     /// Export the [`SecretStorageKey`] as a base58-encoded string as defined in
     /// the [spec].
     ///
@@ -818,6 +1000,63 @@ mod test {
         );
     }
 
+    #[test]
+    fn base58_validation() {
+        let key = "EsTpRvZTnjck8YrhRAtwXLS84Nr2r9S9LGAWDaExVAPBvLRK";
+
+        for entered in 0..48 {
+            assert_eq!(
+                SecretStorageKey::validate_base58(&key[..entered]),
+                RecoveryKeyValidation::Incomplete { entered, expected: 48 },
+            );
+        }
+
+        assert_eq!(SecretStorageKey::validate_base58(key), RecoveryKeyValidation::Valid);
+        assert_eq!(
+            SecretStorageKey::validate_base58(
+                "EsTp RvZT njck 8Yrh RAtw XLS8 4Nr2 r9S9 LGAW DaEx VAPB vLRK"
+            ),
+            RecoveryKeyValidation::Valid,
+            "Whitespace should not matter"
+        );
+
+        assert_eq!(
+            SecretStorageKey::validate_base58("EsTp RvZ0"),
+            RecoveryKeyValidation::InvalidCharacter { position: 8, character: '0' },
+        );
+        assert_eq!(
+            SecretStorageKey::validate_base58(&format!("{key}a")),
+            RecoveryKeyValidation::TooLong { entered: 49, expected: 48 },
+        );
+        assert_eq!(
+            SecretStorageKey::validate_base58("EsTpRvZTnjck8YrhRAtwXLS84Nr2r9S9LGAWDaExVAPBvLRk"),
+            RecoveryKeyValidation::InvalidChecksum,
+        );
+        assert_eq!(
+            SecretStorageKey::validate_base58("AATpRvZTnjck8YrhRAtwXLS84Nr2r9S9LGAWDaExVAPBvLRk"),
+            RecoveryKeyValidation::InvalidPrefix,
+        );
+
+        let key = SecretStorageKey::new().to_base58();
+        assert_eq!(SecretStorageKey::validate_base58(&key), RecoveryKeyValidation::Valid);
+    }
+
+    #[test]
+    fn passphrase_strength() {
+        assert_eq!(PassphraseStrength::estimate(""), PassphraseStrength::VeryWeak);
+        assert_eq!(PassphraseStrength::estimate("aaaaaaaaaaaaaaaa"), PassphraseStrength::VeryWeak);
+        assert_eq!(PassphraseStrength::estimate("abcdefghijklmnop"), PassphraseStrength::VeryWeak);
+        assert_eq!(PassphraseStrength::estimate("secret"), PassphraseStrength::Weak);
+        assert_eq!(PassphraseStrength::estimate("Tr0ub4dor&3"), PassphraseStrength::Strong);
+        assert_eq!(
+            PassphraseStrength::estimate("correct horse battery staple"),
+            PassphraseStrength::VeryStrong
+        );
+
+        assert!(!PassphraseStrength::Weak.is_acceptable());
+        assert!(PassphraseStrength::Fair.is_acceptable());
+    }
+
     #[test]
     fn encrypted_data_decoding() {
         let json = json!({
@@ -110,6 +110,13 @@ impl GroupSessionCache {
         }
     }
 
+    /// Remove the outbound group session for the given room from the cache,
+    /// so it's loaded from the store again next time it's needed.
+    pub(crate) fn remove(&self, room_id: &RoomId) {
+        self.sessions.write().remove(room_id);
+        self.sessions_being_shared.write().retain(|_, session| session.room_id() != room_id);
+    }
+
     /// Get an outbound group session for a room, if one exists.
     ///
     /// # Arguments
@@ -827,7 +834,7 @@ impl GroupSessionManager {
     /// Returns a tuple containing (1) the list of to-device requests, and (2)
     /// the list of devices that we could not find an olm session for (so
     /// need a withheld message).
-    async fn encrypt_content_for_devices(
+    pub(crate) async fn encrypt_content_for_devices(
         &self,
         recipient_devices: Vec<DeviceData>,
         event_type: &str,
@@ -121,6 +121,11 @@ impl DeviceStore {
         self.entries.write().get_mut(user_id)?.remove(device_id)
     }
 
+    /// Remove all the devices from the store.
+    pub fn clear(&self) {
+        self.entries.write().clear();
+    }
+
     /// Get a read-only view over all devices of the given user.
     pub fn user_devices(&self, user_id: &UserId) -> HashMap<OwnedDeviceId, DeviceData> {
         self.entries
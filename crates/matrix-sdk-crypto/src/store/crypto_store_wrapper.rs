@@ -11,11 +11,18 @@ use tokio::sync::{
 use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use tracing::{debug, trace, warn};
 
-use super::{caches::SessionStore, DeviceChanges, IdentityChanges, LockableCryptoStore};
+use super::{
+    caches::SessionStore,
+    journal::{Journal, JournalEntry, JOURNAL_STORE_KEY},
+    DeviceChanges, IdentityChanges, LockableCryptoStore, PendingChanges,
+};
 use crate::{
     olm::InboundGroupSession,
     store,
-    store::{Changes, DynCryptoStore, IntoCryptoStore, RoomKeyInfo, RoomKeyWithheldInfo},
+    store::{
+        Changes, DecryptedToDeviceEvent, DynCryptoStore, IntoCryptoStore, RoomKeyInfo,
+        RoomKeyWithheldInfo,
+    },
     CryptoStoreError, GossippedSecret, OwnUserIdentityData, Session, UserIdentityData,
 };
 
@@ -49,6 +56,29 @@ pub(crate) struct CryptoStoreWrapper {
     /// identities which got updated or newly created.
     identities_broadcaster:
         broadcast::Sender<(Option<OwnUserIdentityData>, IdentityChanges, DeviceChanges)>,
+
+    /// The sender side of a broadcast channel which sends out the to-device
+    /// events of custom types we received encrypted and decrypted.
+    decrypted_to_device_events_broadcaster: broadcast::Sender<Vec<DecryptedToDeviceEvent>>,
+
+    /// The state of the journal, as known by this process.
+    ///
+    /// The mutex serializes the updates of the journal within this process;
+    /// other processes are excluded by the cross-process lock.
+    journal: Mutex<JournalState>,
+}
+
+/// The state of the journal of the changes made to the store, as known by this
+/// process.
+#[derive(Debug, Default)]
+struct JournalState {
+    /// The current crypto store generation of this process, if the store is
+    /// shared with other processes.
+    generation: Option<u64>,
+
+    /// The journal as last written by this process, if no other process
+    /// could have updated it since then.
+    journal: Option<Journal>,
 }
 
 impl CryptoStoreWrapper {
@@ -59,6 +89,7 @@ impl CryptoStoreWrapper {
         // The identities broadcaster is responsible for user identities as well as
         // devices, that's why we increase the capacity here.
         let identities_broadcaster = broadcast::Sender::new(20);
+        let decrypted_to_device_events_broadcaster = broadcast::Sender::new(10);
 
         Self {
             user_id: user_id.to_owned(),
@@ -69,6 +100,8 @@ impl CryptoStoreWrapper {
             room_keys_withheld_received_sender,
             secrets_broadcaster,
             identities_broadcaster,
+            decrypted_to_device_events_broadcaster,
+            journal: Default::default(),
         }
     }
 
@@ -111,6 +144,8 @@ impl CryptoStoreWrapper {
         let devices = changes.devices.to_owned();
         let identities = changes.identities.to_owned();
 
+        self.record_changes_in_journal(&changes).await?;
+
         if devices
             .changed
             .iter()
@@ -200,6 +235,121 @@ impl CryptoStoreWrapper {
         Ok(())
     }
 
+    /// Save the pending changes (i.e. the account) to the store.
+    pub async fn save_pending_changes(&self, changes: PendingChanges) -> store::Result<()> {
+        if changes.account.is_some() {
+            self.record_in_journal(|entry| entry.account = true).await?;
+        }
+
+        self.store.save_pending_changes(changes).await
+    }
+
+    /// Save the given tracked users to the store.
+    pub async fn save_tracked_users(&self, users: &[(&UserId, bool)]) -> store::Result<()> {
+        if !users.is_empty() {
+            self.record_in_journal(|entry| entry.tracked_users = true).await?;
+        }
+
+        self.store.save_tracked_users(users).await
+    }
+
+    async fn record_changes_in_journal(&self, changes: &Changes) -> store::Result<()> {
+        let backup_keys =
+            changes.backup_decryption_key.is_some() || changes.backup_version.is_some();
+
+        if changes.sessions.is_empty()
+            && changes.private_identity.is_none()
+            && changes.outbound_group_sessions.is_empty()
+            && !backup_keys
+        {
+            return Ok(());
+        }
+
+        self.record_in_journal(|entry| {
+            entry.olm_sessions.extend(changes.sessions.iter().map(|s| s.sender_key.to_base64()));
+            entry.private_identity |= changes.private_identity.is_some();
+            entry
+                .outbound_group_sessions
+                .extend(changes.outbound_group_sessions.iter().map(|s| s.room_id().to_owned()));
+            entry.backup_keys |= backup_keys;
+        })
+        .await
+    }
+
+    /// Set the current crypto store generation of this process, which enables
+    /// the journal.
+    ///
+    /// This must be called with the cross-process lock held, every time the
+    /// generation has been maintained. If the generation changed, another
+    /// process might have updated the journal, so it will be reloaded before
+    /// the next update.
+    pub(crate) async fn set_journal_generation(&self, generation: u64) {
+        let mut state = self.journal.lock().await;
+
+        if state.generation != Some(generation) {
+            state.generation = Some(generation);
+            state.journal = None;
+        }
+    }
+
+    /// Record changes made by this process in the journal, so other processes
+    /// sharing the store know which of their caches they need to invalidate.
+    ///
+    /// This is a no-op if the store isn't shared with other processes, i.e.
+    /// if no crypto store generation has been set with
+    /// [`CryptoStoreWrapper::set_journal_generation`].
+    pub(crate) async fn record_in_journal(
+        &self,
+        record: impl FnOnce(&mut JournalEntry),
+    ) -> store::Result<()> {
+        let mut state = self.journal.lock().await;
+        let JournalState { generation, journal } = &mut *state;
+
+        let Some(generation) = *generation else {
+            return Ok(());
+        };
+
+        // Only the processes holding the cross-process lock write to the journal, so
+        // our copy is up-to-date as long as the generation didn't change.
+        let journal = match journal {
+            Some(journal) => journal,
+            None => journal.insert(self.load_journal().await?),
+        };
+        journal.record(generation, record);
+
+        let result =
+            self.store.set_custom_value(JOURNAL_STORE_KEY, serde_json::to_vec(&journal)?).await;
+
+        if result.is_err() {
+            // We don't know whether the journal was written, reload it next time.
+            state.journal = None;
+        }
+
+        result
+    }
+
+    /// Load the journal of the changes made to the store, by all processes.
+    pub(crate) async fn load_journal(&self) -> store::Result<Journal> {
+        let Some(value) = self.store.get_custom_value(JOURNAL_STORE_KEY).await? else {
+            return Ok(Journal::default());
+        };
+
+        Ok(serde_json::from_slice(&value).unwrap_or_else(|error| {
+            warn!("Couldn't deserialize the crypto store journal: {error}");
+            Journal::incomplete()
+        }))
+    }
+
+    /// Drop the cached Olm sessions for the given sender keys, so they're
+    /// reloaded from the store next time they're needed.
+    pub(crate) async fn invalidate_sessions(&self, sender_keys: impl Iterator<Item = &str>) {
+        let mut entries = self.sessions.entries.write().await;
+
+        for sender_key in sender_keys {
+            entries.remove(sender_key);
+        }
+    }
+
     async fn check_all_identities_and_update_was_previously_verified_flag_if_needed(
         &self,
         own_identity_after: &OwnUserIdentityData,
@@ -333,6 +483,24 @@ impl CryptoStoreWrapper {
         Self::filter_errors_out_of_stream(stream, "secrets_stream")
     }
 
+    /// Receive the to-device events of custom types that have been received
+    /// encrypted and successfully decrypted, as a [`Stream`].
+    pub fn decrypted_to_device_events_stream(
+        &self,
+    ) -> impl Stream<Item = Vec<DecryptedToDeviceEvent>> {
+        let stream = BroadcastStream::new(self.decrypted_to_device_events_broadcaster.subscribe());
+        Self::filter_errors_out_of_stream(stream, "decrypted_to_device_events_stream")
+    }
+
+    /// Notify the listeners of [`Self::decrypted_to_device_events_stream`]
+    /// about the given events.
+    pub(crate) fn notify_decrypted_to_device_events(&self, events: Vec<DecryptedToDeviceEvent>) {
+        if !events.is_empty() {
+            // Ignore the result. It can only fail if there are no listeners.
+            let _ = self.decrypted_to_device_events_broadcaster.send(events);
+        }
+    }
+
     /// Returns a stream of newly created or updated cryptographic identities.
     ///
     /// This is just a helper method which allows us to build higher level
@@ -391,10 +559,12 @@ impl Deref for CryptoStoreWrapper {
 #[cfg(test)]
 mod test {
     use matrix_sdk_test::async_test;
-    use ruma::user_id;
+    use ruma::{device_id, user_id};
 
     use super::*;
-    use crate::machine::test_helpers::get_machine_pair_with_setup_sessions_test_helper;
+    use crate::{
+        machine::test_helpers::get_machine_pair_with_setup_sessions_test_helper, store::MemoryStore,
+    };
 
     #[async_test]
     async fn test_cache_cleared_after_device_update() {
@@ -436,4 +606,41 @@ mod test {
             "The session should no longer be in the cache after our own device keys changed"
         );
     }
+
+    #[async_test]
+    async fn test_journal_is_only_maintained_when_the_store_is_shared() {
+        let store = CryptoStoreWrapper::new(
+            user_id!("@alice:example.com"),
+            device_id!("ALICEDEVICE"),
+            MemoryStore::new(),
+        );
+        let bob = user_id!("@bob:example.com");
+
+        // The store isn't shared with other processes, so the journal isn't written.
+        store.save_tracked_users(&[(bob, true)]).await.unwrap();
+        assert!(store.get_custom_value(JOURNAL_STORE_KEY).await.unwrap().is_none());
+
+        // Once the crypto store generation is set, the changes are recorded.
+        store.set_journal_generation(1).await;
+        store.save_tracked_users(&[(bob, false)]).await.unwrap();
+
+        let mut journal = store.load_journal().await.unwrap();
+        assert!(journal.changes_between(0, 2).unwrap().tracked_users);
+
+        // Another process records its changes with the next generation.
+        journal.record(2, |entry| entry.account = true);
+        store
+            .set_custom_value(JOURNAL_STORE_KEY, serde_json::to_vec(&journal).unwrap())
+            .await
+            .unwrap();
+
+        // When this process gets the lock back, the generation changed, so the journal
+        // is reloaded before recording the new changes.
+        store.set_journal_generation(3).await;
+        store.save_tracked_users(&[(bob, true)]).await.unwrap();
+
+        let journal = store.load_journal().await.unwrap();
+        assert!(journal.changes_between(1, 3).unwrap().account);
+        assert!(journal.changes_between(2, 4).unwrap().tracked_users);
+    }
 }
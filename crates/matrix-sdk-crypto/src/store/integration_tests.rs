@@ -1276,6 +1276,33 @@ macro_rules! cryptostore_integration_tests {
                 assert_eq!(None, loaded_2);
             }
 
+            #[async_test]
+            async fn test_clear() {
+                let (account, store) = get_loaded_store("clear").await;
+
+                let changes = Changes {
+                    devices: DeviceChanges { new: vec![DeviceData::from_account(&account)], ..Default::default() },
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.unwrap();
+                store.save_tracked_users(&[(account.user_id(), false)]).await.unwrap();
+                store.set_custom_value("A", "Hello".as_bytes().to_vec()).await.unwrap();
+
+                store.clear().await.unwrap();
+
+                assert!(store.load_account().await.unwrap().is_none());
+                assert!(store.get_device(account.user_id(), account.device_id()).await.unwrap().is_none());
+                assert!(store.load_tracked_users().await.unwrap().is_empty());
+                assert!(store.get_custom_value("A").await.unwrap().is_none());
+
+                // The store can be used again afterwards.
+                store
+                    .save_pending_changes(PendingChanges { account: Some(account.deep_clone()) })
+                    .await
+                    .expect("Can't save account");
+                assert!(store.load_account().await.unwrap().is_some());
+            }
+
             fn session_info(session: &InboundGroupSession) -> (&RoomId, &str) {
                 (&session.room_id(), &session.session_id())
             }
@@ -0,0 +1,202 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A journal of the changes each process made to the crypto store, while it
+//! was holding the cross-process lock.
+//!
+//! When several processes share the same crypto store (e.g. an app and its
+//! notification service extension), a process must refresh its in-memory
+//! caches once another process has written to the store. Instead of
+//! recreating the whole [`OlmMachine`], each process records which cached
+//! objects it touched, tagged with its crypto store generation, so the other
+//! processes can invalidate only those.
+//!
+//! [`OlmMachine`]: crate::OlmMachine
+
+use std::collections::BTreeSet;
+
+use ruma::OwnedRoomId;
+use serde::{Deserialize, Serialize};
+
+/// The key under which the journal is stored, as a custom value.
+pub(crate) const JOURNAL_STORE_KEY: &str = "crypto-store-journal";
+
+/// The maximum number of entries kept in the journal.
+///
+/// Processes that are more generations behind than that will have to reload
+/// everything.
+const MAX_JOURNAL_ENTRIES: usize = 32;
+
+/// The cached objects a process touched during one crypto store generation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    /// The crypto store generation of the process that made these changes.
+    pub generation: u64,
+
+    /// Whether the account has been saved.
+    #[serde(default)]
+    pub account: bool,
+
+    /// The sender keys of the Olm sessions that have been saved.
+    #[serde(default)]
+    pub olm_sessions: BTreeSet<String>,
+
+    /// Whether the set of tracked users has been saved.
+    #[serde(default)]
+    pub tracked_users: bool,
+
+    /// Whether the private cross-signing identity has been saved.
+    #[serde(default)]
+    pub private_identity: bool,
+
+    /// The rooms whose outbound group session has been saved.
+    #[serde(default)]
+    pub outbound_group_sessions: BTreeSet<OwnedRoomId>,
+
+    /// Whether the backup decryption key or version have been saved.
+    #[serde(default)]
+    pub backup_keys: bool,
+}
+
+impl JournalEntry {
+    fn merge(&mut self, other: &JournalEntry) {
+        self.account |= other.account;
+        self.olm_sessions.extend(other.olm_sessions.iter().cloned());
+        self.tracked_users |= other.tracked_users;
+        self.private_identity |= other.private_identity;
+        self.outbound_group_sessions.extend(other.outbound_group_sessions.iter().cloned());
+        self.backup_keys |= other.backup_keys;
+    }
+}
+
+/// The journal itself, as persisted in the store.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Journal {
+    /// The most recent entries, sorted by generation.
+    entries: Vec<JournalEntry>,
+
+    /// The highest generation of the entries that have been dropped from the
+    /// journal, if any.
+    ///
+    /// Changes made after that generation are all known.
+    #[serde(default)]
+    max_dropped_generation: Option<u64>,
+}
+
+impl Journal {
+    /// A journal that can't be trusted to describe any change, e.g. because
+    /// it couldn't be deserialized.
+    pub fn incomplete() -> Self {
+        Self { entries: Vec::new(), max_dropped_generation: Some(u64::MAX) }
+    }
+
+    /// Record changes in the entry for the given generation, creating it if
+    /// needs be.
+    pub fn record(&mut self, generation: u64, record: impl FnOnce(&mut JournalEntry)) {
+        match self.entries.iter_mut().find(|entry| entry.generation == generation) {
+            Some(entry) => record(entry),
+            None => {
+                let mut entry = JournalEntry { generation, ..Default::default() };
+                record(&mut entry);
+                self.entries.push(entry);
+                self.entries.sort_by_key(|entry| entry.generation);
+            }
+        }
+
+        if self.entries.len() > MAX_JOURNAL_ENTRIES {
+            let num_dropped = self.entries.len() - MAX_JOURNAL_ENTRIES;
+            let max_dropped = self.entries.drain(..num_dropped).map(|entry| entry.generation).max();
+            self.max_dropped_generation = self.max_dropped_generation.max(max_dropped);
+        }
+    }
+
+    /// Return all the changes made by other processes, after
+    /// `previous_generation` and before `current_generation`.
+    ///
+    /// Returns `None` if the journal doesn't know about all these changes.
+    pub fn changes_between(
+        &self,
+        previous_generation: u64,
+        current_generation: u64,
+    ) -> Option<JournalEntry> {
+        if self.max_dropped_generation.is_some_and(|dropped| dropped > previous_generation) {
+            return None;
+        }
+
+        let mut entries = self.entries.iter().filter(|entry| {
+            entry.generation > previous_generation && entry.generation < current_generation
+        });
+
+        // If another process bumped the generation, it must have recorded an entry; if
+        // there's none, it didn't maintain the journal.
+        let mut changes = entries.next()?.clone();
+
+        for entry in entries {
+            changes.merge(entry);
+        }
+
+        Some(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::owned_room_id;
+
+    use super::{Journal, MAX_JOURNAL_ENTRIES};
+
+    #[test]
+    fn test_changes_between_merges_entries() {
+        let mut journal = Journal::default();
+
+        journal.record(1, |entry| entry.account = true);
+        journal.record(2, |entry| {
+            entry.olm_sessions.insert("sender_key".to_owned());
+        });
+        journal.record(3, |entry| {
+            entry.outbound_group_sessions.insert(owned_room_id!("!a:b.c"));
+        });
+        journal.record(4, |_| {});
+
+        // Our own changes, and the ones from our new generation, are excluded.
+        let changes = journal.changes_between(1, 4).unwrap();
+        assert!(!changes.account);
+        assert!(changes.olm_sessions.contains("sender_key"));
+        assert!(changes.outbound_group_sessions.contains(&owned_room_id!("!a:b.c")));
+        assert!(!changes.tracked_users);
+    }
+
+    #[test]
+    fn test_changes_between_without_entries() {
+        let mut journal = Journal::default();
+        journal.record(1, |entry| entry.account = true);
+
+        // Another process bumped the generation without maintaining the journal.
+        assert!(journal.changes_between(1, 3).is_none());
+        assert!(Journal::incomplete().changes_between(1, 3).is_none());
+    }
+
+    #[test]
+    fn test_changes_between_after_truncation() {
+        let mut journal = Journal::default();
+
+        for generation in 0..MAX_JOURNAL_ENTRIES as u64 + 2 {
+            journal.record(generation, |entry| entry.tracked_users = true);
+        }
+
+        // The entries for the generations 0 and 1 have been dropped.
+        assert!(journal.changes_between(0, 10).is_none());
+        assert!(journal.changes_between(1, 10).unwrap().tracked_users);
+    }
+}
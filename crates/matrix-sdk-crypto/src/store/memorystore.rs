@@ -733,6 +733,33 @@ impl CryptoStore for MemoryStore {
         Ok(())
     }
 
+    async fn clear(&self) -> Result<()> {
+        let _guard = self.save_changes_lock.lock().await;
+
+        *self.static_account.write() = None;
+        *self.account.write() = None;
+        self.sessions.write().clear();
+        self.inbound_group_sessions.write().clear();
+        self.inbound_group_sessions_backed_up_to.write().clear();
+        self.outbound_group_sessions.write().clear();
+        *self.private_identity.write() = None;
+        self.tracked_users.write().clear();
+        self.olm_hashes.write().clear();
+        self.devices.clear();
+        self.identities.write().clear();
+        self.outgoing_key_requests.write().clear();
+        self.key_requests_by_info.write().clear();
+        self.direct_withheld_info.write().clear();
+        self.custom_values.write().clear();
+        self.secret_inbox.write().clear();
+        *self.backup_keys.write().await = Default::default();
+        *self.dehydrated_device_pickle_key.write().await = None;
+        *self.next_batch_token.write().await = None;
+        self.room_settings.write().clear();
+
+        Ok(())
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -1523,6 +1550,10 @@ mod integration_tests {
             self.0.remove_custom_value(key).await
         }
 
+        async fn clear(&self) -> Result<(), Self::Error> {
+            self.0.clear().await
+        }
+
         async fn try_take_leased_lock(
             &self,
             lease_duration_ms: u32,
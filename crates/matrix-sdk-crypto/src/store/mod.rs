@@ -52,8 +52,10 @@ use futures_core::Stream;
 use futures_util::StreamExt;
 use matrix_sdk_common::locks::RwLock as StdRwLock;
 use ruma::{
-    encryption::KeyUsage, events::secret::request::SecretName, DeviceId, OwnedDeviceId,
-    OwnedRoomId, OwnedUserId, RoomId, UserId,
+    encryption::KeyUsage,
+    events::{secret::request::SecretName, AnyToDeviceEvent},
+    serde::Raw,
+    DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -66,7 +68,7 @@ use zeroize::{Zeroize, ZeroizeOnDrop};
 #[cfg(doc)]
 use crate::{backups::BackupMachine, identities::OwnUserIdentity};
 use crate::{
-    gossiping::GossippedSecret,
+    gossiping::{GossippedSecret, RoomKeyGossipPolicy},
     identities::{user::UserIdentity, Device, DeviceData, UserDevices, UserIdentityData},
     olm::{
         Account, ExportedRoomKey, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
@@ -83,6 +85,7 @@ use crate::{
 pub mod caches;
 mod crypto_store_wrapper;
 mod error;
+pub(crate) mod journal;
 mod memorystore;
 mod traits;
 
@@ -94,6 +97,7 @@ pub mod integration_tests;
 use caches::{SequenceNumber, UsersForKeyQuery};
 pub(crate) use crypto_store_wrapper::CryptoStoreWrapper;
 pub use error::{CryptoStoreError, Result};
+use journal::JournalEntry;
 use matrix_sdk_common::{
     deserialized_responses::WithheldCode, store_locks::CrossProcessStoreLock, timeout::timeout,
 };
@@ -474,7 +478,7 @@ impl StoreTransaction {
         // Save changes in the database.
         let account = self.changes.account.as_ref().map(|acc| acc.deep_clone());
 
-        self.store.save_pending_changes(self.changes).await?;
+        self.store.inner.store.save_pending_changes(self.changes).await?;
 
         // Make the cache coherent with the database.
         if let Some(account) = account {
@@ -996,6 +1000,28 @@ impl From<&InboundGroupSession> for RoomKeyInfo {
     }
 }
 
+/// Information on a to-device event of a custom type, which has been received
+/// encrypted and successfully decrypted.
+///
+/// Events the `OlmMachine` handles itself, like room keys or secrets, aren't
+/// reported this way.
+#[derive(Clone, Debug)]
+pub struct DecryptedToDeviceEvent {
+    /// The sender of the event, as set by the homeserver.
+    ///
+    /// This has been checked to match the sender in the encrypted payload.
+    pub sender: OwnedUserId,
+
+    /// The Curve25519 key of the device which sent the event.
+    pub sender_key: Curve25519PublicKey,
+
+    /// The type of the decrypted event.
+    pub event_type: String,
+
+    /// The decrypted event.
+    pub raw_event: Raw<AnyToDeviceEvent>,
+}
+
 /// Information on a room key that has been withheld
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RoomKeyWithheldInfo {
@@ -1088,6 +1114,35 @@ impl Store {
         self.inner.identity.clone()
     }
 
+    /// Drop the cached data another process has changed in the store, as
+    /// described by the given journal entry, so it's reloaded from the store.
+    pub(crate) async fn invalidate_caches(&self, changes: &JournalEntry) -> Result<()> {
+        {
+            // Take the write lock, so no transaction or cache guard is alive while the
+            // cache is being invalidated.
+            let cache = self.inner.cache.write().await;
+
+            if changes.account {
+                *cache.account.lock().await = None;
+            }
+
+            if changes.tracked_users {
+                cache.tracked_users.write().clear();
+                *cache.loaded_tracked_users.write().await = false;
+            }
+        }
+
+        self.inner.store.invalidate_sessions(changes.olm_sessions.iter().map(String::as_str)).await;
+
+        if changes.private_identity {
+            if let Some(identity) = self.inner.store.load_identity().await? {
+                *self.inner.identity.lock().await = identity;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Save the given Sessions to the store
     pub(crate) async fn save_sessions(&self, sessions: &[Session]) -> Result<()> {
         let changes = Changes { sessions: sessions.to_vec(), ..Default::default() };
@@ -1566,6 +1621,18 @@ impl Store {
         self.set_value("only_allow_trusted_devices", &block_untrusted_devices).await
     }
 
+    /// Get the persisted policy describing how room keys are gossiped, or the
+    /// default policy if none was set.
+    pub async fn get_room_key_gossip_policy(&self) -> Result<RoomKeyGossipPolicy> {
+        let value = self.get_value("room_key_gossip_policy").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Persist the policy describing how room keys are gossiped.
+    pub async fn set_room_key_gossip_policy(&self, policy: &RoomKeyGossipPolicy) -> Result<()> {
+        self.set_value("room_key_gossip_policy", policy).await
+    }
+
     /// Get custom stored value associated with a key
     pub async fn get_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
         let Some(value) = self.get_custom_value(key).await? else {
@@ -1792,6 +1859,21 @@ impl Store {
         self.inner.store.secrets_stream()
     }
 
+    /// Receive notifications of encrypted to-device events of custom types
+    /// being received and decrypted, as a [`Stream`].
+    ///
+    /// The events decrypted while processing a single sync response are
+    /// batched into a [`Vec`], which is sent once the changes caused by the
+    /// decryption have been persisted.
+    ///
+    /// If the reader of the stream lags too far behind, a warning will be
+    /// logged and items will be dropped.
+    pub fn decrypted_to_device_events_stream(
+        &self,
+    ) -> impl Stream<Item = Vec<DecryptedToDeviceEvent>> {
+        self.inner.store.decrypted_to_device_events_stream()
+    }
+
     /// Import the given room keys into the store.
     ///
     /// # Arguments
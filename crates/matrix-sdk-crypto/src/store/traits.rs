@@ -346,6 +346,13 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// * `key` - The key to insert data into
     async fn remove_custom_value(&self, key: &str) -> Result<(), Self::Error>;
 
+    /// Remove all the data of the store, e.g. after the account has been
+    /// deactivated.
+    ///
+    /// The leased locks are kept, so the processes sharing the store still
+    /// agree on who holds them.
+    async fn clear(&self) -> Result<(), Self::Error>;
+
     /// Try to take a leased lock.
     ///
     /// This attempts to take a lock for the given lease duration.
@@ -581,6 +588,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.remove_custom_value(key).await.map_err(Into::into)
     }
 
+    async fn clear(&self) -> Result<(), Self::Error> {
+        self.0.clear().await.map_err(Into::into)
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -95,6 +95,23 @@ pub enum UtdCause {
     ///
     /// Expected message to user: "You need to verify this device".
     HistoricalMessageAndDeviceIsUnverified = 8,
+
+    /// We are missing the keys for this event, and the room keys of the room
+    /// are not marked as shared history (see [MSC3061]), so they were never
+    /// meant to be shared with us.
+    ///
+    /// This happens if the event was sent while we were only invited to the
+    /// room: the keys of a session that isn't shared history aren't forwarded
+    /// to new members, so they won't be available from key storage or other
+    /// devices either.
+    ///
+    /// Not to be confused with pre-join messages we have explicit membership
+    /// information for (see [`UtdCause::SentBeforeWeJoined`] for that).
+    ///
+    /// Expected message to user: "You don't have access to this message".
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    HistoryNotShared = 9,
 }
 
 /// MSC4115 membership info in the unsigned area.
@@ -133,6 +150,14 @@ pub struct CryptoContextInfo {
     /// True if key storage is correctly set up and can be used by the current
     /// client to download and decrypt message keys.
     pub is_backup_configured: bool,
+
+    /// True if the room keys of the room are marked as shared history, as
+    /// defined in [MSC3061], i.e. if its current history visibility is
+    /// `shared` or `world_readable`. Used to decide whether the keys for
+    /// events sent before we joined were meant to be shared with us at all.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub room_history_shared: bool,
 }
 
 impl UtdCause {
@@ -160,9 +185,17 @@ impl UtdCause {
                 if let Some(unsigned) =
                     raw_event.get_field::<UnsignedWithMembership>("unsigned").ok().flatten()
                 {
-                    if let Membership::Leave = unsigned.membership {
+                    match unsigned.membership {
                         // We were not a member - this is the cause of the UTD
-                        return UtdCause::SentBeforeWeJoined;
+                        Membership::Leave => return UtdCause::SentBeforeWeJoined,
+                        // We were only invited, and the keys are not shared history, so they
+                        // were not forwarded to us when we joined, either the whole session
+                        // or the message indices before the join.
+                        Membership::Invite if !crypto_context_info.room_history_shared => {
+                            return UtdCause::HistoryNotShared;
+                        }
+                        // We were joined, so the keys should have been shared with us.
+                        Membership::Invite | Membership::Join => {}
                     }
                 }
 
@@ -192,6 +225,15 @@ impl UtdCause {
         }
     }
 
+    /// Whether this UTD is expected, because the keys for the event were never
+    /// meant to be shared with us.
+    ///
+    /// Expected UTDs shouldn't be reported as decryption failures, since
+    /// there's nothing that could have been done to decrypt them.
+    pub fn is_expected(&self) -> bool {
+        matches!(self, UtdCause::SentBeforeWeJoined | UtdCause::HistoryNotShared)
+    }
+
     /**
      * Below is the flow chart we follow for deciding whether historical
      * UTDs are expected. This function starts at position `B`.
@@ -344,6 +386,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_if_membership_is_invite_and_history_is_not_shared_history_is_not_shared() {
+        // If membership=invite, but the room keys are not shared history, the keys were
+        // never sent to us.
+        let context = CryptoContextInfo { room_history_shared: false, ..device_old() };
+
+        let cause = UtdCause::determine(
+            &raw_event(json!({ "unsigned": { "membership": "invite" } })),
+            context,
+            &missing_megolm_session(),
+        );
+        assert_eq!(cause, UtdCause::HistoryNotShared);
+        assert!(cause.is_expected());
+    }
+
+    #[test]
+    fn test_unknown_message_index_before_join_without_shared_history_is_history_not_shared() {
+        // We have the session, but only from a later index on, the event was sent
+        // before we joined, and the session isn't shared history.
+        let context = CryptoContextInfo { room_history_shared: false, ..device_old() };
+        assert_eq!(
+            UtdCause::determine(
+                &raw_event(json!({ "unsigned": { "membership": "invite" } })),
+                context,
+                &unknown_megolm_message_index()
+            ),
+            UtdCause::HistoryNotShared
+        );
+
+        // If we were already joined, the index should have been shared with us.
+        assert_eq!(
+            UtdCause::determine(
+                &raw_event(json!({ "unsigned": { "membership": "join" } })),
+                context,
+                &unknown_megolm_message_index()
+            ),
+            UtdCause::Unknown
+        );
+
+        // With shared history, the earlier part of the session might still turn up.
+        assert_eq!(
+            UtdCause::determine(
+                &raw_event(json!({ "unsigned": { "membership": "invite" } })),
+                device_old(),
+                &unknown_megolm_message_index()
+            ),
+            UtdCause::Unknown
+        );
+    }
+
+    #[test]
+    fn test_only_pre_join_and_history_not_shared_utds_are_expected() {
+        assert!(UtdCause::SentBeforeWeJoined.is_expected());
+        assert!(UtdCause::HistoryNotShared.is_expected());
+        assert!(!UtdCause::Unknown.is_expected());
+        assert!(!UtdCause::HistoricalMessageAndBackupIsDisabled.is_expected());
+        assert!(!UtdCause::WithheldBySender.is_expected());
+    }
+
     #[test]
     fn test_verification_violation_is_passed_through() {
         assert_eq!(
@@ -544,6 +645,7 @@ mod tests {
             this_device_is_verified: false,
             is_backup_configured: false,
             backup_exists_on_server: false,
+            room_history_shared: true,
         }
     }
 
@@ -553,6 +655,7 @@ mod tests {
             this_device_is_verified: false,
             is_backup_configured: false,
             backup_exists_on_server: false,
+            room_history_shared: true,
         }
     }
 
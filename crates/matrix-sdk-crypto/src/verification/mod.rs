@@ -45,6 +45,7 @@ use ruma::{
     DeviceId, EventId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedTransactionId, RoomId,
     UserId,
 };
+use sas::emoji_from_index;
 pub use sas::{AcceptSettings, AcceptedProtocols, EmojiShortAuthString, Sas, SasState};
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
@@ -81,6 +82,76 @@ pub struct Emoji {
     pub description: &'static str,
 }
 
+/// The prefix of the [translation keys] of the emojis used for interactive
+/// verification.
+///
+/// [translation keys]: LocalizableEmoji::translation_key
+pub const SAS_EMOJI_TRANSLATION_KEY_PREFIX: &str = "sas_emoji_";
+
+/// An emoji that is used for interactive verification using a short auth
+/// string, with the data needed to present it in the language of the user.
+///
+/// The [spec] contains a table of [translated descriptions] for every emoji,
+/// which can be looked up with the `index`. Apps that ship their own
+/// translations can use the [`LocalizableEmoji::translation_key()`] instead.
+///
+/// [spec]: https://spec.matrix.org/unstable/client-server-api/#sas-method-emoji
+/// [translated descriptions]: https://github.com/matrix-org/matrix-spec/blob/main/data-definitions/sas-emoji.json
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalizableEmoji {
+    /// The index of the emoji in the table of the spec, between 0 and 63.
+    pub index: u8,
+    /// The emoji symbol and its English description.
+    pub emoji: Emoji,
+}
+
+impl LocalizableEmoji {
+    /// Get the emoji with the given index in the table of the spec.
+    ///
+    /// Returns `None` if the index is greater than 63.
+    pub fn from_index(index: u8) -> Option<Self> {
+        (index < 64).then(|| Self::from_valid_index(index))
+    }
+
+    /// Get the emoji with the given index, which must be lower than 64.
+    fn from_valid_index(index: u8) -> Self {
+        Self { index, emoji: emoji_from_index(index) }
+    }
+
+    /// A stable identifier for the emoji, which is its English description in
+    /// snake case, for example `thumbs_up`.
+    pub fn identifier(&self) -> String {
+        self.emoji
+            .description
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    /// The key of the description of the emoji in a table of translations,
+    /// which is the [identifier] with the
+    /// [`SAS_EMOJI_TRANSLATION_KEY_PREFIX`], for example `sas_emoji_thumbs_up`.
+    ///
+    /// [identifier]: Self::identifier
+    pub fn translation_key(&self) -> String {
+        format!("{SAS_EMOJI_TRANSLATION_KEY_PREFIX}{}", self.identifier())
+    }
+}
+
+/// The short auth string of an interactive verification, in all the formats
+/// that can be presented to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortAuthString {
+    /// The emojis that represent the short auth string, `None` if the emoji
+    /// method wasn't accepted by both sides.
+    pub emojis: Option<[LocalizableEmoji; 7]>,
+    /// The three 4-digit numbers that represent the short auth string, which
+    /// can always be used as a fallback.
+    pub decimals: (u16, u16, u16),
+}
+
 /// Format the list of emojis as a two line string.
 ///
 /// The first line will contain the emojis spread out so the second line can
@@ -733,7 +804,7 @@ impl IdentitiesBeingVerified {
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::sync::Arc;
+    use std::{collections::BTreeSet, sync::Arc};
 
     use ruma::{
         device_id,
@@ -742,7 +813,7 @@ pub(crate) mod tests {
     };
     use tokio::sync::Mutex;
 
-    use super::{event_enums::OutgoingContent, VerificationStore};
+    use super::{event_enums::OutgoingContent, LocalizableEmoji, VerificationStore};
     use crate::{
         olm::PrivateCrossSigningIdentity,
         store::{Changes, CryptoStore, CryptoStoreWrapper, IdentityChanges, MemoryStore},
@@ -886,4 +957,24 @@ pub(crate) mod tests {
 
         (alice, alice_store, bob, bob_store)
     }
+
+    #[test]
+    fn test_localizable_emoji() {
+        let dog = LocalizableEmoji::from_index(0).unwrap();
+        assert_eq!(dog.emoji.symbol, "🐶");
+        assert_eq!(dog.identifier(), "dog");
+        assert_eq!(dog.translation_key(), "sas_emoji_dog");
+
+        let thumbs_up = LocalizableEmoji::from_index(36).unwrap();
+        assert_eq!(thumbs_up.emoji.description, "Thumbs Up");
+        assert_eq!(thumbs_up.identifier(), "thumbs_up");
+
+        assert!(LocalizableEmoji::from_index(64).is_none());
+
+        // Identifiers are unique.
+        let identifiers: BTreeSet<_> = (0..64)
+            .map(|index| LocalizableEmoji::from_index(index).unwrap().identifier())
+            .collect();
+        assert_eq!(identifiers.len(), 64);
+    }
 }
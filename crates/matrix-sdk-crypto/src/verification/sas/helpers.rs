@@ -80,7 +80,7 @@ pub fn calculate_commitment(public_key: Curve25519PublicKey, content: &StartCont
 /// bigger than 63.
 ///
 /// [spec]: https://matrix.org/docs/spec/client_server/latest#sas-method-emoji
-fn emoji_from_index(index: u8) -> Emoji {
+pub(crate) fn emoji_from_index(index: u8) -> Emoji {
     /*
     This list was generated from the data in the spec [1] with the following command:
 
@@ -22,6 +22,7 @@ use as_variant::as_variant;
 use eyeball::{ObservableWriteGuard, SharedObservable};
 use futures_core::Stream;
 use futures_util::StreamExt;
+pub(crate) use helpers::emoji_from_index;
 use inner_sas::InnerSas;
 use ruma::{
     api::client::keys::upload_signatures::v3::Request as SignatureUploadRequest,
@@ -45,7 +46,7 @@ use crate::{
     olm::StaticAccountData,
     store::CryptoStoreError,
     types::requests::{OutgoingVerificationRequest, RoomMessageRequest, ToDeviceRequest},
-    Emoji,
+    Emoji, LocalizableEmoji, ShortAuthString,
 };
 
 /// Short authentication string object.
@@ -113,6 +114,14 @@ pub struct EmojiShortAuthString {
     pub emojis: [Emoji; 7],
 }
 
+impl EmojiShortAuthString {
+    /// Get the emojis of the short auth string, with the data needed to
+    /// localize them.
+    pub fn localizable(&self) -> [LocalizableEmoji; 7] {
+        self.indices.map(LocalizableEmoji::from_valid_index)
+    }
+}
+
 /// An Enum describing the state the SAS verification is in.
 #[derive(Debug, Clone)]
 pub enum SasState {
@@ -650,6 +659,22 @@ impl Sas {
         self.inner.read().decimals()
     }
 
+    /// Get the short auth string in all the formats that can be presented to
+    /// the user.
+    ///
+    /// Returns `None` if we can't yet present the short auth string.
+    pub fn short_auth_string(&self) -> Option<ShortAuthString> {
+        let inner = self.inner.read();
+        let decimals = inner.decimals()?;
+        let emojis = inner
+            .supports_emoji()
+            .then(|| inner.emoji_index())
+            .flatten()
+            .map(|indices| indices.map(LocalizableEmoji::from_valid_index));
+
+        Some(ShortAuthString { emojis, decimals })
+    }
+
     /// Listen for changes in the SAS verification process.
     ///
     /// The changes are presented as a stream of [`SasState`] values.
@@ -983,6 +1008,13 @@ mod tests {
         assert_eq!(alice.emoji().unwrap(), bob.emoji().unwrap());
         assert_eq!(alice.decimals().unwrap(), bob.decimals().unwrap());
 
+        let short_auth_string = alice.short_auth_string().unwrap();
+        assert_eq!(short_auth_string, bob.short_auth_string().unwrap());
+        assert_eq!(short_auth_string.decimals, alice.decimals().unwrap());
+        let emojis = short_auth_string.emojis.unwrap();
+        assert_eq!(emojis.clone().map(|emoji| emoji.index), alice.emoji_index().unwrap());
+        assert_eq!(emojis.map(|emoji| emoji.emoji), alice.emoji().unwrap());
+
         let mut requests = alice.confirm().await.unwrap().0;
         assert_matches!(alice.state(), SasState::Confirmed);
         assert!(requests.len() == 1);
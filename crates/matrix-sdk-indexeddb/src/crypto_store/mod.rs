@@ -1390,6 +1390,52 @@ impl_crypto_store! {
         Ok(())
     }
 
+    async fn clear(&self) -> Result<()> {
+        let _guard = self.save_changes_lock.lock().await;
+
+        let stores = [
+            keys::CORE,
+            keys::SESSION,
+            keys::INBOUND_GROUP_SESSIONS_V3,
+            keys::OUTBOUND_GROUP_SESSIONS,
+            keys::TRACKED_USERS,
+            keys::OLM_HASHES,
+            keys::DEVICES,
+            keys::IDENTITIES,
+            keys::BACKUP_KEYS,
+            keys::GOSSIP_REQUESTS,
+            keys::ROOM_SETTINGS,
+            keys::SECRETS_INBOX,
+            keys::DIRECT_WITHHELD_INFO,
+        ];
+
+        let tx = self.inner.transaction_on_multi_with_mode(&stores, IdbTransactionMode::Readwrite)?;
+
+        for store in stores.iter().filter(|store| **store != keys::CORE) {
+            tx.object_store(store)?.clear()?;
+        }
+
+        // The leased locks live next to the other values of the core object store, keep
+        // only them. The store cipher is in the meta database, so it isn't affected.
+        if let Some(cursor) = tx.object_store(keys::CORE)?.open_cursor()?.await? {
+            loop {
+                if self.serializer.deserialize_value::<Lease>(cursor.value()).is_err() {
+                    cursor.delete()?;
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        tx.await.into_result()?;
+
+        *self.static_account.write().unwrap() = None;
+
+        Ok(())
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
@@ -1404,12 +1450,6 @@ impl_crypto_store! {
         let object_store = txn
             .object_store(keys::CORE)?;
 
-        #[derive(serde::Deserialize, serde::Serialize)]
-        struct Lease {
-            holder: String,
-            expiration_ts: u64,
-        }
-
         let now_ts: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
         let expiration_ts = now_ts + lease_duration_ms as u64;
 
@@ -1432,6 +1472,13 @@ impl_crypto_store! {
     }
 }
 
+/// A leased lock, as stored in the [`keys::CORE`] object store.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Lease {
+    holder: String,
+    expiration_ts: u64,
+}
+
 impl Drop for IndexeddbCryptoStore {
     fn drop(&mut self) {
         // Must release the database access manually as it's not done when
@@ -0,0 +1,1219 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An IndexedDB-backed implementation of the [`EventCacheStore`].
+//!
+//! The linked chunks, the events and the media are stored in a dedicated
+//! database, next to the state and crypto databases. Since IndexedDB
+//! read-write transactions over the same object store are serialized, even
+//! across browser tabs, the leases stored here can be used by the
+//! cross-process lock to coordinate the tabs sharing the same database.
+//!
+//! Every opened store is a distinct lease holder, even if the tabs use the
+//! same holder name, so a lease taken by a tab always excludes the other tabs.
+//! The tabs aren't notified of the changes made by the other tabs though: the
+//! event cache of a client only loads a room from the store the first time
+//! it's used, so a single client should use the database at a time.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use gloo_utils::format::JsValueSerdeExt;
+use indexed_db_futures::{prelude::*, request::OpenDbRequest, IdbDatabase, IdbVersionChangeEvent};
+use matrix_sdk_base::{
+    event_cache::{
+        store::{
+            compute_filters_string, extract_event_relation,
+            media::{
+                EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage,
+                MediaRetentionPolicy, MediaService,
+            },
+            EventCacheStore, EventCacheStoreError, RetainedContent,
+        },
+        Event, Gap,
+    },
+    linked_chunk::{
+        ChunkContent, ChunkIdentifier, ChunkIdentifierGenerator, Position, RawChunk, Update,
+    },
+    media::{MediaRequestParameters, UniqueKey},
+};
+use matrix_sdk_store_encryption::{Error as EncryptionError, StoreCipher};
+use ruma::{
+    events::relation::RelationType, time::SystemTime, EventId, MilliSecondsSinceUnixEpoch, MxcUri,
+    OwnedEventId, OwnedMxcUri, RoomId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::error;
+use wasm_bindgen::JsValue;
+use web_sys::IdbKeyRange;
+
+use crate::safe_encode::SafeEncode;
+
+const CURRENT_DB_VERSION: u32 = 1;
+
+mod keys {
+    pub const LEASES: &str = "leases";
+    pub const LINKED_CHUNKS: &str = "linked_chunks";
+    pub const EVENTS: &str = "events";
+    pub const MEDIA: &str = "media";
+    pub const KV: &str = "kv";
+
+    /// All names of the current event cache stores for convenience.
+    pub const ALL_STORES: &[&str] = &[LEASES, LINKED_CHUNKS, EVENTS, MEDIA, KV];
+
+    // static keys
+
+    pub const MEDIA_RETENTION_POLICY: &str = "media_retention_policy";
+    pub const LAST_MEDIA_CLEANUP_TIME: &str = "last_media_cleanup_time";
+    pub const RETAINED_CONTENT: &str = "retained_content";
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexeddbEventCacheStoreError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("DomException {name} ({code}): {message}")]
+    DomException { name: String, message: String, code: u16 },
+    #[error("Creating a key range failed: {0}")]
+    KeyRange(String),
+    #[error("The store contains invalid data: {0}")]
+    InvalidData(String),
+}
+
+impl From<web_sys::DomException> for IndexeddbEventCacheStoreError {
+    fn from(frm: web_sys::DomException) -> IndexeddbEventCacheStoreError {
+        IndexeddbEventCacheStoreError::DomException {
+            name: frm.name(),
+            message: frm.message(),
+            code: frm.code(),
+        }
+    }
+}
+
+impl From<IndexeddbEventCacheStoreError> for EventCacheStoreError {
+    fn from(e: IndexeddbEventCacheStoreError) -> Self {
+        match e {
+            IndexeddbEventCacheStoreError::Json(e) => EventCacheStoreError::Serialization(e),
+            IndexeddbEventCacheStoreError::Encryption(e) => EventCacheStoreError::Encryption(e),
+            IndexeddbEventCacheStoreError::InvalidData(details) => {
+                EventCacheStoreError::InvalidData { details }
+            }
+            _ => EventCacheStoreError::backend(e),
+        }
+    }
+}
+
+type Result<A, E = IndexeddbEventCacheStoreError> = std::result::Result<A, E>;
+
+/// A lease of the cross-process lock, as stored in the database.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    holder: String,
+    /// The expiration time of the lease, in milliseconds since the Unix epoch.
+    expiration: u64,
+}
+
+/// A chunk of a linked chunk, as stored in the database.
+///
+/// The events of an items chunk are only referenced by their ID, the events
+/// themselves are stored separately, in [`EventRecord`]s.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkRecord {
+    id: u64,
+    previous: Option<u64>,
+    next: Option<u64>,
+    content: ChunkRecordContent,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ChunkRecordContent {
+    Gap { prev_token: String },
+    Events(Vec<OwnedEventId>),
+}
+
+/// An event, as stored in the database.
+#[derive(Debug, Serialize, Deserialize)]
+struct EventRecord {
+    event: Event,
+    /// The ID of the event this event relates to, if any.
+    relates_to: Option<OwnedEventId>,
+    /// The type of the relation, if any.
+    rel_type: Option<String>,
+}
+
+impl EventRecord {
+    fn new(event: Event) -> Self {
+        let (relates_to, rel_type) = extract_event_relation(event.raw()).unzip();
+        Self { event, relates_to, rel_type }
+    }
+}
+
+/// A media content, as stored in the database.
+#[derive(Debug, Serialize, Deserialize)]
+struct MediaRecord {
+    uri: OwnedMxcUri,
+    data: Vec<u8>,
+    ignore_policy: bool,
+    /// The time of the last access, in milliseconds since the Unix epoch.
+    last_access: u64,
+}
+
+fn time_to_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn millis_to_time(millis: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// An IndexedDB-backed [`EventCacheStore`].
+///
+/// It is usually opened with [`crate::open_event_cache_store()`], to share the
+/// name and the encryption key of an [`crate::IndexeddbStateStore`].
+#[derive(Clone)]
+pub struct IndexeddbEventCacheStore {
+    name: String,
+    /// A random identifier of this instance of the store, to tell apart the
+    /// lease holders of different tabs.
+    instance_id: String,
+    inner: Arc<IdbDatabase>,
+    store_cipher: Option<Arc<StoreCipher>>,
+    media_service: MediaService,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for IndexeddbEventCacheStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexeddbEventCacheStore").field("name", &self.name).finish()
+    }
+}
+
+impl IndexeddbEventCacheStore {
+    /// Open the event cache store with the given name prefix, encrypting the
+    /// data with the given cipher, if any.
+    pub(crate) async fn open_with_store_cipher(
+        prefix: &str,
+        store_cipher: Option<Arc<StoreCipher>>,
+    ) -> Result<Self> {
+        let name = format!("{prefix}::matrix-sdk-event-cache");
+
+        let mut db_req: OpenDbRequest = IdbDatabase::open_u32(&name, CURRENT_DB_VERSION)?;
+        db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+            if evt.old_version() < 1.0 {
+                for store in keys::ALL_STORES {
+                    evt.db().create_object_store(store)?;
+                }
+            }
+
+            Ok(())
+        }));
+        let db = db_req.await?;
+
+        // The holder names of the cross-process lock are usually the same in all the
+        // tabs, so they need to be told apart.
+        let instance_id = format!("{:016x}", (js_sys::Math::random() * u64::MAX as f64) as u64);
+
+        let this = Self {
+            name,
+            instance_id,
+            inner: Arc::new(db),
+            store_cipher,
+            media_service: MediaService::new(),
+        };
+
+        let media_retention_policy = this.get_kv_value(keys::MEDIA_RETENTION_POLICY).await?;
+        let last_media_cleanup_time =
+            this.get_kv_value::<u64>(keys::LAST_MEDIA_CLEANUP_TIME).await?.map(millis_to_time);
+        this.media_service.restore(media_retention_policy, last_media_cleanup_time);
+
+        Ok(this)
+    }
+
+    /// Open the event cache store with the given name, without encryption.
+    pub async fn open_with_name(name: &str) -> Result<Self> {
+        Self::open_with_store_cipher(name, None).await
+    }
+
+    /// Encrypt (if needs be) then JSON-serialize a value.
+    fn serialize_value(&self, value: &impl Serialize) -> Result<JsValue> {
+        Ok(match &self.store_cipher {
+            Some(cipher) => {
+                let data = serde_json::to_vec(value)?;
+                JsValue::from_serde(&cipher.encrypt_value_data(data)?)?
+            }
+            None => JsValue::from_serde(value)?,
+        })
+    }
+
+    /// Deserialize a JSON value and then decrypt it (if needs be).
+    fn deserialize_value<T: DeserializeOwned>(&self, value: &JsValue) -> Result<T> {
+        match &self.store_cipher {
+            Some(cipher) => {
+                use zeroize::Zeroize;
+                let mut plaintext = cipher.decrypt_value_data(value.into_serde()?)?;
+                let ret = serde_json::from_slice(&plaintext);
+                plaintext.zeroize();
+                Ok(ret?)
+            }
+            None => Ok(value.into_serde()?),
+        }
+    }
+
+    fn encode_key<T>(&self, table_name: &str, key: T) -> JsValue
+    where
+        T: SafeEncode,
+    {
+        match &self.store_cipher {
+            Some(cipher) => key.as_secure_string(table_name, cipher),
+            None => key.as_encoded_string(),
+        }
+        .into()
+    }
+
+    fn encode_to_range<T>(&self, table_name: &str, key: T) -> Result<IdbKeyRange>
+    where
+        T: SafeEncode,
+    {
+        match &self.store_cipher {
+            Some(cipher) => key.encode_to_range_secure(table_name, cipher),
+            None => key.encode_to_range(),
+        }
+        .map_err(IndexeddbEventCacheStoreError::KeyRange)
+    }
+
+    fn chunk_key(&self, room_id: &RoomId, chunk_id: u64) -> JsValue {
+        self.encode_key(keys::LINKED_CHUNKS, (room_id, chunk_id.to_string()))
+    }
+
+    fn event_key(&self, room_id: &RoomId, event_id: &EventId) -> JsValue {
+        self.encode_key(keys::EVENTS, (room_id, event_id))
+    }
+
+    fn media_key(&self, request: &MediaRequestParameters) -> JsValue {
+        self.encode_key(keys::MEDIA, (request.uri(), request.unique_key()))
+    }
+
+    async fn get_kv_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::KV, IdbTransactionMode::Readonly)?
+            .object_store(keys::KV)?
+            .get(&self.encode_key(keys::KV, key))?
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn set_kv_value(&self, key: &str, value: &impl Serialize) -> Result<()> {
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::KV, IdbTransactionMode::Readwrite)?;
+        tx.object_store(keys::KV)?
+            .put_key_val(&self.encode_key(keys::KV, key), &self.serialize_value(value)?)?;
+        tx.await.into_result()?;
+        Ok(())
+    }
+
+    async fn load_chunk_record(
+        &self,
+        store: &IdbObjectStore<'_>,
+        room_id: &RoomId,
+        chunk_id: u64,
+    ) -> Result<ChunkRecord> {
+        store
+            .get(&self.chunk_key(room_id, chunk_id))?
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()?
+            .ok_or_else(|| {
+                IndexeddbEventCacheStoreError::InvalidData(format!(
+                    "chunk {chunk_id} of room {room_id} doesn't exist"
+                ))
+            })
+    }
+
+    fn save_chunk_record(
+        &self,
+        store: &IdbObjectStore<'_>,
+        room_id: &RoomId,
+        chunk: &ChunkRecord,
+    ) -> Result<()> {
+        store.put_key_val(&self.chunk_key(room_id, chunk.id), &self.serialize_value(chunk)?)?;
+        Ok(())
+    }
+
+    fn save_event_record(
+        &self,
+        store: &IdbObjectStore<'_>,
+        room_id: &RoomId,
+        event_id: &EventId,
+        event: Event,
+    ) -> Result<()> {
+        store.put_key_val(
+            &self.event_key(room_id, event_id),
+            &self.serialize_value(&EventRecord::new(event))?,
+        )?;
+        Ok(())
+    }
+
+    async fn load_room_chunk_records(&self, room_id: &RoomId) -> Result<Vec<ChunkRecord>> {
+        let range = self.encode_to_range(keys::LINKED_CHUNKS, room_id)?;
+
+        self.inner
+            .transaction_on_one_with_mode(keys::LINKED_CHUNKS, IdbTransactionMode::Readonly)?
+            .object_store(keys::LINKED_CHUNKS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value(&value))
+            .collect()
+    }
+
+    /// Insert a new chunk, and link it to its neighbours.
+    async fn insert_chunk(
+        &self,
+        store: &IdbObjectStore<'_>,
+        room_id: &RoomId,
+        previous: Option<ChunkIdentifier>,
+        new: ChunkIdentifier,
+        next: Option<ChunkIdentifier>,
+        content: ChunkRecordContent,
+    ) -> Result<()> {
+        let previous = previous.map(|id| id.index());
+        let new = new.index();
+        let next = next.map(|id| id.index());
+
+        if let Some(previous) = previous {
+            let mut chunk = self.load_chunk_record(store, room_id, previous).await?;
+            chunk.next = Some(new);
+            self.save_chunk_record(store, room_id, &chunk)?;
+        }
+
+        if let Some(next) = next {
+            let mut chunk = self.load_chunk_record(store, room_id, next).await?;
+            chunk.previous = Some(new);
+            self.save_chunk_record(store, room_id, &chunk)?;
+        }
+
+        self.save_chunk_record(store, room_id, &ChunkRecord { id: new, previous, next, content })
+    }
+
+    /// Load the events of the given chunk, to build a [`RawChunk`].
+    async fn load_raw_chunk(
+        &self,
+        room_id: &RoomId,
+        chunk: ChunkRecord,
+    ) -> Result<RawChunk<Event, Gap>> {
+        let content = match chunk.content {
+            ChunkRecordContent::Gap { prev_token } => ChunkContent::Gap(Gap { prev_token }),
+
+            ChunkRecordContent::Events(event_ids) => {
+                let tx = self
+                    .inner
+                    .transaction_on_one_with_mode(keys::EVENTS, IdbTransactionMode::Readonly)?;
+                let store = tx.object_store(keys::EVENTS)?;
+
+                let mut events = Vec::with_capacity(event_ids.len());
+
+                for event_id in event_ids {
+                    let record: EventRecord = store
+                        .get(&self.event_key(room_id, &event_id))?
+                        .await?
+                        .map(|value| self.deserialize_value(&value))
+                        .transpose()?
+                        .ok_or_else(|| {
+                            IndexeddbEventCacheStoreError::InvalidData(format!(
+                                "event {event_id} of chunk {} doesn't exist",
+                                chunk.id
+                            ))
+                        })?;
+
+                    events.push(record.event);
+                }
+
+                ChunkContent::Items(events)
+            }
+        };
+
+        Ok(RawChunk {
+            content,
+            previous: chunk.previous.map(ChunkIdentifier::new),
+            identifier: ChunkIdentifier::new(chunk.id),
+            next: chunk.next.map(ChunkIdentifier::new),
+        })
+    }
+
+    /// Get all the media records, with their keys.
+    async fn load_media_records(
+        &self,
+        store: &IdbObjectStore<'_>,
+    ) -> Result<Vec<(JsValue, MediaRecord)>> {
+        let Some(cursor) = store.open_cursor()?.await? else {
+            return Ok(Vec::new());
+        };
+
+        cursor
+            .into_vec(0)
+            .await?
+            .into_iter()
+            .map(|kv| Ok((kv.key().clone(), self.deserialize_value(kv.value())?)))
+            .collect()
+    }
+}
+
+/// Get the event IDs of an items chunk, or an error if it's a gap.
+fn chunk_event_ids(chunk: &mut ChunkRecord) -> Result<&mut Vec<OwnedEventId>> {
+    match &mut chunk.content {
+        ChunkRecordContent::Events(event_ids) => Ok(event_ids),
+        ChunkRecordContent::Gap { .. } => Err(IndexeddbEventCacheStoreError::InvalidData(format!(
+            "chunk {} is a gap, it can't contain events",
+            chunk.id
+        ))),
+    }
+}
+
+/// Check that the position of an item is valid in the given chunk.
+fn check_index(event_ids: &[OwnedEventId], position: Position, inclusive: bool) -> Result<usize> {
+    let index = position.index();
+    let len = event_ids.len();
+
+    if index < len || (inclusive && index == len) {
+        Ok(index)
+    } else {
+        Err(IndexeddbEventCacheStoreError::InvalidData(format!(
+            "position {index} is out of bounds in chunk {}, which has {len} items",
+            position.chunk_identifier().index()
+        )))
+    }
+}
+
+#[async_trait(?Send)]
+impl EventCacheStore for IndexeddbEventCacheStore {
+    type Error = IndexeddbEventCacheStoreError;
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let encoded_key = self.encode_key(keys::LEASES, key);
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let holder = format!("{holder}@{}", self.instance_id);
+
+        // The read and the write happen in the same read-write transaction, which
+        // can't interleave with a transaction on the same store from another tab.
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::LEASES, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::LEASES)?;
+
+        let lease = store
+            .get(&encoded_key)?
+            .await?
+            .map(|value| self.deserialize_value::<Lease>(&value))
+            .transpose()?;
+
+        let can_take = match lease {
+            Some(lease) => lease.holder == holder || lease.expiration < now,
+            None => true,
+        };
+
+        if can_take {
+            let lease = Lease { holder, expiration: now + u64::from(lease_duration_ms) };
+            store.put_key_val(&encoded_key, &self.serialize_value(&lease)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(can_take)
+    }
+
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<Update<Event, Gap>>,
+    ) -> Result<()> {
+        let tx = self.inner.transaction_on_multi_with_mode(
+            &[keys::LINKED_CHUNKS, keys::EVENTS],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let chunks = tx.object_store(keys::LINKED_CHUNKS)?;
+        let events = tx.object_store(keys::EVENTS)?;
+
+        for update in updates {
+            match update {
+                Update::NewItemsChunk { previous, new, next } => {
+                    let content = ChunkRecordContent::Events(Vec::new());
+                    self.insert_chunk(&chunks, room_id, previous, new, next, content).await?;
+                }
+
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    let content = ChunkRecordContent::Gap { prev_token: gap.prev_token };
+                    self.insert_chunk(&chunks, room_id, previous, new, next, content).await?;
+                }
+
+                Update::RemoveChunk(chunk_id) => {
+                    let chunk = self.load_chunk_record(&chunks, room_id, chunk_id.index()).await?;
+
+                    // Link the neighbours together.
+                    if let Some(previous) = chunk.previous {
+                        let mut previous =
+                            self.load_chunk_record(&chunks, room_id, previous).await?;
+                        previous.next = chunk.next;
+                        self.save_chunk_record(&chunks, room_id, &previous)?;
+                    }
+
+                    if let Some(next) = chunk.next {
+                        let mut next = self.load_chunk_record(&chunks, room_id, next).await?;
+                        next.previous = chunk.previous;
+                        self.save_chunk_record(&chunks, room_id, &next)?;
+                    }
+
+                    chunks.delete(&self.chunk_key(room_id, chunk.id))?;
+                }
+
+                Update::PushItems { at, items } => {
+                    let mut chunk = self
+                        .load_chunk_record(&chunks, room_id, at.chunk_identifier().index())
+                        .await?;
+                    let event_ids = chunk_event_ids(&mut chunk)?;
+                    let mut index = check_index(event_ids, at, true)?;
+
+                    for event in items {
+                        let Some(event_id) = event.event_id() else {
+                            error!(%room_id, "Trying to push an event with no ID");
+                            continue;
+                        };
+
+                        self.save_event_record(&events, room_id, &event_id, event)?;
+                        event_ids.insert(index, event_id);
+                        index += 1;
+                    }
+
+                    self.save_chunk_record(&chunks, room_id, &chunk)?;
+                }
+
+                Update::ReplaceItem { at, item } => {
+                    let Some(event_id) = item.event_id() else {
+                        error!(%room_id, "Trying to replace an event with a new one that has no ID");
+                        continue;
+                    };
+
+                    let mut chunk = self
+                        .load_chunk_record(&chunks, room_id, at.chunk_identifier().index())
+                        .await?;
+                    let event_ids = chunk_event_ids(&mut chunk)?;
+                    let index = check_index(event_ids, at, false)?;
+
+                    self.save_event_record(&events, room_id, &event_id, item)?;
+                    event_ids[index] = event_id;
+
+                    self.save_chunk_record(&chunks, room_id, &chunk)?;
+                }
+
+                Update::RemoveItem { at } => {
+                    let mut chunk = self
+                        .load_chunk_record(&chunks, room_id, at.chunk_identifier().index())
+                        .await?;
+                    let event_ids = chunk_event_ids(&mut chunk)?;
+                    let index = check_index(event_ids, at, false)?;
+
+                    event_ids.remove(index);
+
+                    self.save_chunk_record(&chunks, room_id, &chunk)?;
+                }
+
+                Update::DetachLastItems { at } => {
+                    let mut chunk = self
+                        .load_chunk_record(&chunks, room_id, at.chunk_identifier().index())
+                        .await?;
+                    let event_ids = chunk_event_ids(&mut chunk)?;
+                    let index = check_index(event_ids, at, true)?;
+
+                    event_ids.truncate(index);
+
+                    self.save_chunk_record(&chunks, room_id, &chunk)?;
+                }
+
+                Update::StartReattachItems | Update::EndReattachItems => {
+                    // Nothing to do.
+                }
+
+                Update::Clear => {
+                    chunks.delete(&self.encode_to_range(keys::LINKED_CHUNKS, room_id)?)?;
+                    events.delete(&self.encode_to_range(keys::EVENTS, room_id)?)?;
+                }
+            }
+        }
+
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn load_all_chunks(&self, room_id: &RoomId) -> Result<Vec<RawChunk<Event, Gap>>> {
+        let mut raw_chunks = Vec::new();
+
+        for chunk in self.load_room_chunk_records(room_id).await? {
+            raw_chunks.push(self.load_raw_chunk(room_id, chunk).await?);
+        }
+
+        Ok(raw_chunks)
+    }
+
+    async fn load_last_chunk(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<RawChunk<Event, Gap>>, ChunkIdentifierGenerator)> {
+        let chunks = self.load_room_chunk_records(room_id).await?;
+
+        let chunk_identifier_generator = match chunks.iter().map(|chunk| chunk.id).max() {
+            Some(last_chunk_identifier) => {
+                ChunkIdentifierGenerator::new_from_previous_chunk_identifier(ChunkIdentifier::new(
+                    last_chunk_identifier,
+                ))
+            }
+            None => ChunkIdentifierGenerator::new_from_scratch(),
+        };
+
+        if chunks.is_empty() {
+            return Ok((None, chunk_identifier_generator));
+        }
+
+        let Some(last_chunk) = chunks.into_iter().find(|chunk| chunk.next.is_none()) else {
+            return Err(IndexeddbEventCacheStoreError::InvalidData(
+                "last chunk is not found but chunks exist: the linked chunk contains a cycle"
+                    .to_owned(),
+            ));
+        };
+
+        Ok((Some(self.load_raw_chunk(room_id, last_chunk).await?), chunk_identifier_generator))
+    }
+
+    async fn load_previous_chunk(
+        &self,
+        room_id: &RoomId,
+        before_chunk_identifier: ChunkIdentifier,
+    ) -> Result<Option<RawChunk<Event, Gap>>> {
+        let before = before_chunk_identifier.index();
+
+        let Some(chunk) = self
+            .load_room_chunk_records(room_id)
+            .await?
+            .into_iter()
+            .find(|chunk| chunk.next == Some(before))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.load_raw_chunk(room_id, chunk).await?))
+    }
+
+    async fn clear_all_rooms_chunks(&self) -> Result<()> {
+        // Load the retained content before starting the transaction, so it isn't
+        // committed early while waiting on another one.
+        let retained = self.get_kv_value::<RetainedContent>(keys::RETAINED_CONTENT).await?;
+
+        let tx = self.inner.transaction_on_multi_with_mode(
+            &[keys::LINKED_CHUNKS, keys::EVENTS],
+            IdbTransactionMode::Readwrite,
+        )?;
+
+        let chunks = tx.object_store(keys::LINKED_CHUNKS)?;
+        let events = tx.object_store(keys::EVENTS)?;
+
+        // Collect the retained content first, to put it back after everything has been
+        // cleared.
+        let mut kept_chunks = Vec::new();
+        let mut kept_events = Vec::new();
+
+        if let Some(retained) = retained {
+            for room_id in &retained.rooms {
+                let range = self.encode_to_range(keys::LINKED_CHUNKS, room_id)?;
+                for value in chunks.get_all_with_key(&range)?.await?.iter() {
+                    let chunk: ChunkRecord = self.deserialize_value(&value)?;
+                    kept_chunks.push((self.chunk_key(room_id, chunk.id), value));
+                }
+
+                let range = self.encode_to_range(keys::EVENTS, room_id)?;
+                for value in events.get_all_with_key(&range)?.await?.iter() {
+                    let record: EventRecord = self.deserialize_value(&value)?;
+                    if let Some(event_id) = record.event.event_id() {
+                        kept_events.push((self.event_key(room_id, &event_id), value));
+                    }
+                }
+            }
+
+            for (room_id, event_ids) in &retained.events {
+                for event_id in event_ids {
+                    let key = self.event_key(room_id, event_id);
+                    if let Some(value) = events.get(&key)?.await? {
+                        kept_events.push((key, value));
+                    }
+                }
+            }
+        }
+
+        chunks.clear()?;
+        events.clear()?;
+
+        for (key, value) in kept_chunks {
+            chunks.put_key_val(&key, &value)?;
+        }
+
+        for (key, value) in kept_events {
+            events.put_key_val(&key, &value)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn filter_duplicated_events(
+        &self,
+        room_id: &RoomId,
+        events: Vec<OwnedEventId>,
+    ) -> Result<Vec<(OwnedEventId, Position)>> {
+        if events.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chunks = self.load_room_chunk_records(room_id).await?;
+
+        let duplicated_events = events
+            .into_iter()
+            .filter_map(|event_id| {
+                let position = chunks.iter().find_map(|chunk| {
+                    let ChunkRecordContent::Events(event_ids) = &chunk.content else {
+                        return None;
+                    };
+
+                    let index = event_ids.iter().position(|known| *known == event_id)?;
+                    Some(Position::new(ChunkIdentifier::new(chunk.id), index))
+                })?;
+
+                Some((event_id, position))
+            })
+            .collect();
+
+        Ok(duplicated_events)
+    }
+
+    async fn find_event(&self, room_id: &RoomId, event_id: &EventId) -> Result<Option<Event>> {
+        let record = self
+            .inner
+            .transaction_on_one_with_mode(keys::EVENTS, IdbTransactionMode::Readonly)?
+            .object_store(keys::EVENTS)?
+            .get(&self.event_key(room_id, event_id))?
+            .await?
+            .map(|value| self.deserialize_value::<EventRecord>(&value))
+            .transpose()?;
+
+        Ok(record.map(|record| record.event))
+    }
+
+    async fn find_event_relations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        filters: Option<&[RelationType]>,
+    ) -> Result<Vec<Event>> {
+        let filters = compute_filters_string(filters);
+        let range = self.encode_to_range(keys::EVENTS, room_id)?;
+
+        let records = self
+            .inner
+            .transaction_on_one_with_mode(keys::EVENTS, IdbTransactionMode::Readonly)?
+            .object_store(keys::EVENTS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value::<EventRecord>(&value))
+            .collect::<Result<Vec<_>>>()?;
+
+        let related_events = records
+            .into_iter()
+            .filter(|record| {
+                if record.relates_to.as_deref() != Some(event_id) {
+                    return false;
+                }
+
+                match (&filters, &record.rel_type) {
+                    (Some(filters), Some(rel_type)) => filters.contains(rel_type),
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+            })
+            .map(|record| record.event)
+            .collect();
+
+        Ok(related_events)
+    }
+
+    async fn save_event(&self, room_id: &RoomId, event: Event) -> Result<()> {
+        let Some(event_id) = event.event_id() else {
+            error!(%room_id, "Trying to save an event with no ID");
+            return Ok(());
+        };
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::EVENTS, IdbTransactionMode::Readwrite)?;
+        self.save_event_record(&tx.object_store(keys::EVENTS)?, room_id, &event_id, event)?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<()> {
+        let mut content = self.retained_content().await?;
+        content.set_room(room_id, retained);
+        self.set_kv_value(keys::RETAINED_CONTENT, &content).await
+    }
+
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<()> {
+        let mut content = self.retained_content().await?;
+        content.set_event(room_id, event_id, retained);
+        self.set_kv_value(keys::RETAINED_CONTENT, &content).await
+    }
+
+    async fn retained_content(&self) -> Result<RetainedContent> {
+        Ok(self.get_kv_value(keys::RETAINED_CONTENT).await?.unwrap_or_default())
+    }
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        data: Vec<u8>,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        self.media_service.add_media_content(self, request, data, ignore_policy).await
+    }
+
+    async fn replace_media_key(
+        &self,
+        from: &MediaRequestParameters,
+        to: &MediaRequestParameters,
+    ) -> Result<()> {
+        let from_key = self.media_key(from);
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        if let Some(value) = store.get(&from_key)?.await? {
+            let mut record: MediaRecord = self.deserialize_value(&value)?;
+            record.uri = to.uri().to_owned();
+
+            store.delete(&from_key)?;
+            store.put_key_val(&self.media_key(to), &self.serialize_value(&record)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn get_media_content(&self, request: &MediaRequestParameters) -> Result<Option<Vec<u8>>> {
+        self.media_service.get_media_content(self, request).await
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequestParameters) -> Result<()> {
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        tx.object_store(keys::MEDIA)?.delete(&self.media_key(request))?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn get_media_content_for_uri(&self, uri: &MxcUri) -> Result<Option<Vec<u8>>> {
+        self.media_service.get_media_content_for_uri(self, uri).await
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        let range = self.encode_to_range(keys::MEDIA, uri)?;
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        tx.object_store(keys::MEDIA)?.delete(&range)?;
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn set_media_retention_policy(&self, policy: MediaRetentionPolicy) -> Result<()> {
+        self.media_service.set_media_retention_policy(self, policy).await
+    }
+
+    fn media_retention_policy(&self) -> MediaRetentionPolicy {
+        self.media_service.media_retention_policy()
+    }
+
+    async fn set_ignore_media_retention_policy(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        self.media_service.set_ignore_media_retention_policy(self, request, ignore_policy).await
+    }
+
+    async fn clean_up_media_cache(&self) -> Result<()> {
+        self.media_service.clean_up_media_cache(self).await
+    }
+
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage> {
+        self.media_service.media_cache_usage(self).await
+    }
+}
+
+#[async_trait(?Send)]
+impl EventCacheStoreMedia for IndexeddbEventCacheStore {
+    type Error = IndexeddbEventCacheStoreError;
+
+    async fn media_retention_policy_inner(&self) -> Result<Option<MediaRetentionPolicy>> {
+        self.get_kv_value(keys::MEDIA_RETENTION_POLICY).await
+    }
+
+    async fn set_media_retention_policy_inner(&self, policy: MediaRetentionPolicy) -> Result<()> {
+        self.set_kv_value(keys::MEDIA_RETENTION_POLICY, &policy).await
+    }
+
+    async fn add_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        data: Vec<u8>,
+        last_access: SystemTime,
+        policy: MediaRetentionPolicy,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        let key = self.media_key(request);
+        let ignore_policy = ignore_policy.is_yes();
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        if !ignore_policy && policy.exceeds_max_file_size(data.len() as u64) {
+            // Do not store it, but make sure that a previous version is not kept
+            // either.
+            store.delete(&key)?;
+        } else {
+            let record = MediaRecord {
+                uri: request.uri().to_owned(),
+                data,
+                ignore_policy,
+                last_access: time_to_millis(last_access),
+            };
+            store.put_key_val(&key, &self.serialize_value(&record)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn set_ignore_media_retention_policy_inner(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        let key = self.media_key(request);
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        if let Some(value) = store.get(&key)?.await? {
+            let mut record: MediaRecord = self.deserialize_value(&value)?;
+            record.ignore_policy = ignore_policy.is_yes();
+            store.put_key_val(&key, &self.serialize_value(&record)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(())
+    }
+
+    async fn get_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = self.media_key(request);
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        let Some(value) = store.get(&key)?.await? else {
+            return Ok(None);
+        };
+
+        // Update the last access time.
+        let mut record: MediaRecord = self.deserialize_value(&value)?;
+        record.last_access = time_to_millis(current_time);
+        store.put_key_val(&key, &self.serialize_value(&record)?)?;
+
+        tx.await.into_result()?;
+
+        Ok(Some(record.data))
+    }
+
+    async fn get_media_content_for_uri_inner(
+        &self,
+        uri: &MxcUri,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>> {
+        let range = self.encode_to_range(keys::MEDIA, uri)?;
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        let Some(cursor) = store.open_cursor_with_range(&range)?.await? else {
+            return Ok(None);
+        };
+
+        // Update the last access time.
+        let mut record: MediaRecord = self.deserialize_value(&cursor.value())?;
+        record.last_access = time_to_millis(current_time);
+        cursor.update(&self.serialize_value(&record)?)?.await?;
+
+        tx.await.into_result()?;
+
+        Ok(Some(record.data))
+    }
+
+    async fn clean_up_media_cache_inner(
+        &self,
+        policy: MediaRetentionPolicy,
+        current_time: SystemTime,
+    ) -> Result<()> {
+        if policy.has_limitations() {
+            let tx = self
+                .inner
+                .transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
+            let store = tx.object_store(keys::MEDIA)?;
+
+            // The media that ignore the policy are never removed.
+            let mut records = self
+                .load_media_records(&store)
+                .await?
+                .into_iter()
+                .filter(|(_, record)| !record.ignore_policy)
+                .collect::<Vec<_>>();
+
+            // Sort the media by last access, the most recent first, so the oldest media
+            // are the first to go when the cache is too big.
+            records.sort_by(|(_, lhs), (_, rhs)| rhs.last_access.cmp(&lhs.last_access));
+
+            let mut cache_size = 0u64;
+
+            for (key, record) in records {
+                let size = record.data.len() as u64;
+
+                let remove = policy.exceeds_max_file_size(size)
+                    || policy.has_content_expired(current_time, millis_to_time(record.last_access))
+                    || policy.max_cache_size.is_some_and(|max_cache_size| {
+                        cache_size = cache_size.saturating_add(size);
+                        cache_size > max_cache_size
+                    });
+
+                if remove {
+                    store.delete(&key)?;
+                }
+            }
+
+            tx.await.into_result()?;
+        }
+
+        self.set_kv_value(keys::LAST_MEDIA_CLEANUP_TIME, &time_to_millis(current_time)).await
+    }
+
+    async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>> {
+        Ok(self.get_kv_value::<u64>(keys::LAST_MEDIA_CLEANUP_TIME).await?.map(millis_to_time))
+    }
+
+    async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage> {
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readonly)?;
+        let store = tx.object_store(keys::MEDIA)?;
+
+        let mut usage = MediaCacheUsage::default();
+
+        for (_, record) in self.load_media_records(&store).await? {
+            let size = record.data.len() as u64;
+
+            usage.media_count += 1;
+            usage.total_size += size;
+
+            if record.ignore_policy {
+                usage.pinned_media_count += 1;
+                usage.pinned_size += size;
+            }
+        }
+
+        Ok(usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    use matrix_sdk_base::{
+        event_cache_store_integration_tests, event_cache_store_media_integration_tests,
+    };
+    use uuid::Uuid;
+
+    use super::{IndexeddbEventCacheStore, Result};
+
+    async fn get_event_cache_store() -> Result<IndexeddbEventCacheStore> {
+        let db_name = format!("test-event-cache-plain-{}", Uuid::new_v4().as_hyphenated());
+        IndexeddbEventCacheStore::open_with_name(&db_name).await
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_media_integration_tests!(with_media_size_tests);
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    use std::sync::Arc;
+
+    use matrix_sdk_base::{
+        event_cache_store_integration_tests, event_cache_store_media_integration_tests,
+    };
+    use matrix_sdk_store_encryption::StoreCipher;
+    use uuid::Uuid;
+
+    use super::{IndexeddbEventCacheStore, Result};
+
+    async fn get_event_cache_store() -> Result<IndexeddbEventCacheStore> {
+        let db_name = format!("test-event-cache-encrypted-{}", Uuid::new_v4().as_hyphenated());
+        let store_cipher = Arc::new(StoreCipher::new()?);
+        IndexeddbEventCacheStore::open_with_store_cipher(&db_name, Some(store_cipher)).await
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_media_integration_tests!();
+}
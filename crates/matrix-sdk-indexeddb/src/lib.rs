@@ -6,6 +6,8 @@ use thiserror::Error;
 
 #[cfg(feature = "e2e-encryption")]
 mod crypto_store;
+#[cfg(all(feature = "event-cache-store", target_arch = "wasm32"))]
+mod event_cache_store;
 mod safe_encode;
 #[cfg(feature = "e2e-encryption")]
 mod serialize_bool_for_indexeddb;
@@ -14,6 +16,8 @@ mod state_store;
 
 #[cfg(feature = "e2e-encryption")]
 pub use crypto_store::{IndexeddbCryptoStore, IndexeddbCryptoStoreError};
+#[cfg(all(feature = "event-cache-store", target_arch = "wasm32"))]
+pub use event_cache_store::{IndexeddbEventCacheStore, IndexeddbEventCacheStoreError};
 #[cfg(feature = "state-store")]
 pub use state_store::{
     IndexeddbStateStore, IndexeddbStateStoreBuilder, IndexeddbStateStoreError,
@@ -58,6 +62,20 @@ pub async fn open_state_store(
     Ok(state_store)
 }
 
+/// Create an [`IndexeddbEventCacheStore`] that uses the same name and
+/// encryption key as the given [`IndexeddbStateStore`].
+#[cfg(all(feature = "event-cache-store", feature = "state-store", target_arch = "wasm32"))]
+pub async fn open_event_cache_store(
+    name: &str,
+    state_store: &IndexeddbStateStore,
+) -> Result<IndexeddbEventCacheStore, OpenStoreError> {
+    let event_cache_store =
+        IndexeddbEventCacheStore::open_with_store_cipher(name, state_store.store_cipher.clone())
+            .await?;
+
+    Ok(event_cache_store)
+}
+
 /// All the errors that can occur when opening an IndexedDB store.
 #[derive(Error, Debug)]
 pub enum OpenStoreError {
@@ -70,4 +88,9 @@ pub enum OpenStoreError {
     #[cfg(feature = "e2e-encryption")]
     #[error(transparent)]
     Crypto(#[from] IndexeddbCryptoStoreError),
+
+    /// An error occurred with the event cache store implementation.
+    #[cfg(all(feature = "event-cache-store", target_arch = "wasm32"))]
+    #[error(transparent)]
+    EventCache(#[from] IndexeddbEventCacheStoreError),
 }
@@ -147,6 +147,35 @@ pub struct OngoingMigration {
     data: HashMap<&'static str, Vec<(JsValue, JsValue)>>,
 }
 
+/// Change the passphrase that encrypts the store cipher saved in the given
+/// meta database.
+///
+/// Does nothing if the store doesn't use a passphrase.
+pub async fn change_meta_db_passphrase(
+    meta_db: &IdbDatabase,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<()> {
+    let tx: IdbTransaction<'_> = meta_db
+        .transaction_on_one_with_mode(keys::INTERNAL_STATE, IdbTransactionMode::Readwrite)?;
+    let ob = tx.object_store(keys::INTERNAL_STATE)?;
+
+    let Some(StoreKeyWrapper(export)) =
+        ob.get(&JsValue::from_str(keys::STORE_KEY))?.await?.map(|v| v.into_serde()).transpose()?
+    else {
+        return Ok(());
+    };
+
+    let export = StoreCipher::change_passphrase(&export, old_passphrase, new_passphrase)?;
+    ob.put_key_val(
+        &JsValue::from_str(keys::STORE_KEY),
+        &JsValue::from_serde(&StoreKeyWrapper(export))?,
+    )?;
+
+    tx.await.into_result()?;
+    Ok(())
+}
+
 pub async fn upgrade_inner_db(
     name: &str,
     store_cipher: Option<&StoreCipher>,
@@ -55,7 +55,7 @@ use web_sys::IdbKeyRange;
 mod migrations;
 
 pub use self::migrations::MigrationConflictStrategy;
-use self::migrations::{upgrade_inner_db, upgrade_meta_db};
+use self::migrations::{change_meta_db_passphrase, upgrade_inner_db, upgrade_meta_db};
 use crate::safe_encode::SafeEncode;
 
 #[derive(Debug, thiserror::Error)]
@@ -288,6 +288,24 @@ impl IndexeddbStateStore {
         self.meta.version() as u32
     }
 
+    /// Change the passphrase that encrypts the store cipher.
+    ///
+    /// Only the store cipher is encrypted again with the new passphrase, so
+    /// the data doesn't need to be encrypted again. If `old_passphrase` is
+    /// wrong, a [`matrix_sdk_store_encryption::Error::WrongPassphraseOrKey`]
+    /// error is returned.
+    ///
+    /// The crypto and event cache stores opened with
+    /// [`open_stores_with_name()`](crate::open_stores_with_name) use the same
+    /// store cipher, so this changes their passphrase too.
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        change_meta_db_passphrase(&self.meta, old_passphrase, new_passphrase).await
+    }
+
     /// Whether this database has any migration backups
     pub async fn has_backups(&self) -> Result<bool> {
         Ok(self
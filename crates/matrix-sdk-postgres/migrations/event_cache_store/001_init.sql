@@ -0,0 +1,110 @@
+-- Basic kv metadata like the database version and store cipher.
+CREATE TABLE "kv" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "value" BYTEA NOT NULL
+);
+
+CREATE TABLE "media" (
+    -- Used to remove the least recently accessed media first.
+    "id" BIGINT GENERATED ALWAYS AS IDENTITY,
+
+    "uri" BYTEA NOT NULL,
+    "format" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL,
+    -- In seconds since Unix Epoch.
+    "last_access" BIGINT NOT NULL,
+    "ignore_policy" BOOLEAN NOT NULL DEFAULT FALSE,
+
+    PRIMARY KEY ("uri", "format")
+);
+CREATE INDEX "media_uri"
+    ON "media" ("uri");
+
+CREATE TABLE "lease_locks" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "holder" TEXT NOT NULL,
+    -- In milliseconds since Unix Epoch.
+    "expiration" BIGINT NOT NULL
+);
+
+CREATE TABLE "linked_chunks" (
+    -- Which room does this chunk belong to? (hashed key shared with the two other tables)
+    "room_id" BYTEA NOT NULL,
+    -- Identifier of the chunk, unique per room. Corresponds to a `ChunkIdentifier`.
+    "id" BIGINT NOT NULL,
+
+    -- Previous chunk in the linked list. Corresponds to a `ChunkIdentifier`.
+    "previous" BIGINT,
+    -- Next chunk in the linked list. Corresponds to a `ChunkIdentifier`.
+    "next" BIGINT,
+    -- Type of underlying entries: E for events, G for gaps
+    "type" TEXT CHECK ("type" IN ('E', 'G')) NOT NULL,
+
+    -- Primary key is composed of the room ID and the chunk identifier.
+    -- Such pairs must be unique.
+    PRIMARY KEY ("room_id", "id")
+);
+
+CREATE TABLE "gap_chunks" (
+    -- Which room does this gap belong to? (hashed key shared with linked_chunks)
+    "room_id" BYTEA NOT NULL,
+    -- Which chunk does this gap refer to? Corresponds to a `ChunkIdentifier`.
+    "chunk_id" BIGINT NOT NULL,
+
+    -- The previous batch token of a gap (encrypted value).
+    "prev_token" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "chunk_id"),
+
+    -- If the owning chunk gets deleted, delete the entry too.
+    FOREIGN KEY ("room_id", "chunk_id") REFERENCES "linked_chunks" ("room_id", "id") ON DELETE CASCADE
+);
+
+-- Events and their content.
+CREATE TABLE "events" (
+    -- The room in which the event is located.
+    "room_id" BYTEA NOT NULL,
+
+    -- The `OwnedEventId` of this event.
+    "event_id" TEXT NOT NULL,
+
+    -- JSON serialized `TimelineEvent` (encrypted value).
+    "content" BYTEA NOT NULL,
+
+    -- If this event is an aggregation (related event), the event id of the event it relates to.
+    -- Can be null if this event isn't an aggregation.
+    "relates_to" TEXT,
+
+    -- If this event is an aggregation (related event), the kind of relation it has to the event it
+    -- relates to.
+    -- Can be null if this event isn't an aggregation.
+    "rel_type" TEXT,
+
+    PRIMARY KEY ("room_id", "event_id")
+);
+CREATE INDEX "events_room_id_relates_to"
+    ON "events" ("room_id", "relates_to");
+
+-- Entries inside an event chunk.
+CREATE TABLE "event_chunks" (
+    -- Which room does this event belong to? (hashed key shared with linked_chunks)
+    "room_id" BYTEA NOT NULL,
+    -- Which chunk does this event refer to? Corresponds to a `ChunkIdentifier`.
+    "chunk_id" BIGINT NOT NULL,
+
+    -- `OwnedEventId` for events.
+    "event_id" TEXT NOT NULL,
+    -- Position (index) in the chunk.
+    "position" BIGINT NOT NULL,
+
+    PRIMARY KEY ("room_id", "event_id"),
+
+    -- We need a uniqueness constraint over the `room_id`, `chunk_id` and
+    -- `position` tuple because (i) they must be unique, (ii) it dramatically
+    -- improves the performance. It is only checked at the end of each statement,
+    -- so that the positions of several events can be shifted at once.
+    UNIQUE ("room_id", "chunk_id", "position") DEFERRABLE INITIALLY IMMEDIATE,
+
+    -- If the owning chunk gets deleted, delete the entry too.
+    FOREIGN KEY ("room_id", "chunk_id") REFERENCES "linked_chunks" ("room_id", "id") ON DELETE CASCADE
+);
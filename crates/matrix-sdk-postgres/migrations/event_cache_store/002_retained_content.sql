@@ -0,0 +1,21 @@
+-- Rooms that are retained as a whole, regardless of any cleanup.
+CREATE TABLE "retained_rooms" (
+    -- The room ID (hashed key shared with linked_chunks).
+    "room_id" BYTEA NOT NULL,
+    -- The `OwnedRoomId` of the room (encrypted value).
+    "room_id_value" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id")
+);
+
+-- Events that are retained individually, regardless of any cleanup.
+CREATE TABLE "retained_events" (
+    -- The room in which the event is located (hashed key shared with linked_chunks).
+    "room_id" BYTEA NOT NULL,
+    -- The `OwnedRoomId` of the room (encrypted value).
+    "room_id_value" BYTEA NOT NULL,
+    -- The `OwnedEventId` of this event.
+    "event_id" TEXT NOT NULL,
+
+    PRIMARY KEY ("room_id", "event_id")
+);
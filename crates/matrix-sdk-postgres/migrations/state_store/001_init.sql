@@ -0,0 +1,143 @@
+-- Basic kv data like the database version and store cipher.
+CREATE TABLE "kv" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "value" BYTEA NOT NULL
+);
+
+-- Another general-purpose kv table, but with binary keys, used for the sync
+-- token, filters and other things.
+CREATE TABLE "kv_blob" (
+    "key" BYTEA PRIMARY KEY NOT NULL,
+    "value" BYTEA NOT NULL
+);
+
+CREATE TABLE "room_info" (
+    "room_id" BYTEA PRIMARY KEY NOT NULL,
+    "state" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL
+);
+
+CREATE TABLE "state_event" (
+    "room_id" BYTEA NOT NULL,
+    "event_type" BYTEA NOT NULL,
+    "state_key" BYTEA NOT NULL,
+    "stripped" BOOLEAN NOT NULL,
+    "event_id" BYTEA,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "event_type", "state_key")
+);
+CREATE INDEX "state_event_room_id_event_id"
+    ON "state_event" ("room_id", "event_id");
+
+CREATE TABLE "global_account_data" (
+    "event_type" BYTEA PRIMARY KEY NOT NULL,
+    "data" BYTEA NOT NULL
+);
+
+CREATE TABLE "room_account_data" (
+    "room_id" BYTEA NOT NULL,
+    "event_type" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "event_type")
+);
+
+CREATE TABLE "member" (
+    "room_id" BYTEA NOT NULL,
+    "user_id" BYTEA NOT NULL,
+    "membership" BYTEA NOT NULL,
+    "stripped" BOOLEAN NOT NULL,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "user_id")
+);
+CREATE INDEX "member_room_id_membership"
+    ON "member" ("room_id", "membership");
+
+CREATE TABLE "profile" (
+    "room_id" BYTEA NOT NULL,
+    "user_id" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "user_id")
+);
+
+CREATE TABLE "receipt" (
+    "room_id" BYTEA NOT NULL,
+    "user_id" BYTEA NOT NULL,
+    "receipt_type" BYTEA NOT NULL,
+    "thread" BYTEA NOT NULL,
+    "event_id" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "user_id", "receipt_type", "thread")
+);
+CREATE INDEX "receipt_room_id_receipt_type_thread_event_id"
+    ON "receipt" ("room_id", "receipt_type", "thread", "event_id");
+
+CREATE TABLE "display_name" (
+    "room_id" BYTEA NOT NULL,
+    "name" BYTEA NOT NULL,
+    "data" BYTEA NOT NULL,
+
+    PRIMARY KEY ("room_id", "name")
+);
+
+-- Send queue requests, keyed by room id and transaction id.
+CREATE TABLE "send_queue_events" (
+    -- Used to keep the insertion order.
+    "id" BIGINT GENERATED ALWAYS AS IDENTITY,
+
+    -- This is used as a key, thus hashed.
+    "room_id" BYTEA NOT NULL,
+
+    -- This is used as a value (thus encrypted/decrypted).
+    "room_id_val" BYTEA NOT NULL,
+
+    -- This is used as both a key and a value, thus neither encrypted/decrypted/hashed.
+    "transaction_id" TEXT NOT NULL,
+
+    -- Used as a value, thus encrypted/decrypted.
+    "content" BYTEA NOT NULL,
+
+    -- The serialized error that caused the request to be wedged, if any. Used as a value, thus
+    -- encrypted/decrypted.
+    "wedge_reason" BYTEA,
+
+    -- In clear.
+    "priority" BIGINT NOT NULL DEFAULT 0,
+
+    -- In clear, in milliseconds since Unix Epoch.
+    "created_at" BIGINT,
+
+    PRIMARY KEY ("room_id", "transaction_id")
+);
+
+-- Send queue requests that depend on another one.
+CREATE TABLE "dependent_send_queue_events" (
+    -- Used to keep the insertion order.
+    "id" BIGINT GENERATED ALWAYS AS IDENTITY,
+
+    -- This is used as a key, thus hashed.
+    "room_id" BYTEA NOT NULL,
+
+    -- This is used as both a key and a value, thus neither encrypted/decrypted/hashed.
+    -- This is the transaction id for the *parent* transaction, not our own.
+    "parent_transaction_id" TEXT NOT NULL,
+
+    -- This is used as both a key and a value, thus neither encrypted/decrypted/hashed.
+    -- This is a transaction id used for the dependent event itself, not the parent.
+    "own_transaction_id" TEXT NOT NULL,
+
+    -- Serialized `SentRequestKey`, used as a value (thus encrypted/decrypted), can be null.
+    "parent_key" BYTEA,
+
+    -- Serialized `DependentQueuedRequestKind`, used as a value (thus encrypted/decrypted).
+    "content" BYTEA NOT NULL,
+
+    -- In clear, in milliseconds since Unix Epoch.
+    "created_at" BIGINT
+);
+CREATE INDEX "dependent_send_queue_events_room_id"
+    ON "dependent_send_queue_events" ("room_id");
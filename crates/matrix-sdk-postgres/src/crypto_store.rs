@@ -0,0 +1,1288 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use deadpool_postgres::{Object as PostgresConn, Pool, Transaction};
+use matrix_sdk_crypto::{
+    olm::{
+        InboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
+        PrivateCrossSigningIdentity, SenderDataType, Session, StaticAccountData,
+    },
+    store::{
+        BackupKeys, Changes, CryptoStore, DehydratedDeviceKey, PendingChanges, RoomKeyCounts,
+        RoomSettings,
+    },
+    types::events::room_key_withheld::RoomKeyWithheldEvent,
+    Account, DeviceData, GossipRequest, GossippedSecret, SecretInfo, TrackedUser, UserIdentityData,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{
+    events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
+    RoomId, TransactionId, UserId,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument, warn};
+use vodozemac::Curve25519PublicKey;
+
+use crate::{
+    error::{Error, Result},
+    utils::{lock_and_create_schema, Key, PostgresClientExt, PostgresKeyValueStoreExt},
+    OpenStoreError, PostgresStoreConfig,
+};
+
+/// The name of the store, used as the suffix of its schema.
+const STORE_NAME: &str = "crypto";
+
+/// A PostgreSQL based cryptostore.
+#[derive(Clone)]
+pub struct PostgresCryptoStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    pool: Pool,
+
+    // DB values cached in memory
+    static_account: Arc<RwLock<Option<StaticAccountData>>>,
+    save_changes_lock: Arc<Mutex<()>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for PostgresCryptoStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresCryptoStore").finish_non_exhaustive()
+    }
+}
+
+impl PostgresCryptoStore {
+    /// Open the PostgreSQL-based crypto store in the database at the given
+    /// connection string, using the given passphrase to encrypt private data.
+    pub async fn open(url: &str, passphrase: Option<&str>) -> Result<Self, OpenStoreError> {
+        Self::open_with_config(PostgresStoreConfig::from_url(url)?.passphrase(passphrase)).await
+    }
+
+    /// Open the PostgreSQL-based crypto store with the given config.
+    pub async fn open_with_config(config: PostgresStoreConfig) -> Result<Self, OpenStoreError> {
+        let schema = config.schema(STORE_NAME)?;
+        let pool = config.create_pool(&schema)?;
+
+        let mut conn = pool.get().await?;
+        let txn = conn.transaction().await?;
+
+        let version = lock_and_create_schema(&txn, &schema).await?;
+        run_migrations(&txn, version).await?;
+
+        let store_cipher = match config.passphrase.as_deref() {
+            Some(p) => Some(Arc::new(txn.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        txn.commit().await?;
+        drop(conn);
+
+        Ok(Self {
+            store_cipher,
+            pool,
+            static_account: Arc::new(RwLock::new(None)),
+            save_changes_lock: Default::default(),
+        })
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(data)?;
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    fn serialize_value(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = rmp_serde::to_vec_named(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn deserialize_value<T: DeserializeOwned>(&self, value: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(value)?;
+        Ok(rmp_serde::from_slice(&decoded)?)
+    }
+
+    fn deserialize_and_unpickle_inbound_group_session(
+        &self,
+        value: Vec<u8>,
+        backed_up: bool,
+    ) -> Result<InboundGroupSession> {
+        let mut pickle: PickledInboundGroupSession = self.deserialize_value(&value)?;
+
+        // The `backed_up` SQL column is the source of truth, because we update it
+        // inside `mark_inbound_group_sessions_as_backed_up` and don't update
+        // the pickled value inside the `data` column (until now, when we are puling it
+        // out of the DB).
+        pickle.backed_up = backed_up;
+
+        Ok(InboundGroupSession::from_pickle(pickle)?)
+    }
+
+    fn deserialize_key_request(&self, value: &[u8], sent_out: bool) -> Result<GossipRequest> {
+        let mut request: GossipRequest = self.deserialize_value(value)?;
+        // sent_out SQL column is source of truth, sent_out field in serialized value
+        // needed for other stores though
+        request.sent_out = sent_out;
+        Ok(request)
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    fn get_static_account(&self) -> Option<StaticAccountData> {
+        self.static_account.read().unwrap().clone()
+    }
+
+    async fn acquire(&self) -> Result<PostgresConn> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+/// Identifier of the latest database version.
+///
+/// This is used to figure whether the database requires a migration.
+/// Every new SQL migration should imply a bump of this number, and changes in
+/// the [`run_migrations`] function.
+const DATABASE_VERSION: u8 = 1;
+
+/// key for the dehydrated device pickle key in the key/value table.
+const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
+
+/// Run the database migrations from the given version.
+async fn run_migrations(txn: &Transaction<'_>, version: u8) -> Result<()> {
+    if version >= DATABASE_VERSION {
+        return Ok(());
+    }
+
+    debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+
+    if version < 1 {
+        txn.batch_execute(include_str!("../migrations/crypto_store/001_init.sql")).await?;
+        txn.set_db_version(1).await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+trait PostgresCryptoStoreExt: PostgresClientExt {
+    async fn set_session(&self, session_id: &[u8], sender_key: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO session (session_id, sender_key, data) VALUES ($1, $2, $3)
+             ON CONFLICT (session_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&session_id, &sender_key, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_sessions_for_sender_key(&self, sender_key: &Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT data FROM session WHERE sender_key = $1", &[&sender_key])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn set_inbound_group_session(
+        &self,
+        room_id: &[u8],
+        session_id: &[u8],
+        data: &[u8],
+        backed_up: bool,
+        sender_key: Option<&[u8]>,
+        sender_data_type: Option<i16>,
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO inbound_group_session
+                 (session_id, room_id, data, backed_up, sender_key, sender_data_type)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (session_id) DO UPDATE
+             SET data = EXCLUDED.data, backed_up = EXCLUDED.backed_up,
+                 sender_key = EXCLUDED.sender_key, sender_data_type = EXCLUDED.sender_data_type",
+            &[&session_id, &room_id, &data, &backed_up, &sender_key, &sender_data_type],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_inbound_group_session(
+        &self,
+        session_id: &Key,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>, bool)>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT room_id, data, backed_up FROM inbound_group_session WHERE session_id = $1",
+                &[&session_id],
+            )
+            .await?
+            .map(|row| (row.get(0), row.get(1), row.get(2))))
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<(Vec<u8>, bool)>> {
+        Ok(self
+            .query_cached("SELECT data, backed_up FROM inbound_group_session", &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn get_inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
+        let row = self
+            .query_one_cached(
+                "SELECT count(*), count(*) FILTER (WHERE backed_up) FROM inbound_group_session",
+                &[],
+            )
+            .await?;
+        let total: i64 = row.get(0);
+        let backed_up: i64 = row.get(1);
+
+        Ok(RoomKeyCounts { total: total as usize, backed_up: backed_up as usize })
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: &Key,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<Key>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, bool)>> {
+        let sender_data_type = sender_data_type as i16;
+
+        // If we are not provided with an `after_session_id`, use a key which will sort
+        // before all real keys: the empty string.
+        let after_session_id = after_session_id.unwrap_or(Key::Plain(Vec::new()));
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        Ok(self
+            .query_cached(
+                "SELECT data, backed_up
+                 FROM inbound_group_session
+                 WHERE sender_key = $1
+                     AND sender_data_type = $2
+                     AND session_id > $3
+                 ORDER BY session_id
+                 LIMIT $4",
+                &[&sender_key, &sender_data_type, &after_session_id, &limit],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn get_inbound_group_sessions_for_backup(&self, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        Ok(self
+            .query_cached(
+                "SELECT data FROM inbound_group_session WHERE backed_up IS FALSE LIMIT $1",
+                &[&limit],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(&self, session_ids: &[Key]) -> Result<()> {
+        if session_ids.is_empty() {
+            // We are not expecting to be called with an empty list of sessions
+            warn!("No sessions to mark as backed up!");
+            return Ok(());
+        }
+
+        self.execute_cached(
+            "UPDATE inbound_group_session SET backed_up = TRUE WHERE session_id = ANY($1)",
+            &[&session_ids],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_inbound_group_session_backup_state(&self) -> Result<()> {
+        self.execute_cached("UPDATE inbound_group_session SET backed_up = FALSE", &[]).await?;
+        Ok(())
+    }
+
+    async fn set_outbound_group_session(&self, room_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO outbound_group_session (room_id, data) VALUES ($1, $2)
+             ON CONFLICT (room_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&room_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_outbound_group_session(&self, room_id: &Key) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM outbound_group_session WHERE room_id = $1",
+                &[&room_id],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn set_device(&self, user_id: &[u8], device_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO device (user_id, device_id, data) VALUES ($1, $2, $3)
+             ON CONFLICT (user_id, device_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&user_id, &device_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_device(&self, user_id: &[u8], device_id: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM device WHERE user_id = $1 AND device_id = $2",
+            &[&user_id, &device_id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_device(&self, user_id: &Key, device_id: &Key) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM device WHERE user_id = $1 AND device_id = $2",
+                &[&user_id, &device_id],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn get_user_devices(&self, user_id: &Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT data FROM device WHERE user_id = $1", &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn set_identity(&self, user_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO identity (user_id, data) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&user_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_user_identity(&self, user_id: &Key) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached("SELECT data FROM identity WHERE user_id = $1", &[&user_id])
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn add_olm_hash(&self, data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO olm_hash (data) VALUES ($1) ON CONFLICT DO NOTHING",
+            &[&data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn has_olm_hash(&self, data: &[u8]) -> Result<bool> {
+        Ok(self
+            .query_one_cached("SELECT EXISTS (SELECT 1 FROM olm_hash WHERE data = $1)", &[&data])
+            .await?
+            .get(0))
+    }
+
+    async fn get_tracked_users(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT data FROM tracked_user", &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn add_tracked_user(&self, user_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO tracked_user (user_id, data) VALUES ($1, $2)
+             ON CONFLICT (user_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&user_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn set_key_request(&self, request_id: &[u8], sent_out: bool, data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO key_requests (request_id, sent_out, data) VALUES ($1, $2, $3)
+             ON CONFLICT (request_id) DO UPDATE
+             SET sent_out = EXCLUDED.sent_out, data = EXCLUDED.data",
+            &[&request_id, &sent_out, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_outgoing_secret_request(
+        &self,
+        request_id: &Key,
+    ) -> Result<Option<(Vec<u8>, bool)>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data, sent_out FROM key_requests WHERE request_id = $1",
+                &[&request_id],
+            )
+            .await?
+            .map(|row| (row.get(0), row.get(1))))
+    }
+
+    async fn get_outgoing_secret_requests(&self) -> Result<Vec<(Vec<u8>, bool)>> {
+        Ok(self
+            .query_cached("SELECT data, sent_out FROM key_requests", &[])
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT data FROM key_requests WHERE sent_out IS FALSE", &[])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn delete_key_request(&self, request_id: &Key) -> Result<()> {
+        self.execute_cached("DELETE FROM key_requests WHERE request_id = $1", &[&request_id])
+            .await?;
+        Ok(())
+    }
+
+    async fn set_secret(&self, secret_name: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO secrets (secret_name, data) VALUES ($1, $2)",
+            &[&secret_name, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_secrets_from_inbox(&self, secret_name: &Key) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT data FROM secrets WHERE secret_name = $1", &[&secret_name])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &Key) -> Result<()> {
+        self.execute_cached("DELETE FROM secrets WHERE secret_name = $1", &[&secret_name]).await?;
+        Ok(())
+    }
+
+    async fn set_direct_withheld(
+        &self,
+        session_id: &[u8],
+        room_id: &[u8],
+        data: &[u8],
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO direct_withheld_info (session_id, room_id, data) VALUES ($1, $2, $3)
+             ON CONFLICT (session_id) DO UPDATE
+             SET room_id = EXCLUDED.room_id, data = EXCLUDED.data",
+            &[&session_id, &room_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_direct_withheld_info(
+        &self,
+        session_id: &Key,
+        room_id: &Key,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM direct_withheld_info WHERE session_id = $1 AND room_id = $2",
+                &[&session_id, &room_id],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn set_room_settings(&self, room_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO room_settings (room_id, data) VALUES ($1, $2)
+             ON CONFLICT (room_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&room_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_room_settings(&self, room_id: &Key) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached("SELECT data FROM room_settings WHERE room_id = $1", &[&room_id])
+            .await?
+            .map(|row| row.get(0)))
+    }
+}
+
+impl<T: PostgresClientExt> PostgresCryptoStoreExt for T {}
+
+#[async_trait]
+impl CryptoStore for PostgresCryptoStore {
+    type Error = Error;
+
+    async fn load_account(&self) -> Result<Option<Account>> {
+        let conn = self.acquire().await?;
+        if let Some(pickle) = conn.get_kv("account").await? {
+            let pickle = self.deserialize_value(&pickle)?;
+
+            let account = Account::from_pickle(pickle).map_err(|_| Error::Unpickle)?;
+
+            *self.static_account.write().unwrap() = Some(account.static_data().clone());
+
+            Ok(Some(account))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn load_identity(&self) -> Result<Option<PrivateCrossSigningIdentity>> {
+        let conn = self.acquire().await?;
+        if let Some(i) = conn.get_kv("identity").await? {
+            let pickle = self.deserialize_value(&i)?;
+            Ok(Some(PrivateCrossSigningIdentity::from_pickle(pickle).map_err(|_| Error::Unpickle)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn save_pending_changes(&self, changes: PendingChanges) -> Result<()> {
+        // Serialize calls to `save_pending_changes`; there are multiple await points
+        // below, and we're pickling data as we go, so we don't want to
+        // invalidate data we've previously read and overwrite it in the store.
+        let _guard = self.save_changes_lock.lock().await;
+
+        if let Some(account) = changes.account {
+            *self.static_account.write().unwrap() = Some(account.static_data().clone());
+            let serialized_account = self.serialize_value(&account.pickle())?;
+            self.acquire().await?.set_kv("account", &serialized_account).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn save_changes(&self, changes: Changes) -> Result<()> {
+        // Serialize calls to `save_changes`; there are multiple await points below, and
+        // we're pickling data as we go, so we don't want to invalidate data
+        // we've previously read and overwrite it in the store.
+        let _guard = self.save_changes_lock.lock().await;
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        if let Some(i) = changes.private_identity {
+            let serialized_private_identity = self.serialize_value(&i.pickle().await)?;
+            txn.set_kv("identity", &serialized_private_identity).await?;
+        }
+
+        if let Some(token) = &changes.next_batch_token {
+            let serialized_token = self.serialize_value(token)?;
+            txn.set_kv("next_batch_token", &serialized_token).await?;
+        }
+
+        if let Some(decryption_key) = &changes.backup_decryption_key {
+            let serialized_decryption_key = self.serialize_value(decryption_key)?;
+            txn.set_kv("recovery_key_v1", &serialized_decryption_key).await?;
+        }
+
+        if let Some(backup_version) = &changes.backup_version {
+            let serialized_backup_version = self.serialize_value(backup_version)?;
+            txn.set_kv("backup_version_v1", &serialized_backup_version).await?;
+        }
+
+        if let Some(pickle_key) = &changes.dehydrated_device_pickle_key {
+            let serialized_pickle_key = self.serialize_value(pickle_key)?;
+            txn.set_kv(DEHYDRATED_DEVICE_PICKLE_KEY, &serialized_pickle_key).await?;
+        }
+
+        for device in changes.devices.new.iter().chain(&changes.devices.changed) {
+            let user_id = self.encode_key("device", device.user_id().as_bytes());
+            let device_id = self.encode_key("device", device.device_id().as_bytes());
+            let data = self.serialize_value(&device)?;
+            txn.set_device(&user_id, &device_id, &data).await?;
+        }
+
+        for device in &changes.devices.deleted {
+            let user_id = self.encode_key("device", device.user_id().as_bytes());
+            let device_id = self.encode_key("device", device.device_id().as_bytes());
+            txn.delete_device(&user_id, &device_id).await?;
+        }
+
+        for identity in changes.identities.changed.iter().chain(&changes.identities.new) {
+            let user_id = self.encode_key("identity", identity.user_id().as_bytes());
+            let data = self.serialize_value(&identity)?;
+            txn.set_identity(&user_id, &data).await?;
+        }
+
+        for session in changes.sessions {
+            let session_id = self.encode_key("session", session.session_id());
+            let sender_key = self.encode_key("session", session.sender_key().to_base64());
+            let serialized_session = self.serialize_value(&session.pickle().await)?;
+            txn.set_session(&session_id, &sender_key, &serialized_session).await?;
+        }
+
+        for session in changes.inbound_group_sessions {
+            let room_id = self.encode_key("inbound_group_session", session.room_id().as_bytes());
+            let session_id = self.encode_key("inbound_group_session", session.session_id());
+            let sender_key =
+                self.encode_key("inbound_group_session", session.sender_key().to_base64());
+            let pickle = session.pickle().await;
+            let serialized_session = self.serialize_value(&pickle)?;
+            txn.set_inbound_group_session(
+                &room_id,
+                &session_id,
+                &serialized_session,
+                pickle.backed_up,
+                Some(&sender_key),
+                Some(pickle.sender_data.to_type() as i16),
+            )
+            .await?;
+        }
+
+        for session in changes.outbound_group_sessions {
+            let room_id = self.encode_key("outbound_group_session", session.room_id().as_bytes());
+            let serialized_session = self.serialize_json(&session.pickle().await)?;
+            txn.set_outbound_group_session(&room_id, &serialized_session).await?;
+        }
+
+        for hash in &changes.message_hashes {
+            let hash = rmp_serde::to_vec(hash)?;
+            txn.add_olm_hash(&hash).await?;
+        }
+
+        for request in changes.key_requests {
+            let request_id = self.encode_key("key_requests", request.request_id.as_bytes());
+            let serialized_request = self.serialize_value(&request)?;
+            txn.set_key_request(&request_id, request.sent_out, &serialized_request).await?;
+        }
+
+        for (room_id, data) in changes.withheld_session_info {
+            for (session_id, event) in data {
+                let session_id = self.encode_key("direct_withheld_info", session_id);
+                let room_id = self.encode_key("direct_withheld_info", &room_id);
+                let serialized_info = self.serialize_json(&event)?;
+                txn.set_direct_withheld(&session_id, &room_id, &serialized_info).await?;
+            }
+        }
+
+        for (room_id, settings) in changes.room_settings {
+            let room_id = self.encode_key("room_settings", room_id.as_bytes());
+            let value = self.serialize_value(&settings)?;
+            txn.set_room_settings(&room_id, &value).await?;
+        }
+
+        for secret in changes.secrets {
+            let secret_name = self.encode_key("secrets", secret.secret_name.to_string());
+            let value = self.serialize_json(&secret)?;
+            txn.set_secret(&secret_name, &value).await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn save_inbound_group_sessions(
+        &self,
+        sessions: Vec<InboundGroupSession>,
+        backed_up_to_version: Option<&str>,
+    ) -> matrix_sdk_crypto::store::Result<(), Self::Error> {
+        // Sanity-check that the data in the sessions corresponds to backed_up_version
+        sessions.iter().for_each(|s| {
+            let backed_up = s.backed_up();
+            if backed_up != backed_up_to_version.is_some() {
+                warn!(
+                    backed_up,
+                    backed_up_to_version,
+                    "Session backed-up flag does not correspond to backup version setting",
+                );
+            }
+        });
+
+        // Currently, this store doesn't save the backup version separately, so this
+        // just delegates to save_changes.
+        self.save_changes(Changes { inbound_group_sessions: sessions, ..Changes::default() }).await
+    }
+
+    async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        let sessions: Vec<_> = self
+            .acquire()
+            .await?
+            .get_sessions_for_sender_key(&self.encode_key("session", sender_key.as_bytes()))
+            .await?
+            .into_iter()
+            .map(|bytes| {
+                let pickle = self.deserialize_value(&bytes)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| Error::AccountUnset)
+            })
+            .collect::<Result<_>>()?;
+
+        if sessions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(sessions))
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<InboundGroupSession>> {
+        let session_id = self.encode_key("inbound_group_session", session_id);
+        let Some((room_id_from_db, value, backed_up)) =
+            self.acquire().await?.get_inbound_group_session(&session_id).await?
+        else {
+            return Ok(None);
+        };
+
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        if *room_id != room_id_from_db {
+            warn!("expected room_id for session_id doesn't match what's in the DB");
+            return Ok(None);
+        }
+
+        Ok(Some(self.deserialize_and_unpickle_inbound_group_session(value, backed_up)?))
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions()
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                self.deserialize_and_unpickle_inbound_group_session(value, backed_up)
+            })
+            .collect()
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error> {
+        let after_session_id =
+            after_session_id.map(|session_id| self.encode_key("inbound_group_session", session_id));
+        let sender_key = self.encode_key("inbound_group_session", sender_key.to_base64());
+
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions_for_device_batch(
+                &sender_key,
+                sender_data_type,
+                after_session_id,
+                limit,
+            )
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                self.deserialize_and_unpickle_inbound_group_session(value, backed_up)
+            })
+            .collect()
+    }
+
+    async fn inbound_group_session_counts(
+        &self,
+        _backup_version: Option<&str>,
+    ) -> Result<RoomKeyCounts> {
+        self.acquire().await?.get_inbound_group_session_counts().await
+    }
+
+    async fn inbound_group_sessions_for_backup(
+        &self,
+        _backup_version: &str,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions_for_backup(limit)
+            .await?
+            .into_iter()
+            .map(|value| self.deserialize_and_unpickle_inbound_group_session(value, false))
+            .collect()
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(
+        &self,
+        _backup_version: &str,
+        session_ids: &[(&RoomId, &str)],
+    ) -> Result<()> {
+        let session_ids: Vec<_> =
+            session_ids.iter().map(|(_, s)| self.encode_key("inbound_group_session", s)).collect();
+
+        self.acquire().await?.mark_inbound_group_sessions_as_backed_up(&session_ids).await
+    }
+
+    async fn reset_backup_state(&self) -> Result<()> {
+        self.acquire().await?.reset_inbound_group_session_backup_state().await
+    }
+
+    async fn load_backup_keys(&self) -> Result<BackupKeys> {
+        let conn = self.acquire().await?;
+
+        let backup_version = conn
+            .get_kv("backup_version_v1")
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()?;
+
+        let decryption_key = conn
+            .get_kv("recovery_key_v1")
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()?;
+
+        Ok(BackupKeys { backup_version, decryption_key })
+    }
+
+    async fn load_dehydrated_device_pickle_key(&self) -> Result<Option<DehydratedDeviceKey>> {
+        let conn = self.acquire().await?;
+
+        conn.get_kv(DEHYDRATED_DEVICE_PICKLE_KEY)
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn delete_dehydrated_device_pickle_key(&self) -> Result<(), Self::Error> {
+        let conn = self.acquire().await?;
+        conn.clear_kv(DEHYDRATED_DEVICE_PICKLE_KEY).await?;
+
+        Ok(())
+    }
+
+    async fn get_outbound_group_session(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<OutboundGroupSession>> {
+        let room_id = self.encode_key("outbound_group_session", room_id.as_bytes());
+        let Some(value) = self.acquire().await?.get_outbound_group_session(&room_id).await? else {
+            return Ok(None);
+        };
+
+        let account_info = self.get_static_account().ok_or(Error::AccountUnset)?;
+
+        let pickle = self.deserialize_json(&value)?;
+        let session = OutboundGroupSession::from_pickle(
+            account_info.device_id,
+            account_info.identity_keys,
+            pickle,
+        )
+        .map_err(|_| Error::Unpickle)?;
+
+        Ok(Some(session))
+    }
+
+    async fn load_tracked_users(&self) -> Result<Vec<TrackedUser>> {
+        self.acquire()
+            .await?
+            .get_tracked_users()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value(value))
+            .collect()
+    }
+
+    async fn save_tracked_users(&self, tracked_users: &[(&UserId, bool)]) -> Result<()> {
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        for (user_id, dirty) in tracked_users {
+            let data =
+                self.serialize_value(&TrackedUser { user_id: (*user_id).into(), dirty: *dirty })?;
+            let user_id = self.encode_key("tracked_users", user_id.as_bytes());
+            txn.add_tracked_user(&user_id, &data).await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_device(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<DeviceData>> {
+        let user_id = self.encode_key("device", user_id.as_bytes());
+        let device_id = self.encode_key("device", device_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_device(&user_id, &device_id)
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn get_user_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<OwnedDeviceId, DeviceData>> {
+        let user_id = self.encode_key("device", user_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_user_devices(&user_id)
+            .await?
+            .into_iter()
+            .map(|value| {
+                let device: DeviceData = self.deserialize_value(&value)?;
+                Ok((device.device_id().to_owned(), device))
+            })
+            .collect()
+    }
+
+    async fn get_own_device(&self) -> Result<DeviceData> {
+        let account_info = self.get_static_account().ok_or(Error::AccountUnset)?;
+
+        Ok(self
+            .get_device(&account_info.user_id, &account_info.device_id)
+            .await?
+            .expect("We should be able to find our own device."))
+    }
+
+    async fn get_user_identity(&self, user_id: &UserId) -> Result<Option<UserIdentityData>> {
+        let user_id = self.encode_key("identity", user_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_user_identity(&user_id)
+            .await?
+            .map(|value| self.deserialize_value(&value))
+            .transpose()
+    }
+
+    async fn is_message_known(
+        &self,
+        message_hash: &matrix_sdk_crypto::olm::OlmMessageHash,
+    ) -> Result<bool> {
+        let value = rmp_serde::to_vec(message_hash)?;
+        self.acquire().await?.has_olm_hash(&value).await
+    }
+
+    async fn get_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<Option<GossipRequest>> {
+        let request_id = self.encode_key("key_requests", request_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_outgoing_secret_request(&request_id)
+            .await?
+            .map(|(value, sent_out)| self.deserialize_key_request(&value, sent_out))
+            .transpose()
+    }
+
+    async fn get_secret_request_by_info(
+        &self,
+        key_info: &SecretInfo,
+    ) -> Result<Option<GossipRequest>> {
+        let requests = self.acquire().await?.get_outgoing_secret_requests().await?;
+        for (request, sent_out) in requests {
+            let request = self.deserialize_key_request(&request, sent_out)?;
+            if request.info == *key_info {
+                return Ok(Some(request));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.acquire()
+            .await?
+            .get_unsent_secret_requests()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_key_request(value, false))
+            .collect()
+    }
+
+    async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
+        let request_id = self.encode_key("key_requests", request_id.as_bytes());
+        self.acquire().await?.delete_key_request(&request_id).await
+    }
+
+    async fn get_secrets_from_inbox(
+        &self,
+        secret_name: &SecretName,
+    ) -> Result<Vec<GossippedSecret>> {
+        let secret_name = self.encode_key("secrets", secret_name.to_string());
+
+        self.acquire()
+            .await?
+            .get_secrets_from_inbox(&secret_name)
+            .await?
+            .into_iter()
+            .map(|value| self.deserialize_json(value.as_ref()))
+            .collect()
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<()> {
+        let secret_name = self.encode_key("secrets", secret_name.to_string());
+        self.acquire().await?.delete_secrets_from_inbox(&secret_name).await
+    }
+
+    async fn get_withheld_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<RoomKeyWithheldEvent>> {
+        let room_id = self.encode_key("direct_withheld_info", room_id);
+        let session_id = self.encode_key("direct_withheld_info", session_id);
+
+        self.acquire()
+            .await?
+            .get_direct_withheld_info(&session_id, &room_id)
+            .await?
+            .map(|value| self.deserialize_json::<RoomKeyWithheldEvent>(&value))
+            .transpose()
+    }
+
+    async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
+        let room_id = self.encode_key("room_settings", room_id.as_bytes());
+        let Some(value) = self.acquire().await?.get_room_settings(&room_id).await? else {
+            return Ok(None);
+        };
+
+        let settings = self.deserialize_value(&value)?;
+
+        Ok(Some(settings))
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(serialized) = self.acquire().await?.get_kv(key).await? else {
+            return Ok(None);
+        };
+        let value = if let Some(cipher) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(&serialized)?;
+            cipher.decrypt_value_data(encrypted)?
+        } else {
+            serialized
+        };
+
+        Ok(Some(value))
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let serialized = if let Some(cipher) = &self.store_cipher {
+            let encrypted = cipher.encrypt_value_data(value)?;
+            rmp_serde::to_vec_named(&encrypted)?
+        } else {
+            value
+        };
+
+        self.acquire().await?.set_kv(key, &serialized).await?;
+        Ok(())
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        self.acquire().await?.clear_kv(key).await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let _guard = self.save_changes_lock.lock().await;
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // The version of the schema and the store cipher must survive, and the leased
+        // locks are kept.
+        txn.batch_execute(
+            "DELETE FROM session;
+             DELETE FROM inbound_group_session;
+             DELETE FROM outbound_group_session;
+             DELETE FROM device;
+             DELETE FROM identity;
+             DELETE FROM tracked_user;
+             DELETE FROM olm_hash;
+             DELETE FROM key_requests;
+             DELETE FROM room_settings;
+             DELETE FROM direct_withheld_info;
+             DELETE FROM secrets;
+             DELETE FROM kv WHERE key NOT IN ('version', 'cipher');",
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        *self.static_account.write().unwrap() = None;
+
+        Ok(())
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = (now + lease_duration_ms as u64) as i64;
+        let now = now as i64;
+
+        let num_touched = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "INSERT INTO lease_locks (key, holder, expiration)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (key) DO UPDATE
+                 SET holder = EXCLUDED.holder, expiration = EXCLUDED.expiration
+                 WHERE lease_locks.holder = EXCLUDED.holder
+                 OR lease_locks.expiration < $4",
+                &[&key, &holder, &expiration, &now],
+            )
+            .await?;
+
+        Ok(num_touched == 1)
+    }
+
+    async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
+        let conn = self.acquire().await?;
+        if let Some(token) = conn.get_kv("next_batch_token").await? {
+            let maybe_token: Option<String> = self.deserialize_value(&token)?;
+            Ok(maybe_token)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod tests {
+    use matrix_sdk_crypto::{cryptostore_integration_tests, cryptostore_integration_tests_time};
+    use matrix_sdk_test::async_test;
+
+    use super::PostgresCryptoStore;
+    use crate::utils::test_utils::{reopen_test_config, test_config};
+
+    #[async_test]
+    async fn test_pool_size() {
+        let config = test_config("c_test_pool_size").await.pool_max_size(42);
+
+        let store = PostgresCryptoStore::open_with_config(config).await.unwrap();
+
+        assert_eq!(store.pool.status().max_size, 42);
+    }
+
+    async fn get_store(
+        name: &str,
+        passphrase: Option<&str>,
+        clear_data: bool,
+    ) -> PostgresCryptoStore {
+        // The names of the tests are valid namespaces.
+        let namespace = format!("c_{name}");
+
+        let config =
+            if clear_data { test_config(&namespace).await } else { reopen_test_config(&namespace) };
+
+        PostgresCryptoStore::open_with_config(config.passphrase(passphrase))
+            .await
+            .expect("Can't create a passphrase protected store")
+    }
+
+    cryptostore_integration_tests!();
+    cryptostore_integration_tests_time!();
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod encrypted_tests {
+    use matrix_sdk_crypto::{cryptostore_integration_tests, cryptostore_integration_tests_time};
+
+    use super::PostgresCryptoStore;
+    use crate::utils::test_utils::{reopen_test_config, test_config};
+
+    async fn get_store(
+        name: &str,
+        passphrase: Option<&str>,
+        clear_data: bool,
+    ) -> PostgresCryptoStore {
+        let namespace = format!("ce_{name}");
+        let pass = passphrase.unwrap_or("default_test_password");
+
+        let config =
+            if clear_data { test_config(&namespace).await } else { reopen_test_config(&namespace) };
+
+        PostgresCryptoStore::open_with_config(config.passphrase(Some(pass)))
+            .await
+            .expect("Can't create a passphrase protected store")
+    }
+
+    cryptostore_integration_tests!();
+    cryptostore_integration_tests_time!();
+}
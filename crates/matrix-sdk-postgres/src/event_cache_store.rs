@@ -0,0 +1,1475 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A PostgreSQL-based backend for the [`EventCacheStore`].
+
+use std::{borrow::Cow, fmt, sync::Arc};
+
+use async_trait::async_trait;
+use deadpool_postgres::{GenericClient, Object as PostgresConn, Pool, Transaction};
+use matrix_sdk_base::{
+    deserialized_responses::TimelineEvent,
+    event_cache::{
+        store::{
+            compute_filters_string, extract_event_relation,
+            media::{
+                EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage,
+                MediaRetentionPolicy, MediaService,
+            },
+            EventCacheStore, RetainedContent,
+        },
+        Event, Gap,
+    },
+    linked_chunk::{
+        ChunkContent, ChunkIdentifier, ChunkIdentifierGenerator, Position, RawChunk, Update,
+    },
+    media::{MediaRequestParameters, UniqueKey},
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{
+    events::relation::RelationType, time::SystemTime, EventId, MilliSecondsSinceUnixEpoch, MxcUri,
+    OwnedEventId, OwnedRoomId, RoomId,
+};
+use tracing::{debug, error, trace};
+
+use crate::{
+    error::{Error, Result},
+    utils::{
+        lock_and_create_schema, time_to_timestamp, Key, PostgresClientExt, PostgresKeyValueStoreExt,
+    },
+    OpenStoreError, PostgresStoreConfig,
+};
+
+mod keys {
+    // Entries in Key-value store
+    pub const MEDIA_RETENTION_POLICY: &str = "media_retention_policy";
+    pub const LAST_MEDIA_CLEANUP_TIME: &str = "last_media_cleanup_time";
+
+    // Tables
+    pub const LINKED_CHUNKS: &str = "linked_chunks";
+    pub const MEDIA: &str = "media";
+}
+
+/// The name of the store, used as the suffix of its schema.
+const STORE_NAME: &str = "event_cache";
+
+/// Identifier of the latest database version.
+///
+/// This is used to figure whether the database requires a migration.
+/// Every new SQL migration should imply a bump of this number, and changes in
+/// the [`run_migrations`] function.
+const DATABASE_VERSION: u8 = 2;
+
+/// The string used to identify a chunk of type events, in the `type` field in
+/// the database.
+const CHUNK_TYPE_EVENT_TYPE_STRING: &str = "E";
+/// The string used to identify a chunk of type gap, in the `type` field in the
+/// database.
+const CHUNK_TYPE_GAP_TYPE_STRING: &str = "G";
+
+/// A PostgreSQL-based event cache store.
+#[derive(Clone)]
+pub struct PostgresEventCacheStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    pool: Pool,
+    media_service: MediaService,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for PostgresEventCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresEventCacheStore").finish_non_exhaustive()
+    }
+}
+
+impl PostgresEventCacheStore {
+    /// Open the PostgreSQL-based event cache store in the database at the
+    /// given connection string, using the given passphrase to encrypt private
+    /// data.
+    pub async fn open(url: &str, passphrase: Option<&str>) -> Result<Self, OpenStoreError> {
+        Self::open_with_config(PostgresStoreConfig::from_url(url)?.passphrase(passphrase)).await
+    }
+
+    /// Open the PostgreSQL-based event cache store with the given config.
+    pub async fn open_with_config(config: PostgresStoreConfig) -> Result<Self, OpenStoreError> {
+        let schema = config.schema(STORE_NAME)?;
+        let pool = config.create_pool(&schema)?;
+
+        let mut conn = pool.get().await?;
+        let txn = conn.transaction().await?;
+
+        let version = lock_and_create_schema(&txn, &schema).await?;
+        run_migrations(&txn, version).await?;
+
+        let store_cipher = match config.passphrase.as_deref() {
+            Some(p) => Some(Arc::new(txn.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        let media_service = MediaService::new();
+        let media_retention_policy = txn.get_serialized_kv(keys::MEDIA_RETENTION_POLICY).await?;
+        let last_media_cleanup_time = txn.get_serialized_kv(keys::LAST_MEDIA_CLEANUP_TIME).await?;
+        media_service.restore(media_retention_policy, last_media_cleanup_time);
+
+        txn.commit().await?;
+        drop(conn);
+
+        Ok(Self { store_cipher, pool, media_service })
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    async fn acquire(&self) -> Result<PostgresConn> {
+        Ok(self.pool.get().await?)
+    }
+
+    fn encode_event(&self, event: &TimelineEvent) -> Result<EncodedEvent> {
+        let serialized = serde_json::to_vec(event)?;
+
+        // Extract the relationship info here.
+        let raw_event = event.raw();
+        let (relates_to, rel_type) = extract_event_relation(raw_event).unzip();
+
+        // The content may be encrypted.
+        let content = self.encode_value(serialized)?;
+
+        Ok(EncodedEvent {
+            content,
+            rel_type,
+            relates_to: relates_to.map(|relates_to| relates_to.to_string()),
+        })
+    }
+
+    fn decode_event(&self, content: &[u8]) -> Result<Event> {
+        Ok(serde_json::from_slice(&self.decode_value(content)?)?)
+    }
+
+    async fn rebuild_chunk(
+        &self,
+        txn: &Transaction<'_>,
+        room_id: &Key,
+        previous: Option<i64>,
+        id: i64,
+        next: Option<i64>,
+        chunk_type: &str,
+    ) -> Result<RawChunk<Event, Gap>> {
+        let previous = previous.map(|previous| ChunkIdentifier::new(previous as u64));
+        let next = next.map(|next| ChunkIdentifier::new(next as u64));
+        let id = ChunkIdentifier::new(id as u64);
+
+        match chunk_type {
+            CHUNK_TYPE_GAP_TYPE_STRING => {
+                // It's a gap!
+                let gap = self.load_gap_content(txn, room_id, id).await?;
+                Ok(RawChunk { content: ChunkContent::Gap(gap), previous, identifier: id, next })
+            }
+
+            CHUNK_TYPE_EVENT_TYPE_STRING => {
+                // It's events!
+                let events = self.load_events_content(txn, room_id, id).await?;
+                Ok(RawChunk {
+                    content: ChunkContent::Items(events),
+                    previous,
+                    identifier: id,
+                    next,
+                })
+            }
+
+            other => {
+                // It's an error!
+                Err(Error::InvalidData {
+                    details: format!("a linked chunk has an unknown type {other}"),
+                })
+            }
+        }
+    }
+
+    async fn load_gap_content(
+        &self,
+        txn: &Transaction<'_>,
+        room_id: &Key,
+        chunk_id: ChunkIdentifier,
+    ) -> Result<Gap> {
+        // There's at most one row for it in the database, so a call to `query_one` is
+        // sufficient.
+        let row = txn
+            .query_one_cached(
+                "SELECT prev_token FROM gap_chunks WHERE chunk_id = $1 AND room_id = $2",
+                &[&(chunk_id.index() as i64), &room_id],
+            )
+            .await?;
+        let prev_token_bytes = self.decode_value(row.get(0))?;
+        let prev_token = serde_json::from_slice(&prev_token_bytes)?;
+        Ok(Gap { prev_token })
+    }
+
+    async fn load_events_content(
+        &self,
+        txn: &Transaction<'_>,
+        room_id: &Key,
+        chunk_id: ChunkIdentifier,
+    ) -> Result<Vec<Event>> {
+        txn.query_cached(
+            "SELECT events.content
+             FROM event_chunks ec
+             INNER JOIN events USING (event_id, room_id)
+             WHERE ec.chunk_id = $1 AND ec.room_id = $2
+             ORDER BY ec.position ASC",
+            &[&(chunk_id.index() as i64), &room_id],
+        )
+        .await?
+        .iter()
+        .map(|row| self.decode_event(row.get(0)))
+        .collect()
+    }
+}
+
+struct EncodedEvent {
+    content: Vec<u8>,
+    rel_type: Option<String>,
+    relates_to: Option<String>,
+}
+
+/// Run the database migrations from the given version.
+async fn run_migrations(txn: &Transaction<'_>, version: u8) -> Result<()> {
+    if version >= DATABASE_VERSION {
+        return Ok(());
+    }
+
+    debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+
+    if version < 1 {
+        txn.batch_execute(include_str!("../migrations/event_cache_store/001_init.sql")).await?;
+        txn.set_db_version(1).await?;
+    }
+
+    if version < 2 {
+        txn.batch_execute(include_str!("../migrations/event_cache_store/002_retained_content.sql"))
+            .await?;
+        txn.set_db_version(2).await?;
+    }
+
+    Ok(())
+}
+
+/// Insert or replace the content of an event.
+///
+/// The event might have been already inserted in the database. This is the
+/// case when an event is deduplicated and moved to another position; or because
+/// it was inserted outside the context of a linked chunk (e.g. pinned event).
+async fn upsert_event(
+    client: &impl GenericClient,
+    room_id: &Key,
+    event_id: &str,
+    encoded_event: EncodedEvent,
+) -> Result<()> {
+    client
+        .execute_cached(
+            "INSERT INTO events (room_id, event_id, content, relates_to, rel_type)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (room_id, event_id) DO UPDATE
+             SET content = EXCLUDED.content, relates_to = EXCLUDED.relates_to,
+                 rel_type = EXCLUDED.rel_type",
+            &[
+                &room_id,
+                &event_id,
+                &encoded_event.content,
+                &encoded_event.relates_to,
+                &encoded_event.rel_type,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn insert_chunk(
+    txn: &Transaction<'_>,
+    room_id: &Key,
+    previous: Option<i64>,
+    new: i64,
+    next: Option<i64>,
+    type_str: &str,
+) -> Result<()> {
+    // First, insert the new chunk.
+    txn.execute_cached(
+        "INSERT INTO linked_chunks (id, room_id, previous, next, type)
+         VALUES ($1, $2, $3, $4, $5)",
+        &[&new, &room_id, &previous, &next, &type_str],
+    )
+    .await?;
+
+    // If this chunk has a previous one, update its `next` field.
+    if let Some(previous) = previous {
+        txn.execute_cached(
+            "UPDATE linked_chunks SET next = $1 WHERE id = $2 AND room_id = $3",
+            &[&new, &previous, &room_id],
+        )
+        .await?;
+    }
+
+    // If this chunk has a next one, update its `previous` field.
+    if let Some(next) = next {
+        txn.execute_cached(
+            "UPDATE linked_chunks SET previous = $1 WHERE id = $2 AND room_id = $3",
+            &[&new, &next, &room_id],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl EventCacheStore for PostgresEventCacheStore {
+    type Error = Error;
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = (now + lease_duration_ms as u64) as i64;
+        let now = now as i64;
+
+        let num_touched = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "INSERT INTO lease_locks (key, holder, expiration)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (key) DO UPDATE
+                 SET holder = EXCLUDED.holder, expiration = EXCLUDED.expiration
+                 WHERE lease_locks.holder = EXCLUDED.holder
+                 OR lease_locks.expiration < $4",
+                &[&key, &holder, &expiration, &now],
+            )
+            .await?;
+
+        Ok(num_touched == 1)
+    }
+
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<Update<Event, Gap>>,
+    ) -> Result<(), Self::Error> {
+        // Use a single transaction throughout this function, so that either all updates
+        // work, or none is taken into account.
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        for up in updates {
+            match up {
+                Update::NewItemsChunk { previous, new, next } => {
+                    let previous = previous.as_ref().map(|id| id.index() as i64);
+                    let new = new.index() as i64;
+                    let next = next.as_ref().map(|id| id.index() as i64);
+
+                    trace!(
+                        %room_id,
+                        "new events chunk (prev={previous:?}, i={new}, next={next:?})",
+                    );
+
+                    insert_chunk(
+                        &txn,
+                        &hashed_room_id,
+                        previous,
+                        new,
+                        next,
+                        CHUNK_TYPE_EVENT_TYPE_STRING,
+                    )
+                    .await?;
+                }
+
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    let serialized = serde_json::to_vec(&gap.prev_token)?;
+                    let prev_token = self.encode_value(serialized)?;
+
+                    let previous = previous.as_ref().map(|id| id.index() as i64);
+                    let new = new.index() as i64;
+                    let next = next.as_ref().map(|id| id.index() as i64);
+
+                    trace!(
+                        %room_id,
+                        "new gap chunk (prev={previous:?}, i={new}, next={next:?})",
+                    );
+
+                    // Insert the chunk as a gap.
+                    insert_chunk(
+                        &txn,
+                        &hashed_room_id,
+                        previous,
+                        new,
+                        next,
+                        CHUNK_TYPE_GAP_TYPE_STRING,
+                    )
+                    .await?;
+
+                    // Insert the gap's value.
+                    txn.execute_cached(
+                        "INSERT INTO gap_chunks (chunk_id, room_id, prev_token) VALUES ($1, $2, $3)",
+                        &[&new, &hashed_room_id, &prev_token],
+                    )
+                    .await?;
+                }
+
+                Update::RemoveChunk(chunk_identifier) => {
+                    let chunk_id = chunk_identifier.index() as i64;
+
+                    trace!(%room_id, "removing chunk @ {chunk_id}");
+
+                    // Find chunk to delete.
+                    let row = txn
+                        .query_one_cached(
+                            "SELECT previous, next FROM linked_chunks WHERE id = $1 AND room_id = $2",
+                            &[&chunk_id, &hashed_room_id],
+                        )
+                        .await?;
+                    let previous: Option<i64> = row.get(0);
+                    let next: Option<i64> = row.get(1);
+
+                    // Replace its previous' next to its own next.
+                    if let Some(previous) = previous {
+                        txn.execute_cached(
+                            "UPDATE linked_chunks SET next = $1 WHERE id = $2 AND room_id = $3",
+                            &[&next, &previous, &hashed_room_id],
+                        )
+                        .await?;
+                    }
+
+                    // Replace its next' previous to its own previous.
+                    if let Some(next) = next {
+                        txn.execute_cached(
+                            "UPDATE linked_chunks SET previous = $1 WHERE id = $2 AND room_id = $3",
+                            &[&previous, &next, &hashed_room_id],
+                        )
+                        .await?;
+                    }
+
+                    // Now delete it, and let cascading delete corresponding entries in the
+                    // other data tables.
+                    txn.execute_cached(
+                        "DELETE FROM linked_chunks WHERE id = $1 AND room_id = $2",
+                        &[&chunk_id, &hashed_room_id],
+                    )
+                    .await?;
+                }
+
+                Update::PushItems { at, items } => {
+                    if items.is_empty() {
+                        // Should never happens, but better be safe.
+                        continue;
+                    }
+
+                    let chunk_id = at.chunk_identifier().index() as i64;
+
+                    trace!(%room_id, "pushing {} items @ {chunk_id}", items.len());
+
+                    let events = items.into_iter().filter_map(|event| {
+                        let Some(event_id) = event.event_id() else {
+                            error!(%room_id, "Trying to push an event with no ID");
+                            return None;
+                        };
+
+                        Some((event_id.to_string(), event))
+                    });
+
+                    for (i, (event_id, event)) in events.enumerate() {
+                        // Insert the location information into the database.
+                        let index = (at.index() + i) as i64;
+                        txn.execute_cached(
+                            "INSERT INTO event_chunks (chunk_id, room_id, event_id, position)
+                             VALUES ($1, $2, $3, $4)",
+                            &[&chunk_id, &hashed_room_id, &event_id, &index],
+                        )
+                        .await?;
+
+                        // Now, insert the event content into the database.
+                        let encoded_event = self.encode_event(&event)?;
+                        upsert_event(&txn, &hashed_room_id, &event_id, encoded_event).await?;
+                    }
+                }
+
+                Update::ReplaceItem { at, item: event } => {
+                    let chunk_id = at.chunk_identifier().index() as i64;
+                    let index = at.index() as i64;
+
+                    trace!(%room_id, "replacing item @ {chunk_id}:{index}");
+
+                    // The event id should be the same, but just in case it changed…
+                    let Some(event_id) = event.event_id().map(|event_id| event_id.to_string())
+                    else {
+                        error!(%room_id, "Trying to replace an event with a new one that has no ID");
+                        continue;
+                    };
+
+                    // Replace the event's content. Really we'd like to update, but in case the
+                    // event id changed, we are a bit lenient here and will allow an insertion
+                    // of the new event.
+                    let encoded_event = self.encode_event(&event)?;
+                    upsert_event(&txn, &hashed_room_id, &event_id, encoded_event).await?;
+
+                    // Replace the event id in the linked chunk, in case it changed.
+                    txn.execute_cached(
+                        "UPDATE event_chunks SET event_id = $1
+                         WHERE room_id = $2 AND chunk_id = $3 AND position = $4",
+                        &[&event_id, &hashed_room_id, &chunk_id, &index],
+                    )
+                    .await?;
+                }
+
+                Update::RemoveItem { at } => {
+                    let chunk_id = at.chunk_identifier().index() as i64;
+                    let index = at.index() as i64;
+
+                    trace!(%room_id, "removing item @ {chunk_id}:{index}");
+
+                    // Remove the entry in the chunk table.
+                    txn.execute_cached(
+                        "DELETE FROM event_chunks
+                         WHERE room_id = $1 AND chunk_id = $2 AND position = $3",
+                        &[&hashed_room_id, &chunk_id, &index],
+                    )
+                    .await?;
+
+                    // Decrement the index of each item after the one we're going to remove.
+                    txn.execute_cached(
+                        "UPDATE event_chunks SET position = position - 1
+                         WHERE room_id = $1 AND chunk_id = $2 AND position > $3",
+                        &[&hashed_room_id, &chunk_id, &index],
+                    )
+                    .await?;
+                }
+
+                Update::DetachLastItems { at } => {
+                    let chunk_id = at.chunk_identifier().index() as i64;
+                    let index = at.index() as i64;
+
+                    trace!(%room_id, "truncating items >= {chunk_id}:{index}");
+
+                    // Remove these entries.
+                    txn.execute_cached(
+                        "DELETE FROM event_chunks
+                         WHERE room_id = $1 AND chunk_id = $2 AND position >= $3",
+                        &[&hashed_room_id, &chunk_id, &index],
+                    )
+                    .await?;
+                }
+
+                Update::Clear => {
+                    trace!(%room_id, "clearing items");
+
+                    // Remove chunks, and let cascading do its job.
+                    txn.execute_cached(
+                        "DELETE FROM linked_chunks WHERE room_id = $1",
+                        &[&hashed_room_id],
+                    )
+                    .await?;
+                }
+
+                Update::StartReattachItems | Update::EndReattachItems => {
+                    // Nothing.
+                }
+            }
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn load_all_chunks(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RawChunk<Event, Gap>>, Self::Error> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // Use `ORDER BY id` to get a deterministic ordering for testing purposes.
+        let rows = txn
+            .query_cached(
+                "SELECT id, previous, next, type FROM linked_chunks WHERE room_id = $1 ORDER BY id",
+                &[&hashed_room_id],
+            )
+            .await?;
+
+        let mut items = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            let chunk = self
+                .rebuild_chunk(
+                    &txn,
+                    &hashed_room_id,
+                    row.get(1),
+                    row.get(0),
+                    row.get(2),
+                    row.get(3),
+                )
+                .await?;
+            items.push(chunk);
+        }
+
+        txn.commit().await?;
+
+        Ok(items)
+    }
+
+    async fn load_last_chunk(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<(Option<RawChunk<Event, Gap>>, ChunkIdentifierGenerator), Self::Error> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // Find the latest chunk identifier to generate a `ChunkIdentifierGenerator`,
+        // and count the number of chunks.
+        let row = txn
+            .query_one_cached(
+                "SELECT MAX(id), COUNT(*) FROM linked_chunks WHERE room_id = $1",
+                &[&hashed_room_id],
+            )
+            .await?;
+        // `MAX(id)` is `NULL` if there are no chunks.
+        let last_chunk_identifier: Option<i64> = row.get(0);
+        let number_of_chunks: i64 = row.get(1);
+
+        let chunk_identifier_generator = match last_chunk_identifier {
+            Some(last_chunk_identifier) => {
+                ChunkIdentifierGenerator::new_from_previous_chunk_identifier(ChunkIdentifier::new(
+                    last_chunk_identifier as u64,
+                ))
+            }
+            None => ChunkIdentifierGenerator::new_from_scratch(),
+        };
+
+        // Find the last chunk.
+        let Some(row) = txn
+            .query_opt_cached(
+                "SELECT id, previous, type FROM linked_chunks WHERE room_id = $1 AND next IS NULL",
+                &[&hashed_room_id],
+            )
+            .await?
+        else {
+            // Chunk is not found and there are zero chunks for this room, this is
+            // consistent, all good.
+            if number_of_chunks == 0 {
+                return Ok((None, chunk_identifier_generator));
+            }
+
+            // Chunk is not found **but** there are chunks for this room, this is
+            // inconsistent. The linked chunk is malformed.
+            //
+            // Returning `Ok((None, _))` would be invalid here: we must return an error.
+            return Err(Error::InvalidData {
+                details:
+                    "last chunk is not found but chunks exist: the linked chunk contains a cycle"
+                        .to_owned(),
+            });
+        };
+
+        // Build the chunk.
+        let last_chunk = self
+            .rebuild_chunk(&txn, &hashed_room_id, row.get(1), row.get(0), None, row.get(2))
+            .await?;
+
+        txn.commit().await?;
+
+        Ok((Some(last_chunk), chunk_identifier_generator))
+    }
+
+    async fn load_previous_chunk(
+        &self,
+        room_id: &RoomId,
+        before_chunk_identifier: ChunkIdentifier,
+    ) -> Result<Option<RawChunk<Event, Gap>>, Self::Error> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // Find the chunk before the chunk identified by `before_chunk_identifier`.
+        let Some(row) = txn
+            .query_opt_cached(
+                "SELECT id, previous, next, type FROM linked_chunks
+                 WHERE room_id = $1 AND next = $2",
+                &[&hashed_room_id, &(before_chunk_identifier.index() as i64)],
+            )
+            .await?
+        else {
+            // Chunk is not found.
+            return Ok(None);
+        };
+
+        // Build the chunk.
+        let previous_chunk = self
+            .rebuild_chunk(&txn, &hashed_room_id, row.get(1), row.get(0), row.get(2), row.get(3))
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(Some(previous_chunk))
+    }
+
+    async fn clear_all_rooms_chunks(&self) -> Result<(), Self::Error> {
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // Remove all the chunks of the rooms that aren't retained, and let cascading do
+        // its job.
+        txn.execute_cached(
+            "DELETE FROM linked_chunks WHERE room_id NOT IN (SELECT room_id FROM retained_rooms)",
+            &[],
+        )
+        .await?;
+        // Also clear the contents of all the events that aren't retained.
+        txn.execute_cached(
+            "DELETE FROM events
+             WHERE room_id NOT IN (SELECT room_id FROM retained_rooms)
+             AND NOT EXISTS (
+                 SELECT 1 FROM retained_events re
+                 WHERE re.room_id = events.room_id AND re.event_id = events.event_id
+             )",
+            &[],
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn filter_duplicated_events(
+        &self,
+        room_id: &RoomId,
+        events: Vec<OwnedEventId>,
+    ) -> Result<Vec<(OwnedEventId, Position)>, Self::Error> {
+        // If there's no events for which we want to check duplicates, we can return
+        // early.
+        if events.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Select all events that exist in the store, i.e. the duplicates.
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let events: Vec<_> = events.iter().map(|event_id| event_id.as_str()).collect();
+
+        let rows = self
+            .acquire()
+            .await?
+            .query_cached(
+                "SELECT event_id, chunk_id, position
+                 FROM event_chunks
+                 WHERE room_id = $1 AND event_id = ANY($2)
+                 ORDER BY chunk_id ASC, position ASC",
+                &[&hashed_room_id, &events],
+            )
+            .await?;
+
+        let mut duplicated_events = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            let duplicated_event: String = row.get(0);
+            let chunk_identifier: i64 = row.get(1);
+            let index: i64 = row.get(2);
+
+            let Ok(duplicated_event) = EventId::parse(&duplicated_event) else {
+                // Normally unreachable, but the event ID has been stored even if it is
+                // malformed, let's skip it.
+                error!(%duplicated_event, %room_id, "Reading an malformed event ID");
+                continue;
+            };
+
+            duplicated_events.push((
+                duplicated_event,
+                Position::new(ChunkIdentifier::new(chunk_identifier as u64), index as usize),
+            ));
+        }
+
+        Ok(duplicated_events)
+    }
+
+    async fn find_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<Event>, Self::Error> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+
+        self.acquire()
+            .await?
+            .query_opt_cached(
+                "SELECT content FROM events WHERE event_id = $1 AND room_id = $2",
+                &[&event_id.as_str(), &hashed_room_id],
+            )
+            .await?
+            .map(|row| self.decode_event(row.get(0)))
+            .transpose()
+    }
+
+    async fn find_event_relations(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        filters: Option<&[RelationType]>,
+    ) -> Result<Vec<Event>, Self::Error> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let conn = self.acquire().await?;
+
+        let rows = if let Some(filters) = compute_filters_string(filters) {
+            conn.query_cached(
+                "SELECT content FROM events
+                 WHERE relates_to = $1 AND room_id = $2 AND rel_type = ANY($3)",
+                &[&event_id.as_str(), &hashed_room_id, &filters],
+            )
+            .await?
+        } else {
+            conn.query_cached(
+                "SELECT content FROM events WHERE relates_to = $1 AND room_id = $2",
+                &[&event_id.as_str(), &hashed_room_id],
+            )
+            .await?
+        };
+
+        rows.iter().map(|row| self.decode_event(row.get(0))).collect()
+    }
+
+    async fn save_event(&self, room_id: &RoomId, event: Event) -> Result<(), Self::Error> {
+        let Some(event_id) = event.event_id() else {
+            error!(%room_id, "Trying to save an event with no ID");
+            return Ok(());
+        };
+
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let encoded_event = self.encode_event(&event)?;
+
+        let conn = self.acquire().await?;
+        upsert_event(&conn, &hashed_room_id, event_id.as_str(), encoded_event).await
+    }
+
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<()> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let conn = self.acquire().await?;
+
+        if retained {
+            let room_id_value = self.encode_value(room_id.as_bytes().to_owned())?;
+            conn.execute_cached(
+                "INSERT INTO retained_rooms (room_id, room_id_value) VALUES ($1, $2)
+                 ON CONFLICT (room_id) DO UPDATE SET room_id_value = EXCLUDED.room_id_value",
+                &[&hashed_room_id, &room_id_value],
+            )
+            .await?;
+        } else {
+            conn.execute_cached(
+                "DELETE FROM retained_rooms WHERE room_id = $1",
+                &[&hashed_room_id],
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<()> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let conn = self.acquire().await?;
+
+        if retained {
+            let room_id_value = self.encode_value(room_id.as_bytes().to_owned())?;
+            conn.execute_cached(
+                "INSERT INTO retained_events (room_id, room_id_value, event_id) VALUES ($1, $2, $3)
+                 ON CONFLICT (room_id, event_id) DO UPDATE SET room_id_value = EXCLUDED.room_id_value",
+                &[&hashed_room_id, &room_id_value, &event_id.as_str()],
+            )
+            .await?;
+        } else {
+            conn.execute_cached(
+                "DELETE FROM retained_events WHERE room_id = $1 AND event_id = $2",
+                &[&hashed_room_id, &event_id.as_str()],
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn retained_content(&self) -> Result<RetainedContent> {
+        let decode_room_id = |value: &[u8]| -> Result<OwnedRoomId> {
+            let value = self.decode_value(value)?;
+            let room_id = std::str::from_utf8(&value)
+                .map_err(|err| Error::InvalidData { details: err.to_string() })?;
+            OwnedRoomId::try_from(room_id)
+                .map_err(|err| Error::InvalidData { details: err.to_string() })
+        };
+
+        let conn = self.acquire().await?;
+        let mut content = RetainedContent::default();
+
+        for row in conn.query_cached("SELECT room_id_value FROM retained_rooms", &[]).await? {
+            content.rooms.insert(decode_room_id(row.get(0))?);
+        }
+
+        for row in
+            conn.query_cached("SELECT room_id_value, event_id FROM retained_events", &[]).await?
+        {
+            let event_id = OwnedEventId::try_from(row.get::<_, &str>(1))
+                .map_err(|err| Error::InvalidData { details: err.to_string() })?;
+
+            content.events.entry(decode_room_id(row.get(0))?).or_default().insert(event_id);
+        }
+
+        Ok(content)
+    }
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        content: Vec<u8>,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<()> {
+        self.media_service.add_media_content(self, request, content, ignore_policy).await
+    }
+
+    async fn replace_media_key(
+        &self,
+        from: &MediaRequestParameters,
+        to: &MediaRequestParameters,
+    ) -> Result<(), Self::Error> {
+        let prev_uri = self.encode_key(keys::MEDIA, from.source.unique_key());
+        let prev_format = self.encode_key(keys::MEDIA, from.format.unique_key());
+
+        let new_uri = self.encode_key(keys::MEDIA, to.source.unique_key());
+        let new_format = self.encode_key(keys::MEDIA, to.format.unique_key());
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "UPDATE media SET uri = $1, format = $2 WHERE uri = $3 AND format = $4",
+                &[&new_uri, &new_format, &prev_uri, &prev_format],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_media_content(&self, request: &MediaRequestParameters) -> Result<Option<Vec<u8>>> {
+        self.media_service.get_media_content(self, request).await
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequestParameters) -> Result<()> {
+        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
+        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
+
+        self.acquire()
+            .await?
+            .execute_cached("DELETE FROM media WHERE uri = $1 AND format = $2", &[&uri, &format])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_media_content_for_uri(
+        &self,
+        uri: &MxcUri,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.media_service.get_media_content_for_uri(self, uri).await
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        let uri = self.encode_key(keys::MEDIA, uri);
+
+        self.acquire().await?.execute_cached("DELETE FROM media WHERE uri = $1", &[&uri]).await?;
+
+        Ok(())
+    }
+
+    async fn set_media_retention_policy(
+        &self,
+        policy: MediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.media_service.set_media_retention_policy(self, policy).await
+    }
+
+    fn media_retention_policy(&self) -> MediaRetentionPolicy {
+        self.media_service.media_retention_policy()
+    }
+
+    async fn set_ignore_media_retention_policy(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.media_service.set_ignore_media_retention_policy(self, request, ignore_policy).await
+    }
+
+    async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
+        self.media_service.clean_up_media_cache(self).await
+    }
+
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage, Self::Error> {
+        self.media_service.media_cache_usage(self).await
+    }
+}
+
+#[async_trait]
+impl EventCacheStoreMedia for PostgresEventCacheStore {
+    type Error = Error;
+
+    async fn media_retention_policy_inner(
+        &self,
+    ) -> Result<Option<MediaRetentionPolicy>, Self::Error> {
+        self.acquire().await?.get_serialized_kv(keys::MEDIA_RETENTION_POLICY).await
+    }
+
+    async fn set_media_retention_policy_inner(
+        &self,
+        policy: MediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        self.acquire().await?.set_serialized_kv(keys::MEDIA_RETENTION_POLICY, policy).await
+    }
+
+    async fn add_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        data: Vec<u8>,
+        last_access: SystemTime,
+        policy: MediaRetentionPolicy,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let ignore_policy = ignore_policy.is_yes();
+        let data = self.encode_value(data)?;
+
+        if !ignore_policy && policy.exceeds_max_file_size(data.len() as u64) {
+            return Ok(());
+        }
+
+        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
+        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
+        let timestamp = time_to_timestamp(last_access);
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "INSERT INTO media (uri, format, data, last_access, ignore_policy)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (uri, format) DO UPDATE
+                 SET data = EXCLUDED.data, last_access = EXCLUDED.last_access,
+                     ignore_policy = EXCLUDED.ignore_policy",
+                &[&uri, &format, &data, &timestamp, &ignore_policy],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_ignore_media_retention_policy_inner(
+        &self,
+        request: &MediaRequestParameters,
+        ignore_policy: IgnoreMediaRetentionPolicy,
+    ) -> Result<(), Self::Error> {
+        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
+        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
+        let ignore_policy = ignore_policy.is_yes();
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "UPDATE media SET ignore_policy = $1 WHERE uri = $2 AND format = $3",
+                &[&ignore_policy, &uri, &format],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_media_content_inner(
+        &self,
+        request: &MediaRequestParameters,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
+        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
+        let timestamp = time_to_timestamp(current_time);
+
+        // Update the last access and get the data at once.
+        let row = self
+            .acquire()
+            .await?
+            .query_opt_cached(
+                "UPDATE media SET last_access = $1 WHERE uri = $2 AND format = $3 RETURNING data",
+                &[&timestamp, &uri, &format],
+            )
+            .await?;
+
+        row.map(|row| self.decode_value(row.get(0)).map(Into::into)).transpose()
+    }
+
+    async fn get_media_content_for_uri_inner(
+        &self,
+        uri: &MxcUri,
+        current_time: SystemTime,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        let uri = self.encode_key(keys::MEDIA, uri);
+        let timestamp = time_to_timestamp(current_time);
+
+        // Update the last access and get the data at once. Several formats may exist
+        // for the same URI, any of them will do.
+        let rows = self
+            .acquire()
+            .await?
+            .query_cached(
+                "UPDATE media SET last_access = $1 WHERE uri = $2 RETURNING data",
+                &[&timestamp, &uri],
+            )
+            .await?;
+
+        rows.first().map(|row| self.decode_value(row.get(0)).map(Into::into)).transpose()
+    }
+
+    async fn clean_up_media_cache_inner(
+        &self,
+        policy: MediaRetentionPolicy,
+        current_time: SystemTime,
+    ) -> Result<(), Self::Error> {
+        if !policy.has_limitations() {
+            // We can safely skip all the checks.
+            return Ok(());
+        }
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        // First, check media content that exceed the max filesize.
+        if let Some(max_file_size) = policy.computed_max_file_size() {
+            let max_file_size = i64::try_from(max_file_size).unwrap_or(i64::MAX);
+            txn.execute_cached(
+                "DELETE FROM media WHERE ignore_policy IS FALSE AND octet_length(data)::BIGINT > $1",
+                &[&max_file_size],
+            )
+            .await?;
+        }
+
+        // Then, clean up expired media content.
+        if let Some(last_access_expiry) = policy.last_access_expiry {
+            let current_timestamp = time_to_timestamp(current_time);
+            let expiry_secs = i64::try_from(last_access_expiry.as_secs()).unwrap_or(i64::MAX);
+            txn.execute_cached(
+                "DELETE FROM media WHERE ignore_policy IS FALSE AND ($1 - last_access) >= $2",
+                &[&current_timestamp, &expiry_secs],
+            )
+            .await?;
+        }
+
+        // Finally, if the cache size is too big, remove old items until it fits.
+        if let Some(max_cache_size) = policy.max_cache_size {
+            // Get the sizes of the media contents ordered by last access.
+            let content_sizes = txn
+                .query_cached(
+                    "SELECT id, octet_length(data)::BIGINT FROM media
+                     WHERE ignore_policy IS FALSE ORDER BY last_access DESC",
+                    &[],
+                )
+                .await?;
+
+            let mut accumulated_items_size = 0u64;
+            let mut limit_reached = false;
+            let mut rows_to_remove = Vec::new();
+
+            for row in content_sizes {
+                let row_id: i64 = row.get(0);
+                let size = row.get::<_, i64>(1) as u64;
+
+                if limit_reached {
+                    rows_to_remove.push(row_id);
+                    continue;
+                }
+
+                match accumulated_items_size.checked_add(size) {
+                    Some(acc) if acc > max_cache_size => {
+                        // We can stop accumulating.
+                        limit_reached = true;
+                        rows_to_remove.push(row_id);
+                    }
+                    Some(acc) => accumulated_items_size = acc,
+                    None => {
+                        // The accumulated size is overflowing but the setting cannot be
+                        // bigger than usize::MAX, we can stop accumulating.
+                        limit_reached = true;
+                        rows_to_remove.push(row_id);
+                    }
+                };
+            }
+
+            if !rows_to_remove.is_empty() {
+                txn.execute_cached("DELETE FROM media WHERE id = ANY($1)", &[&rows_to_remove])
+                    .await?;
+            }
+        }
+
+        txn.set_serialized_kv(keys::LAST_MEDIA_CLEANUP_TIME, current_time).await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn last_media_cleanup_time_inner(&self) -> Result<Option<SystemTime>, Self::Error> {
+        self.acquire().await?.get_serialized_kv(keys::LAST_MEDIA_CLEANUP_TIME).await
+    }
+
+    async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage, Self::Error> {
+        let row = self
+            .acquire()
+            .await?
+            .query_one_cached(
+                "SELECT count(*),
+                        coalesce(sum(octet_length(data)), 0)::BIGINT,
+                        count(*) FILTER (WHERE ignore_policy IS TRUE),
+                        coalesce(sum(octet_length(data)) FILTER (WHERE ignore_policy IS TRUE), 0)::BIGINT
+                 FROM media",
+                &[],
+            )
+            .await?;
+
+        // The values returned by PostgreSQL are always positive.
+        let value = |idx| row.get::<_, i64>(idx) as u64;
+
+        Ok(MediaCacheUsage::new(value(0), value(1), value(2), value(3)))
+    }
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::{
+        event_cache::store::{
+            integration_tests::{check_test_event, make_test_event},
+            EventCacheStore, EventCacheStoreError,
+        },
+        event_cache_store_integration_tests, event_cache_store_integration_tests_time,
+        event_cache_store_media_integration_tests,
+        linked_chunk::{ChunkContent, ChunkIdentifier, Position, Update},
+    };
+    use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
+
+    use super::PostgresEventCacheStore;
+    use crate::utils::test_utils::test_config;
+
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    fn new_namespace() -> String {
+        format!("test_event_cache_{}_{}", std::process::id(), NUM.fetch_add(1, SeqCst))
+    }
+
+    async fn get_event_cache_store() -> Result<PostgresEventCacheStore, EventCacheStoreError> {
+        let namespace = new_namespace();
+
+        tracing::info!("using event cache store in namespace {namespace}");
+
+        Ok(PostgresEventCacheStore::open_with_config(test_config(&namespace).await).await.unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_integration_tests_time!();
+    event_cache_store_media_integration_tests!(with_media_size_tests);
+
+    #[async_test]
+    async fn test_pool_size() {
+        let config = test_config(&new_namespace()).await.pool_max_size(42);
+
+        let store = PostgresEventCacheStore::open_with_config(config).await.unwrap();
+
+        assert_eq!(store.pool.status().max_size, 42);
+    }
+
+    #[async_test]
+    async fn test_linked_chunk_remove_item_shifts_positions() {
+        let store = get_event_cache_store().await.expect("creating cache store failed");
+
+        let room_id = *DEFAULT_TEST_ROOM_ID;
+
+        store
+            .handle_linked_chunk_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(42),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(42), 0),
+                        items: vec![
+                            make_test_event(room_id, "hello"),
+                            make_test_event(room_id, "world"),
+                            make_test_event(room_id, "!"),
+                        ],
+                    },
+                    Update::RemoveItem { at: Position::new(ChunkIdentifier::new(42), 0) },
+                ],
+            )
+            .await
+            .unwrap();
+
+        let mut chunks = store.load_all_chunks(room_id).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+
+        // Shifting the positions of the remaining events doesn't violate the unique
+        // constraint on the positions.
+        let c = chunks.remove(0);
+        assert_matches!(c.content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 2);
+            check_test_event(&events[0], "world");
+            check_test_event(&events[1], "!");
+        });
+    }
+
+    #[async_test]
+    async fn test_linked_chunk_update_is_a_transaction() {
+        let store = get_event_cache_store().await.expect("creating cache store failed");
+
+        let room_id = *DEFAULT_TEST_ROOM_ID;
+
+        // Trigger a violation of the unique constraint on the (room id, chunk id)
+        // couple.
+        let err = store
+            .handle_linked_chunk_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(42),
+                        next: None,
+                    },
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(42),
+                        next: None,
+                    },
+                ],
+            )
+            .await
+            .unwrap_err();
+
+        // The operation fails with a constraint violation error.
+        assert_matches!(err, crate::error::Error::Postgres(err) => {
+            assert_eq!(err.code(), Some(&tokio_postgres::error::SqlState::UNIQUE_VIOLATION));
+        });
+
+        // If the updates have been handled transactionally, then no new chunks should
+        // have been added; failure of the second update leads to the first one being
+        // rolled back.
+        let chunks = store.load_all_chunks(room_id).await.unwrap();
+        assert!(chunks.is_empty());
+    }
+
+    #[async_test]
+    async fn test_load_last_chunk_with_a_cycle() {
+        let room_id = *DEFAULT_TEST_ROOM_ID;
+        let store = get_event_cache_store().await.expect("creating cache store failed");
+
+        store
+            .handle_linked_chunk_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(0),
+                        next: None,
+                    },
+                    Update::NewItemsChunk {
+                        // Because `previous` connects to chunk #0, it will create a cycle.
+                        // Chunk #0 will have a `next` set to chunk #1! Consequently, the last chunk
+                        // **does not exist**. We have to detect this cycle.
+                        previous: Some(ChunkIdentifier::new(0)),
+                        new: ChunkIdentifier::new(1),
+                        next: Some(ChunkIdentifier::new(0)),
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        store.load_last_chunk(room_id).await.unwrap_err();
+    }
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache::store::EventCacheStoreError, event_cache_store_integration_tests,
+        event_cache_store_integration_tests_time, event_cache_store_media_integration_tests,
+    };
+
+    use super::PostgresEventCacheStore;
+    use crate::utils::test_utils::test_config;
+
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<PostgresEventCacheStore, EventCacheStoreError> {
+        let namespace =
+            format!("test_enc_event_cache_{}_{}", std::process::id(), NUM.fetch_add(1, SeqCst));
+
+        tracing::info!("using event cache store in namespace {namespace}");
+
+        let config = test_config(&namespace).await.passphrase(Some("default_test_password"));
+        Ok(PostgresEventCacheStore::open_with_config(config).await.unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+    event_cache_store_integration_tests_time!();
+    event_cache_store_media_integration_tests!();
+}
@@ -0,0 +1,1824 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, iter,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use deadpool_postgres::{Object as PostgresConn, Pool, Transaction};
+use matrix_sdk_base::{
+    deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    store::{
+        ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind, QueueWedgeError,
+        QueuedRequest, QueuedRequestKind, RoomLoadSettings, SentRequestKey,
+    },
+    MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
+    StateStoreDataKey, StateStoreDataValue,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{
+    canonical_json::{redact, RedactedBecause},
+    events::{
+        presence::PresenceEvent,
+        receipt::{Receipt, ReceiptThread, ReceiptType},
+        room::member::{StrippedRoomMemberEvent, SyncRoomMemberEvent},
+        AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent, AnySyncStateEvent,
+        GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
+    },
+    serde::Raw,
+    CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
+    OwnedTransactionId, OwnedUserId, RoomId, RoomVersionId, TransactionId, UInt, UserId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    error::{Error, Result},
+    utils::{lock_and_create_schema, Key, PostgresClientExt, PostgresKeyValueStoreExt},
+    OpenStoreError, PostgresStoreConfig,
+};
+
+mod keys {
+    // Tables
+    pub const KV_BLOB: &str = "kv_blob";
+    pub const ROOM_INFO: &str = "room_info";
+    pub const STATE_EVENT: &str = "state_event";
+    pub const GLOBAL_ACCOUNT_DATA: &str = "global_account_data";
+    pub const ROOM_ACCOUNT_DATA: &str = "room_account_data";
+    pub const MEMBER: &str = "member";
+    pub const PROFILE: &str = "profile";
+    pub const RECEIPT: &str = "receipt";
+    pub const DISPLAY_NAME: &str = "display_name";
+    pub const SEND_QUEUE: &str = "send_queue_events";
+    pub const DEPENDENTS_SEND_QUEUE: &str = "dependent_send_queue_events";
+}
+
+/// The name of the store, used as the suffix of its schema.
+const STORE_NAME: &str = "state";
+
+/// Identifier of the latest database version.
+///
+/// This is used to figure whether the database requires a migration.
+/// Every new SQL migration should imply a bump of this number, and changes in
+/// the [`run_migrations`] function.
+const DATABASE_VERSION: u8 = 1;
+
+/// A PostgreSQL based state store.
+#[derive(Clone)]
+pub struct PostgresStateStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    pool: Pool,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for PostgresStateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresStateStore").finish_non_exhaustive()
+    }
+}
+
+impl PostgresStateStore {
+    /// Open the PostgreSQL-based state store in the database at the given
+    /// connection string, using the given passphrase to encrypt private data.
+    pub async fn open(url: &str, passphrase: Option<&str>) -> Result<Self, OpenStoreError> {
+        Self::open_with_config(PostgresStoreConfig::from_url(url)?.passphrase(passphrase)).await
+    }
+
+    /// Open the PostgreSQL-based state store with the given config.
+    pub async fn open_with_config(config: PostgresStoreConfig) -> Result<Self, OpenStoreError> {
+        let schema = config.schema(STORE_NAME)?;
+        let pool = config.create_pool(&schema)?;
+
+        let mut conn = pool.get().await?;
+        let txn = conn.transaction().await?;
+
+        let version = lock_and_create_schema(&txn, &schema).await?;
+        run_migrations(&txn, version).await?;
+
+        let store_cipher = match config.passphrase.as_deref() {
+            Some(p) => Some(Arc::new(txn.get_or_create_store_cipher(p).await?)),
+            None => None,
+        };
+
+        txn.commit().await?;
+        drop(conn);
+
+        Ok(Self { store_cipher, pool })
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn serialize_value(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = rmp_serde::to_vec_named(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(data)?;
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    fn deserialize_value<T: DeserializeOwned>(&self, value: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(value)?;
+        Ok(rmp_serde::from_slice(&decoded)?)
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    fn encode_state_store_data_key(&self, key: StateStoreDataKey<'_>) -> Key {
+        let key_s = match key {
+            StateStoreDataKey::SyncToken => Cow::Borrowed(StateStoreDataKey::SYNC_TOKEN),
+            StateStoreDataKey::ServerCapabilities => {
+                Cow::Borrowed(StateStoreDataKey::SERVER_CAPABILITIES)
+            }
+            StateStoreDataKey::Filter(f) => {
+                Cow::Owned(format!("{}:{f}", StateStoreDataKey::FILTER))
+            }
+            StateStoreDataKey::UserAvatarUrl(u) => {
+                Cow::Owned(format!("{}:{u}", StateStoreDataKey::USER_AVATAR_URL))
+            }
+            StateStoreDataKey::RecentlyVisitedRooms(b) => {
+                Cow::Owned(format!("{}:{b}", StateStoreDataKey::RECENTLY_VISITED_ROOMS))
+            }
+            StateStoreDataKey::UtdHookManagerData => {
+                Cow::Borrowed(StateStoreDataKey::UTD_HOOK_MANAGER_DATA)
+            }
+            StateStoreDataKey::ComposerDraft(room_id) => {
+                Cow::Owned(format!("{}:{room_id}", StateStoreDataKey::COMPOSER_DRAFT))
+            }
+            StateStoreDataKey::SeenKnockRequests(room_id) => {
+                Cow::Owned(format!("{}:{room_id}", StateStoreDataKey::SEEN_KNOCK_REQUESTS))
+            }
+        };
+
+        self.encode_key(keys::KV_BLOB, &*key_s)
+    }
+
+    fn encode_presence_key(&self, user_id: &UserId) -> Key {
+        self.encode_key(keys::KV_BLOB, format!("presence:{user_id}"))
+    }
+
+    fn encode_custom_key(&self, key: &[u8]) -> Key {
+        let mut full_key = b"custom:".to_vec();
+        full_key.extend(key);
+        self.encode_key(keys::KV_BLOB, full_key)
+    }
+
+    async fn acquire(&self) -> Result<PostgresConn> {
+        Ok(self.pool.get().await?)
+    }
+
+    async fn remove_maybe_stripped_room_data(
+        &self,
+        txn: &Transaction<'_>,
+        room_id: &RoomId,
+        stripped: bool,
+    ) -> Result<()> {
+        let state_event_room_id = self.encode_key(keys::STATE_EVENT, room_id);
+        txn.remove_room_state_events(&state_event_room_id, Some(stripped)).await?;
+
+        let member_room_id = self.encode_key(keys::MEMBER, room_id);
+        txn.remove_room_members(&member_room_id, Some(stripped)).await
+    }
+
+    /// Get the version of the given room from its stored room info, or the
+    /// default version if it is unknown.
+    async fn room_version(&self, txn: &Transaction<'_>, room_id: &RoomId) -> RoomVersionId {
+        let encoded_room_id = self.encode_key(keys::ROOM_INFO, room_id);
+
+        txn.get_room_infos(Some(&encoded_room_id))
+            .await
+            .ok()
+            .and_then(|infos| infos.into_iter().next())
+            .and_then(|v| self.deserialize_json::<RoomInfo>(&v).ok())
+            .and_then(|info| info.room_version().cloned())
+            .unwrap_or_else(|| {
+                warn!(?room_id, "Unable to find the room version, assume version 9");
+                RoomVersionId::V9
+            })
+    }
+}
+
+/// Run the database migrations from the given version.
+async fn run_migrations(txn: &Transaction<'_>, version: u8) -> Result<()> {
+    if version >= DATABASE_VERSION {
+        return Ok(());
+    }
+
+    debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+
+    if version < 1 {
+        txn.batch_execute(include_str!("../migrations/state_store/001_init.sql")).await?;
+        txn.set_db_version(1).await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+trait PostgresStateStoreExt: PostgresClientExt {
+    async fn get_kv_blob(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached("SELECT value FROM kv_blob WHERE key = $1", &[&key])
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn get_kv_blobs(&self, keys: &[Key]) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached("SELECT value FROM kv_blob WHERE key = ANY($1)", &[&keys])
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn set_kv_blob(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO kv_blob (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+            &[&key, &value],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_kv_blob(&self, key: &[u8]) -> Result<u64> {
+        Ok(self.execute_cached("DELETE FROM kv_blob WHERE key = $1", &[&key]).await?)
+    }
+
+    async fn set_global_account_data(&self, event_type: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO global_account_data (event_type, data) VALUES ($1, $2)
+             ON CONFLICT (event_type) DO UPDATE SET data = EXCLUDED.data",
+            &[&event_type, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_global_account_data(&self, event_type: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM global_account_data WHERE event_type = $1",
+                &[&event_type],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn set_room_account_data(
+        &self,
+        room_id: &[u8],
+        event_type: &[u8],
+        data: &[u8],
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO room_account_data (room_id, event_type, data) VALUES ($1, $2, $3)
+             ON CONFLICT (room_id, event_type) DO UPDATE SET data = EXCLUDED.data",
+            &[&room_id, &event_type, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_room_account_data(
+        &self,
+        room_id: &[u8],
+        event_type: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM room_account_data WHERE room_id = $1 AND event_type = $2",
+                &[&room_id, &event_type],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn remove_room_account_data(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM room_account_data WHERE room_id = $1", &[&room_id])
+            .await?;
+        Ok(())
+    }
+
+    async fn set_room_info(&self, room_id: &[u8], state: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO room_info (room_id, state, data) VALUES ($1, $2, $3)
+             ON CONFLICT (room_id) DO UPDATE SET state = EXCLUDED.state, data = EXCLUDED.data",
+            &[&room_id, &state, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_room_infos(&self, room_id: Option<&[u8]>) -> Result<Vec<Vec<u8>>> {
+        let rows = match room_id {
+            None => self.query_cached("SELECT data FROM room_info", &[]).await?,
+            Some(room_id) => {
+                self.query_cached("SELECT data FROM room_info WHERE room_id = $1", &[&room_id])
+                    .await?
+            }
+        };
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Remove the room info for the given room.
+    async fn remove_room_info(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM room_info WHERE room_id = $1", &[&room_id]).await?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn set_state_event(
+        &self,
+        room_id: &[u8],
+        event_type: &[u8],
+        state_key: &[u8],
+        stripped: bool,
+        event_id: Option<&[u8]>,
+        data: &[u8],
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO state_event (room_id, event_type, state_key, stripped, event_id, data)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (room_id, event_type, state_key) DO UPDATE
+             SET stripped = EXCLUDED.stripped, event_id = EXCLUDED.event_id, data = EXCLUDED.data",
+            &[&room_id, &event_type, &state_key, &stripped, &event_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_state_event_by_id(
+        &self,
+        room_id: &[u8],
+        event_id: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM state_event WHERE room_id = $1 AND event_id = $2",
+                &[&room_id, &event_id],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn get_maybe_stripped_state_events(
+        &self,
+        room_id: &[u8],
+        event_type: &[u8],
+    ) -> Result<Vec<(bool, Vec<u8>)>> {
+        Ok(self
+            .query_cached(
+                "SELECT stripped, data FROM state_event WHERE room_id = $1 AND event_type = $2",
+                &[&room_id, &event_type],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn get_maybe_stripped_state_events_for_keys(
+        &self,
+        room_id: &[u8],
+        event_type: &[u8],
+        state_keys: &[Key],
+    ) -> Result<Vec<(bool, Vec<u8>)>> {
+        Ok(self
+            .query_cached(
+                "SELECT stripped, data FROM state_event
+                 WHERE room_id = $1 AND event_type = $2 AND state_key = ANY($3)",
+                &[&room_id, &event_type, &state_keys],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    /// Remove state events for the given room.
+    ///
+    /// If `stripped` is `Some()`, only removes state events for the given
+    /// stripped state. Otherwise, state events are removed regardless of the
+    /// stripped state.
+    async fn remove_room_state_events(&self, room_id: &[u8], stripped: Option<bool>) -> Result<()> {
+        if let Some(stripped) = stripped {
+            self.execute_cached(
+                "DELETE FROM state_event WHERE room_id = $1 AND stripped = $2",
+                &[&room_id, &stripped],
+            )
+            .await?;
+        } else {
+            self.execute_cached("DELETE FROM state_event WHERE room_id = $1", &[&room_id]).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_member(
+        &self,
+        room_id: &[u8],
+        user_id: &[u8],
+        membership: &[u8],
+        stripped: bool,
+        data: &[u8],
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO member (room_id, user_id, membership, stripped, data)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (room_id, user_id) DO UPDATE
+             SET membership = EXCLUDED.membership, stripped = EXCLUDED.stripped,
+                 data = EXCLUDED.data",
+            &[&room_id, &user_id, &membership, &stripped, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_user_ids(&self, room_id: &[u8], memberships: &[Key]) -> Result<Vec<Vec<u8>>> {
+        let rows = if memberships.is_empty() {
+            self.query_cached("SELECT data FROM member WHERE room_id = $1", &[&room_id]).await?
+        } else {
+            self.query_cached(
+                "SELECT data FROM member WHERE room_id = $1 AND membership = ANY($2)",
+                &[&room_id, &memberships],
+            )
+            .await?
+        };
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Remove members for the given room.
+    ///
+    /// If `stripped` is `Some()`, only removes members for the given stripped
+    /// state. Otherwise, members are removed regardless of the stripped state.
+    async fn remove_room_members(&self, room_id: &[u8], stripped: Option<bool>) -> Result<()> {
+        if let Some(stripped) = stripped {
+            self.execute_cached(
+                "DELETE FROM member WHERE room_id = $1 AND stripped = $2",
+                &[&room_id, &stripped],
+            )
+            .await?;
+        } else {
+            self.execute_cached("DELETE FROM member WHERE room_id = $1", &[&room_id]).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_profile(&self, room_id: &[u8], user_id: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO profile (room_id, user_id, data) VALUES ($1, $2, $3)
+             ON CONFLICT (room_id, user_id) DO UPDATE SET data = EXCLUDED.data",
+            &[&room_id, &user_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_profiles(
+        &self,
+        room_id: &[u8],
+        user_ids: &[Key],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .query_cached(
+                "SELECT user_id, data FROM profile WHERE room_id = $1 AND user_id = ANY($2)",
+                &[&room_id, &user_ids],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn remove_room_profiles(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM profile WHERE room_id = $1", &[&room_id]).await?;
+        Ok(())
+    }
+
+    async fn remove_room_profile(&self, room_id: &[u8], user_id: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM profile WHERE room_id = $1 AND user_id = $2",
+            &[&room_id, &user_id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn set_receipt(
+        &self,
+        room_id: &[u8],
+        user_id: &[u8],
+        receipt_type: &[u8],
+        thread: &[u8],
+        event_id: &[u8],
+        data: &[u8],
+    ) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO receipt (room_id, user_id, receipt_type, thread, event_id, data)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (room_id, user_id, receipt_type, thread) DO UPDATE
+             SET event_id = EXCLUDED.event_id, data = EXCLUDED.data",
+            &[&room_id, &user_id, &receipt_type, &thread, &event_id, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_user_receipt(
+        &self,
+        room_id: &[u8],
+        receipt_type: &[u8],
+        thread: &[u8],
+        user_id: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_opt_cached(
+                "SELECT data FROM receipt
+                 WHERE room_id = $1 AND receipt_type = $2 AND thread = $3 AND user_id = $4",
+                &[&room_id, &receipt_type, &thread, &user_id],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn get_event_receipts(
+        &self,
+        room_id: &[u8],
+        receipt_type: &[u8],
+        thread: &[u8],
+        event_id: &[u8],
+    ) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .query_cached(
+                "SELECT data FROM receipt
+                 WHERE room_id = $1 AND receipt_type = $2 AND thread = $3 AND event_id = $4",
+                &[&room_id, &receipt_type, &thread, &event_id],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    async fn remove_room_receipts(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM receipt WHERE room_id = $1", &[&room_id]).await?;
+        Ok(())
+    }
+
+    async fn set_display_name(&self, room_id: &[u8], name: &[u8], data: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "INSERT INTO display_name (room_id, name, data) VALUES ($1, $2, $3)
+             ON CONFLICT (room_id, name) DO UPDATE SET data = EXCLUDED.data",
+            &[&room_id, &name, &data],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_display_names(
+        &self,
+        room_id: &[u8],
+        names: &[Key],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .query_cached(
+                "SELECT name, data FROM display_name WHERE room_id = $1 AND name = ANY($2)",
+                &[&room_id, &names],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+
+    async fn remove_display_name(&self, room_id: &[u8], name: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM display_name WHERE room_id = $1 AND name = $2",
+            &[&room_id, &name],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_room_display_names(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM display_name WHERE room_id = $1", &[&room_id]).await?;
+        Ok(())
+    }
+
+    async fn remove_room_send_queue(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached("DELETE FROM send_queue_events WHERE room_id = $1", &[&room_id])
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_room_dependent_send_queue(&self, room_id: &[u8]) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM dependent_send_queue_events WHERE room_id = $1",
+            &[&room_id],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl<T: PostgresClientExt> PostgresStateStoreExt for T {}
+
+#[async_trait]
+impl StateStore for PostgresStateStore {
+    type Error = Error;
+
+    async fn get_kv_data(&self, key: StateStoreDataKey<'_>) -> Result<Option<StateStoreDataValue>> {
+        self.acquire()
+            .await?
+            .get_kv_blob(&self.encode_state_store_data_key(key))
+            .await?
+            .map(|data| {
+                Ok(match key {
+                    StateStoreDataKey::SyncToken => {
+                        StateStoreDataValue::SyncToken(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::ServerCapabilities => {
+                        StateStoreDataValue::ServerCapabilities(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::Filter(_) => {
+                        StateStoreDataValue::Filter(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UserAvatarUrl(_) => {
+                        StateStoreDataValue::UserAvatarUrl(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::RecentlyVisitedRooms(_) => {
+                        StateStoreDataValue::RecentlyVisitedRooms(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::UtdHookManagerData => {
+                        StateStoreDataValue::UtdHookManagerData(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::ComposerDraft(_) => {
+                        StateStoreDataValue::ComposerDraft(self.deserialize_value(&data)?)
+                    }
+                    StateStoreDataKey::SeenKnockRequests(_) => {
+                        StateStoreDataValue::SeenKnockRequests(self.deserialize_value(&data)?)
+                    }
+                })
+            })
+            .transpose()
+    }
+
+    async fn set_kv_data(
+        &self,
+        key: StateStoreDataKey<'_>,
+        value: StateStoreDataValue,
+    ) -> Result<()> {
+        let serialized_value = match key {
+            StateStoreDataKey::SyncToken => self.serialize_value(
+                &value.into_sync_token().expect("Session data not a sync token"),
+            )?,
+            StateStoreDataKey::ServerCapabilities => self.serialize_value(
+                &value
+                    .into_server_capabilities()
+                    .expect("Session data not containing server capabilities"),
+            )?,
+            StateStoreDataKey::Filter(_) => {
+                self.serialize_value(&value.into_filter().expect("Session data not a filter"))?
+            }
+            StateStoreDataKey::UserAvatarUrl(_) => self.serialize_value(
+                &value.into_user_avatar_url().expect("Session data not an user avatar url"),
+            )?,
+            StateStoreDataKey::RecentlyVisitedRooms(_) => self.serialize_value(
+                &value.into_recently_visited_rooms().expect("Session data not breadcrumbs"),
+            )?,
+            StateStoreDataKey::UtdHookManagerData => self.serialize_value(
+                &value.into_utd_hook_manager_data().expect("Session data not UtdHookManagerData"),
+            )?,
+            StateStoreDataKey::ComposerDraft(_) => self.serialize_value(
+                &value.into_composer_draft().expect("Session data not a composer draft"),
+            )?,
+            StateStoreDataKey::SeenKnockRequests(_) => self.serialize_value(
+                &value
+                    .into_seen_knock_requests()
+                    .expect("Session data is not a set of seen knock request ids"),
+            )?,
+        };
+
+        self.acquire()
+            .await?
+            .set_kv_blob(&self.encode_state_store_data_key(key), &serialized_value)
+            .await
+    }
+
+    async fn remove_kv_data(&self, key: StateStoreDataKey<'_>) -> Result<()> {
+        self.acquire().await?.delete_kv_blob(&self.encode_state_store_data_key(key)).await?;
+        Ok(())
+    }
+
+    async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
+        let StateChanges {
+            sync_token,
+            account_data,
+            presence,
+            profiles,
+            profiles_to_delete,
+            state,
+            room_account_data,
+            room_infos,
+            receipts,
+            redactions,
+            stripped_state,
+            ambiguity_maps,
+        } = changes;
+
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        if let Some(sync_token) = sync_token {
+            let key = self.encode_state_store_data_key(StateStoreDataKey::SyncToken);
+            let value = self.serialize_value(sync_token)?;
+            txn.set_kv_blob(&key, &value).await?;
+        }
+
+        for (event_type, event) in account_data {
+            let event_type = self.encode_key(keys::GLOBAL_ACCOUNT_DATA, event_type.to_string());
+            let data = self.serialize_json(event)?;
+            txn.set_global_account_data(&event_type, &data).await?;
+        }
+
+        for (room_id, events) in room_account_data {
+            let room_id = self.encode_key(keys::ROOM_ACCOUNT_DATA, room_id);
+            for (event_type, event) in events {
+                let event_type = self.encode_key(keys::ROOM_ACCOUNT_DATA, event_type.to_string());
+                let data = self.serialize_json(event)?;
+                txn.set_room_account_data(&room_id, &event_type, &data).await?;
+            }
+        }
+
+        for (user_id, event) in presence {
+            let key = self.encode_presence_key(user_id);
+            let value = self.serialize_json(event)?;
+            txn.set_kv_blob(&key, &value).await?;
+        }
+
+        for (room_id, room_info) in room_infos {
+            let stripped = room_info.state() == RoomState::Invited;
+            // Remove non-stripped data for stripped rooms and vice-versa.
+            self.remove_maybe_stripped_room_data(&txn, room_id, !stripped).await?;
+
+            let room_id = self.encode_key(keys::ROOM_INFO, room_id);
+            let state =
+                self.encode_key(keys::ROOM_INFO, serde_json::to_string(&room_info.state())?);
+            let data = self.serialize_json(room_info)?;
+            txn.set_room_info(&room_id, &state, &data).await?;
+        }
+
+        for (room_id, user_ids) in profiles_to_delete {
+            let room_id = self.encode_key(keys::PROFILE, room_id);
+            for user_id in user_ids {
+                let user_id = self.encode_key(keys::PROFILE, user_id);
+                txn.remove_room_profile(&room_id, &user_id).await?;
+            }
+        }
+
+        for (room_id, state_event_types) in state {
+            let profiles = profiles.get(room_id);
+            let encoded_room_id = self.encode_key(keys::STATE_EVENT, room_id);
+
+            for (event_type, state_events) in state_event_types {
+                let encoded_event_type = self.encode_key(keys::STATE_EVENT, event_type.to_string());
+
+                for (state_key, raw_state_event) in state_events {
+                    let encoded_state_key = self.encode_key(keys::STATE_EVENT, state_key);
+                    let data = self.serialize_json(raw_state_event)?;
+
+                    let event_id: Option<String> =
+                        raw_state_event.get_field("event_id").ok().flatten();
+                    let encoded_event_id =
+                        event_id.as_ref().map(|e| self.encode_key(keys::STATE_EVENT, e));
+
+                    txn.set_state_event(
+                        &encoded_room_id,
+                        &encoded_event_type,
+                        &encoded_state_key,
+                        false,
+                        encoded_event_id.as_deref(),
+                        &data,
+                    )
+                    .await?;
+
+                    if *event_type == StateEventType::RoomMember {
+                        let member_event =
+                            match raw_state_event.deserialize_as::<SyncRoomMemberEvent>() {
+                                Ok(ev) => ev,
+                                Err(e) => {
+                                    debug!(event_id, "Failed to deserialize member event: {e}");
+                                    continue;
+                                }
+                            };
+
+                        let encoded_room_id = self.encode_key(keys::MEMBER, room_id);
+                        let user_id = self.encode_key(keys::MEMBER, state_key);
+                        let membership =
+                            self.encode_key(keys::MEMBER, member_event.membership().as_str());
+                        let data = self.serialize_value(state_key)?;
+
+                        txn.set_member(&encoded_room_id, &user_id, &membership, false, &data)
+                            .await?;
+
+                        if let Some(profile) =
+                            profiles.and_then(|p| p.get(member_event.state_key()))
+                        {
+                            let room_id = self.encode_key(keys::PROFILE, room_id);
+                            let user_id = self.encode_key(keys::PROFILE, state_key);
+                            let data = self.serialize_json(profile)?;
+                            txn.set_profile(&room_id, &user_id, &data).await?;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (room_id, stripped_state_event_types) in stripped_state {
+            let encoded_room_id = self.encode_key(keys::STATE_EVENT, room_id);
+
+            for (event_type, stripped_state_events) in stripped_state_event_types {
+                let encoded_event_type = self.encode_key(keys::STATE_EVENT, event_type.to_string());
+
+                for (state_key, raw_stripped_state_event) in stripped_state_events {
+                    let encoded_state_key = self.encode_key(keys::STATE_EVENT, state_key);
+                    let data = self.serialize_json(raw_stripped_state_event)?;
+                    txn.set_state_event(
+                        &encoded_room_id,
+                        &encoded_event_type,
+                        &encoded_state_key,
+                        true,
+                        None,
+                        &data,
+                    )
+                    .await?;
+
+                    if *event_type == StateEventType::RoomMember {
+                        let member_event = match raw_stripped_state_event
+                            .deserialize_as::<StrippedRoomMemberEvent>()
+                        {
+                            Ok(ev) => ev,
+                            Err(e) => {
+                                debug!("Failed to deserialize stripped member event: {e}");
+                                continue;
+                            }
+                        };
+
+                        let room_id = self.encode_key(keys::MEMBER, room_id);
+                        let user_id = self.encode_key(keys::MEMBER, state_key);
+                        let membership =
+                            self.encode_key(keys::MEMBER, member_event.content.membership.as_str());
+                        let data = self.serialize_value(state_key)?;
+
+                        txn.set_member(&room_id, &user_id, &membership, true, &data).await?;
+                    }
+                }
+            }
+        }
+
+        for (room_id, receipt_event) in receipts {
+            let room_id = self.encode_key(keys::RECEIPT, room_id);
+
+            for (event_id, receipt_types) in receipt_event.iter() {
+                let encoded_event_id = self.encode_key(keys::RECEIPT, event_id);
+
+                for (receipt_type, receipt_users) in receipt_types {
+                    let receipt_type = self.encode_key(keys::RECEIPT, receipt_type.as_str());
+
+                    for (user_id, receipt) in receipt_users {
+                        let encoded_user_id = self.encode_key(keys::RECEIPT, user_id);
+                        // We cannot have a NULL primary key so we rely on serialization
+                        // instead of the string representation.
+                        let thread = self
+                            .encode_key(keys::RECEIPT, rmp_serde::to_vec_named(&receipt.thread)?);
+                        let data = self.serialize_json(&ReceiptData {
+                            receipt: receipt.clone(),
+                            event_id: event_id.clone(),
+                            user_id: user_id.clone(),
+                        })?;
+
+                        txn.set_receipt(
+                            &room_id,
+                            &encoded_user_id,
+                            &receipt_type,
+                            &thread,
+                            &encoded_event_id,
+                            &data,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        for (room_id, redactions) in redactions {
+            let encoded_room_id = self.encode_key(keys::STATE_EVENT, room_id);
+            let mut room_version = None;
+
+            for (event_id, redaction) in redactions {
+                let event_id = self.encode_key(keys::STATE_EVENT, event_id);
+
+                if let Some(Ok(raw_event)) = txn
+                    .get_state_event_by_id(&encoded_room_id, &event_id)
+                    .await?
+                    .map(|value| self.deserialize_json::<Raw<AnySyncStateEvent>>(&value))
+                {
+                    if room_version.is_none() {
+                        room_version = Some(self.room_version(&txn, room_id).await);
+                    }
+
+                    let event = raw_event.deserialize()?;
+                    let redacted = redact(
+                        raw_event.deserialize_as::<CanonicalJsonObject>()?,
+                        room_version.as_ref().expect("the room version was just set"),
+                        Some(RedactedBecause::from_raw_event(redaction)?),
+                    )
+                    .map_err(Error::Redaction)?;
+                    let data = self.serialize_json(&redacted)?;
+
+                    let event_type =
+                        self.encode_key(keys::STATE_EVENT, event.event_type().to_string());
+                    let state_key = self.encode_key(keys::STATE_EVENT, event.state_key());
+
+                    txn.set_state_event(
+                        &encoded_room_id,
+                        &event_type,
+                        &state_key,
+                        false,
+                        Some(&event_id),
+                        &data,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        for (room_id, display_names) in ambiguity_maps {
+            let room_id = self.encode_key(keys::DISPLAY_NAME, room_id);
+
+            for (name, user_ids) in display_names {
+                let encoded_name = self.encode_key(
+                    keys::DISPLAY_NAME,
+                    name.as_normalized_str().unwrap_or_else(|| name.as_raw_str()),
+                );
+                let data = self.serialize_json(user_ids)?;
+
+                if user_ids.is_empty() {
+                    txn.remove_display_name(&room_id, &encoded_name).await?;
+
+                    // Like in the SQLite store, the raw and normalized display names may
+                    // live in separate buckets, so if the merged bucket is empty, both
+                    // must be removed. See `get_users_with_display_names`.
+                    let raw_name = self.encode_key(keys::DISPLAY_NAME, name.as_raw_str());
+                    txn.remove_display_name(&room_id, &raw_name).await?;
+                } else {
+                    // We only create new buckets with the normalized display name.
+                    txn.set_display_name(&room_id, &encoded_name, &data).await?;
+                }
+            }
+        }
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_presence_event(&self, user_id: &UserId) -> Result<Option<Raw<PresenceEvent>>> {
+        self.acquire()
+            .await?
+            .get_kv_blob(&self.encode_presence_key(user_id))
+            .await?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn get_presence_events(
+        &self,
+        user_ids: &[OwnedUserId],
+    ) -> Result<Vec<Raw<PresenceEvent>>> {
+        if user_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let user_ids: Vec<_> = user_ids.iter().map(|u| self.encode_presence_key(u)).collect();
+        self.acquire()
+            .await?
+            .get_kv_blobs(&user_ids)
+            .await?
+            .into_iter()
+            .map(|data| self.deserialize_json(&data))
+            .collect()
+    }
+
+    async fn get_state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+        state_key: &str,
+    ) -> Result<Option<RawAnySyncOrStrippedState>> {
+        Ok(self
+            .get_state_events_for_keys(room_id, event_type, &[state_key])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    async fn get_state_events(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+    ) -> Result<Vec<RawAnySyncOrStrippedState>> {
+        let room_id = self.encode_key(keys::STATE_EVENT, room_id);
+        let event_type = self.encode_key(keys::STATE_EVENT, event_type.to_string());
+        self.acquire()
+            .await?
+            .get_maybe_stripped_state_events(&room_id, &event_type)
+            .await?
+            .into_iter()
+            .map(|(stripped, data)| {
+                let ev = if stripped {
+                    RawAnySyncOrStrippedState::Stripped(self.deserialize_json(&data)?)
+                } else {
+                    RawAnySyncOrStrippedState::Sync(self.deserialize_json(&data)?)
+                };
+
+                Ok(ev)
+            })
+            .collect()
+    }
+
+    async fn get_state_events_for_keys(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+        state_keys: &[&str],
+    ) -> Result<Vec<RawAnySyncOrStrippedState>, Self::Error> {
+        if state_keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let room_id = self.encode_key(keys::STATE_EVENT, room_id);
+        let event_type = self.encode_key(keys::STATE_EVENT, event_type.to_string());
+        let state_keys: Vec<_> =
+            state_keys.iter().map(|k| self.encode_key(keys::STATE_EVENT, k)).collect();
+        self.acquire()
+            .await?
+            .get_maybe_stripped_state_events_for_keys(&room_id, &event_type, &state_keys)
+            .await?
+            .into_iter()
+            .map(|(stripped, data)| {
+                let ev = if stripped {
+                    RawAnySyncOrStrippedState::Stripped(self.deserialize_json(&data)?)
+                } else {
+                    RawAnySyncOrStrippedState::Sync(self.deserialize_json(&data)?)
+                };
+
+                Ok(ev)
+            })
+            .collect()
+    }
+
+    async fn get_profile(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<MinimalRoomMemberEvent>> {
+        let room_id = self.encode_key(keys::PROFILE, room_id);
+        let user_ids = [self.encode_key(keys::PROFILE, user_id)];
+
+        self.acquire()
+            .await?
+            .get_profiles(&room_id, &user_ids)
+            .await?
+            .into_iter()
+            .next()
+            .map(|(_, data)| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn get_profiles<'a>(
+        &self,
+        room_id: &RoomId,
+        user_ids: &'a [OwnedUserId],
+    ) -> Result<BTreeMap<&'a UserId, MinimalRoomMemberEvent>> {
+        if user_ids.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let room_id = self.encode_key(keys::PROFILE, room_id);
+        let mut user_ids_map = user_ids
+            .iter()
+            .map(|u| (self.encode_key(keys::PROFILE, u), u.as_ref()))
+            .collect::<BTreeMap<_, _>>();
+        let user_ids: Vec<_> = user_ids_map.keys().cloned().collect();
+
+        self.acquire()
+            .await?
+            .get_profiles(&room_id, &user_ids)
+            .await?
+            .into_iter()
+            .map(|(user_id, data)| {
+                Ok((
+                    user_ids_map
+                        .remove(user_id.as_slice())
+                        .expect("returned user IDs were requested"),
+                    self.deserialize_json(&data)?,
+                ))
+            })
+            .collect()
+    }
+
+    async fn get_user_ids(
+        &self,
+        room_id: &RoomId,
+        membership: RoomMemberships,
+    ) -> Result<Vec<OwnedUserId>> {
+        let room_id = self.encode_key(keys::MEMBER, room_id);
+        let memberships: Vec<_> = membership
+            .as_vec()
+            .into_iter()
+            .map(|m| self.encode_key(keys::MEMBER, m.as_str()))
+            .collect();
+        self.acquire()
+            .await?
+            .get_user_ids(&room_id, &memberships)
+            .await?
+            .iter()
+            .map(|data| self.deserialize_value(data))
+            .collect()
+    }
+
+    async fn get_room_infos(&self, room_load_settings: &RoomLoadSettings) -> Result<Vec<RoomInfo>> {
+        let room_id = match room_load_settings {
+            RoomLoadSettings::All => None,
+            RoomLoadSettings::One(room_id) => Some(self.encode_key(keys::ROOM_INFO, room_id)),
+        };
+
+        self.acquire()
+            .await?
+            .get_room_infos(room_id.as_deref())
+            .await?
+            .into_iter()
+            .map(|data| self.deserialize_json(&data))
+            .collect()
+    }
+
+    async fn get_users_with_display_name(
+        &self,
+        room_id: &RoomId,
+        display_name: &DisplayName,
+    ) -> Result<BTreeSet<OwnedUserId>> {
+        let room_id = self.encode_key(keys::DISPLAY_NAME, room_id);
+        let names = [self.encode_key(
+            keys::DISPLAY_NAME,
+            display_name.as_normalized_str().unwrap_or_else(|| display_name.as_raw_str()),
+        )];
+
+        Ok(self
+            .acquire()
+            .await?
+            .get_display_names(&room_id, &names)
+            .await?
+            .into_iter()
+            .next()
+            .map(|(_, data)| self.deserialize_json(&data))
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    async fn get_users_with_display_names<'a>(
+        &self,
+        room_id: &RoomId,
+        display_names: &'a [DisplayName],
+    ) -> Result<HashMap<&'a DisplayName, BTreeSet<OwnedUserId>>> {
+        let mut result = HashMap::new();
+
+        if display_names.is_empty() {
+            return Ok(result);
+        }
+
+        let room_id = self.encode_key(keys::DISPLAY_NAME, room_id);
+        let mut names_map = display_names
+            .iter()
+            .flat_map(|display_name| {
+                // We encode the display name as the `raw_str()` and the normalized string,
+                // to be consistent with the other stores, which must merge the previously
+                // distinct buckets of user IDs of "Alice" and "alice" for example.
+                let raw =
+                    (self.encode_key(keys::DISPLAY_NAME, display_name.as_raw_str()), display_name);
+                let normalized = display_name.as_normalized_str().map(|normalized| {
+                    (self.encode_key(keys::DISPLAY_NAME, normalized), display_name)
+                });
+
+                iter::once(raw).chain(normalized.into_iter())
+            })
+            .collect::<BTreeMap<_, _>>();
+        let names: Vec<_> = names_map.keys().cloned().collect();
+
+        for (name, data) in
+            self.acquire().await?.get_display_names(&room_id, &names).await?.into_iter()
+        {
+            let display_name =
+                names_map.remove(name.as_slice()).expect("returned display names were requested");
+            let user_ids: BTreeSet<_> = self.deserialize_json(&data)?;
+
+            result.entry(display_name).or_insert_with(BTreeSet::new).extend(user_ids);
+        }
+
+        Ok(result)
+    }
+
+    async fn get_account_data_event(
+        &self,
+        event_type: GlobalAccountDataEventType,
+    ) -> Result<Option<Raw<AnyGlobalAccountDataEvent>>> {
+        let event_type = self.encode_key(keys::GLOBAL_ACCOUNT_DATA, event_type.to_string());
+        self.acquire()
+            .await?
+            .get_global_account_data(&event_type)
+            .await?
+            .map(|value| self.deserialize_json(&value))
+            .transpose()
+    }
+
+    async fn get_room_account_data_event(
+        &self,
+        room_id: &RoomId,
+        event_type: RoomAccountDataEventType,
+    ) -> Result<Option<Raw<AnyRoomAccountDataEvent>>> {
+        let room_id = self.encode_key(keys::ROOM_ACCOUNT_DATA, room_id);
+        let event_type = self.encode_key(keys::ROOM_ACCOUNT_DATA, event_type.to_string());
+        self.acquire()
+            .await?
+            .get_room_account_data(&room_id, &event_type)
+            .await?
+            .map(|value| self.deserialize_json(&value))
+            .transpose()
+    }
+
+    async fn get_user_room_receipt_event(
+        &self,
+        room_id: &RoomId,
+        receipt_type: ReceiptType,
+        thread: ReceiptThread,
+        user_id: &UserId,
+    ) -> Result<Option<(OwnedEventId, Receipt)>> {
+        let room_id = self.encode_key(keys::RECEIPT, room_id);
+        let receipt_type = self.encode_key(keys::RECEIPT, receipt_type.to_string());
+        // We cannot have a NULL primary key so we rely on serialization instead of the
+        // string representation.
+        let thread = self.encode_key(keys::RECEIPT, rmp_serde::to_vec_named(&thread)?);
+        let user_id = self.encode_key(keys::RECEIPT, user_id);
+
+        self.acquire()
+            .await?
+            .get_user_receipt(&room_id, &receipt_type, &thread, &user_id)
+            .await?
+            .map(|value| {
+                self.deserialize_json::<ReceiptData>(&value).map(|d| (d.event_id, d.receipt))
+            })
+            .transpose()
+    }
+
+    async fn get_event_room_receipt_events(
+        &self,
+        room_id: &RoomId,
+        receipt_type: ReceiptType,
+        thread: ReceiptThread,
+        event_id: &EventId,
+    ) -> Result<Vec<(OwnedUserId, Receipt)>> {
+        let room_id = self.encode_key(keys::RECEIPT, room_id);
+        let receipt_type = self.encode_key(keys::RECEIPT, receipt_type.to_string());
+        // We cannot have a NULL primary key so we rely on serialization instead of the
+        // string representation.
+        let thread = self.encode_key(keys::RECEIPT, rmp_serde::to_vec_named(&thread)?);
+        let event_id = self.encode_key(keys::RECEIPT, event_id);
+
+        self.acquire()
+            .await?
+            .get_event_receipts(&room_id, &receipt_type, &thread, &event_id)
+            .await?
+            .iter()
+            .map(|value| {
+                self.deserialize_json::<ReceiptData>(value).map(|d| (d.user_id, d.receipt))
+            })
+            .collect()
+    }
+
+    async fn get_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.acquire().await?.get_kv_blob(&self.encode_custom_key(key)).await
+    }
+
+    async fn set_custom_value_no_read(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.acquire().await?.set_kv_blob(&self.encode_custom_key(key), &value).await
+    }
+
+    async fn set_custom_value(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let mut conn = self.acquire().await?;
+        let key = self.encode_custom_key(key);
+
+        let txn = conn.transaction().await?;
+        let previous = txn.get_kv_blob(&key).await?;
+        txn.set_kv_blob(&key, &value).await?;
+        txn.commit().await?;
+
+        Ok(previous)
+    }
+
+    async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut conn = self.acquire().await?;
+        let key = self.encode_custom_key(key);
+
+        let txn = conn.transaction().await?;
+        let previous = txn.get_kv_blob(&key).await?;
+        if previous.is_some() {
+            txn.delete_kv_blob(&key).await?;
+        }
+        txn.commit().await?;
+
+        Ok(previous)
+    }
+
+    async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
+        let mut conn = self.acquire().await?;
+        let txn = conn.transaction().await?;
+
+        let room_info_room_id = self.encode_key(keys::ROOM_INFO, room_id);
+        txn.remove_room_info(&room_info_room_id).await?;
+
+        let state_event_room_id = self.encode_key(keys::STATE_EVENT, room_id);
+        txn.remove_room_state_events(&state_event_room_id, None).await?;
+
+        let member_room_id = self.encode_key(keys::MEMBER, room_id);
+        txn.remove_room_members(&member_room_id, None).await?;
+
+        let profile_room_id = self.encode_key(keys::PROFILE, room_id);
+        txn.remove_room_profiles(&profile_room_id).await?;
+
+        let room_account_data_room_id = self.encode_key(keys::ROOM_ACCOUNT_DATA, room_id);
+        txn.remove_room_account_data(&room_account_data_room_id).await?;
+
+        let receipt_room_id = self.encode_key(keys::RECEIPT, room_id);
+        txn.remove_room_receipts(&receipt_room_id).await?;
+
+        let display_name_room_id = self.encode_key(keys::DISPLAY_NAME, room_id);
+        txn.remove_room_display_names(&display_name_room_id).await?;
+
+        let send_queue_room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        txn.remove_room_send_queue(&send_queue_room_id).await?;
+
+        let dependent_send_queue_room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+        txn.remove_room_dependent_send_queue(&dependent_send_queue_room_id).await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: OwnedTransactionId,
+        created_at: MilliSecondsSinceUnixEpoch,
+        content: QueuedRequestKind,
+        priority: usize,
+    ) -> Result<(), Self::Error> {
+        let room_id_key = self.encode_key(keys::SEND_QUEUE, room_id);
+        let room_id_value = self.serialize_value(&room_id.to_owned())?;
+
+        let content = self.serialize_json(&content)?;
+        // The transaction id is used both as a key (in remove/update) and a value (as
+        // it's useful for the callers), so we keep it as is, and neither hash
+        // it (with encode_key) or encrypt it (through serialize_value). After
+        // all, it carries no personal information, so this is considered fine.
+        let transaction_id = transaction_id.as_str();
+
+        let priority = priority as i64;
+        let created_at_ts = u64::from(created_at.0) as i64;
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "INSERT INTO send_queue_events
+                   (room_id, room_id_val, transaction_id, content, priority, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &room_id_key,
+                    &room_id_value,
+                    &transaction_id,
+                    &content,
+                    &priority,
+                    &created_at_ts,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        content: QueuedRequestKind,
+    ) -> Result<bool, Self::Error> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        let content = self.serialize_json(&content)?;
+        // See comment in [`Self::save_send_queue_request`] to understand why the
+        // transaction id is neither encrypted or hashed.
+        let transaction_id = transaction_id.as_str();
+
+        let num_updated = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "UPDATE send_queue_events SET wedge_reason = NULL, content = $1
+                 WHERE room_id = $2 AND transaction_id = $3",
+                &[&content, &room_id, &transaction_id],
+            )
+            .await?;
+
+        Ok(num_updated > 0)
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        // See comment in `save_send_queue_request`.
+        let transaction_id = transaction_id.as_str();
+
+        let num_deleted = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "DELETE FROM send_queue_events WHERE room_id = $1 AND transaction_id = $2",
+                &[&room_id, &transaction_id],
+            )
+            .await?;
+
+        Ok(num_deleted > 0)
+    }
+
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        // Note: the `id` column is an auto-incremented integer counter, so we can sort
+        // using it to maintain the insertion order.
+        // Note 2: transaction_id is not encoded, see why in `save_send_queue_request`.
+        let rows = self
+            .acquire()
+            .await?
+            .query_cached(
+                "SELECT transaction_id, content, wedge_reason, priority, created_at
+                 FROM send_queue_events WHERE room_id = $1 ORDER BY priority DESC, id",
+                &[&room_id],
+            )
+            .await?;
+
+        let mut requests = Vec::with_capacity(rows.len());
+        for row in rows {
+            let created_at = row
+                .get::<_, Option<i64>>(4)
+                .and_then(|ts| UInt::new(ts.try_into().ok()?))
+                .map_or_else(MilliSecondsSinceUnixEpoch::now, MilliSecondsSinceUnixEpoch);
+            let wedge_reason: Option<Vec<u8>> = row.get(2);
+
+            requests.push(QueuedRequest {
+                transaction_id: row.get::<_, String>(0).into(),
+                kind: self.deserialize_json(row.get(1))?,
+                error: wedge_reason.map(|v| self.deserialize_value(&v)).transpose()?,
+                priority: row.get::<_, i64>(3).try_into().unwrap_or_default(),
+                created_at,
+            });
+        }
+
+        Ok(requests)
+    }
+
+    async fn update_send_queue_request_status(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+        error: Option<QueueWedgeError>,
+    ) -> Result<(), Self::Error> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        // See comment in `save_send_queue_request`.
+        let transaction_id = transaction_id.as_str();
+
+        // Serialize the error to json bytes (encrypted if option is enabled) if set.
+        let error_value = error.map(|e| self.serialize_value(&e)).transpose()?;
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "UPDATE send_queue_events SET wedge_reason = $1
+                 WHERE room_id = $2 AND transaction_id = $3",
+                &[&error_value, &room_id, &transaction_id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error> {
+        // If the values were not encrypted, we could use `SELECT DISTINCT` here, but we
+        // have to manually do the deduplication: indeed, for all X, encrypt(X)
+        // != encrypted(X), since we use a nonce in the encryption process.
+        let rows = self
+            .acquire()
+            .await?
+            .query_cached("SELECT room_id_val FROM send_queue_events", &[])
+            .await?;
+
+        // So we collect the results into a `BTreeSet` to perform the deduplication, and
+        // then rejigger that into a vector.
+        Ok(rows
+            .into_iter()
+            .map(|row| self.deserialize_value(row.get(0)))
+            .collect::<Result<BTreeSet<OwnedRoomId>, _>>()?
+            .into_iter()
+            .collect())
+    }
+
+    async fn save_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        parent_txn_id: &TransactionId,
+        own_txn_id: ChildTransactionId,
+        created_at: MilliSecondsSinceUnixEpoch,
+        content: DependentQueuedRequestKind,
+    ) -> Result<()> {
+        let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+        let content = self.serialize_json(&content)?;
+
+        // See comment in `save_send_queue_request`.
+        let parent_txn_id = parent_txn_id.as_str();
+        let own_txn_id = own_txn_id.to_string();
+
+        let created_at_ts = u64::from(created_at.0) as i64;
+
+        self.acquire()
+            .await?
+            .execute_cached(
+                "INSERT INTO dependent_send_queue_events
+                   (room_id, parent_transaction_id, own_transaction_id, content, created_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[&room_id, &parent_txn_id, &own_txn_id, &content, &created_at_ts],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        own_transaction_id: &ChildTransactionId,
+        new_content: DependentQueuedRequestKind,
+    ) -> Result<bool> {
+        let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+        let content = self.serialize_json(&new_content)?;
+
+        // See comment in `save_send_queue_request`.
+        let own_txn_id = own_transaction_id.to_string();
+
+        let num_updated = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "UPDATE dependent_send_queue_events SET content = $1
+                 WHERE own_transaction_id = $2 AND room_id = $3",
+                &[&content, &own_txn_id, &room_id],
+            )
+            .await?;
+
+        if num_updated > 1 {
+            return Err(Error::InconsistentUpdate);
+        }
+
+        Ok(num_updated == 1)
+    }
+
+    async fn mark_dependent_queued_requests_as_ready(
+        &self,
+        room_id: &RoomId,
+        parent_txn_id: &TransactionId,
+        parent_key: SentRequestKey,
+    ) -> Result<usize> {
+        let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+        let parent_key = self.serialize_value(&parent_key)?;
+
+        // See comment in `save_send_queue_request`.
+        let parent_txn_id = parent_txn_id.as_str();
+
+        let num_updated = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "UPDATE dependent_send_queue_events SET parent_key = $1
+                 WHERE parent_transaction_id = $2 AND room_id = $3",
+                &[&parent_key, &parent_txn_id, &room_id],
+            )
+            .await?;
+
+        Ok(num_updated as usize)
+    }
+
+    async fn remove_dependent_queued_request(
+        &self,
+        room_id: &RoomId,
+        txn_id: &ChildTransactionId,
+    ) -> Result<bool> {
+        let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+
+        // See comment in `save_send_queue_request`.
+        let txn_id = txn_id.to_string();
+
+        let num_deleted = self
+            .acquire()
+            .await?
+            .execute_cached(
+                "DELETE FROM dependent_send_queue_events
+                 WHERE own_transaction_id = $1 AND room_id = $2",
+                &[&txn_id, &room_id],
+            )
+            .await?;
+
+        Ok(num_deleted > 0)
+    }
+
+    async fn load_dependent_queued_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<DependentQueuedRequest>> {
+        let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
+
+        // Note: transaction_id is not encoded, see why in `save_send_queue_request`.
+        let rows = self
+            .acquire()
+            .await?
+            .query_cached(
+                "SELECT own_transaction_id, parent_transaction_id, parent_key, content, created_at
+                 FROM dependent_send_queue_events WHERE room_id = $1 ORDER BY id",
+                &[&room_id],
+            )
+            .await?;
+
+        let mut dependent_events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let created_at = row
+                .get::<_, Option<i64>>(4)
+                .and_then(|ts| UInt::new(ts.try_into().ok()?))
+                .map_or_else(MilliSecondsSinceUnixEpoch::now, MilliSecondsSinceUnixEpoch);
+            let parent_key: Option<Vec<u8>> = row.get(2);
+
+            dependent_events.push(DependentQueuedRequest {
+                own_transaction_id: row.get::<_, String>(0).into(),
+                parent_transaction_id: row.get::<_, String>(1).into(),
+                parent_key: parent_key.map(|bytes| self.deserialize_value(&bytes)).transpose()?,
+                kind: self.deserialize_json(row.get(3))?,
+                created_at,
+            });
+        }
+
+        Ok(dependent_events)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReceiptData {
+    receipt: Receipt,
+    event_id: OwnedEventId,
+    user_id: OwnedUserId,
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+
+    use super::PostgresStateStore;
+    use crate::utils::test_utils::test_config;
+
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_store() -> Result<impl StateStore, StoreError> {
+        let namespace = format!("test_state_{}_{}", std::process::id(), NUM.fetch_add(1, SeqCst));
+
+        tracing::info!("using store in namespace {namespace}");
+
+        Ok(PostgresStateStore::open_with_config(test_config(&namespace).await).await.unwrap())
+    }
+
+    statestore_integration_tests!();
+}
+
+#[cfg(all(test, feature = "postgres-tests"))]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+    use matrix_sdk_test::async_test;
+
+    use super::PostgresStateStore;
+    use crate::utils::test_utils::test_config;
+
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    fn new_namespace() -> String {
+        format!("test_enc_state_{}_{}", std::process::id(), NUM.fetch_add(1, SeqCst))
+    }
+
+    async fn get_store() -> Result<impl StateStore, StoreError> {
+        let namespace = new_namespace();
+
+        tracing::info!("using store in namespace {namespace}");
+
+        let config = test_config(&namespace).await.passphrase(Some("default_test_password"));
+        Ok(PostgresStateStore::open_with_config(config).await.unwrap())
+    }
+
+    #[async_test]
+    async fn test_pool_size() {
+        let config = test_config(&new_namespace()).await.pool_max_size(42);
+
+        let store = PostgresStateStore::open_with_config(config).await.unwrap();
+
+        assert_eq!(store.pool.status().max_size, 42);
+    }
+
+    #[async_test]
+    async fn test_reopen_with_passphrase() {
+        let namespace = new_namespace();
+        let config = test_config(&namespace).await.passphrase(Some("secret"));
+
+        let store = PostgresStateStore::open_with_config(config.clone()).await.unwrap();
+        store.set_custom_value(b"foo", b"bar".to_vec()).await.unwrap();
+        drop(store);
+
+        // The store cipher is loaded from the database, so the data can be read back.
+        let store = PostgresStateStore::open_with_config(config).await.unwrap();
+        assert_eq!(store.get_custom_value(b"foo").await.unwrap().as_deref(), Some(&b"bar"[..]));
+    }
+
+    statestore_integration_tests!();
+}
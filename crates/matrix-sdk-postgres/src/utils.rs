@@ -0,0 +1,302 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{borrow::Borrow, error::Error as StdError, ops::Deref};
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use deadpool_postgres::{GenericClient, Transaction};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::time::SystemTime;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio_postgres::{
+    types::{to_sql_checked, IsNull, ToSql, Type},
+    Row,
+};
+
+use crate::{error::Result, OpenStoreError};
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Key {
+    Plain(Vec<u8>),
+    Hashed([u8; 32]),
+}
+
+impl Deref for Key {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Key::Plain(slice) => slice,
+            Key::Hashed(bytes) => bytes,
+        }
+    }
+}
+
+impl Borrow<[u8]> for Key {
+    fn borrow(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl ToSql for Key {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        self.deref().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&[u8] as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+/// Helpers to run queries with statements that are prepared once per
+/// connection.
+#[async_trait]
+pub(crate) trait PostgresClientExt: GenericClient {
+    async fn execute_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, tokio_postgres::Error> {
+        let statement = self.prepare_cached(sql).await?;
+        self.execute(&statement, params).await
+    }
+
+    async fn query_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let statement = self.prepare_cached(sql).await?;
+        self.query(&statement, params).await
+    }
+
+    async fn query_one_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, tokio_postgres::Error> {
+        let statement = self.prepare_cached(sql).await?;
+        self.query_one(&statement, params).await
+    }
+
+    async fn query_opt_cached(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, tokio_postgres::Error> {
+        let statement = self.prepare_cached(sql).await?;
+        self.query_opt(&statement, params).await
+    }
+}
+
+impl<T: GenericClient> PostgresClientExt for T {}
+
+#[async_trait]
+pub(crate) trait PostgresKeyValueStoreExt: PostgresClientExt {
+    /// Check whether the `kv` table exists in the schema of the store.
+    async fn kv_table_exists(&self) -> Result<bool, tokio_postgres::Error> {
+        Ok(self.query_one("SELECT to_regclass('kv') IS NOT NULL", &[]).await?.get(0))
+    }
+
+    /// Get the stored value for the given key.
+    async fn get_kv(&self, key: &str) -> Result<Option<Vec<u8>>, tokio_postgres::Error> {
+        Ok(self
+            .query_opt_cached("SELECT value FROM kv WHERE key = $1", &[&key])
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    /// Get the stored serialized value for the given key.
+    async fn get_serialized_kv<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let Some(bytes) = self.get_kv(key).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(rmp_serde::from_slice(&bytes)?))
+    }
+
+    /// Store the given value for the given key.
+    async fn set_kv(&self, key: &str, value: &[u8]) -> Result<(), tokio_postgres::Error> {
+        self.execute_cached(
+            "INSERT INTO kv (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+            &[&key, &value],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Store the given value for the given key by serializing it.
+    async fn set_serialized_kv<T: Serialize + Send + Sync>(
+        &self,
+        key: &str,
+        value: T,
+    ) -> Result<()> {
+        let serialized_value = rmp_serde::to_vec_named(&value)?;
+        self.set_kv(key, &serialized_value).await?;
+
+        Ok(())
+    }
+
+    /// Removes the current key and value if exists.
+    #[cfg(feature = "crypto-store")]
+    async fn clear_kv(&self, key: &str) -> Result<(), tokio_postgres::Error> {
+        self.execute_cached("DELETE FROM kv WHERE key = $1", &[&key]).await?;
+        Ok(())
+    }
+
+    /// Set the version of the database.
+    async fn set_db_version(&self, version: u8) -> Result<(), tokio_postgres::Error> {
+        self.set_kv("version", &[version]).await
+    }
+
+    /// Get the version of the database.
+    async fn db_version(&self) -> Result<u8, OpenStoreError> {
+        let kv_exists = self.kv_table_exists().await.map_err(OpenStoreError::LoadVersion)?;
+
+        if kv_exists {
+            match self.get_kv("version").await.map_err(OpenStoreError::LoadVersion)?.as_deref() {
+                Some([v]) => Ok(*v),
+                Some(_) => Err(OpenStoreError::InvalidVersion),
+                None => Err(OpenStoreError::MissingVersion),
+            }
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Get the [`StoreCipher`] of the database or create it.
+    async fn get_or_create_store_cipher(
+        &self,
+        passphrase: &str,
+    ) -> Result<StoreCipher, OpenStoreError> {
+        let encrypted_cipher = self.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?;
+
+        let cipher = if let Some(encrypted) = encrypted_cipher {
+            StoreCipher::import(passphrase, &encrypted)?
+        } else {
+            let cipher = StoreCipher::new()?;
+            #[cfg(not(test))]
+            let export = cipher.export(passphrase);
+            #[cfg(test)]
+            let export = cipher._insecure_export_fast_for_testing(passphrase);
+            self.set_kv("cipher", &export?).await.map_err(OpenStoreError::SaveCipher)?;
+            cipher
+        };
+
+        Ok(cipher)
+    }
+}
+
+impl<T: GenericClient> PostgresKeyValueStoreExt for T {}
+
+/// Create the schema of a store if it doesn't exist yet, and return the
+/// version of its database.
+///
+/// This takes a lock that is held until the end of the transaction, so that
+/// processes opening the same store concurrently don't create or migrate it at
+/// the same time.
+pub(crate) async fn lock_and_create_schema(
+    txn: &Transaction<'_>,
+    schema: &str,
+) -> Result<u8, OpenStoreError> {
+    txn.execute("SELECT pg_advisory_xact_lock(hashtext($1))", &[&schema])
+        .await
+        .map_err(OpenStoreError::CreateSchema)?;
+
+    // The name of the schema has been validated by `PostgresStoreConfig::schema`,
+    // so it's safe to use it in the query.
+    txn.batch_execute(&format!(r#"CREATE SCHEMA IF NOT EXISTS "{schema}""#))
+        .await
+        .map_err(OpenStoreError::CreateSchema)?;
+
+    txn.db_version().await
+}
+
+/// Convert the given `SystemTime` to a timestamp, as the number of seconds
+/// since Unix Epoch.
+///
+/// Returns an `i64` as it is the type of `BIGINT` columns.
+pub(crate) fn time_to_timestamp(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .and_then(|d| d.as_secs().try_into().ok())
+        // It is unlikely to happen unless the time on the system is seriously wrong, but we always
+        // need a value.
+        .unwrap_or(0)
+}
+
+/// Helpers for the tests that need a PostgreSQL server.
+#[cfg(all(test, feature = "postgres-tests"))]
+pub(crate) mod test_utils {
+    use tokio_postgres::NoTls;
+
+    use crate::PostgresStoreConfig;
+
+    /// The connection string of the PostgreSQL server used by the tests.
+    fn test_url() -> String {
+        std::env::var("MATRIX_SDK_POSTGRES_TEST_URL")
+            .unwrap_or_else(|_| "postgresql://postgres@localhost/postgres".to_owned())
+    }
+
+    /// Get a configuration for the stores in the given namespace, removing
+    /// the data of a previous test run first.
+    pub async fn test_config(namespace: &str) -> PostgresStoreConfig {
+        let (client, connection) = tokio_postgres::connect(&test_url(), NoTls)
+            .await
+            .expect("Can't connect to the test PostgreSQL server");
+        let connection = tokio::spawn(connection);
+
+        client
+            .batch_execute(&format!(
+                r#"DROP SCHEMA IF EXISTS "{namespace}_state", "{namespace}_event_cache", "{namespace}_crypto" CASCADE"#
+            ))
+            .await
+            .unwrap();
+
+        drop(client);
+        connection.await.unwrap().unwrap();
+
+        reopen_test_config(namespace)
+    }
+
+    /// Get a configuration for the stores in the given namespace, keeping the
+    /// data of previous stores.
+    pub fn reopen_test_config(namespace: &str) -> PostgresStoreConfig {
+        PostgresStoreConfig::from_url(&test_url()).unwrap().namespace(namespace).pool_max_size(4)
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_time_to_timestamp() {
+        assert_eq!(time_to_timestamp(SystemTime::UNIX_EPOCH), 0);
+        assert_eq!(time_to_timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(60)), 60);
+
+        // Fallback value on overflow.
+        assert_eq!(time_to_timestamp(SystemTime::UNIX_EPOCH - Duration::from_secs(60)), 0);
+    }
+}
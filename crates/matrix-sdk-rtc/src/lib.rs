@@ -0,0 +1,41 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MatrixRTC calls for the Matrix SDK.
+//!
+//! The participants of the calls of a room are advertised with
+//! `m.call.member` state events, as defined in MSC3401 and MSC4143, one per
+//! device. [`RoomCalls`] tracks the active calls of a room from these events,
+//! and manages the membership of the device of the user, renewing it before it
+//! expires.
+//!
+//! The legacy 1:1 VoIP calls, negotiated with `m.call.*` events, are handled
+//! by [`voip::VoipCalls`].
+//!
+//! With the `widgets` feature, [`widget`] provides the capabilities needed to
+//! run Element Call with the widget driver of the SDK.
+
+pub mod membership;
+mod room_calls;
+pub mod voip;
+#[cfg(feature = "widgets")]
+pub mod widget;
+
+pub use self::{
+    membership::{ActiveCall, CallParticipant},
+    room_calls::{JoinCallOptions, RoomCalls},
+};
+
+#[cfg(test)]
+matrix_sdk_test::init_tracing_for_tests!();
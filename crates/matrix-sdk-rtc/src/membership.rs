@@ -0,0 +1,355 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The active calls of a room, computed from its `m.call.member` state events.
+
+use std::time::Duration;
+
+use matrix_sdk::deserialized_responses::RawAnySyncOrStrippedState;
+use ruma::{
+    events::call::member::{
+        ActiveFocus, Application, CallMemberEventContent, CallMemberStateKey, CallScope, Focus,
+        MembershipData,
+    },
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, UInt,
+};
+use serde::Deserialize;
+use tracing::debug;
+
+/// How long a membership is valid when its event doesn't say otherwise, as
+/// defined in MSC4143.
+pub const DEFAULT_MEMBERSHIP_EXPIRATION: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// A device of a user taking part in a call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallParticipant {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+
+    /// The ID of the device of the user.
+    pub device_id: OwnedDeviceId,
+
+    /// The focus currently used by this device, if it uses a session
+    /// membership.
+    pub focus_active: Option<ActiveFocus>,
+
+    /// The foci that this device proposes to use.
+    pub foci_preferred: Vec<Focus>,
+
+    /// When this device joined the call.
+    pub created_ts: MilliSecondsSinceUnixEpoch,
+
+    /// When the membership of this device expires, unless it is renewed.
+    pub expires_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// A call that has at least one participant in a room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveCall {
+    /// The ID of the call, which is empty for the call of the room.
+    pub call_id: String,
+
+    /// Who owns the call.
+    pub scope: CallScope,
+
+    /// The participants of the call, sorted by the time they joined.
+    pub participants: Vec<CallParticipant>,
+}
+
+impl ActiveCall {
+    /// Whether this is the call of the room, that every member of the room can
+    /// join.
+    pub fn is_room_call(&self) -> bool {
+        self.call_id.is_empty() && self.scope == CallScope::Room
+    }
+
+    /// When the first membership of this call expires, unless it is renewed.
+    pub fn next_expiration(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.participants.iter().map(|participant| participant.expires_at).min()
+    }
+}
+
+/// The identifier of a call in a room.
+#[derive(Clone, PartialEq)]
+struct CallApplicationKey {
+    call_id: String,
+    scope: CallScope,
+}
+
+/// The parts of a `m.call.member` state event that are needed to compute its
+/// memberships.
+#[derive(Deserialize)]
+struct CallMemberEventParts {
+    state_key: CallMemberStateKey,
+    sender: OwnedUserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    content: serde_json::Value,
+}
+
+/// The fields of a session membership that are not part of
+/// [`CallMemberEventContent`].
+#[derive(Deserialize)]
+struct SessionMembershipTimestamps {
+    /// The timestamp of the first event of the session, copied over when the
+    /// membership is renewed.
+    created_ts: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The duration of the membership, relative to `created_ts`, in
+    /// milliseconds.
+    expires: Option<UInt>,
+}
+
+/// Add the given duration to the given timestamp.
+pub(crate) fn add_duration(
+    ts: MilliSecondsSinceUnixEpoch,
+    duration: Duration,
+) -> MilliSecondsSinceUnixEpoch {
+    let millis =
+        u64::from(ts.0).saturating_add(duration.as_millis().try_into().unwrap_or(u64::MAX));
+    MilliSecondsSinceUnixEpoch(UInt::new_saturating(millis))
+}
+
+/// Get the participants described by the given `m.call.member` state event,
+/// including the expired ones.
+fn event_participants(
+    raw: &RawAnySyncOrStrippedState,
+) -> Vec<(CallApplicationKey, CallParticipant)> {
+    // Only joined rooms have calls.
+    let RawAnySyncOrStrippedState::Sync(raw) = raw else {
+        return Vec::new();
+    };
+
+    let event = match raw.deserialize_as::<CallMemberEventParts>() {
+        Ok(event) => event,
+        Err(error) => {
+            debug!("Ignoring a malformed call member event: {error}");
+            return Vec::new();
+        }
+    };
+
+    // Users can only send memberships for themselves.
+    if event.state_key.user_id() != event.sender {
+        debug!(sender = %event.sender, "Ignoring a call member event for another user");
+        return Vec::new();
+    }
+
+    let content = match serde_json::from_value::<CallMemberEventContent>(event.content.clone()) {
+        Ok(content) => content,
+        Err(error) => {
+            debug!(sender = %event.sender, "Ignoring a malformed call member event: {error}");
+            return Vec::new();
+        }
+    };
+
+    let mut participants = Vec::new();
+
+    match &content {
+        CallMemberEventContent::LegacyContent(_) => {
+            for membership in content.memberships() {
+                let MembershipData::Legacy(membership) = membership else {
+                    continue;
+                };
+                let created_ts = membership.created_ts.unwrap_or(event.origin_server_ts);
+                let Application::Call(call) = &membership.application else {
+                    continue;
+                };
+
+                participants.push((
+                    CallApplicationKey { call_id: call.call_id.clone(), scope: call.scope.clone() },
+                    CallParticipant {
+                        user_id: event.sender.clone(),
+                        device_id: membership.device_id.clone(),
+                        focus_active: None,
+                        foci_preferred: membership.foci_active.clone(),
+                        created_ts,
+                        expires_at: add_duration(created_ts, membership.expires),
+                    },
+                ));
+            }
+        }
+        CallMemberEventContent::SessionContent(membership) => {
+            let Application::Call(call) = &membership.application else {
+                return Vec::new();
+            };
+
+            let timestamps = serde_json::from_value::<SessionMembershipTimestamps>(event.content)
+                .unwrap_or(SessionMembershipTimestamps { created_ts: None, expires: None });
+            let created_ts = timestamps.created_ts.unwrap_or(event.origin_server_ts);
+            let expires = timestamps
+                .expires
+                .map(|expires| Duration::from_millis(expires.into()))
+                .unwrap_or(DEFAULT_MEMBERSHIP_EXPIRATION);
+
+            participants.push((
+                CallApplicationKey { call_id: call.call_id.clone(), scope: call.scope.clone() },
+                CallParticipant {
+                    user_id: event.sender,
+                    device_id: membership.device_id.clone(),
+                    focus_active: Some(membership.focus_active.clone()),
+                    foci_preferred: membership.foci_preferred.clone(),
+                    created_ts,
+                    expires_at: add_duration(created_ts, expires),
+                },
+            ));
+        }
+        // The user left the call.
+        _ => {}
+    }
+
+    participants
+}
+
+/// Compute the active calls of a room from its `m.call.member` state events.
+///
+/// The memberships that expire before `now` are ignored. The calls are sorted
+/// by the time their first participant joined.
+pub fn active_calls(
+    events: &[RawAnySyncOrStrippedState],
+    now: MilliSecondsSinceUnixEpoch,
+) -> Vec<ActiveCall> {
+    let mut calls: Vec<ActiveCall> = Vec::new();
+
+    for (key, participant) in events.iter().flat_map(event_participants) {
+        if participant.expires_at <= now {
+            continue;
+        }
+
+        match calls.iter_mut().find(|call| call.call_id == key.call_id && call.scope == key.scope) {
+            Some(call) => call.participants.push(participant),
+            None => calls.push(ActiveCall {
+                call_id: key.call_id,
+                scope: key.scope,
+                participants: vec![participant],
+            }),
+        }
+    }
+
+    for call in &mut calls {
+        call.participants.sort_by_key(|participant| participant.created_ts);
+    }
+    calls.sort_by_key(|call| call.participants.first().map(|participant| participant.created_ts));
+
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::deserialized_responses::RawAnySyncOrStrippedState;
+    use ruma::{serde::Raw, MilliSecondsSinceUnixEpoch, UInt};
+    use serde_json::{json, Value as JsonValue};
+
+    use super::active_calls;
+
+    fn ts(millis: u64) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(UInt::new(millis).unwrap())
+    }
+
+    fn call_member_event(
+        sender: &str,
+        device_id: &str,
+        origin_server_ts: u64,
+        content: JsonValue,
+    ) -> RawAnySyncOrStrippedState {
+        let event = json!({
+            "type": "org.matrix.msc3401.call.member",
+            "state_key": format!("_{sender}_{device_id}"),
+            "sender": sender,
+            "event_id": format!("${device_id}-{origin_server_ts}"),
+            "origin_server_ts": origin_server_ts,
+            "content": content,
+        });
+        RawAnySyncOrStrippedState::Sync(Raw::new(&event).unwrap().cast())
+    }
+
+    fn session_content(device_id: &str, call_id: &str, extra: JsonValue) -> JsonValue {
+        let mut content = json!({
+            "application": "m.call",
+            "call_id": call_id,
+            "scope": "m.room",
+            "device_id": device_id,
+            "focus_active": { "type": "livekit", "focus_selection": "oldest_membership" },
+            "foci_preferred": [],
+        });
+        content.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        content
+    }
+
+    #[test]
+    fn test_active_calls() {
+        let events = vec![
+            call_member_event(
+                "@alice:localhost",
+                "ALICE",
+                2_000,
+                session_content("ALICE", "", json!({ "expires": 10_000 })),
+            ),
+            call_member_event(
+                "@bob:localhost",
+                "BOB",
+                5_000,
+                // The membership was renewed.
+                session_content("BOB", "", json!({ "created_ts": 1_000, "expires": 20_000 })),
+            ),
+            call_member_event(
+                "@carol:localhost",
+                "CAROL",
+                3_000,
+                session_content("CAROL", "other", json!({})),
+            ),
+            // Left the call.
+            call_member_event("@dan:localhost", "DAN", 4_000, json!({})),
+        ];
+
+        let calls = active_calls(&events, ts(6_000));
+        assert_eq!(calls.len(), 2);
+
+        let room_call = &calls[0];
+        assert!(room_call.is_room_call());
+        assert_eq!(room_call.participants.len(), 2);
+        assert_eq!(room_call.participants[0].user_id, "@bob:localhost");
+        assert_eq!(room_call.participants[0].created_ts, ts(1_000));
+        assert_eq!(room_call.participants[0].expires_at, ts(21_000));
+        assert_eq!(room_call.participants[1].user_id, "@alice:localhost");
+        assert_eq!(room_call.participants[1].expires_at, ts(12_000));
+        assert_eq!(room_call.next_expiration(), Some(ts(12_000)));
+
+        let other_call = &calls[1];
+        assert!(!other_call.is_room_call());
+        assert_eq!(other_call.call_id, "other");
+        assert_eq!(other_call.participants[0].user_id, "@carol:localhost");
+
+        // Alice's membership has expired.
+        let calls = active_calls(&events, ts(15_000));
+        assert_eq!(calls[0].participants.len(), 1);
+        assert_eq!(calls[0].participants[0].user_id, "@bob:localhost");
+    }
+
+    #[test]
+    fn test_active_calls_ignores_memberships_for_other_users() {
+        let mut event = json!({
+            "type": "org.matrix.msc3401.call.member",
+            "state_key": "_@alice:localhost_ALICE",
+            "sender": "@mallory:localhost",
+            "event_id": "$event",
+            "origin_server_ts": 1_000,
+            "content": session_content("ALICE", "", json!({})),
+        });
+        let events = vec![RawAnySyncOrStrippedState::Sync(Raw::new(&event).unwrap().cast())];
+        assert!(active_calls(&events, ts(2_000)).is_empty());
+
+        event["sender"] = json!("@alice:localhost");
+        let events = vec![RawAnySyncOrStrippedState::Sync(Raw::new(&event).unwrap().cast())];
+        assert_eq!(active_calls(&events, ts(2_000)).len(), 1);
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The calls of a room, and the membership of the user in them.
+
+use std::{sync::Mutex, time::Duration};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk::{
+    executor::{spawn, JoinHandle},
+    sleep::sleep,
+    timeout::timeout,
+    Error, Result, Room,
+};
+use ruma::{
+    events::{
+        call::member::{
+            ActiveFocus, Application, CallApplicationContent, CallMemberEventContent,
+            CallMemberStateKey, CallScope, Focus,
+        },
+        StateEventType,
+    },
+    MilliSecondsSinceUnixEpoch, UInt,
+};
+use serde_json::json;
+use tracing::{debug, instrument, warn};
+
+use crate::membership::{active_calls, ActiveCall, DEFAULT_MEMBERSHIP_EXPIRATION};
+
+/// The settings of the membership of the user in a call, used with
+/// [`RoomCalls::join()`].
+#[derive(Clone, Debug)]
+pub struct JoinCallOptions {
+    call_id: String,
+    scope: CallScope,
+    focus_active: ActiveFocus,
+    foci_preferred: Vec<Focus>,
+    expiration: Duration,
+}
+
+impl JoinCallOptions {
+    /// Create the settings to join the call of the room with the given focus.
+    pub fn new(focus_active: ActiveFocus) -> Self {
+        Self {
+            call_id: String::new(),
+            scope: CallScope::Room,
+            focus_active,
+            foci_preferred: Vec::new(),
+            expiration: DEFAULT_MEMBERSHIP_EXPIRATION,
+        }
+    }
+
+    /// Join the call with the given ID and scope, instead of the call of the
+    /// room.
+    pub fn call(mut self, call_id: impl Into<String>, scope: CallScope) -> Self {
+        self.call_id = call_id.into();
+        self.scope = scope;
+        self
+    }
+
+    /// Set the foci that the device proposes to use.
+    pub fn foci_preferred(mut self, foci_preferred: Vec<Focus>) -> Self {
+        self.foci_preferred = foci_preferred;
+        self
+    }
+
+    /// Set how long the membership is valid if it isn't renewed, for example
+    /// because the app was killed.
+    ///
+    /// Defaults to [`DEFAULT_MEMBERSHIP_EXPIRATION`]. The membership is renewed
+    /// when half of this duration has elapsed.
+    pub fn expiration(mut self, expiration: Duration) -> Self {
+        self.expiration = expiration;
+        self
+    }
+}
+
+/// The membership of the user in a call.
+struct OwnMembership {
+    state_key: CallMemberStateKey,
+
+    /// The task renewing the membership before it expires.
+    heartbeat: JoinHandle<()>,
+}
+
+/// The calls of a room, and the membership of the device of the user in them.
+///
+/// The active calls are computed from the `m.call.member` state events of the
+/// room, as defined in MSC3401 and MSC4143, and updated when the state of the
+/// room changes or a membership expires.
+///
+/// Only one membership per device is supported, so joining a call leaves the
+/// previous one. The membership is not left when this object is dropped, but
+/// it stops being renewed, so it expires eventually.
+pub struct RoomCalls {
+    room: Room,
+
+    /// The active calls of the room.
+    calls: SharedObservable<Vec<ActiveCall>>,
+
+    /// The task updating the active calls.
+    refresh_task: JoinHandle<()>,
+
+    /// The membership of the user, if they joined a call.
+    own_membership: Mutex<Option<OwnMembership>>,
+}
+
+impl std::fmt::Debug for RoomCalls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RoomCalls").field("room_id", &self.room.room_id()).finish_non_exhaustive()
+    }
+}
+
+impl RoomCalls {
+    /// Start tracking the calls of the given room.
+    pub async fn new(room: Room) -> Result<Self> {
+        let calls = SharedObservable::new(load_active_calls(&room).await?);
+        let refresh_task = spawn(refresh_calls(room.clone(), calls.clone()));
+
+        Ok(Self { room, calls, refresh_task, own_membership: Mutex::new(None) })
+    }
+
+    /// The room of the calls.
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// The active calls of the room, with their participants.
+    pub fn calls(&self) -> Vec<ActiveCall> {
+        self.calls.get()
+    }
+
+    /// Subscribe to the changes of the active calls of the room.
+    pub fn subscribe(&self) -> Subscriber<Vec<ActiveCall>> {
+        self.calls.subscribe()
+    }
+
+    /// The call of the room, that every member can join, if it has
+    /// participants.
+    pub fn room_call(&self) -> Option<ActiveCall> {
+        self.calls.get().into_iter().find(ActiveCall::is_room_call)
+    }
+
+    /// Join a call with the device of the user.
+    ///
+    /// The membership is sent with an expiration, and renewed in the
+    /// background before it expires, until [`RoomCalls::leave()`] is called
+    /// or this object is dropped.
+    #[instrument(skip_all, fields(room_id = ?self.room.room_id()))]
+    pub async fn join(&self, options: JoinCallOptions) -> Result<()> {
+        let client = self.room.client();
+        let own_user_id = client.user_id().ok_or(Error::AuthenticationRequired)?.to_owned();
+        let own_device_id = client.device_id().ok_or(Error::AuthenticationRequired)?.to_owned();
+        let state_key = CallMemberStateKey::new(own_user_id, Some(own_device_id), true);
+
+        // Stop renewing the previous membership, it is replaced by this one.
+        if let Some(previous) = self.own_membership.lock().unwrap().take() {
+            previous.heartbeat.abort();
+        }
+
+        let created_ts = MilliSecondsSinceUnixEpoch::now();
+        send_membership(&self.room, &state_key, &options, created_ts).await?;
+
+        let heartbeat =
+            spawn(renew_membership(self.room.clone(), state_key.clone(), options, created_ts));
+        let previous =
+            self.own_membership.lock().unwrap().replace(OwnMembership { state_key, heartbeat });
+
+        // Another call to this method raced with this one.
+        if let Some(previous) = previous {
+            previous.heartbeat.abort();
+        }
+
+        Ok(())
+    }
+
+    /// Leave the call joined with [`RoomCalls::join()`].
+    ///
+    /// Does nothing if the user didn't join a call.
+    #[instrument(skip_all, fields(room_id = ?self.room.room_id()))]
+    pub async fn leave(&self) -> Result<()> {
+        let Some(membership) = self.own_membership.lock().unwrap().take() else {
+            return Ok(());
+        };
+        membership.heartbeat.abort();
+
+        self.room
+            .send_state_event_for_key(
+                &membership.state_key,
+                CallMemberEventContent::new_empty(None),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Whether the device of the user has joined a call with
+    /// [`RoomCalls::join()`].
+    pub fn has_joined(&self) -> bool {
+        self.own_membership.lock().unwrap().is_some()
+    }
+}
+
+impl Drop for RoomCalls {
+    fn drop(&mut self) {
+        self.refresh_task.abort();
+
+        if let Some(membership) = self.own_membership.lock().unwrap().take() {
+            membership.heartbeat.abort();
+        }
+    }
+}
+
+/// Load the active calls of the room from the store.
+async fn load_active_calls(room: &Room) -> Result<Vec<ActiveCall>> {
+    let events = room.get_state_events(StateEventType::CallMember).await?;
+    Ok(active_calls(&events, MilliSecondsSinceUnixEpoch::now()))
+}
+
+/// Update the active calls when the room changes, or when a membership
+/// expires.
+async fn refresh_calls(room: Room, calls: SharedObservable<Vec<ActiveCall>>) {
+    let mut room_info = room.subscribe_info();
+
+    loop {
+        let next_expiration = calls.read().iter().filter_map(ActiveCall::next_expiration).min();
+
+        if let Some(next_expiration) = next_expiration {
+            let now = MilliSecondsSinceUnixEpoch::now();
+            let delay = u64::from(next_expiration.0).saturating_sub(now.0.into());
+
+            // If the timeout elapses, a membership expired, so the calls must be updated.
+            if let Ok(None) = timeout(room_info.next(), Duration::from_millis(delay + 1)).await {
+                break;
+            }
+        } else if room_info.next().await.is_none() {
+            break;
+        }
+
+        match load_active_calls(&room).await {
+            Ok(active_calls) => {
+                calls.set_if_not_eq(active_calls);
+            }
+            Err(error) => {
+                warn!(room_id = ?room.room_id(), "Couldn't load the call memberships: {error}");
+            }
+        }
+    }
+}
+
+/// Send the membership of the user to the room.
+///
+/// The `created_ts` is the time of the first event of the membership, and the
+/// expiration is relative to it, so it is bumped when the membership is
+/// renewed.
+async fn send_membership(
+    room: &Room,
+    state_key: &CallMemberStateKey,
+    options: &JoinCallOptions,
+    created_ts: MilliSecondsSinceUnixEpoch,
+) -> Result<()> {
+    let own_device_id = room.client().device_id().ok_or(Error::AuthenticationRequired)?.to_owned();
+
+    let content = CallMemberEventContent::new(
+        Application::Call(CallApplicationContent::new(
+            options.call_id.clone(),
+            options.scope.clone(),
+        )),
+        own_device_id,
+        options.focus_active.clone(),
+        options.foci_preferred.clone(),
+        None,
+    );
+
+    let elapsed =
+        u64::from(MilliSecondsSinceUnixEpoch::now().0).saturating_sub(created_ts.0.into());
+    let expires = UInt::new_saturating(
+        elapsed.saturating_add(options.expiration.as_millis().try_into().unwrap_or(u64::MAX)),
+    );
+
+    let mut content = serde_json::to_value(content)?;
+    if let Some(object) = content.as_object_mut() {
+        object.insert("created_ts".to_owned(), json!(created_ts));
+        object.insert("expires".to_owned(), json!(expires));
+    }
+
+    room.send_state_event_raw(&StateEventType::CallMember.to_string(), state_key.as_ref(), content)
+        .await?;
+
+    Ok(())
+}
+
+/// Renew the membership of the user when half of its expiration has elapsed.
+async fn renew_membership(
+    room: Room,
+    state_key: CallMemberStateKey,
+    options: JoinCallOptions,
+    created_ts: MilliSecondsSinceUnixEpoch,
+) {
+    let mut delay = options.expiration / 2;
+
+    loop {
+        sleep(delay).await;
+
+        match send_membership(&room, &state_key, &options, created_ts).await {
+            Ok(()) => {
+                debug!(room_id = ?room.room_id(), "Renewed the call membership");
+                delay = options.expiration / 2;
+            }
+            Err(error) => {
+                warn!(room_id = ?room.room_id(), "Couldn't renew the call membership: {error}");
+                // Try again sooner, before the membership expires.
+                delay = (delay / 2).max(Duration::from_secs(1));
+            }
+        }
+    }
+}
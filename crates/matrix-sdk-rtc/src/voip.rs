@@ -0,0 +1,922 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signaling of the legacy 1:1 VoIP calls.
+//!
+//! These calls are negotiated with the `m.call.*` message-like events of the
+//! Client-Server API, usually in direct message rooms. [`VoipCalls`] routes the
+//! received events to a [`CallSignaling`] per call, which tracks the state of
+//! the call and sends the events of the user.
+//!
+//! The media itself is not handled here: the app provides the session
+//! descriptions and ICE candidates of its WebRTC stack, and gets the ones of
+//! the opponent.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk::{event_handler::EventHandlerHandle, executor::spawn, sleep::sleep, Client, Room};
+use ruma::{
+    events::call::{
+        answer::{CallAnswerEventContent, OriginalSyncCallAnswerEvent},
+        candidates::{CallCandidatesEventContent, Candidate, OriginalSyncCallCandidatesEvent},
+        hangup::{CallHangupEventContent, OriginalSyncCallHangupEvent, Reason},
+        invite::{CallInviteEventContent, OriginalSyncCallInviteEvent},
+        reject::{CallRejectEventContent, OriginalSyncCallRejectEvent},
+        select_answer::{CallSelectAnswerEventContent, OriginalSyncCallSelectAnswerEvent},
+        SessionDescription,
+    },
+    MilliSecondsSinceUnixEpoch, OwnedUserId, OwnedVoipId, UInt, UserId, VoipId,
+};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, instrument, warn};
+
+use crate::membership::add_duration;
+
+/// How long an invite is valid by default, if it isn't answered.
+pub const DEFAULT_INVITE_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How long the local ICE candidates are kept before being sent, so they can be
+/// batched.
+pub const CANDIDATES_BATCH_DELAY: Duration = Duration::from_millis(200);
+
+/// The maximum number of times the delay before sending the local ICE
+/// candidates again is doubled after failures.
+const MAX_CANDIDATES_BACKOFF_EXPONENT: u32 = 5;
+
+/// The ongoing calls, by ID.
+type CallsMap = Mutex<BTreeMap<OwnedVoipId, CallSignaling>>;
+
+/// An error when using a [`CallSignaling`].
+#[derive(Debug, Error)]
+pub enum CallSignalingError {
+    /// The operation is not possible in the current state of the call.
+    #[error("the call is in the wrong state for this operation: {0:?}")]
+    InvalidState(CallState),
+
+    /// An error occurred when sending an event.
+    #[error(transparent)]
+    Sdk(#[from] matrix_sdk::Error),
+}
+
+/// The state of a call.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallState {
+    /// The user sent an invite, which wasn't answered yet.
+    InviteSent,
+
+    /// The user received an invite, which wasn't answered yet.
+    Ringing,
+
+    /// The call was answered.
+    Connected,
+
+    /// The call has ended.
+    Ended(CallEndReason),
+}
+
+/// Why a call has ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallEndReason {
+    /// The call was hung up.
+    Hangup {
+        /// The reason of the hangup.
+        reason: Reason,
+
+        /// Whether the opponent hung up, rather than the user.
+        by_opponent: bool,
+    },
+
+    /// The invite was rejected, by the opponent or by the user.
+    Rejected,
+
+    /// The invite was answered or rejected on another device of the user.
+    HandledElsewhere,
+
+    /// The invite wasn't answered before its lifetime.
+    InviteExpired,
+
+    /// The opponent placed a call at the same time, which replaces this one.
+    Replaced(OwnedVoipId),
+}
+
+/// Whether the user placed or received the call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallDirection {
+    /// The user placed the call.
+    Outgoing,
+
+    /// The user received the call.
+    Incoming,
+}
+
+/// The mutable data of a call.
+struct CallData {
+    /// The party ID of the opponent, once it is known.
+    ///
+    /// For incoming calls, it's the party that sent the invite. For outgoing
+    /// calls, it's the first party that answered.
+    opponent_party_id: Option<OwnedVoipId>,
+
+    /// The session description of the opponent, either its offer or its answer.
+    remote_description: Option<SessionDescription>,
+
+    /// The candidates received before the opponent was known, by party.
+    pending_remote_candidates: HashMap<OwnedVoipId, Vec<Candidate>>,
+
+    /// The sender of the candidates of the opponent, to the app.
+    remote_candidates_tx: mpsc::UnboundedSender<Vec<Candidate>>,
+
+    /// The receiver of the candidates of the opponent, until the app takes it.
+    remote_candidates_rx: Option<mpsc::UnboundedReceiver<Vec<Candidate>>>,
+
+    /// The local candidates waiting to be sent.
+    local_candidates: Vec<Candidate>,
+
+    /// Whether a task to send the local candidates is scheduled.
+    flush_scheduled: bool,
+
+    /// The number of times in a row that sending the local candidates failed.
+    failed_flushes: u32,
+}
+
+struct CallInner {
+    room: Room,
+    call_id: OwnedVoipId,
+    party_id: OwnedVoipId,
+    direction: CallDirection,
+    opponent_user_id: OwnedUserId,
+    replaces: Option<OwnedVoipId>,
+    state: SharedObservable<CallState>,
+    data: Mutex<CallData>,
+
+    /// The ongoing calls of the [`VoipCalls`], to remove this call from them
+    /// once it has ended.
+    calls: Weak<CallsMap>,
+}
+
+/// The signaling of a legacy 1:1 VoIP call.
+///
+/// Get one by placing a call with [`VoipCalls::place_call()`], or from the
+/// incoming calls with [`VoipCalls::subscribe_to_incoming_calls()`].
+#[derive(Clone)]
+pub struct CallSignaling {
+    inner: Arc<CallInner>,
+}
+
+impl std::fmt::Debug for CallSignaling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallSignaling")
+            .field("room_id", &self.inner.room.room_id())
+            .field("call_id", &self.inner.call_id)
+            .field("direction", &self.inner.direction)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CallSignaling {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        calls: Weak<CallsMap>,
+        room: Room,
+        call_id: OwnedVoipId,
+        direction: CallDirection,
+        opponent_user_id: OwnedUserId,
+        opponent_party_id: Option<OwnedVoipId>,
+        remote_description: Option<SessionDescription>,
+        replaces: Option<OwnedVoipId>,
+    ) -> Self {
+        let state = match direction {
+            CallDirection::Outgoing => CallState::InviteSent,
+            CallDirection::Incoming => CallState::Ringing,
+        };
+        let (remote_candidates_tx, remote_candidates_rx) = mpsc::unbounded_channel();
+
+        Self {
+            inner: Arc::new(CallInner {
+                room,
+                call_id,
+                party_id: VoipId::new(),
+                direction,
+                opponent_user_id,
+                replaces,
+                state: SharedObservable::new(state),
+                data: Mutex::new(CallData {
+                    opponent_party_id,
+                    remote_description,
+                    pending_remote_candidates: HashMap::new(),
+                    remote_candidates_tx,
+                    remote_candidates_rx: Some(remote_candidates_rx),
+                    local_candidates: Vec::new(),
+                    flush_scheduled: false,
+                    failed_flushes: 0,
+                }),
+                calls,
+            }),
+        }
+    }
+
+    /// The room of the call.
+    pub fn room(&self) -> &Room {
+        &self.inner.room
+    }
+
+    /// The ID of the call.
+    pub fn call_id(&self) -> &VoipId {
+        &self.inner.call_id
+    }
+
+    /// Whether the user placed or received the call.
+    pub fn direction(&self) -> CallDirection {
+        self.inner.direction
+    }
+
+    /// The user at the other end of the call.
+    pub fn opponent_user_id(&self) -> &UserId {
+        &self.inner.opponent_user_id
+    }
+
+    /// The ID of the call that this one replaces, after both users called each
+    /// other at the same time.
+    ///
+    /// The app should answer this call right away, with the media of the
+    /// replaced call.
+    pub fn replaces(&self) -> Option<&VoipId> {
+        self.inner.replaces.as_deref()
+    }
+
+    /// The session description of the opponent: the offer of an incoming call,
+    /// or the answer to an outgoing call once it's connected.
+    pub fn remote_description(&self) -> Option<SessionDescription> {
+        self.inner.data.lock().unwrap().remote_description.clone()
+    }
+
+    /// The current state of the call.
+    pub fn state(&self) -> CallState {
+        self.inner.state.get()
+    }
+
+    /// Subscribe to the changes of the state of the call.
+    pub fn subscribe_to_state(&self) -> Subscriber<CallState> {
+        self.inner.state.subscribe()
+    }
+
+    /// Take the receiver of the ICE candidates of the opponent.
+    ///
+    /// The candidates received before are buffered, so none is lost. Returns
+    /// `None` if it was already taken.
+    pub fn take_remote_candidates(&self) -> Option<mpsc::UnboundedReceiver<Vec<Candidate>>> {
+        self.inner.data.lock().unwrap().remote_candidates_rx.take()
+    }
+
+    /// Answer an incoming call with the given session description.
+    #[instrument(skip_all, fields(call_id = %self.inner.call_id))]
+    pub async fn answer(&self, answer: SessionDescription) -> Result<(), CallSignalingError> {
+        self.ensure_state(CallState::Ringing)?;
+
+        let content = CallAnswerEventContent::version_1(
+            answer,
+            self.inner.call_id.clone(),
+            self.inner.party_id.clone(),
+        );
+        self.inner.room.send(content).await?;
+
+        if self.set_state_if(CallState::Ringing, CallState::Connected) {
+            self.schedule_candidates_flush();
+        }
+
+        Ok(())
+    }
+
+    /// Reject an incoming call.
+    #[instrument(skip_all, fields(call_id = %self.inner.call_id))]
+    pub async fn reject(&self) -> Result<(), CallSignalingError> {
+        self.ensure_state(CallState::Ringing)?;
+
+        let content = CallRejectEventContent::version_1(
+            self.inner.call_id.clone(),
+            self.inner.party_id.clone(),
+        );
+        self.inner.room.send(content).await?;
+
+        self.set_state_if(CallState::Ringing, CallState::Ended(CallEndReason::Rejected));
+
+        Ok(())
+    }
+
+    /// Hang up the call with the given reason.
+    ///
+    /// An outgoing call can be hung up before it's answered, to cancel it.
+    #[instrument(skip_all, fields(call_id = %self.inner.call_id))]
+    pub async fn hangup(&self, reason: Reason) -> Result<(), CallSignalingError> {
+        let state = self.state();
+        if !matches!(state, CallState::InviteSent | CallState::Connected) {
+            return Err(CallSignalingError::InvalidState(state));
+        }
+
+        let content = CallHangupEventContent::version_1(
+            self.inner.call_id.clone(),
+            self.inner.party_id.clone(),
+            reason.clone(),
+        );
+        self.inner.room.send(content).await?;
+
+        self.end(CallEndReason::Hangup { reason, by_opponent: false });
+
+        Ok(())
+    }
+
+    /// Queue local ICE candidates, to be sent with the other candidates after
+    /// [`CANDIDATES_BATCH_DELAY`].
+    ///
+    /// The candidates of an incoming call are only sent once it's answered.
+    pub fn add_local_candidates(&self, candidates: impl IntoIterator<Item = Candidate>) {
+        self.inner.data.lock().unwrap().local_candidates.extend(candidates);
+
+        if matches!(self.state(), CallState::InviteSent | CallState::Connected) {
+            self.schedule_candidates_flush();
+        }
+    }
+
+    fn ensure_state(&self, expected: CallState) -> Result<(), CallSignalingError> {
+        let state = self.state();
+        if state == expected {
+            Ok(())
+        } else {
+            Err(CallSignalingError::InvalidState(state))
+        }
+    }
+
+    /// Set the state of the call if it's the expected one.
+    ///
+    /// Returns whether the state was changed.
+    fn set_state_if(&self, expected: CallState, new: CallState) -> bool {
+        let ended = matches!(new, CallState::Ended(_));
+
+        {
+            let mut state = self.inner.state.write();
+            if *state != expected {
+                return false;
+            }
+            eyeball::ObservableWriteGuard::set(&mut state, new);
+        }
+
+        if ended {
+            self.forget();
+        }
+        true
+    }
+
+    /// End the call, unless it has already ended.
+    fn end(&self, reason: CallEndReason) {
+        {
+            let mut state = self.inner.state.write();
+            if matches!(*state, CallState::Ended(_)) {
+                return;
+            }
+            eyeball::ObservableWriteGuard::set(&mut state, CallState::Ended(reason));
+        }
+
+        self.forget();
+    }
+
+    /// Remove the call from the ongoing calls of the [`VoipCalls`], once it
+    /// has ended.
+    fn forget(&self) {
+        let Some(calls) = self.inner.calls.upgrade() else {
+            return;
+        };
+
+        let mut calls = calls.lock().unwrap();
+        if calls.get(&self.inner.call_id).is_some_and(|call| Arc::ptr_eq(&call.inner, &self.inner))
+        {
+            calls.remove(&self.inner.call_id);
+        }
+    }
+
+    /// Send the queued local candidates after [`CANDIDATES_BATCH_DELAY`].
+    ///
+    /// The delay is doubled every time sending the candidates failed in a row.
+    fn schedule_candidates_flush(&self) {
+        let backoff = {
+            let mut data = self.inner.data.lock().unwrap();
+            if data.flush_scheduled || data.local_candidates.is_empty() {
+                return;
+            }
+            data.flush_scheduled = true;
+            2u32.pow(data.failed_flushes.min(MAX_CANDIDATES_BACKOFF_EXPONENT))
+        };
+
+        let call = self.clone();
+        spawn(async move {
+            sleep(CANDIDATES_BATCH_DELAY * backoff).await;
+
+            let candidates = {
+                let mut data = call.inner.data.lock().unwrap();
+                data.flush_scheduled = false;
+                std::mem::take(&mut data.local_candidates)
+            };
+
+            if candidates.is_empty() || matches!(call.state(), CallState::Ended(_)) {
+                return;
+            }
+
+            let content = CallCandidatesEventContent::version_1(
+                call.inner.call_id.clone(),
+                call.inner.party_id.clone(),
+                candidates.clone(),
+            );
+
+            match call.inner.room.send(content).await {
+                Ok(_) => {
+                    call.inner.data.lock().unwrap().failed_flushes = 0;
+                }
+                Err(error) => {
+                    warn!(call_id = %call.inner.call_id, "Couldn't send the ICE candidates: {error}");
+
+                    // Put them back in front of the newer ones, to retry with them.
+                    {
+                        let mut data = call.inner.data.lock().unwrap();
+                        let newer = std::mem::replace(&mut data.local_candidates, candidates);
+                        data.local_candidates.extend(newer);
+                        data.failed_flushes = data.failed_flushes.saturating_add(1);
+                    }
+
+                    call.schedule_candidates_flush();
+                }
+            }
+        });
+    }
+
+    /// End an outgoing call if it's not answered before the given lifetime.
+    fn expire_invite_after(&self, lifetime: Duration) {
+        let call = self.clone();
+        spawn(async move {
+            sleep(lifetime).await;
+
+            if call.state() != CallState::InviteSent {
+                return;
+            }
+
+            let content = CallHangupEventContent::version_1(
+                call.inner.call_id.clone(),
+                call.inner.party_id.clone(),
+                Reason::InviteTimeout,
+            );
+            if let Err(error) = call.inner.room.send(content).await {
+                warn!(call_id = %call.inner.call_id, "Couldn't hang up the expired call: {error}");
+            }
+
+            call.set_state_if(
+                CallState::InviteSent,
+                CallState::Ended(CallEndReason::InviteExpired),
+            );
+        });
+    }
+
+    /// Hang up an outgoing call that is replaced by an incoming call from the
+    /// opponent, placed at the same time.
+    fn replace_with(&self, call_id: OwnedVoipId) {
+        if !self
+            .set_state_if(CallState::InviteSent, CallState::Ended(CallEndReason::Replaced(call_id)))
+        {
+            return;
+        }
+
+        let call = self.clone();
+        spawn(async move {
+            let content = CallHangupEventContent::version_1(
+                call.inner.call_id.clone(),
+                call.inner.party_id.clone(),
+                Reason::UserHangup,
+            );
+            if let Err(error) = call.inner.room.send(content).await {
+                warn!(call_id = %call.inner.call_id, "Couldn't hang up the replaced call: {error}");
+            }
+        });
+    }
+
+    /// End an incoming call if it's not answered before it expires.
+    fn expire_ringing_at(&self, expires_at: MilliSecondsSinceUnixEpoch) {
+        let delay =
+            u64::from(expires_at.0).saturating_sub(MilliSecondsSinceUnixEpoch::now().0.into());
+
+        let call = self.clone();
+        spawn(async move {
+            sleep(Duration::from_millis(delay)).await;
+            call.set_state_if(CallState::Ringing, CallState::Ended(CallEndReason::InviteExpired));
+        });
+    }
+
+    /// Whether the given event was sent by the opponent, when it's known.
+    fn is_from_opponent(&self, sender: &UserId, party_id: Option<&VoipId>) -> bool {
+        if sender != self.inner.opponent_user_id {
+            return false;
+        }
+
+        let data = self.inner.data.lock().unwrap();
+        match (&data.opponent_party_id, party_id) {
+            (Some(opponent), Some(party_id)) => opponent == party_id,
+            // VoIP version 0 doesn't have party IDs.
+            _ => true,
+        }
+    }
+
+    /// Whether the given event was sent by another device of the user.
+    fn is_from_other_own_device(&self, sender: &UserId, party_id: Option<&VoipId>) -> bool {
+        sender == self.inner.room.own_user_id()
+            && party_id.is_none_or(|party_id| party_id != self.inner.party_id)
+    }
+
+    fn handle_answer(&self, event: OriginalSyncCallAnswerEvent) {
+        let party_id = event.content.party_id.as_deref();
+
+        match self.inner.direction {
+            CallDirection::Outgoing => {
+                if event.sender != self.inner.opponent_user_id {
+                    return;
+                }
+
+                let pending_candidates = {
+                    let mut data = self.inner.data.lock().unwrap();
+                    if data.opponent_party_id.is_some() {
+                        // Another device of the opponent answered first.
+                        return;
+                    }
+                    data.opponent_party_id = event.content.party_id.clone();
+                    data.remote_description = Some(event.content.answer);
+
+                    let pending = match party_id {
+                        Some(party_id) => data.pending_remote_candidates.remove(party_id),
+                        None => None,
+                    };
+                    data.pending_remote_candidates.clear();
+                    pending
+                };
+
+                if let Some(candidates) = pending_candidates {
+                    self.forward_remote_candidates(candidates);
+                }
+
+                if self.set_state_if(CallState::InviteSent, CallState::Connected) {
+                    self.select_answer(event.content.party_id);
+                }
+            }
+            CallDirection::Incoming => {
+                if self.is_from_other_own_device(&event.sender, party_id) {
+                    self.set_state_if(
+                        CallState::Ringing,
+                        CallState::Ended(CallEndReason::HandledElsewhere),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Tell the other devices of the opponent which answer was selected.
+    fn select_answer(&self, selected_party_id: Option<OwnedVoipId>) {
+        // VoIP version 0 doesn't select answers.
+        let Some(selected_party_id) = selected_party_id else {
+            return;
+        };
+
+        let call = self.clone();
+        spawn(async move {
+            let content = CallSelectAnswerEventContent::version_1(
+                call.inner.call_id.clone(),
+                call.inner.party_id.clone(),
+                selected_party_id,
+            );
+            if let Err(error) = call.inner.room.send(content).await {
+                warn!(call_id = %call.inner.call_id, "Couldn't select the answer: {error}");
+            }
+        });
+    }
+
+    fn handle_candidates(&self, event: OriginalSyncCallCandidatesEvent) {
+        if event.sender != self.inner.opponent_user_id {
+            return;
+        }
+
+        let party_id = event.content.party_id;
+        let candidates = event.content.candidates;
+
+        {
+            let mut data = self.inner.data.lock().unwrap();
+            match (&data.opponent_party_id, party_id) {
+                (Some(opponent), Some(party_id)) if *opponent != party_id => return,
+                // The opponent isn't known yet, keep the candidates until an answer is
+                // selected.
+                (None, Some(party_id)) if self.inner.direction == CallDirection::Outgoing => {
+                    data.pending_remote_candidates.entry(party_id).or_default().extend(candidates);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        self.forward_remote_candidates(candidates);
+    }
+
+    fn forward_remote_candidates(&self, candidates: Vec<Candidate>) {
+        // The receiver might have been dropped by the app, which is fine.
+        let _ = self.inner.data.lock().unwrap().remote_candidates_tx.send(candidates);
+    }
+
+    fn handle_hangup(&self, event: OriginalSyncCallHangupEvent) {
+        if self.is_from_opponent(&event.sender, event.content.party_id.as_deref()) {
+            self.end(CallEndReason::Hangup { reason: event.content.reason, by_opponent: true });
+        }
+    }
+
+    fn handle_reject(&self, event: OriginalSyncCallRejectEvent) {
+        let party_id = Some(&*event.content.party_id);
+
+        match self.inner.direction {
+            CallDirection::Outgoing if event.sender == self.inner.opponent_user_id => {
+                // Only a rejection before any answer ends the call.
+                self.set_state_if(CallState::InviteSent, CallState::Ended(CallEndReason::Rejected));
+            }
+            CallDirection::Incoming if self.is_from_other_own_device(&event.sender, party_id) => {
+                self.set_state_if(
+                    CallState::Ringing,
+                    CallState::Ended(CallEndReason::HandledElsewhere),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_select_answer(&self, event: OriginalSyncCallSelectAnswerEvent) {
+        if self.inner.direction != CallDirection::Incoming
+            || !self.is_from_opponent(&event.sender, Some(&event.content.party_id))
+        {
+            return;
+        }
+
+        if event.content.selected_party_id != self.inner.party_id {
+            // The caller picked the answer of another device of the user.
+            self.end(CallEndReason::HandledElsewhere);
+        }
+    }
+}
+
+struct VoipCallsInner {
+    client: Client,
+
+    /// The calls that haven't ended yet, by ID.
+    calls: Arc<CallsMap>,
+
+    /// The sender of the incoming calls.
+    incoming_calls_tx: broadcast::Sender<CallSignaling>,
+}
+
+/// The legacy 1:1 VoIP calls of a client.
+///
+/// It listens to the `m.call.*` events received in the sync, and routes them to
+/// the [`CallSignaling`] of their call, until it is dropped.
+pub struct VoipCalls {
+    inner: Arc<VoipCallsInner>,
+    event_handlers: Vec<EventHandlerHandle>,
+}
+
+impl std::fmt::Debug for VoipCalls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VoipCalls").finish_non_exhaustive()
+    }
+}
+
+impl VoipCalls {
+    /// Start handling the VoIP calls of the given client.
+    pub fn new(client: &Client) -> Self {
+        let (incoming_calls_tx, _) = broadcast::channel(16);
+        let inner = Arc::new(VoipCallsInner {
+            client: client.clone(),
+            calls: Arc::new(Mutex::new(BTreeMap::new())),
+            incoming_calls_tx,
+        });
+
+        let event_handlers = vec![
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallInviteEvent, room: Room| {
+                    let inner = inner.clone();
+                    async move { inner.handle_invite(event, room) }
+                }
+            }),
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallAnswerEvent| {
+                    let inner = inner.clone();
+                    async move {
+                        if let Some(call) = inner.call(&event.content.call_id) {
+                            call.handle_answer(event);
+                        }
+                    }
+                }
+            }),
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallCandidatesEvent| {
+                    let inner = inner.clone();
+                    async move {
+                        if let Some(call) = inner.call(&event.content.call_id) {
+                            call.handle_candidates(event);
+                        }
+                    }
+                }
+            }),
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallHangupEvent| {
+                    let inner = inner.clone();
+                    async move {
+                        if let Some(call) = inner.call(&event.content.call_id) {
+                            call.handle_hangup(event);
+                        }
+                    }
+                }
+            }),
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallRejectEvent| {
+                    let inner = inner.clone();
+                    async move {
+                        if let Some(call) = inner.call(&event.content.call_id) {
+                            call.handle_reject(event);
+                        }
+                    }
+                }
+            }),
+            client.add_event_handler({
+                let inner = inner.clone();
+                move |event: OriginalSyncCallSelectAnswerEvent| {
+                    let inner = inner.clone();
+                    async move {
+                        if let Some(call) = inner.call(&event.content.call_id) {
+                            call.handle_select_answer(event);
+                        }
+                    }
+                }
+            }),
+        ];
+
+        Self { inner, event_handlers }
+    }
+
+    /// Place a call to the given user in the given room, with the given offer.
+    ///
+    /// The call is hung up if it's not answered before `lifetime`, which
+    /// defaults to [`DEFAULT_INVITE_LIFETIME`].
+    #[instrument(skip(self, room, offer), fields(room_id = ?room.room_id()))]
+    pub async fn place_call(
+        &self,
+        room: &Room,
+        invitee: &UserId,
+        offer: SessionDescription,
+        lifetime: Option<Duration>,
+    ) -> Result<CallSignaling, CallSignalingError> {
+        let lifetime = lifetime.unwrap_or(DEFAULT_INVITE_LIFETIME);
+        let call = CallSignaling::new(
+            Arc::downgrade(&self.inner.calls),
+            room.clone(),
+            VoipId::new(),
+            CallDirection::Outgoing,
+            invitee.to_owned(),
+            None,
+            None,
+            None,
+        );
+
+        let mut content = CallInviteEventContent::version_1(
+            call.inner.call_id.clone(),
+            call.inner.party_id.clone(),
+            UInt::new_saturating(lifetime.as_millis().try_into().unwrap_or(u64::MAX)),
+            offer,
+        );
+        content.invitee = Some(invitee.to_owned());
+
+        // Register the call first, so the events about it are not missed.
+        self.inner.calls.lock().unwrap().insert(call.inner.call_id.clone(), call.clone());
+
+        if let Err(error) = room.send(content).await {
+            self.inner.calls.lock().unwrap().remove(&call.inner.call_id);
+            return Err(error.into());
+        }
+
+        call.expire_invite_after(lifetime);
+        call.schedule_candidates_flush();
+
+        Ok(call)
+    }
+
+    /// Get the call with the given ID, if it hasn't ended.
+    pub fn call(&self, call_id: &VoipId) -> Option<CallSignaling> {
+        self.inner.call(call_id)
+    }
+
+    /// Subscribe to the calls received by the user.
+    pub fn subscribe_to_incoming_calls(&self) -> broadcast::Receiver<CallSignaling> {
+        self.inner.incoming_calls_tx.subscribe()
+    }
+}
+
+impl Drop for VoipCalls {
+    fn drop(&mut self) {
+        for handle in self.event_handlers.drain(..) {
+            self.inner.client.remove_event_handler(handle);
+        }
+    }
+}
+
+impl VoipCallsInner {
+    fn call(&self, call_id: &VoipId) -> Option<CallSignaling> {
+        self.calls.lock().unwrap().get(call_id).cloned()
+    }
+
+    fn handle_invite(&self, event: OriginalSyncCallInviteEvent, room: Room) {
+        let own_user_id = room.own_user_id();
+        let content = event.content;
+
+        // The invites sent by the user are handled by the device that sent them.
+        if event.sender == own_user_id {
+            return;
+        }
+        if content.invitee.as_ref().is_some_and(|invitee| invitee != own_user_id) {
+            return;
+        }
+
+        let expires_at =
+            add_duration(event.origin_server_ts, Duration::from_millis(content.lifetime.into()));
+        if expires_at <= MilliSecondsSinceUnixEpoch::now() {
+            debug!(call_id = %content.call_id, "Ignoring an expired call invite");
+            return;
+        }
+
+        let mut calls = self.calls.lock().unwrap();
+        if calls.contains_key(&content.call_id) {
+            return;
+        }
+
+        // Both users called each other at the same time: the call with the lowest ID
+        // wins.
+        let glare = calls
+            .values()
+            .find(|call| {
+                call.inner.direction == CallDirection::Outgoing
+                    && call.inner.room.room_id() == room.room_id()
+                    && call.inner.opponent_user_id == event.sender
+                    && call.state() == CallState::InviteSent
+            })
+            .cloned();
+
+        let replaced = match glare {
+            Some(outgoing) if content.call_id < outgoing.inner.call_id => {
+                debug!(call_id = %content.call_id, replaced = %outgoing.inner.call_id, "Call glare, replacing the outgoing call");
+                Some(outgoing)
+            }
+            Some(outgoing) => {
+                debug!(call_id = %content.call_id, kept = %outgoing.inner.call_id, "Call glare, ignoring the incoming call");
+                return;
+            }
+            None => None,
+        };
+
+        let call = CallSignaling::new(
+            Arc::downgrade(&self.calls),
+            room,
+            content.call_id.clone(),
+            CallDirection::Incoming,
+            event.sender,
+            content.party_id,
+            Some(content.offer),
+            replaced.as_ref().map(|outgoing| outgoing.inner.call_id.clone()),
+        );
+        call.expire_ringing_at(expires_at);
+        calls.insert(content.call_id.clone(), call.clone());
+        drop(calls);
+
+        if let Some(outgoing) = replaced {
+            outgoing.replace_with(content.call_id);
+        }
+
+        // There might be no subscriber, which is fine.
+        let _ = self.incoming_calls_tx.send(call);
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integration with the [`WidgetDriver`][matrix_sdk::widget::WidgetDriver] to
+//! run Element Call.
+
+use matrix_sdk::{
+    async_trait,
+    widget::{
+        Capabilities, CapabilitiesProvider, EventFilter, MessageLikeEventFilter, StateEventFilter,
+        ToDeviceEventFilter,
+    },
+};
+use ruma::{
+    events::{MessageLikeEventType, StateEventType},
+    DeviceId, UserId,
+};
+
+/// The capabilities that Element Call needs to manage the call membership of
+/// the given device.
+pub fn element_call_capabilities(own_user_id: &UserId, own_device_id: &DeviceId) -> Capabilities {
+    let read_send: Vec<_> = [
+        // To read and send rageshake requests from other room members.
+        "org.matrix.rageshake_request",
+        // To read and send the encryption keys of the call.
+        "io.element.call.encryption_keys",
+        // To read and send the custom reactions of Element Call, that can be sent
+        // several times to the same event.
+        "io.element.call.reaction",
+    ]
+    .into_iter()
+    .map(MessageLikeEventType::from)
+    // To raise and lower a hand.
+    .chain([MessageLikeEventType::Reaction, MessageLikeEventType::RoomRedaction])
+    .map(|event_type| EventFilter::MessageLike(MessageLikeEventFilter::WithType(event_type)))
+    // To read and send the encryption keys of the call with to-device events, for
+    // the newer versions of Element Call.
+    .chain([EventFilter::ToDevice(ToDeviceEventFilter::new(
+        "io.element.call.encryption_keys".into(),
+    ))])
+    .collect();
+
+    let read = [
+        // To compute the current state of the call.
+        StateEventType::CallMember,
+        // To detect the members leaving the room during a call.
+        StateEventType::RoomMember,
+        // To encrypt the call streams if the room is encrypted.
+        StateEventType::RoomEncryption,
+        // To know the room version, and its auth rules.
+        StateEventType::RoomCreate,
+    ]
+    .into_iter()
+    .map(|event_type| EventFilter::State(StateEventFilter::WithType(event_type)))
+    .chain(read_send.clone())
+    .collect();
+
+    let send = [
+        // The legacy membership with all the devices of the user.
+        own_user_id.to_string(),
+        // The session membership of the device, with and without a leading
+        // underscore.
+        format!("{own_user_id}_{own_device_id}"),
+        format!("_{own_user_id}_{own_device_id}"),
+    ]
+    .into_iter()
+    .map(|state_key| {
+        EventFilter::State(StateEventFilter::WithTypeAndStateKey(
+            StateEventType::CallMember,
+            state_key,
+        ))
+    })
+    .chain(read_send)
+    .collect();
+
+    Capabilities {
+        read,
+        send,
+        requires_client: true,
+        update_delayed_event: true,
+        send_delayed_event: true,
+    }
+}
+
+/// A [`CapabilitiesProvider`] for Element Call, that grants the requested
+/// capabilities among the ones returned by [`element_call_capabilities()`].
+#[derive(Clone, Debug)]
+pub struct ElementCallCapabilitiesProvider {
+    allowed: Capabilities,
+}
+
+impl ElementCallCapabilitiesProvider {
+    /// Create a provider for Element Call running with the given device.
+    pub fn new(own_user_id: &UserId, own_device_id: &DeviceId) -> Self {
+        Self { allowed: element_call_capabilities(own_user_id, own_device_id) }
+    }
+}
+
+#[async_trait]
+impl CapabilitiesProvider for ElementCallCapabilitiesProvider {
+    async fn acquire_capabilities(&self, requested: Capabilities) -> Capabilities {
+        let is_allowed = |allowed: &[EventFilter], filter: &EventFilter| {
+            allowed.iter().any(|allowed| same_filter(allowed, filter))
+        };
+
+        Capabilities {
+            read: requested
+                .read
+                .into_iter()
+                .filter(|filter| is_allowed(&self.allowed.read, filter))
+                .collect(),
+            send: requested
+                .send
+                .into_iter()
+                .filter(|filter| is_allowed(&self.allowed.send, filter))
+                .collect(),
+            requires_client: requested.requires_client && self.allowed.requires_client,
+            update_delayed_event: requested.update_delayed_event
+                && self.allowed.update_delayed_event,
+            send_delayed_event: requested.send_delayed_event && self.allowed.send_delayed_event,
+        }
+    }
+}
+
+/// Whether the two filters match the same events.
+fn same_filter(first: &EventFilter, second: &EventFilter) -> bool {
+    match (first, second) {
+        (
+            EventFilter::MessageLike(MessageLikeEventFilter::WithType(first)),
+            EventFilter::MessageLike(MessageLikeEventFilter::WithType(second)),
+        ) => first == second,
+        (
+            EventFilter::MessageLike(MessageLikeEventFilter::RoomMessageWithMsgtype(first)),
+            EventFilter::MessageLike(MessageLikeEventFilter::RoomMessageWithMsgtype(second)),
+        ) => first == second,
+        (
+            EventFilter::State(StateEventFilter::WithType(first)),
+            EventFilter::State(StateEventFilter::WithType(second)),
+        ) => first == second,
+        (
+            EventFilter::State(StateEventFilter::WithTypeAndStateKey(first, first_key)),
+            EventFilter::State(StateEventFilter::WithTypeAndStateKey(second, second_key)),
+        ) => first == second && first_key == second_key,
+        (EventFilter::ToDevice(first), EventFilter::ToDevice(second)) => {
+            first.event_type == second.event_type
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use matrix_sdk::widget::{
+        Capabilities, CapabilitiesProvider, EventFilter, MessageLikeEventFilter, StateEventFilter,
+    };
+    use ruma::{device_id, events::StateEventType, user_id};
+
+    use super::ElementCallCapabilitiesProvider;
+
+    #[tokio::test]
+    async fn test_element_call_capabilities_provider() {
+        let provider =
+            ElementCallCapabilitiesProvider::new(user_id!("@alice:localhost"), device_id!("ALICE"));
+
+        let requested = Capabilities {
+            read: vec![
+                EventFilter::State(StateEventFilter::WithType(StateEventType::CallMember)),
+                EventFilter::State(StateEventFilter::WithType(StateEventType::RoomPowerLevels)),
+            ],
+            send: vec![
+                EventFilter::State(StateEventFilter::WithTypeAndStateKey(
+                    StateEventType::CallMember,
+                    "_@alice:localhost_ALICE".to_owned(),
+                )),
+                // Element Call must not send memberships for other users.
+                EventFilter::State(StateEventFilter::WithTypeAndStateKey(
+                    StateEventType::CallMember,
+                    "_@bob:localhost_BOB".to_owned(),
+                )),
+                EventFilter::MessageLike(MessageLikeEventFilter::RoomMessageWithMsgtype(
+                    "m.text".to_owned(),
+                )),
+            ],
+            requires_client: true,
+            update_delayed_event: false,
+            send_delayed_event: true,
+        };
+
+        let granted = provider.acquire_capabilities(requested).await;
+
+        assert_eq!(granted.read.len(), 1);
+        assert_matches!(
+            &granted.read[0],
+            EventFilter::State(StateEventFilter::WithType(StateEventType::CallMember))
+        );
+        assert_eq!(granted.send.len(), 1);
+        assert_matches!(
+            &granted.send[0],
+            EventFilter::State(StateEventFilter::WithTypeAndStateKey(
+                StateEventType::CallMember,
+                state_key
+            ))
+        );
+        assert_eq!(state_key, "_@alice:localhost_ALICE");
+        assert!(granted.requires_client);
+        assert!(!granted.update_delayed_event);
+        assert!(granted.send_delayed_event);
+    }
+}
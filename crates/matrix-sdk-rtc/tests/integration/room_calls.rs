@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use matrix_sdk::{sleep::sleep, test_utils::mocks::MatrixMockServer, timeout::timeout};
+use matrix_sdk_rtc::{JoinCallOptions, RoomCalls};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder};
+use ruma::{
+    events::{call::member::ActiveFocus, AnySyncStateEvent},
+    room_id,
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch,
+};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{body_json, body_partial_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+/// The path to send the membership of the device of the user.
+const MEMBERSHIP_PATH: &str =
+    r"/state/org\.matrix\.msc3401\.call\.member/_(@|%40)example(:|%3A)localhost_DEVICEID$";
+
+/// The bodies of the memberships sent by the device of the user.
+async fn sent_memberships(server: &MatrixMockServer) -> Vec<JsonValue> {
+    server
+        .server()
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| request.url.path().contains("/state/org.matrix.msc3401.call.member/"))
+        .map(|request| request.body_json().unwrap())
+        .collect()
+}
+
+fn call_member_event(sender: &str, device_id: &str) -> Raw<AnySyncStateEvent> {
+    Raw::new(&json!({
+        "type": "org.matrix.msc3401.call.member",
+        "state_key": format!("_{sender}_{device_id}"),
+        "sender": sender,
+        "event_id": format!("${device_id}"),
+        "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+        "content": {
+            "application": "m.call",
+            "call_id": "",
+            "scope": "m.room",
+            "device_id": device_id,
+            "focus_active": { "type": "livekit", "focus_selection": "oldest_membership" },
+            "foci_preferred": [],
+        },
+    }))
+    .unwrap()
+    .cast()
+}
+
+fn focus_active() -> ActiveFocus {
+    serde_json::from_value(json!({ "type": "livekit", "focus_selection": "oldest_membership" }))
+        .unwrap()
+}
+
+#[async_test]
+async fn test_room_calls_tracks_the_participants() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!call:localhost");
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([call_member_event("@alice:localhost", "ALICE")]),
+        )
+        .await;
+
+    let room_calls = RoomCalls::new(room).await.unwrap();
+
+    let room_call = room_calls.room_call().unwrap();
+    assert_eq!(room_call.participants.len(), 1);
+    assert_eq!(room_call.participants[0].user_id, "@alice:localhost");
+
+    let mut subscriber = room_calls.subscribe();
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([call_member_event("@bob:localhost", "BOB")]),
+        )
+        .await;
+
+    let calls = timeout(subscriber.next(), Duration::from_secs(1)).await.unwrap().unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].participants.len(), 2);
+    assert_eq!(calls[0].participants[1].user_id, "@bob:localhost");
+}
+
+#[async_test]
+async fn test_room_calls_join_and_leave() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!call:localhost")).await;
+
+    let room_calls = RoomCalls::new(room).await.unwrap();
+    assert!(room_calls.calls().is_empty());
+
+    // The membership is sent when joining, then renewed.
+    Mock::given(method("PUT"))
+        .and(path_regex(MEMBERSHIP_PATH))
+        .and(body_partial_json(json!({
+            "application": "m.call",
+            "call_id": "",
+            "scope": "m.room",
+            "device_id": "DEVICEID",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$join" })))
+        .expect(2..)
+        .named("join")
+        .mount(server.server())
+        .await;
+
+    room_calls
+        .join(JoinCallOptions::new(focus_active()).expiration(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    assert!(room_calls.has_joined());
+
+    sleep(Duration::from_millis(150)).await;
+
+    let bodies = sent_memberships(&server).await;
+    assert!(bodies.len() >= 2);
+    // The membership keeps the same creation time, and its expiration is bumped.
+    assert_eq!(bodies[0]["created_ts"], bodies[1]["created_ts"]);
+    assert!(bodies[1]["expires"].as_u64().unwrap() > bodies[0]["expires"].as_u64().unwrap());
+
+    Mock::given(method("PUT"))
+        .and(path_regex(MEMBERSHIP_PATH))
+        .and(body_json(json!({})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$leave" })))
+        .expect(1)
+        .named("leave")
+        .mount(server.server())
+        .await;
+
+    room_calls.leave().await.unwrap();
+    assert!(!room_calls.has_joined());
+
+    // The membership is not renewed anymore.
+    let num_memberships = sent_memberships(&server).await.len();
+    sleep(Duration::from_millis(150)).await;
+    assert_eq!(sent_memberships(&server).await.len(), num_memberships);
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use assert_matches2::assert_matches;
+use matrix_sdk::{sleep::sleep, test_utils::mocks::MatrixMockServer, timeout::timeout};
+use matrix_sdk_rtc::voip::{
+    CallDirection, CallEndReason, CallState, VoipCalls, CANDIDATES_BATCH_DELAY,
+};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder};
+use ruma::{
+    event_id,
+    events::{
+        call::{candidates::Candidate, hangup::Reason, SessionDescription},
+        AnySyncTimelineEvent,
+    },
+    room_id,
+    serde::Raw,
+    user_id, MilliSecondsSinceUnixEpoch, OwnedVoipId, RoomId,
+};
+use serde_json::{json, Value as JsonValue};
+
+fn call_event(event_type: &str, content: JsonValue) -> Raw<AnySyncTimelineEvent> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+    Raw::new(&json!({
+        "type": event_type,
+        "sender": "@bob:localhost",
+        "event_id": format!("$call_event_{n}"),
+        "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+        "content": content,
+    }))
+    .unwrap()
+    .cast()
+}
+
+fn offer() -> SessionDescription {
+    SessionDescription::new("offer".to_owned(), "offer sdp".to_owned())
+}
+
+/// The bodies of the events of the given type sent by the client.
+async fn sent_events(server: &MatrixMockServer, event_type: &str) -> Vec<JsonValue> {
+    let path = format!("/send/{event_type}/");
+    server
+        .server()
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| request.url.path().contains(&path))
+        .map(|request| request.body_json().unwrap())
+        .collect()
+}
+
+async fn sync_call_event(
+    server: &MatrixMockServer,
+    client: &matrix_sdk::Client,
+    room_id: &RoomId,
+    event_type: &str,
+    content: JsonValue,
+) {
+    server
+        .sync_room(
+            client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(call_event(event_type, content)),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_outgoing_call() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!dm:localhost");
+    let room = server.sync_joined_room(&client, room_id).await;
+    server.mock_room_send().ok(event_id!("$sent")).mount().await;
+
+    let voip_calls = VoipCalls::new(&client);
+    let call =
+        voip_calls.place_call(&room, user_id!("@bob:localhost"), offer(), None).await.unwrap();
+    assert_eq!(call.direction(), CallDirection::Outgoing);
+    assert_eq!(call.state(), CallState::InviteSent);
+
+    let invites = sent_events(&server, "m.call.invite").await;
+    assert_eq!(invites.len(), 1);
+    assert_eq!(invites[0]["call_id"], call.call_id().as_str());
+    assert_eq!(invites[0]["invitee"], "@bob:localhost");
+    assert_eq!(invites[0]["version"], "1");
+
+    // The local candidates are batched.
+    call.add_local_candidates([Candidate::new("candidate 1".to_owned())]);
+    call.add_local_candidates([Candidate::new("candidate 2".to_owned())]);
+    sleep(CANDIDATES_BATCH_DELAY * 2).await;
+
+    let candidates = sent_events(&server, "m.call.candidates").await;
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0]["candidates"].as_array().unwrap().len(), 2);
+
+    // The candidates of a device of the opponent are kept until its answer is
+    // selected.
+    let mut remote_candidates = call.take_remote_candidates().unwrap();
+    for party_id in ["BOBPHONE", "BOBLAPTOP"] {
+        sync_call_event(
+            &server,
+            &client,
+            room_id,
+            "m.call.candidates",
+            json!({
+                "call_id": call.call_id(),
+                "party_id": party_id,
+                "version": "1",
+                "candidates": [{ "candidate": party_id }],
+            }),
+        )
+        .await;
+    }
+    assert!(remote_candidates.try_recv().is_err());
+
+    sync_call_event(
+        &server,
+        &client,
+        room_id,
+        "m.call.answer",
+        json!({
+            "call_id": call.call_id(),
+            "party_id": "BOBLAPTOP",
+            "version": "1",
+            "answer": { "type": "answer", "sdp": "answer sdp" },
+        }),
+    )
+    .await;
+
+    assert_eq!(call.state(), CallState::Connected);
+    assert_eq!(call.remote_description().unwrap().sdp, "answer sdp");
+
+    let received = remote_candidates.try_recv().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].candidate, "BOBLAPTOP");
+    assert!(remote_candidates.try_recv().is_err());
+
+    sleep(Duration::from_millis(50)).await;
+    let select_answers = sent_events(&server, "m.call.select_answer").await;
+    assert_eq!(select_answers.len(), 1);
+    assert_eq!(select_answers[0]["selected_party_id"], "BOBLAPTOP");
+
+    // Only the selected device of the opponent can hang up.
+    for party_id in ["BOBPHONE", "BOBLAPTOP"] {
+        sync_call_event(
+            &server,
+            &client,
+            room_id,
+            "m.call.hangup",
+            json!({
+                "call_id": call.call_id(),
+                "party_id": party_id,
+                "version": "1",
+                "reason": "user_hangup",
+            }),
+        )
+        .await;
+
+        if party_id == "BOBPHONE" {
+            assert_eq!(call.state(), CallState::Connected);
+        }
+    }
+
+    assert_eq!(
+        call.state(),
+        CallState::Ended(CallEndReason::Hangup { reason: Reason::UserHangup, by_opponent: true })
+    );
+    assert!(voip_calls.call(call.call_id()).is_none());
+}
+
+#[async_test]
+async fn test_incoming_call_and_glare() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!dm:localhost");
+    let room = server.sync_joined_room(&client, room_id).await;
+    server.mock_room_send().ok(event_id!("$sent")).mount().await;
+
+    let voip_calls = VoipCalls::new(&client);
+    let mut incoming_calls = voip_calls.subscribe_to_incoming_calls();
+
+    let outgoing =
+        voip_calls.place_call(&room, user_id!("@bob:localhost"), offer(), None).await.unwrap();
+
+    // Bob called at the same time, with a higher call ID: his call is ignored.
+    sync_call_event(
+        &server,
+        &client,
+        room_id,
+        "m.call.invite",
+        json!({
+            "call_id": "zzzz",
+            "party_id": "BOBLAPTOP",
+            "version": "1",
+            "lifetime": 60_000,
+            "offer": { "type": "offer", "sdp": "offer sdp" },
+        }),
+    )
+    .await;
+    assert!(incoming_calls.try_recv().is_err());
+    assert_eq!(outgoing.state(), CallState::InviteSent);
+
+    // Bob called at the same time, with a lower call ID: his call replaces ours.
+    sync_call_event(
+        &server,
+        &client,
+        room_id,
+        "m.call.invite",
+        json!({
+            "call_id": "0",
+            "party_id": "BOBLAPTOP",
+            "version": "1",
+            "lifetime": 60_000,
+            "offer": { "type": "offer", "sdp": "offer sdp" },
+        }),
+    )
+    .await;
+
+    let incoming = timeout(incoming_calls.recv(), Duration::from_secs(1)).await.unwrap().unwrap();
+    assert_eq!(incoming.call_id(), "0");
+    assert_eq!(incoming.direction(), CallDirection::Incoming);
+    assert_eq!(incoming.state(), CallState::Ringing);
+    assert_eq!(incoming.replaces(), Some(outgoing.call_id()));
+    assert_eq!(incoming.remote_description().unwrap().sdp, "offer sdp");
+    assert_matches!(outgoing.state(), CallState::Ended(CallEndReason::Replaced(call_id)));
+    assert_eq!(call_id, OwnedVoipId::from("0"));
+
+    // The replaced call is hung up, and forgotten.
+    assert!(voip_calls.call(outgoing.call_id()).is_none());
+    assert!(voip_calls.call(incoming.call_id()).is_some());
+    sleep(Duration::from_millis(100)).await;
+    let hangups = sent_events(&server, "m.call.hangup").await;
+    assert_eq!(hangups.len(), 1);
+    assert_eq!(hangups[0]["call_id"], outgoing.call_id().as_str());
+
+    // The local candidates are only sent once the call is answered.
+    incoming.add_local_candidates([Candidate::new("candidate".to_owned())]);
+    sleep(CANDIDATES_BATCH_DELAY * 2).await;
+    assert!(sent_events(&server, "m.call.candidates").await.is_empty());
+
+    incoming
+        .answer(SessionDescription::new("answer".to_owned(), "answer sdp".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(incoming.state(), CallState::Connected);
+
+    let answers = sent_events(&server, "m.call.answer").await;
+    assert_eq!(answers.len(), 1);
+    assert_eq!(answers[0]["call_id"], "0");
+
+    sleep(CANDIDATES_BATCH_DELAY * 2).await;
+    assert_eq!(sent_events(&server, "m.call.candidates").await.len(), 1);
+
+    incoming.hangup(Reason::UserHangup).await.unwrap();
+    assert_eq!(
+        incoming.state(),
+        CallState::Ended(CallEndReason::Hangup { reason: Reason::UserHangup, by_opponent: false })
+    );
+    assert_eq!(sent_events(&server, "m.call.hangup").await.len(), 2);
+    assert!(voip_calls.call(incoming.call_id()).is_none());
+}
@@ -21,7 +21,7 @@ use std::{
 };
 
 use async_trait::async_trait;
-use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
+use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool};
 use matrix_sdk_crypto::{
     olm::{
         InboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
@@ -41,7 +41,7 @@ use ruma::{
 };
 use rusqlite::{named_params, params_from_iter, OptionalExtension};
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::{fs, sync::Mutex};
+use tokio::sync::Mutex;
 use tracing::{debug, instrument, warn};
 use vodozemac::Curve25519PublicKey;
 
@@ -87,17 +87,10 @@ impl SqliteCryptoStore {
 
     /// Open the sqlite-based crypto store with the config open config.
     pub async fn open_with_config(config: SqliteStoreConfig) -> Result<Self, OpenStoreError> {
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
+        let pool = config.create_pool(DATABASE_NAME).await?;
 
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
-
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
-        config.pool = Some(pool_config);
-
-        let pool = config.create_pool(Runtime::Tokio1)?;
-
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
-        this.pool.get().await?.apply_runtime_config(runtime_config).await?;
+        let this = Self::open_with_pool(pool, config.passphrase.as_deref()).await?;
+        this.pool.get().await?.apply_runtime_config(config.runtime_config).await?;
 
         Ok(this)
     }
@@ -808,6 +801,7 @@ impl CryptoStore for SqliteCryptoStore {
         Ok(())
     }
 
+    #[instrument(name = "matrix_sdk.store_transaction", skip_all, fields(store = "crypto"))]
     async fn save_changes(&self, changes: Changes) -> Result<()> {
         // Serialize calls to `save_changes`; there are multiple await points below, and
         // we're pickling data as we go, so we don't want to invalidate data
@@ -1373,6 +1367,36 @@ impl CryptoStore for SqliteCryptoStore {
         Ok(())
     }
 
+    async fn clear(&self) -> Result<()> {
+        let _guard = self.save_changes_lock.lock().await;
+
+        self.acquire()
+            .await?
+            .with_transaction(|txn| {
+                // The version of the schema and the store cipher must survive, and the
+                // leased locks are kept.
+                txn.execute_batch(
+                    "DELETE FROM session;
+                     DELETE FROM inbound_group_session;
+                     DELETE FROM outbound_group_session;
+                     DELETE FROM device;
+                     DELETE FROM identity;
+                     DELETE FROM tracked_user;
+                     DELETE FROM olm_hash;
+                     DELETE FROM key_requests;
+                     DELETE FROM room_settings;
+                     DELETE FROM direct_withheld_info;
+                     DELETE FROM secrets;
+                     DELETE FROM kv WHERE key NOT IN ('version', 'cipher');",
+                )
+            })
+            .await?;
+
+        *self.static_account.write().unwrap() = None;
+
+        Ok(())
+    }
+
     async fn try_take_leased_lock(
         &self,
         lease_duration_ms: u32,
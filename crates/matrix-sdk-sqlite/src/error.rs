@@ -58,6 +58,10 @@ pub enum OpenStoreError {
     #[error("Failed to initialize the store cipher: {0}")]
     InitCipher(#[from] matrix_sdk_store_encryption::Error),
 
+    /// The passphrase is wrong, the store cipher can't be decrypted with it.
+    #[error("The passphrase of the store is wrong")]
+    WrongPassphrase,
+
     /// Failed to load the store cipher from the DB.
     #[error("Failed to load the store cipher from the DB: {0}")]
     LoadCipher(#[source] rusqlite::Error),
@@ -65,6 +69,21 @@ pub enum OpenStoreError {
     /// Failed to save the store cipher to the DB.
     #[error("Failed to save the store cipher to the DB: {0}")]
     SaveCipher(#[source] rusqlite::Error),
+
+    /// Failed to export a database to change its key.
+    #[cfg(feature = "sqlcipher")]
+    #[error("Failed to change the key of the database: {0}")]
+    ChangeDatabaseKey(#[source] rusqlite::Error),
+
+    /// Failed to replace a database by the copy that uses the new key.
+    #[cfg(feature = "sqlcipher")]
+    #[error("Failed to replace the database with its re-encrypted copy: {0}")]
+    ReplaceDatabase(#[source] io::Error),
+
+    /// The task exporting a database to change its key failed.
+    #[cfg(feature = "sqlcipher")]
+    #[error("The task changing the key of the database failed: {0}")]
+    ChangeDatabaseKeyTask(#[source] tokio::task::JoinError),
 }
 
 #[derive(Debug, Error)]
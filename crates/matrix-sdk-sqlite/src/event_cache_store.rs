@@ -17,17 +17,17 @@
 use std::{borrow::Cow, fmt, iter::once, path::Path, sync::Arc};
 
 use async_trait::async_trait;
-use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
+use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool};
 use matrix_sdk_base::{
     deserialized_responses::TimelineEvent,
     event_cache::{
         store::{
             compute_filters_string, extract_event_relation,
             media::{
-                EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaRetentionPolicy,
-                MediaService,
+                EventCacheStoreMedia, IgnoreMediaRetentionPolicy, MediaCacheUsage,
+                MediaRetentionPolicy, MediaService,
             },
-            EventCacheStore,
+            EventCacheStore, RetainedContent,
         },
         Event, Gap,
     },
@@ -39,11 +39,10 @@ use matrix_sdk_base::{
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::relation::RelationType, time::SystemTime, EventId, MilliSecondsSinceUnixEpoch, MxcUri,
-    OwnedEventId, RoomId,
+    OwnedEventId, OwnedRoomId, RoomId,
 };
 use rusqlite::{params_from_iter, OptionalExtension, ToSql, Transaction, TransactionBehavior};
-use tokio::fs;
-use tracing::{debug, error, trace};
+use tracing::{debug, error, instrument, trace};
 
 use crate::{
     error::{Error, Result},
@@ -72,7 +71,7 @@ const DATABASE_NAME: &str = "matrix-sdk-event-cache.sqlite3";
 /// This is used to figure whether the SQLite database requires a migration.
 /// Every new SQL migration should imply a bump of this number, and changes in
 /// the [`run_migrations`] function.
-const DATABASE_VERSION: u8 = 7;
+const DATABASE_VERSION: u8 = 8;
 
 /// The string used to identify a chunk of type events, in the `type` field in
 /// the database.
@@ -108,17 +107,10 @@ impl SqliteEventCacheStore {
 
     /// Open the sqlite-based event cache store with the config open config.
     pub async fn open_with_config(config: SqliteStoreConfig) -> Result<Self, OpenStoreError> {
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
+        let pool = config.create_pool(DATABASE_NAME).await?;
 
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
-
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
-        config.pool = Some(pool_config);
-
-        let pool = config.create_pool(Runtime::Tokio1)?;
-
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
-        this.pool.get().await?.apply_runtime_config(runtime_config).await?;
+        let this = Self::open_with_pool(pool, config.passphrase.as_deref()).await?;
+        this.pool.get().await?.apply_runtime_config(config.runtime_config).await?;
 
         Ok(this)
     }
@@ -417,6 +409,16 @@ async fn run_migrations(conn: &SqliteAsyncConn, version: u8) -> Result<()> {
         .await?;
     }
 
+    if version < 8 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!(
+                "../migrations/event_cache_store/008_retained_content.sql"
+            ))?;
+            txn.set_db_version(8)
+        })
+        .await?;
+    }
+
     Ok(())
 }
 
@@ -457,6 +459,7 @@ impl EventCacheStore for SqliteEventCacheStore {
         Ok(num_touched == 1)
     }
 
+    #[instrument(name = "matrix_sdk.store_transaction", skip_all, fields(store = "event_cache"))]
     async fn handle_linked_chunk_updates(
         &self,
         room_id: &RoomId,
@@ -863,10 +866,24 @@ impl EventCacheStore for SqliteEventCacheStore {
         self.acquire()
             .await?
             .with_transaction(move |txn| {
-                // Remove all the chunks, and let cascading do its job.
-                txn.execute("DELETE FROM linked_chunks", ())?;
-                // Also clear all the events' contents.
-                txn.execute("DELETE FROM events", ())
+                // Remove all the chunks of the rooms that aren't retained, and let cascading
+                // do its job.
+                txn.execute(
+                    "DELETE FROM linked_chunks WHERE room_id NOT IN (SELECT room_id FROM retained_rooms)",
+                    (),
+                )?;
+                // Also clear the contents of all the events that aren't retained.
+                txn.execute(
+                    r#"
+                        DELETE FROM events
+                        WHERE room_id NOT IN (SELECT room_id FROM retained_rooms)
+                        AND NOT EXISTS (
+                            SELECT 1 FROM retained_events re
+                            WHERE re.room_id = events.room_id AND re.event_id = events.event_id
+                        )
+                    "#,
+                    (),
+                )
             })
             .await?;
         Ok(())
@@ -1050,6 +1067,93 @@ impl EventCacheStore for SqliteEventCacheStore {
             .await
     }
 
+    async fn set_room_retained(&self, room_id: &RoomId, retained: bool) -> Result<()> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let conn = self.acquire().await?;
+
+        if retained {
+            let room_id_value = self.encode_value(room_id.as_bytes().to_owned())?;
+            conn.execute(
+                "INSERT OR REPLACE INTO retained_rooms(room_id, room_id_value) VALUES (?, ?)",
+                (hashed_room_id, room_id_value),
+            )
+            .await?;
+        } else {
+            conn.execute("DELETE FROM retained_rooms WHERE room_id = ?", (hashed_room_id,)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_event_retained(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        retained: bool,
+    ) -> Result<()> {
+        let hashed_room_id = self.encode_key(keys::LINKED_CHUNKS, room_id);
+        let event_id = event_id.to_string();
+        let conn = self.acquire().await?;
+
+        if retained {
+            let room_id_value = self.encode_value(room_id.as_bytes().to_owned())?;
+            conn.execute(
+                "INSERT OR REPLACE INTO retained_events(room_id, room_id_value, event_id) VALUES (?, ?, ?)",
+                (hashed_room_id, room_id_value, event_id),
+            )
+            .await?;
+        } else {
+            conn.execute(
+                "DELETE FROM retained_events WHERE room_id = ? AND event_id = ?",
+                (hashed_room_id, event_id),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn retained_content(&self) -> Result<RetainedContent> {
+        let this = self.clone();
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| -> Result<_> {
+                let decode_room_id = |value: Vec<u8>| -> Result<OwnedRoomId> {
+                    let value = this.decode_value(&value)?;
+                    let room_id = std::str::from_utf8(&value)
+                        .map_err(|err| Error::InvalidData { details: err.to_string() })?;
+                    OwnedRoomId::try_from(room_id)
+                        .map_err(|err| Error::InvalidData { details: err.to_string() })
+                };
+
+                let mut content = RetainedContent::default();
+
+                for room_id in txn
+                    .prepare("SELECT room_id_value FROM retained_rooms")?
+                    .query_map((), |row| row.get::<_, Vec<u8>>(0))?
+                {
+                    content.rooms.insert(decode_room_id(room_id?)?);
+                }
+
+                for row in txn
+                    .prepare("SELECT room_id_value, event_id FROM retained_events")?
+                    .query_map((), |row| {
+                        Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?))
+                    })?
+                {
+                    let (room_id, event_id) = row?;
+                    let event_id = OwnedEventId::try_from(event_id)
+                        .map_err(|err| Error::InvalidData { details: err.to_string() })?;
+
+                    content.events.entry(decode_room_id(room_id)?).or_default().insert(event_id);
+                }
+
+                Ok(content)
+            })
+            .await
+    }
+
     async fn add_media_content(
         &self,
         request: &MediaRequestParameters,
@@ -1132,6 +1236,10 @@ impl EventCacheStore for SqliteEventCacheStore {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.media_service.clean_up_media_cache(self).await
     }
+
+    async fn media_cache_usage(&self) -> Result<MediaCacheUsage, Self::Error> {
+        self.media_service.media_cache_usage(self).await
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -1395,6 +1503,23 @@ impl EventCacheStoreMedia for SqliteEventCacheStore {
         let conn = self.acquire().await?;
         conn.get_serialized_kv(keys::LAST_MEDIA_CLEANUP_TIME).await
     }
+
+    async fn media_cache_usage_inner(&self) -> Result<MediaCacheUsage, Self::Error> {
+        let conn = self.acquire().await?;
+        let usage = conn
+            .query_row(
+                "SELECT count(*), \
+                        ifnull(sum(length(data)), 0), \
+                        count(*) FILTER (WHERE ignore_policy IS TRUE), \
+                        ifnull(sum(length(data)) FILTER (WHERE ignore_policy IS TRUE), 0) \
+                 FROM media",
+                (),
+                |row| Ok(MediaCacheUsage::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .await?;
+
+        Ok(usage)
+    }
 }
 
 /// Like `deadpool::managed::Object::with_transaction`, but starts the
@@ -2436,13 +2561,20 @@ mod encrypted_tests {
     use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
 
     use matrix_sdk_base::{
-        event_cache::store::EventCacheStoreError, event_cache_store_integration_tests,
-        event_cache_store_integration_tests_time, event_cache_store_media_integration_tests,
+        event_cache::store::{
+            integration_tests::{check_test_event, make_test_event_with_event_id},
+            EventCacheStore, EventCacheStoreError,
+        },
+        event_cache_store_integration_tests, event_cache_store_integration_tests_time,
+        event_cache_store_media_integration_tests,
     };
+    use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
     use once_cell::sync::Lazy;
+    use ruma::event_id;
     use tempfile::{tempdir, TempDir};
 
     use super::SqliteEventCacheStore;
+    use crate::utils::SqliteAsyncConnExt;
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
     static NUM: AtomicU32 = AtomicU32::new(0);
@@ -2464,4 +2596,36 @@ mod encrypted_tests {
     event_cache_store_integration_tests!();
     event_cache_store_integration_tests_time!();
     event_cache_store_media_integration_tests!();
+
+    #[async_test]
+    async fn test_decrypted_events_are_encrypted_at_rest() {
+        let store = get_event_cache_store().await.unwrap();
+
+        let room_id = &*DEFAULT_TEST_ROOM_ID;
+        let event_id = event_id!("$secret");
+
+        // Decrypted events are persisted with their cleartext content…
+        let event = make_test_event_with_event_id(room_id, "the cake is a lie", Some(event_id));
+        store.save_event(room_id, event).await.unwrap();
+
+        // … which is encrypted with the store cipher before hitting the disk.
+        let content: Vec<u8> = store
+            .acquire()
+            .await
+            .unwrap()
+            .with_transaction(move |txn| {
+                txn.query_row(
+                    "SELECT content FROM events WHERE event_id = ?",
+                    (event_id.as_str(),),
+                    |row| row.get(0),
+                )
+            })
+            .await
+            .unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("the cake is a lie"));
+
+        // The decrypted event can be read back without decrypting it again.
+        let event = store.find_event(room_id, event_id).await.unwrap().unwrap();
+        check_test_event(&event, "the cake is a lie");
+    }
 }
@@ -21,6 +21,9 @@ mod crypto_store;
 mod error;
 #[cfg(feature = "event-cache")]
 mod event_cache_store;
+mod passphrase;
+#[cfg(feature = "sqlcipher")]
+mod sqlcipher;
 #[cfg(feature = "state-store")]
 mod state_store;
 mod utils;
@@ -29,19 +32,30 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use deadpool_sqlite::PoolConfig;
+use deadpool_sqlite::{CreatePoolError, Pool as SqlitePool, PoolConfig, Runtime};
+use tokio::fs;
 
 #[cfg(feature = "crypto-store")]
 pub use self::crypto_store::SqliteCryptoStore;
-pub use self::error::OpenStoreError;
 #[cfg(feature = "event-cache")]
 pub use self::event_cache_store::SqliteEventCacheStore;
+#[cfg(feature = "sqlcipher")]
+pub use self::sqlcipher::change_database_key;
 #[cfg(feature = "state-store")]
 pub use self::state_store::SqliteStateStore;
+pub use self::{error::OpenStoreError, passphrase::change_passphrase};
 
 #[cfg(test)]
 matrix_sdk_test::init_tracing_for_tests!();
 
+/// The file names of the databases of all the stores.
+///
+/// They are listed here rather than taken from the stores, because the
+/// databases of stores that are not enabled in this build should be handled
+/// too.
+const DATABASE_NAMES: [&str; 3] =
+    ["matrix-sdk-state.sqlite3", "matrix-sdk-crypto.sqlite3", "matrix-sdk-event-cache.sqlite3"];
+
 /// A configuration structure used for opening a store.
 #[derive(Clone)]
 pub struct SqliteStoreConfig {
@@ -53,6 +67,9 @@ pub struct SqliteStoreConfig {
     pool_config: PoolConfig,
     /// The runtime configuration to apply when opening an SQLite connection.
     runtime_config: RuntimeConfig,
+    /// The key used by SQLCipher to encrypt the whole database, if any.
+    #[cfg(feature = "sqlcipher")]
+    database_key: Option<String>,
 }
 
 impl fmt::Debug for SqliteStoreConfig {
@@ -78,6 +95,8 @@ impl SqliteStoreConfig {
             passphrase: None,
             pool_config: PoolConfig::new(num_cpus::get_physical() * 4),
             runtime_config: RuntimeConfig::default(),
+            #[cfg(feature = "sqlcipher")]
+            database_key: None,
         }
     }
 
@@ -118,6 +137,34 @@ impl SqliteStoreConfig {
         self
     }
 
+    /// Get the path of the directory containing the store database.
+    ///
+    /// Use [`Self::path`] to override it.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the passphrase used to encode the store, if any.
+    ///
+    /// Use [`Self::passphrase`] to define it.
+    pub fn get_passphrase(&self) -> Option<&str> {
+        self.passphrase.as_deref()
+    }
+
+    /// Define the key used by SQLCipher to encrypt the whole database, if any.
+    ///
+    /// Contrary to the [passphrase][Self::passphrase], which is used to
+    /// encrypt the values stored in the database, this also encrypts the
+    /// schema, the keys and the metadata of the database.
+    ///
+    /// An existing database can be encrypted, or its key can be changed, with
+    /// [`change_database_key()`].
+    #[cfg(feature = "sqlcipher")]
+    pub fn database_key(mut self, key: Option<&str>) -> Self {
+        self.database_key = key.map(|key| key.to_owned());
+        self
+    }
+
     /// Define the maximum pool size for [`deadpool_sqlite`].
     ///
     /// See [`deadpool_sqlite::PoolConfig::max_size`] to learn more.
@@ -177,6 +224,25 @@ impl SqliteStoreConfig {
         self.runtime_config.journal_size_limit = limit;
         self
     }
+
+    /// Create a pool of connections to the database with the given file name,
+    /// in the directory of this configuration.
+    async fn create_pool(&self, database_name: &str) -> Result<SqlitePool, OpenStoreError> {
+        fs::create_dir_all(&self.path).await.map_err(OpenStoreError::CreateDir)?;
+
+        let mut config = deadpool_sqlite::Config::new(self.path.join(database_name));
+        config.pool = Some(self.pool_config);
+
+        let builder = config.builder(Runtime::Tokio1).map_err(CreatePoolError::Config)?;
+
+        #[cfg(feature = "sqlcipher")]
+        let builder = match &self.database_key {
+            Some(key) => builder.post_create(sqlcipher::set_key_hook(key.clone())),
+            None => builder,
+        };
+
+        Ok(builder.build().map_err(CreatePoolError::Build)?)
+    }
 }
 
 /// This type represents values to set at runtime when a database is opened.
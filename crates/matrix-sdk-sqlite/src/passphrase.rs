@@ -0,0 +1,335 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rotation of the passphrase that encrypts the store ciphers.
+
+use deadpool_sqlite::Pool as SqlitePool;
+use matrix_sdk_store_encryption::{Error as StoreEncryptionError, StoreCipher};
+use tokio::fs;
+use tracing::{debug, error};
+
+use crate::{
+    utils::{
+        map_import_cipher_error, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
+        SqliteKeyValueStoreConnExt, PREVIOUS_CIPHER_KEY,
+    },
+    OpenStoreError, SqliteStoreConfig, DATABASE_NAMES,
+};
+
+/// Change the passphrase that encrypts the values of the stores in the
+/// directory of the given config.
+///
+/// Only the store cipher of each database is encrypted again with the new
+/// passphrase, so this is fast even for large databases. The path and the
+/// database key of `config` are used to open the databases, its passphrase is
+/// ignored.
+///
+/// The passphrase of every database is checked before any of them is changed,
+/// and [`OpenStoreError::WrongPassphrase`] is returned if `old_passphrase` is
+/// wrong for one of them. Databases that don't use a passphrase are left
+/// untouched.
+///
+/// The previous store cipher of each database is kept until all of them have
+/// been updated. If updating one of them fails, the databases that were
+/// already updated are restored, so `old_passphrase` stays valid. If the
+/// change is interrupted, opening a store with `old_passphrase` restores its
+/// database, and calling this function again with the same passphrases
+/// completes the change.
+///
+/// The stores must not be open while this function runs.
+pub async fn change_passphrase(
+    config: &SqliteStoreConfig,
+    old_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<(), OpenStoreError> {
+    let mut changes = Vec::new();
+
+    for database_name in DATABASE_NAMES {
+        let database_path = config.path.join(database_name);
+
+        // If it can't be checked, opening the database will report the error.
+        if !fs::try_exists(&database_path).await.unwrap_or(true) {
+            continue;
+        }
+
+        let pool = config.create_pool(database_name).await?;
+        let conn = pool.get().await?;
+
+        let Some(mut old_export) =
+            conn.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?
+        else {
+            debug!(database_name, "The database doesn't use a passphrase");
+            continue;
+        };
+
+        let new_export =
+            match StoreCipher::change_passphrase(&old_export, old_passphrase, new_passphrase) {
+                Ok(new_export) => new_export,
+                Err(StoreEncryptionError::WrongPassphraseOrKey) => {
+                    // A previous change may have been interrupted after this database was
+                    // updated.
+                    let Some(previous) = conn
+                        .get_kv(PREVIOUS_CIPHER_KEY)
+                        .await
+                        .map_err(OpenStoreError::LoadCipher)?
+                    else {
+                        return Err(OpenStoreError::WrongPassphrase);
+                    };
+
+                    let new_export =
+                        StoreCipher::change_passphrase(&previous, old_passphrase, new_passphrase)
+                            .map_err(map_import_cipher_error)?;
+                    old_export = previous;
+
+                    new_export
+                }
+                Err(error) => return Err(map_import_cipher_error(error)),
+            };
+
+        changes.push((database_name, pool, old_export, new_export));
+    }
+
+    for (index, (database_name, pool, old_export, new_export)) in changes.iter().enumerate() {
+        debug!(database_name, "Changing the passphrase of the database");
+
+        if let Err(error) = stage_cipher(pool, old_export.clone(), new_export.clone()).await {
+            for (database_name, pool, old_export, _) in &changes[..index] {
+                if let Err(error) = restore_cipher(pool, old_export.clone()).await {
+                    // It will be restored the next time the database is opened.
+                    error!(database_name, "Couldn't restore the previous store cipher: {error}");
+                }
+            }
+
+            return Err(error);
+        }
+    }
+
+    // Every database uses the new passphrase, the previous store ciphers can go.
+    for (_, pool, _, _) in &changes {
+        pool.get()
+            .await?
+            .clear_kv(PREVIOUS_CIPHER_KEY)
+            .await
+            .map_err(OpenStoreError::SaveCipher)?;
+    }
+
+    Ok(())
+}
+
+/// Replace the store cipher of the database with the new export, keeping the
+/// old one until the passphrase of every database has been changed.
+async fn stage_cipher(
+    pool: &SqlitePool,
+    old_export: Vec<u8>,
+    new_export: Vec<u8>,
+) -> Result<(), OpenStoreError> {
+    pool.get()
+        .await?
+        .with_transaction(move |txn| {
+            txn.set_kv(PREVIOUS_CIPHER_KEY, &old_export)?;
+            txn.set_kv("cipher", &new_export)
+        })
+        .await
+        .map_err(OpenStoreError::SaveCipher)
+}
+
+/// Restore the old store cipher of a database, after the passphrase of
+/// another database couldn't be changed.
+async fn restore_cipher(pool: &SqlitePool, old_export: Vec<u8>) -> Result<(), OpenStoreError> {
+    pool.get()
+        .await?
+        .with_transaction(move |txn| {
+            txn.set_kv("cipher", &old_export)?;
+            txn.clear_kv(PREVIOUS_CIPHER_KEY)
+        })
+        .await
+        .map_err(OpenStoreError::SaveCipher)
+}
+
+#[cfg(all(test, feature = "state-store"))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::store::StateStore;
+    use matrix_sdk_store_encryption::StoreCipher;
+    use matrix_sdk_test::async_test;
+    use tempfile::tempdir;
+
+    use super::change_passphrase;
+    use crate::{utils::PREVIOUS_CIPHER_KEY, OpenStoreError, SqliteStateStore, SqliteStoreConfig};
+
+    const CUSTOM_KEY: &[u8] = b"custom";
+    const STATE_DB: &str = "matrix-sdk-state.sqlite3";
+    #[cfg(feature = "event-cache")]
+    const EVENT_CACHE_DB: &str = "matrix-sdk-event-cache.sqlite3";
+
+    async fn open_store(
+        path: &std::path::Path,
+        passphrase: &str,
+    ) -> Result<SqliteStateStore, OpenStoreError> {
+        SqliteStateStore::open_with_config(
+            SqliteStoreConfig::new(path).passphrase(Some(passphrase)),
+        )
+        .await
+    }
+
+    #[async_test]
+    async fn test_change_passphrase() {
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), "old").await.unwrap();
+        store.set_custom_value(CUSTOM_KEY, b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        let config = SqliteStoreConfig::new(dir.path());
+
+        // The old passphrase must be correct.
+        assert_matches!(
+            change_passphrase(&config, "wrong", "new").await,
+            Err(OpenStoreError::WrongPassphrase)
+        );
+
+        change_passphrase(&config, "old", "new").await.unwrap();
+
+        assert_matches!(open_store(dir.path(), "old").await, Err(OpenStoreError::WrongPassphrase));
+
+        // The values are still readable with the new passphrase.
+        let store = open_store(dir.path(), "new").await.unwrap();
+        assert_eq!(
+            store.get_custom_value(CUSTOM_KEY).await.unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+    }
+
+    /// Whether the database still has a previous store cipher.
+    fn has_previous_cipher(path: &std::path::Path, database_name: &str) -> bool {
+        rusqlite::Connection::open(path.join(database_name))
+            .unwrap()
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM kv WHERE key = ?1)",
+                (PREVIOUS_CIPHER_KEY,),
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[cfg(feature = "event-cache")]
+    #[async_test]
+    async fn test_change_passphrase_is_rolled_back_on_failure() {
+        use crate::SqliteEventCacheStore;
+
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), "old").await.unwrap();
+        store.set_custom_value(CUSTOM_KEY, b"value".to_vec()).await.unwrap();
+        drop(store);
+        drop(
+            SqliteEventCacheStore::open_with_config(
+                SqliteStoreConfig::new(dir.path()).passphrase(Some("old")),
+            )
+            .await
+            .unwrap(),
+        );
+
+        // Make changing the store cipher of the event cache database fail, after the
+        // state database has been updated.
+        let event_cache_db = rusqlite::Connection::open(dir.path().join(EVENT_CACHE_DB)).unwrap();
+        event_cache_db
+            .execute_batch(
+                "CREATE TRIGGER fail_cipher BEFORE UPDATE ON kv WHEN NEW.key = 'cipher'
+                 BEGIN SELECT RAISE(ABORT, 'read-only'); END;",
+            )
+            .unwrap();
+
+        let config = SqliteStoreConfig::new(dir.path());
+        assert_matches!(
+            change_passphrase(&config, "old", "new").await,
+            Err(OpenStoreError::SaveCipher(_))
+        );
+
+        event_cache_db.execute_batch("DROP TRIGGER fail_cipher").unwrap();
+        drop(event_cache_db);
+
+        // The state database has been restored, so the old passphrase is still valid
+        // everywhere.
+        assert!(!has_previous_cipher(dir.path(), STATE_DB));
+        assert!(!has_previous_cipher(dir.path(), EVENT_CACHE_DB));
+        assert_matches!(open_store(dir.path(), "new").await, Err(OpenStoreError::WrongPassphrase));
+
+        let store = open_store(dir.path(), "old").await.unwrap();
+        assert_eq!(
+            store.get_custom_value(CUSTOM_KEY).await.unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+        drop(store);
+        SqliteEventCacheStore::open_with_config(
+            SqliteStoreConfig::new(dir.path()).passphrase(Some("old")),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[async_test]
+    async fn test_interrupted_change_passphrase() {
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), "old").await.unwrap();
+        store.set_custom_value(CUSTOM_KEY, b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        // Simulate a change of the passphrase interrupted after the state database
+        // has been updated.
+        let state_db = rusqlite::Connection::open(dir.path().join(STATE_DB)).unwrap();
+        let old_export: Vec<u8> = state_db
+            .query_row("SELECT value FROM kv WHERE key = 'cipher'", (), |row| row.get(0))
+            .unwrap();
+        let new_export = StoreCipher::change_passphrase(&old_export, "old", "new").unwrap();
+        state_db
+            .execute("INSERT INTO kv VALUES (?1, ?2)", (PREVIOUS_CIPHER_KEY, old_export))
+            .unwrap();
+        state_db.execute("UPDATE kv SET value = ?1 WHERE key = 'cipher'", (new_export,)).unwrap();
+        drop(state_db);
+
+        // The change can be completed.
+        let config = SqliteStoreConfig::new(dir.path());
+        change_passphrase(&config, "old", "new").await.unwrap();
+        assert!(!has_previous_cipher(dir.path(), STATE_DB));
+        assert_matches!(open_store(dir.path(), "old").await, Err(OpenStoreError::WrongPassphrase));
+        drop(open_store(dir.path(), "new").await.unwrap());
+
+        // Simulate the interruption again, this time from "new" to "newer".
+        let state_db = rusqlite::Connection::open(dir.path().join(STATE_DB)).unwrap();
+        let old_export: Vec<u8> = state_db
+            .query_row("SELECT value FROM kv WHERE key = 'cipher'", (), |row| row.get(0))
+            .unwrap();
+        let new_export = StoreCipher::change_passphrase(&old_export, "new", "newer").unwrap();
+        state_db
+            .execute("INSERT INTO kv VALUES (?1, ?2)", (PREVIOUS_CIPHER_KEY, old_export))
+            .unwrap();
+        state_db.execute("UPDATE kv SET value = ?1 WHERE key = 'cipher'", (new_export,)).unwrap();
+        drop(state_db);
+
+        // Opening the store with the old passphrase restores the previous store cipher.
+        let store = open_store(dir.path(), "new").await.unwrap();
+        assert_eq!(
+            store.get_custom_value(CUSTOM_KEY).await.unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+        drop(store);
+        assert!(!has_previous_cipher(dir.path(), STATE_DB));
+        assert_matches!(
+            open_store(dir.path(), "newer").await,
+            Err(OpenStoreError::WrongPassphrase)
+        );
+    }
+}
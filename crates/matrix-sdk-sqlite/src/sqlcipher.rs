@@ -0,0 +1,353 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Full-database encryption with SQLCipher.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use deadpool_sqlite::{Hook, HookError};
+use rusqlite::Connection;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, error};
+
+use crate::{OpenStoreError, DATABASE_NAMES};
+
+/// The suffix of the copy of a database that uses the new key.
+const REKEY_SUFFIX: &str = ".rekey";
+
+/// The suffix of a database that uses the old key, while it is being
+/// replaced.
+const OLD_SUFFIX: &str = ".old";
+
+/// Create a hook that sets the given key on new connections.
+///
+/// It also checks that the key is valid, because SQLCipher only reports
+/// invalid keys when the database is read for the first time.
+pub(crate) fn set_key_hook(key: String) -> Hook {
+    Hook::async_fn(move |conn, _| {
+        let key = key.clone();
+
+        Box::pin(async move {
+            conn.interact(move |conn| set_key(conn, &key))
+                .await
+                .map_err(|error| HookError::message(error.to_string()))?
+                .map_err(HookError::Backend)
+        })
+    })
+}
+
+/// Set the key of the database of the given connection, and check that it is
+/// valid.
+fn set_key(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    conn.query_row("SELECT count(*) FROM sqlite_master", (), |_| Ok(()))
+}
+
+/// Change the key used by SQLCipher to encrypt the databases of the stores in
+/// the given directory.
+///
+/// `old_key` is the key the databases are currently encrypted with, and
+/// `new_key` is the key they should be encrypted with. Using `None` means that
+/// the database is not encrypted, so this can be used to encrypt existing
+/// databases, to rotate their key, or to decrypt them.
+///
+/// The content of each database is first exported to a new database encrypted
+/// with the new key. Only once all of them have been exported successfully are
+/// they swapped with the old ones, which are kept until all the databases have
+/// been replaced. If an error occurs, the databases that were already replaced
+/// are restored, so either all the databases use the new key, or none of them
+/// do.
+///
+/// The stores must not be open while this function runs.
+pub async fn change_database_key(
+    path: impl AsRef<Path>,
+    old_key: Option<&str>,
+    new_key: Option<&str>,
+) -> Result<(), OpenStoreError> {
+    let mut database_paths = Vec::new();
+
+    for database_name in DATABASE_NAMES {
+        let database_path = path.as_ref().join(database_name);
+
+        if fs::try_exists(&database_path).await.map_err(OpenStoreError::ReplaceDatabase)? {
+            database_paths.push(database_path);
+        }
+    }
+
+    // Export all the databases first, the old ones are left untouched if one of
+    // the exports fails.
+    for database_path in &database_paths {
+        if let Err(error) = export_with_new_key(database_path, old_key, new_key).await {
+            for database_path in &database_paths {
+                let _ = remove_file_if_exists(&with_suffix(database_path, REKEY_SUFFIX)).await;
+            }
+
+            return Err(error);
+        }
+    }
+
+    // Then swap them in, keeping the old databases around to be able to roll back.
+    let mut replaced = Vec::with_capacity(database_paths.len());
+
+    for database_path in &database_paths {
+        if let Err(error) = swap_in_new_database(database_path).await {
+            for database_path in replaced {
+                restore_old_database(database_path).await;
+            }
+
+            for database_path in &database_paths {
+                let _ = remove_file_if_exists(&with_suffix(database_path, REKEY_SUFFIX)).await;
+            }
+
+            return Err(error);
+        }
+
+        replaced.push(database_path);
+    }
+
+    for database_path in &database_paths {
+        remove_file_if_exists(&with_suffix(database_path, OLD_SUFFIX)).await?;
+    }
+
+    Ok(())
+}
+
+/// Export the database at the given path to a copy that uses the new key.
+async fn export_with_new_key(
+    database_path: &Path,
+    old_key: Option<&str>,
+    new_key: Option<&str>,
+) -> Result<(), OpenStoreError> {
+    debug!(?database_path, "Changing the key of the database");
+
+    let export_path = with_suffix(database_path, REKEY_SUFFIX);
+    // Remove the leftover of a previous failed attempt.
+    remove_file_if_exists(&export_path).await?;
+
+    let old_key = old_key.map(ToOwned::to_owned);
+    let new_key = new_key.map(ToOwned::to_owned);
+    let source_path = database_path.to_owned();
+
+    spawn_blocking(move || {
+        export_database(&source_path, old_key.as_deref(), &export_path, new_key.as_deref())
+    })
+    .await
+    .map_err(OpenStoreError::ChangeDatabaseKeyTask)?
+    .map_err(OpenStoreError::ChangeDatabaseKey)
+}
+
+/// Replace the database at the given path with its copy that uses the new
+/// key, and keep the old one next to it.
+async fn swap_in_new_database(database_path: &Path) -> Result<(), OpenStoreError> {
+    // The source database was closed properly, so its WAL file should have been
+    // removed, but make sure that no file is left that could be applied to the new
+    // database.
+    remove_file_if_exists(&with_suffix(database_path, "-wal")).await?;
+    remove_file_if_exists(&with_suffix(database_path, "-shm")).await?;
+
+    fs::rename(database_path, with_suffix(database_path, OLD_SUFFIX))
+        .await
+        .map_err(OpenStoreError::ReplaceDatabase)?;
+
+    if let Err(error) = fs::rename(with_suffix(database_path, REKEY_SUFFIX), database_path).await {
+        restore_old_database(database_path).await;
+        return Err(OpenStoreError::ReplaceDatabase(error));
+    }
+
+    Ok(())
+}
+
+/// Put back the database that uses the old key at the given path.
+async fn restore_old_database(database_path: &Path) {
+    if let Err(error) = fs::rename(with_suffix(database_path, OLD_SUFFIX), database_path).await {
+        error!(?database_path, "Failed to restore the database with the old key: {error}");
+    }
+}
+
+/// Export the content of the database at `source_path`, encrypted with
+/// `source_key`, to a new database at `target_path`, encrypted with
+/// `target_key`.
+fn export_database(
+    source_path: &Path,
+    source_key: Option<&str>,
+    target_path: &Path,
+    target_key: Option<&str>,
+) -> rusqlite::Result<()> {
+    let conn = Connection::open(source_path)?;
+
+    if let Some(key) = source_key {
+        set_key(&conn, key)?;
+    }
+
+    // An empty key means that the attached database is not encrypted.
+    conn.execute(
+        "ATTACH DATABASE ?1 AS target KEY ?2",
+        (target_path.to_string_lossy(), target_key.unwrap_or_default()),
+    )?;
+    conn.query_row("SELECT sqlcipher_export('target')", (), |_| Ok(()))?;
+    conn.execute("DETACH DATABASE target", ())?;
+    conn.close().map_err(|(_, error)| error)?;
+
+    // The journal mode is not exported, and the stores use WAL.
+    let conn = Connection::open(target_path)?;
+
+    if let Some(key) = target_key {
+        set_key(&conn, key)?;
+    }
+
+    conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+    conn.close().map_err(|(_, error)| error)
+}
+
+/// Append the given suffix to the file name of the given path.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+async fn remove_file_if_exists(path: &Path) -> Result<(), OpenStoreError> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            Err(OpenStoreError::ReplaceDatabase(error))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "state-store"))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::store::StateStore;
+    use matrix_sdk_test::async_test;
+    use tempfile::tempdir;
+
+    use super::change_database_key;
+    use crate::{OpenStoreError, SqliteStateStore, SqliteStoreConfig};
+
+    const CUSTOM_KEY: &str = "custom";
+
+    async fn open_store(path: &std::path::Path, key: Option<&str>) -> SqliteStateStore {
+        let config = SqliteStoreConfig::new(path).database_key(key);
+        SqliteStateStore::open_with_config(config).await.unwrap()
+    }
+
+    async fn open_store_err(path: &std::path::Path, key: Option<&str>) -> OpenStoreError {
+        let config = SqliteStoreConfig::new(path).database_key(key);
+        SqliteStateStore::open_with_config(config).await.unwrap_err()
+    }
+
+    async fn assert_has_custom_value(store: &SqliteStateStore) {
+        assert_eq!(
+            store.get_custom_value(CUSTOM_KEY.as_bytes()).await.unwrap().as_deref(),
+            Some(&b"value"[..])
+        );
+    }
+
+    #[async_test]
+    async fn test_open_with_database_key() {
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), Some("secret")).await;
+        store.set_custom_value(CUSTOM_KEY.as_bytes(), b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        // The database can't be read without the key, or with another key.
+        assert_matches!(open_store_err(dir.path(), None).await, OpenStoreError::LoadVersion(_));
+        assert_matches!(open_store_err(dir.path(), Some("wrong")).await, OpenStoreError::Pool(_));
+
+        let store = open_store(dir.path(), Some("secret")).await;
+        assert_has_custom_value(&store).await;
+    }
+
+    #[async_test]
+    async fn test_encrypt_existing_database() {
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), None).await;
+        store.set_custom_value(CUSTOM_KEY.as_bytes(), b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        change_database_key(dir.path(), None, Some("secret")).await.unwrap();
+
+        assert_matches!(open_store_err(dir.path(), None).await, OpenStoreError::LoadVersion(_));
+
+        let store = open_store(dir.path(), Some("secret")).await;
+        assert_has_custom_value(&store).await;
+    }
+
+    #[async_test]
+    async fn test_rotate_database_key() {
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), Some("old")).await;
+        store.set_custom_value(CUSTOM_KEY.as_bytes(), b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        // The old key must be correct.
+        assert_matches!(
+            change_database_key(dir.path(), Some("wrong"), Some("new")).await,
+            Err(OpenStoreError::ChangeDatabaseKey(_))
+        );
+
+        change_database_key(dir.path(), Some("old"), Some("new")).await.unwrap();
+
+        assert_matches!(open_store_err(dir.path(), Some("old")).await, OpenStoreError::Pool(_));
+
+        let store = open_store(dir.path(), Some("new")).await;
+        assert_has_custom_value(&store).await;
+        drop(store);
+
+        // The database can be decrypted too.
+        change_database_key(dir.path(), Some("new"), None).await.unwrap();
+
+        let store = open_store(dir.path(), None).await;
+        assert_has_custom_value(&store).await;
+    }
+
+    #[cfg(feature = "event-cache")]
+    #[async_test]
+    async fn test_change_database_key_is_atomic() {
+        use crate::SqliteEventCacheStore;
+
+        let dir = tempdir().unwrap();
+
+        let store = open_store(dir.path(), Some("old")).await;
+        store.set_custom_value(CUSTOM_KEY.as_bytes(), b"value".to_vec()).await.unwrap();
+        drop(store);
+
+        // The event cache database uses another key, so it can't be exported.
+        let config = SqliteStoreConfig::new(dir.path()).database_key(Some("other"));
+        drop(SqliteEventCacheStore::open_with_config(config).await.unwrap());
+
+        assert_matches!(
+            change_database_key(dir.path(), Some("old"), Some("new")).await,
+            Err(OpenStoreError::ChangeDatabaseKey(_))
+        );
+
+        // The state database hasn't been changed either.
+        let store = open_store(dir.path(), Some("old")).await;
+        assert_has_custom_value(&store).await;
+        drop(store);
+
+        // And no temporary file was left behind.
+        let mut file_names = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap());
+        assert!(!file_names.any(|name| name.ends_with(".rekey") || name.ends_with(".old")));
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 };
 
 use async_trait::async_trait;
-use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
+use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool};
 use matrix_sdk_base::{
     deserialized_responses::{DisplayName, RawAnySyncOrStrippedState, SyncOrStrippedState},
     store::{
@@ -37,8 +37,7 @@ use ruma::{
 };
 use rusqlite::{OptionalExtension, Transaction};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tokio::fs;
-use tracing::{debug, warn};
+use tracing::{debug, instrument, warn};
 
 use crate::{
     error::{Error, Result},
@@ -99,17 +98,10 @@ impl SqliteStateStore {
 
     /// Open the sqlite-based state store with the config open config.
     pub async fn open_with_config(config: SqliteStoreConfig) -> Result<Self, OpenStoreError> {
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
+        let pool = config.create_pool(DATABASE_NAME).await?;
 
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
-
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
-        config.pool = Some(pool_config);
-
-        let pool = config.create_pool(Runtime::Tokio1)?;
-
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
-        this.pool.get().await?.apply_runtime_config(runtime_config).await?;
+        let this = Self::open_with_pool(pool, config.passphrase.as_deref()).await?;
+        this.pool.get().await?.apply_runtime_config(config.runtime_config).await?;
 
         Ok(this)
     }
@@ -1093,6 +1085,7 @@ impl StateStore for SqliteStateStore {
         self.acquire().await?.delete_kv_blob(self.encode_state_store_data_key(key)).await
     }
 
+    #[instrument(name = "matrix_sdk.store_transaction", skip_all, fields(store = "state"))]
     async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
         let changes = changes.to_owned();
         let this = self.clone();
@@ -18,10 +18,11 @@ use std::{borrow::Borrow, cmp::min, iter, ops::Deref};
 use async_trait::async_trait;
 use deadpool_sqlite::Object as SqliteAsyncConn;
 use itertools::Itertools;
-use matrix_sdk_store_encryption::StoreCipher;
+use matrix_sdk_store_encryption::{Error as StoreEncryptionError, StoreCipher};
 use ruma::time::SystemTime;
 use rusqlite::{limits::Limit, OptionalExtension, Params, Row, Statement, Transaction};
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::warn;
 
 use crate::{
     error::{Error, Result},
@@ -380,6 +381,12 @@ impl SqliteKeyValueStoreConnExt for rusqlite::Connection {
     }
 }
 
+/// The key of the previous store cipher, kept while the passphrase of the
+/// databases is being changed by [`change_passphrase()`].
+///
+/// [`change_passphrase()`]: crate::change_passphrase
+pub(crate) const PREVIOUS_CIPHER_KEY: &str = "cipher_previous";
+
 /// Extension trait for an [`SqliteAsyncConn`] that contains a key-value
 /// table named `kv`.
 ///
@@ -449,6 +456,10 @@ pub(crate) trait SqliteKeyValueStoreAsyncConnExt: SqliteAsyncConnExt {
     }
 
     /// Get the [`StoreCipher`] of the database or create it.
+    ///
+    /// If a change of the passphrase was interrupted after this database was
+    /// updated, and the old passphrase is used, the previous store cipher is
+    /// restored.
     async fn get_or_create_store_cipher(
         &self,
         passphrase: &str,
@@ -456,7 +467,32 @@ pub(crate) trait SqliteKeyValueStoreAsyncConnExt: SqliteAsyncConnExt {
         let encrypted_cipher = self.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?;
 
         let cipher = if let Some(encrypted) = encrypted_cipher {
-            StoreCipher::import(passphrase, &encrypted)?
+            match StoreCipher::import(passphrase, &encrypted) {
+                Ok(cipher) => cipher,
+                Err(StoreEncryptionError::WrongPassphraseOrKey) => {
+                    let Some(previous) = self
+                        .get_kv(PREVIOUS_CIPHER_KEY)
+                        .await
+                        .map_err(OpenStoreError::LoadCipher)?
+                    else {
+                        return Err(OpenStoreError::WrongPassphrase);
+                    };
+
+                    let cipher = StoreCipher::import(passphrase, &previous)
+                        .map_err(map_import_cipher_error)?;
+
+                    warn!("A change of the passphrase was interrupted, restoring the previous one");
+                    self.with_transaction(move |txn| {
+                        txn.set_kv("cipher", &previous)?;
+                        txn.clear_kv(PREVIOUS_CIPHER_KEY)
+                    })
+                    .await
+                    .map_err(OpenStoreError::SaveCipher)?;
+
+                    cipher
+                }
+                Err(error) => return Err(map_import_cipher_error(error)),
+            }
         } else {
             let cipher = StoreCipher::new()?;
             #[cfg(not(test))]
@@ -471,6 +507,15 @@ pub(crate) trait SqliteKeyValueStoreAsyncConnExt: SqliteAsyncConnExt {
     }
 }
 
+/// Convert an error that occurred when importing a store cipher, to report a
+/// wrong passphrase explicitly.
+pub(crate) fn map_import_cipher_error(error: StoreEncryptionError) -> OpenStoreError {
+    match error {
+        StoreEncryptionError::WrongPassphraseOrKey => OpenStoreError::WrongPassphrase,
+        error => OpenStoreError::InitCipher(error),
+    }
+}
+
 #[async_trait]
 impl SqliteKeyValueStoreAsyncConnExt for SqliteAsyncConn {
     async fn set_kv(&self, key: &str, value: Vec<u8>) -> rusqlite::Result<()> {
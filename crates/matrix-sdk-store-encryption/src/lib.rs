@@ -79,6 +79,11 @@ pub enum Error {
     /// we are trying to import it using a key or vice-versa.
     #[error("Failed to import a store cipher, the export used a passphrase while we are trying to import it using a key or vice-versa")]
     KdfMismatch,
+
+    /// Failed to import a store cipher, because the passphrase or the key is
+    /// wrong, or because the export is corrupted.
+    #[error("Failed to import a store cipher, the passphrase or the key is wrong")]
+    WrongPassphraseOrKey,
 }
 
 /// An encryption key that can be used to encrypt data for key/value stores.
@@ -232,7 +237,10 @@ impl StoreCipher {
             CipherTextInfo::ChaCha20Poly1305 { nonce, ciphertext } => {
                 let cipher = XChaCha20Poly1305::new(key);
                 let nonce = XNonce::from_slice(&nonce);
-                cipher.decrypt(nonce, ciphertext.as_ref())?
+                // The ciphertext is authenticated, so a wrong key is detected here.
+                cipher
+                    .decrypt(nonce, ciphertext.as_ref())
+                    .map_err(|_| Error::WrongPassphraseOrKey)?
             }
         };
 
@@ -283,14 +291,7 @@ impl StoreCipher {
     /// # anyhow::Ok(()) };
     /// ```
     pub fn import(passphrase: &str, encrypted: &[u8]) -> Result<Self, Error> {
-        // Our old export format used serde_json for the serialization format. Let's
-        // first try the new format and if that fails, try the old one.
-        let encrypted: EncryptedStoreCipher =
-            if let Ok(deserialized) = rmp_serde::from_slice(encrypted) {
-                deserialized
-            } else {
-                serde_json::from_slice(encrypted)?
-            };
+        let encrypted = Self::deserialize_export(encrypted)?;
 
         let key = match encrypted.kdf_info {
             KdfInfo::Pbkdf2ToChaCha20Poly1305 { rounds, kdf_salt } => {
@@ -306,6 +307,65 @@ impl StoreCipher {
         Self::import_helper(key, encrypted)
     }
 
+    /// Change the passphrase of an export of a store cipher.
+    ///
+    /// The store cipher itself doesn't change, only the key that encrypts it,
+    /// so the data encrypted with the store cipher doesn't need to be
+    /// encrypted again. The new export uses the same key derivation
+    /// parameters as the old one, with a new salt.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted` - The exported and encrypted version of the store cipher.
+    ///
+    /// * `old_passphrase` - The passphrase that was used to export the store
+    ///   cipher. [`Error::WrongPassphraseOrKey`] is returned if it's wrong.
+    ///
+    /// * `new_passphrase` - The passphrase to encrypt the new export with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let example = || {
+    /// use matrix_sdk_store_encryption::StoreCipher;
+    ///
+    /// let store_cipher = StoreCipher::new()?;
+    /// let export = store_cipher.export("old-passphrase")?;
+    ///
+    /// // Replace the export in your key/value store.
+    /// let export = StoreCipher::change_passphrase(
+    ///     &export,
+    ///     "old-passphrase",
+    ///     "new-passphrase",
+    /// )?;
+    ///
+    /// let imported = StoreCipher::import("new-passphrase", &export)?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn change_passphrase(
+        encrypted: &[u8],
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let KdfInfo::Pbkdf2ToChaCha20Poly1305 { rounds, .. } =
+            Self::deserialize_export(encrypted)?.kdf_info
+        else {
+            return Err(Error::KdfMismatch);
+        };
+
+        Self::import(old_passphrase, encrypted)?.export_kdf(new_passphrase, rounds)
+    }
+
+    fn deserialize_export(encrypted: &[u8]) -> Result<EncryptedStoreCipher, Error> {
+        // Our old export format used serde_json for the serialization format. Let's
+        // first try the new format and if that fails, try the old one.
+        if let Ok(deserialized) = rmp_serde::from_slice(encrypted) {
+            Ok(deserialized)
+        } else {
+            Ok(serde_json::from_slice(encrypted)?)
+        }
+    }
+
     /// Restore a store cipher from an export encrypted with a random key.
     ///
     /// # Arguments
@@ -822,6 +882,34 @@ mod tests {
         StoreCipher::new().unwrap();
     }
 
+    #[test]
+    fn changing_passphrase() -> Result<(), Error> {
+        let store_cipher = StoreCipher::new()?;
+        let encrypted_value = store_cipher.encrypt_value(&json!({ "some": "data" }))?;
+
+        let export = store_cipher._insecure_export_fast_for_testing("old")?;
+
+        // Same as below, can't use assert_matches.
+        match StoreCipher::change_passphrase(&export, "wrong", "new") {
+            Err(Error::WrongPassphraseOrKey) => {}
+            _ => panic!("Invalid error when changing the passphrase with a wrong passphrase"),
+        }
+
+        let export = StoreCipher::change_passphrase(&export, "old", "new")?;
+
+        match StoreCipher::import("old", &export) {
+            Err(Error::WrongPassphraseOrKey) => {}
+            _ => panic!("Invalid error when importing a store cipher with the old passphrase"),
+        }
+
+        // The store cipher didn't change, so the values can still be decrypted.
+        let imported = StoreCipher::import("new", &export)?;
+        let decrypted_value: Value = imported.decrypt_value(&encrypted_value)?;
+        assert_eq!(decrypted_value, json!({ "some": "data" }));
+
+        Ok(())
+    }
+
     #[test]
     fn exporting_store_cipher() -> Result<(), Error> {
         let passphrase = "it's a secret to everybody";
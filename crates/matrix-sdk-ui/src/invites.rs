@@ -0,0 +1,522 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The pending invites of the user.
+//!
+//! The [`InviteService`] lists the rooms the user is invited to, with the
+//! profile of the user who sent each invite, and keeps the list up to date
+//! with the sync, so a client doesn't have to build it from the stripped state
+//! of the invited rooms. Invites can be accepted or declined, and declining an
+//! invite can also report the room and ignore the inviter.
+//!
+//! An [`InviteFilter`] can be set to hide the invites considered as spam, like
+//! the [`InviteRateFilter`] which hides the invites of users who send too many
+//! of them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use eyeball::SharedObservable;
+use futures_core::Stream;
+use matrix_sdk::{
+    deserialized_responses::RawSyncOrStrippedState,
+    executor::{spawn, JoinHandle},
+    sync::RoomUpdates,
+    Client, Room, RoomState, SendOutsideWasm, SyncOutsideWasm,
+};
+use ruma::{
+    events::room::member::RoomMemberEventContent, MilliSecondsSinceUnixEpoch, OwnedMxcUri,
+    OwnedRoomId, OwnedUserId, RoomId,
+};
+use thiserror::Error;
+use tokio::sync::{broadcast::error::RecvError, Mutex as AsyncMutex};
+use tracing::{debug, warn};
+
+/// Errors related to the [`InviteService`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The user isn't invited to the room.
+    #[error("not invited to room `{0}`")]
+    NotInvited(OwnedRoomId),
+
+    /// Error from the client.
+    #[error(transparent)]
+    Sdk(#[from] matrix_sdk::Error),
+}
+
+/// The user who sent an invite.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Inviter {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+
+    /// The display name of the user, if known.
+    pub display_name: Option<String>,
+
+    /// The MXC URI of the avatar of the user, if known.
+    pub avatar_url: Option<OwnedMxcUri>,
+}
+
+/// A pending invite to a room.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invite {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+
+    /// The display name of the room.
+    pub room_name: Option<String>,
+
+    /// The MXC URI of the avatar of the room, if set.
+    pub room_avatar_url: Option<OwnedMxcUri>,
+
+    /// Whether the invite is for a direct message room.
+    pub is_direct: bool,
+
+    /// The user who sent the invite, if known.
+    pub inviter: Option<Inviter>,
+
+    /// When the invite was sent, according to the homeserver of the inviter,
+    /// if known.
+    pub timestamp: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+/// What to do in addition to leaving the room when declining an invite.
+#[derive(Clone, Debug, Default)]
+pub struct DeclineOptions {
+    /// Report the room to the homeserver, with the given optional reason.
+    pub report: Option<Option<String>>,
+
+    /// Ignore the user who sent the invite, which also hides their other
+    /// invites.
+    pub ignore_inviter: bool,
+}
+
+/// A hook deciding which invites are spam.
+///
+/// The invites considered as spam are hidden from the list of pending invites,
+/// but aren't declined.
+pub trait InviteFilter: SendOutsideWasm + SyncOutsideWasm {
+    /// Whether the given invite is spam.
+    ///
+    /// This is called every time the list of invites is computed, so it must
+    /// return the same result for the same invite, unless the pending invites
+    /// changed the decision.
+    fn is_spam(&self, invite: &Invite) -> bool;
+
+    /// Called with all the pending invites, including the spam ones, every
+    /// time the list of invites is computed, before [`InviteFilter::is_spam()`]
+    /// is called for each of them.
+    ///
+    /// Filters keeping track of the invites can use it to forget the ones that
+    /// aren't pending anymore.
+    fn pending_invites_changed(&self, _invites: &[Invite]) {}
+}
+
+/// An [`InviteFilter`] hiding the invites of users who sent too many of them in
+/// a short period of time.
+///
+/// The invites are timestamped with their [`Invite::timestamp`], or when the
+/// filter sees them for the first time if it's unknown. An invite is spam if
+/// its inviter sent more than `max_invites` pending invites within `period`
+/// before or after it, including it.
+#[derive(Debug)]
+pub struct InviteRateFilter {
+    max_invites: usize,
+    period: Duration,
+    seen: StdMutex<HashMap<OwnedUserId, HashMap<OwnedRoomId, MilliSecondsSinceUnixEpoch>>>,
+}
+
+impl InviteRateFilter {
+    /// Hide the invites of users who send more than `max_invites` invites
+    /// within `period`.
+    pub fn new(max_invites: usize, period: Duration) -> Self {
+        Self { max_invites, period, seen: Default::default() }
+    }
+
+    fn is_spam_at(&self, invite: &Invite, now: MilliSecondsSinceUnixEpoch) -> bool {
+        let Some(inviter) = &invite.inviter else {
+            return false;
+        };
+
+        let mut seen = self.seen.lock().unwrap();
+        let invites = seen.entry(inviter.user_id.clone()).or_default();
+        let sent_at =
+            *invites.entry(invite.room_id.clone()).or_insert(invite.timestamp.unwrap_or(now));
+
+        let period = u64::try_from(self.period.as_millis()).unwrap_or(u64::MAX);
+        let close_invites = invites
+            .values()
+            .filter(|other| u64::from(other.0).abs_diff(u64::from(sent_at.0)) < period)
+            .count();
+
+        close_invites > self.max_invites
+    }
+}
+
+impl InviteFilter for InviteRateFilter {
+    fn is_spam(&self, invite: &Invite) -> bool {
+        self.is_spam_at(invite, MilliSecondsSinceUnixEpoch::now())
+    }
+
+    fn pending_invites_changed(&self, invites: &[Invite]) {
+        let pending = invites
+            .iter()
+            .filter_map(|invite| Some((&invite.inviter.as_ref()?.user_id, &invite.room_id)))
+            .collect::<HashSet<_>>();
+
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|user_id, invites| {
+            invites.retain(|room_id, _| pending.contains(&(user_id, room_id)));
+            !invites.is_empty()
+        });
+    }
+}
+
+/// The service to list and handle the pending invites of the user.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use matrix_sdk::Client;
+/// use matrix_sdk_ui::invites::{
+///     DeclineOptions, InviteRateFilter, InviteService,
+/// };
+///
+/// # async {
+/// # let client: Client = todo!();
+/// let invite_service = InviteService::new(client)
+///     .with_filter(InviteRateFilter::new(5, Duration::from_secs(60 * 60)));
+///
+/// // The pending invites, updated live.
+/// let (invites, invites_stream) = invite_service.subscribe_to_invites().await;
+///
+/// if let Some(invite) = invites.first() {
+///     let options =
+///         DeclineOptions { report: Some(None), ignore_inviter: true };
+///     invite_service.decline(&invite.room_id, options).await?;
+/// }
+/// # anyhow::Ok(()) };
+/// ```
+pub struct InviteService {
+    client: Client,
+    filter: Option<Arc<dyn InviteFilter>>,
+    invites: SharedObservable<Vec<Invite>>,
+    room_updates_task: AsyncMutex<Option<JoinHandle<()>>>,
+}
+
+impl InviteService {
+    /// Create a new `InviteService`.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            filter: None,
+            invites: SharedObservable::new(Vec::new()),
+            room_updates_task: AsyncMutex::new(None),
+        }
+    }
+
+    /// Hide the invites that the given filter considers as spam.
+    pub fn with_filter(mut self, filter: impl InviteFilter + 'static) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Get the pending invites of the user, sorted by room ID.
+    pub async fn invites(&self) -> Vec<Invite> {
+        let invites = pending_invites(&self.client, self.filter.as_deref()).await;
+        self.invites.set_if_not_eq(invites.clone());
+        invites
+    }
+
+    /// Get the pending invites of the user, and a stream of updates for them.
+    pub async fn subscribe_to_invites(&self) -> (Vec<Invite>, impl Stream<Item = Vec<Invite>>) {
+        let mut room_updates_task = self.room_updates_task.lock().await;
+
+        if room_updates_task.is_none() {
+            self.invites.set_if_not_eq(pending_invites(&self.client, self.filter.as_deref()).await);
+            *room_updates_task = Some(spawn(invites_task(
+                self.client.clone(),
+                self.filter.clone(),
+                self.invites.clone(),
+            )));
+        }
+
+        let subscriber = self.invites.subscribe();
+        (self.invites.get(), subscriber)
+    }
+
+    /// Accept the invite to the given room, by joining it.
+    pub async fn accept(&self, room_id: &RoomId) -> Result<Room, Error> {
+        let room = self.invited_room(room_id)?;
+        room.join().await?;
+
+        self.invites().await;
+        Ok(room)
+    }
+
+    /// Decline the invite to the given room, by leaving it.
+    ///
+    /// The room is also forgotten, so it's removed from the store.
+    pub async fn decline(&self, room_id: &RoomId, options: DeclineOptions) -> Result<(), Error> {
+        let room = self.invited_room(room_id)?;
+
+        let inviter_id = match room.invite_details().await {
+            Ok(details) => Some(details.invitee.event().sender().to_owned()),
+            Err(error) => {
+                warn!(%room_id, "Couldn't get the details of the invite: {error}");
+                None
+            }
+        };
+
+        if let Some(reason) = options.report {
+            room.report_room(reason).await?;
+        }
+
+        room.leave().await?;
+
+        if let Err(error) = room.forget().await {
+            warn!(%room_id, "Couldn't forget the room after declining the invite: {error}");
+        }
+
+        if options.ignore_inviter {
+            if let Some(inviter_id) = inviter_id {
+                self.client.account().ignore_user(&inviter_id).await?;
+            }
+        }
+
+        self.invites().await;
+        Ok(())
+    }
+
+    fn invited_room(&self, room_id: &RoomId) -> Result<Room, Error> {
+        self.client
+            .get_room(room_id)
+            .filter(|room| room.state() == RoomState::Invited)
+            .ok_or_else(|| Error::NotInvited(room_id.to_owned()))
+    }
+}
+
+impl Drop for InviteService {
+    fn drop(&mut self) {
+        if let Some(room_updates_task) = self.room_updates_task.get_mut().take() {
+            room_updates_task.abort();
+        }
+    }
+}
+
+/// Keep the pending invites up to date.
+async fn invites_task(
+    client: Client,
+    filter: Option<Arc<dyn InviteFilter>>,
+    invites: SharedObservable<Vec<Invite>>,
+) {
+    let mut receiver = client.subscribe_to_all_room_updates();
+
+    loop {
+        match receiver.recv().await {
+            Ok(updates) => {
+                if !touches_invites(&updates, &invites.get()) {
+                    continue;
+                }
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+
+        invites.set_if_not_eq(pending_invites(&client, filter.as_deref()).await);
+    }
+}
+
+/// Whether the given updates may change the list of pending invites.
+fn touches_invites(updates: &RoomUpdates, invites: &[Invite]) -> bool {
+    !updates.invited.is_empty()
+        || invites.iter().any(|invite| {
+            updates.joined.contains_key(&invite.room_id)
+                || updates.left.contains_key(&invite.room_id)
+        })
+}
+
+/// Compute the list of the pending invites that aren't spam.
+async fn pending_invites(client: &Client, filter: Option<&dyn InviteFilter>) -> Vec<Invite> {
+    let mut invites = Vec::new();
+    for room in client.invited_rooms() {
+        invites.push(invite_for_room(client, &room).await);
+    }
+
+    if let Some(filter) = filter {
+        filter.pending_invites_changed(&invites);
+        invites.retain(|invite| {
+            let is_spam = filter.is_spam(invite);
+            if is_spam {
+                debug!(room_id = %invite.room_id, "Hiding invite considered as spam");
+            }
+            !is_spam
+        });
+    }
+
+    invites.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+    invites
+}
+
+/// Build the invite to the given room, resolving the profile of the inviter if
+/// it's not in the stripped state of the room.
+async fn invite_for_room(client: &Client, room: &Room) -> Invite {
+    let inviter = match room.invite_details().await {
+        Ok(details) => {
+            let inviter_id = details.invitee.event().sender().to_owned();
+            let member = details.inviter.filter(|inviter| {
+                inviter.display_name().is_some() || inviter.avatar_url().is_some()
+            });
+
+            Some(match member {
+                Some(member) => Inviter {
+                    user_id: inviter_id,
+                    display_name: member.display_name().map(ToOwned::to_owned),
+                    avatar_url: member.avatar_url().map(ToOwned::to_owned),
+                },
+                None => match client.profile(&inviter_id).await {
+                    Ok(profile) => Inviter {
+                        user_id: inviter_id,
+                        display_name: profile.display_name,
+                        avatar_url: profile.avatar_url,
+                    },
+                    Err(error) => {
+                        debug!(%inviter_id, "Couldn't fetch the profile of the inviter: {error}");
+                        Inviter { user_id: inviter_id, display_name: None, avatar_url: None }
+                    }
+                },
+            })
+        }
+        Err(error) => {
+            warn!(room_id = %room.room_id(), "Couldn't get the details of the invite: {error}");
+            None
+        }
+    };
+
+    // Stripped state events usually don't have a timestamp, but some homeservers
+    // add it to the invite.
+    let own_member_event =
+        room.get_state_event_static_for_key::<RoomMemberEventContent, _>(room.own_user_id()).await;
+    let timestamp = match own_member_event {
+        Ok(Some(RawSyncOrStrippedState::Sync(raw))) => raw.get_field("origin_server_ts"),
+        Ok(Some(RawSyncOrStrippedState::Stripped(raw))) => raw.get_field("origin_server_ts"),
+        _ => Ok(None),
+    };
+
+    Invite {
+        room_id: room.room_id().to_owned(),
+        room_name: room.display_name().await.ok().map(|name| name.to_string()),
+        room_avatar_url: room.avatar_url(),
+        is_direct: room.is_direct().await.unwrap_or(false),
+        inviter,
+        timestamp: timestamp.ok().flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{owned_room_id, user_id, MilliSecondsSinceUnixEpoch, OwnedRoomId, UInt, UserId};
+
+    use super::{Invite, InviteFilter, InviteRateFilter, Inviter};
+
+    fn invite(room_id: OwnedRoomId, inviter: &UserId) -> Invite {
+        Invite {
+            room_id,
+            room_name: None,
+            room_avatar_url: None,
+            is_direct: false,
+            inviter: Some(Inviter {
+                user_id: inviter.to_owned(),
+                display_name: None,
+                avatar_url: None,
+            }),
+            timestamp: None,
+        }
+    }
+
+    fn at(secs: u64) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(UInt::new(secs * 1000).unwrap())
+    }
+
+    #[test]
+    fn test_rate_filter() {
+        let filter = InviteRateFilter::new(2, Duration::from_secs(60));
+
+        let first = invite(owned_room_id!("!first:localhost"), user_id!("@spammer:localhost"));
+        let second = invite(owned_room_id!("!second:localhost"), user_id!("@spammer:localhost"));
+        let third = invite(owned_room_id!("!third:localhost"), user_id!("@spammer:localhost"));
+        let other = invite(owned_room_id!("!other:localhost"), user_id!("@friend:localhost"));
+
+        assert!(!filter.is_spam_at(&first, at(0)));
+        assert!(!filter.is_spam_at(&second, at(10)));
+        assert!(!filter.is_spam_at(&other, at(10)));
+
+        // A third invite in the same minute is too much, and makes the previous ones
+        // spam too.
+        assert!(filter.is_spam_at(&third, at(20)));
+        assert!(filter.is_spam_at(&first, at(30)));
+
+        // The decision doesn't change with time.
+        assert!(filter.is_spam_at(&second, at(3600)));
+
+        // Other users aren't affected.
+        assert!(!filter.is_spam_at(&other, at(30)));
+
+        // An invite much later isn't spam.
+        let later = invite(owned_room_id!("!later:localhost"), user_id!("@spammer:localhost"));
+        assert!(!filter.is_spam_at(&later, at(3600)));
+    }
+
+    #[test]
+    fn test_rate_filter_uses_the_invite_timestamp() {
+        let filter = InviteRateFilter::new(1, Duration::from_secs(60));
+
+        let mut first = invite(owned_room_id!("!first:localhost"), user_id!("@alice:localhost"));
+        first.timestamp = Some(at(0));
+        let mut second = invite(owned_room_id!("!second:localhost"), user_id!("@alice:localhost"));
+        second.timestamp = Some(at(3600));
+
+        // The invites were sent an hour apart, even if they are seen at the same time,
+        // like after a restart.
+        assert!(!filter.is_spam_at(&first, at(7200)));
+        assert!(!filter.is_spam_at(&second, at(7200)));
+    }
+
+    #[test]
+    fn test_rate_filter_forgets_the_invites_that_are_not_pending() {
+        let filter = InviteRateFilter::new(1, Duration::from_secs(60));
+
+        let first = invite(owned_room_id!("!first:localhost"), user_id!("@spammer:localhost"));
+        let second = invite(owned_room_id!("!second:localhost"), user_id!("@spammer:localhost"));
+
+        filter.pending_invites_changed(&[first.clone(), second.clone()]);
+        assert!(!filter.is_spam_at(&first, at(0)));
+        assert!(filter.is_spam_at(&second, at(10)));
+
+        // The first invite was declined, so it's forgotten.
+        filter.pending_invites_changed(std::slice::from_ref(&second));
+        assert!(!filter.is_spam_at(&second, at(20)));
+        assert_eq!(filter.seen.lock().unwrap().len(), 1);
+
+        filter.pending_invites_changed(&[]);
+        assert!(filter.seen.lock().unwrap().is_empty());
+    }
+}
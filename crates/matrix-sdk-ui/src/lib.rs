@@ -22,11 +22,14 @@ mod events;
 pub use eyeball_im;
 
 pub mod encryption_sync_service;
+pub mod invites;
 pub mod notification_client;
 pub mod room_list_service;
+pub mod spaces;
 pub mod sync_service;
 pub mod timeline;
-pub mod unable_to_decrypt_hook;
+
+pub use matrix_sdk::unable_to_decrypt_hook;
 
 pub use self::{room_list_service::RoomListService, timeline::Timeline};
 
@@ -62,6 +62,7 @@ mod non_left;
 mod none;
 mod normalized_match_room_name;
 mod not;
+mod space;
 mod unread;
 
 #[cfg(test)]
@@ -84,6 +85,7 @@ pub use normalized_match_room_name::new_filter as new_filter_normalized_match_ro
 pub use not::new_filter as new_filter_not;
 #[cfg(test)]
 use ruma::RoomId;
+pub use space::{new_filter as new_filter_space, SpaceRooms};
 use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 pub use unread::new_filter as new_filter_unread;
 #[cfg(test)]
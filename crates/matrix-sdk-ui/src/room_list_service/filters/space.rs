@@ -0,0 +1,196 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk::{
+    executor::{spawn, JoinHandle},
+    Client,
+};
+use ruma::{OwnedRoomId, RoomId};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+use super::{super::Room, Filter};
+use crate::spaces::{space_descendants, touches_space_state, SpaceDescendants};
+
+/// The rooms of a space, including the rooms of its sub-spaces, kept up to
+/// date as the children of the spaces change.
+///
+/// The children are computed from the `m.space.child` state events of the
+/// spaces known locally, and from the hierarchies cached by
+/// [`SpaceRoomList`](crate::spaces::SpaceRoomList).
+///
+/// It's cheap to clone, and it's used by [`new_filter`] and by
+/// [`RoomListDynamicEntriesController::set_filter_in_space`].
+///
+/// [`RoomListDynamicEntriesController::set_filter_in_space`]: super::super::RoomListDynamicEntriesController::set_filter_in_space
+#[derive(Clone, Debug)]
+pub struct SpaceRooms {
+    inner: Arc<SpaceRoomsInner>,
+}
+
+#[derive(Debug)]
+struct SpaceRoomsInner {
+    space_id: OwnedRoomId,
+    room_ids: SharedObservable<BTreeSet<OwnedRoomId>>,
+    update_task: JoinHandle<()>,
+}
+
+impl Drop for SpaceRoomsInner {
+    fn drop(&mut self) {
+        self.update_task.abort();
+    }
+}
+
+impl SpaceRooms {
+    /// Compute the rooms of the given space, and keep them up to date.
+    pub async fn new(client: Client, space_id: OwnedRoomId) -> Self {
+        let descendants = space_descendants(&client, &space_id).await;
+        let room_ids = SharedObservable::new(descendants.rooms.clone());
+
+        let update_task =
+            spawn(update_task(client, space_id.clone(), descendants, room_ids.clone()));
+
+        Self { inner: Arc::new(SpaceRoomsInner { space_id, room_ids, update_task }) }
+    }
+
+    /// The ID of the space.
+    pub fn space_id(&self) -> &RoomId {
+        &self.inner.space_id
+    }
+
+    /// Whether the given room is in the space or in one of its sub-spaces.
+    pub fn contains(&self, room_id: &RoomId) -> bool {
+        self.inner.room_ids.read().contains(room_id)
+    }
+
+    /// Get the IDs of the rooms of the space.
+    pub fn room_ids(&self) -> BTreeSet<OwnedRoomId> {
+        self.inner.room_ids.get()
+    }
+
+    /// Subscribe to the changes of the rooms of the space.
+    pub fn subscribe(&self) -> Subscriber<BTreeSet<OwnedRoomId>> {
+        self.inner.room_ids.subscribe()
+    }
+}
+
+/// Recompute the rooms of the space when the children of the space, or of one
+/// of its sub-spaces, change.
+async fn update_task(
+    client: Client,
+    space_id: OwnedRoomId,
+    mut descendants: SpaceDescendants,
+    room_ids: SharedObservable<BTreeSet<OwnedRoomId>>,
+) {
+    let mut receiver = client.subscribe_to_all_room_updates();
+
+    loop {
+        match receiver.recv().await {
+            Ok(updates) => {
+                let children_changed = updates.joined.iter().any(|(room_id, update)| {
+                    descendants.spaces.contains(room_id) && touches_space_state(update)
+                });
+
+                // Joining or leaving a sub-space changes whether its children are known.
+                let sub_space_changed =
+                    updates.joined.keys().chain(updates.left.keys()).any(|room_id| {
+                        descendants.rooms.contains(room_id)
+                            && client.get_room(room_id).is_some_and(|room| room.is_space())
+                    });
+
+                if !children_changed && !sub_space_changed {
+                    continue;
+                }
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+
+        debug!(?space_id, "children of the space may have changed, recomputing its rooms");
+
+        descendants = space_descendants(&client, &space_id).await;
+        room_ids.set_if_not_eq(descendants.rooms.clone());
+    }
+}
+
+struct SpaceRoomMatcher<F>
+where
+    F: Fn(&RoomId) -> bool,
+{
+    is_in_space: F,
+}
+
+impl<F> SpaceRoomMatcher<F>
+where
+    F: Fn(&RoomId) -> bool,
+{
+    fn matches(&self, room: &Room) -> bool {
+        (self.is_in_space)(room.room_id())
+    }
+}
+
+/// Create a new filter that will filter out rooms that are not in the given
+/// space, or in one of its sub-spaces.
+///
+/// The filter always uses the current rooms of the space, but the room list
+/// isn't filtered again when they change. Use
+/// [`RoomListDynamicEntriesController::set_filter_in_space`] for that.
+///
+/// [`RoomListDynamicEntriesController::set_filter_in_space`]: super::super::RoomListDynamicEntriesController::set_filter_in_space
+pub fn new_filter(space_rooms: &SpaceRooms) -> impl Filter {
+    let space_rooms = space_rooms.clone();
+    let matcher = SpaceRoomMatcher { is_in_space: move |room_id| space_rooms.contains(room_id) };
+
+    move |room| -> bool { matcher.matches(room) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        super::{client_and_server_prelude, new_rooms},
+        *,
+    };
+
+    #[async_test]
+    async fn test_room_in_space() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room, other_room] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server, &sliding_sync)
+                .await;
+
+        let matcher = SpaceRoomMatcher { is_in_space: |room_id| room_id == "!a:b.c" };
+
+        assert!(matcher.matches(&room));
+        assert!(matcher.matches(&other_room).not());
+    }
+
+    #[async_test]
+    async fn test_unknown_space_has_no_rooms() {
+        let (client, _server, _sliding_sync) = client_and_server_prelude().await;
+
+        let space_rooms = SpaceRooms::new(client, room_id!("!space:b.c").to_owned()).await;
+
+        assert_eq!(space_rooms.space_id(), "!space:b.c");
+        assert!(space_rooms.room_ids().is_empty());
+        assert!(space_rooms.contains(room_id!("!a:b.c")).not());
+    }
+}
@@ -382,6 +382,17 @@ impl RoomListService {
         ))
     }
 
+    /// Get the rooms of a space, and of its sub-spaces, to scope a room list
+    /// to the space.
+    ///
+    /// The rooms of the space are computed from the hierarchies cached by
+    /// [`SpaceRoomList`](crate::spaces::SpaceRoomList), since the spaces
+    /// themselves aren't part of the room list. See
+    /// [`RoomListDynamicEntriesController::set_filter_in_space`].
+    pub async fn space_rooms(&self, space_id: OwnedRoomId) -> filters::SpaceRooms {
+        filters::SpaceRooms::new(self.client.clone(), space_id).await
+    }
+
     /// Subscribe to rooms.
     ///
     /// It means that all events from these rooms will be received every time,
@@ -12,7 +12,10 @@
 // See the License for that specific language governing permissions and
 // limitations under the License.
 
-use std::{future::ready, sync::Arc};
+use std::{
+    future::ready,
+    sync::{Arc, Mutex},
+};
 
 use async_cell::sync::AsyncCell;
 use async_rx::StreamExt as _;
@@ -33,8 +36,11 @@ use tokio::{
 use tracing::{error, trace};
 
 use super::{
-    filters::BoxedFilterFn,
-    sorters::{new_sorter_lexicographic, new_sorter_name, new_sorter_recency},
+    filters::{BoxedFilterFn, SpaceRooms},
+    sorters::{
+        new_sorter_lexicographic, new_sorter_name, new_sorter_recency, new_sorter_tags,
+        BoxedSorterFn,
+    },
     Error, Room, State,
 };
 
@@ -146,6 +152,25 @@ impl RoomList {
     pub fn entries_with_dynamic_adapters(
         &self,
         page_size: usize,
+    ) -> (impl Stream<Item = Vec<VectorDiff<Room>>> + '_, RoomListDynamicEntriesController) {
+        self.entries_with_dynamic_adapters_impl(page_size, false)
+    }
+
+    /// Like [`Self::entries_with_dynamic_adapters`], but the rooms are sorted
+    /// by their tags first: the favourite rooms come first, and the low
+    /// priority rooms come last, both sorted by the `order` of their tag. See
+    /// [`new_sorter_tags`] to learn more.
+    pub fn entries_with_dynamic_adapters_sorted_by_tags(
+        &self,
+        page_size: usize,
+    ) -> (impl Stream<Item = Vec<VectorDiff<Room>>> + '_, RoomListDynamicEntriesController) {
+        self.entries_with_dynamic_adapters_impl(page_size, true)
+    }
+
+    fn entries_with_dynamic_adapters_impl(
+        &self,
+        page_size: usize,
+        sort_by_tags: bool,
     ) -> (impl Stream<Item = Vec<VectorDiff<Room>>> + '_, RoomListDynamicEntriesController) {
         let room_info_notable_update_receiver = self.client.room_info_notable_update_receiver();
         let list = self.sliding_sync_list.clone();
@@ -171,12 +196,18 @@ impl RoomList {
                 // Combine normal stream events with other updates from rooms
                 let merged_streams = merge_stream_and_receiver(raw_values.clone(), raw_stream, room_info_notable_update_receiver.resubscribe());
 
+                let mut sorters: Vec<BoxedSorterFn> = Vec::with_capacity(3);
+
+                if sort_by_tags {
+                    sorters.push(Box::new(new_sorter_tags()));
+                }
+
+                sorters.push(Box::new(new_sorter_recency()));
+                sorters.push(Box::new(new_sorter_name()));
+
                 let (values, stream) = (raw_values, merged_streams)
                     .filter(filter_fn)
-                    .sort_by(new_sorter_lexicographic(vec![
-                        Box::new(new_sorter_recency()),
-                        Box::new(new_sorter_name())
-                    ]))
+                    .sort_by(new_sorter_lexicographic(sorters))
                     .dynamic_head_with_initial_value(page_size, limit_stream.clone());
 
                 // Clearing the stream before chaining with the real stream.
@@ -299,6 +330,15 @@ pub struct RoomListDynamicEntriesController {
     page_size: usize,
     limit: SharedObservable<usize>,
     maximum_number_of_rooms: Subscriber<Option<u32>>,
+    space_filter_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Drop for RoomListDynamicEntriesController {
+    fn drop(&mut self) {
+        if let Some(space_filter_task) = self.space_filter_task.get_mut().unwrap().take() {
+            space_filter_task.abort();
+        }
+    }
 }
 
 impl RoomListDynamicEntriesController {
@@ -308,7 +348,13 @@ impl RoomListDynamicEntriesController {
         limit_stream: SharedObservable<usize>,
         maximum_number_of_rooms: Subscriber<Option<u32>>,
     ) -> Self {
-        Self { filter, page_size, limit: limit_stream, maximum_number_of_rooms }
+        Self {
+            filter,
+            page_size,
+            limit: limit_stream,
+            maximum_number_of_rooms,
+            space_filter_task: Mutex::new(None),
+        }
     }
 
     /// Set the filter.
@@ -316,6 +362,61 @@ impl RoomListDynamicEntriesController {
     /// If the associated stream has been dropped, returns `false` to indicate
     /// the operation didn't have an effect.
     pub fn set_filter(&self, filter: BoxedFilterFn) -> bool {
+        self.abort_space_filter_task();
+        self.set_filter_impl(filter)
+    }
+
+    /// Set the filter, and scope the room list to the rooms of a space.
+    ///
+    /// Only the rooms that are in the space, or in one of its sub-spaces, and
+    /// that match `filter` are kept. Unlike with [`new_filter_space`], the room
+    /// list is filtered again every time the rooms of the space change, so
+    /// it's updated live when rooms are added to or removed from the space.
+    ///
+    /// The scope is removed by the next call to [`Self::set_filter`].
+    ///
+    /// If the associated stream has been dropped, returns `false` to indicate
+    /// the operation didn't have an effect.
+    ///
+    /// [`new_filter_space`]: super::filters::new_filter_space
+    pub fn set_filter_in_space(&self, space_rooms: SpaceRooms, filter: BoxedFilterFn) -> bool {
+        self.abort_space_filter_task();
+
+        let filter = Arc::new(filter);
+        let scoped_filter =
+            |space_rooms: SpaceRooms, filter: Arc<BoxedFilterFn>| -> BoxedFilterFn {
+                Box::new(move |room| space_rooms.contains(room.room_id()) && filter(room))
+            };
+
+        if !self.set_filter_impl(scoped_filter(space_rooms.clone(), filter.clone())) {
+            return false;
+        }
+
+        // Don't keep the stream alive from the task.
+        let filter_cell = Arc::downgrade(&self.filter);
+        let mut room_ids = space_rooms.subscribe();
+
+        *self.space_filter_task.lock().unwrap() = Some(spawn(async move {
+            while room_ids.next().await.is_some() {
+                let Some(filter_cell) = filter_cell.upgrade() else {
+                    break;
+                };
+
+                trace!(space_id = ?space_rooms.space_id(), "rooms of the space changed, filtering again");
+                filter_cell.set(scoped_filter(space_rooms.clone(), filter.clone()));
+            }
+        }));
+
+        true
+    }
+
+    fn abort_space_filter_task(&self) {
+        if let Some(space_filter_task) = self.space_filter_task.lock().unwrap().take() {
+            space_filter_task.abort();
+        }
+    }
+
+    fn set_filter_impl(&self, filter: BoxedFilterFn) -> bool {
         if Arc::strong_count(&self.filter) == 1 {
             // there is no other reference to the boxed filter fn, setting it
             // would be pointless (no new references can be created from self,
@@ -17,12 +17,14 @@
 mod lexicographic;
 mod name;
 mod recency;
+mod tags;
 
 use std::cmp::Ordering;
 
 pub use lexicographic::new_sorter as new_sorter_lexicographic;
 pub use name::new_sorter as new_sorter_name;
 pub use recency::new_sorter as new_sorter_recency;
+pub use tags::new_sorter as new_sorter_tags;
 
 use super::Room;
 
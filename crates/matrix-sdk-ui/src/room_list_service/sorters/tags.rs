@@ -0,0 +1,188 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use ruma::events::tag::TagName;
+
+use super::{Room, Sorter};
+
+/// The section of the room list a room belongs to, according to its tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    /// The room has the `m.favourite` tag.
+    Favourite,
+
+    /// The room has neither the `m.favourite` nor the `m.lowpriority` tag.
+    Normal,
+
+    /// The room has the `m.lowpriority` tag.
+    LowPriority,
+}
+
+/// Where a room must be placed, according to its tags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TagPosition {
+    section: Section,
+
+    /// The order of the tag of the section, if any.
+    order: Option<f64>,
+}
+
+impl TagPosition {
+    fn of(room: &Room) -> Self {
+        let (section, tag) = if room.is_favourite() {
+            (Section::Favourite, Some(TagName::Favorite))
+        } else if room.is_low_priority() {
+            (Section::LowPriority, Some(TagName::LowPriority))
+        } else {
+            (Section::Normal, None)
+        };
+
+        Self { section, order: tag.and_then(|tag| room.tag_order(&tag)) }
+    }
+}
+
+struct TagsMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagPosition, TagPosition),
+{
+    positions: F,
+}
+
+impl<F> TagsMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagPosition, TagPosition),
+{
+    fn matches(&self, left: &Room, right: &Room) -> Ordering {
+        let (left, right) = (self.positions)(left, right);
+
+        left.section.cmp(&right.section).then_with(|| match (left.order, right.order) {
+            (Some(left_order), Some(right_order)) => left_order.total_cmp(&right_order),
+
+            // Rooms with an order come before the rooms without one.
+            (Some(_), None) => Ordering::Less,
+
+            (None, Some(_)) => Ordering::Greater,
+
+            (None, None) => Ordering::Equal,
+        })
+    }
+}
+
+/// Create a new sorter that will sort two [`Room`] by their tags.
+///
+/// The favourite rooms come first, then the rooms without the `m.favourite`
+/// nor `m.lowpriority` tags, then the low priority rooms. Inside the favourite
+/// and low priority sections, the rooms are sorted by the `order` of their
+/// tag, i.e. 0.1 < 0.5, and the rooms with no order come last.
+///
+/// Rooms in the same position are considered equal, so this sorter is meant
+/// to be combined with other sorters with [`super::new_sorter_lexicographic`].
+pub fn new_sorter() -> impl Sorter {
+    let matcher = TagsMatcher {
+        positions: move |left, right| (TagPosition::of(left), TagPosition::of(right)),
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        super::super::filters::{client_and_server_prelude, new_rooms},
+        *,
+    };
+
+    fn position(section: Section, order: Option<f64>) -> TagPosition {
+        TagPosition { section, order }
+    }
+
+    #[async_test]
+    async fn test_with_different_sections() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server, &sliding_sync)
+                .await;
+
+        // `room_a` is a favourite, `room_b` has no notable tag.
+        {
+            let matcher = TagsMatcher {
+                positions: |_left, _right| {
+                    (position(Section::Favourite, None), position(Section::Normal, None))
+                },
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // `room_a` is low priority, `room_b` has no notable tag.
+        {
+            let matcher = TagsMatcher {
+                positions: |_left, _right| {
+                    (position(Section::LowPriority, Some(0.1)), position(Section::Normal, None))
+                },
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+    }
+
+    #[async_test]
+    async fn test_with_orders() {
+        let (client, server, sliding_sync) = client_and_server_prelude().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server, &sliding_sync)
+                .await;
+
+        // `room_a` has a greater order than `room_b`.
+        {
+            let matcher = TagsMatcher {
+                positions: |_left, _right| {
+                    (
+                        position(Section::Favourite, Some(0.8)),
+                        position(Section::Favourite, Some(0.2)),
+                    )
+                },
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+
+        // `room_a` has an order, `room_b` has none.
+        {
+            let matcher = TagsMatcher {
+                positions: |_left, _right| {
+                    (position(Section::Favourite, Some(0.8)), position(Section::Favourite, None))
+                },
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // Both rooms have no order.
+        {
+            let matcher = TagsMatcher {
+                positions: |_left, _right| {
+                    (position(Section::Normal, None), position(Section::Normal, None))
+                },
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+        }
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level view of the spaces of the user and of their children.
+//!
+//! The [`SpaceService`] lists the top-level spaces the user has joined, and
+//! creates a [`SpaceRoomList`] to browse the children of a space, as returned
+//! by the `/hierarchy` endpoint. Both are kept up to date with the sync, so a
+//! client can render a space tree without handling the `m.space.child` and
+//! `m.space.parent` events by itself.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use eyeball::SharedObservable;
+use futures_core::Stream;
+use matrix_sdk::{
+    executor::{spawn, JoinHandle},
+    sync::{JoinedRoomUpdate, RoomUpdates},
+    Client, HttpError, Room,
+};
+use matrix_sdk_base::deserialized_responses::SyncOrStrippedState;
+use ruma::{
+    events::{space::child::SpaceChildEventContent, StateEventType, SyncStateEvent},
+    room::RoomType,
+    OwnedRoomId, RoomId,
+};
+use thiserror::Error;
+use tokio::sync::{broadcast::error::RecvError, Mutex as AsyncMutex};
+use tracing::warn;
+
+mod organization;
+mod room;
+mod room_list;
+
+pub use self::{
+    room::SpaceRoom,
+    room_list::{SpaceRoomList, SpaceRoomListPaginationState},
+};
+
+/// Errors related to the [`SpaceService`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The hierarchy of a space couldn't be loaded from the homeserver.
+    #[error("couldn't load the space hierarchy: {0}")]
+    LoadHierarchy(#[source] HttpError),
+
+    /// The room isn't joined by the user.
+    #[error("room `{0}` isn't joined")]
+    RoomNotJoined(OwnedRoomId),
+
+    /// The user isn't allowed to send a state event in a room.
+    #[error("not allowed to send `{event_type}` events in room `{room_id}`")]
+    MissingPermission {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+        /// The type of the state event.
+        event_type: StateEventType,
+    },
+
+    /// The room isn't a child of the space.
+    #[error("room `{child_id}` isn't a child of space `{space_id}`")]
+    NotAChild {
+        /// The ID of the space.
+        space_id: OwnedRoomId,
+        /// The ID of the room.
+        child_id: OwnedRoomId,
+    },
+
+    /// The order of a child of a space is invalid.
+    #[error("invalid order `{0}`: it must have at most 50 printable ASCII characters")]
+    InvalidOrder(String),
+
+    /// Error from the client, when updating a space.
+    #[error(transparent)]
+    Sdk(#[from] matrix_sdk::Error),
+}
+
+/// The service to browse the spaces of the user.
+///
+/// # Example
+///
+/// ```no_run
+/// use matrix_sdk::{ruma::owned_room_id, Client};
+/// use matrix_sdk_ui::spaces::SpaceService;
+///
+/// # async {
+/// # let client: Client = todo!();
+/// let space_service = SpaceService::new(client);
+///
+/// // The top-level spaces of the user, updated live.
+/// let (spaces, spaces_stream) =
+///     space_service.subscribe_to_joined_spaces().await;
+///
+/// // The children of a space, loaded page by page.
+/// let room_list = space_service
+///     .space_room_list(owned_room_id!("!space:example.org"))
+///     .await;
+/// room_list.paginate().await?;
+/// let (rooms, rooms_stream) = room_list.subscribe_to_room_updates();
+/// # anyhow::Ok(()) };
+/// ```
+pub struct SpaceService {
+    client: Client,
+    joined_spaces: SharedObservable<Vec<SpaceRoom>>,
+    room_updates_task: AsyncMutex<Option<JoinHandle<()>>>,
+}
+
+impl SpaceService {
+    /// Create a new `SpaceService`.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            joined_spaces: SharedObservable::new(Vec::new()),
+            room_updates_task: AsyncMutex::new(None),
+        }
+    }
+
+    /// Get the top-level spaces the user has joined.
+    ///
+    /// A space is at the top level if it isn't the child of another space the
+    /// user has joined. The spaces are sorted by name.
+    pub async fn joined_spaces(&self) -> Vec<SpaceRoom> {
+        let spaces = top_level_joined_spaces(&self.client).await;
+        self.joined_spaces.set_if_not_eq(spaces.clone());
+        spaces
+    }
+
+    /// Get the top-level spaces the user has joined, and a stream of updates
+    /// for them.
+    ///
+    /// See [`SpaceService::joined_spaces()`] for the definition of top-level
+    /// spaces.
+    pub async fn subscribe_to_joined_spaces(
+        &self,
+    ) -> (Vec<SpaceRoom>, impl Stream<Item = Vec<SpaceRoom>>) {
+        let mut room_updates_task = self.room_updates_task.lock().await;
+
+        if room_updates_task.is_none() {
+            self.joined_spaces.set_if_not_eq(top_level_joined_spaces(&self.client).await);
+            *room_updates_task =
+                Some(spawn(joined_spaces_task(self.client.clone(), self.joined_spaces.clone())));
+        }
+
+        let subscriber = self.joined_spaces.subscribe();
+        (self.joined_spaces.get(), subscriber)
+    }
+
+    /// Create a list to browse the children of the given space.
+    ///
+    /// The list is initially filled with the children that were cached the
+    /// last time the space was browsed. Call [`SpaceRoomList::paginate()`] to
+    /// load the children from the homeserver.
+    pub async fn space_room_list(&self, space_id: OwnedRoomId) -> SpaceRoomList {
+        SpaceRoomList::new(self.client.clone(), space_id).await
+    }
+}
+
+impl Drop for SpaceService {
+    fn drop(&mut self) {
+        if let Some(room_updates_task) = self.room_updates_task.get_mut().take() {
+            room_updates_task.abort();
+        }
+    }
+}
+
+/// Keep the top-level joined spaces up to date.
+async fn joined_spaces_task(client: Client, joined_spaces: SharedObservable<Vec<SpaceRoom>>) {
+    let mut receiver = client.subscribe_to_all_room_updates();
+
+    loop {
+        match receiver.recv().await {
+            Ok(updates) => {
+                if !touches_joined_spaces(&client, &updates) {
+                    continue;
+                }
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+
+        joined_spaces.set_if_not_eq(top_level_joined_spaces(&client).await);
+    }
+}
+
+/// Whether the given updates may change the list of top-level joined spaces.
+///
+/// Only the spaces that were left, and the joined spaces whose state changed
+/// (their name, avatar, topic or children for example), are relevant. The
+/// messages sent in a space don't change the list.
+fn touches_joined_spaces(client: &Client, updates: &RoomUpdates) -> bool {
+    let is_space = |room_id: &RoomId| client.get_room(room_id).is_some_and(|room| room.is_space());
+
+    updates.left.keys().any(|room_id| is_space(room_id))
+        || updates
+            .joined
+            .iter()
+            .any(|(room_id, update)| touches_state(update, |_| true) && is_space(room_id))
+}
+
+/// Whether the given update contains changes to the `m.space.child` or
+/// `m.space.parent` state events of the room.
+pub(crate) fn touches_space_state(update: &JoinedRoomUpdate) -> bool {
+    touches_state(update, |event_type| {
+        matches!(event_type, StateEventType::SpaceChild | StateEventType::SpaceParent)
+    })
+}
+
+/// Whether the given update contains changes to state events of the room
+/// whose type matches the given filter.
+fn touches_state(update: &JoinedRoomUpdate, filter: impl Fn(StateEventType) -> bool) -> bool {
+    let matches = |event_type: Option<StateEventType>| event_type.is_some_and(&filter);
+
+    update.state.iter().any(|event| matches(event.get_field("type").ok().flatten()))
+        || update.timeline.events.iter().any(|event| {
+            event.raw().get_field::<&str>("state_key").is_ok_and(|key| key.is_some())
+                && matches(event.raw().get_field("type").ok().flatten())
+        })
+}
+
+async fn top_level_joined_spaces(client: &Client) -> Vec<SpaceRoom> {
+    let mut children = BTreeMap::new();
+
+    for space in client.joined_rooms().into_iter().filter(|room| room.is_space()) {
+        let space_children = space_children(&space).await;
+        children.insert(space.room_id().to_owned(), (space, space_children));
+    }
+
+    let nested_spaces = children
+        .values()
+        .flat_map(|(_, space_children)| space_children)
+        .filter(|room_id| children.contains_key(*room_id))
+        .collect::<BTreeSet<_>>();
+
+    let mut spaces = children
+        .iter()
+        .filter(|(room_id, _)| !nested_spaces.contains(room_id))
+        .map(|(_, (space, space_children))| {
+            SpaceRoom::from_room(space, space_children.len() as u64)
+        })
+        .collect::<Vec<_>>();
+
+    spaces.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.room_id.cmp(&b.room_id)));
+    spaces
+}
+
+/// The rooms and the sub-spaces of a space, at any depth.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SpaceDescendants {
+    /// The IDs of the rooms in the space or in one of its sub-spaces,
+    /// including the sub-spaces themselves.
+    pub rooms: BTreeSet<OwnedRoomId>,
+    /// The IDs of the space and of its sub-spaces.
+    pub spaces: BTreeSet<OwnedRoomId>,
+}
+
+/// Compute the descendants of the given space.
+///
+/// The children of each space are the ones of its `m.space.child` state
+/// events if the space is known locally, and the ones of its cached
+/// hierarchy, as loaded by a [`SpaceRoomList`].
+pub(crate) async fn space_descendants(client: &Client, space_id: &RoomId) -> SpaceDescendants {
+    let mut descendants = SpaceDescendants::default();
+    let mut queue = VecDeque::from([space_id.to_owned()]);
+
+    while let Some(space_id) = queue.pop_front() {
+        if !descendants.spaces.insert(space_id.clone()) {
+            // Spaces can form cycles.
+            continue;
+        }
+
+        let mut children = BTreeMap::new();
+
+        if let Some(space) = client.get_room(&space_id) {
+            for child_id in space_children(&space).await {
+                let is_space = client.get_room(&child_id).is_some_and(|room| room.is_space());
+                children.insert(child_id, is_space);
+            }
+        }
+
+        for chunk in room_list::load_cached_rooms(client, &space_id).await {
+            let is_space = chunk.room_type == Some(RoomType::Space);
+            *children.entry(chunk.room_id).or_default() |= is_space;
+        }
+
+        for (child_id, is_space) in children {
+            if is_space {
+                queue.push_back(child_id.clone());
+            }
+            descendants.rooms.insert(child_id);
+        }
+    }
+
+    descendants
+}
+
+/// The IDs of the children of the given space, according to its
+/// `m.space.child` state events.
+async fn space_children(space: &Room) -> Vec<OwnedRoomId> {
+    let events = match space.get_state_events_static::<SpaceChildEventContent>().await {
+        Ok(events) => events,
+        Err(error) => {
+            warn!(space_id = ?space.room_id(), "failed to load the children of the space: {error}");
+            return Vec::new();
+        }
+    };
+
+    events
+        .into_iter()
+        .filter_map(|event| match event.deserialize() {
+            // A child without `via` servers has been removed from the space.
+            Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event)))
+                if !event.content.via.is_empty() =>
+            {
+                Some(event.state_key)
+            }
+            Ok(SyncOrStrippedState::Stripped(event))
+                if event.content.via.as_ref().is_some_and(|via| !via.is_empty()) =>
+            {
+                Some(event.state_key)
+            }
+            _ => None,
+        })
+        .collect()
+}
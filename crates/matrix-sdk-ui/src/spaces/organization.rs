@@ -0,0 +1,354 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations to organize the children of a space.
+
+use matrix_sdk::{Room, RoomState};
+use matrix_sdk_base::deserialized_responses::{RawAnySyncOrStrippedState, SyncOrStrippedState};
+use ruma::{
+    api::client::room::create_room::v3::{CreationContent, Request as CreateRoomRequest},
+    events::{
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        InitialStateEvent, StateEventType, SyncStateEvent,
+    },
+    room::RoomType,
+    serde::Raw,
+    OwnedRoomId, OwnedServerName, RoomId,
+};
+use serde_json::{json, Value as JsonValue};
+use tracing::{instrument, warn};
+
+use super::{Error, SpaceService};
+
+impl SpaceService {
+    /// Add a room to a space.
+    ///
+    /// This sets the `m.space.child` state event in the space and, if the user
+    /// is allowed to, the `m.space.parent` state event in the room. If one of
+    /// them can't be set, the other one is rolled back.
+    ///
+    /// # Arguments
+    ///
+    /// * `space_id` - The ID of the space, which must be joined.
+    /// * `child_id` - The ID of the room to add to the space.
+    /// * `order` - The order of the room among its siblings, see
+    ///   [`SpaceService::set_child_order()`].
+    /// * `suggested` - Whether the room is suggested to the members of the
+    ///   space.
+    #[instrument(skip(self))]
+    pub async fn add_child_to_space(
+        &self,
+        space_id: &RoomId,
+        child_id: &RoomId,
+        order: Option<String>,
+        suggested: bool,
+    ) -> Result<(), Error> {
+        if let Some(order) = &order {
+            validate_order(order)?;
+        }
+
+        let space = self.room_with_permission(space_id, StateEventType::SpaceChild).await?;
+        let via = self.via();
+
+        let mut batch = StateBatch::default();
+
+        let mut child_content = SpaceChildEventContent::new(via.clone());
+        child_content.order = order;
+        child_content.suggested = suggested;
+        batch.push(&space, StateEventType::SpaceChild, child_id, to_json(child_content)?).await?;
+
+        // The parent relationship is optional, so it's only set when possible.
+        if let Some(child) = self.joined_room_with_permission(child_id).await? {
+            let parent_content = SpaceParentEventContent::new(via);
+            batch
+                .push(&child, StateEventType::SpaceParent, space_id, to_json(parent_content)?)
+                .await?;
+        }
+
+        batch.apply().await
+    }
+
+    /// Remove a room from a space.
+    ///
+    /// This removes the `m.space.child` state event from the space and, if
+    /// the user is allowed to, the `m.space.parent` state event from the room.
+    /// If one of them can't be removed, the other one is rolled back.
+    #[instrument(skip(self))]
+    pub async fn remove_child_from_space(
+        &self,
+        space_id: &RoomId,
+        child_id: &RoomId,
+    ) -> Result<(), Error> {
+        let space = self.room_with_permission(space_id, StateEventType::SpaceChild).await?;
+
+        let mut batch = StateBatch::default();
+        batch.push(&space, StateEventType::SpaceChild, child_id, json!({})).await?;
+
+        if let Some(child) = self.joined_room_with_permission(child_id).await? {
+            if child
+                .get_state_event_static_for_key::<SpaceParentEventContent, _>(space_id)
+                .await?
+                .is_some()
+            {
+                batch.push(&child, StateEventType::SpaceParent, space_id, json!({})).await?;
+            }
+        }
+
+        batch.apply().await
+    }
+
+    /// Set the order of a child of a space among its siblings.
+    ///
+    /// The children of a space are sorted by the lexicographic order of their
+    /// `order`, and the children without one come last. An order must have at
+    /// most 50 characters, all in the printable ASCII range.
+    #[instrument(skip(self))]
+    pub async fn set_child_order(
+        &self,
+        space_id: &RoomId,
+        child_id: &RoomId,
+        order: Option<String>,
+    ) -> Result<(), Error> {
+        if let Some(order) = &order {
+            validate_order(order)?;
+        }
+
+        self.update_child(space_id, child_id, |content| content.order = order).await
+    }
+
+    /// Set whether a child of a space is suggested to the members of the
+    /// space.
+    #[instrument(skip(self))]
+    pub async fn set_child_suggested(
+        &self,
+        space_id: &RoomId,
+        child_id: &RoomId,
+        suggested: bool,
+    ) -> Result<(), Error> {
+        self.update_child(space_id, child_id, |content| content.suggested = suggested).await
+    }
+
+    /// Create a new space, and add it as a child of the given space.
+    ///
+    /// The new space lists the given space as its canonical parent. If it
+    /// can't be added to the given space, the new space is left.
+    #[instrument(skip(self))]
+    pub async fn create_subspace(
+        &self,
+        space_id: &RoomId,
+        name: String,
+        topic: Option<String>,
+    ) -> Result<Room, Error> {
+        let space = self.room_with_permission(space_id, StateEventType::SpaceChild).await?;
+        let via = self.via();
+
+        let mut creation_content = CreationContent::new();
+        creation_content.room_type = Some(RoomType::Space);
+
+        let mut parent_content = SpaceParentEventContent::new(via.clone());
+        parent_content.canonical = true;
+
+        let mut request = CreateRoomRequest::new();
+        request.name = Some(name);
+        request.topic = topic;
+        request.creation_content =
+            Some(Raw::new(&creation_content).map_err(matrix_sdk::Error::from)?);
+        request.initial_state =
+            vec![InitialStateEvent { content: parent_content, state_key: space_id.to_owned() }
+                .to_raw_any()];
+
+        let subspace = self.client.create_room(request).await?;
+
+        let mut batch = StateBatch::default();
+        batch
+            .push(
+                &space,
+                StateEventType::SpaceChild,
+                subspace.room_id(),
+                to_json(SpaceChildEventContent::new(via))?,
+            )
+            .await?;
+
+        if let Err(error) = batch.apply().await {
+            if let Err(leave_error) = subspace.leave().await {
+                warn!("failed to leave the subspace after an error: {leave_error}");
+            }
+            return Err(error);
+        }
+
+        Ok(subspace)
+    }
+
+    /// Update the `m.space.child` state event of a child of a space.
+    async fn update_child(
+        &self,
+        space_id: &RoomId,
+        child_id: &RoomId,
+        update: impl FnOnce(&mut SpaceChildEventContent),
+    ) -> Result<(), Error> {
+        let space = self.room_with_permission(space_id, StateEventType::SpaceChild).await?;
+
+        let mut content = space
+            .get_state_event_static_for_key::<SpaceChildEventContent, _>(child_id)
+            .await?
+            .and_then(|event| match event.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event))) => {
+                    Some(event.content)
+                }
+                _ => None,
+            })
+            .filter(|content| !content.via.is_empty())
+            .ok_or_else(|| Error::NotAChild {
+                space_id: space_id.to_owned(),
+                child_id: child_id.to_owned(),
+            })?;
+
+        update(&mut content);
+
+        let mut batch = StateBatch::default();
+        batch.push(&space, StateEventType::SpaceChild, child_id, to_json(content)?).await?;
+        batch.apply().await
+    }
+
+    /// Get the joined room with the given ID, if the user is allowed to send
+    /// the given state event type in it.
+    async fn room_with_permission(
+        &self,
+        room_id: &RoomId,
+        event_type: StateEventType,
+    ) -> Result<Room, Error> {
+        let room = self
+            .client
+            .get_room(room_id)
+            .filter(|room| room.state() == RoomState::Joined)
+            .ok_or_else(|| Error::RoomNotJoined(room_id.to_owned()))?;
+
+        let user_id = self.client.user_id().ok_or(matrix_sdk::Error::AuthenticationRequired)?;
+        if !room.can_user_send_state(user_id, event_type.clone()).await? {
+            return Err(Error::MissingPermission { room_id: room_id.to_owned(), event_type });
+        }
+
+        Ok(room)
+    }
+
+    /// Get the joined room with the given ID, if the user is allowed to set
+    /// its parent spaces.
+    async fn joined_room_with_permission(&self, room_id: &RoomId) -> Result<Option<Room>, Error> {
+        match self.room_with_permission(room_id, StateEventType::SpaceParent).await {
+            Ok(room) => Ok(Some(room)),
+            Err(Error::RoomNotJoined(_) | Error::MissingPermission { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// The servers to use to join the rooms of a space.
+    fn via(&self) -> Vec<OwnedServerName> {
+        self.client.user_id().map(|user_id| user_id.server_name().to_owned()).into_iter().collect()
+    }
+}
+
+/// A state update of a [`StateBatch`].
+struct StateUpdate {
+    room: Room,
+    event_type: StateEventType,
+    state_key: OwnedRoomId,
+    content: JsonValue,
+    previous_content: JsonValue,
+}
+
+/// A batch of state updates, that are rolled back if one of them fails.
+#[derive(Default)]
+struct StateBatch {
+    updates: Vec<StateUpdate>,
+}
+
+impl StateBatch {
+    /// Add a state update to the batch, and remember the current content of
+    /// the state event to roll it back.
+    async fn push(
+        &mut self,
+        room: &Room,
+        event_type: StateEventType,
+        state_key: &RoomId,
+        content: JsonValue,
+    ) -> Result<(), Error> {
+        let previous_content = room
+            .get_state_event(event_type.clone(), state_key.as_str())
+            .await?
+            .and_then(|event| match event {
+                RawAnySyncOrStrippedState::Sync(event) => event.get_field("content").ok().flatten(),
+                RawAnySyncOrStrippedState::Stripped(event) => {
+                    event.get_field("content").ok().flatten()
+                }
+            })
+            .unwrap_or_else(|| json!({}));
+
+        self.updates.push(StateUpdate {
+            room: room.clone(),
+            event_type,
+            state_key: state_key.to_owned(),
+            content,
+            previous_content,
+        });
+
+        Ok(())
+    }
+
+    /// Send all the state updates, and roll back the ones that were sent if
+    /// one of them fails.
+    async fn apply(self) -> Result<(), Error> {
+        for (index, update) in self.updates.iter().enumerate() {
+            if let Err(error) = update.send(&update.content).await {
+                for update in self.updates[..index].iter().rev() {
+                    if let Err(error) = update.send(&update.previous_content).await {
+                        warn!(
+                            room_id = ?update.room.room_id(),
+                            "failed to roll back a `{}` state event: {error}",
+                            update.event_type
+                        );
+                    }
+                }
+
+                return Err(error.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl StateUpdate {
+    async fn send(&self, content: &JsonValue) -> matrix_sdk::Result<()> {
+        self.room
+            .send_state_event_raw(
+                &self.event_type.to_string(),
+                self.state_key.as_str(),
+                content.clone(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Check that the given string is a valid `order` of a space child.
+fn validate_order(order: &str) -> Result<(), Error> {
+    if order.len() > 50 || !order.bytes().all(|byte| (0x20..=0x7e).contains(&byte)) {
+        return Err(Error::InvalidOrder(order.to_owned()));
+    }
+    Ok(())
+}
+
+fn to_json(content: impl serde::Serialize) -> Result<JsonValue, Error> {
+    Ok(serde_json::to_value(content).map_err(matrix_sdk::Error::from)?)
+}
@@ -0,0 +1,101 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::{Client, Room, RoomState};
+use ruma::{
+    api::client::space::SpaceHierarchyRoomsChunk,
+    events::room::{guest_access::GuestAccess, history_visibility::HistoryVisibility},
+    room::RoomType,
+    space::SpaceRoomJoinRule,
+    OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
+};
+
+/// A room or a space, as listed in a space hierarchy.
+///
+/// It is built from the summary returned by the homeserver for rooms the user
+/// may not know about, and from the local data for the rooms the user is in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpaceRoom {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+    /// The canonical alias of the room, if any.
+    pub canonical_alias: Option<OwnedRoomAliasId>,
+    /// The name of the room, if any.
+    pub name: Option<String>,
+    /// The topic of the room, if any.
+    pub topic: Option<String>,
+    /// The avatar URL of the room, if any.
+    pub avatar_url: Option<OwnedMxcUri>,
+    /// The type of the room, e.g. `m.space` for a space.
+    pub room_type: Option<RoomType>,
+    /// The number of members that have joined the room.
+    pub num_joined_members: u64,
+    /// The join rule of the room.
+    pub join_rule: SpaceRoomJoinRule,
+    /// Whether the room may be viewed by users without joining.
+    pub world_readable: bool,
+    /// Whether guest users may join the room.
+    pub guest_can_join: bool,
+    /// The number of children of the room, if it is a space.
+    pub children_count: u64,
+    /// The state of the room for the current user, if the room is known
+    /// locally.
+    pub state: Option<RoomState>,
+}
+
+impl SpaceRoom {
+    /// Create a `SpaceRoom` from a chunk of a space hierarchy, reconciled with
+    /// the local state of the room.
+    pub(super) fn from_chunk(chunk: SpaceHierarchyRoomsChunk, client: &Client) -> Self {
+        let local_room = client.get_room(&chunk.room_id);
+
+        Self {
+            name: chunk.name.or_else(|| local_room.as_ref().and_then(|room| room.name())),
+            state: local_room.map(|room| room.state()),
+            room_id: chunk.room_id,
+            canonical_alias: chunk.canonical_alias,
+            topic: chunk.topic,
+            avatar_url: chunk.avatar_url,
+            room_type: chunk.room_type,
+            num_joined_members: chunk.num_joined_members.into(),
+            join_rule: chunk.join_rule,
+            world_readable: chunk.world_readable,
+            guest_can_join: chunk.guest_can_join,
+            children_count: chunk.children_state.len() as u64,
+        }
+    }
+
+    /// Create a `SpaceRoom` from a room known locally.
+    pub(super) fn from_room(room: &Room, children_count: u64) -> Self {
+        Self {
+            room_id: room.room_id().to_owned(),
+            canonical_alias: room.canonical_alias(),
+            name: room.name(),
+            topic: room.topic(),
+            avatar_url: room.avatar_url(),
+            room_type: room.room_type(),
+            num_joined_members: room.joined_members_count(),
+            join_rule: room.join_rule().as_str().into(),
+            world_readable: room.history_visibility() == Some(HistoryVisibility::WorldReadable),
+            guest_can_join: room.guest_access() == GuestAccess::CanJoin,
+            children_count,
+            state: Some(room.state()),
+        }
+    }
+
+    /// Whether this room is a space.
+    pub fn is_space(&self) -> bool {
+        self.room_type == Some(RoomType::Space)
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use eyeball_im::{ObservableVector, VectorDiff};
+use futures_core::Stream;
+use imbl::Vector;
+use matrix_sdk::{
+    executor::{spawn, JoinHandle},
+    Client,
+};
+use ruma::{
+    api::client::space::{get_hierarchy, SpaceHierarchyRoomsChunk},
+    uint, OwnedRoomId, RoomId,
+};
+use tokio::sync::{broadcast::error::RecvError, Mutex as AsyncMutex};
+use tracing::{debug, warn};
+
+use super::{touches_space_state, Error, SpaceRoom};
+
+/// The pagination state of a [`SpaceRoomList`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpaceRoomListPaginationState {
+    /// No page is being loaded.
+    Idle {
+        /// Whether all the children of the space have been loaded.
+        end_reached: bool,
+    },
+    /// A page is being loaded.
+    Loading,
+}
+
+#[derive(Debug, Default)]
+enum PaginationToken {
+    /// No page has been loaded yet.
+    #[default]
+    None,
+    /// There are more pages, and this is the token to load the next one.
+    HasMore(String),
+    /// All the pages have been loaded.
+    HitEnd,
+}
+
+/// The list of the children of a space, loaded page by page from the
+/// homeserver.
+///
+/// The first page is cached in the state store, so the list is filled with the
+/// last known children before anything is loaded. The list is reloaded when
+/// the children of the space change, and the state of the rooms is updated
+/// when the user joins or leaves them.
+///
+/// It's created with [`SpaceService::space_room_list()`].
+///
+/// [`SpaceService::space_room_list()`]: super::SpaceService::space_room_list
+pub struct SpaceRoomList {
+    inner: Arc<SpaceRoomListInner>,
+    room_updates_task: JoinHandle<()>,
+}
+
+struct SpaceRoomListInner {
+    client: Client,
+    space_id: OwnedRoomId,
+    token: AsyncMutex<PaginationToken>,
+    rooms: Mutex<ObservableVector<SpaceRoom>>,
+    pagination_state: SharedObservable<SpaceRoomListPaginationState>,
+}
+
+impl SpaceRoomList {
+    pub(super) async fn new(client: Client, space_id: OwnedRoomId) -> Self {
+        let mut rooms = ObservableVector::new();
+        rooms.append(
+            load_cached_rooms(&client, &space_id)
+                .await
+                .into_iter()
+                .map(|chunk| SpaceRoom::from_chunk(chunk, &client))
+                .collect(),
+        );
+
+        let inner = Arc::new(SpaceRoomListInner {
+            client,
+            space_id,
+            token: Default::default(),
+            rooms: Mutex::new(rooms),
+            pagination_state: SharedObservable::new(SpaceRoomListPaginationState::Idle {
+                end_reached: false,
+            }),
+        });
+
+        let room_updates_task = spawn(room_updates_task(inner.clone()));
+
+        Self { inner, room_updates_task }
+    }
+
+    /// The ID of the space.
+    pub fn space_id(&self) -> &RoomId {
+        &self.inner.space_id
+    }
+
+    /// Load the next page of children of the space.
+    ///
+    /// Does nothing if all the children have already been loaded.
+    pub async fn paginate(&self) -> Result<(), Error> {
+        self.inner.paginate().await
+    }
+
+    /// Reload the children of the space from the first page.
+    ///
+    /// The current children stay in the list until the first page is loaded.
+    pub async fn reset(&self) -> Result<(), Error> {
+        self.inner.reset().await;
+        self.inner.paginate().await
+    }
+
+    /// Get the children of the space that have been loaded so far.
+    pub fn rooms(&self) -> Vector<SpaceRoom> {
+        self.inner.rooms.lock().unwrap().clone()
+    }
+
+    /// Get the children of the space that have been loaded so far, and a
+    /// stream of updates for them.
+    pub fn subscribe_to_room_updates(
+        &self,
+    ) -> (Vector<SpaceRoom>, impl Stream<Item = Vec<VectorDiff<SpaceRoom>>>) {
+        self.inner.rooms.lock().unwrap().subscribe().into_values_and_batched_stream()
+    }
+
+    /// Get the current pagination state.
+    pub fn pagination_state(&self) -> SpaceRoomListPaginationState {
+        self.inner.pagination_state.get()
+    }
+
+    /// Subscribe to the updates of the pagination state.
+    pub fn subscribe_to_pagination_state_updates(
+        &self,
+    ) -> Subscriber<SpaceRoomListPaginationState> {
+        self.inner.pagination_state.subscribe()
+    }
+}
+
+impl Drop for SpaceRoomList {
+    fn drop(&mut self) {
+        self.room_updates_task.abort();
+    }
+}
+
+impl SpaceRoomListInner {
+    async fn paginate(&self) -> Result<(), Error> {
+        let mut token = self.token.lock().await;
+
+        let from = match &*token {
+            PaginationToken::None => None,
+            PaginationToken::HasMore(token) => Some(token.clone()),
+            PaginationToken::HitEnd => return Ok(()),
+        };
+        let is_first_page = from.is_none();
+
+        self.pagination_state.set(SpaceRoomListPaginationState::Loading);
+
+        let mut request = get_hierarchy::v1::Request::new(self.space_id.clone());
+        request.from = from;
+        request.max_depth = Some(uint!(1));
+
+        let response = match self.client.send(request).await {
+            Ok(response) => response,
+            Err(error) => {
+                self.pagination_state
+                    .set(SpaceRoomListPaginationState::Idle { end_reached: false });
+                return Err(Error::LoadHierarchy(error));
+            }
+        };
+
+        *token = match response.next_batch {
+            Some(next_batch) => PaginationToken::HasMore(next_batch),
+            None => PaginationToken::HitEnd,
+        };
+
+        // The first room of the first page is the space itself.
+        let chunks = response
+            .rooms
+            .into_iter()
+            .filter(|chunk| chunk.room_id != self.space_id)
+            .collect::<Vec<_>>();
+
+        if is_first_page {
+            save_cached_rooms(&self.client, &self.space_id, &chunks).await;
+        }
+
+        let rooms = chunks.into_iter().map(|chunk| SpaceRoom::from_chunk(chunk, &self.client));
+
+        {
+            let mut list = self.rooms.lock().unwrap();
+            if is_first_page {
+                list.clear();
+            }
+            list.append(rooms.collect());
+        }
+
+        self.pagination_state.set(SpaceRoomListPaginationState::Idle {
+            end_reached: matches!(*token, PaginationToken::HitEnd),
+        });
+
+        Ok(())
+    }
+
+    async fn reset(&self) {
+        *self.token.lock().await = PaginationToken::None;
+        self.pagination_state.set(SpaceRoomListPaginationState::Idle { end_reached: false });
+    }
+
+    /// Update the local state of the rooms of the list that are in
+    /// `room_ids`, or of all the rooms if it is `None`.
+    fn update_room_states(&self, room_ids: Option<&BTreeSet<&RoomId>>) {
+        let mut rooms = self.rooms.lock().unwrap();
+
+        for index in 0..rooms.len() {
+            let room = &rooms[index];
+
+            if room_ids.is_some_and(|room_ids| !room_ids.contains(&*room.room_id)) {
+                continue;
+            }
+
+            let state = self.client.get_room(&room.room_id).map(|room| room.state());
+
+            if room.state != state {
+                let mut room = room.clone();
+                room.state = state;
+                rooms.set(index, room);
+            }
+        }
+    }
+}
+
+/// Keep the list up to date with the changes of the space and of its
+/// children.
+async fn room_updates_task(inner: Arc<SpaceRoomListInner>) {
+    let mut receiver = inner.client.subscribe_to_all_room_updates();
+
+    loop {
+        let updates = match receiver.recv().await {
+            Ok(updates) => updates,
+            Err(RecvError::Lagged(_)) => {
+                warn!("missed some room updates, refreshing all the rooms of the space");
+                inner.update_room_states(None);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        if updates.joined.get(&inner.space_id).is_some_and(touches_space_state) {
+            debug!(space_id = ?inner.space_id, "children of the space changed, reloading");
+
+            inner.reset().await;
+            if let Err(error) = inner.paginate().await {
+                warn!("failed to reload the children of the space: {error}");
+            }
+        }
+
+        let room_ids = updates
+            .joined
+            .keys()
+            .chain(updates.left.keys())
+            .chain(updates.invited.keys())
+            .chain(updates.knocked.keys())
+            .map(|room_id| &**room_id)
+            .collect::<BTreeSet<_>>();
+        inner.update_room_states(Some(&room_ids));
+    }
+}
+
+fn cache_key(space_id: &RoomId) -> String {
+    format!("space_hierarchy:{space_id}")
+}
+
+pub(super) async fn load_cached_rooms(
+    client: &Client,
+    space_id: &RoomId,
+) -> Vec<SpaceHierarchyRoomsChunk> {
+    let value = match client.state_store().get_custom_value(cache_key(space_id).as_bytes()).await {
+        Ok(Some(value)) => value,
+        Ok(None) => return Vec::new(),
+        Err(error) => {
+            warn!("failed to load the cached space hierarchy: {error}");
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_slice(&value).unwrap_or_else(|error| {
+        warn!("failed to deserialize the cached space hierarchy: {error}");
+        Vec::new()
+    })
+}
+
+async fn save_cached_rooms(client: &Client, space_id: &RoomId, rooms: &[SpaceHierarchyRoomsChunk]) {
+    let value = match serde_json::to_vec(rooms) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("failed to serialize the space hierarchy: {error}");
+            return;
+        }
+    };
+
+    if let Err(error) =
+        client.state_store().set_custom_value_no_read(cache_key(space_id).as_bytes(), value).await
+    {
+        warn!("failed to cache the space hierarchy: {error}");
+    }
+}
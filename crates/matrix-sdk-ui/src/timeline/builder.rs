@@ -33,6 +33,7 @@ use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tracing::{info_span, trace, warn, Instrument, Span};
 
 use super::{
+    content_transform::ContentTransformer,
     controller::{TimelineController, TimelineSettings},
     to_device::{handle_forwarded_room_key_event, handle_room_key_event},
     DateDividerMode, Error, Timeline, TimelineDropHandle, TimelineFocus,
@@ -148,6 +149,17 @@ impl TimelineBuilder {
         self
     }
 
+    /// Use the given hook to transform the bodies of the messages, for
+    /// instance to translate them or to flag phishing links.
+    ///
+    /// It's applied to all the messages of the timeline, whether they're
+    /// received from sync or from pagination. See [`ContentTransformer`] for
+    /// details.
+    pub fn with_content_transformer(mut self, transformer: Arc<dyn ContentTransformer>) -> Self {
+        self.settings.content_transformer = Some(transformer);
+        self
+    }
+
     /// Create a [`Timeline`] with the options set on this builder.
     #[tracing::instrument(
         skip(self),
@@ -194,6 +206,12 @@ impl TimelineBuilder {
             None
         };
 
+        let content_transform_join_handle = controller
+            .settings
+            .content_transformer
+            .is_some()
+            .then(|| spawn(content_transform_task(controller.clone())));
+
         let encryption_changes_handle = spawn({
             let inner = controller.clone();
             async move {
@@ -291,6 +309,7 @@ impl TimelineBuilder {
                 event_handler_handles: event_handlers,
                 room_update_join_handle,
                 pinned_events_join_handle,
+                content_transform_join_handle,
                 room_key_from_backups_join_handle,
                 room_key_backup_enabled_join_handle,
                 room_keys_received_join_handle,
@@ -330,6 +349,21 @@ where
     }
 }
 
+/// The task that transforms the content of the messages when the timeline
+/// items change.
+async fn content_transform_task(timeline_controller: TimelineController) {
+    let (_, stream) = timeline_controller.subscribe().await;
+    pin_mut!(stream);
+
+    timeline_controller.apply_content_transforms().await;
+
+    // Updating the items will trigger another round, which is a no-op since all the
+    // messages are transformed by then.
+    while stream.next().await.is_some() {
+        timeline_controller.apply_content_transforms().await;
+    }
+}
+
 /// The task that handles the [`RoomEventCacheUpdate`]s.
 async fn room_event_cache_updates_task(
     room_event_cache: RoomEventCache,
@@ -0,0 +1,97 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Post-processing of the bodies of the messages in a timeline, like
+//! translation or profanity filtering.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Mutex,
+};
+
+use matrix_sdk::{BoxFuture, SendOutsideWasm, SyncOutsideWasm};
+use ruma::{EventId, OwnedEventId};
+
+/// A hook transforming the bodies of the messages displayed in a
+/// [`Timeline`](super::Timeline).
+///
+/// It's called at most once per event and body: its results are cached, and
+/// it's only called again if the message is edited. The transformed body is
+/// exposed with
+/// [`Message::transformed_body()`](super::Message::transformed_body), while the
+/// original one is still available with
+/// [`Message::body()`](super::Message::body).
+pub trait ContentTransformer: SendOutsideWasm + SyncOutsideWasm + fmt::Debug {
+    /// Transform the body of the message with the given event ID.
+    ///
+    /// Returns `None` if the body should be displayed as is.
+    fn transform<'a>(
+        &'a self,
+        event_id: &'a EventId,
+        body: &'a str,
+    ) -> BoxFuture<'a, Option<String>>;
+}
+
+/// The cached result of a [`ContentTransformer`] for a single event.
+#[derive(Clone, Debug)]
+struct CachedTransform {
+    /// The body the transformer was called with.
+    source: String,
+
+    /// The result of the transformer.
+    transformed: Option<String>,
+}
+
+/// The results of a [`ContentTransformer`] for the events of a timeline, and
+/// the events for which the original content should be displayed instead.
+#[derive(Debug, Default)]
+pub(super) struct ContentTransformCache {
+    transforms: Mutex<HashMap<OwnedEventId, CachedTransform>>,
+    show_original: Mutex<HashSet<OwnedEventId>>,
+}
+
+impl ContentTransformCache {
+    /// Get the cached transformation of the given body, if it's been computed
+    /// already.
+    ///
+    /// The outer `Option` is `None` if the body hasn't been transformed yet.
+    pub fn get(&self, event_id: &EventId, source: &str) -> Option<Option<String>> {
+        let transforms = self.transforms.lock().unwrap();
+        let cached = transforms.get(event_id)?;
+        (cached.source == source).then(|| cached.transformed.clone())
+    }
+
+    /// Save the transformation of the given body.
+    pub fn insert(&self, event_id: OwnedEventId, source: String, transformed: Option<String>) {
+        self.transforms.lock().unwrap().insert(event_id, CachedTransform { source, transformed });
+    }
+
+    /// Whether the original content of the given event should be displayed.
+    pub fn shows_original(&self, event_id: &EventId) -> bool {
+        self.show_original.lock().unwrap().contains(event_id)
+    }
+
+    /// Set whether the original content of the given event should be
+    /// displayed.
+    pub fn set_show_original(&self, event_id: &EventId, show_original: bool) {
+        let mut set = self.show_original.lock().unwrap();
+
+        if show_original {
+            set.insert(event_id.to_owned());
+        } else {
+            set.remove(event_id);
+        }
+    }
+}
@@ -12,13 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeSet, fmt, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    sync::Arc,
+};
 
 use as_variant::as_variant;
 use decryption_retry_task::DecryptionRetryTask;
 use eyeball_im::VectorDiff;
 use eyeball_im_util::vector::VectorObserverExt;
 use futures_core::Stream;
+use futures_util::future::join_all;
 use imbl::Vector;
 #[cfg(test)]
 use matrix_sdk::{crypto::OlmMachine, SendOutsideWasm};
@@ -64,6 +69,7 @@ pub(super) use self::{
 };
 use super::{
     algorithms::{rfind_event_by_id, rfind_event_item},
+    content_transform::{ContentTransformCache, ContentTransformer},
     event_handler::TimelineEventKind,
     event_item::{ReactionStatus, RemoteEventOrigin},
     item::TimelineUniqueId,
@@ -135,6 +141,9 @@ pub(super) struct TimelineController<P: RoomDataProvider = Room, D: Decryptor =
     /// Long-running task used to retry decryption of timeline items without
     /// blocking main processing.
     decryption_retry_task: DecryptionRetryTask<D>,
+
+    /// The results of the [`ContentTransformer`] of the timeline, if any.
+    content_transforms: Arc<ContentTransformCache>,
 }
 
 #[derive(Clone)]
@@ -151,6 +160,9 @@ pub(super) struct TimelineSettings {
 
     /// Should the timeline items be grouped by day or month?
     pub(super) date_divider_mode: DateDividerMode,
+
+    /// An optional hook transforming the bodies of the messages.
+    pub(super) content_transformer: Option<Arc<dyn ContentTransformer>>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -170,6 +182,7 @@ impl Default for TimelineSettings {
             event_filter: Arc::new(default_event_filter),
             add_failed_to_parse: true,
             date_divider_mode: DateDividerMode::Daily,
+            content_transformer: None,
         }
     }
 }
@@ -311,6 +324,7 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
             room_data_provider,
             settings,
             decryption_retry_task,
+            content_transforms: Default::default(),
         }
     }
 
@@ -1162,6 +1176,112 @@ impl<P: RoomDataProvider, D: Decryptor> TimelineController<P, D> {
         trace!("Done forcing update of sender profiles");
     }
 
+    /// Run the [`ContentTransformer`] of the timeline on the messages whose
+    /// body hasn't been transformed yet, and update their items.
+    pub(super) async fn apply_content_transforms(&self) {
+        let Some(transformer) = &self.settings.content_transformer else {
+            return;
+        };
+
+        // Collect the bodies to transform first, to not hold the lock on the state
+        // while the transformer runs.
+        let pending = {
+            let state = self.state.read().await;
+            state
+                .items
+                .iter()
+                .filter_map(|item| {
+                    let event_item = item.as_event()?;
+                    let event_id = event_item.event_id()?;
+                    let body = event_item.content().as_message()?.body();
+                    self.content_transforms
+                        .get(event_id, body)
+                        .is_none()
+                        .then(|| (event_id.to_owned(), body.to_owned()))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        if !pending.is_empty() {
+            trace!("Transforming the content of {} messages", pending.len());
+
+            let results = join_all(
+                pending.iter().map(|(event_id, body)| transformer.transform(event_id, body)),
+            )
+            .await;
+
+            for ((event_id, body), transformed) in pending.into_iter().zip(results) {
+                self.content_transforms.insert(event_id, body, transformed);
+            }
+        }
+
+        self.update_transformed_items(None).await;
+    }
+
+    /// Set whether the original body of the given message should be displayed,
+    /// instead of its transformed body.
+    ///
+    /// Returns `false` if there's no message with the given event ID in the
+    /// timeline.
+    pub(super) async fn show_original_content(
+        &self,
+        event_id: &EventId,
+        show_original: bool,
+    ) -> bool {
+        self.content_transforms.set_show_original(event_id, show_original);
+        self.update_transformed_items(Some(event_id)).await
+    }
+
+    /// Update the items of the messages whose transformation or "show
+    /// original" flag is out of date, optionally limited to a single event.
+    ///
+    /// Returns whether a message item has been found.
+    async fn update_transformed_items(&self, only_event_id: Option<&EventId>) -> bool {
+        let mut found = false;
+
+        let mut state = self.state.write().await;
+        let mut entries = state.items.entries();
+        while let Some(mut entry) = entries.next() {
+            let Some(event_item) = entry.as_event() else { continue };
+            let Some(event_id) = event_item.event_id() else { continue };
+
+            if only_event_id.is_some_and(|only_event_id| only_event_id != event_id) {
+                continue;
+            }
+
+            let TimelineItemContent::MsgLike(msglike) = event_item.content() else { continue };
+            let MsgLikeKind::Message(message) = &msglike.kind else { continue };
+
+            found = true;
+
+            // Only update the items whose body has been transformed already.
+            let Some(transformed_body) = self.content_transforms.get(event_id, message.body())
+            else {
+                continue;
+            };
+            let show_original = self.content_transforms.shows_original(event_id);
+
+            if message.transformed_body == transformed_body
+                && message.show_original == show_original
+            {
+                continue;
+            }
+
+            trace!(?event_id, "Updating the transformed content of a message");
+
+            let mut message = message.clone();
+            message.transformed_body = transformed_body;
+            message.show_original = show_original;
+
+            let new_content =
+                TimelineItemContent::MsgLike(msglike.with_kind(MsgLikeKind::Message(message)));
+            let new_item = entry.with_kind(event_item.with_content(new_content));
+            ObservableItemsEntry::replace(&mut entry, new_item);
+        }
+
+        found
+    }
+
     #[cfg(test)]
     pub(super) async fn handle_read_receipts(&self, receipt_event_content: ReceiptEventContent) {
         let own_user_id = self.room_data_provider.own_user_id();
@@ -1414,6 +1534,65 @@ impl TimelineController {
         Ok(())
     }
 
+    /// Walk the reply chain of the event with the given ID, see
+    /// [`Timeline::replied_to_chain`].
+    ///
+    /// [`Timeline::replied_to_chain`]: super::Timeline::replied_to_chain
+    pub(super) async fn replied_to_chain(
+        &self,
+        event_id: &EventId,
+        max_depth: usize,
+    ) -> Result<Vec<InReplyToDetails>, Error> {
+        let items = self.items().await;
+        let (_, item) = rfind_event_by_id(&items, event_id)
+            .ok_or(Error::EventNotInTimeline(TimelineEventItemId::EventId(event_id.to_owned())))?;
+
+        let mut chain = Vec::new();
+        let mut visited = HashSet::from([event_id.to_owned()]);
+        let mut next = item.content().in_reply_to().map(|details| details.event_id);
+
+        while let Some(ancestor_id) = next.take() {
+            if chain.len() >= max_depth {
+                break;
+            }
+
+            if !visited.insert(ancestor_id.clone()) {
+                warn!(%ancestor_id, "The reply chain contains a cycle");
+                break;
+            }
+
+            let event = if let Some((_, item)) = rfind_event_by_id(&items, &ancestor_id) {
+                trace!(%ancestor_id, "Found ancestor locally");
+                TimelineDetails::Ready(Box::new(RepliedToEvent::from_timeline_item(&item)))
+            } else {
+                trace!(%ancestor_id, "Fetching ancestor");
+                match self.room().load_or_fetch_event(&ancestor_id, None).await {
+                    Ok(timeline_event) => {
+                        match RepliedToEvent::try_from_timeline_event(timeline_event, self.room())
+                            .await
+                        {
+                            Ok(event) => TimelineDetails::Ready(Box::new(event)),
+                            Err(err) => {
+                                debug!(%ancestor_id, "Can't walk the reply chain further: {err}");
+                                TimelineDetails::Unavailable
+                            }
+                        }
+                    }
+                    Err(err) => TimelineDetails::Error(Arc::new(err)),
+                }
+            };
+
+            next =
+                as_variant!(&event, TimelineDetails::Ready(event) => event.content().in_reply_to())
+                    .flatten()
+                    .map(|details| details.event_id);
+
+            chain.push(InReplyToDetails { event_id: ancestor_id, event });
+        }
+
+        Ok(chain)
+    }
+
     /// Check whether the given receipt should be sent.
     ///
     /// Returns `false` if the given receipt is older than the current one.
@@ -404,6 +404,8 @@ mod observable_items_tests {
                         msgtype: MessageType::Text(TextMessageEventContent::plain("hello")),
                         edited: false,
                         mentions: None,
+                        transformed_body: None,
+                        show_original: false,
                     }),
                     reactions: Default::default(),
                     thread_root: None,
@@ -24,7 +24,7 @@ use ruma::{
         },
         room::message::{
             MessageType, Relation, RoomMessageEventContent, RoomMessageEventContentWithoutRelation,
-            SyncRoomMessageEvent,
+            SyncRoomMessageEvent, UnstableAmplitude,
         },
         AnySyncMessageLikeEvent, AnySyncTimelineEvent, BundledMessageLikeRelations, Mentions,
     },
@@ -41,6 +41,8 @@ pub struct Message {
     pub(in crate::timeline) msgtype: MessageType,
     pub(in crate::timeline) edited: bool,
     pub(in crate::timeline) mentions: Option<Mentions>,
+    pub(in crate::timeline) transformed_body: Option<String>,
+    pub(in crate::timeline) show_original: bool,
 }
 
 impl Message {
@@ -53,7 +55,13 @@ impl Message {
         let mut msgtype = c.msgtype;
         msgtype.sanitize(DEFAULT_SANITIZER_MODE, remove_reply_fallback);
 
-        let mut ret = Self { msgtype, edited: false, mentions: c.mentions };
+        let mut ret = Self {
+            msgtype,
+            edited: false,
+            mentions: c.mentions,
+            transformed_body: None,
+            show_original: false,
+        };
 
         if let Some(edit) = edit {
             ret.apply_edit(edit);
@@ -70,6 +78,8 @@ impl Message {
         self.msgtype = new_content.msgtype;
         self.mentions = new_content.mentions;
         self.edited = true;
+        // The transformation applied to the previous body is now stale.
+        self.transformed_body = None;
     }
 
     /// Get the `msgtype`-specific data of this message.
@@ -84,6 +94,35 @@ impl Message {
         self.msgtype.body()
     }
 
+    /// Get the body of this message, as transformed by the
+    /// [`ContentTransformer`] of the timeline, if any.
+    ///
+    /// [`ContentTransformer`]: crate::timeline::ContentTransformer
+    pub fn transformed_body(&self) -> Option<&str> {
+        self.transformed_body.as_deref()
+    }
+
+    /// Whether the original body of this message should be displayed, instead
+    /// of its transformed body.
+    ///
+    /// See [`Timeline::show_original_content()`].
+    ///
+    /// [`Timeline::show_original_content()`]: crate::timeline::Timeline::show_original_content
+    pub fn is_showing_original(&self) -> bool {
+        self.show_original
+    }
+
+    /// Get the body of this message that should be displayed.
+    ///
+    /// This is the transformed body if there's one and the original body
+    /// wasn't requested, and the original body otherwise.
+    pub fn display_body(&self) -> &str {
+        match &self.transformed_body {
+            Some(transformed) if !self.show_original => transformed,
+            _ => self.body(),
+        }
+    }
+
     /// Get the edit state of this message (has been edited: `true` /
     /// `false`).
     pub fn is_edited(&self) -> bool {
@@ -94,6 +133,28 @@ impl Message {
     pub fn mentions(&self) -> Option<&Mentions> {
         self.mentions.as_ref()
     }
+
+    /// Whether this message is a voice message, as defined in [MSC3245].
+    ///
+    /// [MSC3245]: https://github.com/matrix-org/matrix-spec-proposals/pull/3245
+    pub fn is_voice_message(&self) -> bool {
+        matches!(&self.msgtype, MessageType::Audio(c) if c.voice.is_some())
+    }
+
+    /// Get the waveform of this message, if it is an audio message with
+    /// [MSC1767] audio details.
+    ///
+    /// The amplitudes are between 0 and 1024.
+    ///
+    /// [MSC1767]: https://github.com/matrix-org/matrix-spec-proposals/pull/1767
+    pub fn voice_waveform(&self) -> Option<Vec<u16>> {
+        let MessageType::Audio(c) = &self.msgtype else {
+            return None;
+        };
+
+        let waveform = c.audio.as_ref()?.waveform.iter();
+        Some(waveform.map(|a| u16::try_from(a.get()).unwrap_or(UnstableAmplitude::MAX)).collect())
+    }
 }
 
 /// Extracts the raw json of the edit event part of bundled relations.
@@ -170,9 +231,12 @@ pub(crate) fn extract_poll_edit_content(
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { msgtype: _, edited, mentions: _ } = self;
+        let Self { msgtype: _, edited, mentions: _, transformed_body: _, show_original } = self;
         // since timeline items are logged, don't include all fields here so
         // people don't leak personal data in bug reports
-        f.debug_struct("Message").field("edited", edited).finish_non_exhaustive()
+        f.debug_struct("Message")
+            .field("edited", edited)
+            .field("show_original", show_original)
+            .finish_non_exhaustive()
     }
 }
@@ -630,6 +630,17 @@ impl EncryptedMessage {
         }
     }
 
+    /// Whether we expected to be unable to decrypt this message, because its
+    /// keys were never meant to be shared with us.
+    ///
+    /// See [`UtdCause::is_expected`].
+    pub fn is_expected_utd(&self) -> bool {
+        match self {
+            EncryptedMessage::MegolmV1AesSha2 { cause, .. } => cause.is_expected(),
+            EncryptedMessage::OlmV1Curve25519AesSha2 { .. } | EncryptedMessage::Unknown => false,
+        }
+    }
+
     /// Return the ID of the Megolm session used to encrypt this message, if it
     /// was received via a Megolm session.
     pub(crate) fn session_id(&self) -> Option<&str> {
@@ -16,6 +16,7 @@
 
 use std::collections::HashMap;
 
+use matrix_sdk::room::polls::PollResults;
 use ruma::{
     events::poll::{
         compile_unstable_poll_results,
@@ -164,6 +165,33 @@ impl PollState {
     pub fn is_edit(&self) -> bool {
         self.has_been_edited
     }
+
+    /// Get the tally of the poll, with the users that selected each answer.
+    ///
+    /// This is updated live as responses are received, including responses
+    /// that were received before the poll start event itself.
+    pub fn tally(&self) -> PollResults {
+        PollResults::compile(
+            &self.start_event_content.poll_start,
+            self.response_data.iter().map(|response_data| PollResponseData {
+                sender: &response_data.sender,
+                origin_server_ts: response_data.timestamp,
+                selections: &response_data.answers,
+            }),
+            self.end_event_timestamp,
+        )
+    }
+
+    /// Get the answers that the given user selected, according to their
+    /// latest valid response.
+    pub fn user_answers(&self, user_id: &UserId) -> Vec<String> {
+        self.tally()
+            .answers
+            .into_iter()
+            .filter(|answer| answer.voters.iter().any(|voter| voter == user_id))
+            .map(|answer| answer.id)
+            .collect()
+    }
 }
 
 impl From<PollState> for NewUnstablePollStartEventContent {
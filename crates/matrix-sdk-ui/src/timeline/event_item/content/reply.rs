@@ -22,8 +22,8 @@ use matrix_sdk::{
 };
 use ruma::{
     events::{
-        poll::unstable_start::UnstablePollStartEventContent, AnyMessageLikeEventContent,
-        AnySyncTimelineEvent,
+        poll::unstable_start::UnstablePollStartEventContent, room::message::Relation,
+        AnyMessageLikeEventContent, AnySyncTimelineEvent,
     },
     html::RemoveReplyFallback,
     OwnedEventId, OwnedUserId, UserId,
@@ -69,6 +69,18 @@ impl InReplyToDetails {
 
         InReplyToDetails { event_id, event: TimelineDetails::from_initial_value(event) }
     }
+
+    /// Create the details, not fetched yet, about the event replied to with
+    /// the given relation, if any.
+    fn from_relation<C>(relation: Option<&Relation<C>>) -> Option<Self> {
+        let event_id = match relation? {
+            Relation::Reply { in_reply_to } => in_reply_to.event_id.clone(),
+            Relation::Thread(thread) => thread.in_reply_to.as_ref()?.event_id.clone(),
+            _ => return None,
+        };
+
+        Some(Self { event_id, event: TimelineDetails::Unavailable })
+    }
 }
 
 /// An event that is replied to.
@@ -140,7 +152,9 @@ impl RepliedToEvent {
                     // include detailed information like reactions.
                     let reactions = ReactionsByKeyBySender::default();
                     let thread_root = None;
-                    let in_reply_to = None;
+                    // Keep the ID of the event this one replies to, so the reply chain can be
+                    // walked further, see `Timeline::replied_to_chain`.
+                    let in_reply_to = InReplyToDetails::from_relation(c.relates_to.as_ref());
                     let thread_summary = None;
 
                     TimelineItemContent::MsgLike(MsgLikeContent {
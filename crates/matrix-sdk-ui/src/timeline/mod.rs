@@ -28,7 +28,7 @@ use matrix_sdk::{
     event_cache::{EventCacheDropHandles, RoomEventCache},
     event_handler::EventHandlerHandle,
     executor::JoinHandle,
-    room::{edit::EditedContent, reply::Reply, Receipts, Room},
+    room::{edit::EditedContent, polls::NewPoll, reply::Reply, Receipts, Room},
     send_queue::{RoomSendQueueError, SendHandle},
     Client, Result,
 };
@@ -37,7 +37,11 @@ use pinned_events_loader::PinnedEventsRoom;
 use ruma::{
     api::client::receipt::create_receipt::v3::ReceiptType,
     events::{
-        poll::unstable_start::{NewUnstablePollStartEventContent, UnstablePollStartEventContent},
+        poll::{
+            unstable_end::UnstablePollEndEventContent,
+            unstable_response::UnstablePollResponseEventContent,
+            unstable_start::{NewUnstablePollStartEventContent, UnstablePollStartEventContent},
+        },
         receipt::{Receipt, ReceiptThread},
         room::{
             message::RoomMessageEventContentWithoutRelation,
@@ -57,6 +61,7 @@ use self::{
 
 mod algorithms;
 mod builder;
+mod content_transform;
 mod controller;
 mod date_dividers;
 mod error;
@@ -76,6 +81,7 @@ mod virtual_item;
 
 pub use self::{
     builder::TimelineBuilder,
+    content_transform::ContentTransformer,
     controller::default_event_filter,
     error::*,
     event_item::{
@@ -210,6 +216,19 @@ impl Timeline {
         Some(item.to_owned())
     }
 
+    /// Set whether the original body of the given message should be displayed,
+    /// instead of the body transformed by the [`ContentTransformer`] of the
+    /// timeline.
+    ///
+    /// The choice is remembered for the lifetime of the timeline, and
+    /// reflected by [`Message::is_showing_original()`].
+    ///
+    /// Returns `false` if there's no message with the given event ID in the
+    /// timeline.
+    pub async fn show_original_content(&self, event_id: &EventId, show_original: bool) -> bool {
+        self.controller.show_original_content(event_id, show_original).await
+    }
+
     /// Get the latest of the timeline's event items.
     pub async fn latest_event(&self) -> Option<EventTimelineItem> {
         if self.controller.is_live().await {
@@ -258,6 +277,49 @@ impl Timeline {
         self.room().send_queue().send(content).await
     }
 
+    /// Send a poll to the room, with a local echo.
+    ///
+    /// See [`Timeline::send`] for more details.
+    pub async fn send_poll(&self, poll: NewPoll) -> Result<SendHandle, RoomSendQueueError> {
+        let content = NewUnstablePollStartEventContent::from(poll);
+        self.send(AnyMessageLikeEventContent::UnstablePollStart(content.into())).await
+    }
+
+    /// Answer a poll, with a local echo.
+    ///
+    /// Only the latest response of a user is taken into account by the poll,
+    /// so this can be used to change the user's answers.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_event_id` - The event ID of the poll start event.
+    /// * `answers` - The IDs of the selected answers.
+    pub async fn send_poll_response(
+        &self,
+        poll_start_event_id: &EventId,
+        answers: Vec<String>,
+    ) -> Result<SendHandle, RoomSendQueueError> {
+        let content =
+            UnstablePollResponseEventContent::new(answers, poll_start_event_id.to_owned());
+        self.send(AnyMessageLikeEventContent::UnstablePollResponse(content)).await
+    }
+
+    /// End a poll, with a local echo.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_event_id` - The event ID of the poll start event.
+    /// * `text` - The text representation of the end of the poll, for clients
+    ///   that don't support polls.
+    pub async fn end_poll(
+        &self,
+        poll_start_event_id: &EventId,
+        text: impl Into<String>,
+    ) -> Result<SendHandle, RoomSendQueueError> {
+        let content = UnstablePollEndEventContent::new(text, poll_start_event_id.to_owned());
+        self.send(AnyMessageLikeEventContent::UnstablePollEnd(content)).await
+    }
+
     /// Send a reply to the given event.
     ///
     /// Currently it only supports events with an event ID and JSON being
@@ -465,6 +527,32 @@ impl Timeline {
         self.controller.fetch_in_reply_to_details(event_id).await
     }
 
+    /// Walk the reply chain of the event with the given ID, from the event it
+    /// replies to up to its oldest ancestor.
+    ///
+    /// Ancestors are looked for in the timeline first, then loaded from the
+    /// event cache, or fetched from the server. This allows showing nested
+    /// quotes of a reply, or jumping to any of its ancestors, even when they
+    /// aren't loaded in the timeline.
+    ///
+    /// The walk stops after `max_depth` ancestors, when the chain loops, or
+    /// at the first ancestor that couldn't be loaded, which is then the last
+    /// item of the returned chain, with the [`TimelineDetails::Error`] or
+    /// [`TimelineDetails::Unavailable`] state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the identifier doesn't match any event in the
+    /// timeline.
+    #[instrument(skip(self), fields(room_id = ?self.room().room_id()))]
+    pub async fn replied_to_chain(
+        &self,
+        event_id: &EventId,
+        max_depth: usize,
+    ) -> Result<Vec<InReplyToDetails>, Error> {
+        self.controller.replied_to_chain(event_id, max_depth).await
+    }
+
     /// Fetch all member events for the room this timeline is displaying.
     ///
     /// If the full member list is not known, sender profiles are currently
@@ -688,6 +776,7 @@ struct TimelineDropHandle {
     event_handler_handles: Vec<EventHandlerHandle>,
     room_update_join_handle: JoinHandle<()>,
     pinned_events_join_handle: Option<JoinHandle<()>>,
+    content_transform_join_handle: Option<JoinHandle<()>>,
     room_key_from_backups_join_handle: JoinHandle<()>,
     room_keys_received_join_handle: JoinHandle<()>,
     room_key_backup_enabled_join_handle: JoinHandle<()>,
@@ -706,6 +795,10 @@ impl Drop for TimelineDropHandle {
             handle.abort()
         };
 
+        if let Some(handle) = self.content_transform_join_handle.take() {
+            handle.abort()
+        };
+
         self.local_echo_listener_handle.abort();
         self.room_update_join_handle.abort();
         self.room_key_from_backups_join_handle.abort();
@@ -153,6 +153,50 @@ async fn test_sticker() {
     assert!(item.content().is_sticker());
 }
 
+#[async_test]
+async fn test_voice_message() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_event(TimelineEvent::new(sync_timeline_event!({
+            "content": {
+                "body": "Voice message",
+                "msgtype": "m.audio",
+                "info": {
+                    "duration": 2140,
+                    "mimetype": "audio/ogg",
+                    "size": 4021,
+                },
+                "url": "mxc://server.name/JWEIFJgwEIhweiWJE",
+                "org.matrix.msc1767.audio": {
+                    "duration": 2140,
+                    "waveform": [0, 512, 1024, 2048],
+                },
+                "org.matrix.msc3245.voice": {},
+            },
+            "event_id": "$143273582443PhrSn",
+            "origin_server_ts": 143273582,
+            "sender": "@alice:server.name",
+            "type": "m.room.message",
+        })))
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let message = item.content().as_message().unwrap();
+    assert!(message.is_voice_message());
+    assert_eq!(message.voice_waveform().unwrap(), [0, 512, 1024, 1024]);
+
+    // A regular text message isn't a voice message.
+    let f = &timeline.factory;
+    timeline.handle_live_event(f.text_msg("hello").sender(*ALICE)).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let message = item.content().as_message().unwrap();
+    assert!(!message.is_voice_message());
+    assert_eq!(message.voice_waveform(), None);
+}
+
 #[async_test]
 async fn test_room_member() {
     let timeline = TestTimeline::new();
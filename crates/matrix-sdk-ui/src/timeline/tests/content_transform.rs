@@ -0,0 +1,130 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use matrix_sdk::BoxFuture;
+use matrix_sdk_test::{async_test, event_factory::EventFactory, ALICE, BOB};
+use ruma::{event_id, events::room::message::MessageType, room_id, EventId};
+
+use super::{TestTimeline, TestTimelineBuilder};
+use crate::timeline::{controller::TimelineSettings, ContentTransformer, Message};
+
+/// A transformer shouting all the messages.
+#[derive(Debug, Default)]
+struct Shout {
+    num_calls: AtomicUsize,
+}
+
+impl ContentTransformer for Shout {
+    fn transform<'a>(
+        &'a self,
+        _event_id: &'a EventId,
+        body: &'a str,
+    ) -> BoxFuture<'a, Option<String>> {
+        self.num_calls.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move { (body != "quiet").then(|| body.to_uppercase()) })
+    }
+}
+
+async fn message(timeline: &TestTimeline, event_id: &EventId) -> Message {
+    timeline
+        .controller
+        .items()
+        .await
+        .iter()
+        .filter_map(|item| item.as_event())
+        .find(|item| item.event_id() == Some(event_id))
+        .and_then(|item| item.content().as_message().cloned())
+        .unwrap()
+}
+
+#[async_test]
+async fn test_content_transform() {
+    let shout = Arc::new(Shout::default());
+    let timeline = TestTimelineBuilder::new()
+        .settings(TimelineSettings {
+            content_transformer: Some(shout.clone() as Arc<dyn ContentTransformer>),
+            ..Default::default()
+        })
+        .build();
+
+    let f = EventFactory::new().room(room_id!("!room:localhost"));
+    let live_event_id = event_id!("$live");
+    let paginated_event_id = event_id!("$paginated");
+    let quiet_event_id = event_id!("$quiet");
+
+    timeline.handle_live_event(f.text_msg("hello").sender(&ALICE).event_id(live_event_id)).await;
+    timeline
+        .handle_back_paginated_event(
+            f.text_msg("hi there").sender(&BOB).event_id(paginated_event_id).into_raw_timeline(),
+        )
+        .await;
+    timeline.handle_live_event(f.text_msg("quiet").sender(&BOB).event_id(quiet_event_id)).await;
+
+    // Nothing is transformed until the transformer runs.
+    assert!(message(&timeline, live_event_id).await.transformed_body().is_none());
+
+    timeline.controller.apply_content_transforms().await;
+    assert_eq!(shout.num_calls.load(Ordering::SeqCst), 3);
+
+    // Both live and paginated events are transformed.
+    let live = message(&timeline, live_event_id).await;
+    assert_eq!(live.body(), "hello");
+    assert_eq!(live.transformed_body(), Some("HELLO"));
+    assert_eq!(live.display_body(), "HELLO");
+
+    let paginated = message(&timeline, paginated_event_id).await;
+    assert_eq!(paginated.display_body(), "HI THERE");
+
+    // The transformer can leave a body as is.
+    let quiet = message(&timeline, quiet_event_id).await;
+    assert!(quiet.transformed_body().is_none());
+    assert_eq!(quiet.display_body(), "quiet");
+
+    // The results are cached.
+    timeline.controller.apply_content_transforms().await;
+    assert_eq!(shout.num_calls.load(Ordering::SeqCst), 3);
+
+    // The original body can be displayed instead.
+    assert!(timeline.controller.show_original_content(live_event_id, true).await);
+    let live = message(&timeline, live_event_id).await;
+    assert!(live.is_showing_original());
+    assert_eq!(live.display_body(), "hello");
+
+    assert!(timeline.controller.show_original_content(live_event_id, false).await);
+    assert_eq!(message(&timeline, live_event_id).await.display_body(), "HELLO");
+
+    assert!(!timeline.controller.show_original_content(event_id!("$unknown"), true).await);
+
+    // An edit invalidates the transformation.
+    timeline
+        .handle_live_event(
+            f.text_msg("* hello world")
+                .sender(&ALICE)
+                .edit(live_event_id, MessageType::text_plain("hello world").into()),
+        )
+        .await;
+
+    let live = message(&timeline, live_event_id).await;
+    assert_eq!(live.body(), "hello world");
+    assert!(live.transformed_body().is_none());
+
+    timeline.controller.apply_content_transforms().await;
+    assert_eq!(shout.num_calls.load(Ordering::SeqCst), 4);
+    assert_eq!(message(&timeline, live_event_id).await.display_body(), "HELLO WORLD");
+}
@@ -711,11 +711,15 @@ async fn test_utd_cause_for_nonmember_event_is_found() {
     let event = item.as_event().unwrap();
     assert_let!(
         TimelineItemContent::MsgLike(MsgLikeContent {
-            kind: MsgLikeKind::UnableToDecrypt(EncryptedMessage::MegolmV1AesSha2 { cause, .. }),
+            kind: MsgLikeKind::UnableToDecrypt(
+                encrypted @ EncryptedMessage::MegolmV1AesSha2 { cause, .. }
+            ),
             ..
         }) = event.content()
     );
     assert_eq!(*cause, UtdCause::SentBeforeWeJoined);
+    // And it's an expected UTD.
+    assert!(encrypted.is_expected_utd());
 }
 
 #[async_test]
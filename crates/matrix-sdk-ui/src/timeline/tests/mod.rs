@@ -67,6 +67,7 @@ use crate::{
 };
 
 mod basic;
+mod content_transform;
 mod echo;
 mod edit;
 mod encryption;
@@ -346,6 +347,7 @@ impl RoomDataProvider for TestRoomDataProvider {
             is_backup_configured: false,
             this_device_is_verified: true,
             backup_exists_on_server: true,
+            room_history_shared: true,
         }
     }
 
@@ -187,6 +187,45 @@ async fn test_events_received_before_start_are_not_lost() {
     assert_eq!(results.votes["1"], vec![ALICE.to_string()]);
 }
 
+#[async_test]
+async fn test_tally_is_updated_live() {
+    let timeline = TestTimeline::new();
+    let poll_id: OwnedEventId = EventId::new(server_name!("dummy.server"));
+
+    // A vote received before the start event is counted.
+    let response = timeline.factory.poll_response(vec!["0"], &poll_id).sender(&ALICE).server_ts(1);
+    timeline.handle_live_event(response).await;
+
+    let start_ev = poll_a2(&timeline.factory).sender(&ALICE).event_id(&poll_id);
+    timeline.handle_live_event(start_ev).await;
+
+    let tally = timeline.poll_state().await.tally();
+    assert!(!tally.has_ended());
+    assert_eq!(tally.total_voters(), 1);
+    assert_eq!(tally.answers[0].voters, [ALICE.to_owned()]);
+    assert_eq!(tally.winning_answers()[0].id, "0");
+
+    timeline.send_poll_response(&BOB, vec!["1"], &poll_id).await;
+    timeline.send_poll_response(&ALICE, vec!["1"], &poll_id).await;
+
+    let poll_state = timeline.poll_state().await;
+    assert_eq!(poll_state.user_answers(&ALICE), ["1"]);
+    let tally = poll_state.tally();
+    assert_eq!(tally.total_voters(), 2);
+    assert!(tally.answers[0].voters.is_empty());
+    assert_eq!(tally.answers[1].voters.len(), 2);
+
+    timeline.send_poll_end(&ALICE, "ENDED", &poll_id).await;
+
+    // A vote received after the end isn't counted.
+    timeline.send_poll_response(&BOB, vec!["0"], &poll_id).await;
+
+    let tally = timeline.poll_state().await.tally();
+    assert!(tally.has_ended());
+    assert_eq!(tally.total_voters(), 2);
+    assert_eq!(tally.winning_answers()[0].id, "1");
+}
+
 #[async_test]
 async fn test_adding_response_doesnt_clear_latest_json_edit() {
     let timeline = TestTimeline::new();
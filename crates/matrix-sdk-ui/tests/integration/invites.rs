@@ -0,0 +1,149 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use matrix_sdk::{test_utils::mocks::MatrixMockServer, Client, RoomState};
+use matrix_sdk_test::{async_test, stripped_state_event, InvitedRoomBuilder};
+use matrix_sdk_ui::invites::{DeclineOptions, InviteRateFilter, InviteService};
+use ruma::{room_id, user_id, RoomId};
+use serde_json::json;
+use stream_assert::assert_pending;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+/// Sync an invite to the given room, sent by the given user.
+async fn sync_invite(
+    server: &MatrixMockServer,
+    client: &Client,
+    room_id: &RoomId,
+    inviter: &str,
+    inviter_name: Option<&str>,
+) {
+    server
+        .sync_room(
+            client,
+            InvitedRoomBuilder::new(room_id).add_state_bulk([
+                stripped_state_event!({
+                    "content": { "name": format!("Room {room_id}") },
+                    "sender": inviter,
+                    "state_key": "",
+                    "type": "m.room.name",
+                }),
+                stripped_state_event!({
+                    "content": { "displayname": inviter_name, "membership": "join" },
+                    "sender": inviter,
+                    "state_key": inviter,
+                    "type": "m.room.member",
+                }),
+                stripped_state_event!({
+                    "content": { "membership": "invite" },
+                    "sender": inviter,
+                    "state_key": "@example:localhost",
+                    "type": "m.room.member",
+                }),
+            ]),
+        )
+        .await;
+}
+
+#[async_test]
+async fn test_pending_invites() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let invite_service = InviteService::new(client.clone());
+
+    let room_a = room_id!("!a:localhost");
+    let room_b = room_id!("!b:localhost");
+
+    sync_invite(&server, &client, room_a, "@alice:localhost", Some("Alice")).await;
+
+    let (invites, mut stream) = invite_service.subscribe_to_invites().await;
+    assert_eq!(invites.len(), 1);
+    assert_eq!(invites[0].room_id, room_a);
+    assert_eq!(invites[0].room_name.as_deref(), Some("Room !a:localhost"));
+
+    let inviter = invites[0].inviter.as_ref().unwrap();
+    assert_eq!(inviter.user_id, user_id!("@alice:localhost"));
+    assert_eq!(inviter.display_name.as_deref(), Some("Alice"));
+    assert_pending!(stream);
+
+    // A new invite updates the list.
+    sync_invite(&server, &client, room_b, "@bob:localhost", Some("Bob")).await;
+
+    let invites = stream.next().await.unwrap();
+    assert_eq!(invites.len(), 2);
+    assert_eq!(invites[1].room_id, room_b);
+
+    // Accepting an invite removes it from the list.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/rooms/!a:localhost/join"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_a })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let room = invite_service.accept(room_a).await.unwrap();
+    assert_eq!(room.state(), RoomState::Joined);
+
+    let invites = stream.next().await.unwrap();
+    assert_eq!(invites.len(), 1);
+    assert_eq!(invites[0].room_id, room_b);
+}
+
+#[async_test]
+async fn test_decline_invite() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let invite_service = InviteService::new(client.clone());
+    let room_id = room_id!("!a:localhost");
+
+    sync_invite(&server, &client, room_id, "@alice:localhost", Some("Alice")).await;
+    assert_eq!(invite_service.invites().await.len(), 1);
+
+    server.mock_room_leave().ok(room_id).expect(1).mount().await;
+
+    invite_service.decline(room_id, DeclineOptions::default()).await.unwrap();
+
+    assert!(invite_service.invites().await.is_empty());
+
+    // The room isn't an invite anymore.
+    assert!(invite_service.decline(room_id, DeclineOptions::default()).await.is_err());
+}
+
+#[async_test]
+async fn test_invite_spam_filter() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let invite_service = InviteService::new(client.clone())
+        .with_filter(InviteRateFilter::new(1, Duration::from_secs(60 * 60)));
+
+    sync_invite(&server, &client, room_id!("!a:localhost"), "@friend:localhost", Some("Friend"))
+        .await;
+    sync_invite(&server, &client, room_id!("!b:localhost"), "@spammer:localhost", None).await;
+
+    let invites = invite_service.invites().await;
+    assert_eq!(invites.len(), 2);
+
+    // A second invite from the same user in less than an hour hides all of their
+    // invites.
+    sync_invite(&server, &client, room_id!("!c:localhost"), "@spammer:localhost", None).await;
+
+    let invites = invite_service.invites().await;
+    assert_eq!(invites.len(), 1);
+    assert_eq!(invites[0].room_id, room_id!("!a:localhost"));
+}
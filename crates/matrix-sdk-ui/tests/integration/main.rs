@@ -23,9 +23,11 @@ use wiremock::{
 };
 
 mod encryption_sync_service;
+mod invites;
 mod notification_client;
 mod room_list_service;
 mod sliding_sync;
+mod spaces;
 mod sync_service;
 mod timeline;
 
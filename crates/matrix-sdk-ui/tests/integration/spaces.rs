@@ -0,0 +1,438 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use futures_util::StreamExt;
+use matrix_sdk::{test_utils::mocks::MatrixMockServer, RoomState};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, StateTestEvent};
+use matrix_sdk_ui::spaces::{Error, SpaceRoomListPaginationState, SpaceService};
+use ruma::{
+    api::client::space::{SpaceHierarchyRoomsChunk, SpaceHierarchyRoomsChunkInit},
+    event_id,
+    events::{
+        room::{create::RoomCreateEventContent, power_levels::RoomPowerLevelsEventContent},
+        space::child::SpaceChildEventContent,
+        AnySyncStateEvent, StateEventType,
+    },
+    owned_server_name,
+    room::RoomType,
+    room_id,
+    serde::Raw,
+    space::SpaceRoomJoinRule,
+    uint, user_id, RoomId,
+};
+use serde_json::json;
+use stream_assert::{assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+fn space_create_event(f: &EventFactory) -> Raw<AnySyncStateEvent> {
+    let mut content = RoomCreateEventContent::new_v11();
+    content.room_type = Some(RoomType::Space);
+    f.event(content).state_key("").into_raw_sync().cast()
+}
+
+fn space_child_event(f: &EventFactory, child_id: &RoomId) -> Raw<AnySyncStateEvent> {
+    f.event(SpaceChildEventContent::new(vec![owned_server_name!("localhost")]))
+        .state_key(child_id.as_str())
+        .into_raw_sync()
+        .cast()
+}
+
+fn hierarchy_chunk(room_id: &RoomId, name: &str) -> SpaceHierarchyRoomsChunk {
+    let mut chunk: SpaceHierarchyRoomsChunk = SpaceHierarchyRoomsChunkInit {
+        num_joined_members: uint!(2),
+        room_id: room_id.to_owned(),
+        world_readable: false,
+        guest_can_join: false,
+        join_rule: SpaceRoomJoinRule::Public,
+        children_state: Vec::new(),
+    }
+    .into();
+    chunk.name = Some(name.to_owned());
+    chunk
+}
+
+#[async_test]
+async fn test_joined_spaces() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let f = EventFactory::new().sender(user_id!("@alice:localhost"));
+
+    let parent_space_id = room_id!("!parent:localhost");
+    let child_space_id = room_id!("!child:localhost");
+    let room_id = room_id!("!room:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(parent_space_id).add_state_bulk([
+                space_create_event(&f),
+                f.room_name("Parent").state_key("").into_raw_sync().cast(),
+                space_child_event(&f, child_space_id),
+                space_child_event(&f, room_id),
+            ]),
+        )
+        .await;
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(child_space_id).add_state_bulk([space_create_event(&f)]),
+        )
+        .await;
+    server.sync_joined_room(&client, room_id).await;
+
+    let space_service = SpaceService::new(client.clone());
+
+    // Only the top-level spaces are listed.
+    let spaces = space_service.joined_spaces().await;
+    assert_eq!(spaces.len(), 1);
+    assert_eq!(spaces[0].room_id, parent_space_id);
+    assert_eq!(spaces[0].name.as_deref(), Some("Parent"));
+    assert_eq!(spaces[0].children_count, 2);
+    assert_eq!(spaces[0].state, Some(RoomState::Joined));
+    assert!(spaces[0].is_space());
+
+    let (spaces, mut stream) = space_service.subscribe_to_joined_spaces().await;
+    assert_eq!(spaces.len(), 1);
+    assert_pending!(stream);
+
+    // Joining a new space updates the list.
+    let other_space_id = room_id!("!other:localhost");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(other_space_id).add_state_bulk([
+                space_create_event(&f),
+                f.room_name("Other").state_key("").into_raw_sync().cast(),
+            ]),
+        )
+        .await;
+
+    let spaces = stream.next().await.unwrap();
+    assert_eq!(spaces.len(), 2);
+    assert_eq!(spaces[0].room_id, other_space_id);
+    assert_eq!(spaces[1].room_id, parent_space_id);
+
+    // Joining a regular room doesn't.
+    server.sync_joined_room(&client, room_id!("!another_room:localhost")).await;
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn test_space_room_list() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let f = EventFactory::new().sender(user_id!("@alice:localhost"));
+
+    let space_id = room_id!("!space:localhost");
+    let first_room_id = room_id!("!first:localhost");
+    let second_room_id = room_id!("!second:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id).add_state_bulk([
+                space_create_event(&f),
+                space_child_event(&f, first_room_id),
+                space_child_event(&f, second_room_id),
+            ]),
+        )
+        .await;
+
+    server
+        .mock_get_hierarchy()
+        .ok(
+            vec![hierarchy_chunk(space_id, "Space"), hierarchy_chunk(first_room_id, "First")],
+            Some("next".to_owned()),
+        )
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_get_hierarchy()
+        .ok(vec![hierarchy_chunk(second_room_id, "Second")], None)
+        .mock_once()
+        .mount()
+        .await;
+
+    let space_service = SpaceService::new(client.clone());
+    let room_list = space_service.space_room_list(space_id.to_owned()).await;
+
+    let (rooms, mut stream) = room_list.subscribe_to_room_updates();
+    assert!(rooms.is_empty());
+    assert_eq!(
+        room_list.pagination_state(),
+        SpaceRoomListPaginationState::Idle { end_reached: false }
+    );
+
+    // The space itself isn't part of its children.
+    room_list.paginate().await.unwrap();
+    assert_next_matches!(stream, diffs => {
+        assert_eq!(diffs.len(), 1);
+        assert_let!(VectorDiff::Append { values } = &diffs[0]);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].room_id, first_room_id);
+        assert_eq!(values[0].name.as_deref(), Some("First"));
+        assert_eq!(values[0].state, None);
+    });
+
+    room_list.paginate().await.unwrap();
+    assert_next_matches!(stream, diffs => {
+        assert_let!(VectorDiff::Append { values } = &diffs[0]);
+        assert_eq!(values[0].room_id, second_room_id);
+    });
+    assert_eq!(
+        room_list.pagination_state(),
+        SpaceRoomListPaginationState::Idle { end_reached: true }
+    );
+
+    // Joining a child updates its state.
+    server.sync_joined_room(&client, second_room_id).await;
+    let diffs = stream.next().await.unwrap();
+    assert_let!(VectorDiff::Set { index: 1, value } = &diffs[0]);
+    assert_eq!(value.state, Some(RoomState::Joined));
+    assert_pending!(stream);
+
+    // A new child reloads the list.
+    let third_room_id = room_id!("!third:localhost");
+    server
+        .mock_get_hierarchy()
+        .ok(
+            vec![
+                hierarchy_chunk(space_id, "Space"),
+                hierarchy_chunk(first_room_id, "First"),
+                hierarchy_chunk(third_room_id, "Third"),
+            ],
+            None,
+        )
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id).add_state_bulk([space_child_event(&f, third_room_id)]),
+        )
+        .await;
+
+    let diffs = stream.next().await.unwrap();
+    assert_eq!(diffs.len(), 2);
+    assert_let!(VectorDiff::Clear = &diffs[0]);
+    assert_let!(VectorDiff::Append { values } = &diffs[1]);
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[1].room_id, third_room_id);
+    assert_eq!(
+        room_list.pagination_state(),
+        SpaceRoomListPaginationState::Idle { end_reached: true }
+    );
+
+    // The first page is loaded from the cache.
+    drop(room_list);
+    let room_list = space_service.space_room_list(space_id.to_owned()).await;
+    let rooms = room_list.rooms();
+    assert_eq!(rooms.len(), 2);
+    assert_eq!(rooms[0].room_id, first_room_id);
+    assert_eq!(rooms[1].room_id, third_room_id);
+}
+
+#[async_test]
+async fn test_add_child_to_space() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let f = EventFactory::new().sender(user_id!("@example:localhost"));
+
+    let space_id = room_id!("!space:localhost");
+    let child_id = room_id!("!child:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id)
+                .add_state_bulk([space_create_event(&f)])
+                .add_state_event(StateTestEvent::PowerLevels),
+        )
+        .await;
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(child_id).add_state_event(StateTestEvent::PowerLevels),
+        )
+        .await;
+
+    let space_service = SpaceService::new(client.clone());
+
+    assert_let!(
+        Err(Error::InvalidOrder(_)) =
+            space_service.add_child_to_space(space_id, child_id, Some("é".to_owned()), false).await
+    );
+
+    // Both the child and the parent are set.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceChild)
+        .for_key(child_id.to_string())
+        .body_matches_partial_json(json!({ "via": ["localhost"], "order": "a", "suggested": true }))
+        .ok(event_id!("$child"))
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceParent)
+        .for_key(space_id.to_string())
+        .body_matches_partial_json(json!({ "via": ["localhost"] }))
+        .ok(event_id!("$parent"))
+        .mock_once()
+        .mount()
+        .await;
+
+    space_service.add_child_to_space(space_id, child_id, Some("a".to_owned()), true).await.unwrap();
+    server.server().verify().await;
+    server.server().reset().await;
+
+    // If the parent can't be set, the child is rolled back.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceChild)
+        .ok(event_id!("$child"))
+        .expect(2)
+        .mount()
+        .await;
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceParent)
+        .error500()
+        .expect(1)
+        .mount()
+        .await;
+
+    assert_let!(
+        Err(Error::Sdk(_)) =
+            space_service.add_child_to_space(space_id, child_id, None, false).await
+    );
+
+    let requests = server.server().received_requests().await.unwrap();
+    let rollback = requests.last().unwrap();
+    assert!(rollback.url.path().ends_with(&format!("/state/m.space.child/{child_id}")));
+    assert_eq!(rollback.body_json::<serde_json::Value>().unwrap(), json!({}));
+}
+
+#[async_test]
+async fn test_update_space_child() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let f = EventFactory::new().sender(user_id!("@example:localhost"));
+
+    let space_id = room_id!("!space:localhost");
+    let child_id = room_id!("!child:localhost");
+    let other_space_id = room_id!("!other_space:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id)
+                .add_state_bulk([space_create_event(&f), space_child_event(&f, child_id)])
+                .add_state_event(StateTestEvent::PowerLevels),
+        )
+        .await;
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(other_space_id).add_state_bulk([
+                space_create_event(&f),
+                space_child_event(&f, child_id),
+                f.event(RoomPowerLevelsEventContent::new()).state_key("").into_raw_sync().cast(),
+            ]),
+        )
+        .await;
+
+    let space_service = SpaceService::new(client.clone());
+
+    // The user must be allowed to send `m.space.child` events.
+    assert_let!(
+        Err(Error::MissingPermission { room_id, .. }) =
+            space_service.set_child_suggested(other_space_id, child_id, true).await
+    );
+    assert_eq!(room_id, other_space_id);
+
+    // The room must be a child of the space.
+    assert_let!(
+        Err(Error::NotAChild { .. }) =
+            space_service.set_child_suggested(space_id, other_space_id, true).await
+    );
+
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceChild)
+        .for_key(child_id.to_string())
+        .body_matches_partial_json(json!({ "via": ["localhost"], "order": "b" }))
+        .ok(event_id!("$child"))
+        .mock_once()
+        .mount()
+        .await;
+
+    space_service.set_child_order(space_id, child_id, Some("b".to_owned())).await.unwrap();
+}
+
+#[async_test]
+async fn test_create_subspace() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let f = EventFactory::new().sender(user_id!("@example:localhost"));
+
+    let space_id = room_id!("!space:localhost");
+    let subspace_id = room_id!("!subspace:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id)
+                .add_state_bulk([space_create_event(&f)])
+                .add_state_event(StateTestEvent::PowerLevels),
+        )
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .and(body_partial_json(json!({
+            "name": "Subspace",
+            "creation_content": { "type": "m.space" },
+            "initial_state": [{
+                "type": "m.space.parent",
+                "state_key": space_id,
+                "content": { "via": ["localhost"], "canonical": true },
+            }],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": subspace_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::SpaceChild)
+        .for_key(subspace_id.to_string())
+        .ok(event_id!("$child"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let space_service = SpaceService::new(client.clone());
+    let subspace =
+        space_service.create_subspace(space_id, "Subspace".to_owned(), None).await.unwrap();
+    assert_eq!(subspace.room_id(), subspace_id);
+}
@@ -637,6 +637,82 @@ async fn test_transfer_in_reply_to_details_to_re_received_item() {
     assert_matches!(in_reply_to.event, TimelineDetails::Ready(_));
 }
 
+#[async_test]
+async fn test_replied_to_chain() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let f = EventFactory::new().room(room_id);
+
+    let timeline = room.timeline().await.unwrap();
+
+    // Given a reply to a reply, with only the last reply in the timeline...
+    let root_id = event_id!("$root");
+    let first_reply_id = event_id!("$first_reply");
+    let second_reply_id = event_id!("$second_reply");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(
+                f.text_msg("Second reply")
+                    .sender(&CAROL)
+                    .reply_to(first_reply_id)
+                    .event_id(second_reply_id),
+            ),
+        )
+        .await;
+
+    server
+        .mock_room_event()
+        .match_event_id()
+        .ok(f
+            .text_msg("First reply")
+            .sender(&BOB)
+            .reply_to(root_id)
+            .event_id(first_reply_id)
+            .into())
+        .mount()
+        .await;
+    server
+        .mock_room_event()
+        .match_event_id()
+        .ok(f.text_msg("Root").sender(&ALICE).event_id(root_id).into())
+        .mount()
+        .await;
+
+    // ... walking the reply chain fetches all the ancestors, from the most recent
+    // one.
+    let chain = timeline.replied_to_chain(second_reply_id, 10).await.unwrap();
+    assert_eq!(chain.len(), 2);
+
+    assert_eq!(chain[0].event_id, first_reply_id);
+    assert_let!(TimelineDetails::Ready(first_reply) = &chain[0].event);
+    assert_eq!(first_reply.sender(), *BOB);
+    assert_eq!(first_reply.content().in_reply_to().unwrap().event_id, root_id);
+
+    assert_eq!(chain[1].event_id, root_id);
+    assert_let!(TimelineDetails::Ready(root) = &chain[1].event);
+    assert_eq!(root.sender(), *ALICE);
+    assert!(root.content().in_reply_to().is_none());
+
+    // ... and the walk stops at the maximum depth.
+    let chain = timeline.replied_to_chain(second_reply_id, 1).await.unwrap();
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].event_id, first_reply_id);
+
+    // Walking the reply chain of an event that isn't in the timeline fails.
+    assert_matches!(
+        timeline.replied_to_chain(root_id, 10).await,
+        Err(TimelineError::EventNotInTimeline(_))
+    );
+}
+
 #[async_test]
 async fn test_send_reply() {
     let server = MatrixMockServer::new().await;
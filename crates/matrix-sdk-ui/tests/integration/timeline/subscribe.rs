@@ -195,7 +195,7 @@ async fn test_event_filter() {
 }
 
 #[async_test]
-async fn test_timeline_is_reset_when_a_user_is_ignored_or_unignored() {
+async fn test_timeline_hides_the_events_of_ignored_users() {
     let room_id = room_id!("!a98sd12bjh:example.org");
     let (client, server) = logged_in_client_with_server().await;
     let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
@@ -271,8 +271,11 @@ async fn test_timeline_is_reset_when_a_user_is_ignored_or_unignored() {
     assert_let!(Some(timeline_updates) = timeline_stream.next().await);
     assert_eq!(timeline_updates.len(), 1);
 
-    // The timeline has been emptied.
-    assert_let!(VectorDiff::Clear = &timeline_updates[0]);
+    // The event of Bob has been removed from the timeline, after the date divider
+    // and the first event.
+    assert_let!(VectorDiff::Remove { index: 2 } = &timeline_updates[0]);
+
+    assert_pending!(timeline_stream);
 
     let fourth_event_id = event_id!("$YTQwYl2pl4");
     let fifth_event_id = event_id!("$YTQwYl2pl5");
@@ -293,18 +296,25 @@ async fn test_timeline_is_reset_when_a_user_is_ignored_or_unignored() {
     assert_let!(Some(timeline_updates) = timeline_stream.next().await);
     assert_eq!(timeline_updates.len(), 4);
 
-    // Timeline receives events as before.
-    assert_let!(VectorDiff::PushBack { value } = &timeline_updates[0]);
+    // Timeline receives events as before, after the remaining events.
+    assert_let!(VectorDiff::Set { index: 2, value } = &timeline_updates[0]);
+    assert_eq!(value.as_event().unwrap().event_id(), Some(third_event_id));
+
+    assert_let!(VectorDiff::PushBack { value } = &timeline_updates[1]);
     assert_eq!(value.as_event().unwrap().event_id(), Some(fourth_event_id));
 
-    assert_let!(VectorDiff::Set { index: 0, value } = &timeline_updates[1]);
+    assert_let!(VectorDiff::Set { index: 3, value } = &timeline_updates[2]);
     assert_eq!(value.as_event().unwrap().event_id(), Some(fourth_event_id));
 
-    assert_let!(VectorDiff::PushBack { value } = &timeline_updates[2]);
+    assert_let!(VectorDiff::PushBack { value } = &timeline_updates[3]);
     assert_eq!(value.as_event().unwrap().event_id(), Some(fifth_event_id));
 
-    assert_let!(VectorDiff::PushFront { value } = &timeline_updates[3]);
-    assert!(value.is_date_divider());
+    let items = timeline.items().await;
+    let event_ids = items
+        .iter()
+        .filter_map(|item| item.as_event()?.event_id().map(ToOwned::to_owned))
+        .collect::<Vec<_>>();
+    assert_eq!(event_ids, [first_event_id, third_event_id, fourth_event_id, fifth_event_id]);
 
     assert_pending!(timeline_stream);
 }
@@ -0,0 +1,98 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The rooms recently opened by the account, shared between its clients with
+//! the `im.vector.setting.breadcrumbs` global account data event.
+
+use ruma::{events::macros::EventContent, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of rooms kept in the [`BreadcrumbsEventContent`].
+pub const MAX_BREADCRUMBS: usize = 20;
+
+/// The content of the `im.vector.setting.breadcrumbs` global account data
+/// event.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "im.vector.setting.breadcrumbs", kind = GlobalAccountData)]
+pub struct BreadcrumbsEventContent {
+    /// The recently opened rooms, the most recent one first.
+    #[serde(default)]
+    pub recent_rooms: Vec<OwnedRoomId>,
+}
+
+impl BreadcrumbsEventContent {
+    /// Record that the given room was opened.
+    ///
+    /// The room is moved to the front of the list. The least recently opened
+    /// rooms are dropped if the list grows longer than [`MAX_BREADCRUMBS`].
+    ///
+    /// Returns `false` if the room was already the most recent one, i.e. the
+    /// content didn't change.
+    pub fn push_room(&mut self, room_id: &RoomId) -> bool {
+        if self.recent_rooms.first().is_some_and(|recent| recent == room_id) {
+            return false;
+        }
+
+        self.recent_rooms.retain(|recent| recent != room_id);
+        self.recent_rooms.insert(0, room_id.to_owned());
+        self.recent_rooms.truncate(MAX_BREADCRUMBS);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{owned_room_id, room_id, RoomId};
+    use serde_json::{from_value, json, to_value};
+
+    use super::{BreadcrumbsEventContent, MAX_BREADCRUMBS};
+
+    #[test]
+    fn test_push_room() {
+        let mut content = BreadcrumbsEventContent::default();
+
+        assert!(content.push_room(room_id!("!a:b.c")));
+        assert!(content.push_room(room_id!("!d:e.f")));
+        assert!(content.push_room(room_id!("!a:b.c")));
+
+        // Pushing the most recent room again doesn't change anything.
+        assert!(!content.push_room(room_id!("!a:b.c")));
+
+        assert_eq!(to_value(&content).unwrap(), json!({ "recent_rooms": ["!a:b.c", "!d:e.f"] }));
+    }
+
+    #[test]
+    fn test_push_room_drops_the_least_recent_rooms() {
+        let mut content = BreadcrumbsEventContent::default();
+
+        for i in 0..=MAX_BREADCRUMBS {
+            content.push_room(&RoomId::parse(format!("!{i}:b.c")).unwrap());
+        }
+
+        assert_eq!(content.recent_rooms.len(), MAX_BREADCRUMBS);
+        assert_eq!(content.recent_rooms[0], format!("!{MAX_BREADCRUMBS}:b.c"));
+        assert!(!content.recent_rooms.contains(&owned_room_id!("!0:b.c")));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let content: BreadcrumbsEventContent =
+            from_value(json!({ "recent_rooms": ["!a:b.c"] })).unwrap();
+        assert_eq!(content.recent_rooms, [owned_room_id!("!a:b.c")]);
+
+        let content: BreadcrumbsEventContent = from_value(json!({})).unwrap();
+        assert!(content.recent_rooms.is_empty());
+    }
+}
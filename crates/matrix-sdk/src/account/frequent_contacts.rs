@@ -0,0 +1,137 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The number of messages sent by the account to its contacts in direct
+//! messages, shared between its clients with the `io.element.frequent_contacts`
+//! global account data event.
+
+use ruma::{events::macros::EventContent, uint, OwnedUserId, UInt, UserId};
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of contacts kept in the [`FrequentContactsEventContent`].
+pub const MAX_FREQUENT_CONTACTS: usize = 100;
+
+/// The content of the `io.element.frequent_contacts` global account data
+/// event.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "io.element.frequent_contacts", kind = GlobalAccountData)]
+pub struct FrequentContactsEventContent {
+    /// The contacts, the most used one first.
+    #[serde(default)]
+    pub contacts: Vec<FrequentContact>,
+}
+
+impl FrequentContactsEventContent {
+    /// Record a message sent to the given user.
+    ///
+    /// The use count of the contact is incremented, and the contact is moved
+    /// in front of the contacts that weren't used more often. The least used
+    /// contacts are dropped if the list grows longer than
+    /// [`MAX_FREQUENT_CONTACTS`].
+    pub fn record(&mut self, user_id: &UserId) {
+        let count = match self.contacts.iter().position(|contact| contact.user_id == user_id) {
+            Some(index) => self.contacts.remove(index).count.saturating_add(uint!(1)),
+            None => uint!(1),
+        };
+
+        let index = self.contacts.partition_point(|contact| contact.count > count);
+        self.contacts.insert(index, FrequentContact { user_id: user_id.to_owned(), count });
+        self.contacts.truncate(MAX_FREQUENT_CONTACTS);
+    }
+
+    /// Get the number of messages sent to the given user.
+    pub fn count(&self, user_id: &UserId) -> UInt {
+        self.contacts
+            .iter()
+            .find(|contact| contact.user_id == user_id)
+            .map_or(uint!(0), |contact| contact.count)
+    }
+}
+
+/// A contact of the account in direct messages.
+///
+/// It is serialized as a `[user_id, count]` array.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "(OwnedUserId, UInt)", into = "(OwnedUserId, UInt)")]
+pub struct FrequentContact {
+    /// The user ID of the contact.
+    pub user_id: OwnedUserId,
+
+    /// How many messages were sent to the contact.
+    pub count: UInt,
+}
+
+impl From<(OwnedUserId, UInt)> for FrequentContact {
+    fn from((user_id, count): (OwnedUserId, UInt)) -> Self {
+        Self { user_id, count }
+    }
+}
+
+impl From<FrequentContact> for (OwnedUserId, UInt) {
+    fn from(contact: FrequentContact) -> Self {
+        (contact.user_id, contact.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{uint, user_id, UserId};
+    use serde_json::{from_value, json, to_value};
+
+    use super::{FrequentContactsEventContent, MAX_FREQUENT_CONTACTS};
+
+    #[test]
+    fn test_record() {
+        let mut content = FrequentContactsEventContent::default();
+
+        content.record(user_id!("@alice:b.c"));
+        content.record(user_id!("@bob:b.c"));
+        content.record(user_id!("@bob:b.c"));
+        content.record(user_id!("@carl:b.c"));
+        content.record(user_id!("@alice:b.c"));
+
+        assert_eq!(
+            to_value(&content).unwrap(),
+            json!({ "contacts": [["@alice:b.c", 2], ["@bob:b.c", 2], ["@carl:b.c", 1]] })
+        );
+        assert_eq!(content.count(user_id!("@bob:b.c")), uint!(2));
+        assert_eq!(content.count(user_id!("@dan:b.c")), uint!(0));
+    }
+
+    #[test]
+    fn test_record_drops_the_least_used_contacts() {
+        let mut content = FrequentContactsEventContent::default();
+
+        content.record(user_id!("@alice:b.c"));
+        content.record(user_id!("@alice:b.c"));
+        for i in 0..MAX_FREQUENT_CONTACTS {
+            content.record(&UserId::parse(format!("@user{i}:b.c")).unwrap());
+        }
+
+        assert_eq!(content.contacts.len(), MAX_FREQUENT_CONTACTS);
+        assert_eq!(content.contacts[0].user_id, "@alice:b.c");
+        assert!(!content.contacts.iter().any(|contact| contact.user_id == "@user0:b.c"));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let content: FrequentContactsEventContent =
+            from_value(json!({ "contacts": [["@alice:b.c", 5]] })).unwrap();
+        assert_eq!(content.contacts[0].user_id, "@alice:b.c");
+        assert_eq!(content.contacts[0].count, uint!(5));
+
+        let content: FrequentContactsEventContent = from_value(json!({})).unwrap();
+        assert!(content.contacts.is_empty());
+    }
+}
@@ -14,17 +14,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! High-level account management API.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use async_stream::stream;
+use futures_core::Stream;
 use matrix_sdk_base::{
     media::{MediaFormat, MediaRequestParameters},
     store::StateStoreExt,
-    StateStoreDataKey, StateStoreDataValue,
+    SendOutsideWasm, StateStoreDataKey, StateStoreDataValue, SyncOutsideWasm,
 };
+use matrix_sdk_common::executor::spawn;
 use mime::Mime;
 use ruma::{
     api::client::{
         account::{
             add_3pid, change_password, deactivate, delete_3pid, get_3pids,
             request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
+            unbind_3pid,
         },
         config::{get_global_account_data, set_global_account_data},
         error::ErrorKind,
@@ -44,12 +52,53 @@ use ruma::{
     push::Ruleset,
     serde::Raw,
     thirdparty::Medium,
-    ClientSecret, MxcUri, OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId, SessionId, UInt, UserId,
+    ClientSecret, MilliSecondsSinceUnixEpoch, MxcUri, OwnedMxcUri, OwnedRoomId, OwnedUserId,
+    RoomId, SessionId, UInt, UserId,
 };
-use serde::Deserialize;
-use tracing::error;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+
+pub use self::observable::ObservableAccountData;
+use self::{
+    breadcrumbs::BreadcrumbsEventContent,
+    frequent_contacts::FrequentContactsEventContent,
+    recent_emojis::{RecentEmoji, RecentEmojisEventContent},
+    threepid::ThreepidValidation,
+};
+use crate::{
+    config::RequestConfig,
+    image_pack::{
+        load_emotes, AccountImagePackEventContent, EmoteRegistry, ImagePack, ImagePackRoomContent,
+        ImagePackRoomsEventContent, ObservableEmotes,
+    },
+    Client, Error, Result, SessionChange,
+};
+
+pub mod breadcrumbs;
+pub mod frequent_contacts;
+mod observable;
+pub mod recent_emojis;
+pub mod threepid;
+
+/// How long the emojis and contacts used in the events sent with the send
+/// queue are kept in memory before being recorded in the account data, so
+/// they can be batched.
+pub const USAGE_BATCH_DELAY: Duration = Duration::from_secs(5);
+
+/// The uses of emojis and contacts waiting to be recorded in the account data.
+#[derive(Default)]
+pub(crate) struct PendingUsage {
+    /// The emojis used in reactions, oldest first.
+    emojis: Vec<String>,
 
-use crate::{config::RequestConfig, Client, Error, Result};
+    /// The users that were sent a message in direct message rooms, oldest
+    /// first.
+    contacts: Vec<OwnedUserId>,
+
+    /// Whether a task to record the pending uses is scheduled.
+    flush_scheduled: bool,
+}
 
 /// A high-level API to manage the client owner's account.
 ///
@@ -116,7 +165,11 @@ impl Account {
         let request =
             set_display_name::v3::Request::new(user_id.to_owned(), name.map(ToOwned::to_owned));
         self.client.send(request).await?;
-        Ok(())
+        self.client
+            .update_cached_profile(user_id, |profile| {
+                profile.display_name = name.map(ToOwned::to_owned);
+            })
+            .await
     }
 
     /// Get the MXC URI of the account's avatar, if set.
@@ -188,7 +241,11 @@ impl Account {
         let request =
             set_avatar_url::v3::Request::new(user_id.to_owned(), url.map(ToOwned::to_owned));
         self.client.send(request).await?;
-        Ok(())
+        self.client
+            .update_cached_profile(user_id, |profile| {
+                profile.avatar_url = url.map(ToOwned::to_owned);
+            })
+            .await
     }
 
     /// Get the account's avatar, if set.
@@ -359,10 +416,25 @@ impl Account {
 
     /// Deactivate this account definitively.
     ///
+    /// Once the homeserver has deactivated the account, all the access tokens
+    /// of the account are invalidated, so the session of this client is torn
+    /// down locally:
+    ///
+    /// * a [`SessionChange::UnknownToken`] is broadcast, and the sync loops
+    ///   stop at their next request, since the homeserver rejects it,
+    /// * the rooms are forgotten, removing their data from the state store and
+    ///   the event cache store, and the sync token is removed.
+    ///
+    /// The homeserver removes the pushers of the account by itself. The
+    /// crypto store is wiped, since the keys of the device are useless now,
+    /// but it's up to the application to delete the stores of the session, as
+    /// it would do after a logout.
+    ///
     /// # Arguments
     ///
     /// * `id_server` - The identity server from which to unbind the user’s
-    ///   [Third Party Identifiers][3pid].
+    ///   [Third Party Identifiers][3pid]. If not provided, the homeserver
+    ///   unbinds them from the identity servers they were bound to.
     ///
     /// * `auth_data` - This request uses the [User-Interactive Authentication
     ///   API][uiaa]. The first request needs to set this to `None` and will
@@ -370,28 +442,36 @@ impl Account {
     ///   information for the interactive auth and the same request needs to be
     ///   made but this time with some `auth_data` provided.
     ///
-    /// * `erase` - Whether the user would like their content to be erased as
-    ///   much as possible from the server.
+    /// * `erase_data` - Whether the user would like their content to be erased
+    ///   as much as possible from the server.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use matrix_sdk::Client;
-    /// # use matrix_sdk::ruma::{
-    /// #     api::client::{
-    /// #         account::change_password::v3::{Request as ChangePasswordRequest},
-    /// #         uiaa::{AuthData, Dummy},
-    /// #     },
-    /// #     assign,
-    /// # };
+    /// # use matrix_sdk::ruma::api::client::uiaa::{AuthData, Password, UserIdentifier};
     /// # use url::Url;
     /// # async {
     /// # let homeserver = Url::parse("http://localhost:8080")?;
     /// # let client = Client::new(homeserver).await?;
-    /// # let account = client.account();
-    /// let response = account.deactivate(None, None, false).await;
+    /// # let password = "secret".to_owned();
+    /// let account = client.account();
+    ///
+    /// if let Err(error) = account.deactivate(None, None, false).await {
+    ///     let Some(info) = error.as_uiaa_response() else {
+    ///         return Err(error.into());
+    ///     };
+    ///
+    ///     // Proceed with UIAA, using the session of the response.
+    ///     let user_id = client.user_id().unwrap();
+    ///     let mut password = Password::new(
+    ///         UserIdentifier::UserIdOrLocalpart(user_id.to_string()),
+    ///         password,
+    ///     );
+    ///     password.session = info.session.clone();
     ///
-    /// // Proceed with UIAA.
+    ///     account.deactivate(None, Some(AuthData::Password(password)), false).await?;
+    /// }
     /// # anyhow::Ok(()) };
     /// ```
     /// [3pid]: https://spec.matrix.org/v1.2/appendices/#3pid-types
@@ -408,7 +488,44 @@ impl Account {
             auth: auth_data,
             erase: erase_data,
         });
-        Ok(self.client.send(request).await?)
+        let response = self.client.send(request).await?;
+
+        self.tear_down_session().await?;
+
+        Ok(response)
+    }
+
+    /// Remove the local data of the session, after the account has been
+    /// deactivated.
+    async fn tear_down_session(&self) -> Result<()> {
+        // Nothing can be synced anymore, and the sync loops must not write to the
+        // stores while they are wiped.
+        self.client.stop_sync();
+
+        // All the access tokens have been invalidated with the account, let the
+        // observers know that the session is over.
+        _ = self
+            .client
+            .auth_ctx()
+            .session_change_sender
+            .send(SessionChange::UnknownToken { soft_logout: false });
+
+        // Clear the in-memory caches first, so the live observers are notified.
+        if let Err(err) = self.client.event_cache().clear_all_rooms().await {
+            warn!("couldn't clear the event cache after deactivating the account: {err}");
+        }
+
+        for room in self.client.rooms() {
+            self.client.base_client().forget_room(room.room_id()).await?;
+        }
+
+        self.client.state_store().remove_kv_data(StateStoreDataKey::SyncToken).await?;
+
+        // The keys of the device are useless now, and must not be kept around.
+        #[cfg(feature = "e2e-encryption")]
+        self.client.base_client().clear_crypto_store().await?;
+
+        Ok(())
     }
 
     /// Get the registered [Third Party Identifiers][3pid] on the homeserver of
@@ -692,6 +809,100 @@ impl Account {
         Ok(self.client.send(request).await?)
     }
 
+    /// Unbind a [Third Party Identifier][3pid] from an identity server, while
+    /// keeping it on the homeserver.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The 3PID being unbound.
+    ///
+    /// * `medium` - The type of the 3PID.
+    ///
+    /// * `id_server` - The identity server to unbind from. If not provided, the
+    ///   homeserver should unbind the 3PID from the identity server it was
+    ///   bound to previously.
+    ///
+    /// [3pid]: https://spec.matrix.org/v1.2/appendices/#3pid-types
+    pub async fn unbind_3pid(
+        &self,
+        address: &str,
+        medium: Medium,
+        id_server: Option<&str>,
+    ) -> Result<unbind_3pid::v3::Response> {
+        let request = assign!(unbind_3pid::v3::Request::new(medium, address.to_owned()), {
+            id_server: id_server.map(ToOwned::to_owned),
+        });
+        Ok(self.client.send(request).await?)
+    }
+
+    /// Start the validation of an email address, to use it as a [Third Party
+    /// Identifier][3pid].
+    ///
+    /// The homeserver sends a token to the email address. Once it's
+    /// submitted, the returned [`ThreepidValidation`] gives a
+    /// [`ValidatedThreepid`] that can be added to the account.
+    ///
+    /// This method might return an [`ErrorKind::ThreepidInUse`] error if the
+    /// email address is already registered for this account or another, or an
+    /// [`ErrorKind::ThreepidDenied`] error if it is denied.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let token = "123456";
+    /// let validation =
+    ///     client.account().start_email_validation("john@matrix.org").await?;
+    ///
+    /// let threepid = if validation.expects_token() {
+    ///     // Prompt the user for the token they received.
+    ///     validation.submit_token(token).await?
+    /// } else {
+    ///     // Wait for the user to confirm that they followed the link they
+    ///     // received.
+    ///     validation.token_submitted()
+    /// };
+    ///
+    /// let uiaa_response = threepid.add(None).await;
+    ///
+    /// // Proceed with UIAA.
+    /// # anyhow::Ok(()) };
+    /// ```
+    /// [3pid]: https://spec.matrix.org/v1.2/appendices/#3pid-types
+    /// [`ValidatedThreepid`]: threepid::ValidatedThreepid
+    /// [`ErrorKind::ThreepidInUse`]: ruma::api::client::error::ErrorKind::ThreepidInUse
+    /// [`ErrorKind::ThreepidDenied`]: ruma::api::client::error::ErrorKind::ThreepidDenied
+    pub async fn start_email_validation(&self, email: &str) -> Result<ThreepidValidation> {
+        ThreepidValidation::email(self, email).await
+    }
+
+    /// Start the validation of a phone number, to use it as a [Third Party
+    /// Identifier][3pid].
+    ///
+    /// This works like [`Account::start_email_validation()`], except that the
+    /// token is sent to the phone number.
+    ///
+    /// # Arguments
+    ///
+    /// * `country` - The two-letter uppercase ISO-3166-1 alpha-2 country code
+    ///   that the number in phone_number should be parsed as if it were dialled
+    ///   from.
+    ///
+    /// * `phone_number` - The phone number to validate.
+    ///
+    /// [3pid]: https://spec.matrix.org/v1.2/appendices/#3pid-types
+    pub async fn start_msisdn_validation(
+        &self,
+        country: &str,
+        phone_number: &str,
+    ) -> Result<ThreepidValidation> {
+        ThreepidValidation::msisdn(self, country, phone_number).await
+    }
+
     /// Get the content of an account data event of statically-known type.
     ///
     /// # Examples
@@ -720,6 +931,45 @@ impl Account {
         get_raw_content(self.client.state_store().get_account_data_event_static::<C>().await?)
     }
 
+    /// Observe the content of an account data event of statically-known type.
+    ///
+    /// The returned [`ObservableAccountData`] holds the current content of the
+    /// event, loaded from the store, and is updated every time a new content is
+    /// received from the homeserver.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use futures_util::StreamExt;
+    /// # use matrix_sdk::Client;
+    /// # async {
+    /// # let client = Client::new("http://localhost:8080".parse()?).await?;
+    /// # let account = client.account();
+    /// use matrix_sdk::ruma::events::ignored_user_list::IgnoredUserListEventContent;
+    ///
+    /// let ignored_users =
+    ///     account.observe_account_data::<IgnoredUserListEventContent>().await?;
+    /// println!("Ignored users: {:?}", ignored_users.get());
+    ///
+    /// let mut updates = ignored_users.subscribe();
+    /// while let Some(content) = updates.next().await {
+    ///     println!("Ignored users changed: {content:?}");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn observe_account_data<C>(&self) -> Result<ObservableAccountData<C>>
+    where
+        C: GlobalAccountDataEventContent
+            + StaticEventContent
+            + DeserializeOwned
+            + Clone
+            + SendOutsideWasm
+            + SyncOutsideWasm
+            + 'static,
+    {
+        ObservableAccountData::global(&self.client).await
+    }
+
     /// Get the content of an account data event of a given type.
     pub async fn account_data_raw(
         &self,
@@ -883,26 +1133,39 @@ impl Account {
     }
 
     /// Adds the given user ID to the account's ignore list.
+    ///
+    /// The new ignore list is applied locally as soon as the homeserver
+    /// accepted it, so [`Client::ignored_users()`] is updated and the events
+    /// of the user are removed from the event cache without waiting for the
+    /// sync.
+    ///
+    /// [`Client::ignored_users()`]: crate::Client::ignored_users
     pub async fn ignore_user(&self, user_id: &UserId) -> Result<()> {
         let mut ignored_user_list = self.get_ignored_user_list_event_content().await?;
         ignored_user_list.ignored_users.insert(user_id.to_owned(), IgnoredUser::new());
 
-        // Updating the account data
-        self.set_account_data(ignored_user_list).await?;
-        // TODO: I think I should reset all the storage and perform a new local sync
-        // here but I don't know how
-        Ok(())
+        self.set_ignored_user_list(ignored_user_list).await
     }
 
     /// Removes the given user ID from the account's ignore list.
+    ///
+    /// The new ignore list is applied locally as soon as the homeserver
+    /// accepted it. The event cache is then cleared, so the events of the user
+    /// are loaded again from the homeserver.
     pub async fn unignore_user(&self, user_id: &UserId) -> Result<()> {
         let mut ignored_user_list = self.get_ignored_user_list_event_content().await?;
         ignored_user_list.ignored_users.remove(user_id);
 
+        self.set_ignored_user_list(ignored_user_list).await
+    }
+
+    async fn set_ignored_user_list(
+        &self,
+        ignored_user_list: IgnoredUserListEventContent,
+    ) -> Result<()> {
         // Updating the account data
-        self.set_account_data(ignored_user_list).await?;
-        // TODO: I think I should reset all the storage and perform a new local sync
-        // here but I don't know how
+        self.set_account_data(ignored_user_list.clone()).await?;
+        self.client.base_client().receive_ignored_user_list(ignored_user_list).await?;
         Ok(())
     }
 
@@ -980,6 +1243,383 @@ impl Account {
             .await?;
         Ok(())
     }
+
+    /// Get the emojis recently used by the account in reactions, the most
+    /// recent one first, from storage.
+    pub async fn recent_emojis(&self) -> Result<Vec<RecentEmoji>> {
+        Ok(self
+            .account_data::<RecentEmojisEventContent>()
+            .await?
+            .map(|raw| raw.deserialize())
+            .transpose()?
+            .map(|content| content.recent_emoji)
+            .unwrap_or_default())
+    }
+
+    /// Observe the emojis recently used by the account in reactions.
+    ///
+    /// See [`Account::observe_account_data()`] for more details.
+    pub async fn observe_recent_emojis(
+        &self,
+    ) -> Result<ObservableAccountData<RecentEmojisEventContent>> {
+        self.observe_account_data().await
+    }
+
+    /// Record a use of the given emoji in a reaction.
+    ///
+    /// This is called automatically for the reactions sent with the send
+    /// queue.
+    pub async fn add_recent_emoji(&self, emoji: &str) -> Result<()> {
+        self.add_recent_emojis(&[emoji.to_owned()]).await
+    }
+
+    /// Record the uses of the given emojis in reactions, oldest first, with a
+    /// single update of the account data.
+    async fn add_recent_emojis(&self, emojis: &[String]) -> Result<()> {
+        // The recent emojis are updated with a read/update/store of the account data
+        // event, make sure that concurrent calls don't trample on each other.
+        let _guard = self.client.locks().recent_emojis_lock.lock().await;
+
+        // We are fetching the content from the server because we can't rely on `/sync`
+        // having given us the latest update yet.
+        let mut content = self
+            .fetch_account_data(RecentEmojisEventContent::TYPE.into())
+            .await?
+            .map(|raw| raw.deserialize_as::<RecentEmojisEventContent>())
+            .transpose()?
+            .unwrap_or_default();
+
+        for emoji in emojis {
+            content.record(emoji);
+        }
+        self.set_account_data(content).await?;
+
+        Ok(())
+    }
+
+    /// Get the rooms recently opened by the account, the most recent one
+    /// first, from storage.
+    ///
+    /// They are shared with the other clients of the account with the
+    /// `im.vector.setting.breadcrumbs` global account data event.
+    pub async fn breadcrumbs(&self) -> Result<Vec<OwnedRoomId>> {
+        Ok(self
+            .account_data::<BreadcrumbsEventContent>()
+            .await?
+            .map(|raw| raw.deserialize())
+            .transpose()?
+            .map(|content| content.recent_rooms)
+            .unwrap_or_default())
+    }
+
+    /// Observe the rooms recently opened by the account.
+    ///
+    /// See [`Account::observe_account_data()`] for more details.
+    pub async fn observe_breadcrumbs(
+        &self,
+    ) -> Result<ObservableAccountData<BreadcrumbsEventContent>> {
+        self.observe_account_data().await
+    }
+
+    /// Record that the given room was opened, by moving it to the front of
+    /// the [breadcrumbs](Self::breadcrumbs).
+    ///
+    /// At most [`MAX_BREADCRUMBS`](breadcrumbs::MAX_BREADCRUMBS) rooms are
+    /// kept. Nothing is sent to the homeserver if the room was already the
+    /// most recent one.
+    pub async fn push_breadcrumb(&self, room_id: &RoomId) -> Result<()> {
+        // The breadcrumbs are updated with a read/update/store of the account data
+        // event, make sure that concurrent calls don't trample on each other.
+        let _guard = self.client.locks().breadcrumbs_lock.lock().await;
+
+        // We are fetching the content from the server because we can't rely on `/sync`
+        // having given us the latest update yet.
+        let mut content = self
+            .fetch_account_data(BreadcrumbsEventContent::TYPE.into())
+            .await?
+            .map(|raw| raw.deserialize_as::<BreadcrumbsEventContent>())
+            .transpose()?
+            .unwrap_or_default();
+
+        if content.push_room(room_id) {
+            self.set_account_data(content).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the image pack of the account, from storage.
+    pub async fn image_pack(&self) -> Result<Option<ImagePack>> {
+        ImagePack::load_user_pack(&self.client).await
+    }
+
+    /// Replace the image pack of the account.
+    pub async fn set_image_pack(&self, content: AccountImagePackEventContent) -> Result<()> {
+        self.set_account_data(content).await?;
+        Ok(())
+    }
+
+    /// Get the image packs that can be used in all rooms, from storage.
+    ///
+    /// This is the image pack of the account, followed by the image packs of
+    /// rooms that were enabled globally with
+    /// [`Account::enable_room_image_pack()`].
+    pub async fn image_packs(&self) -> Result<Vec<ImagePack>> {
+        let mut packs = Vec::from_iter(self.image_pack().await?);
+        packs.extend(ImagePack::load_enabled_room_packs(&self.client).await?);
+        Ok(packs)
+    }
+
+    /// Get the custom emotes that can be used in all rooms, from storage.
+    ///
+    /// They are the emotes of the image packs returned by
+    /// [`Account::image_packs()`].
+    pub async fn emotes(&self) -> Result<EmoteRegistry> {
+        load_emotes(&self.client, None).await
+    }
+
+    /// Observe the custom emotes that can be used in all rooms.
+    pub async fn observe_emotes(&self) -> Result<ObservableEmotes> {
+        ObservableEmotes::new(&self.client, None).await
+    }
+
+    /// Enable or disable an image pack of a room globally, so it can be used
+    /// in all rooms.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room where the image pack is defined.
+    ///
+    /// * `state_key` - The state key of the state event of the image pack.
+    ///
+    /// * `enable` - Whether the image pack should be enabled.
+    pub async fn enable_room_image_pack(
+        &self,
+        room_id: &RoomId,
+        state_key: &str,
+        enable: bool,
+    ) -> Result<()> {
+        // We are fetching the content from the server because we can't rely on `/sync`
+        // having given us the latest update yet.
+        let mut content = self
+            .fetch_account_data(ImagePackRoomsEventContent::TYPE.into())
+            .await?
+            .map(|raw| raw.deserialize_as::<ImagePackRoomsEventContent>())
+            .transpose()?
+            .unwrap_or_default();
+
+        if enable {
+            content
+                .rooms
+                .entry(room_id.to_owned())
+                .or_default()
+                .insert(state_key.to_owned(), ImagePackRoomContent::new());
+        } else if let Some(state_keys) = content.rooms.get_mut(room_id) {
+            state_keys.remove(state_key);
+
+            if state_keys.is_empty() {
+                content.rooms.remove(room_id);
+            }
+        }
+
+        self.set_account_data(content).await?;
+
+        Ok(())
+    }
+
+    /// Get the contacts of the account in direct messages, the most used one
+    /// first.
+    ///
+    /// The contacts are ranked by the number of messages sent to them, as
+    /// recorded in the `io.element.frequent_contacts` global account data
+    /// event. Contacts with the same number of messages are ranked by the
+    /// activity of their direct message room, measured with its recency
+    /// stamp, or the timestamp of its latest event when it doesn't have one.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of users to return.
+    pub async fn frequent_contacts(&self, limit: usize) -> Result<Vec<OwnedUserId>> {
+        let content = self
+            .account_data::<FrequentContactsEventContent>()
+            .await?
+            .map(|raw| raw.deserialize())
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(self.rank_frequent_contacts(&content, limit))
+    }
+
+    fn rank_frequent_contacts(
+        &self,
+        content: &FrequentContactsEventContent,
+        limit: usize,
+    ) -> Vec<OwnedUserId> {
+        let own_user_id = self.client.user_id();
+        let mut activities = BTreeMap::<OwnedUserId, _>::new();
+
+        for room in self.client.joined_rooms() {
+            let activity = (
+                room.recency_stamp(),
+                room.latest_event().and_then(|latest_event| {
+                    latest_event
+                        .event()
+                        .raw()
+                        .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                        .ok()
+                        .flatten()
+                }),
+            );
+
+            for target in room.direct_targets() {
+                let Some(user_id) = target.as_user_id() else { continue };
+                if Some(user_id) == own_user_id {
+                    continue;
+                }
+
+                let entry = activities.entry(user_id.to_owned()).or_insert(activity);
+                *entry = (*entry).max(activity);
+            }
+        }
+
+        let mut contacts = activities
+            .into_iter()
+            .map(|(user_id, activity)| {
+                let count = content.count(&user_id);
+                (user_id, (count, activity))
+            })
+            .collect::<Vec<_>>();
+        // Sort by decreasing use count, then by decreasing activity. The sort is
+        // stable, so contacts with the same rank stay sorted by user ID.
+        contacts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        contacts.into_iter().take(limit).map(|(user_id, _)| user_id).collect()
+    }
+
+    /// Get the contacts of the account in direct messages, and a stream of
+    /// updates for them.
+    ///
+    /// The ranking is computed again after every sync, and the stream only
+    /// yields it when it changed. See [`Account::frequent_contacts()`] for
+    /// more details.
+    pub async fn subscribe_to_frequent_contacts(
+        &self,
+        limit: usize,
+    ) -> Result<(Vec<OwnedUserId>, impl Stream<Item = Vec<OwnedUserId>>)> {
+        let mut room_updates = self.client.subscribe_to_all_room_updates();
+        let observable = self.observe_account_data::<FrequentContactsEventContent>().await?;
+        let mut content_updates = observable.subscribe();
+
+        let initial = self.rank_frequent_contacts(&observable.get().unwrap_or_default(), limit);
+
+        let account = self.clone();
+        let mut current = initial.clone();
+        let stream = stream! {
+            // Keep the observer alive as long as the stream.
+            let observable = observable;
+
+            loop {
+                tokio::select! {
+                    update = room_updates.recv() => match update {
+                        Ok(_) | Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                    content = content_updates.next() => {
+                        if content.is_none() {
+                            break;
+                        }
+                    }
+                }
+
+                let content = observable.get().unwrap_or_default();
+                let contacts = account.rank_frequent_contacts(&content, limit);
+                if contacts != current {
+                    current = contacts.clone();
+                    yield contacts;
+                }
+            }
+        };
+
+        Ok((initial, stream))
+    }
+
+    /// Record a message sent to the given users in direct messages.
+    ///
+    /// This is called automatically for the messages sent with the send queue
+    /// in direct message rooms.
+    pub async fn add_frequent_contacts(&self, user_ids: &[OwnedUserId]) -> Result<()> {
+        // The frequent contacts are updated with a read/update/store of the account
+        // data event, make sure that concurrent calls don't trample on each other.
+        let _guard = self.client.locks().frequent_contacts_lock.lock().await;
+
+        // We are fetching the content from the server because we can't rely on `/sync`
+        // having given us the latest update yet.
+        let mut content = self
+            .fetch_account_data(FrequentContactsEventContent::TYPE.into())
+            .await?
+            .map(|raw| raw.deserialize_as::<FrequentContactsEventContent>())
+            .transpose()?
+            .unwrap_or_default();
+
+        for user_id in user_ids {
+            content.record(user_id);
+        }
+        self.set_account_data(content).await?;
+
+        Ok(())
+    }
+
+    /// Queue a use of the given emoji in a reaction, to be recorded with the
+    /// other pending uses after [`USAGE_BATCH_DELAY`].
+    pub(crate) fn queue_recent_emoji(&self, emoji: String) {
+        let mut pending = self.client.inner.pending_usage.lock().unwrap();
+        pending.emojis.push(emoji);
+        self.schedule_usage_flush(&mut pending);
+    }
+
+    /// Queue a message sent to the given users in direct messages, to be
+    /// recorded with the other pending uses after [`USAGE_BATCH_DELAY`].
+    pub(crate) fn queue_frequent_contacts(&self, user_ids: Vec<OwnedUserId>) {
+        let mut pending = self.client.inner.pending_usage.lock().unwrap();
+        pending.contacts.extend(user_ids);
+        self.schedule_usage_flush(&mut pending);
+    }
+
+    /// Spawn a task to record the pending uses after a delay, if there isn't
+    /// one already.
+    fn schedule_usage_flush(&self, pending: &mut PendingUsage) {
+        if pending.flush_scheduled {
+            return;
+        }
+        pending.flush_scheduled = true;
+
+        let account = self.clone();
+        spawn(async move {
+            account.client.clock().sleep(USAGE_BATCH_DELAY).await;
+            account.flush_usage().await;
+        });
+    }
+
+    /// Record the pending uses of emojis and contacts in the account data,
+    /// with at most one update of each account data event.
+    async fn flush_usage(&self) {
+        let (emojis, contacts) = {
+            let mut pending = self.client.inner.pending_usage.lock().unwrap();
+            pending.flush_scheduled = false;
+            (std::mem::take(&mut pending.emojis), std::mem::take(&mut pending.contacts))
+        };
+
+        if !emojis.is_empty() {
+            if let Err(err) = self.add_recent_emojis(&emojis).await {
+                warn!("couldn't record the recently used emojis: {err}");
+            }
+        }
+
+        if !contacts.is_empty() {
+            if let Err(err) = self.add_frequent_contacts(&contacts).await {
+                warn!("couldn't record the frequent contacts: {err}");
+            }
+        }
+    }
 }
 
 fn get_raw_content<Ev, C>(raw: Option<Raw<Ev>>) -> Result<Option<Raw<C>>> {
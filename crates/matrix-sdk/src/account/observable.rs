@@ -0,0 +1,132 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::ready;
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_common::{SendOutsideWasm, SyncOutsideWasm};
+use ruma::events::{
+    GlobalAccountDataEvent, GlobalAccountDataEventContent, RoomAccountDataEvent,
+    RoomAccountDataEventContent, StaticEventContent,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{event_handler::EventHandlerDropGuard, Client, Result, Room};
+
+/// An observer of the content of an account data event, either global or
+/// specific to a room.
+///
+/// It starts with the content from the store, and is updated every time a new
+/// content is received from the homeserver.
+///
+/// Only the most recent content can be observed. Subscribers are notified when
+/// a new content is received, but rapid updates are coalesced: there is no
+/// guarantee that they will see all the intermediate contents.
+///
+/// To create such observer, use [`Account::observe_account_data()`] or
+/// [`Room::observe_account_data()`]. The observer stops receiving updates as
+/// soon as it's dropped.
+///
+/// [`Account::observe_account_data()`]: crate::Account::observe_account_data
+#[derive(Debug)]
+pub struct ObservableAccountData<C> {
+    observable: SharedObservable<Option<C>>,
+    _event_handler_guard: EventHandlerDropGuard,
+}
+
+impl<C> ObservableAccountData<C>
+where
+    C: Clone + SendOutsideWasm + SyncOutsideWasm + 'static,
+{
+    /// Observe the global account data event of type `C`.
+    pub(crate) async fn global(client: &Client) -> Result<Self>
+    where
+        C: StaticEventContent + GlobalAccountDataEventContent + DeserializeOwned,
+    {
+        let observable = SharedObservable::new(None);
+
+        // Register the event handler before loading the content from the store, so no
+        // update can be missed in between.
+        let event_handler_guard = client.event_handler_drop_guard(client.add_event_handler({
+            let observable = observable.clone();
+            move |event: GlobalAccountDataEvent<C>| {
+                observable.set(Some(event.content));
+                ready(())
+            }
+        }));
+
+        let stored = client.account().account_data::<C>().await?;
+        let this = Self { observable, _event_handler_guard: event_handler_guard };
+        this.set_initial_content(stored.map(|raw| raw.deserialize()).transpose()?);
+
+        Ok(this)
+    }
+
+    /// Observe the account data event of type `C` in the given room.
+    pub(crate) async fn room(room: &Room) -> Result<Self>
+    where
+        C: StaticEventContent + RoomAccountDataEventContent + DeserializeOwned,
+    {
+        let client = room.client();
+        let observable = SharedObservable::new(None);
+
+        // Register the event handler before loading the content from the store, so no
+        // update can be missed in between.
+        let event_handler_guard =
+            client.event_handler_drop_guard(client.add_room_event_handler(room.room_id(), {
+                let observable = observable.clone();
+                move |event: RoomAccountDataEvent<C>| {
+                    observable.set(Some(event.content));
+                    ready(())
+                }
+            }));
+
+        let stored = room.account_data_static::<C>().await?;
+        let this = Self { observable, _event_handler_guard: event_handler_guard };
+        this.set_initial_content(
+            stored.map(|raw| raw.deserialize()).transpose()?.map(|ev| ev.content),
+        );
+
+        Ok(this)
+    }
+
+    /// Set the content loaded from the store, unless a newer one was received
+    /// from the homeserver in the meantime.
+    fn set_initial_content(&self, content: Option<C>) {
+        if let Some(content) = content {
+            self.observable.update_if(|value| {
+                if value.is_some() {
+                    return false;
+                }
+
+                *value = Some(content);
+                true
+            });
+        }
+    }
+
+    /// Get the current content of the account data event, or `None` if it
+    /// has never been set.
+    pub fn get(&self) -> Option<C> {
+        self.observable.get()
+    }
+
+    /// Subscribe to the updates of the content of the account data event.
+    ///
+    /// The returned [`Subscriber`] implements `Stream`, yielding the new
+    /// content each time it changes. It ends when this observer is dropped.
+    pub fn subscribe(&self) -> Subscriber<Option<C>> {
+        self.observable.subscribe()
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The emojis recently used by the account in reactions, shared between its
+//! clients with the `io.element.recent_emoji` global account data event.
+
+use ruma::{events::macros::EventContent, uint, UInt};
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of emojis kept in the [`RecentEmojisEventContent`].
+pub const MAX_RECENT_EMOJIS: usize = 100;
+
+/// The content of the `io.element.recent_emoji` global account data event.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "io.element.recent_emoji", kind = GlobalAccountData)]
+pub struct RecentEmojisEventContent {
+    /// The recently used emojis, the most recent one first.
+    #[serde(default)]
+    pub recent_emoji: Vec<RecentEmoji>,
+}
+
+impl RecentEmojisEventContent {
+    /// Record a use of the given emoji.
+    ///
+    /// The emoji is moved to the front of the list, and its use count is
+    /// incremented. The least recently used emojis are dropped if the list
+    /// grows longer than [`MAX_RECENT_EMOJIS`].
+    pub fn record(&mut self, emoji: &str) {
+        let count = match self.recent_emoji.iter().position(|recent| recent.emoji == emoji) {
+            Some(index) => self.recent_emoji.remove(index).count.saturating_add(uint!(1)),
+            None => uint!(1),
+        };
+
+        self.recent_emoji.insert(0, RecentEmoji { emoji: emoji.to_owned(), count });
+        self.recent_emoji.truncate(MAX_RECENT_EMOJIS);
+    }
+}
+
+/// An emoji recently used by the account.
+///
+/// It is serialized as an `[emoji, count]` array.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "(String, UInt)", into = "(String, UInt)")]
+pub struct RecentEmoji {
+    /// The emoji.
+    pub emoji: String,
+
+    /// How many times the emoji was used.
+    pub count: UInt,
+}
+
+impl From<(String, UInt)> for RecentEmoji {
+    fn from((emoji, count): (String, UInt)) -> Self {
+        Self { emoji, count }
+    }
+}
+
+impl From<RecentEmoji> for (String, UInt) {
+    fn from(recent: RecentEmoji) -> Self {
+        (recent.emoji, recent.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::uint;
+    use serde_json::{from_value, json, to_value};
+
+    use super::{RecentEmojisEventContent, MAX_RECENT_EMOJIS};
+
+    #[test]
+    fn test_record() {
+        let mut content = RecentEmojisEventContent::default();
+
+        content.record("👍");
+        content.record("🎉");
+        content.record("👍");
+
+        assert_eq!(to_value(&content).unwrap(), json!({ "recent_emoji": [["👍", 2], ["🎉", 1]] }));
+    }
+
+    #[test]
+    fn test_record_drops_the_least_recent_emojis() {
+        let mut content = RecentEmojisEventContent::default();
+
+        for i in 0..=MAX_RECENT_EMOJIS {
+            content.record(&i.to_string());
+        }
+
+        assert_eq!(content.recent_emoji.len(), MAX_RECENT_EMOJIS);
+        assert_eq!(content.recent_emoji[0].emoji, MAX_RECENT_EMOJIS.to_string());
+        assert!(!content.recent_emoji.iter().any(|recent| recent.emoji == "0"));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let content: RecentEmojisEventContent =
+            from_value(json!({ "recent_emoji": [["😀", 5]] })).unwrap();
+        assert_eq!(content.recent_emoji[0].emoji, "😀");
+        assert_eq!(content.recent_emoji[0].count, uint!(5));
+
+        let content: RecentEmojisEventContent = from_value(json!({})).unwrap();
+        assert!(content.recent_emoji.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The flows to validate the ownership of a [Third Party Identifier][3pid],
+//! and then add it to the account or bind it to an identity server.
+//!
+//! The validation starts with [`Account::start_email_validation()`] or
+//! [`Account::start_msisdn_validation()`], which ask the homeserver to send a
+//! token to the 3PID and return a [`ThreepidValidation`]. Once the token has
+//! been submitted, the [`ValidatedThreepid`] can be added to the account or
+//! bound to an identity server.
+//!
+//! [3pid]: https://spec.matrix.org/v1.2/appendices/#3pid-types
+
+use ruma::{
+    api::client::{
+        account::{add_3pid, bind_3pid, IdentityServerInfo},
+        uiaa::AuthData,
+    },
+    assign,
+    thirdparty::Medium,
+    uint, ClientSecret, OwnedClientSecret, OwnedSessionId, SessionId, UInt,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Account;
+use crate::{Client, Result};
+
+/// An error that happened while submitting the token that validates a 3PID.
+#[derive(Debug, Error)]
+pub enum ThreepidValidationError {
+    /// The homeserver didn't provide a URL to submit the token to.
+    ///
+    /// The token must be submitted out-of-band, for example by following the
+    /// link in the email that was sent to the user.
+    #[error("the token of this 3PID can't be submitted by the client")]
+    NoSubmitUrl,
+
+    /// The token was rejected by the server.
+    #[error("the token was rejected")]
+    TokenRejected,
+
+    /// The request to submit the token failed.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    /// The response to the token submission could not be deserialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// A 3PID waiting for the token sent by the homeserver to be submitted.
+///
+/// This is the first state of the validation of a 3PID. The next state,
+/// [`ValidatedThreepid`], is reached with
+/// [`ThreepidValidation::submit_token()`] or, when the token was submitted
+/// out-of-band, with [`ThreepidValidation::token_submitted()`].
+#[derive(Debug, Clone)]
+pub struct ThreepidValidation {
+    client: Client,
+    medium: Medium,
+    address: String,
+    country: Option<String>,
+    client_secret: OwnedClientSecret,
+    sid: OwnedSessionId,
+    submit_url: Option<String>,
+    send_attempt: UInt,
+}
+
+impl ThreepidValidation {
+    /// Ask the homeserver to send a token to the given email address.
+    pub(super) async fn email(account: &Account, email: &str) -> Result<Self> {
+        let client_secret = ClientSecret::new();
+        let send_attempt = uint!(1);
+        let response =
+            account.request_3pid_email_token(&client_secret, email, send_attempt).await?;
+
+        Ok(Self {
+            client: account.client.clone(),
+            medium: Medium::Email,
+            address: email.to_owned(),
+            country: None,
+            client_secret,
+            sid: response.sid,
+            submit_url: response.submit_url,
+            send_attempt,
+        })
+    }
+
+    /// Ask the homeserver to send a token to the given phone number.
+    pub(super) async fn msisdn(
+        account: &Account,
+        country: &str,
+        phone_number: &str,
+    ) -> Result<Self> {
+        let client_secret = ClientSecret::new();
+        let send_attempt = uint!(1);
+        let response = account
+            .request_3pid_msisdn_token(&client_secret, country, phone_number, send_attempt)
+            .await?;
+
+        Ok(Self {
+            client: account.client.clone(),
+            medium: Medium::Msisdn,
+            address: phone_number.to_owned(),
+            country: Some(country.to_owned()),
+            client_secret,
+            sid: response.sid,
+            submit_url: response.submit_url,
+            send_attempt,
+        })
+    }
+
+    /// The type of the 3PID being validated.
+    pub fn medium(&self) -> &Medium {
+        &self.medium
+    }
+
+    /// The address of the 3PID being validated.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The identifier of the validation session.
+    pub fn sid(&self) -> &SessionId {
+        &self.sid
+    }
+
+    /// Whether the token must be given to the client, to be submitted with
+    /// [`ThreepidValidation::submit_token()`].
+    ///
+    /// If this is `false`, the token is submitted out-of-band, for example by
+    /// following a link, and the user should confirm when it's done.
+    pub fn expects_token(&self) -> bool {
+        self.submit_url.is_some()
+    }
+
+    /// Ask the homeserver to send a new token, if the user didn't receive the
+    /// previous one.
+    pub async fn resend_token(&mut self) -> Result<()> {
+        let account = Account::new(self.client.clone());
+        let send_attempt = self.send_attempt + uint!(1);
+
+        let (sid, submit_url) = match &self.country {
+            Some(country) => {
+                let response = account
+                    .request_3pid_msisdn_token(
+                        &self.client_secret,
+                        country,
+                        &self.address,
+                        send_attempt,
+                    )
+                    .await?;
+                (response.sid, response.submit_url)
+            }
+            None => {
+                let response = account
+                    .request_3pid_email_token(&self.client_secret, &self.address, send_attempt)
+                    .await?;
+                (response.sid, response.submit_url)
+            }
+        };
+
+        self.sid = sid;
+        self.submit_url = submit_url;
+        self.send_attempt = send_attempt;
+
+        Ok(())
+    }
+
+    /// Submit the token that was sent to the 3PID.
+    ///
+    /// If the token is rejected, this can be called again with another token.
+    pub async fn submit_token(
+        &self,
+        token: &str,
+    ) -> Result<ValidatedThreepid, ThreepidValidationError> {
+        #[derive(Serialize)]
+        struct SubmitTokenRequest<'a> {
+            sid: &'a SessionId,
+            client_secret: &'a ClientSecret,
+            token: &'a str,
+        }
+
+        #[derive(Deserialize)]
+        struct SubmitTokenResponse {
+            success: bool,
+        }
+
+        let submit_url = self.submit_url.as_deref().ok_or(ThreepidValidationError::NoSubmitUrl)?;
+        let body = serde_json::to_vec(&SubmitTokenRequest {
+            sid: &self.sid,
+            client_secret: &self.client_secret,
+            token,
+        })?;
+
+        let response = self
+            .client
+            .http_client()
+            .post(submit_url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        if serde_json::from_slice::<SubmitTokenResponse>(&response)?.success {
+            Ok(self.validated())
+        } else {
+            Err(ThreepidValidationError::TokenRejected)
+        }
+    }
+
+    /// Confirm that the token was submitted out-of-band, for example by
+    /// following the link in the email that was sent to the user.
+    ///
+    /// If it wasn't, the homeserver will reject the next requests using the
+    /// [`ValidatedThreepid`].
+    pub fn token_submitted(self) -> ValidatedThreepid {
+        self.validated()
+    }
+
+    fn validated(&self) -> ValidatedThreepid {
+        ValidatedThreepid {
+            client: self.client.clone(),
+            medium: self.medium.clone(),
+            address: self.address.clone(),
+            client_secret: self.client_secret.clone(),
+            sid: self.sid.clone(),
+        }
+    }
+}
+
+/// A 3PID whose ownership has been validated.
+///
+/// It can be added to the account with [`ValidatedThreepid::add()`], and bound
+/// to an identity server with [`ValidatedThreepid::bind()`].
+#[derive(Debug, Clone)]
+pub struct ValidatedThreepid {
+    client: Client,
+    medium: Medium,
+    address: String,
+    client_secret: OwnedClientSecret,
+    sid: OwnedSessionId,
+}
+
+impl ValidatedThreepid {
+    /// The type of the 3PID.
+    pub fn medium(&self) -> &Medium {
+        &self.medium
+    }
+
+    /// The address of the 3PID.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Add the 3PID to the account.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_data` - This request uses the [User-Interactive Authentication
+    ///   API][uiaa]. The first request needs to set this to `None` and will
+    ///   always fail with an [`UiaaResponse`]. The response will contain
+    ///   information for the interactive auth and the same request needs to be
+    ///   made but this time with some `auth_data` provided.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    /// [`UiaaResponse`]: ruma::api::client::uiaa::UiaaResponse
+    pub async fn add(&self, auth_data: Option<AuthData>) -> Result<()> {
+        let request = assign!(
+            add_3pid::v3::Request::new(self.client_secret.clone(), self.sid.clone()),
+            { auth: auth_data }
+        );
+        self.client.send(request).await?;
+        Ok(())
+    }
+
+    /// Bind the 3PID to an identity server, so other users can find the
+    /// account with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id_server` - The hostname of the identity server, with the port if
+    ///   it's not the default one.
+    ///
+    /// * `id_access_token` - An access token previously registered with the
+    ///   identity server.
+    pub async fn bind(&self, id_server: &str, id_access_token: &str) -> Result<()> {
+        let request = bind_3pid::v3::Request::new(
+            self.client_secret.clone(),
+            IdentityServerInfo::new(id_server.to_owned(), id_access_token.to_owned()),
+            self.sid.clone(),
+        );
+        self.client.send(request).await?;
+        Ok(())
+    }
+}
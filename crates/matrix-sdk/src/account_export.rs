@@ -0,0 +1,558 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the data of an account, for data portability.
+//!
+//! [`Client::export_account_data()`] writes an archive of the data the client
+//! knows about the account: the profile of the user, their account data, and
+//! the history of their joined rooms. The archive is written in the [JSON
+//! Lines] format, where each line is an [`ExportRecord`].
+//!
+//! [JSON Lines]: https://jsonlines.org/
+
+use std::{io::Write, time::Duration};
+
+use matrix_sdk_base::{
+    deserialized_responses::TimelineEvent,
+    event_cache::{store::DEFAULT_CHUNK_CAPACITY, Gap},
+    linked_chunk::{lazy_loader::from_all_chunks, ChunkContent},
+    media::{MediaFormat, MediaRequestParameters},
+};
+use ruma::{
+    events::{
+        room::{message::MessageType, MediaSource},
+        AnyGlobalAccountDataEventContent, AnyRoomAccountDataEvent, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent, GlobalAccountDataEventType, RoomAccountDataEventType,
+    },
+    serde::{Base64, Raw},
+    uint, OwnedEventId, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, UInt,
+};
+use serde::Serialize;
+use tracing::{instrument, warn};
+
+use crate::{room::MessagesOptions, sleep::sleep, Client, Error, Result, Room};
+
+/// The global account data exported by [`Client::export_account_data()`].
+const GLOBAL_ACCOUNT_DATA_TYPES: &[GlobalAccountDataEventType] = &[
+    GlobalAccountDataEventType::Direct,
+    GlobalAccountDataEventType::IdentityServer,
+    GlobalAccountDataEventType::IgnoredUserList,
+    GlobalAccountDataEventType::PushRules,
+];
+
+/// The room account data exported by [`Client::export_account_data()`].
+const ROOM_ACCOUNT_DATA_TYPES: &[RoomAccountDataEventType] = &[
+    RoomAccountDataEventType::FullyRead,
+    RoomAccountDataEventType::MarkedUnread,
+    RoomAccountDataEventType::Tag,
+];
+
+/// Options for [`Client::export_account_data()`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct AccountExportOptions {
+    /// Fetch older events from the homeserver until each room has at least
+    /// this many events, or until the start of its history is reached.
+    ///
+    /// Defaults to `0`, which only exports the events that are already in the
+    /// event cache.
+    pub min_events_per_room: usize,
+
+    /// The number of events to request at once when fetching older events.
+    ///
+    /// Defaults to `100`.
+    pub pagination_limit: UInt,
+
+    /// How long to wait between two requests for older events, to not hit the
+    /// rate limits of the homeserver.
+    ///
+    /// Requests that are rate-limited anyway are retried after the delay
+    /// requested by the homeserver. Defaults to 500 milliseconds.
+    pub pagination_delay: Duration,
+
+    /// Whether to download the media referenced by the exported events and
+    /// include them in the archive.
+    ///
+    /// Defaults to `false`, which only exports the references to the media.
+    pub include_media: bool,
+}
+
+impl AccountExportOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for AccountExportOptions {
+    fn default() -> Self {
+        Self {
+            min_events_per_room: 0,
+            pagination_limit: uint!(100),
+            pagination_delay: Duration::from_millis(500),
+            include_media: false,
+        }
+    }
+}
+
+/// A record of the archive written by [`Client::export_account_data()`].
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportRecord {
+    /// The profile of the user.
+    Profile {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+
+        /// The display name of the user, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        display_name: Option<String>,
+
+        /// The avatar of the user, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        avatar_url: Option<OwnedMxcUri>,
+    },
+
+    /// Global account data.
+    AccountData {
+        /// The type of the account data.
+        event_type: GlobalAccountDataEventType,
+
+        /// The content of the account data.
+        content: Raw<AnyGlobalAccountDataEventContent>,
+    },
+
+    /// A joined room.
+    Room {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+
+        /// The name of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+
+        /// The topic of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        topic: Option<String>,
+
+        /// The canonical alias of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        canonical_alias: Option<OwnedRoomAliasId>,
+    },
+
+    /// Account data of a room.
+    RoomAccountData {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+
+        /// The account data event.
+        event: Raw<AnyRoomAccountDataEvent>,
+    },
+
+    /// An event of a room, decrypted if possible.
+    Event {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+
+        /// The event.
+        event: Raw<AnySyncTimelineEvent>,
+    },
+
+    /// A media referenced by the profile or by an event.
+    Media {
+        /// The room of the event referencing the media, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        room_id: Option<OwnedRoomId>,
+
+        /// The event referencing the media, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        event_id: Option<OwnedEventId>,
+
+        /// The source of the media.
+        source: MediaSource,
+
+        /// The content of the media, if it was downloaded.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<Base64>,
+    },
+}
+
+/// A summary of what [`Client::export_account_data()`] exported.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AccountExportSummary {
+    /// The number of exported rooms.
+    pub num_rooms: usize,
+
+    /// The number of exported events.
+    pub num_events: usize,
+
+    /// The number of exported media references.
+    pub num_media: usize,
+}
+
+impl Client {
+    /// Export the data of the account, for data portability.
+    ///
+    /// An archive is written to `writer`, in the [JSON Lines] format where
+    /// each line is an [`ExportRecord`]. It contains:
+    ///
+    /// - the profile of the user,
+    /// - the well-known global account data, like the direct rooms and the
+    ///   ignored users,
+    /// - for each joined room, its name, topic and account data, and the events
+    ///   of its history that are in the event cache, in chronological order.
+    ///   More history can be fetched from the homeserver with
+    ///   [`AccountExportOptions::min_events_per_room`].
+    /// - the references to the media of the profile and the events, along with
+    ///   their content if [`AccountExportOptions::include_media`] is set.
+    ///
+    /// The event cache must have storage enabled for the history of the rooms
+    /// to be exported from the cache.
+    ///
+    /// [JSON Lines]: https://jsonlines.org/
+    #[instrument(skip_all)]
+    pub async fn export_account_data(
+        &self,
+        mut writer: impl Write,
+        options: AccountExportOptions,
+    ) -> Result<AccountExportSummary> {
+        let user_id = self.user_id().ok_or(Error::AuthenticationRequired)?.to_owned();
+        let mut summary = AccountExportSummary::default();
+
+        let profile = self.account().fetch_user_profile().await?;
+        write_record(
+            &mut writer,
+            &ExportRecord::Profile {
+                user_id,
+                display_name: profile.displayname,
+                avatar_url: profile.avatar_url.clone(),
+            },
+        )?;
+
+        if let Some(avatar_url) = profile.avatar_url {
+            self.export_media(&mut writer, None, None, MediaSource::Plain(avatar_url), &options)
+                .await?;
+            summary.num_media += 1;
+        }
+
+        for event_type in GLOBAL_ACCOUNT_DATA_TYPES {
+            if let Some(content) = self.account().account_data_raw(event_type.clone()).await? {
+                write_record(
+                    &mut writer,
+                    &ExportRecord::AccountData { event_type: event_type.clone(), content },
+                )?;
+            }
+        }
+
+        for room in self.joined_rooms() {
+            self.export_room(&mut writer, &room, &options, &mut summary).await?;
+        }
+
+        writer.flush()?;
+
+        Ok(summary)
+    }
+
+    #[instrument(skip_all, fields(room_id = %room.room_id()))]
+    async fn export_room(
+        &self,
+        writer: &mut impl Write,
+        room: &Room,
+        options: &AccountExportOptions,
+        summary: &mut AccountExportSummary,
+    ) -> Result<()> {
+        let room_id = room.room_id().to_owned();
+
+        write_record(
+            writer,
+            &ExportRecord::Room {
+                room_id: room_id.clone(),
+                name: room.name(),
+                topic: room.topic(),
+                canonical_alias: room.canonical_alias(),
+            },
+        )?;
+        summary.num_rooms += 1;
+
+        for event_type in ROOM_ACCOUNT_DATA_TYPES {
+            if let Some(event) = room.account_data(event_type.clone()).await? {
+                write_record(
+                    writer,
+                    &ExportRecord::RoomAccountData { room_id: room_id.clone(), event },
+                )?;
+            }
+        }
+
+        let (mut events, mut prev_token, mut reached_start) = self.cached_history(room).await?;
+
+        // Fetch older events from the homeserver, if needs be.
+        let mut older_events = Vec::new();
+        let mut is_first_request = true;
+
+        while !reached_start && events.len() + older_events.len() < options.min_events_per_room {
+            if !is_first_request {
+                sleep(options.pagination_delay).await;
+            }
+            is_first_request = false;
+
+            let mut messages_options = MessagesOptions::backward().from(prev_token.as_deref());
+            messages_options.limit = options.pagination_limit;
+
+            let messages = room.messages(messages_options).await?;
+
+            reached_start = messages.end.is_none() || messages.chunk.is_empty();
+            older_events.extend(messages.chunk);
+            prev_token = messages.end;
+        }
+
+        // The older events are received in reverse chronological order.
+        older_events.reverse();
+        older_events.append(&mut events);
+
+        for event in older_events {
+            self.export_event(writer, &room_id, event, options, summary).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load the events of the room that are in the event cache, in
+    /// chronological order.
+    ///
+    /// Returns the events, the token to fetch older events, and whether the
+    /// start of the history of the room is known to be reached.
+    async fn cached_history(
+        &self,
+        room: &Room,
+    ) -> Result<(Vec<TimelineEvent>, Option<String>, bool)> {
+        let chunks = self.event_cache_store().lock().await?.load_all_chunks(room.room_id()).await?;
+
+        let linked_chunk =
+            match from_all_chunks::<DEFAULT_CHUNK_CAPACITY, TimelineEvent, Gap>(chunks) {
+                Ok(Some(linked_chunk)) => linked_chunk,
+                Ok(None) => return Ok((Vec::new(), None, false)),
+                Err(error) => {
+                    warn!("Couldn't load the cached history of the room: {error}");
+                    return Ok((Vec::new(), None, false));
+                }
+            };
+
+        let events = linked_chunk.items().map(|(_, event)| event.clone()).collect();
+
+        // If the first chunk is a gap, there's more history before the cached events.
+        // Otherwise, the start of the room has been reached.
+        let prev_token = match linked_chunk.chunks().next().map(|chunk| chunk.content()) {
+            Some(ChunkContent::Gap(gap)) => Some(gap.prev_token.clone()),
+            _ => None,
+        };
+        let reached_start = prev_token.is_none();
+
+        Ok((events, prev_token, reached_start))
+    }
+
+    async fn export_event(
+        &self,
+        writer: &mut impl Write,
+        room_id: &OwnedRoomId,
+        event: TimelineEvent,
+        options: &AccountExportOptions,
+        summary: &mut AccountExportSummary,
+    ) -> Result<()> {
+        let event_id = event.event_id();
+        let media_source = media_source(event.raw());
+
+        write_record(
+            writer,
+            &ExportRecord::Event { room_id: room_id.clone(), event: event.into_raw() },
+        )?;
+        summary.num_events += 1;
+
+        if let Some(source) = media_source {
+            self.export_media(writer, Some(room_id.clone()), event_id, source, options).await?;
+            summary.num_media += 1;
+        }
+
+        Ok(())
+    }
+
+    async fn export_media(
+        &self,
+        writer: &mut impl Write,
+        room_id: Option<OwnedRoomId>,
+        event_id: Option<OwnedEventId>,
+        source: MediaSource,
+        options: &AccountExportOptions,
+    ) -> Result<()> {
+        let data = if options.include_media {
+            let request =
+                MediaRequestParameters { source: source.clone(), format: MediaFormat::File };
+
+            match self.media().get_media_content(&request, true).await {
+                Ok(data) => Some(Base64::new(data)),
+                Err(error) => {
+                    warn!("Couldn't download a media to export it: {error}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        write_record(writer, &ExportRecord::Media { room_id, event_id, source, data })
+    }
+}
+
+/// Get the source of the media referenced by the given event, if any.
+fn media_source(event: &Raw<AnySyncTimelineEvent>) -> Option<MediaSource> {
+    let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(message)) =
+        event.deserialize().ok()?
+    else {
+        return None;
+    };
+
+    match message.as_original()?.content.msgtype.clone() {
+        MessageType::Audio(content) => Some(content.source),
+        MessageType::File(content) => Some(content.source),
+        MessageType::Image(content) => Some(content.source),
+        MessageType::Video(content) => Some(content.source),
+        _ => None,
+    }
+}
+
+/// Write a record of the archive, on its own line.
+fn write_record(writer: &mut impl Write, record: &ExportRecord) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_test::{async_test, event_factory::EventFactory};
+    use ruma::{event_id, mxc_uri, owned_mxc_uri, room_id, serde::Base64, user_id};
+    use serde_json::{json, Value as JsonValue};
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, ResponseTemplate,
+    };
+
+    use super::{AccountExportOptions, AccountExportSummary};
+    use crate::test_utils::mocks::{MatrixMockServer, RoomMessagesResponseTemplate};
+
+    #[async_test]
+    async fn test_export_account_data() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@example:localhost"));
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/profile/@example:localhost"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "displayname": "Example",
+                "avatar_url": "mxc://localhost/avatar",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex("^/_matrix/client/v1/media/download/localhost/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"media".to_vec()))
+            .mount(server.server())
+            .await;
+
+        server.sync_joined_room(&client, room_id).await;
+
+        // Nothing is cached, so the history is fetched from the homeserver, in reverse
+        // chronological order.
+        server
+            .mock_room_messages()
+            .ok(RoomMessagesResponseTemplate::default().events(vec![
+                f.image("cat.png".to_owned(), owned_mxc_uri!("mxc://localhost/cat"))
+                    .event_id(event_id!("$2")),
+                f.text_msg("hello").event_id(event_id!("$1")),
+            ]))
+            .mock_once()
+            .mount()
+            .await;
+
+        let mut options = AccountExportOptions::new();
+        options.min_events_per_room = 10;
+        options.include_media = true;
+
+        let mut archive = Vec::new();
+        let summary = client.export_account_data(&mut archive, options).await.unwrap();
+
+        assert_eq!(summary, AccountExportSummary { num_rooms: 1, num_events: 2, num_media: 2 });
+
+        let records = String::from_utf8(archive)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 6);
+
+        assert_eq!(records[0]["type"], "profile");
+        assert_eq!(records[0]["user_id"], "@example:localhost");
+        assert_eq!(records[0]["display_name"], "Example");
+
+        assert_eq!(records[1]["type"], "media");
+        assert_eq!(records[1]["source"]["url"], "mxc://localhost/avatar");
+        let data: Base64 = Base64::parse(records[1]["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data.as_bytes(), b"media");
+
+        assert_eq!(records[2]["type"], "room");
+        assert_eq!(records[2]["room_id"], room_id.as_str());
+
+        // The events are in chronological order.
+        assert_eq!(records[3]["type"], "event");
+        assert_eq!(records[3]["event"]["event_id"], "$1");
+        assert_eq!(records[4]["type"], "event");
+        assert_eq!(records[4]["event"]["event_id"], "$2");
+
+        assert_eq!(records[5]["type"], "media");
+        assert_eq!(records[5]["event_id"], "$2");
+        assert_eq!(records[5]["source"]["url"], mxc_uri!("mxc://localhost/cat").as_str());
+    }
+
+    #[async_test]
+    async fn test_export_account_data_only_cached_history_by_default() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/profile/@example:localhost"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(server.server())
+            .await;
+
+        server.sync_joined_room(&client, room_id!("!room:localhost")).await;
+
+        // The homeserver isn't asked for more history.
+        server
+            .mock_room_messages()
+            .ok(RoomMessagesResponseTemplate::default())
+            .never()
+            .mount()
+            .await;
+
+        let mut archive = Vec::new();
+        let summary =
+            client.export_account_data(&mut archive, AccountExportOptions::new()).await.unwrap();
+
+        assert_eq!(summary, AccountExportSummary { num_rooms: 1, num_events: 0, num_media: 0 });
+    }
+}
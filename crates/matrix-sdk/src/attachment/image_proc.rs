@@ -0,0 +1,1089 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-side processing of images for attachments: generation of thumbnails
+//! and [BlurHash]es, and transcoding before upload.
+//!
+//! JPEG, PNG and WebP images are supported. Other formats can be supported by
+//! enabling them on the `image` crate.
+//!
+//! [BlurHash]: https://blurha.sh/
+
+use std::{f32::consts::PI, io::Cursor};
+
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageReader, RgbImage,
+};
+use mime::Mime;
+use ruma::UInt;
+
+use super::{BaseImageInfo, Thumbnail};
+
+/// The default maximum size of a generated thumbnail, in pixels.
+const DEFAULT_THUMBNAIL_SIZE: (u32, u32) = (800, 600);
+
+/// The number of horizontal and vertical components of a generated BlurHash.
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+/// The maximum size of the image a BlurHash is computed from.
+///
+/// A BlurHash only keeps a handful of components, so computing it from a
+/// small version of the image gives the same result for a fraction of the
+/// cost.
+const BLURHASH_SOURCE_SIZE: u32 = 64;
+
+/// The JPEG quality used when an image is re-encoded in its original JPEG
+/// format.
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// The EXIF tag of the pointer to the GPS information.
+const EXIF_GPS_INFO_TAG: u16 = 0x8825;
+
+const BASE83_CHARACTERS: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// The format of a generated thumbnail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    /// Use the same format as the original image.
+    #[default]
+    Original,
+    /// Always use the given format.
+    Fixed(ImageFormat),
+}
+
+/// Settings for the generation of a thumbnail, see
+/// [`AttachmentConfig::generate_thumbnail()`].
+///
+/// [`AttachmentConfig::generate_thumbnail()`]: super::AttachmentConfig::generate_thumbnail
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ThumbnailSettings {
+    pub(crate) size: Option<(u32, u32)>,
+    pub(crate) format: ThumbnailFormat,
+}
+
+/// An error that happened while processing an image.
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    /// The image could not be decoded or encoded.
+    #[error(transparent)]
+    Proc(#[from] image::ImageError),
+
+    /// The format of the image could not be determined, or isn't supported.
+    #[error("the format of the image could not be determined")]
+    FormatNotSupported,
+}
+
+/// Generate a thumbnail and the metadata for the given image.
+///
+/// The image is decoded and its dimensions and [BlurHash] are computed. If it
+/// is bigger than the requested size, it is scaled down, preserving its
+/// aspect ratio, and encoded in the requested format.
+///
+/// # Arguments
+///
+/// * `content_type` - The type of the image. If it doesn't map to a known image
+///   format, the format is guessed from the data.
+///
+/// * `data` - The raw bytes of the image.
+///
+/// * `size` - The maximum `(width, height)` of the thumbnail, in pixels.
+///   Defaults to 800x600.
+///
+/// * `format` - The format to encode the thumbnail in.
+///
+/// Returns the thumbnail, or `None` if the image already fits in the
+/// requested size, and the metadata of the original image.
+///
+/// [BlurHash]: https://blurha.sh/
+pub fn generate_image_thumbnail(
+    content_type: &Mime,
+    data: &[u8],
+    size: Option<(u32, u32)>,
+    format: ThumbnailFormat,
+) -> Result<(Option<Thumbnail>, BaseImageInfo), ImageError> {
+    let image_format = image_format(content_type, data)?;
+    let image = image::load_from_memory_with_format(data, image_format)?;
+
+    let (width, height) = image.dimensions();
+    let (max_width, max_height) = size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+
+    let thumbnail = if width > max_width || height > max_height {
+        let thumbnail_format = match format {
+            ThumbnailFormat::Original => image_format,
+            ThumbnailFormat::Fixed(format) => format,
+        };
+
+        Some(encode_thumbnail(image.thumbnail(max_width, max_height), thumbnail_format)?)
+    } else {
+        None
+    };
+
+    let info = BaseImageInfo {
+        width: Some(width.into()),
+        height: Some(height.into()),
+        size: UInt::new(data.len() as u64),
+        blurhash: Some(image_blurhash(&image)),
+        is_animated: None,
+    };
+
+    Ok((thumbnail, info))
+}
+
+/// Compute the [BlurHash] of the given image.
+///
+/// # Arguments
+///
+/// * `content_type` - The type of the image. If it doesn't map to a known image
+///   format, the format is guessed from the data.
+///
+/// * `data` - The raw bytes of the image.
+///
+/// [BlurHash]: https://blurha.sh/
+pub fn generate_blurhash(content_type: &Mime, data: &[u8]) -> Result<String, ImageError> {
+    let image_format = image_format(content_type, data)?;
+    let image = image::load_from_memory_with_format(data, image_format)?;
+
+    Ok(image_blurhash(&image))
+}
+
+/// The format to transcode an image to before uploading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscodeFormat {
+    /// JPEG, with the given quality between 1 and 100.
+    Jpeg {
+        /// The quality of the encoded image, between 1 and 100.
+        quality: u8,
+    },
+    /// Lossless WebP.
+    WebP,
+}
+
+impl TranscodeFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Jpeg { .. } => ImageFormat::Jpeg,
+            Self::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+/// Settings for the processing of an image before it is uploaded.
+///
+/// An image is only re-encoded if it needs to be downscaled or converted to
+/// another format. A re-encoded image never keeps the metadata of the original
+/// image.
+#[derive(Debug, Clone, Default)]
+pub struct ImageTranscoding {
+    /// The maximum `(width, height)` of the image, in pixels.
+    ///
+    /// Bigger images are downscaled, preserving their aspect ratio.
+    pub max_dimensions: Option<(u32, u32)>,
+
+    /// The format to re-encode the image to.
+    ///
+    /// If this is `None`, the image is only re-encoded when it is downscaled,
+    /// in its original format.
+    pub format: Option<TranscodeFormat>,
+
+    /// Whether to remove the location from the EXIF metadata of the image.
+    ///
+    /// The location is removed without re-encoding the image for JPEG, PNG
+    /// and WebP images.
+    pub strip_location: bool,
+}
+
+impl ImageTranscoding {
+    /// Create new empty `ImageTranscoding` settings, that don't modify the
+    /// image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum `(width, height)` of the image, in pixels.
+    #[must_use]
+    pub fn max_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.max_dimensions = Some((width, height));
+        self
+    }
+
+    /// Set the format to re-encode the image to.
+    #[must_use]
+    pub fn format(mut self, format: TranscodeFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Set whether to remove the location from the EXIF metadata of the image.
+    #[must_use]
+    pub fn strip_location(mut self, strip_location: bool) -> Self {
+        self.strip_location = strip_location;
+        self
+    }
+}
+
+/// The transformations that were applied to an image by
+/// [`transcode_image()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImageTranscodingReport {
+    /// The original `(width, height)` of the image and the new one, if it was
+    /// downscaled.
+    pub downscaled: Option<((u32, u32), (u32, u32))>,
+
+    /// The format the image was re-encoded to, if it was re-encoded.
+    pub reencoded: Option<ImageFormat>,
+
+    /// Whether the location was removed from the metadata of the image.
+    ///
+    /// This is also `true` when the location was requested to be removed and
+    /// the image was re-encoded, since all the metadata is lost in that case.
+    pub location_stripped: bool,
+}
+
+impl ImageTranscodingReport {
+    /// Whether the image was modified.
+    pub fn is_modified(&self) -> bool {
+        self.downscaled.is_some() || self.reencoded.is_some() || self.location_stripped
+    }
+}
+
+/// An image processed by [`transcode_image()`].
+#[derive(Debug)]
+pub struct TranscodedImage {
+    /// The raw bytes of the image.
+    pub data: Vec<u8>,
+    /// The type of the image.
+    pub content_type: Mime,
+    /// The transformations that were applied to the image.
+    pub report: ImageTranscodingReport,
+}
+
+/// Process an image before uploading it.
+///
+/// Depending on the settings, the image is downscaled, re-encoded in another
+/// format and its location is removed from its metadata. The orientation from
+/// the metadata is applied to the pixels when the image is re-encoded, so it
+/// is displayed the same way.
+///
+/// # Arguments
+///
+/// * `content_type` - The type of the image. If it doesn't map to a known image
+///   format, the format is guessed from the data. If the format is not
+///   supported, the image is returned unchanged.
+///
+/// * `data` - The raw bytes of the image.
+///
+/// * `settings` - The transformations to apply to the image.
+pub fn transcode_image(
+    content_type: &Mime,
+    mut data: Vec<u8>,
+    settings: &ImageTranscoding,
+) -> Result<TranscodedImage, ImageError> {
+    let mut report = ImageTranscodingReport::default();
+
+    let image_format = match image_format(content_type, &data) {
+        Ok(format) if format.reading_enabled() && format.writing_enabled() => format,
+        // The image can't be processed, upload it as is.
+        _ => return Ok(TranscodedImage { data, content_type: content_type.clone(), report }),
+    };
+
+    if settings.strip_location {
+        report.location_stripped = strip_exif_location(image_format, &mut data);
+    }
+
+    if settings.max_dimensions.is_none()
+        && settings.format.is_none_or(|format| format.image_format() == image_format)
+    {
+        // There is nothing else to do.
+        return Ok(TranscodedImage { data, content_type: content_type.clone(), report });
+    }
+
+    let mut decoder = ImageReader::with_format(Cursor::new(&data), image_format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let dimensions = image.dimensions();
+    let needs_downscale = settings.max_dimensions.is_some_and(|(max_width, max_height)| {
+        dimensions.0 > max_width || dimensions.1 > max_height
+    });
+    let needs_conversion =
+        settings.format.is_some_and(|format| format.image_format() != image_format);
+
+    if !needs_downscale && !needs_conversion {
+        return Ok(TranscodedImage { data, content_type: content_type.clone(), report });
+    }
+
+    if let Some((max_width, max_height)) = settings.max_dimensions.filter(|_| needs_downscale) {
+        image = image.resize(max_width, max_height, FilterType::Lanczos3);
+        report.downscaled = Some((dimensions, image.dimensions()));
+    }
+
+    let format = match (settings.format, image_format) {
+        (Some(format), _) => format,
+        (None, ImageFormat::Jpeg) => TranscodeFormat::Jpeg { quality: DEFAULT_JPEG_QUALITY },
+        (None, ImageFormat::WebP) => TranscodeFormat::WebP,
+        (None, _) => {
+            // Keep the original format.
+            let thumbnail = encode_thumbnail(image, image_format)?;
+            report.reencoded = Some(image_format);
+            report.location_stripped |= settings.strip_location;
+
+            return Ok(TranscodedImage {
+                data: thumbnail.data,
+                content_type: thumbnail.content_type,
+                report,
+            });
+        }
+    };
+
+    let mut data = Vec::new();
+    match format {
+        TranscodeFormat::Jpeg { quality } => {
+            let encoder = JpegEncoder::new_with_quality(&mut data, quality.clamp(1, 100));
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        TranscodeFormat::WebP => {
+            let encoder = WebPEncoder::new_lossless(&mut data);
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            image.write_with_encoder(encoder)?;
+        }
+    }
+
+    let image_format = format.image_format();
+    report.reencoded = Some(image_format);
+    report.location_stripped |= settings.strip_location;
+
+    Ok(TranscodedImage {
+        data,
+        content_type: image_format.to_mime_type().parse().unwrap_or(mime::APPLICATION_OCTET_STREAM),
+        report,
+    })
+}
+
+/// Get the format of the image from its content type, or from its data.
+fn image_format(content_type: &Mime, data: &[u8]) -> Result<ImageFormat, ImageError> {
+    ImageFormat::from_mime_type(content_type.essence_str())
+        .or_else(|| image::guess_format(data).ok())
+        .ok_or(ImageError::FormatNotSupported)
+}
+
+/// Encode the given image as a [`Thumbnail`].
+fn encode_thumbnail(image: DynamicImage, format: ImageFormat) -> Result<Thumbnail, ImageError> {
+    // Not all encoders support an alpha channel or high bit depths, so only keep
+    // what the format can represent.
+    let image = match format {
+        ImageFormat::Jpeg | ImageFormat::Bmp | ImageFormat::Pnm => {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        }
+        _ => image,
+    };
+
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format)?;
+
+    let content_type = format.to_mime_type().parse().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+
+    Ok(Thumbnail {
+        width: image.width().into(),
+        height: image.height().into(),
+        size: UInt::new(data.len() as u64).unwrap_or(UInt::MAX),
+        data,
+        content_type,
+    })
+}
+
+/// Compute the BlurHash of a decoded image.
+fn image_blurhash(image: &DynamicImage) -> String {
+    let small = image.thumbnail(BLURHASH_SOURCE_SIZE, BLURHASH_SOURCE_SIZE).to_rgb8();
+    encode_blurhash(BLURHASH_COMPONENTS, &small)
+}
+
+/// Encode the BlurHash of the given image, with the given number of
+/// `(horizontal, vertical)` components.
+///
+/// This is a port of the [reference implementation].
+///
+/// [reference implementation]: https://github.com/woltapp/blurhash/blob/master/Algorithm.md
+fn encode_blurhash((components_x, components_y): (u32, u32), image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+
+    let mut factors = Vec::with_capacity((components_x * components_y) as usize);
+    for j in 0..components_y {
+        for i in 0..components_x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f32; 3];
+
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let basis = normalisation
+                    * (PI * i as f32 * x as f32 / width as f32).cos()
+                    * (PI * j as f32 * y as f32 / height as f32).cos();
+
+                for (channel, value) in factor.iter_mut().zip(pixel.0) {
+                    *channel += basis * srgb_to_linear(value);
+                }
+            }
+
+            let scale = 1.0 / (width * height) as f32;
+            factors.push(factor.map(|channel| channel * scale));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().expect("there is always at least one component");
+
+    let mut blurhash = String::with_capacity(4 + 2 * factors.len());
+
+    let size_flag = (components_x - 1) + (components_y - 1) * 9;
+    encode_base83(size_flag, 1, &mut blurhash);
+
+    let maximum_value = if ac.is_empty() {
+        encode_base83(0, 1, &mut blurhash);
+        1.0
+    } else {
+        let actual_maximum = ac.iter().flatten().fold(0.0f32, |max, value| max.max(value.abs()));
+        let quantised_maximum = (actual_maximum * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        encode_base83(quantised_maximum, 1, &mut blurhash);
+        (quantised_maximum + 1) as f32 / 166.0
+    };
+
+    let [r, g, b] = dc.map(linear_to_srgb);
+    encode_base83((r << 16) + (g << 8) + b, 4, &mut blurhash);
+
+    for factor in ac {
+        let [r, g, b] = factor.map(|value| {
+            (sign_pow(value / maximum_value, 0.5) * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        encode_base83(r * 19 * 19 + g * 19 + b, 2, &mut blurhash);
+    }
+
+    blurhash
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u32 {
+    let value = value.clamp(0.0, 1.0);
+
+    if value <= 0.003_130_8 {
+        (value * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * value.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn sign_pow(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+fn encode_base83(value: u32, length: u32, output: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        output.push(BASE83_CHARACTERS[digit as usize] as char);
+    }
+}
+
+/// Remove the GPS information from the EXIF metadata of an image, in place.
+///
+/// Returns whether GPS information was found and removed. Only JPEG, PNG and
+/// WebP images are supported.
+fn strip_exif_location(format: ImageFormat, data: &mut [u8]) -> bool {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg_exif_location(data),
+        ImageFormat::Png => strip_png_exif_location(data),
+        ImageFormat::WebP => strip_webp_exif_location(data),
+        _ => false,
+    }
+}
+
+/// Remove the GPS information from the given EXIF metadata, which might start
+/// with the `Exif\0\0` identifier, in place.
+///
+/// Returns whether GPS information was found and removed.
+fn strip_exif_payload_location(payload: &mut [u8]) -> bool {
+    let tiff = if payload.starts_with(b"Exif\0\0") { &mut payload[6..] } else { payload };
+    Tiff::new(tiff).and_then(|mut tiff| tiff.strip_gps_info()).unwrap_or(false)
+}
+
+/// Remove the GPS information from the EXIF metadata of a JPEG image, in
+/// place.
+///
+/// Returns whether GPS information was found and removed.
+fn strip_jpeg_exif_location(data: &mut [u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+
+    let mut pos = 2;
+
+    // Look for the APP1 segment containing the EXIF metadata, which must appear
+    // before the image data.
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+
+        match marker {
+            // Padding.
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Markers without a payload.
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            // Start of scan or end of image.
+            0xDA | 0xD9 => return false,
+            _ => {}
+        }
+
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment_end = pos + 2 + len;
+
+        if len < 2 || segment_end > data.len() {
+            return false;
+        }
+
+        let segment = &mut data[pos + 4..segment_end];
+
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return strip_exif_payload_location(segment);
+        }
+
+        pos = segment_end;
+    }
+
+    false
+}
+
+/// Remove the GPS information from the `eXIf` chunk of a PNG image, in place.
+///
+/// Returns whether GPS information was found and removed.
+fn strip_png_exif_location(data: &mut [u8]) -> bool {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    if !data.starts_with(SIGNATURE) {
+        return false;
+    }
+
+    let mut pos = SIGNATURE.len();
+
+    // Each chunk has a length, a type, the data and a CRC.
+    while pos + 12 <= data.len() {
+        let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let Some(data_end) = usize::try_from(len).ok().and_then(|len| (pos + 8).checked_add(len))
+        else {
+            return false;
+        };
+        let chunk_end = data_end + 4;
+
+        if chunk_end > data.len() {
+            return false;
+        }
+
+        let chunk_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+
+        match &chunk_type {
+            b"eXIf" => {
+                if !strip_exif_payload_location(&mut data[pos + 8..data_end]) {
+                    return false;
+                }
+
+                // The CRC covers the type and the data of the chunk.
+                let crc = crc32(&data[pos + 4..data_end]);
+                data[data_end..chunk_end].copy_from_slice(&crc.to_be_bytes());
+
+                return true;
+            }
+            b"IEND" => return false,
+            _ => {}
+        }
+
+        pos = chunk_end;
+    }
+
+    false
+}
+
+/// Compute the CRC-32 checksum of the chunks of PNG images.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+
+    for &byte in data {
+        crc ^= u32::from(byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// Remove the GPS information from the `EXIF` chunk of a WebP image, in place.
+///
+/// Returns whether GPS information was found and removed.
+fn strip_webp_exif_location(data: &mut [u8]) -> bool {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return false;
+    }
+
+    let mut pos = 12;
+
+    // Each chunk has a type, a length and the data, padded to an even length.
+    while pos + 8 <= data.len() {
+        let len = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]);
+        let Some(chunk_end) = usize::try_from(len)
+            .ok()
+            .and_then(|len| (pos + 8).checked_add(len))
+            .filter(|chunk_end| *chunk_end <= data.len())
+        else {
+            return false;
+        };
+
+        if &data[pos..pos + 4] == b"EXIF" {
+            return strip_exif_payload_location(&mut data[pos + 8..chunk_end]);
+        }
+
+        pos = chunk_end + chunk_end % 2;
+    }
+
+    false
+}
+
+/// Minimal access to the TIFF structure of EXIF metadata.
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Self> {
+        let big_endian = match data.get(..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+
+        Some(Self { data, big_endian })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn set_u16_at(&mut self, offset: usize, value: u16) -> Option<()> {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.data.get_mut(offset..offset + 2)?.copy_from_slice(&bytes);
+        Some(())
+    }
+
+    fn zero(&mut self, range: std::ops::Range<usize>) -> Option<()> {
+        self.data.get_mut(range)?.fill(0);
+        Some(())
+    }
+
+    /// Remove the GPS information from the first IFD.
+    ///
+    /// The values of the GPS IFD are erased and the pointer to it is removed
+    /// from the first IFD.
+    fn strip_gps_info(&mut self) -> Option<bool> {
+        let ifd0 = self.u32_at(4)? as usize;
+        let count = self.u16_at(ifd0)? as usize;
+
+        for index in 0..count {
+            let entry = ifd0 + 2 + index * 12;
+
+            if self.u16_at(entry)? != EXIF_GPS_INFO_TAG {
+                continue;
+            }
+
+            let gps_ifd = self.u32_at(entry + 8)? as usize;
+            self.erase_ifd(gps_ifd)?;
+
+            // Remove the entry by moving the following entries and the offset of the
+            // next IFD over it.
+            let ifd0_end = ifd0 + 2 + count * 12 + 4;
+            if ifd0_end > self.data.len() {
+                return None;
+            }
+
+            self.data.copy_within(entry + 12..ifd0_end, entry);
+            self.zero(ifd0_end - 12..ifd0_end)?;
+            self.set_u16_at(ifd0, (count - 1) as u16)?;
+
+            return Some(true);
+        }
+
+        Some(false)
+    }
+
+    /// Erase the entries of the IFD at the given offset, and their values.
+    fn erase_ifd(&mut self, ifd: usize) -> Option<()> {
+        let count = self.u16_at(ifd)? as usize;
+
+        for index in 0..count {
+            let entry = ifd + 2 + index * 12;
+
+            let value_size = match self.u16_at(entry + 2)? {
+                // BYTE, ASCII, SBYTE, UNDEFINED.
+                1 | 2 | 6 | 7 => 1,
+                // SHORT, SSHORT.
+                3 | 8 => 2,
+                // LONG, SLONG, FLOAT.
+                4 | 9 | 11 => 4,
+                // RATIONAL, SRATIONAL, DOUBLE.
+                5 | 10 | 12 => 8,
+                _ => 0,
+            };
+            let size = value_size * self.u32_at(entry + 4)? as usize;
+
+            // Values that don't fit in the entry are stored at an offset.
+            if size > 4 {
+                let offset = self.u32_at(entry + 8)? as usize;
+                self.zero(offset..offset + size)?;
+            }
+        }
+
+        self.zero(ifd..ifd + 2 + count * 12)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use assert_matches2::assert_let;
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+    use ruma::uint;
+
+    use super::{
+        crc32, encode_blurhash, generate_blurhash, generate_image_thumbnail,
+        strip_jpeg_exif_location, strip_png_exif_location, strip_webp_exif_location,
+        transcode_image, ImageTranscoding, ThumbnailFormat, TranscodeFormat,
+    };
+    use crate::attachment::{AttachmentConfig, AttachmentInfo, BaseImageInfo};
+
+    fn bmp_image(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+        });
+
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Bmp)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_encode_blurhash_solid_color() {
+        let black = RgbImage::new(8, 8);
+        assert_eq!(encode_blurhash((4, 3), &black), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+
+        let white = RgbImage::from_pixel(8, 8, Rgb([255, 255, 255]));
+        assert_eq!(encode_blurhash((1, 1), &white), "00TSUA");
+    }
+
+    #[test]
+    fn test_generate_image_thumbnail() {
+        let data = bmp_image(1600, 800);
+
+        let (thumbnail, info) = generate_image_thumbnail(
+            &"image/bmp".parse().unwrap(),
+            &data,
+            None,
+            Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(info.width, Some(uint!(1600)));
+        assert_eq!(info.height, Some(uint!(800)));
+        assert_eq!(info.size, Some(data.len().try_into().unwrap()));
+        assert_eq!(info.blurhash.as_ref().map(|hash| hash.len()), Some(28));
+
+        // The thumbnail fits in the default size and keeps the aspect ratio.
+        let thumbnail = thumbnail.unwrap();
+        assert_eq!(thumbnail.width, uint!(800));
+        assert_eq!(thumbnail.height, uint!(400));
+        assert_eq!(thumbnail.content_type, "image/bmp");
+        assert_eq!(thumbnail.size, thumbnail.data.len().try_into().unwrap());
+
+        // The thumbnail can be decoded again.
+        let blurhash = generate_blurhash(&thumbnail.content_type, &thumbnail.data).unwrap();
+        assert_eq!(blurhash.len(), 28);
+    }
+
+    #[test]
+    fn test_generate_image_thumbnail_small_image() {
+        let data = bmp_image(100, 50);
+
+        // The content type is unknown, so the format is guessed from the data.
+        let (thumbnail, info) = generate_image_thumbnail(
+            &mime::APPLICATION_OCTET_STREAM,
+            &data,
+            Some((200, 200)),
+            ThumbnailFormat::Original,
+        )
+        .unwrap();
+
+        assert!(thumbnail.is_none());
+        assert_eq!(info.width, Some(uint!(100)));
+        assert_eq!(info.height, Some(uint!(50)));
+        assert!(info.blurhash.is_some());
+    }
+
+    #[test]
+    fn test_generate_image_thumbnail_invalid_data() {
+        assert_let!(
+            Err(_) = generate_image_thumbnail(
+                &"image/bmp".parse().unwrap(),
+                b"not an image",
+                None,
+                ThumbnailFormat::Original
+            )
+        );
+    }
+
+    #[test]
+    fn test_attachment_config_generate_thumbnail() {
+        let data = bmp_image(1600, 800);
+        let content_type = "image/bmp".parse().unwrap();
+
+        // The provided info takes precedence over the generated one.
+        let mut config = AttachmentConfig::new()
+            .info(AttachmentInfo::Image(BaseImageInfo {
+                blurhash: Some("custom".to_owned()),
+                ..Default::default()
+            }))
+            .generate_thumbnail(Some((400, 400)), ThumbnailFormat::Original);
+        config.generate_thumbnail_and_info(&content_type, &data);
+
+        let thumbnail = config.thumbnail.unwrap();
+        assert_eq!(thumbnail.width, uint!(400));
+        assert_eq!(thumbnail.height, uint!(200));
+
+        assert_let!(Some(AttachmentInfo::Image(info)) = config.info);
+        assert_eq!(info.width, Some(uint!(1600)));
+        assert_eq!(info.height, Some(uint!(800)));
+        assert_eq!(info.blurhash.as_deref(), Some("custom"));
+
+        // Without the setting, nothing is generated.
+        let mut config = AttachmentConfig::new();
+        config.generate_thumbnail_and_info(&content_type, &data);
+        assert!(config.thumbnail.is_none());
+        assert!(config.info.is_none());
+    }
+
+    /// EXIF metadata containing an orientation and a GPS latitude, without the
+    /// `Exif\0\0` identifier.
+    fn exif_with_location() -> Vec<u8> {
+        let mut tiff = Vec::new();
+        // Header, with the first IFD at offset 8.
+        tiff.extend_from_slice(b"II");
+        tiff.extend_from_slice(&42u16.to_le_bytes());
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        // First IFD: orientation and pointer to the GPS IFD at offset 38.
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        tiff.extend_from_slice(&[0x25, 0x88, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        // GPS IFD: latitude, with its value at offset 56.
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&[0x02, 0x00, 5, 0, 3, 0, 0, 0, 56, 0, 0, 0]);
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        // The 3 rationals of the latitude.
+        tiff.extend_from_slice(&[0x5A; 24]);
+
+        tiff
+    }
+
+    /// A JPEG image with EXIF metadata containing an orientation and a GPS
+    /// latitude.
+    fn jpeg_image_with_location() -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(16, 16))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+
+        let tiff = exif_with_location();
+        let mut app1 = vec![0xFF, 0xE1];
+        app1.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        app1.extend_from_slice(b"Exif\0\0");
+        app1.extend_from_slice(&tiff);
+
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    #[test]
+    fn test_strip_jpeg_exif_location() {
+        let mut data = jpeg_image_with_location();
+        assert!(data.windows(24).any(|window| window == [0x5A; 24]));
+
+        assert!(strip_jpeg_exif_location(&mut data));
+
+        // The location is gone, the orientation is still there.
+        assert!(!data.windows(4).any(|window| window == [0x5A; 4]));
+        assert!(data.windows(2).any(|window| window == [0x12, 0x01]));
+        assert!(!data.windows(2).any(|window| window == [0x25, 0x88]));
+
+        // There is nothing to strip anymore.
+        assert!(!strip_jpeg_exif_location(&mut data));
+
+        // The image is still valid.
+        let image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg).unwrap();
+        assert_eq!(image.width(), 16);
+    }
+
+    #[test]
+    fn test_strip_png_exif_location() {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(16, 16))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        // Insert the eXIf chunk after the IHDR chunk.
+        let tiff = exif_with_location();
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&(tiff.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(b"eXIf");
+        chunk.extend_from_slice(&tiff);
+        chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+        data.splice(33..33, chunk);
+
+        assert!(strip_png_exif_location(&mut data));
+        assert!(!data.windows(4).any(|window| window == [0x5A; 4]));
+        assert!(!strip_png_exif_location(&mut data));
+
+        // The image is still valid, with a correct CRC.
+        let image = image::load_from_memory_with_format(&data, ImageFormat::Png).unwrap();
+        assert_eq!(image.width(), 16);
+    }
+
+    #[test]
+    fn test_strip_webp_exif_location() {
+        let mut tiff = b"Exif\0\0".to_vec();
+        tiff.extend_from_slice(&exif_with_location());
+
+        let mut data = b"RIFF\0\0\0\0WEBP".to_vec();
+        // A padded chunk before the EXIF chunk.
+        data.extend_from_slice(b"ICCP");
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(b"EXIF");
+        data.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        data.extend_from_slice(&tiff);
+
+        assert!(strip_webp_exif_location(&mut data));
+        assert!(!data.windows(4).any(|window| window == [0x5A; 4]));
+        assert!(!strip_webp_exif_location(&mut data));
+    }
+
+    #[test]
+    fn test_transcode_image_strip_location_without_reencoding() {
+        let data = jpeg_image_with_location();
+        let len = data.len();
+
+        let transcoded =
+            transcode_image(&mime::IMAGE_JPEG, data, &ImageTranscoding::new().strip_location(true))
+                .unwrap();
+
+        assert!(transcoded.report.location_stripped);
+        assert_eq!(transcoded.report.reencoded, None);
+        assert_eq!(transcoded.report.downscaled, None);
+        assert_eq!(transcoded.data.len(), len);
+        assert_eq!(transcoded.content_type, mime::IMAGE_JPEG);
+    }
+
+    #[test]
+    fn test_transcode_image_downscale_and_convert() {
+        let data = bmp_image(1600, 800);
+
+        let transcoded = transcode_image(
+            &"image/bmp".parse().unwrap(),
+            data,
+            &ImageTranscoding::new()
+                .max_dimensions(400, 400)
+                .format(TranscodeFormat::Jpeg { quality: 80 }),
+        )
+        .unwrap();
+
+        assert_eq!(transcoded.report.downscaled, Some(((1600, 800), (400, 200))));
+        assert_eq!(transcoded.report.reencoded, Some(ImageFormat::Jpeg));
+        assert!(!transcoded.report.location_stripped);
+        assert_eq!(transcoded.content_type, mime::IMAGE_JPEG);
+
+        let image =
+            image::load_from_memory_with_format(&transcoded.data, ImageFormat::Jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (400, 200));
+
+        // A small image is only converted.
+        let transcoded = transcode_image(
+            &"image/bmp".parse().unwrap(),
+            bmp_image(100, 50),
+            &ImageTranscoding::new().max_dimensions(400, 400).format(TranscodeFormat::WebP),
+        )
+        .unwrap();
+
+        assert_eq!(transcoded.report.downscaled, None);
+        assert_eq!(transcoded.report.reencoded, Some(ImageFormat::WebP));
+        assert_eq!(transcoded.content_type, "image/webp");
+    }
+
+    #[test]
+    fn test_transcode_image_unchanged() {
+        let data = bmp_image(100, 50);
+
+        // The image is small enough and in the right format.
+        let transcoded = transcode_image(
+            &"image/bmp".parse().unwrap(),
+            data.clone(),
+            &ImageTranscoding::new().max_dimensions(400, 400),
+        )
+        .unwrap();
+        assert!(!transcoded.report.is_modified());
+        assert_eq!(transcoded.data, data);
+
+        // The format is not supported.
+        let transcoded = transcode_image(
+            &"image/svg+xml".parse().unwrap(),
+            b"<svg></svg>".to_vec(),
+            &ImageTranscoding::new().format(TranscodeFormat::WebP),
+        )
+        .unwrap();
+        assert!(!transcoded.report.is_modified());
+        assert_eq!(transcoded.data, b"<svg></svg>");
+    }
+}
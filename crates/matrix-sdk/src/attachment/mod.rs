@@ -14,8 +14,12 @@
 
 //! Types and traits for attachments.
 
+#[cfg(feature = "image-proc")]
+mod image_proc;
+
 use std::time::Duration;
 
+use mime::Mime;
 use ruma::{
     assign,
     events::{
@@ -27,8 +31,17 @@ use ruma::{
     },
     OwnedTransactionId, TransactionId, UInt,
 };
+#[cfg(feature = "image-proc")]
+use tracing::warn;
 
-use crate::room::reply::Reply;
+#[cfg(feature = "image-proc")]
+use self::image_proc::ThumbnailSettings;
+#[cfg(feature = "image-proc")]
+pub use self::image_proc::{
+    generate_blurhash, generate_image_thumbnail, transcode_image, ImageError, ImageTranscoding,
+    ImageTranscodingReport, ThumbnailFormat, TranscodeFormat, TranscodedImage,
+};
+use crate::{media::UploadConfig, room::reply::Reply};
 
 /// Base metadata about an image.
 #[derive(Debug, Clone, Default)]
@@ -159,7 +172,7 @@ pub struct Thumbnail {
     /// The raw bytes of the thumbnail.
     pub data: Vec<u8>,
     /// The type of the thumbnail, this will be used as the content-type header.
-    pub content_type: mime::Mime,
+    pub content_type: Mime,
     /// The height of the thumbnail in pixels.
     pub height: UInt,
     /// The width of the thumbnail in pixels.
@@ -170,7 +183,7 @@ pub struct Thumbnail {
 
 impl Thumbnail {
     /// Convert this `Thumbnail` into a `(data, content_type, info)` tuple.
-    pub fn into_parts(self) -> (Vec<u8>, mime::Mime, Box<ThumbnailInfo>) {
+    pub fn into_parts(self) -> (Vec<u8>, Mime, Box<ThumbnailInfo>) {
         let thumbnail_info = assign!(ThumbnailInfo::new(), {
             height: Some(self.height),
             width: Some(self.width),
@@ -191,6 +204,9 @@ pub struct AttachmentConfig {
     pub(crate) formatted_caption: Option<FormattedBody>,
     pub(crate) mentions: Option<Mentions>,
     pub(crate) reply: Option<Reply>,
+    pub(crate) upload_config: Option<UploadConfig>,
+    #[cfg(feature = "image-proc")]
+    pub(crate) generate_thumbnail: Option<ThumbnailSettings>,
 }
 
 impl AttachmentConfig {
@@ -236,6 +252,30 @@ impl AttachmentConfig {
         self
     }
 
+    /// Send the attachment as a voice message, as defined in [MSC3245].
+    ///
+    /// This replaces any metadata set with [`AttachmentConfig::info()`]. The
+    /// `content_type` of the attachment must be an audio type for this to have
+    /// any effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration of the voice message.
+    ///
+    /// * `waveform` - The amplitudes of the voice message, between 0 and 1024.
+    ///   Bigger values are clamped to 1024. Clients usually send between 30 and
+    ///   120 values.
+    ///
+    /// [MSC3245]: https://github.com/matrix-org/matrix-spec-proposals/pull/3245
+    #[must_use]
+    pub fn voice(mut self, duration: Duration, waveform: Vec<u16>) -> Self {
+        self.info = Some(AttachmentInfo::Voice {
+            audio_info: BaseAudioInfo { duration: Some(duration), size: None },
+            waveform: Some(waveform),
+        });
+        self
+    }
+
     /// Set the optional caption
     ///
     /// # Arguments
@@ -275,4 +315,153 @@ impl AttachmentConfig {
         self.reply = reply;
         self
     }
+
+    /// Process the file with [`Media::prepare_upload()`] before sending it.
+    ///
+    /// The file is transcoded and its size is checked according to the given
+    /// settings. If the image is downscaled, its dimensions and size in the
+    /// metadata provided with [`AttachmentConfig::info()`] are updated. The
+    /// thumbnail is generated from the processed file.
+    ///
+    /// [`Media::prepare_upload()`]: crate::media::Media::prepare_upload
+    #[must_use]
+    pub fn upload_config(mut self, config: UploadConfig) -> Self {
+        self.upload_config = Some(config);
+        self
+    }
+
+    /// Generate the thumbnail and the metadata of the attachment before
+    /// sending it.
+    ///
+    /// For images, a thumbnail is generated if none was provided with
+    /// [`AttachmentConfig::thumbnail()`] and the image is bigger than the
+    /// requested size, and the dimensions, size and BlurHash of the image are
+    /// added to the metadata, unless they were already provided with
+    /// [`AttachmentConfig::info()`].
+    ///
+    /// For videos, the BlurHash is computed from the provided thumbnail, if
+    /// any, because the SDK can't decode videos.
+    ///
+    /// The attachment is decoded on a thread where blocking is acceptable,
+    /// before it is sent. If the processing fails, for example because the
+    /// image format is not enabled on the `image` crate, a warning is
+    /// logged and the attachment is sent as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum `(width, height)` of the thumbnail, in pixels.
+    ///   Defaults to 800x600.
+    ///
+    /// * `format` - The format to encode the thumbnail in.
+    #[cfg(feature = "image-proc")]
+    #[must_use]
+    pub fn generate_thumbnail(mut self, size: Option<(u32, u32)>, format: ThumbnailFormat) -> Self {
+        self.generate_thumbnail = Some(ThumbnailSettings { size, format });
+        self
+    }
+
+    /// Generate the thumbnail and the metadata of the attachment, if it was
+    /// requested with [`AttachmentConfig::generate_thumbnail()`], without
+    /// blocking the async runtime.
+    ///
+    /// Returns the updated config, and the data of the attachment.
+    #[cfg(feature = "image-proc")]
+    pub(crate) async fn with_generated_thumbnail_and_info(
+        mut self,
+        content_type: Mime,
+        data: Vec<u8>,
+    ) -> (Self, Vec<u8>) {
+        if self.generate_thumbnail.is_none() {
+            return (self, data);
+        }
+
+        let generate = move || {
+            self.generate_thumbnail_and_info(&content_type, &data);
+            (self, data)
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let task = tokio::task::spawn_blocking(generate);
+            task.await.expect("Task join error")
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        generate()
+    }
+
+    /// Generate the thumbnail and the metadata of the attachment, if it was
+    /// requested with [`AttachmentConfig::generate_thumbnail()`].
+    #[cfg(feature = "image-proc")]
+    fn generate_thumbnail_and_info(&mut self, content_type: &Mime, data: &[u8]) {
+        let Some(settings) = self.generate_thumbnail.take() else {
+            return;
+        };
+
+        match content_type.type_() {
+            mime::IMAGE => {
+                let (thumbnail, generated) = match generate_image_thumbnail(
+                    content_type,
+                    data,
+                    settings.size,
+                    settings.format,
+                ) {
+                    Ok(res) => res,
+                    Err(error) => {
+                        warn!(%content_type, "Failed to generate the image thumbnail: {error}");
+                        return;
+                    }
+                };
+
+                if self.thumbnail.is_none() {
+                    self.thumbnail = thumbnail;
+                }
+
+                match &mut self.info {
+                    Some(AttachmentInfo::Image(info)) => {
+                        info.height = info.height.or(generated.height);
+                        info.width = info.width.or(generated.width);
+                        info.size = info.size.or(generated.size);
+                        info.blurhash = info.blurhash.take().or(generated.blurhash);
+                    }
+                    None => self.info = Some(AttachmentInfo::Image(generated)),
+                    // The info doesn't match the content type, it will be ignored anyway.
+                    Some(_) => {}
+                }
+            }
+
+            mime::VIDEO => {
+                let Some(thumbnail) = &self.thumbnail else {
+                    return;
+                };
+
+                if let Some(AttachmentInfo::Video(BaseVideoInfo { blurhash: Some(_), .. })) =
+                    &self.info
+                {
+                    return;
+                }
+
+                let blurhash = match generate_blurhash(&thumbnail.content_type, &thumbnail.data) {
+                    Ok(blurhash) => blurhash,
+                    Err(error) => {
+                        warn!(%content_type, "Failed to generate the video BlurHash: {error}");
+                        return;
+                    }
+                };
+
+                match &mut self.info {
+                    Some(AttachmentInfo::Video(info)) => info.blurhash = Some(blurhash),
+                    None => {
+                        self.info = Some(AttachmentInfo::Video(BaseVideoInfo {
+                            blurhash: Some(blurhash),
+                            ..Default::default()
+                        }))
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            _ => {}
+        }
+    }
 }
@@ -521,7 +521,7 @@ impl MatrixAuth {
         };
 
         let request = refresh_token::v3::Request::new(refresh_token);
-        let res = self.client.send_inner(request, None, Default::default()).await;
+        let res = self.client.send_inner(request, None, Default::default(), None).await;
 
         match res {
             Ok(res) => {
@@ -533,6 +533,7 @@ impl MatrixAuth {
                 }
 
                 self.client.auth_ctx().set_session_tokens(session_tokens);
+                self.client.auth_ctx().set_access_token_expires_in(res.expires_in_ms);
 
                 if let Some(save_session_callback) =
                     self.client.inner.auth_ctx.save_session_callback.get()
@@ -724,6 +725,7 @@ impl MatrixAuth {
             None,
         )
         .await?;
+        self.client.restore_is_guest().await?;
         debug!("Done restoring Matrix auth session");
         Ok(())
     }
@@ -749,6 +751,8 @@ impl MatrixAuth {
         )
         .await?;
 
+        self.client.auth_ctx().set_access_token_expires_in(response.expires_in);
+
         Ok(())
     }
 
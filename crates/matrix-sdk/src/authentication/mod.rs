@@ -14,9 +14,13 @@
 
 //! Types and functions related to authentication in Matrix.
 
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 use matrix_sdk_base::{locks::Mutex, SessionMeta};
+use ruma::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{broadcast, Mutex as AsyncMutex, OnceCell};
 
@@ -63,6 +67,13 @@ pub(crate) struct AuthCtx {
     /// `M_UNKNOWN_TOKEN` error is encountered.
     pub(crate) handle_refresh_tokens: bool,
 
+    /// How long before the access token expires it should be refreshed, if
+    /// tokens are refreshed automatically.
+    pub(crate) refresh_access_token_ahead_of_expiry: Option<Duration>,
+
+    /// When the current access token expires, if the server told us.
+    pub(crate) access_token_expires_at: Mutex<Option<Instant>>,
+
     /// Lock making sure we're only doing one token refresh at a time.
     pub(crate) refresh_token_lock: Arc<AsyncMutex<Result<(), RefreshTokenError>>>,
 
@@ -77,6 +88,9 @@ pub(crate) struct AuthCtx {
     /// The current session tokens.
     pub(crate) tokens: OnceCell<Mutex<SessionTokens>>,
 
+    /// Whether the current session belongs to a guest account.
+    pub(crate) is_guest: AtomicBool,
+
     /// A callback called whenever we need an absolute source of truth for the
     /// current session tokens.
     ///
@@ -105,12 +119,46 @@ impl AuthCtx {
     }
 
     /// Set the current session tokens.
+    ///
+    /// This forgets the expiration time of the previous access token, use
+    /// [`AuthCtx::set_access_token_expires_in()`] afterwards if it is known.
     pub(crate) fn set_session_tokens(&self, session_tokens: SessionTokens) {
         if let Some(tokens) = self.tokens.get() {
             *tokens.lock() = session_tokens;
         } else {
             let _ = self.tokens.set(Mutex::new(session_tokens));
         }
+
+        *self.access_token_expires_at.lock() = None;
+    }
+
+    /// Set the lifetime of the current access token, starting now.
+    pub(crate) fn set_access_token_expires_in(&self, expires_in: Option<Duration>) {
+        *self.access_token_expires_at.lock() =
+            expires_in.and_then(|expires_in| Instant::now().checked_add(expires_in));
+    }
+
+    /// Whether the current access token should be refreshed before it is
+    /// rejected by the server.
+    ///
+    /// This is only the case if the tokens are refreshed automatically, if
+    /// [`ClientBuilder::refresh_access_token_ahead_of_expiry()`] was used and
+    /// if we know when the access token expires.
+    ///
+    /// [`ClientBuilder::refresh_access_token_ahead_of_expiry()`]: crate::ClientBuilder::refresh_access_token_ahead_of_expiry
+    pub(crate) fn access_token_expires_soon(&self) -> bool {
+        if !self.handle_refresh_tokens {
+            return false;
+        }
+
+        let Some(margin) = self.refresh_access_token_ahead_of_expiry else {
+            return false;
+        };
+        let Some(expires_at) = *self.access_token_expires_at.lock() else {
+            return false;
+        };
+
+        Instant::now().checked_add(margin).is_none_or(|deadline| deadline >= expires_at)
     }
 }
 
@@ -16,6 +16,8 @@
 //!
 //! This is a Matrix extension introduced in [MSC4191](https://github.com/matrix-org/matrix-spec-proposals/pull/4191).
 
+use std::collections::BTreeSet;
+
 use ruma::{
     api::client::discovery::get_authorization_server_metadata::msc2965::AccountManagementAction,
     OwnedDeviceId,
@@ -34,6 +36,9 @@ pub enum AccountManagementActionFull {
     /// `org.matrix.profile`
     ///
     /// The user wishes to view their profile (name, avatar, contact details).
+    ///
+    /// [MSC4191] doesn't define an action to change the password, it is usually
+    /// accessible from the profile.
     Profile,
 
     /// `org.matrix.sessions_list`
@@ -106,6 +111,10 @@ impl AccountManagementActionFull {
 /// browser where the end-user will be able to access the account management
 /// capabilities of the issuer.
 ///
+/// Servers are not required to support all the actions, so
+/// [`AccountManagementUrlBuilder::is_action_supported()`] should be checked
+/// before offering an action to the user.
+///
 /// # Example
 ///
 /// ```no_run
@@ -134,13 +143,32 @@ impl AccountManagementActionFull {
 #[derive(Debug, Clone)]
 pub struct AccountManagementUrlBuilder {
     account_management_uri: Url,
+    actions_supported: BTreeSet<AccountManagementAction>,
     action: Option<AccountManagementActionFull>,
 }
 
 impl AccountManagementUrlBuilder {
-    /// Construct an [`AccountManagementUrlBuilder`] for the given URL.
-    pub(super) fn new(account_management_uri: Url) -> Self {
-        Self { account_management_uri, action: None }
+    /// Construct an [`AccountManagementUrlBuilder`] for the given URL, which
+    /// supports the given actions.
+    pub(super) fn new(
+        account_management_uri: Url,
+        actions_supported: BTreeSet<AccountManagementAction>,
+    ) -> Self {
+        Self { account_management_uri, actions_supported, action: None }
+    }
+
+    /// The actions advertised by the server as supported by the account
+    /// management URL.
+    pub fn actions_supported(&self) -> &BTreeSet<AccountManagementAction> {
+        &self.actions_supported
+    }
+
+    /// Whether the given action is supported by the account management URL.
+    ///
+    /// Unsupported actions are usually ignored by the server, which shows its
+    /// default page instead.
+    pub fn is_action_supported(&self, action: &AccountManagementActionFull) -> bool {
+        self.actions_supported.contains(&action.action_type())
     }
 
     /// Set the action that the user wishes to take.
@@ -160,11 +188,24 @@ impl AccountManagementUrlBuilder {
 
         account_management_uri
     }
+
+    /// Build the URL to present to the end user to take the given action, if
+    /// the action is supported.
+    ///
+    /// Returns `None` if the server doesn't advertise support for the action.
+    pub fn build_for_action(self, action: AccountManagementActionFull) -> Option<Url> {
+        self.is_action_supported(&action).then(|| self.action(action).build())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use ruma::owned_device_id;
+    use std::collections::BTreeSet;
+
+    use ruma::{
+        api::client::discovery::get_authorization_server_metadata::msc2965::AccountManagementAction,
+        owned_device_id,
+    };
     use url::Url;
 
     use super::{AccountManagementActionFull, AccountManagementUrlBuilder};
@@ -174,20 +215,20 @@ mod tests {
         let base_url = Url::parse("https://example.org").unwrap();
         let device_id = owned_device_id!("ABCDEFG");
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone()).build();
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new()).build();
         assert_eq!(url, base_url);
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::Profile)
             .build();
         assert_eq!(url.as_str(), "https://example.org/?action=org.matrix.profile");
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::SessionsList)
             .build();
         assert_eq!(url.as_str(), "https://example.org/?action=org.matrix.sessions_list");
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::SessionView { device_id: device_id.clone() })
             .build();
         assert_eq!(
@@ -195,7 +236,7 @@ mod tests {
             "https://example.org/?action=org.matrix.session_view&device_id=ABCDEFG"
         );
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::SessionEnd { device_id })
             .build();
         assert_eq!(
@@ -203,12 +244,12 @@ mod tests {
             "https://example.org/?action=org.matrix.session_end&device_id=ABCDEFG"
         );
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::AccountDeactivate)
             .build();
         assert_eq!(url.as_str(), "https://example.org/?action=org.matrix.account_deactivate");
 
-        let url = AccountManagementUrlBuilder::new(base_url)
+        let url = AccountManagementUrlBuilder::new(base_url, BTreeSet::new())
             .action(AccountManagementActionFull::CrossSigningReset)
             .build();
         assert_eq!(url.as_str(), "https://example.org/?action=org.matrix.cross_signing_reset");
@@ -218,12 +259,12 @@ mod tests {
     fn test_build_account_management_url_with_query() {
         let base_url = Url::parse("https://example.org/?sid=123456").unwrap();
 
-        let url = AccountManagementUrlBuilder::new(base_url.clone())
+        let url = AccountManagementUrlBuilder::new(base_url.clone(), BTreeSet::new())
             .action(AccountManagementActionFull::Profile)
             .build();
         assert_eq!(url.as_str(), "https://example.org/?sid=123456&action=org.matrix.profile");
 
-        let url = AccountManagementUrlBuilder::new(base_url)
+        let url = AccountManagementUrlBuilder::new(base_url, BTreeSet::new())
             .action(AccountManagementActionFull::SessionView {
                 device_id: owned_device_id!("ABCDEFG"),
             })
@@ -233,4 +274,24 @@ mod tests {
             "https://example.org/?sid=123456&action=org.matrix.session_view&device_id=ABCDEFG"
         );
     }
+
+    #[test]
+    fn test_build_account_management_url_for_supported_action() {
+        let base_url = Url::parse("https://example.org").unwrap();
+        let builder = AccountManagementUrlBuilder::new(
+            base_url,
+            BTreeSet::from([AccountManagementAction::SessionsList]),
+        );
+
+        assert!(builder.is_action_supported(&AccountManagementActionFull::SessionsList));
+        assert!(!builder.is_action_supported(&AccountManagementActionFull::CrossSigningReset));
+
+        let url = builder
+            .clone()
+            .build_for_action(AccountManagementActionFull::SessionsList)
+            .expect("the action should be supported");
+        assert_eq!(url.as_str(), "https://example.org/?action=org.matrix.sessions_list");
+
+        assert_eq!(builder.build_for_action(AccountManagementActionFull::CrossSigningReset), None);
+    }
 }
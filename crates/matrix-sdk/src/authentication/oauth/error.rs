@@ -14,6 +14,7 @@
 
 //! Error types used in the [`OAuth`](super::OAuth) API.
 
+use as_variant::as_variant;
 use matrix_sdk_base::deserialized_responses::PrivOwnedStr;
 use oauth2::ErrorResponseType;
 pub use oauth2::{
@@ -25,7 +26,10 @@ pub use oauth2::{
     StandardErrorResponse,
 };
 use ruma::{
-    api::client::discovery::get_authorization_server_metadata::msc2965::AuthorizationServerMetadataUrlError,
+    api::client::{
+        discovery::get_authorization_server_metadata::msc2965::AuthorizationServerMetadataUrlError,
+        uiaa::UiaaInfo,
+    },
     serde::{PartialEqAsRefStr, StringEnum},
 };
 
@@ -83,6 +87,11 @@ pub enum OAuthError {
     #[error("failed to log out: {0}")]
     Logout(#[from] OAuthTokenRevocationError),
 
+    /// An error occurred deleting the device of the current session from the
+    /// homeserver.
+    #[error("failed to delete the device: {0}")]
+    DeleteDevice(crate::HttpError),
+
     /// An error occurred caused by the cross-process locks.
     #[cfg(feature = "e2e-encryption")]
     #[error(transparent)]
@@ -97,6 +106,19 @@ pub enum OAuthError {
     SessionMismatch,
 }
 
+impl OAuthError {
+    /// If the device of the current session could not be deleted because the
+    /// homeserver requires User-Interactive Authentication, get the information
+    /// needed to perform it.
+    ///
+    /// See [`OAuth::logout_everywhere()`].
+    ///
+    /// [`OAuth::logout_everywhere()`]: super::OAuth::logout_everywhere
+    pub fn as_uiaa_response(&self) -> Option<&UiaaInfo> {
+        as_variant!(self, Self::DeleteDevice).and_then(|error| error.as_uiaa_response())
+    }
+}
+
 /// All errors that can occur when discovering the OAuth 2.0 server metadata.
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
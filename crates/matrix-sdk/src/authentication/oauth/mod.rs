@@ -185,12 +185,15 @@ use oauth2::{
 };
 pub use oauth2::{ClientId, CsrfToken};
 use ruma::{
-    api::client::discovery::{
-        get_authentication_issuer,
-        get_authorization_server_metadata::{
-            self,
-            msc2965::{AccountManagementAction, AuthorizationServerMetadata},
+    api::client::{
+        discovery::{
+            get_authentication_issuer,
+            get_authorization_server_metadata::{
+                self,
+                msc2965::{AccountManagementAction, AuthorizationServerMetadata},
+            },
         },
+        uiaa,
     },
     serde::Raw,
     DeviceId, OwnedDeviceId,
@@ -217,7 +220,7 @@ mod tests;
 #[cfg(feature = "e2e-encryption")]
 use self::cross_process::{CrossProcessRefreshLockGuard, CrossProcessRefreshManager};
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
-use self::qrcode::LoginWithQrCode;
+use self::qrcode::{LoginWithQrCode, LoginWithQrCodeReciprocate};
 pub use self::{
     account_management_url::{AccountManagementActionFull, AccountManagementUrlBuilder},
     auth_code_builder::{OAuthAuthCodeUrlBuilder, OAuthAuthorizationData},
@@ -463,6 +466,59 @@ impl OAuth {
         LoginWithQrCode::new(&self.client, data, registration_data)
     }
 
+    /// Log in a new device, from this already logged-in device, using a QR
+    /// code which is displayed on this device and scanned by the new device.
+    ///
+    /// This is the counterpart of [`OAuth::login_with_qr_code()`]: this method
+    /// creates the rendezvous channel that the new device connects to, checks
+    /// that the device ID picked by the new device is available, lets the user
+    /// approve the device authorization grant requested by the new device, and
+    /// finally sends our end-to-end encryption secrets to the new device so it
+    /// becomes a verified device.
+    ///
+    /// This requires the private cross-signing keys to be available on this
+    /// device.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use matrix_sdk::{authentication::oauth::qrcode::GrantLoginProgress, Client};
+    /// # fn read_check_code() -> u8 { unimplemented!() }
+    /// # _ = async {
+    /// # let client: Client = unimplemented!();
+    /// let oauth = client.oauth();
+    /// let login = oauth.login_with_qr_code_reciprocate();
+    /// let mut progress = login.subscribe_to_progress();
+    ///
+    /// let task = tokio::spawn(async move {
+    ///     while let Some(state) = progress.next().await {
+    ///         match state {
+    ///             GrantLoginProgress::QrCodeReady { qr_code_data } => {
+    ///                 // Render `qr_code_data.to_bytes()` as a QR code.
+    ///             }
+    ///             GrantLoginProgress::WaitingForCheckCode { check_code_sender } => {
+    ///                 // Ask the user for the code displayed on the new device.
+    ///                 check_code_sender.send(read_check_code());
+    ///             }
+    ///             GrantLoginProgress::WaitingForAuth { verification_uri } => {
+    ///                 println!("Please open {verification_uri} to approve the new device");
+    ///             }
+    ///             GrantLoginProgress::Done => break,
+    ///             _ => (),
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// login.await?;
+    /// task.abort();
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
+    pub fn login_with_qr_code_reciprocate(&self) -> LoginWithQrCodeReciprocate<'_> {
+        LoginWithQrCodeReciprocate::new(&self.client)
+    }
+
     /// Restore or register the OAuth 2.0 client for the server with the given
     /// metadata, with the given optional [`ClientRegistrationData`].
     ///
@@ -532,7 +588,7 @@ impl OAuth {
         &self,
     ) -> Result<Option<AccountManagementUrlBuilder>, OAuthError> {
         let server_metadata = self.server_metadata().await?;
-        Ok(server_metadata.account_management_uri.map(AccountManagementUrlBuilder::new))
+        Ok(account_management_url_builder(server_metadata))
     }
 
     /// Get the account management URL where the user can manage their
@@ -566,7 +622,28 @@ impl OAuth {
             server_metadata
         };
 
-        Ok(metadata.account_management_uri.map(AccountManagementUrlBuilder::new))
+        Ok(account_management_url_builder(metadata))
+    }
+
+    /// Get the URL where the user can take the given account management action.
+    ///
+    /// Like [`OAuth::account_management_url()`], the server metadata is cached
+    /// for a while.
+    ///
+    /// Returns `Ok(None)` if the server doesn't advertise an account
+    /// management URL, or if it doesn't support the action. In that case, the
+    /// action should not be offered to the user.
+    ///
+    /// Returns an error if the request to get the server metadata fails.
+    pub async fn account_management_url_for_action(
+        &self,
+        action: AccountManagementActionFull,
+    ) -> Result<Option<Url>, OAuthError> {
+        let Some(url_builder) = self.account_management_url().await? else {
+            return Ok(None);
+        };
+
+        Ok(url_builder.build_for_action(action))
     }
 
     /// Discover the authentication issuer and retrieve the
@@ -1130,6 +1207,7 @@ impl OAuth {
             access_token: response.access_token().secret().clone(),
             refresh_token: response.refresh_token().map(RefreshToken::secret).cloned(),
         });
+        self.client.auth_ctx().set_access_token_expires_in(response.expires_in());
 
         Ok(validation_data.device_id)
     }
@@ -1204,6 +1282,7 @@ impl OAuth {
             access_token: response.access_token().secret().to_owned(),
             refresh_token: response.refresh_token().map(|t| t.secret().to_owned()),
         });
+        self.client.auth_ctx().set_access_token_expires_in(response.expires_in());
 
         Ok(())
     }
@@ -1251,6 +1330,7 @@ impl OAuth {
         let tokens_clone = tokens.clone();
 
         self.client.auth_ctx().set_session_tokens(tokens);
+        self.client.auth_ctx().set_access_token_expires_in(response.expires_in());
 
         // Call the save_session_callback if set, while the optional lock is being held.
         if let Some(save_session_callback) = self.client.auth_ctx().save_session_callback.get() {
@@ -1400,6 +1480,55 @@ impl OAuth {
 
     /// Log out from the currently authenticated session.
     pub async fn logout(&self) -> Result<(), OAuthError> {
+        self.revoke_tokens().await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(manager) = self.ctx().cross_process_token_refresh_manager.get() {
+            manager.on_logout().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Log out from the currently authenticated session, and delete its device
+    /// from the homeserver.
+    ///
+    /// Contrary to [`OAuth::logout()`], which only revokes the tokens of the
+    /// session, this also removes the device from the list of devices of the
+    /// user, along with its end-to-end encryption keys.
+    ///
+    /// Deleting a device might require User-Interactive Authentication. In that
+    /// case, the information about the required authentication can be obtained
+    /// with [`OAuthError::as_uiaa_response()`], and this method must be called
+    /// again with the corresponding `auth_data`.
+    ///
+    /// Once the device is deleted, its tokens are not valid anymore, so a
+    /// failure to revoke them is only logged.
+    pub async fn logout_everywhere(
+        &self,
+        auth_data: Option<uiaa::AuthData>,
+    ) -> Result<(), OAuthError> {
+        let device_id = self.client.device_id().ok_or(OAuthError::NotAuthenticated)?.to_owned();
+
+        self.client
+            .delete_devices(&[device_id], auth_data)
+            .await
+            .map_err(OAuthError::DeleteDevice)?;
+
+        if let Err(error) = self.revoke_tokens().await {
+            warn!("Couldn't revoke the tokens of the deleted device: {error}");
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(manager) = self.ctx().cross_process_token_refresh_manager.get() {
+            manager.on_logout().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Revoke the tokens of the currently authenticated session.
+    async fn revoke_tokens(&self) -> Result<(), OAuthError> {
         let client_id = self.client_id().ok_or(OAuthError::NotAuthenticated)?.clone();
 
         let server_metadata = self.server_metadata().await?;
@@ -1418,11 +1547,6 @@ impl OAuth {
             .await
             .map_err(OAuthTokenRevocationError::Revoke)?;
 
-        #[cfg(feature = "e2e-encryption")]
-        if let Some(manager) = self.ctx().cross_process_token_refresh_manager.get() {
-            manager.on_logout().await?;
-        }
-
         Ok(())
     }
 }
@@ -1528,6 +1652,17 @@ fn hash_str(x: &str) -> impl fmt::LowerHex {
     sha2::Sha256::new().chain_update(x).finalize()
 }
 
+/// Construct an [`AccountManagementUrlBuilder`] from the given server metadata,
+/// if it advertises an account management URL.
+fn account_management_url_builder(
+    server_metadata: AuthorizationServerMetadata,
+) -> Option<AccountManagementUrlBuilder> {
+    let actions_supported = server_metadata.account_management_actions_supported;
+    server_metadata
+        .account_management_uri
+        .map(|uri| AccountManagementUrlBuilder::new(uri, actions_supported))
+}
+
 /// Data to register or restore a client.
 #[derive(Debug, Clone)]
 pub struct ClientRegistrationData {
@@ -0,0 +1,439 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    future::IntoFuture,
+    sync::{Arc, Mutex},
+};
+
+use eyeball::SharedObservable;
+use futures_core::Stream;
+use matrix_sdk_base::{boxed_into_future, crypto::types::qr_login::QrCodeData};
+use ruma::{DeviceId, OwnedDeviceId};
+use tokio::sync::oneshot;
+use tracing::trace;
+use url::Url;
+
+use super::{
+    messages::{LoginFailureReason, LoginProtocolType, QrAuthMessage},
+    secure_channel::{EstablishedSecureChannel, SecureChannel},
+    QRCodeGrantLoginError, SecureChannelError,
+};
+#[cfg(doc)]
+use crate::authentication::oauth::OAuth;
+use crate::{config::RequestConfig, http_client::HttpClient, Client};
+
+async fn send_unexpected_message_error(
+    channel: &mut EstablishedSecureChannel,
+) -> Result<(), SecureChannelError> {
+    channel
+        .send_json(QrAuthMessage::LoginFailure {
+            reason: LoginFailureReason::UnexpectedMessageReceived,
+            homeserver: None,
+        })
+        .await
+}
+
+/// A handle used to pass the check code, displayed on the new device, back to
+/// the QR code login reciprocation.
+#[derive(Clone, Debug)]
+pub struct CheckCodeSender {
+    inner: Arc<Mutex<Option<oneshot::Sender<u8>>>>,
+}
+
+impl CheckCodeSender {
+    fn new(sender: oneshot::Sender<u8>) -> Self {
+        Self { inner: Arc::new(Mutex::new(Some(sender))) }
+    }
+
+    /// Send the check code the user has read on the new device.
+    ///
+    /// Returns `false` if the check code was already sent, or if the login
+    /// has been aborted in the meantime.
+    pub fn send(&self, check_code: u8) -> bool {
+        match self.inner.lock().unwrap().take() {
+            Some(sender) => sender.send(check_code).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Type telling us about the progress of the QR code login reciprocation, i.e.
+/// of an existing device logging in a new device.
+#[derive(Clone, Debug, Default)]
+pub enum GrantLoginProgress {
+    /// We're just starting up, this is the default and initial state.
+    #[default]
+    Starting,
+    /// The rendezvous channel has been created, the [`QrCodeData`] needs to be
+    /// rendered as a QR code and scanned by the new device.
+    QrCodeReady {
+        /// The data to encode in the QR code.
+        qr_code_data: QrCodeData,
+    },
+    /// The new device has scanned the QR code and displays a check code. The
+    /// user needs to enter it on this device, so we can verify that the
+    /// secure channel is indeed secure.
+    WaitingForCheckCode {
+        /// The handle used to pass the check code back to us.
+        check_code_sender: CheckCodeSender,
+    },
+    /// The new device has requested a device authorization grant from the
+    /// OAuth 2.0 authorization server. The user needs to open the URL and
+    /// approve the log in.
+    WaitingForAuth {
+        /// The URL the user should open to approve the new device.
+        verification_uri: Url,
+    },
+    /// The new device is logged in, we're sending it our secrets.
+    SyncingSecrets,
+    /// The login process has completed.
+    Done,
+}
+
+/// Named future for the [`OAuth::login_with_qr_code_reciprocate()`] method.
+#[derive(Debug)]
+pub struct LoginWithQrCodeReciprocate<'a> {
+    client: &'a Client,
+    state: SharedObservable<GrantLoginProgress>,
+}
+
+impl LoginWithQrCodeReciprocate<'_> {
+    /// Subscribe to the progress of the QR code login reciprocation.
+    ///
+    /// It's necessary to subscribe to this to get the QR code to display, and
+    /// to pass the check code the new device displays back to us.
+    pub fn subscribe_to_progress(&self) -> impl Stream<Item = GrantLoginProgress> {
+        self.state.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for LoginWithQrCodeReciprocate<'a> {
+    type Output = Result<(), QRCodeGrantLoginError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            // Let's make sure that we'll be able to give the new device our secrets before
+            // showing any QR code, there's no point in logging the new device in if it
+            // can't become a verified device afterwards.
+            let bundle = self.client.encryption().export_secrets_bundle().await?;
+
+            let homeserver = self.client.homeserver();
+            let http_client = HttpClient::new(
+                self.client.inner.http_client.inner.clone(),
+                RequestConfig::short_retry(),
+            );
+
+            trace!("Creating the rendezvous channel.");
+            let channel = SecureChannel::new(http_client, &homeserver).await?;
+            self.state.set(GrantLoginProgress::QrCodeReady {
+                qr_code_data: channel.qr_code_data().clone(),
+            });
+
+            // Wait for the new device to scan the QR code and to connect to the channel.
+            let channel = channel.connect().await?;
+
+            trace!("The new device has connected, waiting for the check code.");
+            let (sender, receiver) = oneshot::channel();
+            self.state.set(GrantLoginProgress::WaitingForCheckCode {
+                check_code_sender: CheckCodeSender::new(sender),
+            });
+
+            let check_code =
+                receiver.await.map_err(|_| QRCodeGrantLoginError::CheckCodeNotProvided)?;
+            let mut channel = channel.confirm(check_code)?;
+
+            trace!("Established the secure channel, waiting for the login protocol.");
+            let (authorization_grant, device_id) = match channel.receive_json().await? {
+                QrAuthMessage::LoginProtocol {
+                    device_authorization_grant,
+                    protocol,
+                    device_id,
+                } => {
+                    if protocol != LoginProtocolType::DeviceAuthorizationGrant {
+                        channel
+                            .send_json(QrAuthMessage::LoginFailure {
+                                reason: LoginFailureReason::UnsupportedProtocol,
+                                homeserver: Some(homeserver),
+                            })
+                            .await?;
+
+                        return Err(QRCodeGrantLoginError::UnsupportedProtocol(protocol));
+                    }
+
+                    (device_authorization_grant, OwnedDeviceId::from(device_id.to_base64()))
+                }
+                QrAuthMessage::LoginFailure { reason, homeserver } => {
+                    return Err(QRCodeGrantLoginError::LoginFailure { reason, homeserver });
+                }
+                message => {
+                    send_unexpected_message_error(&mut channel).await?;
+
+                    return Err(QRCodeGrantLoginError::UnexpectedMessage {
+                        expected: "m.login.protocol",
+                        received: message,
+                    });
+                }
+            };
+
+            // The new device picks its device ID, make sure it doesn't clash with one of
+            // our existing devices.
+            if self.device_exists(&device_id).await? {
+                channel
+                    .send_json(QrAuthMessage::LoginFailure {
+                        reason: LoginFailureReason::DeviceAlreadyExists,
+                        homeserver: None,
+                    })
+                    .await?;
+
+                return Err(QRCodeGrantLoginError::DeviceIdAlreadyInUse);
+            }
+
+            trace!("Accepting the login protocol.");
+            channel.send_json(QrAuthMessage::LoginProtocolAccepted).await?;
+
+            // Prefer the URL which has the user code pre-filled, if it's a valid one.
+            let verification_uri = authorization_grant
+                .verification_uri_complete
+                .and_then(|uri| Url::parse(uri.secret()).ok())
+                .unwrap_or_else(|| authorization_grant.verification_uri.url().clone());
+            self.state.set(GrantLoginProgress::WaitingForAuth { verification_uri });
+
+            trace!("Waiting for the new device to obtain its access token.");
+            match channel.receive_json().await? {
+                QrAuthMessage::LoginSuccess => (),
+                QrAuthMessage::LoginDeclined => return Err(QRCodeGrantLoginError::LoginDeclined),
+                QrAuthMessage::LoginFailure { reason, homeserver } => {
+                    return Err(QRCodeGrantLoginError::LoginFailure { reason, homeserver });
+                }
+                message => {
+                    send_unexpected_message_error(&mut channel).await?;
+
+                    return Err(QRCodeGrantLoginError::UnexpectedMessage {
+                        expected: "m.login.success",
+                        received: message,
+                    });
+                }
+            }
+
+            // Don't trust the new device blindly, the homeserver must know about it
+            // before we hand over our secrets.
+            if !self.device_exists(&device_id).await? {
+                channel
+                    .send_json(QrAuthMessage::LoginFailure {
+                        reason: LoginFailureReason::DeviceNotFound,
+                        homeserver: None,
+                    })
+                    .await?;
+
+                return Err(QRCodeGrantLoginError::DeviceNotFound);
+            }
+
+            trace!("Sending the secrets bundle to the new device.");
+            self.state.set(GrantLoginProgress::SyncingSecrets);
+            channel.send_json(QrAuthMessage::LoginSecrets(bundle)).await?;
+
+            trace!("The new device has been successfully logged in.");
+            self.state.set(GrantLoginProgress::Done);
+
+            Ok(())
+        })
+    }
+}
+
+impl<'a> LoginWithQrCodeReciprocate<'a> {
+    pub(crate) fn new(client: &'a Client) -> LoginWithQrCodeReciprocate<'a> {
+        LoginWithQrCodeReciprocate { client, state: Default::default() }
+    }
+
+    /// Check if the homeserver knows a device with the given ID for our own
+    /// user.
+    async fn device_exists(&self, device_id: &DeviceId) -> Result<bool, QRCodeGrantLoginError> {
+        let response = self.client.devices().await.map_err(QRCodeGrantLoginError::DeviceLookup)?;
+        Ok(response.devices.iter().any(|device| device.device_id == device_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches2::{assert_let, assert_matches};
+    use futures_util::StreamExt;
+    use matrix_sdk_base::crypto::types::{qr_login::QrCodeMode, SecretsBundle};
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        authentication::oauth::qrcode::{
+            messages::AuthorizationGrant, secure_channel::test::MockedRendezvousServer,
+        },
+        test_utils::mocks::MatrixMockServer,
+    };
+
+    fn secrets_bundle() -> SecretsBundle {
+        let json = json!({
+            "cross_signing": {
+                "master_key": "rTtSv67XGS6k/rg6/yTG/m573cyFTPFRqluFhQY+hSw",
+                "self_signing_key": "4jbPt7jh5D2iyM4U+3IDa+WthgJB87IQN1ATdkau+xk",
+                "user_signing_key": "YkFKtkjcsTxF6UAzIIG/l6Nog/G2RigCRfWj3cjNWeM",
+            },
+        });
+
+        serde_json::from_value(json).expect("We should be able to deserialize a secrets bundle")
+    }
+
+    fn authorization_grant() -> AuthorizationGrant {
+        serde_json::from_value(json!({
+            "verification_uri": "https://auth.example.org/link",
+            "verification_uri_complete": "https://auth.example.org/link?code=123456",
+        }))
+        .unwrap()
+    }
+
+    /// The new device side of the QR login dance, as far as the existing
+    /// device is concerned.
+    ///
+    /// Returns the message the existing device sent after the new device told
+    /// it about its login success.
+    async fn request_login(
+        qr_code_data: QrCodeData,
+        device_id: vodozemac::Curve25519PublicKey,
+        check_code_sender: oneshot::Sender<u8>,
+    ) -> QrAuthMessage {
+        let mut bob = EstablishedSecureChannel::from_qr_code(
+            reqwest::Client::new(),
+            &qr_code_data,
+            QrCodeMode::Login,
+        )
+        .await
+        .expect("Bob should be able to establish the secure channel");
+
+        check_code_sender.send(bob.check_code().to_digit()).unwrap();
+
+        bob.send_json(QrAuthMessage::authorization_grant_login_protocol(
+            authorization_grant(),
+            device_id,
+        ))
+        .await
+        .unwrap();
+
+        let message: QrAuthMessage = bob.receive_json().await.unwrap();
+
+        if !matches!(message, QrAuthMessage::LoginProtocolAccepted) {
+            return message;
+        }
+
+        bob.send_json(QrAuthMessage::LoginSuccess).await.unwrap();
+        bob.receive_json().await.unwrap()
+    }
+
+    async fn reciprocate_login(
+        server: &MatrixMockServer,
+        alice: &Client,
+        device_id: vodozemac::Curve25519PublicKey,
+    ) -> (Result<(), QRCodeGrantLoginError>, QrAuthMessage) {
+        let _rendezvous_server = MockedRendezvousServer::new(server.server(), "abcdEFG12345").await;
+
+        let oauth = alice.oauth();
+        let reciprocation = oauth.login_with_qr_code_reciprocate();
+        let mut progress = reciprocation.subscribe_to_progress();
+
+        let bob_task = tokio::spawn(async move {
+            let (sender, receiver) = oneshot::channel();
+            let mut sender = Some(sender);
+            let mut receiver = Some(receiver);
+            let mut bob_task = None;
+
+            while let Some(update) = progress.next().await {
+                match update {
+                    GrantLoginProgress::QrCodeReady { qr_code_data } => {
+                        let sender = sender.take().expect("Only one QR code should be generated");
+                        bob_task =
+                            Some(tokio::spawn(request_login(qr_code_data, device_id, sender)));
+                    }
+                    GrantLoginProgress::WaitingForCheckCode { check_code_sender } => {
+                        let check_code = receiver.take().unwrap().await.unwrap();
+                        assert!(check_code_sender.send(check_code));
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+
+            bob_task.expect("Bob should have scanned the QR code").await.unwrap()
+        });
+
+        let result = reciprocation.await;
+
+        (result, bob_task.await.unwrap())
+    }
+
+    #[async_test]
+    async fn test_reciprocate_login() {
+        let server = MatrixMockServer::new().await;
+        let alice = server.client_builder().build().await;
+        alice.encryption().import_secrets_bundle(&secrets_bundle()).await.unwrap();
+
+        let bob_device_id = vodozemac::olm::Account::new().identity_keys().curve25519;
+        let bob_device_id_string = OwnedDeviceId::from(bob_device_id.to_base64());
+
+        server.mock_devices().ok(&[]).mock_once().named("devices_before_login").mount().await;
+        server
+            .mock_devices()
+            .ok(&[&bob_device_id_string])
+            .mock_once()
+            .named("devices_after_login")
+            .mount()
+            .await;
+
+        let (result, message) = reciprocate_login(&server, &alice, bob_device_id).await;
+
+        result.expect("Alice should be able to log Bob in");
+        assert_let!(QrAuthMessage::LoginSecrets(bundle) = message);
+        assert_eq!(
+            bundle.cross_signing.master_key,
+            secrets_bundle().cross_signing.master_key,
+            "Bob should have received Alice's cross-signing keys"
+        );
+    }
+
+    #[async_test]
+    async fn test_reciprocate_login_device_already_exists() {
+        let server = MatrixMockServer::new().await;
+        let alice = server.client_builder().build().await;
+        alice.encryption().import_secrets_bundle(&secrets_bundle()).await.unwrap();
+
+        let bob_device_id = vodozemac::olm::Account::new().identity_keys().curve25519;
+        let bob_device_id_string = OwnedDeviceId::from(bob_device_id.to_base64());
+
+        server.mock_devices().ok(&[&bob_device_id_string]).mock_once().mount().await;
+
+        let (result, message) = reciprocate_login(&server, &alice, bob_device_id).await;
+
+        assert_matches!(result, Err(QRCodeGrantLoginError::DeviceIdAlreadyInUse));
+        assert_let!(QrAuthMessage::LoginFailure { reason, .. } = message);
+        assert_eq!(reason, LoginFailureReason::DeviceAlreadyExists);
+    }
+
+    #[async_test]
+    async fn test_reciprocate_login_without_secrets() {
+        let server = MatrixMockServer::new().await;
+        let alice = server.client_builder().build().await;
+
+        let result = alice.oauth().login_with_qr_code_reciprocate().await;
+
+        assert_matches!(result, Err(QRCodeGrantLoginError::SecretsBundleExport(_)));
+    }
+}
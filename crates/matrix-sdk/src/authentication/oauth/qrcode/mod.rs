@@ -17,15 +17,16 @@
 //! Please note, QR code logins are only supported when using OAuth 2.0 as the
 //! authentication mechanism, native Matrix authentication does not support it.
 //!
-//! This currently only implements the case where the new device is scanning the
-//! QR code. To log in using a QR code, please take a look at the
-//! [`OAuth::login_with_qr_code()`] method.
+//! To log in a new device by scanning a QR code, please take a look at the
+//! [`OAuth::login_with_qr_code()`] method. To log in a new device from an
+//! existing device by showing a QR code, please take a look at the
+//! [`OAuth::login_with_qr_code_reciprocate()`] method.
 
 use as_variant::as_variant;
 pub use matrix_sdk_base::crypto::types::qr_login::{
     LoginQrCodeDecodeError, QrCodeData, QrCodeMode, QrCodeModeData,
 };
-use matrix_sdk_base::crypto::SecretImportError;
+use matrix_sdk_base::crypto::{store::SecretsBundleExportError, SecretImportError};
 pub use oauth2::{
     basic::{BasicErrorResponse, BasicRequestTokenError},
     ConfigurationError, DeviceCodeErrorResponse, DeviceCodeErrorResponseType, HttpClientError,
@@ -35,12 +36,14 @@ use thiserror::Error;
 use url::Url;
 pub use vodozemac::ecies::{Error as EciesError, MessageDecodeError};
 
+mod grant;
 mod login;
 mod messages;
 mod rendezvous_channel;
 mod secure_channel;
 
 pub use self::{
+    grant::{CheckCodeSender, GrantLoginProgress, LoginWithQrCodeReciprocate},
     login::{LoginProgress, LoginWithQrCode},
     messages::{LoginFailureReason, LoginProtocolType, QrAuthMessage},
 };
@@ -106,6 +109,73 @@ pub enum QRCodeLoginError {
     SecretImport(#[from] SecretImportError),
 }
 
+/// The error type for failures while trying to log in a new device from an
+/// existing device, by showing a QR code.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
+pub enum QRCodeGrantLoginError {
+    /// The secrets bundle, which will be sent to the new device, failed to be
+    /// exported. The cross-signing private keys are most likely missing from
+    /// this device.
+    #[error(transparent)]
+    SecretsBundleExport(#[from] SecretsBundleExportError),
+
+    /// The end-to-end encryption of this device hasn't been set up yet, there
+    /// are no secrets to send to the new device.
+    #[error("The end-to-end encryption of this device hasn't been set up")]
+    MissingOlmMachine,
+
+    /// An error happened while exchanging messages with the new device.
+    #[error(transparent)]
+    SecureChannel(#[from] SecureChannelError),
+
+    /// The check code displayed by the new device has never been provided.
+    #[error("The check code has not been provided")]
+    CheckCodeNotProvided,
+
+    /// The new device has signaled to us that the login has failed.
+    #[error("The login failed, reason: {reason}")]
+    LoginFailure {
+        /// The reason, as signaled by the new device, for the login failure.
+        reason: LoginFailureReason,
+        /// The homeserver that the new device attempted to log in to.
+        homeserver: Option<Url>,
+    },
+
+    /// The OAuth 2.0 authorization server has declined to give the new device
+    /// an access token, i.e. because the user declined the log in.
+    #[error("The login has been declined")]
+    LoginDeclined,
+
+    /// An unexpected message was received from the new device.
+    #[error("We have received an unexpected message, expected: {expected}, got {received:?}")]
+    UnexpectedMessage {
+        /// The message we expected.
+        expected: &'static str,
+        /// The message we received instead.
+        received: QrAuthMessage,
+    },
+
+    /// The new device wants to use a login protocol we don't support.
+    #[error("The new device requested an unsupported login protocol: {0}")]
+    UnsupportedProtocol(LoginProtocolType),
+
+    /// The device ID the new device wants to use is already in use by one of
+    /// our devices.
+    #[error("The requested device ID is already in use")]
+    DeviceIdAlreadyInUse,
+
+    /// The new device claims to be logged in, but the homeserver doesn't know
+    /// about it.
+    #[error("The new device couldn't be found on the homeserver")]
+    DeviceNotFound,
+
+    /// An error happened while we were fetching the list of our devices from
+    /// the homeserver.
+    #[error(transparent)]
+    DeviceLookup(HttpError),
+}
+
 /// Error type describing failures in the interaction between the device
 /// attempting to log in and the OAuth 2.0 authorization server.
 #[derive(Debug, Error)]
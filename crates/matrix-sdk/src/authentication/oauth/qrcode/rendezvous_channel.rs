@@ -106,7 +106,6 @@ impl RendezvousChannel {
     /// By outbound we mean that we're going to tell the Matrix server to create
     /// a new rendezvous session. We're going to send an initial empty message
     /// through the channel.
-    #[cfg(test)]
     pub(super) async fn create_outbound(
         client: HttpClient,
         rendezvous_server: &Url,
@@ -115,7 +114,7 @@ impl RendezvousChannel {
 
         let request = create_rendezvous_session::unstable::Request::default();
         let response = client
-            .send(request, None, rendezvous_server.to_string(), None, &[], Default::default())
+            .send(request, None, rendezvous_server.to_string(), None, &[], Default::default(), None)
             .await?;
 
         let rendezvous_url = response.url;
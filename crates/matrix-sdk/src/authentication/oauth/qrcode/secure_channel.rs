@@ -12,16 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[cfg(test)]
-use matrix_sdk_base::crypto::types::qr_login::QrCodeModeData;
-use matrix_sdk_base::crypto::types::qr_login::{QrCodeData, QrCodeMode};
+use matrix_sdk_base::crypto::types::qr_login::{QrCodeData, QrCodeMode, QrCodeModeData};
 use serde::{de::DeserializeOwned, Serialize};
 use tracing::{instrument, trace};
-#[cfg(test)]
 use url::Url;
-use vodozemac::ecies::{CheckCode, Ecies, EstablishedEcies, Message, OutboundCreationResult};
-#[cfg(test)]
-use vodozemac::ecies::{InboundCreationResult, InitialMessage};
+use vodozemac::ecies::{
+    CheckCode, Ecies, EstablishedEcies, InboundCreationResult, InitialMessage, Message,
+    OutboundCreationResult,
+};
 
 use super::{
     rendezvous_channel::{InboundChannelCreationResult, RendezvousChannel},
@@ -32,26 +30,23 @@ use crate::{config::RequestConfig, http_client::HttpClient};
 const LOGIN_INITIATE_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_INITIATE";
 const LOGIN_OK_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_OK";
 
-#[cfg(test)]
+/// The side of the secure channel which creates the rendezvous channel and
+/// shows the QR code, used by the existing device when it reciprocates a
+/// login.
 pub(super) struct SecureChannel {
     channel: RendezvousChannel,
     qr_code_data: QrCodeData,
     ecies: Ecies,
 }
 
-// This is only used in tests because we're only supporting the new device part
-// of the QR login flow. It will be needed once we support reciprocating of the
-// login.
-//
-// It's still very much useful to have this, as we're testing the whole flow by
-// mocking the reciprocation.
-#[cfg(test)]
 impl SecureChannel {
     pub(super) async fn new(http_client: HttpClient, homeserver_url: &Url) -> Result<Self, Error> {
         let channel = RendezvousChannel::create_outbound(http_client, homeserver_url).await?;
         let rendezvous_url = channel.rendezvous_url().to_owned();
-        // We're a bit abusing the QR code data here, since we're passing the homeserver
-        // URL, but for our tests this is fine.
+        // The new device passes this to
+        // `ClientBuilder::server_name_or_homeserver_url()`, so we can put the
+        // homeserver URL in here and spare the new device a
+        // discovery round-trip.
         let mode_data = QrCodeModeData::Reciprocate { server_name: homeserver_url.to_string() };
 
         let ecies = Ecies::new();
@@ -98,13 +93,10 @@ impl SecureChannel {
 }
 
 /// An SecureChannel that is yet to be confirmed as with the [`CheckCode`].
-/// Same deal as for the [`SecureChannel`], not used for now.
-#[cfg(test)]
 pub(super) struct AlmostEstablishedSecureChannel {
     secure_channel: EstablishedSecureChannel,
 }
 
-#[cfg(test)]
 impl AlmostEstablishedSecureChannel {
     /// Confirm that the secure channel is indeed secure.
     ///
@@ -4,8 +4,8 @@ use matrix_sdk_base::store::RoomLoadSettings;
 use matrix_sdk_test::async_test;
 use oauth2::{ClientId, CsrfToken, PkceCodeChallenge, RedirectUrl};
 use ruma::{
-    api::client::discovery::get_authorization_server_metadata::msc2965::Prompt, device_id,
-    owned_device_id, user_id, DeviceId, ServerName,
+    api::client::{discovery::get_authorization_server_metadata::msc2965::Prompt, uiaa},
+    device_id, owned_device_id, user_id, DeviceId, ServerName,
 };
 use serde_json::json;
 use tokio::sync::broadcast::error::TryRecvError;
@@ -16,8 +16,8 @@ use wiremock::{
 };
 
 use super::{
-    AuthorizationCode, AuthorizationError, AuthorizationResponse, OAuth, OAuthAuthorizationData,
-    OAuthError, RedirectUriQueryParseError, UrlOrQuery,
+    AccountManagementActionFull, AuthorizationCode, AuthorizationError, AuthorizationResponse,
+    OAuth, OAuthAuthorizationData, OAuthError, RedirectUriQueryParseError, UrlOrQuery,
 };
 use crate::{
     authentication::oauth::{
@@ -643,6 +643,29 @@ async fn test_management_url_cache() {
     assert!(management_url.is_some());
 }
 
+#[async_test]
+async fn test_account_management_url_for_action() {
+    let server = MatrixMockServer::new().await;
+
+    let oauth_server = server.oauth();
+    oauth_server.mock_server_metadata().ok().expect(1).mount().await;
+
+    let client = server.client_builder().logged_in_with_oauth().build().await;
+    let oauth = client.oauth();
+
+    let url = oauth
+        .account_management_url_for_action(AccountManagementActionFull::AccountDeactivate)
+        .await
+        .unwrap()
+        .expect("the action should be supported");
+    assert_eq!(url.query(), Some("action=org.matrix.account_deactivate"));
+
+    // The metadata is cached.
+    let url_builder = oauth.account_management_url().await.unwrap().unwrap();
+    assert_eq!(url_builder.actions_supported().len(), 6);
+    assert!(url_builder.is_action_supported(&AccountManagementActionFull::CrossSigningReset));
+}
+
 #[async_test]
 async fn test_server_metadata() {
     let server = MatrixMockServer::new().await;
@@ -726,3 +749,37 @@ async fn test_client_registration_data() {
     oauth.use_registration_data(&server_metadata, Some(&client_metadata.into())).await.unwrap();
     assert_eq!(oauth.client_id().map(|id| id.as_str()), Some("test_client_id"));
 }
+
+#[async_test]
+async fn test_logout_everywhere() {
+    let server = MatrixMockServer::new().await;
+
+    let oauth_server = server.oauth();
+    oauth_server
+        .mock_server_metadata()
+        .ok_https()
+        .expect(1..)
+        .named("server_metadata")
+        .mount()
+        .await;
+    oauth_server.mock_revocation().ok().expect(1).named("revocation").mount().await;
+
+    // The device deletion requires UIA the first time.
+    server.mock_delete_devices().uiaa().mock_once().named("delete_devices_uiaa").mount().await;
+    server.mock_delete_devices().ok().expect(1).named("delete_devices").mount().await;
+
+    let client = server.client_builder().logged_in_with_oauth().build().await;
+    let oauth = client.oauth().insecure_rewrite_https_to_http();
+
+    let error = oauth.logout_everywhere(None).await.unwrap_err();
+    assert_matches!(error, OAuthError::DeleteDevice(_));
+    let uiaa_info = error.as_uiaa_response().expect("the error should be a UIA response");
+
+    let mut password = uiaa::Password::new(
+        uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+        "wordpass".to_owned(),
+    );
+    password.session = uiaa_info.session.clone();
+
+    oauth.logout_everywhere(Some(uiaa::AuthData::Password(password))).await.unwrap();
+}
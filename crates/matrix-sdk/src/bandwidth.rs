@@ -0,0 +1,195 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bandwidth profiles, to use less data on slow or metered connections.
+//!
+//! The [`BandwidthProfile`] of a [`Client`] can be switched at runtime with
+//! [`Client::set_bandwidth_profile()`]. In the
+//! [`BandwidthProfile::LowBandwidth`] profile:
+//!
+//! - the `timeline_limit` of the sliding sync lists and room subscriptions is
+//!   reduced,
+//! - the room keys of undecryptable events aren't downloaded from the backup
+//!   until the profile is switched back,
+//! - read receipts are batched over a longer delay.
+//!
+//! The SDK doesn't download media by itself, so
+//! [`BandwidthSettings::should_auto_download()`] tells clients whether they
+//! should download a media automatically. The profile can be observed with
+//! [`Client::subscribe_to_bandwidth_profile()`] so the UI can adapt.
+
+use std::time::Duration;
+
+use eyeball::Subscriber;
+use matrix_sdk_base::media::MediaFormat;
+
+use crate::{room::receipts::RECEIPTS_BATCH_DELAY, Client};
+
+/// A bandwidth profile of the [`Client`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BandwidthProfile {
+    /// The default profile.
+    #[default]
+    Normal,
+
+    /// A profile that reduces the data used by the client.
+    LowBandwidth,
+}
+
+impl BandwidthProfile {
+    /// The settings of this profile.
+    pub fn settings(self) -> BandwidthSettings {
+        match self {
+            Self::Normal => BandwidthSettings {
+                max_timeline_limit: None,
+                auto_download_media: true,
+                max_auto_download_thumbnail_size: None,
+                defer_backup_download: false,
+                receipts_batch_delay: RECEIPTS_BATCH_DELAY,
+            },
+            Self::LowBandwidth => BandwidthSettings {
+                max_timeline_limit: Some(1),
+                auto_download_media: false,
+                max_auto_download_thumbnail_size: Some(50 * 1024),
+                defer_backup_download: true,
+                receipts_batch_delay: Duration::from_secs(5),
+            },
+        }
+    }
+}
+
+/// The settings of a [`BandwidthProfile`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BandwidthSettings {
+    /// The maximum `timeline_limit` of the sliding sync lists and room
+    /// subscriptions, if any.
+    pub max_timeline_limit: Option<u32>,
+
+    /// Whether media files should be downloaded automatically.
+    pub auto_download_media: bool,
+
+    /// The maximum size, in bytes, of the thumbnails that should be downloaded
+    /// automatically, if any.
+    pub max_auto_download_thumbnail_size: Option<u64>,
+
+    /// Whether the download of the room keys from the backup, after a
+    /// decryption failure, should be deferred until the profile changes.
+    pub defer_backup_download: bool,
+
+    /// The delay during which read receipts are batched, see
+    /// [`Room::queue_receipt()`](crate::Room::queue_receipt).
+    pub receipts_batch_delay: Duration,
+}
+
+impl BandwidthSettings {
+    /// Whether a media should be downloaded automatically, e.g. when it's
+    /// displayed in a timeline.
+    ///
+    /// A thumbnail with an unknown size isn't downloaded automatically when
+    /// there is a maximum size.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the media to download.
+    ///
+    /// * `size` - The size of the media in bytes, if known.
+    pub fn should_auto_download(&self, format: &MediaFormat, size: Option<u64>) -> bool {
+        match format {
+            MediaFormat::File => self.auto_download_media,
+            MediaFormat::Thumbnail(_) => self
+                .max_auto_download_thumbnail_size
+                .is_none_or(|max_size| size.is_some_and(|size| size <= max_size)),
+        }
+    }
+
+    /// Clamp a `timeline_limit` to the maximum of these settings.
+    pub(crate) fn clamp_timeline_limit(&self, timeline_limit: u32) -> u32 {
+        self.max_timeline_limit.map_or(timeline_limit, |max| timeline_limit.min(max))
+    }
+}
+
+impl Client {
+    /// Get the current bandwidth profile.
+    pub fn bandwidth_profile(&self) -> BandwidthProfile {
+        self.inner.bandwidth_profile.get()
+    }
+
+    /// Get the settings of the current bandwidth profile.
+    pub fn bandwidth_settings(&self) -> BandwidthSettings {
+        self.bandwidth_profile().settings()
+    }
+
+    /// Switch to another bandwidth profile.
+    ///
+    /// The new profile applies to the next sliding sync requests and read
+    /// receipts, and the deferred room key downloads resume when switching
+    /// back to [`BandwidthProfile::Normal`].
+    pub fn set_bandwidth_profile(&self, profile: BandwidthProfile) {
+        self.inner.bandwidth_profile.set_if_not_eq(profile);
+    }
+
+    /// Subscribe to the changes of the bandwidth profile.
+    pub fn subscribe_to_bandwidth_profile(&self) -> Subscriber<BandwidthProfile> {
+        self.inner.bandwidth_profile.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_base::media::{MediaFormat, MediaThumbnailSettings};
+    use matrix_sdk_test::async_test;
+    use ruma::uint;
+
+    use super::{BandwidthProfile, BandwidthSettings};
+    use crate::test_utils::logged_in_client;
+
+    #[test]
+    fn test_should_auto_download() {
+        let thumbnail = MediaFormat::Thumbnail(MediaThumbnailSettings::new(uint!(100), uint!(100)));
+
+        let settings = BandwidthProfile::Normal.settings();
+        assert!(settings.should_auto_download(&MediaFormat::File, None));
+        assert!(settings.should_auto_download(&thumbnail, None));
+        assert!(settings.should_auto_download(&thumbnail, Some(10 * 1024 * 1024)));
+
+        let settings = BandwidthProfile::LowBandwidth.settings();
+        assert!(!settings.should_auto_download(&MediaFormat::File, Some(1)));
+        assert!(!settings.should_auto_download(&thumbnail, None));
+        assert!(settings.should_auto_download(&thumbnail, Some(1024)));
+        assert!(!settings.should_auto_download(&thumbnail, Some(10 * 1024 * 1024)));
+    }
+
+    #[test]
+    fn test_clamp_timeline_limit() {
+        let settings = BandwidthProfile::Normal.settings();
+        assert_eq!(settings.clamp_timeline_limit(20), 20);
+
+        let settings = BandwidthSettings { max_timeline_limit: Some(5), ..settings };
+        assert_eq!(settings.clamp_timeline_limit(20), 5);
+        assert_eq!(settings.clamp_timeline_limit(1), 1);
+    }
+
+    #[async_test]
+    async fn test_switch_bandwidth_profile() {
+        let client = logged_in_client(None).await;
+        assert_eq!(client.bandwidth_profile(), BandwidthProfile::Normal);
+
+        let mut subscriber = client.subscribe_to_bandwidth_profile();
+
+        client.set_bandwidth_profile(BandwidthProfile::LowBandwidth);
+        assert_eq!(subscriber.next().await, Some(BandwidthProfile::LowBandwidth));
+        assert_eq!(client.bandwidth_settings(), BandwidthProfile::LowBandwidth.settings());
+    }
+}
@@ -171,6 +171,7 @@ async fn discover_homeserver(
             None,
             &[MatrixVersion::V1_0],
             Default::default(),
+            None,
         )
         .await
         .map_err(|e| match e {
@@ -195,6 +196,7 @@ pub(super) async fn get_supported_versions(
             None,
             &[MatrixVersion::V1_0],
             Default::default(),
+            None,
         )
         .await
 }
@@ -17,7 +17,7 @@ mod homeserver_config;
 
 #[cfg(feature = "sqlite")]
 use std::path::Path;
-use std::{fmt, sync::Arc};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 use homeserver_config::*;
 use matrix_sdk_base::{store::StoreConfig, BaseClient};
@@ -25,8 +25,11 @@ use matrix_sdk_base::{store::StoreConfig, BaseClient};
 use matrix_sdk_sqlite::SqliteStoreConfig;
 use ruma::{
     api::{error::FromHttpResponseError, MatrixVersion},
+    time::Duration,
     OwnedServerName, ServerName,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use rustls_pki_types::CertificateDer;
 use thiserror::Error;
 use tokio::sync::{broadcast, Mutex, OnceCell};
 use tracing::{debug, field::debug, instrument, Span};
@@ -36,15 +39,19 @@ use super::{Client, ClientInner};
 use crate::crypto::{CollectStrategy, TrustRequirement};
 #[cfg(feature = "e2e-encryption")]
 use crate::encryption::EncryptionSettings;
+#[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+use crate::http_client::CertificatePin;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::http_client::HttpSettings;
 use crate::{
     authentication::{oauth::OAuthCtx, AuthCtx},
     client::ClientServerCapabilities,
-    config::RequestConfig,
+    clock::{Clock, SystemClock},
+    config::{RequestBudget, RequestCategory, RequestConfig},
     error::RumaApiError,
-    http_client::HttpClient,
+    http_client::{HttpClient, HttpMiddleware},
     send_queue::SendQueueData,
+    session_handoff::SessionStoreLocation,
     sliding_sync::VersionBuilder as SlidingSyncVersionBuilder,
     HttpError, IdParseError,
 };
@@ -93,11 +100,15 @@ pub struct ClientBuilder {
     homeserver_cfg: Option<HomeserverConfig>,
     sliding_sync_version_builder: SlidingSyncVersionBuilder,
     http_cfg: Option<HttpConfig>,
+    http_middlewares: Vec<Arc<dyn HttpMiddleware>>,
+    clock: Arc<dyn Clock>,
     store_config: BuilderStoreConfig,
     request_config: RequestConfig,
+    request_budgets: BTreeMap<RequestCategory, RequestBudget>,
     respect_login_well_known: bool,
     server_versions: Option<Box<[MatrixVersion]>>,
     handle_refresh_tokens: bool,
+    refresh_access_token_ahead_of_expiry: Option<Duration>,
     base_client: Option<BaseClient>,
     #[cfg(feature = "e2e-encryption")]
     encryption_settings: EncryptionSettings,
@@ -116,13 +127,17 @@ impl ClientBuilder {
             homeserver_cfg: None,
             sliding_sync_version_builder: SlidingSyncVersionBuilder::Native,
             http_cfg: None,
+            http_middlewares: Vec::new(),
+            clock: Arc::new(SystemClock),
             store_config: BuilderStoreConfig::Custom(StoreConfig::new(
                 Self::DEFAULT_CROSS_PROCESS_STORE_LOCKS_HOLDER_NAME.to_owned(),
             )),
             request_config: Default::default(),
+            request_budgets: BTreeMap::new(),
             respect_login_well_known: true,
             server_versions: None,
             handle_refresh_tokens: false,
+            refresh_access_token_ahead_of_expiry: None,
             base_client: None,
             #[cfg(feature = "e2e-encryption")]
             encryption_settings: Default::default(),
@@ -299,13 +314,26 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the timeout and concurrency limits of a category of requests.
+    ///
+    /// See [`RequestBudget`] for more details.
+    pub fn request_budget(mut self, category: RequestCategory, budget: RequestBudget) -> Self {
+        self.request_budgets.insert(category, budget);
+        self
+    }
+
     /// Set the proxy through which all the HTTP requests should go.
     ///
-    /// Note, only HTTP proxies are supported.
+    /// HTTP and HTTPS proxies are supported. SOCKS5 proxies are supported too
+    /// with the `socks` feature, with a `socks5://` or `socks5h://` URL.
+    ///
+    /// The proxy only applies to this client, the proxy settings of the system,
+    /// like the `HTTPS_PROXY` environment variable, are ignored when it is
+    /// set.
     ///
     /// # Arguments
     ///
-    /// * `proxy` - The HTTP URL of the proxy.
+    /// * `proxy` - The URL of the proxy.
     ///
     /// # Examples
     ///
@@ -320,6 +348,29 @@ impl ClientBuilder {
         self
     }
 
+    /// Ignore the proxy settings of the system, like the `HTTPS_PROXY`
+    /// environment variable, when no proxy is set with
+    /// [`proxy()`][ClientBuilder::proxy].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn disable_system_proxy(mut self) -> Self {
+        self.http_settings().disable_system_proxy = true;
+        self
+    }
+
+    /// Never resolve the host names locally, to avoid leaking them through DNS
+    /// requests.
+    ///
+    /// This requires a proxy to be set with [`proxy()`][ClientBuilder::proxy],
+    /// otherwise [`build()`][ClientBuilder::build] fails. With a SOCKS5 proxy,
+    /// the host names are resolved by the proxy, as if a `socks5h://` URL was
+    /// used. This is necessary to reach `.onion` addresses through Tor for
+    /// example.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn disable_local_dns_resolution(mut self) -> Self {
+        self.http_settings().disable_local_dns_resolution = true;
+        self
+    }
+
     /// Disable SSL verification for the HTTP requests.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn disable_ssl_verification(mut self) -> Self {
@@ -334,16 +385,17 @@ impl ClientBuilder {
         self
     }
 
-    /// Add the given list of certificates to the certificate store of the HTTP
-    /// client.
+    /// Add the given list of DER-encoded certificates to the certificate store
+    /// of the HTTP client.
     ///
     /// These additional certificates will be trusted and considered when
-    /// establishing a HTTP request.
+    /// establishing a HTTP request, including when the certificates are pinned
+    /// with [`pin_certificates()`][ClientBuilder::pin_certificates].
     ///
     /// Internally this will call the
     /// [`reqwest::ClientBuilder::add_root_certificate()`] method.
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn add_root_certificates(mut self, certificates: Vec<reqwest::Certificate>) -> Self {
+    pub fn add_root_certificates(mut self, certificates: Vec<CertificateDer<'static>>) -> Self {
         self.http_settings().additional_root_certificates = certificates;
         self
     }
@@ -357,6 +409,44 @@ impl ClientBuilder {
         self
     }
 
+    /// Only trust the servers whose certificate chain contains one of the
+    /// given public keys.
+    ///
+    /// The pinned keys can be the ones of the certificates of the servers, or
+    /// of one of their issuers. Several pins can be set, to pin a backup key or
+    /// to rotate the keys without breaking the existing clients.
+    ///
+    /// The certificate chains must still be issued by one of the root
+    /// certificates, including the ones added with
+    /// [`add_root_certificates()`][ClientBuilder::add_root_certificates], or by
+    /// one of the pinned issuers. When the key of the server itself is pinned,
+    /// its certificate can be self-signed, but it must still be valid.
+    /// [`disable_ssl_verification()`][ClientBuilder::disable_ssl_verification]
+    /// is ignored when pins are set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use matrix_sdk::{CertificatePin, Client};
+    ///
+    /// let client_builder = Client::builder().pin_certificates([
+    ///     // The current key of the server.
+    ///     CertificatePin::from_base64(
+    ///         "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+    ///     )?,
+    ///     // The backup key.
+    ///     CertificatePin::from_base64(
+    ///         "sha256/jNz0SbYE0n9wjoFjhoHzTvNfEb0fQ0Z8zfOEA2n6XZM=",
+    ///     )?,
+    /// ]);
+    /// # anyhow::Ok(())
+    /// ```
+    #[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+    pub fn pin_certificates(mut self, pins: impl IntoIterator<Item = CertificatePin>) -> Self {
+        self.http_settings().certificate_pins = pins.into_iter().collect();
+        self
+    }
+
     /// Specify a [`reqwest::Client`] instance to handle sending requests and
     /// receiving responses.
     ///
@@ -371,6 +461,28 @@ impl ClientBuilder {
         self
     }
 
+    /// Add a middleware to inspect or modify the HTTP requests sent to the
+    /// homeserver, and their responses.
+    ///
+    /// Middlewares are called in the order they were added. See
+    /// [`HttpMiddleware`] for more details.
+    pub fn add_http_middleware(mut self, middleware: impl HttpMiddleware + 'static) -> Self {
+        self.http_middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Set the clock used by the client to wait, for example before retrying
+    /// a request or sending batched receipts.
+    ///
+    /// Defaults to the [`SystemClock`]. Tests can use a [`TestClock`] to
+    /// advance the time without waiting.
+    ///
+    /// [`TestClock`]: crate::clock::TestClock
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Specify the Matrix versions supported by the homeserver manually, rather
     /// than `build()` doing it using a `get_supported_versions` request.
     ///
@@ -411,6 +523,23 @@ impl ClientBuilder {
         self
     }
 
+    /// Refresh the access token when it is about to expire, instead of waiting
+    /// for the homeserver to reject it.
+    ///
+    /// Before sending an authenticated request, the `Client` will refresh the
+    /// access token if it expires within the given `margin`. This avoids a
+    /// failed request and a retry for every request sent around the expiration
+    /// of the access token.
+    ///
+    /// This only has an effect if [`ClientBuilder::handle_refresh_tokens()`]
+    /// is enabled, and if the server tells us when the access token expires.
+    /// If the proactive refresh fails, the request is sent anyway and the
+    /// regular handling of expired access tokens applies.
+    pub fn refresh_access_token_ahead_of_expiry(mut self, margin: Duration) -> Self {
+        self.refresh_access_token_ahead_of_expiry = Some(margin);
+        self
+    }
+
     /// Public for test only
     #[doc(hidden)]
     pub fn base_client(mut self, base_client: BaseClient) -> Self {
@@ -481,12 +610,23 @@ impl ClientBuilder {
         let inner_http_client = match self.http_cfg.unwrap_or_default() {
             #[cfg(not(target_arch = "wasm32"))]
             HttpConfig::Settings(mut settings) => {
+                if settings.disable_local_dns_resolution && settings.proxy.is_none() {
+                    return Err(ClientBuildError::MissingProxy);
+                }
+
                 settings.timeout = self.request_config.timeout;
                 settings.make_client()?
             }
             HttpConfig::Custom(c) => c,
         };
 
+        // The store configuration is ignored when a base client is provided.
+        let session_store_location = if self.base_client.is_none() {
+            self.store_config.session_store_location()
+        } else {
+            None
+        };
+
         let base_client = if let Some(base_client) = self.base_client {
             base_client
         } else {
@@ -505,7 +645,10 @@ impl ClientBuilder {
             client
         };
 
-        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
+        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config)
+            .with_middlewares(self.http_middlewares)
+            .with_clock(self.clock)
+            .with_request_budgets(self.request_budgets);
 
         #[allow(unused_variables)]
         let HomeserverDiscoveryResult { server, homeserver, supported_versions } =
@@ -531,10 +674,13 @@ impl ClientBuilder {
 
         let auth_ctx = Arc::new(AuthCtx {
             handle_refresh_tokens: self.handle_refresh_tokens,
+            refresh_access_token_ahead_of_expiry: self.refresh_access_token_ahead_of_expiry,
+            access_token_expires_at: Default::default(),
             refresh_token_lock: Arc::new(Mutex::new(Ok(()))),
             session_change_sender: broadcast::Sender::new(1),
             auth_data: OnceCell::default(),
             tokens: OnceCell::default(),
+            is_guest: Default::default(),
             reload_session_callback: OnceCell::default(),
             save_session_callback: OnceCell::default(),
             oauth: OAuthCtx::new(allow_insecure_oauth),
@@ -566,6 +712,10 @@ impl ClientBuilder {
         )
         .await;
 
+        if let Some(location) = session_store_location {
+            let _ = inner.session_store_location.set(location);
+        }
+
         debug!("Done building the Client");
 
         Ok(Client { inner })
@@ -638,26 +788,27 @@ async fn build_indexeddb_store_config(
     let cross_process_store_locks_holder_name = cross_process_store_locks_holder_name.to_owned();
 
     #[cfg(feature = "e2e-encryption")]
-    let store_config = {
+    let (state_store, store_config) = {
         let (state_store, crypto_store) =
             matrix_sdk_indexeddb::open_stores_with_name(name, passphrase).await?;
-        StoreConfig::new(cross_process_store_locks_holder_name)
-            .state_store(state_store)
-            .crypto_store(crypto_store)
+        (
+            state_store,
+            StoreConfig::new(cross_process_store_locks_holder_name).crypto_store(crypto_store),
+        )
     };
 
     #[cfg(not(feature = "e2e-encryption"))]
-    let store_config = {
+    let (state_store, store_config) = {
         let state_store = matrix_sdk_indexeddb::open_state_store(name, passphrase).await?;
-        StoreConfig::new(cross_process_store_locks_holder_name).state_store(state_store)
+        (state_store, StoreConfig::new(cross_process_store_locks_holder_name))
     };
 
-    let store_config = {
-        tracing::warn!("The IndexedDB backend does not implement an event cache store, falling back to the in-memory event cache store…");
-        store_config.event_cache_store(matrix_sdk_base::event_cache::store::MemoryStore::new())
-    };
+    // The event cache store shares the name and the encryption key of the state
+    // store, so the timelines and the media of the send queue survive a reload.
+    let event_cache_store =
+        matrix_sdk_indexeddb::open_event_cache_store(name, &state_store).await?;
 
-    Ok(store_config)
+    Ok(store_config.state_store(state_store).event_cache_store(event_cache_store))
 }
 
 #[cfg(all(not(target_arch = "wasm32"), feature = "indexeddb"))]
@@ -718,6 +869,26 @@ enum BuilderStoreConfig {
     Custom(StoreConfig),
 }
 
+impl BuilderStoreConfig {
+    /// The location of the stores, for [`Client::serialize_session()`].
+    fn session_store_location(&self) -> Option<SessionStoreLocation> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite { config, cache_path } => Some(SessionStoreLocation::Sqlite {
+                path: config.get_path().to_owned(),
+                cache_path: cache_path.clone(),
+                passphrase: config.get_passphrase().map(ToOwned::to_owned),
+            }),
+            #[cfg(feature = "indexeddb")]
+            Self::IndexedDb { name, passphrase } => Some(SessionStoreLocation::IndexedDb {
+                name: name.clone(),
+                passphrase: passphrase.clone(),
+            }),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for BuilderStoreConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -755,6 +926,10 @@ pub enum ClientBuildError {
     #[error("Error looking up the .well-known endpoint on auto-discovery")]
     AutoDiscovery(FromHttpResponseError<RumaApiError>),
 
+    /// Local DNS resolution was disabled, but no proxy was configured.
+    #[error("local DNS resolution was disabled, but no proxy was configured")]
+    MissingProxy,
+
     /// Error when building the sliding sync version.
     #[error(transparent)]
     SlidingSyncVersion(#[from] crate::sliding_sync::VersionBuilderError),
@@ -976,6 +1151,139 @@ pub(crate) mod tests {
 
     /* Helper functions */
 
+    #[async_test]
+    async fn test_disable_local_dns_resolution_without_proxy() {
+        let homeserver = make_mock_homeserver().await;
+
+        let error = ClientBuilder::new()
+            .homeserver_url(homeserver.uri())
+            .disable_local_dns_resolution()
+            .build()
+            .await
+            .unwrap_err();
+
+        assert_matches!(error, ClientBuildError::MissingProxy);
+    }
+
+    #[async_test]
+    async fn test_requests_go_through_proxy() {
+        // The mock server acts as the HTTP proxy, and receives the requests for a
+        // homeserver whose name can't be resolved.
+        let proxy = make_mock_homeserver().await;
+
+        let client = ClientBuilder::new()
+            .homeserver_url("http://matrix.invalid")
+            .proxy(proxy.uri())
+            .disable_local_dns_resolution()
+            .build()
+            .await
+            .unwrap();
+
+        client.server_versions().await.unwrap();
+    }
+
+    #[async_test]
+    #[cfg(feature = "rustls-tls")]
+    async fn test_pin_certificates() {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        let matching_pin = CertificatePin::from_certificate_der(cert.der()).unwrap();
+        let mismatching_pin = CertificatePin::from_sha256([0; 32]);
+        let homeserver_url = make_tls_mock_homeserver(cert, key_pair).await;
+
+        // The certificate of the server is trusted because its key is pinned, even if
+        // it is self-signed.
+        let client = ClientBuilder::new()
+            .homeserver_url(&homeserver_url)
+            .pin_certificates([matching_pin])
+            .build()
+            .await
+            .unwrap();
+        client.server_versions().await.unwrap();
+
+        // The certificate of the server is rejected if its key is not pinned.
+        let client = ClientBuilder::new()
+            .homeserver_url(&homeserver_url)
+            .pin_certificates([mismatching_pin])
+            .build()
+            .await
+            .unwrap();
+        client.server_versions().await.unwrap_err();
+
+        // The pins don't apply to plain HTTP.
+        let homeserver = make_mock_homeserver().await;
+        let client = ClientBuilder::new()
+            .homeserver_url(homeserver.uri())
+            .pin_certificates([mismatching_pin])
+            .build()
+            .await
+            .unwrap();
+        client.server_versions().await.unwrap();
+    }
+
+    /// Start a HTTPS server for `localhost` with the given certificate, that
+    /// answers every request with the supported versions.
+    ///
+    /// Returns the URL of the server.
+    #[cfg(feature = "rustls-tls")]
+    async fn make_tls_mock_homeserver(
+        certificate: rcgen::Certificate,
+        key_pair: rcgen::KeyPair,
+    ) -> String {
+        use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certificate.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der())),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+
+                tokio::spawn(async move {
+                    // The handshake fails when the client rejects the certificate.
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+
+                    // Read the whole request, which doesn't have a body.
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(len) => request.extend_from_slice(&buffer[..len]),
+                        }
+                    }
+
+                    let body = serde_json::to_string(&*test_json::VERSIONS).unwrap();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        format!("https://localhost:{port}")
+    }
+
     async fn make_mock_homeserver() -> MockServer {
         let homeserver = MockServer::start().await;
         Mock::given(method("GET"))
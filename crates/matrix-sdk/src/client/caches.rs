@@ -12,11 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+
 use matrix_sdk_base::ttl_cache::TtlCache;
-use ruma::api::client::discovery::get_authorization_server_metadata::msc2965::AuthorizationServerMetadata;
-use tokio::sync::RwLock;
+use ruma::{
+    api::client::discovery::get_authorization_server_metadata::msc2965::AuthorizationServerMetadata,
+    UInt,
+};
+use tokio::sync::{Mutex, RwLock};
+use url::Url;
 
-use super::ClientServerCapabilities;
+use super::{homeserver_capabilities::HomeserverCapabilitiesCache, ClientServerCapabilities};
+use crate::{
+    identity_server::HashDetails, profile::ProfileCache, room_preview::RoomPreviewCache,
+    user_search::KnownUsersCache,
+};
 
 /// A collection of in-memory data that the `Client` might want to cache to
 /// avoid hitting the homeserver every time users request the data.
@@ -24,5 +34,24 @@ pub(crate) struct ClientCaches {
     /// Server capabilities, either prefilled during building or fetched from
     /// the server.
     pub(super) server_capabilities: RwLock<ClientServerCapabilities>,
-    pub(crate) server_metadata: tokio::sync::Mutex<TtlCache<String, AuthorizationServerMetadata>>,
+    /// The capabilities returned by [`Client::server_capabilities()`].
+    ///
+    /// [`Client::server_capabilities()`]: crate::Client::server_capabilities
+    pub(super) homeserver_capabilities: HomeserverCapabilitiesCache,
+    pub(crate) server_metadata: Mutex<TtlCache<String, AuthorizationServerMetadata>>,
+    /// The maximum size of an upload, from the media configuration of the
+    /// homeserver.
+    pub(crate) max_upload_size: Mutex<TtlCache<(), UInt>>,
+    /// The profiles of users.
+    pub(crate) profiles: ProfileCache,
+    /// The members of the joined rooms, for the user searches.
+    pub(crate) known_users: KnownUsersCache,
+    /// The previews of rooms.
+    pub(crate) room_previews: RoomPreviewCache,
+    /// The access tokens registered with identity servers, keyed by their base
+    /// URL.
+    pub(crate) identity_server_tokens: Mutex<BTreeMap<Url, String>>,
+    /// The hashing parameters of the lookups of identity servers, keyed by
+    /// their base URL.
+    pub(crate) identity_server_hash_details: Mutex<BTreeMap<Url, HashDetails>>,
 }
@@ -21,14 +21,17 @@ use eyeball::SharedObservable;
 use eyeball::Subscriber;
 use matrix_sdk_common::boxed_into_future;
 use oauth2::{basic::BasicErrorResponseType, RequestTokenError};
-use ruma::api::{client::error::ErrorKind, error::FromHttpResponseError, OutgoingRequest};
-use tracing::{error, trace};
+use ruma::api::{
+    client::error::ErrorKind, error::FromHttpResponseError, AuthScheme, OutgoingRequest,
+};
+use tracing::{error, trace, warn};
 
 use super::super::Client;
 use crate::{
     authentication::oauth::OAuthError,
     config::RequestConfig,
     error::{HttpError, HttpResult},
+    http_client::PartialDownload,
     RefreshTokenError, TransmissionProgress,
 };
 
@@ -39,6 +42,7 @@ pub struct SendRequest<R> {
     pub(crate) request: R,
     pub(crate) config: Option<RequestConfig>,
     pub(crate) send_progress: SharedObservable<TransmissionProgress>,
+    pub(crate) download: Option<PartialDownload>,
 }
 
 impl<R> SendRequest<R> {
@@ -56,6 +60,15 @@ impl<R> SendRequest<R> {
         self
     }
 
+    /// Download the body of the response into the given [`PartialDownload`].
+    ///
+    /// This allows to track the progress of the download, and to resume it if
+    /// it was interrupted.
+    pub(crate) fn with_partial_download(mut self, download: PartialDownload) -> Self {
+        self.download = Some(download);
+        self
+    }
+
     /// Use the given [`RequestConfig`] for this send request, instead of the
     /// one provided by default.
     pub fn with_request_config(mut self, request_config: impl Into<Option<RequestConfig>>) -> Self {
@@ -81,11 +94,31 @@ where
     boxed_into_future!();
 
     fn into_future(self) -> Self::IntoFuture {
-        let Self { client, request, config, send_progress } = self;
+        let Self { client, request, config, send_progress, download } = self;
 
         Box::pin(async move {
-            let res =
-                Box::pin(client.send_inner(request.clone(), config, send_progress.clone())).await;
+            // Refresh the access token before it expires, rather than waiting for the
+            // request to fail.
+            if matches!(
+                R::METADATA.authentication,
+                AuthScheme::AccessToken | AuthScheme::AccessTokenOptional
+            ) && client.inner.auth_ctx.access_token_expires_soon()
+            {
+                trace!("Token refresh: The access token is about to expire, refreshing it.");
+
+                if let Err(refresh_error) = client.refresh_access_token().await {
+                    // Send the request anyway, an `M_UNKNOWN_TOKEN` error is handled below.
+                    warn!("Token refresh: Refreshing ahead of expiry failed: {refresh_error}");
+                }
+            }
+
+            let res = Box::pin(client.send_inner(
+                request.clone(),
+                config,
+                send_progress.clone(),
+                download.clone(),
+            ))
+            .await;
 
             // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh.
             if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
@@ -141,7 +174,8 @@ where
                     }
                 } else {
                     trace!("Token refresh: Refresh succeeded, retrying request.");
-                    return Box::pin(client.send_inner(request, config, send_progress)).await;
+                    return Box::pin(client.send_inner(request, config, send_progress, download))
+                        .await;
                 }
             }
 
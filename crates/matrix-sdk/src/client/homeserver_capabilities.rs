@@ -0,0 +1,290 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A typed and cached view of what the homeserver supports.
+
+use std::collections::BTreeMap;
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::{store::ServerCapabilities, StateStoreDataKey, StateStoreDataValue};
+use ruma::{
+    api::{
+        client::discovery::{discover_homeserver, get_capabilities::Capabilities},
+        MatrixVersion,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use super::Client;
+use crate::{config::RequestConfig, HttpResult};
+
+/// The unstable feature advertising support for the stable authenticated
+/// media endpoints, before Matrix 1.11.
+pub(crate) const AUTHENTICATED_MEDIA_STABLE_FEATURE: &str = "org.matrix.msc3916.stable";
+
+/// The unstable feature advertising support for delayed events
+/// ([MSC4140](https://github.com/matrix-org/matrix-spec-proposals/pull/4140)).
+pub(crate) const DELAYED_EVENTS_FEATURE: &str = "org.matrix.msc4140";
+
+/// The unstable feature advertising support for the native simplified sliding
+/// sync ([MSC4186](https://github.com/matrix-org/matrix-spec-proposals/pull/4186)).
+pub(crate) const SIMPLIFIED_SLIDING_SYNC_FEATURE: &str = "org.matrix.simplified_msc3575";
+
+/// How long the [`HomeserverCapabilities`] are cached before being fetched
+/// again.
+const HOMESERVER_CAPABILITIES_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// What the homeserver supports, as returned by
+/// [`Client::server_capabilities()`].
+#[derive(Clone, Debug)]
+pub struct HomeserverCapabilities {
+    /// The Matrix versions supported by the homeserver.
+    pub versions: Box<[MatrixVersion]>,
+
+    /// The unstable features advertised by the homeserver, and whether they
+    /// are enabled.
+    pub unstable_features: BTreeMap<String, bool>,
+
+    /// The capabilities of the homeserver for the current user, from the
+    /// `/capabilities` endpoint.
+    ///
+    /// This is `None` if the client is not logged in, since this endpoint
+    /// requires authentication.
+    pub capabilities: Option<Capabilities>,
+
+    /// The client discovery information of the server, from its
+    /// `/.well-known/matrix/client` file.
+    ///
+    /// This is `None` if the homeserver was not discovered from a server name,
+    /// or if the server doesn't publish this file.
+    pub well_known: Option<discover_homeserver::Response>,
+}
+
+impl HomeserverCapabilities {
+    /// Whether the homeserver supports the given Matrix version.
+    pub fn supports_version(&self, version: MatrixVersion) -> bool {
+        self.versions.contains(&version)
+    }
+
+    /// Whether the given unstable feature is advertised and enabled by the
+    /// homeserver.
+    pub fn is_unstable_feature_enabled(&self, feature: &str) -> bool {
+        is_unstable_feature_enabled(&self.unstable_features, feature)
+    }
+
+    /// Whether the homeserver supports the authenticated media endpoints.
+    pub fn supports_authenticated_media(&self) -> bool {
+        self.supports_version(MatrixVersion::V1_11)
+            || self.is_unstable_feature_enabled(AUTHENTICATED_MEDIA_STABLE_FEATURE)
+    }
+
+    /// Whether the homeserver supports sending delayed events.
+    pub fn supports_delayed_events(&self) -> bool {
+        self.is_unstable_feature_enabled(DELAYED_EVENTS_FEATURE)
+    }
+
+    /// Whether the homeserver supports the native simplified sliding sync.
+    pub fn supports_simplified_sliding_sync(&self) -> bool {
+        self.is_unstable_feature_enabled(SIMPLIFIED_SLIDING_SYNC_FEATURE)
+    }
+}
+
+/// Whether the given unstable feature is present and enabled in the given
+/// list.
+pub(crate) fn is_unstable_feature_enabled(
+    unstable_features: &BTreeMap<String, bool>,
+    feature: &str,
+) -> bool {
+    unstable_features.get(feature).copied().unwrap_or(false)
+}
+
+/// The in-memory cache of the [`HomeserverCapabilities`].
+#[derive(Default)]
+pub(crate) struct HomeserverCapabilitiesCache {
+    /// When the capabilities were last fetched.
+    ///
+    /// This lock is also held while fetching the capabilities, so they are
+    /// only fetched once at a time.
+    fetched_at: Mutex<Option<Instant>>,
+
+    /// The last fetched capabilities.
+    capabilities: SharedObservable<Option<HomeserverCapabilities>>,
+}
+
+impl HomeserverCapabilitiesCache {
+    /// Forget the cached capabilities, so they are fetched again next time.
+    pub(crate) async fn reset(&self) {
+        *self.fetched_at.lock().await = None;
+    }
+}
+
+impl Client {
+    /// Get what the homeserver supports.
+    ///
+    /// This combines the Matrix versions and unstable features from
+    /// `/versions`, the capabilities from `/capabilities` and the client
+    /// discovery information from `/.well-known/matrix/client`.
+    ///
+    /// The result is cached for an hour. After that, it is fetched again the
+    /// next time this method is called. Use
+    /// [`Client::subscribe_to_server_capabilities()`] to be notified when they
+    /// change.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let capabilities = client.server_capabilities().await?;
+    ///
+    /// if capabilities.supports_delayed_events() {
+    ///     // Schedule a delayed event.
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn server_capabilities(&self) -> HttpResult<HomeserverCapabilities> {
+        let cache = &self.inner.caches.homeserver_capabilities;
+        let mut fetched_at = cache.fetched_at.lock().await;
+
+        if let (Some(fetched_at), Some(capabilities)) = (*fetched_at, cache.capabilities.get()) {
+            if fetched_at.elapsed() < HOMESERVER_CAPABILITIES_TTL {
+                return Ok(capabilities);
+            }
+        }
+
+        let capabilities = self.fetch_homeserver_capabilities().await?;
+
+        *fetched_at = Some(Instant::now());
+        cache.capabilities.set(Some(capabilities.clone()));
+
+        Ok(capabilities)
+    }
+
+    /// Subscribe to the changes of the capabilities of the homeserver.
+    ///
+    /// The value is `None` until [`Client::server_capabilities()`] is called
+    /// for the first time. Then it is updated every time the capabilities are
+    /// fetched again.
+    pub fn subscribe_to_server_capabilities(&self) -> Subscriber<Option<HomeserverCapabilities>> {
+        self.inner.caches.homeserver_capabilities.capabilities.subscribe()
+    }
+
+    /// Fetch the capabilities of the homeserver from the network, and update
+    /// the cache of the server versions and unstable features with them.
+    async fn fetch_homeserver_capabilities(&self) -> HttpResult<HomeserverCapabilities> {
+        debug!("Fetching the homeserver capabilities");
+
+        let (versions, unstable_features) = self.fetch_server_capabilities(None).await?;
+
+        {
+            let mut guard = self.inner.caches.server_capabilities.write().await;
+            guard.server_versions = Some(versions.clone());
+            guard.unstable_features = Some(unstable_features.clone());
+
+            let encoded = ServerCapabilities::new(&versions, unstable_features.clone());
+            if let Err(err) = self
+                .state_store()
+                .set_kv_data(
+                    StateStoreDataKey::ServerCapabilities,
+                    StateStoreDataValue::ServerCapabilities(encoded),
+                )
+                .await
+            {
+                warn!("error when caching server capabilities: {err}");
+            }
+        }
+
+        let capabilities =
+            if self.session_meta().is_some() { Some(self.get_capabilities().await?) } else { None };
+
+        let well_known = match self.server() {
+            Some(server) => self
+                .inner
+                .http_client
+                .send(
+                    discover_homeserver::Request::new(),
+                    Some(RequestConfig::short_retry()),
+                    server.to_string(),
+                    None,
+                    &[MatrixVersion::V1_0],
+                    Default::default(),
+                    None,
+                )
+                .await
+                .inspect_err(|err| warn!("couldn't fetch the client well-known file: {err}"))
+                .ok(),
+            None => None,
+        };
+
+        Ok(HomeserverCapabilities { versions, unstable_features, capabilities, well_known })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_test::async_test;
+    use ruma::api::MatrixVersion;
+
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    #[async_test]
+    async fn test_server_capabilities() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        server.mock_versions().ok_with_unstable_features().expect(1).mount().await;
+        server.mock_capabilities().ok().expect(1).mount().await;
+
+        let mut subscriber = client.subscribe_to_server_capabilities();
+        assert!(subscriber.get().is_none());
+
+        let capabilities = client.server_capabilities().await.unwrap();
+        assert!(capabilities.supports_version(MatrixVersion::V1_11));
+        assert!(capabilities.supports_authenticated_media());
+        assert!(capabilities.supports_delayed_events());
+        assert!(!capabilities.supports_simplified_sliding_sync());
+        assert!(!capabilities.capabilities.unwrap().change_password.enabled);
+        assert!(capabilities.well_known.is_none());
+
+        assert_matches!(subscriber.next().await, Some(Some(_)));
+
+        // The capabilities are cached, the endpoints are not called again.
+        client.server_capabilities().await.unwrap();
+
+        // The server versions cache is updated too.
+        assert!(client.unstable_features().await.unwrap().contains_key("org.matrix.msc4140"));
+    }
+
+    #[async_test]
+    async fn test_reset_server_capabilities() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        server.mock_versions().ok().expect(2).mount().await;
+        server.mock_capabilities().ok().expect(2).mount().await;
+
+        let capabilities = client.server_capabilities().await.unwrap();
+        assert!(!capabilities.supports_delayed_events());
+
+        // After a reset, the capabilities are fetched again.
+        client.reset_server_capabilities().await.unwrap();
+        client.server_capabilities().await.unwrap();
+    }
+}
@@ -19,7 +19,10 @@ use std::{
     fmt::{self, Debug},
     future::{ready, Future},
     pin::Pin,
-    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock, Weak},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex, RwLock as StdRwLock, Weak,
+    },
 };
 
 use caches::ClientCaches;
@@ -36,9 +39,7 @@ use matrix_sdk_base::{
     BaseClient, RoomInfoNotableUpdate, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta,
     StateStoreDataKey, StateStoreDataValue, SyncOutsideWasm,
 };
-use matrix_sdk_common::ttl_cache::TtlCache;
-#[cfg(feature = "e2e-encryption")]
-use ruma::events::{room::encryption::RoomEncryptionEventContent, InitialStateEvent};
+use matrix_sdk_common::{executor::spawn, ttl_cache::TtlCache};
 use ruma::{
     api::{
         client::{
@@ -53,7 +54,7 @@ use ruma::{
             error::ErrorKind,
             filter::{create_filter::v3::Request as FilterUploadRequest, FilterDefinition},
             knock::knock_room,
-            membership::{join_room_by_id, join_room_by_id_or_alias},
+            membership::{join_room_by_id, join_room_by_id_or_alias, ThirdPartySigned},
             room::create_room,
             session::login::v3::DiscoveryInfo,
             sync::sync_events,
@@ -64,10 +65,11 @@ use ruma::{
         MatrixVersion, OutgoingRequest,
     },
     assign,
+    events::identity_server::IdentityServerEventContent,
     push::Ruleset,
     time::Instant,
     DeviceId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName,
-    RoomAliasId, RoomId, RoomOrAliasId, ServerName, UInt, UserId,
+    OwnedUserId, RoomAliasId, RoomId, RoomOrAliasId, ServerName, UInt, UserId,
 };
 use serde::de::DeserializeOwned;
 use tokio::sync::{broadcast, Mutex, OnceCell, RwLock, RwLockReadGuard};
@@ -76,26 +78,35 @@ use url::Url;
 
 use self::futures::SendRequest;
 use crate::{
+    account::PendingUsage,
     authentication::{
         matrix::MatrixAuth, oauth::OAuth, AuthCtx, AuthData, ReloadSessionCallback,
         SaveSessionCallback,
     },
-    config::RequestConfig,
+    bandwidth::BandwidthProfile,
+    clock::Clock,
+    config::{RequestCategory, RequestConfig},
     deduplicating_handler::DeduplicatingHandler,
     error::HttpResult,
     event_cache::EventCache,
     event_handler::{
         EventHandler, EventHandlerContext, EventHandlerDropGuard, EventHandlerHandle,
-        EventHandlerStore, ObservableEventHandler, SyncEvent,
+        EventHandlerMetrics, EventHandlerStore, EventHandlerSupervision, ObservableEventHandler,
+        SyncEvent,
     },
-    http_client::HttpClient,
+    http_client::{HttpClient, PartialDownload},
+    identity_server::IdentityServer,
+    message_search::{MessageSearch, MessageSearchOptions},
     notification_settings::NotificationSettings,
+    room::{builder::RoomBuilder, receipts::PendingReceipts, TypingGuards},
     room_preview::RoomPreview,
     send_queue::SendQueueData,
+    session_handoff::SessionStoreLocation,
     sliding_sync::Version as SlidingSyncVersion,
     sync::{RoomUpdate, SyncResponse},
-    Account, AuthApi, AuthSession, Error, HttpError, Media, Pusher, RefreshTokenError, Result,
-    Room, SessionTokens, TransmissionProgress,
+    user_search::UserSearch,
+    Account, AuthApi, AuthSession, Error, HttpDiagnostics, HttpError, Media, Pusher,
+    RefreshTokenError, RequestCategoryMetrics, Result, Room, SessionTokens, TransmissionProgress,
 };
 #[cfg(feature = "e2e-encryption")]
 use crate::{
@@ -106,8 +117,12 @@ use crate::{
 mod builder;
 pub(crate) mod caches;
 pub(crate) mod futures;
+pub(crate) mod homeserver_capabilities;
 
-pub use self::builder::{sanitize_server_name, ClientBuildError, ClientBuilder};
+pub use self::{
+    builder::{sanitize_server_name, ClientBuildError, ClientBuilder},
+    homeserver_capabilities::HomeserverCapabilities,
+};
 
 #[cfg(not(target_arch = "wasm32"))]
 type NotificationHandlerFut = Pin<Box<dyn Future<Output = ()> + Send>>;
@@ -120,6 +135,11 @@ type NotificationHandlerFn =
 #[cfg(target_arch = "wasm32")]
 type NotificationHandlerFn = Box<dyn Fn(Notification, Room, Client) -> NotificationHandlerFut>;
 
+/// The ID of a notification handler, to remove it with
+/// [`Client::remove_notification_handler()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct NotificationHandlerId(u64);
+
 /// Enum controlling if a loop running callbacks should continue or abort.
 ///
 /// This is mainly used in the [`sync_with_callback`] method, the return value
@@ -161,6 +181,28 @@ pub(crate) struct ClientLocks {
     /// explanation.
     pub(crate) mark_as_dm_lock: Mutex<()>,
 
+    /// Lock ensuring that only a single emoji may be recorded as recently used
+    /// at once. Look at the [`Account::add_recent_emoji()`] method for a more
+    /// detailed explanation.
+    pub(crate) recent_emojis_lock: Mutex<()>,
+
+    /// Lock ensuring that only a single message may be recorded for the
+    /// frequent contacts at once. Look at the
+    /// [`Account::add_frequent_contacts()`] method for a more detailed
+    /// explanation.
+    pub(crate) frequent_contacts_lock: Mutex<()>,
+
+    /// Lock ensuring that only a single room may be pushed to the breadcrumbs
+    /// at once. Look at the [`Account::push_breadcrumb()`] method for a more
+    /// detailed explanation.
+    pub(crate) breadcrumbs_lock: Mutex<()>,
+
+    /// Lock ensuring that only a single DM is looked up or created at once by
+    /// [`Client::dm_with()`], so it doesn't create duplicate DMs. It holds the
+    /// DMs created by this method, until they are marked as direct messages
+    /// locally.
+    pub(crate) dm_with_lock: Mutex<BTreeMap<OwnedUserId, OwnedRoomId>>,
+
     /// Lock ensuring that only a single secret store is getting opened at the
     /// same time.
     ///
@@ -208,6 +250,15 @@ pub(crate) struct ClientLocks {
     /// internal implementation detail, see [`Self::send_single_receipt`].
     pub(crate) read_receipt_deduplicated_handler: DeduplicatingHandler<(String, OwnedEventId)>,
 
+    /// Handler to ensure that only one download of a media content is running
+    /// at a time, given the unique key of its request.
+    pub(crate) media_download_deduplicated_handler: DeduplicatingHandler<String>,
+
+    /// The downloads of media content that are ongoing or were interrupted,
+    /// given the unique key of their request, so they can be shared or
+    /// resumed.
+    pub(crate) partial_media_downloads: StdMutex<BTreeMap<String, PartialDownload>>,
+
     #[cfg(feature = "e2e-encryption")]
     pub(crate) cross_process_crypto_store_lock:
         OnceCell<CrossProcessStoreLock<LockableCryptoStore>>,
@@ -288,11 +339,31 @@ pub(crate) struct ClientInner {
     /// keyed by room.
     pub(crate) typing_notice_times: StdRwLock<BTreeMap<OwnedRoomId, Instant>>,
 
+    /// The live [`TypingGuard`]s, keyed by room.
+    ///
+    /// [`TypingGuard`]: crate::room::TypingGuard
+    pub(crate) typing_guards: StdMutex<BTreeMap<OwnedRoomId, TypingGuards>>,
+
+    /// The read receipts queued or sent by [`Room::queue_receipt()`], keyed by
+    /// room.
+    pub(crate) pending_receipts: StdMutex<BTreeMap<OwnedRoomId, Arc<Mutex<PendingReceipts>>>>,
+
+    /// The uses of emojis and contacts waiting to be recorded in the account
+    /// data, see [`USAGE_BATCH_DELAY`](crate::account::USAGE_BATCH_DELAY).
+    pub(crate) pending_usage: StdMutex<PendingUsage>,
+
+    /// The current bandwidth profile, see [`Client::set_bandwidth_profile()`].
+    pub(crate) bandwidth_profile: SharedObservable<BandwidthProfile>,
+
     /// Event handlers. See `add_event_handler`.
     pub(crate) event_handlers: EventHandlerStore,
 
     /// Notification handlers. See `register_notification_handler`.
-    notification_handlers: RwLock<Vec<NotificationHandlerFn>>,
+    notification_handlers: RwLock<Vec<(NotificationHandlerId, NotificationHandlerFn)>>,
+
+    /// The rooms the user isn't a member of that were peeked into with
+    /// [`Client::peek_room()`], which aren't in the store.
+    pub(crate) peeked_rooms: StdRwLock<BTreeMap<OwnedRoomId, matrix_sdk_base::Room>>,
 
     /// The sender-side of channels used to receive room updates.
     pub(crate) room_update_channels: StdMutex<BTreeMap<OwnedRoomId, broadcast::Sender<RoomUpdate>>>,
@@ -312,6 +383,10 @@ pub(crate) struct ClientInner {
     /// store.
     pub(crate) sync_beat: event_listener::Event,
 
+    /// An event that is fired to stop the running sync loops, see
+    /// [`Client::stop_sync()`].
+    pub(crate) sync_stop: event_listener::Event,
+
     /// A central cache for events, inactive first.
     ///
     /// It becomes active when [`EventCache::subscribe`] is called.
@@ -329,6 +404,10 @@ pub(crate) struct ClientInner {
     ///
     /// [`SendQueue`]: crate::send_queue::SendQueue
     pub(crate) send_queue_data: Arc<SendQueueData>,
+
+    /// The location of the stores, if the client has been built with
+    /// persistent stores configured by the [`ClientBuilder`].
+    pub(crate) session_store_location: std::sync::OnceLock<SessionStoreLocation>,
 }
 
 impl ClientInner {
@@ -354,7 +433,14 @@ impl ClientInner {
     ) -> Arc<Self> {
         let caches = ClientCaches {
             server_capabilities: server_capabilities.into(),
+            homeserver_capabilities: Default::default(),
             server_metadata: Mutex::new(TtlCache::new()),
+            max_upload_size: Mutex::new(TtlCache::new()),
+            profiles: Default::default(),
+            known_users: Default::default(),
+            room_previews: Default::default(),
+            identity_server_tokens: Default::default(),
+            identity_server_hash_details: Default::default(),
         };
 
         let client = Self {
@@ -368,16 +454,23 @@ impl ClientInner {
             locks: Default::default(),
             cross_process_store_locks_holder_name,
             typing_notice_times: Default::default(),
+            typing_guards: Default::default(),
+            pending_receipts: Default::default(),
+            pending_usage: Default::default(),
+            bandwidth_profile: Default::default(),
             event_handlers: Default::default(),
             notification_handlers: Default::default(),
+            peeked_rooms: Default::default(),
             room_update_channels: Default::default(),
             // A single `RoomUpdates` is sent once per sync, so we assume that 32 is sufficient
             // ballast for all observers to catch up.
             room_updates_sender: broadcast::Sender::new(32),
             respect_login_well_known,
             sync_beat: event_listener::Event::new(),
+            sync_stop: event_listener::Event::new(),
             event_cache,
             send_queue_data: send_queue,
+            session_store_location: Default::default(),
             #[cfg(feature = "e2e-encryption")]
             e2ee: EncryptionData::new(encryption_settings),
             #[cfg(feature = "e2e-encryption")]
@@ -422,6 +515,26 @@ impl Client {
         self.inner.base_client.subscribe_to_ignore_user_list_changes()
     }
 
+    /// Get the users ignored by the current account.
+    ///
+    /// Users can be ignored with [`Account::ignore_user()`], and unignored
+    /// with [`Account::unignore_user()`]. The events sent by ignored users are
+    /// removed from the event cache, and thus from the timelines.
+    pub fn ignored_users(&self) -> Vec<OwnedUserId> {
+        parse_ignored_users(self.subscribe_to_ignore_user_list_changes().get())
+    }
+
+    /// Get the users ignored by the current account, and a stream of updates
+    /// for them.
+    ///
+    /// See [`Client::ignored_users()`] for more details.
+    pub fn subscribe_to_ignored_users(
+        &self,
+    ) -> (Vec<OwnedUserId>, impl Stream<Item = Vec<OwnedUserId>>) {
+        let subscriber = self.subscribe_to_ignore_user_list_changes();
+        (parse_ignored_users(subscriber.get()), subscriber.map(parse_ignored_users))
+    }
+
     /// Create a new [`ClientBuilder`].
     pub fn builder() -> ClientBuilder {
         ClientBuilder::new()
@@ -502,6 +615,36 @@ impl Client {
         self.inner.http_client.request_config
     }
 
+    /// Subscribe to the diagnostics of the HTTP requests sent by this client.
+    ///
+    /// Every time a request to the homeserver is done, after its final
+    /// response or error, metadata about it is sent to the subscribers, like
+    /// its endpoint, its duration and the number of retries. This can be used
+    /// to build a network inspector in an application for example.
+    ///
+    /// The diagnostics are only collected while there is at least one
+    /// subscriber.
+    pub fn subscribe_to_http_diagnostics(&self) -> broadcast::Receiver<HttpDiagnostics> {
+        self.inner.http_client.subscribe_to_diagnostics()
+    }
+
+    /// Get the metrics of the requests of the given category, like the number
+    /// of queued requests and the time they spent waiting for a concurrency
+    /// slot.
+    ///
+    /// See [`ClientBuilder::request_budget()`] to configure the limits of a
+    /// category.
+    pub fn request_metrics(&self, category: RequestCategory) -> RequestCategoryMetrics {
+        self.inner.http_client.request_metrics(category)
+    }
+
+    /// The clock used by the client to wait.
+    ///
+    /// See [`ClientBuilder::clock()`].
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.inner.http_client.clock
+    }
+
     /// Check whether the client has been activated.
     ///
     /// A client is considered active when:
@@ -554,6 +697,40 @@ impl Client {
         self.base_client().room_info_notable_update_receiver()
     }
 
+    /// Search users, for example to pick whom to invite in a room.
+    ///
+    /// The returned [`UserSearch`] merges the users known locally, i.e. the
+    /// contacts in direct messages and the members of the joined rooms, with
+    /// the results of the user directory of the homeserver. See its
+    /// documentation for more details.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_term` - The search term, matched case-insensitively on user
+    ///   IDs and display names.
+    pub fn user_search(&self, search_term: &str) -> UserSearch {
+        UserSearch::new(self.clone(), search_term)
+    }
+
+    /// Search messages in the rooms.
+    ///
+    /// The returned [`MessageSearch`] merges the results of the search API of
+    /// the homeserver with the events of the event cache, which are the only
+    /// results in encrypted rooms. See its documentation for more details.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_term` - The search term.
+    ///
+    /// * `options` - Which rooms to search, and how.
+    pub fn search_messages(
+        &self,
+        search_term: &str,
+        options: MessageSearchOptions,
+    ) -> MessageSearch {
+        MessageSearch::new(self.clone(), search_term, options)
+    }
+
     /// Performs a search for users.
     /// The search is performed case-insensitively on user IDs and display names
     ///
@@ -648,6 +825,22 @@ impl Client {
         Account::new(self.clone())
     }
 
+    /// Get the identity server that the user configured in their account data,
+    /// if any.
+    pub async fn identity_server(&self) -> Result<Option<IdentityServer>> {
+        let Some(content) = self.account().account_data::<IdentityServerEventContent>().await?
+        else {
+            return Ok(None);
+        };
+
+        let Some(base_url) = content.deserialize()?.base_url.into_option() else {
+            return Ok(None);
+        };
+
+        let base_url = Url::parse(&base_url).map_err(|error| Error::UnknownError(error.into()))?;
+        Ok(Some(IdentityServer::new(self.clone(), base_url)))
+    }
+
     /// Get the encryption manager of the client.
     #[cfg(feature = "e2e-encryption")]
     pub fn encryption(&self) -> Encryption {
@@ -664,6 +857,20 @@ impl Client {
         Pusher::new(self.clone())
     }
 
+    /// Get the sync-less API of the client, to send messages or fetch events
+    /// without syncing first.
+    pub fn lightweight(&self) -> crate::lightweight::Lightweight {
+        crate::lightweight::Lightweight::new(self.clone())
+    }
+
+    /// Access the admin API of Synapse.
+    ///
+    /// The user of the client must be an admin of the homeserver.
+    #[cfg(feature = "synapse-admin")]
+    pub fn synapse_admin(&self) -> crate::synapse_admin::SynapseAdmin {
+        crate::synapse_admin::SynapseAdmin::new(self.clone())
+    }
+
     /// Access the OAuth 2.0 API of the client.
     pub fn oauth(&self) -> OAuth {
         OAuth::new(self.clone())
@@ -1059,6 +1266,26 @@ impl Client {
         self.inner.event_handlers.add_context(ctx);
     }
 
+    /// Set how the event handlers are run, to isolate the sync processing from
+    /// the event handlers that fail, panic or are too slow.
+    ///
+    /// It replaces the previous supervision settings, and applies to the
+    /// events received after this call.
+    pub fn set_event_handler_supervision(&self, supervision: EventHandlerSupervision) {
+        self.inner.event_handlers.set_supervision(supervision);
+    }
+
+    /// Get the metrics about the execution of the event handler identified by
+    /// the given handle.
+    ///
+    /// Returns `None` if the event handler was removed.
+    pub fn event_handler_metrics(
+        &self,
+        handle: &EventHandlerHandle,
+    ) -> Option<EventHandlerMetrics> {
+        self.inner.event_handlers.metrics(handle)
+    }
+
     /// Register a handler for a notification.
     ///
     /// Similar to [`Client::add_event_handler`], but only allows functions
@@ -1069,11 +1296,34 @@ impl Client {
         H: Fn(Notification, Room, Client) -> Fut + SendOutsideWasm + SyncOutsideWasm + 'static,
         Fut: Future<Output = ()> + SendOutsideWasm + 'static,
     {
-        self.inner.notification_handlers.write().await.push(Box::new(
-            move |notification, room, client| Box::pin((handler)(notification, room, client)),
+        self.add_notification_handler(handler).await;
+        self
+    }
+
+    /// Register a handler for a notification, like
+    /// [`Client::register_notification_handler()`], and get its ID to remove
+    /// it later.
+    pub(crate) async fn add_notification_handler<H, Fut>(&self, handler: H) -> NotificationHandlerId
+    where
+        H: Fn(Notification, Room, Client) -> Fut + SendOutsideWasm + SyncOutsideWasm + 'static,
+        Fut: Future<Output = ()> + SendOutsideWasm + 'static,
+    {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NotificationHandlerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+        self.inner.notification_handlers.write().await.push((
+            id,
+            Box::new(move |notification, room, client| {
+                Box::pin((handler)(notification, room, client))
+            }),
         ));
 
-        self
+        id
+    }
+
+    /// Remove the notification handler with the given ID.
+    pub(crate) async fn remove_notification_handler(&self, id: NotificationHandlerId) {
+        self.inner.notification_handlers.write().await.retain(|(handler_id, _)| *handler_id != id);
     }
 
     /// Subscribe to all updates for the room with the given ID.
@@ -1099,7 +1349,7 @@ impl Client {
 
     pub(crate) async fn notification_handlers(
         &self,
-    ) -> RwLockReadGuard<'_, Vec<NotificationHandlerFn>> {
+    ) -> RwLockReadGuard<'_, Vec<(NotificationHandlerId, NotificationHandlerFn)>> {
         self.inner.notification_handlers.read().await
     }
 
@@ -1163,12 +1413,20 @@ impl Client {
     /// # Arguments
     ///
     /// `room_id` - The unique id of the room that should be fetched.
+    ///
+    /// The rooms that were peeked into with [`Client::peek_room()`] are
+    /// returned too.
     pub fn get_room(&self, room_id: &RoomId) -> Option<Room> {
-        self.base_client().get_room(room_id).map(|room| Room::new(self.clone(), room))
+        self.base_client()
+            .get_room(room_id)
+            .or_else(|| self.inner.peeked_rooms.read().unwrap().get(room_id).cloned())
+            .map(|room| Room::new(self.clone(), room))
     }
 
     /// Gets the preview of a room, whether the current user has joined it or
     /// not.
+    ///
+    /// The preview is cached, see [`Client::cached_room_preview()`].
     pub async fn get_room_preview(
         &self,
         room_or_alias_id: &RoomOrAliasId,
@@ -1179,21 +1437,66 @@ impl Client {
             Err(alias) => self.resolve_room_alias(alias).await?.room_id,
         };
 
-        if let Some(room) = self.get_room(&room_id) {
+        let preview = match self.get_room(&room_id) {
             // The cached data can only be trusted if the room state is joined or
             // banned: for invite and knock rooms, no updates will be received
             // for the rooms after the invite/knock action took place so we may
             // have very out to date data for important fields such as
             // `join_rule`. For left rooms, the homeserver should return the latest info.
-            match room.state() {
-                RoomState::Joined | RoomState::Banned => {
-                    return Ok(RoomPreview::from_known_room(&room).await);
-                }
-                RoomState::Left | RoomState::Invited | RoomState::Knocked => {}
+            Some(room) if matches!(room.state(), RoomState::Joined | RoomState::Banned) => {
+                RoomPreview::from_known_room(&room).await
+            }
+            _ => RoomPreview::from_remote_room(self, room_id, room_or_alias_id, via).await?,
+        };
+
+        self.inner.caches.room_previews.set(preview.clone());
+        Ok(preview)
+    }
+
+    /// Get the last preview of the given room returned by
+    /// [`Client::get_room_preview()`], if any, without any request.
+    pub fn cached_room_preview(&self, room_id: &RoomId) -> Option<RoomPreview> {
+        self.inner.caches.room_previews.get(room_id)
+    }
+
+    /// Subscribe to the preview of a room.
+    ///
+    /// The returned [`Subscriber`] starts with the cached preview of the room,
+    /// or a preview built from the local data if the room is known, like for
+    /// an invite, so it can be rendered immediately. The preview is then
+    /// refreshed in the background with [`Client::get_room_preview()`], and
+    /// the subscriber yields the new preview.
+    pub async fn subscribe_to_room_preview(
+        &self,
+        room_or_alias_id: &RoomOrAliasId,
+        via: Vec<OwnedServerName>,
+    ) -> Result<Subscriber<Option<RoomPreview>>> {
+        let room_id = match <&RoomId>::try_from(room_or_alias_id) {
+            Ok(room_id) => room_id.to_owned(),
+            Err(alias) => self.resolve_room_alias(alias).await?.room_id,
+        };
+
+        let previews = &self.inner.caches.room_previews;
+        let observable = previews.observable(&room_id);
+
+        if observable.get().is_none() {
+            if let Some(room) = self.get_room(&room_id) {
+                previews.set(RoomPreview::from_known_room(&room).await);
             }
         }
 
-        RoomPreview::from_remote_room(self, room_id, room_or_alias_id, via).await
+        let subscriber = observable.subscribe();
+
+        let client = self.clone();
+        spawn(async move {
+            if let Err(error) =
+                client.get_room_preview(<&RoomOrAliasId>::from(&*room_id), via).await
+            {
+                warn!(%room_id, "Couldn't refresh the room preview: {error}");
+            }
+        });
+
+        Ok(subscriber)
     }
 
     /// Resolve a room alias to a room id and a list of servers which know
@@ -1421,6 +1724,30 @@ impl Client {
         Ok(Room::new(self.clone(), base_room))
     }
 
+    /// Join a room by `RoomId`, with the proof that the user owns the 3PID
+    /// that was invited to the room.
+    ///
+    /// The proof can be obtained with
+    /// [`IdentityServer::sign_invitation()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to be joined.
+    ///
+    /// * `third_party_signed` - The proof signed by the identity server.
+    pub async fn join_room_by_third_party_signed(
+        &self,
+        room_id: &RoomId,
+        third_party_signed: ThirdPartySigned,
+    ) -> Result<Room> {
+        let request = assign!(join_room_by_id::v3::Request::new(room_id.to_owned()), {
+            third_party_signed: Some(third_party_signed),
+        });
+        let response = self.send(request).await?;
+        let base_room = self.base_client().room_joined(&response.room_id).await?;
+        Ok(Room::new(self.clone(), base_room))
+    }
+
     /// Join a room by `RoomId`.
     ///
     /// Returns a `join_room_by_id_or_alias::Response` consisting of the
@@ -1551,22 +1878,16 @@ impl Client {
     ///
     /// * `user_id` - The ID of the user to create a DM for.
     pub async fn create_dm(&self, user_id: &UserId) -> Result<Room> {
-        #[cfg(feature = "e2e-encryption")]
-        let initial_state =
-            vec![InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
-                .to_raw_any()];
-
-        #[cfg(not(feature = "e2e-encryption"))]
-        let initial_state = vec![];
-
-        let request = assign!(create_room::v3::Request::new(), {
-            invite: vec![user_id.to_owned()],
-            is_direct: true,
-            preset: Some(create_room::v3::RoomPreset::TrustedPrivateChat),
-            initial_state,
-        });
+        self.room_builder().invite([user_id.to_owned()]).direct().await
+    }
 
-        self.create_room(request).await
+    /// Create a room with a [`RoomBuilder`].
+    ///
+    /// The room is created when the builder is awaited. This is easier to use
+    /// than [`Client::create_room()`], and checks that the settings of the room
+    /// are consistent.
+    pub fn room_builder(&self) -> RoomBuilder {
+        RoomBuilder::new(self.clone())
     }
 
     /// Search the homeserver's directory for public rooms with a filter.
@@ -1655,6 +1976,7 @@ impl Client {
             request,
             config: None,
             send_progress: Default::default(),
+            download: None,
         }
     }
 
@@ -1663,6 +1985,7 @@ impl Client {
         request: Request,
         config: Option<RequestConfig>,
         send_progress: SharedObservable<TransmissionProgress>,
+        download: Option<PartialDownload>,
     ) -> HttpResult<Request::IncomingResponse>
     where
         Request: OutgoingRequest + Debug,
@@ -1680,6 +2003,7 @@ impl Client {
                 access_token.as_deref(),
                 &self.server_versions().await?,
                 send_progress,
+                download,
             )
             .await
     }
@@ -1707,6 +2031,7 @@ impl Client {
                 None,
                 &[MatrixVersion::V1_0],
                 Default::default(),
+                None,
             )
             .await?;
 
@@ -1835,8 +2160,13 @@ impl Client {
     /// Since the SDK caches server capabilities (versions and unstable
     /// features), it's possible to have a stale entry in the cache. This
     /// functions makes it possible to force reset it.
+    ///
+    /// This also resets the cache of [`Client::server_capabilities()`] and of
+    /// [`Media::max_upload_size()`](crate::media::Media::max_upload_size).
     pub async fn reset_server_capabilities(&self) -> Result<()> {
         // Empty the in-memory caches.
+        self.inner.caches.homeserver_capabilities.reset().await;
+        self.inner.caches.max_upload_size.lock().await.remove(&());
         let mut guard = self.inner.caches.server_capabilities.write().await;
         guard.server_versions = None;
         guard.unstable_features = None;
@@ -2054,7 +2384,7 @@ impl Client {
     /// [`get_or_upload_filter()`]: #method.get_or_upload_filter
     /// [long polling]: #long-polling
     /// [filtered]: #filtering-events
-    #[instrument(skip(self))]
+    #[instrument(name = "matrix_sdk.sync", skip(self))]
     pub async fn sync_once(
         &self,
         sync_settings: crate::config::SyncSettings,
@@ -2098,11 +2428,11 @@ impl Client {
 
     /// Repeatedly synchronize the client state with the server.
     ///
-    /// This method will only return on error, if cancellation is needed
-    /// the method should be wrapped in a cancelable task or the
-    /// [`Client::sync_with_callback`] method can be used or
-    /// [`Client::sync_with_result_callback`] if you want to handle error
-    /// cases in the loop, too.
+    /// This method will only return on error, or when [`Client::stop_sync`]
+    /// is called. If cancellation is needed otherwise, the method should be
+    /// wrapped in a cancelable task or the [`Client::sync_with_callback`]
+    /// method can be used or [`Client::sync_with_result_callback`] if you
+    /// want to handle error cases in the loop, too.
     ///
     /// This method will internally call [`Client::sync_once`] in a loop.
     ///
@@ -2304,6 +2634,7 @@ impl Client {
         C: Future<Output = Result<LoopCtrl, Error>>,
     {
         let mut last_sync_time: Option<Instant> = None;
+        let mut stop_listener = self.inner.sync_stop.listen();
 
         if sync_settings.token.is_none() {
             sync_settings.token = self.sync_token().await;
@@ -2311,7 +2642,16 @@ impl Client {
 
         loop {
             trace!("Syncing");
-            let result = self.sync_loop_helper(&mut sync_settings).await;
+            let result = tokio::select! {
+                biased;
+
+                _ = &mut stop_listener => {
+                    debug!("The sync loop was stopped");
+                    break;
+                }
+
+                result = self.sync_loop_helper(&mut sync_settings) => result,
+            };
 
             trace!("Running callback");
             if callback(result).await? == LoopCtrl::Break {
@@ -2374,6 +2714,7 @@ impl Client {
         mut sync_settings: crate::config::SyncSettings,
     ) -> impl Stream<Item = Result<SyncResponse>> + '_ {
         let mut last_sync_time: Option<Instant> = None;
+        let mut stop_listener = self.inner.sync_stop.listen();
 
         if sync_settings.token.is_none() {
             sync_settings.token = self.sync_token().await;
@@ -2383,13 +2724,38 @@ impl Client {
 
         async_stream::stream! {
             loop {
-                yield self.sync_loop_helper(&mut sync_settings).instrument(parent_span.clone()).await;
+                let result = tokio::select! {
+                    biased;
+
+                    _ = &mut stop_listener => {
+                        debug!("The sync stream was stopped");
+                        break;
+                    }
+
+                    result = self.sync_loop_helper(&mut sync_settings).instrument(parent_span.clone()) => result,
+                };
+
+                yield result;
 
                 Client::delay_sync(&mut last_sync_time).await
             }
         }
     }
 
+    /// Stop the sync loops that are currently running.
+    ///
+    /// The loops started with [`Client::sync`],
+    /// [`Client::sync_with_callback`] and
+    /// [`Client::sync_with_result_callback`] return `Ok(())`, and the streams
+    /// returned by [`Client::sync_stream`] and [`SlidingSync::sync`] end, even
+    /// if a sync request is in flight. The sync loops started afterwards are
+    /// not affected.
+    ///
+    /// [`SlidingSync::sync`]: crate::sliding_sync::SlidingSync::sync
+    pub fn stop_sync(&self) {
+        self.inner.sync_stop.notify(usize::MAX);
+    }
+
     /// Get the current, if any, sync token of the client.
     /// This will be None if the client didn't sync at least once.
     pub(crate) async fn sync_token(&self) -> Option<String> {
@@ -2399,7 +2765,9 @@ impl Client {
     /// Gets information about the owner of a given access token.
     pub async fn whoami(&self) -> HttpResult<whoami::v3::Response> {
         let request = whoami::v3::Request::new();
-        self.send(request).await
+        let response = self.send(request).await?;
+        self.auth_ctx().is_guest.store(response.is_guest, Ordering::SeqCst);
+        Ok(response)
     }
 
     /// Subscribes a new receiver to client SessionChange broadcasts.
@@ -2555,6 +2923,12 @@ struct ClientServerCapabilities {
     unstable_features: Option<BTreeMap<String, bool>>,
 }
 
+/// Parse the user IDs of the ignored user list, as published by the base
+/// client.
+fn parse_ignored_users(user_ids: Vec<String>) -> Vec<OwnedUserId> {
+    user_ids.into_iter().filter_map(|user_id| user_id.try_into().ok()).collect()
+}
+
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_arch = "wasm32")))]
 pub(crate) mod tests {
@@ -0,0 +1,618 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A registry of [`Client`]s, for apps supporting several accounts at once.
+//!
+//! The [`ClientRegistry`] keeps track of the logged-in clients of an app, one
+//! per account. It provides:
+//!
+//! - a single HTTP client shared by all the accounts, so they use the same
+//!   connection pool,
+//! - a single stream of notifications for all the accounts, and a way to find
+//!   the account that should handle a push notification, from the account the
+//!   pusher was registered with,
+//! - an active account, which is the only one synced in the background if
+//!   [`ClientRegistry::sync_active_account()`] was called. Switching to another
+//!   account suspends the sync of the previous one.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex as StdMutex, Weak},
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::sync::Notification;
+use matrix_sdk_common::executor::{spawn, JoinHandle};
+use ruma::{DeviceId, OwnedUserId, RoomId, UserId};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+use crate::{client::NotificationHandlerId, config::SyncSettings, Client, ClientBuilder, Room};
+
+/// A notification received by one of the accounts of a [`ClientRegistry`].
+#[derive(Clone, Debug)]
+pub struct AccountNotification {
+    /// The ID of the account that received the notification.
+    pub user_id: OwnedUserId,
+
+    /// The notification.
+    pub notification: Notification,
+
+    /// The room where the notification was triggered.
+    pub room: Room,
+}
+
+/// An error that can occur when using a [`ClientRegistry`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ClientRegistryError {
+    /// The client is not logged in, so it can't be associated to an account.
+    #[error("the client is not logged in")]
+    NotLoggedIn,
+
+    /// A client is already registered for this account.
+    #[error("a client is already registered for {0}")]
+    AlreadyRegistered(OwnedUserId),
+
+    /// No client is registered for this account.
+    #[error("no client is registered for {0}")]
+    UnknownAccount(OwnedUserId),
+}
+
+/// A registry of logged-in [`Client`]s, one per account.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Clone)]
+pub struct ClientRegistry {
+    inner: Arc<ClientRegistryInner>,
+}
+
+struct ClientRegistryInner {
+    /// The HTTP client shared by all the clients built with
+    /// [`ClientRegistry::client_builder()`].
+    http_client: reqwest::Client,
+
+    /// The registered clients, by account.
+    accounts: StdMutex<BTreeMap<OwnedUserId, RegisteredClient>>,
+
+    /// The account currently in use.
+    active_account: SharedObservable<Option<OwnedUserId>>,
+
+    /// The settings used to sync the active account, if it should be synced in
+    /// the background.
+    sync_settings: StdMutex<Option<SyncSettings>>,
+
+    /// The sender for the notifications of all the accounts.
+    notification_sender: broadcast::Sender<AccountNotification>,
+}
+
+struct RegisteredClient {
+    client: Client,
+
+    /// The handler forwarding the notifications of this client to the
+    /// registry, once it is registered.
+    notification_handler: Option<NotificationHandlerId>,
+
+    /// The task syncing this client in the background, if any.
+    sync_task: Option<JoinHandle<()>>,
+}
+
+impl RegisteredClient {
+    /// Start syncing the client in the background, if it isn't already.
+    fn start_sync(&mut self, user_id: &UserId, sync_settings: SyncSettings) {
+        if self.sync_task.is_some() {
+            return;
+        }
+
+        debug!(%user_id, "Starting the background sync");
+
+        let client = self.client.clone();
+        let user_id = user_id.to_owned();
+        self.sync_task = Some(spawn(async move {
+            if let Err(error) = client.sync(sync_settings).await {
+                warn!(%user_id, "The background sync stopped: {error}");
+            }
+        }));
+    }
+
+    /// Stop syncing the client in the background.
+    ///
+    /// The sync loop is stopped with [`Client::stop_sync()`], so it isn't
+    /// interrupted in the middle of the processing of a response.
+    fn stop_sync(&mut self, user_id: &UserId) {
+        if self.sync_task.take().is_some() {
+            debug!(%user_id, "Suspending the background sync");
+            self.client.stop_sync();
+        }
+    }
+}
+
+impl ClientRegistry {
+    /// Create a new empty `ClientRegistry`.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client to share between the clients built
+    ///   with [`ClientRegistry::client_builder()`].
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self {
+            inner: Arc::new(ClientRegistryInner {
+                http_client,
+                accounts: Default::default(),
+                active_account: Default::default(),
+                sync_settings: Default::default(),
+                notification_sender: broadcast::Sender::new(32),
+            }),
+        }
+    }
+
+    /// Get a [`ClientBuilder`] using the HTTP client of this registry.
+    ///
+    /// The clients built with it share the same connection pool.
+    pub fn client_builder(&self) -> ClientBuilder {
+        Client::builder().http_client(self.inner.http_client.clone())
+    }
+
+    /// Add a logged-in client to the registry.
+    ///
+    /// If there is no active account yet, the account of this client becomes
+    /// the active one.
+    ///
+    /// Returns an error if the client is not logged in, or if a client is
+    /// already registered for the same account.
+    pub async fn add_client(&self, client: Client) -> Result<(), ClientRegistryError> {
+        let user_id = client.user_id().ok_or(ClientRegistryError::NotLoggedIn)?.to_owned();
+
+        {
+            let mut accounts = self.inner.accounts.lock().unwrap();
+
+            if accounts.contains_key(&user_id) {
+                return Err(ClientRegistryError::AlreadyRegistered(user_id));
+            }
+
+            accounts.insert(
+                user_id.clone(),
+                RegisteredClient {
+                    client: client.clone(),
+                    notification_handler: None,
+                    sync_task: None,
+                },
+            );
+        }
+
+        // The notifications are only forwarded while this client is registered, in
+        // case it is removed before its handler is.
+        let registry = Arc::downgrade(&self.inner);
+        let handler_user_id = user_id.clone();
+        let handler = client
+            .add_notification_handler(move |notification, room, client| {
+                let registry = registry.clone();
+                let user_id = handler_user_id.clone();
+
+                async move {
+                    forward_notification(registry, user_id, notification, room, client);
+                }
+            })
+            .await;
+
+        let handler_registered = match self.inner.accounts.lock().unwrap().get_mut(&user_id) {
+            Some(registered) if Arc::ptr_eq(&registered.client.inner, &client.inner) => {
+                registered.notification_handler = Some(handler);
+                true
+            }
+            _ => false,
+        };
+
+        if !handler_registered {
+            // The client was removed in the meantime.
+            client.remove_notification_handler(handler).await;
+        }
+
+        if self.inner.active_account.get().is_none() {
+            self.activate(&user_id);
+        }
+
+        Ok(())
+    }
+
+    /// Remove the client of the given account from the registry.
+    ///
+    /// Its background sync is stopped, and its notifications are not forwarded
+    /// anymore. If it was the active account, there is no active account
+    /// anymore.
+    ///
+    /// Returns the removed client, if any.
+    pub async fn remove_client(&self, user_id: &UserId) -> Option<Client> {
+        let mut registered = self.inner.accounts.lock().unwrap().remove(user_id)?;
+        registered.stop_sync(user_id);
+
+        if self.inner.active_account.get().as_deref() == Some(user_id) {
+            self.inner.active_account.set(None);
+        }
+
+        if let Some(handler) = registered.notification_handler {
+            registered.client.remove_notification_handler(handler).await;
+        }
+
+        Some(registered.client)
+    }
+
+    /// Get the client of the given account.
+    pub fn client(&self, user_id: &UserId) -> Option<Client> {
+        Some(self.inner.accounts.lock().unwrap().get(user_id)?.client.clone())
+    }
+
+    /// Get the clients of all the registered accounts.
+    pub fn clients(&self) -> Vec<Client> {
+        self.inner.accounts.lock().unwrap().values().map(|r| r.client.clone()).collect()
+    }
+
+    /// Get the IDs of all the registered accounts.
+    pub fn user_ids(&self) -> Vec<OwnedUserId> {
+        self.inner.accounts.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Get the ID of the active account.
+    pub fn active_account(&self) -> Option<OwnedUserId> {
+        self.inner.active_account.get()
+    }
+
+    /// Subscribe to the changes of the active account.
+    pub fn subscribe_to_active_account(&self) -> Subscriber<Option<OwnedUserId>> {
+        self.inner.active_account.subscribe()
+    }
+
+    /// Get the client of the active account.
+    pub fn active_client(&self) -> Option<Client> {
+        self.client(&self.active_account()?)
+    }
+
+    /// Make the given account the active one.
+    ///
+    /// If the active account is synced in the background, the sync of the
+    /// previously active account is suspended, and the sync of the given
+    /// account is started.
+    ///
+    /// Returns the client of the given account.
+    pub fn switch_to(&self, user_id: &UserId) -> Result<Client, ClientRegistryError> {
+        let client = self
+            .client(user_id)
+            .ok_or_else(|| ClientRegistryError::UnknownAccount(user_id.to_owned()))?;
+
+        if self.inner.active_account.get().as_deref() != Some(user_id) {
+            self.activate(user_id);
+        }
+
+        Ok(client)
+    }
+
+    /// Sync the active account in the background, with the given settings.
+    ///
+    /// Only the active account is synced, the other accounts are suspended
+    /// until they become active with [`ClientRegistry::switch_to()`].
+    pub fn sync_active_account(&self, sync_settings: SyncSettings) {
+        *self.inner.sync_settings.lock().unwrap() = Some(sync_settings.clone());
+
+        if let Some(user_id) = self.active_account() {
+            if let Some(registered) = self.inner.accounts.lock().unwrap().get_mut(&user_id) {
+                registered.start_sync(&user_id, sync_settings);
+            }
+        }
+    }
+
+    /// Stop syncing the active account in the background.
+    pub fn stop_syncing(&self) {
+        *self.inner.sync_settings.lock().unwrap() = None;
+
+        for (user_id, registered) in self.inner.accounts.lock().unwrap().iter_mut() {
+            registered.stop_sync(user_id);
+        }
+    }
+
+    /// Whether the given account is currently synced in the background.
+    pub fn is_syncing(&self, user_id: &UserId) -> bool {
+        self.inner
+            .accounts
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .and_then(|registered| registered.sync_task.as_ref())
+            .is_some_and(|sync_task| !sync_task.is_finished())
+    }
+
+    /// Subscribe to the notifications of all the registered accounts.
+    pub fn subscribe_to_notifications(&self) -> broadcast::Receiver<AccountNotification> {
+        self.inner.notification_sender.subscribe()
+    }
+
+    /// Find the client that should handle a push notification.
+    ///
+    /// The push is routed to the account that registered the pusher, if the
+    /// app included its user ID in the data of the pusher, and to the same
+    /// session if the device ID was included too. No other account is used in
+    /// this case, so the push of an account that isn't registered, or of a
+    /// previous session, is not shown by another account.
+    ///
+    /// Otherwise, the active account is preferred if it knows about the room,
+    /// and then the first registered account that knows about the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The ID of the account that registered the pusher, if
+    ///   known.
+    ///
+    /// * `device_id` - The ID of the device that registered the pusher, if
+    ///   known.
+    ///
+    /// * `room_id` - The room of the push notification.
+    pub fn client_for_push(
+        &self,
+        user_id: Option<&UserId>,
+        device_id: Option<&DeviceId>,
+        room_id: &RoomId,
+    ) -> Option<Client> {
+        if let Some(user_id) = user_id {
+            let client = self.client(user_id)?;
+
+            if device_id.is_some_and(|device_id| client.device_id() != Some(device_id)) {
+                debug!(%user_id, ?device_id, "Ignoring a push for a previous session");
+                return None;
+            }
+
+            return Some(client);
+        }
+
+        if let Some(client) = self.active_client() {
+            if client.get_room(room_id).is_some() {
+                return Some(client);
+            }
+        }
+
+        self.clients().into_iter().find(|client| client.get_room(room_id).is_some())
+    }
+
+    /// Make the given account the active one, and move the background sync to
+    /// it if needed.
+    fn activate(&self, user_id: &UserId) {
+        let sync_settings = self.inner.sync_settings.lock().unwrap().clone();
+
+        {
+            let mut accounts = self.inner.accounts.lock().unwrap();
+
+            for (other_user_id, registered) in accounts.iter_mut() {
+                if other_user_id != user_id {
+                    registered.stop_sync(other_user_id);
+                }
+            }
+
+            if let (Some(sync_settings), Some(registered)) =
+                (sync_settings, accounts.get_mut(user_id))
+            {
+                registered.start_sync(user_id, sync_settings);
+            }
+        }
+
+        self.inner.active_account.set(Some(user_id.to_owned()));
+    }
+}
+
+/// Forward a notification received by a client to the registry, if the client
+/// is still registered.
+fn forward_notification(
+    registry: Weak<ClientRegistryInner>,
+    user_id: OwnedUserId,
+    notification: Notification,
+    room: Room,
+    client: Client,
+) {
+    let Some(registry) = registry.upgrade() else {
+        return;
+    };
+
+    let is_registered = registry
+        .accounts
+        .lock()
+        .unwrap()
+        .get(&user_id)
+        .is_some_and(|registered| Arc::ptr_eq(&registered.client.inner, &client.inner));
+
+    if is_registered {
+        // An error means that there are no receivers, which is fine.
+        let _ =
+            registry.notification_sender.send(AccountNotification { user_id, notification, room });
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for ClientRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientRegistry")
+            .field("accounts", &self.user_ids())
+            .field("active_account", &self.active_account())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::store::RoomLoadSettings;
+    use matrix_sdk_test::{async_test, JoinedRoomBuilder};
+    use ruma::{device_id, owned_user_id, room_id, user_id};
+
+    use super::{ClientRegistry, ClientRegistryError};
+    use crate::{
+        authentication::matrix::MatrixSession,
+        config::SyncSettings,
+        test_utils::{client::mock_session_tokens, mocks::MatrixMockServer},
+        Client, SessionMeta,
+    };
+
+    async fn logged_in_client(server: &MatrixMockServer, user_id: &str) -> Client {
+        let client = server.client_builder().unlogged().build().await;
+        client
+            .matrix_auth()
+            .restore_session(
+                MatrixSession {
+                    meta: SessionMeta {
+                        user_id: user_id.try_into().unwrap(),
+                        device_id: device_id!("DEVICEID").to_owned(),
+                    },
+                    tokens: mock_session_tokens(),
+                },
+                RoomLoadSettings::default(),
+            )
+            .await
+            .unwrap();
+        client
+    }
+
+    #[async_test]
+    async fn test_add_switch_and_remove_clients() {
+        let server = MatrixMockServer::new().await;
+        let registry = ClientRegistry::new(reqwest::Client::new());
+
+        let alice = user_id!("@alice:localhost");
+        let bob = user_id!("@bob:localhost");
+
+        let unlogged_client = server.client_builder().unlogged().build().await;
+        assert_matches!(
+            registry.add_client(unlogged_client).await,
+            Err(ClientRegistryError::NotLoggedIn)
+        );
+
+        // The first client becomes the active one.
+        registry.add_client(logged_in_client(&server, alice.as_str()).await).await.unwrap();
+        registry.add_client(logged_in_client(&server, bob.as_str()).await).await.unwrap();
+        assert_eq!(registry.user_ids(), [alice.to_owned(), bob.to_owned()]);
+        assert_eq!(registry.active_account().as_deref(), Some(alice));
+
+        assert_matches!(
+            registry.add_client(logged_in_client(&server, bob.as_str()).await).await,
+            Err(ClientRegistryError::AlreadyRegistered(user_id)) => {
+                assert_eq!(user_id, bob);
+            }
+        );
+
+        let mut active_account = registry.subscribe_to_active_account();
+
+        let client = registry.switch_to(bob).unwrap();
+        assert_eq!(client.user_id(), Some(bob));
+        assert_eq!(active_account.next().await, Some(Some(bob.to_owned())));
+        assert_eq!(registry.active_client().unwrap().user_id(), Some(bob));
+
+        assert_matches!(
+            registry.switch_to(user_id!("@carol:localhost")),
+            Err(ClientRegistryError::UnknownAccount(_))
+        );
+
+        // Removing the active account leaves no active account.
+        let client = registry.remove_client(bob).await.unwrap();
+        assert_eq!(client.user_id(), Some(bob));
+        assert_eq!(registry.active_account(), None);
+        assert_eq!(registry.user_ids(), [owned_user_id!("@alice:localhost")]);
+        assert!(registry.remove_client(bob).await.is_none());
+    }
+
+    #[async_test]
+    async fn test_sync_only_active_account() {
+        let server = MatrixMockServer::new().await;
+        let registry = ClientRegistry::new(reqwest::Client::new());
+
+        let alice = user_id!("@alice:localhost");
+        let bob = user_id!("@bob:localhost");
+
+        registry.add_client(logged_in_client(&server, alice.as_str()).await).await.unwrap();
+        registry.add_client(logged_in_client(&server, bob.as_str()).await).await.unwrap();
+
+        registry.sync_active_account(SyncSettings::new());
+        assert!(registry.is_syncing(alice));
+        assert!(!registry.is_syncing(bob));
+
+        // Switching suspends the sync of the inactive account.
+        registry.switch_to(bob).unwrap();
+        assert!(!registry.is_syncing(alice));
+        assert!(registry.is_syncing(bob));
+
+        registry.stop_syncing();
+        assert!(!registry.is_syncing(alice));
+        assert!(!registry.is_syncing(bob));
+    }
+
+    #[async_test]
+    async fn test_client_for_room() {
+        let server = MatrixMockServer::new().await;
+        let registry = ClientRegistry::new(reqwest::Client::new());
+
+        let alice_client = logged_in_client(&server, "@alice:localhost").await;
+        let bob_client = logged_in_client(&server, "@bob:localhost").await;
+
+        let shared_room_id = room_id!("!shared:localhost");
+        let bob_room_id = room_id!("!bob:localhost");
+        server.sync_room(&alice_client, JoinedRoomBuilder::new(shared_room_id)).await;
+        server.sync_room(&bob_client, JoinedRoomBuilder::new(shared_room_id)).await;
+        server.sync_room(&bob_client, JoinedRoomBuilder::new(bob_room_id)).await;
+
+        registry.add_client(alice_client).await.unwrap();
+        registry.add_client(bob_client).await.unwrap();
+
+        let alice = user_id!("@alice:localhost");
+        let bob = user_id!("@bob:localhost");
+
+        // The account of the pusher is used.
+        let client = registry.client_for_push(Some(bob), None, shared_room_id).unwrap();
+        assert_eq!(client.user_id().unwrap(), bob);
+        let client = registry
+            .client_for_push(Some(bob), Some(device_id!("DEVICEID")), shared_room_id)
+            .unwrap();
+        assert_eq!(client.user_id().unwrap(), bob);
+
+        // The pushes of another session or of an unknown account are ignored, even if
+        // another account knows the room.
+        assert!(registry
+            .client_for_push(Some(bob), Some(device_id!("OLDDEVICE")), shared_room_id)
+            .is_none());
+        assert!(registry
+            .client_for_push(Some(user_id!("@carol:localhost")), None, shared_room_id)
+            .is_none());
+
+        // Without the account of the pusher, the active account is preferred.
+        let client = registry.client_for_push(None, None, shared_room_id).unwrap();
+        assert_eq!(client.user_id().unwrap(), alice);
+
+        // Otherwise the account knowing the room is used.
+        let client = registry.client_for_push(None, None, bob_room_id).unwrap();
+        assert_eq!(client.user_id().unwrap(), bob);
+
+        assert!(registry.client_for_push(None, None, room_id!("!unknown:localhost")).is_none());
+    }
+
+    #[async_test]
+    async fn test_notifications_are_forwarded_once_after_re_adding_a_client() {
+        let server = MatrixMockServer::new().await;
+        let registry = ClientRegistry::new(reqwest::Client::new());
+
+        let client = logged_in_client(&server, "@alice:localhost").await;
+        registry.add_client(client.clone()).await.unwrap();
+        registry.remove_client(user_id!("@alice:localhost")).await.unwrap();
+        registry.add_client(client.clone()).await.unwrap();
+
+        assert_eq!(client.notification_handlers().await.len(), 1);
+
+        registry.remove_client(user_id!("@alice:localhost")).await.unwrap();
+        assert!(client.notification_handlers().await.is_empty());
+    }
+}
@@ -168,11 +168,118 @@ impl RequestConfig {
     }
 }
 
+/// A category of requests that can be given its own [`RequestBudget`].
+///
+/// Requests that don't belong to any of these categories only use the
+/// [`RequestConfig`] of the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum RequestCategory {
+    /// The sync requests, including sliding sync.
+    Sync,
+
+    /// The uploads of media.
+    MediaUpload,
+
+    /// The downloads of media and thumbnails.
+    MediaDownload,
+
+    /// The to-device messages sent to other devices.
+    ToDevice,
+
+    /// The requests to claim one-time keys of other devices.
+    KeysClaim,
+}
+
+impl RequestCategory {
+    /// All the request categories.
+    pub const ALL: [RequestCategory; 5] =
+        [Self::Sync, Self::MediaUpload, Self::MediaDownload, Self::ToDevice, Self::KeysClaim];
+
+    /// Find the category of a request from the path of its URL.
+    pub(crate) fn from_path(path: &str) -> Option<Self> {
+        if path.ends_with("/sync") {
+            Some(Self::Sync)
+        } else if path.contains("/media/") && path.contains("/upload") {
+            Some(Self::MediaUpload)
+        } else if path.contains("/media/")
+            && (path.contains("/download/") || path.contains("/thumbnail/"))
+        {
+            Some(Self::MediaDownload)
+        } else if path.contains("/sendToDevice/") {
+            Some(Self::ToDevice)
+        } else if path.ends_with("/keys/claim") {
+            Some(Self::KeysClaim)
+        } else {
+            None
+        }
+    }
+}
+
+/// The timeout and concurrency limits of a [`RequestCategory`].
+///
+/// This allows to isolate categories of requests from one another, so that for
+/// example slow media downloads can't use all the available connections and
+/// stall the sync loop.
+///
+/// # Examples
+///
+/// ```
+/// use std::{num::NonZeroUsize, time::Duration};
+///
+/// use matrix_sdk::{
+///     config::{RequestBudget, RequestCategory},
+///     Client,
+/// };
+///
+/// let client_builder = Client::builder().request_budget(
+///     RequestCategory::MediaDownload,
+///     RequestBudget::new()
+///         .max_concurrent_requests(NonZeroUsize::new(4))
+///         .timeout(Duration::from_secs(120)),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestBudget {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) max_concurrent_requests: Option<NonZeroUsize>,
+}
+
+impl RequestBudget {
+    /// Create a new `RequestBudget` without limits.
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the timeout of the requests in this category.
+    ///
+    /// This overrides the timeout of the [`RequestConfig`] used for the
+    /// request. Note that the sync requests use a timeout that depends on
+    /// their long-polling timeout by default.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The limit of requests in this category that are run concurrently.
+    ///
+    /// When this is set, the requests in this category are not counted in the
+    /// global limit set with [`RequestConfig::max_concurrent_requests()`], and
+    /// they are queued fairly once the limit is reached.
+    #[must_use]
+    pub fn max_concurrent_requests(mut self, limit: Option<NonZeroUsize>) -> Self {
+        self.max_concurrent_requests = limit;
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
 
-    use super::RequestConfig;
+    use super::{RequestCategory, RequestConfig};
 
     #[test]
     fn smoketest() {
@@ -200,4 +307,40 @@ mod tests {
         let cfg = RequestConfig::short_retry();
         assert_eq!(cfg.retry_limit, Some(3));
     }
+
+    #[test]
+    fn test_request_category_from_path() {
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/client/v3/sync"),
+            Some(RequestCategory::Sync)
+        );
+        assert_eq!(
+            RequestCategory::from_path(
+                "/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"
+            ),
+            Some(RequestCategory::Sync)
+        );
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/media/v3/upload"),
+            Some(RequestCategory::MediaUpload)
+        );
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/client/v1/media/download/localhost/abcd"),
+            Some(RequestCategory::MediaDownload)
+        );
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/media/v3/thumbnail/localhost/abcd"),
+            Some(RequestCategory::MediaDownload)
+        );
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/client/v3/sendToDevice/m.room.encrypted/1"),
+            Some(RequestCategory::ToDevice)
+        );
+        assert_eq!(
+            RequestCategory::from_path("/_matrix/client/v3/keys/claim"),
+            Some(RequestCategory::KeysClaim)
+        );
+        assert_eq!(RequestCategory::from_path("/_matrix/client/v3/keys/query"), None);
+        assert_eq!(RequestCategory::from_path("/_matrix/client/v3/account/whoami"), None);
+    }
 }
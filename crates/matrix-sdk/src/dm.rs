@@ -0,0 +1,191 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Direct messages with other users.
+//!
+//! [`Client::dm_with()`] finds the canonical direct message room with a user,
+//! or creates it, so a client doesn't end up creating a new room every time
+//! the user wants to talk to the same person.
+
+use matrix_sdk_base::{deserialized_responses::SyncOrStrippedState, RoomMemberships};
+use ruma::{
+    events::{
+        direct::DirectUserIdentifier, macros::EventContent, room::member::MembershipState,
+        EmptyStateKey,
+    },
+    OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::{Client, Result, Room, RoomState};
+
+/// The content of the `io.element.functional_members` state event.
+///
+/// It lists the members of a room that are services, like bots or bridges,
+/// rather than people. A room with a user and such services is still a direct
+/// message with this user.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "io.element.functional_members", kind = State, state_key_type = EmptyStateKey)]
+pub struct FunctionalMembersEventContent {
+    /// The user IDs of the service members.
+    #[serde(default)]
+    pub service_members: Vec<OwnedUserId>,
+}
+
+/// How good a candidate for the canonical direct message room with a user is.
+///
+/// The variants are sorted by increasing preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Candidate {
+    /// The user was invited, or their membership isn't known yet.
+    Invited,
+
+    /// The user has joined the room.
+    Joined,
+}
+
+impl Client {
+    /// Get the direct message room with the given user, or create it.
+    ///
+    /// The existing room is the best joined room that is marked as a direct
+    /// message with this user, and only this user, in the `m.direct` account
+    /// data, where the user didn't leave and no one else is a member, ignoring
+    /// the service members listed in the [`FunctionalMembersEventContent`]. The
+    /// rooms where the user has joined are preferred, then the most recently
+    /// active ones.
+    ///
+    /// Otherwise, a pending direct message invite from the user is accepted if
+    /// there is one, or a new room is created with [`Client::create_dm()`],
+    /// which is encrypted if the `e2e-encryption` feature is enabled.
+    ///
+    /// Concurrent calls are serialized, so they return the same room instead of
+    /// creating one each.
+    #[instrument(skip(self))]
+    pub async fn dm_with(&self, user_id: &UserId) -> Result<Room> {
+        let mut created_dms = self.locks().dm_with_lock.lock().await;
+
+        // The rooms created by this method might not have been received in a sync yet,
+        // so they might not be marked as direct messages locally. Forget them once they
+        // are, or once they were left.
+        created_dms.retain(|user_id, room_id| {
+            self.get_room(room_id).is_some_and(|room| {
+                room.state() == RoomState::Joined
+                    && !room.direct_targets().contains(<&DirectUserIdentifier>::from(&**user_id))
+            })
+        });
+        let created_room = created_dms.get(user_id).and_then(|room_id| self.get_room(room_id));
+
+        if let Some(room) = self.find_dm(user_id, created_room).await? {
+            debug!(room_id = ?room.room_id(), "Found an existing DM");
+            return Ok(room);
+        }
+
+        for room in self.invited_rooms() {
+            if !room.is_direct().await? {
+                continue;
+            }
+
+            let inviter = room.invite_details().await?.inviter;
+            if inviter.is_some_and(|inviter| inviter.user_id() == user_id) {
+                debug!(room_id = ?room.room_id(), "Accepting the DM invite");
+                room.join().await?;
+                return Ok(room);
+            }
+        }
+
+        let room = self.create_dm(user_id).await?;
+        debug!(room_id = ?room.room_id(), "Created a new DM");
+        created_dms.insert(user_id.to_owned(), room.room_id().to_owned());
+
+        Ok(room)
+    }
+
+    /// Find the best existing direct message room with the given user.
+    async fn find_dm(&self, user_id: &UserId, created_room: Option<Room>) -> Result<Option<Room>> {
+        let mut best: Option<(Candidate, Option<u64>, Room)> = None;
+
+        let rooms = self
+            .joined_rooms()
+            .into_iter()
+            .filter(|room| room.direct_targets().contains(<&DirectUserIdentifier>::from(user_id)))
+            .chain(created_room.filter(|room| room.state() == RoomState::Joined));
+
+        for room in rooms {
+            let Some(candidate) = Self::dm_candidate(&room, user_id).await? else {
+                continue;
+            };
+
+            let recency = room.recency_stamp();
+            if best.as_ref().is_none_or(|(best_candidate, best_recency, _)| {
+                (candidate, recency) > (*best_candidate, *best_recency)
+            }) {
+                best = Some((candidate, recency, room));
+            }
+        }
+
+        Ok(best.map(|(_, _, room)| room))
+    }
+
+    /// Check whether the given room is a direct message with the given user.
+    async fn dm_candidate(room: &Room, user_id: &UserId) -> Result<Option<Candidate>> {
+        let service_members = room
+            .get_state_event_static::<FunctionalMembersEventContent>()
+            .await?
+            .and_then(|event| event.deserialize().ok())
+            .and_then(|event| match event {
+                SyncOrStrippedState::Sync(event) => {
+                    event.as_original().map(|event| event.content.service_members.clone())
+                }
+                SyncOrStrippedState::Stripped(event) => Some(event.content.service_members),
+            })
+            .unwrap_or_default();
+
+        // The other targets in `m.direct` must all be services.
+        let has_other_targets = room.direct_targets().iter().any(|target| {
+            target.as_user_id().is_some_and(|target| {
+                target != user_id && !service_members.iter().any(|member| member == target)
+            })
+        });
+        if has_other_targets {
+            return Ok(None);
+        }
+
+        // So must the other members.
+        let own_user_id = room.own_user_id();
+        let members = room.members_no_sync(RoomMemberships::JOIN | RoomMemberships::INVITE).await?;
+        let has_other_members = members.iter().any(|member| {
+            let member_id = member.user_id();
+            member_id != own_user_id
+                && member_id != user_id
+                && !service_members.iter().any(|service| service == member_id)
+        });
+        if has_other_members {
+            return Ok(None);
+        }
+
+        let candidate = match room.get_member_no_sync(user_id).await? {
+            Some(member) => match member.membership() {
+                MembershipState::Join => Candidate::Joined,
+                MembershipState::Invite => Candidate::Invited,
+                // The user left the room, so it's not a DM anymore.
+                _ => return Ok(None),
+            },
+            // The members of a room that was just created might not be known yet.
+            None => Candidate::Invited,
+        };
+
+        Ok(Some(candidate))
+    }
+}
@@ -51,7 +51,7 @@ use tracing::{error, info, instrument, trace, warn, Span};
 pub mod futures;
 pub(crate) mod types;
 
-pub use types::{BackupState, UploadState};
+pub use types::{BackupState, OnDemandDownloadStats, UploadState};
 
 use self::futures::WaitForSteadyState;
 use crate::{
@@ -369,6 +369,18 @@ impl Backups {
         self.client.inner.e2ee.backup_state.global_state.get()
     }
 
+    /// Get the statistics about the room keys that were downloaded from the
+    /// backup because an event couldn't be decrypted.
+    ///
+    /// This is only relevant with the
+    /// [`BackupDownloadStrategy::AfterDecryptionFailure`] strategy, and can be
+    /// used to know how often this path helps to decrypt events.
+    ///
+    /// [`BackupDownloadStrategy::AfterDecryptionFailure`]: crate::encryption::BackupDownloadStrategy::AfterDecryptionFailure
+    pub fn on_demand_download_stats(&self) -> OnDemandDownloadStats {
+        *self.client.inner.e2ee.backup_state.on_demand_download_stats.read().unwrap()
+    }
+
     /// Are backups enabled for the current [`Client`]?
     ///
     /// This method will check if we locally have an active backup key and
@@ -61,6 +61,9 @@ pub(crate) struct BackupClientState {
     /// on the server was changed by some other client, we will have a old
     /// value.
     pub(super) backup_exists_on_server: RwLock<Option<bool>>,
+
+    /// Statistics about the room keys downloaded after decryption failures.
+    pub(crate) on_demand_download_stats: RwLock<OnDemandDownloadStats>,
 }
 
 impl BackupClientState {
@@ -84,6 +87,15 @@ impl BackupClientState {
     pub(crate) fn clear_backup_exists_on_server(&self) {
         *self.backup_exists_on_server.write().unwrap() = None;
     }
+
+    /// Update the statistics about the room keys downloaded after decryption
+    /// failures.
+    pub(crate) fn update_on_demand_download_stats(
+        &self,
+        update: impl FnOnce(&mut OnDemandDownloadStats),
+    ) {
+        update(&mut self.on_demand_download_stats.write().unwrap());
+    }
 }
 
 const DEFAULT_BACKUP_UPLOAD_DELAY: Duration = Duration::from_millis(100);
@@ -96,10 +108,55 @@ impl Default for BackupClientState {
             global_state: Default::default(),
             room_keys_broadcaster: broadcast::Sender::new(100),
             backup_exists_on_server: RwLock::new(None),
+            on_demand_download_stats: Default::default(),
         }
     }
 }
 
+/// Statistics about the room keys that were downloaded from the backup because
+/// an event couldn't be decrypted, with the
+/// [`BackupDownloadStrategy::AfterDecryptionFailure`] strategy.
+///
+/// They can be retrieved with [`Backups::on_demand_download_stats()`].
+///
+/// [`BackupDownloadStrategy::AfterDecryptionFailure`]: crate::encryption::BackupDownloadStrategy::AfterDecryptionFailure
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OnDemandDownloadStats {
+    /// The number of room keys that were successfully downloaded.
+    pub downloaded: u64,
+
+    /// The number of room keys that were not found in the backup.
+    pub not_found: u64,
+
+    /// The number of downloads that failed for another reason, like a network
+    /// error.
+    pub failed: u64,
+
+    /// The number of downloads that were skipped because too many room keys
+    /// were downloaded recently.
+    pub rate_limited: u64,
+
+    /// The number of downloads that were skipped because we don't have the
+    /// decryption key of the current backup.
+    pub untrusted: u64,
+}
+
+impl OnDemandDownloadStats {
+    /// The number of downloads that were attempted.
+    pub fn attempts(&self) -> u64 {
+        self.downloaded + self.not_found + self.failed
+    }
+
+    /// The ratio of attempted downloads that retrieved the room key, between
+    /// `0.0` and `1.0`.
+    ///
+    /// Returns `None` if no download was attempted.
+    pub fn success_rate(&self) -> Option<f64> {
+        let attempts = self.attempts();
+        (attempts > 0).then(|| self.downloaded as f64 / attempts as f64)
+    }
+}
+
 /// The possible states of the [`Client`]'s room key backup mechanism.
 ///
 /// A local backup instance can be created either by receiving a valid backup
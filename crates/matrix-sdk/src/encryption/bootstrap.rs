@@ -0,0 +1,318 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Set up end-to-end encryption for an account in a single step.
+//!
+//! Setting up end-to-end encryption for a new account requires multiple steps
+//! which need to happen in a particular order:
+//!
+//! 1. A cross-signing identity needs to be created and uploaded, this usually
+//!    requires the user to authenticate again.
+//! 2. A server-side key backup needs to be created.
+//! 3. Secret storage needs to be set up, so the cross-signing keys and the
+//!    backup key can be recovered on other devices.
+//!
+//! The [`Encryption::bootstrap()`] method takes care of all of those steps.
+//! Every step first checks if it has already been completed, so the method
+//! can be called again if a previous attempt failed halfway through.
+//!
+//! [`Encryption::bootstrap()`]: super::Encryption::bootstrap
+
+use std::future::IntoFuture;
+
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk_common::boxed_into_future;
+use ruma::api::client::uiaa::{AuthData, UiaaInfo};
+use thiserror::Error;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::{debug, info, Instrument, Span};
+use url::Url;
+
+use super::{
+    identities::ManualVerifyError,
+    recovery::{EnableProgress, RecoveryError, RecoveryState},
+    CrossSigningResetAuthType, Encryption,
+};
+use crate::{utils::ChannelObservable, Error};
+
+/// Options for the [`Encryption::bootstrap()`] method.
+///
+/// [`Encryption::bootstrap()`]: super::Encryption::bootstrap
+#[derive(Clone, Default)]
+pub struct RecoveryOptions {
+    /// An optional passphrase which can be used to recover the secrets in
+    /// addition to the recovery key.
+    pub passphrase: Option<String>,
+
+    /// Should the bootstrap wait for *all* room keys to be uploaded to the
+    /// server-side key backup before completing?
+    pub wait_for_backups_to_upload: bool,
+
+    /// The authentication data which should be used if the homeserver
+    /// requires user-interactive authentication for the upload of the
+    /// cross-signing keys.
+    ///
+    /// The session ID of the authentication flow will be filled in
+    /// automatically for password based authentication.
+    pub auth_data: Option<AuthData>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for RecoveryOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecoveryOptions")
+            .field("wait_for_backups_to_upload", &self.wait_for_backups_to_upload)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Error type for the [`Encryption::bootstrap()`] method.
+///
+/// [`Encryption::bootstrap()`]: super::Encryption::bootstrap
+#[derive(Debug, Error)]
+pub enum BootstrapError {
+    /// The homeserver requires user-interactive authentication to upload the
+    /// cross-signing keys, but no [`RecoveryOptions::auth_data`] was
+    /// provided, or the provided data was rejected.
+    ///
+    /// Call the bootstrap method again with the appropriate authentication
+    /// data.
+    #[error("The homeserver requires additional authentication to upload the cross-signing keys")]
+    AuthenticationRequired(Box<UiaaInfo>),
+
+    /// A cross-signing identity already exists on the homeserver, but the
+    /// private cross-signing keys aren't available on this device.
+    ///
+    /// The device should be verified, or the secrets should be recovered
+    /// using [`Recovery::recover()`], instead.
+    ///
+    /// [`Recovery::recover()`]: super::recovery::Recovery::recover
+    #[error("A cross-signing identity already exists but its private keys aren't available")]
+    CrossSigningKeysMissing,
+
+    /// A typical SDK error.
+    #[error(transparent)]
+    Sdk(#[from] Error),
+
+    /// Our own device couldn't be signed with the cross-signing keys.
+    #[error(transparent)]
+    DeviceSigning(#[from] ManualVerifyError),
+
+    /// Error while setting up the key backup or secret storage.
+    #[error(transparent)]
+    Recovery(#[from] RecoveryError),
+}
+
+/// Enum describing the states the [`Encryption::bootstrap()`] method can be
+/// in.
+///
+/// [`Encryption::bootstrap()`]: super::Encryption::bootstrap
+#[derive(Clone, Debug, Default)]
+pub enum BootstrapProgress {
+    /// The bootstrap process is just starting, this is the initial state.
+    #[default]
+    Starting,
+
+    /// The client is setting up cross-signing.
+    SettingUpCrossSigning,
+
+    /// The upload of the cross-signing keys needs to be approved by the user,
+    /// by opening the given URL.
+    ///
+    /// The bootstrap process will continue once the upload has been approved.
+    WaitingForApproval {
+        /// The URL where the user can approve the upload.
+        approval_url: Url,
+    },
+
+    /// The client is setting up the key backup and secret storage.
+    SettingUpRecovery(EnableProgress),
+
+    /// Encryption has been successfully set up, this is the final state.
+    Done,
+}
+
+/// Named future for the [`Encryption::bootstrap()`] method.
+///
+/// Resolves to the newly created recovery key, or `None` if recovery was
+/// already enabled.
+///
+/// [`Encryption::bootstrap()`]: super::Encryption::bootstrap
+#[derive(Debug)]
+pub struct Bootstrap<'a> {
+    encryption: &'a Encryption,
+    options: RecoveryOptions,
+    progress: ChannelObservable<BootstrapProgress>,
+    tracing_span: Span,
+}
+
+impl<'a> Bootstrap<'a> {
+    pub(super) fn new(encryption: &'a Encryption, options: RecoveryOptions) -> Self {
+        Self { encryption, options, progress: Default::default(), tracing_span: Span::current() }
+    }
+
+    /// Subscribe to updates to the bootstrap progress.
+    pub fn subscribe_to_progress(
+        &self,
+    ) -> impl Stream<Item = Result<BootstrapProgress, BroadcastStreamRecvError>> {
+        self.progress.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for Bootstrap<'a> {
+    type Output = Result<Option<String>, BootstrapError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { encryption, options, progress, tracing_span } = self;
+
+        let future = async move {
+            progress.set(BootstrapProgress::SettingUpCrossSigning);
+            set_up_cross_signing(encryption, options.auth_data, &progress).await?;
+
+            let recovery = encryption.recovery();
+            let recovery_key = if recovery.state() == RecoveryState::Enabled {
+                debug!("Recovery is already enabled, skipping");
+                None
+            } else {
+                let mut enable = recovery.enable();
+
+                if let Some(passphrase) = &options.passphrase {
+                    enable = enable.with_passphrase(passphrase);
+                }
+
+                if options.wait_for_backups_to_upload {
+                    enable = enable.wait_for_backups_to_upload();
+                }
+
+                let enable_progress = enable.subscribe_to_progress();
+
+                let forward_progress = {
+                    let progress = progress.clone();
+                    async move {
+                        pin_mut!(enable_progress);
+
+                        while let Some(Ok(update)) = enable_progress.next().await {
+                            progress.set(BootstrapProgress::SettingUpRecovery(update));
+                        }
+                    }
+                };
+
+                // The task can only be aborted outside of wasm.
+                #[cfg(not(target_arch = "wasm32"))]
+                let progress_task = matrix_sdk_common::executor::spawn(forward_progress);
+                #[cfg(target_arch = "wasm32")]
+                matrix_sdk_common::executor::spawn(forward_progress);
+
+                let result = enable.await;
+
+                #[cfg(not(target_arch = "wasm32"))]
+                progress_task.abort();
+
+                Some(result?)
+            };
+
+            progress.set(BootstrapProgress::Done);
+
+            Ok(recovery_key)
+        };
+
+        Box::pin(future.instrument(tracing_span))
+    }
+}
+
+/// Make sure that we have a cross-signing identity on the server, and that
+/// our own device is signed by it.
+async fn set_up_cross_signing(
+    encryption: &Encryption,
+    auth_data: Option<AuthData>,
+    progress: &ChannelObservable<BootstrapProgress>,
+) -> Result<(), BootstrapError> {
+    let client = &encryption.client;
+    let user_id = client.user_id().ok_or(Error::AuthenticationRequired)?;
+
+    // Always ask the server, a previous attempt might have created the
+    // identity locally without managing to upload it.
+    let response = {
+        let olm = client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+        let (request_id, request) = olm.query_keys_for_users([user_id]);
+        client.keys_query(&request_id, request.device_keys).await?
+    };
+
+    if !response.master_keys.contains_key(user_id) {
+        info!("No cross-signing identity found on the server, uploading one");
+
+        // This reuses the private cross-signing keys if a previous attempt
+        // already created them.
+        if let Some(handle) = encryption.upload_cross_signing_identity(false).await? {
+            match handle.auth_type() {
+                CrossSigningResetAuthType::OAuth(info) => {
+                    progress.set(BootstrapProgress::WaitingForApproval {
+                        approval_url: info.approval_url.clone(),
+                    });
+                    handle.auth(None).await?;
+                }
+                CrossSigningResetAuthType::Uiaa(info) => {
+                    let Some(auth_data) = auth_data else {
+                        return Err(BootstrapError::AuthenticationRequired(info.clone().into()));
+                    };
+
+                    let auth_data = with_session(auth_data, info.session.clone());
+
+                    if let Err(error) = handle.auth(Some(auth_data)).await {
+                        return Err(match error.as_uiaa_response() {
+                            Some(info) => {
+                                BootstrapError::AuthenticationRequired(info.clone().into())
+                            }
+                            None => error.into(),
+                        });
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    let status = encryption.cross_signing_status().await.ok_or(Error::NoOlmMachine)?;
+
+    if !status.is_complete() {
+        return Err(BootstrapError::CrossSigningKeysMissing);
+    }
+
+    // A previous attempt might have uploaded the cross-signing keys without
+    // managing to upload the signature of our own device.
+    if let Some(device) = encryption.get_own_device().await.map_err(Error::from)? {
+        if !device.is_cross_signed_by_owner() {
+            info!("Our own device isn't signed by our cross-signing identity, signing it");
+            device.verify().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fill in the session ID of the user-interactive authentication flow, if the
+/// app didn't do so itself.
+fn with_session(mut auth_data: AuthData, session: Option<String>) -> AuthData {
+    if let AuthData::Password(password) = &mut auth_data {
+        if password.session.is_none() {
+            password.session = session;
+        }
+    }
+
+    auth_data
+}
@@ -20,6 +20,7 @@ use std::{
     collections::{BTreeMap, HashSet},
     io::{Cursor, Read, Write},
     iter,
+    ops::Deref,
     path::PathBuf,
     sync::Arc,
 };
@@ -31,13 +32,17 @@ use futures_util::{
     stream::{self, StreamExt},
 };
 use matrix_sdk_base::crypto::{
-    store::RoomKeyInfo,
+    store::{DecryptedToDeviceEvent, RoomKeyInfo},
     types::requests::{
         OutgoingRequest, OutgoingVerificationRequest, RoomMessageRequest, ToDeviceRequest,
     },
-    CrossSigningBootstrapRequests, OlmMachine,
+    CrossSigningBootstrapRequests, DecryptionSettings, OlmMachine, RoomEventDecryptionResult,
+};
+use matrix_sdk_common::{
+    deserialized_responses::TimelineEvent,
+    executor::{spawn, JoinHandle},
+    locks::Mutex as StdMutex,
 };
-use matrix_sdk_common::{executor::spawn, locks::Mutex as StdMutex};
 use ruma::{
     api::client::{
         keys::{
@@ -54,8 +59,11 @@ use ruma::{
     events::{
         direct::DirectUserIdentifier,
         room::{MediaSource, ThumbnailInfo},
+        AnySyncTimelineEvent, AnyToDeviceEventContent,
     },
-    DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, TransactionId, UserId,
+    serde::Raw,
+    DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId, RoomId, TransactionId,
+    UserId,
 };
 use serde::Deserialize;
 use tokio::sync::{Mutex, RwLockReadGuard};
@@ -66,6 +74,7 @@ use vodozemac::Curve25519PublicKey;
 
 use self::{
     backups::{types::BackupClientState, Backups},
+    bootstrap::{Bootstrap, RecoveryOptions},
     futures::UploadEncryptedFile,
     identities::{Device, DeviceUpdates, IdentityUpdates, UserDevices, UserIdentity},
     recovery::{Recovery, RecoveryState},
@@ -82,6 +91,7 @@ use crate::{
 };
 
 pub mod backups;
+pub mod bootstrap;
 pub mod futures;
 pub mod identities;
 pub mod recovery;
@@ -95,12 +105,17 @@ pub use matrix_sdk_base::crypto::{
         SessionExportError as OlmSessionExportError,
     },
     vodozemac, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError, KeyExportError,
-    LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
-    SessionCreationError, SignatureError, VERSION,
+    KeyForwardingRecipients, KeyRequestRecipients, LocalTrust, MediaEncryptionInfo, MegolmError,
+    OlmError, RoomKeyGossipPolicy, RoomKeyImportResult, SecretImportError, SessionCreationError,
+    SignatureError, VERSION,
 };
 
 pub use crate::error::RoomKeyImportError;
 
+/// The maximum backoff, in milliseconds, while waiting for the cross-process
+/// lock of the crypto store in [`Client::decrypt_raw_event()`].
+const DECRYPT_RAW_EVENT_LOCK_MAX_BACKOFF_MS: u32 = 60_000;
+
 /// All the data related to the encryption state.
 pub(crate) struct EncryptionData {
     /// Background tasks related to encryption (key backup, initialization
@@ -156,6 +171,21 @@ impl EncryptionData {
     }
 }
 
+/// A handle to a handler registered with
+/// [`Encryption::add_encrypted_to_device_handler`].
+///
+/// The handler is unregistered when this handle is dropped.
+#[derive(Debug)]
+pub struct EncryptedToDeviceHandlerHandle {
+    join_handle: JoinHandle<()>,
+}
+
+impl Drop for EncryptedToDeviceHandlerHandle {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
+}
+
 /// Settings for end-to-end encryption features.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct EncryptionSettings {
@@ -606,7 +636,72 @@ impl Client {
         room
     }
 
-    async fn send_outgoing_request(&self, r: OutgoingRequest) -> Result<()> {
+    /// Decrypt an event that was received outside of a sync, and evaluate the
+    /// push rules of the user against it.
+    ///
+    /// This is meant for processes that handle push notifications, like a
+    /// notification service extension, which receive events without running
+    /// a sync. If a cross-process lock was enabled with
+    /// [`Encryption::enable_cross_process_store_lock()`], it is held while the
+    /// event is decrypted, so the crypto store is not used concurrently by
+    /// another process.
+    ///
+    /// Events that are not encrypted are returned as is. If the event can't be
+    /// decrypted, the returned [`TimelineEvent`] represents the decryption
+    /// error, and the room key is requested from the key backup if it is
+    /// enabled.
+    ///
+    /// The push actions of the event are only computed if the room is known
+    /// by the client, they are `None` otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room the event was sent in.
+    ///
+    /// * `raw` - The event to decrypt.
+    #[instrument(skip(self, raw))]
+    pub async fn decrypt_raw_event(
+        &self,
+        room_id: &RoomId,
+        raw: &Raw<AnySyncTimelineEvent>,
+    ) -> Result<TimelineEvent> {
+        let _guard =
+            self.encryption().spin_lock_store(Some(DECRYPT_RAW_EVENT_LOCK_MAX_BACKOFF_MS)).await?;
+
+        let event_type = raw.get_field::<String>("type")?;
+
+        let mut event = if event_type.as_deref() == Some("m.room.encrypted") {
+            let machine = self.olm_machine().await;
+            let machine = machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            let decryption_settings = DecryptionSettings {
+                sender_device_trust_requirement: self.base_client().decryption_trust_requirement,
+            };
+
+            match machine
+                .try_decrypt_room_event(raw.cast_ref(), room_id, &decryption_settings)
+                .await?
+            {
+                RoomEventDecryptionResult::Decrypted(decrypted) => decrypted.into(),
+                RoomEventDecryptionResult::UnableToDecrypt(utd_info) => {
+                    self.encryption()
+                        .backups()
+                        .maybe_download_room_key(room_id.to_owned(), raw.clone().cast());
+                    TimelineEvent::new_utd_event(raw.clone(), utd_info)
+                }
+            }
+        } else {
+            TimelineEvent::new(raw.clone())
+        };
+
+        if let Some(room) = self.get_room(room_id) {
+            event.push_actions = room.event_push_actions(event.raw()).await?;
+        }
+
+        Ok(event)
+    }
+
+    pub(crate) async fn send_outgoing_request(&self, r: OutgoingRequest) -> Result<()> {
         use matrix_sdk_base::crypto::types::requests::AnyOutgoingRequest;
 
         match r.request() {
@@ -731,6 +826,21 @@ impl Encryption {
         olm_machine.store().import_secrets_bundle(bundle).await
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn export_secrets_bundle(
+        &self,
+    ) -> Result<
+        matrix_sdk_base::crypto::types::SecretsBundle,
+        crate::authentication::oauth::qrcode::QRCodeGrantLoginError,
+    > {
+        use crate::authentication::oauth::qrcode::QRCodeGrantLoginError;
+
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(QRCodeGrantLoginError::MissingOlmMachine)?;
+
+        Ok(olm_machine.store().export_secrets_bundle().await?)
+    }
+
     /// Get the status of the private cross signing keys.
     ///
     /// This can be used to check which private cross signing keys we have
@@ -1078,6 +1188,101 @@ impl Encryption {
             .map(move |updates| IdentityUpdates::new(client.to_owned(), updates)))
     }
 
+    /// Encrypt and send a to-device event of a custom type to the given
+    /// devices.
+    ///
+    /// Olm sessions are established with the devices we don't share one with
+    /// yet, by claiming one-time keys for them first.
+    ///
+    /// The recipients get the decrypted event through their
+    /// [`Encryption::add_encrypted_to_device_handler`] handlers.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient_devices` - The devices the event should be sent to.
+    /// * `event_type` - The type of the event to be sent.
+    /// * `content` - The content of the event to be sent.
+    ///
+    /// # Returns
+    ///
+    /// The devices the event couldn't be sent to, because no Olm session could
+    /// be established with them.
+    pub async fn encrypt_and_send_raw_to_device(
+        &self,
+        recipient_devices: Vec<&Device>,
+        event_type: &str,
+        content: Raw<AnyToDeviceEventContent>,
+    ) -> Result<Vec<(OwnedUserId, OwnedDeviceId)>> {
+        let users = recipient_devices.iter().map(|device| device.user_id()).collect::<HashSet<_>>();
+        self.client.claim_one_time_keys(users.into_iter()).await?;
+
+        let content = content.deserialize_as::<serde_json::Value>()?;
+        let devices = recipient_devices.into_iter().map(|device| device.deref().clone()).collect();
+
+        let (requests, withheld) = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.encrypt_content_for_devices(devices, event_type, &content).await?
+        };
+
+        for request in requests {
+            let response = self.client.send_to_device(&request).await?;
+            self.client.mark_request_as_sent(&request.txn_id, &response).await?;
+        }
+
+        Ok(withheld
+            .into_iter()
+            .map(|(device, _)| (device.user_id().to_owned(), device.device_id().to_owned()))
+            .collect())
+    }
+
+    /// Register a handler for the to-device events of the given custom type,
+    /// which have been received encrypted and successfully decrypted.
+    ///
+    /// Unlike regular to-device event handlers, this handler is never called
+    /// for events that were sent in clear.
+    ///
+    /// The handler is unregistered when the returned handle is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the events the handler should be called
+    ///   for.
+    /// * `handler` - The handler to call with each decrypted event.
+    pub async fn add_encrypted_to_device_handler(
+        &self,
+        event_type: impl Into<String>,
+        handler: impl Fn(DecryptedToDeviceEvent) + Send + Sync + 'static,
+    ) -> Result<EncryptedToDeviceHandlerHandle> {
+        let event_type = event_type.into();
+        let weak_client = WeakClient::from_client(&self.client);
+
+        let mut stream = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.store().decrypted_to_device_events_stream().boxed()
+        };
+
+        let join_handle = spawn(async move {
+            loop {
+                while let Some(events) = stream.next().await {
+                    for event in events.into_iter().filter(|event| event.event_type == event_type) {
+                        handler(event);
+                    }
+                }
+
+                // The stream ends when the `OlmMachine` is dropped, e.g. because it has been
+                // regenerated; listen to the new one, if any.
+                let Some(client) = weak_client.get() else { break };
+                let olm = client.olm_machine().await;
+                let Some(olm) = olm.as_ref() else { break };
+                stream = olm.store().decrypted_to_device_events_stream().boxed();
+            }
+        });
+
+        Ok(EncryptedToDeviceHandlerHandle { join_handle })
+    }
+
     /// Create and upload a new cross signing identity.
     ///
     /// # Arguments
@@ -1179,6 +1384,18 @@ impl Encryption {
     /// # anyhow::Ok(()) };
     /// ```
     pub async fn reset_cross_signing(&self) -> Result<Option<CrossSigningResetHandle>> {
+        self.upload_cross_signing_identity(true).await
+    }
+
+    /// Upload our cross-signing identity, creating a new one if `reset` is set
+    /// or if we don't have one yet.
+    ///
+    /// Returns a [`CrossSigningResetHandle`] if the homeserver requires
+    /// additional authentication for the upload.
+    async fn upload_cross_signing_identity(
+        &self,
+        reset: bool,
+    ) -> Result<Option<CrossSigningResetHandle>> {
         let olm = self.client.olm_machine().await;
         let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
 
@@ -1186,7 +1403,7 @@ impl Encryption {
             upload_keys_req,
             upload_signing_keys_req,
             upload_signatures_req,
-        } = olm.bootstrap_cross_signing(true).await?;
+        } = olm.bootstrap_cross_signing(reset).await?;
 
         let upload_signing_keys_req = assign!(UploadSigningKeysRequest::new(), {
             auth: None,
@@ -1475,6 +1692,27 @@ impl Encryption {
         Some(olm.store().room_keys_received_stream())
     }
 
+    /// Configure which devices room keys are requested from and forwarded
+    /// to.
+    ///
+    /// The policy is persisted in the crypto store. See
+    /// [`RoomKeyGossipPolicy`] for the available options.
+    #[cfg(feature = "automatic-room-key-forwarding")]
+    pub async fn set_room_key_gossip_policy(&self, policy: RoomKeyGossipPolicy) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+        Ok(olm.set_room_key_gossip_policy(policy).await?)
+    }
+
+    /// Get the policy describing which devices room keys are requested from
+    /// and forwarded to.
+    ///
+    /// Returns `None` if the encryption isn't set up yet.
+    pub async fn room_key_gossip_policy(&self) -> Option<RoomKeyGossipPolicy> {
+        let olm = self.client.olm_machine().await;
+        Some(olm.as_ref()?.room_key_gossip_policy())
+    }
+
     /// Get the secret storage manager of the client.
     pub fn secret_storage(&self) -> SecretStorage {
         SecretStorage { client: self.client.to_owned() }
@@ -1490,6 +1728,60 @@ impl Encryption {
         Recovery { client: self.client.to_owned() }
     }
 
+    /// Set up end-to-end encryption for the account in a single step.
+    ///
+    /// This creates and uploads a cross-signing identity, creates a
+    /// server-side key backup and sets up secret storage, in that order.
+    /// Steps which have already been completed are skipped, so this method
+    /// can be called again if a previous attempt failed halfway through.
+    ///
+    /// Resolves to the newly created recovery key, or `None` if recovery was
+    /// already enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `recovery` - The options for the setup, e.g. the authentication data
+    ///   the homeserver might require for the upload of the cross-signing keys.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{
+    /// #     encryption::bootstrap::{BootstrapError, BootstrapProgress, RecoveryOptions},
+    /// #     Client,
+    /// # };
+    /// # use futures_util::StreamExt;
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// let encryption = client.encryption();
+    /// let bootstrap = encryption.bootstrap(RecoveryOptions::default());
+    ///
+    /// let mut progress = bootstrap.subscribe_to_progress();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Some(Ok(update)) = progress.next().await {
+    ///         if let BootstrapProgress::WaitingForApproval { approval_url } = update
+    ///         {
+    ///             println!("Please approve the setup at {approval_url}");
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// match bootstrap.await {
+    ///     Ok(Some(recovery_key)) => println!("Your recovery key: {recovery_key}"),
+    ///     Ok(None) => println!("Encryption was already set up"),
+    ///     Err(BootstrapError::AuthenticationRequired(_)) => {
+    ///         // Ask the user for their password and try again, with
+    ///         // `RecoveryOptions::auth_data` set.
+    ///     }
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn bootstrap(&self, recovery: RecoveryOptions) -> Bootstrap<'_> {
+        Bootstrap::new(self, recovery)
+    }
+
     /// Enables the crypto-store cross-process lock.
     ///
     /// This may be required if there are multiple processes that may do writes
@@ -1548,15 +1840,34 @@ impl Encryption {
     async fn on_lock_newly_acquired(&self) -> Result<u64, Error> {
         let olm_machine_guard = self.client.olm_machine().await;
         if let Some(olm_machine) = olm_machine_guard.as_ref() {
+            let previous_generation = *self.client.locks().crypto_store_generation.lock().await;
             let (new_gen, generation_number) = olm_machine
                 .maintain_crypto_store_generation(&self.client.locks().crypto_store_generation)
                 .await?;
             // If the crypto store generation has changed,
             if new_gen {
-                // (get rid of the reference to the current crypto store first)
-                drop(olm_machine_guard);
-                // Recreate the OlmMachine.
-                self.client.base_client().regenerate_olm(None).await?;
+                // Try to only invalidate the caches for what the other processes changed,
+                // according to the crypto store journal.
+                let invalidated = olm_machine
+                    .invalidate_caches_from_journal(previous_generation, generation_number)
+                    .await?;
+
+                if !invalidated {
+                    // (get rid of the reference to the current crypto store first)
+                    drop(olm_machine_guard);
+                    // Recreate the OlmMachine.
+                    self.client.base_client().regenerate_olm(None).await?;
+
+                    // Let the new OlmMachine know about the current generation, so it records
+                    // its changes in the journal.
+                    if let Some(olm_machine) = self.client.olm_machine().await.as_ref() {
+                        olm_machine
+                            .maintain_crypto_store_generation(
+                                &self.client.locks().crypto_store_generation,
+                            )
+                            .await?;
+                    }
+                }
             }
             Ok(generation_number)
         } else {
@@ -1742,6 +2053,7 @@ impl Encryption {
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use std::{
+        collections::BTreeMap,
         ops::Not,
         sync::{
             atomic::{AtomicBool, Ordering},
@@ -1750,27 +2062,39 @@ mod tests {
         time::Duration,
     };
 
+    use assert_matches2::assert_let;
+    use futures_util::{pin_mut, FutureExt, StreamExt};
+    use matrix_sdk_base::crypto::{
+        types::requests::AnyOutgoingRequest, EncryptionSyncChanges, OlmMachine,
+    };
     use matrix_sdk_test::{
         async_test, test_json, GlobalAccountDataTestEvent, JoinedRoomBuilder, StateTestEvent,
         SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
     };
     use ruma::{
+        api::client::keys::{claim_keys, get_keys},
+        device_id,
+        encryption::{DeviceKeys, OneTimeKey},
         event_id,
         events::{reaction::ReactionEventContent, relation::Annotation},
-        user_id,
+        serde::Raw,
+        to_device::DeviceIdOrAllDevices,
+        user_id, OwnedOneTimeKeyId,
     };
     use serde_json::json;
+    use tokio::sync::mpsc;
     use wiremock::{
         matchers::{header, method, path_regex},
         Mock, MockServer, Request, ResponseTemplate,
     };
 
     use crate::{
-        assert_next_matches_with_timeout,
+        assert_let_timeout, assert_next_matches_with_timeout,
         config::RequestConfig,
         encryption::{OAuthCrossSigningResetInfo, VerificationState},
         test_utils::{
-            client::mock_matrix_session, logged_in_client, no_retry_test_client, set_client_session,
+            client::mock_matrix_session, logged_in_client, mocks::MatrixMockServer,
+            no_retry_test_client, set_client_session,
         },
         Client,
     };
@@ -1988,10 +2312,11 @@ mod tests {
         let acquired1 = client1.encryption().try_lock_store_once().await.unwrap();
         assert!(acquired1.is_some());
 
-        // But now its olm machine has been invalidated and thus regenerated!
+        // Client2 didn't change anything, according to the crypto store journal, so
+        // the olm machine is kept.
         let olm_machine = client1.olm_machine().await.clone().expect("must have an olm machine");
 
-        assert!(!initial_olm_machine.same_as(&olm_machine));
+        assert!(initial_olm_machine.same_as(&olm_machine));
 
         let backup_key_new = olm_machine.backup_machine().get_backup_keys().await.unwrap();
         assert!(backup_key_new.decryption_key.is_some());
@@ -2066,9 +2391,11 @@ mod tests {
             assert!(acquired.is_some());
         }
 
-        // Taking the lock the first time will update the olm machine.
+        // Taking the lock the first time will invalidate the caches using the crypto
+        // store journal; since the other client didn't change anything, the olm
+        // machine is kept.
         let after_taking_lock_first_time = client.olm_machine().await.as_ref().unwrap().clone();
-        assert!(!initial_olm_machine.same_as(&after_taking_lock_first_time));
+        assert!(initial_olm_machine.same_as(&after_taking_lock_first_time));
 
         {
             let acquired = client.encryption().try_lock_store_once().await.unwrap();
@@ -2080,6 +2407,74 @@ mod tests {
         assert!(after_taking_lock_first_time.same_as(&after_taking_lock_second_time));
     }
 
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    async fn test_generation_counter_invalidates_caches_from_journal() {
+        use matrix_sdk_base::store::RoomLoadSettings;
+
+        // Create two clients using the same sqlite database.
+        let sqlite_path = std::env::temp_dir().join("generation_counter_journal.db");
+        let session = mock_matrix_session();
+
+        let client1 = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store(&sqlite_path, None)
+            .build()
+            .await
+            .unwrap();
+        client1
+            .matrix_auth()
+            .restore_session(session.clone(), RoomLoadSettings::default())
+            .await
+            .unwrap();
+
+        let client2 = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store(sqlite_path, None)
+            .build()
+            .await
+            .unwrap();
+        client2.matrix_auth().restore_session(session, RoomLoadSettings::default()).await.unwrap();
+
+        client1.encryption().enable_cross_process_store_lock("client1".to_owned()).await.unwrap();
+        client2.encryption().enable_cross_process_store_lock("client2".to_owned()).await.unwrap();
+
+        {
+            let acquired1 = client1.encryption().try_lock_store_once().await.unwrap();
+            assert!(acquired1.is_some());
+        }
+
+        let initial_olm_machine =
+            client1.olm_machine().await.clone().expect("must have an olm machine");
+        let bob = user_id!("@bob:example.org");
+        assert!(initial_olm_machine.tracked_users().await.unwrap().contains(bob).not());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The other client starts tracking a user, while holding the lock.
+        {
+            let acquired2 = client2.encryption().try_lock_store_once().await.unwrap();
+            assert!(acquired2.is_some());
+
+            let olm_machine = client2.olm_machine().await.clone().unwrap();
+            olm_machine.update_tracked_users([bob]).await.unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        {
+            let acquired1 = client1.encryption().try_lock_store_once().await.unwrap();
+            assert!(acquired1.is_some());
+        }
+
+        // The olm machine has been kept, but its caches have been invalidated.
+        let olm_machine = client1.olm_machine().await.clone().expect("must have an olm machine");
+        assert!(initial_olm_machine.same_as(&olm_machine));
+        assert!(olm_machine.tracked_users().await.unwrap().contains(bob));
+    }
+
     #[async_test]
     async fn test_update_verification_state_is_updated_before_any_requests_happen() {
         // Given a client and a server
@@ -2140,4 +2535,199 @@ mod tests {
         OAuthCrossSigningResetInfo::from_auth_info(&auth_info)
             .expect("We should be able to fetch the cross-signing reset info from the auth info");
     }
+
+    /// Get the device keys and a one-time key of the given `OlmMachine`, as
+    /// they would be uploaded to the server.
+    async fn device_keys_and_one_time_key(
+        olm: &OlmMachine,
+    ) -> (Raw<DeviceKeys>, OwnedOneTimeKeyId, Raw<OneTimeKey>) {
+        let requests = olm.outgoing_requests().await.unwrap();
+        let upload = requests
+            .iter()
+            .find_map(|request| match request.request() {
+                AnyOutgoingRequest::KeysUpload(upload) => Some(upload),
+                _ => None,
+            })
+            .expect("The keys of a new device should be uploaded");
+
+        let (key_id, one_time_key) = upload.one_time_keys.first_key_value().unwrap();
+        (upload.device_keys.clone().unwrap(), key_id.clone(), one_time_key.clone())
+    }
+
+    #[async_test]
+    async fn test_encrypt_and_send_raw_to_device() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let alice_id = client.user_id().unwrap().to_owned();
+
+        let bob_id = user_id!("@bob:example.org");
+        let bob_device_id = device_id!("BOBDEVICE");
+        let bob = OlmMachine::new(bob_id, bob_device_id).await;
+        let (device_keys, key_id, one_time_key) = device_keys_and_one_time_key(&bob).await;
+
+        server
+            .mock_query_keys()
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "device_keys": {
+                    "@bob:example.org": { "BOBDEVICE": device_keys },
+                },
+            })))
+            .mount()
+            .await;
+
+        // A one-time key of Bob's device is claimed to establish an Olm session.
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/v3/keys/claim"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "one_time_keys": {
+                    "@bob:example.org": {
+                        "BOBDEVICE": { key_id.to_string(): one_time_key },
+                    },
+                },
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        // The encrypted event is sent to Bob's device.
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/v3/sendToDevice/m.room.encrypted/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        client.encryption().request_user_identity(bob_id).await.unwrap();
+        let device = client.encryption().get_device(bob_id, bob_device_id).await.unwrap().unwrap();
+
+        let content = json!({ "secret": "hunter2" });
+        let failed_devices = client
+            .encryption()
+            .encrypt_and_send_raw_to_device(
+                vec![&device],
+                "org.example.custom",
+                Raw::new(&content).unwrap().cast(),
+            )
+            .await
+            .unwrap();
+        assert!(failed_devices.is_empty());
+
+        // Bob can decrypt the event that was sent to his device.
+        let requests = server.server().received_requests().await.unwrap();
+        let request =
+            requests.iter().find(|request| request.url.path().contains("/sendToDevice/")).unwrap();
+        let body = request.body_json::<serde_json::Value>().unwrap();
+        let event = Raw::new(&json!({
+            "sender": alice_id,
+            "type": "m.room.encrypted",
+            "content": body["messages"]["@bob:example.org"]["BOBDEVICE"],
+        }))
+        .unwrap()
+        .cast();
+
+        let stream = bob.store().decrypted_to_device_events_stream();
+        pin_mut!(stream);
+
+        bob.receive_sync_changes(EncryptionSyncChanges {
+            to_device_events: vec![event],
+            changed_devices: &Default::default(),
+            one_time_keys_counts: &Default::default(),
+            unused_fallback_keys: None,
+            next_batch_token: None,
+        })
+        .await
+        .unwrap();
+
+        assert_let!(Some(Some(events)) = stream.next().now_or_never());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].sender, alice_id);
+        assert_eq!(events[0].event_type, "org.example.custom");
+
+        let decrypted = events[0].raw_event.deserialize_as::<serde_json::Value>().unwrap();
+        assert_eq!(decrypted["content"], content);
+    }
+
+    #[async_test]
+    async fn test_encrypted_to_device_handler_survives_olm_machine_regeneration() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let alice_id = client.user_id().unwrap().to_owned();
+        let alice_device_id = client.device_id().unwrap().to_owned();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let _handle = client
+            .encryption()
+            .add_encrypted_to_device_handler("org.example.custom", move |event| {
+                sender.send(event).unwrap();
+            })
+            .await
+            .unwrap();
+
+        // When the `OlmMachine` is regenerated, which ends the streams of the previous
+        // one,
+        client.base_client().regenerate_olm(None).await.unwrap();
+
+        // And Bob sends an encrypted event to the new one,
+        let (device_keys, key_id, one_time_key) = {
+            let olm = client.olm_machine_for_testing().await;
+            device_keys_and_one_time_key(olm.as_ref().unwrap()).await
+        };
+
+        let bob_id = user_id!("@bob:example.org");
+        let bob = OlmMachine::new(bob_id, device_id!("BOBDEVICE")).await;
+
+        let (request_id, _) = bob.query_keys_for_users([&*alice_id]);
+        let mut keys_query = get_keys::v3::Response::new();
+        keys_query.device_keys = BTreeMap::from([(
+            alice_id.clone(),
+            BTreeMap::from([(alice_device_id.clone(), device_keys)]),
+        )]);
+        bob.mark_request_as_sent(&request_id, &keys_query).await.unwrap();
+
+        let (request_id, _) =
+            bob.get_missing_sessions([&*alice_id].into_iter()).await.unwrap().unwrap();
+        let keys_claim = claim_keys::v3::Response::new(BTreeMap::from([(
+            alice_id.clone(),
+            BTreeMap::from([(alice_device_id.clone(), BTreeMap::from([(key_id, one_time_key)]))]),
+        )]));
+        bob.mark_request_as_sent(&request_id, &keys_claim).await.unwrap();
+
+        let alice_device =
+            bob.get_device(&alice_id, &alice_device_id, None).await.unwrap().unwrap();
+        let content = json!({ "secret": "hunter2" });
+        let (requests, _) = bob
+            .encrypt_content_for_devices(
+                vec![(*alice_device).clone()],
+                "org.example.custom",
+                &content,
+            )
+            .await
+            .unwrap();
+        let encrypted_content = requests[0].messages[&alice_id]
+            [&DeviceIdOrAllDevices::DeviceId(alice_device_id.clone())]
+            .clone();
+
+        server
+            .mock_sync()
+            .ok_and_run(&client, |builder| {
+                builder.add_to_device_event(
+                    Raw::new(&json!({
+                        "sender": bob_id,
+                        "type": "m.room.encrypted",
+                        "content": encrypted_content,
+                    }))
+                    .unwrap()
+                    .cast(),
+                );
+            })
+            .await;
+
+        // Then the handler is still called with the decrypted event.
+        assert_let_timeout!(Some(event) = receiver.recv());
+        assert_eq!(event.sender, bob_id);
+        assert_eq!(event.event_type, "org.example.custom");
+
+        let decrypted = event.raw_event.deserialize_as::<serde_json::Value>().unwrap();
+        assert_eq!(decrypted["content"], content);
+    }
 }
@@ -92,6 +92,8 @@
 
 use futures_core::{Future, Stream};
 use futures_util::StreamExt as _;
+use matrix_sdk_base::crypto::secret_storage::SecretStorageKey;
+pub use matrix_sdk_base::crypto::secret_storage::{PassphraseStrength, RecoveryKeyValidation};
 use ruma::{
     api::client::keys::get_keys,
     events::{
@@ -109,7 +111,11 @@ use crate::encryption::{
     backups::Backups,
     secret_storage::{SecretStorage, SecretStore},
 };
-use crate::{client::WeakClient, encryption::backups::BackupState, Client};
+use crate::{
+    client::WeakClient,
+    encryption::{backups::BackupState, secret_storage::SecretStorageError},
+    Client,
+};
 
 pub mod futures;
 mod types;
@@ -491,6 +497,69 @@ impl Recovery {
         Ok(())
     }
 
+    /// Check if the given recovery key, or passphrase, can be used to recover
+    /// the secrets stored on the homeserver.
+    ///
+    /// Unlike [`Recovery::recover()`], this doesn't import any secrets or
+    /// change the state of the recovery subsystem, it's meant to give the
+    /// user feedback before they attempt to recover.
+    ///
+    /// Returns `false` if the key doesn't match the default secret storage
+    /// key.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// let recovery = client.encryption().recovery();
+    ///
+    /// if recovery.check_recovery_key("my recovery key").await? {
+    ///     recovery.recover("my recovery key").await?;
+    /// } else {
+    ///     println!("That's not the right recovery key");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn check_recovery_key(&self, recovery_key: &str) -> Result<bool> {
+        match self.client.encryption().secret_storage().open_secret_store(recovery_key).await {
+            Ok(_) => Ok(true),
+            Err(SecretStorageError::SecretStorageKey(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Validate a, possibly partially typed in, recovery key.
+    ///
+    /// This doesn't contact the homeserver, so it can be called whenever the
+    /// user changes their input. Use [`Recovery::check_recovery_key()`] to
+    /// check if a well-formed recovery key matches the user's secret storage.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use matrix_sdk::encryption::recovery::{Recovery, RecoveryKeyValidation};
+    ///
+    /// match Recovery::validate_recovery_key("EsTp RvZT") {
+    ///     RecoveryKeyValidation::Incomplete { entered, expected } => {
+    ///         println!("{entered} out of {expected} characters entered")
+    ///     }
+    ///     validation => println!("{validation:?}"),
+    /// }
+    /// ```
+    pub fn validate_recovery_key(input: &str) -> RecoveryKeyValidation {
+        SecretStorageKey::validate_base58(input)
+    }
+
+    /// Estimate how hard it would be to guess the given passphrase.
+    ///
+    /// This can be used to give the user feedback while they choose the
+    /// passphrase passed to [`Enable::with_passphrase()`].
+    pub fn passphrase_strength(passphrase: &str) -> PassphraseStrength {
+        PassphraseStrength::estimate(passphrase)
+    }
+
     /// Is this device the last device the user has?
     ///
     /// This method is useful to check if we should recommend to the user that
@@ -12,12 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 
 use matrix_sdk_common::failures_cache::FailuresCache;
 use ruma::{
+    api::client::error::ErrorKind,
     events::room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
     serde::Raw,
+    time::Instant,
     OwnedEventId, OwnedRoomId,
 };
 use tokio::sync::{
@@ -218,6 +224,22 @@ impl BackupDownloadTask {
         #[cfg(not(test))]
         crate::sleep::sleep(Duration::from_millis(Self::DOWNLOAD_DELAY_MILLIS)).await;
 
+        // In the low bandwidth profile, wait until the profile changes.
+        let bandwidth_profile =
+            state.lock().await.client.get().map(|client| client.subscribe_to_bandwidth_profile());
+
+        if let Some(mut bandwidth_profile) = bandwidth_profile {
+            while bandwidth_profile.get().settings().defer_backup_download {
+                trace!(event_id = ?download_request.event_id, "Deferring the backup download");
+
+                if bandwidth_profile.next().await.is_none() {
+                    // The client was dropped.
+                    state.lock().await.active_tasks.remove(&download_request.event_id);
+                    return;
+                }
+            }
+        }
+
         // Now take the lock, and check that we still want to do a download. If we do,
         // keep hold of a strong reference to the `Client`.
         let client = {
@@ -251,6 +273,8 @@ impl BackupDownloadTask {
             .download_room_key(&download_request.room_id, &download_request.megolm_session_id)
             .await;
 
+        let backup_state = &client.inner.e2ee.backup_state;
+
         // Then take the lock again to update the state.
         {
             let mut state = state.lock().await;
@@ -258,6 +282,8 @@ impl BackupDownloadTask {
 
             match result {
                 Ok(true) => {
+                    backup_state.update_on_demand_download_stats(|stats| stats.downloaded += 1);
+
                     // We successfully downloaded the room key. We can clear any record of previous
                     // backoffs from the failures cache, because we won't be needing them again.
                     state.failures_cache.remove(std::iter::once(&room_key_info))
@@ -267,7 +293,15 @@ impl BackupDownloadTask {
                     // even attempt to download the room key.
                     state.downloaded_room_keys.remove(std::iter::once(&room_key_info));
                 }
-                Err(_) => {
+                Err(error) => {
+                    if error.client_api_error_kind() == Some(&ErrorKind::NotFound) {
+                        debug!(?download_request, "The room key is not in the backup");
+                        backup_state.update_on_demand_download_stats(|stats| stats.not_found += 1);
+                    } else {
+                        warn!(?download_request, "Failed to download the room key: {error}");
+                        backup_state.update_on_demand_download_stats(|stats| stats.failed += 1);
+                    }
+
                     // We were unable to download the room key. Update the failure cache so that we
                     // back off from more requests, and also remove the entry from the list of
                     // room keys that we are downloading.
@@ -300,9 +334,19 @@ struct BackupDownloadTaskListenerState {
     /// from the backup, there's not much point trying again even if we get
     /// another UTD event that uses the same room key.
     downloaded_room_keys: DownloadCache,
+
+    /// The times of the most recent downloads, within the last
+    /// [`Self::RATE_LIMIT_WINDOW`].
+    recent_downloads: VecDeque<Instant>,
 }
 
 impl BackupDownloadTaskListenerState {
+    /// The maximum number of downloads in [`Self::RATE_LIMIT_WINDOW`].
+    const MAX_DOWNLOADS_PER_WINDOW: usize = 20;
+
+    /// The duration over which the number of downloads is limited.
+    const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+
     /// Prepare a new `BackupDownloadTaskListenerState`.
     ///
     /// # Arguments
@@ -318,20 +362,42 @@ impl BackupDownloadTaskListenerState {
                 Duration::from_secs(60 * 60 * 24),
                 60,
             ),
+            recent_downloads: Default::default(),
         }
     }
 
+    /// Check whether too many downloads happened recently, and record a new
+    /// download otherwise.
+    fn is_rate_limited(&mut self, now: Instant) -> bool {
+        while self
+            .recent_downloads
+            .front()
+            .is_some_and(|time| now.duration_since(*time) >= Self::RATE_LIMIT_WINDOW)
+        {
+            self.recent_downloads.pop_front();
+        }
+
+        if self.recent_downloads.len() >= Self::MAX_DOWNLOADS_PER_WINDOW {
+            return true;
+        }
+
+        self.recent_downloads.push_back(now);
+        false
+    }
+
     /// Check if we should set off a download for the given request.
     ///
     /// Checks if:
+    ///  * we don't have the decryption key of the current backup,
     ///  * we already have the key,
-    ///  * we have already downloaded this room key, or are about to do so, or
-    ///  * we've backed off from trying to download this room key.
+    ///  * we have already downloaded this room key, or are about to do so,
+    ///  * we've backed off from trying to download this room key, or
+    ///  * too many room keys were downloaded recently.
     ///
     /// If any of the above are true, returns `false`. Otherwise, returns
     /// `true`.
     pub async fn should_download(
-        &self,
+        &mut self,
         client: &Client,
         download_request: &RoomKeyDownloadRequest,
     ) -> bool {
@@ -351,6 +417,25 @@ impl BackupDownloadTaskListenerState {
             return false;
         }
 
+        // Only download room keys from a backup that we trust, i.e. one for which we
+        // have the decryption key.
+        let backup_keys = machine.backup_machine().get_backup_keys().await.unwrap_or_default();
+        if backup_keys.decryption_key.is_none()
+            || backup_keys.backup_version != machine.backup_machine().backup_version().await
+        {
+            debug!(
+                ?download_request,
+                "Not performing backup download because we don't have the decryption key of the current backup"
+            );
+            client
+                .inner
+                .e2ee
+                .backup_state
+                .update_on_demand_download_stats(|stats| stats.untrusted += 1);
+
+            return false;
+        }
+
         // Check if the keys for this message have arrived in the meantime.
         // If we get a StoreError doing the lookup, we assume the keys haven't arrived
         // (though if the store is returning errors, probably something else is
@@ -384,6 +469,21 @@ impl BackupDownloadTaskListenerState {
             return false;
         }
 
+        // Check if we're not downloading too many room keys at once.
+        if self.is_rate_limited(Instant::now()) {
+            debug!(
+                ?download_request,
+                "Not performing backup download because too many room keys were downloaded recently"
+            );
+            client
+                .inner
+                .e2ee
+                .backup_state
+                .update_on_demand_download_stats(|stats| stats.rate_limited += 1);
+
+            return false;
+        }
+
         debug!(?download_request, "Performing backup download");
         true
     }
@@ -455,4 +555,24 @@ mod test {
             )
         }
     }
+
+    #[async_test]
+    async fn test_download_rate_limit() {
+        let client = logged_in_client(None).await;
+        let mut state = BackupDownloadTaskListenerState::new(WeakClient::from_client(&client));
+
+        let now = Instant::now();
+        for _ in 0..BackupDownloadTaskListenerState::MAX_DOWNLOADS_PER_WINDOW {
+            assert!(!state.is_rate_limited(now));
+        }
+
+        // Too many downloads happened in the window.
+        assert!(state.is_rate_limited(now));
+        assert!(state.is_rate_limited(now + Duration::from_secs(5)));
+
+        // Once the window has passed, downloads are allowed again.
+        let later = now + BackupDownloadTaskListenerState::RATE_LIMIT_WINDOW;
+        assert!(!state.is_rate_limited(later));
+        assert_eq!(state.recent_downloads.len(), 1);
+    }
 }
@@ -37,7 +37,7 @@ mod sas;
 use as_variant::as_variant;
 pub use matrix_sdk_base::crypto::{
     format_emojis, AcceptSettings, AcceptedProtocols, CancelInfo, Emoji, EmojiShortAuthString,
-    SasState,
+    LocalizableEmoji, SasState, ShortAuthString, SAS_EMOJI_TRANSLATION_KEY_PREFIX,
 };
 #[cfg(feature = "qrcode")]
 pub use matrix_sdk_base::crypto::{
@@ -48,7 +48,7 @@ pub use matrix_sdk_base::crypto::{
 pub use qrcode::QrVerification;
 pub use requests::{VerificationRequest, VerificationRequestState};
 use ruma::RoomId;
-pub use sas::SasVerification;
+pub use sas::{SasFlowState, SasVerification, SasVerificationFlow};
 
 /// An enum over the different verification types the SDK supports.
 #[derive(Debug, Clone)]
@@ -12,13 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
 use matrix_sdk_base::crypto::{
-    AcceptSettings, CancelInfo, DeviceData, Emoji, Sas as BaseSas, SasState,
+    AcceptSettings, CancelInfo, DeviceData, Emoji, Sas as BaseSas, SasState, ShortAuthString,
 };
+use matrix_sdk_common::{timeout::timeout, SendOutsideWasm};
 use ruma::{events::key::verification::cancel::CancelCode, RoomId, UserId};
+use tracing::warn;
+
+use crate::{
+    error::Result,
+    executor::{spawn, JoinHandle},
+    Client,
+};
 
-use crate::{error::Result, Client};
+/// How often a running [`SasVerificationFlow`] checks whether the
+/// verification has timed out.
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// An object controlling the short auth string verification flow.
 #[derive(Debug, Clone)]
@@ -156,6 +169,17 @@ impl SasVerification {
         self.inner.emoji()
     }
 
+    /// Get the short auth string in a form suitable for localized display.
+    ///
+    /// Contains the decimal representation, and the emoji representation
+    /// with stable identifiers and translation keys if emoji were agreed on
+    /// by both sides.
+    ///
+    /// Returns `None` if the short auth string can't be presented yet.
+    pub fn short_auth_string(&self) -> Option<ShortAuthString> {
+        self.inner.short_auth_string()
+    }
+
     /// Get the decimal version of the short auth string.
     pub fn decimals(&self) -> Option<(u16, u16, u16)> {
         self.inner.decimals()
@@ -333,4 +357,198 @@ impl SasVerification {
     pub fn room_id(&self) -> Option<&RoomId> {
         self.inner.room_id()
     }
+
+    /// Drive this verification in the background, reporting every state the
+    /// UI needs to render to the given callback.
+    ///
+    /// The callback is called once with the current state, and then every
+    /// time the [`SasFlowState`] changes, until a final state is reached.
+    /// Transitions caused by the other side are mirrored as well, and the
+    /// verification is cancelled with [`CancelCode::Timeout`] if it times
+    /// out.
+    ///
+    /// The UI still calls [`SasVerification::accept`],
+    /// [`SasVerification::confirm`] or [`SasVerification::mismatch`] in
+    /// response to user input.
+    ///
+    /// The flow stops when the returned [`SasVerificationFlow`] is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use matrix_sdk::encryption::verification::{SasFlowState, SasVerification};
+    ///
+    /// # let sas: SasVerification = unimplemented!();
+    /// let flow = sas.run_flow(|state| match state {
+    ///     SasFlowState::Compare(sas) => {
+    ///         for emoji in sas.emojis.into_iter().flatten() {
+    ///             println!("{} {}", emoji.emoji.symbol, emoji.translation_key());
+    ///         }
+    ///     }
+    ///     SasFlowState::Cancelled(info) => {
+    ///         println!("Cancelled: {}", info.reason());
+    ///     }
+    ///     _ => {}
+    /// });
+    /// ```
+    pub fn run_flow(
+        &self,
+        on_state: impl Fn(SasFlowState) + SendOutsideWasm + 'static,
+    ) -> SasVerificationFlow {
+        let verification = self.clone();
+
+        let join_handle = spawn({
+            let verification = verification.clone();
+
+            async move {
+                let changes = verification.changes();
+                pin_mut!(changes);
+
+                let mut current = SasFlowState::from(verification.state());
+                on_state(current.clone());
+
+                while !current.is_final() {
+                    let next = match timeout(changes.next(), TIMEOUT_CHECK_INTERVAL).await {
+                        Ok(Some(state)) => SasFlowState::from(state),
+                        Ok(None) => break,
+                        Err(_) => {
+                            if verification.inner.timed_out() {
+                                verification.cancel_timed_out().await;
+                            }
+
+                            SasFlowState::from(verification.state())
+                        }
+                    };
+
+                    if !next.same_state(&current) {
+                        current = next;
+                        on_state(current.clone());
+                    }
+                }
+            }
+        });
+
+        SasVerificationFlow { verification, join_handle }
+    }
+
+    async fn cancel_timed_out(&self) {
+        if let Some(request) = self.inner.cancel_with_code(CancelCode::Timeout) {
+            if let Err(e) = self.client.send_verification_request(request).await {
+                warn!("Couldn't send the cancellation of a timed out verification: {e}");
+            }
+        }
+    }
+}
+
+/// The state of a SAS verification, reduced to what a UI needs to render.
+///
+/// Obtained from a [`SasState`] or through [`SasVerification::run_flow`].
+#[derive(Clone, Debug)]
+pub enum SasFlowState {
+    /// The other side started the verification, the user needs to accept it
+    /// with [`SasVerification::accept`].
+    Incoming,
+
+    /// We're waiting for the other side to respond.
+    Waiting,
+
+    /// The short auth strings should be shown to the user, who confirms
+    /// with [`SasVerification::confirm`] or rejects with
+    /// [`SasVerification::mismatch`].
+    Compare(ShortAuthString),
+
+    /// The user confirmed the short auth strings, we're waiting for the
+    /// other side to confirm as well.
+    WaitingForOtherSide,
+
+    /// The verification was successfully completed.
+    Done,
+
+    /// The verification was cancelled, by either side.
+    Cancelled(CancelInfo),
+}
+
+impl SasFlowState {
+    /// Is this a final state, i.e. the verification won't progress anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Done | Self::Cancelled(_))
+    }
+
+    /// Do both values represent the same state.
+    ///
+    /// The short auth string can't change once presented, and a verification
+    /// is only cancelled once, so comparing the variants is enough.
+    fn same_state(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl From<SasState> for SasFlowState {
+    fn from(state: SasState) -> Self {
+        match state {
+            SasState::Started { .. } => Self::Incoming,
+            SasState::Created { .. } | SasState::Accepted { .. } => Self::Waiting,
+            SasState::KeysExchanged { emojis, decimals } => {
+                Self::Compare(ShortAuthString { emojis: emojis.map(|e| e.localizable()), decimals })
+            }
+            SasState::Confirmed => Self::WaitingForOtherSide,
+            SasState::Done { .. } => Self::Done,
+            SasState::Cancelled(info) => Self::Cancelled(info),
+        }
+    }
+}
+
+/// A running SAS verification flow, created by
+/// [`SasVerification::run_flow`].
+///
+/// The flow is stopped when this object is dropped.
+#[derive(Debug)]
+pub struct SasVerificationFlow {
+    verification: SasVerification,
+    join_handle: JoinHandle<()>,
+}
+
+impl SasVerificationFlow {
+    /// Get the verification this flow is driving.
+    pub fn verification(&self) -> &SasVerification {
+        &self.verification
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for SasVerificationFlow {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_let;
+    use matrix_sdk_base::crypto::SasState;
+
+    use super::SasFlowState;
+
+    #[test]
+    fn test_flow_state_from_sas_state() {
+        let state =
+            SasFlowState::from(SasState::KeysExchanged { emojis: None, decimals: (1, 2, 3) });
+        assert_let!(SasFlowState::Compare(sas) = &state);
+        assert!(sas.emojis.is_none());
+        assert_eq!(sas.decimals, (1, 2, 3));
+        assert!(!state.is_final());
+
+        let state = SasFlowState::from(SasState::Confirmed);
+        assert_let!(SasFlowState::WaitingForOtherSide = &state);
+        assert!(!state.is_final());
+
+        let state = SasFlowState::from(SasState::Done {
+            verified_devices: Vec::new(),
+            verified_identities: Vec::new(),
+        });
+        assert_let!(SasFlowState::Done = &state);
+        assert!(state.is_final());
+        assert!(state.same_state(&SasFlowState::Done));
+        assert!(!state.same_state(&SasFlowState::Waiting));
+    }
 }
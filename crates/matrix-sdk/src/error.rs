@@ -37,17 +37,28 @@ use ruma::{
         },
         error::{FromHttpResponseError, IntoHttpError},
     },
-    events::tag::InvalidUserTagName,
+    events::{tag::InvalidUserTagName, StateEventType},
     push::{InsertPushRuleError, RemovePushRuleError},
-    IdParseError,
+    IdParseError, RoomVersionId,
 };
 use serde_json::Error as JsonError;
 use thiserror::Error;
 use url::ParseError as UrlParseError;
 
 use crate::{
-    authentication::oauth::OAuthError, event_cache::EventCacheError, media::MediaError,
-    room::reply::ReplyError, sliding_sync::Error as SlidingSyncError, store_locks::LockStoreError,
+    authentication::oauth::OAuthError,
+    event_cache::EventCacheError,
+    guest::GuestError,
+    identity_server::IdentityServerError,
+    image_pack::ImagePackError,
+    lightweight::LightweightError,
+    media::MediaError,
+    room::{
+        builder::RoomBuilderError, mentions::MentionsError, message_builder::MessageBuilderError,
+        permissions::RoomPermissionsError, reply::ReplyError, state::StateBatchError,
+    },
+    sliding_sync::Error as SlidingSyncError,
+    store_locks::LockStoreError,
 };
 
 /// Result type of the matrix-sdk.
@@ -388,6 +399,56 @@ pub enum Error {
     /// An error happened while attempting to reply to an event.
     #[error(transparent)]
     ReplyError(#[from] ReplyError),
+
+    /// An invalid combination of settings was used to create a room.
+    #[error(transparent)]
+    RoomBuilder(#[from] RoomBuilderError),
+
+    /// The user doesn't have the power level required to send state events of
+    /// the given type in the room.
+    #[error("the user isn't allowed to send `{0}` state events in this room")]
+    InsufficientPowerLevel(StateEventType),
+
+    /// The join rule isn't supported by the version of the room.
+    #[error("the `{join_rule}` join rule isn't supported in rooms of version {room_version}")]
+    UnsupportedJoinRule {
+        /// The unsupported join rule.
+        join_rule: String,
+        /// The version of the room.
+        room_version: RoomVersionId,
+    },
+
+    /// An error happened while changing the power levels of a room.
+    #[error(transparent)]
+    RoomPermissions(#[from] RoomPermissionsError),
+
+    /// An error happened while sending a batch of state events to a room.
+    #[error(transparent)]
+    StateBatch(#[from] StateBatchError),
+
+    /// An error happened while using an identity server.
+    #[error(transparent)]
+    IdentityServer(#[from] IdentityServerError),
+
+    /// An error happened while using an image pack.
+    #[error(transparent)]
+    ImagePack(#[from] ImagePackError),
+
+    /// An error happened while building a message with mentions.
+    #[error(transparent)]
+    Mentions(#[from] MentionsError),
+
+    /// An error happened while building a rich text message.
+    #[error(transparent)]
+    MessageBuilder(#[from] MessageBuilderError),
+
+    /// An error happened while using guest access.
+    #[error(transparent)]
+    Guest(#[from] GuestError),
+
+    /// An error happened while using the sync-less lightweight API.
+    #[error(transparent)]
+    Lightweight(#[from] LightweightError),
 }
 
 #[rustfmt::skip] // stop rustfmt breaking the `<code>` in docs across multiple lines
@@ -0,0 +1,168 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The registry of the latest displayable event of each room, for example to
+//! show a preview in a room list without creating a timeline for every room.
+//!
+//! It is updated by each room's event cache, every time its loaded events
+//! change, be it from sync, back-pagination or a re-decryption.
+
+use std::collections::BTreeMap;
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use ruma::{
+    events::{
+        relation::RelationType,
+        room::message::{MessageType, Relation},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    OwnedRoomId, RoomId,
+};
+
+use super::room::events::RoomEvents;
+
+/// The latest displayable event of each room, keyed by room.
+#[derive(Clone, Debug, Default)]
+pub(super) struct LatestEvents(SharedObservable<BTreeMap<OwnedRoomId, TimelineEvent>>);
+
+impl LatestEvents {
+    /// The latest displayable event of the given room, if any.
+    pub fn get(&self, room_id: &RoomId) -> Option<TimelineEvent> {
+        self.0.read().get(room_id).cloned()
+    }
+
+    /// Subscribe to the latest displayable event of all the rooms.
+    pub fn subscribe(&self) -> Subscriber<BTreeMap<OwnedRoomId, TimelineEvent>> {
+        self.0.subscribe()
+    }
+
+    /// Update the latest displayable event of the given room, from its loaded
+    /// events.
+    ///
+    /// If none of the loaded events is displayable, the previous latest event
+    /// is kept, since it was loaded before and is still the latest one.
+    pub fn update(&self, room_id: &RoomId, events: &RoomEvents) {
+        let Some(latest_event) =
+            events.revents().map(|(_position, event)| event).find(|event| is_displayable(event))
+        else {
+            return;
+        };
+
+        self.0.update_if(|latest_events| {
+            let changed = latest_events.get(room_id).is_none_or(|previous| {
+                previous.event_id() != latest_event.event_id()
+                    || previous.raw().json().get() != latest_event.raw().json().get()
+            });
+
+            if changed {
+                latest_events.insert(room_id.to_owned(), latest_event.clone());
+            }
+
+            changed
+        });
+    }
+}
+
+/// Whether the given event can be displayed as the latest event of a room.
+///
+/// Events that couldn't be decrypted are displayable, they're replaced by
+/// their decrypted form once the room key is received.
+fn is_displayable(event: &TimelineEvent) -> bool {
+    let Ok(AnySyncTimelineEvent::MessageLike(event)) = event.raw().deserialize() else {
+        return false;
+    };
+
+    match event {
+        AnySyncMessageLikeEvent::RoomMessage(message) => {
+            let Some(message) = message.as_original() else {
+                // Redacted messages are still displayed.
+                return true;
+            };
+
+            !matches!(message.content.msgtype, MessageType::VerificationRequest(_))
+                && !matches!(message.content.relates_to, Some(Relation::Replacement(_)))
+        }
+
+        AnySyncMessageLikeEvent::Sticker(_)
+        | AnySyncMessageLikeEvent::UnstablePollStart(_)
+        | AnySyncMessageLikeEvent::CallInvite(_)
+        | AnySyncMessageLikeEvent::CallNotify(_) => true,
+
+        AnySyncMessageLikeEvent::RoomEncrypted(event) => {
+            // Edits that couldn't be decrypted shouldn't replace the latest event.
+            event.as_original().is_none_or(|event| {
+                event.content.relates_to.as_ref().and_then(|relation| relation.rel_type())
+                    != Some(RelationType::Replacement)
+            })
+        }
+
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::event_factory::EventFactory;
+    use ruma::{
+        event_id, events::room::message::RoomMessageEventContentWithoutRelation, room_id, user_id,
+    };
+    use stream_assert::assert_pending;
+
+    use super::{is_displayable, LatestEvents};
+    use crate::event_cache::room::events::RoomEvents;
+
+    #[test]
+    fn test_is_displayable() {
+        let f = EventFactory::new().room(room_id!("!r:b.c")).sender(user_id!("@a:b.c"));
+
+        assert!(is_displayable(&f.text_msg("hello").into_event()));
+        assert!(is_displayable(&f.notice("hello").into_event()));
+        assert!(!is_displayable(
+            &f.text_msg("* edit")
+                .edit(
+                    event_id!("$original"),
+                    RoomMessageEventContentWithoutRelation::text_plain("edit")
+                )
+                .into_event()
+        ));
+        assert!(!is_displayable(&f.reaction(event_id!("$original"), "👍").into_event()));
+        assert!(!is_displayable(&f.room_name("Room").into_event()));
+    }
+
+    #[test]
+    fn test_latest_events_update() {
+        let room_id = room_id!("!r:b.c");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@a:b.c"));
+        let latest_events = LatestEvents::default();
+        let mut subscriber = latest_events.subscribe();
+
+        let mut events = RoomEvents::new();
+        events.push_events([
+            f.text_msg("hello").event_id(event_id!("$1")).into_event(),
+            f.reaction(event_id!("$1"), "👍").event_id(event_id!("$2")).into_event(),
+        ]);
+        latest_events.update(room_id, &events);
+
+        assert_eq!(latest_events.get(room_id).unwrap().event_id().unwrap(), "$1");
+        assert!(subscriber.next_now().contains_key(room_id));
+
+        // Without displayable events, the previous latest event is kept.
+        let mut events = RoomEvents::new();
+        events.push_events([f.reaction(event_id!("$1"), "🎉").into_event()]);
+        latest_events.update(room_id, &events);
+        assert_eq!(latest_events.get(room_id).unwrap().event_id().unwrap(), "$1");
+        assert_pending!(subscriber);
+    }
+}
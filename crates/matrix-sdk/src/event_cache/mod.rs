@@ -27,8 +27,10 @@
 
 #![forbid(missing_docs)]
 
+#[cfg(feature = "e2e-encryption")]
+use std::sync::RwLock as StdRwLock;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     sync::{Arc, OnceLock},
 };
@@ -47,7 +49,8 @@ use matrix_sdk_common::executor::{spawn, JoinHandle};
 use once_cell::sync::OnceCell;
 use room::RoomEventCacheState;
 use ruma::{
-    events::AnySyncEphemeralRoomEvent, serde::Raw, OwnedEventId, OwnedRoomId, RoomId, RoomVersionId,
+    events::AnySyncEphemeralRoomEvent, serde::Raw, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    RoomVersionId,
 };
 use tokio::sync::{
     broadcast::{error::RecvError, Receiver},
@@ -55,16 +58,36 @@ use tokio::sync::{
 };
 use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument as _, Span};
 
-use self::paginator::PaginatorError;
+use self::{latest_events::LatestEvents, paginator::PaginatorError};
+#[cfg(feature = "e2e-encryption")]
+use crate::unable_to_decrypt_hook::UtdHookManager;
 use crate::{client::WeakClient, Client};
 
 mod deduplicator;
+mod latest_events;
 mod pagination;
+mod preprocessor;
+#[cfg(feature = "e2e-encryption")]
+mod redecryptor;
 mod room;
+#[cfg(feature = "e2e-encryption")]
+mod utd_hook;
+#[cfg(feature = "e2e-encryption")]
+mod utd_reporter;
 
 pub mod paginator;
-pub use pagination::{PaginationToken, RoomPagination, RoomPaginationStatus};
+pub use matrix_sdk_base::event_cache::store::RetainedContent;
+pub use pagination::{
+    PaginationToken, RoomPagination, RoomPaginationMetrics, RoomPaginationStatus,
+};
+pub use preprocessor::{
+    EventPreprocessor, EventPreprocessorHandle, EventPreprocessorMetrics, PreprocessingOutcome,
+};
+#[cfg(feature = "e2e-encryption")]
+pub use redecryptor::{PendingDecryption, RetryReason};
 pub use room::{RoomEventCache, RoomEventCacheListener};
+#[cfg(feature = "e2e-encryption")]
+pub use utd_reporter::{AnonymizedUtdReport, UtdReportDestination, UtdReporter, UtdReporterConfig};
 
 /// An error observed in the [`EventCache`].
 #[derive(thiserror::Error, Debug)]
@@ -105,6 +128,21 @@ pub enum EventCacheError {
     /// [`LinkedChunk`]: matrix_sdk_common::linked_chunk::LinkedChunk
     #[error(transparent)]
     LinkedChunkLoader(#[from] LazyLoaderError),
+
+    /// The linked chunk of a room has been found to be inconsistent, e.g. it
+    /// contains the same event several times.
+    #[error("The linked chunk of a room is inconsistent")]
+    InconsistentLinkedChunk,
+
+    /// The storage of the event cache hasn't been enabled with
+    /// [`EventCache::enable_storage`], so the requested operation isn't
+    /// possible.
+    #[error("The event cache storage hasn't been enabled, call `EventCache::enable_storage()`")]
+    StorageDisabled,
+
+    /// An event couldn't be found in the event cache.
+    #[error("The event {0} couldn't be found in the event cache")]
+    EventNotFound(OwnedEventId),
 }
 
 /// A result using the [`EventCacheError`].
@@ -120,6 +158,16 @@ pub struct EventCacheDropHandles {
 
     /// The task used to automatically shrink the linked chunks.
     auto_shrink_linked_chunk_task: JoinHandle<()>,
+
+    /// The task retrying to decrypt events when their room keys are received.
+    #[cfg(feature = "e2e-encryption")]
+    redecryption_task: JoinHandle<()>,
+
+    /// The task reporting UTDs to the [`UtdHookManager`].
+    ///
+    /// [`UtdHookManager`]: crate::unable_to_decrypt_hook::UtdHookManager
+    #[cfg(feature = "e2e-encryption")]
+    utd_hook_task: JoinHandle<()>,
 }
 
 impl Debug for EventCacheDropHandles {
@@ -133,6 +181,10 @@ impl Drop for EventCacheDropHandles {
         self.listen_updates_task.abort();
         self.ignore_user_list_update_task.abort();
         self.auto_shrink_linked_chunk_task.abort();
+        #[cfg(feature = "e2e-encryption")]
+        self.redecryption_task.abort();
+        #[cfg(feature = "e2e-encryption")]
+        self.utd_hook_task.abort();
     }
 }
 
@@ -164,6 +216,12 @@ impl EventCache {
                 by_room: Default::default(),
                 drop_handles: Default::default(),
                 auto_shrink_sender: Default::default(),
+                latest_events: Default::default(),
+                preprocessors: Default::default(),
+                #[cfg(feature = "e2e-encryption")]
+                utd_hook: Default::default(),
+                #[cfg(feature = "e2e-encryption")]
+                utd_hook_sender: Default::default(),
             }),
         }
     }
@@ -172,6 +230,12 @@ impl EventCache {
     ///
     /// Has an effect only the first time it's called. It's safe to call it
     /// multiple times.
+    ///
+    /// Events that have been decrypted are persisted along with their
+    /// decrypted content, so they don't have to be decrypted again when
+    /// they're reloaded from storage. Use a passphrase-protected event cache
+    /// store to keep this content encrypted at rest. When such an event is
+    /// redacted, its persisted copy is replaced by the redacted form.
     pub fn enable_storage(&self) -> Result<()> {
         let _ = self.inner.store.get_or_try_init::<_, EventCacheError>(|| {
             let client = self.inner.client()?;
@@ -213,16 +277,103 @@ impl EventCache {
             let auto_shrink_linked_chunk_tasks =
                 spawn(Self::auto_shrink_linked_chunk_task(self.inner.clone(), rx));
 
+            #[cfg(feature = "e2e-encryption")]
+            let redecryption_task = spawn(redecryptor::redecryption_task(self.inner.clone()));
+
+            #[cfg(feature = "e2e-encryption")]
+            let utd_hook_task = {
+                let (tx, rx) = mpsc::unbounded_channel();
+
+                // Force-initialize the sender in the [`RoomEventCacheInner`].
+                self.inner.utd_hook_sender.get_or_init(|| tx);
+
+                spawn(utd_hook::utd_hook_task(self.inner.clone(), rx))
+            };
+
             Arc::new(EventCacheDropHandles {
                 listen_updates_task,
                 ignore_user_list_update_task,
                 auto_shrink_linked_chunk_task: auto_shrink_linked_chunk_tasks,
+                #[cfg(feature = "e2e-encryption")]
+                redecryption_task,
+                #[cfg(feature = "e2e-encryption")]
+                utd_hook_task,
             })
         });
 
         Ok(())
     }
 
+    /// Install a hook that will be notified about every event the event cache
+    /// couldn't decrypt, and their eventual decryption.
+    ///
+    /// The grace period before reporting a UTD, and whether the decryption of
+    /// the UTDs that have already been reported is reported too, are
+    /// configured on the [`UtdHookManager`]. The same manager can be given to
+    /// the timelines, so their UTDs are deduplicated with the ones of the
+    /// event cache.
+    ///
+    /// Replaces any previously installed hook.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn set_unable_to_decrypt_hook(&self, hook: Arc<UtdHookManager>) {
+        *self.inner.utd_hook.write().unwrap() = Some(hook);
+    }
+
+    /// Add a pre-processor, run on every event received from the server
+    /// before it's added to the event cache.
+    ///
+    /// The pre-processors run in ascending order of `priority`, and in the
+    /// order they were added when they have the same priority. The `name`
+    /// identifies the pre-processor in the
+    /// [`EventCache::preprocessor_metrics`].
+    ///
+    /// The events received before the pre-processor is added aren't
+    /// processed again.
+    pub fn add_preprocessor(
+        &self,
+        name: impl Into<String>,
+        priority: i32,
+        preprocessor: Arc<dyn EventPreprocessor>,
+    ) -> EventPreprocessorHandle {
+        self.inner.preprocessors.add(name.into(), priority, preprocessor)
+    }
+
+    /// Remove a pre-processor added with [`EventCache::add_preprocessor`].
+    ///
+    /// Returns whether the pre-processor was still there.
+    pub fn remove_preprocessor(&self, handle: EventPreprocessorHandle) -> bool {
+        self.inner.preprocessors.remove(handle)
+    }
+
+    /// Get the counters about the events seen by each pre-processor, in the
+    /// order the pre-processors run.
+    pub fn preprocessor_metrics(&self) -> Vec<EventPreprocessorMetrics> {
+        self.inner.preprocessors.metrics()
+    }
+
+    /// Get the latest displayable event of the given room, if any.
+    ///
+    /// Only the rooms whose event cache has been loaded, for example by
+    /// receiving events from sync or by calling [`Room::event_cache()`], have
+    /// a latest event.
+    ///
+    /// [`Room::event_cache()`]: crate::Room::event_cache
+    pub fn latest_event(&self, room_id: &RoomId) -> Option<TimelineEvent> {
+        self.inner.latest_events.get(room_id)
+    }
+
+    /// Subscribe to the latest displayable event of each room.
+    ///
+    /// This is meant to display a preview of the rooms, for example in a room
+    /// list, without creating a timeline for each room. The events are
+    /// decrypted when possible, and updated when they're decrypted later.
+    ///
+    /// Like [`Self::latest_event`], it only covers the rooms whose event cache
+    /// has been loaded.
+    pub fn subscribe_to_latest_events(&self) -> Subscriber<BTreeMap<OwnedRoomId, TimelineEvent>> {
+        self.inner.latest_events.subscribe()
+    }
+
     #[instrument(skip_all)]
     async fn ignore_user_list_update_task(
         inner: Arc<EventCacheInner>,
@@ -232,10 +383,37 @@ impl EventCache {
         span.follows_from(Span::current());
 
         async move {
-            while ignore_user_list_stream.next().await.is_some() {
+            let parse_user_ids = |user_ids: Vec<String>| {
+                user_ids
+                    .into_iter()
+                    .filter_map(|user_id| OwnedUserId::try_from(user_id).ok())
+                    .collect::<BTreeSet<_>>()
+            };
+
+            let mut ignored_users = parse_user_ids(ignore_user_list_stream.get());
+
+            while let Some(user_ids) = ignore_user_list_stream.next().await {
                 info!("Received an ignore user list change");
-                if let Err(err) = inner.clear_all_rooms().await {
-                    error!("when clearing room storage after ignore user list change: {err}");
+
+                let new_ignored_users = parse_user_ids(user_ids);
+                let unignored_users =
+                    ignored_users.difference(&new_ignored_users).cloned().collect::<BTreeSet<_>>();
+                let newly_ignored_users =
+                    new_ignored_users.difference(&ignored_users).cloned().collect::<BTreeSet<_>>();
+                ignored_users = new_ignored_users;
+
+                if !unignored_users.is_empty() {
+                    // The events of the unignored users were never received, so their rooms
+                    // must be reloaded from the homeserver.
+                    if let Err(err) = inner.clear_rooms_with_users(&unignored_users).await {
+                        error!("when clearing room storage after ignore user list change: {err}");
+                    }
+                }
+
+                if !newly_ignored_users.is_empty() {
+                    if let Err(err) = inner.remove_events_from_senders(&newly_ignored_users).await {
+                        error!("when removing the events of ignored users: {err}");
+                    }
                 }
             }
             info!("Ignore user list stream has closed");
@@ -365,10 +543,24 @@ impl EventCache {
     /// Cleanly clear all the rooms' event caches.
     ///
     /// This will notify any live observers that the room has been cleared.
+    ///
+    /// The rooms and events marked as retained with
+    /// [`RoomEventCache::set_retained`] and
+    /// [`RoomEventCache::set_event_retained`] are kept.
     pub async fn clear_all_rooms(&self) -> Result<()> {
         self.inner.clear_all_rooms().await
     }
 
+    /// Get all the rooms and events that are retained in the event cache
+    /// store, regardless of any cleanup.
+    ///
+    /// See [`RoomEventCache::set_retained`] and
+    /// [`RoomEventCache::set_event_retained`] to change them.
+    pub async fn retained_content(&self) -> Result<RetainedContent> {
+        let store = self.inner.store.get().ok_or(EventCacheError::StorageDisabled)?;
+        Ok(store.lock().await?.retained_content().await?)
+    }
+
     /// Add an initial set of events to the event cache, reloaded from a cache.
     ///
     /// TODO: temporary for API compat, as the event cache should take care of
@@ -444,6 +636,30 @@ struct EventCacheInner {
     ///
     /// See doc comment of [`EventCache::auto_shrink_linked_chunk_task`].
     auto_shrink_sender: OnceLock<mpsc::Sender<AutoShrinkChannelPayload>>,
+
+    /// The latest displayable event of each room.
+    ///
+    /// Needs to live here, so it may be passed to each [`RoomEventCache`]
+    /// instance.
+    latest_events: LatestEvents,
+
+    /// The pre-processors to run on the events received from the server.
+    ///
+    /// Needs to live here, so it may be passed to each [`RoomEventCache`]
+    /// instance.
+    preprocessors: Arc<preprocessor::EventPreprocessors>,
+
+    /// The hook UTDs are reported to, if any.
+    #[cfg(feature = "e2e-encryption")]
+    utd_hook: StdRwLock<Option<Arc<UtdHookManager>>>,
+
+    /// A sender for the events that each [`RoomEventCache`] observed, to be
+    /// reported to the [`UtdHookManager`].
+    ///
+    /// Needs to live here, so it may be passed to each [`RoomEventCache`]
+    /// instance.
+    #[cfg(feature = "e2e-encryption")]
+    utd_hook_sender: OnceLock<mpsc::UnboundedSender<utd_hook::UtdHookPayload>>,
 }
 
 type AutoShrinkChannelPayload = OwnedRoomId;
@@ -458,6 +674,83 @@ impl EventCacheInner {
         self.store.get().is_some()
     }
 
+    /// Get the IDs of the rooms that may contain events from the given users,
+    /// i.e. the rooms where they are known members, or where events they sent
+    /// are loaded in memory.
+    async fn rooms_with_users(&self, user_ids: &BTreeSet<OwnedUserId>) -> Result<Vec<OwnedRoomId>> {
+        let client = self.client()?;
+
+        // Without storage, only the rooms loaded in memory have events.
+        let room_ids = if self.has_storage() {
+            client.rooms().iter().map(|room| room.room_id().to_owned()).collect()
+        } else {
+            self.by_room.read().await.keys().cloned().collect::<Vec<_>>()
+        };
+
+        let mut rooms_with_users = Vec::new();
+
+        'rooms: for room_id in room_ids {
+            let Some(room) = client.get_room(&room_id) else {
+                continue;
+            };
+
+            for user_id in user_ids {
+                // If the member can't be loaded, consider that the user may be in the room.
+                if !matches!(room.get_member_no_sync(user_id).await, Ok(None)) {
+                    rooms_with_users.push(room_id);
+                    continue 'rooms;
+                }
+            }
+
+            let room_event_cache = self.by_room.read().await.get(&room_id).cloned();
+
+            if let Some(room_event_cache) = room_event_cache {
+                let is_sender = |event: &TimelineEvent| {
+                    event
+                        .raw()
+                        .get_field::<OwnedUserId>("sender")
+                        .ok()
+                        .flatten()
+                        .is_some_and(|sender| user_ids.contains(&sender))
+                };
+
+                if !room_event_cache.rfind_events(is_sender, 1).await.is_empty() {
+                    rooms_with_users.push(room_id);
+                }
+            }
+        }
+
+        Ok(rooms_with_users)
+    }
+
+    /// Removes the events sent by the given users from the rooms where they
+    /// may have sent events.
+    async fn remove_events_from_senders(&self, senders: &BTreeSet<OwnedUserId>) -> Result<()> {
+        for room_id in self.rooms_with_users(senders).await? {
+            let room = self.for_room(&room_id).await?;
+            let diffs = room.inner.state.write().await.remove_events_from_senders(senders).await?;
+
+            if !diffs.is_empty() {
+                let _ = room.inner.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
+                    diffs,
+                    origin: EventsOrigin::Cache,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clears the data of the rooms that may contain events from the given
+    /// users.
+    async fn clear_rooms_with_users(&self, user_ids: &BTreeSet<OwnedUserId>) -> Result<()> {
+        for room_id in self.rooms_with_users(user_ids).await? {
+            self.for_room(&room_id).await?.clear().await?;
+        }
+
+        Ok(())
+    }
+
     /// Clears all the room's data.
     async fn clear_all_rooms(&self) -> Result<()> {
         // Okay, here's where things get complicated.
@@ -508,16 +801,24 @@ impl EventCacheInner {
         )
         .await;
 
-        // Clear the storage for all the rooms, using the storage facility.
-        if let Some(store) = self.store.get() {
+        // Clear the storage for all the rooms, using the storage facility. Retained
+        // rooms are kept as is in the storage.
+        let retained_rooms = if let Some(store) = self.store.get() {
             let store_guard = store.lock().await?;
             store_guard.clear_all_rooms_chunks().await?;
-        }
+            store_guard.retained_content().await?.rooms
+        } else {
+            BTreeSet::new()
+        };
 
-        // At this point, all the in-memory linked chunks are desynchronized from the
-        // storage. Resynchronize them manually by calling reset(), and
-        // propagate updates to observers.
-        try_join_all(room_locks.into_iter().map(|(room, mut state_guard)| async move {
+        // At this point, all the in-memory linked chunks of the rooms that aren't
+        // retained are desynchronized from the storage. Resynchronize them manually
+        // by calling reset(), and propagate updates to observers.
+        let room_locks = room_locks
+            .into_iter()
+            .filter(|(room, _)| !retained_rooms.contains(room.inner.weak_room.room_id()));
+
+        try_join_all(room_locks.map(|(room, mut state_guard)| async move {
             let updates_as_vector_diffs = state_guard.reset().await?;
             let _ = room.inner.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
                 diffs: updates_as_vector_diffs,
@@ -609,6 +910,11 @@ impl EventCacheInner {
                     room_version,
                     self.store.clone(),
                     pagination_status.clone(),
+                    self.latest_events.clone(),
+                    #[cfg(feature = "e2e-encryption")]
+                    self.utd_hook_sender.get().cloned().expect(
+                        "we must have called `EventCache::subscribe()` before calling here.",
+                    ),
                 )
                 .await?;
 
@@ -625,6 +931,7 @@ impl EventCacheInner {
                     pagination_status,
                     room_id.to_owned(),
                     auto_shrink_sender,
+                    self.preprocessors.clone(),
                 );
 
                 by_room_guard.insert(room_id.to_owned(), room_event_cache.clone());
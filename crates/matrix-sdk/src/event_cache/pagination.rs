@@ -18,7 +18,8 @@ use std::{sync::Arc, time::Duration};
 
 use eyeball::{SharedObservable, Subscriber};
 use matrix_sdk_base::{
-    deserialized_responses::TimelineEvent, linked_chunk::ChunkIdentifier, timeout::timeout,
+    deserialized_responses::TimelineEvent, linked_chunk::ChunkIdentifier,
+    timeout::timeout_with_clock,
 };
 use matrix_sdk_common::linked_chunk::ChunkContent;
 use ruma::api::Direction;
@@ -30,7 +31,11 @@ use super::{
     room::{events::Gap, LoadMoreEventsBackwardsOutcome, RoomEventCacheInner},
     BackPaginationOutcome, EventsOrigin, Result, RoomEventCacheState, RoomEventCacheUpdate,
 };
-use crate::{event_cache::EventCacheError, room::MessagesOptions};
+use crate::{
+    clock::{Clock, SystemClock},
+    event_cache::EventCacheError,
+    room::MessagesOptions,
+};
 
 /// Status for the back-pagination on a room event cache.
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -47,6 +52,26 @@ pub enum RoomPaginationStatus {
     Paginating,
 }
 
+/// Counters about the back-paginations which ran on a room event cache.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct RoomPaginationMetrics {
+    /// The number of back-paginations which completed successfully.
+    pub num_paginations: u64,
+
+    /// The number of back-paginations which failed.
+    pub num_failures: u64,
+
+    /// The number of `/messages` requests sent to the server.
+    pub num_network_requests: u64,
+
+    /// The number of events obtained from the server.
+    pub num_events_from_network: u64,
+
+    /// The number of events loaded from the event cache store.
+    pub num_events_from_store: u64,
+}
+
 /// Small RAII guard to reset the pagination status on drop, if not disarmed in
 /// the meanwhile.
 struct ResetStatusOnDrop {
@@ -143,7 +168,18 @@ impl RoomPagination {
             pagination_status: status_observable.clone(),
         };
 
-        match self.paginate_backwards_impl(batch_size).await? {
+        let result = self.paginate_backwards_impl(batch_size).await;
+
+        {
+            let mut metrics = self.inner.pagination_metrics.lock().unwrap();
+            match &result {
+                Ok(Some(_)) => metrics.num_paginations += 1,
+                Ok(None) => {}
+                Err(_) => metrics.num_failures += 1,
+            }
+        }
+
+        match result? {
             Some(outcome) => {
                 // Back-pagination's over and successful, don't reset the status to the previous
                 // value.
@@ -186,9 +222,15 @@ impl RoomPagination {
                     // Release the state guard while waiting, to not deadlock the sync task.
                     drop(state_guard);
 
+                    let clock: Arc<dyn Clock> = match self.inner.weak_room.get() {
+                        Some(room) => room.client().clock().clone(),
+                        None => Arc::new(SystemClock),
+                    };
+
                     // Otherwise, wait for a notification that we received a previous-batch token.
                     trace!("waiting for a pagination token…");
-                    let _ = timeout(
+                    let _ = timeout_with_clock(
+                        clock.as_ref(),
                         self.inner.pagination_batch_token_notifier.notified(),
                         DEFAULT_WAIT_FOR_TOKEN_DURATION,
                     )
@@ -223,6 +265,9 @@ impl RoomPagination {
                     timeline_event_diffs,
                     reached_start,
                 } => {
+                    self.inner.pagination_metrics.lock().unwrap().num_events_from_store +=
+                        events.len() as u64;
+
                     if !timeline_event_diffs.is_empty() {
                         let _ =
                             self.inner.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
@@ -248,6 +293,7 @@ impl RoomPagination {
     /// while to get one, or if it's already done so or if it's seen a
     /// previous-batch token before, it will immediately indicate it's
     /// reached the end of the timeline.
+    #[instrument(name = "matrix_sdk.pagination", skip(self), fields(room_id = %self.inner.weak_room.room_id()))]
     async fn paginate_backwards_with_network(
         &self,
         batch_size: u16,
@@ -265,15 +311,22 @@ impl RoomPagination {
             let mut options = MessagesOptions::new(Direction::Backward).from(prev_token.as_deref());
             options.limit = batch_size.into();
 
+            self.inner.pagination_metrics.lock().unwrap().num_network_requests += 1;
+
             let response = room.messages(options).await.map_err(|err| {
                 EventCacheError::BackpaginationError(
                     crate::event_cache::paginator::PaginatorError::SdkError(Box::new(err)),
                 )
             })?;
 
+            self.inner.pagination_metrics.lock().unwrap().num_events_from_network +=
+                response.chunk.len() as u64;
+
             let new_gap = response.end.map(|prev_token| Gap { prev_token });
 
-            (response.chunk, new_gap)
+            let events = self.inner.preprocessors.run(room.room_id(), response.chunk);
+
+            (events, new_gap)
         };
 
         // Make sure the `RoomEvents` isn't updated while we are saving events from
@@ -464,6 +517,12 @@ impl RoomPagination {
     pub fn status(&self) -> Subscriber<RoomPaginationStatus> {
         self.inner.pagination_status.subscribe()
     }
+
+    /// Returns the counters about the back-paginations which ran on this room
+    /// so far.
+    pub fn metrics(&self) -> RoomPaginationMetrics {
+        *self.inner.pagination_metrics.lock().unwrap()
+    }
 }
 
 /// Pagination token data, indicating in which state is the current pagination.
@@ -0,0 +1,290 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-side pre-processing of the events received from the server.
+//!
+//! The [`EventPreprocessor`]s added with [`EventCache::add_preprocessor`] run
+//! on every event received from sync or from a back-pagination, before it's
+//! added to the event cache. They can modify the event, for example to
+//! normalize its content or to upgrade an experimental event type to its
+//! stable version, or discard it, for example if it's been scored as spam.
+//!
+//! The pre-processors run in ascending order of priority, and in the order
+//! they were added when they have the same priority. Once a pre-processor
+//! discards an event, the next ones don't see it.
+//!
+//! [`EventCache::add_preprocessor`]: super::EventCache::add_preprocessor
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock as StdRwLock,
+    },
+    time::Duration,
+};
+
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use ruma::{time::Instant, RoomId};
+use tracing::trace;
+
+/// What to do with an event, once it's been pre-processed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreprocessingOutcome {
+    /// Keep the event, as possibly modified by the pre-processor, and pass it
+    /// to the next pre-processor.
+    Keep,
+
+    /// Discard the event: it won't be added to the event cache.
+    Discard,
+}
+
+/// A pre-processor, run on every event received from the server before it's
+/// added to the event cache.
+///
+/// It's called while the events of a sync response are being handled, so it
+/// must be quick, and shouldn't block.
+pub trait EventPreprocessor: std::fmt::Debug + Send + Sync {
+    /// Process an event received in the given room.
+    fn process(&self, room_id: &RoomId, event: &mut TimelineEvent) -> PreprocessingOutcome;
+}
+
+/// A handle to a pre-processor added with
+/// [`EventCache::add_preprocessor`](super::EventCache::add_preprocessor), to
+/// remove it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EventPreprocessorHandle(u64);
+
+/// Counters about the events a pre-processor has seen.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventPreprocessorMetrics {
+    /// The name the pre-processor was added with.
+    pub name: String,
+
+    /// The number of events the pre-processor has processed.
+    pub num_processed: u64,
+
+    /// The number of events the pre-processor has discarded.
+    pub num_discarded: u64,
+
+    /// The total time spent in the pre-processor.
+    pub total_duration: Duration,
+}
+
+#[derive(Debug)]
+struct Entry {
+    handle: EventPreprocessorHandle,
+    name: String,
+    priority: i32,
+    processor: Arc<dyn EventPreprocessor>,
+    num_processed: AtomicU64,
+    num_discarded: AtomicU64,
+    total_duration_micros: AtomicU64,
+}
+
+/// The pipeline of pre-processors, shared by the event cache of all the rooms.
+#[derive(Debug, Default)]
+pub(super) struct EventPreprocessors {
+    /// The pre-processors, in the order they must run.
+    entries: StdRwLock<Vec<Arc<Entry>>>,
+    next_handle: AtomicU64,
+}
+
+impl EventPreprocessors {
+    pub(super) fn add(
+        &self,
+        name: String,
+        priority: i32,
+        processor: Arc<dyn EventPreprocessor>,
+    ) -> EventPreprocessorHandle {
+        let handle = EventPreprocessorHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+
+        let mut entries = self.entries.write().unwrap();
+
+        // Insert after the pre-processors with the same priority, to run them in the
+        // order they were added.
+        let index = entries.partition_point(|entry| entry.priority <= priority);
+        entries.insert(
+            index,
+            Arc::new(Entry {
+                handle,
+                name,
+                priority,
+                processor,
+                num_processed: Default::default(),
+                num_discarded: Default::default(),
+                total_duration_micros: Default::default(),
+            }),
+        );
+
+        handle
+    }
+
+    pub(super) fn remove(&self, handle: EventPreprocessorHandle) -> bool {
+        let mut entries = self.entries.write().unwrap();
+        let len_before = entries.len();
+        entries.retain(|entry| entry.handle != handle);
+        entries.len() != len_before
+    }
+
+    pub(super) fn metrics(&self) -> Vec<EventPreprocessorMetrics> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|entry| EventPreprocessorMetrics {
+                name: entry.name.clone(),
+                num_processed: entry.num_processed.load(Ordering::Relaxed),
+                num_discarded: entry.num_discarded.load(Ordering::Relaxed),
+                total_duration: Duration::from_micros(
+                    entry.total_duration_micros.load(Ordering::Relaxed),
+                ),
+            })
+            .collect()
+    }
+
+    /// Run the pre-processors on the events, and return the ones which are
+    /// kept.
+    pub(super) fn run(&self, room_id: &RoomId, events: Vec<TimelineEvent>) -> Vec<TimelineEvent> {
+        // Take a snapshot, so that the lock isn't held while the pre-processors run.
+        let entries = self.entries.read().unwrap().clone();
+
+        if entries.is_empty() {
+            return events;
+        }
+
+        events
+            .into_iter()
+            .filter_map(|mut event| {
+                for entry in &entries {
+                    let start = Instant::now();
+                    let outcome = entry.processor.process(room_id, &mut event);
+                    let elapsed = start.elapsed().as_micros().try_into().unwrap_or(u64::MAX);
+
+                    entry.num_processed.fetch_add(1, Ordering::Relaxed);
+                    entry.total_duration_micros.fetch_add(elapsed, Ordering::Relaxed);
+
+                    if outcome == PreprocessingOutcome::Discard {
+                        entry.num_discarded.fetch_add(1, Ordering::Relaxed);
+                        trace!(
+                            %room_id,
+                            event_id = ?event.event_id(),
+                            preprocessor = entry.name,
+                            "event discarded by a pre-processor"
+                        );
+                        return None;
+                    }
+                }
+
+                Some(event)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use matrix_sdk_base::deserialized_responses::TimelineEvent;
+    use matrix_sdk_test::event_factory::EventFactory;
+    use ruma::{event_id, room_id, user_id, RoomId};
+
+    use super::{EventPreprocessor, EventPreprocessors, PreprocessingOutcome};
+
+    /// Records the order in which the pre-processors run, and discards the
+    /// events whose body is `spam`.
+    #[derive(Debug)]
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl EventPreprocessor for Recorder {
+        fn process(&self, _room_id: &RoomId, event: &mut TimelineEvent) -> PreprocessingOutcome {
+            self.calls.lock().unwrap().push(self.name);
+
+            let body = event.raw().get_field::<serde_json::Value>("content").unwrap().unwrap()
+                ["body"]
+                .clone();
+
+            if body == "spam" {
+                PreprocessingOutcome::Discard
+            } else {
+                PreprocessingOutcome::Keep
+            }
+        }
+    }
+
+    #[test]
+    fn test_preprocessors_run_in_order() {
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let preprocessors = EventPreprocessors::default();
+        for (name, priority) in [("late", 10), ("first", 0), ("second", 0)] {
+            preprocessors.add(
+                name.to_owned(),
+                priority,
+                Arc::new(Recorder { name, calls: calls.clone() }),
+            );
+        }
+
+        let events = preprocessors.run(
+            room_id,
+            vec![
+                f.text_msg("hello").event_id(event_id!("$1")).into_event(),
+                f.text_msg("spam").event_id(event_id!("$2")).into_event(),
+            ],
+        );
+
+        // The spam has been discarded.
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().unwrap(), event_id!("$1"));
+
+        // The pre-processors ran by priority, then in the order they were added, and
+        // none ran after the spam was discarded.
+        assert_eq!(*calls.lock().unwrap(), ["first", "second", "late", "first"],);
+
+        let metrics = preprocessors.metrics();
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(metrics[0].name, "first");
+        assert_eq!(metrics[0].num_processed, 2);
+        assert_eq!(metrics[0].num_discarded, 1);
+        assert_eq!(metrics[2].name, "late");
+        assert_eq!(metrics[2].num_processed, 1);
+        assert_eq!(metrics[2].num_discarded, 0);
+    }
+
+    #[test]
+    fn test_removed_preprocessor_doesnt_run() {
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let f = EventFactory::new().room(room_id).sender(user_id!("@ben:saucisse.bzh"));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let preprocessors = EventPreprocessors::default();
+        let handle = preprocessors.add(
+            "spam".to_owned(),
+            0,
+            Arc::new(Recorder { name: "spam", calls: calls.clone() }),
+        );
+
+        assert!(preprocessors.remove(handle));
+        assert!(!preprocessors.remove(handle));
+
+        let events = preprocessors.run(room_id, vec![f.text_msg("spam").into_event()]);
+        assert_eq!(events.len(), 1);
+        assert!(calls.lock().unwrap().is_empty());
+    }
+}
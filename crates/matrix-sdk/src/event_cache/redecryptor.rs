@@ -0,0 +1,664 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Automatic re-decryption of the events the event cache couldn't decrypt.
+//!
+//! Every event that is unable to decrypt (UTD) and lives in a loaded
+//! [`RoomEventCache`] is considered pending, with a [`RetryReason`] explaining
+//! why it couldn't be decrypted so far.
+//!
+//! The redecryptor task, spawned by [`EventCache::subscribe`], listens to the
+//! room keys received by the crypto store, whatever their origin (to-device
+//! messages, key backup downloads, manual imports), and to the withheld
+//! notices. Each time one such update concerns a pending event, the event is
+//! decrypted again, replaced in the linked chunk, and the change is pushed to
+//! the room's observers as a [`RoomEventCacheUpdate::UpdateTimelineEvents`].
+//! Only the rooms and the events loaded in memory are retried, so a room key
+//! never triggers a scan of the history of a room in the store.
+//!
+//! [`EventCache::subscribe`]: super::EventCache::subscribe
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_core::Stream;
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt as _,
+};
+use matrix_sdk_base::{
+    crypto::store::{RoomKeyInfo, RoomKeyWithheldInfo},
+    deserialized_responses::{
+        TimelineEvent, TimelineEventKind, UnableToDecryptReason, WithheldCode,
+    },
+};
+use matrix_sdk_common::sleep::sleep;
+use ruma::{
+    events::AnySyncTimelineEvent, serde::Raw, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
+};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::{debug, instrument, trace, warn};
+
+use super::{EventCacheInner, EventsOrigin, Result, RoomEventCache, RoomEventCacheUpdate};
+use crate::Room;
+
+/// The initial delay before checking again whether the client has an
+/// `OlmMachine`, when the redecryptor starts before the client is logged in.
+const OLM_MACHINE_INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The maximum delay between two checks of whether the client has an
+/// `OlmMachine`.
+const OLM_MACHINE_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The reason why an event couldn't be decrypted yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryReason {
+    /// We don't have the room key for this event yet; it may still arrive via
+    /// a to-device message, a key request or the key backup.
+    MissingRoomKey,
+
+    /// The sender told us they wouldn't share the room key with us, for the
+    /// given reason.
+    Withheld(WithheldCode),
+
+    /// The event has been sent before this device was created, so only the
+    /// key backup or a key import may provide its room key.
+    Historical,
+
+    /// The event couldn't be decrypted for another reason, that is unlikely
+    /// to be resolved by receiving a new room key.
+    Other(UnableToDecryptReason),
+}
+
+/// An event the event cache couldn't decrypt, and that will be retried
+/// automatically.
+#[derive(Clone, Debug)]
+pub struct PendingDecryption {
+    /// The ID of the event that couldn't be decrypted.
+    pub event_id: OwnedEventId,
+
+    /// The ID of the megolm session used to encrypt this event, if known.
+    pub session_id: Option<String>,
+
+    /// Why the event couldn't be decrypted yet.
+    pub reason: RetryReason,
+}
+
+impl PendingDecryption {
+    /// Create a [`PendingDecryption`] from a timeline event, if it is a UTD.
+    ///
+    /// `device_creation_ts` is the creation time of our own device, used to
+    /// tell apart historical events.
+    pub(super) fn from_event(
+        event: &TimelineEvent,
+        device_creation_ts: MilliSecondsSinceUnixEpoch,
+    ) -> Option<Self> {
+        let TimelineEventKind::UnableToDecrypt { utd_info, .. } = &event.kind else {
+            return None;
+        };
+
+        let reason = match &utd_info.reason {
+            UnableToDecryptReason::MissingMegolmSession { withheld_code: Some(code) } => {
+                RetryReason::Withheld(code.clone())
+            }
+
+            reason if reason.is_missing_room_key() => {
+                let origin_server_ts = event
+                    .raw()
+                    .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                    .ok()
+                    .flatten();
+
+                if origin_server_ts.is_some_and(|ts| ts < device_creation_ts) {
+                    RetryReason::Historical
+                } else {
+                    RetryReason::MissingRoomKey
+                }
+            }
+
+            reason => RetryReason::Other(reason.clone()),
+        };
+
+        Some(Self { event_id: event.event_id()?, session_id: utd_info.session_id.clone(), reason })
+    }
+}
+
+/// A batch of sessions for which something changed, grouped by room.
+///
+/// `None` means we may have missed some updates, and that all the pending
+/// events must be retried.
+type SessionsByRoom = Option<BTreeMap<OwnedRoomId, BTreeSet<String>>>;
+
+/// The stream of the room keys received by the crypto store.
+type RoomKeysStream = BoxStream<'static, Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>>;
+
+/// The stream of the withheld notices received by the crypto store.
+type RoomKeysWithheldStream = BoxStream<'static, Vec<RoomKeyWithheldInfo>>;
+
+/// Listen to the room keys and withheld notices received by the crypto store,
+/// and retry decrypting the matching pending events.
+///
+/// If the client doesn't have an `OlmMachine` yet, e.g. because it isn't
+/// logged in, the task waits for one to be set up, checking again with an
+/// exponential backoff.
+///
+/// The streams of the crypto store end when the `OlmMachine` is dropped, e.g.
+/// because it has been regenerated: the task then listens to the new one, and
+/// retries all the pending events, since some room keys may have been received
+/// in the meantime.
+#[instrument(skip_all)]
+pub(super) async fn redecryption_task(inner: Arc<EventCacheInner>) {
+    let mut is_first_olm_machine = true;
+
+    loop {
+        let Some((room_keys_stream, withheld_stream)) = subscribe_to_room_keys(&inner).await else {
+            debug!("The client has been dropped, exiting the redecryptor");
+            return;
+        };
+
+        if !is_first_olm_machine {
+            if let Err(err) = inner.retry_pending_decryptions(None).await {
+                debug!("Closing the redecryptor: {err}");
+                return;
+            }
+        }
+        is_first_olm_machine = false;
+
+        let mut updates = room_keys_updates(room_keys_stream, withheld_stream);
+
+        while let Some(sessions) = updates.next().await {
+            if let Err(err) = inner.retry_pending_decryptions(sessions).await {
+                debug!("Closing the redecryptor: {err}");
+                return;
+            }
+        }
+
+        debug!("The room keys streams have closed, listening to the new olm machine");
+    }
+}
+
+/// Subscribe to the room keys and withheld notices received by the crypto
+/// store of the current `OlmMachine`, waiting for one to be set up if needs
+/// be.
+///
+/// Returns `None` if the client has been dropped.
+async fn subscribe_to_room_keys(
+    inner: &EventCacheInner,
+) -> Option<(RoomKeysStream, RoomKeysWithheldStream)> {
+    let mut retry_delay = OLM_MACHINE_INITIAL_RETRY_DELAY;
+
+    loop {
+        let client = inner.client().ok()?;
+        let olm_machine = client.olm_machine().await;
+
+        if let Some(olm_machine) = olm_machine.as_ref() {
+            let store = olm_machine.store();
+
+            return Some((
+                store.room_keys_received_stream().boxed(),
+                store.room_keys_withheld_received_stream().boxed(),
+            ));
+        }
+
+        // Don't keep the client alive while waiting.
+        drop(olm_machine);
+        drop(client);
+
+        debug!(?retry_delay, "No olm machine for the redecryptor, retrying later");
+        sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(OLM_MACHINE_MAX_RETRY_DELAY);
+    }
+}
+
+/// Merge the room keys and withheld notices streams, as batches of sessions
+/// grouped by room.
+fn room_keys_updates(
+    room_keys_stream: RoomKeysStream,
+    withheld_stream: RoomKeysWithheldStream,
+) -> impl Stream<Item = SessionsByRoom> {
+    let room_keys_stream = room_keys_stream.map(|room_keys| match room_keys {
+        Ok(room_keys) => {
+            Some(group_by_room(room_keys.into_iter().map(|info| (info.room_id, info.session_id))))
+        }
+
+        Err(err) => {
+            warn!("Lagged behind the room keys stream, retrying all pending events: {err}");
+            None
+        }
+    });
+
+    let withheld_stream = withheld_stream.map(|withheld| {
+        Some(group_by_room(withheld.into_iter().map(|info| (info.room_id, info.session_id))))
+    });
+
+    stream::select(room_keys_stream, withheld_stream)
+}
+
+fn group_by_room(
+    sessions: impl Iterator<Item = (OwnedRoomId, String)>,
+) -> BTreeMap<OwnedRoomId, BTreeSet<String>> {
+    let mut by_room = BTreeMap::<_, BTreeSet<_>>::new();
+
+    for (room_id, session_id) in sessions {
+        by_room.entry(room_id).or_default().insert(session_id);
+    }
+
+    by_room
+}
+
+impl EventCacheInner {
+    /// Retry decrypting the pending events, for the given sessions.
+    ///
+    /// Only fails if the client has been dropped.
+    async fn retry_pending_decryptions(&self, sessions: SessionsByRoom) -> Result<()> {
+        let client = self.client()?;
+
+        // Only consider the rooms that are loaded in memory, rather than loading the
+        // other ones from the store.
+        let rooms: Vec<(RoomEventCache, Option<BTreeSet<String>>)> = {
+            let by_room = self.by_room.read().await;
+
+            match sessions {
+                Some(sessions) => sessions
+                    .into_iter()
+                    .filter_map(|(room_id, session_ids)| {
+                        Some((by_room.get(&room_id)?.clone(), Some(session_ids)))
+                    })
+                    .collect(),
+
+                // We don't know which rooms are concerned.
+                None => by_room.values().map(|room| (room.clone(), None)).collect(),
+            }
+        };
+
+        for (room_event_cache, session_ids) in rooms {
+            let Some(room) = client.get_room(room_event_cache.inner.weak_room.room_id()) else {
+                continue;
+            };
+
+            if let Err(err) = room_event_cache.inner.retry_decryption(&room, session_ids).await {
+                warn!(room_id = %room.room_id(), "Couldn't retry decrypting events: {err}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Try to decrypt a UTD again.
+///
+/// Returns the new event if something changed: either it's been decrypted, or
+/// the reason why it can't be has been updated (e.g. the key has been
+/// withheld).
+async fn redecrypt(
+    room: &Room,
+    event_id: &OwnedEventId,
+    raw: &Raw<AnySyncTimelineEvent>,
+    previous_reason: &UnableToDecryptReason,
+) -> Option<TimelineEvent> {
+    let event = match room.decrypt_event(raw.cast_ref()).await {
+        Ok(event) => event,
+        Err(err) => {
+            warn!(%event_id, "Couldn't retry decrypting an event: {err}");
+            return None;
+        }
+    };
+
+    match &event.kind {
+        TimelineEventKind::UnableToDecrypt { utd_info, .. } => {
+            (utd_info.reason != *previous_reason).then_some(event)
+        }
+        _ => Some(event),
+    }
+}
+
+impl super::room::RoomEventCacheInner {
+    /// Retry decrypting the UTDs of this room that are loaded in memory,
+    /// optionally restricted to the given sessions, and propagate the ones
+    /// that changed.
+    ///
+    /// The events are replaced in the linked chunk, and the observers of the
+    /// room are notified.
+    #[instrument(name = "matrix_sdk.decryption_batch", skip_all, fields(room_id = %room.room_id()))]
+    async fn retry_decryption(
+        &self,
+        room: &Room,
+        session_ids: Option<BTreeSet<String>>,
+    ) -> Result<()> {
+        // Collect the candidates first, and release the lock while decrypting.
+        let candidates = self
+            .state
+            .read()
+            .await
+            .events()
+            .events()
+            .filter_map(|(_position, event)| {
+                let TimelineEventKind::UnableToDecrypt { utd_info, .. } = &event.kind else {
+                    return None;
+                };
+
+                if let Some(session_ids) = &session_ids {
+                    if !utd_info.session_id.as_ref().is_some_and(|id| session_ids.contains(id)) {
+                        return None;
+                    }
+                }
+
+                Some((event.event_id()?, event.raw().clone(), utd_info.reason.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        trace!(num_candidates = candidates.len(), "retrying decryption");
+
+        let mut resolved = Vec::new();
+
+        for (event_id, raw, previous_reason) in candidates {
+            if let Some(event) = redecrypt(room, &event_id, &raw, &previous_reason).await {
+                resolved.push((event_id, event));
+            }
+        }
+
+        if resolved.is_empty() {
+            return Ok(());
+        }
+
+        self.replace_in_memory_events(resolved).await
+    }
+
+    /// Replace the given in-memory events, and notify the observers.
+    async fn replace_in_memory_events(
+        &self,
+        resolved: Vec<(OwnedEventId, TimelineEvent)>,
+    ) -> Result<()> {
+        debug!(num_resolved = resolved.len(), "replacing re-decrypted events");
+
+        let mut state = self.state.write().await;
+
+        let diffs = state
+            .with_events_mut(|room_events| {
+                let mut replaced = Vec::with_capacity(resolved.len());
+
+                for (event_id, event) in resolved {
+                    // The event may have moved, or been removed, while we weren't holding
+                    // the lock; look for it again.
+                    let Some(position) = room_events.revents().find_map(|(position, event)| {
+                        (event.event_id().as_deref() == Some(&*event_id)).then_some(position)
+                    }) else {
+                        continue;
+                    };
+
+                    room_events
+                        .replace_event_at(position, event.clone())
+                        .expect("should have been a valid position of an item");
+
+                    replaced.push(event);
+                }
+
+                // A decrypted event may be a redaction, so post-process them all.
+                replaced
+            })
+            .await?;
+
+        if !diffs.is_empty() {
+            let _ = self.sender.send(RoomEventCacheUpdate::UpdateTimelineEvents {
+                diffs,
+                origin: EventsOrigin::Cache,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(super) mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use assert_matches::assert_matches;
+    use assert_matches2::assert_let;
+    use eyeball_im::VectorDiff;
+    use matrix_sdk_base::{
+        crypto::decrypt_room_key_export,
+        deserialized_responses::TimelineEventKind,
+        event_cache::store::EventCacheStore as _,
+        linked_chunk::{ChunkIdentifier, Position, Update},
+        sleep::sleep,
+    };
+    use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, BOB};
+    use ruma::{
+        event_id,
+        events::room::encrypted::{
+            EncryptedEventScheme, MegolmV1AesSha2ContentInit, RoomEncryptedEventContent,
+        },
+        room_id,
+    };
+
+    use super::RetryReason;
+    use crate::{
+        assert_let_timeout, event_cache::RoomEventCacheUpdate, test_utils::mocks::MatrixMockServer,
+        Client,
+    };
+
+    const SESSION_ID: &str = "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU";
+    const SESSION_KEY: &[u8] = b"\
+        -----BEGIN MEGOLM SESSION DATA-----\n\
+        ASKcWoiAVUM97482UAi83Avce62hSLce7i5JhsqoF6xeAAAACqt2Cg3nyJPRWTTMXxXH7TXnkfdlmBXbQtq5\
+        bpHo3LRijcq2Gc6TXilESCmJN14pIsfKRJrWjZ0squ/XsoTFytuVLWwkNaW3QF6obeg2IoVtJXLMPdw3b2vO\
+        vgwGY3OMP0XafH13j1vcb6YLzvgLkZQLnYvd47hv3yK/9GmKS9tokuaQ7dCVYckYcIOS09EDTs70YdxUd5WG\
+        rQynATCLFP1p/NAGv70r9MK7Cy/mNpjD0r4qC7UEDIoi1kOWzHgnLo19wtvwsb8Fg8ATxcs3Wmtj8hIUYpDx\
+        ia4sM10zbytUuaPUAfCDf42IyxdmOnGe1CueXhgI71y+RW0s0argNqUt7jB70JT0o9CyX6UBGRaqLk2MPY9T\
+        hUu5J8X3UgIa6rcbWigzohzWm9rdbEHFrSWqjpfQYMaAKQQgETrjSy4XTrp2RhC2oNqG/hylI4ab+F4X6fpH\
+        DYP1NqNMP5g36xNu7LhDnrUB5qsPjYOmWORxGLfudpF3oLYCSlr3DgHqEIB6HjQblLZ3KQuPBse3zxyROTnS\
+        AhdPH4a/z1wioFtKNVph3hecsiKEdqnz4Y2coSIdhz58mJ9JWNQoFAENE5CSsoEZAGvafYZVpW4C75YY2zq1\
+        wIeiFi1dT43/jLAUGkslsi1VvnyfUu8qO404RxYO3XHoGLMFoFLOO+lZ+VGci2Vz10AhxJhEBHxRKxw4k2uB\
+        HztoSJUr/2Y\n\
+        -----END MEGOLM SESSION DATA-----";
+
+    /// The content of an event encrypted with the session from
+    /// [`SESSION_KEY`], in the room `!DovneieKSTkdHKpIXy:morpheus.localhost`.
+    pub(crate) fn encrypted_event_content() -> RoomEncryptedEventContent {
+        RoomEncryptedEventContent::new(
+            EncryptedEventScheme::MegolmV1AesSha2(
+                MegolmV1AesSha2ContentInit {
+                    ciphertext: "\
+                        AwgAEtABPRMavuZMDJrPo6pGQP4qVmpcuapuXtzKXJyi3YpEsjSWdzuRKIgJzD4P\
+                        cSqJM1A8kzxecTQNJsC5q22+KSFEPxPnI4ltpm7GFowSoPSW9+bFdnlfUzEP1jPq\
+                        YevHAsMJp2fRKkzQQbPordrUk1gNqEpGl4BYFeRqKl9GPdKFwy45huvQCLNNueql\
+                        CFZVoYMuhxrfyMiJJAVNTofkr2um2mKjDTlajHtr39pTG8k0eOjSXkLOSdZvNOMz\
+                        hGhSaFNeERSA2G2YbeknOvU7MvjiO0AKuxaAe1CaVhAI14FCgzrJ8g0y5nly+n7x\
+                        QzL2G2Dn8EoXM5Iqj8W99iokQoVsSrUEnaQ1WnSIfewvDDt4LCaD/w7PGETMCQ"
+                        .to_owned(),
+                    sender_key: "DeHIg4gwhClxzFYcmNntPNF9YtsdZbmMy8+3kzCMXHA".to_owned(),
+                    device_id: "NLAZCWIOCO".into(),
+                    session_id: SESSION_ID.into(),
+                }
+                .into(),
+            ),
+            None,
+        )
+    }
+
+    /// Import the room key from [`SESSION_KEY`] into the client's crypto
+    /// store.
+    pub(crate) async fn import_room_key(client: &Client) {
+        let room_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
+        client
+            .olm_machine()
+            .await
+            .as_ref()
+            .unwrap()
+            .store()
+            .import_exported_room_keys(room_keys, |_, _| {})
+            .await
+            .unwrap();
+    }
+
+    #[async_test]
+    async fn test_utd_is_redecrypted_when_room_key_arrives() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        client.event_cache().subscribe().unwrap();
+
+        // The room key has been exported from this room.
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+        let (events, mut subscriber) = room_event_cache.subscribe().await;
+        assert!(events.is_empty());
+
+        // When I receive an encrypted event for which I don't have the key yet,
+        let f = EventFactory::new().room(room_id).sender(&BOB);
+        server
+            .sync_room(
+                &client,
+                JoinedRoomBuilder::new(room_id)
+                    .add_timeline_event(f.event(encrypted_event_content())),
+            )
+            .await;
+
+        // Then it's stored as a UTD, and is pending decryption.
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = subscriber.recv()
+        );
+        assert_eq!(diffs.len(), 1);
+        assert_let!(VectorDiff::Append { values: events } = &diffs[0]);
+        assert_eq!(events.len(), 1);
+        assert_matches!(events[0].kind, TimelineEventKind::UnableToDecrypt { .. });
+
+        let pending = room_event_cache.pending_decryptions().await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].session_id.as_deref(), Some(SESSION_ID));
+        // The event factory uses timestamps from way before our device was created.
+        assert_eq!(pending[0].reason, RetryReason::Historical);
+
+        // When the room key is received,
+        import_room_key(&client).await;
+
+        // Then the event is decrypted, and replaced in the event cache.
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = subscriber.recv()
+        );
+        assert_eq!(diffs.len(), 1);
+        assert_let!(VectorDiff::Set { index: 0, value: event } = &diffs[0]);
+        assert_let!(TimelineEventKind::Decrypted(_) = &event.kind);
+
+        assert!(room_event_cache.pending_decryptions().await.is_empty());
+    }
+
+    #[async_test]
+    async fn test_utd_is_redecrypted_after_olm_machine_regeneration() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        client.event_cache().subscribe().unwrap();
+
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+        let (_, mut subscriber) = room_event_cache.subscribe().await;
+
+        let f = EventFactory::new().room(room_id).sender(&BOB);
+        server
+            .sync_room(
+                &client,
+                JoinedRoomBuilder::new(room_id)
+                    .add_timeline_event(f.event(encrypted_event_content())),
+            )
+            .await;
+
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = subscriber.recv()
+        );
+        assert_eq!(room_event_cache.pending_decryptions().await.len(), 1);
+
+        // When the `OlmMachine` is regenerated, which ends the streams of the previous
+        // one,
+        client.base_client().regenerate_olm(None).await.unwrap();
+
+        // And the room key is received by the new one,
+        import_room_key(&client).await;
+
+        // Then the event is still decrypted.
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = subscriber.recv()
+        );
+        assert_let!(VectorDiff::Set { index: 0, value: event } = &diffs[0]);
+        assert_let!(TimelineEventKind::Decrypted(_) = &event.kind);
+
+        assert!(room_event_cache.pending_decryptions().await.is_empty());
+    }
+
+    #[async_test]
+    async fn test_room_key_does_not_load_rooms_from_the_store() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let f = EventFactory::new().room(room_id).sender(&BOB);
+        let utd_event_id = event_id!("$utd");
+
+        // The UTD is in the store only.
+        client
+            .event_cache_store()
+            .lock()
+            .await
+            .unwrap()
+            .handle_linked_chunk_updates(
+                room_id,
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(0),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(0), 0),
+                        items: vec![f
+                            .event(encrypted_event_content())
+                            .event_id(utd_event_id)
+                            .into_utd_sync_timeline_event()],
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_storage().unwrap();
+
+        // When the room key is received,
+        import_room_key(&client).await;
+        sleep(Duration::from_millis(100)).await;
+
+        // Then the room isn't loaded, and the event in the store is left untouched.
+        assert!(!event_cache.inner.by_room.read().await.contains_key(room_id));
+
+        let event = client
+            .event_cache_store()
+            .lock()
+            .await
+            .unwrap()
+            .find_event(room_id, utd_event_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_matches!(event.kind, TimelineEventKind::UnableToDecrypt { .. });
+    }
+}
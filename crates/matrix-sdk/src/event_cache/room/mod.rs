@@ -20,7 +20,7 @@ use std::{
     ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
 };
 
@@ -44,12 +44,14 @@ use tokio::sync::{
 use tracing::{instrument, trace, warn};
 
 use super::{
-    deduplicator::DeduplicationOutcome, AutoShrinkChannelPayload, EventsOrigin, Result,
-    RoomEventCacheUpdate, RoomPagination, RoomPaginationStatus,
+    deduplicator::DeduplicationOutcome, preprocessor::EventPreprocessors, AutoShrinkChannelPayload,
+    EventsOrigin, Result, RoomEventCacheUpdate, RoomPagination, RoomPaginationMetrics,
+    RoomPaginationStatus,
 };
 use crate::{client::WeakClient, room::WeakRoom};
 
 pub(super) mod events;
+mod repair;
 
 /// A subset of an event cache, for a room.
 ///
@@ -146,6 +148,7 @@ impl RoomEventCache {
         pagination_status: SharedObservable<RoomPaginationStatus>,
         room_id: OwnedRoomId,
         auto_shrink_sender: mpsc::Sender<AutoShrinkChannelPayload>,
+        preprocessors: Arc<EventPreprocessors>,
     ) -> Self {
         Self {
             inner: Arc::new(RoomEventCacheInner::new(
@@ -154,6 +157,7 @@ impl RoomEventCache {
                 pagination_status,
                 room_id,
                 auto_shrink_sender,
+                preprocessors,
             )),
         }
     }
@@ -197,6 +201,57 @@ impl RoomEventCache {
             .map(|(_loc, event)| event)
     }
 
+    /// Find the most recent events matching the given predicate, among the
+    /// events loaded in memory.
+    ///
+    /// The most recent event comes first. At most `limit` events are returned.
+    /// Back-paginate first to search older events.
+    pub async fn rfind_events(
+        &self,
+        predicate: impl Fn(&TimelineEvent) -> bool,
+        limit: usize,
+    ) -> Vec<TimelineEvent> {
+        self.inner
+            .state
+            .read()
+            .await
+            .events()
+            .revents()
+            .filter(|(_position, event)| predicate(event))
+            .take(limit)
+            .map(|(_position, event)| event.clone())
+            .collect()
+    }
+
+    /// Compare the positions of two events in this room, among the events
+    /// loaded in memory.
+    ///
+    /// Returns `None` if one of the events isn't loaded.
+    pub async fn compare_events_positions(
+        &self,
+        first: &EventId,
+        second: &EventId,
+    ) -> Option<std::cmp::Ordering> {
+        let state = self.inner.state.read().await;
+        let mut first_index = None;
+        let mut second_index = None;
+
+        for (index, (_position, event)) in state.events().events().enumerate() {
+            let event_id = event.event_id();
+            if event_id.as_deref() == Some(first) {
+                first_index = Some(index);
+            }
+            if event_id.as_deref() == Some(second) {
+                second_index = Some(index);
+            }
+            if first_index.is_some() && second_index.is_some() {
+                break;
+            }
+        }
+
+        Some(first_index?.cmp(&second_index?))
+    }
+
     /// Try to find an event by id in this room, along with its related events.
     ///
     /// You can filter which types of related events to retrieve using
@@ -234,6 +289,35 @@ impl RoomEventCache {
         Ok(())
     }
 
+    /// Mark this room as retained in the event cache store, or not.
+    ///
+    /// The linked chunk and all the events of a retained room are kept when
+    /// all the rooms are cleared with [`EventCache::clear_all_rooms`], so they
+    /// remain available offline.
+    ///
+    /// [`EventCache::clear_all_rooms`]: super::EventCache::clear_all_rooms
+    pub async fn set_retained(&self, retained: bool) -> Result<()> {
+        self.inner.state.read().await.set_retained(retained).await
+    }
+
+    /// Mark a single event of this room as retained in the event cache store,
+    /// or not.
+    ///
+    /// A retained event is kept when all the rooms are cleared with
+    /// [`EventCache::clear_all_rooms`], so it can still be found with
+    /// [`Self::event`] afterwards, even offline.
+    ///
+    /// The event must be known to the event cache to be retained, otherwise
+    /// [`EventCacheError::EventNotFound`] is returned. Use
+    /// [`Room::load_or_fetch_event`] to make sure of that beforehand.
+    ///
+    /// [`EventCache::clear_all_rooms`]: super::EventCache::clear_all_rooms
+    /// [`EventCacheError::EventNotFound`]: super::EventCacheError::EventNotFound
+    /// [`Room::load_or_fetch_event`]: crate::Room::load_or_fetch_event
+    pub async fn set_event_retained(&self, event_id: &EventId, retained: bool) -> Result<()> {
+        self.inner.state.read().await.set_event_retained(event_id, retained).await
+    }
+
     /// Save some events in the event cache, for further retrieval with
     /// [`Self::event`].
     pub(crate) async fn save_events(&self, events: impl IntoIterator<Item = TimelineEvent>) {
@@ -242,6 +326,29 @@ impl RoomEventCache {
         }
     }
 
+    /// Return the loaded events of this room that couldn't be decrypted yet,
+    /// and that will be retried automatically when a matching room key is
+    /// received.
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn pending_decryptions(&self) -> Vec<super::PendingDecryption> {
+        let Some(room) = self.inner.weak_room.get() else {
+            return Vec::new();
+        };
+
+        let device_creation_ts = room.client().encryption().device_creation_timestamp().await;
+
+        self.inner
+            .state
+            .read()
+            .await
+            .events()
+            .events()
+            .filter_map(|(_position, event)| {
+                super::PendingDecryption::from_event(event, device_creation_ts)
+            })
+            .collect()
+    }
+
     /// Return a nice debug string (a vector of lines) for the linked chunk of
     /// events for this room.
     pub async fn debug_string(&self) -> Vec<String> {
@@ -267,11 +374,17 @@ pub(super) struct RoomEventCacheInner {
 
     pub pagination_status: SharedObservable<RoomPaginationStatus>,
 
+    /// Counters about the back-paginations which ran on this room.
+    pub pagination_metrics: Mutex<RoomPaginationMetrics>,
+
     /// Sender to the auto-shrink channel.
     ///
     /// See doc comment around [`EventCache::auto_shrink_linked_chunk_task`] for
     /// more details.
     auto_shrink_sender: mpsc::Sender<AutoShrinkChannelPayload>,
+
+    /// The pre-processors to run on the events received from the server.
+    pub preprocessors: Arc<EventPreprocessors>,
 }
 
 impl RoomEventCacheInner {
@@ -283,6 +396,7 @@ impl RoomEventCacheInner {
         pagination_status: SharedObservable<RoomPaginationStatus>,
         room_id: OwnedRoomId,
         auto_shrink_sender: mpsc::Sender<AutoShrinkChannelPayload>,
+        preprocessors: Arc<EventPreprocessors>,
     ) -> Self {
         let sender = Sender::new(32);
         let weak_room = WeakRoom::new(client, room_id);
@@ -294,6 +408,8 @@ impl RoomEventCacheInner {
             pagination_batch_token_notifier: Default::default(),
             auto_shrink_sender,
             pagination_status,
+            pagination_metrics: Default::default(),
+            preprocessors,
         }
     }
 
@@ -358,10 +474,12 @@ impl RoomEventCacheInner {
     async fn handle_timeline(
         &self,
         has_storage: bool,
-        timeline: Timeline,
+        mut timeline: Timeline,
         ephemeral_events: Vec<Raw<AnySyncEphemeralRoomEvent>>,
         ambiguity_changes: BTreeMap<OwnedEventId, AmbiguityChange>,
     ) -> Result<()> {
+        timeline.events = self.preprocessors.run(&self.room_id, timeline.events);
+
         if !has_storage && timeline.limited {
             // Ideally we'd try to reconcile existing events against those received in the
             // timeline, but we're not there yet. In the meanwhile, clear the
@@ -614,7 +732,7 @@ pub(super) enum LoadMoreEventsBackwardsOutcome {
 // Use a private module to hide `events` to this parent module.
 mod private {
     use std::{
-        collections::HashSet,
+        collections::{BTreeSet, HashSet},
         sync::{atomic::AtomicUsize, Arc},
     };
 
@@ -623,8 +741,13 @@ mod private {
     use matrix_sdk_base::{
         apply_redaction,
         deserialized_responses::{TimelineEvent, TimelineEventKind},
-        event_cache::{store::EventCacheStoreLock, Event, Gap},
-        linked_chunk::{lazy_loader, ChunkContent, ChunkIdentifierGenerator, Position, Update},
+        event_cache::{
+            store::{EventCacheStoreLock, EventCacheStoreLockGuard, DEFAULT_CHUNK_CAPACITY},
+            Event, Gap,
+        },
+        linked_chunk::{
+            lazy_loader, ChunkContent, ChunkIdentifierGenerator, LinkedChunk, Position, Update,
+        },
     };
     use matrix_sdk_common::executor::spawn;
     use once_cell::sync::OnceCell;
@@ -634,8 +757,10 @@ mod private {
             MessageLikeEventType,
         },
         serde::Raw,
-        EventId, OwnedEventId, OwnedRoomId, RoomVersionId,
+        EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
     };
+    #[cfg(feature = "e2e-encryption")]
+    use tokio::sync::mpsc;
     use tracing::{debug, error, instrument, trace, warn};
 
     use super::{
@@ -644,9 +769,12 @@ mod private {
             EventCacheError,
         },
         events::RoomEvents,
+        repair::{has_duplicated_events, repair_stored_linked_chunk},
         sort_positions_descending, EventLocation, LoadMoreEventsBackwardsOutcome,
     };
-    use crate::event_cache::RoomPaginationStatus;
+    #[cfg(feature = "e2e-encryption")]
+    use crate::event_cache::utd_hook::{observe_events, UtdHookPayload};
+    use crate::event_cache::{latest_events::LatestEvents, RoomPaginationStatus};
 
     /// State for a single room's event cache.
     ///
@@ -682,6 +810,15 @@ mod private {
         /// An atomic count of the current number of listeners of the
         /// [`super::RoomEventCache`].
         pub(super) listener_count: Arc<AtomicUsize>,
+
+        /// The latest displayable event of each room, updated every time the
+        /// events of this room change.
+        latest_events: LatestEvents,
+
+        /// Sender to the task reporting UTDs to the
+        /// [`crate::unable_to_decrypt_hook::UtdHookManager`].
+        #[cfg(feature = "e2e-encryption")]
+        utd_hook_sender: mpsc::UnboundedSender<UtdHookPayload>,
     }
 
     impl RoomEventCacheState {
@@ -699,22 +836,38 @@ mod private {
             room_version: RoomVersionId,
             store: Arc<OnceCell<EventCacheStoreLock>>,
             pagination_status: SharedObservable<RoomPaginationStatus>,
+            latest_events: LatestEvents,
+            #[cfg(feature = "e2e-encryption")] utd_hook_sender: mpsc::UnboundedSender<
+                UtdHookPayload,
+            >,
         ) -> Result<Self, EventCacheError> {
             let (events, deduplicator) = if let Some(store) = store.get() {
                 let store_lock = store.lock().await?;
 
-                let linked_chunk = match store_lock
-                    .load_last_chunk(&room_id)
+                let mut loaded = Self::load_linked_chunk(&store_lock, &room_id).await;
+
+                if let Err(err) = &loaded {
+                    error!("error when reloading a linked chunk from memory: {err}");
+
+                    // Try to rebuild a consistent linked chunk from the stored chunks, and
+                    // reload it.
+                    loaded = match repair_stored_linked_chunk(
+                        &store_lock,
+                        &room_id,
+                        "the last chunk couldn't be loaded",
+                    )
                     .await
-                    .map_err(EventCacheError::from)
-                    .and_then(|(last_chunk, chunk_identifier_generator)| {
-                        lazy_loader::from_last_chunk(last_chunk, chunk_identifier_generator)
-                            .map_err(EventCacheError::from)
-                    }) {
+                    {
+                        Ok(_) => Self::load_linked_chunk(&store_lock, &room_id).await,
+                        Err(err) => Err(err),
+                    };
+                }
+
+                let linked_chunk = match loaded {
                     Ok(linked_chunk) => linked_chunk,
 
                     Err(err) => {
-                        error!("error when reloading a linked chunk from memory: {err}");
+                        error!("error when repairing a linked chunk: {err}");
 
                         // Clear storage for this room.
                         store_lock
@@ -734,6 +887,8 @@ mod private {
                 (RoomEvents::default(), Deduplicator::new_memory_based())
             };
 
+            latest_events.update(&room_id, &events);
+
             Ok(Self {
                 room: room_id,
                 room_version,
@@ -743,9 +898,79 @@ mod private {
                 waited_for_initial_prev_token: false,
                 listener_count: Default::default(),
                 pagination_status,
+                latest_events,
+                #[cfg(feature = "e2e-encryption")]
+                utd_hook_sender,
             })
         }
 
+        /// Load the last chunk of the linked chunk of a room from the store.
+        ///
+        /// A last chunk containing the same event several times is reported as
+        /// an error, so that the linked chunk gets repaired.
+        async fn load_linked_chunk(
+            store: &EventCacheStoreLockGuard<'_>,
+            room_id: &RoomId,
+        ) -> Result<Option<LinkedChunk<DEFAULT_CHUNK_CAPACITY, Event, Gap>>, EventCacheError>
+        {
+            let (last_chunk, chunk_identifier_generator) = store.load_last_chunk(room_id).await?;
+
+            if last_chunk.as_ref().is_some_and(has_duplicated_events) {
+                return Err(EventCacheError::InconsistentLinkedChunk);
+            }
+
+            Ok(lazy_loader::from_last_chunk(last_chunk, chunk_identifier_generator)?)
+        }
+
+        /// Repair the stored linked chunk of this room, see
+        /// [`repair_stored_linked_chunk`], and reload its last chunk.
+        ///
+        /// Return a single [`VectorDiff::Reset`] with the reloaded events, for
+        /// the external listeners.
+        async fn repair_and_reload(
+            &mut self,
+            store: &EventCacheStoreLockGuard<'_>,
+            reason: &str,
+        ) -> Result<Vec<VectorDiff<TimelineEvent>>, EventCacheError> {
+            if let Err(err) = repair_stored_linked_chunk(store, &self.room, reason).await {
+                error!("error when repairing a linked chunk: {err}");
+
+                // Clear storage for this room.
+                store.handle_linked_chunk_updates(&self.room, vec![Update::Clear]).await?;
+            }
+
+            let (last_chunk, chunk_identifier_generator) =
+                store.load_last_chunk(&self.room).await?;
+            if let Err(err) = self.events.replace_with(last_chunk, chunk_identifier_generator) {
+                error!("error when replacing the linked chunk: {err}");
+                return self.reset().await;
+            }
+
+            // The repaired linked chunk is already in the store: drain the store updates.
+            let _ = self.events.store_updates().take();
+
+            // Collapse the updates into a single reset of the timeline.
+            let _ = self.updates_as_vector_diffs();
+
+            self.pagination_status.set(RoomPaginationStatus::Idle { hit_timeline_start: false });
+
+            Ok(vec![VectorDiff::Reset {
+                values: self.events.events().map(|(_position, event)| event.clone()).collect(),
+            }])
+        }
+
+        /// Take the updates of the events as [`VectorDiff`]s, and update the
+        /// latest event of the room accordingly.
+        fn updates_as_vector_diffs(&mut self) -> Vec<VectorDiff<TimelineEvent>> {
+            let diffs = self.events.updates_as_vector_diffs();
+
+            if !diffs.is_empty() {
+                self.latest_events.update(&self.room, &self.events);
+            }
+
+            diffs
+        }
+
         /// Deduplicate `events` considering all events in `Self::events`.
         ///
         /// The returned tuple contains:
@@ -862,14 +1087,31 @@ mod private {
                     Err(err) => {
                         error!("error when loading the previous chunk of a linked chunk: {err}");
 
-                        // Clear storage for this room.
-                        store.handle_linked_chunk_updates(&self.room, vec![Update::Clear]).await?;
-
-                        // Return the error.
-                        return Err(err.into());
+                        return self
+                            .repair_and_reload(&store, "the previous chunk couldn't be loaded")
+                            .await
+                            .map(Self::repaired_outcome);
                     }
                 };
 
+            // The events of the previous chunk must not be known already; otherwise, the
+            // stored linked chunk is inconsistent.
+            if let ChunkContent::Items(events) = &new_first_chunk.content {
+                let event_ids =
+                    events.iter().filter_map(|event| event.event_id()).collect::<HashSet<_>>();
+
+                if has_duplicated_events(&new_first_chunk)
+                    || self.events.events().any(|(_position, event)| {
+                        event.event_id().is_some_and(|event_id| event_ids.contains(&event_id))
+                    })
+                {
+                    return self
+                        .repair_and_reload(&store, "the previous chunk contains known events")
+                        .await
+                        .map(Self::repaired_outcome);
+                }
+            }
+
             let chunk_content = new_first_chunk.content.clone();
 
             // We've reached the start on disk, if and only if, there was no chunk prior to
@@ -882,11 +1124,10 @@ mod private {
             if let Err(err) = self.events.insert_new_chunk_as_first(new_first_chunk) {
                 error!("error when inserting the previous chunk into its linked chunk: {err}");
 
-                // Clear storage for this room.
-                store.handle_linked_chunk_updates(&self.room, vec![Update::Clear]).await?;
-
-                // Return the error.
-                return Err(err.into());
+                return self
+                    .repair_and_reload(&store, "the previous chunk couldn't be inserted")
+                    .await
+                    .map(Self::repaired_outcome);
             };
 
             // ⚠️ Let's not propagate the updates to the store! We already have these data
@@ -894,7 +1135,7 @@ mod private {
             let _ = self.events.store_updates().take();
 
             // However, we want to get updates as `VectorDiff`s.
-            let timeline_event_diffs = self.events.updates_as_vector_diffs();
+            let timeline_event_diffs = self.updates_as_vector_diffs();
 
             Ok(match chunk_content {
                 ChunkContent::Gap(gap) => {
@@ -913,6 +1154,18 @@ mod private {
             })
         }
 
+        /// The [`LoadMoreEventsBackwardsOutcome`] after the linked chunk has
+        /// been repaired and reloaded, with the given timeline reset.
+        fn repaired_outcome(
+            timeline_event_diffs: Vec<VectorDiff<TimelineEvent>>,
+        ) -> LoadMoreEventsBackwardsOutcome {
+            LoadMoreEventsBackwardsOutcome::Events {
+                events: Vec::new(),
+                timeline_event_diffs,
+                reached_start: false,
+            }
+        }
+
         /// If storage is enabled, unload all the chunks, then reloads only the
         /// last one.
         ///
@@ -934,23 +1187,40 @@ mod private {
             let store_lock = store.lock().await?;
 
             // Attempt to load the last chunk.
-            let (last_chunk, chunk_identifier_generator) = match store_lock
-                .load_last_chunk(&self.room)
-                .await
-            {
-                Ok(pair) => pair,
-
-                Err(err) => {
-                    // If loading the last chunk failed, clear the entire linked chunk.
-                    error!("error when reloading a linked chunk from memory: {err}");
+            let (last_chunk, chunk_identifier_generator) =
+                match store_lock.load_last_chunk(&self.room).await {
+                    Ok(pair) => pair,
 
-                    // Clear storage for this room.
-                    store_lock.handle_linked_chunk_updates(&self.room, vec![Update::Clear]).await?;
+                    Err(err) => {
+                        // If loading the last chunk failed, repair the linked chunk, and try again.
+                        error!("error when reloading a linked chunk from memory: {err}");
 
-                    // Restart with an empty linked chunk.
-                    (None, ChunkIdentifierGenerator::new_from_scratch())
-                }
-            };
+                        let repaired = match repair_stored_linked_chunk(
+                            &store_lock,
+                            &self.room,
+                            "the last chunk couldn't be loaded",
+                        )
+                        .await
+                        {
+                            Ok(_) => store_lock.load_last_chunk(&self.room).await.ok(),
+                            Err(_) => None,
+                        };
+
+                        match repaired {
+                            Some(pair) => pair,
+
+                            None => {
+                                // Clear storage for this room.
+                                store_lock
+                                    .handle_linked_chunk_updates(&self.room, vec![Update::Clear])
+                                    .await?;
+
+                                // Restart with an empty linked chunk.
+                                (None, ChunkIdentifierGenerator::new_from_scratch())
+                            }
+                        }
+                    }
+                };
 
             debug!("unloading the linked chunk, and resetting it to its last chunk");
 
@@ -972,7 +1242,7 @@ mod private {
 
             // However, we want to get updates as `VectorDiff`s, for the external listeners.
             // Check we're respecting the contract defined in the doc comment.
-            let diffs = self.events.updates_as_vector_diffs();
+            let diffs = self.updates_as_vector_diffs();
             assert!(matches!(diffs[0], VectorDiff::Clear));
 
             Ok(Some(diffs))
@@ -1094,6 +1364,74 @@ mod private {
             Ok(timeline_event_diffs)
         }
 
+        /// Remove the events sent by the given users, in `RoomEvents` and in
+        /// `EventCacheStore`.
+        #[must_use = "Updates as `VectorDiff` must probably be propagated via `RoomEventCacheUpdate`"]
+        #[instrument(skip_all, fields(room_id = %self.room))]
+        pub(crate) async fn remove_events_from_senders(
+            &mut self,
+            senders: &BTreeSet<OwnedUserId>,
+        ) -> Result<Vec<VectorDiff<TimelineEvent>>, EventCacheError> {
+            let is_from_senders = |event: &TimelineEvent| {
+                event
+                    .raw()
+                    .get_field::<OwnedUserId>("sender")
+                    .ok()
+                    .flatten()
+                    .is_some_and(|sender| senders.contains(&sender))
+            };
+
+            let in_memory_events = self
+                .events
+                .revents()
+                .filter(|(_position, event)| is_from_senders(event))
+                .filter_map(|(position, event)| Some((event.event_id()?, position)))
+                .collect::<Vec<_>>();
+
+            // The events that haven't been loaded in memory yet must be removed from the
+            // store directly.
+            let mut in_store_events = Vec::new();
+
+            if let Some(store) = self.store.get() {
+                let in_memory_chunk_identifiers =
+                    self.events.chunks().map(|chunk| chunk.identifier()).collect::<HashSet<_>>();
+
+                let chunks = store.lock().await?.load_all_chunks(&self.room).await?;
+
+                for chunk in chunks {
+                    if in_memory_chunk_identifiers.contains(&chunk.identifier) {
+                        continue;
+                    }
+
+                    let ChunkContent::Items(events) = chunk.content else {
+                        continue;
+                    };
+
+                    in_store_events.extend(
+                        events
+                            .iter()
+                            .enumerate()
+                            .filter(|(_index, event)| is_from_senders(event))
+                            .filter_map(|(index, event)| {
+                                Some((event.event_id()?, Position::new(chunk.identifier, index)))
+                            }),
+                    );
+                }
+            }
+
+            if in_memory_events.is_empty() && in_store_events.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            debug!(
+                num_in_memory = in_memory_events.len(),
+                num_in_store = in_store_events.len(),
+                "removing the events of ignored users"
+            );
+
+            self.remove_events(in_memory_events, in_store_events).await
+        }
+
         /// Propagate changes to the underlying storage.
         async fn propagate_changes(&mut self) -> Result<(), EventCacheError> {
             let updates = self.events.store_updates().take();
@@ -1171,7 +1509,7 @@ mod private {
             // TODO: likely must cancel any ongoing back-paginations too
             self.pagination_status.set(RoomPaginationStatus::Idle { hit_timeline_start: false });
 
-            let diff_updates = self.events.updates_as_vector_diffs();
+            let diff_updates = self.updates_as_vector_diffs();
 
             // Ensure the contract defined in the doc comment is true:
             debug_assert_eq!(diff_updates.len(), 1);
@@ -1214,6 +1552,33 @@ mod private {
                 .map(|event| (EventLocation::Store, event)))
         }
 
+        /// Mark this room as retained in the storage, or not.
+        pub async fn set_retained(&self, retained: bool) -> Result<(), EventCacheError> {
+            let store = self.store.get().ok_or(EventCacheError::StorageDisabled)?;
+            store.lock().await?.set_room_retained(&self.room, retained).await?;
+            Ok(())
+        }
+
+        /// Mark an event of this room as retained in the storage, or not.
+        ///
+        /// Events loaded in memory have been persisted through the linked chunk
+        /// updates already, so it's enough to check that the event is known
+        /// before retaining it.
+        pub async fn set_event_retained(
+            &self,
+            event_id: &EventId,
+            retained: bool,
+        ) -> Result<(), EventCacheError> {
+            let store = self.store.get().ok_or(EventCacheError::StorageDisabled)?;
+
+            if retained && self.find_event(event_id).await?.is_none() {
+                return Err(EventCacheError::EventNotFound(event_id.to_owned()));
+            }
+
+            store.lock().await?.set_event_retained(&self.room, event_id, retained).await?;
+            Ok(())
+        }
+
         /// Find an event and all its relations in the persisted storage.
         ///
         /// This goes straight to the database, as a simplification; we don't
@@ -1301,6 +1666,12 @@ mod private {
                 self.maybe_apply_new_redaction(event).await?;
             }
 
+            #[cfg(feature = "e2e-encryption")]
+            observe_events(&self.utd_hook_sender, &self.room, &events_to_post_process);
+
+            #[cfg(feature = "opentelemetry")]
+            crate::telemetry::record_unable_to_decrypt(&events_to_post_process);
+
             // If we've never waited for an initial previous-batch token, and we now have at
             // least one gap in the chunk, no need to wait for a previous-batch token later.
             if !self.waited_for_initial_prev_token
@@ -1309,7 +1680,7 @@ mod private {
                 self.waited_for_initial_prev_token = true;
             }
 
-            let updates_as_vector_diffs = self.events.updates_as_vector_diffs();
+            let updates_as_vector_diffs = self.updates_as_vector_diffs();
 
             Ok(updates_as_vector_diffs)
         }
@@ -1819,6 +2190,84 @@ mod tests {
         assert!(chunks.next().is_none());
     }
 
+    #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
+    #[async_test]
+    async fn test_write_to_storage_keeps_decrypted_events_until_redacted() {
+        use matrix_sdk_base::{
+            event_cache::store::integration_tests::{
+                check_test_event, make_test_event_with_event_id,
+            },
+            linked_chunk::{lazy_loader::from_all_chunks, RawChunk},
+        };
+        use matrix_sdk_common::deserialized_responses::TimelineEventKind;
+
+        let room_id = room_id!("!galette:saucisse.bzh");
+        let f = EventFactory::new().room(room_id).sender(*ALICE);
+        let event_id = event_id!("$secret");
+
+        let event_cache_store = Arc::new(MemoryStore::new());
+
+        let client = MockClientBuilder::new("http://localhost".to_owned())
+            .store_config(
+                StoreConfig::new("hodlor".to_owned()).event_cache_store(event_cache_store.clone()),
+            )
+            .build()
+            .await;
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_storage().unwrap();
+
+        client.base_client().get_or_create_room(room_id, matrix_sdk_base::RoomState::Joined);
+        let room = client.get_room(room_id).unwrap();
+
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+        // Receive an event that's been decrypted.
+        let ev = make_test_event_with_event_id(room_id, "the cake is a lie", Some(event_id));
+        let timeline = Timeline { limited: false, prev_batch: None, events: vec![ev] };
+        room_event_cache
+            .inner
+            .handle_joined_room_update(true, JoinedRoomUpdate { timeline, ..Default::default() })
+            .await
+            .unwrap();
+
+        // The decrypted content is persisted, so it doesn't have to be decrypted again
+        // when the room is reloaded from storage.
+        let stored_events = |chunks: Vec<RawChunk<TimelineEvent, Gap>>| {
+            let linked_chunk = from_all_chunks::<3, _, _>(chunks).unwrap().unwrap();
+            linked_chunk.items().map(|(_, event)| event.clone()).collect::<Vec<_>>()
+        };
+
+        let events = stored_events(event_cache_store.load_all_chunks(room_id).await.unwrap());
+        assert_eq!(events.len(), 1);
+        check_test_event(&events[0], "the cake is a lie");
+
+        // Then the event is redacted.
+        let timeline = Timeline {
+            limited: false,
+            prev_batch: None,
+            events: vec![f.redaction(event_id).into_event()],
+        };
+        room_event_cache
+            .inner
+            .handle_joined_room_update(true, JoinedRoomUpdate { timeline, ..Default::default() })
+            .await
+            .unwrap();
+
+        // The persisted cleartext has been replaced by the redacted form.
+        let events = stored_events(event_cache_store.load_all_chunks(room_id).await.unwrap());
+        assert_eq!(events.len(), 2);
+        assert_matches!(&events[0].kind, TimelineEventKind::Decrypted(decrypted) => {
+            assert!(!decrypted.event.json().get().contains("the cake is a lie"));
+        });
+        assert_let!(
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(msg)) =
+                events[0].raw().deserialize().unwrap()
+        );
+        assert!(msg.as_original().is_none());
+    }
+
     #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
     #[async_test]
     async fn test_clear() {
@@ -2144,14 +2593,20 @@ mod tests {
 
         let (items, _stream) = room_event_cache.subscribe().await;
 
-        // Because the persisted content was invalid, the room store is reset: there are
-        // no events in the cache.
-        assert!(items.is_empty());
+        // Because the persisted content was invalid, the linked chunk has been
+        // repaired: the event is still in the cache.
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].event_id().unwrap(), event_id!("$42"));
 
-        // Storage doesn't contain anything. It would also be valid that it contains a
-        // single initial empty items chunk.
+        // Storage contains the repaired linked chunk, i.e. a single chunk with the
+        // event.
         let raw_chunks = event_cache_store.load_all_chunks(room_id).await.unwrap();
-        assert!(raw_chunks.is_empty());
+        assert_eq!(raw_chunks.len(), 1);
+        assert!(raw_chunks[0].previous.is_none());
+        assert!(raw_chunks[0].next.is_none());
+        assert_matches!(&raw_chunks[0].content, ChunkContent::Items(events) => {
+            assert_eq!(events.len(), 1);
+        });
     }
 
     #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
@@ -2432,6 +2887,105 @@ mod tests {
         assert!(outcome.reached_start);
     }
 
+    #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
+    #[async_test]
+    async fn test_remove_events_from_senders() {
+        use std::collections::BTreeSet;
+
+        use eyeball_im::VectorDiff;
+
+        let room_id = room_id!("!galette:saucisse.bzh");
+
+        let client = MockClientBuilder::new("http://localhost".to_owned()).build().await;
+
+        let f = EventFactory::new().room(room_id);
+
+        let evid1 = event_id!("$1");
+        let evid2 = event_id!("$2");
+        let evid3 = event_id!("$3");
+        let evid4 = event_id!("$4");
+
+        // Fill the event cache store with 2 events chunks, the first one won't be
+        // loaded in memory.
+        {
+            let store = client.event_cache_store();
+            let store = store.lock().await.unwrap();
+            store
+                .handle_linked_chunk_updates(
+                    room_id,
+                    vec![
+                        Update::NewItemsChunk {
+                            previous: None,
+                            new: ChunkIdentifier::new(0),
+                            next: None,
+                        },
+                        Update::PushItems {
+                            at: Position::new(ChunkIdentifier::new(0), 0),
+                            items: vec![
+                                f.text_msg("hello").sender(*ALICE).event_id(evid1).into_event(),
+                                f.text_msg("spam").sender(*BOB).event_id(evid2).into_event(),
+                            ],
+                        },
+                        Update::NewItemsChunk {
+                            previous: Some(ChunkIdentifier::new(0)),
+                            new: ChunkIdentifier::new(1),
+                            next: None,
+                        },
+                        Update::PushItems {
+                            at: Position::new(ChunkIdentifier::new(1), 0),
+                            items: vec![
+                                f.text_msg("spam").sender(*BOB).event_id(evid3).into_event(),
+                                f.text_msg("howdy").sender(*ALICE).event_id(evid4).into_event(),
+                            ],
+                        },
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_storage().unwrap();
+
+        client.base_client().get_or_create_room(room_id, matrix_sdk_base::RoomState::Joined);
+        let room = client.get_room(room_id).unwrap();
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+        let diffs = room_event_cache
+            .inner
+            .state
+            .write()
+            .await
+            .remove_events_from_senders(&BTreeSet::from([BOB.to_owned()]))
+            .await
+            .unwrap();
+
+        // Only the event loaded in memory generates an update.
+        assert_eq!(diffs.len(), 1);
+        assert_matches!(&diffs[0], VectorDiff::Remove { index: 0 });
+
+        let (events, _) = room_event_cache.subscribe().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().as_deref(), Some(evid4));
+
+        // The events have been removed from the store too.
+        let store = client.event_cache_store().lock().await.unwrap();
+        let event_ids = store
+            .load_all_chunks(room_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|chunk| match chunk.content {
+                ChunkContent::Items(events) => Some(events),
+                ChunkContent::Gap(_) => None,
+            })
+            .flatten()
+            .filter_map(|event| event.event_id())
+            .collect::<Vec<_>>();
+        assert_eq!(event_ids, [evid1, evid4]);
+    }
+
     #[cfg(not(target_arch = "wasm32"))] // This uses the cross-process lock, so needs time support.
     #[async_test]
     async fn test_auto_shrink_after_all_subscribers_are_gone() {
@@ -0,0 +1,343 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Repair of a stored linked chunk that has been found to be inconsistent.
+//!
+//! A linked chunk can become inconsistent in the store because of a bug or of
+//! a corruption of the store: a chunk can link to a chunk which doesn't
+//! exist, or which doesn't link back to it, or the same event can be present
+//! in several chunks. Instead of dropping all the events of the room, the
+//! linked chunk is rebuilt from the chunks which can still be reached from
+//! its last chunk, the other chunks being reattached before them, and
+//! rewritten in the store.
+
+use std::collections::{BTreeMap, HashSet};
+
+use matrix_sdk_base::{
+    event_cache::{store::EventCacheStoreLockGuard, Event, Gap},
+    linked_chunk::{ChunkContent, ChunkIdentifier, Position, RawChunk, Update},
+};
+use ruma::RoomId;
+use tracing::warn;
+
+use super::super::EventCacheError;
+
+/// A report about the repair of the linked chunk of a room.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct RepairReport {
+    /// The number of chunks in the store, before the repair.
+    pub num_chunks: usize,
+
+    /// The number of chunks which couldn't be reached from the last chunk, and
+    /// have been reattached at the start of the linked chunk.
+    pub num_unreachable_chunks: usize,
+
+    /// The number of events of the reattached chunks.
+    pub num_reattached_events: usize,
+
+    /// The number of events which were present more than once, and for which
+    /// only the most recent occurrence has been kept.
+    pub num_duplicated_events: usize,
+
+    /// The number of events in the repaired linked chunk.
+    pub num_kept_events: usize,
+}
+
+/// Whether the given chunk contains the same event several times.
+pub(super) fn has_duplicated_events(chunk: &RawChunk<Event, Gap>) -> bool {
+    let ChunkContent::Items(events) = &chunk.content else {
+        return false;
+    };
+
+    let mut event_ids = HashSet::new();
+    events.iter().filter_map(|event| event.event_id()).any(|event_id| !event_ids.insert(event_id))
+}
+
+/// Rebuild the contents of a consistent linked chunk, from the oldest chunk to
+/// the newest one, from all the stored chunks of a linked chunk.
+///
+/// The chunks are chained from the last chunk, i.e. the chunk with no next
+/// chunk and the highest identifier, by following the links to the previous
+/// chunks as long as they are consistent. The other chunks are reattached
+/// before them, in the order of their identifiers, so they are behind the gap
+/// at the start of the chain, if there is one. Events which are present
+/// several times are only kept at their most recent position.
+pub(super) fn rebuild(
+    chunks: Vec<RawChunk<Event, Gap>>,
+) -> (Vec<ChunkContent<Event, Gap>>, RepairReport) {
+    let mut report = RepairReport { num_chunks: chunks.len(), ..Default::default() };
+
+    let mut chunks: BTreeMap<ChunkIdentifier, RawChunk<Event, Gap>> =
+        chunks.into_iter().map(|chunk| (chunk.identifier, chunk)).collect();
+
+    // Pick the last chunk, falling back to the most recently created chunk if every
+    // chunk has a next chunk, e.g. if they form a cycle.
+    let last_chunk_id = chunks
+        .values()
+        .rev()
+        .find(|chunk| chunk.next.is_none())
+        .or_else(|| chunks.values().next_back())
+        .map(|chunk| chunk.identifier);
+
+    // Chain the chunks, from the newest to the oldest.
+    let mut chain = Vec::new();
+    let mut next_id = last_chunk_id;
+
+    while let Some(chunk) = next_id.and_then(|id| chunks.remove(&id)) {
+        next_id = chunk.previous.filter(|previous| {
+            // Stop at a dangling link, or at a chunk that doesn't link back to this one.
+            chunks.get(previous).is_some_and(|previous| previous.next == Some(chunk.identifier))
+        });
+        chain.push(chunk.content);
+    }
+
+    // Reattach the unreachable chunks before the oldest chunk of the chain, from
+    // the newest to the oldest too.
+    report.num_unreachable_chunks = chunks.len();
+
+    for chunk in chunks.into_values().rev() {
+        if let ChunkContent::Items(events) = &chunk.content {
+            report.num_reattached_events += events.len();
+        }
+        chain.push(chunk.content);
+    }
+
+    // Deduplicate the events, keeping the most recent occurrence, i.e. the first
+    // one seen while walking from the newest chunk.
+    let mut seen_event_ids = HashSet::new();
+
+    for content in &mut chain {
+        if let ChunkContent::Items(events) = content {
+            // Walk the events of the chunk from the newest too.
+            let mut kept = Vec::with_capacity(events.len());
+
+            for event in events.drain(..).rev() {
+                match event.event_id() {
+                    Some(event_id) if !seen_event_ids.insert(event_id) => {
+                        report.num_duplicated_events += 1;
+                    }
+                    _ => kept.push(event),
+                }
+            }
+
+            kept.reverse();
+            report.num_kept_events += kept.len();
+            *events = kept;
+        }
+    }
+
+    // Drop the chunks left empty, and put the chunks back in order, from the oldest
+    // to the newest.
+    chain.retain(|content| !matches!(content, ChunkContent::Items(events) if events.is_empty()));
+    chain.reverse();
+
+    (chain, report)
+}
+
+/// The store updates to replace a linked chunk with the given contents.
+fn updates_for(contents: Vec<ChunkContent<Event, Gap>>) -> Vec<Update<Event, Gap>> {
+    let mut updates = vec![Update::Clear];
+    let mut previous = None;
+
+    for (index, content) in contents.into_iter().enumerate() {
+        let new = ChunkIdentifier::new(index as u64);
+
+        match content {
+            ChunkContent::Items(items) => {
+                updates.push(Update::NewItemsChunk { previous, new, next: None });
+                updates.push(Update::PushItems { at: Position::new(new, 0), items });
+            }
+            ChunkContent::Gap(gap) => {
+                updates.push(Update::NewGapChunk { previous, new, next: None, gap });
+            }
+        }
+
+        previous = Some(new);
+    }
+
+    updates
+}
+
+/// Repair the stored linked chunk of the given room, by rebuilding it from its
+/// stored chunks with [`rebuild`], and rewriting it in the store.
+///
+/// The `reason` is logged with the report of the repair.
+pub(super) async fn repair_stored_linked_chunk(
+    store: &EventCacheStoreLockGuard<'_>,
+    room_id: &RoomId,
+    reason: &str,
+) -> Result<RepairReport, EventCacheError> {
+    let chunks = store.load_all_chunks(room_id).await?;
+    let (contents, report) = rebuild(chunks);
+
+    store.handle_linked_chunk_updates(room_id, updates_for(contents)).await?;
+
+    warn!(
+        %room_id,
+        reason,
+        num_chunks = report.num_chunks,
+        num_unreachable_chunks = report.num_unreachable_chunks,
+        num_reattached_events = report.num_reattached_events,
+        num_duplicated_events = report.num_duplicated_events,
+        num_kept_events = report.num_kept_events,
+        "repaired an inconsistent linked chunk"
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_base::{
+        event_cache::{Event, Gap},
+        linked_chunk::{ChunkContent, ChunkIdentifier as CId, RawChunk},
+    };
+    use matrix_sdk_test::{event_factory::EventFactory, ALICE};
+    use ruma::{room_id, EventId};
+
+    use super::{has_duplicated_events, rebuild, RepairReport};
+
+    fn event(id: &str) -> Event {
+        EventFactory::new()
+            .room(room_id!("!galette:saucisse.bzh"))
+            .sender(*ALICE)
+            .text_msg(id)
+            .event_id(&EventId::parse(id).unwrap())
+            .into_event()
+    }
+
+    fn items(
+        previous: Option<u64>,
+        identifier: u64,
+        next: Option<u64>,
+        ids: &[&str],
+    ) -> RawChunk<Event, Gap> {
+        RawChunk {
+            content: ChunkContent::Items(ids.iter().map(|id| event(id)).collect()),
+            previous: previous.map(CId::new),
+            identifier: CId::new(identifier),
+            next: next.map(CId::new),
+        }
+    }
+
+    fn event_ids(content: &ChunkContent<Event, Gap>) -> Vec<String> {
+        match content {
+            ChunkContent::Items(events) => {
+                events.iter().map(|event| event.event_id().unwrap().to_string()).collect()
+            }
+            ChunkContent::Gap(gap) => vec![gap.prev_token.clone()],
+        }
+    }
+
+    #[test]
+    fn test_has_duplicated_events() {
+        assert!(!has_duplicated_events(&items(None, 0, None, &["$a", "$b"])));
+        assert!(has_duplicated_events(&items(None, 0, None, &["$a", "$b", "$a"])));
+    }
+
+    #[test]
+    fn test_rebuild_consistent_linked_chunk() {
+        let gap = RawChunk {
+            content: ChunkContent::Gap(Gap { prev_token: "prev".to_owned() }),
+            previous: None,
+            identifier: CId::new(0),
+            next: Some(CId::new(1)),
+        };
+
+        let (contents, report) = rebuild(vec![
+            gap,
+            items(Some(0), 1, Some(2), &["$a", "$b"]),
+            items(Some(1), 2, None, &["$c"]),
+        ]);
+
+        assert_eq!(
+            contents.iter().map(event_ids).collect::<Vec<_>>(),
+            [vec!["prev"], vec!["$a", "$b"], vec!["$c"]]
+        );
+        assert_eq!(
+            report,
+            RepairReport { num_chunks: 3, num_kept_events: 3, ..Default::default() }
+        );
+    }
+
+    #[test]
+    fn test_rebuild_reattaches_unreachable_chunks() {
+        // Chunk 1 links to a chunk 0 that doesn't exist, and chunk 3 isn't linked from
+        // the chain.
+        let (contents, report) = rebuild(vec![
+            items(Some(0), 1, Some(2), &["$a"]),
+            items(Some(1), 2, None, &["$b"]),
+            items(None, 3, Some(2), &["$c", "$d"]),
+        ]);
+
+        assert_eq!(
+            contents.iter().map(event_ids).collect::<Vec<_>>(),
+            [vec!["$c", "$d"], vec!["$a"], vec!["$b"]]
+        );
+        assert_eq!(
+            report,
+            RepairReport {
+                num_chunks: 3,
+                num_unreachable_chunks: 1,
+                num_reattached_events: 2,
+                num_duplicated_events: 0,
+                num_kept_events: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rebuild_reattaches_unreachable_chunks_behind_the_gap() {
+        // Chunk 0 doesn't link back to the gap, which starts the chain.
+        let gap = RawChunk {
+            content: ChunkContent::Gap(Gap { prev_token: "prev".to_owned() }),
+            previous: None,
+            identifier: CId::new(1),
+            next: Some(CId::new(2)),
+        };
+
+        let (contents, report) = rebuild(vec![
+            items(None, 0, None, &["$a", "$b"]),
+            gap,
+            items(Some(1), 2, None, &["$b", "$c"]),
+        ]);
+
+        // The reattached chunk is behind the gap, and the duplicated event is kept in
+        // the chain.
+        assert_eq!(
+            contents.iter().map(event_ids).collect::<Vec<_>>(),
+            [vec!["$a"], vec!["prev"], vec!["$b", "$c"]]
+        );
+        assert_eq!(report.num_unreachable_chunks, 1);
+        assert_eq!(report.num_reattached_events, 2);
+        assert_eq!(report.num_duplicated_events, 1);
+        assert_eq!(report.num_kept_events, 3);
+    }
+
+    #[test]
+    fn test_rebuild_keeps_the_most_recent_duplicates() {
+        let (contents, report) = rebuild(vec![
+            items(None, 0, Some(1), &["$a", "$b"]),
+            items(Some(0), 1, Some(2), &["$a"]),
+            items(Some(1), 2, None, &["$c", "$b"]),
+        ]);
+
+        assert_eq!(
+            contents.iter().map(event_ids).collect::<Vec<_>>(),
+            [vec!["$a"], vec!["$c", "$b"]]
+        );
+        assert_eq!(report.num_duplicated_events, 2);
+        assert_eq!(report.num_kept_events, 3);
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-wide reporting of unable-to-decrypt (UTD) events, for analytics.
+//!
+//! Every event entering the event cache, be it from sync, back-pagination or
+//! a re-decryption, is fed to the [`UtdHookManager`] installed with
+//! [`EventCache::set_unable_to_decrypt_hook`], if any, which deduplicates them
+//! and reports them to its [`UnableToDecryptHook`].
+//!
+//! [`EventCache::set_unable_to_decrypt_hook`]: super::EventCache::set_unable_to_decrypt_hook
+//! [`UnableToDecryptHook`]: crate::unable_to_decrypt_hook::UnableToDecryptHook
+
+use std::sync::Arc;
+
+use matrix_sdk_base::{
+    crypto::types::events::UtdCause,
+    deserialized_responses::{TimelineEvent, TimelineEventKind},
+};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId};
+use tokio::sync::mpsc;
+use tracing::{instrument, trace};
+
+use super::EventCacheInner;
+use crate::unable_to_decrypt_hook::UtdHookManager;
+
+/// Payload sent by the rooms' event caches to the [`utd_hook_task`].
+#[derive(Debug)]
+pub(super) enum UtdHookPayload {
+    /// An event couldn't be decrypted.
+    Utd { room_id: OwnedRoomId, event: TimelineEvent },
+
+    /// An event has been successfully decrypted.
+    Decrypted { event_id: OwnedEventId },
+}
+
+/// Let the [`utd_hook_task`] know about the new events of a room.
+pub(super) fn observe_events(
+    sender: &mpsc::UnboundedSender<UtdHookPayload>,
+    room_id: &RoomId,
+    events: &[TimelineEvent],
+) {
+    for event in events {
+        let payload = match &event.kind {
+            TimelineEventKind::UnableToDecrypt { .. } => {
+                UtdHookPayload::Utd { room_id: room_id.to_owned(), event: event.clone() }
+            }
+
+            TimelineEventKind::Decrypted(_) => {
+                let Some(event_id) = event.event_id() else {
+                    continue;
+                };
+                UtdHookPayload::Decrypted { event_id }
+            }
+
+            TimelineEventKind::PlainText { .. } => continue,
+        };
+
+        // The receiver only goes away with the event cache.
+        let _ = sender.send(payload);
+    }
+}
+
+/// Listen to the UTDs and decrypted events observed by the rooms' event
+/// caches, and feed them to the [`UtdHookManager`], if any.
+#[instrument(skip_all)]
+pub(super) async fn utd_hook_task(
+    inner: Arc<EventCacheInner>,
+    mut rx: mpsc::UnboundedReceiver<UtdHookPayload>,
+) {
+    while let Some(payload) = rx.recv().await {
+        let Some(hook) = inner.utd_hook.read().unwrap().clone() else {
+            // Nobody to report to.
+            continue;
+        };
+
+        match payload {
+            UtdHookPayload::Utd { room_id, event } => {
+                let Some(event_id) = event.event_id() else {
+                    continue;
+                };
+
+                if hook.has_seen(&event_id).await {
+                    // Already reported, or about to be.
+                    continue;
+                }
+
+                let Some((cause, timestamp, sender)) = inner.classify(&room_id, &event).await
+                else {
+                    continue;
+                };
+
+                trace!(%event_id, ?cause, "reporting a new UTD");
+                hook.on_utd(&event_id, cause, timestamp, &sender).await;
+            }
+
+            UtdHookPayload::Decrypted { event_id } => {
+                hook.on_late_decrypt(&event_id).await;
+            }
+        }
+    }
+
+    trace!("UTD hook channel closed, exiting");
+}
+
+impl EventCacheInner {
+    /// Compute the cause of a UTD, and get its timestamp and sender.
+    async fn classify(
+        &self,
+        room_id: &RoomId,
+        event: &TimelineEvent,
+    ) -> Option<(UtdCause, MilliSecondsSinceUnixEpoch, OwnedUserId)> {
+        let TimelineEventKind::UnableToDecrypt { utd_info, .. } = &event.kind else {
+            return None;
+        };
+
+        let sender = event.raw().get_field::<OwnedUserId>("sender").ok().flatten()?;
+        let timestamp = event
+            .raw()
+            .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+            .ok()
+            .flatten()
+            .unwrap_or_else(MilliSecondsSinceUnixEpoch::now);
+
+        let room = self.client().ok()?.get_room(room_id)?;
+        let crypto_context_info = room.crypto_context_info().await;
+        let cause = UtdCause::determine(event.raw(), crypto_context_info, utd_info);
+
+        Some((cause, timestamp, sender))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use assert_matches2::assert_let;
+    use matrix_sdk_base::crypto::types::events::UtdCause;
+    use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, BOB};
+    use ruma::room_id;
+    use tokio::sync::mpsc;
+
+    use crate::{
+        event_cache::redecryptor::tests::{encrypted_event_content, import_room_key},
+        test_utils::mocks::MatrixMockServer,
+        unable_to_decrypt_hook::{
+            UnableToDecryptHook, UnableToDecryptInfo, UtdHookManager, UtdReportKind,
+        },
+    };
+
+    #[derive(Debug)]
+    struct ChannelHook(mpsc::UnboundedSender<UnableToDecryptInfo>);
+
+    impl UnableToDecryptHook for ChannelHook {
+        fn on_utd(&self, info: UnableToDecryptInfo) {
+            self.0.send(info).unwrap();
+        }
+    }
+
+    async fn next_report(
+        reports: &mut mpsc::UnboundedReceiver<UnableToDecryptInfo>,
+    ) -> Option<UnableToDecryptInfo> {
+        tokio::time::timeout(Duration::from_secs(1), reports.recv()).await.ok().flatten()
+    }
+
+    #[async_test]
+    async fn test_utd_reported_as_permanent_then_late() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let (tx, mut reports) = mpsc::unbounded_channel();
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.set_unable_to_decrypt_hook(Arc::new(
+            UtdHookManager::new(Arc::new(ChannelHook(tx)), client.clone())
+                .report_late_decryptions(),
+        ));
+
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let f = EventFactory::new().room(room_id).sender(&BOB);
+
+        server
+            .sync_room(
+                &client,
+                JoinedRoomBuilder::new(room_id)
+                    .add_timeline_event(f.event(encrypted_event_content())),
+            )
+            .await;
+
+        // Without a grace period, the UTD is reported as permanent immediately.
+        assert_let!(Some(report) = next_report(&mut reports).await);
+        assert_eq!(report.kind, UtdReportKind::Permanent);
+        assert_eq!(report.time_to_decrypt, None);
+        // The event factory uses timestamps from way before our device was created,
+        // and there's no backup.
+        assert_eq!(report.cause, UtdCause::HistoricalMessageAndBackupIsDisabled);
+        assert!(!report.key_backup_pending);
+        assert!(report.event_local_age_millis < 0);
+
+        let event_id = report.event_id;
+
+        import_room_key(&client).await;
+
+        // Once the room key is received, the UTD is reported as a late decryption.
+        assert_let!(Some(report) = next_report(&mut reports).await);
+        assert_eq!(report.event_id, event_id);
+        assert_eq!(report.kind, UtdReportKind::Late);
+        assert!(report.time_to_decrypt.is_some());
+
+        assert!(next_report(&mut reports).await.is_none());
+    }
+
+    #[async_test]
+    async fn test_utd_resolved_within_grace_period() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let (tx, mut reports) = mpsc::unbounded_channel();
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.set_unable_to_decrypt_hook(Arc::new(
+            UtdHookManager::new(Arc::new(ChannelHook(tx)), client.clone())
+                .with_max_delay(Duration::from_secs(60)),
+        ));
+
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        let room = server.sync_joined_room(&client, room_id).await;
+        let (_room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+        let f = EventFactory::new().room(room_id).sender(&BOB);
+        server
+            .sync_room(
+                &client,
+                JoinedRoomBuilder::new(room_id)
+                    .add_timeline_event(f.event(encrypted_event_content())),
+            )
+            .await;
+
+        // Nothing is reported during the grace period.
+        assert!(next_report(&mut reports).await.is_none());
+
+        import_room_key(&client).await;
+
+        // Once the room key is received, the UTD is reported as resolved.
+        assert_let!(Some(report) = next_report(&mut reports).await);
+        assert_eq!(report.kind, UtdReportKind::Resolved);
+        assert!(report.time_to_decrypt.is_some());
+
+        assert!(next_report(&mut reports).await.is_none());
+    }
+}
@@ -0,0 +1,518 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Opt-in telemetry of unable-to-decrypt (UTD) events.
+//!
+//! A [`UtdReporter`] is an [`UnableToDecryptHook`] that anonymizes the
+//! [`UnableToDecryptInfo`]s it receives, and sends them in batches to a
+//! configurable endpoint, or to the homeserver if it supports it, so
+//! deployments can track the reliability of end-to-end encryption in the field.
+//!
+//! The anonymized reports contain no identifier: neither the room, the event,
+//! the sender nor the homeservers are sent, only what was observed about the
+//! UTD.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::{sync::Arc, time::Duration};
+//!
+//! use matrix_sdk::{
+//!     event_cache::{UtdReportDestination, UtdReporter, UtdReporterConfig},
+//!     unable_to_decrypt_hook::UtdHookManager,
+//!     Client,
+//! };
+//!
+//! # async fn example(client: Client) {
+//! let reporter = UtdReporter::new(
+//!     &client,
+//!     UtdReporterConfig::new(UtdReportDestination::Homeserver)
+//!         .flush_interval(Duration::from_secs(5 * 60)),
+//! );
+//!
+//! client.event_cache().set_unable_to_decrypt_hook(Arc::new(
+//!     UtdHookManager::new(reporter, client.clone())
+//!         .with_max_delay(Duration::from_secs(60))
+//!         .report_late_decryptions(),
+//! ));
+//! # }
+//! ```
+//!
+//! [`UnableToDecryptHook`]: crate::unable_to_decrypt_hook::UnableToDecryptHook
+//! [`UnableToDecryptInfo`]: crate::unable_to_decrypt_hook::UnableToDecryptInfo
+
+use std::{mem, sync::Arc, time::Duration};
+
+use matrix_sdk_base::crypto::types::events::UtdCause;
+use matrix_sdk_common::{executor::spawn, timeout::timeout};
+use reqwest::header;
+use ruma::time::Instant;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, instrument, trace, warn};
+use url::Url;
+
+use crate::{
+    client::WeakClient,
+    unable_to_decrypt_hook::{UnableToDecryptHook, UnableToDecryptInfo, UtdReportKind},
+    Client,
+};
+
+/// The unstable feature advertised by homeservers accepting UTD reports.
+const UNSTABLE_FEATURE: &str = "org.matrix.msc4081";
+
+/// Where the [`UtdReporter`] sends its reports.
+#[derive(Clone, Debug)]
+pub enum UtdReportDestination {
+    /// A custom endpoint, receiving the reports as JSON in a `POST` request.
+    Endpoint(Url),
+
+    /// The homeserver, if it advertises support for UTD reports. Otherwise,
+    /// the reports are dropped.
+    Homeserver,
+}
+
+/// The configuration of a [`UtdReporter`].
+#[derive(Clone, Debug)]
+pub struct UtdReporterConfig {
+    destination: UtdReportDestination,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_pending_reports: usize,
+    include_expected: bool,
+}
+
+impl UtdReporterConfig {
+    /// Create a new configuration sending the reports to the given
+    /// destination.
+    ///
+    /// By default, at most one batch of at most 50 reports is sent per minute,
+    /// at most 500 reports are kept waiting, and expected UTDs aren't
+    /// reported.
+    pub fn new(destination: UtdReportDestination) -> Self {
+        Self {
+            destination,
+            batch_size: 50,
+            flush_interval: Duration::from_secs(60),
+            max_pending_reports: 500,
+            include_expected: false,
+        }
+    }
+
+    /// Set the maximum number of reports sent in a single request.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the interval between two requests.
+    ///
+    /// This is also the maximum time a report waits before being sent.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Set the maximum number of reports waiting to be sent.
+    ///
+    /// Further reports are dropped, and only counted in the next batch.
+    pub fn max_pending_reports(mut self, max_pending_reports: usize) -> Self {
+        self.max_pending_reports = max_pending_reports;
+        self
+    }
+
+    /// Set whether expected UTDs, whose keys were never meant to be shared
+    /// with us, are reported too.
+    ///
+    /// See [`UnableToDecryptInfo::is_expected`].
+    pub fn include_expected(mut self, include_expected: bool) -> Self {
+        self.include_expected = include_expected;
+        self
+    }
+}
+
+/// An anonymized [`UnableToDecryptInfo`], as sent by the [`UtdReporter`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnonymizedUtdReport {
+    /// What this report is about: `permanent`, `late` or `resolved`.
+    pub kind: String,
+
+    /// Our best guess at the reason why the event couldn't be decrypted, e.g.
+    /// `sent_before_we_joined`.
+    pub cause: String,
+
+    /// Whether this UTD was expected.
+    pub expected: bool,
+
+    /// Whether the room key might still be downloaded from the backup.
+    pub key_backup_pending: bool,
+
+    /// The time it took to decrypt the event, in milliseconds, for `late` and
+    /// `resolved` reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_to_decrypt_ms: Option<u64>,
+
+    /// Whether the event was sent before our device was created.
+    pub sent_before_device_creation: bool,
+}
+
+impl From<&UnableToDecryptInfo> for AnonymizedUtdReport {
+    fn from(report: &UnableToDecryptInfo) -> Self {
+        let kind = match report.kind {
+            UtdReportKind::Permanent => "permanent",
+            UtdReportKind::Late => "late",
+            UtdReportKind::Resolved => "resolved",
+        };
+
+        let cause = match report.cause {
+            UtdCause::Unknown => "unknown",
+            UtdCause::SentBeforeWeJoined => "sent_before_we_joined",
+            UtdCause::VerificationViolation => "verification_violation",
+            UtdCause::UnsignedDevice => "unsigned_device",
+            UtdCause::UnknownDevice => "unknown_device",
+            UtdCause::HistoricalMessageAndBackupIsDisabled => {
+                "historical_message_and_backup_is_disabled"
+            }
+            UtdCause::WithheldForUnverifiedOrInsecureDevice => {
+                "withheld_for_unverified_or_insecure_device"
+            }
+            UtdCause::WithheldBySender => "withheld_by_sender",
+            UtdCause::HistoricalMessageAndDeviceIsUnverified => {
+                "historical_message_and_device_is_unverified"
+            }
+            UtdCause::HistoryNotShared => "history_not_shared",
+        };
+
+        Self {
+            kind: kind.to_owned(),
+            cause: cause.to_owned(),
+            expected: report.is_expected(),
+            key_backup_pending: report.key_backup_pending,
+            time_to_decrypt_ms: report
+                .time_to_decrypt
+                .map(|duration| duration.as_millis().try_into().unwrap_or(u64::MAX)),
+            sent_before_device_creation: report.event_local_age_millis < 0,
+        }
+    }
+}
+
+/// A batch of reports, as sent in a single request.
+#[derive(Debug, Serialize)]
+struct UtdReportBatch {
+    reports: Vec<AnonymizedUtdReport>,
+    num_dropped: u64,
+}
+
+/// An [`UnableToDecryptHook`] sending anonymized reports of the UTDs, batched
+/// and rate-limited.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct UtdReporter {
+    sender: mpsc::UnboundedSender<AnonymizedUtdReport>,
+    include_expected: bool,
+}
+
+impl UtdReporter {
+    /// Create a new reporter for the given client.
+    ///
+    /// The reporter must be wrapped in a [`UtdHookManager`] to receive the
+    /// UTDs, for example one installed with
+    /// [`EventCache::set_unable_to_decrypt_hook`]. The pending reports are sent
+    /// one last time once it's dropped.
+    ///
+    /// [`UtdHookManager`]: crate::unable_to_decrypt_hook::UtdHookManager
+    /// [`EventCache::set_unable_to_decrypt_hook`]: super::EventCache::set_unable_to_decrypt_hook
+    pub fn new(client: &Client, config: UtdReporterConfig) -> Arc<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let include_expected = config.include_expected;
+
+        // The task ends once the reporter, and thus the sender, is dropped.
+        let _ = spawn(reporter_task(WeakClient::from_client(client), config, receiver));
+
+        Arc::new(Self { sender, include_expected })
+    }
+}
+
+impl UnableToDecryptHook for UtdReporter {
+    fn on_utd(&self, report: UnableToDecryptInfo) {
+        if report.is_expected() && !self.include_expected {
+            return;
+        }
+
+        // The receiver only goes away with the reporter.
+        let _ = self.sender.send(AnonymizedUtdReport::from(&report));
+    }
+}
+
+/// Collect the reports, and send them in batches, at most once per flush
+/// interval.
+#[instrument(skip_all)]
+async fn reporter_task(
+    client: WeakClient,
+    config: UtdReporterConfig,
+    mut receiver: mpsc::UnboundedReceiver<AnonymizedUtdReport>,
+) {
+    let mut pending = Vec::new();
+    let mut num_dropped = 0;
+    let mut next_flush = Instant::now() + config.flush_interval;
+    let mut closed = false;
+
+    while !closed {
+        match timeout(receiver.recv(), next_flush.saturating_duration_since(Instant::now())).await {
+            Ok(Some(report)) => {
+                if pending.len() < config.max_pending_reports {
+                    pending.push(report);
+                } else {
+                    num_dropped += 1;
+                }
+                continue;
+            }
+
+            // The reporter has been dropped: send the pending reports one last time.
+            Ok(None) => closed = true,
+
+            // Time to flush.
+            Err(_) => {}
+        }
+
+        next_flush = Instant::now() + config.flush_interval;
+
+        if pending.is_empty() && num_dropped == 0 {
+            continue;
+        }
+
+        let Some(client) = client.get() else {
+            trace!("The client has been dropped, stopping");
+            break;
+        };
+
+        loop {
+            let batch = UtdReportBatch {
+                reports: pending.drain(..pending.len().min(config.batch_size)).collect(),
+                num_dropped: mem::take(&mut num_dropped),
+            };
+            let num_reports = batch.reports.len();
+
+            match send_batch(&client, &config.destination, batch).await {
+                Ok(true) => debug!(num_reports, "Sent UTD reports"),
+                Ok(false) => {
+                    trace!("The homeserver doesn't support UTD reports, dropping them");
+                    break;
+                }
+                Err(err) => warn!(num_reports, "Failed to send UTD reports: {err}"),
+            }
+
+            // Once the reporter has been dropped, send all the pending reports.
+            if !closed || pending.is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+/// Send a batch of reports to the given destination.
+///
+/// Returns `false` if the homeserver doesn't support UTD reports.
+async fn send_batch(
+    client: &Client,
+    destination: &UtdReportDestination,
+    batch: UtdReportBatch,
+) -> crate::Result<bool> {
+    match destination {
+        UtdReportDestination::Endpoint(url) => {
+            client
+                .http_client()
+                .post(url.clone())
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&batch)?)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        UtdReportDestination::Homeserver => {
+            let supported =
+                client.unstable_features().await?.get(UNSTABLE_FEATURE).copied().unwrap_or(false);
+
+            if !supported {
+                return Ok(false);
+            }
+
+            client.send(report_utds::Request::new(batch.reports, batch.num_dropped)).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+mod report_utds {
+    //! `POST /_matrix/client/unstable/org.matrix.msc4081/utd_reports`
+    //!
+    //! Send anonymized UTD reports to the homeserver.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        metadata,
+    };
+
+    use super::AnonymizedUtdReport;
+
+    const METADATA: Metadata = metadata! {
+        method: POST,
+        rate_limited: true,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_matrix/client/unstable/org.matrix.msc4081/utd_reports",
+        }
+    };
+
+    /// Request type for the `report_utds` endpoint.
+    #[request(error = Error)]
+    pub struct Request {
+        /// The anonymized reports.
+        pub reports: Vec<AnonymizedUtdReport>,
+
+        /// The number of reports that have been dropped because of rate
+        /// limiting.
+        pub num_dropped: u64,
+    }
+
+    impl Request {
+        /// Creates a new `Request` with the given reports.
+        pub fn new(reports: Vec<AnonymizedUtdReport>, num_dropped: u64) -> Self {
+            Self { reports, num_dropped }
+        }
+    }
+
+    /// Response type for the `report_utds` endpoint.
+    #[response(error = Error)]
+    #[derive(Default)]
+    pub struct Response {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use matrix_sdk_base::crypto::types::events::UtdCause;
+    use ruma::{owned_event_id, owned_server_name, EventId};
+
+    use super::AnonymizedUtdReport;
+    use crate::unable_to_decrypt_hook::{UnableToDecryptInfo, UtdReportKind};
+
+    fn utd_report(event_id: &str) -> UnableToDecryptInfo {
+        UnableToDecryptInfo {
+            event_id: EventId::parse(event_id).unwrap(),
+            kind: UtdReportKind::Permanent,
+            time_to_decrypt: None,
+            cause: UtdCause::Unknown,
+            key_backup_pending: false,
+            event_local_age_millis: 0,
+            user_trusts_own_identity: false,
+            sender_homeserver: owned_server_name!("saucisse.bzh"),
+            own_homeserver: None,
+        }
+    }
+
+    #[test]
+    fn test_anonymized_report() {
+        let report = UnableToDecryptInfo {
+            event_id: owned_event_id!("$ev0"),
+            kind: UtdReportKind::Late,
+            time_to_decrypt: Some(Duration::from_millis(1234)),
+            cause: UtdCause::SentBeforeWeJoined,
+            key_backup_pending: true,
+            event_local_age_millis: -42,
+            user_trusts_own_identity: true,
+            sender_homeserver: owned_server_name!("saucisse.bzh"),
+            own_homeserver: Some(owned_server_name!("galette.bzh")),
+        };
+
+        let anonymized = AnonymizedUtdReport::from(&report);
+        assert_eq!(
+            anonymized,
+            AnonymizedUtdReport {
+                kind: "late".to_owned(),
+                cause: "sent_before_we_joined".to_owned(),
+                expected: true,
+                key_backup_pending: true,
+                time_to_decrypt_ms: Some(1234),
+                sent_before_device_creation: true,
+            }
+        );
+
+        // No identifier makes it into the serialized report.
+        let json = serde_json::to_string(&anonymized).unwrap();
+        assert!(!json.contains("galette"));
+        assert!(!json.contains("saucisse"));
+        assert!(!json.contains("$ev0"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[matrix_sdk_test::async_test]
+    async fn test_all_pending_reports_are_sent_once_dropped() {
+        use wiremock::{
+            matchers::{method, path},
+            Mock, ResponseTemplate,
+        };
+
+        use super::{UtdReportDestination, UtdReporter, UtdReporterConfig};
+        use crate::{
+            test_utils::mocks::MatrixMockServer, unable_to_decrypt_hook::UnableToDecryptHook,
+        };
+
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        Mock::given(method("POST"))
+            .and(path("/utd_reports"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(3)
+            .mount(server.server())
+            .await;
+
+        let url = format!("{}/utd_reports", server.server().uri()).parse().unwrap();
+        let reporter = UtdReporter::new(
+            &client,
+            UtdReporterConfig::new(UtdReportDestination::Endpoint(url))
+                .batch_size(2)
+                .flush_interval(Duration::from_secs(60 * 60)),
+        );
+
+        for i in 0..5 {
+            reporter.on_utd(utd_report(&format!("$ev{i}")));
+        }
+
+        // The 5 reports are sent in 3 batches, without waiting for the flush interval.
+        drop(reporter);
+
+        for _ in 0..100 {
+            if server.server().received_requests().await.unwrap().len() == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let requests = server.server().received_requests().await.unwrap();
+        let num_reports = requests
+            .iter()
+            .map(|request| {
+                let batch: serde_json::Value = request.body_json().unwrap();
+                batch["reports"].as_array().unwrap().len()
+            })
+            .sum::<usize>();
+        assert_eq!(num_reports, 5);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_base::{SendOutsideWasm, SyncOutsideWasm};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{HandlerKind, SyncEvent};
+
+/// The kind of a [`CustomEventContent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CustomEventKind {
+    /// A message-like event, that is part of the timeline of a room.
+    MessageLike,
+
+    /// A state event, that updates the state of a room.
+    State,
+}
+
+/// The content of a custom event type.
+///
+/// Implementing this trait associates a Rust type with an event type, so
+/// events of this type can be received in typed event handlers with
+/// [`CustomSyncEvent`], and sent with [`Room::send_custom()`] or
+/// [`Room::send_custom_state()`].
+///
+/// This is a lightweight alternative to deriving ruma's `EventContent` trait.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk::{
+///     event_handler::{CustomEventContent, CustomEventKind, CustomSyncEvent},
+///     Client,
+/// };
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct GameMove {
+///     from: String,
+///     to: String,
+/// }
+///
+/// impl CustomEventContent for GameMove {
+///     const TYPE: &'static str = "com.example.game.move";
+///     const KIND: CustomEventKind = CustomEventKind::MessageLike;
+/// }
+///
+/// # async fn example(client: Client) -> anyhow::Result<()> {
+/// client.add_event_handler(|ev: CustomSyncEvent<GameMove>| async move {
+///     println!(
+///         "{} moved from {} to {}",
+///         ev.sender, ev.content.from, ev.content.to
+///     );
+/// });
+/// # Ok(()) }
+/// ```
+///
+/// [`Room::send_custom()`]: crate::Room::send_custom
+/// [`Room::send_custom_state()`]: crate::Room::send_custom_state
+pub trait CustomEventContent:
+    Serialize + DeserializeOwned + SendOutsideWasm + SyncOutsideWasm + 'static
+{
+    /// The type of the event, like `com.example.game.move`.
+    const TYPE: &'static str;
+
+    /// The kind of the event.
+    const KIND: CustomEventKind;
+}
+
+/// A custom event received from the homeserver, with its content deserialized
+/// as `C`.
+///
+/// Redacted events are not passed to the event handlers using this type, since
+/// their content was removed.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CustomSyncEvent<C> {
+    /// The content of the event.
+    pub content: C,
+
+    /// The globally unique identifier of the event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+
+    /// The timestamp of the event on the homeserver of the sender.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The state key of the event, if it is a state event.
+    pub state_key: Option<String>,
+}
+
+impl<C> SyncEvent for CustomSyncEvent<C>
+where
+    C: CustomEventContent,
+{
+    const KIND: HandlerKind = match C::KIND {
+        CustomEventKind::MessageLike => HandlerKind::OriginalMessageLike,
+        CustomEventKind::State => HandlerKind::OriginalState,
+    };
+    const TYPE: Option<&'static str> = Some(C::TYPE);
+}
@@ -48,7 +48,7 @@ use std::{
 use anymap2::any::CloneAnySendSync;
 use eyeball::{SharedObservable, Subscriber};
 use futures_core::Stream;
-use futures_util::stream::{FuturesUnordered, StreamExt};
+use futures_util::stream::{self, StreamExt};
 use matrix_sdk_base::{
     deserialized_responses::{EncryptionInfo, TimelineEvent},
     SendOutsideWasm, SyncOutsideWasm,
@@ -63,15 +63,26 @@ use self::maps::EventHandlerMaps;
 use crate::{Client, Room};
 
 mod context;
+mod custom_events;
 mod maps;
 mod static_events;
+mod supervision;
+
+use self::supervision::EventHandlerMetricsStore;
+pub use self::{
+    context::{Ctx, EventHandlerContext, RawEvent},
+    custom_events::{CustomEventContent, CustomEventKind, CustomSyncEvent},
+    supervision::{
+        EventHandlerFailure, EventHandlerFailureKind, EventHandlerMetrics, EventHandlerSupervision,
+    },
+};
 
-pub use self::context::{Ctx, EventHandlerContext, RawEvent};
-
+/// The future of an event handler, that resolves to the error message if the
+/// event handler failed.
 #[cfg(not(target_arch = "wasm32"))]
-type EventHandlerFut = Pin<Box<dyn Future<Output = ()> + Send>>;
+type EventHandlerFut = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
 #[cfg(target_arch = "wasm32")]
-type EventHandlerFut = Pin<Box<dyn Future<Output = ()>>>;
+type EventHandlerFut = Pin<Box<dyn Future<Output = Result<(), String>>>>;
 
 #[cfg(not(target_arch = "wasm32"))]
 type EventHandlerFn = dyn Fn(EventHandlerData<'_>) -> EventHandlerFut + Send + Sync;
@@ -85,10 +96,13 @@ pub(crate) struct EventHandlerStore {
     handlers: RwLock<EventHandlerMaps>,
     context: RwLock<AnyMap>,
     counter: AtomicU64,
+    supervision: RwLock<EventHandlerSupervision>,
+    metrics: EventHandlerMetricsStore,
 }
 
 impl EventHandlerStore {
     pub fn add_handler(&self, handle: EventHandlerHandle, handler_fn: Box<EventHandlerFn>) {
+        self.metrics.add(handle.handler_id);
         self.handlers.write().unwrap().add(handle, handler_fn);
     }
 
@@ -100,9 +114,18 @@ impl EventHandlerStore {
     }
 
     pub fn remove(&self, handle: EventHandlerHandle) {
+        self.metrics.remove(handle.handler_id);
         self.handlers.write().unwrap().remove(handle);
     }
 
+    pub fn set_supervision(&self, supervision: EventHandlerSupervision) {
+        *self.supervision.write().unwrap() = supervision;
+    }
+
+    pub fn metrics(&self, handle: &EventHandlerHandle) -> Option<EventHandlerMetrics> {
+        self.metrics.get(handle.handler_id)
+    }
+
     #[cfg(test)]
     fn len(&self) -> usize {
         self.handlers.read().unwrap().len()
@@ -154,7 +177,7 @@ pub trait SyncEvent {
 }
 
 pub(crate) struct EventHandlerWrapper {
-    handler_fn: Box<EventHandlerFn>,
+    handler_fn: Arc<EventHandlerFn>,
     pub handler_id: u64,
 }
 
@@ -248,10 +271,17 @@ pub struct EventHandlerData<'a> {
 pub trait EventHandlerResult: Sized {
     #[doc(hidden)]
     fn print_error(&self, event_type: Option<&str>);
+
+    #[doc(hidden)]
+    fn error_message(&self) -> Option<String>;
 }
 
 impl EventHandlerResult for () {
     fn print_error(&self, _event_type: Option<&str>) {}
+
+    fn error_message(&self) -> Option<String> {
+        None
+    }
 }
 
 impl<E: fmt::Debug + fmt::Display + 'static> EventHandlerResult for Result<(), E> {
@@ -276,6 +306,10 @@ impl<E: fmt::Debug + fmt::Display + 'static> EventHandlerResult for Result<(), E
             Ok(_) => {}
         }
     }
+
+    fn error_message(&self) -> Option<String> {
+        self.as_ref().err().map(ToString::to_string)
+    }
 }
 
 #[derive(Deserialize)]
@@ -301,7 +335,12 @@ impl Client {
             Box::pin(async move {
                 match maybe_fut {
                     Ok(Some(fut)) => {
-                        fut.await.print_error(Ev::TYPE);
+                        let result = fut.await;
+                        result.print_error(Ev::TYPE);
+
+                        if let Some(message) = result.error_message() {
+                            return Err(message);
+                        }
                     }
                     Ok(None) => {
                         error!(
@@ -317,6 +356,8 @@ impl Client {
                         );
                     }
                 }
+
+                Ok(())
             })
         });
 
@@ -457,14 +498,20 @@ impl Client {
             tracing::Span::current().record("room_id", debug(room_id));
         }
 
-        // Construct event handler futures
-        let mut futures: FuturesUnordered<_> = self
-            .inner
-            .event_handlers
+        let event_handlers = &self.inner.event_handlers;
+        let supervision = event_handlers.supervision.read().unwrap().clone();
+
+        // Get the event handlers first, so the lock isn't held while they are called.
+        let handlers: Vec<_> = event_handlers
             .handlers
             .read()
             .unwrap()
             .get_handlers(event_kind, event_type, room_id)
+            .collect();
+
+        // Construct event handler futures
+        let futures: Vec<_> = handlers
+            .into_iter()
             .map(|(handle, handler_fn)| {
                 let data = EventHandlerData {
                     client: self.clone(),
@@ -472,18 +519,26 @@ impl Client {
                     raw,
                     encryption_info,
                     push_actions,
-                    handle,
+                    handle: handle.clone(),
                 };
 
-                (handler_fn)(data)
+                supervision.run(
+                    move || (handler_fn)(data),
+                    handle,
+                    event_type,
+                    room_id.map(ToOwned::to_owned),
+                    &event_handlers.metrics,
+                )
             })
             .collect();
 
         if !futures.is_empty() {
             debug!(amount = futures.len(), "Calling event handlers");
 
-            // Run the event handler futures with the `self.event_handlers.handlers`
-            // lock no longer being held.
+            // Run the event handler futures, which call the event handlers when they
+            // are first polled.
+            let concurrency = supervision.concurrency(futures.len());
+            let mut futures = stream::iter(futures).buffer_unordered(concurrency);
             while let Some(()) = futures.next().await {}
         }
     }
@@ -681,12 +736,15 @@ mod tests {
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
     use std::{
         future,
+        num::NonZeroUsize,
         sync::{
             atomic::{AtomicU8, Ordering::SeqCst},
-            Arc,
+            Arc, Mutex,
         },
+        time::Duration,
     };
 
+    use assert_matches2::assert_matches;
     use matrix_sdk_test::{StateTestEvent, StrippedStateTestEvent, SyncResponseBuilder};
     use once_cell::sync::Lazy;
     use ruma::{
@@ -704,10 +762,15 @@ mod tests {
         serde::Raw,
         user_id,
     };
+    use serde::{Deserialize, Serialize};
     use serde_json::json;
 
     use crate::{
-        event_handler::Ctx,
+        event_handler::{
+            Ctx, CustomEventContent, CustomEventKind, CustomSyncEvent, EventHandlerFailureKind,
+            EventHandlerSupervision,
+        },
+        sleep::sleep,
         test_utils::{logged_in_client, no_retry_test_client},
         Client, Room,
     };
@@ -1009,6 +1072,191 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    #[allow(dependency_on_unit_never_type_fallback)]
+    async fn test_event_handler_supervision() -> crate::Result<()> {
+        let client = logged_in_client(None).await;
+        let failures = Arc::new(Mutex::new(Vec::new()));
+
+        client.set_event_handler_supervision(
+            EventHandlerSupervision::new()
+                .timeout(Duration::from_millis(100))
+                .catch_panics(true)
+                .on_failure({
+                    let failures = failures.clone();
+                    move |failure| failures.lock().unwrap().push(failure)
+                }),
+        );
+
+        let ok_handle = client.add_event_handler(|_ev: OriginalSyncRoomMemberEvent| async {});
+        let error_handle =
+            client.add_event_handler(|_ev: OriginalSyncRoomMemberEvent| async { Err("oops") });
+        let panic_handle = client.add_event_handler(|_ev: OriginalSyncRoomMemberEvent| async {
+            panic!("boom");
+        });
+        // This one panics before returning its future.
+        let sync_panic_handle = client.add_event_handler(
+            |_ev: OriginalSyncRoomMemberEvent| -> std::future::Ready<()> { panic!("sync boom") },
+        );
+        let slow_handle = client.add_event_handler(|_ev: OriginalSyncRoomMemberEvent| async {
+            sleep(Duration::from_secs(10)).await;
+        });
+
+        let response = SyncResponseBuilder::default()
+            .add_joined_room(JoinedRoomBuilder::default().add_timeline_event(MEMBER_EVENT.clone()))
+            .build_sync_response();
+        client.process_sync(response).await?;
+
+        let mut failures = failures.lock().unwrap().clone();
+        failures.sort_by_key(|failure| failure.handle.handler_id);
+        assert_eq!(failures.len(), 4);
+
+        assert_eq!(failures[0].handle.handler_id, error_handle.handler_id);
+        assert_eq!(failures[0].event_type, "m.room.member");
+        assert_eq!(failures[0].room_id.as_deref(), Some(*DEFAULT_TEST_ROOM_ID));
+        assert_matches!(&failures[0].kind, EventHandlerFailureKind::Failed(message));
+        assert_eq!(message, "oops");
+        assert_eq!(failures[1].handle.handler_id, panic_handle.handler_id);
+        assert_matches!(&failures[1].kind, EventHandlerFailureKind::Panicked(message));
+        assert_eq!(message, "boom");
+        assert_eq!(failures[2].handle.handler_id, sync_panic_handle.handler_id);
+        assert_matches!(&failures[2].kind, EventHandlerFailureKind::Panicked(message));
+        assert_eq!(message, "sync boom");
+        assert_eq!(failures[3].handle.handler_id, slow_handle.handler_id);
+        assert_matches!(&failures[3].kind, EventHandlerFailureKind::TimedOut);
+
+        let metrics = client.event_handler_metrics(&ok_handle).unwrap();
+        assert_eq!(metrics.calls, 1);
+        assert_eq!(metrics.errors + metrics.panics + metrics.timeouts, 0);
+        assert_eq!(client.event_handler_metrics(&error_handle).unwrap().errors, 1);
+        assert_eq!(client.event_handler_metrics(&panic_handle).unwrap().panics, 1);
+        let metrics = client.event_handler_metrics(&slow_handle).unwrap();
+        assert_eq!(metrics.timeouts, 1);
+        assert!(metrics.max_duration >= Duration::from_millis(100));
+
+        client.remove_event_handler(slow_handle.clone());
+        assert!(client.event_handler_metrics(&slow_handle).is_none());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_event_handler_max_concurrency() -> crate::Result<()> {
+        let client = logged_in_client(None).await;
+        client.set_event_handler_supervision(
+            EventHandlerSupervision::new().max_concurrent_handlers(NonZeroUsize::MIN),
+        );
+
+        let running = Arc::new(AtomicU8::new(0));
+        let max_running = Arc::new(AtomicU8::new(0));
+        client.add_event_handler_context((running.clone(), max_running.clone()));
+
+        for _ in 0..3 {
+            client.add_event_handler(
+                |_ev: OriginalSyncRoomMemberEvent,
+                 counters: Ctx<(Arc<AtomicU8>, Arc<AtomicU8>)>| async move {
+                    let (running, max_running) = &*counters;
+                    let now_running = running.fetch_add(1, SeqCst) + 1;
+                    max_running.fetch_max(now_running, SeqCst);
+                    sleep(Duration::from_millis(10)).await;
+                    running.fetch_sub(1, SeqCst);
+                },
+            );
+        }
+
+        let response = SyncResponseBuilder::default()
+            .add_joined_room(JoinedRoomBuilder::default().add_timeline_event(MEMBER_EVENT.clone()))
+            .build_sync_response();
+        client.process_sync(response).await?;
+
+        assert_eq!(max_running.load(SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_custom_event_handler() -> crate::Result<()> {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct GameMove {
+            from: String,
+            to: String,
+        }
+
+        impl CustomEventContent for GameMove {
+            const TYPE: &'static str = "com.example.game.move";
+            const KIND: CustomEventKind = CustomEventKind::MessageLike;
+        }
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct GameBoard {
+            size: u8,
+        }
+
+        impl CustomEventContent for GameBoard {
+            const TYPE: &'static str = "com.example.game.board";
+            const KIND: CustomEventKind = CustomEventKind::State;
+        }
+
+        let client = logged_in_client(None).await;
+        let (moves_sender, mut moves) = tokio::sync::mpsc::unbounded_channel();
+        let (boards_sender, mut boards) = tokio::sync::mpsc::unbounded_channel();
+
+        client.add_event_handler(move |ev: CustomSyncEvent<GameMove>| {
+            moves_sender.send(ev).unwrap();
+            future::ready(())
+        });
+        client.add_event_handler(move |ev: CustomSyncEvent<GameBoard>| {
+            boards_sender.send(ev).unwrap();
+            future::ready(())
+        });
+
+        let response = SyncResponseBuilder::default()
+            .add_joined_room(
+                JoinedRoomBuilder::default()
+                    .add_timeline_event(
+                        Raw::new(&json!({
+                            "content": { "from": "e2", "to": "e4" },
+                            "event_id": "$move",
+                            "origin_server_ts": 152037280,
+                            "sender": "@example:localhost",
+                            "type": "com.example.game.move",
+                        }))
+                        .unwrap()
+                        .cast(),
+                    )
+                    .add_state_event(StateTestEvent::Custom(json!({
+                        "content": { "size": 8 },
+                        "event_id": "$board",
+                        "origin_server_ts": 152037280,
+                        "sender": "@example:localhost",
+                        "state_key": "",
+                        "type": "com.example.game.board",
+                    }))),
+            )
+            .build_sync_response();
+        client.process_sync(response).await?;
+
+        let game_move = moves.try_recv().unwrap();
+        assert_eq!(game_move.event_id, "$move");
+        assert_eq!(game_move.content, GameMove { from: "e2".to_owned(), to: "e4".to_owned() });
+        assert_eq!(game_move.state_key, None);
+        assert!(moves.try_recv().is_err());
+
+        let board = boards.try_recv().unwrap();
+        assert_eq!(board.content, GameBoard { size: 8 });
+        assert_eq!(board.state_key.as_deref(), Some(""));
+        assert!(boards.try_recv().is_err());
+
+        let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+        assert_eq!(
+            room.get_custom_state_content::<GameBoard>("").await?,
+            Some(GameBoard { size: 8 })
+        );
+        assert_eq!(room.get_custom_state_content::<GameBoard>("other").await?, None);
+
+        Ok(())
+    }
+
     #[async_test]
     #[allow(dependency_on_unit_never_type_fallback)]
     async fn test_observe_events() -> crate::Result<()> {
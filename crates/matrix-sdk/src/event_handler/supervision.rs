@@ -0,0 +1,305 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt,
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::FutureExt;
+use matrix_sdk_base::{SendOutsideWasm, SyncOutsideWasm};
+use matrix_sdk_common::timeout::timeout;
+use ruma::{time::Instant, OwnedRoomId};
+use tracing::error;
+
+use super::{EventHandlerFut, EventHandlerHandle};
+
+#[cfg(not(target_arch = "wasm32"))]
+type FailureCallback = dyn Fn(EventHandlerFailure) + Send + Sync;
+#[cfg(target_arch = "wasm32")]
+type FailureCallback = dyn Fn(EventHandlerFailure);
+
+/// How the event handlers of a [`Client`] are run.
+///
+/// By default, the event handlers of an event all run concurrently, without a
+/// timeout, and a panic in one of them is propagated to the sync loop.
+///
+/// Set it with [`Client::set_event_handler_supervision()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use matrix_sdk::{event_handler::EventHandlerSupervision, Client};
+///
+/// # async fn example(client: Client) {
+/// client.set_event_handler_supervision(
+///     EventHandlerSupervision::new()
+///         .timeout(Duration::from_secs(10))
+///         .catch_panics(true)
+///         .on_failure(|failure| {
+///             eprintln!(
+///                 "Event handler for `{}` failed: {:?}",
+///                 failure.event_type, failure.kind
+///             );
+///         }),
+/// );
+/// # }
+/// ```
+///
+/// [`Client`]: crate::Client
+/// [`Client::set_event_handler_supervision()`]: crate::Client::set_event_handler_supervision
+#[derive(Clone, Default)]
+pub struct EventHandlerSupervision {
+    timeout: Option<Duration>,
+    catch_panics: bool,
+    max_concurrent_handlers: Option<NonZeroUsize>,
+    on_failure: Option<Arc<FailureCallback>>,
+}
+
+impl EventHandlerSupervision {
+    /// Create a new `EventHandlerSupervision` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum time an event handler can run for a single event.
+    ///
+    /// When it is reached, the event handler is cancelled, which means that
+    /// its future is dropped.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Whether panics in event handlers should be caught.
+    ///
+    /// When they are caught, they are logged and reported as failures instead
+    /// of unwinding through the sync loop.
+    pub fn catch_panics(mut self, catch_panics: bool) -> Self {
+        self.catch_panics = catch_panics;
+        self
+    }
+
+    /// Set the maximum number of event handlers that can run concurrently for
+    /// a single event.
+    ///
+    /// Setting it to 1 runs the event handlers one after the other.
+    pub fn max_concurrent_handlers(mut self, max: NonZeroUsize) -> Self {
+        self.max_concurrent_handlers = Some(max);
+        self
+    }
+
+    /// Set a callback called every time an event handler fails.
+    pub fn on_failure(
+        mut self,
+        callback: impl Fn(EventHandlerFailure) + SendOutsideWasm + SyncOutsideWasm + 'static,
+    ) -> Self {
+        self.on_failure = Some(Arc::new(callback));
+        self
+    }
+
+    /// The maximum number of event handlers to run concurrently, given the
+    /// number of event handlers to run.
+    pub(super) fn concurrency(&self, handlers: usize) -> usize {
+        self.max_concurrent_handlers.map_or(handlers, NonZeroUsize::get).max(1)
+    }
+
+    /// Call an event handler and run its future, and report how it went.
+    pub(super) async fn run(
+        &self,
+        handler: impl FnOnce() -> EventHandlerFut,
+        handle: EventHandlerHandle,
+        event_type: &str,
+        room_id: Option<OwnedRoomId>,
+        metrics: &EventHandlerMetricsStore,
+    ) {
+        let start = Instant::now();
+
+        let run = async {
+            if self.catch_panics {
+                // The event handler can panic when it is called, before returning its future.
+                let result = match panic::catch_unwind(AssertUnwindSafe(handler)) {
+                    Ok(future) => AssertUnwindSafe(future).catch_unwind().await,
+                    Err(panic) => Err(panic),
+                };
+
+                match result {
+                    Ok(result) => result.map_err(EventHandlerFailureKind::Failed),
+                    Err(panic) => Err(EventHandlerFailureKind::Panicked(panic_message(&*panic))),
+                }
+            } else {
+                handler().await.map_err(EventHandlerFailureKind::Failed)
+            }
+        };
+
+        let result = match self.timeout {
+            Some(duration) => {
+                timeout(run, duration).await.unwrap_or(Err(EventHandlerFailureKind::TimedOut))
+            }
+            None => run.await,
+        };
+
+        metrics.record(handle.handler_id, start.elapsed(), result.as_ref().err());
+
+        let Err(kind) = result else {
+            return;
+        };
+
+        match &kind {
+            // The error was already logged by the event handler.
+            EventHandlerFailureKind::Failed(_) => {}
+            EventHandlerFailureKind::Panicked(message) => {
+                error!(event_type, "Event handler panicked: {message}");
+            }
+            EventHandlerFailureKind::TimedOut => {
+                error!(event_type, "Event handler timed out after {:?}", self.timeout);
+            }
+        }
+
+        if let Some(on_failure) = &self.on_failure {
+            on_failure(EventHandlerFailure {
+                handle,
+                event_type: event_type.to_owned(),
+                room_id,
+                kind,
+            });
+        }
+    }
+}
+
+impl fmt::Debug for EventHandlerSupervision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventHandlerSupervision")
+            .field("timeout", &self.timeout)
+            .field("catch_panics", &self.catch_panics)
+            .field("max_concurrent_handlers", &self.max_concurrent_handlers)
+            .finish_non_exhaustive()
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+/// A failure of an event handler, reported to the callback set with
+/// [`EventHandlerSupervision::on_failure()`].
+#[derive(Clone, Debug)]
+pub struct EventHandlerFailure {
+    /// The handle of the event handler that failed.
+    pub handle: EventHandlerHandle,
+
+    /// The type of the event that was handled.
+    pub event_type: String,
+
+    /// The room of the event that was handled, if any.
+    pub room_id: Option<OwnedRoomId>,
+
+    /// How the event handler failed.
+    pub kind: EventHandlerFailureKind,
+}
+
+/// How an event handler failed.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum EventHandlerFailureKind {
+    /// The event handler returned an error, with this message.
+    Failed(String),
+
+    /// The event handler panicked, with this message.
+    Panicked(String),
+
+    /// The event handler reached the timeout and was cancelled.
+    TimedOut,
+}
+
+/// Metrics about the execution of an event handler.
+///
+/// Get them with [`Client::event_handler_metrics()`].
+///
+/// [`Client::event_handler_metrics()`]: crate::Client::event_handler_metrics
+#[derive(Clone, Debug, Default)]
+pub struct EventHandlerMetrics {
+    /// The number of times the event handler was called.
+    pub calls: u64,
+
+    /// The number of times the event handler returned an error.
+    pub errors: u64,
+
+    /// The number of times the event handler panicked.
+    pub panics: u64,
+
+    /// The number of times the event handler timed out.
+    pub timeouts: u64,
+
+    /// The total time spent running the event handler.
+    pub total_duration: Duration,
+
+    /// The longest time spent running the event handler for a single event.
+    pub max_duration: Duration,
+}
+
+/// The metrics of the event handlers, keyed by handler ID.
+#[derive(Debug, Default)]
+pub(super) struct EventHandlerMetricsStore(Mutex<BTreeMap<u64, EventHandlerMetrics>>);
+
+impl EventHandlerMetricsStore {
+    pub(super) fn add(&self, handler_id: u64) {
+        self.0.lock().unwrap().insert(handler_id, EventHandlerMetrics::default());
+    }
+
+    pub(super) fn remove(&self, handler_id: u64) {
+        self.0.lock().unwrap().remove(&handler_id);
+    }
+
+    pub(super) fn get(&self, handler_id: u64) -> Option<EventHandlerMetrics> {
+        self.0.lock().unwrap().get(&handler_id).cloned()
+    }
+
+    fn record(
+        &self,
+        handler_id: u64,
+        duration: Duration,
+        failure: Option<&EventHandlerFailureKind>,
+    ) {
+        // The event handler might have been removed while it was running.
+        let mut metrics = self.0.lock().unwrap();
+        let Some(metrics) = metrics.get_mut(&handler_id) else {
+            return;
+        };
+
+        metrics.calls += 1;
+        metrics.total_duration += duration;
+        metrics.max_duration = metrics.max_duration.max(duration);
+
+        match failure {
+            Some(EventHandlerFailureKind::Failed(_)) => metrics.errors += 1,
+            Some(EventHandlerFailureKind::Panicked(_)) => metrics.panics += 1,
+            Some(EventHandlerFailureKind::TimedOut) => metrics.timeouts += 1,
+            None => {}
+        }
+    }
+}
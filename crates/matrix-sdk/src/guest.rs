@@ -0,0 +1,469 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Guest access.
+//!
+//! A guest account is a temporary account that doesn't need any credentials,
+//! registered with [`Client::register_guest()`]. Guests can peek into the
+//! rooms whose history is world-readable with [`Client::peek_room()`], and
+//! read their timeline by back-paginating with the event cache, without
+//! joining them.
+//!
+//! A guest account can be upgraded to a full account with
+//! [`Client::upgrade_guest()`]. The user ID and device ID are kept, so the
+//! local data of the client stays valid.
+//!
+//! Whether the session belongs to a guest account is saved in the state store,
+//! so it's known again when the session is restored.
+
+use std::sync::atomic::Ordering;
+
+use matrix_sdk_base::{RoomInfo, RoomInfoNotableUpdateReasons, RoomState, StateChanges};
+use ruma::{
+    api::client::{
+        account::register::{self, RegistrationKind},
+        state::get_state_events,
+        uiaa,
+    },
+    assign,
+    events::room::history_visibility::HistoryVisibility,
+    OwnedRoomId, RoomId, RoomOrAliasId,
+};
+use thiserror::Error;
+use tracing::{instrument, warn};
+
+use crate::{authentication::SessionTokens, Client, Error, Result, Room, SessionChange};
+
+/// The key of the custom value of the state store that is set when the
+/// session belongs to a guest account.
+const IS_GUEST_KEY: &str = "is_guest";
+
+/// An error specific to guest access.
+#[derive(Debug, Error)]
+pub enum GuestError {
+    /// The current session doesn't belong to a guest account.
+    #[error("the current session doesn't belong to a guest account")]
+    NotGuest,
+
+    /// The history of the room isn't world-readable, so it can't be peeked
+    /// into.
+    #[error("the history of the room {0} isn't world-readable")]
+    NotWorldReadable(OwnedRoomId),
+
+    /// The homeserver upgraded the guest account to a different user ID or
+    /// didn't return an access token.
+    #[error("the homeserver didn't upgrade the guest session in place")]
+    UnexpectedUpgradeResponse,
+}
+
+impl Client {
+    /// Register a new guest account and log into it.
+    ///
+    /// The homeserver must allow guest access. A guest account can read the
+    /// rooms that are world-readable, see [`Client::peek_room()`], and only
+    /// do a limited set of actions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a session was already restored or logged in.
+    #[instrument(skip_all)]
+    pub async fn register_guest(&self) -> Result<()> {
+        let request = assign!(register::v3::Request::new(), { kind: RegistrationKind::Guest });
+        self.matrix_auth().register(request).await?;
+
+        if self.session_meta().is_none() {
+            return Err(Error::AuthenticationRequired);
+        }
+
+        self.state_store().set_custom_value(IS_GUEST_KEY.as_bytes(), vec![1]).await?;
+        self.auth_ctx().is_guest.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether the current session belongs to a guest account.
+    ///
+    /// This is known for sessions created with [`Client::register_guest()`],
+    /// and for the sessions restored with the same store. For other sessions,
+    /// it's only known after [`Client::whoami()`] was called.
+    pub fn is_guest(&self) -> bool {
+        self.auth_ctx().is_guest.load(Ordering::SeqCst)
+    }
+
+    /// Load whether the restored session belongs to a guest account from the
+    /// state store.
+    pub(crate) async fn restore_is_guest(&self) -> Result<()> {
+        let is_guest =
+            self.state_store().get_custom_value(IS_GUEST_KEY.as_bytes()).await?.is_some();
+        self.auth_ctx().is_guest.store(is_guest, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Peek into a room the user isn't a member of.
+    ///
+    /// The state of the room is fetched from the homeserver. If the room wasn't
+    /// known before, it's kept in memory only: it isn't saved in the store nor
+    /// added to the list of rooms, but it can be retrieved with
+    /// [`Client::get_room()`] until the client is dropped. Its timeline can be
+    /// loaded by back-paginating with [`Room::event_cache()`].
+    ///
+    /// This works for any user, not only guests, as long as the history of
+    /// the room is world-readable.
+    #[instrument(skip(self))]
+    pub async fn peek_room(&self, room: &RoomOrAliasId) -> Result<Room> {
+        let room_id = match <&RoomId>::try_from(room) {
+            Ok(room_id) => room_id.to_owned(),
+            Err(alias) => self.resolve_room_alias(alias).await?.room_id,
+        };
+
+        let request = get_state_events::v3::Request::new(room_id.clone());
+        let response = self.send(request).await?;
+
+        let _sync_lock = self.base_client().sync_lock().lock().await;
+
+        let known_room = self.base_client().get_room(&room_id);
+        let mut room_info = match &known_room {
+            Some(room) => room.clone_info(),
+            // The room state only matters for the rooms in the store, the room won't be added to
+            // them.
+            None => RoomInfo::new(&room_id, RoomState::Left),
+        };
+
+        for event in response.room_state {
+            match event.deserialize() {
+                Ok(event) => {
+                    room_info.handle_state_event(&event.into());
+                }
+                Err(error) => warn!("Failed to deserialize state event: {error}"),
+            }
+        }
+
+        let Some(base_room) = known_room else {
+            if *room_info.history_visibility_or_default() != HistoryVisibility::WorldReadable {
+                return Err(GuestError::NotWorldReadable(room_id).into());
+            }
+
+            // The user isn't a member of the room, so it must not be saved with their
+            // rooms.
+            let base_room = self.base_client().create_detached_room(room_info);
+            self.inner.peeked_rooms.write().unwrap().insert(room_id, base_room.clone());
+
+            return Ok(Room::new(self.clone(), base_room));
+        };
+
+        let mut changes = StateChanges::default();
+        changes.add_room(room_info.clone());
+        self.state_store().save_changes(&changes).await?;
+
+        base_room.set_room_info(room_info, RoomInfoNotableUpdateReasons::empty());
+        self.inner.peeked_rooms.write().unwrap().remove(&room_id);
+
+        Ok(Room::new(self.clone(), base_room))
+    }
+
+    /// Upgrade the current guest account to a full account, with the given
+    /// password.
+    ///
+    /// The user ID and the device ID of the session are kept, so all the local
+    /// data of the client stays valid. Only the session tokens change, and the
+    /// new ones are notified like refreshed tokens, with
+    /// [`SessionChange::TokensRefreshed`].
+    ///
+    /// The homeserver might require User-Interactive Authentication, in which
+    /// case the error contains the UIAA info, and the request must be retried
+    /// with the `auth` data.
+    #[instrument(skip_all)]
+    pub async fn upgrade_guest(&self, password: &str, auth: Option<uiaa::AuthData>) -> Result<()> {
+        if !self.is_guest() {
+            return Err(GuestError::NotGuest.into());
+        }
+
+        let session = self.matrix_auth().session().ok_or(Error::AuthenticationRequired)?;
+
+        let request = upgrade_guest::Request {
+            username: session.meta.user_id.localpart().to_owned(),
+            password: password.to_owned(),
+            device_id: session.meta.device_id.clone(),
+            auth,
+            guest_access_token: session.tokens.access_token,
+        };
+        let response = self.send(request).await?;
+
+        let Some(access_token) = response.access_token else {
+            return Err(GuestError::UnexpectedUpgradeResponse.into());
+        };
+
+        if response.user_id != session.meta.user_id {
+            return Err(GuestError::UnexpectedUpgradeResponse.into());
+        }
+
+        self.auth_ctx().set_session_tokens(SessionTokens {
+            access_token,
+            refresh_token: response.refresh_token,
+        });
+        self.auth_ctx().is_guest.store(false, Ordering::SeqCst);
+        if let Err(error) = self.state_store().remove_custom_value(IS_GUEST_KEY.as_bytes()).await {
+            warn!("Failed to save that the session doesn't belong to a guest anymore: {error}");
+        }
+
+        if let Some(save_session_callback) = self.auth_ctx().save_session_callback.get() {
+            if let Err(error) = save_session_callback(self.clone()) {
+                warn!("Failed to save the session after upgrading the guest account: {error}");
+            }
+        }
+
+        _ = self.auth_ctx().session_change_sender.send(SessionChange::TokensRefreshed);
+
+        Ok(())
+    }
+}
+
+mod upgrade_guest {
+    //! `POST /_matrix/client/*/register`, with the `guest_access_token` field
+    //! that homeservers use to upgrade a guest account in place.
+    //!
+    //! The field isn't part of the register request of Ruma.
+
+    use ruma::{
+        api::{client::uiaa, request, response, Metadata},
+        metadata, OwnedDeviceId, OwnedUserId,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: POST,
+        rate_limited: true,
+        authentication: None,
+        history: {
+            1.0 => "/_matrix/client/r0/register",
+            1.1 => "/_matrix/client/v3/register",
+        }
+    };
+
+    /// Request type for the `upgrade_guest` endpoint.
+    #[request(error = uiaa::UiaaResponse)]
+    pub struct Request {
+        /// The localpart of the guest account.
+        pub username: String,
+
+        /// The password of the full account.
+        pub password: String,
+
+        /// The device ID of the guest session, to keep it.
+        pub device_id: OwnedDeviceId,
+
+        /// The data for User-Interactive Authentication.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub auth: Option<uiaa::AuthData>,
+
+        /// The access token of the guest session.
+        pub guest_access_token: String,
+    }
+
+    /// Response type for the `upgrade_guest` endpoint.
+    #[response(error = uiaa::UiaaResponse)]
+    pub struct Response {
+        /// The user ID of the upgraded account.
+        pub user_id: OwnedUserId,
+
+        /// The new access token of the session.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub access_token: Option<String>,
+
+        /// The new refresh token of the session, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub refresh_token: Option<String>,
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches2::assert_matches;
+    use matrix_sdk_base::store::{MemoryStore, RoomLoadSettings, StoreConfig};
+    use matrix_sdk_test::async_test;
+    use ruma::{room_id, user_id, RoomOrAliasId};
+    use serde_json::{json, Value as JsonValue};
+    use wiremock::{
+        matchers::{body_partial_json, method, path, query_param},
+        Mock, ResponseTemplate,
+    };
+
+    use super::GuestError;
+    use crate::{test_utils::mocks::MatrixMockServer, Error};
+
+    fn state_event(event_type: &str, content: JsonValue) -> JsonValue {
+        json!({
+            "type": event_type,
+            "state_key": "",
+            "content": content,
+            "event_id": format!("${event_type}"),
+            "room_id": "!room:localhost",
+            "sender": "@alice:localhost",
+            "origin_server_ts": 0,
+        })
+    }
+
+    #[async_test]
+    async fn test_register_and_upgrade_guest() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().unlogged().build().await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/register"))
+            .and(query_param("kind", "guest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@123:localhost",
+                "device_id": "GUESTDEVICE",
+                "access_token": "guest_token",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        client.register_guest().await.unwrap();
+        assert!(client.is_guest());
+        assert_eq!(client.user_id(), Some(user_id!("@123:localhost")));
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/register"))
+            .and(body_partial_json(json!({
+                "username": "123",
+                "password": "hunter2",
+                "device_id": "GUESTDEVICE",
+                "guest_access_token": "guest_token",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@123:localhost",
+                "device_id": "GUESTDEVICE",
+                "access_token": "full_token",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        client.upgrade_guest("hunter2", None).await.unwrap();
+        assert!(!client.is_guest());
+        assert_eq!(client.user_id(), Some(user_id!("@123:localhost")));
+        assert_eq!(client.access_token().as_deref(), Some("full_token"));
+
+        // The account isn't a guest anymore.
+        assert_matches!(
+            client.upgrade_guest("hunter2", None).await,
+            Err(Error::Guest(GuestError::NotGuest))
+        );
+    }
+
+    #[async_test]
+    async fn test_guest_session_is_restored() {
+        let server = MatrixMockServer::new().await;
+        let state_store = Arc::new(MemoryStore::new());
+        let store_config =
+            || StoreConfig::new("holder".to_owned()).state_store(state_store.clone());
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/register"))
+            .and(query_param("kind", "guest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@123:localhost",
+                "device_id": "GUESTDEVICE",
+                "access_token": "guest_token",
+            })))
+            .mount(server.server())
+            .await;
+
+        let client = server.client_builder().unlogged().store_config(store_config()).build().await;
+        client.register_guest().await.unwrap();
+        let session = client.matrix_auth().session().unwrap();
+        drop(client);
+
+        // The guest flag is restored with the session.
+        let client = server.client_builder().unlogged().store_config(store_config()).build().await;
+        client.restore_session(session).await.unwrap();
+        assert!(client.is_guest());
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/register"))
+            .and(body_partial_json(json!({ "guest_access_token": "guest_token" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@123:localhost",
+                "device_id": "GUESTDEVICE",
+                "access_token": "full_token",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        client.upgrade_guest("hunter2", None).await.unwrap();
+        let session = client.matrix_auth().session().unwrap();
+        drop(client);
+
+        // The upgraded session isn't restored as a guest session.
+        let client = server.client_builder().unlogged().store_config(store_config()).build().await;
+        client.restore_session(session).await.unwrap();
+        assert!(!client.is_guest());
+    }
+
+    #[async_test]
+    async fn test_peek_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/rooms/!room:localhost/state"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                state_event("m.room.name", json!({ "name": "Release notes" })),
+                state_event(
+                    "m.room.history_visibility",
+                    json!({ "history_visibility": "world_readable" })
+                ),
+            ])))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let room = client.peek_room(<&RoomOrAliasId>::from(room_id)).await.unwrap();
+
+        assert_eq!(room.name().as_deref(), Some("Release notes"));
+        assert!(client.get_room(room_id).is_some());
+
+        // The room isn't saved with the rooms of the user.
+        assert!(client.rooms().is_empty());
+        let room_infos =
+            client.state_store().get_room_infos(&RoomLoadSettings::default()).await.unwrap();
+        assert!(room_infos.is_empty());
+    }
+
+    #[async_test]
+    async fn test_peek_room_not_world_readable() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/rooms/!room:localhost/state"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([state_event(
+                "m.room.history_visibility",
+                json!({ "history_visibility": "shared" })
+            )])))
+            .mount(server.server())
+            .await;
+
+        assert_matches!(
+            client.peek_room(<&RoomOrAliasId>::from(room_id)).await,
+            Err(Error::Guest(GuestError::NotWorldReadable(_)))
+        );
+        assert!(client.get_room(room_id).is_none());
+    }
+}
@@ -0,0 +1,473 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migration of an account to another homeserver.
+//!
+//! [`Client::migrate_to()`] helps a user moving to a new account on another
+//! homeserver, by re-creating as much as possible of the old account on the
+//! new one:
+//!
+//! 1. the global account data that doesn't depend on the homeserver, like the
+//!    direct rooms and the ignored users, are copied,
+//! 2. the push rules defined by the user are copied, and the server-default
+//!    push rules are enabled or disabled like on the old account,
+//! 3. the new account joins the public rooms of the old account. For the other
+//!    rooms, the old account invites the new one if it's allowed to, or a
+//!    [`MigrationInviteHook`] is asked to get the new account invited, e.g. by
+//!    a helper bot. The tags of the joined rooms are copied,
+//! 4. the room keys of the old account are imported into the new one, so the
+//!    history of the encrypted rooms can be decrypted.
+//!
+//! Both clients must be logged in. The migration continues when a room can't
+//! be migrated: the failures are listed in the [`MigrationReport`].
+
+use std::{fmt, future::IntoFuture, sync::Arc};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    api::client::push::{set_pushrule, set_pushrule_enabled},
+    events::GlobalAccountDataEventType,
+    push::{NewConditionalPushRule, NewPatternedPushRule, NewPushRule, NewSimplePushRule, Ruleset},
+    OwnedRoomId, OwnedServerName, RoomId, UserId,
+};
+use tracing::{debug, instrument, warn};
+
+use crate::{Client, Error, Result, Room};
+
+/// The global account data copied by [`Client::migrate_to()`].
+const GLOBAL_ACCOUNT_DATA_TYPES: &[GlobalAccountDataEventType] =
+    &[GlobalAccountDataEventType::Direct, GlobalAccountDataEventType::IgnoredUserList];
+
+/// A hook called by [`Client::migrate_to()`] for the rooms the new account
+/// can't join by itself.
+pub trait MigrationInviteHook: fmt::Debug + Send + Sync {
+    /// Request an invite for the user of the new account in the given room.
+    ///
+    /// This is called for the rooms which aren't public, and in which the old
+    /// account isn't allowed to invite the new one, e.g. to let a helper bot
+    /// with enough power in the room send the invite.
+    fn request_invite(&self, room_id: &RoomId, user_id: &UserId);
+}
+
+/// The progress of a [`HomeserverMigration`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MigrationProgress {
+    /// The migration hasn't started yet.
+    #[default]
+    Created,
+
+    /// The global account data is being copied.
+    CopyingAccountData,
+
+    /// The push rules are being copied.
+    CopyingPushRules,
+
+    /// The rooms are being migrated.
+    MigratingRooms {
+        /// The number of rooms already handled.
+        migrated: usize,
+
+        /// The total number of rooms to migrate.
+        total: usize,
+    },
+
+    /// The room keys are being imported into the new account.
+    ImportingRoomKeys,
+
+    /// The migration is finished.
+    Done,
+}
+
+/// A summary of what [`Client::migrate_to()`] did.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// The types of the global account data copied to the new account.
+    pub copied_account_data: Vec<GlobalAccountDataEventType>,
+
+    /// The number of push rules copied to the new account, or whose enabled
+    /// state was updated.
+    pub num_push_rules: usize,
+
+    /// The rooms joined by the new account.
+    pub joined_rooms: Vec<OwnedRoomId>,
+
+    /// The rooms for which an invite was requested with the
+    /// [`MigrationInviteHook`].
+    pub requested_invites: Vec<OwnedRoomId>,
+
+    /// The rooms that couldn't be migrated, with the reason why.
+    pub failed_rooms: Vec<(OwnedRoomId, String)>,
+
+    /// The number of room keys imported into the new account, out of the
+    /// number of room keys of the old account.
+    #[cfg(feature = "e2e-encryption")]
+    pub num_imported_room_keys: (usize, usize),
+}
+
+/// What happened to a room of the old account.
+enum RoomMigration {
+    Joined,
+    InviteRequested,
+    CantBeInvited,
+}
+
+impl Client {
+    /// Migrate the account of this client to the account of the `target`
+    /// client, on another homeserver.
+    ///
+    /// See the [module documentation](crate::homeserver_migration) for the
+    /// details of what's migrated.
+    pub fn migrate_to<'a>(&'a self, target: &'a Client) -> HomeserverMigration<'a> {
+        HomeserverMigration {
+            source: self,
+            target,
+            invite_hook: None,
+            #[cfg(feature = "e2e-encryption")]
+            import_room_keys: true,
+            progress: Default::default(),
+        }
+    }
+}
+
+/// Named future for [`Client::migrate_to()`].
+#[derive(Debug)]
+pub struct HomeserverMigration<'a> {
+    source: &'a Client,
+    target: &'a Client,
+    invite_hook: Option<Arc<dyn MigrationInviteHook>>,
+    #[cfg(feature = "e2e-encryption")]
+    import_room_keys: bool,
+    progress: SharedObservable<MigrationProgress>,
+}
+
+impl HomeserverMigration<'_> {
+    /// Set the hook requesting invites for the rooms the new account can't
+    /// join by itself.
+    ///
+    /// Without a hook, these rooms are listed in
+    /// [`MigrationReport::failed_rooms`].
+    pub fn with_invite_hook(mut self, hook: Arc<dyn MigrationInviteHook>) -> Self {
+        self.invite_hook = Some(hook);
+        self
+    }
+
+    /// Don't import the room keys of the old account into the new one.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn without_room_keys(mut self) -> Self {
+        self.import_room_keys = false;
+        self
+    }
+
+    /// Subscribe to the progress of the migration.
+    pub fn subscribe_to_progress(&self) -> Subscriber<MigrationProgress> {
+        self.progress.subscribe()
+    }
+
+    async fn copy_account_data(&self, report: &mut MigrationReport) -> Result<()> {
+        for event_type in GLOBAL_ACCOUNT_DATA_TYPES {
+            if let Some(content) =
+                self.source.account().account_data_raw(event_type.clone()).await?
+            {
+                self.target.account().set_account_data_raw(event_type.clone(), content).await?;
+                report.copied_account_data.push(event_type.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn copy_push_rules(&self, report: &mut MigrationReport) -> Result<()> {
+        let target_user_id = self.target.user_id().ok_or(Error::AuthenticationRequired)?;
+        let ruleset = self.source.account().push_rules().await?;
+        let server_default = Ruleset::server_default(target_user_id);
+
+        for (rule, enabled) in user_defined_push_rules(&ruleset) {
+            let kind = rule.kind();
+            let rule_id = rule.rule_id().to_owned();
+
+            self.target.send(set_pushrule::v3::Request::new(rule)).await?;
+
+            if !enabled {
+                self.target
+                    .send(set_pushrule_enabled::v3::Request::new(kind, rule_id, false))
+                    .await?;
+            }
+
+            report.num_push_rules += 1;
+        }
+
+        // Mirror the enabled state of the server-default push rules.
+        for rule in ruleset.iter().filter(|rule| rule.is_server_default()) {
+            let rule_id = rule.rule_id();
+            let Some(default_rule) = server_default.get(rule.kind(), rule_id) else {
+                continue;
+            };
+
+            if default_rule.enabled() != rule.enabled() {
+                self.target
+                    .send(set_pushrule_enabled::v3::Request::new(
+                        rule.kind(),
+                        rule_id.to_owned(),
+                        rule.enabled(),
+                    ))
+                    .await?;
+                report.num_push_rules += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_room(&self, room: &Room) -> Result<RoomMigration> {
+        let source_user_id = self.source.user_id().ok_or(Error::AuthenticationRequired)?;
+        let target_user_id = self.target.user_id().ok_or(Error::AuthenticationRequired)?;
+
+        let server_names: Vec<OwnedServerName> = room
+            .room_id()
+            .server_name()
+            .into_iter()
+            .chain([source_user_id.server_name()])
+            .map(ToOwned::to_owned)
+            .collect();
+
+        if !room.is_public() {
+            if room.can_user_invite(source_user_id).await? {
+                room.invite_user_by_id(target_user_id).await?;
+            } else if let Some(hook) = &self.invite_hook {
+                hook.request_invite(room.room_id(), target_user_id);
+                return Ok(RoomMigration::InviteRequested);
+            } else {
+                return Ok(RoomMigration::CantBeInvited);
+            }
+        }
+
+        let target_room =
+            self.target.join_room_by_id_or_alias(room.room_id().into(), &server_names).await?;
+
+        for (tag, tag_info) in room.tags().await?.unwrap_or_default() {
+            target_room.set_tag(tag, tag_info).await?;
+        }
+
+        Ok(RoomMigration::Joined)
+    }
+
+    async fn migrate_rooms(&self, report: &mut MigrationReport) {
+        let rooms = self.source.joined_rooms();
+        let total = rooms.len();
+
+        for (migrated, room) in rooms.into_iter().enumerate() {
+            self.progress.set(MigrationProgress::MigratingRooms { migrated, total });
+
+            let room_id = room.room_id().to_owned();
+
+            match self.migrate_room(&room).await {
+                Ok(RoomMigration::Joined) => report.joined_rooms.push(room_id),
+                Ok(RoomMigration::InviteRequested) => report.requested_invites.push(room_id),
+                Ok(RoomMigration::CantBeInvited) => {
+                    debug!(%room_id, "Can't get the new account in the room");
+                    report.failed_rooms.push((
+                        room_id,
+                        "the room isn't public, and the new account can't be invited".to_owned(),
+                    ));
+                }
+                Err(err) => {
+                    warn!(%room_id, "Failed to migrate room: {err}");
+                    report.failed_rooms.push((room_id, err.to_string()));
+                }
+            }
+        }
+
+        self.progress.set(MigrationProgress::MigratingRooms { migrated: total, total });
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn import_room_keys(&self, report: &mut MigrationReport) -> Result<()> {
+        let keys = {
+            let olm = self.source.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.store().export_room_keys(|_| true).await?
+        };
+
+        let result = {
+            let olm = self.target.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.store().import_exported_room_keys(keys, |_, _| {}).await?
+        };
+
+        self.target.encryption().backups().maybe_trigger_backup();
+
+        report.num_imported_room_keys = (result.imported_count, result.total_count);
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoFuture for HomeserverMigration<'a> {
+    type Output = Result<MigrationReport>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { self.run().await })
+    }
+}
+
+impl HomeserverMigration<'_> {
+    #[instrument(skip_all)]
+    async fn run(self) -> Result<MigrationReport> {
+        let mut report = MigrationReport::default();
+
+        self.progress.set(MigrationProgress::CopyingAccountData);
+        self.copy_account_data(&mut report).await?;
+
+        self.progress.set(MigrationProgress::CopyingPushRules);
+        self.copy_push_rules(&mut report).await?;
+
+        self.migrate_rooms(&mut report).await;
+
+        #[cfg(feature = "e2e-encryption")]
+        if self.import_room_keys {
+            self.progress.set(MigrationProgress::ImportingRoomKeys);
+            self.import_room_keys(&mut report).await?;
+        }
+
+        self.progress.set(MigrationProgress::Done);
+
+        Ok(report)
+    }
+}
+
+/// The push rules defined by the user, with their enabled state, from the
+/// lowest to the highest priority within each kind.
+///
+/// They're returned in this order so that inserting them one after the other,
+/// each at the highest priority of its kind, keeps their relative priority.
+fn user_defined_push_rules(ruleset: &Ruleset) -> Vec<(NewPushRule, bool)> {
+    let mut rules = Vec::new();
+
+    for rule in ruleset.override_.iter().rev().filter(|rule| !rule.default) {
+        let new_rule = NewConditionalPushRule::new(
+            rule.rule_id.clone(),
+            rule.conditions.clone(),
+            rule.actions.clone(),
+        );
+        rules.push((NewPushRule::Override(new_rule), rule.enabled));
+    }
+
+    for rule in ruleset.content.iter().rev().filter(|rule| !rule.default) {
+        let new_rule = NewPatternedPushRule::new(
+            rule.rule_id.clone(),
+            rule.pattern.clone(),
+            rule.actions.clone(),
+        );
+        rules.push((NewPushRule::Content(new_rule), rule.enabled));
+    }
+
+    for rule in ruleset.room.iter().rev().filter(|rule| !rule.default) {
+        let new_rule = NewSimplePushRule::new(rule.rule_id.clone(), rule.actions.clone());
+        rules.push((NewPushRule::Room(new_rule), rule.enabled));
+    }
+
+    for rule in ruleset.sender.iter().rev().filter(|rule| !rule.default) {
+        let new_rule = NewSimplePushRule::new(rule.rule_id.clone(), rule.actions.clone());
+        rules.push((NewPushRule::Sender(new_rule), rule.enabled));
+    }
+
+    for rule in ruleset.underride.iter().rev().filter(|rule| !rule.default) {
+        let new_rule = NewConditionalPushRule::new(
+            rule.rule_id.clone(),
+            rule.conditions.clone(),
+            rule.actions.clone(),
+        );
+        rules.push((NewPushRule::Underride(new_rule), rule.enabled));
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{
+        owned_room_id, owned_user_id,
+        push::{Action, NewPatternedPushRule, NewPushRule, NewSimplePushRule, RuleKind, Ruleset},
+        user_id,
+    };
+
+    use super::user_defined_push_rules;
+
+    #[test]
+    fn test_user_defined_push_rules() {
+        let mut ruleset = Ruleset::server_default(user_id!("@alice:example.org"));
+
+        ruleset
+            .insert(
+                NewPushRule::Content(NewPatternedPushRule::new(
+                    "first".to_owned(),
+                    "first".to_owned(),
+                    vec![Action::Notify],
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+        ruleset
+            .insert(
+                NewPushRule::Content(NewPatternedPushRule::new(
+                    "second".to_owned(),
+                    "second".to_owned(),
+                    vec![Action::Notify],
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+        ruleset
+            .insert(
+                NewPushRule::Room(NewSimplePushRule::new(
+                    owned_room_id!("!room:example.org"),
+                    vec![],
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+        ruleset
+            .insert(
+                NewPushRule::Sender(NewSimplePushRule::new(
+                    owned_user_id!("@bob:example.org"),
+                    vec![],
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+        ruleset.set_enabled(RuleKind::Sender, "@bob:example.org", false).unwrap();
+
+        let rules = user_defined_push_rules(&ruleset);
+        let rules =
+            rules.iter().map(|(rule, enabled)| (rule.rule_id(), *enabled)).collect::<Vec<_>>();
+
+        // The server-default rules are skipped, and the rules are in reverse priority
+        // order within each kind: "second" has a higher priority than "first".
+        assert_eq!(
+            rules,
+            [
+                ("first", true),
+                ("second", true),
+                ("!room:example.org", true),
+                ("@bob:example.org", false),
+            ]
+        );
+    }
+}
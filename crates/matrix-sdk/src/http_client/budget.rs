@@ -0,0 +1,164 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use ruma::time::Instant;
+
+use super::{MaybeSemaphore, MaybeSemaphorePermit};
+use crate::config::{RequestBudget, RequestCategory};
+
+/// Metrics about the requests of a [`RequestCategory`], returned by
+/// [`Client::request_metrics()`](crate::Client::request_metrics).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestCategoryMetrics {
+    /// The number of requests that are waiting for a concurrency slot.
+    pub queued: usize,
+
+    /// The number of requests that are currently being sent.
+    pub in_flight: usize,
+
+    /// The total number of requests that were started.
+    pub total_requests: u64,
+
+    /// The total time the requests spent waiting for a concurrency slot.
+    pub total_queue_time: Duration,
+}
+
+/// The state of the requests of a single [`RequestCategory`].
+#[derive(Debug)]
+struct CategoryState {
+    budget: RequestBudget,
+    semaphore: MaybeSemaphore,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    total_requests: AtomicU64,
+    total_queue_time_ms: AtomicU64,
+}
+
+impl CategoryState {
+    fn new(budget: RequestBudget) -> Self {
+        Self {
+            budget,
+            semaphore: MaybeSemaphore::new(budget.max_concurrent_requests),
+            queued: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            total_requests: AtomicU64::new(0),
+            total_queue_time_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The budgets of all the [`RequestCategory`]s, with their metrics.
+#[derive(Debug)]
+pub(super) struct RequestBudgets {
+    categories: BTreeMap<RequestCategory, CategoryState>,
+}
+
+impl RequestBudgets {
+    pub(super) fn new(mut budgets: BTreeMap<RequestCategory, RequestBudget>) -> Self {
+        let categories = RequestCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let budget = budgets.remove(&category).unwrap_or_default();
+                (category, CategoryState::new(budget))
+            })
+            .collect();
+
+        Self { categories }
+    }
+
+    fn state(&self, category: RequestCategory) -> &CategoryState {
+        self.categories.get(&category).expect("all the categories have a state")
+    }
+
+    /// The timeout to use for the requests in the given category, if it
+    /// overrides the one of the request config.
+    pub(super) fn timeout(&self, category: RequestCategory) -> Option<Duration> {
+        self.state(category).budget.timeout
+    }
+
+    /// Wait for a concurrency slot to send a request of the given category.
+    ///
+    /// If the category doesn't have its own concurrency limit, the global one
+    /// is used.
+    pub(super) async fn acquire<'a>(
+        &'a self,
+        category: RequestCategory,
+        global_semaphore: &'a MaybeSemaphore,
+    ) -> RequestBudgetPermit<'a> {
+        let state = self.state(category);
+        let semaphore = if state.budget.max_concurrent_requests.is_some() {
+            &state.semaphore
+        } else {
+            global_semaphore
+        };
+
+        let queued_at = Instant::now();
+        let permit = {
+            // Make sure the request is not counted as queued anymore if this future is
+            // dropped while waiting.
+            let _queued = CounterGuard::new(&state.queued);
+            semaphore.acquire().await
+        };
+
+        let queue_time_ms = queued_at.elapsed().as_millis().try_into().unwrap_or(u64::MAX);
+        state.total_queue_time_ms.fetch_add(queue_time_ms, Ordering::Relaxed);
+        state.total_requests.fetch_add(1, Ordering::Relaxed);
+
+        RequestBudgetPermit { _permit: permit, _in_flight: CounterGuard::new(&state.in_flight) }
+    }
+
+    /// Get the metrics of the given category.
+    pub(super) fn metrics(&self, category: RequestCategory) -> RequestCategoryMetrics {
+        let state = self.state(category);
+
+        RequestCategoryMetrics {
+            queued: state.queued.load(Ordering::Relaxed),
+            in_flight: state.in_flight.load(Ordering::Relaxed),
+            total_requests: state.total_requests.load(Ordering::Relaxed),
+            total_queue_time: Duration::from_millis(
+                state.total_queue_time_ms.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+/// A permit to send a request of a [`RequestCategory`].
+///
+/// The request is counted as in flight until this is dropped.
+pub(super) struct RequestBudgetPermit<'a> {
+    _permit: MaybeSemaphorePermit<'a>,
+    _in_flight: CounterGuard<'a>,
+}
+
+/// Increments a counter while it is alive.
+struct CounterGuard<'a>(&'a AtomicUsize);
+
+impl<'a> CounterGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for CounterGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::atomic::{AtomicU16, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use http::{Method, StatusCode};
+
+/// Metadata about an HTTP request sent by the client and its response, for
+/// debugging purposes.
+///
+/// It doesn't contain the headers or the body of the request or the
+/// response, nor the parameters in the URL, so it can be displayed or logged
+/// safely.
+///
+/// See [`Client::subscribe_to_http_diagnostics()`] to receive them.
+///
+/// [`Client::subscribe_to_http_diagnostics()`]: crate::Client::subscribe_to_http_diagnostics
+#[derive(Clone, Debug)]
+pub struct HttpDiagnostics {
+    /// The ID of the request, as it appears in the logs.
+    pub request_id: String,
+
+    /// The HTTP method of the request.
+    pub method: Method,
+
+    /// The path of the endpoint, with the placeholders for its parameters,
+    /// like `/_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`.
+    pub endpoint: &'static str,
+
+    /// The time it took to get the final response, including the retries and
+    /// the time spent waiting for a concurrency slot.
+    pub duration: Duration,
+
+    /// The status code of the last response received, if any.
+    pub status: Option<StatusCode>,
+
+    /// How many times the request was sent, including the retries.
+    pub attempts: u64,
+}
+
+/// The attempts of sending a request.
+#[derive(Debug, Default)]
+pub(super) struct RequestAttempts {
+    count: AtomicU64,
+    last_status: AtomicU16,
+}
+
+impl RequestAttempts {
+    /// Record a new attempt, and return its number, starting from 1.
+    pub(super) fn start(&self) -> u64 {
+        self.count.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Record the status code of the response to the current attempt.
+    pub(super) fn set_status(&self, status: StatusCode) {
+        self.last_status.store(status.as_u16(), Ordering::SeqCst);
+    }
+
+    /// The number of attempts.
+    pub(super) fn count(&self) -> u64 {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// The status code of the last response, if any.
+    pub(super) fn last_status(&self) -> Option<StatusCode> {
+        StatusCode::from_u16(self.last_status.load(Ordering::SeqCst)).ok()
+    }
+}
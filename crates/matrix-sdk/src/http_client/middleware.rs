@@ -0,0 +1,125 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+
+use bytes::Bytes;
+use matrix_sdk_common::{SendOutsideWasm, SyncOutsideWasm};
+
+/// A hook into the HTTP requests sent to the homeserver by a
+/// [`Client`](crate::Client).
+///
+/// Middlewares are registered with
+/// [`ClientBuilder::add_http_middleware()`](crate::ClientBuilder::add_http_middleware).
+/// They apply to every request made with the Matrix client-server API,
+/// including media downloads and the sliding sync loop, but not to the requests
+/// made to the OAuth 2.0 authorization server.
+///
+/// They can be used for example to add headers to the requests, to record
+/// custom traces, or to mirror the requests to another service.
+///
+/// # Examples
+///
+/// ```
+/// use matrix_sdk::{bytes::Bytes, HttpMiddleware};
+///
+/// #[derive(Debug)]
+/// struct ProxyAuthorization(String);
+///
+/// impl HttpMiddleware for ProxyAuthorization {
+///     fn on_request(&self, request: &mut http::Request<Bytes>) {
+///         request
+///             .headers_mut()
+///             .insert("proxy-authorization", self.0.parse().unwrap());
+///     }
+/// }
+/// ```
+pub trait HttpMiddleware: Debug + SendOutsideWasm + SyncOutsideWasm {
+    /// Called before a request is sent, to inspect or modify it.
+    ///
+    /// This is called once per request, and the same request is sent again if
+    /// it needs to be retried.
+    fn on_request(&self, request: &mut http::Request<Bytes>) {
+        let _ = request;
+    }
+
+    /// Called when a response is received for a request, before it is parsed.
+    ///
+    /// This is called for every attempt of sending the request, and for error
+    /// responses too.
+    fn on_response(&self, request: &http::Request<Bytes>, response: &http::Response<Bytes>) {
+        let _ = (request, response);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, ResponseTemplate,
+    };
+
+    use super::HttpMiddleware;
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    #[derive(Debug, Default)]
+    struct TestMiddleware {
+        responses: Arc<Mutex<Vec<(String, u16)>>>,
+    }
+
+    impl HttpMiddleware for TestMiddleware {
+        fn on_request(&self, request: &mut http::Request<Bytes>) {
+            request.headers_mut().insert("x-custom-header", "custom".parse().unwrap());
+        }
+
+        fn on_response(&self, request: &http::Request<Bytes>, response: &http::Response<Bytes>) {
+            let path = request.uri().path().to_owned();
+            self.responses.lock().unwrap().push((path, response.status().as_u16()));
+        }
+    }
+
+    #[async_test]
+    async fn test_http_middleware() {
+        let server = MatrixMockServer::new().await;
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let client = server
+            .client_builder()
+            .add_http_middleware(TestMiddleware { responses: responses.clone() })
+            .build()
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/v3/account/whoami"))
+            .and(header("x-custom-header", "custom"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@example:localhost",
+                "device_id": "DEVICEID",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let response = client.whoami().await.unwrap();
+        assert_eq!(response.user_id, "@example:localhost");
+
+        // Other requests might have been made in the background, only look at this one.
+        let responses = responses.lock().unwrap();
+        assert!(responses.contains(&("/_matrix/client/v3/account/whoami".to_owned(), 200)));
+    }
+}
@@ -14,11 +14,13 @@
 
 use std::{
     any::type_name,
+    collections::BTreeMap,
     fmt::Debug,
     num::NonZeroUsize,
+    ops::Range,
     sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
     },
     time::Duration,
 };
@@ -27,22 +29,41 @@ use bytes::{Bytes, BytesMut};
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use http::Method;
-use ruma::api::{
-    error::{FromHttpResponseError, IntoHttpError},
-    AuthScheme, MatrixVersion, OutgoingRequest, SendAccessToken,
+use ruma::{
+    api::{
+        error::{FromHttpResponseError, IntoHttpError},
+        AuthScheme, MatrixVersion, OutgoingRequest, SendAccessToken,
+    },
+    time::Instant,
 };
-use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::sync::{broadcast, Semaphore, SemaphorePermit};
 use tracing::{debug, field::debug, instrument, trace};
 
-use crate::{config::RequestConfig, error::HttpError};
+use crate::{
+    clock::{Clock, SystemClock},
+    config::{RequestBudget, RequestCategory, RequestConfig},
+    error::HttpError,
+};
 
+mod budget;
+mod diagnostics;
+mod middleware;
 #[cfg(not(target_arch = "wasm32"))]
 mod native;
+#[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+mod pinning;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
 
+pub use budget::RequestCategoryMetrics;
+use budget::{RequestBudgetPermit, RequestBudgets};
+pub use diagnostics::HttpDiagnostics;
+use diagnostics::RequestAttempts;
+pub use middleware::HttpMiddleware;
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) use native::HttpSettings;
+#[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+pub use pinning::{CertificatePin, CertificatePinError};
 
 pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -52,6 +73,15 @@ struct MaybeSemaphore(Arc<Option<Semaphore>>);
 #[allow(dead_code)] // false-positive lint: we never use it but only hold it for the drop
 struct MaybeSemaphorePermit<'a>(Option<SemaphorePermit<'a>>);
 
+/// The permit to send a request, held until the request is done.
+#[allow(dead_code)] // false-positive lint: we never use it but only hold it for the drop
+enum RequestPermit<'a> {
+    /// The request doesn't have a category, it uses the global limit.
+    Global(MaybeSemaphorePermit<'a>),
+    /// The request uses the budget of its category.
+    Category(RequestBudgetPermit<'a>),
+}
+
 impl MaybeSemaphore {
     fn new(max: Option<NonZeroUsize>) -> Self {
         let inner = max.map(|i| Semaphore::new(i.into()));
@@ -75,7 +105,11 @@ pub(crate) struct HttpClient {
     pub(crate) inner: reqwest::Client,
     pub(crate) request_config: RequestConfig,
     concurrent_request_semaphore: MaybeSemaphore,
+    request_budgets: Arc<RequestBudgets>,
+    diagnostics_sender: broadcast::Sender<HttpDiagnostics>,
     next_request_id: Arc<AtomicU64>,
+    middlewares: Vec<Arc<dyn HttpMiddleware>>,
+    pub(crate) clock: Arc<dyn Clock>,
 }
 
 impl HttpClient {
@@ -86,7 +120,50 @@ impl HttpClient {
             concurrent_request_semaphore: MaybeSemaphore::new(
                 request_config.max_concurrent_requests,
             ),
+            request_budgets: RequestBudgets::new(BTreeMap::new()).into(),
+            diagnostics_sender: broadcast::Sender::new(64),
             next_request_id: AtomicU64::new(0).into(),
+            middlewares: Vec::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use the given middlewares for all the requests sent by this client.
+    pub(crate) fn with_middlewares(mut self, middlewares: Vec<Arc<dyn HttpMiddleware>>) -> Self {
+        self.middlewares = middlewares;
+        self
+    }
+
+    /// Use the given clock to wait before retrying requests.
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Use the given budgets for the categories of requests sent by this
+    /// client.
+    pub(crate) fn with_request_budgets(
+        mut self,
+        budgets: BTreeMap<RequestCategory, RequestBudget>,
+    ) -> Self {
+        self.request_budgets = RequestBudgets::new(budgets).into();
+        self
+    }
+
+    /// Get the metrics of the requests of the given category.
+    pub(crate) fn request_metrics(&self, category: RequestCategory) -> RequestCategoryMetrics {
+        self.request_budgets.metrics(category)
+    }
+
+    /// Subscribe to the diagnostics of the requests sent by this client.
+    pub(crate) fn subscribe_to_diagnostics(&self) -> broadcast::Receiver<HttpDiagnostics> {
+        self.diagnostics_sender.subscribe()
+    }
+
+    /// Let the middlewares handle the response received for the given request.
+    fn on_response(&self, request: &http::Request<Bytes>, response: &http::Response<Bytes>) {
+        for middleware in &self.middlewares {
+            middleware.on_response(request, response);
         }
     }
 
@@ -134,7 +211,17 @@ impl HttpClient {
 
     #[allow(clippy::too_many_arguments)]
     #[instrument(
-        skip(self, request, config, homeserver, access_token, server_versions, send_progress),
+        name = "matrix_sdk.http_request",
+        skip(
+            self,
+            request,
+            config,
+            homeserver,
+            access_token,
+            server_versions,
+            send_progress,
+            download
+        ),
         fields(uri, method, request_size, request_id, status, response_size, sentry_event_id)
     )]
     pub async fn send<R>(
@@ -145,25 +232,28 @@ impl HttpClient {
         access_token: Option<&str>,
         server_versions: &[MatrixVersion],
         send_progress: SharedObservable<TransmissionProgress>,
+        download: Option<PartialDownload>,
     ) -> Result<R::IncomingResponse, HttpError>
     where
         R: OutgoingRequest + Debug,
         HttpError: From<FromHttpResponseError<R::EndpointError>>,
     {
-        let config = match config {
+        let mut config = match config {
             Some(config) => config,
             None => self.request_config,
         };
 
         // Keep some local variables in a separate scope so the compiler doesn't include
         // them in the future type. https://github.com/rust-lang/rust/issues/57478
-        let request = {
-            let request_id = self.get_request_id();
+        let started_at = Instant::now();
+        let request_id = self.get_request_id();
+
+        let (request, category) = {
             let span = tracing::Span::current();
 
             // At this point in the code, the config isn't behind an Option anymore, that's
             // why we record it here, instead of in the #[instrument] macro.
-            span.record("config", debug(config)).record("request_id", request_id);
+            span.record("config", debug(config)).record("request_id", &request_id);
 
             let auth_scheme = R::METADATA.authentication;
             match auth_scheme {
@@ -176,10 +266,19 @@ impl HttpClient {
                 }
             }
 
-            let request = self
+            let mut request = self
                 .serialize_request(request, config, homeserver, access_token, server_versions)
                 .map_err(HttpError::IntoHttp)?;
 
+            let category = RequestCategory::from_path(request.uri().path());
+            if let Some(timeout) = category.and_then(|c| self.request_budgets.timeout(c)) {
+                config.timeout = timeout;
+            }
+
+            for middleware in &self.middlewares {
+                middleware.on_request(&mut request);
+            }
+
             let method = request.method();
 
             let mut uri_parts = request.uri().clone().into_parts();
@@ -201,15 +300,46 @@ impl HttpClient {
                 );
             }
 
-            request
+            (request, category)
         };
 
         // will be automatically dropped at the end of this function
-        let _handle = self.concurrent_request_semaphore.acquire().await;
+        let _handle = match category {
+            Some(category) => RequestPermit::Category(
+                self.request_budgets.acquire(category, &self.concurrent_request_semaphore).await,
+            ),
+            None => RequestPermit::Global(self.concurrent_request_semaphore.acquire().await),
+        };
+
+        let method = request.method().clone();
+        let attempts = RequestAttempts::default();
 
         // There's a bunch of state in send_request, factor out a pinned inner
         // future to reduce this size of futures that await this function.
-        match Box::pin(self.send_request::<R>(request, config, send_progress)).await {
+        let result =
+            Box::pin(self.send_request::<R>(request, config, send_progress, download, &attempts))
+                .await;
+
+        // Only build the diagnostics if someone is listening.
+        if self.diagnostics_sender.receiver_count() > 0 {
+            let history = &R::METADATA.history;
+            let endpoint = history
+                .stable_endpoint_for(server_versions)
+                .or_else(|| history.unstable())
+                .or_else(|| history.all_paths().next())
+                .unwrap_or_default();
+
+            let _ = self.diagnostics_sender.send(HttpDiagnostics {
+                request_id,
+                method,
+                endpoint,
+                duration: started_at.elapsed(),
+                status: attempts.last_status(),
+                attempts: attempts.count(),
+            });
+        }
+
+        match result {
             Ok(response) => {
                 debug!("Got response");
                 Ok(response)
@@ -231,6 +361,186 @@ pub struct TransmissionProgress {
     pub total: usize,
 }
 
+/// The state of the download of a response body.
+///
+/// The received data is kept in this state as it arrives, so it is not lost if
+/// the request is interrupted, and the download can be resumed from where it
+/// stopped with the same `PartialDownload`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PartialDownload {
+    /// The progress of the download.
+    pub(crate) progress: SharedObservable<TransmissionProgress>,
+
+    /// The data that was received so far.
+    data: Arc<StdMutex<Vec<u8>>>,
+
+    /// The range of bytes of the content to request, if only part of it is
+    /// needed.
+    range: Option<Range<u64>>,
+
+    /// The partial content that was returned by the server, if it honored the
+    /// requested range.
+    partial_content: Arc<StdMutex<Option<PartialContent>>>,
+
+    /// The `ETag` or `Last-Modified` header of the response that the data was
+    /// received from, sent in the `If-Range` header when resuming the download
+    /// so the server returns the whole content if it changed.
+    validator: Arc<StdMutex<Option<String>>>,
+
+    /// Whether the received data was persisted, to resume the download after a
+    /// restart.
+    persisted: Arc<AtomicBool>,
+}
+
+impl PartialDownload {
+    /// Create a `PartialDownload` that only requests the given range of bytes
+    /// of the content.
+    ///
+    /// The server is free to ignore the range and to return the whole
+    /// content, which can be checked with [`PartialDownload::partial_content`].
+    pub(crate) fn with_range(range: Range<u64>) -> Self {
+        Self { range: Some(range), ..Default::default() }
+    }
+
+    /// The number of bytes that were received so far.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn received_len(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+
+    /// Forget the data that was received so far, to download the whole content
+    /// again.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn clear(&self) {
+        self.data.lock().unwrap().clear();
+        *self.partial_content.lock().unwrap() = None;
+        *self.validator.lock().unwrap() = None;
+    }
+
+    /// Whether this is the same download as `other`.
+    pub(crate) fn is_same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Serialize the data that was received so far, to persist it and resume
+    /// the download later with [`PartialDownload::restore()`].
+    ///
+    /// Returns `None` if nothing was received, or if the data can't be
+    /// validated when resuming the download because the server didn't send an
+    /// `ETag` or a `Last-Modified` header.
+    pub(crate) fn serialize(&self) -> Option<Vec<u8>> {
+        if self.range.is_some() {
+            return None;
+        }
+
+        let data = self.data.lock().unwrap();
+        let validator = self.validator.lock().unwrap();
+        let validator = validator.as_deref().filter(|_| !data.is_empty())?;
+        let validator_len = u32::try_from(validator.len()).ok()?;
+
+        let mut bytes = Vec::with_capacity(4 + validator.len() + data.len());
+        bytes.extend_from_slice(&validator_len.to_le_bytes());
+        bytes.extend_from_slice(validator.as_bytes());
+        bytes.extend_from_slice(&data);
+
+        Some(bytes)
+    }
+
+    /// Restore the data serialized with [`PartialDownload::serialize()`], if
+    /// nothing was received yet.
+    pub(crate) fn restore(&self, bytes: &[u8]) {
+        let mut data = self.data.lock().unwrap();
+
+        if !data.is_empty() {
+            return;
+        }
+
+        let Some((validator_len, rest)) = bytes.split_first_chunk::<4>() else {
+            return;
+        };
+        let Some((validator, received)) = usize::try_from(u32::from_le_bytes(*validator_len))
+            .ok()
+            .filter(|len| *len <= rest.len())
+            .map(|len| rest.split_at(len))
+        else {
+            return;
+        };
+        let Ok(validator) = String::from_utf8(validator.to_owned()) else {
+            return;
+        };
+
+        data.extend_from_slice(received);
+        *self.validator.lock().unwrap() = Some(validator);
+        self.persisted.store(true, Ordering::SeqCst);
+        self.progress.set(TransmissionProgress { current: data.len(), total: data.len() });
+    }
+
+    /// Mark the received data as persisted.
+    pub(crate) fn mark_as_persisted(&self) {
+        self.persisted.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the received data was persisted, and must be removed from the
+    /// store once the download is complete.
+    pub(crate) fn is_persisted(&self) -> bool {
+        self.persisted.load(Ordering::SeqCst)
+    }
+
+    /// The value of the `If-Range` header to send with the `Range` header, if
+    /// the download is resumed.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn if_range_header(&self) -> Option<String> {
+        if self.range.is_some() || self.received_len() == 0 {
+            return None;
+        }
+
+        self.validator.lock().unwrap().clone()
+    }
+
+    /// The value of the `Range` header to send, if any.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn range_header(&self) -> Option<String> {
+        let received_len = self.received_len() as u64;
+
+        match &self.range {
+            Some(range) => Some(format!(
+                "bytes={}-{}",
+                range.start + received_len,
+                range.end.saturating_sub(1).max(range.start + received_len)
+            )),
+            None => (received_len > 0).then(|| format!("bytes={received_len}-")),
+        }
+    }
+
+    /// The partial content that was returned by the server, or `None` if the
+    /// whole content was returned.
+    pub(crate) fn partial_content(&self) -> Option<PartialContent> {
+        *self.partial_content.lock().unwrap()
+    }
+}
+
+/// The position of partial content returned by the server, parsed from the
+/// `Content-Range` header of the response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PartialContent {
+    /// The offset of the first returned byte in the whole content.
+    pub(crate) start: u64,
+
+    /// The length of the whole content, if the server knows it.
+    pub(crate) total_len: Option<u64>,
+}
+
+impl PartialContent {
+    /// Parse the value of a `Content-Range` header, like `bytes 0-499/1234`.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn parse(content_range: &str) -> Option<Self> {
+        let (range, total_len) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+        let (start, _end) = range.split_once('-')?;
+
+        Some(Self { start: start.trim().parse().ok()?, total_len: total_len.trim().parse().ok() })
+    }
+}
+
 async fn response_to_http_response(
     mut response: reqwest::Response,
 ) -> Result<http::Response<Bytes>, reqwest::Error> {
@@ -253,6 +563,7 @@ async fn response_to_http_response(
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use std::{
+        future::IntoFuture,
         num::NonZeroUsize,
         sync::{
             atomic::{AtomicU8, Ordering},
@@ -261,13 +572,17 @@ mod tests {
         time::Duration,
     };
 
+    use assert_matches::assert_matches;
     use matrix_sdk_test::{async_test, test_json};
+    use ruma::{api::client::media::get_content, mxc_uri};
     use wiremock::{
-        matchers::{method, path},
+        matchers::{header, method, path},
         Mock, Request, ResponseTemplate,
     };
 
+    use super::{HttpError, PartialContent, PartialDownload};
     use crate::{
+        config::{RequestBudget, RequestCategory},
         http_client::RequestConfig,
         test_utils::{set_client_session, test_client_builder_with_server},
     };
@@ -356,4 +671,264 @@ mod tests {
         assert_eq!(counter.load(Ordering::SeqCst), 254, "Not all requests passed through");
         bg_task.abort();
     }
+
+    #[async_test]
+    async fn test_request_budget_is_separate_from_global_limit() {
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder
+            .request_config(RequestConfig::default().max_concurrent_requests(NonZeroUsize::new(1)))
+            .request_budget(
+                RequestCategory::MediaDownload,
+                RequestBudget::new().max_concurrent_requests(NonZeroUsize::new(2)),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        set_client_session(&client).await;
+
+        let media_counter = Arc::new(AtomicU8::new(0));
+        let inner_media_counter = media_counter.clone();
+        let whoami_counter = Arc::new(AtomicU8::new(0));
+        let inner_whoami_counter = whoami_counter.clone();
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .respond_with(move |_req: &Request| {
+                inner_media_counter.fetch_add(1, Ordering::SeqCst);
+                // we stall the requests
+                ResponseTemplate::new(200).set_delay(Duration::from_secs(60))
+            })
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/account/whoami"))
+            .respond_with(move |_req: &Request| {
+                inner_whoami_counter.fetch_add(1, Ordering::SeqCst);
+                ResponseTemplate::new(200).set_delay(Duration::from_secs(60))
+            })
+            .mount(&server)
+            .await;
+
+        let media_client = client.clone();
+        let media_task = tokio::spawn(async move {
+            #[allow(deprecated)]
+            let request =
+                get_content::v3::Request::from_url(mxc_uri!("mxc://localhost/textfile")).unwrap();
+            futures_util::future::join_all(
+                (0..5).map(|_| media_client.send(request.clone()).into_future()),
+            )
+            .await
+        });
+        let whoami_client = client.clone();
+        let whoami_task = tokio::spawn(async move { whoami_client.whoami().await });
+
+        // give it some time to issue the requests
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert_eq!(media_counter.load(Ordering::SeqCst), 2, "The media budget was not observed");
+        assert_eq!(
+            whoami_counter.load(Ordering::SeqCst),
+            1,
+            "The media requests used the global limit"
+        );
+
+        let metrics = client.request_metrics(RequestCategory::MediaDownload);
+        assert_eq!(metrics.in_flight, 2);
+        assert_eq!(metrics.queued, 3);
+        assert_eq!(metrics.total_requests, 2);
+
+        media_task.abort();
+        whoami_task.abort();
+    }
+
+    #[async_test]
+    async fn test_request_budget_timeout() {
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder
+            .request_budget(
+                RequestCategory::MediaDownload,
+                RequestBudget::new().timeout(Duration::from_millis(100)),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+            .mount(&server)
+            .await;
+
+        #[allow(deprecated)]
+        let request =
+            get_content::v3::Request::from_url(mxc_uri!("mxc://localhost/textfile")).unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            client.send(request).with_request_config(RequestConfig::new().disable_retry()),
+        )
+        .await
+        .expect("the request should time out before the test");
+
+        assert_matches!(result, Err(HttpError::Reqwest(error)) if error.is_timeout());
+        assert_eq!(client.request_metrics(RequestCategory::MediaDownload).in_flight, 0);
+    }
+
+    #[async_test]
+    async fn test_http_diagnostics() {
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder.build().await.unwrap();
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        // The first attempt fails with a transient error, the second one succeeds.
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+            .mount(&server)
+            .await;
+
+        let mut diagnostics = client.subscribe_to_http_diagnostics();
+
+        client.whoami().await.unwrap();
+
+        // Other requests might have been made in the background, only look at this one.
+        let whoami_diagnostics = loop {
+            let diagnostics = diagnostics.recv().await.unwrap();
+            if diagnostics.endpoint.ends_with("/account/whoami") {
+                break diagnostics;
+            }
+        };
+
+        assert_eq!(whoami_diagnostics.method, http::Method::GET);
+        assert_eq!(whoami_diagnostics.endpoint, "/_matrix/client/r0/account/whoami");
+        assert_eq!(whoami_diagnostics.status, Some(http::StatusCode::OK));
+        assert_eq!(whoami_diagnostics.attempts, 2);
+        assert!(whoami_diagnostics.request_id.starts_with("REQ-"));
+    }
+
+    #[test]
+    fn test_parse_partial_content() {
+        assert_eq!(
+            PartialContent::parse("bytes 10-19/100"),
+            Some(PartialContent { start: 10, total_len: Some(100) })
+        );
+        assert_eq!(
+            PartialContent::parse("bytes 10-19/*"),
+            Some(PartialContent { start: 10, total_len: None })
+        );
+        assert_eq!(PartialContent::parse("bytes */100"), None);
+        assert_eq!(PartialContent::parse("items 10-19/100"), None);
+    }
+
+    #[async_test]
+    async fn test_resume_partial_download() {
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder.build().await.unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .and(header("range", "bytes=7-"))
+            .and(header("if-range", "\"etag\""))
+            .respond_with(ResponseTemplate::new(206).set_body_string("World!"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Part of the content was received and persisted before the download was
+        // interrupted.
+        let interrupted_download = PartialDownload::default();
+        interrupted_download.data.lock().unwrap().extend_from_slice(b"Hello, ");
+        *interrupted_download.validator.lock().unwrap() = Some("\"etag\"".to_owned());
+
+        let download = PartialDownload::default();
+        download.restore(&interrupted_download.serialize().unwrap());
+        assert!(download.is_persisted());
+
+        #[allow(deprecated)]
+        let request =
+            get_content::v3::Request::from_url(mxc_uri!("mxc://localhost/textfile")).unwrap();
+        let response = client.send(request).with_partial_download(download.clone()).await.unwrap();
+
+        assert_eq!(response.file, b"Hello, World!");
+
+        let progress = download.progress.get();
+        assert_eq!(progress.current, 13);
+        assert_eq!(progress.total, 13);
+        assert_eq!(download.received_len(), 0);
+    }
+
+    #[async_test]
+    async fn test_restart_download_when_range_not_satisfiable() {
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder.build().await.unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .and(header("range", "bytes=20-"))
+            .respond_with(ResponseTemplate::new(416))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // More data was received than the current content has.
+        let download = PartialDownload::default();
+        download.data.lock().unwrap().extend_from_slice(&[0; 20]);
+
+        #[allow(deprecated)]
+        let request =
+            get_content::v3::Request::from_url(mxc_uri!("mxc://localhost/textfile")).unwrap();
+        let response = client.send(request).with_partial_download(download.clone()).await.unwrap();
+
+        assert_eq!(response.file, b"Hello, World!");
+    }
 }
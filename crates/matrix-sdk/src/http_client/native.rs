@@ -12,23 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{
-    fmt::Debug,
-    mem,
-    sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
-};
+#[cfg(feature = "rustls-tls")]
+use std::collections::BTreeSet;
+use std::{fmt::Debug, mem, time::Duration};
 
 use backon::{ExponentialBuilder, Retryable};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
-use http::header::CONTENT_LENGTH;
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    HeaderValue, StatusCode,
+};
 use reqwest::{tls, Certificate};
 use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
+use rustls_pki_types::CertificateDer;
 use tracing::{debug, info, warn};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress, DEFAULT_REQUEST_TIMEOUT};
+#[cfg(feature = "rustls-tls")]
+use super::pinning::{pinned_tls_config, CertificatePin};
+use super::{
+    response_to_http_response, HttpClient, PartialContent, PartialDownload, RequestAttempts,
+    TransmissionProgress, DEFAULT_REQUEST_TIMEOUT,
+};
 use crate::{
     config::RequestConfig,
     error::{HttpError, RetryKind},
@@ -40,6 +46,8 @@ impl HttpClient {
         request: http::Request<Bytes>,
         config: RequestConfig,
         send_progress: SharedObservable<TransmissionProgress>,
+        download: Option<PartialDownload>,
+        attempts: &RequestAttempts,
     ) -> Result<R::IncomingResponse, HttpError>
     where
         R: OutgoingRequest + Debug,
@@ -68,19 +76,21 @@ impl HttpClient {
             backoff
         };
 
-        let retry_count = AtomicU64::new(1);
-
         let send_request = || {
             let send_progress = send_progress.clone();
+            let download = download.clone();
 
             async {
-                let num_attempt = retry_count.fetch_add(1, Ordering::SeqCst);
+                let num_attempt = attempts.start();
                 debug!(num_attempt, "Sending request");
 
                 let response =
-                    send_request(&self.inner, &request, config.timeout, send_progress).await?;
+                    send_request(&self.inner, &request, config.timeout, send_progress, download)
+                        .await?;
+                self.on_response(&request, &response);
 
                 let status_code = response.status();
+                attempts.set_status(status_code);
                 let response_size = ByteSize(response.body().len().try_into().unwrap_or(u64::MAX));
                 tracing::Span::current()
                     .record("status", status_code.as_u16())
@@ -104,9 +114,11 @@ impl HttpClient {
         };
 
         let has_retry_limit = config.retry_limit.is_some();
+        let clock = self.clock.clone();
 
         send_request
             .retry(backoff)
+            .sleep(move |duration| clock.sleep(duration))
             .adjust(|err, default_timeout| {
                 match err.retry_kind() {
                     RetryKind::Transient { retry_after } => {
@@ -144,10 +156,14 @@ impl HttpClient {
 pub(crate) struct HttpSettings {
     pub(crate) disable_ssl_verification: bool,
     pub(crate) proxy: Option<String>,
+    pub(crate) disable_system_proxy: bool,
+    pub(crate) disable_local_dns_resolution: bool,
     pub(crate) user_agent: Option<String>,
     pub(crate) timeout: Duration,
-    pub(crate) additional_root_certificates: Vec<Certificate>,
+    pub(crate) additional_root_certificates: Vec<CertificateDer<'static>>,
     pub(crate) disable_built_in_root_certificates: bool,
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) certificate_pins: BTreeSet<CertificatePin>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -156,10 +172,14 @@ impl Default for HttpSettings {
         Self {
             disable_ssl_verification: false,
             proxy: None,
+            disable_system_proxy: false,
+            disable_local_dns_resolution: false,
             user_agent: None,
             timeout: DEFAULT_REQUEST_TIMEOUT,
             additional_root_certificates: Default::default(),
             disable_built_in_root_certificates: false,
+            #[cfg(feature = "rustls-tls")]
+            certificate_pins: Default::default(),
         }
     }
 }
@@ -188,7 +208,7 @@ impl HttpSettings {
             );
 
             for cert in &self.additional_root_certificates {
-                http_client = http_client.add_root_certificate(cert.clone());
+                http_client = http_client.add_root_certificate(Certificate::from_der(cert)?);
             }
         }
 
@@ -197,25 +217,68 @@ impl HttpSettings {
             http_client = http_client.tls_built_in_root_certs(false);
         }
 
+        #[cfg(feature = "rustls-tls")]
+        if !self.certificate_pins.is_empty() {
+            info!(
+                pins = ?self.certificate_pins,
+                "Only trusting the pinned certificates in the HTTP client"
+            );
+
+            if self.disable_ssl_verification {
+                warn!("The certificates are pinned, ignoring the disabled SSL verification");
+            }
+
+            http_client = http_client.use_preconfigured_tls(pinned_tls_config(
+                self.certificate_pins.clone(),
+                &self.additional_root_certificates,
+                !self.disable_built_in_root_certificates,
+            ));
+        }
+
         if let Some(p) = &self.proxy {
+            let p = proxy_url(p, self.disable_local_dns_resolution);
+
             info!(proxy_url = p, "Setting the proxy for the HTTP client");
             http_client = http_client.proxy(reqwest::Proxy::all(p.as_str())?);
+        } else if self.disable_system_proxy {
+            info!("System proxy disabled in the HTTP client");
+            http_client = http_client.no_proxy();
         }
 
         Ok(http_client.build()?)
     }
 }
 
+/// Get the URL of the proxy to use for the given proxy URL.
+///
+/// If the DNS resolution must not be done locally, the `socks5` scheme is
+/// replaced by the `socks5h` scheme, with which the host names are resolved by
+/// the proxy.
+fn proxy_url(proxy: &str, disable_local_dns_resolution: bool) -> String {
+    const SOCKS5_SCHEME: &str = "socks5://";
+
+    match proxy.split_at_checked(SOCKS5_SCHEME.len()) {
+        Some((scheme, rest))
+            if disable_local_dns_resolution && scheme.eq_ignore_ascii_case(SOCKS5_SCHEME) =>
+        {
+            format!("socks5h://{rest}")
+        }
+        _ => proxy.to_owned(),
+    }
+}
+
 pub(super) async fn send_request(
     client: &reqwest::Client,
     request: &http::Request<Bytes>,
     timeout: Duration,
     send_progress: SharedObservable<TransmissionProgress>,
+    download: Option<PartialDownload>,
 ) -> Result<http::Response<Bytes>, HttpError> {
     use std::convert::Infallible;
 
     use futures_util::stream;
 
+    let original_request = request;
     let request = request.clone();
     let request = {
         let mut request = if send_progress.subscriber_count() != 0 {
@@ -248,11 +311,163 @@ pub(super) async fn send_request(
         };
 
         *request.timeout_mut() = Some(timeout);
+
+        if let Some(range) = download.as_ref().and_then(PartialDownload::range_header) {
+            // Only request the missing part of the content, either because the download
+            // is resumed or because only a range was requested.
+            debug!(range, "Requesting a range of the content");
+            let range = HeaderValue::from_str(&range).expect("range header value should be valid");
+            request.headers_mut().insert(RANGE, range);
+
+            // Only get the rest of the content if it didn't change since we received the
+            // first part of it.
+            if let Some(if_range) = download
+                .as_ref()
+                .and_then(PartialDownload::if_range_header)
+                .and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                request.headers_mut().insert(IF_RANGE, if_range);
+            }
+        }
+
         request
     };
 
     let response = client.execute(request).await?;
-    Ok(response_to_http_response(response).await?)
+
+    if let Some(download) = &download {
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE
+            && download.range.is_none()
+            && download.received_len() > 0
+        {
+            // The data that was received doesn't match the content anymore, download the
+            // whole content again.
+            debug!("The download can't be resumed, starting over");
+            download.clear();
+
+            return Box::pin(send_request(
+                client,
+                original_request,
+                timeout,
+                Default::default(),
+                Some(download.clone()),
+            ))
+            .await;
+        }
+
+        if let Some(range) = download
+            .range
+            .as_ref()
+            .filter(|_| response.status() == StatusCode::RANGE_NOT_SATISFIABLE)
+        {
+            // The requested range starts after the end of the content, return an empty
+            // part of the content.
+            let total_len = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes */"))
+                .and_then(|total_len| total_len.trim().parse().ok());
+            *download.partial_content.lock().unwrap() =
+                Some(PartialContent { start: range.start, total_len });
+
+            return Ok(http::Response::builder()
+                .status(StatusCode::OK)
+                .body(Bytes::new())
+                .expect("Can't construct an empty response"));
+        }
+    }
+
+    match download {
+        Some(download) if response.status().is_success() => {
+            Ok(download_response(response, &download).await?)
+        }
+        _ => Ok(response_to_http_response(response).await?),
+    }
+}
+
+/// Convert the given successful response to an `http::Response`, by
+/// downloading its body into the given `PartialDownload`.
+///
+/// If the response is a partial content, the body is appended to the data
+/// that was already received, and the returned response contains the whole
+/// content.
+async fn download_response(
+    mut response: reqwest::Response,
+    download: &PartialDownload,
+) -> Result<http::Response<Bytes>, reqwest::Error> {
+    let status = response.status();
+
+    let received_len = {
+        let mut data = download.data.lock().unwrap();
+        let mut partial_content = download.partial_content.lock().unwrap();
+
+        if status == StatusCode::PARTIAL_CONTENT {
+            if download.range.is_some() {
+                let content_range = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(PartialContent::parse);
+
+                // The position of the received data in the whole content, which is the
+                // start of the first response when the download was resumed.
+                *partial_content = content_range.map(|content_range| PartialContent {
+                    start: content_range.start.saturating_sub(data.len() as u64),
+                    ..content_range
+                });
+            }
+        } else {
+            // The server sent the whole content, start over.
+            data.clear();
+            *partial_content = None;
+        }
+
+        let mut validator = download.validator.lock().unwrap();
+
+        if data.is_empty() {
+            // Weak ETags can't be used in `If-Range`.
+            *validator = response
+                .headers()
+                .get(ETAG)
+                .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+                .or_else(|| response.headers().get(LAST_MODIFIED))
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned);
+        }
+
+        data.len()
+    };
+
+    let content_length: Option<usize> =
+        response.content_length().and_then(|len| len.try_into().ok());
+    download.progress.set(TransmissionProgress {
+        current: received_len,
+        total: received_len + content_length.unwrap_or_default(),
+    });
+
+    // The whole content is returned, so hide that it was received in several
+    // parts.
+    let mut http_builder = http::Response::builder().status(StatusCode::OK);
+    let headers = http_builder.headers_mut().expect("Can't get the response builder headers");
+
+    for (k, v) in response.headers_mut().drain() {
+        if let Some(key) = k {
+            headers.insert(key, v);
+        }
+    }
+
+    while let Some(chunk) = response.chunk().await? {
+        download.data.lock().unwrap().extend_from_slice(&chunk);
+        download.progress.update(|progress| {
+            progress.current += chunk.len();
+            progress.total = progress.total.max(progress.current);
+        });
+    }
+
+    let body = Bytes::from(mem::take(&mut *download.data.lock().unwrap()));
+
+    Ok(http_builder.body(body).expect("Can't construct a response using the given body"))
 }
 
 struct BytesChunks {
@@ -285,7 +500,7 @@ impl Iterator for BytesChunks {
 mod tests {
     use bytes::Bytes;
 
-    use super::BytesChunks;
+    use super::{proxy_url, BytesChunks};
 
     #[test]
     fn test_bytes_chunks() {
@@ -316,4 +531,14 @@ mod tests {
             [Bytes::from_iter([1, 2]), Bytes::from_iter([3, 4])]
         );
     }
+
+    #[test]
+    fn test_proxy_url() {
+        assert_eq!(proxy_url("socks5://localhost:1080", false), "socks5://localhost:1080");
+        assert_eq!(proxy_url("socks5://localhost:1080", true), "socks5h://localhost:1080");
+        assert_eq!(proxy_url("SOCKS5://localhost:1080", true), "socks5h://localhost:1080");
+        assert_eq!(proxy_url("socks5h://localhost:1080", true), "socks5h://localhost:1080");
+        assert_eq!(proxy_url("http://localhost:8080", true), "http://localhost:8080");
+        assert_eq!(proxy_url("socks", true), "socks");
+    }
 }
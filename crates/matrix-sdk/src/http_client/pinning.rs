@@ -0,0 +1,513 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pinning of the public keys of the certificates of the servers.
+
+use std::{collections::BTreeSet, fmt, iter, sync::Arc};
+
+use ruma::serde::{base64::Standard, Base64};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, TrustAnchor, UnixTime},
+    CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::warn;
+
+/// The pin of the public key of a certificate, to use with
+/// [`ClientBuilder::pin_certificates()`].
+///
+/// It is the SHA-256 hash of the DER-encoded `SubjectPublicKeyInfo` of the
+/// certificate, like the pins used by HTTP Public Key Pinning. It can be
+/// computed from a certificate with:
+///
+/// ```sh
+/// openssl x509 -in cert.pem -pubkey -noout \
+///     | openssl pkey -pubin -outform der \
+///     | openssl dgst -sha256 -binary \
+///     | base64
+/// ```
+///
+/// [`ClientBuilder::pin_certificates()`]: crate::ClientBuilder::pin_certificates
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CertificatePin([u8; 32]);
+
+impl CertificatePin {
+    /// Create a pin from the SHA-256 hash of a `SubjectPublicKeyInfo`.
+    pub fn from_sha256(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+
+    /// Parse a pin from the base64 encoding of the SHA-256 hash, optionally
+    /// prefixed with `sha256/`.
+    pub fn from_base64(pin: &str) -> Result<Self, CertificatePinError> {
+        let pin = pin.strip_prefix("sha256/").unwrap_or(pin);
+        let bytes = Base64::<Standard>::parse(pin)
+            .map_err(|_| CertificatePinError::InvalidBase64)?
+            .into_inner();
+        let hash = bytes.try_into().map_err(|_| CertificatePinError::InvalidLength)?;
+
+        Ok(Self(hash))
+    }
+
+    /// Compute the pin of the public key of the given DER-encoded certificate.
+    pub fn from_certificate_der(certificate: &[u8]) -> Result<Self, CertificatePinError> {
+        let certificate = CertificateDer::from(certificate);
+        let certificate = webpki::EndEntityCert::try_from(&certificate)
+            .map_err(|error| CertificatePinError::InvalidCertificate(error.to_string()))?;
+
+        Ok(Self::from_spki(&certificate.subject_public_key_info()))
+    }
+
+    fn from_spki(spki: &[u8]) -> Self {
+        Self(Sha256::digest(spki).into())
+    }
+}
+
+impl fmt::Debug for CertificatePin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Pins are usually written with padding, which is always a single `=` for
+        // 32 bytes.
+        write!(f, "sha256/{}=", Base64::<Standard>::new(self.0.to_vec()).encode())
+    }
+}
+
+/// An error when creating a [`CertificatePin`].
+#[derive(Debug, Error)]
+pub enum CertificatePinError {
+    /// The pin is not valid base64.
+    #[error("the pin is not valid base64")]
+    InvalidBase64,
+
+    /// The pin is not a SHA-256 hash.
+    #[error("the pin must be a SHA-256 hash of 32 bytes")]
+    InvalidLength,
+
+    /// The certificate could not be parsed.
+    #[error("the certificate is invalid: {0}")]
+    InvalidCertificate(String),
+}
+
+/// Build the TLS configuration that only trusts the servers whose certificate
+/// chain contains one of the given pins.
+///
+/// The chains are verified against the given root certificates, the built-in
+/// ones unless `built_in_root_certificates` is `false`, and the pinned issuers.
+pub(super) fn pinned_tls_config(
+    pins: BTreeSet<CertificatePin>,
+    root_certificates: &[CertificateDer<'static>],
+    built_in_root_certificates: bool,
+) -> rustls::ClientConfig {
+    let provider = Arc::new(ring::default_provider());
+    let verifier =
+        PinningVerifier::new(pins, root_certificates, built_in_root_certificates, provider.clone());
+
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("the default protocol versions are supported by the default provider")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    config
+}
+
+/// A certificate verifier that only trusts the certificate chains that contain
+/// one of the pinned public keys.
+#[derive(Debug)]
+struct PinningVerifier {
+    pins: BTreeSet<CertificatePin>,
+    /// The trusted root certificates.
+    roots: Vec<TrustAnchor<'static>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinningVerifier {
+    fn new(
+        pins: BTreeSet<CertificatePin>,
+        root_certificates: &[CertificateDer<'static>],
+        built_in_root_certificates: bool,
+        provider: Arc<CryptoProvider>,
+    ) -> Self {
+        let mut roots = RootCertStore::empty();
+        if built_in_root_certificates {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+
+        let (_, ignored) = roots.add_parsable_certificates(root_certificates.iter().cloned());
+        if ignored > 0 {
+            warn!("Ignoring {ignored} invalid root certificates");
+        }
+
+        Self { pins, roots: roots.roots, provider }
+    }
+
+    /// Whether the public key of the given trust anchor is pinned.
+    fn is_anchor_pinned(&self, anchor: &TrustAnchor<'_>) -> bool {
+        self.pins.contains(&CertificatePin::from_spki(&der_sequence(
+            anchor.subject_public_key_info.as_ref(),
+        )))
+    }
+
+    /// Whether the verified chain contains one of the pinned public keys.
+    fn is_path_pinned(&self, path: &webpki::VerifiedPath<'_>) -> bool {
+        iter::once(&**path.end_entity()).chain(path.intermediate_certificates()).any(
+            |certificate| {
+                self.pins
+                    .contains(&CertificatePin::from_spki(&certificate.subject_public_key_info()))
+            },
+        ) || self.is_anchor_pinned(path.anchor())
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let certificate = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let is_end_entity_pinned =
+            self.pins.contains(&CertificatePin::from_spki(&certificate.subject_public_key_info()));
+
+        // The pinned issuers sent by the server are trusted, in addition to the root
+        // certificates.
+        let mut anchors = self.roots.clone();
+        for intermediate in intermediates {
+            let Ok(pin) = CertificatePin::from_certificate_der(intermediate) else {
+                return Err(rustls::Error::InvalidCertificate(CertificateError::BadEncoding));
+            };
+
+            if self.pins.contains(&pin) {
+                let anchor = webpki::anchor_from_trusted_cert(intermediate).map_err(|_| {
+                    rustls::Error::InvalidCertificate(CertificateError::BadEncoding)
+                })?;
+                anchors.push(anchor.to_owned());
+            }
+        }
+
+        let result = certificate.verify_for_usage(
+            self.provider.signature_verification_algorithms.all,
+            &anchors,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            None,
+            Some(&|path: &webpki::VerifiedPath<'_>| {
+                if self.is_path_pinned(path) {
+                    Ok(())
+                } else {
+                    Err(webpki::Error::UnknownIssuer)
+                }
+            }),
+        );
+
+        match result {
+            Ok(_) => {}
+            // The key of the server is pinned, so the handshake proves that we are talking
+            // to the right server even if its certificate is self-signed or issued by an
+            // unknown authority. It must still be valid at this time though.
+            Err(error)
+                if is_end_entity_pinned
+                    && !matches!(
+                        error,
+                        webpki::Error::CertExpired | webpki::Error::CertNotValidYet
+                    ) => {}
+            Err(error) => return Err(certificate_error(error)),
+        }
+
+        certificate
+            .verify_is_valid_for_subject_name(server_name)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::NotValidForName))?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Convert an error of the verification of a certificate chain.
+fn certificate_error(error: webpki::Error) -> rustls::Error {
+    let error = match error {
+        webpki::Error::CertExpired => CertificateError::Expired,
+        webpki::Error::CertNotValidYet => CertificateError::NotValidYet,
+        webpki::Error::UnknownIssuer => CertificateError::UnknownIssuer,
+        webpki::Error::InvalidSignatureForPublicKey
+        | webpki::Error::UnsupportedSignatureAlgorithm
+        | webpki::Error::UnsupportedSignatureAlgorithmForPublicKey => {
+            CertificateError::BadSignature
+        }
+        _ => CertificateError::ApplicationVerificationFailure,
+    };
+
+    rustls::Error::InvalidCertificate(error)
+}
+
+/// Wrap the given DER-encoded contents in a `SEQUENCE`.
+///
+/// Trust anchors only keep the contents of the `SubjectPublicKeyInfo`, which
+/// need to be wrapped to compute their pin.
+fn der_sequence(contents: &[u8]) -> Vec<u8> {
+    const SEQUENCE_TAG: u8 = 0x30;
+
+    let mut der = vec![SEQUENCE_TAG];
+
+    let len = contents.len();
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let leading_zeros = len_bytes.iter().take_while(|byte| **byte == 0).count();
+        der.push(0x80 | (len_bytes.len() - leading_zeros) as u8);
+        der.extend_from_slice(&len_bytes[leading_zeros..]);
+    }
+
+    der.extend_from_slice(contents);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use rcgen::{
+        date_time_ymd, BasicConstraints, CertificateParams, CertifiedKey, DnType, IsCa, KeyPair,
+    };
+    use rustls::{
+        client::danger::{ServerCertVerified, ServerCertVerifier},
+        crypto::ring,
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        CertificateError,
+    };
+
+    use super::{CertificatePin, CertificatePinError, PinningVerifier};
+
+    /// Generate a certificate authority with the given name.
+    fn make_ca(name: &str, issuer: Option<&CertifiedKey>) -> CertifiedKey {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+
+        sign(params, issuer)
+    }
+
+    /// Generate a certificate for `localhost`.
+    fn make_server_certificate(issuer: Option<&CertifiedKey>) -> CertifiedKey {
+        sign(CertificateParams::new(["localhost".to_owned()]).unwrap(), issuer)
+    }
+
+    /// Generate a certificate with the given parameters, issued by the given
+    /// issuer, or self-signed.
+    fn sign(params: CertificateParams, issuer: Option<&CertifiedKey>) -> CertifiedKey {
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = match issuer {
+            Some(issuer) => params.signed_by(&key_pair, &issuer.cert, &issuer.key_pair),
+            None => params.self_signed(&key_pair),
+        }
+        .unwrap();
+
+        CertifiedKey { cert, key_pair }
+    }
+
+    fn pin(certificate: &CertifiedKey) -> CertificatePin {
+        CertificatePin::from_certificate_der(certificate.cert.der()).unwrap()
+    }
+
+    fn verifier(
+        pins: impl IntoIterator<Item = CertificatePin>,
+        roots: &[&CertifiedKey],
+    ) -> PinningVerifier {
+        let roots = roots.iter().map(|root| root.cert.der().clone()).collect::<Vec<_>>();
+        PinningVerifier::new(
+            pins.into_iter().collect(),
+            &roots,
+            false,
+            Arc::new(ring::default_provider()),
+        )
+    }
+
+    /// Verify the given chain sent by `server_name`, starting with the
+    /// certificate of the server.
+    fn verify(
+        verifier: &PinningVerifier,
+        chain: &[&CertifiedKey],
+        server_name: &str,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let (end_entity, intermediates) = chain.split_first().unwrap();
+        let intermediates = intermediates
+            .iter()
+            .map(|certificate| certificate.cert.der().clone())
+            .collect::<Vec<CertificateDer<'_>>>();
+
+        verifier.verify_server_cert(
+            end_entity.cert.der(),
+            &intermediates,
+            &ServerName::try_from(server_name.to_owned()).unwrap(),
+            &[],
+            UnixTime::now(),
+        )
+    }
+
+    #[test]
+    fn test_parse_certificate_pin() {
+        let encoded = "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+
+        let pin = CertificatePin::from_base64(encoded).unwrap();
+        assert_eq!(format!("{pin:?}"), encoded);
+
+        // The prefix is optional.
+        let other_pin =
+            CertificatePin::from_base64("47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=").unwrap();
+        assert_eq!(pin, other_pin);
+
+        assert_matches!(
+            CertificatePin::from_base64("sha256/not base64"),
+            Err(CertificatePinError::InvalidBase64)
+        );
+        assert_matches!(
+            CertificatePin::from_base64("sha256/AAAA"),
+            Err(CertificatePinError::InvalidLength)
+        );
+        assert_matches!(
+            CertificatePin::from_certificate_der(b"not a certificate"),
+            Err(CertificatePinError::InvalidCertificate(_))
+        );
+    }
+
+    #[test]
+    fn test_pinned_server_certificate() {
+        let server = make_server_certificate(None);
+
+        // A self-signed certificate is trusted if its key is pinned.
+        let verifier = verifier([pin(&server)], &[]);
+        verify(&verifier, &[&server], "localhost").unwrap();
+
+        // It must still be valid for the server.
+        assert_matches!(
+            verify(&verifier, &[&server], "example.org"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::NotValidForName))
+        );
+
+        // It is rejected if its key is not pinned.
+        let verifier = verifier([CertificatePin::from_sha256([0; 32])], &[]);
+        assert_matches!(
+            verify(&verifier, &[&server], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+    }
+
+    #[test]
+    fn test_pinned_server_certificate_must_be_valid_now() {
+        let mut params = CertificateParams::new(["localhost".to_owned()]).unwrap();
+        params.not_before = date_time_ymd(2000, 1, 1);
+        params.not_after = date_time_ymd(2001, 1, 1);
+        let expired = sign(params, None);
+
+        let verifier = verifier([pin(&expired)], &[]);
+        assert_matches!(
+            verify(&verifier, &[&expired], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Expired))
+        );
+
+        let mut params = CertificateParams::new(["localhost".to_owned()]).unwrap();
+        params.not_before = date_time_ymd(4000, 1, 1);
+        params.not_after = date_time_ymd(4001, 1, 1);
+        let not_valid_yet = sign(params, None);
+
+        let verifier = verifier([pin(&not_valid_yet)], &[]);
+        assert_matches!(
+            verify(&verifier, &[&not_valid_yet], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::NotValidYet))
+        );
+    }
+
+    #[test]
+    fn test_pinned_issuer() {
+        let root = make_ca("Root", None);
+        let intermediate = make_ca("Intermediate", Some(&root));
+        let server = make_server_certificate(Some(&intermediate));
+
+        // The pinned intermediate sent by the server is trusted, even if the root is
+        // unknown.
+        let verifier = verifier([pin(&intermediate)], &[]);
+        verify(&verifier, &[&server, &intermediate], "localhost").unwrap();
+
+        // The pinned root doesn't need to be sent by the server if it is one of the
+        // root certificates.
+        let verifier = verifier([pin(&root)], &[&root]);
+        verify(&verifier, &[&server, &intermediate], "localhost").unwrap();
+
+        // But it is not trusted otherwise.
+        let verifier = verifier([pin(&root)], &[]);
+        assert_matches!(
+            verify(&verifier, &[&server, &intermediate], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+
+        // The chain must be issued by the pinned issuer.
+        let other_server = make_server_certificate(Some(&make_ca("Other", None)));
+        let verifier = verifier([pin(&intermediate)], &[]);
+        assert_matches!(
+            verify(&verifier, &[&other_server, &intermediate], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+    }
+
+    #[test]
+    fn test_pins_with_root_certificates() {
+        let root = make_ca("Root", None);
+        let server = make_server_certificate(Some(&root));
+        let pinned_ca = make_ca("Pinned", None);
+
+        // A chain issued by a root certificate is not trusted if it doesn't contain
+        // any pinned key, even if the server sends a pinned certificate along.
+        let verifier = verifier([pin(&pinned_ca)], &[&root]);
+        assert_matches!(
+            verify(&verifier, &[&server, &pinned_ca], "localhost"),
+            Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer))
+        );
+
+        // A pinned certificate issued by a root certificate is trusted.
+        let verifier = verifier([pin(&server)], &[&root]);
+        verify(&verifier, &[&server], "localhost").unwrap();
+    }
+}
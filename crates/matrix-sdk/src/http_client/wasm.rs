@@ -19,7 +19,9 @@ use bytesize::ByteSize;
 use eyeball::SharedObservable;
 use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
 
-use super::{response_to_http_response, HttpClient, TransmissionProgress};
+use super::{
+    response_to_http_response, HttpClient, PartialDownload, RequestAttempts, TransmissionProgress,
+};
 use crate::{config::RequestConfig, error::HttpError};
 
 impl HttpClient {
@@ -28,17 +30,23 @@ impl HttpClient {
         request: http::Request<Bytes>,
         _config: RequestConfig,
         _send_progress: SharedObservable<TransmissionProgress>,
+        _download: Option<PartialDownload>,
+        attempts: &RequestAttempts,
     ) -> Result<R::IncomingResponse, HttpError>
     where
         R: OutgoingRequest + Debug,
         HttpError: From<FromHttpResponseError<R::EndpointError>>,
     {
+        attempts.start();
         tracing::debug!("Sending request");
 
-        let request = reqwest::Request::try_from(request)?;
-        let response = response_to_http_response(self.inner.execute(request).await?).await?;
+        let reqwest_request = reqwest::Request::try_from(request.clone())?;
+        let response =
+            response_to_http_response(self.inner.execute(reqwest_request).await?).await?;
+        self.on_response(&request, &response);
 
         let status_code = response.status();
+        attempts.set_status(status_code);
         let response_size = ByteSize(response.body().len().try_into().unwrap_or(u64::MAX));
         tracing::Span::current()
             .record("status", status_code.as_u16())
@@ -0,0 +1,670 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A client for the [Identity Service API] of an identity server, to look up
+//! the Matrix ID associated with a [Third Party Identifier][3pid], to find
+//! which contacts of an address book are on Matrix, and to invite users by
+//! their 3PID.
+//!
+//! The identity server of the user is advertised in their account data, and
+//! can be retrieved with [`Client::identity_server()`].
+//!
+//! Identity servers usually require their terms of service to be accepted
+//! before they can be used. In that case, requests fail with
+//! [`IdentityServerError::TermsNotSigned`], and the terms that the user didn't
+//! accept yet can be presented to them with
+//! [`IdentityServer::pending_terms()`], then accepted with
+//! [`IdentityServer::accept_terms()`]. The accepted terms are tracked in the
+//! `m.accepted_terms` account data, so they don't need to be accepted again on
+//! other devices or identity servers.
+//!
+//! [Identity Service API]: https://spec.matrix.org/v1.14/identity-service-api/
+//! [3pid]: https://spec.matrix.org/v1.14/appendices/#3pid-types
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use http::{header, Method, StatusCode};
+use ruma::{
+    api::client::{
+        account::{request_openid_token, unbind_3pid, ThirdPartyIdRemovalStatus},
+        membership::ThirdPartySigned,
+    },
+    events::GlobalAccountDataEventType,
+    serde::{base64::UrlSafe, Base64, Raw},
+    thirdparty::Medium,
+    OwnedUserId, RoomId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+use crate::{account::threepid::ValidatedThreepid, Client, Error, Result, Room};
+
+/// The maximum number of addresses sent in a single lookup request.
+const LOOKUP_BATCH_SIZE: usize = 500;
+
+/// The type of the account data event that tracks the terms of service that
+/// the user accepted.
+const ACCEPTED_TERMS_EVENT_TYPE: &str = "m.accepted_terms";
+
+/// An error returned by an identity server.
+#[derive(Debug, Error)]
+pub enum IdentityServerError {
+    /// The user didn't configure an identity server in their account data.
+    #[error("no identity server is configured")]
+    NotConfigured,
+
+    /// The terms of service of the identity server must be accepted before it
+    /// can be used.
+    #[error("the terms of service of the identity server must be accepted")]
+    TermsNotSigned,
+
+    /// The identity server doesn't support any of the hashing algorithms that
+    /// the client supports for lookups.
+    #[error("the identity server doesn't support any known lookup algorithm")]
+    UnsupportedLookupAlgorithm,
+
+    /// The identity server responded with an error.
+    #[error("the identity server responded with {status}: {errcode}: {error}")]
+    Server {
+        /// The HTTP status code of the response.
+        status: StatusCode,
+        /// The error code of the response.
+        errcode: String,
+        /// The human-readable description of the error.
+        error: String,
+    },
+}
+
+/// The terms of service of an identity server.
+///
+/// They are returned by [`IdentityServer::terms()`].
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdentityServerTerms {
+    /// The policies that must be accepted, keyed by an arbitrary identifier.
+    pub policies: BTreeMap<String, TermsPolicy>,
+}
+
+impl IdentityServerTerms {
+    /// The URLs of the policies, in the given language if available, or in
+    /// any language otherwise.
+    ///
+    /// This is the list to give to [`IdentityServer::accept_terms()`] once the
+    /// user has accepted them.
+    pub fn urls(&self, language: &str) -> Vec<String> {
+        self.policies
+            .values()
+            .filter_map(|policy| {
+                policy
+                    .translations
+                    .get(language)
+                    .or_else(|| policy.translations.values().next())
+                    .map(|translation| translation.url.clone())
+            })
+            .collect()
+    }
+}
+
+/// A 3PID that is associated with a Matrix ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreepidMatch {
+    /// The type of the 3PID.
+    pub medium: Medium,
+
+    /// The address of the 3PID, as it was given.
+    pub address: String,
+
+    /// The Matrix ID associated with the 3PID.
+    pub user_id: OwnedUserId,
+}
+
+/// The hashing parameters of the lookups of an identity server.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct HashDetails {
+    algorithms: Vec<String>,
+    lookup_pepper: String,
+}
+
+/// A policy of the terms of service of an identity server.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TermsPolicy {
+    /// The version of the policy.
+    pub version: String,
+
+    /// The translations of the policy, keyed by language code.
+    #[serde(flatten)]
+    pub translations: BTreeMap<String, TermsTranslation>,
+}
+
+/// A translation of a policy of the terms of service of an identity server.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TermsTranslation {
+    /// The name of the policy in this language.
+    pub name: String,
+
+    /// The URL of the policy in this language.
+    pub url: String,
+}
+
+/// A client for an identity server.
+///
+/// It registers with the identity server the first time an authenticated
+/// request is needed, using an OpenID token of the user. The access token is
+/// then kept in memory by the [`Client`].
+#[derive(Clone, Debug)]
+pub struct IdentityServer {
+    client: Client,
+    base_url: Url,
+}
+
+impl IdentityServer {
+    /// Create a client for the identity server at the given URL.
+    pub fn new(client: Client, base_url: Url) -> Self {
+        Self { client, base_url }
+    }
+
+    /// The base URL of the identity server.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// The hostname of the identity server, with the port if it's not the
+    /// default one, as expected by the homeserver in requests that reference
+    /// an identity server.
+    pub fn server_name(&self) -> String {
+        let host = self.base_url.host_str().unwrap_or_default();
+
+        match self.base_url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_owned(),
+        }
+    }
+
+    /// Get the terms of service of the identity server.
+    pub async fn terms(&self) -> Result<IdentityServerTerms> {
+        self.send(Method::GET, "terms", None, false).await
+    }
+
+    /// Get the terms of service of the identity server that the user didn't
+    /// accept yet.
+    ///
+    /// The policies that the user already accepted, according to the
+    /// `m.accepted_terms` account data, are accepted with the identity server
+    /// if necessary and aren't returned. If the returned terms have no
+    /// policies, the identity server can be used.
+    pub async fn pending_terms(&self) -> Result<IdentityServerTerms> {
+        let mut terms = self.terms().await?;
+        let accepted_urls = self.accepted_terms().await?;
+
+        let (accepted, pending) = terms.policies.into_iter().partition(|(_, policy)| {
+            policy.translations.values().any(|translation| accepted_urls.contains(&translation.url))
+        });
+        terms.policies = pending;
+
+        let accepted = IdentityServerTerms { policies: accepted };
+        let urls = accepted
+            .policies
+            .values()
+            .flat_map(|policy| policy.translations.values())
+            .filter(|translation| accepted_urls.contains(&translation.url))
+            .map(|translation| translation.url.clone())
+            .collect::<Vec<_>>();
+
+        if !urls.is_empty() {
+            let body = json!({ "user_accepts": urls });
+            self.send::<JsonValue>(Method::POST, "terms", Some(body), true).await?;
+        }
+
+        Ok(terms)
+    }
+
+    /// Accept the terms of service of the identity server.
+    ///
+    /// The URLs are also added to the `m.accepted_terms` account data.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs of the policies that the user accepted, as returned
+    ///   by [`IdentityServerTerms::urls()`].
+    pub async fn accept_terms(&self, urls: Vec<String>) -> Result<()> {
+        let body = json!({ "user_accepts": urls });
+        self.send::<JsonValue>(Method::POST, "terms", Some(body), true).await?;
+
+        let mut accepted_urls = self.accepted_terms().await?;
+        let len = accepted_urls.len();
+        accepted_urls.extend(urls);
+
+        if accepted_urls.len() != len {
+            let content = Raw::new(&json!({ "accepted": accepted_urls }))?.cast();
+            self.client
+                .account()
+                .set_account_data_raw(ACCEPTED_TERMS_EVENT_TYPE.into(), content)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The URLs of the policies that the user accepted, according to the
+    /// `m.accepted_terms` account data.
+    async fn accepted_terms(&self) -> Result<BTreeSet<String>> {
+        #[derive(Default, Deserialize)]
+        struct AcceptedTermsEventContent {
+            #[serde(default)]
+            accepted: BTreeSet<String>,
+        }
+
+        let event_type = GlobalAccountDataEventType::from(ACCEPTED_TERMS_EVENT_TYPE);
+        let content = match self.client.account().account_data_raw(event_type).await? {
+            Some(content) => content.deserialize_as::<AcceptedTermsEventContent>()?,
+            None => AcceptedTermsEventContent::default(),
+        };
+
+        Ok(content.accepted)
+    }
+
+    /// Bind a 3PID whose ownership has been validated to this identity server,
+    /// so other users can find the account with it.
+    pub async fn bind(&self, threepid: &ValidatedThreepid) -> Result<()> {
+        threepid.bind(&self.server_name(), &self.access_token().await?).await
+    }
+
+    /// Unbind a 3PID from this identity server, so other users can't find the
+    /// account with it anymore.
+    ///
+    /// The homeserver does the request to the identity server. Returns whether
+    /// it succeeded.
+    pub async fn unbind(&self, medium: Medium, address: &str) -> Result<ThirdPartyIdRemovalStatus> {
+        let mut request = unbind_3pid::v3::Request::new(medium, address.to_owned());
+        request.id_server = Some(self.server_name());
+
+        Ok(self.client.send(request).await?.id_server_unbind_result)
+    }
+
+    /// Get an access token for the identity server, registering with it if
+    /// necessary.
+    pub async fn access_token(&self) -> Result<String> {
+        let access_tokens = &self.client.inner.caches.identity_server_tokens;
+
+        // Don't hold the lock during the requests, so the other requests to the
+        // identity servers aren't blocked if registering takes a while.
+        if let Some(access_token) = access_tokens.lock().await.get(&self.base_url) {
+            return Ok(access_token.clone());
+        }
+
+        #[derive(Serialize)]
+        struct RegisterRequest {
+            access_token: String,
+            token_type: String,
+            matrix_server_name: String,
+            expires_in: u64,
+        }
+
+        #[derive(Deserialize)]
+        struct RegisterResponse {
+            token: String,
+        }
+
+        let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
+        let openid_token =
+            self.client.send(request_openid_token::v3::Request::new(user_id.to_owned())).await?;
+
+        let body = serde_json::to_value(RegisterRequest {
+            access_token: openid_token.access_token,
+            token_type: openid_token.token_type.to_string(),
+            matrix_server_name: openid_token.matrix_server_name.to_string(),
+            expires_in: openid_token.expires_in.as_secs(),
+        })?;
+        let response: RegisterResponse =
+            self.send_with_token(Method::POST, "account/register", Some(body), None).await?;
+
+        access_tokens.lock().await.insert(self.base_url.clone(), response.token.clone());
+        Ok(response.token)
+    }
+
+    /// Exchange the token of an invitation sent to a 3PID of the user for a
+    /// signed proof that the user owns the 3PID.
+    ///
+    /// The token is received by the user out of band, for example in the
+    /// email sent by this identity server. The proof can be used to join the
+    /// room with [`Client::join_room_by_third_party_signed()`].
+    pub async fn sign_invitation(&self, token: &str) -> Result<ThirdPartySigned> {
+        let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
+        let body = json!({ "mxid": user_id, "token": token });
+
+        self.send(Method::POST, "sign-ed25519", Some(body), true).await
+    }
+
+    /// Join a room with the token of an invitation sent to a 3PID of the user.
+    ///
+    /// This is a shortcut for [`IdentityServer::sign_invitation()`] followed
+    /// by [`Client::join_room_by_third_party_signed()`].
+    pub async fn join_room_by_invitation(&self, room_id: &RoomId, token: &str) -> Result<Room> {
+        let third_party_signed = self.sign_invitation(token).await?;
+        self.client.join_room_by_third_party_signed(room_id, third_party_signed).await
+    }
+
+    /// Look up the Matrix ID associated with the given 3PID.
+    ///
+    /// The 3PID is hashed before being sent to the identity server, if it
+    /// supports it.
+    ///
+    /// Returns `None` if no Matrix ID is associated with the 3PID.
+    pub async fn lookup(&self, medium: Medium, address: &str) -> Result<Option<OwnedUserId>> {
+        let matches = self.lookup_many([(medium, address.to_owned())]).await?;
+        Ok(matches.into_iter().next().map(|threepid_match| threepid_match.user_id))
+    }
+
+    /// Look up the Matrix IDs associated with the given 3PIDs.
+    ///
+    /// The 3PIDs are hashed before being sent to the identity server, if it
+    /// supports it. If the identity server rotated its pepper since the last
+    /// lookup, the hashes are computed again with the new one.
+    ///
+    /// Returns the 3PIDs that are associated with a Matrix ID.
+    pub async fn lookup_many(
+        &self,
+        threepids: impl IntoIterator<Item = (Medium, String)>,
+    ) -> Result<Vec<ThreepidMatch>> {
+        let threepids = threepids.into_iter().collect::<Vec<_>>();
+        let mut matches = Vec::new();
+
+        for batch in threepids.chunks(LOOKUP_BATCH_SIZE) {
+            let hash_details = self.hash_details(false).await?;
+
+            let batch_matches = match self.lookup_batch(batch, &hash_details).await {
+                Err(Error::IdentityServer(IdentityServerError::Server { errcode, .. }))
+                    if errcode == "M_INVALID_PEPPER" =>
+                {
+                    let hash_details = self.hash_details(true).await?;
+                    self.lookup_batch(batch, &hash_details).await?
+                }
+                result => result?,
+            };
+
+            matches.extend(batch_matches);
+        }
+
+        Ok(matches)
+    }
+
+    /// Find which contacts of an address book are on Matrix.
+    ///
+    /// Each contact of the address book is identified by a key chosen by the
+    /// caller, and has a list of 3PIDs. Phone numbers must be in international
+    /// format, the formatting characters are ignored.
+    ///
+    /// Returns the Matrix IDs associated with the 3PIDs of each contact, for
+    /// the contacts that have at least one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let client: matrix_sdk::Client = todo!();
+    /// use matrix_sdk::ruma::thirdparty::Medium;
+    ///
+    /// let Some(identity_server) = client.identity_server().await? else {
+    ///     return Ok(());
+    /// };
+    ///
+    /// let address_book = vec![
+    ///     ("Alice", vec![(Medium::Email, "alice@example.org".to_owned())]),
+    ///     ("Bob", vec![(Medium::Msisdn, "+44 7700 900123".to_owned())]),
+    /// ];
+    ///
+    /// for (contact, user_ids) in
+    ///     identity_server.discover_contacts(address_book).await?
+    /// {
+    ///     println!("{contact} is on Matrix: {user_ids:?}");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn discover_contacts<K: Ord>(
+        &self,
+        address_book: impl IntoIterator<Item = (K, Vec<(Medium, String)>)>,
+    ) -> Result<BTreeMap<K, BTreeSet<OwnedUserId>>> {
+        // `Medium` doesn't implement `Ord`, so use its string representation in the
+        // key.
+        let mut contacts_by_threepid = BTreeMap::<(String, String), Vec<usize>>::new();
+        let mut keys = Vec::new();
+
+        for (index, (key, threepids)) in address_book.into_iter().enumerate() {
+            keys.push(Some(key));
+
+            for (medium, address) in threepids {
+                let address = normalize_address(&medium, &address);
+                contacts_by_threepid
+                    .entry((medium.as_str().to_owned(), address))
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let threepids = contacts_by_threepid
+            .keys()
+            .map(|(medium, address)| (medium.as_str().into(), address.clone()));
+        let matches = self.lookup_many(threepids).await?;
+        let mut user_ids_by_contact = BTreeMap::<usize, BTreeSet<OwnedUserId>>::new();
+
+        for threepid_match in matches {
+            let key = (threepid_match.medium.as_str().to_owned(), threepid_match.address);
+            let Some(indices) = contacts_by_threepid.get(&key) else {
+                continue;
+            };
+
+            for index in indices {
+                user_ids_by_contact
+                    .entry(*index)
+                    .or_default()
+                    .insert(threepid_match.user_id.clone());
+            }
+        }
+
+        Ok(user_ids_by_contact
+            .into_iter()
+            .filter_map(|(index, user_ids)| Some((keys[index].take()?, user_ids)))
+            .collect())
+    }
+
+    /// Get the hashing parameters of the lookups, from the cache unless
+    /// `refresh` is `true`.
+    async fn hash_details(&self, refresh: bool) -> Result<HashDetails> {
+        let cache = &self.client.inner.caches.identity_server_hash_details;
+
+        if !refresh {
+            if let Some(hash_details) = cache.lock().await.get(&self.base_url) {
+                return Ok(hash_details.clone());
+            }
+        }
+
+        let hash_details: HashDetails = self.send(Method::GET, "hash_details", None, true).await?;
+        cache.lock().await.insert(self.base_url.clone(), hash_details.clone());
+
+        Ok(hash_details)
+    }
+
+    /// Look up a batch of 3PIDs with the given hashing parameters.
+    async fn lookup_batch(
+        &self,
+        threepids: &[(Medium, String)],
+        hash_details: &HashDetails,
+    ) -> Result<Vec<ThreepidMatch>> {
+        #[derive(Deserialize)]
+        struct LookupResponse {
+            mappings: BTreeMap<String, OwnedUserId>,
+        }
+
+        let use_hash = if hash_details.algorithms.iter().any(|algorithm| algorithm == "sha256") {
+            true
+        } else if hash_details.algorithms.iter().any(|algorithm| algorithm == "none") {
+            false
+        } else {
+            return Err(IdentityServerError::UnsupportedLookupAlgorithm.into());
+        };
+
+        let lookup_addresses = threepids
+            .iter()
+            .map(|(medium, address)| {
+                let address = normalize_address(medium, address);
+
+                if use_hash {
+                    hash_3pid(medium, &address, &hash_details.lookup_pepper)
+                } else {
+                    format!("{address} {medium}")
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let body = json!({
+            "addresses": lookup_addresses,
+            "algorithm": if use_hash { "sha256" } else { "none" },
+            "pepper": hash_details.lookup_pepper,
+        });
+        let mut response: LookupResponse =
+            self.send(Method::POST, "lookup", Some(body), true).await?;
+
+        Ok(threepids
+            .iter()
+            .zip(lookup_addresses)
+            .filter_map(|((medium, address), lookup_address)| {
+                Some(ThreepidMatch {
+                    medium: medium.clone(),
+                    address: address.clone(),
+                    user_id: response.mappings.remove(&lookup_address)?,
+                })
+            })
+            .collect())
+    }
+
+    /// Send a request to the Identity Service API and deserialize the
+    /// response.
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<JsonValue>,
+        authenticated: bool,
+    ) -> Result<T> {
+        let access_token = if authenticated { Some(self.access_token().await?) } else { None };
+        self.send_with_token(method, path, body, access_token).await
+    }
+
+    /// Send a request to the Identity Service API with the given access token
+    /// and deserialize the response.
+    async fn send_with_token<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<JsonValue>,
+        access_token: Option<String>,
+    ) -> Result<T> {
+        #[derive(Deserialize)]
+        struct ErrorResponse {
+            errcode: String,
+            #[serde(default)]
+            error: String,
+        }
+
+        let url = self
+            .base_url
+            .join(&format!("_matrix/identity/v2/{path}"))
+            .map_err(|error| Error::UnknownError(error.into()))?;
+
+        let mut request = self.client.http_client().request(method, url);
+
+        if let Some(access_token) = &access_token {
+            request = request.bearer_auth(access_token);
+        }
+
+        if let Some(body) = body {
+            request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&body)?);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+
+        if status.is_success() {
+            return Ok(serde_json::from_slice(&bytes)?);
+        }
+
+        // Keep the status of the response if the body isn't a Matrix error, for
+        // example when it comes from a reverse proxy.
+        let ErrorResponse { errcode, error } =
+            serde_json::from_slice(&bytes).unwrap_or_else(|_| ErrorResponse {
+                errcode: "M_UNKNOWN".to_owned(),
+                error: String::from_utf8_lossy(&bytes).into_owned(),
+            });
+
+        if errcode == "M_TERMS_NOT_SIGNED" {
+            return Err(IdentityServerError::TermsNotSigned.into());
+        }
+
+        if access_token.is_some() && errcode == "M_UNAUTHORIZED" {
+            // The access token expired, register again on the next request.
+            self.client.inner.caches.identity_server_tokens.lock().await.remove(&self.base_url);
+        }
+
+        Err(IdentityServerError::Server { status, errcode, error }.into())
+    }
+}
+
+/// Normalize the address of a 3PID like identity servers do.
+///
+/// Email addresses are case-insensitive so they are lowercased, and phone
+/// numbers only keep their digits, without the trunk prefix in parentheses
+/// that is sometimes written after the country code, like in
+/// `+44 (0)7700 900123`.
+fn normalize_address(medium: &Medium, address: &str) -> String {
+    match medium {
+        Medium::Email => address.trim().to_lowercase(),
+        Medium::Msisdn => address.replace("(0)", "").chars().filter(char::is_ascii_digit).collect(),
+        _ => address.to_owned(),
+    }
+}
+
+/// Hash a 3PID with the `sha256` algorithm of the Identity Service API.
+fn hash_3pid(medium: &Medium, address: &str, pepper: &str) -> String {
+    let hash = Sha256::digest(format!("{address} {medium} {pepper}"));
+    Base64::<UrlSafe>::new(hash.to_vec()).encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::thirdparty::Medium;
+
+    use super::{hash_3pid, normalize_address};
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(normalize_address(&Medium::Email, " Alice@Example.com"), "alice@example.com");
+        assert_eq!(normalize_address(&Medium::Msisdn, "+44 (0)7700-900123"), "447700900123");
+        assert_eq!(normalize_address(&Medium::Msisdn, "+44 7700 900123"), "447700900123");
+        assert_eq!(normalize_address(&Medium::Msisdn, "(020) 7946 0018"), "02079460018");
+    }
+
+    #[test]
+    fn test_hash_3pid() {
+        // Example from the specification.
+        assert_eq!(
+            hash_3pid(&Medium::Email, "alice@example.com", "matrixrocks"),
+            "4kenr7N9drpCJ4AfalmlGQVsOn3o2RHjkADUpXJWZUc"
+        );
+    }
+}
@@ -0,0 +1,611 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Image packs, to build sticker and custom emoji pickers ([MSC2545]).
+//!
+//! The user can have their own image pack in their account data, available with
+//! [`Account::image_pack()`], and rooms can define image packs in their state,
+//! available with [`Room::image_packs()`]. The user can also enable the image
+//! packs of some rooms globally, so they can be used in all rooms.
+//!
+//! All the image packs that can be used in a room are resolved by
+//! [`Room::available_image_packs()`], and their images can be sent as stickers
+//! with [`Room::send_sticker()`].
+//!
+//! The custom emotes of these image packs are gathered in an [`EmoteRegistry`]
+//! keyed by shortcode, that can be observed with [`Room::observe_emotes()`],
+//! and that can find the custom emotes used in messages.
+//!
+//! The unstable event types of the proposal are used, as they are the ones
+//! implemented by other clients.
+//!
+//! [MSC2545]: https://github.com/matrix-org/matrix-spec-proposals/pull/2545
+
+use std::{
+    collections::{btree_map, BTreeMap},
+    ops::Range,
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::deserialized_responses::SyncOrStrippedState;
+pub use ruma::events::image_pack::{
+    AccountImagePackEventContent, ImagePackRoomContent, ImagePackRoomsEventContent, PackImage,
+    PackInfo, PackUsage, RoomImagePackEventContent,
+};
+use ruma::{
+    events::{
+        room::{message::MessageType, ImageInfo, MediaSource},
+        sticker::StickerEventContent,
+        GlobalAccountDataEvent, SyncStateEvent,
+    },
+    html::{Html, NodeData, NodeRef},
+    MxcUri, OwnedMxcUri, OwnedRoomId, RoomId,
+};
+use thiserror::Error;
+use tracing::warn;
+
+#[cfg(doc)]
+use crate::Account;
+use crate::{
+    event_handler::EventHandlerDropGuard,
+    media::{MediaFormat, MediaRequestParameters},
+    Client, Result, Room,
+};
+
+/// An error when using an image pack.
+#[derive(Debug, Error)]
+pub enum ImagePackError {
+    /// The image pack doesn't have an image with this shortcode.
+    #[error("the image pack doesn't have an image with the shortcode `{0}`")]
+    UnknownShortcode(String),
+
+    /// The image with this shortcode can't be used as a sticker.
+    #[error("the image with the shortcode `{0}` can't be used as a sticker")]
+    NotASticker(String),
+}
+
+/// Where an image pack is defined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImagePackSource {
+    /// The image pack of the user, in their account data.
+    User,
+
+    /// An image pack in the state of a room.
+    Room {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+
+        /// The state key of the state event of the image pack.
+        state_key: String,
+    },
+}
+
+/// An image pack, with the images that can be used as stickers or custom
+/// emojis.
+#[derive(Clone, Debug)]
+pub struct ImagePack {
+    client: Client,
+    source: ImagePackSource,
+    images: BTreeMap<String, PackImage>,
+    info: Option<PackInfo>,
+}
+
+impl ImagePack {
+    /// The image pack of the user, from the account data in the store.
+    pub(crate) async fn load_user_pack(client: &Client) -> Result<Option<Self>> {
+        let Some(raw_content) =
+            client.account().account_data::<AccountImagePackEventContent>().await?
+        else {
+            return Ok(None);
+        };
+
+        let content = raw_content.deserialize()?;
+
+        Ok(Some(Self {
+            client: client.clone(),
+            source: ImagePackSource::User,
+            images: content.images,
+            info: content.pack,
+        }))
+    }
+
+    /// The image packs of the given room, from the state in the store.
+    pub(crate) async fn load_room_packs(room: &Room) -> Result<Vec<Self>> {
+        let events = room.get_state_events_static::<RoomImagePackEventContent>().await?;
+        let mut packs = Vec::with_capacity(events.len());
+
+        for event in events {
+            let Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event))) =
+                event.deserialize().inspect_err(|error| {
+                    warn!(room_id = ?room.room_id(), "Failed to deserialize image pack: {error}");
+                })
+            else {
+                continue;
+            };
+
+            packs.push(Self::from_room_event(
+                room,
+                event.state_key,
+                event.content.images,
+                event.content.pack,
+            ));
+        }
+
+        Ok(packs)
+    }
+
+    /// The image packs of rooms that the user enabled globally, from the
+    /// account data and the state in the store.
+    ///
+    /// The packs of rooms that aren't known by the client are ignored.
+    pub(crate) async fn load_enabled_room_packs(client: &Client) -> Result<Vec<Self>> {
+        let Some(raw_content) =
+            client.account().account_data::<ImagePackRoomsEventContent>().await?
+        else {
+            return Ok(Vec::new());
+        };
+
+        let content = raw_content.deserialize()?;
+        let mut packs = Vec::new();
+
+        for (room_id, state_keys) in content.rooms {
+            let Some(room) = client.get_room(&room_id) else {
+                continue;
+            };
+
+            packs.extend(
+                Self::load_room_packs(&room)
+                    .await?
+                    .into_iter()
+                    .filter(|pack| state_keys.contains_key(pack.state_key().unwrap_or_default())),
+            );
+        }
+
+        Ok(packs)
+    }
+
+    fn from_room_event(
+        room: &Room,
+        state_key: String,
+        images: BTreeMap<String, PackImage>,
+        info: Option<PackInfo>,
+    ) -> Self {
+        Self {
+            client: room.client(),
+            source: ImagePackSource::Room { room_id: room.room_id().to_owned(), state_key },
+            images,
+            info,
+        }
+    }
+
+    /// Where this image pack is defined.
+    pub fn source(&self) -> &ImagePackSource {
+        &self.source
+    }
+
+    /// The ID of the room where this image pack is defined, if any.
+    pub fn room_id(&self) -> Option<&RoomId> {
+        match &self.source {
+            ImagePackSource::User => None,
+            ImagePackSource::Room { room_id, .. } => Some(room_id),
+        }
+    }
+
+    fn state_key(&self) -> Option<&str> {
+        match &self.source {
+            ImagePackSource::User => None,
+            ImagePackSource::Room { state_key, .. } => Some(state_key),
+        }
+    }
+
+    /// The information about this image pack, if any.
+    pub fn info(&self) -> Option<&PackInfo> {
+        self.info.as_ref()
+    }
+
+    /// The display name of this image pack.
+    ///
+    /// If the image pack doesn't have a display name and it is defined in a
+    /// room, the name of the room is used.
+    pub fn display_name(&self) -> Option<String> {
+        if let Some(display_name) = self.info.as_ref().and_then(|info| info.display_name.clone()) {
+            return Some(display_name);
+        }
+
+        self.client.get_room(self.room_id()?)?.name()
+    }
+
+    /// The images of this image pack, keyed by shortcode.
+    pub fn images(&self) -> &BTreeMap<String, PackImage> {
+        &self.images
+    }
+
+    /// Get the image with the given shortcode.
+    pub fn image(&self, shortcode: &str) -> Option<&PackImage> {
+        self.images.get(shortcode)
+    }
+
+    /// The images of this image pack that can be used as stickers, with their
+    /// shortcode.
+    pub fn stickers(&self) -> impl Iterator<Item = (&str, &PackImage)> {
+        self.images_with_usage(PackUsage::Sticker)
+    }
+
+    /// The images of this image pack that can be used as custom emojis, with
+    /// their shortcode.
+    pub fn emoticons(&self) -> impl Iterator<Item = (&str, &PackImage)> {
+        self.images_with_usage(PackUsage::Emoticon)
+    }
+
+    fn images_with_usage(&self, usage: PackUsage) -> impl Iterator<Item = (&str, &PackImage)> {
+        self.images
+            .iter()
+            .filter(move |(_, image)| self.has_usage(image, &usage))
+            .map(|(shortcode, image)| (shortcode.as_str(), image))
+    }
+
+    /// Whether the given image can be used for the given usage.
+    ///
+    /// The usages of the image take precedence over the ones of the pack, and
+    /// an image without usages can be used for anything.
+    fn has_usage(&self, image: &PackImage, usage: &PackUsage) -> bool {
+        let usages = if !image.usage.is_empty() {
+            &image.usage
+        } else if let Some(info) = self.info.as_ref().filter(|info| !info.usage.is_empty()) {
+            &info.usage
+        } else {
+            return true;
+        };
+
+        usages.contains(usage)
+    }
+
+    /// Get the media content of the image with the given shortcode.
+    ///
+    /// The media is cached in the media store, so sticker pickers can load it
+    /// again cheaply.
+    ///
+    /// Returns `None` if the image pack doesn't have an image with this
+    /// shortcode.
+    pub async fn image_content(
+        &self,
+        shortcode: &str,
+        format: MediaFormat,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(image) = self.image(shortcode) else {
+            return Ok(None);
+        };
+
+        let request =
+            MediaRequestParameters { source: MediaSource::Plain(image.url.clone()), format };
+        Ok(Some(self.client.media().get_media_content(&request, true).await?))
+    }
+
+    /// Create the content of the sticker event for the image with the given
+    /// shortcode.
+    pub fn sticker_content(&self, shortcode: &str) -> Result<StickerEventContent, ImagePackError> {
+        let image = self
+            .image(shortcode)
+            .ok_or_else(|| ImagePackError::UnknownShortcode(shortcode.to_owned()))?;
+
+        if !self.has_usage(image, &PackUsage::Sticker) {
+            return Err(ImagePackError::NotASticker(shortcode.to_owned()));
+        }
+
+        let body = image.body.clone().unwrap_or_else(|| shortcode.to_owned());
+        let info = image.info.clone().unwrap_or_else(ImageInfo::new);
+
+        Ok(StickerEventContent::new(body, info, image.url.clone()))
+    }
+}
+
+/// A custom emote, an image that can be used inline in messages.
+#[derive(Clone, Debug)]
+pub struct CustomEmote {
+    /// The shortcode of the emote, without the surrounding colons.
+    pub shortcode: String,
+
+    /// The MXC URI of the image of the emote.
+    pub url: OwnedMxcUri,
+
+    /// The alternative text of the emote, if any.
+    pub body: Option<String>,
+
+    /// The metadata of the image of the emote, if any.
+    pub info: Option<ImageInfo>,
+
+    /// The image pack defining the emote, or `None` if the emote was found in
+    /// a message and isn't part of the known image packs.
+    pub pack: Option<ImagePackSource>,
+}
+
+impl CustomEmote {
+    /// The text that should be displayed instead of the image of the emote.
+    ///
+    /// Defaults to the shortcode surrounded by colons.
+    pub fn alt_text(&self) -> String {
+        self.body.clone().unwrap_or_else(|| format!(":{}:", self.shortcode))
+    }
+}
+
+/// A custom emote found in a text.
+#[derive(Clone, Debug)]
+pub struct EmoteMatch {
+    /// The range of the shortcode of the emote in the text, including the
+    /// surrounding colons.
+    pub range: Range<usize>,
+
+    /// The matching emote.
+    pub emote: CustomEmote,
+}
+
+/// The custom emotes of a set of image packs, keyed by shortcode.
+///
+/// It's returned by [`Room::emotes()`] and [`Account::emotes()`].
+#[derive(Clone, Debug)]
+pub struct EmoteRegistry {
+    client: Client,
+    emotes: BTreeMap<String, CustomEmote>,
+}
+
+impl EmoteRegistry {
+    /// Gather the emotes of the given image packs.
+    ///
+    /// When several image packs define the same shortcode, the emote of the
+    /// first pack is used.
+    pub(crate) fn new(client: Client, packs: Vec<ImagePack>) -> Self {
+        let mut emotes = BTreeMap::new();
+
+        for pack in &packs {
+            for (shortcode, image) in pack.emoticons() {
+                if let btree_map::Entry::Vacant(entry) = emotes.entry(shortcode.to_owned()) {
+                    entry.insert(CustomEmote {
+                        shortcode: shortcode.to_owned(),
+                        url: image.url.clone(),
+                        body: image.body.clone(),
+                        info: image.info.clone(),
+                        pack: Some(pack.source().clone()),
+                    });
+                }
+            }
+        }
+
+        Self { client, emotes }
+    }
+
+    /// Get the emote with the given shortcode.
+    pub fn get(&self, shortcode: &str) -> Option<&CustomEmote> {
+        self.emotes.get(shortcode)
+    }
+
+    /// Get the emote with the given image.
+    pub fn get_by_url(&self, url: &MxcUri) -> Option<&CustomEmote> {
+        self.emotes.values().find(|emote| emote.url == url)
+    }
+
+    /// Iterate over the emotes, ordered by shortcode.
+    pub fn iter(&self) -> impl Iterator<Item = &CustomEmote> {
+        self.emotes.values()
+    }
+
+    /// The number of emotes.
+    pub fn len(&self) -> usize {
+        self.emotes.len()
+    }
+
+    /// Whether there are no emotes.
+    pub fn is_empty(&self) -> bool {
+        self.emotes.is_empty()
+    }
+
+    /// Get the media content of the image of the given emote.
+    ///
+    /// The media is cached in the media store, so emotes that are used often
+    /// are only downloaded once.
+    pub async fn image_content(&self, emote: &CustomEmote, format: MediaFormat) -> Result<Vec<u8>> {
+        let request =
+            MediaRequestParameters { source: MediaSource::Plain(emote.url.clone()), format };
+        self.client.media().get_media_content(&request, true).await
+    }
+
+    /// Find the shortcodes of the emotes in the given plain text, like
+    /// `:shortcode:`.
+    pub fn find_in_text(&self, text: &str) -> Vec<EmoteMatch> {
+        let colons = text.match_indices(':').map(|(index, _)| index).collect::<Vec<_>>();
+        let mut matches = Vec::new();
+        let mut colons = colons.iter().peekable();
+
+        while let Some(&start) = colons.next() {
+            let Some(&&end) = colons.peek() else {
+                break;
+            };
+
+            let shortcode = &text[start + 1..end];
+
+            if shortcode.is_empty() || shortcode.contains(char::is_whitespace) {
+                continue;
+            }
+
+            if let Some(emote) = self.get(shortcode) {
+                matches.push(EmoteMatch { range: start..end + 1, emote: emote.clone() });
+                // The closing colon can't start another shortcode.
+                colons.next();
+            }
+        }
+
+        matches
+    }
+
+    /// Find the emotes in the given HTML, as images with the
+    /// `data-mx-emoticon` attribute.
+    ///
+    /// The emotes that aren't part of this registry are also returned, with
+    /// their shortcode taken from the alternative text of the image.
+    pub fn find_in_html(&self, html: &str) -> Vec<CustomEmote> {
+        let html = Html::parse(html);
+        let mut emotes = Vec::new();
+        let mut nodes = html.children().collect::<Vec<_>>();
+
+        while let Some(node) = nodes.pop() {
+            if let Some(emote) = self.emote_from_node(&node) {
+                emotes.push(emote);
+            }
+
+            nodes.extend(node.children());
+        }
+
+        emotes.reverse();
+        emotes
+    }
+
+    fn emote_from_node(&self, node: &NodeRef) -> Option<CustomEmote> {
+        let NodeData::Element(element) = node.data() else {
+            return None;
+        };
+
+        if &*element.name.local != "img" {
+            return None;
+        }
+
+        let attrs = element.attrs.borrow();
+        let attr = |name: &str| {
+            attrs.iter().find(|attr| &*attr.name.local == name).map(|attr| attr.value.to_string())
+        };
+
+        attr("data-mx-emoticon")?;
+        let url = OwnedMxcUri::from(attr("src")?);
+
+        if let Some(emote) = self.get_by_url(&url) {
+            return Some(emote.clone());
+        }
+
+        let alt = attr("alt").or_else(|| attr("title"));
+        let shortcode = alt.as_deref().unwrap_or_default().trim_matches(':').to_owned();
+
+        Some(CustomEmote { shortcode, url, body: alt, info: None, pack: None })
+    }
+
+    /// Find the emotes used in the given message.
+    ///
+    /// The emotes are found in the formatted body of the message if it has
+    /// one, and in its plain text body otherwise.
+    pub fn find_in_message(&self, msgtype: &MessageType) -> Vec<CustomEmote> {
+        let (body, formatted) = match msgtype {
+            MessageType::Emote(content) => (&content.body, &content.formatted),
+            MessageType::Notice(content) => (&content.body, &content.formatted),
+            MessageType::Text(content) => (&content.body, &content.formatted),
+            _ => return Vec::new(),
+        };
+
+        match formatted {
+            Some(formatted) => self.find_in_html(&formatted.body),
+            None => {
+                self.find_in_text(body).into_iter().map(|emote_match| emote_match.emote).collect()
+            }
+        }
+    }
+}
+
+/// An observable [`EmoteRegistry`], that is updated when the image packs
+/// change.
+///
+/// It's returned by [`Room::observe_emotes()`] and
+/// [`Account::observe_emotes()`].
+#[derive(Debug)]
+pub struct ObservableEmotes {
+    observable: SharedObservable<EmoteRegistry>,
+    _event_handler_guards: Vec<EventHandlerDropGuard>,
+}
+
+impl ObservableEmotes {
+    /// Observe the emotes that can be used in the given room, or in all rooms
+    /// if `room_id` is `None`.
+    pub(crate) async fn new(client: &Client, room_id: Option<OwnedRoomId>) -> Result<Self> {
+        let observable = SharedObservable::new(EmoteRegistry::new(client.clone(), Vec::new()));
+
+        // Any change to an image pack can change the emotes, because of the rooms
+        // whose image packs are enabled globally. Register the event handlers
+        // before loading the emotes from the store, so no update can be missed in
+        // between.
+        let handle_update = {
+            let observable = observable.clone();
+            let room_id = room_id.clone();
+            move |client: Client| {
+                let observable = observable.clone();
+                let room_id = room_id.clone();
+
+                async move {
+                    match load_emotes(&client, room_id.as_deref()).await {
+                        Ok(emotes) => {
+                            observable.set(emotes);
+                        }
+                        Err(error) => warn!("Failed to reload the emotes: {error}"),
+                    }
+                }
+            }
+        };
+
+        let event_handler_guards = vec![
+            client.event_handler_drop_guard(client.add_event_handler({
+                let handle_update = handle_update.clone();
+                move |_: GlobalAccountDataEvent<AccountImagePackEventContent>, client: Client| {
+                    handle_update(client)
+                }
+            })),
+            client.event_handler_drop_guard(client.add_event_handler({
+                let handle_update = handle_update.clone();
+                move |_: GlobalAccountDataEvent<ImagePackRoomsEventContent>, client: Client| {
+                    handle_update(client)
+                }
+            })),
+            client.event_handler_drop_guard(client.add_event_handler({
+                let handle_update = handle_update.clone();
+                move |_: SyncStateEvent<RoomImagePackEventContent>, client: Client| {
+                    handle_update(client)
+                }
+            })),
+        ];
+
+        observable.set(load_emotes(client, room_id.as_deref()).await?);
+
+        Ok(Self { observable, _event_handler_guards: event_handler_guards })
+    }
+
+    /// Get the current emotes.
+    pub fn get(&self) -> EmoteRegistry {
+        self.observable.get()
+    }
+
+    /// Subscribe to the updates of the emotes.
+    ///
+    /// The returned [`Subscriber`] implements `Stream`, yielding the new
+    /// emotes each time they change. It ends when this observer is dropped.
+    pub fn subscribe(&self) -> Subscriber<EmoteRegistry> {
+        self.observable.subscribe()
+    }
+}
+
+/// Load the emotes that can be used in the given room, or in all rooms if
+/// `room_id` is `None`, from the store.
+pub(crate) async fn load_emotes(
+    client: &Client,
+    room_id: Option<&RoomId>,
+) -> Result<EmoteRegistry> {
+    let packs = match room_id.and_then(|room_id| client.get_room(room_id)) {
+        Some(room) => room.available_image_packs().await?,
+        None => client.account().image_packs().await?,
+    };
+
+    Ok(EmoteRegistry::new(client.clone(), packs))
+}
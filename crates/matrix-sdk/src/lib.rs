@@ -32,26 +32,45 @@ pub use matrix_sdk_base::{
 };
 pub use matrix_sdk_common::*;
 pub use reqwest;
+#[cfg(not(target_arch = "wasm32"))]
+pub use rustls_pki_types;
 
-mod account;
+pub mod account;
+pub mod account_export;
 pub mod attachment;
 pub mod authentication;
+pub mod bandwidth;
 mod client;
+pub mod client_registry;
 pub mod config;
 mod deduplicating_handler;
+pub mod dm;
 #[cfg(feature = "e2e-encryption")]
 pub mod encryption;
 mod error;
 pub mod event_cache;
 pub mod event_handler;
+pub mod guest;
+pub mod homeserver_migration;
 mod http_client;
+pub mod identity_server;
+pub mod image_pack;
+pub mod lightweight;
+#[cfg(feature = "log-filter")]
+pub mod log_filter;
 pub mod media;
+pub mod message_search;
 pub mod notification_settings;
+pub mod permalink;
+pub mod profile;
 pub mod pusher;
+#[cfg(feature = "rageshake")]
+pub mod rageshake;
 pub mod room;
 pub mod room_directory_search;
 pub mod room_preview;
 pub mod send_queue;
+pub mod session_handoff;
 pub mod utils;
 pub mod futures {
     //! Named futures returned from methods on types in [the crate root][crate].
@@ -59,24 +78,39 @@ pub mod futures {
     pub use super::client::futures::SendRequest;
 }
 pub mod sliding_sync;
-pub mod sync;
+#[cfg(feature = "synapse-admin")]
+pub mod synapse_admin;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry;
+#[cfg(feature = "e2e-encryption")]
+pub mod unable_to_decrypt_hook;
+pub mod user_search;
 #[cfg(feature = "experimental-widgets")]
 pub mod widget;
 
 pub use account::Account;
 pub use authentication::{AuthApi, AuthSession, SessionTokens};
 pub use client::{
-    sanitize_server_name, Client, ClientBuildError, ClientBuilder, LoopCtrl, SessionChange,
+    sanitize_server_name, Client, ClientBuildError, ClientBuilder, HomeserverCapabilities,
+    LoopCtrl, SessionChange,
 };
+pub use client_registry::ClientRegistry;
 pub use error::{
     Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
     RumaApiError,
 };
-pub use http_client::TransmissionProgress;
+#[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+pub use http_client::{CertificatePin, CertificatePinError};
+pub use http_client::{
+    HttpDiagnostics, HttpMiddleware, RequestCategoryMetrics, TransmissionProgress,
+};
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
 pub use matrix_sdk_sqlite::SqliteCryptoStore;
 #[cfg(feature = "sqlite")]
-pub use matrix_sdk_sqlite::{SqliteEventCacheStore, SqliteStateStore, SqliteStoreConfig};
+pub use matrix_sdk_sqlite::{
+    change_passphrase as change_sqlite_passphrase, OpenStoreError as SqliteOpenStoreError,
+    SqliteEventCacheStore, SqliteStateStore, SqliteStoreConfig,
+};
 pub use media::Media;
 pub use pusher::Pusher;
 pub use room::Room;
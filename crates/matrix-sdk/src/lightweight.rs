@@ -0,0 +1,455 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A sync-less mode for short-lived clients, like CLI tools and CI bots.
+//!
+//! A regular [`Client`] learns about its rooms, their members and the devices
+//! of these members from the sync loop. The [`Lightweight`] API instead only
+//! uses direct requests to the homeserver, so a client can send a message or
+//! fetch an event right after logging in, without ever syncing. The state
+//! store isn't used, so the default in-memory store is enough.
+//!
+//! When sending to an encrypted room, the members of the room are fetched and
+//! their devices queried before every message, since nothing tracks the
+//! changes of their device lists in between. This is more expensive than with
+//! a synced client, which is fine for clients sending a few messages.
+
+#[cfg(feature = "e2e-encryption")]
+use std::ops::Deref;
+
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_base::crypto::{DecryptionSettings, EncryptionSettings, RoomEventDecryptionResult};
+#[cfg(feature = "e2e-encryption")]
+use ruma::{
+    api::client::membership::get_member_events,
+    events::{
+        room::{
+            history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+            member::MembershipState,
+        },
+        AnyTimelineEvent,
+    },
+    OwnedUserId,
+};
+use ruma::{
+    api::client::{
+        alias::get_alias, error::ErrorKind, message::send_message_event, room::get_room_event,
+        state::get_state_events_for_key,
+    },
+    events::{
+        room::encryption::RoomEncryptionEventContent, AnyMessageLikeEventContent,
+        MessageLikeEventContent, StateEventType,
+    },
+    serde::Raw,
+    EventId, OwnedEventId, OwnedRoomId, RoomId, RoomOrAliasId, TransactionId,
+};
+use thiserror::Error;
+#[cfg(feature = "e2e-encryption")]
+use tracing::debug;
+use tracing::{instrument, trace};
+
+#[cfg(feature = "e2e-encryption")]
+use crate::Error;
+use crate::{deserialized_responses::TimelineEvent, Client, Result};
+
+/// An error specific to the [`Lightweight`] API.
+#[derive(Debug, Error)]
+pub enum LightweightError {
+    /// The room is encrypted, but encryption is disabled with
+    /// [`LightweightEncryption::Disabled`], or the `e2e-encryption` feature
+    /// isn't enabled.
+    #[error("the room is encrypted, but encryption is disabled")]
+    EncryptionDisabled,
+}
+
+/// How the [`Lightweight`] API handles encrypted rooms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightweightEncryption {
+    /// Encrypt the messages sent to encrypted rooms, querying the devices of
+    /// the members and claiming one-time keys before every message.
+    #[default]
+    PerSend,
+
+    /// Refuse to send messages to encrypted rooms, and don't try to decrypt
+    /// the fetched events.
+    Disabled,
+}
+
+/// A sync-less API to interact with rooms.
+///
+/// It's created with [`Client::lightweight()`]. See the [module
+/// documentation](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Lightweight {
+    client: Client,
+    encryption: LightweightEncryption,
+}
+
+impl Lightweight {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client, encryption: LightweightEncryption::default() }
+    }
+
+    /// Set how encrypted rooms are handled.
+    pub fn with_encryption(mut self, encryption: LightweightEncryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Resolve a room ID or alias into a room ID.
+    ///
+    /// Room IDs are returned as-is, aliases are resolved with the room
+    /// directory of the homeserver.
+    pub async fn resolve_room(&self, room: &RoomOrAliasId) -> Result<OwnedRoomId> {
+        match <&RoomId>::try_from(room) {
+            Ok(room_id) => Ok(room_id.to_owned()),
+            Err(alias) => {
+                let request = get_alias::v3::Request::new(alias.to_owned());
+                Ok(self.client.send(request).await?.room_id)
+            }
+        }
+    }
+
+    /// Fetch a single event of a room.
+    ///
+    /// Encrypted events are decrypted if the client has the room key, which
+    /// is only the case for events it sent itself, unless the key was received
+    /// some other way, like from the key backup.
+    pub async fn fetch_event(&self, room_id: &RoomId, event_id: &EventId) -> Result<TimelineEvent> {
+        let request = get_room_event::v3::Request::new(room_id.to_owned(), event_id.to_owned());
+        let event = self.client.send(request).await?.event;
+
+        #[cfg(feature = "e2e-encryption")]
+        if self.encryption == LightweightEncryption::PerSend {
+            if let Some(event) = self.try_decrypt(room_id, &event).await? {
+                return Ok(event);
+            }
+        }
+
+        Ok(TimelineEvent::new(event.cast()))
+    }
+
+    /// Send a message-like event to a room.
+    ///
+    /// The own user must be joined to the room. Returns the ID of the sent
+    /// event.
+    pub async fn send(
+        &self,
+        room_id: &RoomId,
+        content: impl MessageLikeEventContent,
+    ) -> Result<OwnedEventId> {
+        let event_type = content.event_type().to_string();
+        let content = Raw::new(&content)?.cast();
+        self.send_raw(room_id, &event_type, content).await
+    }
+
+    /// Send a message-like event of the given type, with a raw content, to a
+    /// room.
+    ///
+    /// The own user must be joined to the room. Returns the ID of the sent
+    /// event.
+    #[instrument(skip(self, content))]
+    pub async fn send_raw(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        content: Raw<AnyMessageLikeEventContent>,
+    ) -> Result<OwnedEventId> {
+        #[cfg_attr(not(feature = "e2e-encryption"), allow(unused_mut))]
+        let (mut event_type, mut content) = (event_type.to_owned(), content);
+
+        // Reactions are currently not encrypted.
+        if event_type != "m.reaction" {
+            if let Some(encryption) = self.encryption_settings(room_id).await? {
+                match self.encryption {
+                    #[cfg(feature = "e2e-encryption")]
+                    LightweightEncryption::PerSend => {
+                        content = self.encrypt(room_id, encryption, &event_type, &content).await?;
+                        event_type = "m.room.encrypted".to_owned();
+                    }
+                    #[cfg(not(feature = "e2e-encryption"))]
+                    LightweightEncryption::PerSend => {
+                        let _ = encryption;
+                        return Err(LightweightError::EncryptionDisabled.into());
+                    }
+                    LightweightEncryption::Disabled => {
+                        return Err(LightweightError::EncryptionDisabled.into());
+                    }
+                }
+            } else {
+                trace!("Sending plaintext event because the room is not encrypted");
+            }
+        }
+
+        let request = send_message_event::v3::Request::new_raw(
+            room_id.to_owned(),
+            TransactionId::new(),
+            event_type.into(),
+            content,
+        );
+
+        Ok(self.client.send(request).await?.event_id)
+    }
+
+    /// Get the content of the `m.room.encryption` state event of the room, if
+    /// it's encrypted.
+    async fn encryption_settings(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<RoomEncryptionEventContent>> {
+        let request = get_state_events_for_key::v3::Request::new(
+            room_id.to_owned(),
+            StateEventType::RoomEncryption,
+            "".to_owned(),
+        );
+
+        match self.client.send(request).await {
+            Ok(response) => Ok(Some(response.content.deserialize_as()?)),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Get the history visibility of the room, falling back to the default
+    /// value if it isn't set.
+    #[cfg(feature = "e2e-encryption")]
+    async fn history_visibility(&self, room_id: &RoomId) -> Result<HistoryVisibility> {
+        let request = get_state_events_for_key::v3::Request::new(
+            room_id.to_owned(),
+            StateEventType::RoomHistoryVisibility,
+            "".to_owned(),
+        );
+
+        match self.client.send(request).await {
+            Ok(response) => Ok(response
+                .content
+                .deserialize_as::<RoomHistoryVisibilityEventContent>()?
+                .history_visibility),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                Ok(HistoryVisibility::Shared)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Get the users that should receive the room keys of the room.
+    #[cfg(feature = "e2e-encryption")]
+    async fn key_recipients(
+        &self,
+        room_id: &RoomId,
+        history_visibility: &HistoryVisibility,
+    ) -> Result<Vec<OwnedUserId>> {
+        let request = get_member_events::v3::Request::new(room_id.to_owned());
+        let response = self.client.send(request).await?;
+
+        Ok(response
+            .chunk
+            .iter()
+            .filter_map(|event| event.deserialize().ok())
+            .filter(|event| match event.membership() {
+                MembershipState::Join => true,
+                // Invited users don't get the keys if they can't see the history before
+                // joining.
+                MembershipState::Invite => *history_visibility != HistoryVisibility::Joined,
+                _ => false,
+            })
+            .map(|event| event.state_key().to_owned())
+            .collect())
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn encrypt(
+        &self,
+        room_id: &RoomId,
+        encryption: RoomEncryptionEventContent,
+        event_type: &str,
+        content: &Raw<AnyMessageLikeEventContent>,
+    ) -> Result<Raw<AnyMessageLikeEventContent>> {
+        let history_visibility = self.history_visibility(room_id).await?;
+        let members = self.key_recipients(room_id, &history_visibility).await?;
+        debug!(num_members = members.len(), "Sharing the room key before sending");
+
+        {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+            // Nothing tracks the changes of the device lists without a sync, so query the
+            // devices of all the members again.
+            olm.update_tracked_users(members.iter().map(Deref::deref)).await?;
+            olm.mark_all_tracked_users_as_dirty().await?;
+        }
+
+        self.client.send_outgoing_requests().await?;
+        self.client.claim_one_time_keys(members.iter().map(Deref::deref)).await?;
+
+        let settings = EncryptionSettings::new(
+            encryption,
+            history_visibility,
+            self.client.base_client().room_key_recipient_strategy.clone(),
+        );
+
+        let requests = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.share_room_key(room_id, members.iter().map(Deref::deref), settings).await?
+        };
+
+        for request in requests {
+            let result: Result<()> = async {
+                let response = self.client.send_to_device(&request).await?;
+                self.client.mark_request_as_sent(&request.txn_id, &response).await?;
+                Ok(())
+            }
+            .await;
+
+            if let Err(error) = result {
+                // Using a room key that wasn't shared with all the members would produce
+                // messages they can't decrypt.
+                if let Some(olm) = self.client.olm_machine().await.as_ref() {
+                    olm.discard_room_key(room_id).await?;
+                }
+                return Err(error);
+            }
+        }
+
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+        Ok(olm.encrypt_room_event_raw(room_id, event_type, content).await?.cast())
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn try_decrypt(
+        &self,
+        room_id: &RoomId,
+        event: &Raw<AnyTimelineEvent>,
+    ) -> Result<Option<TimelineEvent>> {
+        if event.get_field::<String>("type")?.as_deref() != Some("m.room.encrypted") {
+            return Ok(None);
+        }
+
+        let olm = self.client.olm_machine().await;
+        let Some(olm) = olm.as_ref() else {
+            return Ok(None);
+        };
+
+        let decryption_settings = DecryptionSettings {
+            sender_device_trust_requirement: self.client.base_client().decryption_trust_requirement,
+        };
+
+        let event = match olm
+            .try_decrypt_room_event(event.cast_ref(), room_id, &decryption_settings)
+            .await?
+        {
+            RoomEventDecryptionResult::Decrypted(decrypted) => decrypted.into(),
+            RoomEventDecryptionResult::UnableToDecrypt(utd_info) => {
+                TimelineEvent::new_utd_event(event.clone().cast(), utd_info)
+            }
+        };
+
+        Ok(Some(event))
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use assert_matches2::assert_matches;
+    use matrix_sdk_test::{async_test, event_factory::EventFactory};
+    use ruma::{
+        event_id, events::room::message::RoomMessageEventContent, room_alias_id, room_id, user_id,
+        RoomOrAliasId,
+    };
+
+    use super::{LightweightEncryption, LightweightError};
+    use crate::{test_utils::mocks::MatrixMockServer, Error};
+
+    #[async_test]
+    async fn test_send_to_unencrypted_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+
+        server.mock_room_state_encryption().plain().mock_once().mount().await;
+        server.mock_room_send().ok(event_id!("$sent")).mock_once().mount().await;
+
+        let event_id = client
+            .lightweight()
+            .send(room_id, RoomMessageEventContent::text_plain("Build succeeded"))
+            .await
+            .unwrap();
+
+        assert_eq!(event_id, event_id!("$sent"));
+    }
+
+    #[async_test]
+    async fn test_send_to_encrypted_room_with_encryption_disabled() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+
+        server.mock_room_state_encryption().encrypted().mount().await;
+        server.mock_room_send().ok(event_id!("$sent")).never().mount().await;
+
+        let result = client
+            .lightweight()
+            .with_encryption(LightweightEncryption::Disabled)
+            .send(room_id, RoomMessageEventContent::text_plain("Build succeeded"))
+            .await;
+
+        assert_matches!(result, Err(Error::Lightweight(LightweightError::EncryptionDisabled)));
+    }
+
+    #[async_test]
+    async fn test_resolve_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let lightweight = client.lightweight();
+
+        server
+            .mock_room_directory_resolve_alias()
+            .ok("!resolved:localhost", vec!["localhost".to_owned()])
+            .mock_once()
+            .mount()
+            .await;
+
+        let alias = <&RoomOrAliasId>::from(room_alias_id!("#ci:localhost"));
+        assert_eq!(lightweight.resolve_room(alias).await.unwrap(), "!resolved:localhost");
+
+        // Room IDs are returned without any request.
+        let room_id = <&RoomOrAliasId>::from(room_id!("!room:localhost"));
+        assert_eq!(lightweight.resolve_room(room_id).await.unwrap(), "!room:localhost");
+    }
+
+    #[async_test]
+    async fn test_fetch_event() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!room:localhost");
+        let event_id = event_id!("$event");
+
+        let f = EventFactory::new().room(room_id).sender(user_id!("@alice:localhost"));
+        server
+            .mock_room_event()
+            .room(room_id)
+            .match_event_id()
+            .ok(f.text_msg("Deploy finished").event_id(event_id).into())
+            .mock_once()
+            .mount()
+            .await;
+
+        let event = client.lightweight().fetch_event(room_id, event_id).await.unwrap();
+
+        assert_eq!(event.event_id().as_deref(), Some(event_id));
+        assert!(event.encryption_info().is_none());
+    }
+}
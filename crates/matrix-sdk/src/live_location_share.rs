@@ -16,14 +16,17 @@
 //!
 //! Live location sharing allows users to share their real-time location with
 //! others in a room via [MSC3489](https://github.com/matrix-org/matrix-spec-proposals/pull/3489).
+use std::{collections::BTreeMap, time::Duration};
+
 use async_stream::stream;
 use futures_util::Stream;
 use ruma::{
     events::{
-        beacon::OriginalSyncBeaconEvent, beacon_info::BeaconInfoEventContent,
+        beacon::OriginalSyncBeaconEvent,
+        beacon_info::{BeaconInfoEventContent, OriginalSyncBeaconInfoEvent},
         location::LocationContent,
     },
-    MilliSecondsSinceUnixEpoch, OwnedUserId, RoomId,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, RoomId, UInt,
 };
 
 use crate::{event_handler::ObservableEventHandler, Client, Room};
@@ -84,3 +87,111 @@ pub struct LiveLocationShare {
     /// The user ID of the person sharing their live location.
     pub user_id: OwnedUserId,
 }
+
+/// A live location share that is currently active in a room, as returned by
+/// [`Room::subscribe_to_active_live_location_shares()`].
+#[derive(Clone, Debug)]
+pub struct ActiveLiveLocationShare {
+    /// The user ID of the person sharing their live location.
+    pub user_id: OwnedUserId,
+    /// The ID of the `beacon_info` state event that started the share.
+    pub beacon_info_event_id: OwnedEventId,
+    /// Information about the live location share.
+    pub beacon_info: BeaconInfoEventContent,
+    /// The user's last known location, if any location was received since
+    /// the subscription started.
+    pub last_location: Option<LastLocation>,
+}
+
+impl ActiveLiveLocationShare {
+    /// The time at which this live location share expires.
+    pub fn expires_at(&self) -> MilliSecondsSinceUnixEpoch {
+        let timeout = u64::try_from(self.beacon_info.timeout.as_millis()).unwrap_or(u64::MAX);
+        MilliSecondsSinceUnixEpoch(UInt::new_saturating(
+            u64::from(self.beacon_info.ts.0).saturating_add(timeout),
+        ))
+    }
+}
+
+/// The live location shares that are currently active in a room, indexed by
+/// user ID.
+#[derive(Debug, Default)]
+pub(crate) struct ActiveLiveLocationShares {
+    shares: BTreeMap<OwnedUserId, ActiveLiveLocationShare>,
+}
+
+impl ActiveLiveLocationShares {
+    /// Handle a `beacon_info` state event.
+    ///
+    /// Returns `true` if the active shares changed.
+    pub(crate) fn handle_beacon_info(&mut self, event: OriginalSyncBeaconInfoEvent) -> bool {
+        if !event.content.is_live() {
+            return self.shares.remove(&event.state_key).is_some();
+        }
+
+        // Keep the last location if this is an update of the same share.
+        let last_location = self
+            .shares
+            .remove(&event.state_key)
+            .filter(|share| share.beacon_info_event_id == event.event_id)
+            .and_then(|share| share.last_location);
+
+        self.shares.insert(
+            event.state_key.clone(),
+            ActiveLiveLocationShare {
+                user_id: event.state_key,
+                beacon_info_event_id: event.event_id,
+                beacon_info: event.content,
+                last_location,
+            },
+        );
+
+        true
+    }
+
+    /// Handle a `beacon` event.
+    ///
+    /// The location is ignored if it doesn't belong to an active share of its
+    /// sender, or if a more recent location is already known.
+    ///
+    /// Returns `true` if the active shares changed.
+    pub(crate) fn handle_beacon(&mut self, event: OriginalSyncBeaconEvent) -> bool {
+        let Some(share) = self.shares.get_mut(&event.sender) else {
+            return false;
+        };
+
+        if share.beacon_info_event_id != event.content.relates_to.event_id {
+            return false;
+        }
+
+        if share.last_location.as_ref().is_some_and(|last| last.ts > event.origin_server_ts) {
+            return false;
+        }
+
+        share.last_location =
+            Some(LastLocation { location: event.content.location, ts: event.origin_server_ts });
+
+        true
+    }
+
+    /// Remove the shares that are expired.
+    ///
+    /// Returns `true` if the active shares changed.
+    pub(crate) fn remove_expired(&mut self) -> bool {
+        let len = self.shares.len();
+        self.shares.retain(|_, share| share.beacon_info.is_live());
+        self.shares.len() != len
+    }
+
+    /// The time until the next share expires, if any.
+    pub(crate) fn time_until_next_expiry(&self) -> Option<Duration> {
+        let next_expiry = self.shares.values().map(|share| share.expires_at()).min()?;
+        let now = MilliSecondsSinceUnixEpoch::now();
+        Some(Duration::from_millis(u64::from(next_expiry.0).saturating_sub(now.0.into())))
+    }
+
+    /// The currently active shares.
+    pub(crate) fn to_vec(&self) -> Vec<ActiveLiveLocationShare> {
+        self.shares.values().cloned().collect()
+    }
+}
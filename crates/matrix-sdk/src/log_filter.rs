@@ -0,0 +1,126 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Change the log filter of the application at runtime.
+//!
+//! The SDK doesn't set up a tracing subscriber by itself, but applications
+//! that want to change their log levels without restarting, e.g. from a debug
+//! panel, can install a [`ReloadableLogFilter`] in their subscriber and keep
+//! the [`LogFilterHandle`] around.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use matrix_sdk::log_filter::reloadable_log_filter;
+//! use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! let (filter, handle) = reloadable_log_filter("matrix_sdk=info")?;
+//! tracing_subscriber::registry()
+//!     .with(filter)
+//!     .with(tracing_subscriber::fmt::layer())
+//!     .init();
+//!
+//! // Later, when the user wants more details about the event cache.
+//! handle.set_log_filter("matrix_sdk=info,matrix_sdk::event_cache=trace")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use thiserror::Error;
+use tracing_subscriber::{
+    filter::ParseError,
+    reload::{self, Handle},
+    EnvFilter, Registry,
+};
+
+/// A log filter layer whose directives can be replaced at runtime with a
+/// [`LogFilterHandle`].
+///
+/// It must be the first layer added to the [`Registry`].
+pub type ReloadableLogFilter = reload::Layer<EnvFilter, Registry>;
+
+/// An error happened while changing the log filter.
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    /// The directives of the filter are invalid.
+    #[error(transparent)]
+    InvalidDirectives(#[from] ParseError),
+
+    /// The filter couldn't be replaced, most likely because the subscriber it
+    /// was installed in has been dropped.
+    #[error(transparent)]
+    Reload(#[from] reload::Error),
+}
+
+/// Create a log filter layer from the given directives, which can be replaced
+/// at runtime with the returned [`LogFilterHandle`].
+///
+/// The directives use the same syntax as the `RUST_LOG` environment variable,
+/// see [`EnvFilter`].
+pub fn reloadable_log_filter(
+    directives: &str,
+) -> Result<(ReloadableLogFilter, LogFilterHandle), LogFilterError> {
+    let (layer, handle) = reload::Layer::new(EnvFilter::try_new(directives)?);
+    Ok((layer, LogFilterHandle { handle }))
+}
+
+/// A handle to change the directives of a [`ReloadableLogFilter`].
+#[derive(Clone, Debug)]
+pub struct LogFilterHandle {
+    handle: Handle<EnvFilter, Registry>,
+}
+
+impl LogFilterHandle {
+    /// Replace the directives of the log filter.
+    ///
+    /// This applies to everything logged through the subscriber the filter
+    /// was installed in, i.e. usually to the whole process.
+    pub fn set_log_filter(&self, directives: &str) -> Result<(), LogFilterError> {
+        let filter = EnvFilter::try_new(directives)?;
+        self.handle.reload(filter)?;
+
+        tracing::info!(directives, "The log filter has been updated");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{reloadable_log_filter, LogFilterError};
+
+    #[test]
+    fn test_set_log_filter() {
+        let (filter, handle) = reloadable_log_filter("matrix_sdk=info").unwrap();
+        let subscriber = tracing_subscriber::registry().with(filter);
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(target: "matrix_sdk", tracing::Level::DEBUG));
+
+            handle.set_log_filter("matrix_sdk=debug").unwrap();
+            assert!(tracing::enabled!(target: "matrix_sdk", tracing::Level::DEBUG));
+
+            // Invalid directives are rejected and leave the filter untouched.
+            assert_matches!(
+                handle.set_log_filter("matrix_sdk=loud"),
+                Err(LogFilterError::InvalidDirectives(_))
+            );
+            assert!(tracing::enabled!(target: "matrix_sdk", tracing::Level::DEBUG));
+        });
+    }
+}
@@ -17,14 +17,30 @@
 
 #[cfg(feature = "e2e-encryption")]
 use std::io::Read;
-use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use std::{fmt, fs::File, path::Path};
+use std::{
+    future::IntoFuture,
+    io,
+    ops::Range,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
 
 use eyeball::SharedObservable;
-use futures_util::future::try_join;
+#[cfg(not(target_arch = "wasm32"))]
+use eyeball::Subscriber;
+use futures_util::{
+    future::{try_join, AbortHandle, AbortRegistration, Abortable},
+    io::{AsyncRead, AsyncSeek},
+};
 use matrix_sdk_base::event_cache::store::media::IgnoreMediaRetentionPolicy;
-pub use matrix_sdk_base::{event_cache::store::media::MediaRetentionPolicy, media::*};
+pub use matrix_sdk_base::{
+    event_cache::store::media::{MediaCacheUsage, MediaRetentionPolicy},
+    media::*,
+};
+use matrix_sdk_common::{boxed_into_future, BoxFuture};
 use mime::Mime;
 use ruma::{
     api::{
@@ -39,16 +55,30 @@ use ruma::{
 use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::{fs::File as TokioFile, io::AsyncWriteExt};
+use tracing::warn;
 
+#[cfg(feature = "image-proc")]
+use crate::attachment::AttachmentInfo;
+#[cfg(feature = "image-proc")]
+use crate::attachment::{transcode_image, ImageError, ImageTranscoding, ImageTranscodingReport};
 use crate::{
-    attachment::Thumbnail, config::RequestConfig, futures::SendRequest, Client, Error, Result,
-    TransmissionProgress,
+    attachment::{AttachmentConfig, Thumbnail},
+    client::homeserver_capabilities::{
+        is_unstable_feature_enabled, AUTHENTICATED_MEDIA_STABLE_FEATURE,
+    },
+    config::RequestConfig,
+    futures::SendRequest,
+    http_client::{PartialContent, PartialDownload},
+    Client, Error, Result, TransmissionProgress,
 };
 
 /// A conservative upload speed of 1Mbps
 const DEFAULT_UPLOAD_SPEED: u64 = 125_000;
 /// 5 min minimal upload request timeout, used to clamp the request timeout.
 const MIN_UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+/// The default size of the ranges of media content requested by a
+/// [`MediaStream`], 1 MiB.
+const DEFAULT_STREAM_CHUNK_SIZE: u64 = 1024 * 1024;
 /// The server name used to generate local MXC URIs.
 // This mustn't represent a potentially valid media server, otherwise it'd be
 // possible for an attacker to return malicious content under some
@@ -58,6 +88,9 @@ const MIN_UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 5);
 // possible would be coming from the user themselves, which we consider a
 // non-threat.
 const LOCAL_MXC_SERVER_NAME: &str = "send-queue.localhost";
+/// The prefix of the keys of the custom values of the state store where the
+/// data of interrupted downloads is persisted.
+const PARTIAL_DOWNLOAD_KEY_PREFIX: &str = "media::partial_download::";
 
 /// A high-level API to interact with the media API.
 #[derive(Debug, Clone)]
@@ -99,7 +132,7 @@ impl MediaFileHandle {
 #[cfg(not(target_arch = "wasm32"))]
 pub struct PersistError {
     /// The underlying IO error.
-    pub error: std::io::Error,
+    pub error: io::Error,
     /// The temporary file that couldn't be persisted.
     pub file: MediaFileHandle,
 }
@@ -142,11 +175,373 @@ pub enum MediaError {
     /// Local-only media content was not found.
     #[error("local-only media content was not found")]
     LocalMediaNotFound,
+
+    /// The download of the media content was aborted.
+    #[error("the download of the media content was aborted")]
+    DownloadAborted,
+
+    /// The file is bigger than the maximum size of an upload.
+    #[error("the file is too large to be uploaded: {size} bytes, the maximum is {max_size} bytes")]
+    FileTooLarge {
+        /// The size of the file, in bytes.
+        size: u64,
+        /// The maximum size of an upload, in bytes.
+        max_size: u64,
+    },
+
+    /// The processing of an image failed.
+    #[cfg(feature = "image-proc")]
+    #[error(transparent)]
+    ImageProcessing(#[from] ImageError),
+}
+
+/// Settings for the processing of a file before it is uploaded, see
+/// [`Media::prepare_upload()`].
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    #[cfg(feature = "image-proc")]
+    image_transcoding: Option<ImageTranscoding>,
+    max_size: Option<u64>,
+    check_server_limit: bool,
+}
+
+impl UploadConfig {
+    /// Create a new default `UploadConfig`.
+    ///
+    /// By default, the file is not modified and its size is checked against
+    /// the limit of the homeserver.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how images are processed before they are uploaded.
+    ///
+    /// This only applies to files with an `image/*` content type, in a
+    /// supported format.
+    #[cfg(feature = "image-proc")]
+    #[must_use]
+    pub fn transcode_images(mut self, settings: ImageTranscoding) -> Self {
+        self.image_transcoding = Some(settings);
+        self
+    }
+
+    /// Set the maximum size of the file, in bytes.
+    ///
+    /// If the limit of the homeserver is also checked, the lowest limit is
+    /// used.
+    #[must_use]
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set whether the size of the file should be checked against the maximum
+    /// size of an upload allowed by the homeserver.
+    ///
+    /// Defaults to `true`.
+    #[must_use]
+    pub fn check_server_limit(mut self, check: bool) -> Self {
+        self.check_server_limit = check;
+        self
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "image-proc")]
+            image_transcoding: None,
+            max_size: None,
+            check_server_limit: true,
+        }
+    }
+}
+
+/// A file processed by [`Media::prepare_upload()`], ready to be uploaded.
+#[derive(Debug)]
+pub struct PreparedUpload {
+    /// The raw bytes of the file.
+    pub data: Vec<u8>,
+    /// The type of the file, which changes if an image was converted to another
+    /// format.
+    pub content_type: Mime,
+    /// The transformations that were applied to the image, if the file is an
+    /// image that was processed.
+    #[cfg(feature = "image-proc")]
+    pub image_transcoding: Option<ImageTranscodingReport>,
 }
 
 /// `IntoFuture` returned by [`Media::upload`].
 pub type SendUploadRequest = SendRequest<media::create_content::v3::Request>;
 
+/// `IntoFuture` returned by [`Media::download_media_content()`].
+#[allow(missing_debug_implementations)]
+pub struct DownloadMediaContent {
+    media: Media,
+    request: MediaRequestParameters,
+    use_cache: bool,
+    download: PartialDownload,
+    abort_handle: AbortHandle,
+    abort_registration: AbortRegistration,
+}
+
+impl DownloadMediaContent {
+    /// Get a subscriber to observe the progress of the download.
+    ///
+    /// When the download is resumed, the progress starts at the number of
+    /// bytes that were already received.
+    ///
+    /// The progress is not reported for media content that is in the media
+    /// cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.download.progress.subscribe()
+    }
+
+    /// Get a handle to abort the download.
+    ///
+    /// When the download is aborted, the future resolves with
+    /// [`MediaError::DownloadAborted`]. If the media cache is used, the data
+    /// that was already received is kept to resume the download later.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort_handle.clone()
+    }
+}
+
+impl IntoFuture for DownloadMediaContent {
+    type Output = Result<Vec<u8>>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { media, request, use_cache, download, abort_handle: _, abort_registration } =
+            self;
+
+        Box::pin(async move {
+            let future = async {
+                // Ignore request parameters for local medias, notably those pending in the
+                // send queue.
+                if let Some(uri) = Media::as_local_uri(&request.source) {
+                    return media.get_local_media_content(uri).await;
+                }
+
+                if use_cache {
+                    media.download_media_content_with_cache(&request, download.clone()).await
+                } else {
+                    media.fetch_media_content(&request, download.clone()).await
+                }
+            };
+
+            let result = Abortable::new(future, abort_registration)
+                .await
+                .unwrap_or_else(|_| Err(MediaError::DownloadAborted.into()));
+
+            if use_cache {
+                media.end_download(&request, &download, result.is_err()).await;
+            }
+
+            result
+        })
+    }
+}
+
+/// A readable and seekable stream of a media file's content, returned by
+/// [`Media::stream()`].
+///
+/// It implements [`AsyncRead`] and [`AsyncSeek`]. Unless the whole content is
+/// already available, it is fetched from the homeserver in ranges of bytes as
+/// it is read, so seeking doesn't require to download the whole content.
+#[allow(missing_debug_implementations)]
+pub struct MediaStream {
+    media: Media,
+    /// The URI of the content to fetch ranges from, or `None` if the whole
+    /// content is in `chunk`.
+    uri: Option<OwnedMxcUri>,
+    /// The size of the ranges of bytes to request.
+    chunk_size: u64,
+    /// The current position in the content.
+    position: u64,
+    /// The length of the whole content, if it is known.
+    total_len: Option<u64>,
+    /// The last range of the content that was received.
+    chunk: MediaChunk,
+    /// The request of the range of the content at the current position, if
+    /// any.
+    pending_chunk: Option<BoxFuture<'static, Result<MediaChunk>>>,
+}
+
+impl MediaStream {
+    fn new(media: Media, uri: Option<OwnedMxcUri>, chunk: MediaChunk) -> Self {
+        Self {
+            media,
+            uri,
+            chunk_size: DEFAULT_STREAM_CHUNK_SIZE,
+            position: 0,
+            total_len: chunk.total_len,
+            chunk,
+            pending_chunk: None,
+        }
+    }
+
+    /// The length of the whole content, in bytes, if it is known.
+    ///
+    /// It is unknown if the homeserver doesn't report it when returning a
+    /// range of the content. In that case, seeking relative to the end of the
+    /// content is not supported.
+    pub fn content_length(&self) -> Option<u64> {
+        self.total_len
+    }
+
+    /// Set the size of the ranges of bytes that are requested when reading the
+    /// content.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_chunk_size(&mut self, chunk_size: u64) {
+        self.chunk_size = chunk_size.max(1);
+    }
+}
+
+impl AsyncRead for MediaStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            if buf.is_empty() || this.total_len.is_some_and(|len| this.position >= len) {
+                return Poll::Ready(Ok(0));
+            }
+
+            if let Some(data) = this.chunk.data_at(this.position) {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                this.position += len as u64;
+
+                return Poll::Ready(Ok(len));
+            }
+
+            let Some(uri) = &this.uri else {
+                // We have the whole content, so we reached the end.
+                return Poll::Ready(Ok(0));
+            };
+
+            let pending_chunk = this.pending_chunk.get_or_insert_with(|| {
+                let media = this.media.clone();
+                let uri = uri.clone();
+                let start = this.position;
+                let end = start.saturating_add(this.chunk_size);
+                let end = this.total_len.map_or(end, |len| end.min(len));
+
+                Box::pin(async move { media.fetch_media_range(&uri, start..end).await })
+            });
+
+            let chunk = ready!(pending_chunk.as_mut().poll(cx));
+            this.pending_chunk = None;
+            let chunk = chunk.map_err(io::Error::other)?;
+
+            if chunk.total_len.is_some() {
+                this.total_len = chunk.total_len;
+            }
+
+            if chunk.data_at(this.position).is_none() {
+                if chunk.end() <= this.position {
+                    // The content ends before the current position, there is nothing more to
+                    // read.
+                    return Poll::Ready(Ok(0));
+                }
+
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the homeserver did not return the requested range of the media content",
+                )));
+            }
+
+            if chunk.is_whole() {
+                // There is no need to request other ranges anymore.
+                this.uri = None;
+            }
+
+            this.chunk = chunk;
+        }
+    }
+}
+
+impl AsyncSeek for MediaStream {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: io::SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::Current(offset) => this.position.checked_add_signed(offset),
+            io::SeekFrom::End(offset) => {
+                let Some(len) = this.total_len else {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "the length of the media content is unknown",
+                    )));
+                };
+
+                len.checked_add_signed(offset)
+            }
+        };
+
+        let Some(position) = position else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )));
+        };
+
+        if position != this.position {
+            // The pending request is for the previous position.
+            this.pending_chunk = None;
+            this.position = position;
+        }
+
+        Poll::Ready(Ok(position))
+    }
+}
+
+/// A range of bytes of a media file's content.
+#[derive(Debug, Default)]
+struct MediaChunk {
+    /// The offset of the first byte of the range in the whole content.
+    start: u64,
+    /// The bytes of the range.
+    data: Vec<u8>,
+    /// The length of the whole content, if it is known.
+    total_len: Option<u64>,
+}
+
+impl MediaChunk {
+    /// Create a `MediaChunk` containing the whole content.
+    fn whole(data: Vec<u8>) -> Self {
+        Self { start: 0, total_len: Some(data.len() as u64), data }
+    }
+
+    /// Whether this chunk contains the whole content.
+    fn is_whole(&self) -> bool {
+        self.start == 0 && self.total_len == Some(self.data.len() as u64)
+    }
+
+    /// The offset of the byte following this chunk in the whole content.
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    /// The data of this chunk starting at the given position in the whole
+    /// content, if this chunk contains it.
+    fn data_at(&self, position: u64) -> Option<&[u8]> {
+        let offset = usize::try_from(position.checked_sub(self.start)?).ok()?;
+        self.data.get(offset..).filter(|data| !data.is_empty())
+    }
+}
+
 impl Media {
     pub(crate) fn new(client: Client) -> Self {
         Self { client }
@@ -200,6 +595,136 @@ impl Media {
         self.client.send(request).with_request_config(request_config)
     }
 
+    /// Process a file before uploading it.
+    ///
+    /// Depending on the config, images are downscaled, converted to another
+    /// format and their location is removed from their metadata, and the size
+    /// of the file is checked against the size limits.
+    ///
+    /// The processed file can then be uploaded with [`Media::upload()`] or sent
+    /// with [`Room::send_attachment()`](crate::Room::send_attachment).
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - The type of the file.
+    ///
+    /// * `data` - The raw bytes of the file.
+    ///
+    /// * `config` - The processing to apply to the file.
+    ///
+    /// Returns [`MediaError::FileTooLarge`] if the processed file is bigger
+    /// than the size limit.
+    pub async fn prepare_upload(
+        &self,
+        content_type: &Mime,
+        data: Vec<u8>,
+        config: &UploadConfig,
+    ) -> Result<PreparedUpload> {
+        #[cfg(feature = "image-proc")]
+        let (data, content_type, image_transcoding) = match &config.image_transcoding {
+            Some(settings) if content_type.type_() == mime::IMAGE => {
+                let content_type = content_type.clone();
+                let settings = settings.clone();
+                let transcode = move || transcode_image(&content_type, data, &settings);
+
+                #[cfg(not(target_arch = "wasm32"))]
+                let transcoded = tokio::task::spawn_blocking(transcode)
+                    .await
+                    .expect("Task join error")
+                    .map_err(MediaError::ImageProcessing)?;
+                #[cfg(target_arch = "wasm32")]
+                let transcoded = transcode().map_err(MediaError::ImageProcessing)?;
+
+                (transcoded.data, transcoded.content_type, Some(transcoded.report))
+            }
+            _ => (data, content_type.clone(), None),
+        };
+        #[cfg(not(feature = "image-proc"))]
+        let content_type = content_type.clone();
+
+        let mut max_size = config.max_size;
+
+        if config.check_server_limit {
+            let server_max_size = self.max_upload_size().await?.into();
+            max_size =
+                Some(max_size.map_or(server_max_size, |max_size| max_size.min(server_max_size)));
+        }
+
+        let size = data.len() as u64;
+        if let Some(max_size) = max_size.filter(|max_size| size > *max_size) {
+            return Err(MediaError::FileTooLarge { size, max_size }.into());
+        }
+
+        Ok(PreparedUpload {
+            data,
+            content_type,
+            #[cfg(feature = "image-proc")]
+            image_transcoding,
+        })
+    }
+
+    /// Process the file of an attachment with the [`UploadConfig`] of its
+    /// [`AttachmentConfig`], if any, and update the metadata of the attachment
+    /// accordingly.
+    ///
+    /// Returns the type and the data of the file to upload.
+    pub(crate) async fn prepare_attachment_upload(
+        &self,
+        content_type: Mime,
+        data: Vec<u8>,
+        config: &mut AttachmentConfig,
+    ) -> Result<(Mime, Vec<u8>)> {
+        let Some(upload_config) = config.upload_config.take() else {
+            return Ok((content_type, data));
+        };
+
+        let prepared = self.prepare_upload(&content_type, data, &upload_config).await?;
+
+        #[cfg(feature = "image-proc")]
+        if let (Some(report), Some(AttachmentInfo::Image(info))) = (
+            prepared.image_transcoding.as_ref().filter(|report| report.is_modified()),
+            &mut config.info,
+        ) {
+            if let Some((_, (width, height))) = report.downscaled {
+                info.width = Some(width.into());
+                info.height = Some(height.into());
+            }
+
+            info.size = UInt::new(prepared.data.len() as u64);
+        }
+
+        Ok((prepared.content_type, prepared.data))
+    }
+
+    /// Get the maximum size of a file that can be uploaded to the homeserver,
+    /// in bytes.
+    ///
+    /// This is the `m.upload.size` field of the media configuration of the
+    /// homeserver. It is cached for a while, or until
+    /// [`Client::reset_server_capabilities()`] is called.
+    pub async fn max_upload_size(&self) -> Result<UInt> {
+        let mut cache = self.client.inner.caches.max_upload_size.lock().await;
+
+        if let Some(max_upload_size) = cache.get(&()) {
+            return Ok(max_upload_size);
+        }
+
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
+
+        let max_upload_size = if use_auth {
+            let request = authenticated_media::get_media_config::v1::Request::default();
+            self.client.send(request).with_request_config(request_config).await?.upload_size
+        } else {
+            #[allow(deprecated)]
+            let request = media::get_media_config::v3::Request::default();
+            self.client.send(request).await?.upload_size
+        };
+
+        cache.insert((), max_upload_size);
+
+        Ok(max_upload_size)
+    }
+
     /// Returns a reasonable upload timeout for an upload, based on the size of
     /// the data to be uploaded.
     pub(crate) fn reasonable_upload_timeout(data: &[u8]) -> Duration {
@@ -395,6 +920,9 @@ impl Media {
     /// If the content is encrypted and encryption is enabled, the content will
     /// be decrypted.
     ///
+    /// To track the progress of the download or to abort it, use
+    /// [`Media::download_media_content()`].
+    ///
     /// # Arguments
     ///
     /// * `request` - The `MediaRequest` of the content.
@@ -405,59 +933,364 @@ impl Media {
         request: &MediaRequestParameters,
         use_cache: bool,
     ) -> Result<Vec<u8>> {
-        // Ignore request parameters for local medias, notably those pending in the send
-        // queue.
-        if let Some(uri) = Self::as_local_uri(&request.source) {
-            return self.get_local_media_content(uri).await;
+        self.download_media_content(request, use_cache).await
+    }
+
+    /// Download a media file's content, with the possibility to track the
+    /// progress of the download and to abort it.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted.
+    ///
+    /// When the media cache is used, concurrent downloads of the same content
+    /// are deduplicated, and a download that was interrupted, because it was
+    /// aborted or because of a network error, is resumed from where it stopped
+    /// the next time the same content is requested, as long as the homeserver
+    /// supports it. The data that was received is persisted in the state
+    /// store, so the download can be resumed after a restart too, if the
+    /// homeserver returned an `ETag` or a `Last-Modified` header to check that
+    /// the content didn't change.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `use_cache` - If we should use the media cache for this request.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, media::{MediaFormat, MediaRequestParameters}};
+    /// # use ruma::{events::room::MediaSource, mxc_uri};
+    /// # async {
+    /// # let client = Client::new("http://localhost".parse()?).await?;
+    /// let request = MediaRequestParameters {
+    ///     source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+    ///     format: MediaFormat::File,
+    /// };
+    ///
+    /// let download = client.media().download_media_content(&request, true);
+    /// let mut progress = download.subscribe_to_progress();
+    /// let abort_handle = download.abort_handle();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Some(progress) = progress.next().await {
+    ///         println!("Downloaded {} of {} bytes", progress.current, progress.total);
+    ///     }
+    /// });
+    ///
+    /// // Call `abort_handle.abort()` to abort the download.
+    /// let content = download.await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn download_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        use_cache: bool,
+    ) -> DownloadMediaContent {
+        let download = if use_cache {
+            // Share the download with the other requests for the same content.
+            self.client
+                .locks()
+                .partial_media_downloads
+                .lock()
+                .unwrap()
+                .entry(request.unique_key())
+                .or_default()
+                .clone()
+        } else {
+            PartialDownload::default()
+        };
+
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+
+        DownloadMediaContent {
+            media: self.clone(),
+            request: request.clone(),
+            use_cache,
+            download,
+            abort_handle,
+            abort_registration,
         }
+    }
 
+    /// Stream a media file's content.
+    ///
+    /// This is meant for large media like videos, that players need to read
+    /// and seek into without downloading the whole file first. The returned
+    /// [`MediaStream`] requests ranges of bytes of the content from the
+    /// homeserver as it is read, using the authenticated media endpoints when
+    /// the homeserver supports them.
+    ///
+    /// The whole content is read from the media cache if it is available
+    /// there, and it is stored in the media cache if the homeserver returns
+    /// it all at once. Encrypted content is always downloaded and decrypted as
+    /// a whole, since it can't be authenticated partially.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source of the media content.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::events::room::MediaSource};
+    /// # use futures_util::{AsyncReadExt, AsyncSeekExt};
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// # let source: MediaSource = unimplemented!();
+    /// let mut stream = client.media().stream(source).await?;
+    ///
+    /// // Skip the first kilobyte.
+    /// stream.seek(std::io::SeekFrom::Start(1024)).await?;
+    ///
+    /// let mut buf = vec![0; 1024];
+    /// let read = stream.read(&mut buf).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn stream(&self, source: MediaSource) -> Result<MediaStream> {
+        let request = MediaRequestParameters { source, format: MediaFormat::File };
+
+        let uri = match &request.source {
+            MediaSource::Plain(uri) if Self::as_local_uri(&request.source).is_none() => uri.clone(),
+            // Encrypted content can only be decrypted as a whole, and local content is only
+            // in the media cache.
+            _ => {
+                let content = self.get_media_content(&request, true).await?;
+                return Ok(MediaStream::new(self.clone(), None, MediaChunk::whole(content)));
+            }
+        };
+
+        // Fetch the first range of the content right away, to know its length.
+        let chunk = self.fetch_media_range(&uri, 0..DEFAULT_STREAM_CHUNK_SIZE).await?;
+
+        if chunk.is_whole() {
+            return Ok(MediaStream::new(self.clone(), None, chunk));
+        }
+
+        Ok(MediaStream::new(self.clone(), Some(uri), chunk))
+    }
+
+    /// Fetch a range of bytes of a media file's content.
+    ///
+    /// If the whole content is in the media cache, it is returned. Otherwise
+    /// the range is requested from the homeserver, which might return more or
+    /// less than the requested range, or the whole content, in which case it
+    /// is added to the media cache.
+    async fn fetch_media_range(&self, uri: &MxcUri, range: Range<u64>) -> Result<MediaChunk> {
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(uri.to_owned()),
+            format: MediaFormat::File,
+        };
+
+        if let Some(content) =
+            self.client.event_cache_store().lock().await?.get_media_content(&request).await?
+        {
+            return Ok(MediaChunk::whole(content));
+        }
+
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
+        let download = PartialDownload::with_range(range);
+
+        let data = if use_auth {
+            let request = authenticated_media::get_content::v1::Request::from_uri(uri)?;
+            self.client
+                .send(request)
+                .with_request_config(request_config)
+                .with_partial_download(download.clone())
+                .await?
+                .file
+        } else {
+            #[allow(deprecated)]
+            let request = media::get_content::v3::Request::from_url(uri)?;
+            self.client.send(request).with_partial_download(download.clone()).await?.file
+        };
+
+        let chunk = match download.partial_content() {
+            Some(PartialContent { start, total_len }) => MediaChunk { start, data, total_len },
+            None => MediaChunk::whole(data),
+        };
+
+        if chunk.is_whole() {
+            self.client
+                .event_cache_store()
+                .lock()
+                .await?
+                .add_media_content(&request, chunk.data.clone(), IgnoreMediaRetentionPolicy::No)
+                .await?;
+        }
+
+        Ok(chunk)
+    }
+
+    /// Download a media file's content, using the media cache.
+    ///
+    /// Concurrent downloads of the same content are deduplicated.
+    async fn download_media_content_with_cache(
+        &self,
+        request: &MediaRequestParameters,
+        download: PartialDownload,
+    ) -> Result<Vec<u8>> {
         // Read from the cache.
-        if use_cache {
-            if let Some(content) =
-                self.client.event_cache_store().lock().await?.get_media_content(request).await?
-            {
-                return Ok(content);
+        if let Some(content) =
+            self.client.event_cache_store().lock().await?.get_media_content(request).await?
+        {
+            return Ok(content);
+        }
+
+        let key = request.unique_key();
+        let store_key = format!("{PARTIAL_DOWNLOAD_KEY_PREFIX}{key}");
+        let mut downloaded_content = None;
+
+        self.client
+            .locks()
+            .media_download_deduplicated_handler
+            .run(key, async {
+                // The content might have been downloaded by another request while we were
+                // waiting.
+                if self
+                    .client
+                    .event_cache_store()
+                    .lock()
+                    .await?
+                    .get_media_content(request)
+                    .await?
+                    .is_some()
+                {
+                    return Ok(());
+                }
+
+                // Resume the download that was interrupted before a restart, if any.
+                if let Some(bytes) =
+                    self.client.state_store().get_custom_value(store_key.as_bytes()).await?
+                {
+                    download.restore(&bytes);
+                }
+
+                let content = self.fetch_media_content(request, download.clone()).await?;
+
+                self.client
+                    .event_cache_store()
+                    .lock()
+                    .await?
+                    .add_media_content(request, content.clone(), IgnoreMediaRetentionPolicy::No)
+                    .await?;
+
+                // The download is complete, there is nothing to resume anymore.
+                if download.is_persisted() {
+                    self.client.state_store().remove_custom_value(store_key.as_bytes()).await?;
+                }
+
+                downloaded_content = Some(content);
+                Ok(())
+            })
+            .await?;
+
+        if let Some(content) = downloaded_content {
+            return Ok(content);
+        }
+
+        // The content was downloaded by another request.
+        match self.client.event_cache_store().lock().await?.get_media_content(request).await? {
+            Some(content) => Ok(content),
+            // The content was not kept in the cache, because of the media retention policy.
+            None => self.fetch_media_content(request, download).await,
+        }
+    }
+
+    /// Forget a download of media content that ended.
+    ///
+    /// If the download failed or was aborted, the data that was received is
+    /// persisted, so the download can be resumed later, even after a restart.
+    async fn end_download(
+        &self,
+        request: &MediaRequestParameters,
+        download: &PartialDownload,
+        failed: bool,
+    ) {
+        let key = request.unique_key();
+
+        {
+            let mut partial_downloads = self.client.locks().partial_media_downloads.lock().unwrap();
+
+            // Another download of the same content might have started since this one.
+            if partial_downloads.get(&key).is_some_and(|other| other.is_same(download)) {
+                partial_downloads.remove(&key);
             }
+        }
+
+        if !failed {
+            return;
+        }
+
+        let Some(bytes) = download.serialize() else {
+            return;
         };
 
+        let store_key = format!("{PARTIAL_DOWNLOAD_KEY_PREFIX}{key}");
+
+        match self.client.state_store().set_custom_value_no_read(store_key.as_bytes(), bytes).await
+        {
+            Ok(()) => download.mark_as_persisted(),
+            Err(error) => warn!("Failed to persist the partial download of a media: {error}"),
+        }
+    }
+
+    /// Whether the authenticated media endpoints should be used, and the
+    /// request config to use with them.
+    async fn authenticated_media_config(&self) -> Result<(bool, Option<RequestConfig>)> {
         // Use the authenticated endpoints when the server supports Matrix 1.11 or the
         // authenticated media stable feature.
-        const AUTHENTICATED_MEDIA_STABLE_FEATURE: &str = "org.matrix.msc3916.stable";
-
-        let (use_auth, request_config) =
-            if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
-                (true, None)
-            } else if self
-                .client
-                .unstable_features()
-                .await?
-                .get(AUTHENTICATED_MEDIA_STABLE_FEATURE)
-                .is_some_and(|is_supported| *is_supported)
-            {
-                // We need to force the use of the stable endpoint with the Matrix version
-                // because Ruma does not handle stable features.
-                let request_config = self.client.request_config();
-                (true, Some(request_config.force_matrix_version(MatrixVersion::V1_11)))
-            } else {
-                (false, None)
-            };
+        if self.client.server_versions().await?.contains(&MatrixVersion::V1_11) {
+            Ok((true, None))
+        } else if is_unstable_feature_enabled(
+            &self.client.unstable_features().await?,
+            AUTHENTICATED_MEDIA_STABLE_FEATURE,
+        ) {
+            // We need to force the use of the stable endpoint with the Matrix version
+            // because Ruma does not handle stable features.
+            let request_config = self.client.request_config();
+            Ok((true, Some(request_config.force_matrix_version(MatrixVersion::V1_11))))
+        } else {
+            Ok((false, None))
+        }
+    }
+
+    /// Fetch a media file's content from the homeserver.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `download` - The state of the download of the content.
+    async fn fetch_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        download: PartialDownload,
+    ) -> Result<Vec<u8>> {
+        let (use_auth, request_config) = self.authenticated_media_config().await?;
 
         let content: Vec<u8> = match &request.source {
             MediaSource::Encrypted(file) => {
                 let content = if use_auth {
                     let request =
                         authenticated_media::get_content::v1::Request::from_uri(&file.url)?;
-                    self.client.send(request).with_request_config(request_config).await?.file
+                    self.client
+                        .send(request)
+                        .with_request_config(request_config)
+                        .with_partial_download(download.clone())
+                        .await?
+                        .file
                 } else {
                     #[allow(deprecated)]
                     let request = media::get_content::v3::Request::from_url(&file.url)?;
-                    self.client.send(request).await?.file
+                    self.client.send(request).with_partial_download(download.clone()).await?.file
                 };
 
                 #[cfg(feature = "e2e-encryption")]
                 let content = {
                     let content_len = content.len();
-                    let mut cursor = std::io::Cursor::new(content);
+                    let mut cursor = io::Cursor::new(content);
                     let mut reader = matrix_sdk_base::crypto::AttachmentDecryptor::new(
                         &mut cursor,
                         file.as_ref().clone().into(),
@@ -487,7 +1320,12 @@ impl Media {
                         request.method = Some(settings.method.clone());
                         request.animated = Some(settings.animated);
 
-                        self.client.send(request).with_request_config(request_config).await?.file
+                        self.client
+                            .send(request)
+                            .with_request_config(request_config)
+                            .with_partial_download(download.clone())
+                            .await?
+                            .file
                     } else {
                         #[allow(deprecated)]
                         let request = {
@@ -501,28 +1339,28 @@ impl Media {
                             request
                         };
 
-                        self.client.send(request).await?.file
+                        self.client
+                            .send(request)
+                            .with_partial_download(download.clone())
+                            .await?
+                            .file
                     }
                 } else if use_auth {
                     let request = authenticated_media::get_content::v1::Request::from_uri(uri)?;
-                    self.client.send(request).with_request_config(request_config).await?.file
+                    self.client
+                        .send(request)
+                        .with_request_config(request_config)
+                        .with_partial_download(download.clone())
+                        .await?
+                        .file
                 } else {
                     #[allow(deprecated)]
                     let request = media::get_content::v3::Request::from_url(uri)?;
-                    self.client.send(request).await?.file
+                    self.client.send(request).with_partial_download(download.clone()).await?.file
                 }
             }
         };
 
-        if use_cache {
-            self.client
-                .event_cache_store()
-                .lock()
-                .await?
-                .add_media_content(request, content.clone(), IgnoreMediaRetentionPolicy::No)
-                .await?;
-        }
-
         Ok(content)
     }
 
@@ -710,6 +1548,45 @@ impl Media {
         Ok(())
     }
 
+    /// Set whether the media content of the given request is pinned in the
+    /// media cache.
+    ///
+    /// Pinned media content is never removed during a cleanup of the media
+    /// cache, regardless of the [`MediaRetentionPolicy`]. This is useful for
+    /// media that should always be available, like avatars or stickers.
+    ///
+    /// This has no effect if the media content is not in the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequestParameters` of the content.
+    ///
+    /// * `pinned` - Whether the content should be pinned.
+    pub async fn set_media_pinned(
+        &self,
+        request: &MediaRequestParameters,
+        pinned: bool,
+    ) -> Result<()> {
+        let ignore_policy =
+            if pinned { IgnoreMediaRetentionPolicy::Yes } else { IgnoreMediaRetentionPolicy::No };
+
+        self.client
+            .event_cache_store()
+            .lock()
+            .await?
+            .set_ignore_media_retention_policy(request, ignore_policy)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the current usage of the media cache.
+    ///
+    /// This can be used to show how much space the media cache takes in the
+    /// settings of a client.
+    pub async fn media_cache_usage(&self) -> Result<MediaCacheUsage> {
+        Ok(self.client.event_cache_store().lock().await?.media_cache_usage().await?)
+    }
+
     /// Upload the file bytes in `data` and return the source information.
     pub(crate) async fn upload_plain_media_and_thumbnail(
         &self,
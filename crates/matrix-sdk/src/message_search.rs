@@ -0,0 +1,479 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for searching messages in the rooms, merging the results of the
+//! homeserver with the events known locally.
+
+use std::collections::{BTreeSet, HashSet};
+
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use ruma::{
+    api::client::search::search_events::v3::{Categories, Criteria, OrderBy, Request},
+    events::AnyTimelineEvent,
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, UInt,
+};
+use tracing::{debug, warn};
+
+use crate::{Client, Result, Room};
+
+/// The default number of results returned by [`MessageSearch::next_page()`].
+const DEFAULT_BATCH_SIZE: usize = 20;
+
+/// Where a [`MessageSearchResult`] was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageSearchSource {
+    /// The event was returned by the search API of the homeserver.
+    Server,
+
+    /// The event was found in the event cache.
+    Local,
+}
+
+/// A single result of a [`MessageSearch`].
+#[derive(Clone, Debug)]
+pub struct MessageSearchResult {
+    /// The room of the event.
+    pub room_id: OwnedRoomId,
+
+    /// The event that matched the search.
+    ///
+    /// If it was encrypted, it was decrypted if the room key is known.
+    pub event: TimelineEvent,
+
+    /// The rank of the event computed by the homeserver, if any.
+    pub rank: Option<f64>,
+
+    /// The words of the body of the event that should be highlighted.
+    pub highlights: Vec<String>,
+
+    /// Where the event was found.
+    pub source: MessageSearchSource,
+}
+
+/// The options of a [`MessageSearch`].
+#[derive(Clone, Debug)]
+pub struct MessageSearchOptions {
+    rooms: Option<BTreeSet<OwnedRoomId>>,
+    batch_size: usize,
+    search_server: bool,
+    search_local: bool,
+    #[cfg(feature = "e2e-encryption")]
+    decrypt: bool,
+}
+
+impl MessageSearchOptions {
+    /// Create the default options, to search all the joined rooms on the
+    /// homeserver and locally.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only search the given rooms.
+    pub fn rooms(mut self, rooms: impl IntoIterator<Item = OwnedRoomId>) -> Self {
+        self.rooms = Some(rooms.into_iter().collect());
+        self
+    }
+
+    /// Set the number of results returned by [`MessageSearch::next_page()`].
+    ///
+    /// Defaults to 20.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Whether to use the search API of the homeserver.
+    ///
+    /// Defaults to `true`.
+    pub fn search_server(mut self, search_server: bool) -> Self {
+        self.search_server = search_server;
+        self
+    }
+
+    /// Whether to search the events in the event cache, which is the only way
+    /// to search the encrypted rooms.
+    ///
+    /// The event cache must be subscribed to, otherwise this is ignored.
+    ///
+    /// Defaults to `true`.
+    pub fn search_local(mut self, search_local: bool) -> Self {
+        self.search_local = search_local;
+        self
+    }
+
+    /// Whether to decrypt the encrypted events returned by the homeserver,
+    /// with the room keys that are known.
+    ///
+    /// Defaults to `true`.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn decrypt(mut self, decrypt: bool) -> Self {
+        self.decrypt = decrypt;
+        self
+    }
+}
+
+impl Default for MessageSearchOptions {
+    fn default() -> Self {
+        Self {
+            rooms: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            search_server: true,
+            search_local: true,
+            #[cfg(feature = "e2e-encryption")]
+            decrypt: true,
+        }
+    }
+}
+
+/// The state of the search on the homeserver.
+#[derive(Debug)]
+enum ServerSearchState {
+    /// The first batch was not requested yet.
+    Start,
+
+    /// The token to request the next batch.
+    NextBatch(String),
+
+    /// All the results were received.
+    End,
+}
+
+/// A search of messages, merging the results of the [search API] of the
+/// homeserver with the events in the event cache.
+///
+/// The events known locally that contain all the words of the search term are
+/// loaded with the first page, and the homeserver is asked for more results
+/// whenever there are not enough results left to fill a page. The results are
+/// deduplicated and sorted by decreasing timestamp.
+///
+/// To create such a search, use [`Client::search_messages()`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use matrix_sdk::Client;
+/// # use url::Url;
+/// # async {
+/// # let homeserver = Url::parse("http://localhost:8080")?;
+/// # let client = Client::new(homeserver).await?;
+/// use matrix_sdk::message_search::MessageSearchOptions;
+///
+/// let mut search = client
+///     .search_messages("lunch", MessageSearchOptions::new().batch_size(10));
+///
+/// while !search.is_at_end() {
+///     for result in search.next_page().await? {
+///         println!("{}: {:?}", result.room_id, result.event.event_id());
+///     }
+/// }
+/// # anyhow::Ok(()) };
+/// ```
+///
+/// [search API]: https://spec.matrix.org/v1.14/client-server-api/#server-side-search
+#[derive(Debug)]
+pub struct MessageSearch {
+    client: Client,
+    search_term: String,
+
+    /// The lowercase words of the search term.
+    words: Vec<String>,
+
+    options: MessageSearchOptions,
+
+    /// Whether the events known locally were loaded.
+    local_events_loaded: bool,
+
+    server_state: ServerSearchState,
+
+    /// The timestamp of the oldest result of the homeserver.
+    ///
+    /// The results that are more recent can be returned, the homeserver won't
+    /// return more recent results anymore.
+    oldest_server_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The results that were not returned yet, sorted by decreasing timestamp.
+    pending: Vec<(MilliSecondsSinceUnixEpoch, MessageSearchResult)>,
+
+    /// The events that were found so far.
+    found: HashSet<OwnedEventId>,
+}
+
+impl MessageSearch {
+    pub(crate) fn new(client: Client, search_term: &str, options: MessageSearchOptions) -> Self {
+        let search_term = search_term.trim().to_owned();
+        let words = search_term.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>();
+
+        let server_state = if options.search_server && !words.is_empty() {
+            ServerSearchState::Start
+        } else {
+            ServerSearchState::End
+        };
+
+        Self {
+            client,
+            search_term,
+            words,
+            options,
+            local_events_loaded: false,
+            server_state,
+            oldest_server_timestamp: None,
+            pending: Vec::new(),
+            found: HashSet::new(),
+        }
+    }
+
+    /// Get the next page of results.
+    ///
+    /// Returns an empty list once the search is at its end.
+    pub async fn next_page(&mut self) -> Result<Vec<MessageSearchResult>> {
+        if !self.local_events_loaded {
+            self.load_local_events().await;
+            self.local_events_loaded = true;
+        }
+
+        while self.ready_results() < self.options.batch_size && self.search_server().await? {}
+
+        let end = self.options.batch_size.min(self.ready_results());
+        Ok(self.pending.drain(..end).map(|(_, result)| result).collect())
+    }
+
+    /// Whether all the results of the search were returned.
+    pub fn is_at_end(&self) -> bool {
+        self.local_events_loaded
+            && matches!(self.server_state, ServerSearchState::End)
+            && self.pending.is_empty()
+    }
+
+    /// The number of pending results that can be returned, because the
+    /// homeserver won't return more recent results.
+    fn ready_results(&self) -> usize {
+        match (&self.server_state, self.oldest_server_timestamp) {
+            (ServerSearchState::End, _) => self.pending.len(),
+            (_, Some(oldest)) => self.pending.partition_point(|(ts, _)| *ts >= oldest),
+            (_, None) => 0,
+        }
+    }
+
+    /// The rooms to search.
+    fn rooms(&self) -> Vec<Room> {
+        match &self.options.rooms {
+            Some(room_ids) => {
+                room_ids.iter().filter_map(|room_id| self.client.get_room(room_id)).collect()
+            }
+            None => self.client.joined_rooms(),
+        }
+    }
+
+    /// Load the events of the event cache that contain all the words of the
+    /// search term.
+    async fn load_local_events(&mut self) {
+        if !self.options.search_local || self.words.is_empty() {
+            return;
+        }
+
+        for room in self.rooms() {
+            let Ok((room_event_cache, _drop_handles)) = room.event_cache().await else {
+                debug!("The event cache is not subscribed, skipping the local search");
+                return;
+            };
+
+            let events = room_event_cache
+                .rfind_events(
+                    |event| {
+                        event_body(event.raw())
+                            .is_some_and(|body| contains_all_words(&body, &self.words))
+                    },
+                    usize::MAX,
+                )
+                .await;
+
+            for event in events {
+                let Some(event_id) = event.event_id() else { continue };
+                if !self.found.insert(event_id) {
+                    continue;
+                }
+
+                let highlights = self.words.clone();
+                self.push_pending(MessageSearchResult {
+                    room_id: room.room_id().to_owned(),
+                    event,
+                    rank: None,
+                    highlights,
+                    source: MessageSearchSource::Local,
+                });
+            }
+        }
+
+        self.sort_pending();
+    }
+
+    /// Ask the homeserver for more results, if it might have some.
+    ///
+    /// Returns whether the homeserver might have more results.
+    async fn search_server(&mut self) -> Result<bool> {
+        let next_batch = match &self.server_state {
+            ServerSearchState::Start => None,
+            ServerSearchState::NextBatch(token) => Some(token.clone()),
+            ServerSearchState::End => return Ok(false),
+        };
+
+        let mut criteria = Criteria::new(self.search_term.clone());
+        criteria.order_by = Some(OrderBy::Recent);
+        criteria.filter.limit = UInt::new(self.options.batch_size as u64);
+        criteria.filter.rooms =
+            self.options.rooms.as_ref().map(|rooms| rooms.iter().cloned().collect());
+
+        let mut categories = Categories::new();
+        categories.room_events = Some(criteria);
+
+        let mut request = Request::new(categories);
+        request.next_batch = next_batch;
+
+        let response = self.client.send(request).await?.search_categories.room_events;
+
+        self.server_state = match response.next_batch {
+            // Avoid looping forever if the homeserver returns an empty batch.
+            Some(token) if !response.results.is_empty() => ServerSearchState::NextBatch(token),
+            _ => ServerSearchState::End,
+        };
+
+        let highlights = response.highlights.iter().map(|h| h.to_lowercase()).collect::<Vec<_>>();
+
+        for result in response.results {
+            let Some(raw_event) = result.result else { continue };
+
+            let (Ok(Some(event_id)), Ok(Some(room_id)), Ok(Some(timestamp))) = (
+                raw_event.get_field::<OwnedEventId>("event_id"),
+                raw_event.get_field::<OwnedRoomId>("room_id"),
+                raw_event.get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts"),
+            ) else {
+                warn!("Ignoring malformed search result");
+                continue;
+            };
+
+            self.oldest_server_timestamp = Some(
+                self.oldest_server_timestamp.map_or(timestamp, |oldest| oldest.min(timestamp)),
+            );
+
+            if !self.found.insert(event_id) {
+                continue;
+            }
+
+            let event = self.decrypt_event(&room_id, raw_event).await;
+            let highlights = event_body(event.raw())
+                .map(|body| {
+                    highlights.iter().filter(|word| body.contains(word.as_str())).cloned().collect()
+                })
+                .unwrap_or_default();
+
+            self.push_pending(MessageSearchResult {
+                room_id,
+                event,
+                rank: result.rank,
+                highlights,
+                source: MessageSearchSource::Server,
+            });
+        }
+
+        self.sort_pending();
+        Ok(!matches!(self.server_state, ServerSearchState::End))
+    }
+
+    /// Try to decrypt the given event, if it is encrypted.
+    async fn decrypt_event(
+        &self,
+        room_id: &OwnedRoomId,
+        raw_event: Raw<AnyTimelineEvent>,
+    ) -> TimelineEvent {
+        #[cfg(feature = "e2e-encryption")]
+        if self.options.decrypt
+            && raw_event.get_field::<&str>("type").ok().flatten() == Some("m.room.encrypted")
+        {
+            if let Some(room) = self.client.get_room(room_id) {
+                match room.decrypt_event(raw_event.cast_ref()).await {
+                    Ok(event) => return event,
+                    Err(error) => debug!("Failed to decrypt search result: {error}"),
+                }
+            }
+        }
+
+        #[cfg(not(feature = "e2e-encryption"))]
+        let _ = room_id;
+
+        TimelineEvent::new(raw_event.cast())
+    }
+
+    fn push_pending(&mut self, result: MessageSearchResult) {
+        let timestamp = result
+            .event
+            .raw()
+            .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+            .ok()
+            .flatten()
+            .unwrap_or(MilliSecondsSinceUnixEpoch(UInt::MIN));
+
+        self.pending.push((timestamp, result));
+    }
+
+    fn sort_pending(&mut self) {
+        // The sort is stable, so the results with the same timestamp keep their order.
+        self.pending.sort_by(|(a, _), (b, _)| b.cmp(a));
+    }
+}
+
+/// The lowercase body of the given event, if any.
+fn event_body<T>(raw: &Raw<T>) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct Content {
+        body: Option<String>,
+    }
+
+    let content = raw.get_field::<Content>("content").ok().flatten()?;
+    Some(content.body?.to_lowercase())
+}
+
+/// Whether the given lowercase text contains all the given lowercase words.
+fn contains_all_words(text: &str, words: &[String]) -> bool {
+    words.iter().all(|word| text.contains(word.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::serde::Raw;
+    use serde_json::json;
+
+    use super::{contains_all_words, event_body};
+
+    #[test]
+    fn test_event_body() {
+        let event = Raw::new(&json!({
+            "type": "m.room.message",
+            "content": { "msgtype": "m.text", "body": "Lunch at NOON?" },
+        }))
+        .unwrap();
+        let body = event_body(&event).unwrap();
+        assert_eq!(body, "lunch at noon?");
+
+        assert!(contains_all_words(&body, &["noon".to_owned(), "lunch".to_owned()]));
+        assert!(!contains_all_words(&body, &["lunch".to_owned(), "dinner".to_owned()]));
+
+        let event = Raw::new(&json!({
+            "type": "m.room.encrypted",
+            "content": { "algorithm": "m.megolm.v1.aes-sha2", "ciphertext": "…" },
+        }))
+        .unwrap();
+        assert_eq!(event_body(&event), None);
+    }
+}
@@ -82,6 +82,29 @@ impl From<bool> for IsOneToOne {
     }
 }
 
+/// Where the notification mode of a room comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomNotificationModeSource {
+    /// The mode is the default one for this type of room.
+    Default,
+    /// The mode was defined by the user for this room.
+    UserDefined,
+}
+
+/// The notification settings that apply to a room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectiveRoomNotificationSettings {
+    /// The notification mode of the room.
+    pub mode: RoomNotificationMode,
+    /// Whether the mode is the default one, or was defined by the user.
+    pub source: RoomNotificationModeSource,
+    /// The sound played for the messages that notify in this mode, if any.
+    ///
+    /// It's the sound of the mentions if the mode is
+    /// [`RoomNotificationMode::MentionsAndKeywordsOnly`].
+    pub sound: Option<String>,
+}
+
 /// A high-level API to manage the client owner's push notification settings.
 #[derive(Debug, Clone)]
 pub struct NotificationSettings {
@@ -150,6 +173,29 @@ impl NotificationSettings {
         self.rules.read().await.get_default_room_notification_mode(is_encrypted, is_one_to_one)
     }
 
+    /// Get the notification settings that apply to a room: the user-defined
+    /// notification mode if any, the default one for this type of room
+    /// otherwise, and the sound of the notifications.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room
+    /// * `is_encrypted` - `Yes` if the room is encrypted
+    /// * `is_one_to_one` - `Yes` if the room is a direct chat involving two
+    ///   people
+    pub async fn get_effective_room_notification_settings(
+        &self,
+        room_id: &RoomId,
+        is_encrypted: IsEncrypted,
+        is_one_to_one: IsOneToOne,
+    ) -> EffectiveRoomNotificationSettings {
+        self.rules.read().await.get_effective_room_notification_settings(
+            room_id,
+            is_encrypted,
+            is_one_to_one,
+        )
+    }
+
     /// Get all room IDs for which a user-defined rule exists.
     pub async fn get_rooms_with_user_defined_rules(&self, enabled: Option<bool>) -> Vec<String> {
         self.rules.read().await.get_rooms_with_user_defined_rules(enabled)
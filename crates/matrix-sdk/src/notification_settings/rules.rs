@@ -4,8 +4,9 @@ use imbl::HashSet;
 use indexmap::IndexSet;
 use ruma::{
     push::{
-        AnyPushRuleRef, PatternedPushRule, PredefinedContentRuleId, PredefinedOverrideRuleId,
-        PredefinedUnderrideRuleId, PushCondition, RuleKind, Ruleset,
+        Action, AnyPushRuleRef, PatternedPushRule, PredefinedContentRuleId,
+        PredefinedOverrideRuleId, PredefinedUnderrideRuleId, PushCondition, RuleKind, Ruleset,
+        Tweak,
     },
     RoomId,
 };
@@ -13,7 +14,9 @@ use ruma::{
 use super::{command::Command, rule_commands::RuleCommands, RoomNotificationMode};
 use crate::{
     error::NotificationSettingsError,
-    notification_settings::{IsEncrypted, IsOneToOne},
+    notification_settings::{
+        EffectiveRoomNotificationSettings, IsEncrypted, IsOneToOne, RoomNotificationModeSource,
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -126,6 +129,91 @@ impl Rules {
         }
     }
 
+    /// Gets the notification mode that applies to a room, whether it's user
+    /// defined or the default one, and the sound of its notifications.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room
+    /// * `is_encrypted` - `Yes` if the room is encrypted
+    /// * `is_one_to_one` - `Yes` if the room is a direct chat involving two
+    ///   people
+    pub(crate) fn get_effective_room_notification_settings(
+        &self,
+        room_id: &RoomId,
+        is_encrypted: IsEncrypted,
+        is_one_to_one: IsOneToOne,
+    ) -> EffectiveRoomNotificationSettings {
+        let (mode, source) = match self.get_user_defined_room_notification_mode(room_id) {
+            Some(mode) => (mode, RoomNotificationModeSource::UserDefined),
+            None => (
+                self.get_default_room_notification_mode(is_encrypted, is_one_to_one),
+                RoomNotificationModeSource::Default,
+            ),
+        };
+
+        let sound = match (mode, source) {
+            (RoomNotificationMode::Mute, _) => None,
+
+            // Every message notifies with the actions of the rule that made the mode.
+            (RoomNotificationMode::AllMessages, RoomNotificationModeSource::UserDefined) => {
+                self.ruleset.get(RuleKind::Room, room_id).and_then(|rule| sound(rule.actions()))
+            }
+            (RoomNotificationMode::AllMessages, RoomNotificationModeSource::Default) => {
+                let rule_id = get_predefined_underride_room_rule_id(is_encrypted, is_one_to_one);
+                self.ruleset
+                    .get(RuleKind::Underride, rule_id.as_str())
+                    .and_then(|rule| sound(rule.actions()))
+            }
+
+            // Only mentions and keywords notify.
+            (RoomNotificationMode::MentionsAndKeywordsOnly, _) => {
+                self.mentions_and_keywords_sound()
+            }
+        };
+
+        EffectiveRoomNotificationSettings { mode, source, sound }
+    }
+
+    /// Get the sound of the notifications of the messages mentioning the user,
+    /// containing one of their keywords, or mentioning the whole room.
+    ///
+    /// The first sound set by an enabled rule is used, in this order.
+    fn mentions_and_keywords_sound(&self) -> Option<String> {
+        let mut rules = Vec::new();
+
+        match self.ruleset.get(RuleKind::Override, PredefinedOverrideRuleId::IsUserMention) {
+            Some(rule) => rules.push(rule),
+            // Fallback to deprecated rules for compatibility.
+            None => {
+                #[allow(deprecated)]
+                let deprecated_rules = [
+                    self.ruleset
+                        .get(RuleKind::Override, PredefinedOverrideRuleId::ContainsDisplayName),
+                    self.ruleset.get(RuleKind::Content, PredefinedContentRuleId::ContainsUserName),
+                ];
+                rules.extend(deprecated_rules.into_iter().flatten());
+            }
+        }
+
+        rules.extend(
+            self.ruleset.content.iter().filter(|r| !r.default).map(AnyPushRuleRef::Content),
+        );
+
+        match self.ruleset.get(RuleKind::Override, PredefinedOverrideRuleId::IsRoomMention) {
+            Some(rule) => rules.push(rule),
+            // Fallback to deprecated rule for compatibility.
+            None => {
+                #[allow(deprecated)]
+                let deprecated_rule =
+                    self.ruleset.get(RuleKind::Override, PredefinedOverrideRuleId::RoomNotif);
+                rules.extend(deprecated_rule);
+            }
+        }
+
+        rules.into_iter().filter(|rule| rule.enabled()).find_map(|rule| sound(rule.actions()))
+    }
+
     /// Get all room IDs for which a user-defined rule exists.
     pub(crate) fn get_rooms_with_user_defined_rules(&self, enabled: Option<bool>) -> Vec<String> {
         let test_if_enabled = enabled.is_some();
@@ -277,6 +365,14 @@ impl Rules {
     }
 }
 
+/// Get the sound set by the given push rule actions, if any.
+fn sound(actions: &[Action]) -> Option<String> {
+    actions.iter().find_map(|action| match action {
+        Action::SetTweak(Tweak::Sound(sound)) => Some(sound.clone()),
+        _ => None,
+    })
+}
+
 /// Gets the `PredefinedUnderrideRuleId` for rooms corresponding to the given
 /// criteria.
 ///
@@ -320,8 +416,9 @@ pub(crate) mod tests {
     };
     use ruma::{
         push::{
-            Action, NewConditionalPushRule, NewPushRule, PredefinedContentRuleId,
-            PredefinedOverrideRuleId, PredefinedUnderrideRuleId, PushCondition, RuleKind,
+            Action, NewConditionalPushRule, NewPatternedPushRule, NewPushRule,
+            PredefinedContentRuleId, PredefinedOverrideRuleId, PredefinedUnderrideRuleId,
+            PushCondition, RuleKind, Tweak,
         },
         OwnedRoomId, RoomId,
     };
@@ -331,7 +428,8 @@ pub(crate) mod tests {
         error::NotificationSettingsError,
         notification_settings::{
             rules::{self, Rules},
-            IsEncrypted, IsOneToOne, RoomNotificationMode,
+            EffectiveRoomNotificationSettings, IsEncrypted, IsOneToOne, RoomNotificationMode,
+            RoomNotificationModeSource,
         },
     };
 
@@ -484,6 +582,138 @@ pub(crate) mod tests {
         assert_eq!(mode, RoomNotificationMode::AllMessages);
     }
 
+    #[async_test]
+    async fn test_get_effective_room_notification_settings_default() {
+        let room_id = get_test_room_id();
+        let rules = Rules::new(get_server_default_ruleset());
+
+        // The `.m.rule.message` rule notifies without a sound.
+        assert_eq!(
+            rules.get_effective_room_notification_settings(
+                &room_id,
+                IsEncrypted::No,
+                IsOneToOne::No
+            ),
+            EffectiveRoomNotificationSettings {
+                mode: RoomNotificationMode::AllMessages,
+                source: RoomNotificationModeSource::Default,
+                sound: None,
+            }
+        );
+
+        // The `.m.rule.encrypted_room_one_to_one` rule notifies with a sound.
+        assert_eq!(
+            rules.get_effective_room_notification_settings(
+                &room_id,
+                IsEncrypted::Yes,
+                IsOneToOne::Yes
+            ),
+            EffectiveRoomNotificationSettings {
+                mode: RoomNotificationMode::AllMessages,
+                source: RoomNotificationModeSource::Default,
+                sound: Some("default".to_owned()),
+            }
+        );
+    }
+
+    #[async_test]
+    async fn test_get_effective_room_notification_settings_user_defined() {
+        let room_id = get_test_room_id();
+
+        // A `Room` rule that notifies.
+        let rules = Rules::new(build_ruleset(vec![(RuleKind::Room, &room_id, true)]));
+        assert_eq!(
+            rules.get_effective_room_notification_settings(
+                &room_id,
+                IsEncrypted::Yes,
+                IsOneToOne::No
+            ),
+            EffectiveRoomNotificationSettings {
+                mode: RoomNotificationMode::AllMessages,
+                source: RoomNotificationModeSource::UserDefined,
+                sound: Some("default".to_owned()),
+            }
+        );
+
+        // A `Room` rule that doesn't notify, only mentions notify with their own sound.
+        let rules = Rules::new(build_ruleset(vec![(RuleKind::Room, &room_id, false)]));
+        assert_eq!(
+            rules.get_effective_room_notification_settings(
+                &room_id,
+                IsEncrypted::No,
+                IsOneToOne::No
+            ),
+            EffectiveRoomNotificationSettings {
+                mode: RoomNotificationMode::MentionsAndKeywordsOnly,
+                source: RoomNotificationModeSource::UserDefined,
+                sound: Some("default".to_owned()),
+            }
+        );
+
+        // An `Override` rule that mutes the room.
+        let rules = Rules::new(build_ruleset(vec![(RuleKind::Override, &room_id, false)]));
+        assert_eq!(
+            rules.get_effective_room_notification_settings(
+                &room_id,
+                IsEncrypted::No,
+                IsOneToOne::Yes
+            ),
+            EffectiveRoomNotificationSettings {
+                mode: RoomNotificationMode::Mute,
+                source: RoomNotificationModeSource::UserDefined,
+                sound: None,
+            }
+        );
+    }
+
+    #[async_test]
+    async fn test_get_effective_room_notification_settings_mentions_and_keywords_sound() {
+        let room_id = get_test_room_id();
+        let mut ruleset = build_ruleset(vec![(RuleKind::Room, &room_id, false)]);
+        ruleset
+            .set_enabled(RuleKind::Override, PredefinedOverrideRuleId::IsUserMention, false)
+            .unwrap();
+        ruleset
+            .insert(
+                NewPushRule::Content(NewPatternedPushRule::new(
+                    "ping".to_owned(),
+                    "ping".to_owned(),
+                    vec![Action::Notify, Action::SetTweak(Tweak::Sound("ping".to_owned()))],
+                )),
+                None,
+                None,
+            )
+            .unwrap();
+
+        // The user mention rule is disabled, so the sound of the keyword is used.
+        let rules = Rules::new(ruleset.clone());
+        let settings = rules.get_effective_room_notification_settings(
+            &room_id,
+            IsEncrypted::No,
+            IsOneToOne::No,
+        );
+        assert_eq!(settings.mode, RoomNotificationMode::MentionsAndKeywordsOnly);
+        assert_eq!(settings.sound.as_deref(), Some("ping"));
+
+        // Without the keyword, the room mention rule is used.
+        ruleset.set_enabled(RuleKind::Content, "ping", false).unwrap();
+        ruleset
+            .set_actions(
+                RuleKind::Override,
+                PredefinedOverrideRuleId::IsRoomMention,
+                vec![Action::Notify, Action::SetTweak(Tweak::Sound("room".to_owned()))],
+            )
+            .unwrap();
+
+        let rules = Rules::new(ruleset);
+        let settings = rules.get_effective_room_notification_settings(
+            &room_id,
+            IsEncrypted::No,
+            IsOneToOne::No,
+        );
+        assert_eq!(settings.sound.as_deref(), Some("room"));
+    }
+
     #[async_test]
     async fn test_is_user_mention_enabled() {
         // If `IsUserMention` is enable, then is_user_mention_enabled() should return
@@ -0,0 +1,396 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Permalinks to rooms, events and users.
+//!
+//! A [`Permalink`] can be parsed from, and rendered as, both a `matrix.to`
+//! link and a `matrix:` URI. It can be resolved to a local room, and to an
+//! event of the event cache, with [`Client::resolve_permalink()`].
+//!
+//! Permalinks to an event of a room are built with
+//! [`Room::permalink_to()`](crate::Room::permalink_to), with the servers to
+//! route the room ID computed from the members of the room.
+
+use std::fmt;
+
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use ruma::{
+    matrix_uri::MatrixId, MatrixToUri, MatrixUri, OwnedEventId, OwnedRoomAliasId, OwnedRoomId,
+    OwnedServerName, OwnedUserId, RoomId, RoomOrAliasId,
+};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{Client, Result, Room};
+
+/// An error when parsing a [`Permalink`].
+#[derive(Debug, Error)]
+pub enum PermalinkParseError {
+    /// The string is neither a `matrix.to` link nor a `matrix:` URI.
+    #[error("not a matrix.to link or a matrix: URI")]
+    InvalidUri,
+
+    /// The URI points to an entity that isn't supported.
+    #[error("unsupported Matrix entity")]
+    UnsupportedEntity,
+}
+
+/// What a [`Permalink`] points to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PermalinkTarget {
+    /// A room, with its ID.
+    Room(OwnedRoomId),
+
+    /// A room, with one of its aliases.
+    RoomAlias(OwnedRoomAliasId),
+
+    /// An event of a room, with the ID or an alias of the room.
+    Event {
+        /// The ID or the alias of the room.
+        room: RoomIdOrAlias,
+
+        /// The ID of the event.
+        event_id: OwnedEventId,
+    },
+
+    /// A user.
+    User(OwnedUserId),
+}
+
+/// The ID or an alias of a room.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoomIdOrAlias {
+    /// The ID of the room.
+    Id(OwnedRoomId),
+
+    /// An alias of the room.
+    Alias(OwnedRoomAliasId),
+}
+
+impl From<&RoomOrAliasId> for RoomIdOrAlias {
+    fn from(room_or_alias_id: &RoomOrAliasId) -> Self {
+        match <&RoomId>::try_from(room_or_alias_id) {
+            Ok(room_id) => Self::Id(room_id.to_owned()),
+            Err(alias) => Self::Alias(alias.to_owned()),
+        }
+    }
+}
+
+/// A permalink to a room, an event or a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permalink {
+    target: PermalinkTarget,
+    via: Vec<OwnedServerName>,
+}
+
+impl Permalink {
+    /// Create a permalink to the room with the given ID, routed through the
+    /// given servers.
+    pub fn room(room_id: OwnedRoomId, via: Vec<OwnedServerName>) -> Self {
+        Self { target: PermalinkTarget::Room(room_id), via }
+    }
+
+    /// Create a permalink to the room with the given alias.
+    pub fn room_alias(alias: OwnedRoomAliasId) -> Self {
+        Self { target: PermalinkTarget::RoomAlias(alias), via: Vec::new() }
+    }
+
+    /// Create a permalink to an event of the room with the given ID, routed
+    /// through the given servers.
+    pub fn event(room_id: OwnedRoomId, event_id: OwnedEventId, via: Vec<OwnedServerName>) -> Self {
+        Self { target: PermalinkTarget::Event { room: RoomIdOrAlias::Id(room_id), event_id }, via }
+    }
+
+    /// Create a permalink to the given user.
+    pub fn user(user_id: OwnedUserId) -> Self {
+        Self { target: PermalinkTarget::User(user_id), via: Vec::new() }
+    }
+
+    /// Parse a permalink from a `matrix.to` link or a `matrix:` URI.
+    pub fn parse(uri: &str) -> Result<Self, PermalinkParseError> {
+        let (id, via) = if let Ok(uri) = MatrixUri::parse(uri) {
+            (uri.id().clone(), uri.via().to_owned())
+        } else if let Ok(uri) = MatrixToUri::parse(uri) {
+            (uri.id().clone(), uri.via().to_owned())
+        } else {
+            return Err(PermalinkParseError::InvalidUri);
+        };
+
+        let target = match id {
+            MatrixId::Room(room_id) => PermalinkTarget::Room(room_id),
+            MatrixId::RoomAlias(alias) => PermalinkTarget::RoomAlias(alias),
+            MatrixId::Event(room, event_id) => {
+                PermalinkTarget::Event { room: RoomIdOrAlias::from(&*room), event_id }
+            }
+            MatrixId::User(user_id) => PermalinkTarget::User(user_id),
+            _ => return Err(PermalinkParseError::UnsupportedEntity),
+        };
+
+        Ok(Self { target, via })
+    }
+
+    /// What this permalink points to.
+    pub fn target(&self) -> &PermalinkTarget {
+        &self.target
+    }
+
+    /// The servers to route the room ID through.
+    pub fn via(&self) -> &[OwnedServerName] {
+        &self.via
+    }
+
+    /// Render this permalink as a `matrix.to` link.
+    pub fn matrix_to_uri(&self) -> MatrixToUri {
+        let via = self.via.clone();
+
+        match &self.target {
+            PermalinkTarget::Room(room_id) => room_id.matrix_to_uri_via(via),
+            PermalinkTarget::RoomAlias(alias) => alias.matrix_to_uri(),
+            PermalinkTarget::Event { room: RoomIdOrAlias::Id(room_id), event_id } => {
+                room_id.matrix_to_event_uri_via(event_id.clone(), via)
+            }
+            PermalinkTarget::Event { room: RoomIdOrAlias::Alias(alias), event_id } => {
+                alias.matrix_to_event_uri(event_id.clone())
+            }
+            PermalinkTarget::User(user_id) => user_id.matrix_to_uri(),
+        }
+    }
+
+    /// Render this permalink as a `matrix:` URI.
+    ///
+    /// # Arguments
+    ///
+    /// * `join` - For a permalink to a room, whether the user should join the
+    ///   room. For a permalink to a user, whether a chat with the user should
+    ///   be started.
+    pub fn matrix_uri(&self, join: bool) -> MatrixUri {
+        let via = self.via.clone();
+
+        match &self.target {
+            PermalinkTarget::Room(room_id) => room_id.matrix_uri_via(via, join),
+            PermalinkTarget::RoomAlias(alias) => alias.matrix_uri(join),
+            PermalinkTarget::Event { room: RoomIdOrAlias::Id(room_id), event_id } => {
+                room_id.matrix_event_uri_via(event_id.clone(), via)
+            }
+            PermalinkTarget::Event { room: RoomIdOrAlias::Alias(alias), event_id } => {
+                alias.matrix_event_uri(event_id.clone())
+            }
+            PermalinkTarget::User(user_id) => user_id.matrix_uri(join),
+        }
+    }
+}
+
+impl fmt::Display for Permalink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.matrix_to_uri().fmt(f)
+    }
+}
+
+/// A [`Permalink`] resolved with [`Client::resolve_permalink()`].
+#[derive(Debug)]
+pub enum ResolvedPermalink {
+    /// The permalink points to a room known by the client, or to one of its
+    /// events.
+    Room {
+        /// The room.
+        room: Room,
+
+        /// The ID of the event the permalink points to, if any.
+        event_id: Option<OwnedEventId>,
+
+        /// The event the permalink points to, if it's in the event cache.
+        event: Option<TimelineEvent>,
+    },
+
+    /// The permalink points to a room unknown to the client, or to one of its
+    /// events.
+    UnknownRoom {
+        /// The ID of the room.
+        room_id: OwnedRoomId,
+
+        /// The ID of the event the permalink points to, if any.
+        event_id: Option<OwnedEventId>,
+
+        /// The servers to use to join or preview the room.
+        via: Vec<OwnedServerName>,
+    },
+
+    /// The permalink points to a user.
+    User(OwnedUserId),
+}
+
+impl Client {
+    /// Resolve a permalink to a local room, and to an event of the event
+    /// cache.
+    ///
+    /// Aliases are always resolved with the homeserver, since the aliases
+    /// advertised by the state of a room aren't checked and could be claimed by
+    /// any room. The event is only looked up if the event cache is subscribed
+    /// to the sync.
+    pub async fn resolve_permalink(&self, permalink: &Permalink) -> Result<ResolvedPermalink> {
+        let (room, event_id) = match permalink.target() {
+            PermalinkTarget::User(user_id) => return Ok(ResolvedPermalink::User(user_id.clone())),
+            PermalinkTarget::Room(room_id) => (RoomIdOrAlias::Id(room_id.clone()), None),
+            PermalinkTarget::RoomAlias(alias) => (RoomIdOrAlias::Alias(alias.clone()), None),
+            PermalinkTarget::Event { room, event_id } => (room.clone(), Some(event_id.clone())),
+        };
+
+        let mut via = permalink.via().to_owned();
+
+        let room_id = match room {
+            RoomIdOrAlias::Id(room_id) => room_id,
+            RoomIdOrAlias::Alias(alias) => {
+                let response = self.resolve_room_alias(&alias).await?;
+
+                if via.is_empty() {
+                    via = response.servers;
+                }
+
+                response.room_id
+            }
+        };
+
+        let Some(room) = self.get_room(&room_id) else {
+            return Ok(ResolvedPermalink::UnknownRoom { room_id, event_id, via });
+        };
+
+        let event = match &event_id {
+            Some(event_id) => match room.event_cache().await {
+                Ok((room_event_cache, _drop_handles)) => room_event_cache.event(event_id).await,
+                Err(error) => {
+                    debug!("Couldn't look up the event of the permalink: {error}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(ResolvedPermalink::Room { room, event_id, event })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        owned_event_id, owned_room_alias_id, owned_room_id, owned_server_name, room_id, user_id,
+    };
+
+    use super::{
+        Permalink, PermalinkParseError, PermalinkTarget, ResolvedPermalink, RoomIdOrAlias,
+    };
+    use crate::test_utils::logged_in_client;
+
+    #[test]
+    fn test_parse_matrix_to_and_matrix_uris() {
+        let permalink = Permalink::parse(
+            "https://matrix.to/#/!room:example.org/$event?via=example.org&via=other.org",
+        )
+        .unwrap();
+        assert_eq!(
+            permalink.target(),
+            &PermalinkTarget::Event {
+                room: RoomIdOrAlias::Id(owned_room_id!("!room:example.org")),
+                event_id: owned_event_id!("$event"),
+            }
+        );
+        assert_eq!(
+            permalink.via(),
+            [owned_server_name!("example.org"), owned_server_name!("other.org")]
+        );
+
+        // The same permalink, as a `matrix:` URI.
+        let matrix_uri = permalink.matrix_uri(false).to_string();
+        assert_eq!(Permalink::parse(&matrix_uri).unwrap(), permalink);
+
+        let permalink = Permalink::parse("matrix:r/alias:example.org").unwrap();
+        assert_eq!(
+            permalink.target(),
+            &PermalinkTarget::RoomAlias(owned_room_alias_id!("#alias:example.org"))
+        );
+        assert_eq!(permalink.to_string(), "https://matrix.to/#/%23alias:example.org");
+
+        assert_matches!(
+            Permalink::parse("https://example.org/#/!room:example.org"),
+            Err(PermalinkParseError::InvalidUri)
+        );
+    }
+
+    #[async_test]
+    async fn test_resolve_permalink() {
+        let client = logged_in_client(None).await;
+
+        let user_id = user_id!("@alice:example.org");
+        assert_matches!(
+            client.resolve_permalink(&Permalink::user(user_id.to_owned())).await,
+            Ok(ResolvedPermalink::User(resolved)) => {
+                assert_eq!(resolved, user_id);
+            }
+        );
+
+        let permalink = Permalink::event(
+            owned_room_id!("!unknown:example.org"),
+            owned_event_id!("$event"),
+            vec![owned_server_name!("example.org")],
+        );
+        assert_matches!(
+            client.resolve_permalink(&permalink).await,
+            Ok(ResolvedPermalink::UnknownRoom { room_id, event_id, via }) => {
+                assert_eq!(room_id, "!unknown:example.org");
+                assert_eq!(event_id.unwrap(), "$event");
+                assert_eq!(via, [owned_server_name!("example.org")]);
+            }
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn test_resolve_permalink_to_known_room() {
+        use crate::test_utils::mocks::MatrixMockServer;
+
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!known:example.org");
+        server.sync_joined_room(&client, room_id).await;
+
+        // The event cache isn't subscribed to the sync, so the event can't be looked
+        // up.
+        let permalink = Permalink::event(room_id.to_owned(), owned_event_id!("$event"), vec![]);
+        assert_matches!(
+            client.resolve_permalink(&permalink).await,
+            Ok(ResolvedPermalink::Room { room, event_id, event: None }) => {
+                assert_eq!(room.room_id(), room_id);
+                assert_eq!(event_id.unwrap(), "$event");
+            }
+        );
+
+        // Aliases are resolved with the homeserver.
+        server
+            .mock_room_directory_resolve_alias()
+            .ok(room_id.as_str(), vec!["example.org".to_owned()])
+            .mock_once()
+            .mount()
+            .await;
+
+        let permalink = Permalink::room_alias(owned_room_alias_id!("#known:example.org"));
+        assert_matches!(
+            client.resolve_permalink(&permalink).await,
+            Ok(ResolvedPermalink::Room { room, event_id: None, event: None }) => {
+                assert_eq!(room.room_id(), room_id);
+            }
+        );
+    }
+}
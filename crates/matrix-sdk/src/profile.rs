@@ -0,0 +1,423 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The profiles of users, cached in the state store and kept up to date.
+//!
+//! [`Client::profile()`] returns the display name and avatar of any user, and
+//! [`Client::subscribe_to_profile()`] allows to observe them, so all the
+//! places where a user is shown stay consistent. Profiles are fetched from the
+//! homeserver at most once at a time per user, and only when the cached
+//! profile is older than [`PROFILE_LIFETIME`].
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_common::executor::spawn;
+use ruma::{
+    events::room::member::{MembershipState, SyncRoomMemberEvent},
+    MilliSecondsSinceUnixEpoch, OwnedMxcUri, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{deduplicating_handler::DeduplicatingHandler, Client, Result};
+
+/// How long a profile fetched from the homeserver is considered up to date.
+pub const PROFILE_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The maximum number of profiles kept in memory.
+///
+/// When the cache is full, the profile that is not observed and was fetched
+/// the longest time ago is evicted. It can still be loaded from the store.
+const MAX_CACHED_PROFILES: usize = 1000;
+
+/// The public profile of a user.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UserProfile {
+    /// The display name of the user, if set.
+    pub display_name: Option<String>,
+
+    /// The MXC URI of the avatar of the user, if set.
+    pub avatar_url: Option<OwnedMxcUri>,
+}
+
+/// A profile as persisted in the state store.
+#[derive(Deserialize, Serialize)]
+struct StoredProfile {
+    profile: UserProfile,
+    fetched_at: MilliSecondsSinceUnixEpoch,
+}
+
+/// A profile cached in memory.
+struct CachedProfile {
+    observable: SharedObservable<Option<UserProfile>>,
+
+    /// When the profile was fetched from the homeserver, or `None` if it
+    /// hasn't been loaded yet or must be fetched again.
+    fetched_at: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl Default for CachedProfile {
+    fn default() -> Self {
+        Self { observable: SharedObservable::new(None), fetched_at: None }
+    }
+}
+
+/// The in-memory cache of the profiles of users, shared by all the clones of a
+/// [`Client`].
+#[derive(Default)]
+pub(crate) struct ProfileCache {
+    profiles: StdMutex<HashMap<OwnedUserId, CachedProfile>>,
+
+    /// Deduplicates the requests to fetch the profile of the same user.
+    fetches: DeduplicatingHandler<OwnedUserId>,
+
+    /// Whether the event handler that refreshes the profiles when members
+    /// change their profile has been registered.
+    live_updates_enabled: AtomicBool,
+}
+
+impl ProfileCache {
+    /// Get the profile of the given user, with when it was fetched, if it's in
+    /// memory.
+    fn get(&self, user_id: &UserId) -> Option<(UserProfile, Option<MilliSecondsSinceUnixEpoch>)> {
+        let profiles = self.profiles.lock().unwrap();
+        let cached = profiles.get(user_id)?;
+        Some((cached.observable.get()?, cached.fetched_at))
+    }
+
+    /// Get the observable profile of the given user, creating it if needed.
+    fn observable(&self, user_id: &UserId) -> SharedObservable<Option<UserProfile>> {
+        let mut profiles = self.profiles.lock().unwrap();
+        Self::make_room_for(&mut profiles, user_id);
+        profiles.entry(user_id.to_owned()).or_default().observable.clone()
+    }
+
+    /// Set the profile of the given user.
+    fn set(
+        &self,
+        user_id: &UserId,
+        profile: UserProfile,
+        fetched_at: Option<MilliSecondsSinceUnixEpoch>,
+    ) {
+        let mut profiles = self.profiles.lock().unwrap();
+        Self::make_room_for(&mut profiles, user_id);
+        let cached = profiles.entry(user_id.to_owned()).or_default();
+        cached.observable.set_if_not_eq(Some(profile));
+        cached.fetched_at = fetched_at;
+    }
+
+    /// Evict a profile if the cache is full and the profile of the given user
+    /// is not in it.
+    ///
+    /// Only the profiles that are not observed can be evicted, the one fetched
+    /// the longest time ago first.
+    fn make_room_for(profiles: &mut HashMap<OwnedUserId, CachedProfile>, user_id: &UserId) {
+        if profiles.len() < MAX_CACHED_PROFILES || profiles.contains_key(user_id) {
+            return;
+        }
+
+        let evicted = profiles
+            .iter()
+            .filter(|(_, cached)| cached.observable.subscriber_count() == 0)
+            .min_by_key(|(_, cached)| cached.fetched_at)
+            .map(|(user_id, _)| user_id.clone());
+
+        if let Some(evicted) = evicted {
+            profiles.remove(&evicted);
+        }
+    }
+
+    /// Mark the profile of the given user as outdated, if it's in memory.
+    ///
+    /// Returns whether the profile is observed.
+    fn invalidate(&self, user_id: &UserId) -> bool {
+        let mut profiles = self.profiles.lock().unwrap();
+        let Some(cached) = profiles.get_mut(user_id) else {
+            return false;
+        };
+
+        cached.fetched_at = None;
+        cached.observable.subscriber_count() > 0
+    }
+}
+
+/// Whether a profile fetched at the given time is still up to date.
+fn is_fresh(fetched_at: Option<MilliSecondsSinceUnixEpoch>) -> bool {
+    let Some(fetched_at) = fetched_at else {
+        return false;
+    };
+
+    let age =
+        u64::from(MilliSecondsSinceUnixEpoch::now().0).saturating_sub(u64::from(fetched_at.0));
+    u128::from(age) < PROFILE_LIFETIME.as_millis()
+}
+
+fn store_key(user_id: &UserId) -> String {
+    format!("user_profile:{user_id}")
+}
+
+impl Client {
+    /// Get the profile of the given user.
+    ///
+    /// The profile is returned from the cache if it was fetched less than
+    /// [`PROFILE_LIFETIME`] ago, otherwise it is fetched from the homeserver,
+    /// which might have to ask the homeserver of the user.
+    ///
+    /// If the profile can't be fetched, for example because the homeserver of
+    /// the user is unreachable or because it only shares profiles with users
+    /// in the same rooms, the outdated profile is returned if there is one,
+    /// and otherwise the display name and avatar from the membership of the
+    /// user in one of the joined rooms.
+    pub async fn profile(&self, user_id: &UserId) -> Result<UserProfile> {
+        self.enable_profile_live_updates();
+
+        let cached = self.cached_profile(user_id).await?;
+        if let Some((profile, fetched_at)) = &cached {
+            if is_fresh(*fetched_at) {
+                return Ok(profile.clone());
+            }
+        }
+
+        match self.refresh_profile(user_id).await {
+            Ok(profile) => Ok(profile),
+            Err(error) => {
+                if let Some((profile, _)) = cached {
+                    warn!(%user_id, "Couldn't refresh the profile, using the outdated one: {error}");
+                    return Ok(profile);
+                }
+
+                if let Some(profile) = self.profile_from_joined_rooms(user_id).await? {
+                    warn!(%user_id, "Couldn't fetch the profile, using the member state: {error}");
+                    return Ok(profile);
+                }
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Subscribe to the profile of the given user.
+    ///
+    /// The returned [`Subscriber`] starts with the cached profile, which is
+    /// `None` if it was never fetched, and yields the new profile every time it
+    /// changes. If the cached profile is missing or outdated, it is fetched in
+    /// the background.
+    ///
+    /// The profile is also refreshed when the user changes their display name
+    /// or avatar in one of the rooms the client is in.
+    pub async fn subscribe_to_profile(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Subscriber<Option<UserProfile>>> {
+        self.enable_profile_live_updates();
+
+        let cached = self.cached_profile(user_id).await?;
+        let subscriber = self.inner.caches.profiles.observable(user_id).subscribe();
+
+        if !cached.is_some_and(|(_, fetched_at)| is_fresh(fetched_at)) {
+            let client = self.clone();
+            let user_id = user_id.to_owned();
+            spawn(async move {
+                if let Err(error) = client.profile(&user_id).await {
+                    warn!(%user_id, "Couldn't fetch the profile: {error}");
+                }
+            });
+        }
+
+        Ok(subscriber)
+    }
+
+    /// Fetch the profile of the given user from the homeserver, even if the
+    /// cached profile is up to date, and update the cache.
+    ///
+    /// If the profile of the same user is already being fetched, this waits
+    /// for the result of that request instead of sending a new one.
+    pub async fn refresh_profile(&self, user_id: &UserId) -> Result<UserProfile> {
+        let profiles = &self.inner.caches.profiles;
+
+        profiles
+            .fetches
+            .run(user_id.to_owned(), async move {
+                let response = self.account().fetch_user_profile_of(user_id).await?;
+                let profile = UserProfile {
+                    display_name: response.displayname,
+                    avatar_url: response.avatar_url,
+                };
+                self.save_profile(user_id, profile).await
+            })
+            .await?;
+
+        // The profile was just saved, by this call or by the concurrent one. It might
+        // have been evicted from memory since then, but it is still in the store.
+        Ok(self.cached_profile(user_id).await?.map(|(profile, _)| profile).unwrap_or_default())
+    }
+
+    /// Update the cached profile of the given user, if there is one, without
+    /// fetching it from the homeserver.
+    pub(crate) async fn update_cached_profile(
+        &self,
+        user_id: &UserId,
+        update: impl FnOnce(&mut UserProfile),
+    ) -> Result<()> {
+        if let Some((mut profile, _)) = self.cached_profile(user_id).await? {
+            update(&mut profile);
+            self.save_profile(user_id, profile).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the cached profile of the given user, loading it from the store if
+    /// it's not in memory.
+    async fn cached_profile(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<(UserProfile, Option<MilliSecondsSinceUnixEpoch>)>> {
+        let profiles = &self.inner.caches.profiles;
+
+        if let Some(cached) = profiles.get(user_id) {
+            return Ok(Some(cached));
+        }
+
+        let Some(value) =
+            self.state_store().get_custom_value(store_key(user_id).as_bytes()).await?
+        else {
+            return Ok(None);
+        };
+
+        let stored = match serde_json::from_slice::<StoredProfile>(&value) {
+            Ok(stored) => stored,
+            Err(error) => {
+                warn!(%user_id, "Ignoring invalid stored profile: {error}");
+                return Ok(None);
+            }
+        };
+
+        // The profile might have been fetched while the store was read.
+        if let Some(cached) = profiles.get(user_id) {
+            return Ok(Some(cached));
+        }
+
+        profiles.set(user_id, stored.profile.clone(), Some(stored.fetched_at));
+        Ok(Some((stored.profile, Some(stored.fetched_at))))
+    }
+
+    /// Save the profile of the given user, in memory and in the store.
+    async fn save_profile(&self, user_id: &UserId, profile: UserProfile) -> Result<()> {
+        let stored = StoredProfile { profile, fetched_at: MilliSecondsSinceUnixEpoch::now() };
+
+        self.state_store()
+            .set_custom_value_no_read(store_key(user_id).as_bytes(), serde_json::to_vec(&stored)?)
+            .await?;
+        self.inner.caches.profiles.set(user_id, stored.profile, Some(stored.fetched_at));
+
+        Ok(())
+    }
+
+    /// Get the display name and avatar of the given user from their membership
+    /// in the first joined room where they have one of them.
+    async fn profile_from_joined_rooms(&self, user_id: &UserId) -> Result<Option<UserProfile>> {
+        for room in self.joined_rooms() {
+            let Some(member) = room.get_member_no_sync(user_id).await? else {
+                continue;
+            };
+
+            if member.display_name().is_some() || member.avatar_url().is_some() {
+                return Ok(Some(UserProfile {
+                    display_name: member.display_name().map(ToOwned::to_owned),
+                    avatar_url: member.avatar_url().map(ToOwned::to_owned),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Register the event handler that refreshes the observed profiles when
+    /// the users change them, if it's not registered yet.
+    fn enable_profile_live_updates(&self) {
+        if self.inner.caches.profiles.live_updates_enabled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.add_event_handler(|event: SyncRoomMemberEvent, client: Client| async move {
+            let SyncRoomMemberEvent::Original(event) = event else {
+                return;
+            };
+
+            // Only look at the changes of profile of joined members.
+            let Some(prev_content) = event.unsigned.prev_content else {
+                return;
+            };
+            if event.content.membership != MembershipState::Join
+                || prev_content.membership != MembershipState::Join
+                || (event.content.displayname == prev_content.displayname
+                    && event.content.avatar_url == prev_content.avatar_url)
+            {
+                return;
+            }
+
+            let user_id = event.state_key;
+            if client.inner.caches.profiles.invalidate(&user_id) {
+                debug!(%user_id, "The profile changed, refreshing it");
+
+                spawn(async move {
+                    if let Err(error) = client.refresh_profile(&user_id).await {
+                        warn!(%user_id, "Couldn't refresh the profile: {error}");
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{user_id, MilliSecondsSinceUnixEpoch, UInt, UserId};
+
+    use super::{ProfileCache, UserProfile, MAX_CACHED_PROFILES};
+
+    #[test]
+    fn test_cache_evicts_the_oldest_unobserved_profile() {
+        let cache = ProfileCache::default();
+        let fetched_at =
+            |ms: usize| Some(MilliSecondsSinceUnixEpoch(UInt::new(ms as u64).unwrap()));
+
+        // The oldest profile is observed, so it is kept.
+        let observed = user_id!("@observed:b.c");
+        cache.set(observed, UserProfile::default(), fetched_at(0));
+        let _subscriber = cache.observable(observed).subscribe();
+
+        for i in 1..MAX_CACHED_PROFILES {
+            let user_id = UserId::parse(format!("@user{i}:b.c")).unwrap();
+            cache.set(&user_id, UserProfile::default(), fetched_at(i));
+        }
+
+        cache.set(user_id!("@new:b.c"), UserProfile::default(), fetched_at(MAX_CACHED_PROFILES));
+
+        assert_eq!(cache.profiles.lock().unwrap().len(), MAX_CACHED_PROFILES);
+        assert!(cache.get(observed).is_some());
+        assert!(cache.get(user_id!("@user1:b.c")).is_none());
+        assert!(cache.get(user_id!("@user2:b.c")).is_some());
+        assert!(cache.get(user_id!("@new:b.c")).is_some());
+    }
+}
@@ -0,0 +1,639 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-side collection of logs and diagnostics for bug reports.
+//!
+//! A [`LogBuffer`] keeps the most recent log records of the application in
+//! memory, with the values of the sensitive fields redacted. When the user
+//! reports a bug, a snapshot of these logs can be sent to a [rageshake]
+//! server with a [`RageshakeUploader`], together with a summary of the state
+//! of the SDK returned by [`Client::diagnostics()`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::num::NonZeroUsize;
+//!
+//! use matrix_sdk::{
+//!     rageshake::{BugReport, LogBuffer, RageshakeUploader},
+//!     Client,
+//! };
+//! use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+//! use url::Url;
+//!
+//! # async fn example(client: Client) -> anyhow::Result<()> {
+//! let logs = LogBuffer::new(NonZeroUsize::new(5000).unwrap());
+//! tracing_subscriber::registry().with(logs.layer()).init();
+//!
+//! // Later, when the user reports a bug.
+//! let uploader = RageshakeUploader::new(
+//!     Url::parse("https://rageshakes.example.org/api/submit")?,
+//!     "my-app",
+//! );
+//! let report = BugReport::new("The timeline doesn't load")
+//!     .logs(logs.snapshot())
+//!     .diagnostics(client.diagnostics().await);
+//! uploader.upload(report).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [rageshake]: https://github.com/matrix-org/rageshake
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use matrix_sdk_base::event_cache::store::media::MediaCacheUsage;
+use matrix_sdk_common::ring_buffer::RingBuffer;
+use reqwest::multipart::{Form, Part};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedUserId};
+use serde::Deserialize;
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+use url::Url;
+
+#[cfg(feature = "e2e-encryption")]
+use crate::encryption::{
+    backups::BackupState, recovery::RecoveryState, CrossSigningStatus, VerificationState,
+};
+use crate::Client;
+
+/// The replacement of the redacted values.
+const REDACTED: &str = "<redacted>";
+
+/// The fields whose values are redacted, if their name contains one of these.
+const SENSITIVE_FIELDS: &[&str] = &["token", "password", "passphrase", "secret", "recovery_key"];
+
+/// The query parameters whose values are redacted in the messages, in case a
+/// URL is logged.
+const SENSITIVE_QUERY_PARAMS: &[&str] = &["access_token=", "login_token=", "token="];
+
+/// A log record kept in a [`LogBuffer`].
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    /// When the record was logged.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+
+    /// The level of the record, like `INFO`.
+    pub level: String,
+
+    /// The target of the record, usually the module that logged it.
+    pub target: String,
+
+    /// The names of the spans the record was logged in, from the outermost to
+    /// the innermost.
+    pub spans: Vec<String>,
+
+    /// The message of the record.
+    pub message: String,
+
+    /// The other fields of the record, with their sensitive values redacted.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.timestamp.get(), self.level, self.target)?;
+
+        for span in &self.spans {
+            write!(f, ":{span}")?;
+        }
+
+        write!(f, ": {}", self.message)?;
+
+        for (name, value) in &self.fields {
+            write!(f, " {name}={value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An in-memory buffer of the most recent log records.
+///
+/// The records are collected by the [`tracing_subscriber::Layer`] returned by
+/// [`LogBuffer::layer()`]. When the buffer is full, the oldest records are
+/// dropped.
+///
+/// The values of the fields whose name looks sensitive, like `access_token`
+/// or `passphrase`, and tokens in URLs are replaced by `<redacted>`.
+#[derive(Clone, Debug)]
+pub struct LogBuffer {
+    records: Arc<Mutex<RingBuffer<LogRecord>>>,
+}
+
+impl LogBuffer {
+    /// Create a new buffer keeping at most `capacity` records.
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self { records: Arc::new(Mutex::new(RingBuffer::new(capacity))) }
+    }
+
+    /// Get a layer collecting the log records into this buffer.
+    ///
+    /// Use [`Layer::with_filter()`] to restrict the records that are
+    /// collected, for example by level.
+    pub fn layer(&self) -> LogBufferLayer {
+        LogBufferLayer { buffer: self.clone() }
+    }
+
+    /// Get a copy of the records currently in the buffer, from the oldest to
+    /// the most recent.
+    pub fn snapshot(&self) -> Vec<LogRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Remove all the records from the buffer.
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    fn push(&self, record: LogRecord) {
+        self.records.lock().unwrap().push(record);
+    }
+}
+
+/// The [`tracing_subscriber::Layer`] collecting log records into a
+/// [`LogBuffer`].
+#[derive(Debug)]
+pub struct LogBufferLayer {
+    buffer: LogBuffer,
+}
+
+impl<S> Layer<S> for LogBufferLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        let mut visitor = RecordVisitor::default();
+        event.record(&mut visitor);
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| scope.from_root().map(|span| span.name().to_owned()).collect())
+            .unwrap_or_default();
+
+        self.buffer.push(LogRecord {
+            timestamp: MilliSecondsSinceUnixEpoch::now(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_owned(),
+            spans,
+            message: redact_query_params(&visitor.message),
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct RecordVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl RecordVisitor {
+    fn record_value(&mut self, field: &Field, value: String) {
+        let name = field.name();
+
+        if name == "message" {
+            self.message = value;
+        } else if is_sensitive_field(name) {
+            self.fields.insert(name.to_owned(), REDACTED.to_owned());
+        } else {
+            self.fields.insert(name.to_owned(), redact_query_params(&value));
+        }
+    }
+}
+
+impl Visit for RecordVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_value(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_value(field, format!("{value:?}"));
+    }
+}
+
+fn is_sensitive_field(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE_FIELDS.iter().any(|sensitive| name.contains(sensitive))
+}
+
+/// Redact the values of the sensitive query parameters in the given string.
+fn redact_query_params(value: &str) -> String {
+    let mut redacted = value.to_owned();
+
+    for param in SENSITIVE_QUERY_PARAMS {
+        let mut from = 0;
+
+        while let Some(position) = redacted[from..].find(param) {
+            let start = from + position + param.len();
+            let end = redacted[start..]
+                .find(|c: char| c == '&' || c == '"' || c.is_whitespace())
+                .map_or(redacted.len(), |len| start + len);
+
+            redacted.replace_range(start..end, REDACTED);
+            from = start + REDACTED.len();
+        }
+    }
+
+    redacted
+}
+
+/// A summary of the state of the SDK, to attach to bug reports.
+///
+/// It's returned by [`Client::diagnostics()`]. It doesn't contain any secret,
+/// nor the contents of the rooms.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Diagnostics {
+    /// The homeserver of the client.
+    pub homeserver: Url,
+
+    /// The ID of the logged-in user, if any.
+    pub user_id: Option<OwnedUserId>,
+
+    /// The ID of the device of the client, if any.
+    pub device_id: Option<OwnedDeviceId>,
+
+    /// Whether the client is logged in and has loaded its state from the
+    /// stores.
+    pub is_active: bool,
+
+    /// Whether the client has synced with the homeserver at least once.
+    pub has_synced: bool,
+
+    /// The number of joined rooms.
+    pub num_joined_rooms: usize,
+
+    /// The number of invited rooms.
+    pub num_invited_rooms: usize,
+
+    /// The number of left rooms.
+    pub num_left_rooms: usize,
+
+    /// The usage of the media cache, if it could be computed.
+    pub media_cache_usage: Option<MediaCacheUsage>,
+
+    /// The state of the end-to-end encryption.
+    #[cfg(feature = "e2e-encryption")]
+    pub crypto: CryptoDiagnostics,
+}
+
+/// The state of the end-to-end encryption, in [`Diagnostics`].
+#[cfg(feature = "e2e-encryption")]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct CryptoDiagnostics {
+    /// Which private cross-signing keys are available locally, if the crypto
+    /// machine is loaded.
+    pub cross_signing_status: Option<CrossSigningStatus>,
+
+    /// Whether this device is verified.
+    pub verification_state: VerificationState,
+
+    /// The state of the key backup.
+    pub backup_state: BackupState,
+
+    /// The state of the recovery.
+    pub recovery_state: RecoveryState,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "homeserver: {}", self.homeserver)?;
+        writeln!(f, "user_id: {:?}", self.user_id)?;
+        writeln!(f, "device_id: {:?}", self.device_id)?;
+        writeln!(f, "is_active: {}", self.is_active)?;
+        writeln!(f, "has_synced: {}", self.has_synced)?;
+        writeln!(f, "num_joined_rooms: {}", self.num_joined_rooms)?;
+        writeln!(f, "num_invited_rooms: {}", self.num_invited_rooms)?;
+        writeln!(f, "num_left_rooms: {}", self.num_left_rooms)?;
+
+        match &self.media_cache_usage {
+            Some(usage) => {
+                writeln!(f, "media_cache: {} media, {} bytes", usage.media_count, usage.total_size)?
+            }
+            None => writeln!(f, "media_cache: unknown")?,
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        {
+            writeln!(f, "cross_signing_status: {:?}", self.crypto.cross_signing_status)?;
+            writeln!(f, "verification_state: {:?}", self.crypto.verification_state)?;
+            writeln!(f, "backup_state: {:?}", self.crypto.backup_state)?;
+            writeln!(f, "recovery_state: {:?}", self.crypto.recovery_state)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Get a summary of the state of the SDK, to attach to bug reports.
+    ///
+    /// The parts that can't be computed, like the usage of the media cache if
+    /// the store returns an error, are left empty.
+    pub async fn diagnostics(&self) -> Diagnostics {
+        let media_cache_usage = self.media().media_cache_usage().await.ok();
+
+        Diagnostics {
+            homeserver: self.homeserver(),
+            user_id: self.user_id().map(ToOwned::to_owned),
+            device_id: self.device_id().map(ToOwned::to_owned),
+            is_active: self.is_active(),
+            has_synced: self.sync_token().await.is_some(),
+            num_joined_rooms: self.joined_rooms().len(),
+            num_invited_rooms: self.invited_rooms().len(),
+            num_left_rooms: self.left_rooms().len(),
+            media_cache_usage,
+            #[cfg(feature = "e2e-encryption")]
+            crypto: CryptoDiagnostics {
+                cross_signing_status: self.encryption().cross_signing_status().await,
+                verification_state: self.encryption().verification_state().get(),
+                backup_state: self.encryption().backups().state(),
+                recovery_state: self.encryption().recovery().state(),
+            },
+        }
+    }
+}
+
+/// A bug report to send with a [`RageshakeUploader`].
+#[derive(Clone, Debug)]
+pub struct BugReport {
+    text: String,
+    labels: Vec<String>,
+    logs: Vec<LogRecord>,
+    diagnostics: Option<Diagnostics>,
+    extra_fields: BTreeMap<String, String>,
+}
+
+impl BugReport {
+    /// Create a new bug report with the given description from the user.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            labels: Vec::new(),
+            logs: Vec::new(),
+            diagnostics: None,
+            extra_fields: BTreeMap::new(),
+        }
+    }
+
+    /// Add a label to the report, used by the rageshake server to triage it.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.labels.push(label.into());
+        self
+    }
+
+    /// Attach the given log records to the report.
+    pub fn logs(mut self, logs: Vec<LogRecord>) -> Self {
+        self.logs = logs;
+        self
+    }
+
+    /// Attach the given summary of the state of the SDK to the report.
+    pub fn diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Add a custom field to the report.
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_fields.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// An error when uploading a bug report.
+#[derive(Debug, thiserror::Error)]
+pub enum RageshakeError {
+    /// The request to the rageshake server failed.
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    /// The rageshake server rejected the report.
+    #[error("the rageshake server rejected the report with status {status}: {body}")]
+    Rejected {
+        /// The HTTP status of the response.
+        status: u16,
+
+        /// The body of the response.
+        body: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct SubmitResponse {
+    report_url: Option<String>,
+}
+
+/// Uploads bug reports to a [rageshake] server.
+///
+/// [rageshake]: https://github.com/matrix-org/rageshake
+#[derive(Clone, Debug)]
+pub struct RageshakeUploader {
+    http_client: reqwest::Client,
+    url: Url,
+    app: String,
+    version: Option<String>,
+    user_agent: String,
+}
+
+impl RageshakeUploader {
+    /// Create a new uploader to the given `submit` endpoint of a rageshake
+    /// server, like `https://rageshakes.example.org/api/submit`.
+    ///
+    /// The `app` name is used by the server to know where to file the report.
+    pub fn new(url: Url, app: impl Into<String>) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            url,
+            app: app.into(),
+            version: None,
+            user_agent: format!("matrix-rust-sdk/{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    /// Set the version of the application sending the reports.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the user agent sent with the reports.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Use the given HTTP client to upload the reports.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Upload the given bug report.
+    ///
+    /// Returns the URL of the report, if the rageshake server created an
+    /// issue for it.
+    pub async fn upload(&self, report: BugReport) -> Result<Option<String>, RageshakeError> {
+        let mut form = Form::new()
+            .text("text", report.text)
+            .text("app", self.app.clone())
+            .text("user_agent", self.user_agent.clone())
+            .text("sdk_version", env!("CARGO_PKG_VERSION"));
+
+        if let Some(version) = &self.version {
+            form = form.text("version", version.clone());
+        }
+
+        for label in report.labels {
+            form = form.text("label", label);
+        }
+
+        for (name, value) in report.extra_fields {
+            form = form.text(name, value);
+        }
+
+        if let Some(diagnostics) = report.diagnostics {
+            let part = Part::text(diagnostics.to_string()).file_name("diagnostics.txt");
+            form = form.part("file", part);
+        }
+
+        if !report.logs.is_empty() {
+            let mut logs = String::new();
+            for record in &report.logs {
+                let _ = writeln!(logs, "{record}");
+            }
+
+            form = form.part("log", Part::text(logs).file_name("logs.log"));
+        }
+
+        let response = self.http_client.post(self.url.clone()).multipart(form).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(RageshakeError::Rejected { status: status.as_u16(), body });
+        }
+
+        let body = response.bytes().await?;
+        Ok(serde_json::from_slice::<SubmitResponse>(&body).ok().and_then(|r| r.report_url))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use matrix_sdk_test::async_test;
+    use tracing::{info, info_span, subscriber::with_default};
+    use tracing_subscriber::layer::SubscriberExt;
+    use url::Url;
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{redact_query_params, BugReport, LogBuffer, RageshakeUploader};
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    #[test]
+    fn test_redact_query_params() {
+        assert_eq!(
+            redact_query_params("GET https://example.org/sync?access_token=abc&since=s1 done"),
+            "GET https://example.org/sync?access_token=<redacted>&since=s1 done"
+        );
+        assert_eq!(redact_query_params("login_token=xyz"), "login_token=<redacted>");
+        assert_eq!(redact_query_params("nothing to hide"), "nothing to hide");
+    }
+
+    #[test]
+    fn test_log_buffer_collects_and_redacts() {
+        let logs = LogBuffer::new(NonZeroUsize::new(2).unwrap());
+        let subscriber = tracing_subscriber::registry().with(logs.layer());
+
+        with_default(subscriber, || {
+            info!("dropped because the buffer is full");
+
+            let _span = info_span!("login").entered();
+            info!(user = "@alice:localhost", access_token = "secret", "Logging in");
+            info!(url = "https://example.org/?token=abc", "Fetching");
+        });
+
+        let records = logs.snapshot();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0].message, "Logging in");
+        assert_eq!(records[0].spans, ["login"]);
+        assert_eq!(records[0].fields["user"], "@alice:localhost");
+        assert_eq!(records[0].fields["access_token"], "<redacted>");
+
+        assert_eq!(records[1].fields["url"], "https://example.org/?token=<redacted>");
+        let line = records[1].to_string();
+        assert!(line.contains(" INFO matrix_sdk::rageshake::tests:login: Fetching "), "{line}");
+        assert!(line.ends_with("url=https://example.org/?token=<redacted>"), "{line}");
+
+        logs.clear();
+        assert!(logs.snapshot().is_empty());
+    }
+
+    #[async_test]
+    async fn test_upload_report() {
+        let rageshake_server = MockServer::start().await;
+        let matrix_server = MatrixMockServer::new().await;
+        let client = matrix_server.client_builder().build().await;
+
+        Mock::given(method("POST"))
+            .and(path("/api/submit"))
+            .and(body_string_contains("The timeline doesn't load"))
+            .and(body_string_contains("num_joined_rooms: 0"))
+            .and(body_string_contains("filename=\"logs.log\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "report_url": "https://example.org/1" })),
+            )
+            .expect(1)
+            .mount(&rageshake_server)
+            .await;
+
+        let logs = LogBuffer::new(NonZeroUsize::new(10).unwrap());
+        with_default(tracing_subscriber::registry().with(logs.layer()), || {
+            tracing::warn!("Something went wrong");
+        });
+
+        let uploader = RageshakeUploader::new(
+            Url::parse(&format!("{}/api/submit", rageshake_server.uri())).unwrap(),
+            "test-app",
+        )
+        .version("1.0");
+
+        let report = BugReport::new("The timeline doesn't load")
+            .label("timeline")
+            .logs(logs.snapshot())
+            .diagnostics(client.diagnostics().await);
+
+        let report_url = uploader.upload(report).await.unwrap();
+        assert_eq!(report_url.as_deref(), Some("https://example.org/1"));
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder to create rooms, see [`Client::room_builder()`].
+
+use std::{collections::BTreeMap, future::IntoFuture, time::Duration};
+
+use matrix_sdk_common::{boxed_into_future, timeout::timeout};
+use mime::Mime;
+use ruma::{
+    api::client::room::{create_room, Visibility},
+    assign,
+    events::{
+        room::{
+            avatar::{ImageInfo, RoomAvatarEventContent},
+            encryption::RoomEncryptionEventContent,
+        },
+        space::parent::SpaceParentEventContent,
+        AnyInitialStateEvent, InitialStateEvent,
+    },
+    int,
+    serde::Raw,
+    Int, OwnedRoomId, OwnedUserId, UInt,
+};
+use serde_json::json;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{Client, Error, Result, Room, RoomState};
+
+/// An invalid combination of settings in a [`RoomBuilder`].
+#[derive(Debug, Error)]
+pub enum RoomBuilderError {
+    /// A direct room was requested without inviting anyone.
+    #[error("a direct room must invite at least one user")]
+    DirectRoomWithoutInvites,
+
+    /// The space parent is not a space that the user has joined.
+    #[error("the space parent must be a joined space")]
+    InvalidSpaceParent,
+}
+
+/// A builder to create a room, returned by [`Client::room_builder()`].
+///
+/// The room is created when the builder is awaited. The settings are validated
+/// first, then the avatar is uploaded if needed, and the room is created with
+/// all its initial state in a single request, so it never exists without it.
+///
+/// Unless specified otherwise, the room is private, and it is encrypted if the
+/// `e2e-encryption` feature is enabled.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use matrix_sdk::Client;
+/// # use url::Url;
+/// # async {
+/// # let homeserver = Url::parse("http://localhost:8080")?;
+/// # let client = Client::new(homeserver).await?;
+/// use std::time::Duration;
+///
+/// use matrix_sdk::ruma::user_id;
+///
+/// let room = client
+///     .room_builder()
+///     .name("Rust")
+///     .topic("Everything about Rust")
+///     .invite([user_id!("@alice:example.org").to_owned()])
+///     .wait_for_sync(Duration::from_secs(30))
+///     .await?;
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Debug)]
+pub struct RoomBuilder {
+    client: Client,
+    name: Option<String>,
+    topic: Option<String>,
+    avatar: Option<(Mime, Vec<u8>)>,
+    invites: Vec<OwnedUserId>,
+    is_direct: bool,
+    power_levels: BTreeMap<OwnedUserId, Int>,
+    encryption: Option<bool>,
+    visibility: Visibility,
+    preset: Option<create_room::v3::RoomPreset>,
+    space_parent: Option<OwnedRoomId>,
+    wait_for_sync: Option<Duration>,
+}
+
+impl RoomBuilder {
+    pub(crate) fn new(client: Client) -> Self {
+        Self {
+            client,
+            name: None,
+            topic: None,
+            avatar: None,
+            invites: Vec::new(),
+            is_direct: false,
+            power_levels: BTreeMap::new(),
+            encryption: None,
+            visibility: Visibility::Private,
+            preset: None,
+            space_parent: None,
+            wait_for_sync: None,
+        }
+    }
+
+    /// Set the name of the room.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the topic of the room.
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Set the avatar of the room.
+    ///
+    /// The image is uploaded before the room is created.
+    pub fn avatar(mut self, content_type: Mime, data: Vec<u8>) -> Self {
+        self.avatar = Some((content_type, data));
+        self
+    }
+
+    /// Invite the given users in the room.
+    pub fn invite(mut self, user_ids: impl IntoIterator<Item = OwnedUserId>) -> Self {
+        self.invites.extend(user_ids);
+        self
+    }
+
+    /// Create a direct room with the invited users.
+    ///
+    /// The room is marked as direct in the account data, and the invited users
+    /// get the same power level as the creator, unless it's overridden.
+    pub fn direct(mut self) -> Self {
+        self.is_direct = true;
+        self
+    }
+
+    /// Give the given power level to the given user in the room.
+    pub fn power_level(mut self, user_id: OwnedUserId, power_level: Int) -> Self {
+        self.power_levels.insert(user_id, power_level);
+        self
+    }
+
+    /// Whether the room is encrypted.
+    ///
+    /// By default, the room is encrypted if the `e2e-encryption` feature is
+    /// enabled, unless it's published in the room directory. Encrypting a
+    /// public room is allowed, but it makes joining the room and reading its
+    /// history slower for everyone.
+    pub fn encrypted(mut self, encrypted: bool) -> Self {
+        self.encryption = Some(encrypted);
+        self
+    }
+
+    /// Set the visibility of the room in the room directory.
+    ///
+    /// If it's [`Visibility::Public`], the room is also public by default:
+    /// anyone can join it.
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Set the preset of the room, to use other defaults for its join rules,
+    /// history visibility and guest access.
+    ///
+    /// By default, it depends on the visibility of the room and on whether it's
+    /// direct.
+    pub fn preset(mut self, preset: create_room::v3::RoomPreset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Add the room to the given space.
+    ///
+    /// The room declares the space as its parent. The space must have been
+    /// joined by the user.
+    pub fn space_parent(mut self, space_id: OwnedRoomId) -> Self {
+        self.space_parent = Some(space_id);
+        self
+    }
+
+    /// Wait until the room is received in a sync response before returning it,
+    /// for at most the given duration.
+    ///
+    /// This requires the client to be syncing. If the room is not received
+    /// before the timeout, it is returned anyway, since it was created.
+    pub fn wait_for_sync(mut self, timeout: Duration) -> Self {
+        self.wait_for_sync = Some(timeout);
+        self
+    }
+
+    /// Check the settings, and build the request to create the room.
+    async fn into_request(self) -> Result<(Client, create_room::v3::Request, Option<Duration>)> {
+        let Self {
+            client,
+            name,
+            topic,
+            avatar,
+            invites,
+            is_direct,
+            mut power_levels,
+            encryption,
+            visibility,
+            preset,
+            space_parent,
+            wait_for_sync,
+        } = self;
+
+        if is_direct && invites.is_empty() {
+            return Err(RoomBuilderError::DirectRoomWithoutInvites.into());
+        }
+
+        let is_public = visibility == Visibility::Public;
+        let is_encrypted = encryption.unwrap_or(cfg!(feature = "e2e-encryption") && !is_public);
+
+        let space_parent = match space_parent {
+            Some(space_id) => {
+                let space = client
+                    .get_room(&space_id)
+                    .filter(|space| space.is_space() && space.state() == RoomState::Joined)
+                    .ok_or(RoomBuilderError::InvalidSpaceParent)?;
+                Some(space)
+            }
+            None => None,
+        };
+
+        let preset = preset.unwrap_or(if is_public {
+            create_room::v3::RoomPreset::PublicChat
+        } else if is_direct {
+            create_room::v3::RoomPreset::TrustedPrivateChat
+        } else {
+            create_room::v3::RoomPreset::PrivateChat
+        });
+
+        let mut initial_state = Vec::<Raw<AnyInitialStateEvent>>::new();
+
+        if is_encrypted {
+            initial_state.push(
+                InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults())
+                    .to_raw_any(),
+            );
+        }
+
+        if let Some((content_type, data)) = avatar {
+            let size = UInt::try_from(data.len()).ok();
+            let response = client.media().upload(&content_type, data, None).await?;
+            let info = assign!(ImageInfo::new(), {
+                mimetype: Some(content_type.to_string()),
+                size,
+            });
+            let content = assign!(RoomAvatarEventContent::new(), {
+                url: Some(response.content_uri),
+                info: Some(Box::new(info)),
+            });
+            initial_state.push(InitialStateEvent::new(content).to_raw_any());
+        }
+
+        if let Some(space) = space_parent {
+            let own_user_id = client.user_id().ok_or(Error::AuthenticationRequired)?;
+            let content = SpaceParentEventContent::new(vec![own_user_id.server_name().to_owned()]);
+            initial_state.push(
+                InitialStateEvent { content, state_key: space.room_id().to_owned() }.to_raw_any(),
+            );
+        }
+
+        // The power levels override replaces the whole `users` map, so the creator
+        // and, in trusted private chats, the invited users must be added to it.
+        let power_level_content_override = if power_levels.is_empty() {
+            None
+        } else {
+            let own_user_id = client.user_id().ok_or(Error::AuthenticationRequired)?;
+            power_levels.entry(own_user_id.to_owned()).or_insert(int!(100));
+
+            if preset == create_room::v3::RoomPreset::TrustedPrivateChat {
+                for user_id in &invites {
+                    power_levels.entry(user_id.clone()).or_insert(int!(100));
+                }
+            }
+
+            Some(Raw::new(&json!({ "users": power_levels }))?.cast())
+        };
+
+        let request = assign!(create_room::v3::Request::new(), {
+            name,
+            topic,
+            invite: invites,
+            is_direct,
+            visibility,
+            preset: Some(preset),
+            initial_state,
+            power_level_content_override,
+        });
+
+        Ok((client, request, wait_for_sync))
+    }
+}
+
+impl IntoFuture for RoomBuilder {
+    type Output = Result<Room>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let (client, request, wait_for_sync) = self.into_request().await?;
+            let room = client.create_room(request).await?;
+
+            let Some(duration) = wait_for_sync else {
+                return Ok(room);
+            };
+
+            match timeout(client.await_room_remote_echo(room.room_id()), duration).await {
+                Ok(room) => Ok(room),
+                Err(_) => {
+                    warn!(room_id = %room.room_id(), "The created room wasn't received in a sync in time");
+                    Ok(room)
+                }
+            }
+        })
+    }
+}
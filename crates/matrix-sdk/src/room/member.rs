@@ -91,6 +91,21 @@ impl RoomMember {
     }
 }
 
+/// An update of the members of a room, yielded by [`Room::members_stream()`].
+///
+/// [`Room::members_stream()`]: crate::Room::members_stream
+#[derive(Debug, Clone)]
+pub struct RoomMembersChunk {
+    /// The members that were added or updated.
+    pub members: Vec<RoomMember>,
+
+    /// Whether the member list of the room is complete.
+    ///
+    /// It is not complete when members are lazy-loaded by the sync and the
+    /// missing members haven't been fetched from the homeserver yet.
+    pub is_complete: bool,
+}
+
 /// The role of a member in a room.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
@@ -0,0 +1,238 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use ruma::{
+    api::client::filter::RoomEventFilter,
+    events::{room::member::SyncRoomMemberEvent, StateEventType},
+    serde::Raw,
+    uint, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId,
+};
+use tracing::{debug, warn};
+
+use super::{MessagesOptions, Room};
+use crate::Result;
+
+/// The default number of membership changes returned by
+/// [`MembershipHistory::next_page()`].
+const DEFAULT_BATCH_SIZE: usize = 20;
+
+/// The state of the pagination with `/messages`.
+#[derive(Debug)]
+enum PaginationState {
+    /// The first batch was not requested yet.
+    Start,
+
+    /// The token to request the next batch.
+    Next(String),
+
+    /// The start of the timeline was reached.
+    End,
+}
+
+/// The history of the membership changes of a user in a room, from the most
+/// recent to the oldest: joins, leaves, invites, kicks, bans, profile
+/// changes...
+///
+/// The changes known by the event cache are loaded with the first page, and
+/// the homeserver is paginated backwards for `m.room.member` events whenever
+/// there are not enough changes left to fill a page. The changes are
+/// deduplicated and sorted by decreasing timestamp.
+///
+/// To create it, use [`Room::membership_history()`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use matrix_sdk::Room;
+/// # async fn example(room: Room) -> matrix_sdk::Result<()> {
+/// use matrix_sdk::ruma::user_id;
+///
+/// let mut history = room.membership_history(user_id!("@alice:example.org"));
+///
+/// while !history.is_at_end() {
+///     for event in history.next_page().await? {
+///         println!("{}: {}", event.sender(), event.membership());
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug)]
+pub struct MembershipHistory {
+    room: Room,
+    user_id: OwnedUserId,
+    batch_size: usize,
+
+    /// Whether the events of the event cache were loaded.
+    local_events_loaded: bool,
+
+    pagination: PaginationState,
+
+    /// The timestamp of the oldest event returned by the homeserver.
+    ///
+    /// The changes that are more recent can be returned, the homeserver won't
+    /// return more recent events anymore.
+    oldest_server_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The changes that were not returned yet, sorted by decreasing timestamp.
+    pending: Vec<SyncRoomMemberEvent>,
+
+    /// The events that were found so far.
+    found: HashSet<OwnedEventId>,
+}
+
+impl MembershipHistory {
+    pub(super) fn new(room: Room, user_id: OwnedUserId) -> Self {
+        Self {
+            room,
+            user_id,
+            batch_size: DEFAULT_BATCH_SIZE,
+            local_events_loaded: false,
+            pagination: PaginationState::Start,
+            oldest_server_timestamp: None,
+            pending: Vec::new(),
+            found: HashSet::new(),
+        }
+    }
+
+    /// Set the number of membership changes returned by
+    /// [`MembershipHistory::next_page()`].
+    ///
+    /// Defaults to 20.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Get the next page of membership changes, the most recent first.
+    ///
+    /// Returns an empty list once the start of the history is reached.
+    pub async fn next_page(&mut self) -> Result<Vec<SyncRoomMemberEvent>> {
+        if !self.local_events_loaded {
+            self.load_local_events().await;
+            self.local_events_loaded = true;
+        }
+
+        while self.ready_changes() < self.batch_size && self.paginate().await? {}
+
+        let end = self.batch_size.min(self.ready_changes());
+        Ok(self.pending.drain(..end).collect())
+    }
+
+    /// Whether all the membership changes were returned.
+    pub fn is_at_end(&self) -> bool {
+        self.local_events_loaded
+            && matches!(self.pagination, PaginationState::End)
+            && self.pending.is_empty()
+    }
+
+    /// The number of pending changes that can be returned, because the
+    /// homeserver won't return more recent events.
+    fn ready_changes(&self) -> usize {
+        match (&self.pagination, self.oldest_server_timestamp) {
+            (PaginationState::End, _) => self.pending.len(),
+            (_, Some(oldest)) => {
+                self.pending.partition_point(|event| event.origin_server_ts() >= oldest)
+            }
+            (_, None) => 0,
+        }
+    }
+
+    /// Load the membership changes of the user that are in the event cache.
+    async fn load_local_events(&mut self) {
+        let Ok((room_event_cache, _drop_handles)) = self.room.event_cache().await else {
+            debug!("The event cache is not subscribed, only paginating the homeserver");
+            return;
+        };
+
+        let events = room_event_cache
+            .rfind_events(
+                |event| {
+                    event.raw().get_field::<&str>("type").ok().flatten() == Some("m.room.member")
+                        && event.raw().get_field::<&str>("state_key").ok().flatten()
+                            == Some(self.user_id.as_str())
+                },
+                usize::MAX,
+            )
+            .await;
+
+        for event in events {
+            self.push_pending(event.raw().cast_ref());
+        }
+
+        self.sort_pending();
+    }
+
+    /// Ask the homeserver for older `m.room.member` events, if there are some.
+    ///
+    /// Returns whether the homeserver might have older events.
+    async fn paginate(&mut self) -> Result<bool> {
+        let from = match &self.pagination {
+            PaginationState::Start => None,
+            PaginationState::Next(token) => Some(token.clone()),
+            PaginationState::End => return Ok(false),
+        };
+
+        // The homeserver can't filter the events by state key, so ask for more events
+        // than needed, since the events of the other members are discarded.
+        let mut options = MessagesOptions::backward().from(from.as_deref());
+        options.limit = uint!(100);
+        options.filter = RoomEventFilter::default();
+        options.filter.types = Some(vec![StateEventType::RoomMember.to_string()]);
+
+        let messages = self.room.messages(options).await?;
+
+        self.pagination = match messages.end {
+            // Avoid looping forever if the homeserver returns an empty batch.
+            Some(token) if !messages.chunk.is_empty() => PaginationState::Next(token),
+            _ => PaginationState::End,
+        };
+
+        for event in messages.chunk {
+            if let Ok(Some(timestamp)) =
+                event.raw().get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+            {
+                self.oldest_server_timestamp = Some(
+                    self.oldest_server_timestamp.map_or(timestamp, |oldest| oldest.min(timestamp)),
+                );
+            }
+
+            self.push_pending(event.raw().cast_ref());
+        }
+
+        self.sort_pending();
+        Ok(!matches!(self.pagination, PaginationState::End))
+    }
+
+    fn push_pending(&mut self, raw: &Raw<SyncRoomMemberEvent>) {
+        if raw.get_field::<&str>("state_key").ok().flatten() != Some(self.user_id.as_str()) {
+            return;
+        }
+
+        match raw.deserialize() {
+            Ok(event) => {
+                if self.found.insert(event.event_id().to_owned()) {
+                    self.pending.push(event);
+                }
+            }
+            Err(error) => warn!("Ignoring malformed membership event: {error}"),
+        }
+    }
+
+    fn sort_pending(&mut self) {
+        // The sort is stable, so the events with the same timestamp keep their order.
+        self.pending.sort_by_key(|event| std::cmp::Reverse(event.origin_server_ts()));
+    }
+}
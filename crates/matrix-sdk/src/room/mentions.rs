@@ -0,0 +1,241 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder for messages with [intentional mentions], see
+//! [`Room::message_with_mentions()`].
+//!
+//! [intentional mentions]: https://spec.matrix.org/latest/client-server-api/#user-and-room-mentions
+
+use std::{fmt::Write, future::IntoFuture};
+
+use matrix_sdk_base::RoomMemberships;
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent, Mentions},
+    OwnedEventId, OwnedUserId,
+};
+use thiserror::Error;
+
+use super::edit::{EditError, EditedContent};
+use crate::{Result, Room};
+
+/// An error when building a message with [`MentionsMessageBuilder`].
+#[derive(Debug, Error)]
+pub enum MentionsError {
+    /// A mentioned user isn't a member of the room, or invited to it.
+    #[error("the mentioned user {0} isn't in the room")]
+    UserNotInRoom(OwnedUserId),
+
+    /// The own user isn't allowed to mention the whole room.
+    #[error("the user isn't allowed to mention the whole room")]
+    RoomMentionForbidden,
+
+    /// The edited event couldn't be replaced.
+    #[error(transparent)]
+    Edit(#[from] EditError),
+}
+
+/// A part of a message built with [`MentionsMessageBuilder`].
+#[derive(Clone, Debug)]
+enum MessagePart {
+    Text(String),
+    User(OwnedUserId),
+    Room,
+}
+
+/// A builder for a message with intentional mentions, returned by
+/// [`Room::message_with_mentions()`].
+///
+/// The message is made of parts of text and mentions. When the builder is
+/// awaited, the mentioned users are checked to be in the room, and the
+/// content of the message is built with a plain text and an HTML body, where
+/// the users are replaced by their display name, and with the `m.mentions` of
+/// all the mentioned users.
+///
+/// If the message replaces an event with [`MentionsMessageBuilder::edit()`],
+/// only the users that weren't mentioned in the original event are notified.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async {
+/// # let room: matrix_sdk::Room = todo!();
+/// use matrix_sdk::ruma::user_id;
+///
+/// let content = room
+///     .message_with_mentions()
+///     .text("Hey ")
+///     .user(user_id!("@alice:example.org"))
+///     .text(", can you have a look?")
+///     .await?;
+///
+/// room.send(content).await?;
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Debug)]
+pub struct MentionsMessageBuilder {
+    room: Room,
+    parts: Vec<MessagePart>,
+    edited_event_id: Option<OwnedEventId>,
+}
+
+impl MentionsMessageBuilder {
+    pub(crate) fn new(room: Room) -> Self {
+        Self { room, parts: Vec::new(), edited_event_id: None }
+    }
+
+    /// Add text to the message.
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.parts.push(MessagePart::Text(text.into()));
+        self
+    }
+
+    /// Mention a user, who must be a member of the room or invited to it.
+    pub fn user(mut self, user_id: impl Into<OwnedUserId>) -> Self {
+        self.parts.push(MessagePart::User(user_id.into()));
+        self
+    }
+
+    /// Mention the whole room with `@room`.
+    ///
+    /// The own user must be allowed to trigger room notifications.
+    pub fn room(mut self) -> Self {
+        self.parts.push(MessagePart::Room);
+        self
+    }
+
+    /// Build a replacement of the event with the given ID instead of a new
+    /// message.
+    ///
+    /// The `m.mentions` of the replacement only contain the mentions that were
+    /// not in the original event, so users are not notified again.
+    pub fn edit(mut self, event_id: impl Into<OwnedEventId>) -> Self {
+        self.edited_event_id = Some(event_id.into());
+        self
+    }
+
+    /// Build the content of the message, with its intentional mentions.
+    async fn build_content(&self) -> Result<RoomMessageEventContent> {
+        let mut body = String::new();
+        let mut html_body = String::new();
+        let mut mentions = Mentions::new();
+
+        for part in &self.parts {
+            match part {
+                MessagePart::Text(text) => {
+                    body.push_str(text);
+                    html_body.push_str(&escape_html(text));
+                }
+
+                MessagePart::User(user_id) => {
+                    let member = self
+                        .room
+                        .get_member_no_sync(user_id)
+                        .await?
+                        .filter(|member| {
+                            RoomMemberships::JOIN
+                                .union(RoomMemberships::INVITE)
+                                .matches(member.membership())
+                        })
+                        .ok_or_else(|| MentionsError::UserNotInRoom(user_id.clone()))?;
+
+                    let name = member.name();
+                    body.push_str(name);
+                    let _ = write!(
+                        html_body,
+                        "<a href=\"{}\">{}</a>",
+                        user_id.matrix_to_uri(),
+                        escape_html(name)
+                    );
+
+                    mentions.user_ids.insert(user_id.clone());
+                }
+
+                MessagePart::Room => {
+                    let own_user_id = self.room.own_user_id();
+                    let power_levels = self.room.power_levels().await?;
+
+                    if !power_levels.user_can_trigger_room_notification(own_user_id) {
+                        return Err(MentionsError::RoomMentionForbidden.into());
+                    }
+
+                    body.push_str("@room");
+                    html_body.push_str("@room");
+                    mentions.room = true;
+                }
+            }
+        }
+
+        Ok(RoomMessageEventContent::text_html(body, html_body).add_mentions(mentions))
+    }
+}
+
+impl IntoFuture for MentionsMessageBuilder {
+    type Output = Result<RoomMessageEventContent>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let content = self.build_content().await?;
+
+            let Some(event_id) = &self.edited_event_id else {
+                return Ok(content);
+            };
+
+            let replacement = self
+                .room
+                .make_edit_event(event_id, EditedContent::RoomMessage(content.into()))
+                .await
+                .map_err(MentionsError::from)?;
+
+            let AnyMessageLikeEventContent::RoomMessage(replacement) = replacement else {
+                unreachable!("the replacement of a room message is a room message");
+            };
+
+            Ok(replacement)
+        })
+    }
+}
+
+/// Escape the characters of the given text that have a special meaning in
+/// HTML.
+pub(super) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_html;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<b>Tom & \"Jerry\"</b>"),
+            "&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;"
+        );
+    }
+}
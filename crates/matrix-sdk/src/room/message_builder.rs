@@ -0,0 +1,427 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder for rich text messages, see [`MessageBuilder`].
+
+use ruma::{
+    events::{
+        room::message::{
+            EmoteMessageEventContent, MessageType, NoticeMessageEventContent,
+            RoomMessageEventContentWithoutRelation, TextMessageEventContent,
+        },
+        Mentions,
+    },
+    html::{sanitize_html, HtmlSanitizerMode, RemoveReplyFallback},
+};
+use serde::Serialize;
+use thiserror::Error;
+
+/// The default maximum size of the content of a message built with
+/// [`MessageBuilder`], in bytes.
+///
+/// The specification limits the size of a whole event to 64 KiB, including its
+/// envelope. When a message is encrypted, its content is also inflated by the
+/// base64 encoding of the ciphertext, so this leaves some room for it.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 40 * 1024;
+
+/// An error when building a message with [`MessageBuilder`].
+#[derive(Debug, Error)]
+pub enum MessageBuilderError {
+    /// The content of the message is larger than the maximum allowed size.
+    #[error("the message is too large: {size} bytes, the maximum is {max_size} bytes")]
+    TooLarge {
+        /// The size of the serialized content of the message, in bytes.
+        size: usize,
+        /// The maximum allowed size, in bytes.
+        max_size: usize,
+    },
+
+    /// The content of the message couldn't be serialized.
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// The `msgtype` of a message built with [`MessageBuilder`].
+#[derive(Clone, Copy, Debug)]
+enum MessageKind {
+    Text,
+    Notice,
+    Emote,
+}
+
+/// The source of the HTML body of a message built with [`MessageBuilder`].
+#[derive(Clone, Debug)]
+enum HtmlSource {
+    /// The message only has a plain text body.
+    None,
+
+    /// The HTML body is generated from the plain text body, interpreted as
+    /// Markdown.
+    #[cfg(feature = "markdown")]
+    Markdown,
+
+    /// The HTML body was provided.
+    Html(String),
+}
+
+/// A builder for the content of a text, notice or emote message, with an
+/// optional rich text body.
+///
+/// When the content is built, the HTML body is sanitized to only keep the tags
+/// and attributes listed in the Matrix specification. The `language-*` classes
+/// of code blocks are kept, so clients can highlight their syntax. The bodies
+/// are otherwise kept as they were written: the [rich reply] fallbacks are
+/// handled by the SDK when replying with [`Room::make_message()`].
+///
+/// When the message is written in Markdown, text between double pipes, like
+/// `||this||`, is turned into a [spoiler].
+///
+/// The size of the built content is checked against a maximum size, which
+/// defaults to [`DEFAULT_MAX_MESSAGE_SIZE`] and can be lowered for
+/// homeservers that enforce a stricter limit with
+/// [`MessageBuilder::max_size()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # async {
+/// # let room: matrix_sdk::Room = todo!();
+/// use matrix_sdk::room::message_builder::MessageBuilder;
+///
+/// let content =
+///     MessageBuilder::html("Hello world", "<b>Hello</b> world").build()?;
+/// room.send(content.with_relation(None)).await?;
+/// # anyhow::Ok(()) };
+/// ```
+///
+/// [`Room::make_message()`]: crate::Room::make_message
+/// [rich reply]: https://spec.matrix.org/latest/client-server-api/#rich-replies
+/// [spoiler]: https://spec.matrix.org/latest/client-server-api/#spoiler-messages
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    kind: MessageKind,
+    body: String,
+    html: HtmlSource,
+    mentions: Option<Mentions>,
+    max_size: usize,
+}
+
+impl MessageBuilder {
+    fn new(body: String, html: HtmlSource) -> Self {
+        Self {
+            kind: MessageKind::Text,
+            body,
+            html,
+            mentions: None,
+            max_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Create a builder for a message with only a plain text body.
+    pub fn plain(body: impl Into<String>) -> Self {
+        Self::new(body.into(), HtmlSource::None)
+    }
+
+    /// Create a builder for a message written in Markdown.
+    ///
+    /// The Markdown is kept as the plain text body, and is converted to HTML
+    /// if it contains any formatting.
+    #[cfg(feature = "markdown")]
+    pub fn markdown(text: impl Into<String>) -> Self {
+        Self::new(text.into(), HtmlSource::Markdown)
+    }
+
+    /// Create a builder for a message with a plain text body and an HTML body.
+    pub fn html(body: impl Into<String>, html_body: impl Into<String>) -> Self {
+        Self::new(body.into(), HtmlSource::Html(html_body.into()))
+    }
+
+    /// Build an `m.notice` message instead of an `m.text` message.
+    pub fn notice(mut self) -> Self {
+        self.kind = MessageKind::Notice;
+        self
+    }
+
+    /// Build an `m.emote` message instead of an `m.text` message.
+    pub fn emote(mut self) -> Self {
+        self.kind = MessageKind::Emote;
+        self
+    }
+
+    /// Set the intentional mentions of the message.
+    pub fn mentions(mut self, mentions: Mentions) -> Self {
+        self.mentions = Some(mentions);
+        self
+    }
+
+    /// Set the maximum size of the serialized content of the message, in
+    /// bytes.
+    ///
+    /// Defaults to [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// The maximum size of the serialized content of the message, in bytes.
+    pub(crate) fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Build the content of the message.
+    ///
+    /// Returns an error if the content is larger than the maximum size.
+    pub fn build(self) -> Result<RoomMessageEventContentWithoutRelation, MessageBuilderError> {
+        let html_body = match self.html {
+            HtmlSource::None => None,
+            #[cfg(feature = "markdown")]
+            HtmlSource::Markdown => markdown_to_html(&self.body),
+            HtmlSource::Html(html_body) => Some(html_body),
+        }
+        .map(|html_body| {
+            sanitize_html(&html_body, HtmlSanitizerMode::Strict, RemoveReplyFallback::No)
+        });
+        let body = self.body;
+
+        let msgtype = match (self.kind, html_body) {
+            (MessageKind::Text, None) => MessageType::Text(TextMessageEventContent::plain(body)),
+            (MessageKind::Text, Some(html_body)) => {
+                MessageType::Text(TextMessageEventContent::html(body, html_body))
+            }
+            (MessageKind::Notice, None) => {
+                MessageType::Notice(NoticeMessageEventContent::plain(body))
+            }
+            (MessageKind::Notice, Some(html_body)) => {
+                MessageType::Notice(NoticeMessageEventContent::html(body, html_body))
+            }
+            (MessageKind::Emote, None) => MessageType::Emote(EmoteMessageEventContent::plain(body)),
+            (MessageKind::Emote, Some(html_body)) => {
+                MessageType::Emote(EmoteMessageEventContent::html(body, html_body))
+            }
+        };
+
+        let mut content = RoomMessageEventContentWithoutRelation::new(msgtype);
+        if let Some(mentions) = self.mentions {
+            content = content.add_mentions(mentions);
+        }
+
+        check_size(&content, self.max_size)?;
+
+        Ok(content)
+    }
+}
+
+/// Check that the serialized size of the given content is not larger than
+/// `max_size`.
+pub(crate) fn check_size(
+    content: &impl Serialize,
+    max_size: usize,
+) -> Result<(), MessageBuilderError> {
+    let size = serde_json::to_vec(content)?.len();
+
+    if size > max_size {
+        return Err(MessageBuilderError::TooLarge { size, max_size });
+    }
+
+    Ok(())
+}
+
+/// Convert the given Markdown to HTML, with spoilers.
+///
+/// Returns `None` if the text doesn't contain any formatting.
+#[cfg(feature = "markdown")]
+fn markdown_to_html(text: &str) -> Option<String> {
+    use ruma::events::room::message::FormattedBody;
+
+    let formatted = FormattedBody::markdown(text).map(|formatted| formatted.body);
+    let has_formatting = formatted.is_some();
+
+    // Even without formatting, the text might contain spoilers.
+    let html_body =
+        formatted.unwrap_or_else(|| super::mentions::escape_html(text).replace('\n', "<br>"));
+    let with_spoilers = add_spoilers(&html_body);
+
+    (has_formatting || with_spoilers != html_body).then_some(with_spoilers)
+}
+
+/// Wrap the text between double pipes in the given HTML in a spoiler `<span>`.
+///
+/// The text in code blocks and inline code is not modified.
+#[cfg(feature = "markdown")]
+fn add_spoilers(html: &str) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut code_depth = 0usize;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let tag = &rest[..tag_len(rest)];
+
+            if tag.starts_with("<code") || tag.starts_with("<pre") {
+                code_depth += 1;
+            } else if tag.starts_with("</code") || tag.starts_with("</pre") {
+                code_depth = code_depth.saturating_sub(1);
+            }
+
+            result.push_str(tag);
+            rest = &rest[tag.len()..];
+        } else {
+            let text_len = rest.find('<').unwrap_or(rest.len());
+            let text = &rest[..text_len];
+
+            if code_depth == 0 {
+                push_text_with_spoilers(&mut result, text);
+            } else {
+                result.push_str(text);
+            }
+
+            rest = &rest[text_len..];
+        }
+    }
+
+    result
+}
+
+/// The length of the tag at the start of the given HTML, ignoring the `>`
+/// characters in quoted attribute values.
+#[cfg(feature = "markdown")]
+fn tag_len(html: &str) -> usize {
+    let mut quote = None;
+
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+
+    html.len()
+}
+
+/// Push the given text to `result`, wrapping the parts between double pipes
+/// in a spoiler `<span>`.
+#[cfg(feature = "markdown")]
+fn push_text_with_spoilers(result: &mut String, mut text: &str) {
+    while let Some(start) = text.find("||") {
+        let Some(len) = text[start + 2..].find("||") else {
+            break;
+        };
+
+        if len == 0 {
+            // There is nothing to hide.
+            result.push_str(&text[..start + 4]);
+        } else {
+            result.push_str(&text[..start]);
+            result.push_str("<span data-mx-spoiler>");
+            result.push_str(&text[start + 2..start + 2 + len]);
+            result.push_str("</span>");
+        }
+
+        text = &text[start + 4 + len..];
+    }
+
+    result.push_str(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::{assert_let, assert_matches};
+    use ruma::events::room::message::MessageType;
+
+    use super::{MessageBuilder, MessageBuilderError};
+
+    #[test]
+    fn test_html_is_sanitized() {
+        let content = MessageBuilder::html(
+            "Hello",
+            "<b onclick=\"evil()\">Hello</b><iframe src=\"https://evil.org\"></iframe>",
+        )
+        .build()
+        .unwrap();
+
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert_eq!(text.body, "Hello");
+        assert_eq!(text.formatted.unwrap().body, "<b>Hello</b>");
+    }
+
+    #[test]
+    fn test_quotes_are_kept() {
+        // A quote written by the user looks like a reply fallback, but it must not be
+        // removed.
+        let body = "> <@alice:localhost> Hi\n\nHello";
+        let content = MessageBuilder::plain(body).build().unwrap();
+
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert_eq!(text.body, body);
+    }
+
+    #[test]
+    fn test_message_kinds() {
+        let content = MessageBuilder::plain("is here").emote().build().unwrap();
+        assert_let!(MessageType::Emote(emote) = content.msgtype);
+        assert_eq!(emote.body, "is here");
+        assert!(emote.formatted.is_none());
+
+        let content = MessageBuilder::html("Notice", "<i>Notice</i>").notice().build().unwrap();
+        assert_let!(MessageType::Notice(notice) = content.msgtype);
+        assert_eq!(notice.formatted.unwrap().body, "<i>Notice</i>");
+    }
+
+    #[test]
+    fn test_max_size() {
+        let body = "a".repeat(100);
+
+        assert_matches!(
+            MessageBuilder::plain(&body).max_size(100).build(),
+            Err(MessageBuilderError::TooLarge { size, max_size: 100 })
+        );
+        assert!(size > 100);
+
+        MessageBuilder::plain(body).max_size(200).build().unwrap();
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_markdown() {
+        let content = MessageBuilder::markdown("Hello world").build().unwrap();
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert!(text.formatted.is_none());
+
+        let content =
+            MessageBuilder::markdown("Don't tell ||Bob||\nhe's **here**").build().unwrap();
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert_eq!(text.body, "Don't tell ||Bob||\nhe's **here**");
+        assert_eq!(
+            text.formatted.unwrap().body,
+            "Don't tell <span data-mx-spoiler=\"\">Bob</span><br>\n\
+             he's <strong>here</strong>"
+        );
+
+        // Spoilers without any other formatting.
+        let content = MessageBuilder::markdown("It's ||a secret||").build().unwrap();
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert_eq!(text.formatted.unwrap().body, "It's <span data-mx-spoiler=\"\">a secret</span>");
+
+        // Code blocks keep their language, and their pipes.
+        let content =
+            MessageBuilder::markdown("```rust\nlet a = b || c || d;\n```").build().unwrap();
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        assert_eq!(
+            text.formatted.unwrap().body,
+            "<pre><code class=\"language-rust\">let a = b || c || d;\n</code></pre>\n"
+        );
+    }
+}
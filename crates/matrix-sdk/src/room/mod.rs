@@ -16,8 +16,9 @@
 
 use std::{
     borrow::Borrow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     future::Future,
+    mem,
     ops::Deref,
     sync::Arc,
     time::Duration,
@@ -47,51 +48,61 @@ use matrix_sdk_base::{
     event_cache::store::media::IgnoreMediaRetentionPolicy,
     media::MediaThumbnailSettings,
     store::StateStoreExt,
-    ComposerDraft, EncryptionState, RoomInfoNotableUpdateReasons, RoomMemberships, SendOutsideWasm,
-    StateChanges, StateStoreDataKey, StateStoreDataValue,
+    ComposerDraft, EncryptionState, RoomInfoNotableUpdateReasons, RoomMembersUpdate,
+    RoomMemberships, SendOutsideWasm, StateChanges, StateStoreDataKey, StateStoreDataValue,
+    SyncOutsideWasm,
 };
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
 use matrix_sdk_common::BoxFuture;
 use matrix_sdk_common::{
     deserialized_responses::TimelineEvent,
     executor::{spawn, JoinHandle},
+    sleep::sleep,
     timeout::timeout,
 };
 use mime::Mime;
 use reply::Reply;
 #[cfg(feature = "e2e-encryption")]
-use ruma::events::{
-    room::encrypted::OriginalSyncRoomEncryptedEvent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
-    SyncMessageLikeEvent,
-};
+use ruma::events::room::encrypted::OriginalSyncRoomEncryptedEvent;
 use ruma::{
-    api::client::{
-        config::{set_global_account_data, set_room_account_data},
-        context,
-        error::ErrorKind,
-        filter::LazyLoadOptions,
-        membership::{
-            ban_user, forget_room, get_member_events,
-            invite_user::{self, v3::InvitationRecipient},
-            kick_user, leave_room, unban_user, Invite3pid,
+    api::{
+        client::{
+            config::{set_global_account_data, set_room_account_data},
+            context,
+            error::ErrorKind,
+            filter::LazyLoadOptions,
+            membership::{
+                ban_user, forget_room, get_member_events,
+                invite_user::{self, v3::InvitationRecipient},
+                kick_user, leave_room, unban_user, Invite3pid, Invite3pidInit,
+            },
+            message::send_message_event,
+            read_marker::set_read_marker,
+            receipt::create_receipt,
+            redact::redact_event,
+            room::{get_room_event, report_content, report_room},
+            state::{get_state_events, get_state_events_for_key, send_state_event},
+            tag::{create_tag, delete_tag},
+            typing::create_typing_event::{self, v3::Typing},
         },
-        message::send_message_event,
-        read_marker::set_read_marker,
-        receipt::create_receipt,
-        redact::redact_event,
-        room::{get_room_event, report_content, report_room},
-        state::{get_state_events_for_key, send_state_event},
-        tag::{create_tag, delete_tag},
-        typing::create_typing_event::{self, v3::Typing},
+        MatrixVersion,
     },
     assign,
     events::{
-        beacon::BeaconEventContent,
-        beacon_info::BeaconInfoEventContent,
+        beacon::{BeaconEventContent, OriginalSyncBeaconEvent},
+        beacon_info::{BeaconInfoEventContent, OriginalSyncBeaconInfoEvent},
         call::notify::{ApplicationType, CallNotifyEventContent, NotifyType},
         direct::DirectEventContent,
+        location::{AssetType, LocationContent, ZoomLevel},
         marked_unread::{MarkedUnreadEventContent, UnstableMarkedUnreadEventContent},
+        poll::{
+            unstable_end::UnstablePollEndEventContent,
+            unstable_response::UnstablePollResponseEventContent,
+            unstable_start::{NewUnstablePollStartEventContent, UnstablePollStartEventContent},
+            PollResponseData,
+        },
         receipt::{Receipt, ReceiptThread, ReceiptType},
+        relation::RelationType,
         room::{
             avatar::{self, RoomAvatarEventContent},
             encryption::RoomEncryptionEventContent,
@@ -99,9 +110,9 @@ use ruma::{
             member::{MembershipChange, SyncRoomMemberEvent},
             message::{
                 AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
-                FormattedBody, ImageMessageEventContent, MessageType, RoomMessageEventContent,
-                UnstableAudioDetailsContentBlock, UnstableVoiceContentBlock, VideoInfo,
-                VideoMessageEventContent,
+                FormattedBody, ImageMessageEventContent, LocationMessageEventContent, MessageType,
+                RoomMessageEventContent, UnstableAudioDetailsContentBlock,
+                UnstableVoiceContentBlock, VideoInfo, VideoMessageEventContent,
             },
             name::RoomNameEventContent,
             pinned_events::RoomPinnedEventsEventContent,
@@ -111,47 +122,77 @@ use ruma::{
             ImageInfo, MediaSource, ThumbnailInfo,
         },
         space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
-        tag::{TagInfo, TagName},
+        tag::{TagInfo, TagName, Tags},
         typing::SyncTypingEvent,
-        AnyRoomAccountDataEvent, AnyRoomAccountDataEventContent, AnyTimelineEvent, EmptyStateKey,
-        Mentions, MessageLikeEventContent, MessageLikeEventType, OriginalSyncStateEvent,
-        RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
+        AnyMessageLikeEventContent, AnyRoomAccountDataEvent, AnyRoomAccountDataEventContent,
+        AnyStateEventContent, AnySyncMessageLikeEvent, AnySyncTimelineEvent, AnyTimelineEvent,
+        EmptyStateKey, Mentions, MessageLikeEventContent, MessageLikeEventType,
+        OriginalSyncStateEvent, RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
         RoomAccountDataEventContent, RoomAccountDataEventType, StateEventContent, StateEventType,
-        StaticEventContent, StaticStateEventContent, SyncStateEvent,
+        StaticEventContent, StaticStateEventContent, SyncMessageLikeEvent, SyncStateEvent,
     },
     push::{Action, PushConditionRoomCtx},
     serde::Raw,
+    thirdparty::Medium,
     time::Instant,
-    EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
-    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
+    EventId, Int, MatrixToUri, MatrixUri, MilliSecondsSinceUnixEpoch, MxcUri, OwnedEventId,
+    OwnedRoomId, OwnedServerName, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt,
+    UserId,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::value::RawValue as RawJsonValue;
 use thiserror::Error;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, broadcast::error::RecvError, mpsc};
 use tokio_stream::StreamExt;
 use tracing::{debug, info, instrument, warn};
 
 use self::futures::{SendAttachment, SendMessageLikeEvent, SendRawMessageLikeEvent};
+pub(crate) use self::typing::TypingGuards;
 pub use self::{
-    member::{RoomMember, RoomMemberRole},
+    member::{RoomMember, RoomMemberRole, RoomMembersChunk},
+    membership_history::MembershipHistory,
     messages::{EventWithContextResponse, Messages, MessagesOptions},
+    typing::TypingGuard,
 };
 #[cfg(doc)]
 use crate::event_cache::EventCache;
 use crate::{
+    account::ObservableAccountData,
     attachment::{AttachmentConfig, AttachmentInfo},
     client::WeakClient,
     config::RequestConfig,
     error::{BeaconError, WrongRoomState},
     event_cache::{self, EventCacheDropHandles, RoomEventCache},
-    event_handler::{EventHandler, EventHandlerDropGuard, EventHandlerHandle, SyncEvent},
-    live_location_share::ObservableLiveLocation,
+    event_handler::{
+        CustomEventContent, CustomEventKind, EventHandler, EventHandlerDropGuard,
+        EventHandlerHandle, SyncEvent,
+    },
+    identity_server::IdentityServerError,
+    image_pack::{
+        load_emotes, EmoteRegistry, ImagePack, ObservableEmotes, RoomImagePackEventContent,
+    },
+    live_location_share::{
+        ActiveLiveLocationShare, ActiveLiveLocationShares, ObservableLiveLocation,
+    },
     media::{MediaFormat, MediaRequestParameters},
-    notification_settings::{IsEncrypted, IsOneToOne, RoomNotificationMode},
+    notification_settings::{
+        EffectiveRoomNotificationSettings, IsEncrypted, IsOneToOne, NotificationSettings,
+        RoomNotificationMode,
+    },
+    permalink::Permalink,
+    profile::UserProfile,
     room::{
         knock_requests::{KnockRequest, KnockRequestMemberInfo},
+        mentions::MentionsMessageBuilder,
+        message_builder::{check_size, MessageBuilder},
+        moderation::RoomModeration,
+        permissions::RoomPermissions,
+        polls::{NewPoll, PollResults},
         power_levels::{RoomPowerLevelChanges, RoomPowerLevelsExt},
         privacy_settings::RoomPrivacySettings,
+        state::{
+            RoomStateSnapshot, StateBatchError, StateBatchEvent, StateBatchFailure, StateRollback,
+        },
     },
     sync::RoomUpdate,
     utils::{IntoRawMessageLikeEventContent, IntoRawStateEventContent},
@@ -163,21 +204,31 @@ use crate::{
     room::shared_room_history::share_room_history,
 };
 
+pub mod builder;
 pub mod edit;
 pub mod futures;
 pub mod identity_status_changes;
 /// Contains code related to requests to join a room.
 pub mod knock_requests;
 mod member;
+mod membership_history;
+pub mod mentions;
+pub mod message_builder;
 mod messages;
+pub mod moderation;
+pub mod permissions;
+pub mod polls;
 pub mod power_levels;
+pub mod receipts;
 pub mod reply;
+pub mod state;
 
 /// Contains all the functionality for modifying the privacy settings in a room.
 pub mod privacy_settings;
 
 #[cfg(feature = "e2e-encryption")]
 mod shared_room_history;
+mod typing;
 
 /// A struct containing methods that are common for Joined, Invited and Left
 /// Rooms
@@ -604,7 +655,11 @@ impl Room {
             .locks()
             .members_request_deduplicated_handler
             .run(self.room_id().to_owned(), async move {
-                let request = get_member_events::v3::Request::new(self.inner.room_id().to_owned());
+                let mut request =
+                    get_member_events::v3::Request::new(self.inner.room_id().to_owned());
+                // Get the members at the point of the timeline we know about, so they are
+                // consistent with the state we received from the sync.
+                request.at = self.client.sync_token().await;
                 let response = self
                     .client
                     .send(request.clone())
@@ -622,6 +677,7 @@ impl Room {
                     &response,
                 ))
                 .await?;
+                self.client.inner.caches.known_users.invalidate();
 
                 Ok(())
             })
@@ -712,6 +768,11 @@ impl Room {
             this_device_is_verified,
             is_backup_configured: encryption.backups().state() == BackupState::Enabled,
             backup_exists_on_server,
+            // As defined in MSC3061.
+            room_history_shared: matches!(
+                self.inner.history_visibility_or_default(),
+                HistoryVisibility::Shared | HistoryVisibility::WorldReadable
+            ),
         }
     }
 
@@ -728,9 +789,15 @@ impl Room {
 
     /// Sync the member list with the server.
     ///
+    /// The members are fetched as they were at the latest sync, and only the
+    /// member events that are newer than the ones known locally are applied.
+    ///
     /// This method will de-duplicate requests if it is called multiple times in
     /// quick succession, in that case the return value will be `None`. This
     /// method does nothing if the members are already synced.
+    ///
+    /// It is the method used by [`Room::members_stream()`] to fetch the
+    /// missing members, use it to observe the members as they are loaded.
     pub async fn sync_members(&self) -> Result<()> {
         if !self.are_events_visible() {
             return Ok(());
@@ -782,6 +849,39 @@ impl Room {
             .map(|member| RoomMember::new(self.client.clone(), member)))
     }
 
+    /// Get the profile of the given user in this room.
+    ///
+    /// The display name and avatar set in the membership of the user in this
+    /// room take precedence over the ones of their global profile, which is
+    /// only fetched with [`Client::profile()`] if one of them is missing.
+    ///
+    /// This doesn't fetch the members of the room from the homeserver.
+    pub async fn member_profile(&self, user_id: &UserId) -> Result<UserProfile> {
+        let member = self.get_member_no_sync(user_id).await?;
+        let mut profile = UserProfile {
+            display_name: member.as_ref().and_then(|m| m.display_name()).map(ToOwned::to_owned),
+            avatar_url: member.as_ref().and_then(|m| m.avatar_url()).map(ToOwned::to_owned),
+        };
+
+        if profile.display_name.is_some() && profile.avatar_url.is_some() {
+            return Ok(profile);
+        }
+
+        match self.client.profile(user_id).await {
+            Ok(global) => {
+                profile.display_name = profile.display_name.or(global.display_name);
+                profile.avatar_url = profile.avatar_url.or(global.avatar_url);
+            }
+            // The member state is good enough if the global profile is unavailable.
+            Err(error) if member.is_some() => {
+                warn!(%user_id, "Couldn't get the global profile of the member: {error}");
+            }
+            Err(error) => return Err(error),
+        }
+
+        Ok(profile)
+    }
+
     /// Get members for this room, with the given memberships.
     ///
     /// *Note*: This method will fetch the members from the homeserver if the
@@ -813,6 +913,133 @@ impl Room {
             .collect())
     }
 
+    /// Observe the members of this room, with the given memberships.
+    ///
+    /// The stream first yields the members that are known locally, then the
+    /// members that are added or updated afterwards, for example by a sync.
+    ///
+    /// When members are lazy-loaded by the sync, the member list isn't
+    /// complete. In that case, polling the stream after the first chunk
+    /// fetches the missing members from the homeserver, as they were at the
+    /// latest sync, and yields the ones that were missing. The member list is
+    /// then marked as complete in the store, so the members aren't fetched
+    /// again, until a gappy sync makes it incomplete again. In that case, a
+    /// chunk saying that the list is incomplete is yielded, and the missing
+    /// members are fetched again.
+    pub async fn members_stream(
+        &self,
+        memberships: RoomMemberships,
+    ) -> Result<impl Stream<Item = RoomMembersChunk>> {
+        // Subscribe before loading the members, to not miss any update.
+        let mut member_updates = self.room_member_updates_sender.subscribe();
+        let mut room_info_stream = self.subscribe_info();
+        let members = self.members_no_sync(memberships).await?;
+        let this = self.clone();
+
+        Ok(stream! {
+            let mut known_user_ids =
+                members.iter().map(|member| member.user_id().to_owned()).collect();
+            let mut is_complete = this.are_members_synced();
+
+            yield RoomMembersChunk { members, is_complete };
+
+            if !is_complete {
+                if let Err(err) = this.sync_members().await {
+                    warn!("Failed to fetch the members of the room: {err}");
+                }
+            }
+
+            loop {
+                let user_ids = tokio::select! {
+                    // Handle the member updates first, so the members are yielded with the
+                    // completeness of the member list that they resulted in.
+                    biased;
+
+                    update = member_updates.recv() => match update {
+                        Ok(RoomMembersUpdate::Partial(user_ids)) => Some(user_ids),
+                        // We might have missed updates, so compare with all the members.
+                        Ok(RoomMembersUpdate::FullReload) | Err(RecvError::Lagged(_)) => None,
+                        Err(RecvError::Closed) => break,
+                    },
+                    room_info = room_info_stream.next() => {
+                        if room_info.is_none() {
+                            break;
+                        }
+
+                        // Only the completeness of the member list might have changed.
+                        Some(BTreeSet::new())
+                    }
+                };
+
+                let members = match this
+                    .updated_members(memberships, &mut known_user_ids, user_ids)
+                    .await
+                {
+                    Ok(members) => members,
+                    Err(err) => {
+                        warn!("Failed to load the updated members of the room: {err}");
+                        continue;
+                    }
+                };
+
+                let was_complete = mem::replace(&mut is_complete, this.are_members_synced());
+
+                if !members.is_empty() || was_complete != is_complete {
+                    yield RoomMembersChunk { members, is_complete };
+                }
+
+                // A gappy sync made the member list incomplete, fetch the missing members
+                // again.
+                if was_complete && !is_complete {
+                    if let Err(err) = this.sync_members().await {
+                        warn!("Failed to fetch the members of the room: {err}");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Get the members with the given memberships that were updated, and add
+    /// them to the known ones.
+    ///
+    /// If `user_ids` is set, only the members of these users were updated.
+    /// Otherwise, all the members are loaded, and only the ones that weren't
+    /// known are returned.
+    async fn updated_members(
+        &self,
+        memberships: RoomMemberships,
+        known_user_ids: &mut BTreeSet<OwnedUserId>,
+        user_ids: Option<BTreeSet<OwnedUserId>>,
+    ) -> Result<Vec<RoomMember>> {
+        let Some(user_ids) = user_ids else {
+            let mut members = self.members_no_sync(memberships).await?;
+            members.retain(|member| known_user_ids.insert(member.user_id().to_owned()));
+            return Ok(members);
+        };
+
+        let mut members = Vec::new();
+
+        for user_id in user_ids {
+            if let Some(member) = self.get_member_no_sync(&user_id).await? {
+                if memberships.matches(member.membership()) {
+                    known_user_ids.insert(user_id);
+                    members.push(member);
+                }
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Page through the history of the membership changes of the given user in
+    /// this room, for example to audit the moderation actions that targeted
+    /// them.
+    ///
+    /// See [`MembershipHistory`] for more details.
+    pub fn membership_history(&self, user_id: &UserId) -> MembershipHistory {
+        MembershipHistory::new(self.clone(), user_id.to_owned())
+    }
+
     /// Get all state events of a given type in this room.
     pub async fn get_state_events(
         &self,
@@ -976,6 +1203,42 @@ impl Room {
             .await?)
     }
 
+    /// Get the content of a state event of a custom type in this room.
+    ///
+    /// Returns `None` if there is no such state event, or if it was redacted.
+    pub async fn get_custom_state_content<C>(&self, state_key: &str) -> Result<Option<C>>
+    where
+        C: CustomEventContent,
+    {
+        #[derive(Deserialize)]
+        struct StateEventDetails {
+            content: Box<RawJsonValue>,
+            unsigned: Option<UnsignedDetails>,
+        }
+
+        #[derive(Deserialize)]
+        struct UnsignedDetails {
+            redacted_because: Option<serde::de::IgnoredAny>,
+        }
+
+        let Some(raw_event) = self.get_state_event(C::TYPE.into(), state_key).await? else {
+            return Ok(None);
+        };
+
+        let json = match &raw_event {
+            RawAnySyncOrStrippedState::Sync(raw) => raw.json(),
+            RawAnySyncOrStrippedState::Stripped(raw) => raw.json(),
+        };
+        let StateEventDetails { content, unsigned } = serde_json::from_str(json.get())?;
+
+        // The content of a redacted event was removed.
+        if unsigned.and_then(|u| u.redacted_because).is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_str(content.get())?))
+    }
+
     /// Returns the parents this room advertises as its parents.
     ///
     /// Results are in no particular order.
@@ -1088,6 +1351,39 @@ impl Room {
         Ok(self.account_data(C::TYPE.into()).await?.map(Raw::cast))
     }
 
+    /// Observe the account data event of a statically-known type in this room.
+    ///
+    /// The returned [`ObservableAccountData`] holds the current content of the
+    /// event, loaded from the store, and is updated every time a new content is
+    /// received from the homeserver.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// use matrix_sdk::ruma::events::tag::TagEventContent;
+    ///
+    /// let tags = room.observe_account_data::<TagEventContent>().await?;
+    /// if let Some(content) = tags.get() {
+    ///     println!("Tags of the room: {:?}", content.tags);
+    /// }
+    /// # anyhow::Ok(())
+    /// # };
+    /// ```
+    pub async fn observe_account_data<C>(&self) -> Result<ObservableAccountData<C>>
+    where
+        C: StaticEventContent
+            + RoomAccountDataEventContent
+            + DeserializeOwned
+            + Clone
+            + SendOutsideWasm
+            + SyncOutsideWasm
+            + 'static,
+    {
+        ObservableAccountData::room(self).await
+    }
+
     /// Check if all members of this room are verified and all their devices are
     /// verified.
     ///
@@ -1298,6 +1594,27 @@ impl Room {
         Ok(())
     }
 
+    /// Subscribe to the tags of this room.
+    ///
+    /// The stream yields the current tags first, then the new tags every time
+    /// they change, for example after [`Room::set_tag`] or when another
+    /// device changed them.
+    pub fn subscribe_to_tags(&self) -> impl Stream<Item = Tags> {
+        let mut room_info_stream = self.subscribe_info();
+        let mut tags = self.cached_tags();
+
+        stream! {
+            yield tags.clone();
+
+            while let Some(room_info) = room_info_stream.next().await {
+                if *room_info.tags() != tags {
+                    tags = room_info.tags().clone();
+                    yield tags.clone();
+                }
+            }
+        }
+    }
+
     /// Sets whether this room is a DM.
     ///
     /// When setting this room as DM, it will be marked as DM for all active
@@ -1511,6 +1828,40 @@ impl Room {
         Ok(())
     }
 
+    /// Invite a user to this room by their email address.
+    ///
+    /// The identity server configured by the user, as returned by
+    /// [`Client::identity_server()`], is used to look up the Matrix ID
+    /// associated with the email address. If there is one, the user is
+    /// invited with it. Otherwise, the homeserver asks the identity server to
+    /// send an invitation to the email address, and sends an
+    /// `m.room.third_party_invite` event to the room. Once the invitee binds
+    /// the email address to their account, their homeserver turns it into a
+    /// regular invite.
+    ///
+    /// Returns [`IdentityServerError::NotConfigured`] if the user didn't
+    /// configure an identity server, and
+    /// [`IdentityServerError::TermsNotSigned`] if its terms of service need to
+    /// be accepted first.
+    #[instrument(skip_all)]
+    pub async fn invite_by_email(&self, address: &str) -> Result<()> {
+        let identity_server =
+            self.client.identity_server().await?.ok_or(IdentityServerError::NotConfigured)?;
+
+        if let Some(user_id) = identity_server.lookup(Medium::Email, address).await? {
+            return self.invite_user_by_id(&user_id).await;
+        }
+
+        let invite = Invite3pidInit {
+            id_server: identity_server.server_name(),
+            id_access_token: identity_server.access_token().await?,
+            medium: Medium::Email,
+            address: address.to_owned(),
+        };
+
+        self.invite_user_by_3pid(invite.into()).await
+    }
+
     /// Activate typing notice for this room.
     ///
     /// The typing notice remains active for 4s. It can be deactivate at any
@@ -1997,6 +2348,209 @@ impl Room {
         SendRawMessageLikeEvent::new(self, event_type, content)
     }
 
+    /// Send a message-like event of a custom type to this room.
+    ///
+    /// The event type is the one associated with the content in its
+    /// [`CustomEventContent`] implementation. Use
+    /// [`send_custom_state()`][Self::send_custom_state] to send custom state
+    /// events.
+    ///
+    /// Like [`send_raw()`][Self::send_raw], the event is encrypted if the room
+    /// is encrypted.
+    ///
+    /// The kind of the content must be [`CustomEventKind::MessageLike`], which
+    /// is checked at compile time.
+    pub async fn send_custom<C>(&self, content: C) -> Result<send_message_event::v3::Response>
+    where
+        C: CustomEventContent,
+    {
+        const {
+            assert!(
+                matches!(C::KIND, CustomEventKind::MessageLike),
+                "only message-like events can be sent with send_custom()"
+            );
+        }
+
+        let content = Raw::new(&content)?.cast::<AnyMessageLikeEventContent>();
+        self.send_raw(C::TYPE, content).await
+    }
+
+    /// Get the image packs defined in this room, from storage.
+    pub async fn image_packs(&self) -> Result<Vec<ImagePack>> {
+        ImagePack::load_room_packs(self).await
+    }
+
+    /// Get all the image packs that can be used in this room, from storage.
+    ///
+    /// This is the image pack of the account, followed by the image packs
+    /// defined in this room, and then the image packs of other rooms that were
+    /// enabled globally by the user.
+    pub async fn available_image_packs(&self) -> Result<Vec<ImagePack>> {
+        let account = self.client.account();
+
+        let mut packs = Vec::from_iter(account.image_pack().await?);
+        packs.extend(self.image_packs().await?);
+
+        for pack in ImagePack::load_enabled_room_packs(&self.client).await? {
+            if !packs.iter().any(|known_pack| known_pack.source() == pack.source()) {
+                packs.push(pack);
+            }
+        }
+
+        Ok(packs)
+    }
+
+    /// Get the custom emotes that can be used in this room, from storage.
+    ///
+    /// They are the emotes of the image packs returned by
+    /// [`Room::available_image_packs()`].
+    pub async fn emotes(&self) -> Result<EmoteRegistry> {
+        load_emotes(&self.client, Some(self.room_id())).await
+    }
+
+    /// Observe the custom emotes that can be used in this room.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// use futures_util::StreamExt;
+    ///
+    /// let observable = room.observe_emotes().await?;
+    /// let mut subscriber = observable.subscribe();
+    ///
+    /// while let Some(emotes) = subscriber.next().await {
+    ///     for emote in emotes.iter() {
+    ///         println!(":{}: -> {}", emote.shortcode, emote.url);
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn observe_emotes(&self) -> Result<ObservableEmotes> {
+        ObservableEmotes::new(&self.client, Some(self.room_id().to_owned())).await
+    }
+
+    /// Create or replace an image pack in this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `state_key` - The identifier of the image pack in the room.
+    ///
+    /// * `content` - The content of the image pack.
+    pub async fn set_image_pack(
+        &self,
+        state_key: &str,
+        content: RoomImagePackEventContent,
+    ) -> Result<send_state_event::v3::Response> {
+        self.send_state_event_for_key(state_key, content).await
+    }
+
+    /// Send an image of an image pack as a sticker to this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `pack` - The image pack containing the sticker.
+    ///
+    /// * `shortcode` - The shortcode of the sticker in the image pack.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// let packs = room.available_image_packs().await?;
+    ///
+    /// if let Some(pack) = packs.first() {
+    ///     if let Some((shortcode, _)) = pack.stickers().next() {
+    ///         room.send_sticker(pack, shortcode).await?;
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn send_sticker(
+        &self,
+        pack: &ImagePack,
+        shortcode: &str,
+    ) -> Result<send_message_event::v3::Response> {
+        let content = pack.sticker_content(shortcode)?;
+        self.send(content).await
+    }
+
+    /// Build a message with intentional mentions with a
+    /// [`MentionsMessageBuilder`].
+    ///
+    /// The content of the message is built when the builder is awaited, and
+    /// can then be sent with [`Room::send()`].
+    pub fn message_with_mentions(&self) -> MentionsMessageBuilder {
+        MentionsMessageBuilder::new(self.clone())
+    }
+
+    /// Build the content of a message with a [`MessageBuilder`], optionally
+    /// replying to another event.
+    ///
+    /// If the homeserver supports Matrix 1.13, the reply is made with the new
+    /// format, without the [rich reply] fallbacks in its bodies. Otherwise the
+    /// fallbacks are added for older clients.
+    ///
+    /// Returns an error if the content is larger than the maximum size of the
+    /// builder, including the fallbacks.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// use matrix_sdk::{
+    ///     room::{
+    ///         message_builder::MessageBuilder,
+    ///         reply::{EnforceThread, Reply},
+    ///     },
+    ///     ruma::owned_event_id,
+    /// };
+    ///
+    /// let reply = Reply {
+    ///     event_id: owned_event_id!("$replied_to"),
+    ///     enforce_thread: EnforceThread::MaybeThreaded,
+    /// };
+    /// let content = room
+    ///     .make_message(
+    ///         MessageBuilder::html("Sure!", "<b>Sure!</b>"),
+    ///         Some(reply),
+    ///     )
+    ///     .await?;
+    ///
+    /// room.send(content).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    ///
+    /// [rich reply]: https://spec.matrix.org/latest/client-server-api/#rich-replies
+    pub async fn make_message(
+        &self,
+        builder: MessageBuilder,
+        reply: Option<Reply>,
+    ) -> Result<RoomMessageEventContent> {
+        let max_size = builder.get_max_size();
+        let content = builder.build()?;
+
+        let Some(reply) = reply else {
+            return Ok(content.with_relation(None));
+        };
+
+        let msgtype = content.msgtype.clone();
+        let mut content = self.make_reply_event(content, reply).await?;
+
+        if self.client.server_versions().await?.contains(&MatrixVersion::V1_13) {
+            // Only drop the fallbacks that were just added, the bodies written by the
+            // user are kept as they are, even if they look like fallbacks.
+            content.msgtype = msgtype;
+        }
+
+        check_size(&content, max_size)?;
+
+        Ok(content)
+    }
+
     /// Send an attachment to this room.
     ///
     /// This will upload the given data that the reader produces using the
@@ -2097,6 +2651,17 @@ impl Room {
         let txn_id = config.txn_id.take();
         let mentions = config.mentions.take();
 
+        let (content_type, data) = self
+            .client
+            .media()
+            .prepare_attachment_upload(content_type.clone(), data, &mut config)
+            .await?;
+        let content_type = &content_type;
+
+        #[cfg(feature = "image-proc")]
+        let (mut config, data) =
+            config.with_generated_thumbnail_and_info(content_type.clone(), data).await;
+
         let thumbnail = config.thumbnail.take();
 
         // If necessary, store caching data for the thumbnail ahead of time.
@@ -2237,11 +2802,11 @@ impl Room {
                     filename
                 });
 
-                if let Some(AttachmentInfo::Voice { audio_info, waveform: Some(waveform_vec) }) =
-                    &info
-                {
+                if let Some(AttachmentInfo::Voice { audio_info, waveform }) = &info {
+                    // MSC3245 requires the MSC1767 audio block, which can only be built when
+                    // the duration is known. The amplitudes saturate at 1024.
                     if let Some(duration) = audio_info.duration {
-                        let waveform = waveform_vec.iter().map(|v| (*v).into()).collect();
+                        let waveform = waveform.iter().flatten().map(|v| (*v).into()).collect();
                         content.audio =
                             Some(UnstableAudioDetailsContentBlock::new(duration, waveform));
                     }
@@ -2305,6 +2870,61 @@ impl Room {
         Ok(content)
     }
 
+    /// Save the playback position of the voice message with the given event
+    /// ID, so the playback can be resumed later, even after a restart.
+    ///
+    /// The position is only stored locally, in the state store.
+    pub async fn save_voice_playback_position(
+        &self,
+        event_id: &EventId,
+        position: Duration,
+    ) -> Result<()> {
+        let millis = u64::try_from(position.as_millis()).unwrap_or(u64::MAX);
+        self.client
+            .state_store()
+            .set_custom_value_no_read(
+                self.voice_playback_position_key(event_id).as_bytes(),
+                millis.to_le_bytes().to_vec(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Get the playback position of the voice message with the given event ID
+    /// that was saved with [`Room::save_voice_playback_position()`], if any.
+    pub async fn voice_playback_position(&self, event_id: &EventId) -> Result<Option<Duration>> {
+        let Some(value) = self
+            .client
+            .state_store()
+            .get_custom_value(self.voice_playback_position_key(event_id).as_bytes())
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        match <[u8; 8]>::try_from(value.as_slice()) {
+            Ok(bytes) => Ok(Some(Duration::from_millis(u64::from_le_bytes(bytes)))),
+            Err(_) => {
+                warn!(%event_id, "Ignoring invalid stored voice message playback position");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Forget the playback position of the voice message with the given event
+    /// ID, for example once it was played until the end.
+    pub async fn clear_voice_playback_position(&self, event_id: &EventId) -> Result<()> {
+        self.client
+            .state_store()
+            .remove_custom_value(self.voice_playback_position_key(event_id).as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    fn voice_playback_position_key(&self, event_id: &EventId) -> String {
+        format!("voice_playback_position:{}:{event_id}", self.room_id())
+    }
+
     /// Update the power levels of a select set of users of this room.
     ///
     /// Issue a `power_levels` state event request to the server, changing the
@@ -2600,6 +3220,152 @@ impl Room {
         Ok(self.client.send(request).await?)
     }
 
+    /// Fetch all the current state events of this room from the homeserver,
+    /// grouped by type and state key.
+    pub async fn state_snapshot(&self) -> Result<RoomStateSnapshot> {
+        let request = get_state_events::v3::Request::new(self.room_id().to_owned());
+        let response = self.client.send(request).await?;
+        Ok(RoomStateSnapshot::new(response.room_state))
+    }
+
+    /// Send several state events to this room, as close to atomically as
+    /// possible.
+    ///
+    /// Before sending anything, this checks that the own user is allowed to
+    /// send all the state events according to the power levels of the room,
+    /// and fetches the current state of the room with
+    /// [`Room::state_snapshot()`].
+    ///
+    /// The state events are then sent in order. If one of them fails, the ones
+    /// that were already sent are reverted to their previous content, in the
+    /// reverse order, and a [`StateBatchError::Failed`] error reports the
+    /// outcome of the rollback. Since the Matrix protocol doesn't allow to
+    /// remove a state event, one that didn't exist before the batch is
+    /// reverted by sending an empty content.
+    ///
+    /// Returns the IDs of the sent state events, in the same order as the
+    /// given events.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// use matrix_sdk::{
+    ///     room::state::StateBatchEvent,
+    ///     ruma::events::room::{
+    ///         name::RoomNameEventContent, topic::RoomTopicEventContent,
+    ///     },
+    /// };
+    ///
+    /// room.send_state_batch([
+    ///     StateBatchEvent::new(RoomNameEventContent::new("Rust".to_owned()))?,
+    ///     StateBatchEvent::new(RoomTopicEventContent::new(
+    ///         "All about Rust".to_owned(),
+    ///     ))?,
+    /// ])
+    /// .await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn send_state_batch(
+        &self,
+        events: impl IntoIterator<Item = StateBatchEvent>,
+    ) -> Result<Vec<OwnedEventId>> {
+        self.ensure_room_joined()?;
+
+        let events = events.into_iter().collect::<Vec<_>>();
+
+        let power_levels = self.power_levels().await?;
+        for event in &events {
+            if !power_levels.user_can_send_state(self.own_user_id(), event.event_type().clone()) {
+                return Err(StateBatchError::Forbidden(event.event_type().clone()).into());
+            }
+        }
+
+        let snapshot = self.state_snapshot().await?;
+        let mut event_ids = Vec::with_capacity(events.len());
+
+        for (index, event) in events.iter().enumerate() {
+            let result = self
+                .send_state_event_raw(
+                    &event.event_type().to_string(),
+                    event.state_key(),
+                    event.content().clone(),
+                )
+                .await;
+
+            match result {
+                Ok(response) => event_ids.push(response.event_id),
+                Err(error) => {
+                    warn!("Failed to send a state event of the batch, rolling back: {error}");
+
+                    let mut rollback = Vec::with_capacity(index);
+
+                    for sent in events[..index].iter().rev() {
+                        let content = snapshot
+                            .content(sent.event_type(), sent.state_key())
+                            .unwrap_or_else(|| {
+                                Raw::from_json_string("{}".to_owned())
+                                    .expect("an empty object is valid JSON")
+                            });
+
+                        let result = self
+                            .send_state_event_raw(
+                                &sent.event_type().to_string(),
+                                sent.state_key(),
+                                content,
+                            )
+                            .await
+                            .map(|response| response.event_id);
+
+                        rollback.push(StateRollback {
+                            event_type: sent.event_type().clone(),
+                            state_key: sent.state_key().to_owned(),
+                            result,
+                        });
+                    }
+
+                    return Err(StateBatchError::Failed(Box::new(StateBatchFailure {
+                        event_type: event.event_type().clone(),
+                        state_key: event.state_key().to_owned(),
+                        error,
+                        rollback,
+                    }))
+                    .into());
+                }
+            }
+        }
+
+        Ok(event_ids)
+    }
+
+    /// Send a state event of a custom type to this room.
+    ///
+    /// The event type is the one associated with the content in its
+    /// [`CustomEventContent`] implementation.
+    ///
+    /// The kind of the content must be [`CustomEventKind::State`], which is
+    /// checked at compile time.
+    pub async fn send_custom_state<C>(
+        &self,
+        state_key: &str,
+        content: C,
+    ) -> Result<send_state_event::v3::Response>
+    where
+        C: CustomEventContent,
+    {
+        const {
+            assert!(
+                matches!(C::KIND, CustomEventKind::State),
+                "only state events can be sent with send_custom_state()"
+            );
+        }
+
+        let content = Raw::new(&content)?.cast::<AnyStateEventContent>();
+        self.send_state_event_raw(C::TYPE, state_key, content).await
+    }
+
     /// Strips all information out of an event of the room.
     ///
     /// Returns the [`redact_event::v3::Response`] from the server.
@@ -2872,6 +3638,23 @@ impl Room {
         Ok(self.room_id().matrix_event_uri_via(event_id, via))
     }
 
+    /// Get a [`Permalink`] to an event in this room.
+    ///
+    /// Like [`Room::matrix_to_event_permalink()`], we try to use the synced
+    /// members in the room for routing the room ID. The permalink can be
+    /// rendered either as a `matrix.to` link or as a `matrix:` URI.
+    ///
+    /// *Note*: This method does not check if the given event ID is actually
+    /// part of this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event.
+    pub async fn permalink_to(&self, event_id: impl Into<OwnedEventId>) -> Result<Permalink> {
+        let via = self.route().await?;
+        Ok(Permalink::event(self.room_id().to_owned(), event_id.into(), via))
+    }
+
     /// Get the latest receipt of a user in this room.
     ///
     /// # Arguments
@@ -3085,6 +3868,93 @@ impl Room {
         }
     }
 
+    /// Get the notification settings that apply to this room: its notification
+    /// mode, whether it's the default one or was defined by the user, and the
+    /// sound of its notifications.
+    ///
+    /// The default mode depends on whether the room is encrypted and whether
+    /// it's a one-to-one room, so the settings are resolved the same way for
+    /// all rooms.
+    ///
+    /// Returns `None` if the room isn't joined, or if its encryption state
+    /// couldn't be loaded.
+    pub async fn effective_notification_settings(
+        &self,
+    ) -> Option<EffectiveRoomNotificationSettings> {
+        let notification_settings = self.client().notification_settings().await;
+        self.effective_notification_settings_with(&notification_settings).await
+    }
+
+    /// Subscribe to the notification settings that apply to this room.
+    ///
+    /// The stream yields the current settings first, as returned by
+    /// [`Room::effective_notification_settings()`], then the new settings
+    /// every time they change, either because the push rules changed or
+    /// because the room changed, for example when it became encrypted.
+    pub async fn subscribe_to_effective_notification_settings(
+        &self,
+    ) -> impl Stream<Item = Option<EffectiveRoomNotificationSettings>> {
+        // Subscribe before computing the current settings, to not miss any update.
+        let notification_settings = self.client().notification_settings().await;
+        let mut settings_changes = notification_settings.subscribe_to_changes();
+        let mut room_info_stream = self.subscribe_info();
+        let this = self.clone();
+
+        stream! {
+            let mut settings =
+                this.effective_notification_settings_with(&notification_settings).await;
+            yield settings.clone();
+
+            loop {
+                tokio::select! {
+                    change = settings_changes.recv() => {
+                        if let Err(RecvError::Closed) = change {
+                            break;
+                        }
+                    }
+                    room_info = room_info_stream.next() => {
+                        if room_info.is_none() {
+                            break;
+                        }
+                    }
+                }
+
+                let new_settings =
+                    this.effective_notification_settings_with(&notification_settings).await;
+
+                if new_settings != settings {
+                    settings = new_settings;
+                    yield settings.clone();
+                }
+            }
+        }
+    }
+
+    async fn effective_notification_settings_with(
+        &self,
+        notification_settings: &NotificationSettings,
+    ) -> Option<EffectiveRoomNotificationSettings> {
+        if !matches!(self.state(), RoomState::Joined) {
+            return None;
+        }
+
+        let is_encrypted = self.latest_encryption_state().await.ok()?.is_encrypted();
+
+        // From the point of view of notification settings, a `one-to-one` room is one
+        // that involves exactly two people.
+        let is_one_to_one = IsOneToOne::from(self.active_members_count() == 2);
+
+        Some(
+            notification_settings
+                .get_effective_room_notification_settings(
+                    self.room_id(),
+                    IsEncrypted::from(is_encrypted),
+                    is_one_to_one,
+                )
+                .await,
+        )
+    }
+
     /// Get the user-defined notification mode.
     ///
     /// The result is cached for fast and non-async call. To read the cached
@@ -3316,6 +4186,197 @@ impl Room {
         }
     }
 
+    /// Send a static location message in the current room.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The fallback text of the message.
+    /// * `geo_uri` - The geo URI of the location.
+    /// * `description` - An optional description of the location.
+    /// * `zoom_level` - An optional zoom level to display the location with.
+    /// * `asset_type` - The type of asset being located. Defaults to
+    ///   [`AssetType::Self_`].
+    pub async fn send_location(
+        &self,
+        body: String,
+        geo_uri: String,
+        description: Option<String>,
+        zoom_level: Option<ZoomLevel>,
+        asset_type: Option<AssetType>,
+    ) -> Result<send_message_event::v3::Response> {
+        let mut content = LocationMessageEventContent::new(body, geo_uri.clone());
+
+        if let Some(asset_type) = asset_type {
+            content = content.with_asset_type(asset_type);
+        }
+
+        content.location = Some(assign!(LocationContent::new(geo_uri), {
+            description,
+            zoom_level,
+        }));
+
+        self.send(RoomMessageEventContent::new(MessageType::Location(content))).await
+    }
+
+    /// Send a poll in the current room.
+    ///
+    /// The event ID of the response can be used to answer the poll with
+    /// [`Room::send_poll_response()`], and to end it with
+    /// [`Room::end_poll()`].
+    pub async fn send_poll(&self, poll: NewPoll) -> Result<send_message_event::v3::Response> {
+        self.send(UnstablePollStartEventContent::from(NewUnstablePollStartEventContent::from(poll)))
+            .await
+    }
+
+    /// Answer a poll in the current room.
+    ///
+    /// Only the latest response of a user is taken into account, so this can
+    /// be used to change the user's answers, or to remove them by sending an
+    /// empty list.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_event_id` - The event ID of the poll start event.
+    /// * `answers` - The IDs of the selected answers.
+    pub async fn send_poll_response(
+        &self,
+        poll_start_event_id: &EventId,
+        answers: Vec<String>,
+    ) -> Result<send_message_event::v3::Response> {
+        self.send(UnstablePollResponseEventContent::new(answers, poll_start_event_id.to_owned()))
+            .await
+    }
+
+    /// End a poll in the current room.
+    ///
+    /// The responses sent after the end of the poll are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_start_event_id` - The event ID of the poll start event.
+    /// * `text` - The text representation of the end of the poll, for clients
+    ///   that don't support polls.
+    pub async fn end_poll(
+        &self,
+        poll_start_event_id: &EventId,
+        text: impl Into<String>,
+    ) -> Result<send_message_event::v3::Response> {
+        self.send(UnstablePollEndEventContent::new(text, poll_start_event_id.to_owned())).await
+    }
+
+    /// Aggregate the results of a poll from the events known by the
+    /// [`EventCache`].
+    ///
+    /// The latest edit of the poll sent by its creator is applied, only the
+    /// latest valid response of each user is counted, and the responses sent
+    /// after the end of the poll are ignored. The poll can only be ended by
+    /// its creator, or by a user who is allowed to redact the events of other
+    /// users.
+    ///
+    /// This requires the storage of the event cache to be enabled, with
+    /// [`EventCache::enable_storage()`].
+    ///
+    /// Returns `None` if the poll start event is not known by the event cache,
+    /// or if the event is not a poll start event.
+    pub async fn poll_results(&self, poll_start_event_id: &EventId) -> Result<Option<PollResults>> {
+        let (cache, _handles) = self.event_cache().await?;
+
+        let Some((start_event, related_events)) = cache
+            .event_with_relations(
+                poll_start_event_id,
+                Some(vec![RelationType::Reference, RelationType::Replacement]),
+            )
+            .await
+        else {
+            return Ok(None);
+        };
+
+        let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::UnstablePollStart(
+            SyncMessageLikeEvent::Original(start_event),
+        ))) = start_event.raw().deserialize()
+        else {
+            return Ok(None);
+        };
+
+        let UnstablePollStartEventContent::New(start_content) = start_event.content else {
+            return Ok(None);
+        };
+
+        let mut poll = start_content.poll_start;
+        let mut latest_edit_ts = None;
+        let mut responses = Vec::new();
+        let mut ends = Vec::new();
+
+        for event in related_events {
+            let Ok(AnySyncTimelineEvent::MessageLike(event)) = event.raw().deserialize() else {
+                continue;
+            };
+
+            match event {
+                AnySyncMessageLikeEvent::UnstablePollStart(SyncMessageLikeEvent::Original(
+                    edit,
+                )) => {
+                    if edit.sender != start_event.sender
+                        || latest_edit_ts.is_some_and(|ts| ts > edit.origin_server_ts)
+                    {
+                        continue;
+                    }
+
+                    if let UnstablePollStartEventContent::Replacement(replacement) = edit.content {
+                        poll = replacement.relates_to.new_content.poll_start;
+                        latest_edit_ts = Some(edit.origin_server_ts);
+                    }
+                }
+                AnySyncMessageLikeEvent::UnstablePollResponse(SyncMessageLikeEvent::Original(
+                    response,
+                )) => {
+                    responses.push((
+                        response.sender,
+                        response.origin_server_ts,
+                        response.content.poll_response.answers,
+                    ));
+                }
+                AnySyncMessageLikeEvent::UnstablePollEnd(SyncMessageLikeEvent::Original(end)) => {
+                    ends.push((end.sender, end.origin_server_ts));
+                }
+                _ => {}
+            }
+        }
+
+        // Only load the power levels if someone else than the creator tried to end the
+        // poll.
+        let power_levels = if ends.iter().any(|(sender, _)| *sender != start_event.sender) {
+            self.power_levels().await.ok()
+        } else {
+            None
+        };
+
+        let mut end_time: Option<MilliSecondsSinceUnixEpoch> = None;
+
+        for (sender, origin_server_ts) in ends {
+            let can_end = sender == start_event.sender
+                || power_levels
+                    .as_ref()
+                    .is_some_and(|pl| pl.user_can_redact_event_of_other(&sender));
+
+            if !can_end {
+                continue;
+            }
+
+            end_time = Some(end_time.map_or(origin_server_ts, |ts| ts.min(origin_server_ts)));
+        }
+
+        Ok(Some(PollResults::compile(
+            &poll,
+            responses.iter().map(|(sender, origin_server_ts, selections)| PollResponseData {
+                sender,
+                origin_server_ts: *origin_server_ts,
+                selections,
+            }),
+            end_time,
+        )))
+    }
+
     /// Send a call notification event in the current room.
     ///
     /// This is only supposed to be used in **custom** situations where the user
@@ -3406,6 +4467,73 @@ impl Room {
         ObservableLiveLocation::new(&self.client, self.room_id())
     }
 
+    /// Subscribe to the live location shares that are active in this room.
+    ///
+    /// The active shares, including the one of the room's own user, are
+    /// assembled from the `beacon_info` state events and the `beacon` events
+    /// of the room. The current shares are emitted immediately when polling
+    /// the stream, and a new list is emitted whenever a share is started,
+    /// updated with a new location, stopped, or expires.
+    ///
+    /// The last known location of a share is only known once a `beacon` event
+    /// is received after subscribing.
+    pub async fn subscribe_to_active_live_location_shares(
+        &self,
+    ) -> Result<impl Stream<Item = Vec<ActiveLiveLocationShare>>> {
+        // Register the event handlers before loading the current state, so no update
+        // is missed.
+        let (beacon_info_sender, mut beacon_info_receiver) = mpsc::unbounded_channel();
+        let beacon_info_guard = self.client.event_handler_drop_guard(self.add_event_handler(
+            move |event: OriginalSyncBeaconInfoEvent| {
+                let _ = beacon_info_sender.send(event);
+                async {}
+            },
+        ));
+
+        let (beacon_sender, mut beacon_receiver) = mpsc::unbounded_channel();
+        let beacon_guard = self.client.event_handler_drop_guard(self.add_event_handler(
+            move |event: OriginalSyncBeaconEvent| {
+                let _ = beacon_sender.send(event);
+                async {}
+            },
+        ));
+
+        let mut shares = ActiveLiveLocationShares::default();
+
+        for raw_event in self.get_state_events_static::<BeaconInfoEventContent>().await? {
+            match raw_event.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event))) => {
+                    shares.handle_beacon_info(event);
+                }
+                Ok(_) => {}
+                Err(error) => warn!("Failed to deserialize a beacon_info state event: {error}"),
+            }
+        }
+
+        Ok(stream! {
+            let _guards = (beacon_info_guard, beacon_guard);
+
+            yield shares.to_vec();
+
+            loop {
+                let next_expiry = shares.time_until_next_expiry();
+
+                let changed = tokio::select! {
+                    Some(event) = beacon_info_receiver.recv() => shares.handle_beacon_info(event),
+                    Some(event) = beacon_receiver.recv() => shares.handle_beacon(event),
+                    _ = sleep(next_expiry.unwrap_or_default()), if next_expiry.is_some() => {
+                        shares.remove_expired()
+                    }
+                    else => break,
+                };
+
+                if changed {
+                    yield shares.to_vec();
+                }
+            }
+        })
+    }
+
     /// Subscribe to knock requests in this `Room`.
     ///
     /// The current requests to join the room will be emitted immediately
@@ -3553,6 +4681,16 @@ impl Room {
     pub fn privacy_settings(&self) -> RoomPrivacySettings<'_> {
         RoomPrivacySettings::new(&self.inner, &self.client)
     }
+
+    /// Access the tools to moderate the room.
+    pub fn moderation(&self) -> RoomModeration<'_> {
+        RoomModeration::new(self)
+    }
+
+    /// Access the tools to query and change the power levels of the room.
+    pub fn permissions(&self) -> RoomPermissions<'_> {
+        RoomPermissions::new(self)
+    }
 }
 
 #[cfg(all(feature = "e2e-encryption", not(target_arch = "wasm32")))]
@@ -0,0 +1,452 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tools to moderate a room, by removing users and their messages, and by
+//! applying the policy rules of [moderation policy lists].
+//!
+//! [moderation policy lists]: https://spec.matrix.org/v1.14/client-server-api/#moderation-policy-lists
+
+use std::fmt;
+
+use matrix_sdk_base::{
+    deserialized_responses::{SyncOrStrippedState, TimelineEvent},
+    RoomMemberships,
+};
+use ruma::{
+    events::{
+        policy::rule::{
+            room::PolicyRuleRoomEventContent, server::PolicyRuleServerEventContent,
+            user::PolicyRuleUserEventContent, PolicyRuleEventContent, Recommendation,
+        },
+        RedactContent, RedactedStateEventContent, StaticStateEventContent, SyncStateEvent,
+    },
+    OwnedEventId, OwnedUserId, UserId,
+};
+use tracing::{instrument, warn};
+
+use crate::{event_handler::EventHandlerDropGuard, Error, Result, Room};
+
+/// A helper to group the methods in [`Room`] related to its moderation.
+///
+/// It's created with [`Room::moderation()`].
+#[derive(Debug)]
+pub struct RoomModeration<'a> {
+    room: &'a Room,
+}
+
+impl<'a> RoomModeration<'a> {
+    pub(crate) fn new(room: &'a Room) -> Self {
+        Self { room }
+    }
+
+    /// Kick a user out of the room.
+    pub async fn kick(&self, user_id: &UserId, reason: Option<&str>) -> Result<()> {
+        self.room.kick_user(user_id, reason).await
+    }
+
+    /// Ban a user from the room.
+    pub async fn ban(&self, user_id: &UserId, reason: Option<&str>) -> Result<()> {
+        self.room.ban_user(user_id, reason).await
+    }
+
+    /// Unban a user from the room.
+    pub async fn unban(&self, user_id: &UserId, reason: Option<&str>) -> Result<()> {
+        self.room.unban_user(user_id, reason).await
+    }
+
+    /// Ban several users from the room.
+    ///
+    /// All the users are processed, even if some of them can't be banned.
+    /// Returns the users that couldn't be banned, with the corresponding
+    /// error.
+    #[instrument(skip_all, fields(room_id = ?self.room.room_id()))]
+    pub async fn ban_users(
+        &self,
+        user_ids: impl IntoIterator<Item = OwnedUserId>,
+        reason: Option<&str>,
+    ) -> Vec<(OwnedUserId, Error)> {
+        let mut failures = Vec::new();
+
+        for user_id in user_ids {
+            if let Err(error) = self.ban(&user_id, reason).await {
+                warn!(%user_id, "failed to ban user: {error}");
+                failures.push((user_id, error));
+            }
+        }
+
+        failures
+    }
+
+    /// Redact the most recent events sent by a user in the room.
+    ///
+    /// The events are looked up in the event cache, among the events that are
+    /// loaded in memory. State events and events that are already redacted
+    /// are skipped.
+    ///
+    /// All the events are processed, even if some of them can't be redacted.
+    /// Returns the IDs of the redacted events, the most recent first, and the
+    /// events that couldn't be redacted, with the corresponding error.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - The user whose events should be redacted.
+    /// * `limit` - The maximum number of events to redact.
+    /// * `reason` - The reason of the redactions.
+    #[instrument(skip(self), fields(room_id = ?self.room.room_id()))]
+    pub async fn remove_recent_messages(
+        &self,
+        sender: &UserId,
+        limit: usize,
+        reason: Option<&str>,
+    ) -> Result<RemovedMessages> {
+        let (room_event_cache, _drop_handles) = self.room.event_cache().await?;
+
+        let events = room_event_cache
+            .rfind_events(|event| is_redactable_event_from(event, sender), limit)
+            .await;
+
+        let mut removed = RemovedMessages::default();
+
+        for event_id in events.iter().filter_map(|event| event.event_id()) {
+            match self.room.redact(&event_id, reason, None).await {
+                Ok(_) => removed.redacted.push(event_id),
+                Err(error) => {
+                    warn!(%event_id, "failed to redact event: {error}");
+                    removed.failures.push((event_id, error.into()));
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Get the policy rules of this room, if it is a moderation policy list.
+    ///
+    /// The rules are read from the `m.policy.rule.*` state events of the room.
+    pub async fn policy_rules(&self) -> Result<Vec<PolicyRule>> {
+        let user_rules = self.room.get_state_events_static::<PolicyRuleUserEventContent>().await?;
+        let room_rules = self.room.get_state_events_static::<PolicyRuleRoomEventContent>().await?;
+        let server_rules =
+            self.room.get_state_events_static::<PolicyRuleServerEventContent>().await?;
+
+        let user_rules = user_rules
+            .into_iter()
+            .filter_map(|event| original_content(event.deserialize()))
+            .map(|content| PolicyRule::new(PolicyRuleKind::User, content.0));
+        let room_rules = room_rules
+            .into_iter()
+            .filter_map(|event| original_content(event.deserialize()))
+            .map(|content| PolicyRule::new(PolicyRuleKind::Room, content.0));
+        let server_rules = server_rules
+            .into_iter()
+            .filter_map(|event| original_content(event.deserialize()))
+            .map(|content| PolicyRule::new(PolicyRuleKind::Server, content.0));
+
+        Ok(user_rules.chain(room_rules).chain(server_rules).collect())
+    }
+
+    /// Apply the given policy rules to this room.
+    ///
+    /// The members of the room that match a user or server rule with the
+    /// `m.ban` recommendation are banned, with the reason of the rule. Room
+    /// rules are ignored.
+    ///
+    /// All the matching members are processed, even if some of them can't be
+    /// banned. Returns the users that were banned, and the users that couldn't
+    /// be banned, with the corresponding error.
+    #[instrument(skip_all, fields(room_id = ?self.room.room_id()))]
+    pub async fn apply_policy_rules(&self, rules: &[PolicyRule]) -> Result<AppliedPolicyRules> {
+        let own_user_id = self.room.own_user_id();
+        let members = self
+            .room
+            .members(RoomMemberships::JOIN | RoomMemberships::INVITE | RoomMemberships::KNOCK)
+            .await?;
+
+        let mut applied = AppliedPolicyRules::default();
+
+        for member in members {
+            let user_id = member.user_id();
+
+            if user_id == own_user_id {
+                continue;
+            }
+
+            let Some(rule) = rules.iter().find(|rule| {
+                rule.recommendation == Recommendation::Ban && rule.matches_user(user_id)
+            }) else {
+                continue;
+            };
+
+            let reason = (!rule.reason.is_empty()).then_some(rule.reason.as_str());
+
+            match self.ban(user_id, reason).await {
+                Ok(()) => applied.banned.push(user_id.to_owned()),
+                Err(error) => {
+                    warn!(%user_id, "failed to ban user: {error}");
+                    applied.failures.push((user_id.to_owned(), error));
+                }
+            }
+        }
+
+        Ok(applied)
+    }
+
+    /// Apply the policy rules of the given moderation policy list to this
+    /// room.
+    ///
+    /// See [`RoomModeration::apply_policy_rules()`] for the details.
+    pub async fn apply_policy_room(&self, policy_room: &Room) -> Result<AppliedPolicyRules> {
+        let rules = policy_room.moderation().policy_rules().await?;
+        self.apply_policy_rules(&rules).await
+    }
+
+    /// Apply the new policy rules of the given moderation policy list to this
+    /// room, as they are received from the sync.
+    ///
+    /// The rules are applied until the returned [`PolicyRoomSubscription`] is
+    /// dropped. Call [`RoomModeration::apply_policy_room()`] first to apply
+    /// the existing rules.
+    pub fn subscribe_to_policy_room(&self, policy_room: &Room) -> PolicyRoomSubscription {
+        let client = &self.room.client;
+        let policy_room_id = policy_room.room_id();
+
+        let apply_rule = |room: Room, kind, content| async move {
+            let rule = PolicyRule::new(kind, content);
+            // The users that couldn't be banned are already logged.
+            if let Err(error) = room.moderation().apply_policy_rules(&[rule]).await {
+                warn!(room_id = ?room.room_id(), "failed to apply a policy rule: {error}");
+            }
+        };
+
+        let room = self.room.clone();
+        let user_rules_handle = client.add_room_event_handler(
+            policy_room_id,
+            move |event: SyncStateEvent<PolicyRuleUserEventContent>| {
+                let room = room.clone();
+                async move {
+                    if let SyncStateEvent::Original(event) = event {
+                        apply_rule(room, PolicyRuleKind::User, event.content.0).await;
+                    }
+                }
+            },
+        );
+
+        let room = self.room.clone();
+        let server_rules_handle = client.add_room_event_handler(
+            policy_room_id,
+            move |event: SyncStateEvent<PolicyRuleServerEventContent>| {
+                let room = room.clone();
+                async move {
+                    if let SyncStateEvent::Original(event) = event {
+                        apply_rule(room, PolicyRuleKind::Server, event.content.0).await;
+                    }
+                }
+            },
+        );
+
+        PolicyRoomSubscription {
+            _drop_guards: vec![
+                client.event_handler_drop_guard(user_rules_handle),
+                client.event_handler_drop_guard(server_rules_handle),
+            ],
+        }
+    }
+}
+
+/// The outcome of [`RoomModeration::remove_recent_messages()`].
+#[derive(Debug, Default)]
+pub struct RemovedMessages {
+    /// The IDs of the events that were redacted, the most recent first.
+    pub redacted: Vec<OwnedEventId>,
+    /// The IDs of the events that couldn't be redacted, with the
+    /// corresponding error.
+    pub failures: Vec<(OwnedEventId, Error)>,
+}
+
+/// The outcome of [`RoomModeration::apply_policy_rules()`].
+#[derive(Debug, Default)]
+pub struct AppliedPolicyRules {
+    /// The users that were banned.
+    pub banned: Vec<OwnedUserId>,
+    /// The users that match a rule but couldn't be banned, with the
+    /// corresponding error.
+    pub failures: Vec<(OwnedUserId, Error)>,
+}
+
+/// The kind of entity a [`PolicyRule`] applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyRuleKind {
+    /// The rule applies to users.
+    User,
+    /// The rule applies to rooms.
+    Room,
+    /// The rule applies to servers.
+    Server,
+}
+
+/// A rule of a moderation policy list.
+#[derive(Clone, Debug)]
+pub struct PolicyRule {
+    /// The kind of entity this rule applies to.
+    pub kind: PolicyRuleKind,
+    /// The entity this rule applies to, as a glob pattern where `*` matches
+    /// any number of characters and `?` matches a single character.
+    pub entity: String,
+    /// The action to take on the entities matching this rule.
+    pub recommendation: Recommendation,
+    /// The reason of this rule.
+    pub reason: String,
+}
+
+impl PolicyRule {
+    /// Create a new `PolicyRule` from the content of a policy rule event.
+    pub fn new(kind: PolicyRuleKind, content: PolicyRuleEventContent) -> Self {
+        Self {
+            kind,
+            entity: content.entity,
+            recommendation: content.recommendation,
+            reason: content.reason,
+        }
+    }
+
+    /// Whether this rule applies to the given user.
+    ///
+    /// A user rule applies to the users whose ID matches its entity, and a
+    /// server rule applies to the users whose server name matches its entity.
+    pub fn matches_user(&self, user_id: &UserId) -> bool {
+        match self.kind {
+            PolicyRuleKind::User => glob_matches(&self.entity, user_id.as_str()),
+            PolicyRuleKind::Server => glob_matches(&self.entity, user_id.server_name().as_str()),
+            PolicyRuleKind::Room => false,
+        }
+    }
+}
+
+/// A subscription to the new rules of a moderation policy list.
+///
+/// It's created with [`RoomModeration::subscribe_to_policy_room()`], and the
+/// rules stop being applied when it is dropped.
+#[derive(Debug)]
+#[must_use]
+pub struct PolicyRoomSubscription {
+    _drop_guards: Vec<EventHandlerDropGuard>,
+}
+
+/// Get the content of a policy rule event, if it isn't redacted.
+fn original_content<C>(event: serde_json::Result<SyncOrStrippedState<C>>) -> Option<C>
+where
+    C: StaticStateEventContent + RedactContent,
+    C::Redacted: RedactedStateEventContent + fmt::Debug + Clone,
+{
+    match event {
+        Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event))) => Some(event.content),
+        Ok(_) => None,
+        Err(error) => {
+            warn!("failed to deserialize a policy rule: {error}");
+            None
+        }
+    }
+}
+
+/// Whether the given event was sent by `sender` and can be redacted.
+fn is_redactable_event_from(event: &TimelineEvent, sender: &UserId) -> bool {
+    let raw = event.raw();
+
+    raw.get_field::<OwnedUserId>("sender").ok().flatten().is_some_and(|s| s == sender)
+        // State events are kept, to avoid resetting the state of the room.
+        && raw.get_field::<&str>("state_key").ok().flatten().is_none()
+        && raw.get_field::<&str>("type").ok().flatten() != Some("m.room.redaction")
+        && raw
+            .get_field::<serde_json::Value>("unsigned")
+            .ok()
+            .flatten()
+            .is_none_or(|unsigned| unsigned.get("redacted_because").is_none())
+}
+
+/// Whether `text` matches the glob `pattern`, where `*` matches any number of
+/// characters and `?` matches a single character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern, and of the text when it
+    // was reached.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((star_p, star_t)) = backtrack else {
+                    return false;
+                };
+                // Let the last `*` match one more character.
+                p = star_p + 1;
+                t = star_t + 1;
+                backtrack = Some((star_p, star_t + 1));
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{
+        events::policy::rule::{PolicyRuleEventContent, Recommendation},
+        user_id,
+    };
+
+    use super::{glob_matches, PolicyRule, PolicyRuleKind};
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("@spam:example.org", "@spam:example.org"));
+        assert!(!glob_matches("@spam:example.org", "@spam:example.com"));
+        assert!(glob_matches("@spam*:example.org", "@spammer:example.org"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*.example.org", "evil.example.org"));
+        assert!(!glob_matches("*.example.org", "example.org"));
+        assert!(glob_matches("@user?:*", "@user1:example.org"));
+        assert!(!glob_matches("@user?:*", "@user12:example.org"));
+        assert!(glob_matches("*a*b", "xaxxbab"));
+    }
+
+    #[test]
+    fn test_policy_rule_matches_user() {
+        let content = |entity: &str| {
+            PolicyRuleEventContent::new(entity.to_owned(), Recommendation::Ban, "spam".to_owned())
+        };
+
+        let user_rule = PolicyRule::new(PolicyRuleKind::User, content("@spam*:example.org"));
+        assert!(user_rule.matches_user(user_id!("@spambot:example.org")));
+        assert!(!user_rule.matches_user(user_id!("@alice:example.org")));
+
+        let server_rule = PolicyRule::new(PolicyRuleKind::Server, content("*.evil.org"));
+        assert!(server_rule.matches_user(user_id!("@alice:matrix.evil.org")));
+        assert!(!server_rule.matches_user(user_id!("@alice:example.org")));
+
+        let room_rule = PolicyRule::new(PolicyRuleKind::Room, content("*"));
+        assert!(!room_rule.matches_user(user_id!("@alice:example.org")));
+    }
+}
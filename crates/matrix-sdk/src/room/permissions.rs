@@ -0,0 +1,479 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A convenience layer over the [power levels] of a room, to know what users
+//! are allowed to do, and to change it safely.
+//!
+//! [power levels]: https://spec.matrix.org/v1.14/client-server-api/#mroompower_levels
+
+use std::collections::BTreeSet;
+
+use async_stream::stream;
+use futures_core::Stream;
+use ruma::{
+    api::client::state::get_state_events_for_key,
+    events::{
+        room::power_levels::{
+            NotificationPowerLevelType, PowerLevelAction, PowerLevelUserAction, RoomPowerLevels,
+            RoomPowerLevelsEventContent,
+        },
+        MessageLikeEventType, StateEventType,
+    },
+    Int, OwnedUserId, UserId,
+};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{Result, Room};
+
+/// An error when changing the power levels of a room with
+/// [`RoomPermissions`].
+#[derive(Debug, Error)]
+pub enum RoomPermissionsError {
+    /// The power levels of the room were changed since they were last synced,
+    /// so they weren't updated to not overwrite these changes.
+    ///
+    /// The change can be tried again once the new power levels are synced.
+    #[error("the power levels of the room changed since they were last synced")]
+    Conflict,
+
+    /// The own user isn't allowed to change the power levels of the room.
+    #[error("the user isn't allowed to change the power levels of this room")]
+    CannotChangePowerLevels,
+
+    /// A power level would be changed from or to a level that is higher than
+    /// the own user's level, which isn't allowed.
+    #[error("the power level {level} is higher than the user's power level {own_level}")]
+    LevelAboveOwn {
+        /// The power level that is higher than the own user's.
+        level: i64,
+        /// The power level of the own user.
+        own_level: i64,
+    },
+
+    /// The power level of this user can't be changed by the own user, because
+    /// it is not lower than their own.
+    #[error("the power level of {0} can't be changed by the user")]
+    CannotChangeUserLevel(OwnedUserId),
+
+    /// The power level required for this action can't be set directly.
+    #[error("the power level required for {0:?} can't be set")]
+    UnsupportedAction(PowerLevelAction),
+}
+
+/// What the own user is allowed to do in a room, according to its power
+/// levels.
+///
+/// It's returned by [`RoomPermissions::own_capabilities()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoomCapabilities {
+    /// The power level of the own user.
+    pub power_level: i64,
+
+    /// Whether the own user can invite other users.
+    pub can_invite: bool,
+
+    /// Whether the own user can kick other users.
+    pub can_kick: bool,
+
+    /// Whether the own user can ban other users.
+    pub can_ban: bool,
+
+    /// Whether the own user can redact their own events.
+    pub can_redact_own: bool,
+
+    /// Whether the own user can redact the events of other users.
+    pub can_redact_other: bool,
+
+    /// Whether the own user can send messages.
+    pub can_send_messages: bool,
+
+    /// Whether the own user can change the name of the room.
+    pub can_change_name: bool,
+
+    /// Whether the own user can change the topic of the room.
+    pub can_change_topic: bool,
+
+    /// Whether the own user can change the avatar of the room.
+    pub can_change_avatar: bool,
+
+    /// Whether the own user can pin or unpin events.
+    pub can_pin_events: bool,
+
+    /// Whether the own user can change the power levels of the room.
+    pub can_change_power_levels: bool,
+
+    /// Whether the own user can notify the whole room with `@room`.
+    pub can_trigger_room_notification: bool,
+}
+
+impl RoomCapabilities {
+    fn new(power_levels: &RoomPowerLevels, own_user_id: &UserId) -> Self {
+        let can = |action| power_levels.user_can_do(own_user_id, action);
+
+        Self {
+            power_level: power_levels.for_user(own_user_id).into(),
+            can_invite: can(PowerLevelAction::Invite),
+            can_kick: can(PowerLevelAction::Kick),
+            can_ban: can(PowerLevelAction::Ban),
+            can_redact_own: can(PowerLevelAction::RedactOwn),
+            can_redact_other: can(PowerLevelAction::RedactOther),
+            can_send_messages: can(PowerLevelAction::SendMessage(
+                MessageLikeEventType::RoomMessage,
+            )),
+            can_change_name: can(PowerLevelAction::SendState(StateEventType::RoomName)),
+            can_change_topic: can(PowerLevelAction::SendState(StateEventType::RoomTopic)),
+            can_change_avatar: can(PowerLevelAction::SendState(StateEventType::RoomAvatar)),
+            can_pin_events: can(PowerLevelAction::SendState(StateEventType::RoomPinnedEvents)),
+            can_change_power_levels: can(PowerLevelAction::SendState(
+                StateEventType::RoomPowerLevels,
+            )),
+            can_trigger_room_notification: can(PowerLevelAction::TriggerNotification(
+                NotificationPowerLevelType::Room,
+            )),
+        }
+    }
+}
+
+/// A helper to group the methods in [`Room`] related to its power levels.
+///
+/// It's created with [`Room::permissions()`].
+#[derive(Debug)]
+pub struct RoomPermissions<'a> {
+    room: &'a Room,
+}
+
+impl<'a> RoomPermissions<'a> {
+    pub(crate) fn new(room: &'a Room) -> Self {
+        Self { room }
+    }
+
+    /// Whether the given user can do the given action in the room.
+    pub async fn can_user(&self, user_id: &UserId, action: PowerLevelAction) -> Result<bool> {
+        Ok(self.room.power_levels().await?.user_can_do(user_id, action))
+    }
+
+    /// Whether the acting user can do the given action to the target user in
+    /// the room, for example to kick them.
+    pub async fn can_user_do_to_user(
+        &self,
+        acting_user_id: &UserId,
+        target_user_id: &UserId,
+        action: PowerLevelUserAction,
+    ) -> Result<bool> {
+        Ok(self.room.power_levels().await?.user_can_do_to_user(
+            acting_user_id,
+            target_user_id,
+            action,
+        ))
+    }
+
+    /// The minimum power level required to do the given action in the room.
+    pub async fn required_level(&self, action: PowerLevelAction) -> Result<i64> {
+        Ok(self.room.power_levels().await?.for_action(action).into())
+    }
+
+    /// What the own user is allowed to do in the room.
+    pub async fn own_capabilities(&self) -> Result<RoomCapabilities> {
+        let power_levels = self.room.power_levels().await?;
+        Ok(RoomCapabilities::new(&power_levels, self.room.own_user_id()))
+    }
+
+    /// Observe what the own user is allowed to do in the room.
+    ///
+    /// The stream yields the current capabilities first, then every time they
+    /// change.
+    pub async fn subscribe_to_own_capabilities(
+        &self,
+    ) -> Result<impl Stream<Item = RoomCapabilities>> {
+        let mut room_info_stream = self.room.subscribe_info();
+        let mut capabilities = self.own_capabilities().await?;
+        let room = self.room.clone();
+
+        Ok(stream! {
+            yield capabilities.clone();
+
+            while room_info_stream.next().await.is_some() {
+                match room.permissions().own_capabilities().await {
+                    Ok(new_capabilities) => {
+                        if new_capabilities != capabilities {
+                            capabilities = new_capabilities;
+                            yield capabilities.clone();
+                        }
+                    }
+                    Err(err) => warn!("Failed to load the power levels of the room: {err}"),
+                }
+            }
+        })
+    }
+
+    /// Set the minimum power level required to do the given action in the
+    /// room.
+    ///
+    /// The power level required to unban a user can't be set directly, it's
+    /// the highest of the ones to ban and to kick.
+    ///
+    /// See [`RoomPermissions::update()`] for the checks that are done before
+    /// changing the power levels.
+    pub async fn set_required_level(&self, action: PowerLevelAction, level: i64) -> Result<()> {
+        let level = Int::try_from(level)?;
+
+        self.update(|power_levels| {
+            match action {
+                PowerLevelAction::Ban => power_levels.ban = level,
+                PowerLevelAction::Invite => power_levels.invite = level,
+                PowerLevelAction::Kick => power_levels.kick = level,
+                PowerLevelAction::RedactOther => power_levels.redact = level,
+                PowerLevelAction::RedactOwn => {
+                    power_levels.events.insert(MessageLikeEventType::RoomRedaction.into(), level);
+                }
+                PowerLevelAction::SendMessage(event_type) => {
+                    power_levels.events.insert(event_type.into(), level);
+                }
+                PowerLevelAction::SendState(event_type) => {
+                    power_levels.events.insert(event_type.into(), level);
+                }
+                PowerLevelAction::TriggerNotification(NotificationPowerLevelType::Room) => {
+                    power_levels.notifications.room = level;
+                }
+                action => return Err(RoomPermissionsError::UnsupportedAction(action)),
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the power level of the given user in the room.
+    ///
+    /// See [`RoomPermissions::update()`] for the checks that are done before
+    /// changing the power levels.
+    pub async fn set_user_level(&self, user_id: &UserId, level: i64) -> Result<()> {
+        let level = Int::try_from(level)?;
+
+        self.update(|power_levels| {
+            if level == power_levels.users_default {
+                power_levels.users.remove(user_id);
+            } else {
+                power_levels.users.insert(user_id.to_owned(), level);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Change the power levels of the room with the given function.
+    ///
+    /// The power levels are changed only if:
+    ///
+    /// - the power levels on the homeserver are the same as the ones that were
+    ///   synced, so concurrent changes are not overwritten. Otherwise,
+    ///   [`RoomPermissionsError::Conflict`] is returned.
+    /// - the own user is allowed to make these changes, according to the
+    ///   [authorization rules] of the power levels.
+    ///
+    /// Nothing is sent if the function doesn't change the power levels.
+    ///
+    /// [authorization rules]: https://spec.matrix.org/v1.14/rooms/v11/#authorization-rules
+    pub async fn update(
+        &self,
+        f: impl FnOnce(&mut RoomPowerLevels) -> Result<(), RoomPermissionsError>,
+    ) -> Result<()> {
+        let power_levels = self.room.power_levels().await?;
+
+        let request = get_state_events_for_key::v3::Request::new(
+            self.room.room_id().to_owned(),
+            StateEventType::RoomPowerLevels,
+            "".to_owned(),
+        );
+        let response = self.room.client.send(request).await?;
+        let remote_power_levels = RoomPowerLevels::from(
+            response.content.deserialize_as::<RoomPowerLevelsEventContent>()?,
+        );
+
+        if !are_equal(&power_levels, &remote_power_levels)? {
+            return Err(RoomPermissionsError::Conflict.into());
+        }
+
+        let mut new_power_levels = power_levels.clone();
+        f(&mut new_power_levels)?;
+
+        if are_equal(&power_levels, &new_power_levels)? {
+            return Ok(());
+        }
+
+        check_changes(self.room.own_user_id(), &power_levels, &new_power_levels)?;
+
+        self.room.send_state_event(RoomPowerLevelsEventContent::from(new_power_levels)).await?;
+
+        Ok(())
+    }
+}
+
+fn are_equal(a: &RoomPowerLevels, b: &RoomPowerLevels) -> serde_json::Result<bool> {
+    Ok(serde_json::to_value(RoomPowerLevelsEventContent::from(a.clone()))?
+        == serde_json::to_value(RoomPowerLevelsEventContent::from(b.clone()))?)
+}
+
+/// Check that the own user is allowed to change the power levels from
+/// `before` to `after`.
+fn check_changes(
+    own_user_id: &UserId,
+    before: &RoomPowerLevels,
+    after: &RoomPowerLevels,
+) -> Result<(), RoomPermissionsError> {
+    if !before.user_can_send_state(own_user_id, StateEventType::RoomPowerLevels) {
+        return Err(RoomPermissionsError::CannotChangePowerLevels);
+    }
+
+    let own_level = before.for_user(own_user_id);
+    let check_level = |level: Int| {
+        if level > own_level {
+            Err(RoomPermissionsError::LevelAboveOwn {
+                level: level.into(),
+                own_level: own_level.into(),
+            })
+        } else {
+            Ok(())
+        }
+    };
+
+    // The levels that are changed must not be higher than the own level, before
+    // and after the change.
+    let levels = |power_levels: &RoomPowerLevels| {
+        [
+            power_levels.ban,
+            power_levels.invite,
+            power_levels.kick,
+            power_levels.redact,
+            power_levels.events_default,
+            power_levels.state_default,
+            power_levels.users_default,
+            power_levels.notifications.room,
+        ]
+    };
+
+    for (level_before, level_after) in levels(before).into_iter().zip(levels(after)) {
+        if level_before != level_after {
+            check_level(level_before)?;
+            check_level(level_after)?;
+        }
+    }
+
+    let event_types = before.events.keys().chain(after.events.keys()).collect::<BTreeSet<_>>();
+
+    for event_type in event_types {
+        let level_before = before.events.get(event_type);
+        let level_after = after.events.get(event_type);
+
+        if level_before != level_after {
+            level_before.copied().map(check_level).transpose()?;
+            level_after.copied().map(check_level).transpose()?;
+        }
+    }
+
+    // The levels of the other users can only be changed if they are lower than
+    // the own level, and the own level can only be lowered.
+    let user_ids = before.users.keys().chain(after.users.keys()).collect::<BTreeSet<_>>();
+
+    for user_id in user_ids {
+        let level_before = before.users.get(user_id);
+        let level_after = after.users.get(user_id);
+
+        if level_before == level_after {
+            continue;
+        }
+
+        if user_id != own_user_id && level_before.is_some_and(|level| *level >= own_level) {
+            return Err(RoomPermissionsError::CannotChangeUserLevel(user_id.clone()));
+        }
+
+        level_after.copied().map(check_level).transpose()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{
+        events::{room::power_levels::RoomPowerLevelsEventContent, StateEventType},
+        int, user_id,
+    };
+
+    use super::{check_changes, RoomPermissionsError, RoomPowerLevels};
+
+    fn power_levels() -> RoomPowerLevels {
+        let mut content = RoomPowerLevelsEventContent::new();
+        content.users.insert(user_id!("@admin:b.c").to_owned(), int!(100));
+        content.users.insert(user_id!("@mod:b.c").to_owned(), int!(50));
+        content.users.insert(user_id!("@other_mod:b.c").to_owned(), int!(50));
+        content.events.insert(StateEventType::RoomPowerLevels.into(), int!(50));
+        RoomPowerLevels::from(content)
+    }
+
+    #[test]
+    fn test_check_changes() {
+        let admin = user_id!("@admin:b.c");
+        let moderator = user_id!("@mod:b.c");
+        let other_moderator = user_id!("@other_mod:b.c");
+        let user = user_id!("@user:b.c");
+        let before = power_levels();
+
+        // A moderator can promote a user up to their own level.
+        let mut after = before.clone();
+        after.users.insert(user.to_owned(), int!(50));
+        check_changes(moderator, &before, &after).unwrap();
+
+        // But not above.
+        after.users.insert(user.to_owned(), int!(51));
+        assert_matches!(
+            check_changes(moderator, &before, &after),
+            Err(RoomPermissionsError::LevelAboveOwn { level: 51, own_level: 50 })
+        );
+
+        // A moderator can't demote another moderator, but an admin can.
+        let mut after = before.clone();
+        after.users.remove(other_moderator);
+        assert_matches!(
+            check_changes(moderator, &before, &after),
+            Err(RoomPermissionsError::CannotChangeUserLevel(user_id))
+        );
+        assert_eq!(user_id, other_moderator);
+        check_changes(admin, &before, &after).unwrap();
+
+        // A moderator can demote themselves.
+        let mut after = before.clone();
+        after.users.remove(moderator);
+        check_changes(moderator, &before, &after).unwrap();
+
+        // A moderator can't change a level that is above their own.
+        let mut after = before.clone();
+        after.ban = int!(40);
+        check_changes(moderator, &before, &after).unwrap();
+        after.ban = int!(100);
+        assert_matches!(
+            check_changes(moderator, &before, &after),
+            Err(RoomPermissionsError::LevelAboveOwn { level: 100, own_level: 50 })
+        );
+
+        // A user can't change the power levels at all.
+        assert_matches!(
+            check_changes(user, &before, &before.clone()),
+            Err(RoomPermissionsError::CannotChangePowerLevels)
+        );
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types to create polls and aggregate their results, as defined in
+//! [MSC3381](https://github.com/matrix-org/matrix-spec-proposals/pull/3381).
+
+use std::fmt::Write as _;
+
+use ruma::{
+    events::poll::{
+        compile_unstable_poll_results,
+        start::{PollAnswersError, PollKind},
+        unstable_start::{
+            NewUnstablePollStartEventContent, UnstablePollAnswer, UnstablePollAnswers,
+            UnstablePollStartContentBlock,
+        },
+        PollResponseData,
+    },
+    MilliSecondsSinceUnixEpoch, OwnedUserId, TransactionId, UInt,
+};
+
+/// A new poll, to send with [`Room::send_poll()`](crate::Room::send_poll).
+///
+/// It can also be used to edit a poll that has not received votes yet, with
+/// [`EditedContent::PollStart`](crate::room::edit::EditedContent::PollStart).
+#[derive(Clone, Debug)]
+pub struct NewPoll {
+    question: String,
+    answers: UnstablePollAnswers,
+    kind: PollKind,
+    max_selections: UInt,
+}
+
+impl NewPoll {
+    /// Create a new disclosed poll that allows a single selection.
+    ///
+    /// A unique ID is generated for every answer.
+    ///
+    /// Returns an error if there are no answers, or more than 20 answers.
+    pub fn new(
+        question: impl Into<String>,
+        answers: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self, PollAnswersError> {
+        let answers = answers
+            .into_iter()
+            .map(|answer| UnstablePollAnswer::new(TransactionId::new().to_string(), answer))
+            .collect::<Vec<_>>()
+            .try_into()?;
+
+        Ok(Self {
+            question: question.into(),
+            answers,
+            kind: PollKind::Disclosed,
+            max_selections: UInt::new_saturating(1),
+        })
+    }
+
+    /// Set the kind of the poll.
+    ///
+    /// The results of an undisclosed poll should only be shown once the poll
+    /// has ended.
+    #[must_use]
+    pub fn kind(mut self, kind: PollKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Set the maximum number of answers a user can select.
+    ///
+    /// It is clamped between 1 and the number of answers.
+    #[must_use]
+    pub fn max_selections(mut self, max_selections: u8) -> Self {
+        let max_selections = usize::from(max_selections).clamp(1, self.answers.len());
+        self.max_selections = UInt::new_saturating(max_selections as u64);
+        self
+    }
+
+    /// The text representation of the poll, for clients that don't support
+    /// polls.
+    pub fn fallback_text(&self) -> String {
+        self.answers.iter().enumerate().fold(self.question.clone(), |mut text, (index, answer)| {
+            let _ = write!(text, "\n{}. {}", index + 1, answer.text);
+            text
+        })
+    }
+
+    /// Convert this poll into the content block of a poll start event.
+    pub fn into_content_block(self) -> UnstablePollStartContentBlock {
+        let mut block = UnstablePollStartContentBlock::new(self.question, self.answers);
+        block.kind = self.kind;
+        block.max_selections = self.max_selections;
+        block
+    }
+}
+
+impl From<NewPoll> for NewUnstablePollStartEventContent {
+    fn from(poll: NewPoll) -> Self {
+        let fallback_text = poll.fallback_text();
+        Self::plain_text(fallback_text, poll.into_content_block())
+    }
+}
+
+/// The aggregated results of a poll.
+#[derive(Clone, Debug)]
+pub struct PollResults {
+    /// The question of the poll.
+    pub question: String,
+    /// The kind of the poll.
+    pub kind: PollKind,
+    /// The maximum number of answers a user can select.
+    pub max_selections: u64,
+    /// The results of each answer, in the order of the poll.
+    pub answers: Vec<PollAnswerResults>,
+    /// The time when the poll ended, if it has ended.
+    pub end_time: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+/// The results of a single answer of a poll.
+#[derive(Clone, Debug)]
+pub struct PollAnswerResults {
+    /// The ID of the answer.
+    pub id: String,
+    /// The text of the answer.
+    pub text: String,
+    /// The users that selected this answer.
+    pub voters: Vec<OwnedUserId>,
+}
+
+impl PollResults {
+    /// Compile the results of a poll from its responses.
+    ///
+    /// Only the latest valid response of each user is counted, and the
+    /// responses sent after the end of the poll are ignored. All the responses
+    /// to the poll should be provided, in any order.
+    pub fn compile<'a>(
+        poll: &'a UnstablePollStartContentBlock,
+        responses: impl IntoIterator<Item = PollResponseData<'a>>,
+        end_time: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Self {
+        let mut results = compile_unstable_poll_results(poll, responses, end_time);
+
+        Self {
+            question: poll.question.text.clone(),
+            kind: poll.kind.clone(),
+            max_selections: poll.max_selections.into(),
+            answers: poll
+                .answers
+                .iter()
+                .map(|answer| PollAnswerResults {
+                    id: answer.id.clone(),
+                    text: answer.text.clone(),
+                    voters: results
+                        .swap_remove(answer.id.as_str())
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                })
+                .collect(),
+            end_time,
+        }
+    }
+
+    /// Whether the poll has ended.
+    pub fn has_ended(&self) -> bool {
+        self.end_time.is_some()
+    }
+
+    /// Whether the results of the poll can be shown to the users.
+    ///
+    /// This is always the case for disclosed polls, while the results of
+    /// undisclosed polls are only shown once the poll has ended.
+    pub fn are_results_disclosed(&self) -> bool {
+        self.kind != PollKind::Undisclosed || self.has_ended()
+    }
+
+    /// The number of users that voted in the poll.
+    pub fn total_voters(&self) -> usize {
+        let mut voters = self.answers.iter().flat_map(|answer| &answer.voters).collect::<Vec<_>>();
+        voters.sort_unstable();
+        voters.dedup();
+        voters.len()
+    }
+
+    /// The answers with the most votes, or an empty list if nobody voted.
+    pub fn winning_answers(&self) -> Vec<&PollAnswerResults> {
+        let max_votes = self.answers.iter().map(|answer| answer.voters.len()).max().unwrap_or(0);
+
+        if max_votes == 0 {
+            return Vec::new();
+        }
+
+        self.answers.iter().filter(|answer| answer.voters.len() == max_votes).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{
+        events::poll::{start::PollKind, unstable_start::NewUnstablePollStartEventContent},
+        owned_user_id, uint, MilliSecondsSinceUnixEpoch,
+    };
+
+    use super::{NewPoll, PollAnswersError, PollResponseData, PollResults};
+
+    #[test]
+    fn test_new_poll() {
+        assert_matches!(
+            NewPoll::new("Question?", Vec::<String>::new()),
+            Err(PollAnswersError::NotEnoughValues)
+        );
+        assert_matches!(
+            NewPoll::new("Question?", (0..21).map(|i| i.to_string())),
+            Err(PollAnswersError::TooManyValues)
+        );
+
+        let poll = NewPoll::new("What's for lunch?", ["Pizza", "Sushi", "Tacos"])
+            .unwrap()
+            .kind(PollKind::Undisclosed)
+            .max_selections(10);
+
+        assert_eq!(poll.fallback_text(), "What's for lunch?\n1. Pizza\n2. Sushi\n3. Tacos");
+
+        let content = NewUnstablePollStartEventContent::from(poll);
+        assert_eq!(
+            content.text.as_deref(),
+            Some("What's for lunch?\n1. Pizza\n2. Sushi\n3. Tacos")
+        );
+        assert_eq!(content.poll_start.kind, PollKind::Undisclosed);
+        assert_eq!(content.poll_start.max_selections, uint!(3));
+
+        let answers = &content.poll_start.answers;
+        assert_eq!(answers.len(), 3);
+        assert_ne!(answers[0].id, answers[1].id);
+        assert_eq!(answers[2].text, "Tacos");
+    }
+
+    #[test]
+    fn test_poll_results() {
+        let block = NewPoll::new("What's for lunch?", ["Pizza", "Sushi", "Tacos"])
+            .unwrap()
+            .kind(PollKind::Undisclosed)
+            .into_content_block();
+        let pizza = block.answers[0].id.clone();
+        let sushi = block.answers[1].id.clone();
+
+        let alice = owned_user_id!("@alice:localhost");
+        let bob = owned_user_id!("@bob:localhost");
+        let carol = owned_user_id!("@carol:localhost");
+
+        let alice_answers = vec![sushi.clone()];
+        let bob_first_answers = vec![sushi];
+        let bob_answers = vec![pizza.clone()];
+        let carol_answers = vec![pizza];
+
+        let responses = [
+            PollResponseData {
+                sender: &alice,
+                origin_server_ts: MilliSecondsSinceUnixEpoch(uint!(1)),
+                selections: &alice_answers,
+            },
+            PollResponseData {
+                sender: &bob,
+                origin_server_ts: MilliSecondsSinceUnixEpoch(uint!(2)),
+                selections: &bob_first_answers,
+            },
+            // Bob changed their mind.
+            PollResponseData {
+                sender: &bob,
+                origin_server_ts: MilliSecondsSinceUnixEpoch(uint!(3)),
+                selections: &bob_answers,
+            },
+            // This vote arrived after the end of the poll.
+            PollResponseData {
+                sender: &carol,
+                origin_server_ts: MilliSecondsSinceUnixEpoch(uint!(5)),
+                selections: &carol_answers,
+            },
+        ];
+
+        let results = PollResults::compile(&block, responses, None);
+        assert!(!results.has_ended());
+        assert!(!results.are_results_disclosed());
+        assert_eq!(results.total_voters(), 3);
+        assert_eq!(results.answers[0].voters, [bob.clone(), carol.clone()]);
+        let winners = results.winning_answers();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(winners[0].text, "Pizza");
+
+        let results =
+            PollResults::compile(&block, responses, Some(MilliSecondsSinceUnixEpoch(uint!(4))));
+        assert!(results.has_ended());
+        assert!(results.are_results_disclosed());
+        assert_eq!(results.total_voters(), 2);
+        assert_eq!(results.answers[0].voters, [bob]);
+        assert_eq!(results.answers[1].voters, [alice]);
+        assert!(results.answers[2].voters.is_empty());
+        assert_eq!(results.winning_answers().len(), 2);
+    }
+}
@@ -1,7 +1,8 @@
-use matrix_sdk_base::Room as BaseRoom;
+use matrix_sdk_base::{Error as BaseError, Room as BaseRoom, RoomState};
 use ruma::{
     api::client::{
         directory::{get_room_visibility, set_room_visibility},
+        error::ErrorKind,
         room::Visibility,
         state::send_state_event,
     },
@@ -10,14 +11,50 @@ use ruma::{
         room::{
             canonical_alias::RoomCanonicalAliasEventContent,
             history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
-            join_rules::{JoinRule, RoomJoinRulesEventContent},
+            join_rules::{AllowRule, JoinRule, Restricted, RoomJoinRulesEventContent},
+            member::MembershipState,
         },
-        EmptyStateKey,
+        EmptyStateKey, StateEventType,
     },
-    OwnedRoomAliasId, RoomAliasId,
+    OwnedRoomAliasId, OwnedRoomId, RoomAliasId, RoomId, RoomVersionId, UserId,
 };
 
-use crate::{Client, Result};
+use crate::{Client, Error, Result};
+
+/// Whether a user can join a room, according to its join rule.
+///
+/// It's returned by [`RoomPrivacySettings::join_eligibility()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JoinEligibility {
+    /// The user is already a member of the room.
+    AlreadyJoined,
+
+    /// The user was invited to the room, so they can join it.
+    Invited,
+
+    /// The user is banned from the room.
+    Banned,
+
+    /// Anyone can join the room.
+    Public,
+
+    /// The user can join the room because they are a member of these spaces,
+    /// as allowed by the restricted join rule of the room.
+    ViaSpaces(Vec<OwnedRoomId>),
+
+    /// The user can't join the room on their own, they need to be invited
+    /// first.
+    InviteRequired {
+        /// Whether the user can ask to be invited by knocking on the room.
+        can_knock: bool,
+
+        /// The spaces allowed by the restricted join rule of the room for
+        /// which the membership of the user is unknown, because the own user
+        /// isn't a member of them or their members aren't synced. The user
+        /// might be able to join via one of them.
+        unknown_spaces: Vec<OwnedRoomId>,
+    },
+}
 
 /// A helper to group the methods in [Room](crate::Room) related to the room's
 /// visibility and access.
@@ -68,6 +105,87 @@ impl<'a> RoomPrivacySettings<'a> {
         Ok(false)
     }
 
+    /// Add an alias to this room.
+    ///
+    /// The alias is published in the room directory if needed, and added to
+    /// the alternative aliases of the room.
+    ///
+    /// Returns `false` if the alias is already used by another room, in which
+    /// case nothing is changed.
+    pub async fn add_alias(&'a self, alias: &RoomAliasId) -> Result<bool> {
+        self.ensure_can_send_state(StateEventType::RoomCanonicalAlias).await?;
+
+        match self.client.resolve_room_alias(alias).await {
+            Ok(response) if response.room_id != self.room.room_id() => return Ok(false),
+            Ok(_) => {}
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                self.client.create_room_alias(alias, self.room.room_id()).await?;
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        let mut alt_aliases = self.room.alt_aliases();
+        if self.room.canonical_alias().as_deref() != Some(alias)
+            && !alt_aliases.iter().any(|alt_alias| alt_alias == alias)
+        {
+            alt_aliases.push(alias.to_owned());
+            self.update_canonical_alias(self.room.canonical_alias(), alt_aliases).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Remove an alias from this room.
+    ///
+    /// The alias is removed from the canonical alias and the alternative
+    /// aliases of the room, and from the room directory if it points to this
+    /// room.
+    pub async fn remove_alias(&'a self, alias: &RoomAliasId) -> Result<()> {
+        self.ensure_can_send_state(StateEventType::RoomCanonicalAlias).await?;
+
+        let canonical_alias = self.room.canonical_alias();
+        let mut alt_aliases = self.room.alt_aliases();
+        let alt_aliases_len = alt_aliases.len();
+        alt_aliases.retain(|alt_alias| alt_alias != alias);
+
+        if canonical_alias.as_deref() == Some(alias) || alt_aliases.len() != alt_aliases_len {
+            let canonical_alias = canonical_alias.filter(|canonical| canonical != alias);
+            self.update_canonical_alias(canonical_alias, alt_aliases).await?;
+        }
+
+        match self.client.resolve_room_alias(alias).await {
+            Ok(response) if response.room_id == self.room.room_id() => {
+                self.client.remove_room_alias(alias).await?;
+            }
+            Ok(_) => {}
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+
+    /// Set the main alias of this room, or remove it with `None`.
+    ///
+    /// The alias must have been added to the room first, with
+    /// [`RoomPrivacySettings::add_alias()`]. The previous main alias is kept as
+    /// an alternative alias.
+    pub async fn set_canonical_alias(&'a self, alias: Option<&RoomAliasId>) -> Result<()> {
+        self.ensure_can_send_state(StateEventType::RoomCanonicalAlias).await?;
+
+        let mut alt_aliases = self.room.alt_aliases();
+        if let Some(previous) = self.room.canonical_alias() {
+            if !alt_aliases.contains(&previous) {
+                alt_aliases.insert(0, previous);
+            }
+        }
+        if let Some(alias) = alias {
+            alt_aliases.retain(|alt_alias| alt_alias != alias);
+        }
+
+        self.update_canonical_alias(alias.map(ToOwned::to_owned), alt_aliases).await
+    }
+
     /// Update the canonical alias of the room.
     ///
     /// # Arguments:
@@ -127,7 +245,15 @@ impl<'a> RoomPrivacySettings<'a> {
     /// room.
     ///
     /// See <https://spec.matrix.org/v1.12/client-server-api/#mroomjoin_rules> for more info.
+    ///
+    /// Returns [`Error::UnsupportedJoinRule`] if the version of the room
+    /// doesn't support the join rule. The check is skipped if the version of
+    /// the room is unknown.
     pub async fn update_join_rule(&'a self, new_rule: JoinRule) -> Result<()> {
+        if let Some(room_version) = self.room.clone_info().room_version() {
+            ensure_join_rule_supported(&new_rule, room_version)?;
+        }
+
         let request = send_state_event::v3::Request::new(
             self.room.room_id().to_owned(),
             &EmptyStateKey,
@@ -137,6 +263,102 @@ impl<'a> RoomPrivacySettings<'a> {
         Ok(())
     }
 
+    /// Restrict the access to this room to the members of the given spaces.
+    ///
+    /// The members of the spaces can join the room without being invited.
+    /// Other users need to be invited, and if `allow_knock` is `true`, they
+    /// can ask to be invited by knocking on the room.
+    ///
+    /// This requires a room version that supports [restricted rooms], and
+    /// [`Error::UnsupportedJoinRule`] is returned otherwise.
+    ///
+    /// [restricted rooms]: https://spec.matrix.org/v1.12/client-server-api/#restricted-rooms
+    pub async fn restrict_to_spaces(
+        &'a self,
+        spaces: impl IntoIterator<Item = OwnedRoomId>,
+        allow_knock: bool,
+    ) -> Result<()> {
+        let restricted =
+            Restricted::new(spaces.into_iter().map(AllowRule::room_membership).collect());
+
+        let new_rule = if allow_knock {
+            JoinRule::KnockRestricted(restricted)
+        } else {
+            JoinRule::Restricted(restricted)
+        };
+
+        self.update_join_rule(new_rule).await
+    }
+
+    /// Find out whether the given user can join this room, according to its
+    /// join rule and the membership of the user, for example to know whether
+    /// they need to be invited.
+    ///
+    /// For restricted rooms, the membership of the user is looked up in the
+    /// allowed spaces that the own user is a member of. Spaces for which it is
+    /// unknown are listed in [`JoinEligibility::InviteRequired`].
+    pub async fn join_eligibility(&'a self, user_id: &UserId) -> Result<JoinEligibility> {
+        if let Some(member) = self.room.get_member(user_id).await? {
+            match member.membership() {
+                MembershipState::Join => return Ok(JoinEligibility::AlreadyJoined),
+                MembershipState::Invite => return Ok(JoinEligibility::Invited),
+                MembershipState::Ban => return Ok(JoinEligibility::Banned),
+                _ => {}
+            }
+        }
+
+        let (restricted, can_knock) = match self.room.join_rule() {
+            JoinRule::Public => return Ok(JoinEligibility::Public),
+            JoinRule::Knock => (None, true),
+            JoinRule::Restricted(restricted) => (Some(restricted), false),
+            JoinRule::KnockRestricted(restricted) => (Some(restricted), true),
+            _ => (None, false),
+        };
+
+        let mut via_spaces = Vec::new();
+        let mut unknown_spaces = Vec::new();
+
+        for rule in restricted.map(|restricted| restricted.allow).unwrap_or_default() {
+            let AllowRule::RoomMembership(membership) = rule else {
+                continue;
+            };
+
+            match self.is_member_of_space(&membership.room_id, user_id).await? {
+                Some(true) => via_spaces.push(membership.room_id),
+                Some(false) => {}
+                None => unknown_spaces.push(membership.room_id),
+            }
+        }
+
+        if via_spaces.is_empty() {
+            Ok(JoinEligibility::InviteRequired { can_knock, unknown_spaces })
+        } else {
+            Ok(JoinEligibility::ViaSpaces(via_spaces))
+        }
+    }
+
+    /// Whether the given user is a member of the given space, or `None` if it
+    /// is unknown.
+    async fn is_member_of_space(
+        &'a self,
+        space_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<bool>> {
+        let Some(space) = self.client.get_room(space_id) else {
+            return Ok(None);
+        };
+
+        if space.state() != RoomState::Joined {
+            return Ok(None);
+        }
+
+        match space.get_member_no_sync(user_id).await? {
+            Some(member) => Ok(Some(*member.membership() == MembershipState::Join)),
+            None if space.are_members_synced() => Ok(Some(false)),
+            None => Ok(None),
+        }
+    }
+
     /// Returns the visibility for this room in the room directory.
     ///
     /// [Public](`Visibility::Public`) rooms are listed in the room directory
@@ -147,6 +369,30 @@ impl<'a> RoomPrivacySettings<'a> {
         Ok(response.visibility)
     }
 
+    /// Publish this room in the room directory of the homeserver, so it can be
+    /// found by other users.
+    ///
+    /// This is a shorthand for
+    /// [`RoomPrivacySettings::update_room_visibility()`]
+    /// with [`Visibility::Public`], that checks that the user is allowed to
+    /// change it first. Homeservers usually require the same power level as
+    /// for changing the aliases of the room.
+    pub async fn publish_in_room_directory(&'a self) -> Result<()> {
+        self.ensure_can_send_state(StateEventType::RoomCanonicalAlias).await?;
+        self.update_room_visibility(Visibility::Public).await
+    }
+
+    /// Remove this room from the room directory of the homeserver.
+    ///
+    /// This is a shorthand for
+    /// [`RoomPrivacySettings::update_room_visibility()`]
+    /// with [`Visibility::Private`], that checks that the user is allowed to
+    /// change it first.
+    pub async fn unpublish_from_room_directory(&'a self) -> Result<()> {
+        self.ensure_can_send_state(StateEventType::RoomCanonicalAlias).await?;
+        self.update_room_visibility(Visibility::Private).await
+    }
+
     /// Update the visibility for this room in the room directory.
     ///
     /// [Public](`Visibility::Public`) rooms are listed in the room directory
@@ -159,12 +405,57 @@ impl<'a> RoomPrivacySettings<'a> {
 
         Ok(())
     }
+
+    /// Check that the user is allowed to send state events of the given type
+    /// in this room.
+    ///
+    /// The homeserver makes the final decision if the power levels of the room
+    /// are unknown.
+    async fn ensure_can_send_state(&'a self, event_type: StateEventType) -> Result<()> {
+        let own_user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
+
+        match self.room.power_levels().await {
+            Ok(power_levels)
+                if !power_levels.user_can_send_state(own_user_id, event_type.clone()) =>
+            {
+                Err(Error::InsufficientPowerLevel(event_type))
+            }
+            Ok(_) | Err(BaseError::InsufficientData) => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Check that the given join rule is supported by the given room version.
+///
+/// Custom room versions and join rules are left to the homeserver to check.
+fn ensure_join_rule_supported(join_rule: &JoinRule, room_version: &RoomVersionId) -> Result<()> {
+    let Ok(version) = room_version.as_str().parse::<u32>() else {
+        return Ok(());
+    };
+
+    let min_version = match join_rule {
+        JoinRule::Knock => 7,
+        JoinRule::Restricted(_) => 8,
+        JoinRule::KnockRestricted(_) => 10,
+        _ => return Ok(()),
+    };
+
+    if version < min_version {
+        return Err(Error::UnsupportedJoinRule {
+            join_rule: join_rule.as_str().to_owned(),
+            room_version: room_version.clone(),
+        });
+    }
+
+    Ok(())
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use std::ops::Not;
 
+    use assert_matches2::assert_matches;
     use matrix_sdk_test::{async_test, JoinedRoomBuilder, StateTestEvent};
     use ruma::{
         api::client::room::Visibility,
@@ -173,10 +464,12 @@ mod tests {
             room::{history_visibility::HistoryVisibility, join_rules::JoinRule},
             StateEventType,
         },
-        owned_room_alias_id, room_id,
+        owned_room_alias_id, owned_room_id, room_id, user_id, RoomVersionId,
     };
+    use serde_json::json;
 
-    use crate::test_utils::mocks::MatrixMockServer;
+    use super::JoinEligibility;
+    use crate::{test_utils::mocks::MatrixMockServer, Error};
 
     #[async_test]
     async fn test_publish_room_alias_to_room_directory() {
@@ -420,4 +713,301 @@ mod tests {
         let ret = room.privacy_settings().update_room_visibility(Visibility::Private).await;
         assert!(ret.is_ok());
     }
+
+    #[async_test]
+    async fn test_add_alias() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Alias);
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        let room_alias = owned_room_alias_id!("#a:b.c");
+
+        // The alias is published in the room directory first.
+        server
+            .mock_room_directory_resolve_alias()
+            .for_alias(room_alias.to_string())
+            .not_found()
+            .mock_once()
+            .mount()
+            .await;
+        server.mock_room_directory_create_room_alias().ok().mock_once().mount().await;
+
+        // Then it's added to the alternative aliases, keeping the main alias.
+        server
+            .mock_room_send_state()
+            .for_type(StateEventType::RoomCanonicalAlias)
+            .body_matches_partial_json(json!({
+                "alias": "#tutorial:localhost",
+                "alt_aliases": ["#a:b.c"],
+            }))
+            .ok(event_id!("$a:b.c"))
+            .mock_once()
+            .mount()
+            .await;
+
+        let added = room.privacy_settings().add_alias(&room_alias).await.unwrap();
+        assert!(added);
+    }
+
+    #[async_test]
+    async fn test_add_alias_used_by_another_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        let room_alias = owned_room_alias_id!("#a:b.c");
+
+        server
+            .mock_room_directory_resolve_alias()
+            .for_alias(room_alias.to_string())
+            .ok("!other:b.c", Vec::new())
+            .mock_once()
+            .mount()
+            .await;
+        server.mock_room_directory_create_room_alias().ok().never().mount().await;
+        server.mock_room_send_state().ok(event_id!("$a:b.c")).never().mount().await;
+
+        let added = room.privacy_settings().add_alias(&room_alias).await.unwrap();
+        assert!(added.not());
+    }
+
+    #[async_test]
+    async fn test_remove_alias() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Alias);
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        let room_alias = owned_room_alias_id!("#tutorial:localhost");
+
+        // The alias is removed from the room state first.
+        server
+            .mock_room_send_state()
+            .for_type(StateEventType::RoomCanonicalAlias)
+            .ok(event_id!("$a:b.c"))
+            .mock_once()
+            .mount()
+            .await;
+
+        // Then from the room directory.
+        server
+            .mock_room_directory_resolve_alias()
+            .for_alias(room_alias.to_string())
+            .ok(room_id.as_ref(), Vec::new())
+            .mock_once()
+            .mount()
+            .await;
+        server.mock_room_directory_remove_room_alias().ok().mock_once().mount().await;
+
+        room.privacy_settings().remove_alias(&room_alias).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_set_canonical_alias() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Alias);
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        // The previous main alias is kept as an alternative alias.
+        server
+            .mock_room_send_state()
+            .for_type(StateEventType::RoomCanonicalAlias)
+            .body_matches_partial_json(json!({
+                "alias": "#a:b.c",
+                "alt_aliases": ["#tutorial:localhost"],
+            }))
+            .ok(event_id!("$a:b.c"))
+            .mock_once()
+            .mount()
+            .await;
+
+        room.privacy_settings()
+            .set_canonical_alias(Some(&owned_room_alias_id!("#a:b.c")))
+            .await
+            .unwrap();
+    }
+
+    #[async_test]
+    async fn test_publish_in_room_directory() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::PowerLevels);
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        server.mock_room_directory_set_room_visibility().ok().mock_once().mount().await;
+
+        room.privacy_settings().publish_in_room_directory().await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_publish_in_room_directory_without_permission() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": {
+                    "events": { "m.room.canonical_alias": 50 },
+                    "users": { "@bob:b.c": 100 },
+                },
+                "event_id": "$power_levels",
+                "origin_server_ts": 1,
+                "sender": "@bob:b.c",
+                "state_key": "",
+                "type": "m.room.power_levels",
+            })));
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        server.mock_room_directory_set_room_visibility().ok().never().mount().await;
+
+        let error = room.privacy_settings().publish_in_room_directory().await.unwrap_err();
+        assert_matches!(error, Error::InsufficientPowerLevel(StateEventType::RoomCanonicalAlias));
+    }
+
+    #[async_test]
+    async fn test_update_join_rule_unsupported_by_room_version() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder =
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "content": {
+                    "creator": "@bob:b.c",
+                    "room_version": "7",
+                },
+                "event_id": "$create",
+                "origin_server_ts": 1,
+                "sender": "@bob:b.c",
+                "state_key": "",
+                "type": "m.room.create",
+            })));
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        server.mock_room_send_state().ok(event_id!("$a:b.c")).never().mount().await;
+
+        let error = room
+            .privacy_settings()
+            .restrict_to_spaces([owned_room_id!("!space:b.c")], false)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::UnsupportedJoinRule { join_rule, room_version });
+        assert_eq!(join_rule, "restricted");
+        assert_eq!(room_version, RoomVersionId::V7);
+    }
+
+    #[async_test]
+    async fn test_restrict_to_spaces() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let room_id = room_id!("!a:b.c");
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        server
+            .mock_room_send_state()
+            .for_type(StateEventType::RoomJoinRules)
+            .body_matches_partial_json(json!({
+                "join_rule": "knock_restricted",
+                "allow": [{ "type": "m.room_membership", "room_id": "!space:b.c" }],
+            }))
+            .ok(event_id!("$a:b.c"))
+            .mock_once()
+            .mount()
+            .await;
+
+        room.privacy_settings()
+            .restrict_to_spaces([owned_room_id!("!space:b.c")], true)
+            .await
+            .unwrap();
+    }
+
+    #[async_test]
+    async fn test_join_eligibility() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let member_event = |user_id: &str, membership: &str| {
+            let localpart = user_id.trim_start_matches('@').split(':').next().unwrap();
+
+            StateTestEvent::Custom(json!({
+                "content": { "membership": membership },
+                "event_id": format!("${localpart}_{membership}"),
+                "origin_server_ts": 1,
+                "sender": user_id,
+                "state_key": user_id,
+                "type": "m.room.member",
+            }))
+        };
+
+        // Alice is a member of the first space, the members of the second one are
+        // unknown.
+        let space_id = room_id!("!space:b.c");
+        server
+            .sync_room(
+                &client,
+                JoinedRoomBuilder::new(space_id)
+                    .add_state_event(member_event("@alice:b.c", "join")),
+            )
+            .await;
+
+        let room_id = room_id!("!a:b.c");
+        let joined_room_builder = JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Custom(json!({
+                "content": {
+                    "join_rule": "restricted",
+                    "allow": [
+                        { "type": "m.room_membership", "room_id": "!space:b.c" },
+                        { "type": "m.room_membership", "room_id": "!other_space:b.c" },
+                    ],
+                },
+                "event_id": "$join_rules",
+                "origin_server_ts": 1,
+                "sender": "@bob:b.c",
+                "state_key": "",
+                "type": "m.room.join_rules",
+            })))
+            .add_state_event(member_event("@bob:b.c", "join"))
+            .add_state_event(member_event("@mallory:b.c", "ban"));
+        let room = server.sync_room(&client, joined_room_builder).await;
+
+        let privacy_settings = room.privacy_settings();
+
+        assert_eq!(
+            privacy_settings.join_eligibility(user_id!("@alice:b.c")).await.unwrap(),
+            JoinEligibility::ViaSpaces(vec![space_id.to_owned()])
+        );
+        assert_eq!(
+            privacy_settings.join_eligibility(user_id!("@bob:b.c")).await.unwrap(),
+            JoinEligibility::AlreadyJoined
+        );
+        assert_eq!(
+            privacy_settings.join_eligibility(user_id!("@mallory:b.c")).await.unwrap(),
+            JoinEligibility::Banned
+        );
+        assert_eq!(
+            privacy_settings.join_eligibility(user_id!("@carol:b.c")).await.unwrap(),
+            JoinEligibility::InviteRequired {
+                can_knock: false,
+                unknown_spaces: vec![space_id.to_owned(), owned_room_id!("!other_space:b.c")],
+            }
+        );
+    }
 }
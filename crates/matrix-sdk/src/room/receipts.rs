@@ -0,0 +1,422 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batching of the read receipts and of the fully-read marker.
+//!
+//! Apps usually want to move the read receipts every time the user scrolls
+//! through a room. Instead of sending a request every time,
+//! [`Room::queue_receipt()`] keeps the latest receipt of every type and thread,
+//! ignores the ones that would move a receipt backwards, and sends them all
+//! together after [`RECEIPTS_BATCH_DELAY`], or after a longer delay in the
+//! [`BandwidthProfile::LowBandwidth`] profile.
+//!
+//! [`BandwidthProfile::LowBandwidth`]: crate::bandwidth::BandwidthProfile::LowBandwidth
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use matrix_sdk_base::{
+    deserialized_responses::TimelineEvent, event_cache::store::extract_event_relation,
+    read_receipts::marks_as_unread,
+};
+use matrix_sdk_common::executor::spawn;
+use ruma::{
+    api::client::receipt::create_receipt::v3::ReceiptType as SendReceiptType,
+    events::{
+        fully_read::FullyReadEventContent,
+        receipt::{ReceiptThread, ReceiptType},
+        relation::RelationType,
+    },
+    EventId, OwnedEventId, OwnedUserId,
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use super::{Receipts, Room};
+use crate::Result;
+
+/// How long the receipts queued with [`Room::queue_receipt()`] are kept before
+/// being sent, so they can be batched.
+pub const RECEIPTS_BATCH_DELAY: Duration = Duration::from_millis(500);
+
+/// The key of a receipt, since the receipt type and the thread aren't Ord.
+type ReceiptKey = String;
+
+/// The maximum number of times the delay before sending the queued receipts
+/// again is doubled after failures.
+const MAX_FLUSH_BACKOFF_EXPONENT: u32 = 6;
+
+/// The receipts of a room that are queued or were sent by this client.
+#[derive(Default)]
+pub(crate) struct PendingReceipts {
+    /// The receipts waiting to be sent, latest one wins.
+    queued: BTreeMap<ReceiptKey, (SendReceiptType, ReceiptThread, OwnedEventId)>,
+
+    /// The last receipts that were sent, which might not have been received
+    /// back in a sync yet.
+    sent: BTreeMap<ReceiptKey, OwnedEventId>,
+
+    /// Whether a task to send the queued receipts is scheduled.
+    flush_scheduled: bool,
+
+    /// The number of times in a row that sending the queued receipts failed.
+    failed_flushes: u32,
+}
+
+/// The unread state of a thread, computed from the events loaded in the event
+/// cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnreadState {
+    /// The number of events sent by other users after the read receipt of the
+    /// user, among the loaded events.
+    ///
+    /// Only the events that are worth marking a room as unread are counted,
+    /// with the same rules as the unread counts of the rooms.
+    pub num_unread_events: usize,
+
+    /// Whether the read receipt of the user, or an event they sent, was found
+    /// among the loaded events.
+    ///
+    /// If it's `false`, [`UnreadState::num_unread_events`] is only a lower
+    /// bound.
+    pub is_exact: bool,
+}
+
+fn receipt_key(receipt_type: &SendReceiptType, thread: &ReceiptThread) -> ReceiptKey {
+    format!("{}|{}", receipt_type, thread_key(thread))
+}
+
+fn thread_key(thread: &ReceiptThread) -> &str {
+    thread.as_str().unwrap_or("<unthreaded>")
+}
+
+/// The thread of the given event.
+fn event_thread(event: &TimelineEvent) -> ReceiptThread {
+    match extract_event_relation(event.raw()) {
+        Some((thread_root, rel_type)) if rel_type == RelationType::Thread.as_str() => {
+            ReceiptThread::Thread(thread_root)
+        }
+        _ => ReceiptThread::Main,
+    }
+}
+
+impl Room {
+    /// Queue a receipt, to be sent with the other receipts of the room after
+    /// the [`receipts_batch_delay`] of the current bandwidth profile.
+    ///
+    /// Only the latest receipt of each type and thread is sent. The receipt is
+    /// ignored if it's not after the current one, when both events are loaded
+    /// in the event cache.
+    ///
+    /// Returns whether the receipt was queued.
+    ///
+    /// # Arguments
+    ///
+    /// * `receipt_type` - The type of the receipt. Note that it is possible to
+    ///   queue the fully-read marker although it is technically not a receipt.
+    ///
+    /// * `thread` - The thread where this receipt should apply. It's ignored
+    ///   for the fully-read marker, which is always unthreaded.
+    ///
+    /// * `event_id` - The `EventId` of the event to set the receipt on.
+    ///
+    /// [`receipts_batch_delay`]: crate::bandwidth::BandwidthSettings::receipts_batch_delay
+    pub async fn queue_receipt(
+        &self,
+        receipt_type: SendReceiptType,
+        thread: ReceiptThread,
+        event_id: OwnedEventId,
+    ) -> Result<bool> {
+        let thread = if receipt_type == SendReceiptType::FullyRead {
+            ReceiptThread::Unthreaded
+        } else {
+            thread
+        };
+        let key = receipt_key(&receipt_type, &thread);
+
+        let pending = self.pending_receipts();
+        let mut pending_guard = pending.lock().await;
+
+        let current = match pending_guard
+            .queued
+            .get(&key)
+            .map(|(_, _, event_id)| event_id)
+            .or_else(|| pending_guard.sent.get(&key))
+        {
+            Some(current) => Some(current.clone()),
+            None => self.own_receipt(&receipt_type, &thread).await?,
+        };
+
+        if let Some(current) = current {
+            if !self.is_after(&event_id, &current).await {
+                debug!(%event_id, %current, "Ignoring a receipt that is not after the current one");
+                return Ok(false);
+            }
+        }
+
+        pending_guard.queued.insert(key, (receipt_type, thread, event_id));
+        self.schedule_receipts_flush(&mut pending_guard);
+
+        Ok(true)
+    }
+
+    /// Spawn a task to send the queued receipts after a delay, if there isn't
+    /// one already.
+    ///
+    /// The delay is doubled every time sending the receipts failed in a row.
+    fn schedule_receipts_flush(&self, pending_guard: &mut PendingReceipts) {
+        if pending_guard.flush_scheduled {
+            return;
+        }
+        pending_guard.flush_scheduled = true;
+
+        let backoff = 2u32.pow(pending_guard.failed_flushes.min(MAX_FLUSH_BACKOFF_EXPONENT));
+        let room = self.clone();
+        spawn(async move {
+            let delay = room.client.bandwidth_settings().receipts_batch_delay * backoff;
+            room.client.clock().sleep(delay).await;
+
+            if let Err(error) = room.flush_receipts().await {
+                warn!(room_id = ?room.room_id(), "Couldn't send the queued receipts: {error}");
+            }
+        });
+    }
+
+    /// Send the receipts queued with [`Room::queue_receipt()`] now.
+    ///
+    /// The unthreaded receipts and the fully-read marker are sent in a single
+    /// request. If sending fails, the receipts are queued again, unless newer
+    /// ones were queued in the meantime, and they are sent again later.
+    pub async fn flush_receipts(&self) -> Result<()> {
+        let pending = self.pending_receipts();
+
+        let queued = {
+            let mut pending_guard = pending.lock().await;
+            pending_guard.flush_scheduled = false;
+            std::mem::take(&mut pending_guard.queued)
+        };
+
+        if queued.is_empty() {
+            return Ok(());
+        }
+
+        let mut receipts = Receipts::new();
+        let mut threaded = Vec::new();
+
+        for (receipt_type, thread, event_id) in queued.values() {
+            match (receipt_type, thread) {
+                (SendReceiptType::FullyRead, _) => {
+                    receipts.fully_read = Some(event_id.clone());
+                }
+                (SendReceiptType::Read, ReceiptThread::Unthreaded) => {
+                    receipts.public_read_receipt = Some(event_id.clone());
+                }
+                (SendReceiptType::ReadPrivate, ReceiptThread::Unthreaded) => {
+                    receipts.private_read_receipt = Some(event_id.clone());
+                }
+                _ => threaded.push((receipt_type.clone(), thread.clone(), event_id.clone())),
+            }
+        }
+
+        let result = async {
+            self.send_multiple_receipts(receipts).await?;
+
+            for (receipt_type, thread, event_id) in threaded {
+                self.send_single_receipt(receipt_type, thread, event_id).await?;
+            }
+
+            Ok(())
+        }
+        .await;
+
+        let mut pending_guard = pending.lock().await;
+        for (key, receipt) in queued {
+            if result.is_ok() {
+                pending_guard.sent.insert(key, receipt.2);
+            } else {
+                pending_guard.queued.entry(key).or_insert(receipt);
+            }
+        }
+
+        if result.is_ok() {
+            pending_guard.failed_flushes = 0;
+        } else {
+            pending_guard.failed_flushes = pending_guard.failed_flushes.saturating_add(1);
+            self.schedule_receipts_flush(&mut pending_guard);
+        }
+
+        result
+    }
+
+    /// Get the unread state of the given thread, from the receipts of the
+    /// user and the events loaded in the event cache.
+    ///
+    /// The unthreaded receipts apply to all the threads. The events sent by the
+    /// user count as read receipts in their thread, or as unthreaded receipts
+    /// in the main timeline.
+    ///
+    /// Use [`ReceiptThread::Main`] to get the unread state of the main
+    /// timeline, and [`ReceiptThread::Unthreaded`] to get the unread state of
+    /// the whole room, where the events of every thread are read according to
+    /// the receipts of their thread. In the latter case, the state is only
+    /// exact if an unthreaded receipt, or an event sent by the user in the
+    /// main timeline, was found.
+    pub async fn unread_state(&self, thread: ReceiptThread) -> Result<UnreadState> {
+        let unthreaded_receipts = self.own_read_receipts(&ReceiptThread::Unthreaded).await?;
+
+        let (room_event_cache, _drop_handles) = self.event_cache().await?;
+        let own_user_id = self.own_user_id().to_owned();
+
+        // The most recent events come first.
+        let events = room_event_cache.rfind_events(|_| true, usize::MAX).await;
+        let mut state = UnreadState::default();
+
+        // The read receipts of the threads of the events, and the threads whose read
+        // receipt was reached.
+        let mut thread_receipts = BTreeMap::<String, Vec<OwnedEventId>>::new();
+        let mut read_threads = BTreeSet::<String>::new();
+
+        for event in events {
+            let event_id = event.event_id();
+            if event_id.as_ref().is_some_and(|event_id| unthreaded_receipts.contains(event_id)) {
+                state.is_exact = true;
+                break;
+            }
+
+            let event_thread = event_thread(&event);
+            if thread != ReceiptThread::Unthreaded && event_thread != thread {
+                continue;
+            }
+
+            let key = thread_key(&event_thread).to_owned();
+            if read_threads.contains(&key) {
+                continue;
+            }
+
+            if !thread_receipts.contains_key(&key) {
+                let receipts = self.own_read_receipts(&event_thread).await?;
+                thread_receipts.insert(key.clone(), receipts);
+            }
+            let is_receipt =
+                event_id.as_ref().is_some_and(|event_id| thread_receipts[&key].contains(event_id));
+            let sender = event.raw().get_field::<OwnedUserId>("sender").ok().flatten();
+            let is_own = sender.as_ref() == Some(&own_user_id);
+
+            if is_receipt || is_own {
+                // The read receipt of the requested thread was reached, or the user sent an
+                // event in the main timeline, so everything before was read.
+                if thread != ReceiptThread::Unthreaded
+                    || (is_own && event_thread == ReceiptThread::Main)
+                {
+                    state.is_exact = true;
+                    break;
+                }
+
+                read_threads.insert(key);
+                continue;
+            }
+
+            if marks_as_unread(event.raw(), &own_user_id) {
+                state.num_unread_events += 1;
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Get the events of the public and private read receipts of the user in
+    /// the given thread, the queued or sent ones first.
+    async fn own_read_receipts(&self, thread: &ReceiptThread) -> Result<Vec<OwnedEventId>> {
+        let pending = self.pending_receipts();
+        let mut event_ids = Vec::new();
+
+        for receipt_type in [SendReceiptType::Read, SendReceiptType::ReadPrivate] {
+            let key = receipt_key(&receipt_type, thread);
+            let pending_event_id = {
+                let pending_guard = pending.lock().await;
+                pending_guard
+                    .queued
+                    .get(&key)
+                    .map(|(_, _, event_id)| event_id.clone())
+                    .or_else(|| pending_guard.sent.get(&key).cloned())
+            };
+
+            let event_id = match pending_event_id {
+                Some(event_id) => Some(event_id),
+                None => self.own_receipt(&receipt_type, thread).await?,
+            };
+            event_ids.extend(event_id);
+        }
+
+        Ok(event_ids)
+    }
+
+    /// The receipts of this room that are queued or were sent by this client.
+    fn pending_receipts(&self) -> Arc<Mutex<PendingReceipts>> {
+        self.client
+            .inner
+            .pending_receipts
+            .lock()
+            .unwrap()
+            .entry(self.room_id().to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Get the event of the current receipt of the given type and thread of
+    /// the user, from the store.
+    async fn own_receipt(
+        &self,
+        receipt_type: &SendReceiptType,
+        thread: &ReceiptThread,
+    ) -> Result<Option<OwnedEventId>> {
+        let receipt_type = match receipt_type {
+            SendReceiptType::Read => ReceiptType::Read,
+            SendReceiptType::ReadPrivate => ReceiptType::ReadPrivate,
+            SendReceiptType::FullyRead => {
+                let event = self.account_data_static::<FullyReadEventContent>().await?;
+                return Ok(event
+                    .and_then(|event| event.deserialize().ok())
+                    .map(|event| event.content.event_id));
+            }
+            _ => return Ok(None),
+        };
+
+        let receipt =
+            self.load_user_receipt(receipt_type, thread.clone(), self.own_user_id()).await?;
+        Ok(receipt.map(|(event_id, _)| event_id))
+    }
+
+    /// Whether the first event is after the second one in the room.
+    ///
+    /// If one of the events isn't loaded in the event cache, the order is
+    /// unknown, so the first one is assumed to be after.
+    async fn is_after(&self, first: &EventId, second: &EventId) -> bool {
+        if first == second {
+            return false;
+        }
+
+        let Ok((room_event_cache, _drop_handles)) = self.event_cache().await else {
+            return true;
+        };
+
+        room_event_cache
+            .compare_events_positions(first, second)
+            .await
+            .is_none_or(|ordering| ordering.is_gt())
+    }
+}
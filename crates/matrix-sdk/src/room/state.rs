@@ -0,0 +1,277 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk access to the state of a room: fetch all of it at once with
+//! [`Room::state_snapshot()`], or change several state events together with
+//! [`Room::send_state_batch()`], for example in a room settings screen.
+
+use std::{borrow::Borrow, collections::BTreeMap};
+
+use ruma::{
+    events::{
+        AnyStateEvent, AnyStateEventContent, EmptyStateKey, EventContentFromType, RedactContent,
+        RedactedStateEventContent, StateEvent, StateEventContent, StateEventType,
+        StaticEventContent, StaticStateEventContent,
+    },
+    serde::Raw,
+    OwnedEventId,
+};
+use thiserror::Error;
+use tracing::warn;
+
+#[cfg(doc)]
+use crate::Room;
+use crate::{utils::IntoRawStateEventContent, Error};
+
+/// All the current state events of a room, grouped by type and state key.
+///
+/// It's returned by [`Room::state_snapshot()`].
+#[derive(Clone, Debug, Default)]
+pub struct RoomStateSnapshot {
+    events: BTreeMap<StateEventType, BTreeMap<String, Raw<AnyStateEvent>>>,
+}
+
+impl RoomStateSnapshot {
+    pub(super) fn new(events: Vec<Raw<AnyStateEvent>>) -> Self {
+        let mut snapshot = Self::default();
+
+        for event in events {
+            let event_type = event.get_field::<StateEventType>("type");
+            let state_key = event.get_field::<String>("state_key");
+
+            let (Ok(Some(event_type)), Ok(Some(state_key))) = (event_type, state_key) else {
+                warn!("Ignoring state event without type or state key");
+                continue;
+            };
+
+            snapshot.events.entry(event_type).or_default().insert(state_key, event);
+        }
+
+        snapshot
+    }
+
+    /// The types of the state events of the room.
+    pub fn event_types(&self) -> impl Iterator<Item = &StateEventType> {
+        self.events.keys()
+    }
+
+    /// Get the state event with the given type and state key.
+    pub fn get(&self, event_type: &StateEventType, state_key: &str) -> Option<AnyStateEvent> {
+        self.get_raw(event_type, state_key)?
+            .deserialize()
+            .inspect_err(|error| {
+                warn!("Failed to deserialize the `{event_type}` state event: {error}");
+            })
+            .ok()
+    }
+
+    /// Get the raw state event with the given type and state key.
+    pub fn get_raw(
+        &self,
+        event_type: &StateEventType,
+        state_key: &str,
+    ) -> Option<&Raw<AnyStateEvent>> {
+        self.events.get(event_type)?.get(state_key)
+    }
+
+    /// Get all the raw state events of the given type, keyed by state key.
+    pub fn get_all_raw(
+        &self,
+        event_type: &StateEventType,
+    ) -> impl Iterator<Item = (&str, &Raw<AnyStateEvent>)> {
+        self.events
+            .get(event_type)
+            .into_iter()
+            .flatten()
+            .map(|(state_key, event)| (state_key.as_str(), event))
+    }
+
+    /// Get the state event of a statically-known type with an empty state key.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// # let room: matrix_sdk::Room = todo!();
+    /// use matrix_sdk::ruma::events::room::topic::RoomTopicEventContent;
+    ///
+    /// let snapshot = room.state_snapshot().await?;
+    /// let topic = snapshot.get_static::<RoomTopicEventContent>();
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn get_static<C>(&self) -> Option<StateEvent<C>>
+    where
+        C: StaticEventContent
+            + StaticStateEventContent<StateKey = EmptyStateKey>
+            + EventContentFromType
+            + RedactContent,
+        C::Redacted: RedactedStateEventContent<StateKey = C::StateKey> + EventContentFromType,
+    {
+        self.get_static_for_key(&EmptyStateKey)
+    }
+
+    /// Get the state event of a statically-known type with the given state
+    /// key.
+    pub fn get_static_for_key<C, K>(&self, state_key: &K) -> Option<StateEvent<C>>
+    where
+        C: StaticEventContent + StaticStateEventContent + EventContentFromType + RedactContent,
+        C::StateKey: Borrow<K>,
+        C::Redacted: RedactedStateEventContent<StateKey = C::StateKey> + EventContentFromType,
+        K: AsRef<str> + ?Sized,
+    {
+        let event = self.get_raw(&C::TYPE.into(), state_key.as_ref())?;
+
+        event
+            .deserialize_as()
+            .inspect_err(|error| {
+                warn!("Failed to deserialize the `{}` state event: {error}", C::TYPE);
+            })
+            .ok()
+    }
+
+    /// The number of state events in the room.
+    pub fn len(&self) -> usize {
+        self.events.values().map(BTreeMap::len).sum()
+    }
+
+    /// Whether the room has no state events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The content of the state event with the given type and state key, if
+    /// any.
+    pub(super) fn content(
+        &self,
+        event_type: &StateEventType,
+        state_key: &str,
+    ) -> Option<Raw<AnyStateEventContent>> {
+        self.get_raw(event_type, state_key)?.get_field("content").ok().flatten()
+    }
+}
+
+/// A state event to send with [`Room::send_state_batch()`].
+#[derive(Clone, Debug)]
+pub struct StateBatchEvent {
+    event_type: StateEventType,
+    state_key: String,
+    content: Raw<AnyStateEventContent>,
+}
+
+impl StateBatchEvent {
+    /// Create a state event with an empty state key.
+    pub fn new(
+        content: impl StateEventContent<StateKey = EmptyStateKey>,
+    ) -> serde_json::Result<Self> {
+        Self::for_key(&EmptyStateKey, content)
+    }
+
+    /// Create a state event with the given state key.
+    pub fn for_key<C, K>(state_key: &K, content: C) -> serde_json::Result<Self>
+    where
+        C: StateEventContent,
+        C::StateKey: Borrow<K>,
+        K: AsRef<str> + ?Sized,
+    {
+        Ok(Self {
+            event_type: content.event_type(),
+            state_key: state_key.as_ref().to_owned(),
+            content: Raw::new(&content)?.cast(),
+        })
+    }
+
+    /// Create a state event from its raw content.
+    pub fn raw(event_type: &str, state_key: &str, content: impl IntoRawStateEventContent) -> Self {
+        Self {
+            event_type: event_type.into(),
+            state_key: state_key.to_owned(),
+            content: content.into_raw_state_event_content(),
+        }
+    }
+
+    /// The type of the state event.
+    pub fn event_type(&self) -> &StateEventType {
+        &self.event_type
+    }
+
+    /// The state key of the state event.
+    pub fn state_key(&self) -> &str {
+        &self.state_key
+    }
+
+    pub(super) fn content(&self) -> &Raw<AnyStateEventContent> {
+        &self.content
+    }
+}
+
+/// An error when sending state events with [`Room::send_state_batch()`].
+#[derive(Debug, Error)]
+pub enum StateBatchError {
+    /// The own user isn't allowed to send state events of this type, so none
+    /// of the state events were sent.
+    #[error("the user isn't allowed to send `{0}` state events")]
+    Forbidden(StateEventType),
+
+    /// A state event couldn't be sent, and the state events of the batch that
+    /// were sent before it were reverted.
+    #[error(
+        "failed to send the `{}` state event with state key `{}`: {}",
+        .0.event_type,
+        .0.state_key,
+        .0.error
+    )]
+    Failed(Box<StateBatchFailure>),
+}
+
+/// The report of a state event that couldn't be sent with
+/// [`Room::send_state_batch()`].
+#[derive(Debug)]
+pub struct StateBatchFailure {
+    /// The type of the state event that couldn't be sent.
+    pub event_type: StateEventType,
+
+    /// The state key of the state event that couldn't be sent.
+    pub state_key: String,
+
+    /// The reason why the state event couldn't be sent.
+    pub error: Error,
+
+    /// The result of the rollback of each state event of the batch that was
+    /// sent before the failure, in the order in which they were reverted.
+    pub rollback: Vec<StateRollback>,
+}
+
+impl StateBatchFailure {
+    /// Whether all the state events that were sent were reverted
+    /// successfully, leaving the state of the room as it was before the
+    /// batch.
+    pub fn is_rolled_back(&self) -> bool {
+        self.rollback.iter().all(|rollback| rollback.result.is_ok())
+    }
+}
+
+/// The result of the rollback of a state event that was sent by
+/// [`Room::send_state_batch()`].
+#[derive(Debug)]
+pub struct StateRollback {
+    /// The type of the reverted state event.
+    pub event_type: StateEventType,
+
+    /// The state key of the reverted state event.
+    pub state_key: String,
+
+    /// The ID of the state event that restored the previous content, or the
+    /// reason why it couldn't be sent.
+    pub result: Result<OwnedEventId, Error>,
+}
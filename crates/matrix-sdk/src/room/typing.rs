@@ -0,0 +1,160 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{sync::Arc, time::Duration};
+
+use matrix_sdk_common::{executor::spawn, timeout::timeout_with_clock};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use super::{Room, TYPING_NOTICE_RESEND_TIMEOUT};
+
+/// How long the typing notice is kept after the last [`TypingGuard`] of a room
+/// is dropped, in case a new one is created right after, for example on the
+/// next keystroke.
+const TYPING_GUARD_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// The [`TypingGuard`]s of a room.
+pub(crate) struct TypingGuards {
+    /// The number of live guards.
+    count: usize,
+
+    /// Notified when the last guard is dropped.
+    released: Arc<Notify>,
+}
+
+/// A handle keeping the typing notice of the user active in a room, returned
+/// by [`Room::typing_guard()`].
+///
+/// The typing notice is refreshed before it times out while at least one guard
+/// of the room is alive, and it is stopped shortly after the last one is
+/// dropped. The typing notices of a room are all sent by the same task, so a
+/// notice to stop typing is never sent after the notice to start typing of a
+/// newer guard.
+#[derive(Debug)]
+pub struct TypingGuard {
+    room: Room,
+}
+
+impl Drop for TypingGuard {
+    fn drop(&mut self) {
+        let mut guards = self.room.client.inner.typing_guards.lock().unwrap();
+
+        if let Some(room_guards) = guards.get_mut(self.room.room_id()) {
+            room_guards.count -= 1;
+
+            if room_guards.count == 0 {
+                room_guards.released.notify_one();
+            }
+        }
+    }
+}
+
+impl Room {
+    /// Get a handle that keeps the typing notice of the user active in this
+    /// room, until it is dropped.
+    ///
+    /// All the guards of a room share the same typing notice, which is sent
+    /// when the first one is created, and refreshed before it times out. It
+    /// is stopped when the last guard has been dropped for a short grace
+    /// period, so creating a new guard on every keystroke doesn't send a
+    /// request every time.
+    ///
+    /// The typing notices are sent by a task spawned on the async runtime,
+    /// hence this method being async.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::room_id};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room = client.get_room(room_id!("!test:localhost")).unwrap();
+    /// let guard = room.typing_guard().await;
+    ///
+    /// // The user composes their message…
+    ///
+    /// drop(guard);
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn typing_guard(&self) -> TypingGuard {
+        let mut guards = self.client.inner.typing_guards.lock().unwrap();
+
+        let room_guards = guards.entry(self.room_id().to_owned()).or_insert_with(|| {
+            let released = Arc::new(Notify::new());
+            spawn(self.clone().keep_typing(released.clone()));
+            TypingGuards { count: 0, released }
+        });
+        room_guards.count += 1;
+
+        TypingGuard { room: self.clone() }
+    }
+
+    /// Send the typing notice until all the [`TypingGuard`]s of the room are
+    /// released.
+    ///
+    /// The entry of the room in the guards is only removed once the notice to
+    /// stop typing was sent, so the guards created in the meantime don't
+    /// spawn another task that could start typing before it is sent.
+    async fn keep_typing(self, released: Arc<Notify>) {
+        loop {
+            if let Err(error) = self.typing_notice(true).await {
+                warn!(room_id = ?self.room_id(), "Couldn't send the typing notice: {error}");
+            }
+
+            // Wait until the typing notice must be refreshed, or the guards are released.
+            let clock = self.client.clock().clone();
+            if timeout_with_clock(clock.as_ref(), released.notified(), TYPING_NOTICE_RESEND_TIMEOUT)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+
+            clock.sleep(TYPING_GUARD_GRACE_PERIOD).await;
+
+            if self.has_typing_guards() {
+                continue;
+            }
+
+            if let Err(error) = self.typing_notice(false).await {
+                warn!(room_id = ?self.room_id(), "Couldn't stop the typing notice: {error}");
+            }
+
+            {
+                let mut guards = self.client.inner.typing_guards.lock().unwrap();
+                if guards.get(self.room_id()).is_some_and(|room_guards| room_guards.count > 0) {
+                    // A guard was created while the typing notice was being stopped.
+                    continue;
+                }
+                guards.remove(self.room_id());
+            }
+
+            return;
+        }
+    }
+
+    /// Whether there is a live [`TypingGuard`] for this room.
+    fn has_typing_guards(&self) -> bool {
+        self.client
+            .inner
+            .typing_guards
+            .lock()
+            .unwrap()
+            .get(self.room_id())
+            .is_some_and(|room_guards| room_guards.count > 0)
+    }
+}
@@ -20,7 +20,8 @@ use futures_core::Stream;
 use imbl::Vector;
 use ruma::{
     api::client::directory::get_public_rooms_filtered::v3::Request as PublicRoomsFilterRequest,
-    directory::{Filter, PublicRoomJoinRule},
+    directory::{Filter, PublicRoomJoinRule, RoomTypeFilter},
+    room::RoomType,
     OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId,
 };
 
@@ -47,6 +48,8 @@ pub struct RoomDescription {
     pub is_world_readable: bool,
     /// The number of members that have joined the room.
     pub joined_members: u64,
+    /// The type of the room, if any, for example [`RoomType::Space`].
+    pub room_type: Option<RoomType>,
 }
 
 impl From<ruma::directory::PublicRoomsChunk> for RoomDescription {
@@ -60,6 +63,7 @@ impl From<ruma::directory::PublicRoomsChunk> for RoomDescription {
             join_rule: value.join_rule,
             is_world_readable: value.world_readable,
             joined_members: value.num_joined_members.into(),
+            room_type: value.room_type,
         }
     }
 }
@@ -119,6 +123,7 @@ pub struct RoomDirectorySearch {
     batch_size: u32,
     filter: Option<String>,
     server: Option<OwnedServerName>,
+    room_types: Vec<RoomTypeFilter>,
     search_state: SearchState,
     client: Client,
     results: ObservableVector<RoomDescription>,
@@ -131,6 +136,7 @@ impl RoomDirectorySearch {
             batch_size: 0,
             filter: None,
             server: None,
+            room_types: Vec::new(),
             search_state: Default::default(),
             client,
             results: ObservableVector::new(),
@@ -163,6 +169,16 @@ impl RoomDirectorySearch {
         self.next_page().await
     }
 
+    /// Only search for the rooms of the given types, for example to find
+    /// public spaces with [`RoomTypeFilter::Space`].
+    ///
+    /// An empty list, the default, doesn't restrict the results. This should be
+    /// called before [`RoomDirectorySearch::search()`], since it applies to all
+    /// the following requests.
+    pub fn set_room_types(&mut self, room_types: Vec<RoomTypeFilter>) {
+        self.room_types = room_types;
+    }
+
     /// Asks the server for the next page of the current search.
     // Should never be used concurrently with another `next_page` or a
     // `search`.
@@ -173,6 +189,7 @@ impl RoomDirectorySearch {
 
         let mut filter = Filter::new();
         filter.generic_search_term = self.filter.clone();
+        filter.room_types = self.room_types.clone();
 
         let mut request = PublicRoomsFilterRequest::new();
         request.filter = filter;
@@ -282,6 +299,7 @@ mod tests {
             join_rule: ruma::directory::PublicRoomJoinRule::Public,
             is_world_readable: true,
             joined_members: 37,
+            room_type: None,
         }
     }
 
@@ -295,6 +313,7 @@ mod tests {
             join_rule: ruma::directory::PublicRoomJoinRule::Knock,
             is_world_readable: false,
             joined_members: 20,
+            room_type: None,
         }
     }
 
@@ -17,16 +17,25 @@
 //!
 //! This offers a few capabilities for previewing the content of the room as
 //! well.
+//!
+//! The previews fetched with [`Client::get_room_preview()`] are cached in
+//! memory, and can be observed with [`Client::subscribe_to_room_preview()`],
+//! so a screen showing an invite or a room link can be rendered immediately
+//! with the last known preview while it's refreshed.
+
+use std::{collections::HashMap, sync::Mutex as StdMutex};
 
+use eyeball::SharedObservable;
 use futures_util::future::join_all;
 use matrix_sdk_base::{RoomHero, RoomInfo, RoomState};
 use ruma::{
-    api::client::{membership::joined_members, state::get_state_events},
+    api::client::{membership::joined_members, space::get_hierarchy, state::get_state_events},
     directory::PublicRoomJoinRule,
     events::room::{history_visibility::HistoryVisibility, join_rules::JoinRule},
     room::RoomType,
     space::SpaceRoomJoinRule,
-    OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, RoomId, RoomOrAliasId, ServerName,
+    uint, OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, OwnedServerName, RoomId, RoomOrAliasId,
+    ServerName,
 };
 use tokio::try_join;
 use tracing::{instrument, warn};
@@ -80,6 +89,9 @@ pub struct RoomPreview {
 
     /// Room heroes.
     pub heroes: Option<Vec<RoomHero>>,
+
+    /// Whether the room is encrypted, if known.
+    pub is_encrypted: Option<bool>,
 }
 
 impl RoomPreview {
@@ -123,6 +135,10 @@ impl RoomPreview {
             state,
             is_direct,
             heroes: Some(room_info.heroes().to_vec()),
+            is_encrypted: {
+                let encryption_state = room_info.encryption_state();
+                (!encryption_state.is_unknown()).then(|| encryption_state.is_encrypted())
+            },
         }
     }
 
@@ -163,6 +179,15 @@ impl RoomPreview {
             }
         }
 
+        // The hierarchy of a room contains a summary of the room itself.
+        match Self::from_room_hierarchy(client, &room_id).await {
+            Ok(Some(res)) => return Ok(res),
+            Ok(None) => warn!("Room '{room_or_alias_id}' not found in its own hierarchy."),
+            Err(err) => {
+                warn!("error when previewing room from the room hierarchy endpoint: {err}");
+            }
+        }
+
         // Try room directory search next.
         match Self::from_room_directory_search(client, &room_id, room_or_alias_id, via).await {
             Ok(Some(res)) => return Ok(res),
@@ -292,9 +317,53 @@ impl RoomPreview {
             state,
             is_direct,
             heroes: cached_room.map(|r| r.heroes()),
+            // Servers only include the encryption algorithm if the room is encrypted, but it's
+            // optional, so its absence doesn't mean that the room isn't encrypted.
+            is_encrypted: response.encryption.is_some().then_some(true),
         })
     }
 
+    /// Get a [`RoomPreview`] using the first entry of the hierarchy of the
+    /// room, which describes the room itself.
+    ///
+    /// This works for rooms the user has joined, or that are public or
+    /// world-readable.
+    async fn from_room_hierarchy(client: &Client, room_id: &RoomId) -> crate::Result<Option<Self>> {
+        let mut request = get_hierarchy::v1::Request::new(room_id.to_owned());
+        request.max_depth = Some(uint!(0));
+        request.limit = Some(uint!(1));
+
+        let response = client.send(request).await?;
+
+        let Some(chunk) = response.rooms.into_iter().find(|chunk| chunk.room_id == room_id) else {
+            return Ok(None);
+        };
+
+        let cached_room = client.get_room(room_id);
+        let is_direct = if let Some(cached_room) = &cached_room {
+            cached_room.is_direct().await.ok()
+        } else {
+            None
+        };
+
+        Ok(Some(RoomPreview {
+            room_id: chunk.room_id,
+            canonical_alias: chunk.canonical_alias,
+            name: chunk.name,
+            topic: chunk.topic,
+            avatar_url: chunk.avatar_url,
+            num_joined_members: chunk.num_joined_members.into(),
+            num_active_members: cached_room.as_ref().map(|r| r.active_members_count()),
+            room_type: chunk.room_type,
+            join_rule: chunk.join_rule,
+            is_world_readable: Some(chunk.world_readable),
+            state: cached_room.as_ref().map(|r| r.state()),
+            is_direct,
+            heroes: cached_room.map(|r| r.heroes()),
+            is_encrypted: None,
+        }))
+    }
+
     /// Get a [`RoomPreview`] using the room state endpoint.
     ///
     /// This is always available on a remote server, but will only work if one
@@ -320,6 +389,8 @@ impl RoomPreview {
         let num_joined_members = joined_members.joined.len().try_into().unwrap_or(u64::MAX);
 
         let mut room_info = RoomInfo::new(room_id, RoomState::Joined);
+        // The whole state was fetched, so the encryption state is known.
+        room_info.mark_encryption_state_synced();
 
         for ev in state.room_state {
             let ev = match ev.deserialize() {
@@ -387,12 +458,96 @@ async fn search_for_room_preview_in_room_directory(
             state: None,
             is_direct: None,
             heroes: None,
+            is_encrypted: None,
         }));
     }
 
     Ok(None)
 }
 
+/// The maximum number of room previews kept in memory.
+///
+/// When the cache is full, the preview that is not observed and was set the
+/// longest time ago is evicted.
+const MAX_CACHED_ROOM_PREVIEWS: usize = 100;
+
+/// A room preview in the [`RoomPreviewCache`].
+struct CachedRoomPreview {
+    observable: SharedObservable<Option<RoomPreview>>,
+
+    /// When the preview was last set, relative to the other previews.
+    last_set: u64,
+}
+
+/// The room previews in the [`RoomPreviewCache`].
+#[derive(Default)]
+struct CachedRoomPreviews {
+    previews: HashMap<OwnedRoomId, CachedRoomPreview>,
+
+    /// The number of times a preview was set.
+    set_count: u64,
+}
+
+/// The in-memory cache of the room previews, shared by all the clones of a
+/// [`Client`].
+#[derive(Default)]
+pub(crate) struct RoomPreviewCache {
+    previews: StdMutex<CachedRoomPreviews>,
+}
+
+impl RoomPreviewCache {
+    /// Get the cached preview of the given room, if any.
+    pub(crate) fn get(&self, room_id: &RoomId) -> Option<RoomPreview> {
+        self.previews.lock().unwrap().previews.get(room_id)?.observable.get()
+    }
+
+    /// Get the observable preview of the given room, creating it if needed.
+    pub(crate) fn observable(&self, room_id: &RoomId) -> SharedObservable<Option<RoomPreview>> {
+        let mut previews = self.previews.lock().unwrap();
+        Self::cached_preview(&mut previews, room_id).observable.clone()
+    }
+
+    /// Set the preview of its room.
+    pub(crate) fn set(&self, preview: RoomPreview) {
+        let mut previews = self.previews.lock().unwrap();
+        previews.set_count += 1;
+        let set_count = previews.set_count;
+
+        let cached = Self::cached_preview(&mut previews, &preview.room_id);
+        cached.last_set = set_count;
+        cached.observable.set(Some(preview));
+    }
+
+    /// Get the cached preview of the given room, creating it if needed.
+    ///
+    /// If the cache is full, the preview that is not observed and was set the
+    /// longest time ago is evicted first.
+    fn cached_preview<'a>(
+        previews: &'a mut CachedRoomPreviews,
+        room_id: &RoomId,
+    ) -> &'a mut CachedRoomPreview {
+        if previews.previews.len() >= MAX_CACHED_ROOM_PREVIEWS
+            && !previews.previews.contains_key(room_id)
+        {
+            let evicted = previews
+                .previews
+                .iter()
+                .filter(|(_, cached)| cached.observable.subscriber_count() == 0)
+                .min_by_key(|(_, cached)| cached.last_set)
+                .map(|(room_id, _)| room_id.clone());
+
+            if let Some(evicted) = evicted {
+                previews.previews.remove(&evicted);
+            }
+        }
+
+        previews.previews.entry(room_id.to_owned()).or_insert_with(|| CachedRoomPreview {
+            observable: SharedObservable::new(None),
+            last_set: 0,
+        })
+    }
+}
+
 // Make sure the server name of the room id/alias is
 // included in the list of server names to send if no server names are provided
 fn ensure_server_names_is_not_empty(
@@ -413,10 +568,56 @@ fn ensure_server_names_is_not_empty(
 
 #[cfg(test)]
 mod tests {
-    use ruma::{owned_server_name, room_alias_id, room_id, server_name, RoomOrAliasId, ServerName};
+    use ruma::{
+        owned_server_name, room_alias_id, room_id, server_name, space::SpaceRoomJoinRule, RoomId,
+        RoomOrAliasId, ServerName,
+    };
 
+    use super::{RoomPreview, RoomPreviewCache, MAX_CACHED_ROOM_PREVIEWS};
     use crate::room_preview::ensure_server_names_is_not_empty;
 
+    fn preview(room_id: &RoomId) -> RoomPreview {
+        RoomPreview {
+            room_id: room_id.to_owned(),
+            canonical_alias: None,
+            name: None,
+            topic: None,
+            avatar_url: None,
+            num_joined_members: 0,
+            num_active_members: None,
+            room_type: None,
+            join_rule: SpaceRoomJoinRule::Public,
+            is_world_readable: None,
+            state: None,
+            is_direct: None,
+            heroes: None,
+            is_encrypted: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_evicts_the_oldest_unobserved_preview() {
+        let cache = RoomPreviewCache::default();
+
+        // The oldest preview is observed, so it is kept.
+        let observed = room_id!("!observed:b.c");
+        cache.set(preview(observed));
+        let _subscriber = cache.observable(observed).subscribe();
+
+        for i in 1..MAX_CACHED_ROOM_PREVIEWS {
+            let room_id = RoomId::parse(format!("!room{i}:b.c")).unwrap();
+            cache.set(preview(&room_id));
+        }
+
+        cache.set(preview(room_id!("!new:b.c")));
+
+        assert_eq!(cache.previews.lock().unwrap().previews.len(), MAX_CACHED_ROOM_PREVIEWS);
+        assert!(cache.get(observed).is_some());
+        assert!(cache.get(room_id!("!room1:b.c")).is_none());
+        assert!(cache.get(room_id!("!room2:b.c")).is_some());
+        assert!(cache.get(room_id!("!new:b.c")).is_some());
+    }
+
     #[test]
     fn test_ensure_server_names_is_not_empty_when_no_own_server_name_is_provided() {
         let own_server_name: Option<&ServerName> = None;
@@ -652,6 +652,9 @@ impl RoomSendQueue {
                         }
                     }
 
+                    #[cfg(feature = "opentelemetry")]
+                    crate::telemetry::record_send_failure(is_recoverable);
+
                     let error = Arc::new(err);
 
                     let _ = global_error_reporter.send(SendQueueRoomError {
@@ -672,6 +675,38 @@ impl RoomSendQueue {
         info!("exited sending task");
     }
 
+    /// Queue the key of a reaction that has been sent to be recorded in the
+    /// recently used emojis of the account.
+    fn record_recent_emoji(room: &Room, event: &Raw<AnyMessageLikeEventContent>) {
+        let key = match event.deserialize_as::<ReactionEventContent>() {
+            Ok(content) => content.relates_to.key,
+            Err(err) => {
+                warn!("couldn't deserialize the sent reaction: {err}");
+                return;
+            }
+        };
+
+        room.client().account().queue_recent_emoji(key);
+    }
+
+    /// Queue a message that has been sent in a direct message room to be
+    /// recorded in the frequent contacts of the account.
+    fn record_frequent_contacts(room: &Room) {
+        let own_user_id = room.own_user_id();
+        let direct_targets = room.direct_targets();
+        let user_ids = direct_targets
+            .iter()
+            .filter_map(|target| target.as_user_id())
+            .filter(|user_id| *user_id != own_user_id)
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        if user_ids.is_empty() {
+            return;
+        }
+
+        room.client().account().queue_frequent_contacts(user_ids);
+    }
+
     /// Handles a single request and returns the [`SentRequestKey`] on success
     /// (unless the request was cancelled, in which case it'll return
     /// `None`).
@@ -691,6 +726,13 @@ impl RoomSendQueue {
                     .await?;
 
                 trace!(txn_id = %request.transaction_id, event_id = %res.event_id, "event successfully sent");
+
+                match event_type {
+                    "m.reaction" => Self::record_recent_emoji(room, event),
+                    "m.room.message" => Self::record_frequent_contacts(room),
+                    _ => {}
+                }
+
                 Ok(Some(SentRequestKey::Event(res.event_id)))
             }
 
@@ -1847,6 +1889,11 @@ pub enum RoomSendQueueError {
     /// The attachment event failed to be created.
     #[error("the attachment event could not be created")]
     FailedToCreateAttachment,
+
+    /// The file of the attachment could not be prepared for the upload with
+    /// its `UploadConfig`, for example because it is too large.
+    #[error("the attachment could not be prepared for the upload: {0}")]
+    FailedToPrepareUpload(Box<crate::Error>),
 }
 
 /// An error triggered by the send queue storage.
@@ -119,6 +119,17 @@ impl RoomSendQueue {
             return Err(RoomSendQueueError::RoomNotJoined);
         }
 
+        let (content_type, data) = room
+            .client()
+            .media()
+            .prepare_attachment_upload(content_type, data, &mut config)
+            .await
+            .map_err(|error| RoomSendQueueError::FailedToPrepareUpload(Box::new(error)))?;
+
+        #[cfg(feature = "image-proc")]
+        let (mut config, data) =
+            config.with_generated_thumbnail_and_info(content_type.clone(), data).await;
+
         let filename = filename.into();
         let upload_file_txn = TransactionId::new();
         let send_event_txn = config.txn_id.map_or_else(ChildTransactionId::new, Into::into);
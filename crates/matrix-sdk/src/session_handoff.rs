@@ -0,0 +1,345 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hand a logged-in session over to another process.
+//!
+//! [`Client::serialize_session()`] captures everything another process needs
+//! to pick up the session where this client left it: the authentication
+//! tokens, the homeserver, the location of the stores and the positions of the
+//! sliding syncs. It's sealed with a passphrase in a single blob, that can be
+//! passed to a share extension or a background worker, and opened there with
+//! [`Client::restore_from_session()`].
+//!
+//! Both processes open the same stores, so they must use different
+//! [cross-process store locks holder names][holder].
+//!
+//! [holder]: crate::ClientBuilder::cross_process_store_locks_holder_name
+
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use matrix_sdk_base::store::StoreEncryptionError;
+use matrix_sdk_store_encryption::StoreCipher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    authentication::{
+        matrix::MatrixSession,
+        oauth::{ClientId, OAuthSession, UserSession},
+        AuthSession,
+    },
+    sliding_sync::{Version as SlidingSyncVersion, VersionBuilder as SlidingSyncVersionBuilder},
+    Client, ClientBuildError, ClientBuilder,
+};
+
+/// The version of the format of the sealed blob.
+const SESSION_HANDOFF_VERSION: u8 = 1;
+
+/// An error which can happen when handing a session over.
+#[derive(Debug, thiserror::Error)]
+pub enum SessionHandoffError {
+    /// The client isn't logged in, there is no session to hand over.
+    #[error("the client isn't logged in")]
+    NotLoggedIn,
+
+    /// The blob couldn't be sealed or opened, for example because the
+    /// passphrase is wrong.
+    #[error(transparent)]
+    Encryption(#[from] StoreEncryptionError),
+
+    /// The blob couldn't be (de)serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The blob was created by an unsupported version of the SDK.
+    #[error("unsupported session handoff version: {0}")]
+    UnsupportedVersion(u8),
+
+    /// The stores of the session can't be opened, because the required
+    /// feature of the SDK isn't enabled.
+    #[error("the {0} stores of the session can't be opened in this build")]
+    UnsupportedStore(&'static str),
+
+    /// The client couldn't be built.
+    #[error(transparent)]
+    ClientBuild(#[from] ClientBuildError),
+
+    /// The session couldn't be restored.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+}
+
+/// The location of the stores of a client, so that another process can open
+/// them.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum SessionStoreLocation {
+    /// The SQLite stores.
+    Sqlite { path: PathBuf, cache_path: Option<PathBuf>, passphrase: Option<String> },
+
+    /// The IndexedDB stores.
+    IndexedDb { name: String, passphrase: Option<String> },
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SessionStoreLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlite { path, cache_path, .. } => f
+                .debug_struct("Sqlite")
+                .field("path", path)
+                .field("cache_path", cache_path)
+                .finish_non_exhaustive(),
+            Self::IndexedDb { name, .. } => {
+                f.debug_struct("IndexedDb").field("name", name).finish_non_exhaustive()
+            }
+        }
+    }
+}
+
+impl SessionStoreLocation {
+    /// Configure the builder to open the stores at this location.
+    fn apply(self, builder: ClientBuilder) -> Result<ClientBuilder, SessionHandoffError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite { path, cache_path, passphrase } => Ok(match cache_path {
+                Some(cache_path) => {
+                    builder.sqlite_store_with_cache_path(path, cache_path, passphrase.as_deref())
+                }
+                None => builder.sqlite_store(path, passphrase.as_deref()),
+            }),
+            #[cfg(not(feature = "sqlite"))]
+            Self::Sqlite { .. } => {
+                let _ = builder;
+                Err(SessionHandoffError::UnsupportedStore("SQLite"))
+            }
+
+            #[cfg(feature = "indexeddb")]
+            Self::IndexedDb { name, passphrase } => {
+                Ok(builder.indexeddb_store(&name, passphrase.as_deref()))
+            }
+            #[cfg(not(feature = "indexeddb"))]
+            Self::IndexedDb { .. } => {
+                let _ = builder;
+                Err(SessionHandoffError::UnsupportedStore("IndexedDB"))
+            }
+        }
+    }
+}
+
+/// The authentication session, in a serializable form.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "api", rename_all = "snake_case")]
+enum HandoffAuthSession {
+    Matrix(MatrixSession),
+    #[serde(rename = "oauth")]
+    OAuth {
+        client_id: String,
+        user: UserSession,
+    },
+}
+
+impl From<AuthSession> for HandoffAuthSession {
+    fn from(session: AuthSession) -> Self {
+        match session {
+            AuthSession::Matrix(session) => Self::Matrix(session),
+            AuthSession::OAuth(session) => {
+                Self::OAuth { client_id: session.client_id.as_str().to_owned(), user: session.user }
+            }
+        }
+    }
+}
+
+impl From<HandoffAuthSession> for AuthSession {
+    fn from(session: HandoffAuthSession) -> Self {
+        match session {
+            HandoffAuthSession::Matrix(session) => Self::Matrix(session),
+            HandoffAuthSession::OAuth { client_id, user } => {
+                Self::OAuth(Box::new(OAuthSession { client_id: ClientId::new(client_id), user }))
+            }
+        }
+    }
+}
+
+/// Everything needed to restore a session in another process.
+#[derive(Serialize, Deserialize)]
+struct SessionHandoff {
+    homeserver: String,
+    native_sliding_sync: bool,
+    session: HandoffAuthSession,
+    store: Option<SessionStoreLocation>,
+    sliding_sync_positions: BTreeMap<String, String>,
+}
+
+/// The sealed blob.
+#[derive(Serialize, Deserialize)]
+struct SealedSessionHandoff {
+    version: u8,
+    /// The cipher, exported with the passphrase.
+    cipher: Vec<u8>,
+    /// The [`SessionHandoff`], encrypted with the cipher.
+    payload: Vec<u8>,
+}
+
+impl Client {
+    /// Serialize the session of this client in a blob sealed with the given
+    /// passphrase, to hand it over to another process.
+    ///
+    /// The blob contains the authentication tokens, the homeserver URL, the
+    /// location of the stores and their passphrase, and the positions of the
+    /// sliding syncs with the given IDs, if they're shared across processes.
+    /// See the [module documentation](crate::session_handoff) for more
+    /// details.
+    ///
+    /// The stores are only included if the client was built with
+    /// [`ClientBuilder::sqlite_store()`] or a similar method. Other options,
+    /// like the SQLCipher key, must be set on the builder passed to
+    /// [`Client::restore_from_session()`].
+    pub async fn serialize_session(
+        &self,
+        passphrase: &str,
+        sliding_sync_ids: &[&str],
+    ) -> Result<Vec<u8>, SessionHandoffError> {
+        let session = self.session().ok_or(SessionHandoffError::NotLoggedIn)?;
+
+        #[cfg(feature = "e2e-encryption")]
+        let sliding_sync_positions = {
+            let mut positions = BTreeMap::new();
+            for id in sliding_sync_ids {
+                if let Some(pos) = crate::sliding_sync::load_shared_pos(self, id).await? {
+                    positions.insert((*id).to_owned(), pos);
+                }
+            }
+            positions
+        };
+
+        #[cfg(not(feature = "e2e-encryption"))]
+        let sliding_sync_positions = {
+            let _ = sliding_sync_ids;
+            BTreeMap::new()
+        };
+
+        let handoff = SessionHandoff {
+            homeserver: self.homeserver().to_string(),
+            native_sliding_sync: matches!(self.sliding_sync_version(), SlidingSyncVersion::Native),
+            session: session.into(),
+            store: self.inner.session_store_location.get().cloned(),
+            sliding_sync_positions,
+        };
+
+        let cipher = StoreCipher::new()?;
+        let sealed = SealedSessionHandoff {
+            version: SESSION_HANDOFF_VERSION,
+            cipher: cipher.export(passphrase)?,
+            payload: cipher.encrypt_value(&handoff)?,
+        };
+
+        Ok(serde_json::to_vec(&sealed)?)
+    }
+
+    /// Build a client from a blob created by [`Client::serialize_session()`],
+    /// and restore its session.
+    ///
+    /// The homeserver, the sliding sync version and the stores of the builder
+    /// are replaced by the ones in the blob; everything else is configured
+    /// from the builder, which should at least have its own
+    /// [cross-process store locks holder name][holder].
+    ///
+    /// [holder]: ClientBuilder::cross_process_store_locks_holder_name
+    pub async fn restore_from_session(
+        builder: ClientBuilder,
+        blob: &[u8],
+        passphrase: &str,
+    ) -> Result<Client, SessionHandoffError> {
+        let sealed: SealedSessionHandoff = serde_json::from_slice(blob)?;
+
+        if sealed.version != SESSION_HANDOFF_VERSION {
+            return Err(SessionHandoffError::UnsupportedVersion(sealed.version));
+        }
+
+        let cipher = StoreCipher::import(passphrase, &sealed.cipher)?;
+        let handoff: SessionHandoff = cipher.decrypt_value(&sealed.payload)?;
+
+        let mut builder = builder.homeserver_url(&handoff.homeserver).sliding_sync_version_builder(
+            if handoff.native_sliding_sync {
+                SlidingSyncVersionBuilder::Native
+            } else {
+                SlidingSyncVersionBuilder::None
+            },
+        );
+
+        if let Some(store) = handoff.store {
+            builder = store.apply(builder)?;
+        }
+
+        let client = builder.build().await?;
+        client.restore_session(AuthSession::from(handoff.session)).await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        for (id, pos) in handoff.sliding_sync_positions {
+            crate::sliding_sync::save_shared_pos(&client, &id, pos).await?;
+        }
+
+        Ok(client)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches2::assert_matches;
+    use matrix_sdk_test::async_test;
+
+    use super::SessionHandoffError;
+    use crate::{test_utils::mocks::MatrixMockServer, Client};
+
+    #[async_test]
+    async fn test_session_round_trip() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let blob = client.serialize_session("passphrase", &[]).await.unwrap();
+
+        let restored = Client::restore_from_session(
+            Client::builder().cross_process_store_locks_holder_name("worker".to_owned()),
+            &blob,
+            "passphrase",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(restored.homeserver(), client.homeserver());
+        assert_eq!(restored.user_id(), client.user_id());
+        assert_eq!(restored.device_id(), client.device_id());
+        assert_eq!(restored.access_token(), client.access_token());
+    }
+
+    #[async_test]
+    async fn test_wrong_passphrase() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        let blob = client.serialize_session("passphrase", &[]).await.unwrap();
+
+        let result = Client::restore_from_session(Client::builder(), &blob, "wrong").await;
+        assert_matches!(result, Err(SessionHandoffError::Encryption(_)));
+    }
+
+    #[async_test]
+    async fn test_not_logged_in() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().unlogged().build().await;
+
+        let result = client.serialize_session("passphrase", &[]).await;
+        assert_matches!(result, Err(SessionHandoffError::NotLoggedIn));
+    }
+}
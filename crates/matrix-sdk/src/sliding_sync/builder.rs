@@ -9,6 +9,8 @@ use matrix_sdk_common::timer;
 use ruma::{api::client::sync::sync_events::v5 as http, OwnedRoomId};
 use tokio::sync::{broadcast::channel, Mutex as AsyncMutex, RwLock as AsyncRwLock};
 
+#[cfg(feature = "e2e-encryption")]
+use super::to_device_gap::ToDeviceGapDetector;
 use super::{
     cache::{format_storage_key_prefix, restore_sliding_sync_state},
     sticky_parameters::SlidingSyncStickyManager,
@@ -267,6 +269,7 @@ impl SlidingSyncBuilder {
 
         let rooms = AsyncRwLock::new(rooms);
         let lists = AsyncRwLock::new(lists);
+        let bandwidth_profile = StdRwLock::new(client.bandwidth_profile());
 
         Ok(SlidingSync::new(SlidingSyncInner {
             id: self.id,
@@ -286,9 +289,13 @@ impl SlidingSyncBuilder {
                     self.extensions.unwrap_or_default(),
                 ),
             )),
+            bandwidth_profile,
 
             internal_channel: internal_channel_sender,
 
+            #[cfg(feature = "e2e-encryption")]
+            to_device_gaps: ToDeviceGapDetector::new(),
+
             poll_timeout: self.poll_timeout,
             network_timeout: self.network_timeout,
         }))
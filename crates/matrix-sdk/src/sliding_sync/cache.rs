@@ -133,6 +133,47 @@ pub(super) async fn store_sliding_sync_state(
     Ok(())
 }
 
+/// Read the `pos` of the sliding sync with the given ID, as shared across
+/// processes.
+#[cfg(feature = "e2e-encryption")]
+pub(crate) async fn load_shared_pos(client: &Client, id: &str) -> Result<Option<String>> {
+    let Some(user_id) = client.user_id() else {
+        return Ok(None);
+    };
+    let Some(olm_machine) = &*client.olm_machine().await else {
+        return Ok(None);
+    };
+
+    let storage_key = format_storage_key_for_sliding_sync(&format_storage_key_prefix(id, user_id));
+
+    Ok(olm_machine
+        .store()
+        .get_custom_value(&storage_key)
+        .await?
+        .and_then(|blob| serde_json::from_slice::<FrozenSlidingSyncPos>(&blob).ok())
+        .and_then(|frozen_pos| frozen_pos.pos))
+}
+
+/// Write the `pos` of the sliding sync with the given ID, so that it's shared
+/// across processes.
+///
+/// This has no effect if the client isn't logged in.
+#[cfg(feature = "e2e-encryption")]
+pub(crate) async fn save_shared_pos(client: &Client, id: &str, pos: String) -> Result<()> {
+    let Some(user_id) = client.user_id() else {
+        return Ok(());
+    };
+    let Some(olm_machine) = &*client.olm_machine().await else {
+        return Ok(());
+    };
+
+    let storage_key = format_storage_key_for_sliding_sync(&format_storage_key_prefix(id, user_id));
+    let pos_blob = serde_json::to_vec(&FrozenSlidingSyncPos { pos: Some(pos) })?;
+    olm_machine.store().set_custom_value(&storage_key, pos_blob).await?;
+
+    Ok(())
+}
+
 /// Try to restore a single [`SlidingSyncList`] from the cache.
 ///
 /// If it fails to deserialize for some reason, invalidate the cache entry.
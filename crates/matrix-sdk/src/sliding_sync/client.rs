@@ -11,7 +11,12 @@ use ruma::{
 use tracing::error;
 
 use super::{SlidingSync, SlidingSyncBuilder};
-use crate::{Client, Result, SlidingSyncRoom};
+use crate::{
+    client::homeserver_capabilities::{
+        is_unstable_feature_enabled, SIMPLIFIED_SLIDING_SYNC_FEATURE,
+    },
+    Client, Result, SlidingSyncRoom,
+};
 
 /// A sliding sync version.
 #[derive(Clone, Debug)]
@@ -87,9 +92,13 @@ impl VersionBuilder {
                     return Err(VersionBuilderError::MissingVersionsResponse);
                 };
 
-                match versions.unstable_features.get("org.matrix.simplified_msc3575") {
-                    Some(value) if *value => Version::Native,
-                    _ => return Err(VersionBuilderError::NativeVersionIsUnset),
+                if is_unstable_feature_enabled(
+                    &versions.unstable_features,
+                    SIMPLIFIED_SLIDING_SYNC_FEATURE,
+                ) {
+                    Version::Native
+                } else {
+                    return Err(VersionBuilderError::NativeVersionIsUnset);
                 }
             }
         })
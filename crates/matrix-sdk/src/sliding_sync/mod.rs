@@ -22,12 +22,15 @@ mod error;
 mod list;
 mod room;
 mod sticky_parameters;
+#[cfg(feature = "e2e-encryption")]
+mod to_device_gap;
 mod utils;
 
 use std::{
     collections::{btree_map::Entry, BTreeMap},
     fmt::Debug,
     future::Future,
+    mem,
     sync::{Arc, RwLock as StdRwLock},
     time::Duration,
 };
@@ -48,6 +51,10 @@ use tokio::{
 };
 use tracing::{debug, error, info, instrument, trace, warn, Instrument, Span};
 
+#[cfg(feature = "e2e-encryption")]
+pub(crate) use self::cache::{load_shared_pos, save_shared_pos};
+#[cfg(feature = "e2e-encryption")]
+pub use self::to_device_gap::ToDeviceGap;
 #[cfg(feature = "e2e-encryption")]
 use self::utils::JoinHandleExt as _;
 pub use self::{builder::*, client::VersionBuilderError, error::*, list::*, room::*};
@@ -56,7 +63,7 @@ use self::{
     client::SlidingSyncResponseProcessor,
     sticky_parameters::{LazyTransactionId, SlidingSyncStickyManager, StickyData},
 };
-use crate::{config::RequestConfig, Client, Result};
+use crate::{bandwidth::BandwidthProfile, config::RequestConfig, Client, Result};
 
 /// The Sliding Sync instance.
 ///
@@ -118,9 +125,20 @@ pub(super) struct SlidingSyncInner {
     /// Request parameters that are sticky.
     sticky: StdRwLock<SlidingSyncStickyManager<SlidingSyncStickyParameters>>,
 
+    /// The bandwidth profile of the client when the last request was
+    /// generated.
+    ///
+    /// The timeline limits depend on it, so the sticky parameters are sent
+    /// again when it changes.
+    bandwidth_profile: StdRwLock<BandwidthProfile>,
+
     /// Internal channel used to pass messages between Sliding Sync and other
     /// types.
     internal_channel: Sender<SlidingSyncInternalMessage>,
+
+    /// Detects gaps in the to-device message stream.
+    #[cfg(feature = "e2e-encryption")]
+    to_device_gaps: to_device_gap::ToDeviceGapDetector,
 }
 
 impl SlidingSync {
@@ -180,6 +198,18 @@ impl SlidingSync {
         }
     }
 
+    /// Subscribe to the gaps detected in the to-device message stream.
+    ///
+    /// A gap means that to-device messages, notably room keys, may have been
+    /// lost, e.g. because the to-device token couldn't be restored. The
+    /// client automatically requests the room keys for the affected events
+    /// it sees, but some events may still fail to decrypt, and apps may want
+    /// to inform the user about it.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn subscribe_to_to_device_gaps(&self) -> tokio::sync::broadcast::Receiver<ToDeviceGap> {
+        self.inner.to_device_gaps.subscribe()
+    }
+
     /// Lookup a specific room
     pub async fn get_room(&self, room_id: &RoomId) -> Option<SlidingSyncRoom> {
         self.inner.rooms.read().await.get(room_id).cloned()
@@ -269,6 +299,9 @@ impl SlidingSync {
         requested_required_states: RequestedRequiredStates,
     ) -> Result<UpdateSummary, crate::Error> {
         let pos = Some(sliding_sync_response.pos.clone());
+        #[cfg(feature = "e2e-encryption")]
+        let to_device_token =
+            sliding_sync_response.extensions.to_device.as_ref().map(|t| t.next_batch.clone());
 
         let must_process_rooms_response = self.must_process_rooms_response().await;
 
@@ -308,6 +341,14 @@ impl SlidingSync {
 
         debug!(?sync_response, "Sliding Sync response has been handled by the client");
 
+        #[cfg(feature = "e2e-encryption")]
+        if self.is_e2ee_enabled() {
+            self.inner
+                .to_device_gaps
+                .handle_response(&self.inner.client, to_device_token, &sync_response)
+                .await;
+        }
+
         // Commit sticky parameters, if needed.
         if let Some(ref txn_id) = sliding_sync_response.txn_id {
             let txn_id = txn_id.as_str().into();
@@ -424,6 +465,23 @@ impl SlidingSync {
         let require_timeout = {
             let lists = self.inner.lists.read().await;
 
+            // The timeline limits of the sticky parameters depend on the bandwidth profile:
+            // send them again if it changed.
+            let bandwidth_profile = self.inner.client.bandwidth_profile();
+            let previous_bandwidth_profile = mem::replace(
+                &mut *self.inner.bandwidth_profile.write().unwrap(),
+                bandwidth_profile,
+            );
+
+            if previous_bandwidth_profile != bandwidth_profile {
+                debug!(
+                    ?bandwidth_profile,
+                    "Bandwidth profile changed; invalidating sticky parameters"
+                );
+                let _ = self.inner.sticky.write().unwrap().data_mut();
+                lists.values().for_each(|list| list.invalidate_sticky_data());
+            }
+
             // Start at `true` in case there is zero list.
             let mut require_timeout = true;
 
@@ -510,12 +568,40 @@ impl SlidingSync {
         // Apply sticky parameters, if needs be.
         self.inner.sticky.write().unwrap().maybe_apply(&mut request, txn_id);
 
+        // Reduce the timeline limits in the low bandwidth profile.
+        let bandwidth_settings = self.inner.client.bandwidth_settings();
+        if bandwidth_settings.max_timeline_limit.is_some() {
+            let timeline_limits =
+                request.lists.values_mut().map(|list| &mut list.room_details.timeline_limit).chain(
+                    request
+                        .room_subscriptions
+                        .values_mut()
+                        .map(|subscription| &mut subscription.timeline_limit),
+                );
+
+            for timeline_limit in timeline_limits {
+                let clamped = bandwidth_settings.clamp_timeline_limit(
+                    u32::try_from(u64::from(*timeline_limit)).unwrap_or(u32::MAX),
+                );
+                *timeline_limit = clamped.into();
+            }
+        }
+
         // Extensions are now applied (via sticky parameters).
         //
         // Override the to-device token if the extension is enabled.
         if to_device_enabled {
-            request.extensions.to_device.since =
-                restored_fields.and_then(|fields| fields.to_device_token);
+            let to_device_token = restored_fields.and_then(|fields| fields.to_device_token);
+
+            #[cfg(feature = "e2e-encryption")]
+            if self.is_e2ee_enabled() {
+                self.inner
+                    .to_device_gaps
+                    .check(&self.inner.client, to_device_token.as_deref())
+                    .await?;
+            }
+
+            request.extensions.to_device.since = to_device_token;
         }
 
         // Apply the transaction id if one was generated.
@@ -670,7 +756,7 @@ impl SlidingSync {
             || !self.inner.lists.read().await.is_empty()
     }
 
-    #[instrument(skip_all, fields(pos, conn_id = self.inner.id))]
+    #[instrument(name = "matrix_sdk.sync", skip_all, fields(pos, conn_id = self.inner.id))]
     async fn sync_once(&self) -> Result<UpdateSummary> {
         let (request, request_config, position_guard) =
             self.generate_sync_request(&mut LazyTransactionId::new()).await?;
@@ -698,6 +784,7 @@ impl SlidingSync {
         debug!("Starting sync stream");
 
         let mut internal_channel_receiver = self.inner.internal_channel.subscribe();
+        let mut client_stop_listener = self.inner.client.inner.sync_stop.listen();
 
         stream! {
             loop {
@@ -706,6 +793,11 @@ impl SlidingSync {
                 select! {
                     biased;
 
+                    _ = &mut client_stop_listener => {
+                        debug!("Sync stream has been stopped by the client");
+                        break;
+                    }
+
                     internal_message = internal_channel_receiver.recv() => {
                         use SlidingSyncInternalMessage::*;
 
@@ -994,7 +1086,8 @@ mod tests {
         SlidingSyncStickyParameters,
     };
     use crate::{
-        sliding_sync::cache::restore_sliding_sync_state, test_utils::logged_in_client, Result,
+        bandwidth::BandwidthProfile, sliding_sync::cache::restore_sliding_sync_state,
+        test_utils::logged_in_client, Result,
     };
 
     #[derive(Copy, Clone)]
@@ -1144,6 +1237,54 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_timeline_limits_in_low_bandwidth_profile() -> Result<()> {
+        let (_server, sliding_sync) = new_sliding_sync(vec![SlidingSyncList::builder("foo")
+            .sync_mode(SlidingSyncMode::new_selective().add_range(0..=10))
+            .timeline_limit(10)])
+        .await?;
+
+        let room_id = room_id!("!r0:bar.org");
+        sliding_sync.subscribe_to_rooms(
+            &[room_id],
+            Some(assign!(http::request::RoomSubscription::default(), {
+                timeline_limit: uint!(20),
+            })),
+            false,
+        );
+
+        sliding_sync.inner.client.set_bandwidth_profile(BandwidthProfile::LowBandwidth);
+
+        let (request, _, _) =
+            sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+
+        assert_eq!(request.lists["foo"].room_details.timeline_limit, uint!(1));
+        assert_eq!(request.room_subscriptions[room_id].timeline_limit, uint!(1));
+
+        // The server acknowledges the sticky parameters.
+        let txn_id: &TransactionId = request.txn_id.as_deref().unwrap().into();
+        sliding_sync.inner.sticky.write().unwrap().maybe_commit(txn_id);
+        sliding_sync
+            .inner
+            .lists
+            .write()
+            .await
+            .values_mut()
+            .for_each(|list| list.maybe_commit_sticky(txn_id));
+
+        // Going back to the normal profile sends the original limits again.
+        sliding_sync.inner.client.set_bandwidth_profile(BandwidthProfile::Normal);
+
+        let (request, _, _) =
+            sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+
+        assert!(request.txn_id.is_some());
+        assert_eq!(request.lists["foo"].room_details.timeline_limit, uint!(10));
+        assert_eq!(request.room_subscriptions[room_id].timeline_limit, uint!(20));
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_room_subscriptions_are_reset_when_session_expires() -> Result<()> {
         let (_server, sliding_sync) = new_sliding_sync(vec![SlidingSyncList::builder("foo")
@@ -2521,6 +2662,101 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    #[cfg(feature = "e2e-encryption")]
+    async fn test_to_device_gap_detection() -> Result<()> {
+        use tokio::sync::broadcast::error::TryRecvError;
+
+        use super::to_device_gap::TO_DEVICE_TOKEN_SEEN_KEY;
+
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let sliding_sync = client
+            .sliding_sync("test")?
+            .with_to_device_extension(
+                assign!(http::request::ToDevice::default(), { enabled: Some(true)}),
+            )
+            .with_e2ee_extension(assign!(http::request::E2EE::default(), { enabled: Some(true)}))
+            .build()
+            .await?;
+
+        let mut gaps = sliding_sync.subscribe_to_to_device_gaps();
+
+        // The very first request doesn't have a to-device token, which is fine.
+        let (request, _, _) =
+            sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+        assert!(request.extensions.to_device.since.is_none());
+        assert_matches!(gaps.try_recv(), Err(TryRecvError::Empty));
+
+        // Receiving a to-device token is recorded in the crypto store.
+        let server_response = assign!(http::Response::new("0".to_owned()), {
+            extensions: assign!(http::response::Extensions::default(), {
+                to_device: Some(assign!(http::response::ToDevice::default(), {
+                    next_batch: "to-device-token".to_owned(),
+                })),
+            })
+        });
+
+        {
+            let mut position_guard = sliding_sync.inner.position.clone().lock_owned().await;
+
+            sliding_sync
+                .handle_response(
+                    server_response,
+                    &mut position_guard,
+                    RequestedRequiredStates::default(),
+                )
+                .await?;
+        }
+
+        {
+            let olm_machine = &*client.olm_machine_for_testing().await;
+            let store = olm_machine.as_ref().unwrap().store();
+            assert!(store.get_custom_value(TO_DEVICE_TOKEN_SEEN_KEY).await?.is_some());
+        }
+
+        // The next request uses the token, so there's no gap.
+        let (request, _, _) =
+            sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+        assert_eq!(request.extensions.to_device.since.as_deref(), Some("to-device-token"));
+        assert_matches!(gaps.try_recv(), Err(TryRecvError::Empty));
+
+        // Now, a client which has received a to-device token before, but lost it.
+        let client = logged_in_client(Some(server.uri())).await;
+
+        {
+            let olm_machine = &*client.olm_machine_for_testing().await;
+            let store = olm_machine.as_ref().unwrap().store();
+            store.set_custom_value(TO_DEVICE_TOKEN_SEEN_KEY, vec![1]).await?;
+        }
+
+        let sliding_sync = client
+            .sliding_sync("test")?
+            .with_to_device_extension(
+                assign!(http::request::ToDevice::default(), { enabled: Some(true)}),
+            )
+            .with_e2ee_extension(assign!(http::request::E2EE::default(), { enabled: Some(true)}))
+            .build()
+            .await?;
+
+        let mut gaps = sliding_sync.subscribe_to_to_device_gaps();
+
+        // The gap is reported when building the request.
+        let (request, _, _) =
+            sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+        assert!(request.extensions.to_device.since.is_none());
+
+        let gap = gaps.try_recv().expect("the gap should have been reported");
+        assert!(gap.previous_token.is_none());
+
+        // It's only reported once while the recovery is pending.
+        sliding_sync.generate_sync_request(&mut LazyTransactionId::new()).await?;
+        assert_matches!(gaps.try_recv(), Err(TryRecvError::Empty));
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_lock_multiple_requests() -> Result<()> {
         let server = MockServer::start().await;
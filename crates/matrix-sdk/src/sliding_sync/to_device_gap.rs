@@ -0,0 +1,216 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! Detection of, and recovery from, gaps in the to-device message stream.
+//!
+//! The to-device extension is driven by a `since` token which is persisted in
+//! the crypto store. If that token gets lost, e.g. because the store got
+//! partially reset, the server can't know which to-device messages we already
+//! received, and messages sent in the meantime, notably room keys, might never
+//! reach us.
+//!
+//! We remember that we received a to-device token before, both in memory and
+//! in the crypto store, so such a loss can be detected when the next request
+//! is built. When it happens, a [`ToDeviceGap`] is broadcast and the client
+//! tries to limit the damage: device lists are marked as outdated, and room
+//! keys are requested from our other devices for the events which can't be
+//! decrypted in the first response after the gap.
+
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex as StdMutex,
+    },
+};
+
+use matrix_sdk_base::sync::SyncResponse;
+use matrix_sdk_common::deserialized_responses::TimelineEventKind;
+use ruma::MilliSecondsSinceUnixEpoch;
+use tokio::sync::broadcast::{Receiver, Sender};
+use tracing::{info, warn};
+
+use crate::{Client, Error, Result};
+
+/// Be careful: as this is used as a storage key; changing it requires migrating
+/// data!
+pub(super) const TO_DEVICE_TOKEN_SEEN_KEY: &str = "sliding_sync::to_device_token_seen";
+
+/// A gap in the to-device message stream has been detected.
+///
+/// Messages sent to this device during the gap might have been lost, so some
+/// events might fail to decrypt. The client already tried to recover the
+/// missing room keys, but apps may want to inform the user about it.
+#[derive(Clone, Debug)]
+pub struct ToDeviceGap {
+    /// The last to-device token this process received before the gap, if
+    /// any. `None` if the gap was detected right after a restart.
+    pub previous_token: Option<String>,
+
+    /// When the gap was detected.
+    pub detected_at: MilliSecondsSinceUnixEpoch,
+}
+
+#[derive(Debug)]
+pub(super) struct ToDeviceGapDetector {
+    /// The last to-device token received by this process.
+    last_token: StdMutex<Option<String>>,
+
+    /// Has this process already recorded in the crypto store that a to-device
+    /// token has been received?
+    token_seen_persisted: AtomicBool,
+
+    /// A gap has been detected and we still need to request the room keys
+    /// for the events of the next response.
+    recovery_pending: AtomicBool,
+
+    /// Sender for the detected gaps.
+    sender: Sender<ToDeviceGap>,
+}
+
+impl ToDeviceGapDetector {
+    pub fn new() -> Self {
+        Self {
+            last_token: StdMutex::new(None),
+            token_seen_persisted: AtomicBool::new(false),
+            recovery_pending: AtomicBool::new(false),
+            sender: Sender::new(8),
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<ToDeviceGap> {
+        self.sender.subscribe()
+    }
+
+    /// Check the to-device token which is about to be sent to the server.
+    ///
+    /// If no token is sent although we received one before, a gap is
+    /// reported and the recovery is started.
+    pub async fn check(&self, client: &Client, token: Option<&str>) -> Result<()> {
+        if token.is_some() || self.recovery_pending.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let previous_token = self.last_token.lock().unwrap().clone();
+
+        let olm_machine = client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let token_seen = previous_token.is_some()
+            || olm_machine.store().get_custom_value(TO_DEVICE_TOKEN_SEEN_KEY).await?.is_some();
+
+        if !token_seen {
+            // This is the very first to-device request, nothing can be missing.
+            return Ok(());
+        }
+
+        warn!(?previous_token, "The to-device token has been lost, some messages may be missing");
+
+        self.recovery_pending.store(true, Ordering::SeqCst);
+
+        // We might have missed device list updates as well.
+        olm_machine.mark_all_tracked_users_as_dirty().await?;
+
+        let _ = self
+            .sender
+            .send(ToDeviceGap { previous_token, detected_at: MilliSecondsSinceUnixEpoch::now() });
+
+        Ok(())
+    }
+
+    /// Remember the to-device token received in a response, and if a gap has
+    /// been detected, request the room keys for the events of this response
+    /// which couldn't be decrypted.
+    pub async fn handle_response(
+        &self,
+        client: &Client,
+        token: Option<String>,
+        sync_response: &SyncResponse,
+    ) {
+        if let Some(token) = token {
+            *self.last_token.lock().unwrap() = Some(token);
+
+            if !self.token_seen_persisted.swap(true, Ordering::SeqCst) {
+                if let Err(e) = self.persist_token_seen(client).await {
+                    warn!("Couldn't record that a to-device token has been received: {e}");
+                    self.token_seen_persisted.store(false, Ordering::SeqCst);
+                }
+            }
+        }
+
+        if self.recovery_pending.swap(false, Ordering::SeqCst) {
+            request_missing_room_keys(client, sync_response).await;
+        }
+    }
+
+    async fn persist_token_seen(&self, client: &Client) -> Result<()> {
+        let olm_machine = client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+        olm_machine.store().set_custom_value(TO_DEVICE_TOKEN_SEEN_KEY, vec![1]).await?;
+
+        Ok(())
+    }
+}
+
+/// Request the room keys of the sessions used by the events of the given
+/// response which couldn't be decrypted because of a missing room key.
+async fn request_missing_room_keys(client: &Client, sync_response: &SyncResponse) {
+    let olm_machine = client.olm_machine().await;
+    let Some(olm_machine) = olm_machine.as_ref() else { return };
+
+    let mut requests = Vec::new();
+    let mut requested_sessions = BTreeSet::new();
+
+    for (room_id, room) in &sync_response.rooms.joined {
+        for event in &room.timeline.events {
+            let TimelineEventKind::UnableToDecrypt { event, utd_info } = &event.kind else {
+                continue;
+            };
+
+            if !utd_info.reason.is_missing_room_key() {
+                continue;
+            }
+
+            let Some(session_id) = &utd_info.session_id else { continue };
+
+            if !requested_sessions.insert((room_id, session_id)) {
+                continue;
+            }
+
+            match olm_machine.request_room_key(event.cast_ref(), room_id).await {
+                Ok((cancel, request)) => {
+                    requests.extend(cancel);
+                    requests.push(request);
+                }
+                Err(e) => warn!(%room_id, session_id, "Couldn't create a room key request: {e}"),
+            }
+        }
+    }
+
+    if requested_sessions.is_empty() {
+        return;
+    }
+
+    info!(
+        num_sessions = requested_sessions.len(),
+        "Requesting room keys which may have been lost in the to-device gap"
+    );
+
+    // Cancellations must be sent before the new requests, so keep the order.
+    for request in requests {
+        if let Err(e) = client.send_outgoing_request(request).await {
+            warn!("Couldn't send a room key request: {e}");
+        }
+    }
+}
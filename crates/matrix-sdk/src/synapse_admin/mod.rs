@@ -0,0 +1,220 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A typed client for the [admin API] of Synapse.
+//!
+//! The requests are sent with the access token of the client, whose user must
+//! be an admin of the homeserver.
+//!
+//! [admin API]: https://element-hq.github.io/synapse/latest/usage/administration/admin_api/
+
+use ruma::{events::AnyStateEvent, serde::Raw, IdParseError, MxcUri, RoomId, UserId};
+
+use crate::{Client, Result};
+
+pub mod requests;
+
+pub use self::requests::list_users::UserDetails;
+use self::requests::{delete_media, delete_room, list_users, room_state};
+
+/// A high-level API to administrate a Synapse homeserver.
+///
+/// All the methods in this struct send a request to the homeserver.
+#[derive(Debug, Clone)]
+pub struct SynapseAdmin {
+    /// The underlying HTTP client.
+    client: Client,
+}
+
+impl SynapseAdmin {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// List the users of the homeserver.
+    ///
+    /// The request can be used to filter the users, and to get the next page
+    /// of results with the `next_token` of a previous response.
+    pub async fn list_users(&self, request: list_users::Request) -> Result<list_users::Response> {
+        Ok(self.client.send(request).await?)
+    }
+
+    /// Deactivate the account of the given user.
+    ///
+    /// If `erase` is `true`, the messages, display name and avatar of the user
+    /// are erased too.
+    pub async fn deactivate_user(&self, user_id: &UserId, erase: bool) -> Result<()> {
+        let request = requests::deactivate_user::Request::new(user_id.to_owned(), erase);
+        self.client.send(request).await?;
+        Ok(())
+    }
+
+    /// Make all the local users leave the given room, block it and purge it
+    /// from the database.
+    ///
+    /// The purge happens in the background on the homeserver. Returns the ID
+    /// of the deletion, to query its status. Use
+    /// [`SynapseAdmin::delete_room()`] for more control over the deletion.
+    pub async fn purge_room(&self, room_id: &RoomId) -> Result<String> {
+        let mut request = delete_room::Request::new(room_id.to_owned());
+        request.block = true;
+
+        self.delete_room(request).await
+    }
+
+    /// Delete a room.
+    ///
+    /// Returns the ID of the deletion, to query its status.
+    pub async fn delete_room(&self, request: delete_room::Request) -> Result<String> {
+        Ok(self.client.send(request).await?.delete_id)
+    }
+
+    /// Delete a media from the media repository of the homeserver.
+    ///
+    /// Only media uploaded to this homeserver can be deleted.
+    pub async fn delete_media(&self, uri: &MxcUri) -> Result<()> {
+        let (server_name, media_id) = uri.parts().map_err(IdParseError::InvalidMxcUri)?;
+        let request = delete_media::Request::new(server_name.to_owned(), media_id.to_owned());
+        self.client.send(request).await?;
+        Ok(())
+    }
+
+    /// Get the current state of the given room, even if the user isn't in it.
+    pub async fn room_state(&self, room_id: &RoomId) -> Result<Vec<Raw<AnyStateEvent>>> {
+        let request = room_state::Request::new(room_id.to_owned());
+        Ok(self.client.send(request).await?.state)
+    }
+}
+
+// The http mocking library is not supported for wasm32
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::{mxc_uri, room_id, user_id};
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_json, header, method, path, query_param},
+        Mock, ResponseTemplate,
+    };
+
+    use super::requests::list_users;
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    #[async_test]
+    async fn test_list_users() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/admin/v2/users"))
+            .and(header("authorization", "Bearer 1234"))
+            .and(query_param("from", "10"))
+            .and(query_param("deactivated", "true"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "users": [{
+                    "name": "@alice:localhost",
+                    "is_guest": false,
+                    "admin": true,
+                    "deactivated": false,
+                    "displayname": "Alice",
+                    "creation_ts": 1560432506000u64,
+                }],
+                "next_token": "11",
+                "total": 12,
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let mut request = list_users::Request::new();
+        request.from = Some("10".to_owned());
+        request.deactivated = Some(true);
+
+        let response = client.synapse_admin().list_users(request).await.unwrap();
+        assert_eq!(response.users.len(), 1);
+        assert_eq!(response.users[0].name, "@alice:localhost");
+        assert!(response.users[0].admin);
+        assert_eq!(response.users[0].displayname.as_deref(), Some("Alice"));
+        assert_eq!(response.next_token.as_deref(), Some("11"));
+    }
+
+    #[async_test]
+    async fn test_deactivate_user_and_purge_room() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        Mock::given(method("POST"))
+            .and(path("/_synapse/admin/v1/deactivate/@spammer:localhost"))
+            .and(body_json(json!({ "erase": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id_server_unbind_result": "success",
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/_synapse/admin/v2/rooms/!spam:localhost"))
+            .and(body_json(json!({ "block": true, "purge": true })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "delete_id": "delete_1" })),
+            )
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/_synapse/admin/v1/media/localhost/abcdef"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "deleted_media": ["abcdef"],
+                "total": 1,
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let admin = client.synapse_admin();
+        admin.deactivate_user(user_id!("@spammer:localhost"), true).await.unwrap();
+        let delete_id = admin.purge_room(room_id!("!spam:localhost")).await.unwrap();
+        assert_eq!(delete_id, "delete_1");
+        admin.delete_media(mxc_uri!("mxc://localhost/abcdef")).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_room_state() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/admin/v1/rooms/!room:localhost/state"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "state": [{
+                    "type": "m.room.name",
+                    "state_key": "",
+                    "event_id": "$name",
+                    "room_id": "!room:localhost",
+                    "sender": "@alice:localhost",
+                    "origin_server_ts": 1,
+                    "content": { "name": "Spam" },
+                }],
+            })))
+            .expect(1)
+            .mount(server.server())
+            .await;
+
+        let state = client.synapse_admin().room_state(room_id!("!room:localhost")).await.unwrap();
+        assert_eq!(state.len(), 1);
+        assert_eq!(state[0].get_field::<String>("type").unwrap().as_deref(), Some("m.room.name"));
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Definitions of the Synapse admin API endpoints, which aren't part of the
+//! Matrix specification.
+//!
+//! See [the Synapse documentation] for the details of each endpoint.
+//!
+//! [the Synapse documentation]: https://element-hq.github.io/synapse/latest/usage/administration/admin_api/
+
+pub mod list_users {
+    //! `GET /_synapse/admin/v2/users`
+    //!
+    //! List the accounts of the homeserver, page by page.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        metadata, MilliSecondsSinceUnixEpoch, OwnedMxcUri, OwnedUserId, UInt,
+    };
+    use serde::{Deserialize, Serialize};
+
+    const METADATA: Metadata = metadata! {
+        method: GET,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_synapse/admin/v2/users",
+        }
+    };
+
+    /// Request type for the `list_users` endpoint.
+    #[request(error = Error)]
+    #[derive(Default)]
+    pub struct Request {
+        /// The token to get the next page, from a previous response.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub from: Option<String>,
+
+        /// The maximum number of users to return.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub limit: Option<UInt>,
+
+        /// Only return users whose ID or display name contain this string.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+
+        /// Whether to include guest users.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub guests: Option<bool>,
+
+        /// Whether to include deactivated users.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub deactivated: Option<bool>,
+    }
+
+    /// Response type for the `list_users` endpoint.
+    #[response(error = Error)]
+    pub struct Response {
+        /// The users of this page.
+        pub users: Vec<UserDetails>,
+
+        /// The token to get the next page, if there is one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub next_token: Option<String>,
+
+        /// The total number of users matching the request.
+        pub total: UInt,
+    }
+
+    impl Request {
+        /// Creates an empty `Request`, listing the first page of users.
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl Response {
+        /// Creates a new `Response` with the given users and total.
+        pub fn new(users: Vec<UserDetails>, total: UInt) -> Self {
+            Self { users, next_token: None, total }
+        }
+    }
+
+    /// The details of a user, as returned by [`Request`].
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct UserDetails {
+        /// The ID of the user.
+        pub name: OwnedUserId,
+
+        /// Whether the user is a guest.
+        #[serde(default)]
+        pub is_guest: bool,
+
+        /// Whether the user is an admin of the homeserver.
+        #[serde(default)]
+        pub admin: bool,
+
+        /// Whether the account was deactivated.
+        #[serde(default)]
+        pub deactivated: bool,
+
+        /// Whether the user was shadow-banned.
+        #[serde(default)]
+        pub shadow_banned: bool,
+
+        /// Whether the account is locked.
+        #[serde(default)]
+        pub locked: bool,
+
+        /// The display name of the user.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub displayname: Option<String>,
+
+        /// The avatar of the user.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub avatar_url: Option<OwnedMxcUri>,
+
+        /// When the account was created.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub creation_ts: Option<MilliSecondsSinceUnixEpoch>,
+    }
+}
+
+pub mod deactivate_user {
+    //! `POST /_synapse/admin/v1/deactivate/{user_id}`
+    //!
+    //! Deactivate an account.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        metadata, OwnedUserId,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: POST,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_synapse/admin/v1/deactivate/:user_id",
+        }
+    };
+
+    /// Request type for the `deactivate_user` endpoint.
+    #[request(error = Error)]
+    pub struct Request {
+        /// The user to deactivate.
+        #[ruma_api(path)]
+        pub user_id: OwnedUserId,
+
+        /// Whether to also erase the messages, display name and avatar of the
+        /// user.
+        #[serde(default, skip_serializing_if = "ruma::serde::is_default")]
+        pub erase: bool,
+    }
+
+    /// Response type for the `deactivate_user` endpoint.
+    #[response(error = Error)]
+    #[derive(Default)]
+    pub struct Response {}
+
+    impl Request {
+        /// Creates a new `Request` to deactivate the given user.
+        pub fn new(user_id: OwnedUserId, erase: bool) -> Self {
+            Self { user_id, erase }
+        }
+    }
+
+    impl Response {
+        /// Creates an empty `Response`.
+        pub fn new() -> Self {
+            Self {}
+        }
+    }
+}
+
+pub mod delete_room {
+    //! `DELETE /_synapse/admin/v2/rooms/{room_id}`
+    //!
+    //! Remove all the local users from a room and optionally purge it from the
+    //! database. The deletion happens in the background.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        metadata, OwnedRoomId, OwnedUserId,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: DELETE,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_synapse/admin/v2/rooms/:room_id",
+        }
+    };
+
+    /// Request type for the `delete_room` endpoint.
+    #[request(error = Error)]
+    pub struct Request {
+        /// The room to delete.
+        #[ruma_api(path)]
+        pub room_id: OwnedRoomId,
+
+        /// Whether to prevent users from joining the room again.
+        #[serde(default, skip_serializing_if = "ruma::serde::is_default")]
+        pub block: bool,
+
+        /// Whether to remove the room from the database.
+        pub purge: bool,
+
+        /// Whether to purge the room even if local users couldn't be removed.
+        #[serde(default, skip_serializing_if = "ruma::serde::is_default")]
+        pub force_purge: bool,
+
+        /// If set, the local users are moved to a new room created by this
+        /// user.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub new_room_user_id: Option<OwnedUserId>,
+
+        /// The message sent in the new room.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub message: Option<String>,
+    }
+
+    /// Response type for the `delete_room` endpoint.
+    #[response(error = Error)]
+    pub struct Response {
+        /// The ID of the background deletion, to query its status.
+        pub delete_id: String,
+    }
+
+    impl Request {
+        /// Creates a new `Request` to delete the given room and purge it from
+        /// the database.
+        pub fn new(room_id: OwnedRoomId) -> Self {
+            Self {
+                room_id,
+                block: false,
+                purge: true,
+                force_purge: false,
+                new_room_user_id: None,
+                message: None,
+            }
+        }
+    }
+
+    impl Response {
+        /// Creates a new `Response` with the given deletion ID.
+        pub fn new(delete_id: String) -> Self {
+            Self { delete_id }
+        }
+    }
+}
+
+pub mod delete_media {
+    //! `DELETE /_synapse/admin/v1/media/{server_name}/{media_id}`
+    //!
+    //! Delete a media from the local media repository.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        metadata, OwnedServerName,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: DELETE,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_synapse/admin/v1/media/:server_name/:media_id",
+        }
+    };
+
+    /// Request type for the `delete_media` endpoint.
+    #[request(error = Error)]
+    pub struct Request {
+        /// The server name of the media, which must be the homeserver's.
+        #[ruma_api(path)]
+        pub server_name: OwnedServerName,
+
+        /// The ID of the media.
+        #[ruma_api(path)]
+        pub media_id: String,
+    }
+
+    /// Response type for the `delete_media` endpoint.
+    #[response(error = Error)]
+    pub struct Response {
+        /// The IDs of the deleted media.
+        pub deleted_media: Vec<String>,
+    }
+
+    impl Request {
+        /// Creates a new `Request` to delete the given media.
+        pub fn new(server_name: OwnedServerName, media_id: String) -> Self {
+            Self { server_name, media_id }
+        }
+    }
+
+    impl Response {
+        /// Creates a new `Response` with the given deleted media.
+        pub fn new(deleted_media: Vec<String>) -> Self {
+            Self { deleted_media }
+        }
+    }
+}
+
+pub mod room_state {
+    //! `GET /_synapse/admin/v1/rooms/{room_id}/state`
+    //!
+    //! Get the current state of a room, even if the admin isn't in it.
+
+    use ruma::{
+        api::{client::Error, request, response, Metadata},
+        events::AnyStateEvent,
+        metadata,
+        serde::Raw,
+        OwnedRoomId,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: GET,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_synapse/admin/v1/rooms/:room_id/state",
+        }
+    };
+
+    /// Request type for the `room_state` endpoint.
+    #[request(error = Error)]
+    pub struct Request {
+        /// The room to get the state of.
+        #[ruma_api(path)]
+        pub room_id: OwnedRoomId,
+    }
+
+    /// Response type for the `room_state` endpoint.
+    #[response(error = Error)]
+    pub struct Response {
+        /// The state events of the room.
+        pub state: Vec<Raw<AnyStateEvent>>,
+    }
+
+    impl Request {
+        /// Creates a new `Request` for the state of the given room.
+        pub fn new(room_id: OwnedRoomId) -> Self {
+            Self { room_id }
+        }
+    }
+
+    impl Response {
+        /// Creates a new `Response` with the given state events.
+        pub fn new(state: Vec<Raw<AnyStateEvent>>) -> Self {
+            Self { state }
+        }
+    }
+}
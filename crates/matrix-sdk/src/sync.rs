@@ -262,7 +262,7 @@ impl Client {
 
         // Construct notification event handler futures
         let mut futures = Vec::new();
-        for handler in &*self.notification_handlers().await {
+        for (_, handler) in &*self.notification_handlers().await {
             for (room_id, room_notifications) in notifications {
                 let Some(room) = self.get_room(room_id) else {
                     warn!(?room_id, "Can't call notification handler, room not found");
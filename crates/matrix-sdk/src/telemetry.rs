@@ -0,0 +1,135 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integration with [OpenTelemetry].
+//!
+//! The SDK emits spans with stable names for the operations that are useful
+//! to monitor in production, see [`SPAN_NAMES`]. The [`layer()`] function
+//! returns a [`tracing_subscriber::Layer`] exporting only those spans to an
+//! OpenTelemetry tracer, so it can be added to an existing subscriber without
+//! exporting all of the SDK's logs.
+//!
+//! The SDK also records a few counters with the global OpenTelemetry meter,
+//! named `matrix-sdk`:
+//!
+//! - `matrix_sdk.unable_to_decrypt`: the number of events the event cache
+//!   failed to decrypt,
+//! - `matrix_sdk.send_failures`: the number of requests of the send queue that
+//!   failed to be sent, with a `recoverable` attribute.
+//!
+//! The global meter provider must be installed with
+//! [`opentelemetry::global::set_meter_provider()`] before the first counter is
+//! recorded, otherwise the counters are dropped.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+//!
+//! // Use the tracer of the OpenTelemetry exporter of your choice.
+//! let tracer = opentelemetry::trace::noop::NoopTracer::new();
+//!
+//! tracing_subscriber::registry()
+//!     .with(tracing_subscriber::fmt::layer())
+//!     .with(matrix_sdk::telemetry::layer(tracer))
+//!     .init();
+//! ```
+//!
+//! [OpenTelemetry]: https://opentelemetry.io
+
+use matrix_sdk_base::deserialized_responses::{TimelineEvent, TimelineEventKind};
+use once_cell::sync::Lazy;
+use opentelemetry::{global, metrics::Counter, trace::Tracer, KeyValue};
+use tracing::Subscriber;
+use tracing_opentelemetry::PreSampledTracer;
+use tracing_subscriber::{filter::filter_fn, registry::LookupSpan, Layer};
+
+/// The span wrapping a sync request and the processing of its response, for
+/// both the `/sync` and the sliding sync APIs.
+pub const SYNC_SPAN: &str = "matrix_sdk.sync";
+
+/// The span wrapping a request to the homeserver.
+///
+/// Its attributes are `uri`, `method`, `request_size`, `request_id`,
+/// `status` and `response_size`.
+pub const HTTP_REQUEST_SPAN: &str = "matrix_sdk.http_request";
+
+/// The span wrapping a back-pagination of the event cache from the network,
+/// with a `room_id` attribute.
+pub const PAGINATION_SPAN: &str = "matrix_sdk.pagination";
+
+/// The span wrapping an attempt to decrypt again the events of a room that
+/// couldn't be decrypted, with a `room_id` attribute.
+pub const DECRYPTION_BATCH_SPAN: &str = "matrix_sdk.decryption_batch";
+
+/// The span wrapping a write transaction of a store, with a `store`
+/// attribute: `state`, `crypto` or `event_cache`.
+///
+/// Only the SQLite stores emit it for now.
+pub const STORE_TRANSACTION_SPAN: &str = "matrix_sdk.store_transaction";
+
+/// The names of all the spans exported by [`layer()`].
+pub const SPAN_NAMES: &[&str] =
+    &[SYNC_SPAN, HTTP_REQUEST_SPAN, PAGINATION_SPAN, DECRYPTION_BATCH_SPAN, STORE_TRANSACTION_SPAN];
+
+/// Create a layer exporting the spans of the SDK listed in [`SPAN_NAMES`] to
+/// the given OpenTelemetry tracer.
+///
+/// Other spans and events are ignored by this layer.
+pub fn layer<S, T>(tracer: T) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    T: Tracer + PreSampledTracer + Send + Sync + 'static,
+{
+    tracing_opentelemetry::layer().with_tracer(tracer).with_filter(filter_fn(|metadata| {
+        metadata.is_span() && SPAN_NAMES.contains(&metadata.name())
+    }))
+}
+
+struct Metrics {
+    unable_to_decrypt: Counter<u64>,
+    send_failures: Counter<u64>,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| {
+    let meter = global::meter("matrix-sdk");
+
+    Metrics {
+        unable_to_decrypt: meter
+            .u64_counter("matrix_sdk.unable_to_decrypt")
+            .with_description("Number of events that couldn't be decrypted")
+            .build(),
+        send_failures: meter
+            .u64_counter("matrix_sdk.send_failures")
+            .with_description("Number of requests of the send queue that failed to be sent")
+            .build(),
+    }
+});
+
+/// Count the events that couldn't be decrypted among the given ones.
+pub(crate) fn record_unable_to_decrypt(events: &[TimelineEvent]) {
+    let num_utds = events
+        .iter()
+        .filter(|event| matches!(event.kind, TimelineEventKind::UnableToDecrypt { .. }))
+        .count();
+
+    if num_utds > 0 {
+        METRICS.unable_to_decrypt.add(num_utds as u64, &[]);
+    }
+}
+
+/// Count a request of the send queue that failed to be sent.
+pub(crate) fn record_send_failure(is_recoverable: bool) {
+    METRICS.send_failures.add(1, &[KeyValue::new("recoverable", is_recoverable)]);
+}
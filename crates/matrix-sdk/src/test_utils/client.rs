@@ -79,12 +79,36 @@ impl MockClientBuilder {
         self
     }
 
+    /// Use the given Matrix versions instead of Matrix V1.12.
+    pub fn server_versions(mut self, versions: impl IntoIterator<Item = MatrixVersion>) -> Self {
+        self.builder = self.builder.server_versions(versions);
+        self
+    }
+
     /// Handle refreshing access tokens automatically.
     pub fn handle_refresh_tokens(mut self) -> Self {
         self.builder = self.builder.handle_refresh_tokens();
         self
     }
 
+    /// Add a middleware to the HTTP client.
+    pub fn add_http_middleware(mut self, middleware: impl crate::HttpMiddleware + 'static) -> Self {
+        self.builder = self.builder.add_http_middleware(middleware);
+        self
+    }
+
+    /// Refresh access tokens when they expire within the given `margin`.
+    pub fn refresh_access_token_ahead_of_expiry(mut self, margin: std::time::Duration) -> Self {
+        self.builder = self.builder.refresh_access_token_ahead_of_expiry(margin);
+        self
+    }
+
+    /// Use the given clock instead of the system clock.
+    pub fn clock(mut self, clock: std::sync::Arc<dyn crate::clock::Clock>) -> Self {
+        self.builder = self.builder.clock(clock);
+        self
+    }
+
     /// Finish building the client into the final [`Client`] instance.
     pub async fn build(self) -> Client {
         let client = self.builder.build().await.expect("building client failed");
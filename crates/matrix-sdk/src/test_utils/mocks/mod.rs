@@ -29,7 +29,7 @@ use matrix_sdk_test::{
 };
 use percent_encoding::{AsciiSet, CONTROLS};
 use ruma::{
-    api::client::room::Visibility,
+    api::client::{room::Visibility, space::SpaceHierarchyRoomsChunk},
     device_id,
     directory::PublicRoomsChunk,
     events::{
@@ -735,6 +735,48 @@ impl MatrixMockServer {
         self.mock_endpoint(mock, GetRoomVisibilityEndpoint)
     }
 
+    /// Create a prebuilt mock for the `/hierarchy` endpoint of a space.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # tokio_test::block_on(async {
+    /// use js_int::uint;
+    /// use matrix_sdk::{ruma::room_id, test_utils::mocks::MatrixMockServer};
+    /// use ruma::{
+    ///     api::client::space::{get_hierarchy, SpaceHierarchyRoomsChunkInit},
+    ///     space::SpaceRoomJoinRule,
+    /// };
+    ///
+    /// let mock_server = MatrixMockServer::new().await;
+    /// let client = mock_server.client_builder().build().await;
+    /// let space_id = room_id!("!space:localhost");
+    ///
+    /// let rooms = vec![SpaceHierarchyRoomsChunkInit {
+    ///     num_joined_members: uint!(1),
+    ///     room_id: space_id.to_owned(),
+    ///     world_readable: false,
+    ///     guest_can_join: false,
+    ///     join_rule: SpaceRoomJoinRule::Invite,
+    ///     children_state: Vec::new(),
+    /// }
+    /// .into()];
+    ///
+    /// mock_server.mock_get_hierarchy().ok(rooms, None).mock_once().mount().await;
+    ///
+    /// let response = client
+    ///     .send(get_hierarchy::v1::Request::new(space_id.to_owned()))
+    ///     .await
+    ///     .expect("We should be able to get the space hierarchy");
+    /// assert_eq!(response.rooms.len(), 1);
+    /// # anyhow::Ok(()) });
+    /// ```
+    pub fn mock_get_hierarchy(&self) -> MockEndpoint<'_, GetHierarchyEndpoint> {
+        let mock =
+            Mock::given(method("GET")).and(path_regex(r"^/_matrix/client/v1/rooms/.*/hierarchy"));
+        self.mock_endpoint(mock, GetHierarchyEndpoint)
+    }
+
     /// Create a prebuilt mock for fetching information about key storage
     /// backups.
     ///
@@ -922,6 +964,13 @@ impl MatrixMockServer {
         self.mock_endpoint(mock, VersionsEndpoint)
     }
 
+    /// Creates a prebuilt mock for the `/_matrix/client/v3/capabilities`
+    /// endpoint.
+    pub fn mock_capabilities(&self) -> MockEndpoint<'_, CapabilitiesEndpoint> {
+        let mock = Mock::given(method("GET")).and(path("/_matrix/client/v3/capabilities"));
+        self.mock_endpoint(mock, CapabilitiesEndpoint).expect_default_access_token()
+    }
+
     /// Creates a prebuilt mock for the room summary endpoint [MSC3266](https://github.com/matrix-org/matrix-spec-proposals/pull/3266).
     pub fn mock_room_summary(&self) -> MockEndpoint<'_, RoomSummaryEndpoint> {
         let mock = Mock::given(method("GET"))
@@ -1001,6 +1050,20 @@ impl MatrixMockServer {
         let mock = Mock::given(method("POST")).and(path("/_matrix/client/v3/logout"));
         self.mock_endpoint(mock, LogoutEndpoint).expect_default_access_token()
     }
+
+    /// Create a prebuilt mock for the endpoint used to list the devices of the
+    /// current user.
+    pub fn mock_devices(&self) -> MockEndpoint<'_, DevicesEndpoint> {
+        let mock = Mock::given(method("GET")).and(path("/_matrix/client/v3/devices"));
+        self.mock_endpoint(mock, DevicesEndpoint).expect_default_access_token()
+    }
+
+    /// Create a prebuilt mock for the endpoint used to delete devices of the
+    /// current user.
+    pub fn mock_delete_devices(&self) -> MockEndpoint<'_, DeleteDevicesEndpoint> {
+        let mock = Mock::given(method("POST")).and(path("/_matrix/client/v3/delete_devices"));
+        self.mock_endpoint(mock, DeleteDevicesEndpoint).expect_default_access_token()
+    }
 }
 
 /// Parameter to [`MatrixMockServer::sync_room`].
@@ -2211,6 +2274,24 @@ impl<'a> MockEndpoint<'a, PublicRoomsEndpoint> {
     }
 }
 
+/// A prebuilt mock for the `/hierarchy` endpoint of a space.
+pub struct GetHierarchyEndpoint;
+
+impl<'a> MockEndpoint<'a, GetHierarchyEndpoint> {
+    /// Returns a successful response with the given rooms, and the token of
+    /// the next page if any.
+    pub fn ok(
+        self,
+        rooms: Vec<SpaceHierarchyRoomsChunk>,
+        next_batch: Option<String>,
+    ) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": rooms,
+            "next_batch": next_batch,
+        })))
+    }
+}
+
 /// A prebuilt mock for getting the room's visibility in the room directory.
 pub struct GetRoomVisibilityEndpoint;
 
@@ -2304,6 +2385,11 @@ impl<'a> MockEndpoint<'a, DeleteRoomKeysVersionEndpoint> {
 pub struct GetRoomMembersEndpoint;
 
 impl<'a> MockEndpoint<'a, GetRoomMembersEndpoint> {
+    /// Expects the members to be requested at the given sync token.
+    pub fn match_at(self, at: &str) -> Self {
+        Self { mock: self.mock.and(query_param("at", at)), ..self }
+    }
+
     /// Returns a successful get members request with a list of members.
     pub fn ok(self, members: Vec<Raw<RoomMemberEvent>>) -> MatrixMock<'a> {
         self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
@@ -2375,6 +2461,44 @@ impl<'a> MockEndpoint<'a, VersionsEndpoint> {
             ]
         })))
     }
+
+    /// Returns a successful `/_matrix/client/versions` request, advertising
+    /// support for Matrix 1.11 and for the delayed events unstable feature.
+    pub fn ok_with_unstable_features(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "unstable_features": {
+                "org.matrix.msc4140": true,
+                "org.matrix.simplified_msc3575": false,
+            },
+            "versions": [
+                "v1.10",
+                "v1.11"
+            ]
+        })))
+    }
+}
+
+/// A prebuilt mock for `GET /capabilities` request.
+pub struct CapabilitiesEndpoint;
+
+impl<'a> MockEndpoint<'a, CapabilitiesEndpoint> {
+    /// Returns a successful response, where changing the password is disabled.
+    pub fn ok(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "capabilities": {
+                "m.change_password": {
+                    "enabled": false,
+                },
+                "m.room_versions": {
+                    "default": "10",
+                    "available": {
+                        "10": "stable",
+                        "11": "stable",
+                    },
+                },
+            },
+        })))
+    }
 }
 
 /// A prebuilt mock for the room summary endpoint.
@@ -2570,3 +2694,43 @@ impl<'a> MockEndpoint<'a, LogoutEndpoint> {
         self.respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
     }
 }
+
+/// A prebuilt mock for `GET /devices` request.
+pub struct DevicesEndpoint;
+
+impl<'a> MockEndpoint<'a, DevicesEndpoint> {
+    /// Returns a successful response listing the given devices.
+    pub fn ok(self, device_ids: &[&DeviceId]) -> MatrixMock<'a> {
+        let devices: Vec<_> =
+            device_ids.iter().map(|device_id| json!({ "device_id": device_id })).collect();
+
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "devices": devices,
+        })))
+    }
+}
+
+/// A prebuilt mock for `POST /delete_devices` request.
+pub struct DeleteDevicesEndpoint;
+
+impl<'a> MockEndpoint<'a, DeleteDevicesEndpoint> {
+    /// Returns a successful empty response.
+    pub fn ok(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+    }
+
+    /// Returns an error response with a UIAA stage.
+    pub fn uiaa(self) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [
+                {
+                    "stages": [
+                        "m.login.password"
+                    ]
+                }
+            ],
+            "params": {},
+            "session": "oFIJVvtEOCKmRUTYKTYIIPHL"
+        })))
+    }
+}
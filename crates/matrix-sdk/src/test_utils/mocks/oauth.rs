@@ -240,7 +240,7 @@ impl MockServerMetadataBuilder {
             "revocation_endpoint": self.revocation_endpoint(),
             "code_challenge_methods_supported": ["S256"],
             "account_management_uri": self.account_management_uri(),
-            "account_management_actions_supported": ["org.matrix.profile", "org.matrix.sessions_list", "org.matrix.session_view", "org.matrix.session_end", "org.matrix.deactivateaccount", "org.matrix.cross_signing_reset"],
+            "account_management_actions_supported": ["org.matrix.profile", "org.matrix.sessions_list", "org.matrix.session_view", "org.matrix.session_end", "org.matrix.account_deactivate", "org.matrix.cross_signing_reset"],
             "prompt_values_supported": ["create"],
         });
         let json_metadata_object = json_metadata.as_object_mut().unwrap();
@@ -17,20 +17,25 @@
 //!
 //! This provides a general trait that a consumer may implement, as well as
 //! utilities to simplify usage of this trait.
+//!
+//! The same [`UtdHookManager`] can be fed by the timelines, and by the event
+//! cache with [`EventCache::set_unable_to_decrypt_hook`].
+//!
+//! [`EventCache::set_unable_to_decrypt_hook`]: crate::event_cache::EventCache::set_unable_to_decrypt_hook
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{Arc, Mutex},
 };
 
 use growable_bloom_filter::{GrowableBloom, GrowableBloomBuilder};
-use matrix_sdk::{
-    crypto::types::events::UtdCause,
+use matrix_sdk_base::{
+    crypto::types::events::UtdCause, StateStoreDataKey, StateStoreDataValue, StoreError,
+};
+use matrix_sdk_common::{
     executor::{spawn, JoinHandle},
     sleep::sleep,
-    Client,
 };
-use matrix_sdk_base::{StateStoreDataKey, StateStoreDataValue, StoreError};
 use ruma::{
     time::{Duration, Instant},
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedServerName, UserId,
@@ -38,6 +43,15 @@ use ruma::{
 use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
 use tracing::error;
 
+use crate::{encryption::backups::BackupState, Client};
+
+/// The maximum number of UTDs reported as permanent whose late decryption is
+/// tracked, when [`UtdHookManager::report_late_decryptions`] is set.
+///
+/// When it's reached, the oldest UTD is forgotten, so its late decryption
+/// won't be reported.
+const MAX_TRACKED_LATE_UTDS: usize = 1000;
+
 /// A generic interface which methods get called whenever we observe a
 /// unable-to-decrypt (UTD) event.
 pub trait UnableToDecryptHook: std::fmt::Debug + Send + Sync {
@@ -50,12 +64,32 @@ pub trait UnableToDecryptHook: std::fmt::Debug + Send + Sync {
     fn on_utd(&self, info: UnableToDecryptInfo);
 }
 
+/// What an [`UnableToDecryptInfo`] is about.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum UtdReportKind {
+    /// The event couldn't be decrypted within the grace period.
+    Permanent,
+
+    /// The event has been decrypted, after it's been reported as
+    /// [`UtdReportKind::Permanent`].
+    ///
+    /// Only reported if [`UtdHookManager::report_late_decryptions`] is set.
+    Late,
+
+    /// The event has been decrypted within the grace period, so it has never
+    /// been reported as permanent.
+    Resolved,
+}
+
 /// Information about an event we were unable to decrypt (UTD).
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct UnableToDecryptInfo {
     /// The identifier of the event that couldn't get decrypted.
     pub event_id: OwnedEventId,
 
+    /// What this report is about.
+    pub kind: UtdReportKind,
+
     /// If the event could be decrypted late (that is, the event was encrypted
     /// at first, but could be decrypted later on), then this indicates the
     /// time it took to decrypt the event. If it is not set, this is
@@ -66,6 +100,11 @@ pub struct UnableToDecryptInfo {
     /// we were not a member of this room?
     pub cause: UtdCause,
 
+    /// Whether key storage was enabled when the UTD was first observed, and
+    /// the cause is unknown, so the room key might still be downloaded from
+    /// the backup.
+    pub key_backup_pending: bool,
+
     /// The difference between the event creation time (`origin_server_ts`) and
     /// the time our device was created. If negative, this event was sent
     /// *before* our device was created.
@@ -83,6 +122,16 @@ pub struct UnableToDecryptInfo {
     pub own_homeserver: Option<OwnedServerName>,
 }
 
+impl UnableToDecryptInfo {
+    /// Whether this UTD was expected, because the keys for the event were
+    /// never meant to be shared with us.
+    ///
+    /// See [`UtdCause::is_expected`].
+    pub fn is_expected(&self) -> bool {
+        self.cause.is_expected()
+    }
+}
+
 /// Data about a UTD event which we are waiting to report to the parent hook.
 #[derive(Debug)]
 struct PendingUtdReport {
@@ -96,6 +145,47 @@ struct PendingUtdReport {
     utd_info: UnableToDecryptInfo,
 }
 
+/// The UTDs reported as permanent, whose late decryption will be reported.
+#[derive(Debug, Default)]
+struct ReportedUtds {
+    /// The time each UTD was first observed, and its report.
+    by_event_id: HashMap<OwnedEventId, (Instant, UnableToDecryptInfo)>,
+
+    /// The event IDs in the order they were reported, oldest first.
+    ///
+    /// It may contain the IDs of events that have since been decrypted, which
+    /// are skipped when evicting the oldest UTDs.
+    order: VecDeque<OwnedEventId>,
+}
+
+impl ReportedUtds {
+    /// Track a UTD reported as permanent, forgetting the oldest ones if there
+    /// are already `max_tracked` of them.
+    fn insert(&mut self, first_seen: Instant, info: UnableToDecryptInfo, max_tracked: usize) {
+        while self.by_event_id.len() >= max_tracked.max(1) {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.by_event_id.remove(&oldest);
+        }
+
+        // Don't let the IDs of the decrypted events pile up.
+        if self.order.len() >= 2 * max_tracked.max(1) {
+            let by_event_id = &self.by_event_id;
+            self.order.retain(|event_id| by_event_id.contains_key(event_id));
+        }
+
+        self.order.push_back(info.event_id.clone());
+        self.by_event_id.insert(info.event_id.clone(), (first_seen, info));
+    }
+
+    /// Stop tracking the given UTD, and return when it was first observed and
+    /// its report, if it was tracked.
+    fn remove(&mut self, event_id: &EventId) -> Option<(Instant, UnableToDecryptInfo)> {
+        self.by_event_id.remove(event_id)
+    }
+}
+
 /// A manager over an existing [`UnableToDecryptHook`] that deduplicates UTDs
 /// on similar events, and adds basic consistency checks.
 ///
@@ -128,6 +218,10 @@ pub struct UtdHookManager {
     /// Bloom filter containing the event IDs of events which have been reported
     /// as UTDs
     reported_utds: Arc<AsyncMutex<GrowableBloom>>,
+
+    /// The UTDs reported as permanent by this manager, whose late decryption
+    /// will be reported, if [`Self::report_late_decryptions`] is set.
+    late_utds: Option<Arc<Mutex<ReportedUtds>>>,
 }
 
 impl UtdHookManager {
@@ -185,6 +279,7 @@ impl UtdHookManager {
             max_delay: None,
             pending_delayed: Default::default(),
             reported_utds: Arc::new(AsyncMutex::new(bloom_filter)),
+            late_utds: None,
         }
     }
 
@@ -197,6 +292,22 @@ impl UtdHookManager {
         self
     }
 
+    /// Also report the decryption of the events that have already been
+    /// reported as UTDs, with [`UtdReportKind::Late`].
+    ///
+    /// Only the last 1000 UTDs reported by this manager are tracked.
+    pub fn report_late_decryptions(mut self) -> Self {
+        self.late_utds = Some(Default::default());
+        self
+    }
+
+    /// Whether the given event has already been observed as a UTD, by this
+    /// manager or, if it's been reloaded from the store, by a previous one.
+    pub(crate) async fn has_seen(&self, event_id: &EventId) -> bool {
+        self.reported_utds.lock().await.contains(event_id)
+            || self.pending_delayed.lock().unwrap().contains_key(event_id)
+    }
+
     /// Load the persistent data for the UTD hook from the store.
     ///
     /// If the client previously used a UtdHookManager, and UTDs were
@@ -227,7 +338,7 @@ impl UtdHookManager {
     ///    time for local echo).
     ///  * `sender_user_id` - The Matrix user ID of the user that sent the
     ///    undecryptable message.
-    pub(crate) async fn on_utd(
+    pub async fn on_utd(
         &self,
         event_id: &EventId,
         cause: UtdCause,
@@ -268,10 +379,15 @@ impl UtdHookManager {
         let own_homeserver = own_user_id.map(|id| id.server_name().to_owned());
         let sender_homeserver = sender_user_id.server_name().to_owned();
 
+        let key_backup_pending = cause == UtdCause::Unknown
+            && self.client.encryption().backups().state() == BackupState::Enabled;
+
         let info = UnableToDecryptInfo {
             event_id: event_id.to_owned(),
+            kind: UtdReportKind::Permanent,
             time_to_decrypt: None,
             cause,
+            key_backup_pending,
             event_local_age_millis,
             user_trusts_own_identity,
             own_homeserver,
@@ -280,6 +396,13 @@ impl UtdHookManager {
 
         let Some(max_delay) = self.max_delay else {
             // No delay: immediately report the event to the parent hook.
+            if let Some(late_utds) = &self.late_utds {
+                late_utds.lock().unwrap().insert(
+                    Instant::now(),
+                    info.clone(),
+                    MAX_TRACKED_LATE_UTDS,
+                );
+            }
             Self::report_utd(info, &self.parent, &self.client, &mut reported_utds_lock).await;
             return;
         };
@@ -287,6 +410,7 @@ impl UtdHookManager {
         // Clone data shared with the task below.
         let pending_delayed = self.pending_delayed.clone();
         let reported_utds = self.reported_utds.clone();
+        let late_utds = self.late_utds.clone();
         let parent = self.parent.clone();
         let client = self.client.clone();
         let owned_event_id = event_id.to_owned();
@@ -308,6 +432,13 @@ impl UtdHookManager {
             // it's been decrypted since the task was added!
             let pending_report = pending_delayed.lock().unwrap().remove(&owned_event_id);
             if let Some(pending_report) = pending_report {
+                if let Some(late_utds) = &late_utds {
+                    late_utds.lock().unwrap().insert(
+                        pending_report.marked_utd_at,
+                        pending_report.utd_info.clone(),
+                        MAX_TRACKED_LATE_UTDS,
+                    );
+                }
                 Self::report_utd(
                     pending_report.utd_info,
                     &parent,
@@ -330,7 +461,7 @@ impl UtdHookManager {
     ///
     /// Note: if this is called for an event that was never marked as a UTD
     /// before, it has no effect.
-    pub(crate) async fn on_late_decrypt(&self, event_id: &EventId) {
+    pub async fn on_late_decrypt(&self, event_id: &EventId) {
         // Hold the lock on `reported_utds` throughout, to avoid races with other
         // threads.
         let mut reported_utds_lock = self.reported_utds.lock().await;
@@ -339,6 +470,20 @@ impl UtdHookManager {
         // a pending UTD. If so, remove the event from the pending list —
         // doing so will cause the reporting task to no-op if it runs.
         let Some(pending_utd_report) = self.pending_delayed.lock().unwrap().remove(event_id) else {
+            // Otherwise, it might have been reported as permanent already.
+            let reported = self
+                .late_utds
+                .as_ref()
+                .and_then(|late_utds| late_utds.lock().unwrap().remove(event_id));
+
+            if let Some((first_seen, info)) = reported {
+                self.parent.on_utd(UnableToDecryptInfo {
+                    kind: UtdReportKind::Late,
+                    time_to_decrypt: Some(first_seen.elapsed()),
+                    ..info
+                });
+            }
+
             return;
         };
 
@@ -347,6 +492,7 @@ impl UtdHookManager {
 
         // Update the UTD Info struct with new data, then report it
         let mut info = pending_utd_report.utd_info;
+        info.kind = UtdReportKind::Resolved;
         info.time_to_decrypt = Some(pending_utd_report.marked_utd_at.elapsed());
         Self::report_utd(info, &self.parent, &self.client, &mut reported_utds_lock).await;
     }
@@ -400,11 +546,11 @@ impl Drop for UtdHookManager {
 
 #[cfg(test)]
 mod tests {
-    use matrix_sdk::test_utils::{logged_in_client, no_retry_test_client};
     use matrix_sdk_test::async_test;
     use ruma::{event_id, server_name, user_id};
 
     use super::*;
+    use crate::test_utils::{logged_in_client, no_retry_test_client};
 
     #[derive(Debug, Default)]
     struct Dummy {
@@ -725,10 +871,87 @@ mod tests {
             let utds = hook.utds.lock().unwrap();
             assert_eq!(utds.len(), 1);
             assert_eq!(utds[0].event_id, event_id!("$1"));
+            assert_eq!(utds[0].kind, UtdReportKind::Resolved);
             assert!(utds[0].time_to_decrypt.is_some());
         }
 
         // And there aren't any pending delayed reports anymore.
         assert!(wrapper.pending_delayed.lock().unwrap().is_empty());
     }
+
+    #[async_test]
+    async fn test_late_decryption_after_utd_reported() {
+        // If I create a dummy hook,
+        let hook = Arc::new(Dummy::default());
+
+        // And I wrap with the UtdHookManager, reporting late decryptions,
+        let wrapper = UtdHookManager::new(hook.clone(), no_retry_test_client(None).await)
+            .report_late_decryptions();
+
+        // And I call the `on_utd` method for an event,
+        wrapper
+            .on_utd(
+                event_id!("$1"),
+                UtdCause::Unknown,
+                MilliSecondsSinceUnixEpoch::now(),
+                user_id!("@a:b"),
+            )
+            .await;
+
+        // Then the UTD has been notified as permanent.
+        {
+            let utds = hook.utds.lock().unwrap();
+            assert_eq!(utds.len(), 1);
+            assert_eq!(utds[0].kind, UtdReportKind::Permanent);
+        }
+
+        // And when I call the `on_late_decrypt` method,
+        wrapper.on_late_decrypt(event_id!("$1")).await;
+
+        // Then the event is reported again as a late decryption.
+        {
+            let utds = hook.utds.lock().unwrap();
+            assert_eq!(utds.len(), 2);
+            assert_eq!(utds[1].event_id, event_id!("$1"));
+            assert_eq!(utds[1].kind, UtdReportKind::Late);
+            assert!(utds[1].time_to_decrypt.is_some());
+        }
+
+        // But only once.
+        wrapper.on_late_decrypt(event_id!("$1")).await;
+        assert_eq!(hook.utds.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reported_utds_forget_the_oldest_ones() {
+        let info = |event_id: &EventId| UnableToDecryptInfo {
+            event_id: event_id.to_owned(),
+            kind: UtdReportKind::Permanent,
+            time_to_decrypt: None,
+            cause: UtdCause::Unknown,
+            key_backup_pending: false,
+            event_local_age_millis: 0,
+            user_trusts_own_identity: false,
+            sender_homeserver: server_name!("example.org").to_owned(),
+            own_homeserver: None,
+        };
+
+        let mut reported = ReportedUtds::default();
+        let now = Instant::now();
+        reported.insert(now, info(event_id!("$1")), 2);
+        reported.insert(now, info(event_id!("$2")), 2);
+
+        // A decrypted UTD isn't tracked anymore.
+        assert!(reported.remove(event_id!("$2")).is_some());
+        assert!(reported.remove(event_id!("$2")).is_none());
+
+        reported.insert(now, info(event_id!("$3")), 2);
+        reported.insert(now, info(event_id!("$4")), 2);
+
+        // The oldest UTD is forgotten to make room for the new ones.
+        assert_eq!(reported.by_event_id.len(), 2);
+        assert!(reported.remove(event_id!("$1")).is_none());
+        assert!(reported.remove(event_id!("$3")).is_some());
+        assert!(reported.remove(event_id!("$4")).is_some());
+    }
 }
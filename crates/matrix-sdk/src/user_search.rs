@@ -0,0 +1,459 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for searching users, for example to pick whom to invite in a room.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+};
+
+use matrix_sdk_base::RoomMemberships;
+use ruma::{events::room::member::SyncRoomMemberEvent, OwnedMxcUri, OwnedUserId, UserId};
+
+use crate::{Client, Result};
+
+/// The default number of results returned by [`UserSearch::next_page()`].
+const DEFAULT_BATCH_SIZE: usize = 20;
+
+/// Where a [`UserSearchResult`] was found.
+///
+/// The variants are sorted by decreasing relevance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UserSearchSource {
+    /// The user is a contact of the account in direct messages.
+    DirectMessage,
+
+    /// The user is a member of one of the joined rooms.
+    RoomMember,
+
+    /// The user was found in the user directory of the homeserver.
+    Directory,
+}
+
+/// A single result of a [`UserSearch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserSearchResult {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+
+    /// The display name of the user, if known.
+    pub display_name: Option<String>,
+
+    /// The MXC URI of the avatar of the user, if known.
+    pub avatar_url: Option<OwnedMxcUri>,
+
+    /// Where the user was found.
+    pub source: UserSearchSource,
+}
+
+/// The display name and avatar of a member of the joined rooms.
+#[derive(Clone, Debug, Default)]
+struct KnownUser {
+    display_name: Option<String>,
+    avatar_url: Option<OwnedMxcUri>,
+}
+
+/// The members of the joined rooms, with the number of times they were
+/// invalidated.
+#[derive(Default)]
+struct KnownUsers {
+    generation: u64,
+
+    /// The members, or `None` if they must be loaded again from the store.
+    members: Option<Arc<BTreeMap<OwnedUserId, KnownUser>>>,
+}
+
+/// The in-memory cache of the members of the joined rooms, shared by all the
+/// clones of a [`Client`], so every [`UserSearch`] doesn't have to go through
+/// the members of all the rooms.
+#[derive(Default)]
+pub(crate) struct KnownUsersCache {
+    users: StdMutex<KnownUsers>,
+
+    /// Whether the event handler that invalidates the cache when the members
+    /// change has been registered.
+    invalidation_enabled: AtomicBool,
+}
+
+impl KnownUsersCache {
+    /// Mark the cached members as outdated.
+    pub(crate) fn invalidate(&self) {
+        let mut users = self.users.lock().unwrap();
+        users.generation += 1;
+        users.members = None;
+    }
+}
+
+/// How well a user matches the search term.
+///
+/// The variants are sorted by decreasing relevance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchQuality {
+    /// The search term is the user ID, its localpart, or the display name.
+    Exact,
+
+    /// The user ID, its localpart, or a word of the display name start with
+    /// the search term.
+    Prefix,
+
+    /// The user ID or the display name contain the search term.
+    Partial,
+}
+
+impl MatchQuality {
+    /// How well the given user matches the given lowercase search term, or
+    /// `None` if they don't match it.
+    fn compute(search_term: &str, user_id: &UserId, display_name: Option<&str>) -> Option<Self> {
+        let full_id = user_id.as_str().to_lowercase();
+        let localpart = user_id.localpart().to_lowercase();
+        let display_name = display_name.map(str::to_lowercase).unwrap_or_default();
+
+        if full_id == search_term || localpart == search_term || display_name == search_term {
+            Some(Self::Exact)
+        } else if full_id.starts_with(search_term)
+            || localpart.starts_with(search_term)
+            || display_name.split_whitespace().any(|word| word.starts_with(search_term))
+        {
+            Some(Self::Prefix)
+        } else if full_id.contains(search_term) || display_name.contains(search_term) {
+            Some(Self::Partial)
+        } else {
+            None
+        }
+    }
+}
+
+/// A search of users, merging the users known locally with the results of the
+/// [user directory] of the homeserver.
+///
+/// The contacts of the account in direct messages and the members of the joined
+/// rooms that match the search term are loaded with the first page, and the
+/// user directory is asked for more users whenever there are not enough results
+/// left to fill a page. The results are deduplicated, and ranked by how well
+/// they match the search term, then by where they were found. The contacts in
+/// direct messages are ranked with [`Account::frequent_contacts()`].
+///
+/// If the search term is empty, only the contacts in direct messages are
+/// returned.
+///
+/// To create such a search, use [`Client::user_search()`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use matrix_sdk::Client;
+/// # use url::Url;
+/// # async {
+/// # let homeserver = Url::parse("http://localhost:8080")?;
+/// # let client = Client::new(homeserver).await?;
+/// let mut search = client.user_search("ali").batch_size(10);
+///
+/// while !search.is_at_end() {
+///     for user in search.next_page().await? {
+///         println!("{} ({:?})", user.user_id, user.display_name);
+///     }
+/// }
+/// # anyhow::Ok(()) };
+/// ```
+///
+/// [user directory]: https://spec.matrix.org/v1.6/client-server-api/#user-directory
+/// [`Account::frequent_contacts()`]: crate::Account::frequent_contacts
+#[derive(Debug)]
+pub struct UserSearch {
+    client: Client,
+    search_term: String,
+    batch_size: usize,
+
+    /// Whether the users known locally were loaded.
+    local_users_loaded: bool,
+
+    /// The limit used in the last request to the user directory, or `None` if
+    /// the user directory must not be searched anymore.
+    directory_limit: Option<usize>,
+
+    /// The results that were not returned yet, sorted by rank.
+    pending: BTreeMap<PendingKey, UserSearchResult>,
+
+    /// The keys of the users in `pending`.
+    pending_keys: HashMap<OwnedUserId, PendingKey>,
+
+    /// The users that were found so far.
+    found: HashSet<OwnedUserId>,
+
+    /// The number of results that were found so far, to keep the results with
+    /// the same rank in the order they were found.
+    found_count: usize,
+}
+
+/// The rank of a pending result of a [`UserSearch`].
+type PendingKey = (MatchQuality, UserSearchSource, usize);
+
+impl UserSearch {
+    pub(crate) fn new(client: Client, search_term: &str) -> Self {
+        let search_term = search_term.trim().to_lowercase();
+        let directory_limit = (!search_term.is_empty()).then_some(0);
+
+        Self {
+            client,
+            search_term,
+            batch_size: DEFAULT_BATCH_SIZE,
+            local_users_loaded: false,
+            directory_limit,
+            pending: BTreeMap::new(),
+            pending_keys: HashMap::new(),
+            found: HashSet::new(),
+            found_count: 0,
+        }
+    }
+
+    /// Set the number of results returned by [`UserSearch::next_page()`].
+    ///
+    /// Defaults to 20.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Get the next page of results.
+    ///
+    /// Returns an empty list once the search is at its end.
+    pub async fn next_page(&mut self) -> Result<Vec<UserSearchResult>> {
+        if !self.local_users_loaded {
+            self.load_local_users().await?;
+            self.local_users_loaded = true;
+        }
+
+        while self.pending.len() < self.batch_size && self.search_directory().await? {}
+
+        let mut page = Vec::with_capacity(self.batch_size.min(self.pending.len()));
+        while page.len() < self.batch_size {
+            let Some((_, result)) = self.pending.pop_first() else { break };
+            self.pending_keys.remove(&result.user_id);
+            page.push(result);
+        }
+
+        Ok(page)
+    }
+
+    /// Whether all the results of the search were returned.
+    pub fn is_at_end(&self) -> bool {
+        self.local_users_loaded && self.directory_limit.is_none() && self.pending.is_empty()
+    }
+
+    /// Load the contacts in direct messages and the members of the joined rooms
+    /// that match the search term.
+    async fn load_local_users(&mut self) -> Result<()> {
+        let members = self.client.known_users().await?;
+
+        // The contacts come first, sorted by decreasing use.
+        let contacts = self.client.account().frequent_contacts(usize::MAX).await?;
+        for user_id in contacts {
+            let user = members.get(&user_id).cloned().unwrap_or_default();
+            self.push_local_user(user_id, user, UserSearchSource::DirectMessage);
+        }
+
+        if !self.search_term.is_empty() {
+            for (user_id, user) in members.iter() {
+                if !self.found.contains(user_id) {
+                    self.push_local_user(
+                        user_id.clone(),
+                        user.clone(),
+                        UserSearchSource::RoomMember,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the given user known locally to the results, if they match the
+    /// search term.
+    fn push_local_user(&mut self, user_id: OwnedUserId, user: KnownUser, source: UserSearchSource) {
+        let Some(quality) =
+            MatchQuality::compute(&self.search_term, &user_id, user.display_name.as_deref())
+        else {
+            return;
+        };
+
+        self.found.insert(user_id.clone());
+        self.push_pending(
+            quality,
+            UserSearchResult {
+                user_id,
+                display_name: user.display_name,
+                avatar_url: user.avatar_url,
+                source,
+            },
+        );
+    }
+
+    fn push_pending(&mut self, quality: MatchQuality, result: UserSearchResult) {
+        let key = (quality, result.source, self.found_count);
+        self.found_count += 1;
+        self.pending_keys.insert(result.user_id.clone(), key);
+        self.pending.insert(key, result);
+    }
+
+    /// Ask the user directory for more users, if it might have some.
+    ///
+    /// Returns whether new users were found.
+    async fn search_directory(&mut self) -> Result<bool> {
+        let Some(previous_limit) = self.directory_limit else {
+            return Ok(false);
+        };
+
+        // The user directory doesn't support pagination, so ask for more results
+        // every time and ignore the ones that were already found.
+        let limit = previous_limit + self.batch_size;
+        let response = self.client.search_user_directory(&self.search_term, limit as u64).await?;
+        self.directory_limit = response.limited.then_some(limit);
+
+        let own_user_id = self.client.user_id();
+        let mut found_new_users = false;
+
+        for user in response.results {
+            if Some(&*user.user_id) == own_user_id {
+                continue;
+            }
+
+            if !self.found.insert(user.user_id.clone()) {
+                // Fill the details that were missing locally, if the user wasn't returned
+                // yet.
+                if let Some(result) =
+                    self.pending_keys.get(&user.user_id).and_then(|key| self.pending.get_mut(key))
+                {
+                    result.display_name = result.display_name.take().or(user.display_name);
+                    result.avatar_url = result.avatar_url.take().or(user.avatar_url);
+                }
+
+                continue;
+            }
+
+            let quality = MatchQuality::compute(
+                &self.search_term,
+                &user.user_id,
+                user.display_name.as_deref(),
+            )
+            // The homeserver might match the users on other criteria.
+            .unwrap_or(MatchQuality::Partial);
+
+            found_new_users = true;
+            self.push_pending(
+                quality,
+                UserSearchResult {
+                    user_id: user.user_id,
+                    display_name: user.display_name,
+                    avatar_url: user.avatar_url,
+                    source: UserSearchSource::Directory,
+                },
+            );
+        }
+
+        Ok(found_new_users)
+    }
+}
+
+impl Client {
+    /// Get the members of the joined rooms, from the cache or, if they are
+    /// outdated, from the store.
+    async fn known_users(&self) -> Result<Arc<BTreeMap<OwnedUserId, KnownUser>>> {
+        let cache = &self.inner.caches.known_users;
+        self.enable_known_users_invalidation();
+
+        let generation = {
+            let users = cache.users.lock().unwrap();
+            if let Some(members) = &users.members {
+                return Ok(members.clone());
+            }
+            users.generation
+        };
+
+        let own_user_id = self.user_id();
+        let mut members = BTreeMap::<OwnedUserId, KnownUser>::new();
+
+        for room in self.joined_rooms() {
+            for member in room.members_no_sync(RoomMemberships::JOIN).await? {
+                if Some(member.user_id()) == own_user_id {
+                    continue;
+                }
+
+                let user = members.entry(member.user_id().to_owned()).or_default();
+                user.display_name = user
+                    .display_name
+                    .take()
+                    .or_else(|| member.display_name().map(ToOwned::to_owned));
+                user.avatar_url =
+                    user.avatar_url.take().or_else(|| member.avatar_url().map(ToOwned::to_owned));
+            }
+        }
+
+        let members = Arc::new(members);
+
+        // Don't cache the members if they changed while they were loaded.
+        let mut users = cache.users.lock().unwrap();
+        if users.generation == generation {
+            users.members = Some(members.clone());
+        }
+
+        Ok(members)
+    }
+
+    /// Register the event handler that invalidates the cached members of the
+    /// joined rooms when they change, if it's not registered yet.
+    fn enable_known_users_invalidation(&self) {
+        if self.inner.caches.known_users.invalidation_enabled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.add_event_handler(|_: SyncRoomMemberEvent, client: Client| async move {
+            client.inner.caches.known_users.invalidate();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::user_id;
+
+    use super::MatchQuality;
+
+    #[test]
+    fn test_match_quality() {
+        let user_id = user_id!("@alice:example.org");
+
+        assert_eq!(
+            MatchQuality::compute("alice", user_id, Some("Alice Liddell")),
+            Some(MatchQuality::Exact)
+        );
+        assert_eq!(
+            MatchQuality::compute("alice liddell", user_id, Some("Alice Liddell")),
+            Some(MatchQuality::Exact)
+        );
+        assert_eq!(
+            MatchQuality::compute("lid", user_id, Some("Alice Liddell")),
+            Some(MatchQuality::Prefix)
+        );
+        assert_eq!(MatchQuality::compute("@ali", user_id, None), Some(MatchQuality::Prefix));
+        assert_eq!(
+            MatchQuality::compute("example", user_id, Some("Alice")),
+            Some(MatchQuality::Partial)
+        );
+        assert_eq!(MatchQuality::compute("bob", user_id, Some("Alice")), None);
+    }
+}
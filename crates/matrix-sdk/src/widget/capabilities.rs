@@ -24,6 +24,7 @@ use tracing::{debug, error};
 
 use super::{
     filter::MatrixEventFilterInput, EventFilter, MessageLikeEventFilter, StateEventFilter,
+    ToDeviceEventFilter,
 };
 
 /// Must be implemented by a component that provides functionality of deciding
@@ -42,6 +43,9 @@ pub trait CapabilitiesProvider: Send + Sync + 'static {
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Capabilities {
     /// Types of the messages that a widget wants to be able to fetch.
+    ///
+    /// To-device filters allow the widget to receive the matching to-device
+    /// events.
     pub read: Vec<EventFilter>,
     /// Types of the messages that a widget wants to be able to send.
     pub send: Vec<EventFilter>,
@@ -76,6 +80,8 @@ const SEND_EVENT: &str = "org.matrix.msc2762.send.event";
 const READ_EVENT: &str = "org.matrix.msc2762.receive.event";
 const SEND_STATE: &str = "org.matrix.msc2762.send.state_event";
 const READ_STATE: &str = "org.matrix.msc2762.receive.state_event";
+const SEND_TO_DEVICE: &str = "org.matrix.msc3819.send.to_device";
+const READ_TO_DEVICE: &str = "org.matrix.msc3819.receive.to_device";
 const REQUIRES_CLIENT: &str = "io.element.requires_client";
 pub(super) const SEND_DELAYED_EVENT: &str = "org.matrix.msc4157.send.delayed_event";
 pub(super) const UPDATE_DELAYED_EVENT: &str = "org.matrix.msc4157.update_delayed_event";
@@ -91,6 +97,7 @@ impl Serialize for Capabilities {
                 match self.0 {
                     EventFilter::MessageLike(filter) => PrintMessageLikeEventFilter(filter).fmt(f),
                     EventFilter::State(filter) => PrintStateEventFilter(filter).fmt(f),
+                    EventFilter::ToDevice(filter) => write!(f, "{}", filter.event_type),
                 }
             }
         }
@@ -138,6 +145,7 @@ impl Serialize for Capabilities {
             let name = match filter {
                 EventFilter::MessageLike(_) => READ_EVENT,
                 EventFilter::State(_) => READ_STATE,
+                EventFilter::ToDevice(_) => READ_TO_DEVICE,
             };
             seq.serialize_element(&format!("{name}:{}", PrintEventFilter(filter)))?;
         }
@@ -145,6 +153,7 @@ impl Serialize for Capabilities {
             let name = match filter {
                 EventFilter::MessageLike(_) => SEND_EVENT,
                 EventFilter::State(_) => SEND_STATE,
+                EventFilter::ToDevice(_) => SEND_TO_DEVICE,
             };
             seq.serialize_element(&format!("{name}:{}", PrintEventFilter(filter)))?;
         }
@@ -196,6 +205,12 @@ impl<'de> Deserialize<'de> for Capabilities {
                     Some((SEND_STATE, filter_s)) => {
                         Ok(Permission::Send(EventFilter::State(parse_state_event_filter(filter_s))))
                     }
+                    Some((READ_TO_DEVICE, event_type)) => Ok(Permission::Read(
+                        EventFilter::ToDevice(ToDeviceEventFilter::new(event_type.into())),
+                    )),
+                    Some((SEND_TO_DEVICE, event_type)) => Ok(Permission::Send(
+                        EventFilter::ToDevice(ToDeviceEventFilter::new(event_type.into())),
+                    )),
                     _ => {
                         debug!("Unknown capability `{s}`");
                         Ok(Self::Unknown)
@@ -265,6 +280,8 @@ mod tests {
             "org.matrix.msc2762.receive.state_event:org.matrix.msc3401.call.member",
             "org.matrix.msc2762.send.event:org.matrix.rageshake_request",
             "org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member#@user:matrix.server",
+            "org.matrix.msc3819.receive.to_device:io.element.call.encryption_keys",
+            "org.matrix.msc3819.send.to_device:io.element.call.encryption_keys",
             "org.matrix.msc4157.send.delayed_event",
             "org.matrix.msc4157.update_delayed_event"
         ]"#;
@@ -279,6 +296,9 @@ mod tests {
                 EventFilter::State(StateEventFilter::WithType(
                     "org.matrix.msc3401.call.member".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.call.encryption_keys".into(),
+                )),
             ],
             send: vec![
                 EventFilter::MessageLike(MessageLikeEventFilter::WithType(
@@ -288,6 +308,9 @@ mod tests {
                     "org.matrix.msc3401.call.member".into(),
                     "@user:matrix.server".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.call.encryption_keys".into(),
+                )),
             ],
             requires_client: true,
             update_delayed_event: true,
@@ -318,6 +341,7 @@ mod tests {
                     "org.matrix.msc3401.call.member".into(),
                     "@user:matrix.server".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new("io.element.custom".into())),
             ],
             requires_client: true,
             update_delayed_event: false,
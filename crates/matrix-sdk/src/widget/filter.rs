@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType};
+use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType, ToDeviceEventType};
 use serde::Deserialize;
 
-/// Different kinds of filters for timeline events.
+/// Different kinds of filters for timeline and to-device events.
 #[derive(Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 pub enum EventFilter {
@@ -23,6 +23,8 @@ pub enum EventFilter {
     MessageLike(MessageLikeEventFilter),
     /// Filter for state events.
     State(StateEventFilter),
+    /// Filter for to-device events.
+    ToDevice(ToDeviceEventFilter),
 }
 
 impl EventFilter {
@@ -30,6 +32,8 @@ impl EventFilter {
         match self {
             EventFilter::MessageLike(message_filter) => message_filter.matches(matrix_event),
             EventFilter::State(state_filter) => state_filter.matches(matrix_event),
+            // To-device events are never part of a room.
+            EventFilter::ToDevice(_) => false,
         }
     }
 
@@ -52,6 +56,10 @@ impl EventFilter {
             Self::MessageLike(filter) if filter.matches_message_like_event_type(event_type)
         )
     }
+
+    pub(super) fn matches_to_device_event_type(&self, event_type: &ToDeviceEventType) -> bool {
+        matches!(self, Self::ToDevice(filter) if filter.event_type == *event_type)
+    }
 }
 
 /// Filter for message-like events.
@@ -125,6 +133,21 @@ impl StateEventFilter {
     }
 }
 
+/// Filter for to-device events.
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct ToDeviceEventFilter {
+    /// The type of the matching to-device events.
+    pub event_type: ToDeviceEventType,
+}
+
+impl ToDeviceEventFilter {
+    /// Create a filter matching the to-device events with the given `type`.
+    pub fn new(event_type: ToDeviceEventType) -> Self {
+        Self { event_type }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(super) struct MatrixEventFilterInput {
     #[serde(rename = "type")]
@@ -140,11 +163,13 @@ pub(super) struct MatrixEventContent {
 
 #[cfg(test)]
 mod tests {
-    use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType};
+    use ruma::events::{
+        MessageLikeEventType, StateEventType, TimelineEventType, ToDeviceEventType,
+    };
 
     use super::{
         EventFilter, MatrixEventContent, MatrixEventFilterInput, MessageLikeEventFilter,
-        StateEventFilter,
+        StateEventFilter, ToDeviceEventFilter,
     };
 
     fn message_event(event_type: TimelineEventType) -> MatrixEventFilterInput {
@@ -370,4 +395,15 @@ mod tests {
             !room_message_filter().matches_message_like_event_type(&MessageLikeEventType::Reaction)
         );
     }
+
+    #[test]
+    fn to_device_event_filter_matches_its_event_type_only() {
+        let filter = EventFilter::ToDevice(ToDeviceEventFilter::new(
+            "io.element.call.encryption_keys".into(),
+        ));
+
+        assert!(filter.matches_to_device_event_type(&"io.element.call.encryption_keys".into()));
+        assert!(!filter.matches_to_device_event_type(&ToDeviceEventType::RoomKey));
+        assert!(!filter.matches(&message_event("io.element.call.encryption_keys".into())));
+    }
 }
@@ -14,12 +14,20 @@
 
 //! A high-level API for requests that we send to the matrix driver.
 
-use std::marker::PhantomData;
+use std::{collections::BTreeMap, marker::PhantomData};
 
 use ruma::{
-    api::client::{account::request_openid_token, delayed_events::update_delayed_event},
-    events::{AnyTimelineEvent, MessageLikeEventType, StateEventType, TimelineEventType},
+    api::client::{
+        account::request_openid_token, delayed_events::update_delayed_event,
+        to_device::send_event_to_device,
+    },
+    events::{
+        AnyTimelineEvent, AnyToDeviceEventContent, MessageLikeEventType, StateEventType,
+        TimelineEventType, ToDeviceEventType,
+    },
     serde::Raw,
+    to_device::DeviceIdOrAllDevices,
+    OwnedUserId,
 };
 use serde::Deserialize;
 use serde_json::value::RawValue as RawJsonValue;
@@ -54,6 +62,9 @@ pub(crate) enum MatrixDriverRequestData {
 
     /// Data for sending a UpdateDelayedEvent client server api request.
     UpdateDelayedEvent(UpdateDelayedEventRequest),
+
+    /// Send to-device events to the given devices.
+    SendToDeviceEvent(SendToDeviceRequest),
 }
 
 /// A handle to a pending `toWidget` request.
@@ -282,3 +293,39 @@ impl FromMatrixDriverResponse for update_delayed_event::unstable::Response {
         }
     }
 }
+
+/// The to-device events to send, as defined by
+/// [MSC3819](https://github.com/matrix-org/matrix-spec-proposals/pull/3819).
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct SendToDeviceRequest {
+    /// The type of the to-device events.
+    #[serde(rename = "type")]
+    pub(crate) event_type: ToDeviceEventType,
+    /// Whether the events must be encrypted for each device before being sent.
+    pub(crate) encrypted: bool,
+    /// The content of the event to send to each device of each user.
+    pub(crate) messages:
+        BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>>,
+}
+
+impl From<SendToDeviceRequest> for MatrixDriverRequestData {
+    fn from(value: SendToDeviceRequest) -> Self {
+        MatrixDriverRequestData::SendToDeviceEvent(value)
+    }
+}
+
+impl MatrixDriverRequest for SendToDeviceRequest {
+    type Response = send_event_to_device::v3::Response;
+}
+
+impl FromMatrixDriverResponse for send_event_to_device::v3::Response {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::ToDeviceSent(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
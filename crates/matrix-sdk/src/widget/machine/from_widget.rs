@@ -24,7 +24,7 @@ use ruma::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::{SendEventRequest, UpdateDelayedEventRequest};
+use super::{SendEventRequest, SendToDeviceRequest, UpdateDelayedEventRequest};
 use crate::{widget::StateKeySelector, Error, HttpError, RumaApiError};
 
 #[derive(Deserialize, Debug)]
@@ -39,6 +39,7 @@ pub(super) enum FromWidgetRequest {
     SendEvent(SendEventRequest),
     #[serde(rename = "org.matrix.msc4157.update_delayed_event")]
     DelayedEventUpdate(UpdateDelayedEventRequest),
+    SendToDevice(SendToDeviceRequest),
 }
 
 /// The full response a client sends to a [`FromWidgetRequest`] in case of an
@@ -13,8 +13,8 @@
 // limitations under the License.
 
 use ruma::{
-    api::client::{account::request_openid_token, delayed_events},
-    events::AnyTimelineEvent,
+    api::client::{account::request_openid_token, delayed_events, to_device::send_event_to_device},
+    events::{AnyTimelineEvent, AnyToDeviceEvent},
     serde::Raw,
 };
 use serde::{de, Deserialize, Deserializer};
@@ -49,6 +49,19 @@ pub(crate) enum IncomingMessage {
     /// This means that the machine previously subscribed to some events
     /// ([`crate::widget::Action::Subscribe`] request).
     MatrixEventReceived(Raw<AnyTimelineEvent>),
+
+    /// The `MatrixDriver` notified the `WidgetMachine` of a new to-device
+    /// event.
+    ///
+    /// Like for [`IncomingMessage::MatrixEventReceived`], the machine must have
+    /// subscribed to the events first.
+    ToDeviceReceived {
+        /// The event, decrypted if it was encrypted.
+        event: Raw<AnyToDeviceEvent>,
+
+        /// Whether the event was received encrypted.
+        encrypted: bool,
+    },
 }
 
 pub(crate) enum MatrixDriverResponse {
@@ -66,6 +79,9 @@ pub(crate) enum MatrixDriverResponse {
     /// A response to an `Action::SendMatrixEvent` command.
     MatrixEventSent(SendEventResponse),
     MatrixDelayedEventUpdate(delayed_events::update_delayed_event::unstable::Response),
+    /// Client sent some to-device events.
+    /// A response to an `Action::SendToDeviceEvent` command.
+    ToDeviceSent(send_event_to_device::v3::Response),
 }
 
 pub(super) struct IncomingWidgetMessage {
@@ -41,8 +41,9 @@ use self::{
     openid::{OpenIdResponse, OpenIdState},
     pending::{PendingRequests, RequestLimits},
     to_widget::{
-        NotifyCapabilitiesChanged, NotifyNewMatrixEvent, NotifyOpenIdChanged, RequestCapabilities,
-        ToWidgetRequest, ToWidgetRequestHandle, ToWidgetResponse,
+        NotifyCapabilitiesChanged, NotifyNewMatrixEvent, NotifyNewToDeviceEvent,
+        NotifyOpenIdChanged, RequestCapabilities, ToWidgetRequest, ToWidgetRequestHandle,
+        ToWidgetResponse,
     },
 };
 #[cfg(doc)]
@@ -64,7 +65,9 @@ mod tests;
 mod to_widget;
 
 pub(crate) use self::{
-    driver_req::{MatrixDriverRequestData, ReadStateEventRequest, SendEventRequest},
+    driver_req::{
+        MatrixDriverRequestData, ReadStateEventRequest, SendEventRequest, SendToDeviceRequest,
+    },
     from_widget::SendEventResponse,
     incoming::{IncomingMessage, MatrixDriverResponse},
 };
@@ -178,6 +181,33 @@ impl WidgetMachine {
                     })
                     .unwrap_or_default()
             }
+
+            IncomingMessage::ToDeviceReceived { event, encrypted } => {
+                let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+                    error!("Received to-device event before capabilities negotiation");
+                    return Vec::new();
+                };
+
+                let mut notification = match event.deserialize_as::<NotifyNewToDeviceEvent>() {
+                    Ok(notification) => notification,
+                    Err(e) => {
+                        error!("Failed to deserialize to-device event: {e}");
+                        return Vec::new();
+                    }
+                };
+
+                if !capabilities
+                    .read
+                    .iter()
+                    .any(|f| f.matches_to_device_event_type(&notification.event_type))
+                {
+                    return Vec::new();
+                }
+
+                notification.encrypted = encrypted;
+                let action = self.send_to_widget_request(notification).1;
+                action.map(|a| vec![a]).unwrap_or_default()
+            }
         }
     }
 
@@ -299,6 +329,11 @@ impl WidgetMachine {
 
                 request_action.map(|a| vec![a]).unwrap_or_default()
             }
+
+            FromWidgetRequest::SendToDevice(req) => self
+                .process_send_to_device_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
         }
     }
 
@@ -443,6 +478,36 @@ impl WidgetMachine {
         action
     }
 
+    fn process_send_to_device_request(
+        &mut self,
+        request: SendToDeviceRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Received send to-device request before capabilities were negotiated",
+            ));
+        };
+
+        if !capabilities.send.iter().any(|f| f.matches_to_device_event_type(&request.event_type)) {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                format!("Not allowed to send to-device events of type {}", request.event_type),
+            ));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request);
+        request.then(|result, _machine| {
+            vec![Self::send_from_widget_response(
+                raw_request,
+                result.map(|_| JsonObject::new()).map_err(FromWidgetErrorResponse::from_error),
+            )]
+        });
+
+        action
+    }
+
     #[instrument(skip_all, fields(?request_id))]
     fn process_to_widget_response(
         &mut self,
@@ -14,7 +14,11 @@
 
 use std::marker::PhantomData;
 
-use ruma::{events::AnyTimelineEvent, serde::Raw};
+use ruma::{
+    events::{AnyTimelineEvent, ToDeviceEventType},
+    serde::Raw,
+    OwnedUserId,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::value::RawValue as RawJsonValue;
 use tracing::error;
@@ -128,5 +132,23 @@ impl ToWidgetRequest for NotifyNewMatrixEvent {
     type ResponseData = Empty;
 }
 
+/// A to-device event received by the client, as defined by
+/// [MSC3819](https://github.com/matrix-org/matrix-spec-proposals/pull/3819).
+#[derive(Deserialize, Serialize)]
+pub(crate) struct NotifyNewToDeviceEvent {
+    #[serde(rename = "type")]
+    pub(crate) event_type: ToDeviceEventType,
+    pub(crate) sender: OwnedUserId,
+    pub(crate) content: Box<RawJsonValue>,
+    /// Whether the event was received encrypted.
+    #[serde(default)]
+    pub(crate) encrypted: bool,
+}
+
+impl ToWidgetRequest for NotifyNewToDeviceEvent {
+    const ACTION: &'static str = "send_to_device";
+    type ResponseData = Empty;
+}
+
 #[derive(Deserialize)]
 pub(crate) struct Empty {}
@@ -16,28 +16,44 @@
 //! that is relevant for the widget API.
 
 use std::collections::BTreeMap;
+#[cfg(feature = "e2e-encryption")]
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
+#[cfg(feature = "e2e-encryption")]
+use futures_util::{stream::BoxStream, FutureExt, StreamExt};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_base::crypto::store::DecryptedToDeviceEvent;
 use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
 use ruma::{
     api::client::{
         account::request_openid_token::v3::{Request as OpenIdRequest, Response as OpenIdResponse},
         delayed_events::{self, update_delayed_event::unstable::UpdateAction},
         filter::RoomEventFilter,
+        to_device::send_event_to_device,
     },
     assign,
     events::{
         AnyMessageLikeEventContent, AnyStateEventContent, AnySyncMessageLikeEvent,
-        AnySyncStateEvent, AnySyncTimelineEvent, AnyTimelineEvent, MessageLikeEventType,
-        StateEventType, TimelineEventType,
+        AnySyncStateEvent, AnySyncTimelineEvent, AnyTimelineEvent, AnyToDeviceEvent,
+        AnyToDeviceEventContent, MessageLikeEventType, StateEventType, TimelineEventType,
+        ToDeviceEventType,
     },
     serde::{from_raw_json_value, Raw},
-    EventId, RoomId, TransactionId,
+    to_device::DeviceIdOrAllDevices,
+    EventId, OwnedUserId, RoomId, TransactionId,
 };
 use serde_json::{value::RawValue as RawJsonValue, Value};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 use tracing::error;
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
 
 use super::{machine::SendEventResponse, StateKeySelector};
+#[cfg(feature = "e2e-encryption")]
+use crate::encryption::identities::Device;
 use crate::{event_handler::EventHandlerDropGuard, room::MessagesOptions, Error, Result, Room};
 
 /// Thin wrapper around a [`Room`] that provides functionality relevant for
@@ -179,9 +195,95 @@ impl MatrixDriver {
         self.room.client.send(r).await.map_err(|error| Error::Http(Box::new(error)))
     }
 
+    /// Sends the given to-device events ([MSC3819](https://github.com/matrix-org/matrix-spec-proposals/pull/3819)).
+    ///
+    /// If `encrypted` is set, the content is encrypted for each recipient
+    /// device before being sent.
+    pub(crate) async fn send_to_device(
+        &self,
+        event_type: ToDeviceEventType,
+        encrypted: bool,
+        messages: BTreeMap<
+            OwnedUserId,
+            BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>,
+        >,
+    ) -> Result<send_event_to_device::v3::Response> {
+        if encrypted {
+            #[cfg(feature = "e2e-encryption")]
+            return self.encrypt_and_send_to_device(event_type, messages).await;
+
+            #[cfg(not(feature = "e2e-encryption"))]
+            return Err(Error::UnknownError(
+                "Encrypted to-device events require the `e2e-encryption` feature".into(),
+            ));
+        }
+
+        let request =
+            send_event_to_device::v3::Request::new_raw(event_type, TransactionId::new(), messages);
+        self.room.client.send(request).await.map_err(|error| Error::Http(Box::new(error)))
+    }
+
+    /// Encrypts the given to-device events for each recipient device, and
+    /// sends them.
+    ///
+    /// The devices we can't establish an Olm session with are skipped.
+    #[cfg(feature = "e2e-encryption")]
+    async fn encrypt_and_send_to_device(
+        &self,
+        event_type: ToDeviceEventType,
+        messages: BTreeMap<
+            OwnedUserId,
+            BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>,
+        >,
+    ) -> Result<send_event_to_device::v3::Response> {
+        let encryption = self.room.client.encryption();
+
+        // Group the recipient devices by content, to encrypt each content only once.
+        let mut recipients: Vec<(Raw<AnyToDeviceEventContent>, Vec<Device>)> = Vec::new();
+
+        for (user_id, device_messages) in messages {
+            for (device, content) in device_messages {
+                let devices: Vec<_> = match device {
+                    DeviceIdOrAllDevices::DeviceId(device_id) => {
+                        encryption.get_device(&user_id, &device_id).await?.into_iter().collect()
+                    }
+                    DeviceIdOrAllDevices::AllDevices => {
+                        encryption.get_user_devices(&user_id).await?.devices().collect()
+                    }
+                };
+
+                if devices.is_empty() {
+                    warn!(?user_id, "No known device to send the encrypted to-device event to");
+                    continue;
+                }
+
+                match recipients.iter_mut().find(|(c, _)| c.json().get() == content.json().get()) {
+                    Some((_, recipient_devices)) => recipient_devices.extend(devices),
+                    None => recipients.push((content, devices)),
+                }
+            }
+        }
+
+        for (content, devices) in recipients {
+            let failed = encryption
+                .encrypt_and_send_raw_to_device(
+                    devices.iter().collect(),
+                    &event_type.to_string(),
+                    content,
+                )
+                .await?;
+
+            if !failed.is_empty() {
+                warn!(?failed, "Couldn't encrypt the to-device event for some devices");
+            }
+        }
+
+        Ok(send_event_to_device::v3::Response::new())
+    }
+
     /// Starts forwarding new room events. Once the returned `EventReceiver`
     /// is dropped, forwarding will be stopped.
-    pub(crate) fn events(&self) -> EventReceiver {
+    pub(crate) fn events(&self) -> EventReceiver<Raw<AnyTimelineEvent>> {
         let (tx, rx) = unbounded_channel();
         let room_id = self.room.room_id().to_owned();
 
@@ -208,25 +310,167 @@ impl MatrixDriver {
         // section of the sync will not be forwarded to the widget.
         // TODO annotate the events and send both timeline and state section state
         // events.
-        EventReceiver { rx, _drop_guards: [drop_guard_msg_like, drop_guard_state] }
+        EventReceiver { rx, _drop_guards: vec![drop_guard_msg_like, drop_guard_state] }
+    }
+
+    /// Starts forwarding new to-device events, along with whether they were
+    /// received encrypted. Once the returned `EventReceiver` is dropped,
+    /// forwarding will be stopped.
+    pub(crate) async fn to_device_events(&self) -> EventReceiver<(Raw<AnyToDeviceEvent>, bool)> {
+        let (tx, rx) = unbounded_channel();
+
+        #[cfg(feature = "e2e-encryption")]
+        let decrypted_events = DecryptedEventsTracker::new(&self.room.client).await;
+
+        let handle = self.room.client().add_event_handler(move |raw: Raw<AnyToDeviceEvent>| {
+            #[cfg(feature = "e2e-encryption")]
+            let encrypted = decrypted_events.was_decrypted(&raw);
+            #[cfg(not(feature = "e2e-encryption"))]
+            let encrypted = false;
+
+            let _ = tx.send((raw, encrypted));
+            async {}
+        });
+        let drop_guard = self.room.client().event_handler_drop_guard(handle);
+
+        EventReceiver { rx, _drop_guards: vec![drop_guard] }
     }
 }
 
 /// A simple entity that wraps an `UnboundedReceiver`
-/// along with the drop guard for the room event handler.
-pub(crate) struct EventReceiver {
-    rx: UnboundedReceiver<Raw<AnyTimelineEvent>>,
-    _drop_guards: [EventHandlerDropGuard; 2],
+/// along with the drop guards for the event handlers.
+pub(crate) struct EventReceiver<E> {
+    rx: UnboundedReceiver<E>,
+    _drop_guards: Vec<EventHandlerDropGuard>,
 }
 
-impl EventReceiver {
-    pub(crate) async fn recv(&mut self) -> Option<Raw<AnyTimelineEvent>> {
+impl<E> EventReceiver<E> {
+    pub(crate) async fn recv(&mut self) -> Option<E> {
         self.rx.recv().await
     }
 }
 
+/// The maximum number of decrypted to-device events that are kept until they
+/// are passed to the event handler.
+#[cfg(feature = "e2e-encryption")]
+const MAX_PENDING_DECRYPTED_EVENTS: usize = 256;
+
+/// Tells which of the to-device events passed to the event handlers were
+/// received encrypted.
+///
+/// The to-device event handlers get the decrypted events, without any hint
+/// that they were encrypted. The `OlmMachine` notifies the decrypted events
+/// before the handlers are called, so they are looked up in its stream, by
+/// their ID.
+#[cfg(feature = "e2e-encryption")]
+#[derive(Clone)]
+struct DecryptedEventsTracker {
+    inner: Arc<Mutex<DecryptedEventsTrackerInner>>,
+}
+
+#[cfg(feature = "e2e-encryption")]
+struct DecryptedEventsTrackerInner {
+    stream: Option<BoxStream<'static, Vec<DecryptedToDeviceEvent>>>,
+
+    /// The IDs of the decrypted events that weren't passed to the event
+    /// handler yet, the oldest first.
+    pending: VecDeque<String>,
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl DecryptedEventsTracker {
+    async fn new(client: &crate::Client) -> Self {
+        let stream = client
+            .olm_machine()
+            .await
+            .as_ref()
+            .map(|olm| olm.store().decrypted_to_device_events_stream().boxed());
+
+        Self {
+            inner: Arc::new(Mutex::new(DecryptedEventsTrackerInner {
+                stream,
+                pending: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Whether the given event, passed to the event handler, was decrypted.
+    fn was_decrypted(&self, raw: &Raw<AnyToDeviceEvent>) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let DecryptedEventsTrackerInner { stream, pending } = &mut *inner;
+
+        if let Some(stream) = stream {
+            while let Some(Some(events)) = stream.next().now_or_never() {
+                pending.extend(events.iter().map(|event| to_device_event_id(&event.raw_event)));
+            }
+        }
+
+        // The events that are not passed to the event handler, because they can't be
+        // deserialized for example, must not be kept forever.
+        if pending.len() > MAX_PENDING_DECRYPTED_EVENTS {
+            pending.drain(..pending.len() - MAX_PENDING_DECRYPTED_EVENTS);
+        }
+
+        let event_id = to_device_event_id(raw);
+        match pending.iter().position(|id| *id == event_id) {
+            Some(index) => {
+                pending.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Get an ID for the given to-device event.
+///
+/// To-device events don't have an event ID, so it is the message ID set by the
+/// sender in the content, if any, or the JSON of the event.
+#[cfg(feature = "e2e-encryption")]
+fn to_device_event_id(raw: &Raw<AnyToDeviceEvent>) -> String {
+    raw.get_field::<BTreeMap<String, serde_json::Value>>("content")
+        .ok()
+        .flatten()
+        .and_then(|mut content| match content.remove("org.matrix.msgid") {
+            Some(serde_json::Value::String(message_id)) => Some(message_id),
+            _ => None,
+        })
+        .unwrap_or_else(|| raw.json().get().to_owned())
+}
+
 fn attach_room_id(raw_ev: &Raw<AnySyncTimelineEvent>, room_id: &RoomId) -> Raw<AnyTimelineEvent> {
     let mut ev_obj = raw_ev.deserialize_as::<BTreeMap<String, Box<RawJsonValue>>>().unwrap();
     ev_obj.insert("room_id".to_owned(), serde_json::value::to_raw_value(room_id).unwrap());
     Raw::new(&ev_obj).unwrap().cast()
 }
+
+#[cfg(all(test, feature = "e2e-encryption"))]
+mod tests {
+    use ruma::serde::Raw;
+    use serde_json::json;
+
+    use super::to_device_event_id;
+
+    #[test]
+    fn test_to_device_event_id() {
+        // The message ID is used when there is one, so the event is matched even if its
+        // JSON was serialized again.
+        let raw = Raw::new(&json!({
+            "type": "io.element.call.encryption_keys",
+            "sender": "@alice:localhost",
+            "content": { "keys": [], "org.matrix.msgid": "abcdef" },
+        }))
+        .unwrap()
+        .cast();
+        assert_eq!(to_device_event_id(&raw), "abcdef");
+
+        let raw = Raw::new(&json!({
+            "type": "io.element.call.encryption_keys",
+            "sender": "@alice:localhost",
+            "content": { "keys": [] },
+        }))
+        .unwrap()
+        .cast();
+        assert_eq!(to_device_event_id(&raw), raw.json().get());
+    }
+}
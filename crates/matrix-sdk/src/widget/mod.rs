@@ -25,7 +25,7 @@ use tokio_util::sync::{CancellationToken, DropGuard};
 use self::{
     machine::{
         Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, SendEventRequest,
-        WidgetMachine,
+        SendToDeviceRequest, WidgetMachine,
     },
     matrix::MatrixDriver,
 };
@@ -39,7 +39,7 @@ mod settings;
 
 pub use self::{
     capabilities::{Capabilities, CapabilitiesProvider},
-    filter::{EventFilter, MessageLikeEventFilter, StateEventFilter},
+    filter::{EventFilter, MessageLikeEventFilter, StateEventFilter, ToDeviceEventFilter},
     settings::{
         ClientProperties, EncryptionSystem, Intent, VirtualElementCallWidgetOptions, WidgetSettings,
     },
@@ -235,6 +235,14 @@ impl WidgetDriver {
                         .update_delayed_event(req.delay_id, req.action)
                         .await
                         .map(MatrixDriverResponse::MatrixDelayedEventUpdate),
+
+                    MatrixDriverRequestData::SendToDeviceEvent(req) => {
+                        let SendToDeviceRequest { event_type, encrypted, messages } = req;
+                        matrix_driver
+                            .send_to_device(event_type, encrypted, messages)
+                            .await
+                            .map(MatrixDriverResponse::ToDeviceSent)
+                    }
                 };
 
                 // Forward the matrix driver response to the incoming message stream.
@@ -257,6 +265,7 @@ impl WidgetDriver {
                 self.event_forwarding_guard = Some(guard);
 
                 let mut matrix = matrix_driver.events();
+                let mut to_device = matrix_driver.to_device_events().await;
                 let incoming_msg_tx = incoming_msg_tx.clone();
 
                 tokio::spawn(async move {
@@ -271,6 +280,12 @@ impl WidgetDriver {
                                 // Forward all events to the incoming messages stream.
                                 let _ = incoming_msg_tx.send(IncomingMessage::MatrixEventReceived(event));
                             }
+
+                            Some((event, encrypted)) = to_device.recv() => {
+                                // Forward all to-device events to the incoming messages stream.
+                                let message = IncomingMessage::ToDeviceReceived { event, encrypted };
+                                let _ = incoming_msg_tx.send(message);
+                            }
                         }
                     }
                 });
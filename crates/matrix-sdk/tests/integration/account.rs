@@ -1,61 +1,328 @@
+use assert_matches2::{assert_let, assert_matches};
+use futures_util::{pin_mut, StreamExt as _};
+use matrix_sdk::{
+    account::threepid::ThreepidValidationError, test_utils::mocks::MatrixMockServer, SessionChange,
+};
+use matrix_sdk_base::RequestedRequiredStates;
 use matrix_sdk_test::async_test;
-use serde_json::json;
+use ruma::{
+    api::client::{
+        account::ThirdPartyIdRemovalStatus,
+        sync::sync_events::v5 as sliding_sync_http,
+        uiaa::{AuthData, Password, UserIdentifier},
+    },
+    assign,
+    events::ignored_user_list::IgnoredUserListEventContent,
+    owned_room_id, room_id, user_id,
+};
+use serde_json::{from_value, json};
+use stream_assert::assert_pending;
 use wiremock::{
-    matchers::{method, path},
-    Mock, Request, ResponseTemplate,
+    matchers::{body_json, body_partial_json, method, path, path_regex},
+    Mock, ResponseTemplate,
 };
 
-use crate::logged_in_client_with_server;
-
 #[async_test]
 async fn test_account_deactivation() {
-    #[derive(serde::Deserialize)]
-    struct Parameters {
-        pub id_server: Option<String>,
-        pub erase: Option<bool>,
-    }
-
-    let (client, server) = logged_in_client_with_server().await;
-
-    {
-        let _scope = Mock::given(method("POST"))
-            .and(path("/_matrix/client/r0/account/deactivate"))
-            .respond_with(|req: &Request| {
-                let params: Parameters = req.body_json().unwrap();
-                assert_eq!(params.id_server, Some("FirstIdentityServer".to_owned()));
-                assert_eq!(params.erase, None);
-
-                ResponseTemplate::new(200).set_body_json(json!({
-                    "id_server_unbind_result": "success"
-                }))
-            })
-            .expect(1)
-            .mount_as_scoped(&server)
-            .await;
-
-        assert!(client
-            .account()
-            .deactivate(Some("FirstIdentityServer"), None, false)
-            .await
-            .is_ok());
-    }
-
-    {
-        let _scope = Mock::given(method("POST"))
-            .and(path("/_matrix/client/r0/account/deactivate"))
-            .respond_with(|req: &Request| {
-                let params: Parameters = req.body_json().unwrap();
-                assert_eq!(params.id_server, None);
-                assert_eq!(params.erase, Some(true));
-
-                ResponseTemplate::new(200).set_body_json(json!({
-                    "id_server_unbind_result": "success"
-                }))
-            })
-            .expect(1)
-            .mount_as_scoped(&server)
-            .await;
-
-        assert!(client.account().deactivate(None, None, true).await.is_ok());
-    }
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!a:b.c");
+    server.sync_joined_room(&client, room_id).await;
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/account/deactivate$"))
+        .and(body_json(json!({ "erase": true })))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.password"] }],
+            "params": {},
+            "session": "uiaa_session",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/account/deactivate$"))
+        .and(body_partial_json(json!({
+            "auth": { "type": "m.login.password", "session": "uiaa_session" },
+            "erase": true,
+            "id_server": "identity.example.org",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id_server_unbind_result": "success"
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The first request needs the user to authenticate.
+    let error = client.account().deactivate(None, None, true).await.unwrap_err();
+    let info = error.as_uiaa_response().unwrap();
+    assert_eq!(info.session.as_deref(), Some("uiaa_session"));
+
+    // The session hasn't been touched.
+    assert!(client.get_room(room_id).is_some());
+    assert!(session_changes.try_recv().is_err());
+
+    let mut password =
+        Password::new(UserIdentifier::UserIdOrLocalpart("example".to_owned()), "secret".to_owned());
+    password.session = info.session.clone();
+    let response = client
+        .account()
+        .deactivate(Some("identity.example.org"), Some(AuthData::Password(password)), true)
+        .await
+        .unwrap();
+    assert_matches!(response.id_server_unbind_result, ThirdPartyIdRemovalStatus::Success);
+
+    // The session has been torn down.
+    assert_eq!(session_changes.try_recv(), Ok(SessionChange::UnknownToken { soft_logout: false }));
+    assert!(client.get_room(room_id).is_none());
+    #[cfg(feature = "e2e-encryption")]
+    assert!(client.olm_machine_for_testing().await.is_none());
+}
+
+#[async_test]
+async fn test_email_validation() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/account/3pid/email/requestToken$"))
+        .and(body_partial_json(json!({ "email": "john@matrix.org", "send_attempt": 1 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "sid": "validation_session",
+            "submit_url": format!("{}/submit_token", server.server().uri()),
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let validation = client.account().start_email_validation("john@matrix.org").await.unwrap();
+    assert_eq!(validation.address(), "john@matrix.org");
+    assert!(validation.expects_token());
+
+    Mock::given(method("POST"))
+        .and(path("/submit_token"))
+        .and(body_partial_json(json!({ "sid": "validation_session", "token": "123456" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/submit_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": false })))
+        .mount(server.server())
+        .await;
+
+    // A wrong token is rejected, and another one can be submitted.
+    assert_matches!(
+        validation.submit_token("000000").await,
+        Err(ThreepidValidationError::TokenRejected)
+    );
+    let threepid = validation.submit_token("123456").await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/account/3pid/add$"))
+        .and(body_partial_json(json!({ "sid": "validation_session" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    threepid.add(None).await.unwrap();
+}
+
+#[async_test]
+async fn test_ignore_user() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = user_id!("@spammer:example.org");
+
+    assert!(client.ignored_users().is_empty());
+    let (_, ignored_users_stream) = client.subscribe_to_ignored_users();
+    pin_mut!(ignored_users_stream);
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.ignored_user_list$"))
+        .and(body_json(json!({ "ignored_users": { user_id: {} } })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The ignored user list is updated without waiting for the sync.
+    client.account().ignore_user(user_id).await.unwrap();
+    assert_eq!(client.ignored_users(), [user_id]);
+    assert_let!(Some(ignored_users) = ignored_users_stream.next().await);
+    assert_eq!(ignored_users, [user_id]);
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.ignored_user_list$"))
+        .and(body_json(json!({ "ignored_users": {} })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    client.account().unignore_user(user_id).await.unwrap();
+    assert!(client.ignored_users().is_empty());
+    assert_let!(Some(ignored_users) = ignored_users_stream.next().await);
+    assert!(ignored_users.is_empty());
+}
+
+#[async_test]
+async fn test_observe_account_data() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let ignored_user_list = |user_id: &str| {
+        from_value(json!({
+            "type": "m.ignored_user_list",
+            "content": { "ignored_users": { user_id: {} } },
+        }))
+        .unwrap()
+    };
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_bulk([ignored_user_list("@alice:b.c")]);
+        })
+        .await;
+
+    // The observer starts with the content from the store.
+    let observable =
+        client.account().observe_account_data::<IgnoredUserListEventContent>().await.unwrap();
+    assert_let!(Some(content) = observable.get());
+    assert!(content.ignored_users.contains_key(user_id!("@alice:b.c")));
+
+    let mut subscriber = observable.subscribe();
+    assert_pending!(subscriber);
+
+    // Rapid updates are coalesced.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_bulk([ignored_user_list("@bob:b.c")]);
+        })
+        .await;
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_bulk([ignored_user_list("@carl:b.c")]);
+        })
+        .await;
+
+    assert_let!(Some(Some(content)) = subscriber.next().await);
+    assert!(content.ignored_users.contains_key(user_id!("@carl:b.c")));
+    assert_pending!(subscriber);
+
+    // The stream ends when the observer is dropped.
+    drop(observable);
+    assert!(subscriber.next().await.is_none());
+}
+
+#[async_test]
+async fn test_frequent_contacts() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_with_bump_stamp = |bump_stamp: u32| {
+        assign!(sliding_sync_http::response::Room::new(), {
+            initial: Some(true),
+            bump_stamp: Some(bump_stamp.into()),
+        })
+    };
+
+    let mut response = sliding_sync_http::Response::new("0".to_owned());
+    response.rooms.insert(owned_room_id!("!alice:b.c"), room_with_bump_stamp(1));
+    response.rooms.insert(owned_room_id!("!bob:b.c"), room_with_bump_stamp(3));
+    response.rooms.insert(owned_room_id!("!carl:b.c"), room_with_bump_stamp(2));
+    response.extensions.account_data.global = vec![
+        from_value(json!({
+            "type": "m.direct",
+            "content": {
+                "@alice:b.c": ["!alice:b.c"],
+                "@bob:b.c": ["!bob:b.c"],
+                "@carl:b.c": ["!carl:b.c"],
+                "@dan:b.c": ["!dan:b.c"],
+            },
+        }))
+        .unwrap(),
+        from_value(json!({
+            "type": "io.element.frequent_contacts",
+            "content": { "contacts": [["@dan:b.c", 2], ["@alice:b.c", 1]] },
+        }))
+        .unwrap(),
+    ];
+    response.rooms.insert(owned_room_id!("!dan:b.c"), room_with_bump_stamp(0));
+    client
+        .process_sliding_sync_test_helper(&response, &RequestedRequiredStates::default())
+        .await
+        .unwrap();
+
+    // The contacts are ranked by use count first, then by activity.
+    let (contacts, stream) = client.account().subscribe_to_frequent_contacts(3).await.unwrap();
+    pin_mut!(stream);
+    assert_eq!(contacts, [user_id!("@dan:b.c"), user_id!("@alice:b.c"), user_id!("@bob:b.c")]);
+
+    // New activity with Carl moves him above Bob.
+    let mut response = sliding_sync_http::Response::new("1".to_owned());
+    response.rooms.insert(
+        owned_room_id!("!carl:b.c"),
+        assign!(sliding_sync_http::response::Room::new(), { bump_stamp: Some(4u32.into()) }),
+    );
+    client
+        .process_sliding_sync_test_helper(&response, &RequestedRequiredStates::default())
+        .await
+        .unwrap();
+
+    assert_let!(Some(contacts) = stream.next().await);
+    assert_eq!(contacts, [user_id!("@dan:b.c"), user_id!("@alice:b.c"), user_id!("@carl:b.c")]);
+
+    // More messages sent to Alice move her to the top.
+    let mut response = sliding_sync_http::Response::new("2".to_owned());
+    response.extensions.account_data.global = vec![from_value(json!({
+        "type": "io.element.frequent_contacts",
+        "content": { "contacts": [["@alice:b.c", 3], ["@dan:b.c", 2]] },
+    }))
+    .unwrap()];
+    client
+        .process_sliding_sync_test_helper(&response, &RequestedRequiredStates::default())
+        .await
+        .unwrap();
+
+    assert_let!(Some(contacts) = stream.next().await);
+    assert_eq!(contacts, [user_id!("@alice:b.c"), user_id!("@dan:b.c"), user_id!("@carl:b.c")]);
+    assert_eq!(client.account().frequent_contacts(5).await.unwrap().len(), 4);
+}
+
+#[async_test]
+async fn test_push_breadcrumb() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/im.vector.setting.breadcrumbs$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "recent_rooms": ["!a:b.c", "!d:e.f"] })),
+        )
+        .expect(2)
+        .mount(server.server())
+        .await;
+
+    // Only the first push changes the breadcrumbs.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/im.vector.setting.breadcrumbs$"))
+        .and(body_json(json!({ "recent_rooms": ["!d:e.f", "!a:b.c"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let account = client.account();
+    account.push_breadcrumb(room_id!("!d:e.f")).await.unwrap();
+
+    // The room is already the most recent one in the breadcrumbs of the server.
+    account.push_breadcrumb(room_id!("!a:b.c")).await.unwrap();
 }
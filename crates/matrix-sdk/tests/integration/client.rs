@@ -1,21 +1,26 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{collections::BTreeMap, future::IntoFuture, time::Duration};
 
 use assert_matches2::{assert_let, assert_matches};
 use eyeball_im::VectorDiff;
-use futures_util::FutureExt;
+use futures_util::{future::join, FutureExt};
 use matrix_sdk::{
     authentication::oauth::{error::OAuthTokenRevocationError, OAuthError},
     config::{RequestConfig, StoreConfig, SyncSettings},
+    message_search::{MessageSearchOptions, MessageSearchSource},
+    room::builder::RoomBuilderError,
     store::RoomLoadSettings,
     sync::RoomUpdate,
     test_utils::{
         client::mock_matrix_session, mocks::MatrixMockServer, no_retry_test_client_with_server,
     },
+    user_search::UserSearchSource,
     Client, Error, MemoryStore, StateChanges, StateStore,
 };
 use matrix_sdk_base::{sync::RoomUpdates, RoomState};
 use matrix_sdk_test::{
-    async_test, sync_state_event,
+    async_test,
+    event_factory::EventFactory,
+    sync_state_event,
     test_json::{
         self,
         sync::{
@@ -25,7 +30,8 @@ use matrix_sdk_test::{
         sync_events::PINNED_EVENTS,
         TAG,
     },
-    GlobalAccountDataTestEvent, JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
+    GlobalAccountDataTestEvent, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
+    DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
     api::client::{
@@ -33,6 +39,7 @@ use ruma::{
             get_public_rooms,
             get_public_rooms_filtered::{self, v3::Request as PublicRoomsFilterRequest},
         },
+        room::Visibility,
         uiaa,
     },
     assign, device_id,
@@ -42,15 +49,17 @@ use ruma::{
         direct::{DirectEventContent, OwnedDirectUserIdentifier},
         AnyInitialStateEvent,
     },
-    room_id,
+    int, mxc_uri, room_id,
     serde::Raw,
-    user_id, OwnedUserId,
+    user_id, EventId, OwnedUserId,
 };
 use serde_json::{json, Value as JsonValue};
 use stream_assert::{assert_next_matches, assert_pending};
 use tokio_stream::wrappers::BroadcastStream;
 use wiremock::{
-    matchers::{header, method, path, path_regex},
+    matchers::{
+        body_partial_json, header, method, path, path_regex, query_param, query_param_is_missing,
+    },
     Mock, Request, ResponseTemplate,
 };
 
@@ -69,6 +78,37 @@ async fn test_sync() {
     assert_ne!(response.next_batch, "");
 }
 
+#[async_test]
+async fn test_stop_sync() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    // The sync request is still in flight when the sync is stopped.
+    Mock::given(method("GET"))
+        .and(path_regex(r"/sync$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(&*test_json::SYNC)
+                .set_delay(Duration::from_secs(60)),
+        )
+        .mount(&server)
+        .await;
+
+    let sync_task = tokio::spawn({
+        let client = client.clone();
+        async move { client.sync(SyncSettings::new()).await }
+    });
+
+    // Let the sync loop start.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.stop_sync();
+
+    let result = tokio::time::timeout(Duration::from_secs(5), sync_task)
+        .await
+        .expect("the sync loop should have stopped")
+        .unwrap();
+    assert!(result.is_ok());
+}
+
 #[async_test]
 async fn test_devices() {
     let (client, server) = logged_in_client_with_server().await;
@@ -796,6 +836,172 @@ async fn test_encrypt_room_event() {
     );
 }
 
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_decrypt_raw_event() {
+    use std::sync::Arc;
+
+    use ruma::events::room::encrypted::RoomEncryptedEventContent;
+
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": {
+                user_id: {}
+            }
+        })))
+        .mount(&server)
+        .await;
+
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    client
+        .sync_once(SyncSettings::default())
+        .await
+        .expect("We should be able to performs an initial sync");
+
+    let room =
+        client.get_room(&DEFAULT_TEST_ROOM_ID).expect("We should know about our default room");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/state/m.room.encryption/"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithm": "m.megolm.v1.aes-sha2",
+            "rotation_period_ms": 604800000,
+            "rotation_period_msgs": 100
+        })))
+        .mount(&server)
+        .await;
+
+    assert!(
+        room.latest_encryption_state()
+            .await
+            .expect("We should be able to check if the room is encrypted")
+            .is_encrypted(),
+        "The room should be encrypted"
+    );
+
+    Mock::given(method("GET"))
+        .and(path_regex("/_matrix/client/r0/rooms/!SVkFJHzfwvuaIEawgC:localhost/members"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "chunk": []})))
+        .mount(&server)
+        .await;
+
+    let event_content = Arc::new(std::sync::Mutex::new(None));
+
+    let event_content_matcher = {
+        let event_content = event_content.to_owned();
+        move |request: &Request| {
+            let mut path_segments =
+                request.url.path_segments().expect("The URL should be able to be a base");
+
+            let event_type = path_segments
+                .nth_back(1)
+                .expect("The path should have a event type as the last segment")
+                .to_owned();
+
+            assert_eq!(
+                event_type, "m.room.encrypted",
+                "The event type should be the `m.room.encrypted` event type"
+            );
+
+            let content: RoomEncryptedEventContent = request
+                .body_json()
+                .expect("The uploaded content should be a valid `m.room.encrypted` event content");
+
+            *event_content.lock().unwrap() = Some(content);
+
+            true
+        }
+    };
+
+    Mock::given(method("PUT"))
+        .and(path(
+            "/_matrix/client/r0/rooms/!SVkFJHzfwvuaIEawgC:localhost/send/m.room.encrypted/foobar",
+        ))
+        .and(event_content_matcher)
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "event_id": "$foobar"
+        })))
+        .mount(&server)
+        .await;
+
+    room.send_raw("m.room.message", json!({"body": "Hello", "msgtype": "m.text"}))
+        .with_transaction_id("foobar".into())
+        .await
+        .expect("We should be able to send a message to the encrypted room");
+
+    let content = event_content
+        .lock()
+        .unwrap()
+        .take()
+        .expect("We should have intercepted an `m.room.encrypted` event content");
+
+    let raw_event = Raw::new(&json!({
+        "type": "m.room.encrypted",
+        "room_id": room.room_id(),
+        "event_id": "$foobar",
+        "origin_server_ts": 1600000u64,
+        "sender": user_id,
+        "content": content,
+    }))
+    .expect("We should be able to construct a full event from the encrypted event content");
+
+    // The event can be decrypted without going through the room, like in a
+    // notification process.
+    let event = client
+        .decrypt_raw_event(room.room_id(), &raw_event.cast())
+        .await
+        .expect("We should be able to decrypt the raw event from the client");
+    assert!(event.encryption_info().is_some());
+    assert!(event.push_actions.is_some());
+
+    let event =
+        event.raw().deserialize().expect("We should be able to deserialize the decrypted event");
+
+    assert_let!(
+        ruma::events::AnySyncTimelineEvent::MessageLike(
+            ruma::events::AnySyncMessageLikeEvent::RoomMessage(message_event)
+        ) = event
+    );
+
+    let message_event =
+        message_event.as_original().expect("The decrypted event should not be a redacted event");
+
+    assert_eq!(
+        message_event.content.body(),
+        "Hello",
+        "The now decrypted message should match to our plaintext payload"
+    );
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_decrypt_raw_event_not_encrypted() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!unknown:localhost");
+    let raw_event = EventFactory::new()
+        .room(room_id)
+        .sender(user_id!("@alice:localhost"))
+        .text_msg("Hello")
+        .event_id(event_id!("$1"))
+        .into_raw_sync();
+
+    // The event is not encrypted, it is returned as is.
+    let event = client.decrypt_raw_event(room_id, &raw_event).await.unwrap();
+    assert!(event.encryption_info().is_none());
+    assert_eq!(event.raw().json().get(), raw_event.json().get());
+
+    // The room is unknown, so the push rules can't be evaluated.
+    assert!(event.push_actions.is_none());
+}
+
 #[cfg(not(feature = "e2e-encryption"))]
 #[async_test]
 async fn test_create_dm_non_encrypted() {
@@ -920,6 +1126,262 @@ async fn test_create_dm_error() {
     assert_eq!(client_api_error.status_code, 404);
 }
 
+#[async_test]
+async fn test_room_builder() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let space_id = room_id!("!space:localhost");
+    let room_id = room_id!("!room:localhost");
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(space_id).add_state_event(StateTestEvent::Custom(json!({
+                "type": "m.room.create",
+                "state_key": "",
+                "sender": "@example:localhost",
+                "event_id": "$space_create",
+                "origin_server_ts": 1,
+                "content": {
+                    "creator": "@example:localhost",
+                    "room_version": "11",
+                    "type": "m.space",
+                },
+            }))),
+        )
+        .await;
+
+    server
+        .mock_upload()
+        .expect_mime_type("image/png")
+        .ok(mxc_uri!("mxc://localhost/avatar"))
+        .mock_once()
+        .mount()
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .and(body_partial_json(json!({
+            "name": "Rust",
+            "topic": "Everything about Rust",
+            "invite": ["@alice:localhost"],
+            "preset": "private_chat",
+            "initial_state": [
+                {
+                    "type": "m.room.avatar",
+                    "content": {
+                        "url": "mxc://localhost/avatar",
+                        "info": { "mimetype": "image/png", "size": 4 },
+                    },
+                },
+                {
+                    "type": "m.space.parent",
+                    "state_key": space_id,
+                    "content": { "via": ["localhost"] },
+                },
+            ],
+            "power_level_content_override": {
+                "users": {
+                    "@example:localhost": 100,
+                    "@alice:localhost": 50,
+                },
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let builder = client
+        .room_builder()
+        .name("Rust")
+        .topic("Everything about Rust")
+        .avatar(mime::IMAGE_PNG, b"\x89PNG".to_vec())
+        .invite([user_id!("@alice:localhost").to_owned()])
+        .power_level(user_id!("@alice:localhost").to_owned(), int!(50))
+        .encrypted(false)
+        .space_parent(space_id.to_owned())
+        .wait_for_sync(Duration::from_secs(10));
+    let task = tokio::spawn(builder.into_future());
+
+    // The room is created, but not returned until it is received in a sync.
+    while client.get_room(room_id).is_none() {
+        tokio::task::yield_now().await;
+    }
+    assert!(!task.is_finished());
+
+    server.sync_joined_room(&client, room_id).await;
+    let room = task.await.unwrap().unwrap();
+    assert_eq!(room.room_id(), room_id);
+}
+
+#[async_test]
+async fn test_room_builder_invalid_settings() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    // Nothing is sent to the homeserver.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(server.server())
+        .await;
+
+    let error = client.room_builder().direct().await.unwrap_err();
+    assert_matches!(error, Error::RoomBuilder(RoomBuilderError::DirectRoomWithoutInvites));
+
+    // The space parent must be a known space.
+    let error = client
+        .room_builder()
+        .space_parent(room_id!("!space:localhost").to_owned())
+        .await
+        .unwrap_err();
+    assert_matches!(error, Error::RoomBuilder(RoomBuilderError::InvalidSpaceParent));
+}
+
+#[async_test]
+async fn test_room_builder_encrypted_public_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .and(body_partial_json(json!({
+            "visibility": "public",
+            "preset": "public_chat",
+            "initial_state": [{ "type": "m.room.encryption" }],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The room is returned even if it's never received in a sync.
+    let room = client
+        .room_builder()
+        .visibility(Visibility::Public)
+        .encrypted(true)
+        .wait_for_sync(Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert_eq!(room.room_id(), room_id);
+}
+
+#[async_test]
+async fn test_dm_with_finds_existing_dm() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let alice = user_id!("@alice:localhost");
+    let dm_id = room_id!("!dm:localhost");
+    let group_id = room_id!("!group:localhost");
+    let left_id = room_id!("!left:localhost");
+
+    let member = |user_id: &str, membership: &str| {
+        StateTestEvent::Custom(json!({
+            "type": "m.room.member",
+            "state_key": user_id,
+            "sender": user_id,
+            "event_id": format!("${}{membership}", &user_id[1..]),
+            "origin_server_ts": 1,
+            "content": { "membership": membership },
+        }))
+    };
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder
+                .add_global_account_data_bulk([Raw::new(&json!({
+                    "type": "m.direct",
+                    "content": {
+                        alice: [dm_id, group_id, left_id],
+                        "@bot:localhost": [dm_id],
+                    },
+                }))
+                .unwrap()
+                .cast()])
+                // The bot is a service member, so this is still a DM with Alice.
+                .add_joined_room(
+                    JoinedRoomBuilder::new(dm_id)
+                        .add_state_event(member(alice.as_str(), "join"))
+                        .add_state_event(member("@bot:localhost", "join"))
+                        .add_state_event(StateTestEvent::Custom(json!({
+                            "type": "io.element.functional_members",
+                            "state_key": "",
+                            "sender": "@example:localhost",
+                            "event_id": "$functional_members",
+                            "origin_server_ts": 1,
+                            "content": { "service_members": ["@bot:localhost"] },
+                        }))),
+                )
+                // Bob is also in this room, so it's not a DM with Alice.
+                .add_joined_room(
+                    JoinedRoomBuilder::new(group_id)
+                        .add_state_event(member(alice.as_str(), "join"))
+                        .add_state_event(member("@bob:localhost", "join")),
+                )
+                // Alice left this room.
+                .add_joined_room(
+                    JoinedRoomBuilder::new(left_id)
+                        .add_state_event(member(alice.as_str(), "leave")),
+                );
+        })
+        .await;
+
+    // No room is created.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(server.server())
+        .await;
+
+    let room = client.dm_with(alice).await.unwrap();
+    assert_eq!(room.room_id(), dm_id);
+}
+
+#[async_test]
+async fn test_dm_with_creates_a_single_dm() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let alice = user_id!("@alice:localhost");
+    let dm_id = room_id!("!dm:localhost");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/createRoom"))
+        .and(body_partial_json(json!({
+            "invite": [alice],
+            "is_direct": true,
+            "preset": "trusted_private_chat",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": dm_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/user/@example:localhost/account_data/m.direct"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found",
+        })))
+        .mount(server.server())
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/_matrix/client/v3/user/@example:localhost/account_data/m.direct"))
+        .and(body_partial_json(json!({ alice: [dm_id] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // Concurrent calls return the same room, before it is received in a sync.
+    let (first, second) = join(client.dm_with(alice), client.dm_with(alice)).await;
+    assert_eq!(first.unwrap().room_id(), dm_id);
+    assert_eq!(second.unwrap().room_id(), dm_id);
+}
+
 #[async_test]
 async fn test_test_ambiguity_changes() {
     let (client, server) = logged_in_client_with_server().await;
@@ -1433,3 +1895,236 @@ async fn test_logout() {
     assert_matches!(res, Err(Error::OAuth(oauth_error)));
     assert_matches!(*oauth_error, OAuthError::Logout(OAuthTokenRevocationError::Url(_)));
 }
+
+#[async_test]
+async fn test_user_search() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let member = |user_id: &str, display_name: &str| {
+        StateTestEvent::Custom(json!({
+            "type": "m.room.member",
+            "state_key": user_id,
+            "sender": user_id,
+            "event_id": format!("${display_name}_join"),
+            "origin_server_ts": 1,
+            "content": {
+                "membership": "join",
+                "displayname": display_name,
+            },
+        }))
+    };
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder
+                .add_joined_room(
+                    JoinedRoomBuilder::new(room_id!("!dm:b.c"))
+                        .add_state_event(member("@alice:b.c", "Alice")),
+                )
+                .add_joined_room(
+                    JoinedRoomBuilder::new(room_id!("!group:b.c"))
+                        .add_state_event(member("@alice:b.c", "Alice"))
+                        .add_state_event(member("@bob:b.c", "Bob"))
+                        .add_state_event(member("@carl:b.c", "Al Carl")),
+                )
+                .add_global_account_data_bulk([serde_json::from_value(json!({
+                    "type": "m.direct",
+                    "content": { "@alice:b.c": ["!dm:b.c"] },
+                }))
+                .unwrap()]);
+        })
+        .await;
+
+    // The user directory doesn't support pagination, so the limit is increased
+    // instead.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/user_directory/search"))
+        .and(body_partial_json(json!({ "search_term": "al", "limit": 2 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "results": [
+                { "user_id": "@alice:b.c", "display_name": "Alice" },
+                { "user_id": "@albert:d.e", "display_name": "Albert" },
+            ],
+            "limited": true,
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/user_directory/search"))
+        .and(body_partial_json(json!({ "search_term": "al", "limit": 4 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "results": [
+                { "user_id": "@alice:b.c", "display_name": "Alice" },
+                { "user_id": "@albert:d.e", "display_name": "Albert" },
+                { "user_id": "@dave:d.e", "display_name": "Dave" },
+                { "user_id": "@al:d.e" },
+            ],
+            "limited": false,
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let mut search = client.user_search("Al").batch_size(2);
+
+    // The users known locally come first, the contacts in direct messages first.
+    let page = search.next_page().await.unwrap();
+    assert_eq!(
+        page.iter().map(|user| (user.user_id.as_str(), user.source)).collect::<Vec<_>>(),
+        [
+            ("@alice:b.c", UserSearchSource::DirectMessage),
+            ("@carl:b.c", UserSearchSource::RoomMember)
+        ]
+    );
+    assert!(!search.is_at_end());
+
+    // The user directory is searched once they were all returned. The users are
+    // deduplicated, and the best matches come first.
+    let page = search.next_page().await.unwrap();
+    assert_eq!(
+        page.iter().map(|user| user.user_id.as_str()).collect::<Vec<_>>(),
+        ["@al:d.e", "@albert:d.e"]
+    );
+
+    let page = search.next_page().await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].user_id, "@dave:d.e");
+    assert_eq!(page[0].source, UserSearchSource::Directory);
+    assert!(search.is_at_end());
+
+    // The members are loaded again when they change.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_joined_room(
+                JoinedRoomBuilder::new(room_id!("!group:b.c"))
+                    .add_state_event(member("@bob:b.c", "Alan")),
+            );
+        })
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/user_directory/search"))
+        .and(body_partial_json(json!({ "search_term": "alan" })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "results": [], "limited": false })),
+        )
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let page = client.user_search("Alan").next_page().await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].user_id, "@bob:b.c");
+    assert_eq!(page[0].source, UserSearchSource::RoomMember);
+}
+
+#[async_test]
+async fn test_search_messages() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!room:b.c");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@alice:b.c"));
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk(vec![
+                f.text_msg("Lunch tomorrow?").event_id(event_id!("$both")).server_ts(2000).into(),
+                f.text_msg("Dinner").event_id(event_id!("$dinner")).server_ts(2500).into(),
+                f.text_msg("Lunch at noon").event_id(event_id!("$local")).server_ts(3000).into(),
+            ]),
+        )
+        .await;
+
+    let search_result = |event_id: &str, ts: u64, body: &str| {
+        json!({
+            "context": {},
+            "rank": 1.0,
+            "result": f
+                .text_msg(body)
+                .event_id(<&EventId>::try_from(event_id).unwrap())
+                .server_ts(ts)
+                .into_raw_timeline(),
+        })
+    };
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/search"))
+        .and(query_param_is_missing("next_batch"))
+        .and(body_partial_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "search_term": "lunch",
+                    "order_by": "recent",
+                    "filter": { "limit": 2 },
+                },
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "highlights": ["Lunch", "lunches"],
+                    "next_batch": "next",
+                    "results": [
+                        search_result("$recent", 4000, "Lunch is ready"),
+                        search_result("$both", 2000, "Lunch tomorrow?"),
+                    ],
+                },
+            },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/search"))
+        .and(query_param("next_batch", "next"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "highlights": ["lunch"],
+                    "results": [search_result("$old", 1000, "Lunch yesterday")],
+                },
+            },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let mut search = client.search_messages("lunch", MessageSearchOptions::new().batch_size(2));
+
+    // The results of the homeserver and the local results are merged, the most
+    // recent first.
+    let page = search.next_page().await.unwrap();
+    assert_eq!(
+        page.iter()
+            .map(|result| (result.event.event_id().unwrap().to_string(), result.source))
+            .collect::<Vec<_>>(),
+        [
+            ("$recent".to_owned(), MessageSearchSource::Server),
+            ("$local".to_owned(), MessageSearchSource::Local),
+        ]
+    );
+    assert_eq!(page[0].room_id, room_id);
+    assert_eq!(page[0].rank, Some(1.0));
+    assert_eq!(page[0].highlights, ["lunch"]);
+    assert!(!search.is_at_end());
+
+    // The event found locally and by the homeserver is only returned once.
+    let page = search.next_page().await.unwrap();
+    assert_eq!(
+        page.iter()
+            .map(|result| (result.event.event_id().unwrap().to_string(), result.source))
+            .collect::<Vec<_>>(),
+        [
+            ("$both".to_owned(), MessageSearchSource::Local),
+            ("$old".to_owned(), MessageSearchSource::Server),
+        ]
+    );
+    assert!(search.is_at_end());
+    assert!(search.next_page().await.unwrap().is_empty());
+}
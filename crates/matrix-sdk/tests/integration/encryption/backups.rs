@@ -1369,6 +1369,11 @@ async fn test_enable_from_secret_storage_and_download_after_utd() {
     let event = event.as_original().unwrap();
     assert_eq!(event.content.body(), "tt");
 
+    let stats = client.encryption().backups().on_demand_download_stats();
+    assert_eq!(stats.downloaded, 1);
+    assert_eq!(stats.attempts(), 1);
+    assert_eq!(stats.success_rate(), Some(1.0));
+
     server.verify().await;
 }
 
@@ -12,10 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use assert_matches2::assert_let;
-use matrix_sdk::{encryption::CrossSigningResetAuthType, test_utils::mocks::MatrixMockServer};
+use assert_matches2::{assert_let, assert_matches};
+use matrix_sdk::{
+    encryption::{
+        bootstrap::{BootstrapError, RecoveryOptions},
+        recovery::RecoveryError,
+        CrossSigningResetAuthType,
+    },
+    test_utils::mocks::MatrixMockServer,
+};
 use matrix_sdk_test::async_test;
 use ruma::api::client::uiaa;
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path},
+    Mock, ResponseTemplate,
+};
 
 #[async_test]
 async fn test_reset_legacy_auth() {
@@ -164,3 +176,53 @@ async fn test_reset_oauth() {
         "After the reset we have the cross-signing available.",
     );
 }
+
+#[async_test]
+async fn test_bootstrap_legacy_auth() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = client.user_id().expect("We should be able to access the user ID by now");
+
+    server.mock_upload_keys().ok().mount().await;
+    server.mock_query_keys().ok().mount().await;
+
+    // Without any authentication data, the bootstrap stops at the upload of the
+    // cross-signing keys.
+    {
+        let _guard =
+            server.mock_upload_cross_signing_keys().uiaa().expect(1).mount_as_scoped().await;
+
+        let result = client.encryption().bootstrap(RecoveryOptions::default()).await;
+        assert_let!(Err(BootstrapError::AuthenticationRequired(uiaa_info)) = result);
+        assert_eq!(uiaa_info.session.as_deref(), Some("oFIJVvtEOCKmRUTYKTYIIPHL"));
+    }
+
+    // On the next attempt, the session ID is filled in for us.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/keys/device_signing/upload"))
+        .and(body_partial_json(json!({ "auth": { "session": "oFIJVvtEOCKmRUTYKTYIIPHL" } })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("Uploading the cross-signing keys with the UIAA session")
+        .mount(server.server())
+        .await;
+    server.mock_upload_cross_signing_keys().uiaa().expect(1).mount().await;
+    server.mock_upload_cross_signing_signatures().ok().expect(1).mount().await;
+
+    // The bootstrap doesn't overwrite an existing backup.
+    server.mock_room_keys_version().exists().expect(1).mount().await;
+
+    let password = uiaa::Password::new(user_id.to_owned().into(), "1234".to_owned());
+    let options = RecoveryOptions {
+        auth_data: Some(uiaa::AuthData::Password(password)),
+        ..Default::default()
+    };
+
+    let result = client.encryption().bootstrap(options).await;
+    assert_matches!(result, Err(BootstrapError::Recovery(RecoveryError::BackupExistsOnServer)));
+
+    assert!(
+        client.encryption().cross_signing_status().await.unwrap().is_complete(),
+        "The cross-signing keys should have been set up before the recovery step",
+    );
+}
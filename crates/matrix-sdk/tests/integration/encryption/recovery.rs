@@ -186,6 +186,33 @@ async fn test_recovery_status_secret_storage_set_up() {
     server.verify().await;
 }
 
+#[async_test]
+async fn test_check_recovery_key() {
+    const KEY_ID: &str = "yJWwBm2Ts8jHygTBslKpABFyykavhhfA";
+    let user_id = user_id!("@example:morpheus.localhost");
+
+    let session = MatrixSession {
+        meta: SessionMeta { user_id: user_id.into(), device_id: device_id!("DEVICEID").to_owned() },
+        tokens: mock_session_tokens(),
+    };
+
+    let (client, server) = no_retry_test_client_with_server().await;
+
+    mock_secret_store_with_backup_key(user_id, KEY_ID, &server).await;
+
+    client.restore_session(session).await.unwrap();
+    client.encryption().wait_for_e2ee_initialization_tasks().await;
+
+    let recovery = client.encryption().recovery();
+
+    assert!(recovery.check_recovery_key("mypassphrase").await.unwrap());
+    assert!(!recovery.check_recovery_key("not my passphrase").await.unwrap());
+
+    // Checking the key doesn't import any secrets.
+    assert_eq!(recovery.state(), RecoveryState::Incomplete);
+    assert!(!client.encryption().cross_signing_status().await.unwrap().has_master);
+}
+
 #[async_test]
 async fn test_recovery_status_secret_storage_not_set_up() {
     let user_id = user_id!("@example:morpheus.localhost");
@@ -6,10 +6,11 @@ use eyeball_im::VectorDiff;
 use futures_util::FutureExt;
 use imbl::Vector;
 use matrix_sdk::{
-    assert_let_timeout, assert_next_matches_with_timeout,
+    assert_let_timeout, assert_next_matches_with_timeout, assert_next_with_timeout,
     deserialized_responses::TimelineEvent,
     event_cache::{
-        BackPaginationOutcome, EventCacheError, RoomEventCacheUpdate, RoomPaginationStatus,
+        BackPaginationOutcome, EventCacheError, RoomEventCacheUpdate, RoomPaginationMetrics,
+        RoomPaginationStatus,
     },
     linked_chunk::{ChunkIdentifier, Position, Update},
     store::StoreConfig,
@@ -28,7 +29,10 @@ use matrix_sdk_test::{
 };
 use ruma::{
     event_id,
-    events::{AnySyncMessageLikeEvent, AnySyncTimelineEvent},
+    events::{
+        room::message::RoomMessageEventContentWithoutRelation, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent,
+    },
     room_id, user_id, EventId, RoomVersionId,
 };
 use serde_json::json;
@@ -123,14 +127,17 @@ async fn test_ignored_unignored() {
     let ivan = user_id!("@ivan:lab.ch");
     let f = EventFactory::new();
 
-    // Given two known rooms with initial items,
+    // Given two known rooms with initial items, where `dexter` is only a member of
+    // the first one,
     server
         .sync_room(
             &client,
-            JoinedRoomBuilder::new(room_id).add_timeline_bulk(vec![
-                f.text_msg("hey there").sender(dexter).into_raw_sync(),
-                f.text_msg("hoy!").sender(ivan).into_raw_sync(),
-            ]),
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.member(dexter).into_raw(), f.member(ivan).into_raw()])
+                .add_timeline_bulk(vec![
+                    f.text_msg("hey there").sender(dexter).into_raw_sync(),
+                    f.text_msg("hoy!").sender(ivan).into_raw_sync(),
+                ]),
         )
         .await;
 
@@ -138,6 +145,7 @@ async fn test_ignored_unignored() {
         .sync_room(
             &client,
             JoinedRoomBuilder::new(other_room_id)
+                .add_state_bulk([f.member(ivan).into_raw()])
                 .add_timeline_bulk(vec![f.text_msg("demat!").sender(ivan).into_raw_sync()]),
         )
         .await;
@@ -167,6 +175,19 @@ async fn test_ignored_unignored() {
         })
         .await;
 
+    // The events of `dexter` are removed.
+    {
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = room_stream.recv()
+        );
+        assert_eq!(diffs.len(), 1);
+        assert_let!(VectorDiff::Remove { index: 0 } = &diffs[0]);
+    }
+
+    let (events, _) = room_event_cache.subscribe().await;
+    assert_eq!(events.len(), 1);
+    assert_event_matches_msg(&events[0], "hoy!");
+
     // Receiving new events still works.
     server
         .mock_sync()
@@ -178,33 +199,56 @@ async fn test_ignored_unignored() {
         })
         .await;
 
-    // We do receive a clear.
+    // We do receive the new event.
     {
         assert_let_timeout!(
             Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = room_stream.recv()
         );
         assert_eq!(diffs.len(), 1);
-        assert_let!(VectorDiff::Clear = &diffs[0]);
+
+        assert_let!(VectorDiff::Append { values: events } = &diffs[0]);
+        assert_eq!(events.len(), 1);
+        assert_event_matches_msg(&events[0], "i don't like this dexter");
     }
 
-    // We do receive the new event.
+    // The other room, which has no events from `dexter`, is untouched.
+    {
+        let room = client.get_room(other_room_id).unwrap();
+        let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+        let (events, _) = room_event_cache.subscribe().await;
+        assert_eq!(events.len(), 1);
+        assert_event_matches_msg(&events[0], "demat!");
+    }
+
+    // `dexter` is unignored.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |sync_builder| {
+            sync_builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "content": {
+                    "ignored_users": {}
+                },
+                "type": "m.ignored_user_list",
+            })));
+        })
+        .await;
+
+    // The room is cleared, so the events of `dexter` can be loaded again.
     {
         assert_let_timeout!(
             Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = room_stream.recv()
         );
         assert_eq!(diffs.len(), 1);
-
-        assert_let!(VectorDiff::Append { values: events } = &diffs[0]);
-        assert_eq!(events.len(), 1);
-        assert_event_matches_msg(&events[0], "i don't like this dexter");
+        assert_let!(VectorDiff::Clear = &diffs[0]);
     }
 
-    // The other room has been cleared too.
+    // The other room, where `dexter` isn't a member, isn't cleared.
     {
         let room = client.get_room(other_room_id).unwrap();
         let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
         let (events, _) = room_event_cache.subscribe().await;
-        assert!(events.is_empty());
+        assert_eq!(events.len(), 1);
+        assert_event_matches_msg(&events[0], "demat!");
     }
 
     // That's all, folks!
@@ -512,6 +556,18 @@ async fn test_backpaginate_many_times_with_one_iteration() {
     assert_event_matches_msg(&global_events[2], "oh well");
     assert_eq!(global_events.len(), 3);
 
+    // The two back-paginations have been counted in the metrics.
+    assert_eq!(
+        pagination.metrics(),
+        RoomPaginationMetrics {
+            num_paginations: 2,
+            num_failures: 0,
+            num_network_requests: 2,
+            num_events_from_network: 3,
+            num_events_from_store: 0,
+        }
+    );
+
     // First pagination.
     assert_let_timeout!(
         Ok(RoomEventCacheUpdate::UpdateTimelineEvents { diffs, .. }) = room_stream.recv()
@@ -2628,6 +2684,88 @@ async fn test_clear_all_rooms() {
     assert!(maybe_last_chunk.is_none());
 }
 
+#[async_test]
+async fn test_clear_all_rooms_keeps_retained_content() {
+    let sleeping_room_id = room_id!("!dodo:saucisse.bzh");
+    let event_cache_store = Arc::new(MemoryStore::new());
+
+    let f = EventFactory::new().room(sleeping_room_id);
+    let ev0 = f.text_msg("hi").sender(*ALICE).event_id(event_id!("$ev0")).into_event();
+
+    // Feed the cache with one retained room with one event, before the client is
+    // created. This room will remain sleeping.
+    {
+        let cid = ChunkIdentifier::new(0);
+        event_cache_store
+            .handle_linked_chunk_updates(
+                sleeping_room_id,
+                vec![
+                    Update::NewItemsChunk { previous: None, new: cid, next: None },
+                    Update::PushItems { at: Position::new(cid, 0), items: vec![ev0] },
+                ],
+            )
+            .await
+            .unwrap();
+        event_cache_store.set_room_retained(sleeping_room_id, true).await.unwrap();
+    }
+
+    let server = MatrixMockServer::new().await;
+    let client = server
+        .client_builder()
+        .store_config(
+            StoreConfig::new("hodlor".to_owned()).event_cache_store(event_cache_store.clone()),
+        )
+        .build()
+        .await;
+
+    client.event_cache().subscribe().unwrap();
+    client.event_cache().enable_storage().unwrap();
+
+    // Another room gets two live events.
+    let room_id = room_id!("!galette:saucisse.bzh");
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("kept").sender(*BOB).event_id(event_id!("$ev1")))
+                .add_timeline_event(f.text_msg("lost").sender(*BOB).event_id(event_id!("$ev2"))),
+        )
+        .await;
+
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    // An unknown event can't be retained.
+    assert_matches!(
+        room_event_cache.set_event_retained(event_id!("$unknown"), true).await,
+        Err(EventCacheError::EventNotFound(event_id)) => {
+            assert_eq!(event_id, "$unknown");
+        }
+    );
+
+    // Retain one of the live events.
+    room_event_cache.set_event_retained(event_id!("$ev1"), true).await.unwrap();
+
+    let retained = client.event_cache().retained_content().await.unwrap();
+    assert_eq!(retained.rooms.len(), 1);
+    assert!(retained.contains_room(sleeping_room_id));
+    assert!(retained.contains_event(room_id, event_id!("$ev1")));
+    assert!(!retained.contains_event(room_id, event_id!("$ev2")));
+
+    // Now, clear all the rooms.
+    client.event_cache().clear_all_rooms().await.unwrap();
+
+    // The live room has been cleared, but the retained event can still be found.
+    let (events, _stream) = room_event_cache.subscribe().await;
+    assert!(events.is_empty());
+    assert!(room_event_cache.event(event_id!("$ev1")).await.is_some());
+    assert!(room_event_cache.event(event_id!("$ev2")).await.is_none());
+
+    // The sleeping room has been kept.
+    let (maybe_last_chunk, _chunk_id_gen) =
+        event_cache_store.load_last_chunk(sleeping_room_id).await.unwrap();
+    assert!(maybe_last_chunk.is_some());
+}
+
 #[async_test]
 async fn test_sync_while_back_paginate() {
     let server = MatrixMockServer::new().await;
@@ -2737,3 +2875,56 @@ async fn test_sync_while_back_paginate() {
 
     assert!(subscriber.is_empty());
 }
+
+#[async_test]
+async fn test_latest_events() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+
+    let mut latest_events = event_cache.subscribe_to_latest_events();
+    assert!(latest_events.get().is_empty());
+
+    let room_id = room_id!("!omelette:fromage.fr");
+    let f = EventFactory::new().room(room_id).sender(user_id!("@dexter:lab.org"));
+
+    // When a room receives a message and a reaction to it,
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("bonjour monde").event_id(event_id!("$1")))
+                .add_timeline_event(f.reaction(event_id!("$1"), "👍").event_id(event_id!("$2"))),
+        )
+        .await;
+
+    // Then the latest event of the room is the message.
+    let events = assert_next_with_timeout!(latest_events);
+    assert_eq!(events.len(), 1);
+    assert_event_id!(events[room_id], "$1");
+    assert_event_id!(event_cache.latest_event(room_id).unwrap(), "$1");
+
+    // When the room receives an edit and a new message,
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_timeline_event(f.text_msg("au revoir").event_id(event_id!("$3")))
+                .add_timeline_event(
+                    f.text_msg("* au revoir monde")
+                        .edit(
+                            event_id!("$3"),
+                            RoomMessageEventContentWithoutRelation::text_plain("au revoir monde"),
+                        )
+                        .event_id(event_id!("$4")),
+                ),
+        )
+        .await;
+
+    // Then the latest event of the room is the new message, not its edit.
+    let events = assert_next_with_timeout!(latest_events);
+    assert_event_id!(events[room_id], "$3");
+    assert_event_id!(event_cache.latest_event(room_id).unwrap(), "$3");
+}
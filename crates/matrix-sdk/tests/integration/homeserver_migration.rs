@@ -0,0 +1,68 @@
+use matrix_sdk::{homeserver_migration::MigrationProgress, test_utils::mocks::MatrixMockServer};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, RoomAccountDataTestEvent, StateTestEvent};
+use ruma::room_id;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_migrate_rooms() {
+    let server = MatrixMockServer::new().await;
+    let source = server.client_builder().build().await;
+    let target = server.client_builder().build().await;
+
+    let public_room_id = room_id!("!public:localhost");
+    let private_room_id = room_id!("!private:localhost");
+
+    // The public room has tags, the private room has no join rules, so it's
+    // invite-only.
+    server
+        .sync_room(
+            &source,
+            JoinedRoomBuilder::new(public_room_id)
+                .add_state_event(StateTestEvent::JoinRules)
+                .add_account_data(RoomAccountDataTestEvent::Tags),
+        )
+        .await;
+    server.sync_joined_room(&source, private_room_id).await;
+
+    // The new account joins both rooms, after being invited to the private room by
+    // the old account.
+    server.mock_invite_user_by_id().ok().mock_once().mount().await;
+
+    for (room_id, localpart) in [(public_room_id, "public"), (private_room_id, "private")] {
+        Mock::given(method("POST"))
+            .and(path_regex(format!(r"^/_matrix/client/.*/join/.*{localpart}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+            .expect(1)
+            .named(format!("join {room_id}"))
+            .mount(server.server())
+            .await;
+    }
+
+    // The tags of the public room are copied.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/.*/user/.*/rooms/.*/tags/(m.favourite|u.work)"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(2)
+        .named("set tag")
+        .mount(server.server())
+        .await;
+
+    let migration = source.migrate_to(&target);
+    let progress = migration.subscribe_to_progress();
+
+    let report = migration.await.unwrap();
+
+    let mut joined_rooms = report.joined_rooms.clone();
+    joined_rooms.sort();
+    assert_eq!(joined_rooms, [private_room_id, public_room_id]);
+    assert!(report.requested_invites.is_empty());
+    assert!(report.failed_rooms.is_empty());
+    assert!(report.copied_account_data.is_empty());
+    assert_eq!(report.num_push_rules, 0);
+
+    assert_eq!(progress.get(), MigrationProgress::Done);
+}
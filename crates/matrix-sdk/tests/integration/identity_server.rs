@@ -0,0 +1,475 @@
+use assert_matches2::assert_matches;
+use http::StatusCode;
+use matrix_sdk::{
+    identity_server::IdentityServerError, test_utils::mocks::MatrixMockServer, Client, Error,
+};
+use matrix_sdk_test::{async_test, GlobalAccountDataTestEvent};
+use ruma::{api::client::account::ThirdPartyIdRemovalStatus, room_id, thirdparty::Medium, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+/// Configure the mock server as the identity server of the client, and mock the
+/// registration with it.
+async fn set_up_identity_server(server: &MatrixMockServer, client: &Client) {
+    server
+        .mock_sync()
+        .ok_and_run(client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "type": "m.identity_server",
+                "content": { "base_url": server.server().uri() },
+            })));
+        })
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/openid/request_token$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "openid_token",
+            "token_type": "Bearer",
+            "matrix_server_name": "localhost",
+            "expires_in": 3600,
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/account/register"))
+        .and(body_partial_json(json!({
+            "access_token": "openid_token",
+            "matrix_server_name": "localhost",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "token": "is_token" })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/identity/v2/hash_details"))
+        .and(header("authorization", "Bearer is_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithms": ["none", "sha256"],
+            "lookup_pepper": "matrixrocks",
+        })))
+        .mount(server.server())
+        .await;
+}
+
+#[async_test]
+async fn test_invite_by_email_known_user() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+    // The email address is associated with a Matrix ID.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .and(body_partial_json(json!({
+            "addresses": ["4kenr7N9drpCJ4AfalmlGQVsOn3o2RHjkADUpXJWZUc"],
+            "algorithm": "sha256",
+            "pepper": "matrixrocks",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "mappings": { "4kenr7N9drpCJ4AfalmlGQVsOn3o2RHjkADUpXJWZUc": "@alice:example.com" },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // So the user is invited with it.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/invite$"))
+        .and(body_partial_json(json!({ "user_id": "@alice:example.com" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    room.invite_by_email("Alice@example.com").await.unwrap();
+}
+
+#[async_test]
+async fn test_invite_by_email_unknown_user() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "mappings": {} })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The homeserver is asked to invite the email address through the identity
+    // server.
+    let id_server = server.server().address().to_string();
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/invite$"))
+        .and(body_partial_json(json!({
+            "id_server": id_server,
+            "id_access_token": "is_token",
+            "medium": "email",
+            "address": "alice@example.com",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    room.invite_by_email("alice@example.com").await.unwrap();
+}
+
+#[async_test]
+async fn test_invite_by_email_terms_not_signed() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+    // Without an identity server, the invite fails.
+    assert_matches!(
+        room.invite_by_email("alice@example.com").await,
+        Err(Error::IdentityServer(IdentityServerError::NotConfigured))
+    );
+
+    set_up_identity_server(&server, &client).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_TERMS_NOT_SIGNED",
+            "error": "Terms not signed",
+        })))
+        .up_to_n_times(1)
+        .mount(server.server())
+        .await;
+
+    assert_matches!(
+        room.invite_by_email("alice@example.com").await,
+        Err(Error::IdentityServer(IdentityServerError::TermsNotSigned))
+    );
+
+    // The terms can be fetched and accepted.
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/identity/v2/terms"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "policies": {
+                "privacy_policy": {
+                    "version": "1.2",
+                    "en": { "name": "Privacy Policy", "url": "https://example.org/en/privacy" },
+                    "fr": {
+                        "name": "Politique de confidentialité",
+                        "url": "https://example.org/fr/privacy",
+                    },
+                },
+            },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let terms = identity_server.terms().await.unwrap();
+    let urls = terms.urls("fr");
+    assert_eq!(urls, ["https://example.org/fr/privacy"]);
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/terms"))
+        .and(body_partial_json(json!({ "user_accepts": ["https://example.org/fr/privacy"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The accepted terms are tracked in the account data.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/m.accepted_terms$"))
+        .and(body_partial_json(json!({ "accepted": ["https://example.org/fr/privacy"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    identity_server.accept_terms(urls).await.unwrap();
+}
+
+#[async_test]
+async fn test_lookup_pepper_rotation() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+
+    // The first hash details are cached and used for the first lookups.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/identity/v2/hash_details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithms": ["sha256"],
+            "lookup_pepper": "matrixrocks",
+        })))
+        .with_priority(1)
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .and(body_partial_json(json!({ "pepper": "matrixrocks" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "mappings": { "4kenr7N9drpCJ4AfalmlGQVsOn3o2RHjkADUpXJWZUc": "@alice:example.com" },
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let user_id = identity_server.lookup(Medium::Email, "alice@example.com").await.unwrap();
+    assert_eq!(user_id.as_deref(), Some(user_id!("@alice:example.com")));
+
+    // Then the identity server rotates its pepper.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .and(body_partial_json(json!({ "pepper": "matrixrocks" })))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_INVALID_PEPPER",
+            "error": "Unknown or invalid pepper",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/identity/v2/hash_details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithms": ["sha256"],
+            "lookup_pepper": "matrixrules",
+        })))
+        .with_priority(2)
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The lookup is retried with the new pepper, which is used directly for the
+    // next lookup.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .and(body_partial_json(json!({ "pepper": "matrixrules" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "mappings": {} })))
+        .expect(2)
+        .mount(server.server())
+        .await;
+
+    let user_id = identity_server.lookup(Medium::Email, "bob@example.com").await.unwrap();
+    assert!(user_id.is_none());
+
+    let user_id = identity_server.lookup(Medium::Email, "carol@example.com").await.unwrap();
+    assert!(user_id.is_none());
+}
+
+#[async_test]
+async fn test_discover_contacts() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+
+    // Only Alice's email address and Bob's phone number are known.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/lookup"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "mappings": {
+                "4kenr7N9drpCJ4AfalmlGQVsOn3o2RHjkADUpXJWZUc": "@alice:example.com",
+                "THSC4I-3Y3nLb_vqvxaZWXswZuQdugJ_WBrvK0pIy9Y": "@bob:example.com",
+            },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let address_book = vec![
+        (
+            "Alice",
+            vec![
+                (Medium::Email, "Alice@Example.com".to_owned()),
+                (Medium::Msisdn, "+1 555 0100".to_owned()),
+            ],
+        ),
+        ("Bob", vec![(Medium::Msisdn, "+44 7700 900123".to_owned())]),
+        ("Carol", vec![(Medium::Email, "carol@example.com".to_owned())]),
+    ];
+
+    let contacts = identity_server.discover_contacts(address_book).await.unwrap();
+
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts["Alice"].contains(user_id!("@alice:example.com")));
+    assert!(contacts["Bob"].contains(user_id!("@bob:example.com")));
+}
+
+#[async_test]
+async fn test_pending_terms() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    // The privacy policy was accepted on another device.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "type": "m.accepted_terms",
+                "content": { "accepted": ["https://example.org/en/privacy"] },
+            })));
+        })
+        .await;
+    set_up_identity_server(&server, &client).await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/identity/v2/terms"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "policies": {
+                "privacy_policy": {
+                    "version": "1.2",
+                    "en": { "name": "Privacy Policy", "url": "https://example.org/en/privacy" },
+                },
+                "terms_of_service": {
+                    "version": "2.0",
+                    "en": { "name": "Terms of Service", "url": "https://example.org/en/tos" },
+                },
+            },
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // It is accepted with the identity server automatically.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/terms"))
+        .and(body_partial_json(json!({ "user_accepts": ["https://example.org/en/privacy"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // Only the terms of service still need to be accepted.
+    let terms = identity_server.pending_terms().await.unwrap();
+    assert_eq!(terms.urls("en"), ["https://example.org/en/tos"]);
+}
+
+#[async_test]
+async fn test_unbind() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    // The homeserver does the request to the identity server, so there is no
+    // need to register with it.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "type": "m.identity_server",
+                "content": { "base_url": server.server().uri() },
+            })));
+        })
+        .await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+    let id_server = server.server().address().to_string();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/account/3pid/unbind"))
+        .and(body_partial_json(json!({
+            "id_server": id_server,
+            "medium": "email",
+            "address": "alice@example.com",
+        })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "id_server_unbind_result": "success" })),
+        )
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let status = identity_server.unbind(Medium::Email, "alice@example.com").await.unwrap();
+    assert_matches!(status, ThirdPartyIdRemovalStatus::Success);
+}
+
+#[async_test]
+async fn test_join_room_by_invitation() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+    let room_id = room_id!("!room:localhost");
+
+    let signed = json!({
+        "mxid": "@example:localhost",
+        "sender": "@alice:localhost",
+        "token": "invitation_token",
+        "signatures": {
+            "identity.localhost": { "ed25519:0": "signature" },
+        },
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/sign-ed25519"))
+        .and(header("authorization", "Bearer is_token"))
+        .and(body_partial_json(json!({
+            "mxid": "@example:localhost",
+            "token": "invitation_token",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(signed.clone()))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/rooms/!room:localhost/join"))
+        .and(body_partial_json(json!({ "third_party_signed": signed })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let room = identity_server.join_room_by_invitation(room_id, "invitation_token").await.unwrap();
+    assert_eq!(room.room_id(), room_id);
+}
+
+#[async_test]
+async fn test_error_without_json_body() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    set_up_identity_server(&server, &client).await;
+
+    let identity_server = client.identity_server().await.unwrap().unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/identity/v2/sign-ed25519"))
+        .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let error = identity_server.sign_invitation("invitation_token").await.unwrap_err();
+    assert_matches!(
+        error,
+        Error::IdentityServer(IdentityServerError::Server { status, errcode, error })
+    );
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(errcode, "M_UNKNOWN");
+    assert_eq!(error, "Bad Gateway");
+}
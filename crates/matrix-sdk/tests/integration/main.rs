@@ -12,13 +12,17 @@ mod client;
 #[cfg(feature = "e2e-encryption")]
 mod encryption;
 mod event_cache;
+mod homeserver_migration;
+mod identity_server;
 mod matrix_auth;
 mod media;
 mod notification;
+mod profile;
 mod refresh_token;
 mod room;
 mod room_preview;
 mod send_queue;
+mod simulator;
 #[cfg(feature = "experimental-widgets")]
 mod widget;
 
@@ -1,9 +1,15 @@
+use std::{future::IntoFuture, io::SeekFrom, time::Duration};
+
+use assert_matches2::assert_matches;
+use futures_util::{AsyncReadExt, AsyncSeekExt};
 use matrix_sdk::{
     config::RequestConfig,
-    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
+    media::{
+        MediaError, MediaFormat, MediaRequestParameters, MediaThumbnailSettings, UploadConfig,
+    },
     store::RoomLoadSettings,
     test_utils::{client::mock_matrix_session, logged_in_client_with_server},
-    Client,
+    Client, Error,
 };
 use matrix_sdk_test::async_test;
 use ruma::{
@@ -15,7 +21,7 @@ use ruma::{
 use serde_json::json;
 use wiremock::{
     matchers::{header, method, path, query_param},
-    Mock, ResponseTemplate,
+    Mock, Request, ResponseTemplate,
 };
 
 #[async_test]
@@ -453,3 +459,284 @@ async fn test_async_media_upload() {
         .await
         .unwrap();
 }
+
+#[async_test]
+async fn test_download_media_content_progress_and_deduplication() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    let expected_content = "Hello, World!";
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/textfile"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(expected_content)
+                .set_delay(Duration::from_millis(100)),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    let media = client.media();
+    let download = media.download_media_content(&request, true);
+    let progress = download.subscribe_to_progress();
+
+    // Concurrent downloads of the same content only send one request.
+    let (first, second) =
+        tokio::join!(download.into_future(), media.get_media_content(&request, true));
+    assert_eq!(first.unwrap(), expected_content.as_bytes());
+    assert_eq!(second.unwrap(), expected_content.as_bytes());
+
+    let progress = progress.get();
+    assert_eq!(progress.current, expected_content.len());
+    assert_eq!(progress.total, expected_content.len());
+}
+
+#[async_test]
+async fn test_abort_download_media_content() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/textfile"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("Hello, World!")
+                .set_delay(Duration::from_secs(60)),
+        )
+        .mount(&server)
+        .await;
+
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    let download = client.media().download_media_content(&request, true);
+    let abort_handle = download.abort_handle();
+
+    let task = tokio::spawn(download.into_future());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    abort_handle.abort();
+
+    assert_matches!(task.await.unwrap(), Err(Error::Media(MediaError::DownloadAborted)));
+}
+
+#[async_test]
+async fn test_stream_media_content_with_ranges() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    let content: Vec<u8> = (0..100).collect();
+    let server_content = content.clone();
+
+    // The server returns at most 10 bytes per response.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/video"))
+        .respond_with(move |request: &Request| {
+            let range = request.headers.get("range").unwrap().to_str().unwrap();
+            let (start, end) = range.strip_prefix("bytes=").unwrap().split_once('-').unwrap();
+            let start: usize = start.parse().unwrap();
+            let end: usize = end.parse::<usize>().unwrap().min(start + 9).min(99);
+
+            ResponseTemplate::new(206)
+                .insert_header("content-range", format!("bytes {start}-{end}/100"))
+                .set_body_bytes(server_content[start..=end].to_vec())
+        })
+        .mount(&server)
+        .await;
+
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video"));
+    let mut stream = client.media().stream(source).await.unwrap();
+    assert_eq!(stream.content_length(), Some(100));
+
+    // Seek into the content.
+    stream.seek(SeekFrom::Start(55)).await.unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, content[55..60]);
+
+    // Seek relative to the end.
+    stream.seek(SeekFrom::End(-3)).await.unwrap();
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, content[97..]);
+
+    // Read the whole content, across several ranges.
+    stream.seek(SeekFrom::Start(0)).await.unwrap();
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, content);
+}
+
+#[async_test]
+async fn test_stream_media_content_with_unknown_length() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    let content: Vec<u8> = (0..25).collect();
+    let server_content = content.clone();
+
+    // The server returns at most 10 bytes per response, without the length of the
+    // whole content.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/video"))
+        .respond_with(move |request: &Request| {
+            let range = request.headers.get("range").unwrap().to_str().unwrap();
+            let (start, _) = range.strip_prefix("bytes=").unwrap().split_once('-').unwrap();
+            let start: usize = start.parse().unwrap();
+
+            if start >= server_content.len() {
+                return ResponseTemplate::new(416);
+            }
+
+            let end = (start + 9).min(server_content.len() - 1);
+
+            ResponseTemplate::new(206)
+                .insert_header("content-range", format!("bytes {start}-{end}/*"))
+                .set_body_bytes(server_content[start..=end].to_vec())
+        })
+        .mount(&server)
+        .await;
+
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video"));
+    let mut stream = client.media().stream(source).await.unwrap();
+    stream.set_chunk_size(10);
+    assert_eq!(stream.content_length(), None);
+
+    // Reading past the end of the content is not an error.
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, content);
+
+    stream.seek(SeekFrom::Start(100)).await.unwrap();
+    let mut buf = Vec::new();
+    assert_eq!(stream.read_to_end(&mut buf).await.unwrap(), 0);
+}
+
+#[async_test]
+async fn test_stream_media_content_whole_content_is_cached() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    // The server ignores the range and returns the whole content.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/video"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/video"));
+
+    let mut stream = client.media().stream(source.clone()).await.unwrap();
+    assert_eq!(stream.content_length(), Some(13));
+
+    stream.seek(SeekFrom::Start(7)).await.unwrap();
+    let mut buf = String::new();
+    stream.read_to_string(&mut buf).await.unwrap();
+    assert_eq!(buf, "World!");
+
+    // The content is read from the media cache the second time.
+    let mut stream = client.media().stream(source).await.unwrap();
+    let mut buf = String::new();
+    stream.read_to_string(&mut buf).await.unwrap();
+    assert_eq!(buf, "Hello, World!");
+}
+
+#[async_test]
+async fn test_prepare_upload_checks_size_limits() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["r0.6.1"],
+        })))
+        .mount(&server)
+        .await;
+
+    // The media config is only fetched once.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "m.upload.size": 10,
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let media = client.media();
+    assert_eq!(media.max_upload_size().await.unwrap(), uint!(10));
+
+    let prepared = media
+        .prepare_upload(&mime::TEXT_PLAIN, b"Hello".to_vec(), &UploadConfig::new())
+        .await
+        .unwrap();
+    assert_eq!(prepared.data, b"Hello");
+    assert_eq!(prepared.content_type, mime::TEXT_PLAIN);
+
+    // The file is bigger than the limit of the server.
+    assert_matches!(
+        media
+            .prepare_upload(&mime::TEXT_PLAIN, b"Hello, World!".to_vec(), &UploadConfig::new())
+            .await,
+        Err(Error::Media(MediaError::FileTooLarge { size: 13, max_size: 10 }))
+    );
+
+    // The lowest limit is used.
+    assert_matches!(
+        media
+            .prepare_upload(
+                &mime::TEXT_PLAIN,
+                b"Hello".to_vec(),
+                &UploadConfig::new().max_size(Some(3))
+            )
+            .await,
+        Err(Error::Media(MediaError::FileTooLarge { size: 5, max_size: 3 }))
+    );
+
+    // The limit of the server can be ignored.
+    let config = UploadConfig::new().check_server_limit(false);
+    media.prepare_upload(&mime::TEXT_PLAIN, b"Hello, World!".to_vec(), &config).await.unwrap();
+}
@@ -0,0 +1,180 @@
+use assert_matches2::assert_let;
+use futures_util::future::join;
+use matrix_sdk::{profile::UserProfile, test_utils::mocks::MatrixMockServer};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, StateTestEvent};
+use ruma::{mxc_uri, room_id, user_id};
+use serde_json::json;
+use stream_assert::assert_pending;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_profile_is_cached() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = user_id!("@alice:b.c");
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@alice:b.c"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "displayname": "Alice",
+            "avatar_url": "mxc://b.c/alice",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let expected = UserProfile {
+        display_name: Some("Alice".to_owned()),
+        avatar_url: Some(mxc_uri!("mxc://b.c/alice").to_owned()),
+    };
+
+    // Concurrent requests are deduplicated.
+    let (first, second) = join(client.profile(user_id), client.profile(user_id)).await;
+    assert_eq!(first.unwrap(), expected);
+    assert_eq!(second.unwrap(), expected);
+
+    // The profile is then returned from the cache.
+    assert_eq!(client.profile(user_id).await.unwrap(), expected);
+}
+
+#[async_test]
+async fn test_own_profile_is_updated() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = client.user_id().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@example:localhost"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Example" })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/_matrix/client/v3/profile/@example:localhost/displayname"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let mut subscriber = client.subscribe_to_profile(user_id).await.unwrap();
+    assert_eq!(subscriber.get(), None);
+
+    // The profile is fetched in the background.
+    assert_let!(Some(Some(profile)) = subscriber.next().await);
+    assert_eq!(profile.display_name.as_deref(), Some("Example"));
+
+    // Changing the display name updates the cached profile.
+    client.account().set_display_name(Some("New name")).await.unwrap();
+    assert_let!(Some(Some(profile)) = subscriber.next().await);
+    assert_eq!(profile.display_name.as_deref(), Some("New name"));
+    assert_eq!(client.profile(user_id).await.unwrap().display_name.as_deref(), Some("New name"));
+}
+
+#[async_test]
+async fn test_profile_falls_back_to_member_state() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = user_id!("@alice:b.c");
+    let room_id = room_id!("!room:b.c");
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@alice:b.c"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "Profile lookup is restricted",
+        })))
+        .mount(server.server())
+        .await;
+
+    // Without any shared room, the error is returned.
+    client.profile(user_id).await.unwrap_err();
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "type": "m.room.member",
+                "state_key": user_id,
+                "sender": user_id,
+                "event_id": "$alice_join",
+                "origin_server_ts": 1,
+                "content": {
+                    "membership": "join",
+                    "displayname": "Alice in the room",
+                },
+            }))),
+        )
+        .await;
+
+    // The member state is used instead.
+    let profile = client.profile(user_id).await.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Alice in the room"));
+    assert_eq!(profile.avatar_url, None);
+
+    let profile = room.member_profile(user_id).await.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Alice in the room"));
+}
+
+#[async_test]
+async fn test_profile_is_refreshed_on_member_change() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let user_id = user_id!("@alice:b.c");
+    let room_id = room_id!("!room:b.c");
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@alice:b.c"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alice" })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let mut subscriber = client.subscribe_to_profile(user_id).await.unwrap();
+    assert_let!(Some(Some(profile)) = subscriber.next().await);
+    assert_eq!(profile.display_name.as_deref(), Some("Alice"));
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/profile/@alice:b.c"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "displayname": "Alicia" })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "type": "m.room.member",
+                "state_key": user_id,
+                "sender": user_id,
+                "event_id": "$alice_rename",
+                "origin_server_ts": 1,
+                "content": {
+                    "membership": "join",
+                    "displayname": "Alicia",
+                },
+                "unsigned": {
+                    "prev_content": {
+                        "membership": "join",
+                        "displayname": "Alice",
+                    },
+                },
+            }))),
+        )
+        .await;
+
+    // The change of display name triggers a refresh of the observed profile.
+    assert_let!(Some(Some(profile)) = subscriber.next().await);
+    assert_eq!(profile.display_name.as_deref(), Some("Alicia"));
+    assert_pending!(subscriber);
+
+    // Without an avatar in the room, the member profile falls back to the global
+    // one.
+    let profile = room.member_profile(user_id).await.unwrap();
+    assert_eq!(profile.display_name.as_deref(), Some("Alicia"));
+    assert_eq!(profile.avatar_url, None);
+}
@@ -669,6 +669,66 @@ async fn test_oauth_refresh_token_handled_failure() {
     );
 }
 
+#[async_test]
+async fn test_oauth_refresh_token_ahead_of_expiry() {
+    use matrix_sdk::test_utils::{
+        client::{mock_prev_session_tokens_with_refresh, oauth::mock_session},
+        mocks::MatrixMockServer,
+    };
+
+    let server = MatrixMockServer::new().await;
+    // The requests are only sent with the second refreshed access token.
+    server
+        .mock_who_am_i()
+        .expect_access_token("5678")
+        .ok()
+        .expect(2)
+        .named("whoami_refreshed")
+        .mount()
+        .await;
+
+    let oauth_server = server.oauth();
+    oauth_server.mock_server_metadata().ok().expect(1..).named("server_metadata").mount().await;
+    // The access tokens expire in 5 minutes.
+    oauth_server.mock_token().ok().mock_once().named("first_token").mount().await;
+    oauth_server
+        .mock_token()
+        .ok_with_tokens("5678", "ABCDE")
+        .expect(1)
+        .named("second_token")
+        .mount()
+        .await;
+
+    let client = server
+        .client_builder()
+        .unlogged()
+        .handle_refresh_tokens()
+        .refresh_access_token_ahead_of_expiry(Duration::from_secs(10 * 60))
+        .build()
+        .await;
+    let oauth = client.oauth();
+
+    oauth
+        .restore_session(
+            mock_session(mock_prev_session_tokens_with_refresh()),
+            RoomLoadSettings::default(),
+        )
+        .await
+        .unwrap();
+
+    // We don't know when the restored access token expires, so we need a refresh
+    // to learn about it.
+    oauth.refresh_access_token().await.unwrap();
+    assert_eq!(client.access_token().as_deref(), Some("1234"));
+
+    // The access token expires within the margin, so it is refreshed once before
+    // sending the concurrent requests.
+    let (first, second) = tokio::join!(client.whoami(), client.whoami());
+    first.unwrap();
+    second.unwrap();
+    assert_eq!(client.access_token().as_deref(), Some("5678"));
+}
+
 #[async_test]
 async fn test_oauth_handle_refresh_tokens() {
     use matrix_sdk::test_utils::{
@@ -603,3 +603,71 @@ async fn test_room_attachment_send_is_animated() {
 
     assert_eq!(expected_event_id, response.event_id)
 }
+
+#[async_test]
+async fn test_room_attachment_send_voice() {
+    let mock = MatrixMockServer::new().await;
+
+    let expected_event_id = event_id!("$h29iv0s8:example.com");
+    mock.mock_room_send()
+        .body_matches_partial_json(json!({
+            "msgtype": "m.audio",
+            "info": {
+                "mimetype": "audio/ogg",
+                "duration": 2140,
+            },
+            "org.matrix.msc1767.audio": {
+                "duration": 2140,
+                "waveform": [0, 512, 1024, 1024],
+            },
+            "org.matrix.msc3245.voice": {},
+        }))
+        .ok(expected_event_id)
+        .mock_once()
+        .mount()
+        .await;
+
+    mock.mock_upload()
+        .expect_mime_type("audio/ogg")
+        .ok(mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, &DEFAULT_TEST_ROOM_ID).await;
+    mock.mock_room_state_encryption().plain().mount().await;
+
+    let config =
+        AttachmentConfig::new().voice(Duration::from_millis(2140), vec![0, 512, 1024, 2048]);
+
+    let response = room
+        .send_attachment(
+            "voice.ogg",
+            &"audio/ogg".parse().unwrap(),
+            b"Hello world".to_vec(),
+            config,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(expected_event_id, response.event_id);
+
+    // The playback position is persisted per event.
+    assert_eq!(room.voice_playback_position(&response.event_id).await.unwrap(), None);
+
+    room.save_voice_playback_position(&response.event_id, Duration::from_millis(1200))
+        .await
+        .unwrap();
+    assert_eq!(
+        room.voice_playback_position(&response.event_id).await.unwrap(),
+        Some(Duration::from_millis(1200))
+    );
+    assert_eq!(
+        room.voice_playback_position(event_id!("$other_event:example.com")).await.unwrap(),
+        None
+    );
+
+    room.clear_voice_playback_position(&response.event_id).await.unwrap();
+    assert_eq!(room.voice_playback_position(&response.event_id).await.unwrap(), None);
+}
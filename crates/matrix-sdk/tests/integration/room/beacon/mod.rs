@@ -426,3 +426,140 @@ async fn test_observing_live_location_does_not_return_own_beacon_updates() {
 
     assert!(stream.next().now_or_never().is_none());
 }
+
+#[async_test]
+async fn test_subscribe_to_active_live_location_shares() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!a:b.c");
+    let alice = user_id!("@alice:localhost");
+    let bob = user_id!("@bob:localhost");
+    let carol = user_id!("@carol:localhost");
+    let alice_beacon_info_id = event_id!("$alice_beacon_info");
+    let carol_beacon_info_id = event_id!("$carol_beacon_info");
+
+    let f = EventFactory::new().room(room_id);
+
+    let joined_room_builder = JoinedRoomBuilder::new(room_id).add_state_bulk(vec![
+        f.event(BeaconInfoEventContent::new(None, Duration::from_secs(60), true, None))
+            .event_id(alice_beacon_info_id)
+            .sender(alice)
+            .state_key(alice)
+            .into_raw_timeline()
+            .cast(),
+        f.event(BeaconInfoEventContent::new(None, Duration::from_secs(1), true, None))
+            .event_id(event_id!("$bob_beacon_info"))
+            .sender(bob)
+            .state_key(bob)
+            .into_raw_timeline()
+            .cast(),
+        // Carol's share was stopped, so it's not active.
+        f.event(BeaconInfoEventContent::new(None, Duration::from_secs(60), false, None))
+            .event_id(carol_beacon_info_id)
+            .sender(carol)
+            .state_key(carol)
+            .into_raw_timeline()
+            .cast(),
+    ]);
+
+    let room = server.sync_room(&client, joined_room_builder).await;
+
+    let stream = room.subscribe_to_active_live_location_shares().await.unwrap();
+    pin_mut!(stream);
+
+    let shares = stream.next().await.unwrap();
+    assert_eq!(shares.len(), 2);
+    assert_eq!(shares[0].user_id, alice);
+    assert_eq!(shares[0].beacon_info_event_id, alice_beacon_info_id);
+    assert!(shares[0].last_location.is_none());
+    assert_eq!(shares[1].user_id, bob);
+
+    // A location for an active share updates it, the other ones are ignored.
+    let joined = JoinedRoomBuilder::new(room_id)
+        .add_timeline_event(
+            f.event(BeaconEventContent::new(
+                carol_beacon_info_id.to_owned(),
+                "geo:48.8584,2.2945;u=10".to_owned(),
+                None,
+            ))
+            .sender(carol)
+            .into_raw_sync(),
+        )
+        .add_timeline_event(
+            f.event(BeaconEventContent::new(
+                alice_beacon_info_id.to_owned(),
+                "geo:51.5008,0.1247;u=35".to_owned(),
+                None,
+            ))
+            .sender(alice)
+            .into_raw_sync(),
+        );
+    server.sync_room(&client, joined).await;
+
+    let shares = stream.next().await.unwrap();
+    assert_eq!(shares.len(), 2);
+    let last_location = shares[0].last_location.as_ref().unwrap();
+    assert_eq!(last_location.location.uri, "geo:51.5008,0.1247;u=35");
+    assert!(shares[1].last_location.is_none());
+    assert!(stream.next().now_or_never().is_none());
+
+    // Bob's share expires.
+    let shares = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("Bob's share should have expired")
+        .unwrap();
+    assert_eq!(shares.len(), 1);
+    assert_eq!(shares[0].user_id, alice);
+    assert!(shares[0].last_location.is_some());
+
+    // Alice stops her share.
+    let joined = JoinedRoomBuilder::new(room_id).add_state_bulk(vec![f
+        .event(BeaconInfoEventContent::new(None, Duration::from_secs(60), false, None))
+        .event_id(event_id!("$alice_beacon_info_stop"))
+        .sender(alice)
+        .state_key(alice)
+        .into_raw_timeline()
+        .cast()]);
+    server.sync_room(&client, joined).await;
+
+    let shares = stream.next().await.unwrap();
+    assert!(shares.is_empty());
+}
+
+#[async_test]
+async fn test_send_location() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, &DEFAULT_TEST_ROOM_ID).await;
+    server.mock_room_state_encryption().plain().mount().await;
+
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "msgtype": "m.location",
+            "body": "Location",
+            "geo_uri": "geo:51.5008,0.1247;u=35",
+            "org.matrix.msc3488.location": {
+                "uri": "geo:51.5008,0.1247;u=35",
+                "description": "Big Ben",
+            },
+            "org.matrix.msc3488.asset": { "type": "m.pin" },
+        }))
+        .ok(event_id!("$location"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let response = room
+        .send_location(
+            "Location".to_owned(),
+            "geo:51.5008,0.1247;u=35".to_owned(),
+            Some("Big Ben".to_owned()),
+            None,
+            Some(AssetType::Pin),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.event_id, event_id!("$location"));
+}
@@ -0,0 +1,364 @@
+use assert_matches2::assert_matches;
+use matrix_sdk::{
+    image_pack::{ImagePackError, ImagePackSource},
+    media::MediaFormat,
+    test_utils::mocks::MatrixMockServer,
+    Error,
+};
+use matrix_sdk_test::{async_test, GlobalAccountDataTestEvent, JoinedRoomBuilder, StateTestEvent};
+use ruma::{event_id, events::room::message::MessageType, room_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_available_image_packs() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let other_room_id = room_id!("!d:e.f");
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder
+                .add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                    "type": "im.ponies.user_emotes",
+                    "content": {
+                        "images": { "wave": { "url": "mxc://example.org/wave" } },
+                    },
+                })))
+                .add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                    "type": "im.ponies.emote_rooms",
+                    "content": {
+                        "rooms": { other_room_id: { "work": {} } },
+                    },
+                })))
+                .add_joined_room(JoinedRoomBuilder::new(room_id).add_state_event(
+                    StateTestEvent::Custom(json!({
+                        "type": "im.ponies.room_emotes",
+                        "state_key": "cats",
+                        "event_id": "$cats",
+                        "sender": "@alice:b.c",
+                        "origin_server_ts": 1,
+                        "content": {
+                            "images": {
+                                "cat": { "url": "mxc://example.org/cat" },
+                                "catjam": {
+                                    "url": "mxc://example.org/catjam",
+                                    "usage": ["emoticon"],
+                                },
+                            },
+                            "pack": { "display_name": "Cats", "usage": ["sticker"] },
+                        },
+                    })),
+                ))
+                .add_joined_room(
+                    JoinedRoomBuilder::new(other_room_id)
+                        .add_state_event(StateTestEvent::Custom(json!({
+                            "type": "im.ponies.room_emotes",
+                            "state_key": "work",
+                            "event_id": "$work",
+                            "sender": "@alice:b.c",
+                            "origin_server_ts": 1,
+                            "content": {
+                                "images": { "ship_it": { "url": "mxc://example.org/ship_it" } },
+                            },
+                        })))
+                        .add_state_event(StateTestEvent::Custom(json!({
+                            "type": "im.ponies.room_emotes",
+                            "state_key": "fun",
+                            "event_id": "$fun",
+                            "sender": "@alice:b.c",
+                            "origin_server_ts": 1,
+                            "content": {
+                                "images": { "party": { "url": "mxc://example.org/party" } },
+                            },
+                        }))),
+                );
+        })
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+
+    // The user pack, then the packs of the room, then the enabled packs of
+    // other rooms.
+    let packs = room.available_image_packs().await.unwrap();
+    assert_eq!(packs.len(), 3);
+
+    assert_eq!(*packs[0].source(), ImagePackSource::User);
+    assert!(packs[0].image("wave").is_some());
+
+    assert_eq!(
+        *packs[1].source(),
+        ImagePackSource::Room { room_id: room_id.to_owned(), state_key: "cats".to_owned() }
+    );
+    assert_eq!(packs[1].display_name().as_deref(), Some("Cats"));
+    let stickers = packs[1].stickers().map(|(shortcode, _)| shortcode).collect::<Vec<_>>();
+    assert_eq!(stickers, ["cat"]);
+    let emoticons = packs[1].emoticons().map(|(shortcode, _)| shortcode).collect::<Vec<_>>();
+    assert_eq!(emoticons, ["catjam"]);
+
+    assert_eq!(
+        *packs[2].source(),
+        ImagePackSource::Room { room_id: other_room_id.to_owned(), state_key: "work".to_owned() }
+    );
+
+    // The enabled pack is also available in the other room, only once.
+    let other_room = client.get_room(other_room_id).unwrap();
+    let packs = other_room.available_image_packs().await.unwrap();
+    assert_eq!(packs.len(), 3);
+}
+
+#[async_test]
+async fn test_send_sticker() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "type": "im.ponies.room_emotes",
+                "state_key": "cats",
+                "event_id": "$cats",
+                "sender": "@alice:b.c",
+                "origin_server_ts": 1,
+                "content": {
+                    "images": {
+                        "cat": {
+                            "url": "mxc://example.org/cat",
+                            "body": "A happy cat",
+                            "info": { "mimetype": "image/png", "w": 128, "h": 128 },
+                        },
+                        "catjam": { "url": "mxc://example.org/catjam", "usage": ["emoticon"] },
+                    },
+                },
+            }))),
+        )
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let packs = room.image_packs().await.unwrap();
+    assert_eq!(packs.len(), 1);
+    let pack = &packs[0];
+
+    server.mock_room_state_encryption().plain().mount().await;
+    server
+        .mock_room_send()
+        .for_type("m.sticker".into())
+        .body_matches_partial_json(json!({
+            "body": "A happy cat",
+            "url": "mxc://example.org/cat",
+            "info": { "mimetype": "image/png", "w": 128, "h": 128 },
+        }))
+        .ok(event_id!("$sticker"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let response = room.send_sticker(pack, "cat").await.unwrap();
+    assert_eq!(response.event_id, event_id!("$sticker"));
+
+    // Images that can't be used as stickers, or unknown shortcodes, are
+    // rejected.
+    assert_matches!(
+        room.send_sticker(pack, "catjam").await,
+        Err(Error::ImagePack(ImagePackError::NotASticker(shortcode)))
+    );
+    assert_eq!(shortcode, "catjam");
+    assert_matches!(
+        room.send_sticker(pack, "dog").await,
+        Err(Error::ImagePack(ImagePackError::UnknownShortcode(_)))
+    );
+}
+
+#[async_test]
+async fn test_image_content_is_cached() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "type": "im.ponies.user_emotes",
+                "content": {
+                    "images": { "wave": { "url": "mxc://example.org/wave" } },
+                },
+            })));
+        })
+        .await;
+
+    server.mock_versions().ok().mount().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"/download/example.org/wave$"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"wave".to_vec()))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let pack = client.account().image_pack().await.unwrap().unwrap();
+
+    // The media is only downloaded once.
+    for _ in 0..2 {
+        let content = pack.image_content("wave", MediaFormat::File).await.unwrap();
+        assert_eq!(content.as_deref(), Some(b"wave".as_slice()));
+    }
+
+    assert!(pack.image_content("unknown", MediaFormat::File).await.unwrap().is_none());
+}
+
+#[async_test]
+async fn test_enable_room_image_pack() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/im.ponies.emote_rooms$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": { "!a:b.c": { "cats": {} } },
+        })))
+        .mount(server.server())
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/im.ponies.emote_rooms$"))
+        .and(body_json(json!({
+            "rooms": { "!a:b.c": { "cats": {}, "dogs": {} } },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    client.account().enable_room_image_pack(room_id!("!a:b.c"), "dogs", true).await.unwrap();
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/im.ponies.emote_rooms$"))
+        .and(body_json(json!({ "rooms": {} })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    client.account().enable_room_image_pack(room_id!("!a:b.c"), "cats", false).await.unwrap();
+}
+
+#[async_test]
+async fn test_emotes() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder
+                .add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                    "type": "im.ponies.user_emotes",
+                    "content": {
+                        "images": { "cat": { "url": "mxc://example.org/my_cat" } },
+                    },
+                })))
+                .add_joined_room(JoinedRoomBuilder::new(room_id).add_state_event(
+                    StateTestEvent::Custom(json!({
+                        "type": "im.ponies.room_emotes",
+                        "state_key": "cats",
+                        "event_id": "$cats",
+                        "sender": "@alice:b.c",
+                        "origin_server_ts": 1,
+                        "content": {
+                            "images": {
+                                "cat": { "url": "mxc://example.org/cat" },
+                                "catjam": {
+                                    "url": "mxc://example.org/catjam",
+                                    "body": "Cat jamming",
+                                },
+                                "big_cat": {
+                                    "url": "mxc://example.org/big_cat",
+                                    "usage": ["sticker"],
+                                },
+                            },
+                        },
+                    })),
+                ));
+        })
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+
+    // Without a room, only the emotes of the account are available.
+    let emotes = client.account().emotes().await.unwrap();
+    assert_eq!(emotes.len(), 1);
+
+    // The emote of the account takes precedence, and stickers aren't emotes.
+    let emotes = room.emotes().await.unwrap();
+    assert_eq!(emotes.len(), 2);
+    assert_eq!(emotes.get("cat").unwrap().url, "mxc://example.org/my_cat");
+    assert_eq!(emotes.get("catjam").unwrap().alt_text(), "Cat jamming");
+    assert!(emotes.get("big_cat").is_none());
+
+    // Find the emotes in a plain text.
+    let text = "Hello :cat::catjam: :big_cat: :not an emote: :catjam:";
+    let matches = emotes.find_in_text(text);
+    let ranges = matches.iter().map(|emote_match| &text[emote_match.range.clone()]);
+    assert_eq!(ranges.collect::<Vec<_>>(), [":cat:", ":catjam:", ":catjam:"]);
+
+    // Find the emotes in HTML, including the ones that aren't known.
+    let html = "Hello \
+        <img data-mx-emoticon src=\"mxc://example.org/catjam\" alt=\":catjam:\"> \
+        <img src=\"mxc://example.org/picture\" alt=\"Not an emote\"> \
+        <p><img data-mx-emoticon src=\"mxc://example.org/dog\" alt=\":dog:\"></p>";
+    let found = emotes.find_in_html(html);
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].shortcode, "catjam");
+    assert!(found[0].pack.is_some());
+    assert_eq!(found[1].shortcode, "dog");
+    assert_eq!(found[1].url, "mxc://example.org/dog");
+    assert!(found[1].pack.is_none());
+
+    // Find the emotes in a message.
+    let found = emotes.find_in_message(&MessageType::text_html("Hello :cat:", html));
+    assert_eq!(found.len(), 2);
+    let found = emotes.find_in_message(&MessageType::text_plain("Hello :cat:"));
+    assert_eq!(found.len(), 1);
+}
+
+#[async_test]
+async fn test_observe_emotes() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let observable = room.observe_emotes().await.unwrap();
+    assert!(observable.get().is_empty());
+    let mut subscriber = observable.subscribe();
+
+    // An image pack is added to the room.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Custom(json!({
+                "type": "im.ponies.room_emotes",
+                "state_key": "cats",
+                "event_id": "$cats",
+                "sender": "@alice:b.c",
+                "origin_server_ts": 1,
+                "content": {
+                    "images": { "cat": { "url": "mxc://example.org/cat" } },
+                },
+            }))),
+        )
+        .await;
+
+    let emotes = subscriber.next().await.unwrap();
+    assert!(emotes.get("cat").is_some());
+    assert!(observable.get().get("cat").is_some());
+}
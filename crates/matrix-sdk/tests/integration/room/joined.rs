@@ -9,9 +9,17 @@ use assert_matches2::assert_let;
 use futures_util::{future::join_all, pin_mut};
 use matrix_sdk::{
     assert_next_with_timeout, assert_recv_with_timeout,
+    clock::TestClock,
     config::SyncSettings,
-    room::{edit::EditedContent, Receipts, ReportedContentScore, RoomMemberRole},
-    test_utils::mocks::MatrixMockServer,
+    event_handler::{CustomEventContent, CustomEventKind},
+    room::{
+        edit::EditedContent,
+        receipts::{UnreadState, RECEIPTS_BATCH_DELAY},
+        Receipts, ReportedContentScore, RoomMemberRole,
+    },
+    test_utils::mocks::{MatrixMockServer, RoomMessagesResponseTemplate},
+    timeout::timeout,
+    RoomMemberships,
 };
 use matrix_sdk_base::{EncryptionState, RoomMembersUpdate, RoomState};
 use matrix_sdk_test::{
@@ -19,14 +27,15 @@ use matrix_sdk_test::{
     event_factory::EventFactory,
     mocks::mock_encryption_state,
     test_json::{self, sync::CUSTOM_ROOM_POWER_LEVELS},
-    GlobalAccountDataTestEvent, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
-    DEFAULT_TEST_ROOM_ID,
+    GlobalAccountDataTestEvent, JoinedRoomBuilder, RoomAccountDataTestEvent, StateTestEvent,
+    SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
     api::client::{membership::Invite3pidInit, receipt::create_receipt::v3::ReceiptType},
     assign, event_id,
     events::{
         direct::DirectUserIdentifier,
+        marked_unread::MarkedUnreadEventContent,
         receipt::ReceiptThread,
         room::{
             member::MembershipState,
@@ -36,6 +45,7 @@ use ruma::{
     },
     int, mxc_uri, owned_event_id, room_id, thirdparty, user_id, OwnedUserId, TransactionId,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::{from_value, json, Value};
 use stream_assert::assert_pending;
 use tokio::time::sleep;
@@ -268,6 +278,141 @@ async fn test_send_multiple_receipts() {
     room.send_multiple_receipts(receipts).await.unwrap();
 }
 
+#[async_test]
+async fn test_queue_receipts() {
+    let server = MatrixMockServer::new().await;
+    let clock = TestClock::new();
+    let client = server.client_builder().clock(Arc::new(clock.clone())).build().await;
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!room:localhost");
+    let alice = user_id!("@alice:localhost");
+    let f = EventFactory::new().room(room_id).sender(alice);
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk(vec![
+                f.text_msg("first").event_id(event_id!("$1")).into_raw_sync(),
+                f.text_msg("second").event_id(event_id!("$2")).into_raw_sync(),
+                f.text_msg("in thread")
+                    .event_id(event_id!("$3"))
+                    .in_thread(event_id!("$1"), event_id!("$1"))
+                    .into_raw_sync(),
+                f.text_msg("third").event_id(event_id!("$4")).into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/read_markers$"))
+        .and(body_json(json!({ "m.fully_read": "$2", "m.read": "$2" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/receipt/m.read/\$3$"))
+        .and(body_json(json!({ "thread_id": "$1" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/receipt/m.read/\$4$"))
+        .and(body_json(json!({ "thread_id": "main" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let queue = |receipt_type, thread, event_id: &str| {
+        room.queue_receipt(receipt_type, thread, event_id.try_into().unwrap())
+    };
+
+    // The latest receipt wins, and receipts can't move backwards.
+    assert!(queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$1").await.unwrap());
+    assert!(queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$2").await.unwrap());
+    assert!(!queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$1").await.unwrap());
+    assert!(queue(ReceiptType::FullyRead, ReceiptThread::Unthreaded, "$2").await.unwrap());
+    let thread = ReceiptThread::Thread(owned_event_id!("$1"));
+    assert!(queue(ReceiptType::Read, thread.clone(), "$3").await.unwrap());
+
+    // The queued receipts are taken into account in the unread state. The events of
+    // the threads are read according to the receipts of their thread.
+    let state = room.unread_state(ReceiptThread::Unthreaded).await.unwrap();
+    assert_eq!(state, UnreadState { num_unread_events: 1, is_exact: true });
+    let state = room.unread_state(thread).await.unwrap();
+    assert_eq!(state, UnreadState { num_unread_events: 0, is_exact: true });
+    let state = room.unread_state(ReceiptThread::Main).await.unwrap();
+    assert_eq!(state, UnreadState { num_unread_events: 1, is_exact: true });
+
+    // The main timeline has its own receipts.
+    assert!(queue(ReceiptType::Read, ReceiptThread::Main, "$4").await.unwrap());
+    let state = room.unread_state(ReceiptThread::Main).await.unwrap();
+    assert_eq!(state, UnreadState { num_unread_events: 0, is_exact: true });
+    let state = room.unread_state(ReceiptThread::Unthreaded).await.unwrap();
+    assert_eq!(state, UnreadState { num_unread_events: 0, is_exact: true });
+
+    room.flush_receipts().await.unwrap();
+    server.server().verify().await;
+
+    // The sent receipts can't move backwards either.
+    assert!(!queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$1").await.unwrap());
+
+    // The next receipts are sent after a delay.
+    let guard = Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/read_markers$"))
+        .and(body_json(json!({ "m.read": "$4" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount_as_scoped(server.server())
+        .await;
+
+    assert!(queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$4").await.unwrap());
+    // Let the batching task start waiting before moving the time forward.
+    tokio::task::yield_now().await;
+    clock.advance(RECEIPTS_BATCH_DELAY);
+    timeout(guard.wait_until_satisfied(), Duration::from_secs(1)).await.unwrap();
+    drop(guard);
+
+    // The receipts are sent again later if sending them failed.
+    let failure = Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/read_markers$"))
+        .and(body_json(json!({ "m.read": "$5" })))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_UNKNOWN",
+            "error": "Try again later",
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .with_priority(1)
+        .mount_as_scoped(server.server())
+        .await;
+    let success = Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/read_markers$"))
+        .and(body_json(json!({ "m.read": "$5" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount_as_scoped(server.server())
+        .await;
+
+    assert!(queue(ReceiptType::Read, ReceiptThread::Unthreaded, "$5").await.unwrap());
+    timeout(
+        async {
+            while success.received_requests().await.is_empty() {
+                clock.advance(RECEIPTS_BATCH_DELAY);
+                tokio::task::yield_now().await;
+            }
+        },
+        Duration::from_secs(1),
+    )
+    .await
+    .unwrap();
+    drop(failure);
+    drop(success);
+}
+
 #[async_test]
 async fn test_typing_notice() {
     let (client, server) = logged_in_client_with_server().await;
@@ -290,6 +435,44 @@ async fn test_typing_notice() {
     room.typing_notice(true).await.unwrap();
 }
 
+#[async_test]
+async fn test_typing_guard() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!room:localhost")).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The guards share the same typing notice.
+    let first_guard = room.typing_guard().await;
+    let second_guard = room.typing_guard().await;
+    sleep(Duration::from_millis(100)).await;
+    drop(first_guard);
+    drop(second_guard);
+
+    // A new guard right after the last one is dropped keeps the typing notice.
+    let third_guard = room.typing_guard().await;
+    sleep(Duration::from_millis(100)).await;
+    drop(third_guard);
+
+    // The typing notice is stopped after a grace period.
+    sleep(Duration::from_secs(1)).await;
+    server.server().verify().await;
+}
+
 #[async_test]
 async fn test_room_state_event_send() {
     use ruma::events::room::member::{MembershipState, RoomMemberEventContent};
@@ -366,6 +549,64 @@ async fn test_room_redact() {
     assert_eq!(response.event_id, event_id);
 }
 
+#[async_test]
+async fn test_send_custom_events() {
+    #[derive(Debug, Deserialize, Serialize)]
+    struct GameMove {
+        from: String,
+        to: String,
+    }
+
+    impl CustomEventContent for GameMove {
+        const TYPE: &'static str = "com.example.game.move";
+        const KIND: CustomEventKind = CustomEventKind::MessageLike;
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct GameBoard {
+        size: u8,
+    }
+
+    impl CustomEventContent for GameBoard {
+        const TYPE: &'static str = "com.example.game.board";
+        const KIND: CustomEventKind = CustomEventKind::State;
+    }
+
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_joined_room(&client, &DEFAULT_TEST_ROOM_ID).await;
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let event_id = event_id!("$move");
+    server
+        .mock_room_send()
+        .for_type("com.example.game.move".into())
+        .body_matches_partial_json(json!({ "from": "e2", "to": "e4" }))
+        .ok(event_id)
+        .mock_once()
+        .mount()
+        .await;
+
+    let response =
+        room.send_custom(GameMove { from: "e2".to_owned(), to: "e4".to_owned() }).await.unwrap();
+    assert_eq!(response.event_id, event_id);
+
+    let event_id = event_id!("$board");
+    server
+        .mock_room_send_state()
+        .for_type("com.example.game.board".into())
+        .for_key("main".to_owned())
+        .body_matches_partial_json(json!({ "size": 8 }))
+        .ok(event_id)
+        .mock_once()
+        .mount()
+        .await;
+
+    let response = room.send_custom_state("main", GameBoard { size: 8 }).await.unwrap();
+    assert_eq!(response.event_id, event_id);
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 async fn test_fetch_members_deduplication() {
@@ -1195,3 +1436,225 @@ async fn test_report_room() {
 
     room.report_room(Some(reason.to_owned())).await.unwrap();
 }
+
+#[async_test]
+async fn test_observe_room_account_data() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!a:b.c");
+
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    let observable = room.observe_account_data::<MarkedUnreadEventContent>().await.unwrap();
+    assert!(observable.get().is_none());
+    let mut subscriber = observable.subscribe();
+
+    // An update in another room is ignored.
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id!("!other:b.c")).add_account_data(
+                RoomAccountDataTestEvent::Custom(json!({
+                    "type": "m.marked_unread",
+                    "content": { "unread": true },
+                })),
+            ),
+        )
+        .await;
+    assert_pending!(subscriber);
+
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_account_data(RoomAccountDataTestEvent::Custom(
+                json!({
+                    "type": "m.marked_unread",
+                    "content": { "unread": true },
+                }),
+            )),
+        )
+        .await;
+
+    assert_let!(Some(Some(content)) = subscriber.next().await);
+    assert!(content.unread);
+    assert!(observable.get().unwrap().unread);
+}
+
+#[async_test]
+async fn test_members_stream() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let room_id = room_id!("!a:b.c");
+    let alice = user_id!("@alice:b.c");
+    let bob = user_id!("@bob:b.c");
+    let f = EventFactory::new().room(room_id);
+
+    let alice_join = f.member(alice).membership(MembershipState::Join).into_raw_timeline();
+    let bob_join = f.member(bob).membership(MembershipState::Join).into_raw_timeline();
+
+    // The sync only contains the members that are relevant to the timeline.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk(vec![alice_join.clone().cast()]),
+        )
+        .await;
+    assert!(!room.are_members_synced());
+
+    // The missing members are fetched at the point of the latest sync.
+    server
+        .mock_get_members()
+        .match_at("t392-516_47314_0_7_1_1_1_11444_1")
+        .ok(vec![alice_join.cast(), bob_join.cast()])
+        .mock_once()
+        .mount()
+        .await;
+
+    let stream = room.members_stream(RoomMemberships::JOIN).await.unwrap();
+    pin_mut!(stream);
+
+    // First, the members that are known locally are yielded.
+    let chunk = assert_next_with_timeout!(stream);
+    assert!(!chunk.is_complete);
+    assert_eq!(chunk.members.len(), 1);
+    assert_eq!(chunk.members[0].user_id(), alice);
+
+    // Then the members that were missing.
+    let chunk = assert_next_with_timeout!(stream);
+    assert!(chunk.is_complete);
+    assert_eq!(chunk.members.len(), 1);
+    assert_eq!(chunk.members[0].user_id(), bob);
+    assert!(room.are_members_synced());
+
+    // When a member joins, it's added.
+    let carol = user_id!("@carol:b.c");
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk(vec![f
+                .member(carol)
+                .membership(MembershipState::Join)
+                .into_raw_timeline()
+                .cast()]),
+        )
+        .await;
+
+    let chunk = assert_next_with_timeout!(stream);
+    assert!(chunk.is_complete);
+    assert_eq!(chunk.members.len(), 1);
+    assert_eq!(chunk.members[0].user_id(), carol);
+
+    // A gappy sync makes the member list incomplete, and the members are fetched
+    // again. The member event of Carol from the response is older than the one
+    // received by the sync, so it is ignored.
+    let dan = user_id!("@dan:b.c");
+    server
+        .mock_get_members()
+        .match_at("t392-516_47314_0_7_1_1_1_11444_1")
+        .ok(vec![
+            f.member(alice).membership(MembershipState::Join).into_raw_timeline().cast(),
+            f.member(bob).membership(MembershipState::Join).into_raw_timeline().cast(),
+            f.member(carol)
+                .membership(MembershipState::Invite)
+                .server_ts(0)
+                .into_raw_timeline()
+                .cast(),
+            f.member(dan).membership(MembershipState::Join).into_raw_timeline().cast(),
+        ])
+        .mock_once()
+        .mount()
+        .await;
+    server.sync_room(&client, JoinedRoomBuilder::new(room_id).set_timeline_limited()).await;
+
+    let chunk = assert_next_with_timeout!(stream);
+    assert!(!chunk.is_complete);
+    assert!(chunk.members.is_empty());
+
+    let chunk = assert_next_with_timeout!(stream);
+    assert!(chunk.is_complete);
+    assert_eq!(chunk.members.len(), 1);
+    assert_eq!(chunk.members[0].user_id(), dan);
+
+    let carol_member = room.get_member_no_sync(carol).await.unwrap().unwrap();
+    assert_eq!(*carol_member.membership(), MembershipState::Join);
+
+    assert_pending!(stream);
+}
+
+#[async_test]
+async fn test_membership_history() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    client.event_cache().subscribe().unwrap();
+
+    server.mock_room_state_encryption().plain().mount().await;
+
+    let room_id = room_id!("!a:b.c");
+    let alice = user_id!("@alice:b.c");
+    let bob = user_id!("@bob:b.c");
+    let f = EventFactory::new().room(room_id);
+
+    let alice_join = f
+        .member(alice)
+        .membership(MembershipState::Join)
+        .event_id(event_id!("$alice_join"))
+        .server_ts(1000);
+    let bob_join = f
+        .member(bob)
+        .membership(MembershipState::Join)
+        .event_id(event_id!("$bob_join"))
+        .server_ts(2000);
+    let alice_rename = f
+        .member(alice)
+        .membership(MembershipState::Join)
+        .display_name("Alice")
+        .event_id(event_id!("$alice_rename"))
+        .server_ts(3000);
+    let alice_ban = f
+        .member(alice)
+        .membership(MembershipState::Ban)
+        .sender(bob)
+        .event_id(event_id!("$alice_ban"))
+        .server_ts(4000)
+        .into_raw_timeline();
+
+    // The latest change is known by the event cache.
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_event(alice_ban.clone().cast()),
+        )
+        .await;
+
+    // The homeserver returns it too, along with the changes of the other members.
+    server
+        .mock_room_messages()
+        .ok(RoomMessagesResponseTemplate::default().events(vec![
+            alice_ban,
+            alice_rename.into_raw_timeline(),
+            bob_join.into_raw_timeline(),
+            alice_join.into_raw_timeline(),
+        ]))
+        .mock_once()
+        .mount()
+        .await;
+
+    let mut history = room.membership_history(alice).batch_size(2);
+    assert!(!history.is_at_end());
+
+    let page = history.next_page().await.unwrap();
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].event_id(), "$alice_ban");
+    assert_eq!(page[1].event_id(), "$alice_rename");
+    assert!(!history.is_at_end());
+
+    let page = history.next_page().await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].event_id(), "$alice_join");
+    assert!(history.is_at_end());
+
+    assert!(history.next_page().await.unwrap().is_empty());
+}
@@ -0,0 +1,171 @@
+use assert_matches2::{assert_let, assert_matches};
+use matrix_sdk::{room::mentions::MentionsError, test_utils::mocks::MatrixMockServer, Error};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, ALICE, BOB};
+use ruma::{
+    event_id,
+    events::{
+        room::{
+            member::MembershipState,
+            message::{MessageType, Relation, RoomMessageEventContent},
+            power_levels::RoomPowerLevelsEventContent,
+        },
+        Mentions,
+    },
+    int, owned_user_id, room_id, user_id,
+};
+
+#[async_test]
+async fn test_message_with_mentions() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk([
+                f.member(&ALICE).display_name("Alice <3").into_raw(),
+                f.member(&ALICE).invited(&BOB).display_name("Bob").into_raw(),
+                f.member(user_id!("@carol:b.c"))
+                    .membership(MembershipState::Leave)
+                    .display_name("Carol")
+                    .into_raw(),
+            ]),
+        )
+        .await;
+
+    let content = room
+        .message_with_mentions()
+        .text("Hey ")
+        .user(ALICE.to_owned())
+        .text(" & ")
+        .user(BOB.to_owned())
+        .text("!")
+        .await
+        .unwrap();
+
+    assert_let!(MessageType::Text(text) = content.msgtype);
+    assert_eq!(text.body, "Hey Alice <3 & Bob!");
+    assert_eq!(
+        text.formatted.unwrap().body,
+        "Hey <a href=\"https://matrix.to/#/@alice:server.name\">Alice &lt;3</a> &amp; \
+         <a href=\"https://matrix.to/#/@bob:other.server\">Bob</a>!"
+    );
+
+    let mentions = content.mentions.unwrap();
+    assert!(!mentions.room);
+    assert_eq!(
+        mentions.user_ids.into_iter().collect::<Vec<_>>(),
+        [ALICE.to_owned(), BOB.to_owned()]
+    );
+
+    // Users who left the room or who were never in it can't be mentioned.
+    for user_id in [owned_user_id!("@carol:b.c"), owned_user_id!("@dan:b.c")] {
+        assert_matches!(
+            room.message_with_mentions().user(user_id.clone()).await,
+            Err(Error::Mentions(MentionsError::UserNotInRoom(not_in_room)))
+        );
+        assert_eq!(not_in_room, user_id);
+    }
+}
+
+#[async_test]
+async fn test_message_with_room_mention() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk([f
+                .event(RoomPowerLevelsEventContent::new())
+                .state_key("")
+                .into_raw()]),
+        )
+        .await;
+
+    // By default, only moderators can mention the whole room.
+    assert_matches!(
+        room.message_with_mentions().room().text(": hello").await,
+        Err(Error::Mentions(MentionsError::RoomMentionForbidden))
+    );
+
+    let mut power_levels = RoomPowerLevelsEventContent::new();
+    power_levels.users.insert(client.user_id().unwrap().to_owned(), int!(50));
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.event(power_levels).state_key("").into_raw()]),
+        )
+        .await;
+
+    let content = room.message_with_mentions().room().text(": hello").await.unwrap();
+    assert_eq!(content.body(), "@room: hello");
+    assert!(content.mentions.unwrap().room);
+}
+
+#[async_test]
+async fn test_edit_message_with_mentions() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let own_user_id = client.user_id().unwrap();
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_state_bulk([
+                f.member(&ALICE).display_name("Alice").into_raw(),
+                f.member(&BOB).display_name("Bob").into_raw(),
+            ]),
+        )
+        .await;
+
+    // The original message mentions Alice.
+    let event_id = event_id!("$original");
+    server
+        .mock_room_event()
+        .match_event_id()
+        .ok(f
+            .event(
+                RoomMessageEventContent::text_plain("Hey Alice")
+                    .add_mentions(Mentions::with_user_ids([ALICE.to_owned()])),
+            )
+            .sender(own_user_id)
+            .event_id(event_id)
+            .into())
+        .mock_once()
+        .mount()
+        .await;
+
+    // The edit mentions Alice and Bob.
+    let content = room
+        .message_with_mentions()
+        .text("Hey ")
+        .user(ALICE.to_owned())
+        .text(" and ")
+        .user(BOB.to_owned())
+        .edit(event_id)
+        .await
+        .unwrap();
+
+    assert_eq!(content.body(), "* Hey Alice and Bob");
+
+    // Only Bob is notified by the edit.
+    let mentions = content.mentions.unwrap();
+    assert_eq!(mentions.user_ids.into_iter().collect::<Vec<_>>(), [BOB.to_owned()]);
+
+    // But both are mentioned by the new content.
+    assert_let!(Some(Relation::Replacement(replacement)) = content.relates_to);
+    assert_eq!(replacement.event_id, event_id);
+    let new_mentions = replacement.new_content.mentions.unwrap();
+    assert_eq!(
+        new_mentions.user_ids.into_iter().collect::<Vec<_>>(),
+        [ALICE.to_owned(), BOB.to_owned()]
+    );
+}
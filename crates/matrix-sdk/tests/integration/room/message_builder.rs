@@ -0,0 +1,77 @@
+use assert_matches2::{assert_let, assert_matches};
+use matrix_sdk::{
+    room::{
+        message_builder::{MessageBuilder, MessageBuilderError},
+        reply::{EnforceThread, Reply},
+    },
+    test_utils::mocks::MatrixMockServer,
+    Error,
+};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, ALICE};
+use ruma::{
+    api::MatrixVersion,
+    event_id,
+    events::room::message::{MessageType, Relation},
+    room_id,
+};
+
+#[async_test]
+async fn test_make_message_reply() {
+    let room_id = room_id!("!a:b.c");
+    let event_id = event_id!("$replied_to");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+
+    for (version, has_fallback) in [(MatrixVersion::V1_12, true), (MatrixVersion::V1_13, false)] {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().server_versions([version]).build().await;
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        server
+            .mock_room_event()
+            .match_event_id()
+            .ok(f.text_msg("Are you coming?").event_id(event_id).into())
+            .mock_once()
+            .mount()
+            .await;
+
+        let reply =
+            Reply { event_id: event_id.to_owned(), enforce_thread: EnforceThread::MaybeThreaded };
+        let content = room
+            .make_message(MessageBuilder::html("Sure!", "<b>Sure!</b>"), Some(reply))
+            .await
+            .unwrap();
+
+        assert_let!(Some(Relation::Reply { in_reply_to }) = content.relates_to);
+        assert_eq!(in_reply_to.event_id, event_id);
+
+        assert_let!(MessageType::Text(text) = content.msgtype);
+        let html_body = text.formatted.unwrap().body;
+
+        if has_fallback {
+            assert!(text.body.starts_with("> <@alice:server.name> Are you coming?"));
+            assert!(text.body.ends_with("\n\nSure!"));
+            assert!(html_body.starts_with("<mx-reply>"));
+            assert!(html_body.ends_with("<b>Sure!</b>"));
+        } else {
+            assert_eq!(text.body, "Sure!");
+            assert_eq!(html_body, "<b>Sure!</b>");
+        }
+    }
+}
+
+#[async_test]
+async fn test_make_message_too_large() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+    let builder = MessageBuilder::plain("a".repeat(1000)).max_size(500);
+    assert_matches!(
+        room.make_message(builder, None).await,
+        Err(Error::MessageBuilder(MessageBuilderError::TooLarge { max_size: 500, .. }))
+    );
+
+    let content = room.make_message(MessageBuilder::plain("a"), None).await.unwrap();
+    assert_eq!(content.body(), "a");
+    assert!(content.relates_to.is_none());
+}
@@ -2,8 +2,15 @@ mod attachment;
 mod beacon;
 mod beacon_info;
 mod common;
+mod image_pack;
 mod joined;
 mod left;
+mod mentions;
+mod message_builder;
+mod moderation;
 mod notification_mode;
+mod permissions;
+mod polls;
 mod spaces;
+mod state;
 mod tags;
@@ -0,0 +1,184 @@
+use matrix_sdk::test_utils::mocks::MatrixMockServer;
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, ALICE, BOB};
+use ruma::{
+    event_id,
+    events::{
+        policy::rule::{
+            server::PolicyRuleServerEventContent, user::PolicyRuleUserEventContent,
+            PolicyRuleEventContent, Recommendation,
+        },
+        room::member::{MembershipState, RoomMemberEventContent},
+    },
+    room_id, user_id,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_ban_users() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/ban$"))
+        .and(body_partial_json(json!({ "user_id": ALICE.as_str(), "reason": "spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/ban$"))
+        .and(body_partial_json(json!({ "user_id": BOB.as_str() })))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You can't ban this user",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let failures =
+        room.moderation().ban_users([ALICE.to_owned(), BOB.to_owned()], Some("spam")).await;
+
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, *BOB);
+}
+
+#[async_test]
+async fn test_apply_policy_room() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let policy_room_id = room_id!("!policies:b.c");
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().sender(user_id!("@moderator:b.c"));
+
+    let policy_room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(policy_room_id).add_state_bulk([
+                f.event(PolicyRuleUserEventContent(PolicyRuleEventContent::new(
+                    "@spam*:b.c".to_owned(),
+                    Recommendation::Ban,
+                    "spam".to_owned(),
+                )))
+                .state_key("rule:user")
+                .into_raw_sync()
+                .cast(),
+                f.event(PolicyRuleServerEventContent(PolicyRuleEventContent::new(
+                    "evil.org".to_owned(),
+                    Recommendation::Ban,
+                    String::new(),
+                )))
+                .state_key("rule:server")
+                .into_raw_sync()
+                .cast(),
+            ]),
+        )
+        .await;
+
+    let rules = policy_room.moderation().policy_rules().await.unwrap();
+    assert_eq!(rules.len(), 2);
+
+    let member = |user_id| {
+        f.event(RoomMemberEventContent::new(MembershipState::Join))
+            .room(room_id)
+            .sender(user_id)
+            .state_key(user_id)
+            .into_raw_timeline()
+            .cast()
+    };
+    server
+        .mock_get_members()
+        .ok(vec![
+            member(user_id!("@example:localhost")),
+            member(user_id!("@spammer:b.c")),
+            member(user_id!("@alice:evil.org")),
+            member(user_id!("@bob:b.c")),
+            member(user_id!("@spambot:b.c")),
+        ])
+        .mock_once()
+        .mount()
+        .await;
+    let room = server.sync_joined_room(&client, room_id).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/ban$"))
+        .and(body_partial_json(json!({ "user_id": "@spammer:b.c", "reason": "spam" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/ban$"))
+        .and(body_partial_json(json!({ "user_id": "@alice:evil.org" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // A user that can't be banned doesn't prevent the others from being banned.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/ban$"))
+        .and(body_partial_json(json!({ "user_id": "@spambot:b.c" })))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You can't ban this user",
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let mut applied = room.moderation().apply_policy_room(&policy_room).await.unwrap();
+    applied.banned.sort();
+    assert_eq!(applied.banned, [user_id!("@alice:evil.org"), user_id!("@spammer:b.c")]);
+    assert_eq!(applied.failures.len(), 1);
+    assert_eq!(applied.failures[0].0, "@spambot:b.c");
+}
+
+#[async_test]
+async fn test_remove_recent_messages() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id);
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk(vec![
+                f.text_msg("spam 1").sender(&BOB).event_id(event_id!("$1")).into_raw_sync(),
+                f.text_msg("hello").sender(&ALICE).event_id(event_id!("$2")).into_raw_sync(),
+                f.text_msg("spam 2").sender(&BOB).event_id(event_id!("$3")).into_raw_sync(),
+                f.text_msg("spam 3").sender(&BOB).event_id(event_id!("$4")).into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    // An event that can't be redacted doesn't prevent the others from being
+    // redacted.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/redact/(%24|\$)4/"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You can't redact this event",
+        })))
+        .with_priority(1)
+        .expect(1)
+        .mount(server.server())
+        .await;
+    server.mock_room_redact().ok(event_id!("$redaction")).expect(2).mount().await;
+
+    let removed = room.moderation().remove_recent_messages(&BOB, 3, Some("spam")).await.unwrap();
+    assert_eq!(removed.redacted, [event_id!("$3"), event_id!("$1")]);
+    assert_eq!(removed.failures.len(), 1);
+    assert_eq!(removed.failures[0].0, event_id!("$4"));
+}
@@ -0,0 +1,118 @@
+use assert_matches2::assert_matches;
+use futures_util::pin_mut;
+use matrix_sdk::{
+    assert_next_with_timeout, room::permissions::RoomPermissionsError,
+    test_utils::mocks::MatrixMockServer, Error,
+};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, BOB};
+use ruma::{
+    event_id,
+    events::{
+        room::power_levels::{PowerLevelAction, RoomPowerLevelsEventContent},
+        StateEventType,
+    },
+    int, room_id,
+};
+use serde_json::json;
+use stream_assert::assert_pending;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+#[async_test]
+async fn test_room_permissions() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&own_user_id);
+
+    let mut power_levels = RoomPowerLevelsEventContent::new();
+    power_levels.users.insert(own_user_id.clone(), int!(50));
+    power_levels.events.insert(StateEventType::RoomPowerLevels.into(), int!(50));
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.event(power_levels.clone()).state_key("").into_raw()]),
+        )
+        .await;
+
+    let permissions = room.permissions();
+
+    assert!(permissions.can_user(&own_user_id, PowerLevelAction::Kick).await.unwrap());
+    assert!(!permissions.can_user(&BOB, PowerLevelAction::Kick).await.unwrap());
+    assert_eq!(permissions.required_level(PowerLevelAction::Invite).await.unwrap(), 0);
+
+    let capabilities = permissions.subscribe_to_own_capabilities().await.unwrap();
+    pin_mut!(capabilities);
+
+    let own_capabilities = assert_next_with_timeout!(capabilities);
+    assert_eq!(own_capabilities.power_level, 50);
+    assert!(own_capabilities.can_ban);
+    assert!(own_capabilities.can_change_power_levels);
+
+    // The power levels were changed on the homeserver but not synced yet, so they
+    // aren't overwritten.
+    let mut remote_power_levels = power_levels.clone();
+    remote_power_levels.ban = int!(100);
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/m\.room\.power_levels/?$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&remote_power_levels))
+        .up_to_n_times(1)
+        .mount(server.server())
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/m\.room\.power_levels/?$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&power_levels))
+        .mount(server.server())
+        .await;
+
+    assert_matches!(
+        permissions.set_required_level(PowerLevelAction::Invite, 50).await,
+        Err(Error::RoomPermissions(RoomPermissionsError::Conflict))
+    );
+
+    // Once they are the same, they are changed.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomPowerLevels)
+        .body_matches_partial_json(json!({ "invite": 50 }))
+        .ok(event_id!("$power_levels"))
+        .mock_once()
+        .mount()
+        .await;
+
+    permissions.set_required_level(PowerLevelAction::Invite, 50).await.unwrap();
+
+    // A user can't be promoted above the own level.
+    assert_matches!(
+        permissions.set_user_level(&BOB, 100).await,
+        Err(Error::RoomPermissions(RoomPermissionsError::LevelAboveOwn {
+            level: 100,
+            own_level: 50
+        }))
+    );
+
+    // When the own user is demoted, the capabilities are updated.
+    power_levels.users.remove(&own_user_id);
+    server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.event(power_levels).state_key("").into_raw()]),
+        )
+        .await;
+
+    let own_capabilities = assert_next_with_timeout!(capabilities);
+    assert_eq!(own_capabilities.power_level, 0);
+    assert!(!own_capabilities.can_ban);
+    assert!(!own_capabilities.can_change_power_levels);
+    assert!(own_capabilities.can_send_messages);
+
+    assert_pending!(capabilities);
+}
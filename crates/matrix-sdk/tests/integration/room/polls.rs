@@ -0,0 +1,122 @@
+use matrix_sdk::{room::polls::NewPoll, test_utils::mocks::MatrixMockServer};
+use matrix_sdk_test::{
+    async_test, event_factory::EventFactory, JoinedRoomBuilder, ALICE, BOB, CAROL,
+};
+use ruma::{event_id, events::poll::start::PollKind, room_id};
+use serde_json::json;
+
+#[async_test]
+async fn test_send_poll() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!a:b.c");
+    let room = server.sync_joined_room(&client, room_id).await;
+    server.mock_room_state_encryption().plain().mount().await;
+
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "org.matrix.msc1767.text": "What's for lunch?\n1. Pizza\n2. Sushi",
+            "org.matrix.msc3381.poll.start": {
+                "question": { "org.matrix.msc1767.text": "What's for lunch?" },
+                "kind": "org.matrix.msc3381.poll.undisclosed",
+                "max_selections": 2,
+            },
+        }))
+        .ok(event_id!("$poll"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let poll = NewPoll::new("What's for lunch?", ["Pizza", "Sushi"])
+        .unwrap()
+        .kind(PollKind::Undisclosed)
+        .max_selections(2);
+    let response = room.send_poll(poll).await.unwrap();
+    assert_eq!(response.event_id, event_id!("$poll"));
+
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "m.relates_to": { "rel_type": "m.reference", "event_id": "$poll" },
+            "org.matrix.msc3381.poll.response": { "answers": ["pizza"] },
+        }))
+        .ok(event_id!("$response"))
+        .mock_once()
+        .mount()
+        .await;
+
+    room.send_poll_response(event_id!("$poll"), vec!["pizza".to_owned()]).await.unwrap();
+
+    server
+        .mock_room_send()
+        .body_matches_partial_json(json!({
+            "m.relates_to": { "rel_type": "m.reference", "event_id": "$poll" },
+            "org.matrix.msc1767.text": "The poll has ended",
+            "org.matrix.msc3381.poll.end": {},
+        }))
+        .ok(event_id!("$end"))
+        .mock_once()
+        .mount()
+        .await;
+
+    room.end_poll(event_id!("$poll"), "The poll has ended").await.unwrap();
+}
+
+#[async_test]
+async fn test_poll_results() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+    event_cache.enable_storage().unwrap();
+
+    let room_id = room_id!("!a:b.c");
+    let poll_id = event_id!("$poll");
+    let f = EventFactory::new().room(room_id);
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id).add_timeline_bulk(vec![
+                f.poll_start("Lunch?", "What's for lunch?", vec!["Pizza", "Sushi"])
+                    .sender(&ALICE)
+                    .event_id(poll_id)
+                    .server_ts(1)
+                    .into_raw_sync(),
+                f.poll_edit(poll_id, "What's for dinner?", vec!["Pizza", "Sushi"])
+                    .sender(&ALICE)
+                    .server_ts(2)
+                    .into_raw_sync(),
+                // An edit from another user is ignored.
+                f.poll_edit(poll_id, "What's for breakfast?", vec!["Pizza", "Sushi"])
+                    .sender(&BOB)
+                    .server_ts(3)
+                    .into_raw_sync(),
+                f.poll_response(vec!["0"], poll_id).sender(&ALICE).server_ts(4).into_raw_sync(),
+                f.poll_response(vec!["1"], poll_id).sender(&BOB).server_ts(5).into_raw_sync(),
+                // An end from a user who can't redact the events of others is ignored.
+                f.poll_end("Ended", poll_id).sender(&CAROL).server_ts(5).into_raw_sync(),
+                f.poll_response(vec!["0"], poll_id).sender(&BOB).server_ts(6).into_raw_sync(),
+                f.poll_end("Ended", poll_id).sender(&ALICE).server_ts(7).into_raw_sync(),
+                // A late vote is ignored.
+                f.poll_response(vec!["1"], poll_id).sender(&CAROL).server_ts(8).into_raw_sync(),
+            ]),
+        )
+        .await;
+
+    let results = room.poll_results(poll_id).await.unwrap().unwrap();
+    assert_eq!(results.question, "What's for dinner?");
+    assert!(results.has_ended());
+    assert_eq!(results.total_voters(), 2);
+    assert_eq!(results.answers[0].voters, [ALICE.to_owned(), BOB.to_owned()]);
+    assert!(results.answers[1].voters.is_empty());
+
+    let winners = results.winning_answers();
+    assert_eq!(winners.len(), 1);
+    assert_eq!(winners[0].text, "Pizza");
+
+    // Unknown events have no results.
+    assert!(room.poll_results(event_id!("$unknown")).await.unwrap().is_none());
+}
@@ -0,0 +1,248 @@
+use assert_matches2::assert_matches;
+use matrix_sdk::{
+    room::state::{StateBatchError, StateBatchEvent},
+    test_utils::mocks::MatrixMockServer,
+    Client, Error, Room,
+};
+use matrix_sdk_test::{async_test, event_factory::EventFactory, JoinedRoomBuilder, ALICE, BOB};
+use ruma::{
+    event_id,
+    events::{
+        room::{
+            member::MembershipState, name::RoomNameEventContent,
+            power_levels::RoomPowerLevelsEventContent, topic::RoomTopicEventContent,
+        },
+        AnyStateEvent, StateEvent, StateEventType,
+    },
+    int, room_id, RoomId,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+/// Sync a room where the own user is allowed to send all the state events.
+async fn sync_room_as_admin(server: &MatrixMockServer, client: &Client, room_id: &RoomId) -> Room {
+    let mut power_levels = RoomPowerLevelsEventContent::new();
+    power_levels.users.insert(client.user_id().unwrap().to_owned(), int!(100));
+
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    server
+        .sync_room(
+            client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.event(power_levels).state_key("").into_raw()]),
+        )
+        .await
+}
+
+#[async_test]
+async fn test_state_snapshot() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = sync_room_as_admin(&server, &client, room_id).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/?$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            f.room_topic("Rust").into_raw_timeline(),
+            f.member(&ALICE).membership(MembershipState::Join).into_raw_timeline(),
+            f.member(&BOB).membership(MembershipState::Invite).into_raw_timeline(),
+        ])))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let snapshot = room.state_snapshot().await.unwrap();
+    assert_eq!(snapshot.len(), 3);
+    assert_eq!(
+        snapshot.event_types().collect::<Vec<_>>(),
+        [&StateEventType::RoomMember, &StateEventType::RoomTopic]
+    );
+
+    assert_matches!(
+        snapshot.get_static::<RoomTopicEventContent>(),
+        Some(StateEvent::Original(topic))
+    );
+    assert_eq!(topic.content.topic, "Rust");
+    assert!(snapshot.get_static::<RoomNameEventContent>().is_none());
+
+    assert_matches!(
+        snapshot.get(&StateEventType::RoomMember, BOB.as_str()),
+        Some(AnyStateEvent::RoomMember(bob))
+    );
+    assert_eq!(*bob.membership(), MembershipState::Invite);
+
+    let members = snapshot.get_all_raw(&StateEventType::RoomMember).collect::<Vec<_>>();
+    assert_eq!(members.len(), 2);
+}
+
+#[async_test]
+async fn test_send_state_batch() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = sync_room_as_admin(&server, &client, room_id).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/?$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([f.room_topic("Old topic").into_raw_timeline()])),
+        )
+        .mount(server.server())
+        .await;
+
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomName)
+        .ok(event_id!("$name"))
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomTopic)
+        .ok(event_id!("$topic"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let event_ids = room
+        .send_state_batch([
+            StateBatchEvent::new(RoomNameEventContent::new("Rust".to_owned())).unwrap(),
+            StateBatchEvent::new(RoomTopicEventContent::new("All about Rust".to_owned())).unwrap(),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(event_ids, [event_id!("$name"), event_id!("$topic")]);
+}
+
+#[async_test]
+async fn test_send_state_batch_rollback() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+    let room = sync_room_as_admin(&server, &client, room_id).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/?$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([f.room_topic("Old topic").into_raw_timeline()])),
+        )
+        .mount(server.server())
+        .await;
+
+    // The new topic is sent, then restored to the old one.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomTopic)
+        .body_matches_partial_json(json!({ "topic": "All about Rust" }))
+        .ok(event_id!("$new_topic"))
+        .mock_once()
+        .mount()
+        .await;
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomTopic)
+        .body_matches_partial_json(json!({ "topic": "Old topic" }))
+        .ok(event_id!("$old_topic"))
+        .mock_once()
+        .mount()
+        .await;
+
+    // The name didn't exist, so it is cleared.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomName)
+        .body_matches_partial_json(json!({ "name": "Rust" }))
+        .ok(event_id!("$name"))
+        .mock_once()
+        .mount()
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/rooms/.*/state/m.room.name/?$"))
+        .and(body_json(json!({})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$no_name" })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    // The avatar can't be sent.
+    server
+        .mock_room_send_state()
+        .for_type(StateEventType::RoomAvatar)
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_BAD_JSON",
+            "error": "Invalid avatar",
+        })))
+        .mock_once()
+        .mount()
+        .await;
+
+    let error = room
+        .send_state_batch([
+            StateBatchEvent::new(RoomTopicEventContent::new("All about Rust".to_owned())).unwrap(),
+            StateBatchEvent::new(RoomNameEventContent::new("Rust".to_owned())).unwrap(),
+            StateBatchEvent::raw("m.room.avatar", "", json!({ "url": "invalid" })),
+        ])
+        .await
+        .unwrap_err();
+
+    assert_matches!(error, Error::StateBatch(StateBatchError::Failed(failure)));
+    assert_eq!(failure.event_type, StateEventType::RoomAvatar);
+    assert!(failure.is_rolled_back());
+
+    // The events are reverted in the reverse order.
+    assert_eq!(failure.rollback.len(), 2);
+    assert_eq!(failure.rollback[0].event_type, StateEventType::RoomName);
+    assert_eq!(failure.rollback[0].result.as_ref().unwrap(), "$no_name");
+    assert_eq!(failure.rollback[1].event_type, StateEventType::RoomTopic);
+    assert_eq!(failure.rollback[1].result.as_ref().unwrap(), "$old_topic");
+}
+
+#[async_test]
+async fn test_send_state_batch_forbidden() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let own_user_id = client.user_id().unwrap().to_owned();
+
+    let room_id = room_id!("!a:b.c");
+    let f = EventFactory::new().room(room_id).sender(&ALICE);
+
+    let mut power_levels = RoomPowerLevelsEventContent::new();
+    power_levels.users.insert(own_user_id, int!(0));
+    power_levels.events.insert(StateEventType::RoomTopic.into(), int!(0));
+    power_levels.state_default = int!(50);
+
+    let room = server
+        .sync_room(
+            &client,
+            JoinedRoomBuilder::new(room_id)
+                .add_state_bulk([f.event(power_levels).state_key("").into_raw()]),
+        )
+        .await;
+
+    // Nothing is sent since the own user can't change the name.
+    server.mock_room_send_state().ok(event_id!("$unexpected")).never().mount().await;
+
+    let error = room
+        .send_state_batch([
+            StateBatchEvent::new(RoomTopicEventContent::new("All about Rust".to_owned())).unwrap(),
+            StateBatchEvent::new(RoomNameEventContent::new("Rust".to_owned())).unwrap(),
+        ])
+        .await
+        .unwrap_err();
+
+    assert_matches!(error, Error::StateBatch(StateBatchError::Forbidden(event_type)));
+    assert_eq!(event_type, StateEventType::RoomName);
+}
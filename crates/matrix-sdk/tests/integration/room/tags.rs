@@ -1,10 +1,13 @@
 use std::{collections::BTreeMap, ops::Not, time::Duration};
 
+use assert_matches2::assert_let;
+use futures_util::{pin_mut, FutureExt, StreamExt};
 use matrix_sdk::{config::SyncSettings, Client, Room};
 use matrix_sdk_test::{
     async_test, test_json, JoinedRoomBuilder, RoomAccountDataTestEvent, SyncResponseBuilder,
 };
 use ruma::{
+    assign,
     events::tag::{TagInfo, TagName, Tags},
     room_id, RoomId,
 };
@@ -225,3 +228,40 @@ async fn test_unset_low_priority() {
 
     server.verify().await;
 }
+
+#[async_test]
+async fn test_subscribe_to_tags() {
+    let room_id = room_id!("!test:example.org");
+    let mut sync_builder = SyncResponseBuilder::new();
+    let (client, room, server) = synced_client_with_room(&mut sync_builder, room_id).await;
+
+    let tags_stream = room.subscribe_to_tags();
+    pin_mut!(tags_stream);
+
+    // No tags at first.
+    assert_let!(Some(tags) = tags_stream.next().await);
+    assert!(tags.is_empty());
+    assert!(tags_stream.next().now_or_never().is_none());
+
+    // Receive a favourite tag and a user tag, with orders.
+    let work_tag = TagName::User("u.work".parse().unwrap());
+    let tags = BTreeMap::from([
+        (TagName::Favorite, assign!(TagInfo::new(), { order: Some(0.2) })),
+        (work_tag.clone(), assign!(TagInfo::new(), { order: Some(0.7) })),
+    ]);
+    mock_sync_with_tags(&server, &mut sync_builder, room_id, tags.clone()).await;
+    sync_once(&client, &server).await;
+
+    assert_let!(Some(new_tags) = tags_stream.next().await);
+    assert_eq!(new_tags, tags);
+    assert_eq!(room.cached_tags(), tags);
+    assert_eq!(room.tag_order(&TagName::Favorite), Some(0.2));
+    assert_eq!(room.tag_order(&work_tag), Some(0.7));
+    assert_eq!(room.tag_order(&TagName::LowPriority), None);
+
+    // Receiving the same tags again doesn't yield anything.
+    mock_sync_with_tags(&server, &mut sync_builder, room_id, tags).await;
+    sync_once(&client, &server).await;
+
+    assert!(tags_stream.next().now_or_never().is_none());
+}
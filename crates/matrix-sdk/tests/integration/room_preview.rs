@@ -1,10 +1,14 @@
+use std::time::Duration;
+
 use assert_matches::assert_matches;
+use futures_util::StreamExt;
 use js_int::uint;
 use matrix_sdk::{
     config::SyncSettings,
     test_utils::{logged_in_client_with_server, mocks::MatrixMockServer},
 };
 use matrix_sdk_base::{RequestedRequiredStates, RoomState};
+use matrix_sdk_common::timeout::timeout;
 use matrix_sdk_test::{
     async_test, InvitedRoomBuilder, JoinedRoomBuilder, KnockedRoomBuilder, SyncResponseBuilder,
 };
@@ -18,7 +22,7 @@ use ruma::{
 };
 use serde_json::json;
 use wiremock::{
-    matchers::{header, method, path_regex},
+    matchers::{header, method, path, path_regex},
     Mock, MockServer, ResponseTemplate,
 };
 
@@ -179,6 +183,69 @@ async fn test_room_preview_computes_name_if_room_is_known() {
     assert_eq!(room_preview.name.unwrap(), "Alice");
 }
 
+#[async_test]
+async fn test_room_preview_from_hierarchy() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+
+    // The room summary endpoint isn't available, but the hierarchy is.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/rooms/!room:localhost/hierarchy"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [{
+                "room_id": room_id,
+                "name": "Welcome",
+                "num_joined_members": 42,
+                "world_readable": false,
+                "guest_can_join": false,
+                "join_rule": "public",
+                "children_state": [],
+            }],
+        })))
+        .expect(1)
+        .mount(server.server())
+        .await;
+
+    let preview = client.get_room_preview(room_id.into(), Vec::new()).await.unwrap();
+
+    assert_eq!(preview.name.as_deref(), Some("Welcome"));
+    assert_eq!(preview.num_joined_members, 42);
+    assert_eq!(preview.join_rule, SpaceRoomJoinRule::Public);
+    assert_eq!(preview.is_world_readable, Some(false));
+    assert_eq!(preview.is_encrypted, None);
+    assert!(preview.state.is_none());
+}
+
+#[async_test]
+async fn test_subscribe_to_room_preview() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+    let room_id = room_id!("!room:localhost");
+
+    server.sync_room(&client, InvitedRoomBuilder::new(room_id)).await;
+    assert!(client.cached_room_preview(room_id).is_none());
+
+    server.mock_room_summary().ok(room_id).mock_once().mount().await;
+
+    let mut subscriber =
+        client.subscribe_to_room_preview(room_id.into(), Vec::new()).await.unwrap();
+
+    // The preview is available immediately, from the invite.
+    let preview = subscriber.get().unwrap();
+    assert_eq!(preview.state, Some(RoomState::Invited));
+    assert_eq!(preview.num_joined_members, 0);
+
+    // Then it's refreshed from the homeserver.
+    let preview =
+        timeout(subscriber.next(), Duration::from_secs(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(preview.num_joined_members, 1);
+    assert_eq!(preview.join_rule, SpaceRoomJoinRule::Public);
+    assert_eq!(preview.is_encrypted, None);
+
+    assert_eq!(client.cached_room_preview(room_id).unwrap().num_joined_members, 1);
+}
+
 async fn mock_leave(room_id: &RoomId, server: &MockServer) {
     Mock::given(method("POST"))
         .and(path_regex(r"^/_matrix/client/r0/rooms/.*/leave"))
@@ -3,7 +3,9 @@ use std::{ops::Not as _, sync::Arc, time::Duration};
 use as_variant::as_variant;
 use assert_matches2::{assert_let, assert_matches};
 use matrix_sdk::{
+    account::USAGE_BATCH_DELAY,
     attachment::{AttachmentConfig, AttachmentInfo, BaseImageInfo, Thumbnail},
+    clock::TestClock,
     config::StoreConfig,
     media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
     room::reply::Reply,
@@ -15,8 +17,8 @@ use matrix_sdk::{
     Client, MemoryStore,
 };
 use matrix_sdk_test::{
-    async_test, event_factory::EventFactory, InvitedRoomBuilder, KnockedRoomBuilder,
-    LeftRoomBuilder, ALICE,
+    async_test, event_factory::EventFactory, InvitedRoomBuilder, JoinedRoomBuilder,
+    KnockedRoomBuilder, LeftRoomBuilder, ALICE,
 };
 use ruma::{
     event_id,
@@ -25,6 +27,8 @@ use ruma::{
             NewUnstablePollStartEventContent, UnstablePollAnswer, UnstablePollAnswers,
             UnstablePollStartContentBlock, UnstablePollStartEventContent,
         },
+        reaction::ReactionEventContent,
+        relation::Annotation,
         room::{
             message::{
                 ImageMessageEventContent, MessageType, Relation, ReplyWithinThread,
@@ -34,17 +38,20 @@ use ruma::{
         },
         AnyMessageLikeEventContent, EventContent as _, Mentions,
     },
-    mxc_uri, owned_mxc_uri, owned_user_id, room_id,
+    mxc_uri, owned_event_id, owned_mxc_uri, owned_user_id, room_id,
     serde::Raw,
     uint, MxcUri, OwnedEventId, OwnedTransactionId, TransactionId,
 };
 use serde_json::json;
 use tokio::{
-    sync::{broadcast::Receiver, Mutex},
+    sync::{broadcast::Receiver, mpsc::UnboundedReceiver, Mutex},
     task::yield_now,
     time::{sleep, timeout},
 };
-use wiremock::{Request, ResponseTemplate};
+use wiremock::{
+    matchers::{body_json, method, path_regex},
+    Mock, Request, ResponseTemplate,
+};
 
 /// Queues an attachment whenever the actual data/mime type etc. don't matter.
 ///
@@ -3032,3 +3039,137 @@ async fn test_update_caption_while_sending_media_event() {
     // That's all, folks!
     assert!(watch.is_empty());
 }
+
+/// Advance the clock until the given receiver gets a message, to let the
+/// pending uses of emojis and contacts be recorded.
+async fn wait_for_usage_flush(clock: &TestClock, recorded: &mut UnboundedReceiver<()>) {
+    timeout(Duration::from_secs(1), async {
+        loop {
+            clock.advance(USAGE_BATCH_DELAY);
+            if let Ok(recorded) = timeout(Duration::from_millis(10), recorded.recv()).await {
+                return recorded.unwrap();
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[async_test]
+async fn test_sent_reactions_are_recorded_in_recent_emojis() {
+    let mock = MatrixMockServer::new().await;
+
+    let room_id = room_id!("!a:b.c");
+    let clock = TestClock::new();
+    let client = mock.client_builder().clock(Arc::new(clock.clone())).build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_room_send().ok(event_id!("$reaction")).expect(2).mount().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/io.element.recent_emoji$"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "recent_emoji": [["🎉", 1]] })),
+        )
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let (recorded_sender, mut recorded) = tokio::sync::mpsc::unbounded_channel();
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/io.element.recent_emoji$"))
+        .and(body_json(json!({ "recent_emoji": [["🎉", 2], ["👍", 1]] })))
+        .respond_with(move |_: &Request| {
+            recorded_sender.send(()).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({}))
+        })
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let q = room.send_queue();
+    let (_, mut watch) = q.subscribe().await.unwrap();
+
+    for (event_id, key) in [(owned_event_id!("$1"), "👍"), (owned_event_id!("$2"), "🎉")] {
+        q.send(ReactionEventContent::new(Annotation::new(event_id, key.to_owned())).into())
+            .await
+            .unwrap();
+        assert_matches!(
+            timeout(Duration::from_secs(1), watch.recv()).await,
+            Ok(Ok(RoomSendQueueUpdate::NewLocalEvent(_)))
+        );
+        assert_update!(watch => sent {});
+    }
+
+    // The emojis are recorded together in the background, once the reactions
+    // have been sent.
+    wait_for_usage_flush(&clock, &mut recorded).await;
+}
+
+#[async_test]
+async fn test_sent_messages_in_dms_are_recorded_in_frequent_contacts() {
+    let mock = MatrixMockServer::new().await;
+
+    let room_id = room_id!("!a:b.c");
+    let clock = TestClock::new();
+    let client = mock.client_builder().clock(Arc::new(clock.clone())).build().await;
+    mock.mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder
+                .add_global_account_data_bulk([Raw::new(&json!({
+                    "type": "m.direct",
+                    "content": { "@bob:b.c": [room_id] },
+                }))
+                .unwrap()
+                .cast()])
+                .add_joined_room(JoinedRoomBuilder::new(room_id));
+        })
+        .await;
+    let room = client.get_room(room_id).unwrap();
+
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_room_send().ok(event_id!("$message")).expect(2).mount().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/io.element.frequent_contacts$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "contacts": [["@alice:b.c", 1], ["@bob:b.c", 1]] })),
+        )
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let (recorded_sender, mut recorded) = tokio::sync::mpsc::unbounded_channel();
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/user/.*/account_data/io.element.frequent_contacts$"))
+        .and(body_json(json!({ "contacts": [["@bob:b.c", 2], ["@alice:b.c", 1]] })))
+        .respond_with(move |_: &Request| {
+            recorded_sender.send(()).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({}))
+        })
+        .expect(1)
+        .mount(mock.server())
+        .await;
+
+    let q = room.send_queue();
+    let (_, mut watch) = q.subscribe().await.unwrap();
+
+    q.send(RoomMessageEventContent::text_plain("hello").into()).await.unwrap();
+    assert_update!(watch => local echo { body = "hello" });
+    assert_update!(watch => sent {});
+
+    // Other kinds of events are not recorded.
+    q.send_raw(Raw::new(&json!({ "body": "custom" })).unwrap().cast(), "m.custom".to_owned())
+        .await
+        .unwrap();
+    assert_matches!(
+        timeout(Duration::from_secs(1), watch.recv()).await,
+        Ok(Ok(RoomSendQueueUpdate::NewLocalEvent(_)))
+    );
+    assert_update!(watch => sent {});
+
+    // The contact is recorded in the background, once the message has been sent.
+    wait_for_usage_flush(&clock, &mut recorded).await;
+}
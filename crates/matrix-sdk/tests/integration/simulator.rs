@@ -0,0 +1,117 @@
+use matrix_sdk::{
+    assert_let_timeout,
+    authentication::matrix::MatrixSession,
+    config::SyncSettings,
+    event_cache::RoomEventCacheUpdate,
+    store::RoomLoadSettings,
+    test_utils::{assert_event_matches_msg, logged_in_client, no_retry_test_client},
+    SessionMeta, SessionTokens,
+};
+use matrix_sdk_test::{
+    async_test,
+    event_factory::EventFactory,
+    simulator::{HomeserverSimulator, SimulatedEndpoint},
+};
+use ruma::{device_id, events::room::message::RoomMessageEventContent, room_id, user_id};
+use wiremock::ResponseTemplate;
+
+#[async_test]
+async fn test_back_paginate_with_simulated_homeserver() {
+    let simulator = HomeserverSimulator::new().await;
+    let room_id = room_id!("!room:localhost");
+    let alice = user_id!("@alice:localhost");
+
+    simulator.create_room(room_id, alice);
+    simulator.join_room(room_id, user_id!("@example:localhost"));
+
+    let f = EventFactory::new().room(room_id).sender(alice);
+    simulator.push_events(room_id, (0..30).map(|i| f.text_msg(format!("Message {i}"))));
+
+    let client = logged_in_client(Some(simulator.uri())).await;
+    client.event_cache().subscribe().unwrap();
+
+    // The sync only returns the last 10 events, with a previous-batch token.
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    let timeline = &response.rooms.joined[room_id].timeline;
+    assert!(timeline.limited);
+    assert_eq!(timeline.events.len(), 10);
+    assert_event_matches_msg(&timeline.events[0], "Message 20");
+
+    let room = client.get_room(room_id).unwrap();
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+
+    let (events, mut room_stream) = room_event_cache.subscribe().await;
+    if events.is_empty() {
+        assert_let_timeout!(
+            Ok(RoomEventCacheUpdate::UpdateTimelineEvents { .. }) = room_stream.recv()
+        );
+    }
+
+    // Back-paginating follows the tokens of the homeserver until the start of the
+    // room.
+    let pagination = room_event_cache.pagination();
+    while !pagination.run_backwards_once(20).await.unwrap().reached_start {}
+
+    assert_eq!(simulator.num_received(SimulatedEndpoint::Messages), 2);
+
+    let (events, _) = room_event_cache.subscribe().await;
+    // The create, power levels and 2 member events, then the messages.
+    assert_eq!(events.len(), 34);
+
+    for (i, event) in events[4..].iter().enumerate() {
+        assert_event_matches_msg(event, &format!("Message {i}"));
+    }
+}
+
+#[async_test]
+async fn test_send_between_users_of_simulated_homeserver() {
+    let simulator = HomeserverSimulator::new().await;
+    let room_id = room_id!("!room:localhost");
+    let bob = user_id!("@bob:localhost");
+
+    simulator.add_user(bob, device_id!("BOBDEVICE"), "bob_token");
+    simulator.create_room(room_id, bob);
+    simulator.join_room(room_id, user_id!("@example:localhost"));
+
+    let client = logged_in_client(Some(simulator.uri())).await;
+
+    let bob_client = no_retry_test_client(Some(simulator.uri())).await;
+    bob_client
+        .matrix_auth()
+        .restore_session(
+            MatrixSession {
+                meta: SessionMeta {
+                    user_id: bob.to_owned(),
+                    device_id: device_id!("BOBDEVICE").to_owned(),
+                },
+                tokens: SessionTokens { access_token: "bob_token".to_owned(), refresh_token: None },
+            },
+            RoomLoadSettings::default(),
+        )
+        .await
+        .unwrap();
+
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    bob_client.sync_once(SyncSettings::default()).await.unwrap();
+
+    // Bob sends a message.
+    let bob_room = bob_client.get_room(room_id).unwrap();
+    let event_id =
+        bob_room.send(RoomMessageEventContent::text_plain("Hello")).await.unwrap().event_id;
+
+    // A scripted failure of the homeserver is returned as-is.
+    simulator.fail_next(SimulatedEndpoint::Sync, ResponseTemplate::new(500));
+    client.sync_once(SyncSettings::default()).await.unwrap_err();
+
+    // The next sync of the other user only contains the new message.
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    let timeline = &response.rooms.joined[room_id].timeline;
+    assert!(!timeline.limited);
+    assert_eq!(timeline.events.len(), 1);
+    assert_eq!(timeline.events[0].event_id().as_deref(), Some(&*event_id));
+    assert_event_matches_msg(&timeline.events[0], "Hello");
+
+    // And there's nothing new afterwards.
+    let response = client.sync_once(SyncSettings::default()).await.unwrap();
+    assert!(!response.rooms.joined.contains_key(room_id));
+}
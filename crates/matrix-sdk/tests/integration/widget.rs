@@ -31,14 +31,14 @@ use ruma::{
     event_id,
     events::{room::member::MembershipState, MessageLikeEventType, StateEventType},
     owned_room_id,
-    serde::JsonObject,
+    serde::{JsonObject, Raw},
     user_id, OwnedRoomId,
 };
 use serde::Serialize;
 use serde_json::{json, Value as JsonValue};
 use tracing::error;
 use wiremock::{
-    matchers::{method, path_regex},
+    matchers::{body_json, method, path_regex},
     Mock, ResponseTemplate,
 };
 
@@ -843,6 +843,139 @@ async fn test_send_redaction() {
     assert_eq!(redact_room_id, "!a98sd12bjh:example.org");
 }
 
+#[async_test]
+async fn test_send_to_device_event() {
+    let (_, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc3819.send.to_device:io.element.call.encryption_keys"]),
+    )
+    .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/v3/sendToDevice/io.element.call.encryption_keys/.*"))
+        .and(body_json(json!({
+            "messages": {
+                "@alice:example.org": {
+                    "ALICEDEVICE": { "keys": "secret" },
+                },
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(mock_server.server())
+        .await;
+
+    send_request(
+        &driver_handle,
+        "send-to-device",
+        "send_to_device",
+        json!({
+            "type": "io.element.call.encryption_keys",
+            "encrypted": false,
+            "messages": {
+                "@alice:example.org": {
+                    "ALICEDEVICE": { "keys": "secret" },
+                },
+            },
+        }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "send_to_device");
+    assert_eq!(msg["response"], json!({}));
+}
+
+#[async_test]
+async fn test_try_send_to_device_event_without_permission() {
+    let (_, _, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc3819.send.to_device:io.element.call.encryption_keys"]),
+    )
+    .await;
+
+    send_request(
+        &driver_handle,
+        "send-to-device",
+        "send_to_device",
+        json!({
+            "type": "m.room_key_request",
+            "encrypted": false,
+            "messages": {
+                "@alice:example.org": {
+                    "*": { "action": "request" },
+                },
+            },
+        }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "send_to_device");
+    assert_eq!(
+        msg["response"]["error"]["message"],
+        "Not allowed to send to-device events of type m.room_key_request"
+    );
+}
+
+#[async_test]
+async fn test_receive_to_device_events() {
+    let (client, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc3819.receive.to_device:io.element.call.encryption_keys"]),
+    )
+    .await;
+
+    mock_server
+        .mock_sync()
+        .ok_and_run(&client, |sync_builder| {
+            sync_builder
+                // Doesn't match the capabilities.
+                .add_to_device_event(
+                    Raw::new(&json!({
+                        "type": "io.element.other",
+                        "sender": "@alice:example.org",
+                        "content": {},
+                    }))
+                    .unwrap()
+                    .cast(),
+                )
+                .add_to_device_event(
+                    Raw::new(&json!({
+                        "type": "io.element.call.encryption_keys",
+                        "sender": "@alice:example.org",
+                        "content": { "keys": "secret" },
+                    }))
+                    .unwrap()
+                    .cast(),
+                );
+        })
+        .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "toWidget");
+    assert_eq!(msg["action"], "send_to_device");
+    assert_eq!(
+        msg["data"],
+        json!({
+            "type": "io.element.call.encryption_keys",
+            "sender": "@alice:example.org",
+            "content": { "keys": "secret" },
+            "encrypted": false,
+        })
+    );
+
+    // No more messages from the driver
+    assert_matches!(recv_message(&driver_handle).now_or_never(), None);
+}
+
 async fn negotiate_capabilities(driver_handle: &WidgetDriverHandle, caps: JsonValue) {
     {
         // Receive toWidget capabilities request
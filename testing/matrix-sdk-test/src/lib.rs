@@ -116,6 +116,8 @@ pub mod mocks;
 
 pub mod event_factory;
 pub mod notification_settings;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod simulator;
 mod sync_builder;
 pub mod test_json;
 
@@ -0,0 +1,378 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stateful simulation of a homeserver, for integration tests.
+//!
+//! Unlike the mocks returning canned responses, the [`HomeserverSimulator`]
+//! keeps the timeline of its rooms, and answers `/sync`, sliding sync and
+//! `/messages` requests from it, with real sync and pagination tokens. This
+//! allows tests to exercise the handling of limited timelines, gaps and
+//! back-pagination as it happens against a real homeserver.
+//!
+//! The events are ordered by a single stream, shared by all the rooms: each
+//! new event gets the next position of the stream, and the tokens are
+//! positions in this stream.
+//!
+//! Tests script the simulator by pushing events to rooms, from any user, and
+//! can make the next requests to an endpoint fail with
+//! [`HomeserverSimulator::fail_next()`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ruma::{
+    device_id, events::AnyTimelineEvent, serde::Raw, user_id, DeviceId, OwnedEventId, RoomId,
+    UserId,
+};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
+
+use self::state::SimulatorState;
+
+mod state;
+
+/// The endpoints handled by the [`HomeserverSimulator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SimulatedEndpoint {
+    /// `GET /_matrix/client/v3/sync`.
+    Sync,
+    /// `POST /_matrix/client/unstable/org.matrix.simplified_msc3575/sync`.
+    SlidingSync,
+    /// `GET /_matrix/client/v3/rooms/{roomId}/messages`.
+    Messages,
+    /// `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`.
+    SendEvent,
+    /// `PUT /_matrix/client/v3/rooms/{roomId}/state/{eventType}/{stateKey}`.
+    SendStateEvent,
+    /// `GET /_matrix/client/v3/rooms/{roomId}/state/{eventType}/{stateKey}`.
+    GetStateEvent,
+    /// `POST /_matrix/client/v3/keys/upload`.
+    KeysUpload,
+    /// `POST /_matrix/client/v3/keys/query`.
+    KeysQuery,
+    /// `POST /_matrix/client/v3/keys/claim`.
+    KeysClaim,
+}
+
+impl SimulatedEndpoint {
+    fn mock(self) -> Mock {
+        let (http_method, path) = match self {
+            Self::Sync => ("GET", r"^/_matrix/client/v3/sync$"),
+            Self::SlidingSync => {
+                ("POST", r"^/_matrix/client/unstable/org\.matrix\.simplified_msc3575/sync$")
+            }
+            Self::Messages => ("GET", r"^/_matrix/client/v3/rooms/[^/]+/messages$"),
+            Self::SendEvent => ("PUT", r"^/_matrix/client/v3/rooms/[^/]+/send/[^/]+/[^/]+$"),
+            Self::SendStateEvent => {
+                ("PUT", r"^/_matrix/client/v3/rooms/[^/]+/state/[^/]+(/[^/]*)?$")
+            }
+            Self::GetStateEvent => {
+                ("GET", r"^/_matrix/client/v3/rooms/[^/]+/state/[^/]+(/[^/]*)?$")
+            }
+            Self::KeysUpload => ("POST", r"^/_matrix/client/v3/keys/upload$"),
+            Self::KeysQuery => ("POST", r"^/_matrix/client/v3/keys/query$"),
+            Self::KeysClaim => ("POST", r"^/_matrix/client/v3/keys/claim$"),
+        };
+
+        Mock::given(method(http_method)).and(path_regex(path))
+    }
+}
+
+const ALL_ENDPOINTS: [SimulatedEndpoint; 9] = [
+    SimulatedEndpoint::Sync,
+    SimulatedEndpoint::SlidingSync,
+    SimulatedEndpoint::Messages,
+    SimulatedEndpoint::SendEvent,
+    SimulatedEndpoint::SendStateEvent,
+    SimulatedEndpoint::GetStateEvent,
+    SimulatedEndpoint::KeysUpload,
+    SimulatedEndpoint::KeysQuery,
+    SimulatedEndpoint::KeysClaim,
+];
+
+/// A stateful simulation of a homeserver.
+///
+/// It knows a single user when created, `@example:localhost` with the device
+/// `DEVICEID` and the access token `1234`, which is the session used by the
+/// test clients of the SDK. Other users can be added with
+/// [`HomeserverSimulator::add_user()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # async {
+/// use matrix_sdk_test::{
+///     event_factory::EventFactory, simulator::HomeserverSimulator,
+/// };
+/// use ruma::{room_id, user_id};
+///
+/// let simulator = HomeserverSimulator::new().await;
+/// let room_id = room_id!("!room:localhost");
+/// let alice = user_id!("@alice:localhost");
+///
+/// simulator.create_room(room_id, alice);
+/// simulator.join_room(room_id, user_id!("@example:localhost"));
+///
+/// let f = EventFactory::new().room(room_id).sender(alice);
+/// simulator.push_events(
+///     room_id,
+///     (0..30).map(|i| f.text_msg(format!("Message {i}"))),
+/// );
+///
+/// // A client using `simulator.uri()` as its homeserver gets the last
+/// // messages in the sync, and can back-paginate the rest.
+/// # };
+/// ```
+pub struct HomeserverSimulator {
+    server: MockServer,
+    state: Arc<Mutex<SimulatorState>>,
+}
+
+impl HomeserverSimulator {
+    /// Start a new simulated homeserver, without any room.
+    pub async fn new() -> Self {
+        let server = MockServer::start().await;
+        let state = Arc::new(Mutex::new(SimulatorState::new()));
+
+        for endpoint in ALL_ENDPOINTS {
+            endpoint
+                .mock()
+                .respond_with(Responder { state: state.clone(), endpoint })
+                .mount(&server)
+                .await;
+        }
+
+        // Filters are ignored, but clients may want to upload one before syncing.
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/v3/user/[^/]+/filter$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "filter_id": "0" })))
+            .mount(&server)
+            .await;
+
+        let simulator = Self { server, state };
+        simulator.add_user(user_id!("@example:localhost"), device_id!("DEVICEID"), "1234");
+
+        simulator
+    }
+
+    /// The URI of the simulated homeserver.
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// The underlying mock server, to mount additional mocks.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Add a user authenticated with the given access token.
+    pub fn add_user(&self, user_id: &UserId, device_id: &DeviceId, access_token: &str) {
+        self.state.lock().unwrap().add_user(
+            user_id.to_owned(),
+            device_id.to_owned(),
+            access_token.to_owned(),
+        );
+    }
+
+    /// Set the maximum number of events in the timeline of a room in a sync
+    /// response, when no limit is requested by the client.
+    ///
+    /// Defaults to 10.
+    pub fn set_timeline_limit(&self, limit: usize) {
+        self.state.lock().unwrap().timeline_limit = limit;
+    }
+
+    /// Create a room, with the given user as its creator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the room already exists.
+    pub fn create_room(&self, room_id: &RoomId, creator: &UserId) {
+        let mut state = self.state.lock().unwrap();
+        assert!(!state.room_exists(room_id), "the room {room_id} already exists");
+
+        state.push_event(
+            room_id,
+            json!({
+                "type": "m.room.create",
+                "state_key": "",
+                "sender": creator,
+                "content": { "creator": creator, "room_version": "10" },
+            }),
+        );
+        state.push_event(room_id, member_event(creator, creator, "join"));
+        state.push_event(
+            room_id,
+            json!({
+                "type": "m.room.power_levels",
+                "state_key": "",
+                "sender": creator,
+                "content": { "users": { creator.as_str(): 100 } },
+            }),
+        );
+    }
+
+    /// Make the given user join the room.
+    pub fn join_room(&self, room_id: &RoomId, user_id: &UserId) -> OwnedEventId {
+        self.state.lock().unwrap().push_event(room_id, member_event(user_id, user_id, "join"))
+    }
+
+    /// Invite the given user in the room.
+    pub fn invite_user(&self, room_id: &RoomId, sender: &UserId, user_id: &UserId) -> OwnedEventId {
+        self.state.lock().unwrap().push_event(room_id, member_event(sender, user_id, "invite"))
+    }
+
+    /// Make the given user leave the room.
+    pub fn leave_room(&self, room_id: &RoomId, user_id: &UserId) -> OwnedEventId {
+        self.state.lock().unwrap().push_event(room_id, member_event(user_id, user_id, "leave"))
+    }
+
+    /// Append an event to the timeline of the given room.
+    ///
+    /// The event ID is generated if the event doesn't have one. Returns the ID
+    /// of the event.
+    pub fn push_event(
+        &self,
+        room_id: &RoomId,
+        event: impl Into<Raw<AnyTimelineEvent>>,
+    ) -> OwnedEventId {
+        let json = serde_json::from_str(event.into().json().get()).expect("events must be JSON");
+        self.state.lock().unwrap().push_event(room_id, json)
+    }
+
+    /// Append several events to the timeline of the given room, in order.
+    pub fn push_events<E>(
+        &self,
+        room_id: &RoomId,
+        events: impl IntoIterator<Item = E>,
+    ) -> Vec<OwnedEventId>
+    where
+        E: Into<Raw<AnyTimelineEvent>>,
+    {
+        events.into_iter().map(|event| self.push_event(room_id, event)).collect()
+    }
+
+    /// Respond to the next request to the given endpoint with `response`,
+    /// instead of simulating it.
+    ///
+    /// Several responses can be queued for the same endpoint, they are used in
+    /// order.
+    pub fn fail_next(&self, endpoint: SimulatedEndpoint, response: ResponseTemplate) {
+        self.state.lock().unwrap().fail_next(endpoint, response);
+    }
+
+    /// The number of requests received for the given endpoint.
+    pub fn num_received(&self, endpoint: SimulatedEndpoint) -> usize {
+        self.state.lock().unwrap().num_received(endpoint)
+    }
+}
+
+fn member_event(sender: &UserId, user_id: &UserId, membership: &str) -> JsonValue {
+    json!({
+        "type": "m.room.member",
+        "state_key": user_id,
+        "sender": sender,
+        "content": { "membership": membership },
+    })
+}
+
+/// The decoded segments of the path of the request.
+fn path_segments(request: &Request) -> Vec<String> {
+    request
+        .url
+        .path()
+        .split('/')
+        .map(|segment| {
+            percent_encoding::percent_decode_str(segment).decode_utf8_lossy().into_owned()
+        })
+        .collect()
+}
+
+struct Responder {
+    state: Arc<Mutex<SimulatorState>>,
+    endpoint: SimulatedEndpoint,
+}
+
+impl Respond for Responder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(response) = state.receive(self.endpoint) {
+            return response;
+        }
+
+        let (user_id, device_id) = match state.authenticate(request) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        let query: HashMap<String, String> = request.url.query_pairs().into_owned().collect();
+        let query_param = |name: &str| query.get(name).map(String::as_str);
+        let body = || serde_json::from_slice::<JsonValue>(&request.body).unwrap_or_default();
+
+        // All the room endpoints are under `/_matrix/client/v3/rooms/{roomId}/`.
+        let segments = path_segments(request);
+        let room_id = segments.get(5).and_then(|room_id| RoomId::parse(room_id).ok());
+
+        match self.endpoint {
+            SimulatedEndpoint::Sync => state.sync(&user_id, query_param("since")),
+
+            SimulatedEndpoint::SlidingSync => {
+                state.sliding_sync(&user_id, query_param("pos"), &body())
+            }
+
+            SimulatedEndpoint::Messages => {
+                let Some(room_id) = room_id else {
+                    return ResponseTemplate::new(400);
+                };
+                let limit = query_param("limit").and_then(|limit| limit.parse().ok()).unwrap_or(10);
+                let forwards = query_param("dir") == Some("f");
+
+                state.messages(&room_id, query_param("from"), forwards, limit)
+            }
+
+            SimulatedEndpoint::SendEvent | SimulatedEndpoint::SendStateEvent => {
+                let (Some(room_id), Some(event_type)) = (room_id, segments.get(7)) else {
+                    return ResponseTemplate::new(400);
+                };
+
+                let (state_key, txn_id) = if self.endpoint == SimulatedEndpoint::SendEvent {
+                    (None, segments.get(8).map(String::as_str))
+                } else {
+                    (Some(segments.get(8).map_or("", String::as_str)), None)
+                };
+
+                state.send_event(&user_id, &room_id, event_type, state_key, txn_id, body())
+            }
+
+            SimulatedEndpoint::GetStateEvent => {
+                let (Some(room_id), Some(event_type)) = (room_id, segments.get(7)) else {
+                    return ResponseTemplate::new(400);
+                };
+                let state_key = segments.get(8).map_or("", String::as_str);
+
+                state.state_event(&room_id, event_type, state_key)
+            }
+
+            SimulatedEndpoint::KeysUpload => state.upload_keys(&user_id, &device_id, &body()),
+            SimulatedEndpoint::KeysQuery => state.query_keys(&body()),
+            SimulatedEndpoint::KeysClaim => state.claim_keys(&body()),
+        }
+    }
+}
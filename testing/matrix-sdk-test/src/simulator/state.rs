@@ -0,0 +1,701 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The state of the simulated homeserver, and the handling of the requests.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use ruma::{
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    UserId,
+};
+use serde_json::{json, Map, Value as JsonValue};
+use wiremock::ResponseTemplate;
+
+use super::SimulatedEndpoint;
+
+/// A pagination token, pointing between two events of the stream.
+///
+/// Paginating backwards from `t{pos}` returns the events before the position
+/// `pos`, paginating forwards returns the events from `pos` included.
+fn pagination_token(pos: u64) -> String {
+    format!("t{pos}")
+}
+
+/// A sync token, pointing after the event at the position `pos`.
+fn sync_token(pos: u64) -> String {
+    format!("s{pos}")
+}
+
+/// Parse a sync token, or a pagination token, as the position of the first
+/// event that hasn't been seen yet.
+fn parse_token(token: &str) -> Option<u64> {
+    if let Some(pos) = token.strip_prefix('t') {
+        pos.parse().ok()
+    } else if let Some(pos) = token.strip_prefix('s') {
+        pos.parse::<u64>().ok().map(|pos| pos + 1)
+    } else {
+        None
+    }
+}
+
+fn error_response(status: u16, errcode: &str, error: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({ "errcode": errcode, "error": error }))
+}
+
+/// An event of a room, with its position in the stream of the homeserver.
+#[derive(Debug)]
+struct StoredEvent {
+    pos: u64,
+    event_type: String,
+    state_key: Option<String>,
+    json: JsonValue,
+}
+
+impl StoredEvent {
+    fn membership(&self) -> Option<&str> {
+        self.json["content"]["membership"].as_str()
+    }
+
+    fn stripped(&self) -> JsonValue {
+        json!({
+            "type": self.event_type,
+            "state_key": self.state_key,
+            "sender": self.json["sender"],
+            "content": self.json["content"],
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct SimulatedRoom {
+    /// The events of the room, ordered by their position in the stream.
+    events: Vec<StoredEvent>,
+}
+
+impl SimulatedRoom {
+    /// The current membership event of the given user.
+    fn member_event(&self, user_id: &UserId) -> Option<&StoredEvent> {
+        self.events.iter().rev().find(|event| {
+            event.event_type == "m.room.member"
+                && event.state_key.as_deref() == Some(user_id.as_str())
+        })
+    }
+
+    fn num_members(&self, membership: &str) -> usize {
+        self.current_state_before(u64::MAX)
+            .into_iter()
+            .filter(|event| {
+                event.event_type == "m.room.member" && event.membership() == Some(membership)
+            })
+            .count()
+    }
+
+    /// The state of the room before the given position.
+    fn current_state_before(&self, pos: u64) -> Vec<&StoredEvent> {
+        let mut state = BTreeMap::new();
+
+        for event in self.events.iter().take_while(|event| event.pos < pos) {
+            if let Some(state_key) = &event.state_key {
+                state.insert((event.event_type.as_str(), state_key.as_str()), event);
+            }
+        }
+
+        state.into_values().collect()
+    }
+
+    fn latest_pos(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.pos)
+    }
+
+    /// The timeline of the room in a sync response, with the events after
+    /// `since`, or the latest events if `since` is `None`.
+    ///
+    /// Returns the events, whether the timeline is limited, and the state
+    /// before the events if the client has a gap in its state.
+    fn sync_timeline(&self, since: Option<u64>, limit: usize) -> SyncTimeline<'_> {
+        let new_events: Vec<_> = self
+            .events
+            .iter()
+            .filter(|event| since.is_none_or(|since| event.pos > since))
+            .collect();
+
+        let skip = new_events.len().saturating_sub(limit);
+        let events = new_events[skip..].to_vec();
+        let limited = skip > 0;
+
+        let first_pos = events.first().map_or(u64::MAX, |event| event.pos);
+        let state =
+            if since.is_none() || limited { self.current_state_before(first_pos) } else { vec![] };
+
+        SyncTimeline { prev_batch: pagination_token(first_pos), events, limited, state }
+    }
+}
+
+struct SyncTimeline<'a> {
+    prev_batch: String,
+    events: Vec<&'a StoredEvent>,
+    limited: bool,
+    state: Vec<&'a StoredEvent>,
+}
+
+fn to_json(events: &[&StoredEvent]) -> Vec<JsonValue> {
+    events.iter().map(|event| event.json.clone()).collect()
+}
+
+/// The keys uploaded by a device.
+#[derive(Debug, Default)]
+struct DeviceKeys {
+    device_keys: Option<JsonValue>,
+    one_time_keys: Vec<(String, JsonValue)>,
+    fallback_keys: Vec<(String, JsonValue)>,
+}
+
+impl DeviceKeys {
+    fn one_time_key_counts(&self) -> JsonValue {
+        let mut counts = BTreeMap::<&str, u64>::new();
+
+        for (key_id, _) in &self.one_time_keys {
+            let algorithm =
+                key_id.split_once(':').map_or(key_id.as_str(), |(algorithm, _)| algorithm);
+            *counts.entry(algorithm).or_default() += 1;
+        }
+
+        json!(counts)
+    }
+
+    /// Claim a one-time key of the given algorithm, or a fallback key if there
+    /// is none left.
+    fn claim(&mut self, algorithm: &str) -> Option<(String, JsonValue)> {
+        let has_algorithm = |(key_id, _): &(String, JsonValue)| {
+            key_id.split_once(':').is_some_and(|(key_algorithm, _)| key_algorithm == algorithm)
+        };
+
+        if let Some(index) = self.one_time_keys.iter().position(has_algorithm) {
+            Some(self.one_time_keys.remove(index))
+        } else {
+            self.fallback_keys.iter().find(|key| has_algorithm(*key)).cloned()
+        }
+    }
+}
+
+/// The whole state of the simulated homeserver.
+#[derive(Debug)]
+pub(super) struct SimulatorState {
+    /// The position of the last event of the stream.
+    pos: u64,
+    rooms: BTreeMap<OwnedRoomId, SimulatedRoom>,
+    users: HashMap<String, (OwnedUserId, OwnedDeviceId)>,
+    keys: BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, DeviceKeys>>,
+    transactions: HashMap<(OwnedUserId, String), OwnedEventId>,
+    failures: HashMap<SimulatedEndpoint, VecDeque<ResponseTemplate>>,
+    received: HashMap<SimulatedEndpoint, usize>,
+    pub timeline_limit: usize,
+}
+
+impl SimulatorState {
+    pub fn new() -> Self {
+        Self {
+            pos: 0,
+            rooms: BTreeMap::new(),
+            users: HashMap::new(),
+            keys: BTreeMap::new(),
+            transactions: HashMap::new(),
+            failures: HashMap::new(),
+            received: HashMap::new(),
+            timeline_limit: 10,
+        }
+    }
+
+    pub fn add_user(
+        &mut self,
+        user_id: OwnedUserId,
+        device_id: OwnedDeviceId,
+        access_token: String,
+    ) {
+        self.users.insert(access_token, (user_id, device_id));
+    }
+
+    pub fn authenticate(
+        &self,
+        request: &wiremock::Request,
+    ) -> Result<(OwnedUserId, OwnedDeviceId), ResponseTemplate> {
+        request
+            .headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.users.get(token))
+            .cloned()
+            .ok_or_else(|| error_response(401, "M_UNKNOWN_TOKEN", "Unknown access token"))
+    }
+
+    pub fn fail_next(&mut self, endpoint: SimulatedEndpoint, response: ResponseTemplate) {
+        self.failures.entry(endpoint).or_default().push_back(response);
+    }
+
+    /// Count a request to the given endpoint, and return the scripted failure
+    /// for it, if any.
+    pub fn receive(&mut self, endpoint: SimulatedEndpoint) -> Option<ResponseTemplate> {
+        *self.received.entry(endpoint).or_default() += 1;
+        self.failures.get_mut(&endpoint).and_then(VecDeque::pop_front)
+    }
+
+    pub fn num_received(&self, endpoint: SimulatedEndpoint) -> usize {
+        self.received.get(&endpoint).copied().unwrap_or_default()
+    }
+
+    pub fn room_exists(&self, room_id: &RoomId) -> bool {
+        self.rooms.contains_key(room_id)
+    }
+
+    /// Append an event to the timeline of the given room, creating the room if
+    /// needed.
+    ///
+    /// The missing `event_id`, `room_id` and `origin_server_ts` fields are
+    /// filled.
+    pub fn push_event(&mut self, room_id: &RoomId, mut json: JsonValue) -> OwnedEventId {
+        self.pos += 1;
+        let pos = self.pos;
+
+        let object = json.as_object_mut().expect("events must be JSON objects");
+
+        let event_type = object
+            .get("type")
+            .and_then(JsonValue::as_str)
+            .expect("events must have a type")
+            .to_owned();
+        let state_key = object.get("state_key").and_then(JsonValue::as_str).map(ToOwned::to_owned);
+
+        let event_id = match object.get("event_id").and_then(JsonValue::as_str) {
+            Some(event_id) => event_id.try_into().expect("the event ID must be valid"),
+            None => {
+                let event_id = OwnedEventId::try_from(format!("$simulated{pos}"))
+                    .expect("the generated event ID must be valid");
+                object.insert("event_id".to_owned(), json!(event_id));
+                event_id
+            }
+        };
+
+        object.entry("room_id").or_insert_with(|| json!(room_id));
+        object
+            .entry("origin_server_ts")
+            .or_insert_with(|| json!(MilliSecondsSinceUnixEpoch::now()));
+
+        self.rooms.entry(room_id.to_owned()).or_default().events.push(StoredEvent {
+            pos,
+            event_type,
+            state_key,
+            json,
+        });
+
+        event_id
+    }
+
+    /// Handle a request to send an event, from the given user.
+    pub fn send_event(
+        &mut self,
+        user_id: &UserId,
+        room_id: &RoomId,
+        event_type: &str,
+        state_key: Option<&str>,
+        txn_id: Option<&str>,
+        content: JsonValue,
+    ) -> ResponseTemplate {
+        let is_joined = self
+            .rooms
+            .get(room_id)
+            .and_then(|room| room.member_event(user_id))
+            .is_some_and(|event| event.membership() == Some("join"));
+
+        if !is_joined {
+            return error_response(403, "M_FORBIDDEN", "The user isn't joined to the room");
+        }
+
+        if let Some(txn_id) = txn_id {
+            if let Some(event_id) = self.transactions.get(&(user_id.to_owned(), txn_id.to_owned()))
+            {
+                return ResponseTemplate::new(200).set_body_json(json!({ "event_id": event_id }));
+            }
+        }
+
+        let mut event = json!({
+            "type": event_type,
+            "sender": user_id,
+            "content": content,
+        });
+
+        if let Some(state_key) = state_key {
+            event["state_key"] = json!(state_key);
+        }
+
+        if let Some(txn_id) = txn_id {
+            event["unsigned"] = json!({ "transaction_id": txn_id });
+        }
+
+        let event_id = self.push_event(room_id, event);
+
+        if let Some(txn_id) = txn_id {
+            self.transactions.insert((user_id.to_owned(), txn_id.to_owned()), event_id.clone());
+        }
+
+        ResponseTemplate::new(200).set_body_json(json!({ "event_id": event_id }))
+    }
+
+    /// Handle a request to get the content of a state event.
+    pub fn state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        state_key: &str,
+    ) -> ResponseTemplate {
+        let event = self.rooms.get(room_id).and_then(|room| {
+            room.current_state_before(u64::MAX).into_iter().find(|event| {
+                event.event_type == event_type && event.state_key.as_deref() == Some(state_key)
+            })
+        });
+
+        match event {
+            Some(event) => ResponseTemplate::new(200).set_body_json(&event.json["content"]),
+            None => error_response(404, "M_NOT_FOUND", "Event not found"),
+        }
+    }
+
+    /// Handle a `/sync` request.
+    pub fn sync(&self, user_id: &UserId, since: Option<&str>) -> ResponseTemplate {
+        let since = since.and_then(parse_token).map(|pos| pos.saturating_sub(1));
+
+        let mut join = Map::new();
+        let mut invite = Map::new();
+        let mut leave = Map::new();
+
+        for (room_id, room) in &self.rooms {
+            let Some(member_event) = room.member_event(user_id) else {
+                continue;
+            };
+
+            let is_new = since.is_none_or(|since| member_event.pos > since);
+
+            match member_event.membership() {
+                Some("join") => {
+                    // The client gets the whole room if it has just joined it.
+                    let since = if is_new { None } else { since };
+                    let timeline = room.sync_timeline(since, self.timeline_limit);
+
+                    if timeline.events.is_empty() {
+                        continue;
+                    }
+
+                    join.insert(
+                        room_id.to_string(),
+                        json!({
+                            "timeline": {
+                                "events": to_json(&timeline.events),
+                                "limited": timeline.limited,
+                                "prev_batch": timeline.prev_batch,
+                            },
+                            "state": { "events": to_json(&timeline.state) },
+                            "summary": {
+                                "m.joined_member_count": room.num_members("join"),
+                                "m.invited_member_count": room.num_members("invite"),
+                            },
+                        }),
+                    );
+                }
+
+                Some("invite") if is_new => {
+                    let events: Vec<_> = room
+                        .current_state_before(u64::MAX)
+                        .into_iter()
+                        .map(StoredEvent::stripped)
+                        .collect();
+
+                    invite.insert(
+                        room_id.to_string(),
+                        json!({ "invite_state": { "events": events } }),
+                    );
+                }
+
+                Some("leave" | "ban") if is_new && since.is_some() => {
+                    leave.insert(
+                        room_id.to_string(),
+                        json!({
+                            "timeline": { "events": [member_event.json], "limited": false },
+                            "state": { "events": [] },
+                        }),
+                    );
+                }
+
+                _ => {}
+            }
+        }
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "next_batch": sync_token(self.pos),
+            "rooms": { "join": join, "invite": invite, "leave": leave },
+        }))
+    }
+
+    /// Handle a sliding sync request.
+    ///
+    /// All the rooms of the user are in every list, and the whole current state
+    /// of the rooms is returned, whatever the required state is.
+    pub fn sliding_sync(
+        &self,
+        user_id: &UserId,
+        pos: Option<&str>,
+        body: &JsonValue,
+    ) -> ResponseTemplate {
+        let since = pos.and_then(|pos| pos.parse::<u64>().ok());
+
+        let timeline_limit = body["lists"]
+            .as_object()
+            .into_iter()
+            .flat_map(|lists| lists.values())
+            .chain(
+                body["room_subscriptions"].as_object().into_iter().flat_map(|subs| subs.values()),
+            )
+            .filter_map(|params| params["timeline_limit"].as_u64())
+            .max()
+            .map_or(self.timeline_limit, |limit| limit as usize);
+
+        let mut rooms = Map::new();
+        let mut num_rooms = 0;
+
+        for (room_id, room) in &self.rooms {
+            let Some(member_event) = room.member_event(user_id) else {
+                continue;
+            };
+
+            let is_new = since.is_none_or(|since| member_event.pos > since);
+
+            match member_event.membership() {
+                Some("join") => {
+                    num_rooms += 1;
+
+                    let since = if is_new { None } else { since };
+                    let timeline = room.sync_timeline(since, timeline_limit);
+
+                    if timeline.events.is_empty() {
+                        continue;
+                    }
+
+                    let required_state =
+                        if is_new { to_json(&room.current_state_before(u64::MAX)) } else { vec![] };
+
+                    rooms.insert(
+                        room_id.to_string(),
+                        json!({
+                            "initial": is_new,
+                            "timeline": to_json(&timeline.events),
+                            "required_state": required_state,
+                            "prev_batch": timeline.prev_batch,
+                            "limited": timeline.limited,
+                            "bump_stamp": room.latest_pos(),
+                            "joined_count": room.num_members("join"),
+                            "invited_count": room.num_members("invite"),
+                        }),
+                    );
+                }
+
+                Some("invite") => {
+                    num_rooms += 1;
+
+                    if is_new {
+                        let events: Vec<_> = room
+                            .current_state_before(u64::MAX)
+                            .into_iter()
+                            .map(StoredEvent::stripped)
+                            .collect();
+
+                        rooms.insert(
+                            room_id.to_string(),
+                            json!({ "initial": true, "invite_state": events }),
+                        );
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        let lists: Map<_, _> = body["lists"]
+            .as_object()
+            .into_iter()
+            .flat_map(|lists| lists.keys())
+            .map(|name| (name.clone(), json!({ "count": num_rooms })))
+            .collect();
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "pos": self.pos.to_string(),
+            "lists": lists,
+            "rooms": rooms,
+            "extensions": {},
+        }))
+    }
+
+    /// Handle a `/messages` request.
+    pub fn messages(
+        &self,
+        room_id: &RoomId,
+        from: Option<&str>,
+        forwards: bool,
+        limit: usize,
+    ) -> ResponseTemplate {
+        let Some(room) = self.rooms.get(room_id) else {
+            return error_response(404, "M_NOT_FOUND", "Unknown room");
+        };
+
+        let from_pos = match from {
+            Some(token) => match parse_token(token) {
+                Some(pos) => pos,
+                None => return error_response(400, "M_INVALID_PARAM", "Invalid token"),
+            },
+            None if forwards => 0,
+            None => u64::MAX,
+        };
+
+        let (chunk, end): (Vec<_>, _) = if forwards {
+            let candidates: Vec<_> =
+                room.events.iter().filter(|event| event.pos >= from_pos).collect();
+            let chunk: Vec<_> = candidates.iter().take(limit).copied().collect();
+            let end = (candidates.len() > chunk.len())
+                .then(|| chunk.last().map(|event| pagination_token(event.pos + 1)))
+                .flatten();
+            (chunk, end)
+        } else {
+            let candidates: Vec<_> =
+                room.events.iter().rev().filter(|event| event.pos < from_pos).collect();
+            let chunk: Vec<_> = candidates.iter().take(limit).copied().collect();
+            let end = (candidates.len() > chunk.len())
+                .then(|| chunk.last().map(|event| pagination_token(event.pos)))
+                .flatten();
+            (chunk, end)
+        };
+
+        let mut response = json!({
+            "start": from.map_or_else(|| pagination_token(from_pos.min(self.pos + 1)), ToOwned::to_owned),
+            "chunk": to_json(&chunk),
+            "state": [],
+        });
+
+        if let Some(end) = end {
+            response["end"] = json!(end);
+        }
+
+        ResponseTemplate::new(200).set_body_json(response)
+    }
+
+    /// Handle a `/keys/upload` request.
+    pub fn upload_keys(
+        &mut self,
+        user_id: &UserId,
+        device_id: &OwnedDeviceId,
+        body: &JsonValue,
+    ) -> ResponseTemplate {
+        let keys =
+            self.keys.entry(user_id.to_owned()).or_default().entry(device_id.clone()).or_default();
+
+        if let Some(device_keys) = body.get("device_keys") {
+            keys.device_keys = Some(device_keys.clone());
+        }
+
+        if let Some(one_time_keys) = body["one_time_keys"].as_object() {
+            keys.one_time_keys
+                .extend(one_time_keys.iter().map(|(key_id, key)| (key_id.clone(), key.clone())));
+        }
+
+        if let Some(fallback_keys) = body["fallback_keys"].as_object() {
+            keys.fallback_keys =
+                fallback_keys.iter().map(|(key_id, key)| (key_id.clone(), key.clone())).collect();
+        }
+
+        ResponseTemplate::new(200)
+            .set_body_json(json!({ "one_time_key_counts": keys.one_time_key_counts() }))
+    }
+
+    /// Handle a `/keys/query` request.
+    pub fn query_keys(&self, body: &JsonValue) -> ResponseTemplate {
+        let mut device_keys = Map::new();
+
+        for (user_id, requested_devices) in body["device_keys"].as_object().into_iter().flatten() {
+            let requested_devices: Vec<_> = requested_devices
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .collect();
+
+            let devices: Map<_, _> = self
+                .keys
+                .iter()
+                .filter(|(owner, _)| owner.as_str() == user_id.as_str())
+                .flat_map(|(_, devices)| devices)
+                .filter(|(device_id, _)| {
+                    requested_devices.is_empty() || requested_devices.contains(&device_id.as_str())
+                })
+                .filter_map(|(device_id, keys)| {
+                    Some((device_id.to_string(), keys.device_keys.clone()?))
+                })
+                .collect();
+
+            device_keys.insert(user_id.clone(), json!(devices));
+        }
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": device_keys,
+            "failures": {},
+        }))
+    }
+
+    /// Handle a `/keys/claim` request.
+    pub fn claim_keys(&mut self, body: &JsonValue) -> ResponseTemplate {
+        let mut one_time_keys = Map::new();
+
+        for (user_id, devices) in body["one_time_keys"].as_object().into_iter().flatten() {
+            let mut claimed = Map::new();
+
+            for (device_id, algorithm) in devices.as_object().into_iter().flatten() {
+                let Some(algorithm) = algorithm.as_str() else {
+                    continue;
+                };
+
+                let key = self
+                    .keys
+                    .iter_mut()
+                    .find(|(owner, _)| owner.as_str() == user_id.as_str())
+                    .and_then(|(_, devices)| {
+                        devices.iter_mut().find(|(id, _)| id.as_str() == device_id.as_str())
+                    })
+                    .and_then(|(_, keys)| keys.claim(algorithm));
+
+                if let Some((key_id, key)) = key {
+                    claimed.insert(
+                        device_id.clone(),
+                        JsonValue::Object(Map::from_iter([(key_id, key)])),
+                    );
+                }
+            }
+
+            if !claimed.is_empty() {
+                one_time_keys.insert(user_id.clone(), JsonValue::Object(claimed));
+            }
+        }
+
+        ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_keys": one_time_keys,
+            "failures": {},
+        }))
+    }
+}
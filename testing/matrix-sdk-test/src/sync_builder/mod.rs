@@ -8,7 +8,7 @@ use ruma::{
         },
         IncomingResponse,
     },
-    events::{presence::PresenceEvent, AnyGlobalAccountDataEvent},
+    events::{presence::PresenceEvent, AnyGlobalAccountDataEvent, AnyToDeviceEvent},
     serde::Raw,
     OwnedRoomId, OwnedUserId, UserId,
 };
@@ -53,6 +53,8 @@ pub struct SyncResponseBuilder {
     presence: Vec<Raw<PresenceEvent>>,
     /// Global account data events.
     account_data: Vec<Raw<AnyGlobalAccountDataEvent>>,
+    /// To-device events.
+    to_device: Vec<Raw<AnyToDeviceEvent>>,
     /// Internal counter to enable the `prev_batch` and `next_batch` of each
     /// sync response to vary.
     batch_counter: i64,
@@ -157,6 +159,12 @@ impl SyncResponseBuilder {
         self
     }
 
+    /// Add a to-device event.
+    pub fn add_to_device_event(&mut self, event: Raw<AnyToDeviceEvent>) -> &mut Self {
+        self.to_device.push(event);
+        self
+    }
+
     pub fn add_change_device(&mut self, user_id: &UserId) -> &mut Self {
         self.changed_device_lists.push(user_id.to_owned());
         self
@@ -191,7 +199,7 @@ impl SyncResponseBuilder {
                     "knock": self.knocked_rooms,
                 },
                 "to_device": {
-                    "events": []
+                    "events": self.to_device,
                 },
                 "presence": {
                     "events": self.presence,
@@ -237,5 +245,6 @@ impl SyncResponseBuilder {
         self.left_rooms.clear();
         self.knocked_rooms.clear();
         self.presence.clear();
+        self.to_device.clear();
     }
 }
@@ -67,6 +67,7 @@ enum FeatureSet {
     SqliteCryptostore,
     RustlsTls,
     Markdown,
+    ImageProc,
     Socks,
     SsoLogin,
 }
@@ -228,6 +229,7 @@ fn run_feature_tests(cmd: Option<FeatureSet>) -> Result<()> {
         ),
         (FeatureSet::RustlsTls, "--no-default-features --features rustls-tls,testing"),
         (FeatureSet::Markdown, "--features markdown,testing"),
+        (FeatureSet::ImageProc, "--features image-proc,testing"),
         (FeatureSet::Socks, "--features socks,testing"),
         (FeatureSet::SsoLogin, "--features sso-login,testing"),
     ]);